@@ -3,6 +3,7 @@ package net.mullvad.mullvadvpn.lib.daemon.grpc
 import android.net.LocalSocketAddress
 import android.util.Log
 import arrow.core.Either
+import arrow.core.flatMap
 import arrow.core.raise.either
 import arrow.core.raise.ensure
 import arrow.optics.copy
@@ -216,6 +217,8 @@ class ManagementService(
                             _mutableCurrentAccessMethod.update { event.newAccessMethod.toDomain() }
                         }
                         ManagementInterface.DaemonEvent.EventCase.REMOVE_DEVICE -> {}
+                        ManagementInterface.DaemonEvent.EventCase.CONNECTION_VERIFIED -> {}
+                        ManagementInterface.DaemonEvent.EventCase.CONNECT_ESCALATION -> {}
                         ManagementInterface.DaemonEvent.EventCase.EVENT_NOT_SET -> {}
                     }
                 }
@@ -627,18 +630,25 @@ class ManagementService(
     ): Either<TestApiAccessMethodError, Unit> =
         Either.catch { grpc.testCustomApiAccessMethod(customProxy.fromDomain()) }
             .mapLeftStatus { TestApiAccessMethodError.Grpc }
-            .map { result ->
-                either { ensure(result.value) { TestApiAccessMethodError.CouldNotAccess } }
-            }
+            .flatMap { result -> result.toTestResult() }
 
     suspend fun testApiAccessMethodById(
         apiAccessMethodId: ApiAccessMethodId
     ): Either<TestApiAccessMethodError, Unit> =
         Either.catch { grpc.testApiAccessMethodById(apiAccessMethodId.fromDomain()) }
             .mapLeftStatus { TestApiAccessMethodError.Grpc }
-            .map { result ->
-                either { ensure(result.value) { TestApiAccessMethodError.CouldNotAccess } }
-            }
+            .flatMap { result -> result.toTestResult() }
+
+    private fun ManagementInterface.AccessMethodTestResult.toTestResult():
+        Either<TestApiAccessMethodError, Unit> = either {
+        val failedStep = stepsList.firstOrNull { it.hasError() }
+        if (failedStep != null) {
+            raise(
+                TestApiAccessMethodError.StepFailed(failedStep.step.toDomain(), failedStep.error)
+            )
+        }
+        ensure(successful) { TestApiAccessMethodError.CouldNotAccess }
+    }
 
     private fun <A> Either<A, Empty>.mapEmpty() = map {}
 
@@ -23,6 +23,7 @@ import net.mullvad.mullvadvpn.lib.model.RelayItemId
 import net.mullvad.mullvadvpn.lib.model.RelaySettings
 import net.mullvad.mullvadvpn.lib.model.SelectedObfuscation
 import net.mullvad.mullvadvpn.lib.model.SocksAuth
+import net.mullvad.mullvadvpn.lib.model.TlsObfuscationSettings
 import net.mullvad.mullvadvpn.lib.model.TransportProtocol
 import net.mullvad.mullvadvpn.lib.model.Udp2TcpObfuscationSettings
 import net.mullvad.mullvadvpn.lib.model.WireguardConstraints
@@ -80,6 +81,7 @@ internal fun ObfuscationSettings.fromDomain(): ManagementInterface.ObfuscationSe
     ManagementInterface.ObfuscationSettings.newBuilder()
         .setSelectedObfuscation(selectedObfuscation.fromDomain())
         .setUdp2Tcp(udp2tcp.fromDomain())
+        .setTls(tls.fromDomain())
         .build()
 
 internal fun SelectedObfuscation.fromDomain():
@@ -89,8 +91,12 @@ internal fun SelectedObfuscation.fromDomain():
             ManagementInterface.ObfuscationSettings.SelectedObfuscation.UDP2TCP
         SelectedObfuscation.Auto -> ManagementInterface.ObfuscationSettings.SelectedObfuscation.AUTO
         SelectedObfuscation.Off -> ManagementInterface.ObfuscationSettings.SelectedObfuscation.OFF
+        SelectedObfuscation.Tls -> ManagementInterface.ObfuscationSettings.SelectedObfuscation.TLS
     }
 
+internal fun TlsObfuscationSettings.fromDomain(): ManagementInterface.TlsObfuscationSettings =
+    ManagementInterface.TlsObfuscationSettings.newBuilder().setSni(sni).build()
+
 internal fun Udp2TcpObfuscationSettings.fromDomain():
     ManagementInterface.Udp2TcpObfuscationSettings =
     when (val port = port) {
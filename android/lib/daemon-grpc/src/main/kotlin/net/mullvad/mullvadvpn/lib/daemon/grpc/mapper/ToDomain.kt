@@ -9,6 +9,7 @@ import java.util.UUID
 import mullvad_daemon.management_interface.ManagementInterface
 import net.mullvad.mullvadvpn.lib.daemon.grpc.GrpcConnectivityState
 import net.mullvad.mullvadvpn.lib.daemon.grpc.RelayNameComparator
+import net.mullvad.mullvadvpn.lib.model.AccessMethodTestStep
 import net.mullvad.mullvadvpn.lib.model.AccountData
 import net.mullvad.mullvadvpn.lib.model.AccountId
 import net.mullvad.mullvadvpn.lib.model.AccountNumber
@@ -60,6 +61,7 @@ import net.mullvad.mullvadvpn.lib.model.SelectedObfuscation
 import net.mullvad.mullvadvpn.lib.model.Settings
 import net.mullvad.mullvadvpn.lib.model.SocksAuth
 import net.mullvad.mullvadvpn.lib.model.SplitTunnelSettings
+import net.mullvad.mullvadvpn.lib.model.TlsObfuscationSettings
 import net.mullvad.mullvadvpn.lib.model.TransportProtocol
 import net.mullvad.mullvadvpn.lib.model.TunnelEndpoint
 import net.mullvad.mullvadvpn.lib.model.TunnelOptions
@@ -169,6 +171,7 @@ internal fun ManagementInterface.ObfuscationEndpoint.toDomain(): ObfuscationEndp
 internal fun ManagementInterface.ObfuscationType.toDomain(): ObfuscationType =
     when (this) {
         ManagementInterface.ObfuscationType.UDP2TCP -> ObfuscationType.Udp2Tcp
+        ManagementInterface.ObfuscationType.TLS -> ObfuscationType.Tls
         ManagementInterface.ObfuscationType.UNRECOGNIZED ->
             throw IllegalArgumentException("Unrecognized obfuscation type")
     }
@@ -212,6 +215,7 @@ internal fun ManagementInterface.ErrorState.toDomain(): ErrorState =
                     ErrorStateCause.StartTunnelError
                 ManagementInterface.ErrorState.Cause.UNRECOGNIZED,
                 ManagementInterface.ErrorState.Cause.NEED_FULL_DISK_PERMISSIONS,
+                ManagementInterface.ErrorState.Cause.UNSUPPORTED_DNS_MANAGER,
                 ManagementInterface.ErrorState.Cause.CREATE_TUNNEL_DEVICE ->
                     throw IllegalArgumentException("Unrecognized error state cause")
             },
@@ -330,7 +334,8 @@ internal fun ManagementInterface.Ownership.toDomain(): Constraint<Ownership> =
 internal fun ManagementInterface.ObfuscationSettings.toDomain(): ObfuscationSettings =
     ObfuscationSettings(
         selectedObfuscation = selectedObfuscation.toDomain(),
-        udp2tcp = udp2Tcp.toDomain()
+        udp2tcp = udp2Tcp.toDomain(),
+        tls = tls.toDomain()
     )
 
 internal fun ManagementInterface.ObfuscationSettings.SelectedObfuscation.toDomain():
@@ -340,6 +345,7 @@ internal fun ManagementInterface.ObfuscationSettings.SelectedObfuscation.toDomai
         ManagementInterface.ObfuscationSettings.SelectedObfuscation.OFF -> SelectedObfuscation.Off
         ManagementInterface.ObfuscationSettings.SelectedObfuscation.UDP2TCP ->
             SelectedObfuscation.Udp2Tcp
+        ManagementInterface.ObfuscationSettings.SelectedObfuscation.TLS -> SelectedObfuscation.Tls
         ManagementInterface.ObfuscationSettings.SelectedObfuscation.UNRECOGNIZED ->
             throw IllegalArgumentException("Unrecognized selected obfuscation")
     }
@@ -351,6 +357,9 @@ internal fun ManagementInterface.Udp2TcpObfuscationSettings.toDomain(): Udp2TcpO
         Udp2TcpObfuscationSettings(Constraint.Any)
     }
 
+internal fun ManagementInterface.TlsObfuscationSettings.toDomain(): TlsObfuscationSettings =
+    TlsObfuscationSettings(sni)
+
 internal fun ManagementInterface.CustomList.toDomain(): CustomList =
     CustomList(
         id = CustomListId(id),
@@ -419,7 +428,7 @@ internal fun QuantumResistantState.toDomain(): ManagementInterface.QuantumResist
 internal fun ManagementInterface.AppVersionInfo.toDomain(): AppVersionInfo =
     AppVersionInfo(
         supported = supported,
-        suggestedUpgrade = if (hasSuggestedUpgrade()) suggestedUpgrade else null
+        suggestedUpgrade = if (hasSuggestedUpgrade()) suggestedUpgrade.version else null
     )
 
 internal fun ConnectivityState.toDomain(): GrpcConnectivityState =
@@ -576,3 +585,19 @@ internal fun ManagementInterface.Socks5Remote.toDomain(): ApiAccessMethod.Custom
 
 internal fun ManagementInterface.SocksAuth.toDomain(): SocksAuth =
     SocksAuth(username = username, password = password)
+
+internal fun ManagementInterface.AccessMethodTestResult.Step.toDomain(): AccessMethodTestStep =
+    when (this) {
+        ManagementInterface.AccessMethodTestResult.Step.RESOLVE_ADDRESS ->
+            AccessMethodTestStep.ResolveAddress
+        ManagementInterface.AccessMethodTestResult.Step.TCP_CONNECT ->
+            AccessMethodTestStep.TcpConnect
+        ManagementInterface.AccessMethodTestResult.Step.PROXY_HANDSHAKE ->
+            AccessMethodTestStep.ProxyHandshake
+        ManagementInterface.AccessMethodTestResult.Step.TLS_HANDSHAKE ->
+            AccessMethodTestStep.TlsHandshake
+        ManagementInterface.AccessMethodTestResult.Step.HTTP_REQUEST ->
+            AccessMethodTestStep.HttpRequest
+        ManagementInterface.AccessMethodTestResult.Step.UNRECOGNIZED ->
+            throw IllegalArgumentException("Unrecognized access method test step")
+    }
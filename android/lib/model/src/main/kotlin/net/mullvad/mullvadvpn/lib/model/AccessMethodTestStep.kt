@@ -0,0 +1,9 @@
+package net.mullvad.mullvadvpn.lib.model
+
+enum class AccessMethodTestStep {
+    ResolveAddress,
+    TcpConnect,
+    ProxyHandshake,
+    TlsHandshake,
+    HttpRequest
+}
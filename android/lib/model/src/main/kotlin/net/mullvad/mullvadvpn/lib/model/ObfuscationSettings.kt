@@ -5,7 +5,8 @@ import arrow.optics.optics
 @optics
 data class ObfuscationSettings(
     val selectedObfuscation: SelectedObfuscation,
-    val udp2tcp: Udp2TcpObfuscationSettings
+    val udp2tcp: Udp2TcpObfuscationSettings,
+    val tls: TlsObfuscationSettings
 ) {
     companion object
 }
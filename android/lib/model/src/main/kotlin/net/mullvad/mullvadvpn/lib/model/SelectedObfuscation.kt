@@ -3,5 +3,6 @@ package net.mullvad.mullvadvpn.lib.model
 enum class SelectedObfuscation {
     Auto,
     Off,
-    Udp2Tcp
+    Udp2Tcp,
+    Tls
 }
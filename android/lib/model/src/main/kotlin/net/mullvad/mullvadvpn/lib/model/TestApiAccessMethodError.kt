@@ -3,6 +3,9 @@ package net.mullvad.mullvadvpn.lib.model
 sealed interface TestApiAccessMethodError {
     data object CouldNotAccess : TestApiAccessMethodError
 
+    data class StepFailed(val step: AccessMethodTestStep, val reason: String) :
+        TestApiAccessMethodError
+
     data object Grpc : TestApiAccessMethodError
 
     data class Unknown(val t: Throwable) : TestApiAccessMethodError
@@ -0,0 +1,3 @@
+package net.mullvad.mullvadvpn.lib.model
+
+data class TlsObfuscationSettings(val sni: String)
@@ -18,6 +18,10 @@ DAEMON_PLIST_PATH="/Library/LaunchDaemons/net.mullvad.daemon.plist"
 sudo launchctl unload -w "$DAEMON_PLIST_PATH"
 sudo rm -f "$DAEMON_PLIST_PATH"
 
+echo "Removing early boot blocking ..."
+sudo rm -f /Library/LaunchDaemons/net.mullvad.early-boot-blocking.plist
+sudo rm -f /etc/pf.anchors/net.mullvad.early-boot-blocking
+
 echo "Resetting firewall"
 sudo /Applications/Mullvad\ VPN.app/Contents/Resources/mullvad-setup reset-firewall || echo "Failed to reset firewall"
 sudo /Applications/Mullvad\ VPN.app/Contents/Resources/mullvad-setup remove-device || echo "Failed to remove device from account"
@@ -21,6 +21,7 @@ import {
   BridgeState,
   BridgeType,
   ConnectionConfig,
+  ConnectPhase,
   Constraint,
   CustomListError,
   CustomLists,
@@ -36,6 +37,7 @@ import {
   FirewallPolicyErrorType,
   IAppVersionInfo,
   IBridgeConstraints,
+  ICustomBlocklist,
   ICustomList,
   IDaitaSettings,
   IDevice,
@@ -67,6 +69,7 @@ import {
   RelayProtocol,
   RelaySettings,
   SocksAuth,
+  SystemProxyMethod,
   TunnelParameterError,
   TunnelProtocol,
   TunnelState,
@@ -393,6 +396,11 @@ export class DaemonRpc {
           grpcTypes.ObfuscationSettings.SelectedObfuscation.UDP2TCP,
         );
         break;
+      case ObfuscationType.tls:
+        grpcObfuscationSettings.setSelectedObfuscation(
+          grpcTypes.ObfuscationSettings.SelectedObfuscation.TLS,
+        );
+        break;
     }
 
     if (obfuscationSettings.udp2tcpSettings) {
@@ -403,6 +411,12 @@ export class DaemonRpc {
       grpcObfuscationSettings.setUdp2tcp(grpcUdp2tcpSettings);
     }
 
+    if (obfuscationSettings.tlsSettings) {
+      const grpcTlsSettings = new grpcTypes.TlsObfuscationSettings();
+      grpcTlsSettings.setSni(obfuscationSettings.tlsSettings.sni);
+      grpcObfuscationSettings.setTls(grpcTlsSettings);
+    }
+
     await this.call<grpcTypes.ObfuscationSettings, Empty>(
       this.client.setObfuscationSettings,
       grpcObfuscationSettings,
@@ -518,12 +532,17 @@ export class DaemonRpc {
     defaultOptions.setBlockAdultContent(dns.defaultOptions.blockAdultContent);
     defaultOptions.setBlockGambling(dns.defaultOptions.blockGambling);
     defaultOptions.setBlockSocialMedia(dns.defaultOptions.blockSocialMedia);
+    defaultOptions.setCustomBlocklistsList(
+      dns.defaultOptions.customBlocklists.map(convertToCustomBlocklist),
+    );
     dnsOptions.setDefaultOptions(defaultOptions);
 
     const customOptions = new grpcTypes.CustomDnsOptions();
     customOptions.setAddressesList(dns.customOptions.addresses);
     dnsOptions.setCustomOptions(customOptions);
 
+    dnsOptions.setBlockOnUnsupportedManager(dns.blockOnUnsupportedManager);
+
     if (dns.state === 'custom') {
       dnsOptions.setState(grpcTypes.DnsOptions.DnsState.CUSTOM);
     } else {
@@ -535,7 +554,7 @@ export class DaemonRpc {
 
   public async getVersionInfo(): Promise<IAppVersionInfo> {
     const response = await this.callEmpty<grpcTypes.AppVersionInfo>(this.client.getVersionInfo);
-    return response.toObject();
+    return convertFromAppVersionInfo(response);
   }
 
   public async addSplitTunnelingApplication(path: string): Promise<void> {
@@ -665,19 +684,19 @@ export class DaemonRpc {
   public async testApiAccessMethodById(id: string): Promise<boolean> {
     const uuid = new grpcTypes.UUID();
     uuid.setValue(id);
-    const result = await this.call<grpcTypes.UUID, BoolValue>(
+    const result = await this.call<grpcTypes.UUID, grpcTypes.AccessMethodTestResult>(
       this.client.testApiAccessMethodById,
       uuid,
     );
-    return result.getValue();
+    return result.getSuccessful();
   }
 
   public async testCustomApiAccessMethod(method: CustomProxy): Promise<boolean> {
-    const result = await this.call<grpcTypes.CustomProxy, BoolValue>(
+    const result = await this.call<grpcTypes.CustomProxy, grpcTypes.AccessMethodTestResult>(
       this.client.testCustomApiAccessMethod,
       convertToCustomProxy(method),
     );
-    return result.getValue();
+    return result.getSuccessful();
   }
 
   public async applyJsonSettings(settings: string): Promise<void> {
@@ -1051,6 +1070,12 @@ function convertFromTunnelStateError(state: grpcTypes.ErrorState.AsObject): Erro
         ...baseError,
         cause: ErrorStateCause.needFullDiskPermissions,
       };
+    case grpcTypes.ErrorState.Cause.UNSUPPORTED_DNS_MANAGER:
+      return {
+        ...baseError,
+        cause: ErrorStateCause.unsupportedDnsManager,
+        dnsManager: state.dnsManager!,
+      };
     case grpcTypes.ErrorState.Cause.VPN_PERMISSION_DENIED:
       // VPN_PERMISSION_DENIED is only ever created on Android
       throw invalidErrorStateCause;
@@ -1149,6 +1174,7 @@ function convertFromObfuscationEndpoint(
 ): IObfuscationEndpoint {
   const obfuscationTypes: Record<grpcTypes.ObfuscationType, EndpointObfuscationType> = {
     [grpcTypes.ObfuscationType.UDP2TCP]: 'udp2tcp',
+    [grpcTypes.ObfuscationType.TLS]: 'tls',
   };
 
   return {
@@ -1383,14 +1409,40 @@ function convertFromTunnelOptions(tunnelOptions: grpcTypes.TunnelOptions.AsObjec
         blockAdultContent: tunnelOptions.dnsOptions?.defaultOptions?.blockAdultContent ?? false,
         blockGambling: tunnelOptions.dnsOptions?.defaultOptions?.blockGambling ?? false,
         blockSocialMedia: tunnelOptions.dnsOptions?.defaultOptions?.blockSocialMedia ?? false,
+        customBlocklists: (
+          tunnelOptions.dnsOptions?.defaultOptions?.customBlocklistsList ?? []
+        ).map(convertFromCustomBlocklist),
       },
       customOptions: {
         addresses: tunnelOptions.dnsOptions?.customOptions?.addressesList ?? [],
       },
+      blockOnUnsupportedManager: tunnelOptions.dnsOptions?.blockOnUnsupportedManager ?? false,
     },
   };
 }
 
+function convertFromCustomBlocklist(
+  blocklist: grpcTypes.CustomBlocklist.AsObject,
+): ICustomBlocklist {
+  return {
+    source: blocklist.url
+      ? { type: 'url', url: blocklist.url }
+      : { type: 'file', path: blocklist.path },
+    enabled: blocklist.enabled,
+  };
+}
+
+function convertToCustomBlocklist(blocklist: ICustomBlocklist): grpcTypes.CustomBlocklist {
+  const customBlocklist = new grpcTypes.CustomBlocklist();
+  if (blocklist.source.type === 'url') {
+    customBlocklist.setUrl(blocklist.source.url);
+  } else {
+    customBlocklist.setPath(blocklist.source.path);
+  }
+  customBlocklist.setEnabled(blocklist.enabled);
+  return customBlocklist;
+}
+
 function convertFromQuantumResistantState(
   state?: grpcTypes.QuantumResistantState.State,
 ): boolean | undefined {
@@ -1414,6 +1466,9 @@ function convertFromObfuscationSettings(
     case grpcTypes.ObfuscationSettings.SelectedObfuscation.UDP2TCP:
       selectedObfuscationType = ObfuscationType.udp2tcp;
       break;
+    case grpcTypes.ObfuscationSettings.SelectedObfuscation.TLS:
+      selectedObfuscationType = ObfuscationType.tls;
+      break;
   }
 
   return {
@@ -1421,6 +1476,7 @@ function convertFromObfuscationSettings(
     udp2tcpSettings: obfuscationSettings?.udp2tcp
       ? { port: convertFromConstraint(obfuscationSettings.udp2tcp.port) }
       : { port: 'any' },
+    tlsSettings: { sni: obfuscationSettings?.tls?.sni ?? '' },
   };
 }
 
@@ -1452,7 +1508,7 @@ function convertFromDaemonEvent(data: grpcTypes.DaemonEvent): DaemonEvent {
 
   const versionInfo = data.getVersionInfo();
   if (versionInfo !== undefined) {
-    return { appVersionInfo: versionInfo.toObject() };
+    return { appVersionInfo: convertFromAppVersionInfo(versionInfo) };
   }
 
   const newAccessMethod = data.getNewAccessMethod();
@@ -1460,6 +1516,44 @@ function convertFromDaemonEvent(data: grpcTypes.DaemonEvent): DaemonEvent {
     return { accessMethodSetting: convertFromApiAccessMethodSetting(newAccessMethod) };
   }
 
+  const connectionVerification = data.getConnectionVerified();
+  if (connectionVerification !== undefined) {
+    return {
+      connectionVerification: {
+        exitIp: connectionVerification.getExitIp(),
+        mullvadExitIp: connectionVerification.getMullvadExitIp(),
+        exitRelayMatches: connectionVerification.hasExitRelayMatches()
+          ? connectionVerification.getExitRelayMatches()
+          : undefined,
+        dnsHijacked: connectionVerification.hasDnsHijacked()
+          ? connectionVerification.getDnsHijacked()
+          : undefined,
+      },
+    };
+  }
+
+  const connectEscalation = data.getConnectEscalation();
+  if (connectEscalation !== undefined) {
+    return {
+      connectEscalation: {
+        address: connectEscalation.getEndpoint()?.getAddress() ?? '',
+        escalation: connectEscalation.getEscalation(),
+      },
+    };
+  }
+
+  const connectProgress = data.getConnectProgress();
+  if (connectProgress !== undefined) {
+    const elapsed = connectProgress.getElapsed();
+    return {
+      connectProgress: {
+        phase: convertFromConnectPhase(connectProgress.getPhase()),
+        finished: connectProgress.getFinished(),
+        elapsed: elapsed ? Math.round(elapsed.getSeconds() * 1000 + elapsed.getNanos() / 1_000_000) : 0,
+      },
+    };
+  }
+
   // Handle unknown daemon events
   const keys = Object.entries(data.toObject())
     .filter(([, value]) => value !== undefined)
@@ -1467,6 +1561,32 @@ function convertFromDaemonEvent(data: grpcTypes.DaemonEvent): DaemonEvent {
   throw new Error(`Unknown daemon event received containing ${keys}`);
 }
 
+function convertFromAppVersionInfo(versionInfo: grpcTypes.AppVersionInfo): IAppVersionInfo {
+  return {
+    supported: versionInfo.getSupported(),
+    suggestedUpgrade: versionInfo.getSuggestedUpgrade()?.getVersion(),
+  };
+}
+
+function convertFromConnectPhase(phase: grpcTypes.ConnectTrace.Phase): ConnectPhase {
+  switch (phase) {
+    case grpcTypes.ConnectTrace.Phase.RELAY_SELECTION:
+      return 'relay-selection';
+    case grpcTypes.ConnectTrace.Phase.OBFUSCATION:
+      return 'obfuscation';
+    case grpcTypes.ConnectTrace.Phase.TUNNEL_CREATION:
+      return 'tunnel-creation';
+    case grpcTypes.ConnectTrace.Phase.HANDSHAKE:
+      return 'handshake';
+    case grpcTypes.ConnectTrace.Phase.ROUTES:
+      return 'routes';
+    case grpcTypes.ConnectTrace.Phase.DNS:
+      return 'dns';
+    case grpcTypes.ConnectTrace.Phase.FIREWALL:
+      return 'firewall';
+  }
+}
+
 function convertFromOwnership(ownership: grpcTypes.Ownership): Ownership {
   switch (ownership) {
     case grpcTypes.Ownership.ANY:
@@ -1730,6 +1850,9 @@ function convertFromDeviceState(deviceState: grpcTypes.DeviceState): DeviceState
         accountAndDevice: {
           accountToken: accountAndDevice.getAccountToken(),
           device: device && convertFromDevice(device),
+          keyCreated: accountAndDevice.getKeyCreated()?.toDate(),
+          ipv4Address: accountAndDevice.getIpv4Address(),
+          ipv6Address: accountAndDevice.getIpv6Address(),
         },
       };
     }
@@ -1819,6 +1942,11 @@ function fillApiAccessMethodSetting<T extends grpcTypes.NewAccessMethodSetting>(
       accessMethod.setBridges(bridges);
       break;
     }
+    case 'system-proxy': {
+      const systemProxy = new grpcTypes.AccessMethod.SystemProxy();
+      accessMethod.setSystemProxy(systemProxy);
+      break;
+    }
     default:
       accessMethod.setCustom(convertToCustomProxy(method));
   }
@@ -1882,6 +2010,9 @@ function convertFromApiAccessMethodSettings(
   const bridges = convertFromApiAccessMethodSetting(
     ensureExists(accessMethods.getMullvadBridges(), "no 'Mullvad Bridges' access method was found"),
   ) as AccessMethodSetting<BridgesMethod>;
+  const systemProxy = convertFromApiAccessMethodSetting(
+    ensureExists(accessMethods.getSystemProxy(), "no 'System proxy' access method was found"),
+  ) as AccessMethodSetting<SystemProxyMethod>;
   const custom = accessMethods
     .getCustomList()
     .filter((setting) => setting.hasId() && setting.hasAccessMethod())
@@ -1892,6 +2023,7 @@ function convertFromApiAccessMethodSettings(
   return {
     direct,
     mullvadBridges: bridges,
+    systemProxy,
     custom,
   };
 }
@@ -1899,7 +2031,11 @@ function convertFromApiAccessMethodSettings(
 function isCustomProxy(
   accessMethod: AccessMethodSetting,
 ): accessMethod is AccessMethodSetting<CustomProxy> {
-  return accessMethod.type !== 'direct' && accessMethod.type !== 'bridges';
+  return (
+    accessMethod.type !== 'direct' &&
+    accessMethod.type !== 'bridges' &&
+    accessMethod.type !== 'system-proxy'
+  );
 }
 
 function convertFromApiAccessMethodSetting(
@@ -1922,6 +2058,8 @@ function convertFromAccessMethod(method: grpcTypes.AccessMethod): AccessMethod {
       return { type: 'direct' };
     case grpcTypes.AccessMethod.AccessMethodCase.BRIDGES:
       return { type: 'bridges' };
+    case grpcTypes.AccessMethod.AccessMethodCase.SYSTEM_PROXY:
+      return { type: 'system-proxy' };
     case grpcTypes.AccessMethod.AccessMethodCase.CUSTOM: {
       return convertFromCustomProxy(method.getCustom()!);
     }
@@ -63,10 +63,12 @@ export function getDefaultSettings(): ISettings {
           blockAdultContent: false,
           blockGambling: false,
           blockSocialMedia: false,
+          customBlocklists: [],
         },
         customOptions: {
           addresses: [],
         },
+        blockOnUnsupportedManager: false,
       },
     },
     obfuscationSettings: {
@@ -74,6 +76,9 @@ export function getDefaultSettings(): ISettings {
       udp2tcpSettings: {
         port: 'any',
       },
+      tlsSettings: {
+        sni: '',
+      },
     },
     customLists: [],
     apiAccessMethods: getDefaultApiAccessMethods(),
@@ -97,6 +102,12 @@ export function getDefaultApiAccessMethods(): ApiAccessMethodSettings {
       enabled: false,
       type: 'bridges',
     },
+    systemProxy: {
+      id: '',
+      name: 'System proxy',
+      enabled: false,
+      type: 'system-proxy',
+    },
     custom: [],
   };
 }
@@ -700,6 +700,15 @@ class ApplicationMain
           IpcMainEventChannel.settings.notifyApiAccessMethodSettingChange?.(
             daemonEvent.accessMethodSetting,
           );
+        } else if ('connectionVerification' in daemonEvent) {
+          const verification = JSON.stringify(daemonEvent.connectionVerification);
+          log.info(`Connection verification: ${verification}`);
+        } else if ('connectEscalation' in daemonEvent) {
+          const { address, escalation } = daemonEvent.connectEscalation;
+          log.info(`Connect deadline passed for ${address}, escalation ${escalation}`);
+        } else if ('connectProgress' in daemonEvent) {
+          const { phase, finished, elapsed } = daemonEvent.connectProgress;
+          log.verbose(`${finished ? 'Finished' : 'Started'} ${phase} after ${elapsed} ms`);
         }
       },
       (error: Error) => {
@@ -242,7 +242,7 @@ function AllowLan() {
   );
 }
 
-function useDns(setting: keyof IDnsOptions['defaultOptions']) {
+function useDns(setting: Exclude<keyof IDnsOptions['defaultOptions'], 'customBlocklists'>) {
   const dns = useSelector((state) => state.settings.dns);
   const { setDnsOptions } = useAppContext();
 
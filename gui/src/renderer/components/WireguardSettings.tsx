@@ -221,6 +221,10 @@ function ObfuscationSettings() {
         label: messages.pgettext('wireguard-settings-view', 'On (UDP-over-TCP)'),
         value: ObfuscationType.udp2tcp,
       },
+      {
+        label: messages.pgettext('wireguard-settings-view', 'On (TLS)'),
+        value: ObfuscationType.tls,
+      },
       {
         label: messages.gettext('Off'),
         value: ObfuscationType.off,
@@ -176,10 +176,12 @@ const initialState: ISettingsReduxState = {
       blockAdultContent: false,
       blockGambling: false,
       blockSocialMedia: false,
+      customBlocklists: [],
     },
     customOptions: {
       addresses: [],
     },
+    blockOnUnsupportedManager: false,
   },
   splitTunneling: false,
   splitTunnelingApplications: [],
@@ -188,6 +190,9 @@ const initialState: ISettingsReduxState = {
     udp2tcpSettings: {
       port: 'any',
     },
+    tlsSettings: {
+      sni: '',
+    },
   },
   customLists: [],
   apiAccessMethods: getDefaultApiAccessMethods(),
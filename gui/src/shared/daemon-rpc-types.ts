@@ -49,6 +49,7 @@ export enum ErrorStateCause {
   isOffline,
   splitTunnelError,
   needFullDiskPermissions,
+  unsupportedDnsManager,
 }
 
 export enum AuthFailedError {
@@ -95,6 +96,11 @@ export type ErrorState =
       cause: ErrorStateCause.setFirewallPolicyError;
       blockingError?: FirewallPolicyError;
       policyError: FirewallPolicyError;
+    }
+  | {
+      cause: ErrorStateCause.unsupportedDnsManager;
+      blockingError?: FirewallPolicyError;
+      dnsManager: string;
     };
 
 export type AfterDisconnect = 'nothing' | 'block' | 'reconnect';
@@ -112,7 +118,7 @@ export function tunnelTypeToString(tunnel: TunnelType): string {
 }
 
 export type RelayProtocol = 'tcp' | 'udp';
-export type EndpointObfuscationType = 'udp2tcp';
+export type EndpointObfuscationType = 'udp2tcp' | 'tls';
 
 export type Constraint<T> = 'any' | { only: T };
 export type LiftedConstraint<T> = 'any' | T;
@@ -173,7 +179,38 @@ export type DaemonEvent =
   | { appVersionInfo: IAppVersionInfo }
   | { device: DeviceEvent }
   | { deviceRemoval: Array<IDevice> }
-  | { accessMethodSetting: AccessMethodSetting };
+  | { accessMethodSetting: AccessMethodSetting }
+  | { connectionVerification: IConnectionVerification }
+  | { connectEscalation: IConnectEscalation }
+  | { connectProgress: IConnectProgress };
+
+export interface IConnectionVerification {
+  exitIp: string;
+  mullvadExitIp: boolean;
+  exitRelayMatches?: boolean;
+  dnsHijacked?: boolean;
+}
+
+export interface IConnectEscalation {
+  address: string;
+  escalation: number;
+}
+
+export type ConnectPhase =
+  | 'relay-selection'
+  | 'obfuscation'
+  | 'tunnel-creation'
+  | 'handshake'
+  | 'routes'
+  | 'dns'
+  | 'firewall';
+
+export interface IConnectProgress {
+  phase: ConnectPhase;
+  finished: boolean;
+  // Milliseconds since the connection attempt started
+  elapsed: number;
+}
 
 export interface ITunnelStateRelayInfo {
   endpoint: ITunnelEndpoint;
@@ -344,7 +381,14 @@ export interface IDnsOptions {
     blockAdultContent: boolean;
     blockGambling: boolean;
     blockSocialMedia: boolean;
+    customBlocklists: ICustomBlocklist[];
   };
+  blockOnUnsupportedManager: boolean;
+}
+
+export interface ICustomBlocklist {
+  source: { type: 'url'; url: string } | { type: 'file'; path: string };
+  enabled: boolean;
 }
 
 export interface IAppVersionInfo {
@@ -356,6 +400,9 @@ export interface IAppVersionInfo {
 export interface IAccountAndDevice {
   accountToken: AccountToken;
   device?: IDevice;
+  keyCreated?: Date;
+  ipv4Address?: string;
+  ipv6Address?: string;
 }
 
 export type LoggedInDeviceState = { type: 'logged in'; accountAndDevice: IAccountAndDevice };
@@ -415,15 +462,21 @@ export type Udp2TcpObfuscationSettings = {
   port: Constraint<number>;
 };
 
+export type TlsObfuscationSettings = {
+  sni: string;
+};
+
 export enum ObfuscationType {
   auto,
   off,
   udp2tcp,
+  tls,
 }
 
 export type ObfuscationSettings = {
   selectedObfuscation: ObfuscationType;
   udp2tcpSettings: Udp2TcpObfuscationSettings;
+  tlsSettings: TlsObfuscationSettings;
 };
 
 export interface IBridgeConstraints {
@@ -482,7 +535,8 @@ export type NamedCustomProxy = CustomProxy & { name: string };
 
 export type DirectMethod = { type: 'direct' };
 export type BridgesMethod = { type: 'bridges' };
-export type AccessMethod = DirectMethod | BridgesMethod | CustomProxy;
+export type SystemProxyMethod = { type: 'system-proxy' };
+export type AccessMethod = DirectMethod | BridgesMethod | SystemProxyMethod | CustomProxy;
 
 export type NamedAccessMethod<T extends AccessMethod> = T & { name: string };
 
@@ -499,6 +553,7 @@ export type AccessMethodSetting<
 export type ApiAccessMethodSettings = {
   direct: AccessMethodSetting<DirectMethod>;
   mullvadBridges: AccessMethodSetting<BridgesMethod>;
+  systemProxy: AccessMethodSetting<SystemProxyMethod>;
   custom: Array<AccessMethodSetting<CustomProxy>>;
 };
 
@@ -184,6 +184,16 @@ export class ErrorNotificationProvider
           );
         case ErrorStateCause.needFullDiskPermissions:
           return messages.pgettext('notifications', 'Failed to enable split tunneling.');
+        case ErrorStateCause.unsupportedDnsManager:
+          return sprintf(
+            // TRANSLATORS: Available placeholders:
+            // TRANSLATORS: %(dnsManager)s - name of the program that manages DNS, e.g. "dnsmasq"
+            messages.pgettext(
+              'notifications',
+              'Blocking to prevent DNS leaks, since DNS is managed by %(dnsManager)s, which is not supported.',
+            ),
+            { dnsManager: errorState.dnsManager },
+          );
         case ErrorStateCause.splitTunnelError:
           switch (process.platform ?? window.env.platform) {
             case 'darwin':
@@ -274,6 +284,22 @@ export class ErrorNotificationProvider
           steps: troubleshootSteps,
         },
       };
+    } else if (errorState.cause === ErrorStateCause.unsupportedDnsManager) {
+      return {
+        type: 'troubleshoot-dialog',
+        troubleshoot: {
+          details: messages.pgettext(
+            'troubleshoot',
+            'The app can only set DNS servers through systemd-resolved, NetworkManager or resolvconf.',
+          ),
+          steps: [
+            messages.pgettext(
+              'troubleshoot',
+              'Let systemd-resolved, NetworkManager or resolvconf manage /etc/resolv.conf.',
+            ),
+          ],
+        },
+      };
     } else if (errorState.cause === ErrorStateCause.needFullDiskPermissions) {
       let troubleshootButtons = undefined;
       if (this.context.showFullDiskAccessSettings) {
@@ -1,8 +1,11 @@
 #![cfg(target_os = "ios")]
 
 use std::{io, net::SocketAddr};
+use talpid_net::SocketProtection;
 use tokio::sync::oneshot;
-use tunnel_obfuscation::{create_obfuscator, Settings as ObfuscationSettings, Udp2TcpSettings};
+use tunnel_obfuscation::{
+    create_obfuscator, Settings as ObfuscationSettings, Udp2TcpSettings, Udp2TcpTuning,
+};
 
 mod ffi;
 pub use ffi::{start_tunnel_obfuscator_proxy, stop_tunnel_obfuscator_proxy, ProxyHandle};
@@ -17,7 +20,12 @@ impl TunnelObfuscatorRuntime {
         let runtime = tokio::runtime::Builder::new_current_thread()
             .enable_all()
             .build()?;
-        let settings = ObfuscationSettings::Udp2Tcp(Udp2TcpSettings { peer });
+        // The packet tunnel provider keeps its own traffic outside of the tunnel
+        let settings = ObfuscationSettings::Udp2Tcp(Udp2TcpSettings {
+            peer,
+            protection: SocketProtection::default(),
+            tuning: Udp2TcpTuning::default(),
+        });
 
         Ok(Self { runtime, settings })
     }
@@ -8,7 +8,7 @@ use futures::{
 };
 use hyper::StatusCode;
 use mullvad_types::account::{AccessToken, AccessTokenData, AccountToken};
-use std::{borrow::Cow, collections::HashMap};
+use std::collections::HashMap;
 use tokio::select;
 
 pub const AUTH_URL_PREFIX: &str = "auth/v1";
@@ -36,11 +36,7 @@ struct AccountState {
 }
 
 impl AccessTokenStore {
-    pub(crate) fn new(
-        service: RequestServiceHandle,
-        hostname: impl Into<Cow<'static, str>>,
-    ) -> Self {
-        let factory = rest::RequestFactory::new(hostname, None);
+    pub(crate) fn new(service: RequestServiceHandle, factory: RequestFactory) -> Self {
         let (tx, rx) = mpsc::unbounded();
         tokio::spawn(Self::service_requests(rx, service, factory));
         Self { tx }
@@ -172,7 +168,7 @@ async fn fetch_access_token(
 ) -> Result<AccessTokenData, rest::Error> {
     #[derive(serde::Serialize)]
     struct AccessTokenRequest {
-        account_number: String,
+        account_number: AccountToken,
     }
     let request = AccessTokenRequest {
         account_number: account_token,
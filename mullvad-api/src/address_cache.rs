@@ -1,6 +1,8 @@
 //! This module keeps track of the last known good API IP address and reads and stores it on disk.
+//! The other addresses that the API reported are stored along with it. If the primary address
+//! can't be reached, the cache can rotate through them, and through the hardcoded address.
 
-use super::API;
+use super::{custom_endpoint, API};
 use std::{io, net::SocketAddr, path::Path, sync::Arc};
 use tokio::{
     fs,
@@ -32,22 +34,45 @@ pub struct AddressCache {
 impl AddressCache {
     /// Initialize cache using the hardcoded address, and write changes to `write_path`.
     pub fn new(write_path: Option<Box<Path>>) -> Result<Self, Error> {
-        Self::new_inner(API.address(), write_path)
+        Self::new_inner(
+            API.address(),
+            Self::with_hardcoded_fallback(vec![]),
+            write_path,
+        )
     }
 
     pub fn with_static_addr(address: SocketAddr) -> Self {
-        Self::new_inner(address, None)
+        Self::new_inner(address, vec![], None)
             .expect("Failed to construct an address cache from a static address")
     }
 
     /// Initialize cache using `read_path`, and write changes to `write_path`.
     pub async fn from_file(read_path: &Path, write_path: Option<Box<Path>>) -> Result<Self, Error> {
         log::debug!("Loading API addresses from {}", read_path.display());
-        Self::new_inner(read_address_file(read_path).await?, write_path)
+        let mut addresses = read_address_file(read_path).await?;
+        let address = addresses.remove(0);
+        Self::new_inner(
+            address,
+            Self::with_hardcoded_fallback(addresses),
+            write_path,
+        )
     }
 
-    fn new_inner(address: SocketAddr, write_path: Option<Box<Path>>) -> Result<Self, Error> {
-        let cache = AddressCacheInner::from_address(address);
+    /// Returns the addresses to fall back on, followed by the hardcoded address, unless the API
+    /// address has been overridden.
+    fn with_hardcoded_fallback(mut fallbacks: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        if API.address.is_none() && !fallbacks.contains(&API.address()) {
+            fallbacks.push(API.address());
+        }
+        fallbacks
+    }
+
+    fn new_inner(
+        address: SocketAddr,
+        fallbacks: Vec<SocketAddr>,
+        write_path: Option<Box<Path>>,
+    ) -> Result<Self, Error> {
+        let cache = AddressCacheInner::new(address, fallbacks);
         log::debug!("Using API address: {}", cache.address);
 
         let address_cache = Self {
@@ -57,30 +82,53 @@ impl AddressCache {
         Ok(address_cache)
     }
 
-    /// Returns the address if the hostname equals `API.host`. Otherwise, returns `None`.
+    /// Returns the address if the hostname is the API hostname. Otherwise, returns `None`.
     pub async fn resolve_hostname(&self, hostname: &str) -> Option<SocketAddr> {
-        if hostname.eq_ignore_ascii_case(API.host()) {
+        if hostname.eq_ignore_ascii_case(&crate::api_host()) {
             Some(self.get_address().await)
         } else {
             None
         }
     }
 
-    /// Returns the currently selected address.
+    /// Returns the address of the custom API endpoint, if one is in use, or the current address.
     pub async fn get_address(&self) -> SocketAddr {
+        if let Some(address) = custom_endpoint::address() {
+            return address;
+        }
         self.inner.lock().await.address
     }
 
-    pub async fn set_address(&self, address: SocketAddr) -> Result<(), Error> {
+    /// Use the addresses reported by the API. The first one becomes the primary address, and the
+    /// others are fallbacks.
+    pub async fn set_addresses(&self, addresses: &[SocketAddr]) -> Result<(), Error> {
+        let Some((&address, fallbacks)) = addresses.split_first() else {
+            return Ok(());
+        };
+        let fallbacks = Self::with_hardcoded_fallback(fallbacks.to_vec());
         let mut inner = self.inner.lock().await;
-        if address != inner.address {
-            self.save_to_disk(&address).await?;
-            inner.address = address;
+        if address != inner.candidates[0] || fallbacks != inner.fallbacks {
+            self.save_to_disk(addresses).await?;
+            inner.fallbacks = fallbacks;
+            inner.set_primary(address);
         }
         Ok(())
     }
 
-    async fn save_to_disk(&self, address: &SocketAddr) -> Result<(), Error> {
+    /// Switch to the next known address, wrapping around to the primary address once all
+    /// fallback addresses have been tried. The selection is not persisted. Returns the newly
+    /// selected address.
+    pub async fn next_address(&self) -> SocketAddr {
+        let mut inner = self.inner.lock().await;
+        let previous = inner.address;
+        inner.rotate();
+        if inner.address != previous {
+            log::debug!("Switching API address from {previous} to {}", inner.address);
+        }
+        inner.address
+    }
+
+    async fn save_to_disk(&self, addresses: &[SocketAddr]) -> Result<(), Error> {
         let write_path = match self.write_path.as_ref() {
             Some(write_path) => write_path,
             None => return Ok(()),
@@ -89,8 +137,10 @@ impl AddressCache {
         let mut file = mullvad_fs::AtomicFile::new(&**write_path)
             .await
             .map_err(Error::Open)?;
-        let mut contents = address.to_string();
-        contents += "\n";
+        let contents: String = addresses
+            .iter()
+            .map(|address| format!("{address}\n"))
+            .collect();
         file.write_all(contents.as_bytes())
             .await
             .map_err(Error::Write)?;
@@ -100,20 +150,116 @@ impl AddressCache {
 
 #[derive(Clone, PartialEq, Eq)]
 struct AddressCacheInner {
+    /// The currently selected address.
     address: SocketAddr,
+    /// All addresses to rotate through. The first one is the last known good address.
+    candidates: Vec<SocketAddr>,
+    /// Index of `address` in `candidates`.
+    index: usize,
+    /// Addresses to fall back on if the primary address can't be reached.
+    fallbacks: Vec<SocketAddr>,
 }
 
 impl AddressCacheInner {
-    fn from_address(address: SocketAddr) -> Self {
-        Self { address }
+    fn new(address: SocketAddr, fallbacks: Vec<SocketAddr>) -> Self {
+        let mut inner = Self {
+            address,
+            candidates: vec![],
+            index: 0,
+            fallbacks,
+        };
+        inner.set_primary(address);
+        inner
+    }
+
+    fn set_primary(&mut self, address: SocketAddr) {
+        self.candidates = std::iter::once(address)
+            .chain(
+                self.fallbacks
+                    .iter()
+                    .copied()
+                    .filter(|fallback| *fallback != address),
+            )
+            .collect();
+        self.index = 0;
+        self.address = address;
+    }
+
+    fn rotate(&mut self) {
+        self.index = (self.index + 1) % self.candidates.len();
+        self.address = self.candidates[self.index];
     }
 }
 
-async fn read_address_file(path: &Path) -> Result<SocketAddr, Error> {
+/// Read the addresses in the cache file, one per line. The first one is the primary address.
+async fn read_address_file(path: &Path) -> Result<Vec<SocketAddr>, Error> {
     let mut file = fs::File::open(path).await.map_err(Error::Open)?;
-    let mut address = String::new();
-    file.read_to_string(&mut address)
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)
         .await
         .map_err(Error::Read)?;
-    address.trim().parse().map_err(|_| Error::Parse)
+    parse_addresses(&contents)
+}
+
+fn parse_addresses(contents: &str) -> Result<Vec<SocketAddr>, Error> {
+    let addresses = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| line.parse().map_err(|_| Error::Parse))
+        .collect::<Result<Vec<_>, _>>()?;
+    if addresses.is_empty() {
+        return Err(Error::Parse);
+    }
+    Ok(addresses)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rotation_wraps_around_to_primary() {
+        let primary: SocketAddr = "10.0.0.1:443".parse().unwrap();
+        let fallback: SocketAddr = "10.0.0.2:443".parse().unwrap();
+        let mut inner = AddressCacheInner::new(primary, vec![primary, fallback]);
+
+        inner.rotate();
+        assert_eq!(inner.address, fallback);
+        inner.rotate();
+        assert_eq!(inner.address, primary);
+    }
+
+    #[test]
+    fn test_parse_addresses() {
+        // Caches written before fallbacks were stored hold a single address
+        assert_eq!(
+            parse_addresses("10.0.0.1:443\n").unwrap(),
+            vec!["10.0.0.1:443".parse::<SocketAddr>().unwrap()]
+        );
+        assert_eq!(
+            parse_addresses("10.0.0.1:443\n10.0.0.2:443\n")
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(parse_addresses("").is_err());
+        assert!(parse_addresses("10.0.0.1\n").is_err());
+    }
+
+    #[test]
+    fn test_new_primary_resets_rotation() {
+        let primary: SocketAddr = "10.0.0.1:443".parse().unwrap();
+        let fallback: SocketAddr = "10.0.0.2:443".parse().unwrap();
+        let new_primary: SocketAddr = "10.0.0.3:443".parse().unwrap();
+        let mut inner = AddressCacheInner::new(primary, vec![fallback]);
+
+        inner.rotate();
+        inner.set_primary(new_primary);
+        assert_eq!(inner.address, new_primary);
+        inner.rotate();
+        assert_eq!(inner.address, fallback);
+        inner.rotate();
+        assert_eq!(inner.address, new_primary);
+    }
 }
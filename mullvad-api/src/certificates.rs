@@ -0,0 +1,123 @@
+//! Updates of the certificates that the app pins, delivered through the version check. This makes
+//! it possible to rotate certificates between app releases.
+
+use crate::{signature, tls_stream};
+use serde::{Deserialize, Serialize};
+
+/// A [`CertificateBundle`] along with a signature of it made by Mullvad.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedCertificateUpdate {
+    /// JSON encoded [`CertificateBundle`].
+    pub bundle: String,
+    /// Hex encoded ed25519 signature of `bundle`.
+    pub signature: String,
+}
+
+/// Certificates to use instead of the bundled ones. Certificates that are missing from the bundle
+/// are reverted to the bundled ones.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CertificateBundle {
+    /// Increased with every update. Bundles that are not newer than the one in use should be
+    /// ignored, so that old bundles can't be replayed.
+    pub serial: u64,
+    /// PEM encoded root certificates that the API is verified with.
+    pub api_root_certs: Option<String>,
+    /// PEM encoded CA certificate that OpenVPN relays are verified with.
+    pub openvpn_ca: Option<String>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Failed to verify the certificate update")]
+    Signature(#[from] signature::Error),
+
+    #[error("Failed to parse the certificate update")]
+    Parse(#[source] serde_json::Error),
+
+    #[error("The certificate update contains an invalid certificate")]
+    InvalidCertificate,
+}
+
+impl SignedCertificateUpdate {
+    /// Verify the signature of the update, and that all certificates in it can be parsed.
+    pub fn verify(&self) -> Result<CertificateBundle, Error> {
+        self.verify_with_key(signature::signing_public_key()?)
+    }
+
+    fn verify_with_key(&self, public_key: &str) -> Result<CertificateBundle, Error> {
+        signature::verify(self.bundle.as_bytes(), &self.signature, public_key)?;
+        let bundle: CertificateBundle = serde_json::from_str(&self.bundle).map_err(Error::Parse)?;
+        for pem in [&bundle.api_root_certs, &bundle.openvpn_ca]
+            .into_iter()
+            .flatten()
+        {
+            tls_stream::read_cert_store(pem.as_bytes()).ok_or(Error::InvalidCertificate)?;
+        }
+        Ok(bundle)
+    }
+}
+
+/// Verify new connections to the API with the root certificates in `pem`, or with the bundled
+/// ones if `pem` is `None`. Existing connections are not affected.
+pub fn set_api_root_certificates(pem: Option<&str>) -> Result<(), Error> {
+    tls_stream::set_root_certificates(pem.map(str::as_bytes))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ring::{
+        rand::SystemRandom,
+        signature::{Ed25519KeyPair, KeyPair},
+    };
+
+    const ROOT_CERT: &str = include_str!("../le_root_cert.pem");
+
+    fn sign(bundle: String) -> (SignedCertificateUpdate, String) {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let update = SignedCertificateUpdate {
+            signature: hex::encode(key_pair.sign(bundle.as_bytes())),
+            bundle,
+        };
+        (update, hex::encode(key_pair.public_key()))
+    }
+
+    #[test]
+    fn test_verify_certificate_update() {
+        let bundle = serde_json::json!({
+            "serial": 1,
+            "api_root_certs": ROOT_CERT,
+            "openvpn_ca": null,
+        });
+        let (update, public_key) = sign(bundle.to_string());
+
+        let bundle = update.verify_with_key(&public_key).unwrap();
+        assert_eq!(bundle.serial, 1);
+        assert_eq!(bundle.api_root_certs.as_deref(), Some(ROOT_CERT));
+
+        let tampered = SignedCertificateUpdate {
+            bundle: update.bundle.replace("\"serial\":1", "\"serial\":2"),
+            ..update
+        };
+        assert!(matches!(
+            tampered.verify_with_key(&public_key),
+            Err(Error::Signature(signature::Error::InvalidSignature))
+        ));
+    }
+
+    #[test]
+    fn test_reject_invalid_certificate() {
+        let bundle = serde_json::json!({
+            "serial": 1,
+            "api_root_certs": null,
+            "openvpn_ca": "not a certificate",
+        });
+        let (update, public_key) = sign(bundle.to_string());
+
+        assert!(matches!(
+            update.verify_with_key(&public_key),
+            Err(Error::InvalidCertificate)
+        ));
+    }
+}
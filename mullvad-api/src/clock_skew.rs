@@ -0,0 +1,181 @@
+//! Detects that the system clock is wrong, by comparing it with the `Date` header of API responses.
+//! A wrong clock makes the certificate of the API appear expired or not yet valid, which is a
+//! common reason that devices with a bad real-time clock cannot connect.
+//!
+//! Certificates that are only invalid because of the system clock can optionally be accepted, as
+//! long as the clock is off by no more than [`CLOCK_SKEW_TOLERANCE_VAR`] seconds.
+
+use crate::env::CLOCK_SKEW_TOLERANCE_VAR;
+use hyper::header::{self, HeaderMap, HeaderValue};
+use mullvad_types::connection_diagnosis::ClockSkew;
+use once_cell::sync::Lazy;
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+use tokio_rustls::rustls::{
+    self,
+    client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+    Certificate, CertificateError, RootCertStore, ServerName,
+};
+
+/// Offsets smaller than this are not reported. The `Date` header only has a resolution of one
+/// second, and responses may be delayed in transit.
+const MIN_REPORTED_SKEW: Duration = Duration::from_secs(60);
+
+/// Upper bound of the tolerance, so that long expired certificates are never accepted.
+const MAX_TOLERANCE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// How far off the system clock may be for a certificate to be accepted anyway. Zero unless
+/// [`CLOCK_SKEW_TOLERANCE_VAR`] is set.
+static TOLERANCE: Lazy<Duration> = Lazy::new(|| {
+    let Ok(value) = std::env::var(CLOCK_SKEW_TOLERANCE_VAR) else {
+        return Duration::ZERO;
+    };
+    match value.parse() {
+        Ok(secs) => {
+            let tolerance = Duration::from_secs(secs).min(MAX_TOLERANCE);
+            log::info!(
+                "Tolerating a clock skew of up to {} seconds when verifying API certificates",
+                tolerance.as_secs()
+            );
+            tolerance
+        }
+        Err(_) => {
+            log::error!("Invalid value for {CLOCK_SKEW_TOLERANCE_VAR}: {value}");
+            Duration::ZERO
+        }
+    }
+});
+
+static CLOCK_SKEW: Lazy<watch::Sender<Option<ClockSkew>>> = Lazy::new(|| watch::channel(None).0);
+
+/// Return a receiver of the detected clock skew, which is `None` while the system clock appears to
+/// be correct. Small changes of the offset are not sent.
+pub fn subscribe() -> watch::Receiver<Option<ClockSkew>> {
+    CLOCK_SKEW.subscribe()
+}
+
+/// Compare the system clock with the `Date` header of a response.
+pub(crate) fn observe_response(headers: &HeaderMap<HeaderValue>) {
+    let Some(server_time) = headers
+        .get(header::DATE)
+        .and_then(|date| date.to_str().ok())
+        .and_then(|date| httpdate::parse_http_date(date).ok())
+    else {
+        return;
+    };
+    let skew = clock_skew(SystemTime::now(), server_time);
+    CLOCK_SKEW.send_if_modified(|current| {
+        let changed = match (*current, skew) {
+            (Some(current), Some(skew)) => {
+                current.offset_secs.abs_diff(skew.offset_secs) >= MIN_REPORTED_SKEW.as_secs()
+            }
+            (current, skew) => current.is_some() != skew.is_some(),
+        };
+        if changed {
+            match skew {
+                Some(skew) => log::warn!("Clock skew detected: {skew}"),
+                None => log::info!("The system clock no longer appears to be skewed"),
+            }
+            *current = skew;
+        }
+        changed
+    });
+}
+
+/// Return the offset of `now` from `server_time`, unless it is too small to matter.
+fn clock_skew(now: SystemTime, server_time: SystemTime) -> Option<ClockSkew> {
+    let (offset, ahead) = match now.duration_since(server_time) {
+        Ok(offset) => (offset, true),
+        Err(error) => (error.duration(), false),
+    };
+    if offset < MIN_REPORTED_SKEW {
+        return None;
+    }
+    let offset_secs = i64::try_from(offset.as_secs()).unwrap_or(i64::MAX);
+    Some(ClockSkew {
+        offset_secs: if ahead { offset_secs } else { -offset_secs },
+    })
+}
+
+/// Verifies certificates like [`WebPkiVerifier`], but accepts certificates that are expired or not
+/// yet valid if they would be valid with the system clock adjusted by up to [`TOLERANCE`].
+pub(crate) struct SkewTolerantVerifier {
+    inner: WebPkiVerifier,
+}
+
+impl SkewTolerantVerifier {
+    pub fn new(roots: RootCertStore) -> Self {
+        Self {
+            inner: WebPkiVerifier::new(roots, None),
+        }
+    }
+}
+
+impl ServerCertVerifier for SkewTolerantVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let result = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        );
+        if TOLERANCE.is_zero() {
+            return result;
+        }
+        let adjusted_now = match &result {
+            Err(rustls::Error::InvalidCertificate(CertificateError::NotValidYet)) => {
+                now.checked_add(*TOLERANCE)
+            }
+            Err(rustls::Error::InvalidCertificate(CertificateError::Expired)) => {
+                now.checked_sub(*TOLERANCE)
+            }
+            _ => None,
+        };
+        let Some(adjusted_now) = adjusted_now else {
+            return result;
+        };
+        log::warn!("The API certificate is only valid if the system clock is off, retrying");
+        // Signed certificate timestamps are not verified, so there is nothing to pass on
+        self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            &mut std::iter::empty(),
+            ocsp_response,
+            adjusted_now,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_clock_skew() {
+        let server_time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        assert_eq!(
+            clock_skew(server_time + Duration::from_secs(5), server_time),
+            None
+        );
+        assert_eq!(
+            clock_skew(server_time + Duration::from_secs(3600), server_time),
+            Some(ClockSkew { offset_secs: 3600 })
+        );
+        assert_eq!(
+            clock_skew(server_time - Duration::from_secs(3600), server_time),
+            Some(ClockSkew { offset_secs: -3600 })
+        );
+    }
+}
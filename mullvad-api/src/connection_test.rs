@@ -0,0 +1,133 @@
+//! Tests whether the API can be reached using a specific [`ApiConnectionMode`], one step at a
+//! time, so that it's possible to tell why a connection mode doesn't work.
+
+use crate::{
+    https_client_with_sni::{HttpsConnectorWithSni, InnerConnectionMode},
+    proxy::ApiConnectionMode,
+    AddressCache, APP_URL_PREFIX,
+};
+use hyper::{header, Body, Method, Request, Uri};
+use mullvad_types::access_method::{TestResult, TestStep, TestStepResult};
+use std::{
+    future::Future,
+    io,
+    time::{Duration, Instant},
+};
+use talpid_net::SocketProtection;
+
+/// How long to wait for the API to respond to the test request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Records how long each step of connecting takes, and whether it failed.
+#[derive(Default)]
+pub(crate) struct ConnectSteps {
+    steps: Vec<TestStepResult>,
+    proxied: bool,
+}
+
+impl ConnectSteps {
+    fn new(proxied: bool) -> Self {
+        ConnectSteps {
+            steps: vec![],
+            proxied,
+        }
+    }
+
+    /// Run `step` to completion and record the outcome. [`TestStep::ProxyHandshake`] is only
+    /// recorded when a proxy is used.
+    pub async fn run<T>(
+        &mut self,
+        step: TestStep,
+        step_fut: impl Future<Output = io::Result<T>>,
+    ) -> io::Result<T> {
+        if step == TestStep::ProxyHandshake && !self.proxied {
+            return step_fut.await;
+        }
+        let start = Instant::now();
+        let result = step_fut.await;
+        self.steps.push(TestStepResult {
+            step,
+            duration: start.elapsed(),
+            error: result.as_ref().err().map(|error| error.to_string()),
+        });
+        result
+    }
+}
+
+/// Try to reach the API using `connection_mode`. Unlike regular requests, this does not retry
+/// or fall back to other addresses.
+pub(crate) async fn test_connection_mode(
+    connection_mode: ApiConnectionMode,
+    address_cache: AddressCache,
+    protection: SocketProtection,
+) -> TestResult {
+    let start = Instant::now();
+    let mut steps = ConnectSteps::new(connection_mode.is_proxy());
+    let http_status = run_steps(connection_mode, address_cache, &mut steps, protection)
+        .await
+        .ok();
+
+    TestResult {
+        steps: steps.steps,
+        http_status,
+        total: start.elapsed(),
+    }
+}
+
+/// Run every step in turn, stopping at the first one that fails. Returns the HTTP status code
+/// of the response.
+async fn run_steps(
+    connection_mode: ApiConnectionMode,
+    address_cache: AddressCache,
+    steps: &mut ConnectSteps,
+    protection: SocketProtection,
+) -> io::Result<u16> {
+    let connection_mode = match InnerConnectionMode::try_from(connection_mode) {
+        Ok(connection_mode) => connection_mode,
+        Err(error) => {
+            let error = io::Error::new(io::ErrorKind::InvalidInput, error);
+            return steps
+                .run(TestStep::ProxyHandshake, async { Err(error) })
+                .await;
+        }
+    };
+
+    let hostname = crate::api_host();
+    let uri: Uri = format!("https://{hostname}/{APP_URL_PREFIX}/api-addrs")
+        .parse()
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+
+    let addr = steps
+        .run(
+            TestStep::ResolveAddress,
+            HttpsConnectorWithSni::resolve_address(address_cache, uri.clone()),
+        )
+        .await?;
+
+    let connection = connection_mode
+        .connect(&hostname, &addr, steps, protection)
+        .await?;
+
+    steps
+        .run(TestStep::HttpRequest, async move {
+            let (mut sender, connection) = hyper::client::conn::handshake(connection)
+                .await
+                .map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+            tokio::spawn(async move {
+                let _ = connection.await;
+            });
+
+            let request = Request::builder()
+                .method(Method::HEAD)
+                .uri(uri.path())
+                .header(header::HOST, &hostname)
+                .body(Body::empty())
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+            let response = tokio::time::timeout(REQUEST_TIMEOUT, sender.send_request(request))
+                .await
+                .map_err(|error| io::Error::new(io::ErrorKind::TimedOut, error))?
+                .map_err(|error| io::Error::new(io::ErrorKind::Other, error))?;
+            Ok(response.status().as_u16())
+        })
+        .await
+}
@@ -0,0 +1,175 @@
+//! An API deployment that is used instead of the Mullvad API, such as a self-hosted test API.
+//! Unlike the environment variables in [`crate::env`], it is read from the settings and can be
+//! replaced while the daemon is running. Requests that are already in flight are not affected.
+
+use crate::tls_stream;
+use mullvad_types::settings::CustomApiEndpoint;
+use std::{
+    net::SocketAddr,
+    sync::{Arc, RwLock},
+};
+use tokio_rustls::rustls::{ClientConfig, ServerName};
+
+static CUSTOM_ENDPOINT: RwLock<Option<Arc<CustomEndpoint>>> = RwLock::new(None);
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Invalid API hostname: {0}")]
+    InvalidHost(String),
+
+    #[error("The root certificates are not valid PEM encoded certificates")]
+    InvalidCertificate,
+
+    #[error("TLS can only be disabled in builds with the api-override feature")]
+    DisableTlsNotAllowed,
+
+    #[error("Root certificates cannot be pinned when TLS is disabled")]
+    CertificatesWithoutTls,
+}
+
+/// A validated [`CustomApiEndpoint`].
+pub struct CustomEndpoint {
+    host: String,
+    address: SocketAddr,
+    /// Config that trusts the pinned root certificates only, if there are any.
+    tls_config: Option<Arc<ClientConfig>>,
+    #[cfg(feature = "api-override")]
+    disable_tls: bool,
+    force_direct: bool,
+}
+
+impl CustomEndpoint {
+    pub fn new(endpoint: &CustomApiEndpoint) -> Result<Self, Error> {
+        if !matches!(
+            ServerName::try_from(endpoint.host.as_str()),
+            Ok(ServerName::DnsName(_))
+        ) {
+            return Err(Error::InvalidHost(endpoint.host.clone()));
+        }
+        if endpoint.disable_tls {
+            if !cfg!(feature = "api-override") {
+                return Err(Error::DisableTlsNotAllowed);
+            }
+            if endpoint.root_certificates.is_some() {
+                return Err(Error::CertificatesWithoutTls);
+            }
+        }
+        let tls_config = match &endpoint.root_certificates {
+            Some(pem) => {
+                let cert_store =
+                    tls_stream::read_cert_store(pem.as_bytes()).ok_or(Error::InvalidCertificate)?;
+                Some(tls_stream::tls_config(cert_store))
+            }
+            None => None,
+        };
+        Ok(Self {
+            host: endpoint.host.to_lowercase(),
+            address: endpoint.address,
+            tls_config,
+            #[cfg(feature = "api-override")]
+            disable_tls: endpoint.disable_tls,
+            force_direct: endpoint.force_direct,
+        })
+    }
+}
+
+/// Use `endpoint` instead of the Mullvad API for new connections, or go back to the Mullvad API if
+/// it is `None`.
+pub fn set(endpoint: Option<CustomEndpoint>) {
+    match &endpoint {
+        Some(endpoint) => log::info!(
+            "Using custom API endpoint {} at {}",
+            endpoint.host,
+            endpoint.address
+        ),
+        None => {
+            if is_set() {
+                log::info!("No longer using a custom API endpoint");
+            }
+        }
+    }
+    *CUSTOM_ENDPOINT.write().unwrap() = endpoint.map(Arc::new);
+}
+
+/// Whether a custom API endpoint is in use.
+pub fn is_set() -> bool {
+    get().is_some()
+}
+
+/// Whether the API must be reached without bridges or proxies.
+pub fn force_direct() -> bool {
+    get().is_some_and(|endpoint| endpoint.force_direct)
+}
+
+pub(crate) fn host() -> Option<String> {
+    get().map(|endpoint| endpoint.host.clone())
+}
+
+pub(crate) fn address() -> Option<SocketAddr> {
+    get().map(|endpoint| endpoint.address)
+}
+
+/// Return the TLS config for connections to `hostname`, if it is the custom API endpoint and it
+/// has pinned root certificates.
+pub(crate) fn pinned_tls_config(hostname: &str) -> Option<Arc<ClientConfig>> {
+    get()
+        .filter(|endpoint| endpoint.host.eq_ignore_ascii_case(hostname))
+        .and_then(|endpoint| endpoint.tls_config.clone())
+}
+
+/// Whether connections to `hostname` should use plain HTTP.
+#[cfg(feature = "api-override")]
+pub(crate) fn tls_disabled(hostname: &str) -> bool {
+    get().is_some_and(|endpoint| {
+        endpoint.disable_tls && endpoint.host.eq_ignore_ascii_case(hostname)
+    })
+}
+
+fn get() -> Option<Arc<CustomEndpoint>> {
+    CUSTOM_ENDPOINT.read().unwrap().clone()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn endpoint(host: &str) -> CustomApiEndpoint {
+        CustomApiEndpoint {
+            host: host.to_owned(),
+            address: "127.0.0.1:443".parse().unwrap(),
+            root_certificates: None,
+            disable_tls: false,
+            force_direct: false,
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(CustomEndpoint::new(&endpoint("api.stagemole.eu")).is_ok());
+        assert!(matches!(
+            CustomEndpoint::new(&endpoint("127.0.0.1")),
+            Err(Error::InvalidHost(_))
+        ));
+        assert!(matches!(
+            CustomEndpoint::new(&endpoint("not a host")),
+            Err(Error::InvalidHost(_))
+        ));
+        assert!(matches!(
+            CustomEndpoint::new(&CustomApiEndpoint {
+                root_certificates: Some("not a certificate".to_owned()),
+                ..endpoint("api.stagemole.eu")
+            }),
+            Err(Error::InvalidCertificate)
+        ));
+
+        let result = CustomEndpoint::new(&CustomApiEndpoint {
+            disable_tls: true,
+            ..endpoint("api.stagemole.eu")
+        });
+        if cfg!(feature = "api-override") {
+            assert!(result.is_ok());
+        } else {
+            assert!(matches!(result, Err(Error::DisableTlsNotAllowed)));
+        }
+    }
+}
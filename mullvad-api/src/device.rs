@@ -56,7 +56,8 @@ impl DevicesProxy {
             let request = factory
                 .post_json(&format!("{ACCOUNTS_URL_PREFIX}/devices"), &submission)?
                 .account(account)?
-                .expected_status(&[StatusCode::CREATED]);
+                .expected_status(&[StatusCode::CREATED])
+                .priority(rest::Priority::Critical);
             let response = service.request(request).await?;
             let DeviceResponse {
                 id,
@@ -156,6 +157,7 @@ impl DevicesProxy {
                     &req_body,
                 )?
                 .expected_status(&[StatusCode::OK])
+                .priority(rest::Priority::Critical)
                 .account(account)?;
             let response = service.request(request).await?;
             let DeviceResponse {
@@ -0,0 +1,92 @@
+//! Tunnels connections through an HTTP proxy using the `CONNECT` method.
+
+use std::{io, net::SocketAddr};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Maximum size of the response headers that the proxy may send.
+const MAX_RESPONSE_SIZE: usize = 8 * 1024;
+
+/// Ask the HTTP proxy at the other end of `stream` to open a tunnel to `target`. Once this
+/// returns, `stream` is connected to `target`.
+pub(crate) async fn connect<S>(mut stream: S, target: &SocketAddr) -> io::Result<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let request = format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n\r\n");
+    stream.write_all(request.as_bytes()).await?;
+    stream.flush().await?;
+
+    let response = read_response_head(&mut stream).await?;
+    check_status(&response)?;
+    Ok(stream)
+}
+
+/// Read until the end of the response headers. This reads one byte at a time so that nothing
+/// that the target sends after the headers is consumed.
+async fn read_response_head<S: AsyncRead + Unpin>(stream: &mut S) -> io::Result<String> {
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_RESPONSE_SIZE {
+            return Err(proxy_error("Response from HTTP proxy is too large"));
+        }
+        response.push(stream.read_u8().await?);
+    }
+    String::from_utf8(response).map_err(|_| proxy_error("Invalid response from HTTP proxy"))
+}
+
+fn check_status(response: &str) -> io::Result<()> {
+    let status_line = response.lines().next().unwrap_or_default();
+    let mut parts = status_line.splitn(3, ' ');
+    let (Some(version), Some(status)) = (parts.next(), parts.next()) else {
+        return Err(proxy_error("Invalid response from HTTP proxy"));
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Err(proxy_error("Invalid response from HTTP proxy"));
+    }
+    match status.parse::<u16>() {
+        Ok(200..=299) => Ok(()),
+        _ => Err(proxy_error(&format!(
+            "HTTP proxy refused to connect: {status_line}"
+        ))),
+    }
+}
+
+fn proxy_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, message.to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::io::duplex;
+
+    #[tokio::test]
+    async fn test_connect() {
+        let target: SocketAddr = "10.0.0.1:443".parse().unwrap();
+        let (client, mut proxy) = duplex(1024);
+
+        let proxy_task = tokio::spawn(async move {
+            let request = read_response_head(&mut proxy).await.unwrap();
+            assert!(request.starts_with("CONNECT 10.0.0.1:443 HTTP/1.1\r\n"));
+            proxy
+                .write_all(b"HTTP/1.1 200 Connection established\r\n\r\nhello")
+                .await
+                .unwrap();
+        });
+
+        let mut stream = connect(client, &target).await.unwrap();
+        proxy_task.await.unwrap();
+
+        // Data sent after the headers must be left in the stream
+        let mut data = String::new();
+        stream.read_to_string(&mut data).await.unwrap();
+        assert_eq!(data, "hello");
+    }
+
+    #[test]
+    fn test_check_status() {
+        assert!(check_status("HTTP/1.0 200 OK\r\n\r\n").is_ok());
+        assert!(check_status("HTTP/1.1 407 Proxy Authentication Required\r\n\r\n").is_err());
+        assert!(check_status("SSH-2.0-OpenSSH\r\n\r\n").is_err());
+    }
+}
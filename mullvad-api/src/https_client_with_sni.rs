@@ -1,18 +1,19 @@
 use crate::{
     abortable_stream::{AbortableStream, AbortableStreamHandle},
+    connection_test::ConnectSteps,
+    http_proxy,
     proxy::{ApiConnection, ApiConnectionMode, ProxyConfig},
     tls_stream::TlsStream,
     AddressCache,
 };
 use futures::{channel::mpsc, future, pin_mut, StreamExt};
-#[cfg(target_os = "android")]
-use futures::{channel::oneshot, sink::SinkExt};
 use http::uri::Scheme;
 use hyper::{
     client::connect::dns::{GaiResolver, Name},
     service::Service,
     Uri,
 };
+use mullvad_types::access_method::TestStep;
 use shadowsocks::{
     config::ServerType,
     context::{Context as SsContext, SharedContext},
@@ -20,8 +21,6 @@ use shadowsocks::{
     relay::tcprelay::ProxyClientStream,
     ServerConfig,
 };
-#[cfg(target_os = "android")]
-use std::os::unix::io::{AsRawFd, RawFd};
 use std::{
     fmt,
     future::Future,
@@ -33,7 +32,8 @@ use std::{
     task::{Context, Poll},
     time::Duration,
 };
-use talpid_types::{net::proxy, ErrorExt};
+use talpid_net::SocketProtection;
+use talpid_types::{net::proxy, secret::SecretString, ErrorExt};
 use tokio::{
     io::{AsyncRead, AsyncWrite},
     net::{TcpSocket, TcpStream},
@@ -70,35 +70,33 @@ enum HttpsConnectorRequest {
 }
 
 #[derive(Clone)]
-enum InnerConnectionMode {
+pub(crate) enum InnerConnectionMode {
     /// Connect directly to the target.
     Direct,
     /// Connect to the destination via a Shadowsocks proxy.
     Shadowsocks(ShadowsocksConfig),
     /// Connect to the destination via a Socks proxy.
     Socks5(SocksConfig),
+    /// Connect to the destination via an HTTP proxy.
+    HttpConnect(SocketAddr),
 }
 
 impl InnerConnectionMode {
-    async fn connect(
+    /// Connect to `addr`, recording how long each step takes in `steps`.
+    pub(crate) async fn connect(
         self,
         hostname: &str,
         addr: &SocketAddr,
-        #[cfg(target_os = "android")] socket_bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>,
+        steps: &mut ConnectSteps,
+        protection: SocketProtection,
     ) -> Result<ApiConnection, std::io::Error> {
         match self {
             // Set up a TCP-socket connection.
             InnerConnectionMode::Direct => {
                 let first_hop = *addr;
                 let make_proxy_stream = |tcp_stream| async { Ok(tcp_stream) };
-                Self::connect_proxied(
-                    first_hop,
-                    hostname,
-                    make_proxy_stream,
-                    #[cfg(target_os = "android")]
-                    socket_bypass_tx,
-                )
-                .await
+                Self::connect_proxied(first_hop, hostname, make_proxy_stream, steps, protection)
+                    .await
             }
             // Set up a Shadowsocks-connection.
             InnerConnectionMode::Shadowsocks(shadowsocks) => {
@@ -111,14 +109,8 @@ impl InnerConnectionMode {
                         *addr,
                     ))
                 };
-                Self::connect_proxied(
-                    first_hop,
-                    hostname,
-                    make_proxy_stream,
-                    #[cfg(target_os = "android")]
-                    socket_bypass_tx,
-                )
-                .await
+                Self::connect_proxied(first_hop, hostname, make_proxy_stream, steps, protection)
+                    .await
             }
             // Set up a SOCKS5-connection.
             InnerConnectionMode::Socks5(socks) => {
@@ -143,14 +135,13 @@ impl InnerConnectionMode {
                         io::Error::new(io::ErrorKind::Other, format!("SOCKS error: {error}"))
                     })
                 };
-                Self::connect_proxied(
-                    first_hop,
-                    hostname,
-                    make_proxy_stream,
-                    #[cfg(target_os = "android")]
-                    socket_bypass_tx,
-                )
-                .await
+                Self::connect_proxied(first_hop, hostname, make_proxy_stream, steps, protection)
+                    .await
+            }
+            // Set up a tunnel through an HTTP proxy.
+            InnerConnectionMode::HttpConnect(proxy) => {
+                let make_proxy_stream = |tcp_stream| http_proxy::connect(tcp_stream, addr);
+                Self::connect_proxied(proxy, hostname, make_proxy_stream, steps, protection).await
             }
         }
     }
@@ -171,28 +162,36 @@ impl InnerConnectionMode {
         first_hop: SocketAddr,
         hostname: &str,
         make_proxy_stream: ProxyFactory,
-        #[cfg(target_os = "android")] socket_bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>,
+        steps: &mut ConnectSteps,
+        protection: SocketProtection,
     ) -> Result<ApiConnection, io::Error>
     where
         ProxyFactory: FnOnce(TcpStream) -> ProxyFuture,
         ProxyFuture: Future<Output = io::Result<Proxy>>,
         Proxy: AsyncRead + AsyncWrite + Unpin + Send + 'static,
     {
-        let socket = HttpsConnectorWithSni::open_socket(
-            first_hop,
-            #[cfg(target_os = "android")]
-            socket_bypass_tx,
-        )
-        .await?;
+        let socket = steps
+            .run(
+                TestStep::TcpConnect,
+                HttpsConnectorWithSni::open_socket(first_hop, protection),
+            )
+            .await?;
 
-        let proxy = make_proxy_stream(socket).await?;
+        let proxy = steps
+            .run(TestStep::ProxyHandshake, make_proxy_stream(socket))
+            .await?;
 
         #[cfg(feature = "api-override")]
-        if API.disable_tls {
+        if API.disable_tls || crate::custom_endpoint::tls_disabled(hostname) {
             return Ok(ApiConnection::new(Box::new(ConnectionDecorator(proxy))));
         }
 
-        let tls_stream = TlsStream::connect_https(proxy, hostname).await?;
+        let tls_stream = steps
+            .run(
+                TestStep::TlsHandshake,
+                TlsStream::connect_https(proxy, hostname),
+            )
+            .await?;
         Ok(ApiConnection::new(Box::new(tls_stream)))
     }
 }
@@ -206,13 +205,13 @@ struct ShadowsocksConfig {
 #[derive(Clone)]
 struct ParsedShadowsocksConfig {
     peer: SocketAddr,
-    password: String,
+    password: SecretString,
     cipher: CipherKind,
 }
 
 impl From<ParsedShadowsocksConfig> for ServerConfig {
     fn from(config: ParsedShadowsocksConfig) -> Self {
-        ServerConfig::new(config.peer, config.password, config.cipher)
+        ServerConfig::new(config.peer, config.password.expose().clone(), config.cipher)
     }
 }
 
@@ -223,7 +222,7 @@ struct SocksConfig {
 }
 
 #[derive(thiserror::Error, Debug)]
-enum ProxyConfigError {
+pub(crate) enum ProxyConfigError {
     #[error("Unrecognized cipher selected: {0}")]
     InvalidCipher(String),
 }
@@ -255,6 +254,7 @@ impl TryFrom<ApiConnectionMode> for InnerConnectionMode {
                     peer: config.endpoint,
                     authentication: config.auth,
                 }),
+                ProxyConfig::HttpConnect(proxy) => InnerConnectionMode::HttpConnect(proxy),
             },
         })
     }
@@ -267,8 +267,7 @@ pub struct HttpsConnectorWithSni {
     sni_hostname: Option<String>,
     address_cache: AddressCache,
     abort_notify: Arc<tokio::sync::Notify>,
-    #[cfg(target_os = "android")]
-    socket_bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>,
+    protection: SocketProtection,
 }
 
 struct HttpsConnectorWithSniInner {
@@ -276,14 +275,11 @@ struct HttpsConnectorWithSniInner {
     proxy_config: InnerConnectionMode,
 }
 
-#[cfg(target_os = "android")]
-pub type SocketBypassRequest = (RawFd, oneshot::Sender<()>);
-
 impl HttpsConnectorWithSni {
     pub fn new(
         sni_hostname: Option<String>,
         address_cache: AddressCache,
-        #[cfg(target_os = "android")] socket_bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>,
+        protection: SocketProtection,
     ) -> (Self, HttpsConnectorWithSniHandle) {
         let (tx, mut rx) = mpsc::unbounded();
         let abort_notify = Arc::new(tokio::sync::Notify::new());
@@ -331,32 +327,30 @@ impl HttpsConnectorWithSni {
                 sni_hostname,
                 address_cache,
                 abort_notify,
-                #[cfg(target_os = "android")]
-                socket_bypass_tx,
+                protection,
             },
             HttpsConnectorWithSniHandle { tx },
         )
     }
 
-    /// Establishes a TCP connection with a peer at the specified socket address.
+    /// Establishes a TCP connection with a peer at the specified socket address. The socket is
+    /// protected as described by `protection` first.
     ///
     /// Will timeout after [`CONNECT_TIMEOUT`] seconds.
     async fn open_socket(
         addr: SocketAddr,
-        #[cfg(target_os = "android")] socket_bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>,
+        protection: SocketProtection,
     ) -> std::io::Result<TcpStream> {
         let socket = match addr {
             SocketAddr::V4(_) => TcpSocket::new_v4()?,
             SocketAddr::V6(_) => TcpSocket::new_v6()?,
         };
 
-        #[cfg(target_os = "android")]
-        if let Some(mut tx) = socket_bypass_tx {
-            let (done_tx, done_rx) = oneshot::channel();
-            let _ = tx.send((socket.as_raw_fd(), done_tx)).await;
-            if done_rx.await.is_err() {
-                log::error!("Failed to bypass socket, connection might fail");
-            }
+        if let Err(error) = protection.protect(&socket).await {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to protect socket, connection might fail")
+            );
         }
 
         timeout(CONNECT_TIMEOUT, socket.connect(addr))
@@ -364,7 +358,10 @@ impl HttpsConnectorWithSni {
             .map_err(|err| io::Error::new(io::ErrorKind::TimedOut, err))?
     }
 
-    async fn resolve_address(address_cache: AddressCache, uri: Uri) -> io::Result<SocketAddr> {
+    pub(crate) async fn resolve_address(
+        address_cache: AddressCache,
+        uri: Uri,
+    ) -> io::Result<SocketAddr> {
         const DEFAULT_PORT: u16 = 443;
 
         let hostname = uri.host().ok_or_else(|| {
@@ -428,8 +425,7 @@ impl Service<Uri> for HttpsConnectorWithSni {
             });
         let inner = self.inner.clone();
         let abort_notify = self.abort_notify.clone();
-        #[cfg(target_os = "android")]
-        let socket_bypass_tx = self.socket_bypass_tx.clone();
+        let protection = self.protection.clone();
         let address_cache = self.address_cache.clone();
 
         let fut = async move {
@@ -448,12 +444,9 @@ impl Service<Uri> for HttpsConnectorWithSni {
             let stream = loop {
                 let notify = abort_notify.notified();
                 let proxy_config = { inner.lock().unwrap().proxy_config.clone() };
-                let stream_fut = proxy_config.connect(
-                    &hostname,
-                    &addr,
-                    #[cfg(target_os = "android")]
-                    socket_bypass_tx.clone(),
-                );
+                let mut steps = ConnectSteps::default();
+                let stream_fut =
+                    proxy_config.connect(&hostname, &addr, &mut steps, protection.clone());
 
                 pin_mut!(stream_fut);
                 pin_mut!(notify);
@@ -1,10 +1,9 @@
 #![allow(rustdoc::private_intra_doc_links)]
-#[cfg(target_os = "android")]
-use futures::channel::mpsc;
 use hyper::Method;
 #[cfg(target_os = "android")]
 use mullvad_types::account::{PlayPurchase, PlayPurchasePaymentToken};
 use mullvad_types::{
+    access_method::TestResult,
     account::{AccountData, AccountToken, VoucherSubmission},
     version::AppVersion,
 };
@@ -25,16 +24,22 @@ use availability::{ApiAvailability, ApiAvailabilityHandle};
 pub mod rest;
 
 mod abortable_stream;
+mod http_proxy;
 mod https_client_with_sni;
 pub mod proxy;
 mod tls_stream;
-#[cfg(target_os = "android")]
-pub use crate::https_client_with_sni::SocketBypassRequest;
+pub use talpid_net::SocketProtection;
 
 mod access;
 mod address_cache;
+pub mod certificates;
+pub mod clock_skew;
+mod connection_test;
+pub mod custom_endpoint;
 pub mod device;
 mod relay_list;
+pub mod signature;
+pub mod version;
 
 #[cfg(target_os = "ios")]
 pub mod ffi;
@@ -42,7 +47,9 @@ pub mod ffi;
 pub use address_cache::AddressCache;
 pub use device::DevicesProxy;
 pub use hyper::StatusCode;
-pub use relay_list::RelayListProxy;
+pub use relay_list::{
+    parse_signed_relay_list, ImportedRelayList, RelayListImportError, RelayListProxy,
+};
 
 /// Error code returned by the Mullvad API if the voucher has alreaby been used.
 pub const VOUCHER_USED: &str = "VOUCHER_USED";
@@ -71,6 +78,11 @@ const GOOGLE_PAYMENTS_URL_PREFIX: &str = "payments/google-play/v1";
 
 pub static API: LazyManual<ApiEndpoint> = LazyManual::new(ApiEndpoint::from_env_vars);
 
+/// Returns the hostname of the custom API endpoint if one is in use, or else [`API`]'s.
+pub(crate) fn api_host() -> String {
+    custom_endpoint::host().unwrap_or_else(|| API.host().to_owned())
+}
+
 unsafe impl<T, F: Send> Sync for LazyManual<T, F> where OnceLock<T>: Sync {}
 
 /// A value that is either initialized on access or explicitly.
@@ -109,6 +121,7 @@ pub mod env {
     pub const API_ADDR_VAR: &str = "MULLVAD_API_ADDR";
     pub const API_FORCE_DIRECT_VAR: &str = "MULLVAD_API_FORCE_DIRECT";
     pub const DISABLE_TLS_VAR: &str = "MULLVAD_API_DISABLE_TLS";
+    pub const CLOCK_SKEW_TOLERANCE_VAR: &str = "MULLVAD_API_CLOCK_SKEW_TOLERANCE_SECS";
 }
 
 /// A hostname and socketaddr to reach the Mullvad REST API over.
@@ -310,8 +323,7 @@ pub struct Runtime {
     handle: tokio::runtime::Handle,
     address_cache: AddressCache,
     api_availability: availability::ApiAvailability,
-    #[cfg(target_os = "android")]
-    socket_bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>,
+    protection: SocketProtection,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -329,11 +341,7 @@ pub enum Error {
 impl Runtime {
     /// Create a new `Runtime`.
     pub fn new(handle: tokio::runtime::Handle) -> Result<Self, Error> {
-        Self::new_inner(
-            handle,
-            #[cfg(target_os = "android")]
-            None,
-        )
+        Self::new_inner(handle, SocketProtection::default())
     }
 
     #[cfg(target_os = "ios")]
@@ -342,19 +350,19 @@ impl Runtime {
             handle,
             address_cache: AddressCache::with_static_addr(address),
             api_availability: ApiAvailability::new(availability::State::default()),
+            protection: SocketProtection::default(),
         }
     }
 
     fn new_inner(
         handle: tokio::runtime::Handle,
-        #[cfg(target_os = "android")] socket_bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>,
+        protection: SocketProtection,
     ) -> Result<Self, Error> {
         Ok(Runtime {
             handle,
             address_cache: AddressCache::new(None)?,
             api_availability: ApiAvailability::new(availability::State::default()),
-            #[cfg(target_os = "android")]
-            socket_bypass_tx,
+            protection,
         })
     }
 
@@ -363,16 +371,12 @@ impl Runtime {
     pub async fn with_cache(
         cache_dir: &Path,
         write_changes: bool,
-        #[cfg(target_os = "android")] socket_bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>,
+        protection: SocketProtection,
     ) -> Result<Self, Error> {
         let handle = tokio::runtime::Handle::current();
         #[cfg(feature = "api-override")]
         if API.disable_address_cache {
-            return Self::new_inner(
-                handle,
-                #[cfg(target_os = "android")]
-                socket_bypass_tx,
-            );
+            return Self::new_inner(handle, protection);
         }
 
         let cache_file = cache_dir.join(API_IP_CACHE_FILENAME);
@@ -401,8 +405,7 @@ impl Runtime {
             handle,
             address_cache,
             api_availability: ApiAvailability::new(availability::State::default()),
-            #[cfg(target_os = "android")]
-            socket_bypass_tx,
+            protection,
         })
     }
 
@@ -411,15 +414,14 @@ impl Runtime {
         &self,
         sni_hostname: Option<String>,
         connection_mode_provider: T,
-        #[cfg(target_os = "android")] socket_bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>,
+        protection: SocketProtection,
     ) -> rest::RequestServiceHandle {
         rest::RequestService::spawn(
             sni_hostname,
             self.api_availability.handle(),
             self.address_cache.clone(),
             connection_mode_provider,
-            #[cfg(target_os = "android")]
-            socket_bypass_tx,
+            protection,
         )
     }
 
@@ -428,14 +430,11 @@ impl Runtime {
         &self,
         connection_mode_provider: T,
     ) -> rest::MullvadRestHandle {
-        let service = self.new_request_service(
-            Some(API.host().to_string()),
-            connection_mode_provider,
-            #[cfg(target_os = "android")]
-            self.socket_bypass_tx.clone(),
-        );
-        let token_store = access::AccessTokenStore::new(service.clone(), API.host());
-        let factory = rest::RequestFactory::new(API.host().to_owned(), Some(token_store));
+        let service =
+            self.new_request_service(None, connection_mode_provider, self.protection.clone());
+        let token_store =
+            access::AccessTokenStore::new(service.clone(), rest::RequestFactory::api(None));
+        let factory = rest::RequestFactory::api(Some(token_store));
 
         rest::MullvadRestHandle::new(service, factory, self.availability_handle())
     }
@@ -445,10 +444,12 @@ impl Runtime {
         let service = self.new_request_service(
             Some(hostname.clone()),
             ApiConnectionMode::Direct.into_provider(),
-            #[cfg(target_os = "android")]
-            self.socket_bypass_tx.clone(),
+            self.protection.clone(),
+        );
+        let token_store = access::AccessTokenStore::new(
+            service.clone(),
+            rest::RequestFactory::new(hostname.clone(), None),
         );
-        let token_store = access::AccessTokenStore::new(service.clone(), hostname.clone());
         let factory = rest::RequestFactory::new(hostname, Some(token_store));
 
         rest::MullvadRestHandle::new(service, factory, self.availability_handle())
@@ -459,8 +460,20 @@ impl Runtime {
         self.new_request_service(
             None,
             ApiConnectionMode::Direct.into_provider(),
-            #[cfg(target_os = "android")]
-            None,
+            SocketProtection::default(),
+        )
+    }
+
+    /// Test whether the API can be reached using `connection_mode`. The result describes each
+    /// step of connecting, so that it's possible to tell where a failing connection mode fails.
+    pub fn test_connection_mode(
+        &self,
+        connection_mode: ApiConnectionMode,
+    ) -> impl Future<Output = TestResult> + Send + 'static {
+        connection_test::test_connection_mode(
+            connection_mode,
+            self.address_cache.clone(),
+            self.protection.clone(),
         )
     }
 
@@ -637,6 +650,7 @@ impl AccountsProxy {
     }
 }
 
+#[derive(Clone)]
 pub struct ProblemReportProxy {
     handle: rest::MullvadRestHandle,
 }
@@ -692,6 +706,45 @@ pub struct AppVersionResponse {
     pub latest: AppVersion,
     pub latest_stable: Option<AppVersion>,
     pub latest_beta: AppVersion,
+    /// Certificates to use instead of the bundled ones, if they have been rotated since the
+    /// release.
+    #[serde(default)]
+    pub certificates: Option<certificates::SignedCertificateUpdate>,
+    /// Signed copy of the version information. Builds that can verify it require it and use it
+    /// instead of the fields above.
+    #[serde(default)]
+    pub metadata: Option<version::SignedVersionMetadata>,
+}
+
+impl AppVersionResponse {
+    /// Return the version metadata for `app_version`. If this build has a key to verify signed
+    /// metadata with, the response must contain signed metadata, which is verified and used.
+    /// Otherwise, the unsigned fields are used, which are only as trustworthy as the TLS connection
+    /// to the API.
+    pub fn verify_metadata(
+        &self,
+        app_version: &str,
+    ) -> Result<version::VersionMetadata, version::Error> {
+        self.verify_metadata_with_key(app_version, signature::signing_public_key().ok())
+    }
+
+    fn verify_metadata_with_key(
+        &self,
+        app_version: &str,
+        public_key: Option<&str>,
+    ) -> Result<version::VersionMetadata, version::Error> {
+        match (public_key, &self.metadata) {
+            (Some(public_key), Some(signed)) => signed.verify_with_key(app_version, public_key),
+            (Some(_), None) => Err(version::Error::Unsigned),
+            (None, _) => Ok(version::VersionMetadata {
+                app_version: app_version.to_owned(),
+                supported: self.supported,
+                latest_stable: self.latest_stable.clone(),
+                latest_beta: self.latest_beta.clone(),
+                changelog_urls: BTreeMap::new(),
+            }),
+        }
+    }
 }
 
 impl AppVersionProxy {
@@ -713,6 +766,7 @@ impl AppVersionProxy {
         async move {
             let request = request?
                 .expected_status(&[StatusCode::OK])
+                .priority(rest::Priority::Background)
                 .header("M-Platform-Version", &platform_version)?;
             let response = service.request(request).await?;
             response.deserialize().await
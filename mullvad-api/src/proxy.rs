@@ -2,6 +2,7 @@ use hyper::client::connect::Connected;
 use serde::{Deserialize, Serialize};
 use std::{
     fmt, io,
+    net::SocketAddr,
     path::Path,
     pin::Pin,
     task::{self, Poll},
@@ -74,6 +75,8 @@ pub enum ProxyConfig {
     Shadowsocks(proxy::Shadowsocks),
     Socks5Local(proxy::Socks5Local),
     Socks5Remote(proxy::Socks5Remote),
+    /// An HTTP proxy that supports the `CONNECT` method.
+    HttpConnect(SocketAddr),
 }
 
 impl ProxyConfig {
@@ -87,6 +90,9 @@ impl ProxyConfig {
             ProxyConfig::Socks5Remote(remote) => {
                 Endpoint::from_socket_address(remote.endpoint, TransportProtocol::Tcp)
             }
+            ProxyConfig::HttpConnect(endpoint) => {
+                Endpoint::from_socket_address(*endpoint, TransportProtocol::Tcp)
+            }
         }
     }
 }
@@ -97,6 +103,7 @@ impl fmt::Display for ProxyConfig {
         match self {
             ProxyConfig::Shadowsocks(_) => write!(f, "Shadowsocks {}", endpoint),
             ProxyConfig::Socks5Remote(_) => write!(f, "Socks5 {}", endpoint),
+            ProxyConfig::HttpConnect(_) => write!(f, "HTTP {}", endpoint),
             ProxyConfig::Socks5Local(local) => {
                 write!(f, "Socks5 {} via localhost:{}", endpoint, local.local_port)
             }
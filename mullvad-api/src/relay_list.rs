@@ -1,7 +1,8 @@
 //! A module dedicated to retrieving the relay list from the Mullvad API.
 
-use crate::rest;
+use crate::{rest, signature};
 
+use chrono::{DateTime, Utc};
 use hyper::{header, Method, StatusCode};
 use mullvad_types::{location, relay_list};
 use talpid_types::net::wireguard;
@@ -10,7 +11,7 @@ use std::{
     collections::BTreeMap,
     future::Future,
     net::{Ipv4Addr, Ipv6Addr},
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
 /// Fetches relay list from <https://api.mullvad.net/app/v1/relays>
@@ -38,6 +39,7 @@ impl RelayListProxy {
         async move {
             let mut request = request?
                 .timeout(RELAY_LIST_TIMEOUT)
+                .priority(rest::Priority::Background)
                 .expected_status(&[StatusCode::NOT_MODIFIED, StatusCode::OK]);
 
             if let Some(ref tag) = etag {
@@ -66,6 +68,70 @@ impl RelayListProxy {
     }
 }
 
+/// How long after its creation a signed relay list can still be imported.
+const MAX_IMPORTED_RELAY_LIST_AGE: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+
+#[derive(thiserror::Error, Debug)]
+pub enum RelayListImportError {
+    #[error("Failed to verify the relay list")]
+    Signature(#[from] signature::Error),
+
+    #[error("Failed to parse the relay list")]
+    Parse(#[source] serde_json::Error),
+
+    #[error("The relay list was created at {0}, which is too long ago")]
+    Expired(DateTime<Utc>),
+}
+
+/// A relay list that was obtained out-of-band, along with the time at which Mullvad created it.
+#[derive(Debug)]
+pub struct ImportedRelayList {
+    pub relay_list: relay_list::RelayList,
+    pub created: SystemTime,
+}
+
+/// The signed part of a relay list that is obtained out-of-band. The creation time is covered by
+/// the signature, so that an old list cannot be passed off as a new one.
+#[derive(Debug, serde::Deserialize)]
+struct SignedRelayList {
+    created: DateTime<Utc>,
+    relay_list: ServerRelayList,
+}
+
+/// Parse a relay list that was obtained out-of-band, e.g. on a network where the API is reachable.
+/// `relay_list` must be a JSON object with a `created` timestamp and a `relay_list` in the format
+/// served by the API, and `signature` must be a hex encoded ed25519 signature of it made by
+/// Mullvad. Lists older than [`MAX_IMPORTED_RELAY_LIST_AGE`] are rejected.
+pub fn parse_signed_relay_list(
+    relay_list: &[u8],
+    signature: &str,
+) -> Result<ImportedRelayList, RelayListImportError> {
+    parse_verified(relay_list, signature, signature::signing_public_key()?)
+}
+
+fn parse_verified(
+    relay_list: &[u8],
+    signature: &str,
+    public_key: &str,
+) -> Result<ImportedRelayList, RelayListImportError> {
+    signature::verify(relay_list, signature, public_key)?;
+    let signed: SignedRelayList =
+        serde_json::from_slice(relay_list).map_err(RelayListImportError::Parse)?;
+    let created = SystemTime::from(signed.created);
+    let expired = SystemTime::now()
+        .duration_since(created)
+        .map(|age| age > MAX_IMPORTED_RELAY_LIST_AGE)
+        .unwrap_or(false);
+    if expired {
+        return Err(RelayListImportError::Expired(signed.created));
+    }
+    Ok(ImportedRelayList {
+        // There is no ETag, so that the next update from the API replaces the imported list
+        relay_list: signed.relay_list.into_relay_list(None),
+        created,
+    })
+}
+
 #[derive(Debug, serde::Deserialize)]
 struct ServerRelayList {
     locations: BTreeMap<String, Location>,
@@ -158,6 +224,7 @@ fn into_mullvad_relay(
         owned: relay.owned,
         provider: relay.provider,
         weight: relay.weight,
+        stboot: relay.stboot,
         endpoint_data,
         location: Some(location),
     }
@@ -222,6 +289,8 @@ struct Relay {
     ipv6_addr_in: Option<Ipv6Addr>,
     weight: u64,
     include_in_country: bool,
+    #[serde(default)]
+    stboot: bool,
 }
 
 impl Relay {
@@ -305,21 +374,50 @@ struct WireGuardRelay {
     public_key: wireguard::PublicKey,
     #[serde(default)]
     daita: bool,
+    #[serde(default)]
+    quic: bool,
+    #[serde(default)]
+    tls: Option<TlsEndpoint>,
 }
 
 impl WireGuardRelay {
     fn into_mullvad_relay(self, location: location::Location) -> relay_list::Relay {
+        let tls = self.tls.and_then(|tls| {
+            let certificate_sha256 = hex::decode(&tls.certificate_sha256)
+                .ok()
+                .and_then(|digest| <[u8; 32]>::try_from(digest).ok());
+            if certificate_sha256.is_none() {
+                log::error!(
+                    "Ignoring invalid TLS certificate digest of {}",
+                    self.relay.hostname
+                );
+            }
+            Some(relay_list::TlsEndpointData {
+                port: tls.port,
+                certificate_sha256: certificate_sha256?,
+            })
+        });
         into_mullvad_relay(
             self.relay,
             location,
             relay_list::RelayEndpointData::Wireguard(relay_list::WireguardRelayEndpointData {
                 public_key: self.public_key,
                 daita: self.daita,
+                quic: self.quic,
+                tls,
             }),
         )
     }
 }
 
+/// Where a WireGuard relay accepts tunnels wrapped in TLS.
+#[derive(Debug, serde::Deserialize)]
+struct TlsEndpoint {
+    port: u16,
+    /// Hex encoded SHA-256 digest of the DER encoded certificate of the relay.
+    certificate_sha256: String,
+}
+
 #[derive(Debug, serde::Deserialize)]
 struct Bridges {
     shadowsocks: Vec<relay_list::ShadowsocksEndpointData>,
@@ -362,3 +460,99 @@ impl Bridges {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ring::{
+        rand::SystemRandom,
+        signature::{Ed25519KeyPair, KeyPair},
+    };
+
+    const RELAY_LIST: &str = r#"{
+        "locations": {
+            "se-got": {
+                "city": "Gothenburg",
+                "country": "Sweden",
+                "latitude": 57.70887,
+                "longitude": 11.97456
+            }
+        },
+        "openvpn": { "ports": [], "relays": [] },
+        "wireguard": {
+            "port_ranges": [[53, 53], [4000, 33433]],
+            "ipv4_gateway": "10.64.0.1",
+            "ipv6_gateway": "fc00:bbbb:bbbb:bb01::1",
+            "relays": [{
+                "hostname": "se-got-wg-001",
+                "active": true,
+                "owned": true,
+                "location": "se-got",
+                "provider": "31173",
+                "ipv4_addr_in": "185.213.154.68",
+                "ipv6_addr_in": null,
+                "weight": 100,
+                "include_in_country": true,
+                "public_key": "veGD6/aEY6sMfN3Ls7YWPmNgu3AheO7nQqsFT47YSws="
+            }]
+        },
+        "bridge": { "shadowsocks": [], "relays": [] }
+    }"#;
+
+    fn signed_relay_list(created: DateTime<Utc>) -> String {
+        format!(
+            r#"{{ "created": "{}", "relay_list": {RELAY_LIST} }}"#,
+            created.to_rfc3339()
+        )
+    }
+
+    fn sign(data: &[u8]) -> (String, String) {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        (
+            hex::encode(key_pair.sign(data)),
+            hex::encode(key_pair.public_key()),
+        )
+    }
+
+    #[test]
+    fn test_import_signed_relay_list() {
+        let created = Utc::now();
+        let signed = signed_relay_list(created);
+        let (signature, public_key) = sign(signed.as_bytes());
+        let ImportedRelayList {
+            relay_list,
+            created: imported_created,
+        } = parse_verified(signed.as_bytes(), &signature, &public_key)
+            .expect("relay list should be correctly signed");
+
+        assert_eq!(imported_created, SystemTime::from(created));
+        assert_eq!(relay_list.etag, None);
+        let relays: Vec<_> = relay_list.relays().map(|relay| &relay.hostname).collect();
+        assert_eq!(relays, ["se-got-wg-001"]);
+    }
+
+    #[test]
+    fn test_reject_tampered_relay_list() {
+        let signed = signed_relay_list(Utc::now());
+        let (signature, public_key) = sign(signed.as_bytes());
+        let tampered = signed.replace("185.213.154.68", "192.0.2.1");
+        assert!(matches!(
+            parse_verified(tampered.as_bytes(), &signature, &public_key),
+            Err(RelayListImportError::Signature(
+                signature::Error::InvalidSignature
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_reject_expired_relay_list() {
+        let created = Utc::now() - MAX_IMPORTED_RELAY_LIST_AGE - Duration::from_secs(60);
+        let signed = signed_relay_list(created);
+        let (signature, public_key) = sign(signed.as_bytes());
+        assert!(matches!(
+            parse_verified(signed.as_bytes(), &signature, &public_key),
+            Err(RelayListImportError::Expired(_))
+        ));
+    }
+}
@@ -1,5 +1,3 @@
-#[cfg(target_os = "android")]
-pub use crate::https_client_with_sni::SocketBypassRequest;
 use crate::{
     access::AccessTokenStore,
     address_cache::AddressCache,
@@ -24,7 +22,9 @@ use std::{
     sync::{Arc, Weak},
     time::Duration,
 };
+use talpid_net::SocketProtection;
 use talpid_types::ErrorExt;
+use tokio::sync::watch;
 
 pub use hyper::StatusCode;
 
@@ -93,6 +93,31 @@ impl Error {
         matches!(self, Error::Aborted)
     }
 
+    /// Return true if TLS failed because the server certificate is expired or not yet valid. This
+    /// usually means that the system clock is wrong.
+    pub fn is_certificate_time_error(&self) -> bool {
+        use tokio_rustls::rustls::{self, CertificateError};
+
+        let Error::HyperError(error) = self else {
+            return false;
+        };
+        let mut source = error.source();
+        while let Some(error) = source {
+            let tls_error: Option<&rustls::Error> = error.downcast_ref().or_else(|| {
+                let io_error: &std::io::Error = error.downcast_ref()?;
+                io_error.get_ref()?.downcast_ref()
+            });
+            if let Some(rustls::Error::InvalidCertificate(
+                CertificateError::Expired | CertificateError::NotValidYet,
+            )) = tls_error
+            {
+                return true;
+            }
+            source = error.source();
+        }
+        false
+    }
+
     /// Returns a new instance for which `abortable_stream::Aborted` is mapped to `Self::Aborted`.
     fn map_aborted(self) -> Self {
         if let Error::HyperError(error) = &self {
@@ -113,6 +138,87 @@ impl Error {
     }
 }
 
+/// How urgently a request has to be sent. On a constrained link, requests that the tunnel depends
+/// on should not have to compete with requests that can just as well be sent later.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Requests that a tunnel is waiting for, such as rotating the WireGuard key.
+    Critical,
+    #[default]
+    Normal,
+    /// Requests that only refresh cached data, such as the relay list. These are not sent while
+    /// any critical request is in flight, and are cancelled on reconnect.
+    Background,
+}
+
+/// Cancels every request that it has been attached to with [`Request::cancellation_token`]. All
+/// clones of a token refer to the same token. Once cancelled, a token stays cancelled.
+#[derive(Debug, Clone)]
+pub struct CancellationToken(Arc<watch::Sender<bool>>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self(Arc::new(watch::channel(false).0))
+    }
+
+    /// Cancel all requests that this token is attached to. Requests that are attached later fail
+    /// immediately.
+    pub fn cancel(&self) {
+        self.0.send_replace(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    async fn cancelled(&self) {
+        let _ = self.0.subscribe().wait_for(|cancelled| *cancelled).await;
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Resolves once any of the tokens is cancelled.
+async fn any_cancelled(tokens: &[CancellationToken]) {
+    if tokens.is_empty() {
+        return futures::future::pending().await;
+    }
+    futures::future::select_all(tokens.iter().map(|token| Box::pin(token.cancelled()))).await;
+}
+
+/// Number of critical requests that are in flight.
+#[derive(Clone)]
+struct CriticalRequests(Arc<watch::Sender<usize>>);
+
+impl CriticalRequests {
+    fn new() -> Self {
+        Self(Arc::new(watch::channel(0).0))
+    }
+
+    /// Count a critical request as in flight until the returned guard is dropped.
+    fn start(&self) -> CriticalRequestGuard {
+        self.0.send_modify(|count| *count += 1);
+        CriticalRequestGuard(self.clone())
+    }
+
+    /// Wait until no critical request is in flight.
+    async fn wait_until_idle(&self) {
+        let _ = self.0.subscribe().wait_for(|count| *count == 0).await;
+    }
+}
+
+struct CriticalRequestGuard(CriticalRequests);
+
+impl Drop for CriticalRequestGuard {
+    fn drop(&mut self) {
+        self.0 .0.send_modify(|count| *count -= 1);
+    }
+}
+
 /// A service that executes HTTP requests, allowing for on-demand termination of all in-flight
 /// requests
 pub(crate) struct RequestService<T: ConnectionModeProvider> {
@@ -123,6 +229,9 @@ pub(crate) struct RequestService<T: ConnectionModeProvider> {
     connection_mode_provider: T,
     connection_mode_generation: usize,
     api_availability: ApiAvailabilityHandle,
+    critical_requests: CriticalRequests,
+    /// Attached to every background request, and replaced when it is cancelled.
+    background_token: CancellationToken,
 }
 
 impl<T: ConnectionModeProvider + 'static> RequestService<T> {
@@ -132,14 +241,10 @@ impl<T: ConnectionModeProvider + 'static> RequestService<T> {
         api_availability: ApiAvailabilityHandle,
         address_cache: AddressCache,
         connection_mode_provider: T,
-        #[cfg(target_os = "android")] socket_bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>,
+        protection: SocketProtection,
     ) -> RequestServiceHandle {
-        let (connector, connector_handle) = HttpsConnectorWithSni::new(
-            sni_hostname,
-            address_cache.clone(),
-            #[cfg(target_os = "android")]
-            socket_bypass_tx.clone(),
-        );
+        let (connector, connector_handle) =
+            HttpsConnectorWithSni::new(sni_hostname, address_cache.clone(), protection.clone());
 
         connector_handle.set_connection_mode(connection_mode_provider.initial());
 
@@ -156,6 +261,8 @@ impl<T: ConnectionModeProvider + 'static> RequestService<T> {
             connection_mode_provider,
             connection_mode_generation: 0,
             api_availability,
+            critical_requests: CriticalRequests::new(),
+            background_token: CancellationToken::new(),
         };
         let handle = RequestServiceHandle { tx: command_tx };
         tokio::spawn(service.into_future());
@@ -191,6 +298,9 @@ impl<T: ConnectionModeProvider + 'static> RequestService<T> {
             RequestCommand::Reset => {
                 self.connector_handle.reset();
             }
+            RequestCommand::CancelBackground => {
+                std::mem::take(&mut self.background_token).cancel();
+            }
             RequestCommand::NextApiConfig(generation) => {
                 if generation == self.connection_mode_generation {
                     self.connection_mode_generation =
@@ -203,18 +313,36 @@ impl<T: ConnectionModeProvider + 'static> RequestService<T> {
 
     fn handle_new_request(
         &mut self,
-        request: Request,
+        mut request: Request,
         completion_tx: oneshot::Sender<Result<Response>>,
     ) {
         let tx = self.command_tx.upgrade();
 
+        let priority = request.priority;
+        let mut cancellation_tokens = std::mem::take(&mut request.cancellation_tokens);
+        if priority == Priority::Background {
+            cancellation_tokens.push(self.background_token.clone());
+        }
+        let critical_requests = self.critical_requests.clone();
+        let critical_guard = (priority == Priority::Critical).then(|| critical_requests.start());
+
         let api_availability = self.api_availability.clone();
         let request_future = request.into_future(self.client.clone(), api_availability.clone());
 
         let connection_mode_generation = self.connection_mode_generation;
 
         tokio::spawn(async move {
-            let response = request_future.await.map_err(|error| error.map_aborted());
+            let request_future = async move {
+                if priority == Priority::Background {
+                    critical_requests.wait_until_idle().await;
+                }
+                request_future.await
+            };
+            let response = tokio::select! {
+                response = request_future => response.map_err(|error| error.map_aborted()),
+                () = any_cancelled(&cancellation_tokens) => Err(Error::Aborted),
+            };
+            drop(critical_guard);
 
             // Switch API endpoint if the request failed due to a network error
             if let Err(err) = &response {
@@ -245,6 +373,12 @@ impl RequestServiceHandle {
         let _ = self.tx.unbounded_send(RequestCommand::Reset);
     }
 
+    /// Cancels all background requests that have been submitted so far. Requests that are
+    /// submitted afterwards are not affected.
+    pub fn cancel_background_requests(&self) {
+        let _ = self.tx.unbounded_send(RequestCommand::CancelBackground);
+    }
+
     /// Submits a `RestRequest` for execution to the request service.
     pub async fn request(&self, request: Request) -> Result<Response> {
         let (completion_tx, completion_rx) = oneshot::channel();
@@ -262,6 +396,7 @@ pub(crate) enum RequestCommand {
         oneshot::Sender<std::result::Result<Response, Error>>,
     ),
     Reset,
+    CancelBackground,
     NextApiConfig(usize),
 }
 
@@ -273,6 +408,8 @@ pub struct Request {
     access_token_store: Option<AccessTokenStore>,
     account: Option<AccountToken>,
     expected_status: &'static [hyper::StatusCode],
+    priority: Priority,
+    cancellation_tokens: Vec<CancellationToken>,
 }
 
 impl Request {
@@ -305,6 +442,8 @@ impl Request {
             access_token_store,
             account: None,
             expected_status: &[],
+            priority: Priority::Normal,
+            cancellation_tokens: vec![],
         }
     }
 
@@ -329,6 +468,19 @@ impl Request {
         self
     }
 
+    /// Sets the priority of the request. Requests have [`Priority::Normal`] by default.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Cancels the request when `token` is cancelled. The request then fails with
+    /// [`Error::Aborted`].
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancellation_tokens.push(token);
+        self
+    }
+
     pub fn header<T: header::IntoHeaderName>(mut self, key: T, value: &str) -> Result<Self> {
         let header_value =
             http::HeaderValue::from_str(value).map_err(|_| Error::InvalidHeaderError)?;
@@ -379,6 +531,7 @@ impl Request {
         // Parse unexpected responses and errors
 
         let response = response?;
+        crate::clock_skew::observe_response(response.headers());
 
         if !self.expected_status.contains(&response.status()) {
             if !self.expected_status.is_empty() {
@@ -445,7 +598,9 @@ struct NewErrorResponse {
 
 #[derive(Clone)]
 pub struct RequestFactory {
-    hostname: Cow<'static, str>,
+    /// Host to send requests to, or `None` to send them to the API. The API hostname is looked up
+    /// for every request, since it changes if a custom API endpoint is selected.
+    hostname: Option<Cow<'static, str>>,
     token_store: Option<AccessTokenStore>,
     default_timeout: Duration,
 }
@@ -456,7 +611,16 @@ impl RequestFactory {
         token_store: Option<AccessTokenStore>,
     ) -> Self {
         Self {
-            hostname: hostname.into(),
+            hostname: Some(hostname.into()),
+            token_store,
+            default_timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Create a factory for requests to the API, which may be a custom API endpoint.
+    pub(crate) fn api(token_store: Option<AccessTokenStore>) -> Self {
+        Self {
+            hostname: None,
             token_store,
             default_timeout: DEFAULT_TIMEOUT,
         }
@@ -525,7 +689,11 @@ impl RequestFactory {
     }
 
     fn hyper_request(&self, path: &str, method: Method) -> Result<hyper::Request<hyper::Body>> {
-        let uri = self.get_uri(path)?;
+        let hostname = match &self.hostname {
+            Some(hostname) => hostname.clone(),
+            None => Cow::Owned(crate::api_host()),
+        };
+        let uri = Self::get_uri(&hostname, path)?;
         let request = http::request::Builder::new()
             .method(method)
             .uri(uri)
@@ -533,15 +701,15 @@ impl RequestFactory {
             .header(header::ACCEPT, HeaderValue::from_static("application/json"))
             .header(
                 header::HOST,
-                HeaderValue::from_str(&self.hostname).map_err(|_| Error::InvalidHeaderError)?,
+                HeaderValue::from_str(&hostname).map_err(|_| Error::InvalidHeaderError)?,
             );
 
         let result = request.body(hyper::Body::empty())?;
         Ok(result)
     }
 
-    fn get_uri(&self, path: &str) -> Result<Uri> {
-        let uri = format!("https://{}/{}", self.hostname, path);
+    fn get_uri(hostname: &str, path: &str) -> Result<Uri> {
+        let uri = format!("https://{hostname}/{path}");
         hyper::Uri::from_str(&uri).map_err(|_| Error::InvalidUri)
     }
 }
@@ -639,3 +807,36 @@ macro_rules! impl_into_arc_err {
 impl_into_arc_err!(hyper::Error);
 impl_into_arc_err!(serde_json::Error);
 impl_into_arc_err!(http::Error);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_background_requests_wait_for_critical_requests() {
+        let critical_requests = CriticalRequests::new();
+        let guard = critical_requests.start();
+
+        let idle = critical_requests.wait_until_idle();
+        tokio::pin!(idle);
+        assert!(futures::poll!(&mut idle).is_pending());
+
+        drop(guard);
+        idle.await;
+    }
+
+    #[tokio::test]
+    async fn test_cancellation_token() {
+        let token = CancellationToken::new();
+        let tokens = [CancellationToken::new(), token.clone()];
+
+        let cancelled = any_cancelled(&tokens);
+        tokio::pin!(cancelled);
+        assert!(futures::poll!(&mut cancelled).is_pending());
+
+        token.cancel();
+        cancelled.await;
+        assert!(tokens[1].is_cancelled());
+        assert!(!tokens[0].is_cancelled());
+    }
+}
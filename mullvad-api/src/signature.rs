@@ -0,0 +1,38 @@
+//! Verification of data that is signed by Mullvad and obtained outside of the API, such as relay
+//! lists imported by the user.
+//!
+//! The public key is provided at build time in `MULLVAD_SIGNING_PUBLIC_KEY`. No key is hardcoded,
+//! since only a key that Mullvad holds the private half of can verify anything. Builds without it
+//! reject all signed data.
+
+use ring::signature::{UnparsedPublicKey, ED25519};
+
+/// Hex encoded ed25519 public key used to verify data signed by Mullvad, if this build has one.
+const SIGNING_PUBLIC_KEY: Option<&str> = option_env!("MULLVAD_SIGNING_PUBLIC_KEY");
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("This build has no key to verify signed data with")]
+    NoPublicKey,
+
+    #[error("Failed to decode the signature or public key")]
+    Decode(#[source] hex::FromHexError),
+
+    #[error("Invalid signature")]
+    InvalidSignature,
+}
+
+/// Verify that `signature`, a hex encoded ed25519 signature, is a signature of `data` made with the
+/// key corresponding to `public_key`.
+pub(crate) fn verify(data: &[u8], signature: &str, public_key: &str) -> Result<(), Error> {
+    let signature = hex::decode(signature.trim()).map_err(Error::Decode)?;
+    let public_key = hex::decode(public_key).map_err(Error::Decode)?;
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(data, &signature)
+        .map_err(|_| Error::InvalidSignature)
+}
+
+/// Returns the key that data signed by Mullvad is verified with.
+pub(crate) fn signing_public_key() -> Result<&'static str, Error> {
+    SIGNING_PUBLIC_KEY.ok_or(Error::NoPublicKey)
+}
@@ -1,11 +1,13 @@
-//! Provides a TLS 1.3 stream with SNI and LE root cert only.
+//! Provides a TLS 1.3 stream with SNI and LE root cert only. The root certificates can be
+//! replaced at runtime by a signed certificate update. A custom API endpoint may pin its own.
 use std::{
     io::{self, ErrorKind},
     pin::Pin,
-    sync::Arc,
+    sync::{Arc, RwLock},
     task::{self, Poll},
 };
 
+use crate::clock_skew::SkewTolerantVerifier;
 use hyper::client::connect::{Connected, Connection};
 use once_cell::sync::Lazy;
 use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
@@ -16,6 +18,31 @@ use tokio_rustls::{
 
 const LE_ROOT_CERT: &[u8] = include_bytes!("../le_root_cert.pem");
 
+static TLS_CONFIG: Lazy<RwLock<Arc<ClientConfig>>> = Lazy::new(|| {
+    let cert_store = read_cert_store(LE_ROOT_CERT).expect("Failed to add root cert");
+    RwLock::new(tls_config(cert_store))
+});
+
+/// Use the root certificates in `pem`, or the bundled ones if `pem` is `None`, for new
+/// connections.
+pub(crate) fn set_root_certificates(pem: Option<&[u8]>) -> Result<(), crate::certificates::Error> {
+    let cert_store = read_cert_store(pem.unwrap_or(LE_ROOT_CERT))
+        .ok_or(crate::certificates::Error::InvalidCertificate)?;
+    *TLS_CONFIG.write().unwrap() = tls_config(cert_store);
+    Ok(())
+}
+
+pub(crate) fn tls_config(cert_store: rustls::RootCertStore) -> Arc<ClientConfig> {
+    let config = ClientConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .unwrap()
+        .with_custom_certificate_verifier(Arc::new(SkewTolerantVerifier::new(cert_store)))
+        .with_no_client_auth();
+    Arc::new(config)
+}
+
 pub struct TlsStream<S: AsyncRead + AsyncWrite + Unpin> {
     stream: tokio_rustls::client::TlsStream<S>,
 }
@@ -25,18 +52,9 @@ where
     S: AsyncRead + AsyncWrite + Unpin,
 {
     pub async fn connect_https(stream: S, domain: &str) -> io::Result<TlsStream<S>> {
-        static TLS_CONFIG: Lazy<Arc<ClientConfig>> = Lazy::new(|| {
-            let config = ClientConfig::builder()
-                .with_safe_default_cipher_suites()
-                .with_safe_default_kx_groups()
-                .with_protocol_versions(&[&rustls::version::TLS13])
-                .unwrap()
-                .with_root_certificates(read_cert_store())
-                .with_no_client_auth();
-            Arc::new(config)
-        });
-
-        let connector = TlsConnector::from(TLS_CONFIG.clone());
+        let config = crate::custom_endpoint::pinned_tls_config(domain)
+            .unwrap_or_else(|| TLS_CONFIG.read().unwrap().clone());
+        let connector = TlsConnector::from(config);
 
         let host = match ServerName::try_from(domain) {
             Ok(n) => n,
@@ -54,17 +72,18 @@ where
     }
 }
 
-fn read_cert_store() -> rustls::RootCertStore {
+/// Parse the certificates in `pem`. Returns `None` unless there is at least one, and all of them
+/// are valid.
+pub(crate) fn read_cert_store(pem: &[u8]) -> Option<rustls::RootCertStore> {
     let mut cert_store = rustls::RootCertStore::empty();
 
-    let certs = rustls_pemfile::certs(&mut std::io::BufReader::new(LE_ROOT_CERT))
-        .expect("Failed to parse pem file");
+    let certs = rustls_pemfile::certs(&mut std::io::BufReader::new(pem)).ok()?;
     let (num_certs_added, num_failures) = cert_store.add_parsable_certificates(&certs);
-    if num_failures > 0 || num_certs_added != 1 {
-        panic!("Failed to add root cert");
+    if num_failures > 0 || num_certs_added == 0 {
+        return None;
     }
 
-    cert_store
+    Some(cert_store)
 }
 
 impl<S> AsyncRead for TlsStream<S>
@@ -116,6 +135,7 @@ mod test {
 
     #[test]
     fn test_cert_loading() {
-        let _certs = read_cert_store();
+        assert!(read_cert_store(LE_ROOT_CERT).is_some());
+        assert!(read_cert_store(b"").is_none());
     }
 }
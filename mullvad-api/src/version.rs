@@ -0,0 +1,152 @@
+//! Version metadata delivered through the version check. The metadata can be signed by Mullvad, so
+//! that nobody who can tamper with the API responses can trick the app into suggesting an
+//! upgrade to a version of their choosing. Builds that have a key to verify the signature with
+//! reject responses without signed metadata. Builds without one use the unsigned fields.
+
+use crate::signature;
+use mullvad_types::version::AppVersion;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A [`VersionMetadata`] along with a signature of it made by Mullvad.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedVersionMetadata {
+    /// JSON encoded [`VersionMetadata`].
+    pub metadata: String,
+    /// Hex encoded ed25519 signature of `metadata`.
+    pub signature: String,
+}
+
+/// Information about the latest releases, as seen by a specific version of the app.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct VersionMetadata {
+    /// The version that the metadata was issued for. This prevents metadata that was issued for
+    /// one version from being replayed to another.
+    pub app_version: AppVersion,
+    /// False if Mullvad has stopped supporting `app_version`.
+    pub supported: bool,
+    pub latest_stable: Option<AppVersion>,
+    pub latest_beta: AppVersion,
+    /// Links to the changelogs of the releases above.
+    #[serde(default)]
+    pub changelog_urls: BTreeMap<AppVersion, String>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Failed to verify the version metadata")]
+    Signature(#[from] signature::Error),
+
+    #[error("Failed to parse the version metadata")]
+    Parse(#[source] serde_json::Error),
+
+    #[error("The version metadata was issued for version {0}")]
+    WrongAppVersion(AppVersion),
+
+    #[error("The version check response contains no signed version metadata")]
+    Unsigned,
+}
+
+impl SignedVersionMetadata {
+    /// Verify the signature of the metadata, and that it was issued for `app_version`.
+    pub fn verify(&self, app_version: &str) -> Result<VersionMetadata, Error> {
+        self.verify_with_key(app_version, signature::signing_public_key()?)
+    }
+
+    pub(crate) fn verify_with_key(
+        &self,
+        app_version: &str,
+        public_key: &str,
+    ) -> Result<VersionMetadata, Error> {
+        signature::verify(self.metadata.as_bytes(), &self.signature, public_key)?;
+        let metadata: VersionMetadata =
+            serde_json::from_str(&self.metadata).map_err(Error::Parse)?;
+        if metadata.app_version != app_version {
+            return Err(Error::WrongAppVersion(metadata.app_version));
+        }
+        Ok(metadata)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ring::{
+        rand::SystemRandom,
+        signature::{Ed25519KeyPair, KeyPair},
+    };
+
+    fn sign(metadata: String) -> (SignedVersionMetadata, String) {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let signed = SignedVersionMetadata {
+            signature: hex::encode(key_pair.sign(metadata.as_bytes())),
+            metadata,
+        };
+        (signed, hex::encode(key_pair.public_key()))
+    }
+
+    #[test]
+    fn test_verify_version_metadata() {
+        let metadata = serde_json::json!({
+            "app_version": "2024.1",
+            "supported": true,
+            "latest_stable": "2024.2",
+            "latest_beta": "2024.3-beta1",
+            "changelog_urls": {
+                "2024.2": "https://example.com/2024.2",
+            },
+        });
+        let (signed, public_key) = sign(metadata.to_string());
+
+        let metadata = signed.verify_with_key("2024.1", &public_key).unwrap();
+        assert!(metadata.supported);
+        assert_eq!(metadata.latest_stable.as_deref(), Some("2024.2"));
+        assert_eq!(
+            metadata.changelog_urls.get("2024.2").map(String::as_str),
+            Some("https://example.com/2024.2")
+        );
+
+        assert!(matches!(
+            signed.verify_with_key("2023.6", &public_key),
+            Err(Error::WrongAppVersion(version)) if version == "2024.1"
+        ));
+
+        let tampered = SignedVersionMetadata {
+            metadata: signed.metadata.replace("2024.2", "2099.1"),
+            ..signed
+        };
+        assert!(matches!(
+            tampered.verify_with_key("2024.1", &public_key),
+            Err(Error::Signature(signature::Error::InvalidSignature))
+        ));
+    }
+
+    /// Builds without a signing key must use the unsigned fields. Builds with one must reject
+    /// responses without signed metadata.
+    #[test]
+    fn test_unsigned_version_check_response() {
+        let response: crate::AppVersionResponse = serde_json::from_str(
+            r#"{
+                "supported": true,
+                "latest": "2024.4",
+                "latest_stable": "2024.4",
+                "latest_beta": "2024.5-beta1"
+            }"#,
+        )
+        .unwrap();
+
+        let (_, public_key) = sign(String::new());
+        assert!(matches!(
+            response.verify_metadata_with_key("2024.1", Some(&public_key)),
+            Err(Error::Unsigned)
+        ));
+
+        let metadata = response.verify_metadata_with_key("2024.1", None).unwrap();
+        assert_eq!(metadata.app_version, "2024.1");
+        assert!(metadata.supported);
+        assert_eq!(metadata.latest_stable.as_deref(), Some("2024.4"));
+        assert_eq!(metadata.latest_beta, "2024.5-beta1");
+        assert!(metadata.changelog_urls.is_empty());
+    }
+}
@@ -1,13 +1,25 @@
 use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
 use clap::Subcommand;
+use futures::StreamExt;
 use itertools::Itertools;
-use mullvad_management_interface::MullvadProxyClient;
-use mullvad_types::{account::AccountToken, device::DeviceState};
-use std::io::{self, Write};
+use mullvad_management_interface::{client::DaemonEvent, MullvadProxyClient};
+use mullvad_types::{
+    account::AccountToken,
+    device::{Device, DeviceState, LoginOutcome},
+};
+use std::{
+    io::{self, IsTerminal, Write},
+    time::Duration,
+};
 
 const NOT_LOGGED_IN_MESSAGE: &str = "Not logged in on any account";
 const REVOKED_MESSAGE: &str = "The current device has been revoked";
 
+/// How often to fetch the expiry date again while watching. Time can be added to the account
+/// without the daemon sending any event.
+const WATCH_REFRESH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
 #[derive(Subcommand, Debug)]
 pub enum Account {
     /// Create and log in on a new account
@@ -17,16 +29,27 @@ pub enum Account {
     Login {
         /// The Mullvad account token to configure the client with
         account: Option<String>,
+
+        /// Name or UID of the device to revoke if the account has too many devices. If this is
+        /// not given, you are asked which device to revoke
+        #[arg(long)]
+        revoke: Option<String>,
     },
 
     /// Log out of the current account
     Logout,
 
     /// Display information about the current account
+    #[clap(visible_alias = "status")]
     Get {
         /// Enable verbose output
         #[arg(long, short = 'v')]
         verbose: bool,
+
+        /// Keep running, and show a countdown to the expiry date and whether the device is still
+        /// valid. Meant for machines without a GUI
+        #[arg(long, conflicts_with = "verbose")]
+        watch: bool,
     },
 
     /// List devices associated with an account
@@ -62,15 +85,19 @@ impl Account {
         let mut rpc = MullvadProxyClient::new().await?;
         match self {
             Account::Create => Self::create(&mut rpc).await,
-            Account::Login { account } => {
+            Account::Login { account, revoke } => {
                 Self::login(
                     &mut rpc,
-                    unwrap_or_from_stdin(account, "Enter an account number: ").await,
+                    unwrap_or_from_stdin(account, "Enter an account number: ")
+                        .await
+                        .into(),
+                    revoke,
                 )
                 .await
             }
             Account::Logout => Self::logout(&mut rpc).await,
-            Account::Get { verbose } => Self::get(&mut rpc, verbose).await,
+            Account::Get { watch: true, .. } => Self::watch(&mut rpc).await,
+            Account::Get { verbose, .. } => Self::get(&mut rpc, verbose).await,
             Account::ListDevices { account, verbose } => {
                 Self::list_devices(&mut rpc, account, verbose).await
             }
@@ -87,9 +114,36 @@ impl Account {
         Self::get(rpc, false).await
     }
 
-    async fn login(rpc: &mut MullvadProxyClient, token: AccountToken) -> Result<()> {
-        rpc.login_account(token.clone()).await?;
-        println!("Mullvad account \"{token}\" set");
+    async fn login(
+        rpc: &mut MullvadProxyClient,
+        token: AccountToken,
+        mut revoke: Option<String>,
+    ) -> Result<()> {
+        let mut revoke_device = None;
+        loop {
+            let outcome = rpc
+                .login_and_register_device(token.clone(), revoke_device.take())
+                .await?;
+            let devices = match outcome {
+                LoginOutcome::LoggedIn => break,
+                LoginOutcome::TooManyDevices(devices) => devices,
+            };
+
+            println!("There are too many devices on the account. Revoke one to log in.");
+            let device = match revoke.take() {
+                Some(device) => devices
+                    .into_iter()
+                    .find(|dev| {
+                        dev.name.eq_ignore_ascii_case(&device)
+                            || dev.id.eq_ignore_ascii_case(&device)
+                    })
+                    .ok_or(mullvad_management_interface::Error::DeviceNotFound)?,
+                None => select_device(devices).await?,
+            };
+            println!("Revoking {}", device.pretty_name());
+            revoke_device = Some(device.id);
+        }
+        println!("Mullvad account \"{}\" set", token.expose_str());
         Ok(())
     }
 
@@ -106,7 +160,11 @@ impl Account {
 
         match state {
             DeviceState::LoggedIn(device) => {
-                println!("{:<20}{}", "Mullvad account:", device.account_token);
+                println!(
+                    "{:<20}{}",
+                    "Mullvad account:",
+                    device.account_token.expose_str()
+                );
 
                 let data = rpc.get_account_data(device.account_token).await?;
                 println!(
@@ -119,10 +177,21 @@ impl Account {
                 }
 
                 println!("{:<20}{}", "Device name:", device.device.pretty_name());
+                println!(
+                    "{:<20}{}",
+                    "Key created:",
+                    device.key_created.with_timezone(&chrono::Local)
+                );
                 if verbose {
                     println!("{:<20}{}", "Device id:", device.device.id);
                     println!("{:<20}{}", "Device pubkey:", device.device.pubkey);
                     println!("{:<20}{}", "Device created:", device.device.created,);
+                    println!(
+                        "{:<20}{}, {}",
+                        "Tunnel addresses:",
+                        device.tunnel_addresses.ipv4_address,
+                        device.tunnel_addresses.ipv6_address
+                    );
                 }
             }
             DeviceState::LoggedOut => {
@@ -131,7 +200,7 @@ impl Account {
             DeviceState::Revoked => {
                 println!("{REVOKED_MESSAGE}");
                 if let Some(account_token) = rpc.get_account_history().await? {
-                    println!("Mullvad account: {}", account_token);
+                    println!("Mullvad account: {}", account_token.expose_str());
                 }
             }
         }
@@ -139,6 +208,45 @@ impl Account {
         Ok(())
     }
 
+    /// Print the account status whenever it changes, until the daemon goes away.
+    async fn watch(rpc: &mut MullvadProxyClient) -> Result<()> {
+        let mut events = rpc.events_listen().await?;
+        let _ = rpc.update_device().await;
+        let mut state = rpc.get_device().await?;
+        let mut expiry = fetch_expiry(rpc, &state).await;
+
+        let mut printer = StatusLinePrinter::new();
+        let mut tick = tokio::time::interval(Duration::from_secs(1));
+        let mut refresh = tokio::time::interval(WATCH_REFRESH_INTERVAL);
+        // Both intervals tick immediately
+        refresh.tick().await;
+
+        loop {
+            tokio::select! {
+                event = events.next() => match event {
+                    Some(Ok(DaemonEvent::Device(event))) => {
+                        state = event.new_state;
+                        expiry = fetch_expiry(rpc, &state).await;
+                    }
+                    Some(Ok(_)) => continue,
+                    Some(Err(error)) => {
+                        printer.finish();
+                        return Err(error.into());
+                    }
+                    None => {
+                        printer.finish();
+                        return Err(anyhow!("Lost the connection to the daemon"));
+                    }
+                },
+                _ = refresh.tick() => {
+                    expiry = fetch_expiry(rpc, &state).await.or(expiry);
+                }
+                _ = tick.tick() => (),
+            }
+            printer.print(format_watch_status(&state, expiry, Utc::now()));
+        }
+    }
+
     async fn list_devices(
         rpc: &mut MullvadProxyClient,
         account: Option<String>,
@@ -207,9 +315,9 @@ impl Account {
 async fn account_else_current(
     rpc: &mut MullvadProxyClient,
     token: Option<String>,
-) -> Result<String> {
+) -> Result<AccountToken> {
     match token {
-        Some(account) => Ok(account),
+        Some(account) => Ok(AccountToken::from(account)),
         None => {
             let state = rpc.get_device().await?;
             match state {
@@ -220,6 +328,24 @@ async fn account_else_current(
     }
 }
 
+/// Asks the user to pick one of `devices`.
+async fn select_device(mut devices: Vec<Device>) -> Result<Device> {
+    devices.sort_unstable_by_key(|dev| dev.created.timestamp());
+    for (index, device) in devices.iter().enumerate() {
+        println!("{}. {}", index + 1, device.pretty_name());
+    }
+    let choice = tokio::task::spawn_blocking(|| from_stdin("Enter the number of a device: "))
+        .await
+        .unwrap();
+    choice
+        .parse::<usize>()
+        .ok()
+        .and_then(|number| number.checked_sub(1))
+        .filter(|index| *index < devices.len())
+        .map(|index| devices.swap_remove(index))
+        .ok_or_else(|| anyhow!("No device was selected"))
+}
+
 async fn unwrap_or_from_stdin(val: Option<String>, prompt_str: &'static str) -> String {
     if let Some(val) = val {
         return val;
@@ -242,6 +368,107 @@ fn from_stdin(prompt_str: &'static str) -> String {
     val.split_whitespace().join("")
 }
 
+/// Return the expiry date of the account that the device belongs to, if it can be fetched.
+async fn fetch_expiry(rpc: &mut MullvadProxyClient, state: &DeviceState) -> Option<DateTime<Utc>> {
+    let DeviceState::LoggedIn(device) = state else {
+        return None;
+    };
+    rpc.get_account_data(device.account_token.clone())
+        .await
+        .map(|data| data.expiry)
+        .ok()
+}
+
+fn format_watch_status(
+    state: &DeviceState,
+    expiry: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> String {
+    let device = match state {
+        DeviceState::LoggedIn(device) => device,
+        DeviceState::LoggedOut => return NOT_LOGGED_IN_MESSAGE.to_owned(),
+        DeviceState::Revoked => return REVOKED_MESSAGE.to_owned(),
+    };
+    let expiry = match expiry {
+        Some(expiry) if expiry <= now => format!(
+            "expired at {}",
+            expiry
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+        ),
+        Some(expiry) => format!("expires in {}", format_countdown(expiry - now)),
+        None => "expiry unknown".to_owned(),
+    };
+    format!(
+        "Account {}, device {}: {expiry}",
+        device.account_token.expose_str(),
+        device.device.pretty_name()
+    )
+}
+
+/// Format `remaining` as days, hours, minutes and seconds, leaving out leading zero units.
+fn format_countdown(remaining: chrono::Duration) -> String {
+    let seconds = remaining.num_seconds().max(0);
+    let (days, hours, minutes, seconds) = (
+        seconds / 86400,
+        seconds / 3600 % 24,
+        seconds / 60 % 60,
+        seconds % 60,
+    );
+    if days > 0 {
+        format!("{days}d {hours:02}h {minutes:02}m {seconds:02}s")
+    } else if hours > 0 {
+        format!("{hours}h {minutes:02}m {seconds:02}s")
+    } else {
+        format!("{minutes}m {seconds:02}s")
+    }
+}
+
+/// Prints a line of status. On a terminal, the line is updated in place. Otherwise, a new line is
+/// printed whenever the status changes, ignoring the countdown, so that logs stay readable.
+struct StatusLinePrinter {
+    is_terminal: bool,
+    last: Option<String>,
+}
+
+impl StatusLinePrinter {
+    fn new() -> Self {
+        Self {
+            is_terminal: io::stdout().is_terminal(),
+            last: None,
+        }
+    }
+
+    fn print(&mut self, line: String) {
+        if self.is_terminal {
+            if self.last.as_ref() != Some(&line) {
+                print!("\r\x1b[2K{line}");
+                let _ = io::stdout().flush();
+            }
+            self.last = Some(line);
+        } else {
+            let key = strip_countdown(&line).to_owned();
+            if self.last.as_ref() != Some(&key) {
+                println!("{line}");
+            }
+            self.last = Some(key);
+        }
+    }
+
+    /// End the line that is being updated in place.
+    fn finish(&mut self) {
+        if self.is_terminal && self.last.is_some() {
+            println!();
+        }
+    }
+}
+
+fn strip_countdown(line: &str) -> &str {
+    line.split_once(": expires in ")
+        .map(|(prefix, _)| prefix)
+        .unwrap_or(line)
+}
+
 fn format_duration(seconds: u64) -> String {
     let dur = chrono::Duration::seconds(seconds as i64);
     if dur.num_days() > 0 {
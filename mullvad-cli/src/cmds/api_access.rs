@@ -1,6 +1,7 @@
+use crate::format;
 use anyhow::{anyhow, Result};
 use mullvad_management_interface::MullvadProxyClient;
-use mullvad_types::access_method::{AccessMethod, AccessMethodSetting};
+use mullvad_types::access_method::{AccessMethod, AccessMethodSetting, TestResult};
 use talpid_types::net::proxy::CustomProxy;
 
 use clap::{Args, Subcommand};
@@ -9,7 +10,7 @@ use super::proxies::{ProxyEditParams, ShadowsocksAdd, Socks5LocalAdd, Socks5Remo
 
 #[derive(Subcommand, Debug, Clone)]
 pub enum ApiAccess {
-    /// Display the current API access method.
+    /// Display the current API access method and the API address in use.
     Get,
     /// Add a custom API access method
     #[clap(subcommand)]
@@ -33,6 +34,8 @@ pub enum ApiAccess {
     /// connection use https and is therefore encrypted.
     ///
     /// Selecting "Mullvad Bridges" respects your current bridge settings
+    ///
+    /// Selecting "System proxy" uses the HTTP or SOCKS proxy configured in the operating system
     Use(SelectItem),
     /// Try to reach the Mullvad API using a specific access method
     Test(SelectItem),
@@ -102,7 +105,6 @@ impl ApiAccess {
 
     /// Edit the data of an API access method.
     async fn edit(cmd: EditCustomCommands) -> Result<()> {
-        use talpid_types::net::proxy::{Shadowsocks, Socks5Local, Socks5Remote, SocksAuth};
         let mut rpc = MullvadProxyClient::new().await?;
         let mut api_access_method = Self::get_access_method(&mut rpc, &cmd.item).await?;
 
@@ -111,47 +113,13 @@ impl ApiAccess {
             None => return Err(anyhow!("Can not edit built-in access method")),
             Some(x) => match x.clone() {
                 CustomProxy::Shadowsocks(shadowsocks) => {
-                    let ip = cmd.params.ip.unwrap_or(shadowsocks.endpoint.ip());
-                    let port = cmd.params.port.unwrap_or(shadowsocks.endpoint.port());
-                    let password = cmd.params.password.unwrap_or(shadowsocks.password);
-                    let cipher = cmd.params.cipher.unwrap_or(shadowsocks.cipher);
-                    AccessMethod::from(Shadowsocks::new((ip, port), cipher, password))
+                    AccessMethod::from(cmd.params.merge_shadowsocks(&shadowsocks))
                 }
                 CustomProxy::Socks5Local(local) => {
-                    let remote_ip = cmd.params.ip.unwrap_or(local.remote_endpoint.address.ip());
-                    let remote_port = cmd
-                        .params
-                        .port
-                        .unwrap_or(local.remote_endpoint.address.port());
-                    let local_port = cmd.params.local_port.unwrap_or(local.local_port);
-                    let remote_peer_transport_protocol = cmd
-                        .params
-                        .transport_protocol
-                        .unwrap_or(local.remote_endpoint.protocol);
-                    AccessMethod::from(Socks5Local::new_with_transport_protocol(
-                        (remote_ip, remote_port),
-                        local_port,
-                        remote_peer_transport_protocol,
-                    ))
+                    AccessMethod::from(cmd.params.merge_socks_local(&local))
                 }
                 CustomProxy::Socks5Remote(remote) => {
-                    let ip = cmd.params.ip.unwrap_or(remote.endpoint.ip());
-                    let port = cmd.params.port.unwrap_or(remote.endpoint.port());
-                    AccessMethod::from(match remote.auth {
-                        None => Socks5Remote::new((ip, port)),
-                        Some(credentials) => {
-                            let username = cmd
-                                .params
-                                .username
-                                .unwrap_or(credentials.username().to_string());
-                            let password = cmd
-                                .params
-                                .password
-                                .unwrap_or(credentials.password().to_string());
-                            let auth = SocksAuth::new(username, password)?;
-                            Socks5Remote::new_with_authentication((ip, port), auth)
-                        }
-                    })
+                    AccessMethod::from(cmd.params.merge_socks_remote(&remote)?)
                 }
             },
         };
@@ -190,12 +158,25 @@ impl ApiAccess {
         let access_method = Self::get_access_method(&mut rpc, &item).await?;
 
         println!("Testing access method \"{}\"", access_method.name);
-        match rpc.test_api_access_method(access_method.get_id()).await {
-            Ok(true) => {
-                println!("Success!");
-                Ok(())
-            }
-            Ok(false) | Err(_) => Err(anyhow!("Could not reach the Mullvad API.")),
+        let result = rpc.test_api_access_method(access_method.get_id()).await?;
+        format::print_access_method_test_result(&result);
+        if result.is_successful() {
+            println!("Success!");
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "Could not reach the Mullvad API: {}",
+                Self::failure_reason(&result)
+            ))
+        }
+    }
+
+    /// Describe why `result` is not successful.
+    fn failure_reason(result: &TestResult) -> String {
+        match (result.failed_step(), result.http_status) {
+            (Some(step), _) => format!("{} failed", step.step),
+            (None, Some(status)) => format!("unexpected HTTP status {status}"),
+            (None, None) => "no response".to_owned(),
         }
     }
 
@@ -216,13 +197,18 @@ impl ApiAccess {
         let new_access_method = Self::get_access_method(&mut rpc, &item).await?;
         let current_access_method = rpc.get_current_api_access_method().await?;
         // Try to reach the API with the newly selected access method.
-        rpc.test_api_access_method(new_access_method.get_id())
-            .await
-            .map_err(|_| {
-                anyhow!("Could not reach the Mullvad API using access method \"{}\". Rolling back to \"{}\"", new_access_method.get_name(), current_access_method.get_name())
-            })?
-
-            ;
+        let result = rpc.test_api_access_method(new_access_method.get_id()).await;
+        if !result.as_ref().is_ok_and(TestResult::is_successful) {
+            let reason = match &result {
+                Ok(result) => Self::failure_reason(result),
+                Err(error) => error.to_string(),
+            };
+            return Err(anyhow!(
+                "Could not reach the Mullvad API using access method \"{}\" ({reason}). Rolling back to \"{}\"",
+                new_access_method.get_name(),
+                current_access_method.get_name()
+            ));
+        }
         // If the test succeeded, the new access method should be used from now on.
         rpc.set_access_method(new_access_method.get_id()).await?;
         println!("Using access method \"{}\"", new_access_method.get_name());
@@ -235,6 +221,7 @@ impl ApiAccess {
         let mut access_method_formatter = pp::ApiAccessMethodFormatter::new(&current);
         access_method_formatter.settings.write_enabled = false;
         println!("{}", access_method_formatter);
+        println!("API address: {}", rpc.get_api_address().await?);
         Ok(())
     }
 
@@ -363,7 +350,7 @@ pub struct EditParams {
 /// we define them in a hidden-away module.
 mod conversions {
     use super::{AddCustomCommands, AddSocks5Commands};
-    use crate::cmds::proxies::{Error, SocksAuthentication};
+    use crate::cmds::proxies::Error;
     use mullvad_types::access_method as daemon_types;
     use talpid_types::net::proxy as talpid_types;
 
@@ -379,27 +366,12 @@ mod conversions {
                             add.transport_protocol,
                         ),
                     )),
-                    AddSocks5Commands::Remote { add, .. } => {
-                        Ok(daemon_types::AccessMethod::from(match add.authentication {
-                            Some(SocksAuthentication { username, password }) => {
-                                let auth = talpid_types::SocksAuth::new(username, password)?;
-                                talpid_types::Socks5Remote::new_with_authentication(
-                                    (add.remote_ip, add.remote_port),
-                                    auth,
-                                )
-                            }
-                            None => {
-                                talpid_types::Socks5Remote::new((add.remote_ip, add.remote_port))
-                            }
-                        }))
-                    }
+                    AddSocks5Commands::Remote { add, .. } => Ok(daemon_types::AccessMethod::from(
+                        talpid_types::Socks5Remote::try_from(add)?,
+                    )),
                 },
                 AddCustomCommands::Shadowsocks { add, .. } => Ok(daemon_types::AccessMethod::from(
-                    talpid_types::Shadowsocks::new(
-                        (add.remote_ip, add.remote_port),
-                        add.cipher,
-                        add.password,
-                    ),
+                    talpid_types::Shadowsocks::from(add),
                 )),
             }
         }
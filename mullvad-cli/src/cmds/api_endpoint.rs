@@ -0,0 +1,92 @@
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use mullvad_management_interface::MullvadProxyClient;
+use mullvad_types::settings::CustomApiEndpoint;
+use std::{net::SocketAddr, path::PathBuf};
+
+#[derive(Subcommand, Debug)]
+pub enum ApiEndpoint {
+    /// Show the API deployment that is used instead of the Mullvad API, if any
+    Get,
+
+    /// Use a self-hosted API deployment instead of the Mullvad API. Must be allowed by the
+    /// daemon config
+    Set {
+        /// Hostname of the API, used for SNI and to verify its certificate
+        host: String,
+
+        /// IP address and port of the API
+        address: SocketAddr,
+
+        /// Only trust the PEM encoded root certificates in this file
+        #[arg(long, conflicts_with = "disable_tls")]
+        root_certificates: Option<PathBuf>,
+
+        /// Use plain HTTP. Only allowed in development builds
+        #[arg(long)]
+        disable_tls: bool,
+
+        /// Never use bridges or proxies to reach the API
+        #[arg(long)]
+        force_direct: bool,
+    },
+
+    /// Go back to using the Mullvad API
+    Clear,
+}
+
+impl ApiEndpoint {
+    pub async fn handle(self) -> Result<()> {
+        let mut rpc = MullvadProxyClient::new().await?;
+        match self {
+            ApiEndpoint::Get => match rpc.get_settings().await?.custom_api_endpoint {
+                Some(endpoint) => {
+                    println!("API endpoint: {} ({})", endpoint.host, endpoint.address);
+                    println!(
+                        "Root certificates: {}",
+                        if endpoint.disable_tls {
+                            "none, TLS is disabled"
+                        } else if endpoint.root_certificates.is_some() {
+                            "pinned"
+                        } else {
+                            "default"
+                        }
+                    );
+                    println!(
+                        "Force direct: {}",
+                        if endpoint.force_direct { "on" } else { "off" }
+                    );
+                }
+                None => println!("API endpoint: default"),
+            },
+            ApiEndpoint::Set {
+                host,
+                address,
+                root_certificates,
+                disable_tls,
+                force_direct,
+            } => {
+                let root_certificates = root_certificates
+                    .map(|path| {
+                        std::fs::read_to_string(&path)
+                            .with_context(|| format!("Failed to read {}", path.display()))
+                    })
+                    .transpose()?;
+                rpc.set_custom_api_endpoint(CustomApiEndpoint {
+                    host,
+                    address,
+                    root_certificates,
+                    disable_tls,
+                    force_direct,
+                })
+                .await?;
+                println!("Updated API endpoint");
+            }
+            ApiEndpoint::Clear => {
+                rpc.clear_custom_api_endpoint().await?;
+                println!("API endpoint: default");
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,39 @@
+use anyhow::{Context, Result};
+use mullvad_management_interface::MullvadProxyClient;
+use std::{
+    fs::File,
+    io::{read_to_string, stdin, BufReader},
+};
+
+/// Read a settings document and send it to the daemon, which applies all of it or none of it.
+/// The document may be written in YAML or JSON, and uses the same keys as the daemon's
+/// settings file. Settings that are left out keep their current values.
+///
+/// * If `source` is "-", read the document from standard input
+/// * Otherwise, interpret `source` as a filepath and read from the provided file
+pub async fn apply(source: String, dry_run: bool) -> Result<()> {
+    let document = tokio::task::spawn_blocking(move || match source.as_str() {
+        "-" => read_to_string(BufReader::new(stdin())).context("Failed to read from stdin"),
+        _ => read_to_string(File::open(&source)?)
+            .context(format!("Failed to read from path: {source}")),
+    })
+    .await
+    .unwrap()?;
+
+    // YAML is a superset of JSON, so this accepts both
+    let document: serde_json::Value =
+        serde_yaml_ng::from_str(&document).context("Failed to parse settings document")?;
+
+    let mut rpc = MullvadProxyClient::new().await?;
+    rpc.apply_settings(document.to_string(), dry_run)
+        .await
+        .context("Error applying settings")?;
+
+    if dry_run {
+        println!("Settings are valid");
+    } else {
+        println!("Settings applied");
+    }
+
+    Ok(())
+}
@@ -93,6 +93,32 @@ pub enum CustomCommands {
     Use,
     /// Stop using the custom bridge configuration.
     Disable,
+    /// Tunnel through more proxies after the custom bridge.
+    #[clap(subcommand)]
+    Chain(ChainCommands),
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ChainCommands {
+    /// Add a proxy to the end of the chain.
+    #[clap(subcommand)]
+    Add(AddChainCommands),
+    /// Remove every proxy from the chain, leaving only the custom bridge.
+    Clear,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum AddChainCommands {
+    /// Configure a remote SOCKS5 proxy
+    Socks5 {
+        #[clap(flatten)]
+        add: Socks5RemoteAdd,
+    },
+    /// Configure a Shadowsocks proxy
+    Shadowsocks {
+        #[clap(flatten)]
+        add: ShadowsocksAdd,
+    },
 }
 
 #[derive(Subcommand, Debug, Clone)]
@@ -196,6 +222,10 @@ impl Bridge {
             println!("Custom proxy");
             println!("{}", CustomProxyFormatter { custom_proxy });
         }
+        for (index, custom_proxy) in settings.bridge_settings.custom_chain.iter().enumerate() {
+            println!("Chained proxy {}", index + 1);
+            println!("{}", CustomProxyFormatter { custom_proxy });
+        }
 
         Ok(())
     }
@@ -290,7 +320,47 @@ impl Bridge {
             CustomCommands::Edit(edit) => Self::custom_bridge_edit(edit).await,
             CustomCommands::Use => Self::custom_bridge_use().await,
             CustomCommands::Disable => Self::custom_bridge_disable().await,
+            CustomCommands::Chain(ChainCommands::Add(add)) => {
+                Self::custom_bridge_chain_add(add).await
+            }
+            CustomCommands::Chain(ChainCommands::Clear) => Self::custom_bridge_chain_clear().await,
+        }
+    }
+
+    async fn custom_bridge_chain_add(add_commands: AddChainCommands) -> Result<()> {
+        let mut rpc = MullvadProxyClient::new().await?;
+        let mut settings = rpc.get_settings().await?;
+
+        if settings.bridge_settings.custom.is_none() {
+            bail!("Cannot add a chained proxy as there is no custom bridge");
         }
+
+        settings
+            .bridge_settings
+            .custom_chain
+            .push(match add_commands {
+                AddChainCommands::Socks5 { add } => {
+                    CustomProxy::Socks5Remote(Socks5Remote::try_from(add)?)
+                }
+                AddChainCommands::Shadowsocks { add } => {
+                    CustomProxy::Shadowsocks(Shadowsocks::from(add))
+                }
+            });
+
+        rpc.set_bridge_settings(settings.bridge_settings)
+            .await
+            .map_err(anyhow::Error::from)
+    }
+
+    async fn custom_bridge_chain_clear() -> Result<()> {
+        let mut rpc = MullvadProxyClient::new().await?;
+        let mut settings = rpc.get_settings().await?;
+
+        settings.bridge_settings.custom_chain.clear();
+
+        rpc.set_bridge_settings(settings.bridge_settings)
+            .await
+            .map_err(anyhow::Error::from)
     }
 
     async fn custom_bridge_edit(edit: ProxyEditParams) -> Result<()> {
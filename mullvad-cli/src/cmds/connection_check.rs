@@ -0,0 +1,66 @@
+use anyhow::Result;
+use clap::Subcommand;
+use mullvad_management_interface::MullvadProxyClient;
+use mullvad_types::settings::ConnectionCheckSettings;
+
+use super::BooleanOption;
+
+#[derive(Subcommand, Debug)]
+pub enum ConnectionCheck {
+    /// Display the current connection check settings
+    Get,
+
+    /// Change the connection check settings
+    #[clap(subcommand)]
+    Set(SetCommands),
+
+    /// Use am.i.mullvad.net and check the connection every time the tunnel comes up
+    Reset,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum SetCommands {
+    /// Set the host of the service used to look up the exit location and check the
+    /// connection. It must serve the same API as am.i.mullvad.net
+    Host { host: String },
+
+    /// Whether to check the exit IP, exit relay and DNS resolvers every time the tunnel comes up
+    Verify { policy: BooleanOption },
+}
+
+impl ConnectionCheck {
+    pub async fn handle(self) -> Result<()> {
+        let mut rpc = MullvadProxyClient::new().await?;
+        match self {
+            ConnectionCheck::Get => {
+                let settings = rpc.get_settings().await?.connection_check;
+                println!("Host: {}", settings.host);
+                println!(
+                    "Verify after connecting: {}",
+                    BooleanOption::from(settings.verify_after_connect)
+                );
+            }
+            ConnectionCheck::Set(SetCommands::Host { host }) => {
+                let settings = rpc.get_settings().await?.connection_check;
+                rpc.set_connection_check_settings(ConnectionCheckSettings { host, ..settings })
+                    .await?;
+                println!("Updated connection check host");
+            }
+            ConnectionCheck::Set(SetCommands::Verify { policy }) => {
+                let settings = rpc.get_settings().await?.connection_check;
+                rpc.set_connection_check_settings(ConnectionCheckSettings {
+                    verify_after_connect: *policy,
+                    ..settings
+                })
+                .await?;
+                println!("Changed connection check setting");
+            }
+            ConnectionCheck::Reset => {
+                rpc.set_connection_check_settings(ConnectionCheckSettings::default())
+                    .await?;
+                println!("Reset connection check settings");
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,14 +1,67 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use mullvad_management_interface::MullvadProxyClient;
 use mullvad_types::{
+    connection_diagnosis::BlockedReason,
     constraints::Constraint,
     relay_constraints::{RelayConstraints, RelaySettings},
+    states::TargetState,
 };
+use std::time::Duration;
+use talpid_types::self_test::StepOutcome;
 
 #[derive(clap::Subcommand, Debug)]
 pub enum DebugCommands {
     /// Block all internet connection by setting an invalid relay constraint.
     BlockConnection,
+
+    /// Log the given modules at trace level for a limited time and print the redacted output.
+    Capture {
+        /// Modules to capture, e.g. `talpid_core::tunnel_state_machine`. Submodules are
+        /// included. All modules are captured if none are given.
+        modules: Vec<String>,
+
+        /// Number of seconds to capture for. The daemon caps this at five minutes.
+        #[arg(long, short = 'd', default_value_t = 30)]
+        duration: u64,
+    },
+
+    /// Print the environment variables that override the behavior of the daemon
+    RuntimeConfig,
+
+    /// Print the target state and the relay that the daemon would resume if it was restarted
+    PersistedTarget,
+
+    /// Print the routes, firewall rules, resolver configuration and interfaces as they were when
+    /// the daemon last entered the error state
+    LastError,
+
+    /// Print how restoring the network went when the daemon last shut down
+    LastShutdown,
+
+    /// Remove firewall filters left behind by older versions of the app, for example because
+    /// they crashed. Filters that are in use are not removed.
+    #[cfg(target_os = "windows")]
+    CleanupFirewall,
+
+    /// Print the firewall rules that the daemon has loaded into pf. These are read back from pf,
+    /// so they show what is actually being enforced.
+    #[cfg(target_os = "macos")]
+    FirewallRules,
+
+    /// Check whether traffic is blocked during early boot, before the daemon has started. This is
+    /// the case while lockdown mode is enabled.
+    #[cfg(target_os = "macos")]
+    EarlyBootBlocking,
+
+    /// Test tunnel device creation, a WireGuard handshake over loopback, routing, DNS and the
+    /// firewall, and print the outcome of each step. Every change is undone afterwards, but
+    /// network access may be blocked for a moment. Only possible while disconnected.
+    #[command(name = "selftest")]
+    SelfTest,
+
+    /// Probe UDP, DNS and the API outside of any tunnel, and print the likely reasons that
+    /// connecting fails. Only possible while disconnected and not in lockdown mode.
+    Diagnose,
 }
 
 impl DebugCommands {
@@ -41,6 +94,131 @@ impl DebugCommands {
                 eprintln!("WARNING: ENTERED BLOCKED MODE");
                 Ok(())
             }
+            DebugCommands::Capture { modules, duration } => {
+                let mut rpc = MullvadProxyClient::new().await?;
+                eprintln!("Capturing logs for {duration} seconds...");
+                let output = rpc
+                    .capture_debug_log(modules, Duration::from_secs(duration))
+                    .await?;
+                print!("{output}");
+                Ok(())
+            }
+            DebugCommands::RuntimeConfig => {
+                let mut rpc = MullvadProxyClient::new().await?;
+                let config = rpc.get_runtime_config().await?;
+                if config.overrides.is_empty() {
+                    println!("No environment overrides are active");
+                }
+                for env_override in config.overrides {
+                    println!("{env_override}");
+                }
+                Ok(())
+            }
+            DebugCommands::PersistedTarget => {
+                let mut rpc = MullvadProxyClient::new().await?;
+                let persisted = rpc.get_persisted_target().await?;
+                println!(
+                    "Target state: {}",
+                    match persisted.target_state {
+                        TargetState::Secured => "secured",
+                        TargetState::Unsecured => "unsecured",
+                    }
+                );
+                match persisted.relay {
+                    Some(relay) => println!("Last relay: {relay}"),
+                    None => println!("Last relay: none"),
+                }
+                Ok(())
+            }
+            DebugCommands::LastError => {
+                let mut rpc = MullvadProxyClient::new().await?;
+                match rpc.get_last_error_diagnostics().await? {
+                    Some(report) => print!("{report}"),
+                    None => println!("The daemon has not entered the error state since it started"),
+                }
+                Ok(())
+            }
+            DebugCommands::LastShutdown => {
+                let mut rpc = MullvadProxyClient::new().await?;
+                let Some(report) = rpc.get_last_shutdown_report().await? else {
+                    println!("No shutdown has been recorded");
+                    return Ok(());
+                };
+                println!("Shut down at {}", report.time.with_timezone(&chrono::Local));
+                if report.teardown.steps.is_empty() {
+                    println!("Nothing had to be restored");
+                } else {
+                    println!("{}", report.teardown);
+                }
+                Ok(())
+            }
+            #[cfg(target_os = "windows")]
+            DebugCommands::CleanupFirewall => {
+                let mut rpc = MullvadProxyClient::new().await?;
+                let num_removed = rpc.cleanup_firewall().await?;
+                println!("Removed {num_removed} stale firewall filters");
+                Ok(())
+            }
+            #[cfg(target_os = "macos")]
+            DebugCommands::FirewallRules => {
+                let mut rpc = MullvadProxyClient::new().await?;
+                print!("{}", rpc.get_firewall_rules().await?);
+                Ok(())
+            }
+            #[cfg(target_os = "macos")]
+            DebugCommands::EarlyBootBlocking => {
+                let mut rpc = MullvadProxyClient::new().await?;
+                if rpc.is_early_boot_blocking_installed().await? {
+                    println!("Traffic is blocked during early boot");
+                } else {
+                    println!("Traffic is not blocked during early boot");
+                }
+                Ok(())
+            }
+            DebugCommands::SelfTest => {
+                let mut rpc = MullvadProxyClient::new().await?;
+                let steps = rpc.run_self_test().await?;
+                let mut failed = false;
+                for step in steps {
+                    match step.outcome {
+                        StepOutcome::Passed => println!("{:<6}{}", "PASS", step.name),
+                        StepOutcome::Failed(error) => {
+                            failed = true;
+                            println!("{:<6}{}: {error}", "FAIL", step.name);
+                        }
+                        StepOutcome::Skipped(reason) => {
+                            println!("{:<6}{}: {reason}", "SKIP", step.name)
+                        }
+                    }
+                }
+                if failed {
+                    bail!("One or more steps of the self test failed");
+                }
+                Ok(())
+            }
+            DebugCommands::Diagnose => {
+                let mut rpc = MullvadProxyClient::new().await?;
+                let reasons = rpc.diagnose_connection().await?;
+                if reasons.is_empty() {
+                    println!("No reason for connecting to fail was found");
+                }
+                for reason in reasons {
+                    let advice = match reason {
+                        BlockedReason::ClockSkew => "Correct the date and time of this computer.",
+                        BlockedReason::DnsHijack => {
+                            "Log in to the captive portal of the network, if it has one."
+                        }
+                        BlockedReason::UdpBlocked => {
+                            "Enable obfuscation or switch to OpenVPN over TCP."
+                        }
+                        BlockedReason::ApiUnreachable => {
+                            "Check the network connection, or try another API access method."
+                        }
+                    };
+                    println!("Likely cause: {reason}. {advice}");
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -1,19 +1,59 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use clap::Subcommand;
 use mullvad_management_interface::MullvadProxyClient;
-use mullvad_types::settings::{CustomDnsOptions, DefaultDnsOptions, DnsOptions, DnsState};
+use mullvad_types::settings::{
+    BlocklistSource, CustomBlocklist, CustomDnsOptions, DefaultDnsOptions, DnsOptions, DnsState,
+};
 use std::net::IpAddr;
 
+#[cfg(target_os = "linux")]
+use super::BooleanOption;
+
 #[derive(Subcommand, Debug)]
 pub enum Dns {
-    /// Display the current DNS settings
-    Get,
+    /// Display the current DNS settings, or the value of a single setting
+    Get {
+        #[clap(subcommand)]
+        setting: Option<DnsGet>,
+    },
 
     /// Set DNS servers to use
     Set {
         #[clap(subcommand)]
         cmd: DnsSet,
     },
+
+    /// Manage custom lists of domains to block. They are only used together with the default
+    /// DNS server
+    #[clap(subcommand)]
+    Blocklist(Blocklist),
+
+    /// Set whether to block all traffic or only warn if DNS is managed by software that cannot
+    /// be configured, such as a standalone dnsmasq
+    #[cfg(target_os = "linux")]
+    UnsupportedManager {
+        #[arg(value_parser = BooleanOption::custom_parser("block", "warn"))]
+        policy: BooleanOption,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Blocklist {
+    /// Add a blocklist. The list may be in hosts file format, or contain one domain per line
+    Add {
+        /// An 'https://' URL to download the list from, or an absolute path to a file containing
+        /// the list
+        source: BlocklistSource,
+    },
+
+    /// Remove a blocklist
+    Remove { source: BlocklistSource },
+
+    /// Start blocking the domains on a blocklist
+    Enable { source: BlocklistSource },
+
+    /// Stop blocking the domains on a blocklist, without removing it
+    Disable { source: BlocklistSource },
 }
 
 #[derive(Subcommand, Debug, Clone)]
@@ -54,10 +94,31 @@ pub enum DnsSet {
     },
 }
 
+/// A single DNS setting to show. The value is printed in the same form that `dns set` accepts
+#[derive(Subcommand, Debug, Clone)]
+pub enum DnsGet {
+    /// Show which kind of DNS server is used, 'default' or 'custom'
+    State,
+
+    /// Show the content blocking flags used with the default DNS server
+    Default,
+
+    /// Show the custom DNS servers
+    Custom,
+
+    /// Show whether to block all traffic or only warn if DNS is managed by software that cannot
+    /// be configured
+    #[cfg(target_os = "linux")]
+    UnsupportedManager,
+}
+
 impl Dns {
     pub async fn handle(self) -> Result<()> {
         match self {
-            Dns::Get => Self::get().await,
+            Dns::Get { setting: None } => Self::get().await,
+            Dns::Get {
+                setting: Some(setting),
+            } => Self::get_setting(setting).await,
             Dns::Set {
                 cmd:
                     DnsSet::Default {
@@ -82,6 +143,9 @@ impl Dns {
             Dns::Set {
                 cmd: DnsSet::Custom { servers },
             } => Self::set_custom(servers).await,
+            Dns::Blocklist(cmd) => Self::blocklist(cmd).await,
+            #[cfg(target_os = "linux")]
+            Dns::UnsupportedManager { policy } => Self::set_unsupported_manager(policy).await,
         }
     }
 
@@ -104,6 +168,15 @@ impl Dns {
                     "Block social media: {}",
                     options.default_options.block_social_media
                 );
+                println!("Custom blocklists:");
+                for blocklist in &options.default_options.custom_blocklists {
+                    let state = if blocklist.enabled {
+                        "enabled"
+                    } else {
+                        "disabled"
+                    };
+                    println!("{} ({state})", blocklist.source);
+                }
             }
             DnsState::Custom => {
                 println!("Custom DNS: yes\nServers:");
@@ -112,6 +185,55 @@ impl Dns {
                 }
             }
         }
+        #[cfg(target_os = "linux")]
+        println!(
+            "Unsupported DNS manager: {}",
+            BooleanOption::with_labels(options.block_on_unsupported_manager, "block", "warn")
+        );
+
+        Ok(())
+    }
+
+    async fn get_setting(setting: DnsGet) -> Result<()> {
+        let mut rpc = MullvadProxyClient::new().await?;
+        let options = rpc.get_settings().await?.tunnel_options.dns_options;
+
+        match setting {
+            DnsGet::State => match options.state {
+                DnsState::Default => println!("default"),
+                DnsState::Custom => println!("custom"),
+            },
+            DnsGet::Default => {
+                let default_options = &options.default_options;
+                let flags = [
+                    (default_options.block_ads, "--block-ads"),
+                    (default_options.block_trackers, "--block-trackers"),
+                    (default_options.block_malware, "--block-malware"),
+                    (default_options.block_adult_content, "--block-adult-content"),
+                    (default_options.block_gambling, "--block-gambling"),
+                    (default_options.block_social_media, "--block-social-media"),
+                ];
+                let enabled: Vec<_> = flags
+                    .into_iter()
+                    .filter_map(|(enabled, flag)| enabled.then_some(flag))
+                    .collect();
+                println!("{}", enabled.join(" "));
+            }
+            DnsGet::Custom => {
+                let servers: Vec<_> = options
+                    .custom_options
+                    .addresses
+                    .iter()
+                    .map(|server| server.to_string())
+                    .collect();
+                println!("{}", servers.join(" "));
+            }
+            #[cfg(target_os = "linux")]
+            DnsGet::UnsupportedManager => println!(
+                "{}",
+                BooleanOption::with_labels(options.block_on_unsupported_manager, "block", "warn")
+            ),
+        }
 
         Ok(())
     }
@@ -135,6 +257,7 @@ impl Dns {
                 block_adult_content,
                 block_gambling,
                 block_social_media,
+                ..settings.tunnel_options.dns_options.default_options
             },
             ..settings.tunnel_options.dns_options
         })
@@ -155,4 +278,69 @@ impl Dns {
         println!("Updated DNS settings");
         Ok(())
     }
+
+    #[cfg(target_os = "linux")]
+    async fn set_unsupported_manager(policy: BooleanOption) -> Result<()> {
+        let mut rpc = MullvadProxyClient::new().await?;
+        let settings = rpc.get_settings().await?;
+        rpc.set_dns_options(DnsOptions {
+            block_on_unsupported_manager: *policy,
+            ..settings.tunnel_options.dns_options
+        })
+        .await?;
+        println!("Updated DNS settings");
+        Ok(())
+    }
+
+    async fn blocklist(cmd: Blocklist) -> Result<()> {
+        let mut rpc = MullvadProxyClient::new().await?;
+        let mut dns_options = rpc.get_settings().await?.tunnel_options.dns_options;
+        let blocklists = &mut dns_options.default_options.custom_blocklists;
+
+        let message = match cmd {
+            Blocklist::Add { source } => {
+                if blocklists.iter().any(|list| list.source == source) {
+                    return Err(anyhow!("{source} has already been added"));
+                }
+                blocklists.push(CustomBlocklist {
+                    source,
+                    enabled: true,
+                });
+                "Added blocklist"
+            }
+            Blocklist::Remove { source } => {
+                let len = blocklists.len();
+                blocklists.retain(|list| list.source != source);
+                if blocklists.len() == len {
+                    return Err(anyhow!("{source} is not a blocklist"));
+                }
+                "Removed blocklist"
+            }
+            Blocklist::Enable { source } => {
+                set_blocklist_enabled(blocklists, &source, true)?;
+                "Enabled blocklist"
+            }
+            Blocklist::Disable { source } => {
+                set_blocklist_enabled(blocklists, &source, false)?;
+                "Disabled blocklist"
+            }
+        };
+
+        rpc.set_dns_options(dns_options).await?;
+        println!("{message}");
+        Ok(())
+    }
+}
+
+fn set_blocklist_enabled(
+    blocklists: &mut [CustomBlocklist],
+    source: &BlocklistSource,
+    enabled: bool,
+) -> Result<()> {
+    let blocklist = blocklists
+        .iter_mut()
+        .find(|list| &list.source == source)
+        .ok_or_else(|| anyhow!("{source} is not a blocklist"))?;
+    blocklist.enabled = enabled;
+    Ok(())
 }
@@ -0,0 +1,17 @@
+use anyhow::{bail, Result};
+use mullvad_management_interface::MullvadProxyClient;
+use mullvad_types::health::HealthStatus;
+
+pub async fn print() -> Result<()> {
+    let mut rpc = MullvadProxyClient::new().await?;
+    let health = rpc.get_daemon_health().await?;
+
+    for (name, component) in &health.components {
+        println!("{name:22}: {} ({})", component.status, component.details);
+    }
+
+    if health.status() == HealthStatus::Failed {
+        bail!("One or more components of the daemon failed");
+    }
+    Ok(())
+}
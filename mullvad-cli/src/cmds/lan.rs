@@ -14,6 +14,14 @@ pub enum Lan {
         #[arg(value_parser = BooleanOption::custom_parser("allow", "block"))]
         policy: BooleanOption,
     },
+
+    /// Announce the tunnel state on the local network over mDNS, so that home automation can
+    /// react to it. Only the state is announced. Requires local network sharing to be allowed,
+    /// and is only supported on Linux and macOS
+    Beacon {
+        #[arg(value_parser = BooleanOption::custom_parser("on", "off"))]
+        state: BooleanOption,
+    },
 }
 
 impl Lan {
@@ -21,6 +29,7 @@ impl Lan {
         match self {
             Lan::Get => Self::get().await,
             Lan::Set { policy } => Self::set(policy).await,
+            Lan::Beacon { state } => Self::set_beacon(state).await,
         }
     }
 
@@ -31,11 +40,20 @@ impl Lan {
         Ok(())
     }
 
+    async fn set_beacon(state: BooleanOption) -> Result<()> {
+        let mut rpc = MullvadProxyClient::new().await?;
+        rpc.set_lan_beacon(*state).await?;
+        println!("Changed LAN beacon setting");
+        Ok(())
+    }
+
     async fn get() -> Result<()> {
         let mut rpc = MullvadProxyClient::new().await?;
-        let allow_lan =
-            BooleanOption::with_labels(rpc.get_settings().await?.allow_lan, "allow", "block");
+        let settings = rpc.get_settings().await?;
+        let allow_lan = BooleanOption::with_labels(settings.allow_lan, "allow", "block");
         println!("Local network sharing setting: {allow_lan}");
+        let lan_beacon = BooleanOption::with_labels(settings.lan_beacon, "on", "off");
+        println!("LAN beacon: {lan_beacon}");
         Ok(())
     }
 }
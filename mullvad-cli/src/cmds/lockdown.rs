@@ -1,6 +1,7 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use clap::Subcommand;
 use mullvad_management_interface::MullvadProxyClient;
+use std::time::Duration;
 
 use super::BooleanOption;
 
@@ -9,21 +10,42 @@ pub enum LockdownMode {
     /// Display the current lockdown mode setting
     Get,
     /// Change the lockdown mode setting
-    Set { policy: BooleanOption },
+    Set {
+        policy: BooleanOption,
+        /// Seconds to wait before blocking traffic, if the tunnel is disconnected. Use
+        /// `mullvad status listen` to follow the countdown
+        #[arg(long)]
+        grace_period: Option<u32>,
+    },
 }
 
 impl LockdownMode {
     pub async fn handle(self) -> Result<()> {
         match self {
             LockdownMode::Get => Self::get().await,
-            LockdownMode::Set { policy } => Self::set(policy).await,
+            LockdownMode::Set {
+                policy,
+                grace_period,
+            } => Self::set(policy, grace_period).await,
         }
     }
 
-    async fn set(policy: BooleanOption) -> Result<()> {
+    async fn set(policy: BooleanOption, grace_period: Option<u32>) -> Result<()> {
         let mut rpc = MullvadProxyClient::new().await?;
-        rpc.set_block_when_disconnected(*policy).await?;
-        println!("Changed lockdown mode setting");
+        match grace_period {
+            Some(_) if !*policy => {
+                bail!("A grace period can only be used when enabling lockdown mode")
+            }
+            Some(seconds) => {
+                rpc.enable_block_when_disconnected_after(Duration::from_secs(u64::from(seconds)))
+                    .await?;
+                println!("Lockdown mode will be enabled in {seconds} seconds if disconnected");
+            }
+            None => {
+                rpc.set_block_when_disconnected(*policy).await?;
+                println!("Changed lockdown mode setting");
+            }
+        }
         Ok(())
     }
 
@@ -3,25 +3,34 @@ use std::{io::stdin, ops::Deref};
 
 pub mod account;
 pub mod api_access;
+pub mod api_endpoint;
+pub mod apply;
 pub mod auto_connect;
 pub mod beta_program;
 pub mod bridge;
+pub mod connection_check;
 pub mod custom_list;
 pub mod debug;
 pub mod dns;
+pub mod health;
 pub mod lan;
 pub mod lockdown;
 pub mod obfuscation;
 pub mod patch;
+pub mod problem_report;
+pub mod profile;
 pub mod proxies;
 pub mod relay;
 pub mod relay_constraints;
 pub mod reset;
+pub mod settings_lock;
 pub mod split_tunnel;
+pub mod stats;
 pub mod status;
 pub mod tunnel;
 pub mod tunnel_state;
 pub mod version;
+pub mod webhook;
 
 /// A value parser that parses "on" or "off" into a boolean
 #[derive(Debug, Clone, Copy)]
@@ -3,13 +3,19 @@ use clap::Subcommand;
 use mullvad_management_interface::MullvadProxyClient;
 use mullvad_types::{
     constraints::Constraint,
-    relay_constraints::{ObfuscationSettings, SelectedObfuscation, Udp2TcpObfuscationSettings},
+    relay_constraints::{
+        ObfuscationSettings, SelectedObfuscation, TlsObfuscationSettings,
+        Udp2TcpObfuscationSettings,
+    },
 };
 
 #[derive(Subcommand, Debug)]
 pub enum Obfuscation {
-    /// Get current obfuscation settings
-    Get,
+    /// Get current obfuscation settings, or the value of a single setting
+    Get {
+        #[clap(subcommand)]
+        setting: Option<GetCommands>,
+    },
 
     /// Set obfuscation settings
     #[clap(subcommand)]
@@ -28,12 +34,33 @@ pub enum SetCommands {
         #[arg(long, short = 'p')]
         port: Constraint<u16>,
     },
+
+    /// Specifies the config for the TLS obfuscator.
+    Tls {
+        /// Server name to send in the TLS handshake
+        #[arg(long)]
+        sni: String,
+    },
+}
+
+/// A single obfuscation setting to show. The value is printed in the same form that
+/// `obfuscation set` accepts
+#[derive(Subcommand, Debug, Clone)]
+pub enum GetCommands {
+    /// Show the obfuscation mode
+    Mode,
+
+    /// Show the port used by the udp2tcp obfuscator, or 'any'
+    Udp2tcp,
+
+    /// Show the server name sent by the TLS obfuscator
+    Tls,
 }
 
 impl Obfuscation {
     pub async fn handle(self) -> Result<()> {
         match self {
-            Obfuscation::Get => {
+            Obfuscation::Get { setting: None } => {
                 let mut rpc = MullvadProxyClient::new().await?;
                 let obfuscation_settings = rpc.get_settings().await?.obfuscation_settings;
                 println!(
@@ -41,12 +68,27 @@ impl Obfuscation {
                     obfuscation_settings.selected_obfuscation
                 );
                 println!("udp2tcp settings: {}", obfuscation_settings.udp2tcp);
+                println!("TLS settings: {}", obfuscation_settings.tls);
                 Ok(())
             }
+            Obfuscation::Get {
+                setting: Some(setting),
+            } => Self::get_setting(setting).await,
             Obfuscation::Set(subcmd) => Self::set(subcmd).await,
         }
     }
 
+    async fn get_setting(setting: GetCommands) -> Result<()> {
+        let mut rpc = MullvadProxyClient::new().await?;
+        let obfuscation_settings = rpc.get_settings().await?.obfuscation_settings;
+        match setting {
+            GetCommands::Mode => println!("{}", obfuscation_settings.selected_obfuscation),
+            GetCommands::Udp2tcp => println!("{}", obfuscation_settings.udp2tcp.port),
+            GetCommands::Tls => println!("{}", obfuscation_settings.tls.sni),
+        }
+        Ok(())
+    }
+
     async fn set(subcmd: SetCommands) -> Result<()> {
         let mut rpc = MullvadProxyClient::new().await?;
         let current_settings = rpc.get_settings().await?.obfuscation_settings;
@@ -66,6 +108,13 @@ impl Obfuscation {
                 })
                 .await?;
             }
+            SetCommands::Tls { sni } => {
+                rpc.set_obfuscation_settings(ObfuscationSettings {
+                    tls: TlsObfuscationSettings { sni },
+                    ..current_settings
+                })
+                .await?;
+            }
         }
 
         println!("Updated obfuscation settings");
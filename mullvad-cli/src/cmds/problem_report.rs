@@ -0,0 +1,44 @@
+use anyhow::{bail, Context, Result};
+use futures::StreamExt;
+use mullvad_management_interface::{client::DaemonEvent, MullvadProxyClient};
+use mullvad_types::problem_report::{ProblemReportRequest, UploadState};
+use std::path::PathBuf;
+
+/// Have the daemon upload the report at `path`, and print each attempt until it is done. The
+/// upload continues in the daemon if this command is interrupted.
+pub async fn send(path: PathBuf, email: String, message: String) -> Result<()> {
+    let report = tokio::fs::read(&path)
+        .await
+        .with_context(|| format!("Failed to read problem report: {}", path.display()))?;
+    let request = ProblemReportRequest {
+        email,
+        message,
+        report: String::from_utf8_lossy(&report).into_owned(),
+    };
+
+    let mut rpc = MullvadProxyClient::new().await?;
+    // Subscribe before starting the upload so that no progress is missed
+    let mut events = rpc.events_listen().await?;
+    let id = rpc.send_problem_report(request).await?;
+
+    while let Some(event) = events.next().await {
+        let DaemonEvent::ProblemReportProgress(progress) = event? else {
+            continue;
+        };
+        if progress.id != id {
+            continue;
+        }
+        match progress.state {
+            UploadState::Completed => {
+                println!("Problem report sent");
+                return Ok(());
+            }
+            UploadState::Failed(error) => bail!("Failed to send problem report: {error}"),
+            UploadState::Uploading => {
+                println!("Sending problem report ({} bytes)", progress.report_bytes)
+            }
+            state @ UploadState::Retrying { .. } => println!("{state}"),
+        }
+    }
+    bail!("The daemon stopped sending events before the problem report was sent")
+}
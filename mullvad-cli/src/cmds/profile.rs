@@ -0,0 +1,75 @@
+use anyhow::Result;
+use clap::Subcommand;
+use mullvad_management_interface::MullvadProxyClient;
+use mullvad_types::{relay_constraints::RelaySettingsFormatter, settings::DnsState};
+
+#[derive(Subcommand, Debug)]
+pub enum Profile {
+    /// Save the current relay, obfuscation and DNS settings as a profile. An existing profile
+    /// with the same name is replaced
+    Save {
+        /// A name for the profile
+        name: String,
+    },
+
+    /// Replace the relay, obfuscation and DNS settings with the ones in a profile
+    Use {
+        /// A profile
+        name: String,
+    },
+
+    /// Show all profiles
+    List,
+
+    /// Delete a profile
+    Delete {
+        /// A profile
+        name: String,
+    },
+}
+
+impl Profile {
+    pub async fn handle(self) -> Result<()> {
+        let mut rpc = MullvadProxyClient::new().await?;
+        match self {
+            Profile::Save { name } => {
+                rpc.save_profile(name).await?;
+                println!("Saved profile");
+            }
+            Profile::Use { name } => {
+                rpc.use_profile(name).await?;
+                println!("Switched to profile");
+            }
+            Profile::List => Self::list(&mut rpc).await?,
+            Profile::Delete { name } => {
+                rpc.delete_profile(name).await?;
+                println!("Deleted profile");
+            }
+        }
+        Ok(())
+    }
+
+    async fn list(rpc: &mut MullvadProxyClient) -> Result<()> {
+        let settings = rpc.get_settings().await?;
+        for profile in settings.profiles.iter() {
+            println!("{}", profile.name);
+            println!(
+                "\tRelay constraints: {}",
+                RelaySettingsFormatter {
+                    settings: &profile.relay_settings,
+                    custom_lists: &settings.custom_lists,
+                }
+            );
+            println!(
+                "\tObfuscation: {}",
+                profile.obfuscation_settings.selected_obfuscation
+            );
+            let dns = match profile.dns_options.state {
+                DnsState::Default => "default",
+                DnsState::Custom => "custom",
+            };
+            println!("\tDNS: {dns}");
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,9 @@
 use clap::Args;
-use std::net::{IpAddr, SocketAddr};
+use std::{
+    convert::Infallible,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    str::FromStr,
+};
 use talpid_types::net::{
     proxy::{Shadowsocks, Socks5Local, Socks5Remote, SocksAuth, SHADOWSOCKS_CIPHERS},
     Endpoint, TransportProtocol,
@@ -11,6 +15,36 @@ pub enum Error {
     InvalidAuth(#[from] talpid_types::net::proxy::Error),
 }
 
+/// The address of a remote proxy server, given either as an IP address or as a hostname.
+#[derive(Debug, Clone)]
+pub enum RemoteHost {
+    Ip(IpAddr),
+    Hostname(String),
+}
+
+impl FromStr for RemoteHost {
+    type Err = Infallible;
+
+    fn from_str(host: &str) -> Result<Self, Self::Err> {
+        Ok(match host.parse() {
+            Ok(ip) => RemoteHost::Ip(ip),
+            Err(_) => RemoteHost::Hostname(host.to_owned()),
+        })
+    }
+}
+
+impl RemoteHost {
+    /// Returns the IP to use for the proxy endpoint along with the hostname of the proxy, if any.
+    /// A proxy given by hostname gets a placeholder IP, which the daemon replaces with an address
+    /// of the host before the proxy is used.
+    fn into_ip_and_hostname(self) -> (IpAddr, Option<String>) {
+        match self {
+            RemoteHost::Ip(ip) => (ip, None),
+            RemoteHost::Hostname(hostname) => (Ipv4Addr::UNSPECIFIED.into(), Some(hostname)),
+        }
+    }
+}
+
 #[derive(Args, Debug, Clone)]
 pub struct Socks5LocalAdd {
     /// The port that the server on localhost is listening on
@@ -44,8 +78,8 @@ impl From<Socks5LocalAdd> for Socks5Local {
 // We do not support setting the protocol as anything other than tcp for remote socks5 servers
 #[derive(Args, Debug, Clone)]
 pub struct Socks5RemoteAdd {
-    /// The IP of the remote proxy server
-    pub remote_ip: IpAddr,
+    /// The IP or hostname of the remote proxy server
+    pub remote_host: RemoteHost,
     /// The port of the remote proxy server
     pub remote_port: u16,
 
@@ -56,20 +90,22 @@ pub struct Socks5RemoteAdd {
 impl TryFrom<Socks5RemoteAdd> for Socks5Remote {
     type Error = Error;
     fn try_from(add: Socks5RemoteAdd) -> Result<Self, Self::Error> {
+        let (ip, hostname) = add.remote_host.into_ip_and_hostname();
         Ok(Self {
-            endpoint: SocketAddr::new(add.remote_ip, add.remote_port),
+            endpoint: SocketAddr::new(ip, add.remote_port),
             auth: add
                 .authentication
                 .map(|auth| SocksAuth::new(auth.username, auth.password))
                 .transpose()?,
+            hostname,
         })
     }
 }
 
 #[derive(Args, Debug, Clone)]
 pub struct ShadowsocksAdd {
-    /// The IP of the remote Shadowsocks-proxy
-    pub remote_ip: IpAddr,
+    /// The IP or hostname of the remote Shadowsocks-proxy
+    pub remote_host: RemoteHost,
     /// Port on which the remote Shadowsocks-proxy listens for traffic
     pub remote_port: u16,
     /// Password for authentication
@@ -81,10 +117,12 @@ pub struct ShadowsocksAdd {
 
 impl From<ShadowsocksAdd> for Shadowsocks {
     fn from(add: ShadowsocksAdd) -> Self {
+        let (ip, hostname) = add.remote_host.into_ip_and_hostname();
         Self {
-            endpoint: SocketAddr::new(add.remote_ip, add.remote_port),
-            password: add.password,
+            endpoint: SocketAddr::new(ip, add.remote_port),
+            password: add.password.into(),
             cipher: add.cipher,
+            hostname,
         }
     }
 }
@@ -114,6 +152,10 @@ pub struct ProxyEditParams {
     /// The IP of the remote proxy server \[Socks5 (Local & Remote proxy), Shadowsocks\]
     #[arg(long)]
     pub ip: Option<IpAddr>,
+    /// The hostname of the remote proxy server. Setting `--ip` without this removes the hostname
+    /// \[Socks5 (Remote proxy), Shadowsocks\]
+    #[arg(long)]
+    pub hostname: Option<String>,
     /// The port of the remote proxy server \[Socks5 (Local & Remote proxy), Shadowsocks\]
     #[arg(long)]
     pub port: Option<u16>,
@@ -141,6 +183,7 @@ impl ProxyEditParams {
     }
 
     pub fn merge_socks_remote(self, remote: &Socks5Remote) -> Result<Socks5Remote, Error> {
+        let hostname = self.merge_hostname(&remote.hostname);
         let ip = self.ip.unwrap_or(remote.endpoint.ip());
         let port = self.port.unwrap_or(remote.endpoint.port());
         let config = match &remote.auth {
@@ -162,22 +205,44 @@ impl ProxyEditParams {
                 Socks5Remote::new_with_authentication((ip, port), auth)
             }
         };
-        Ok(config)
+        Ok(config.with_hostname(hostname))
     }
 
     pub fn merge_shadowsocks(self, shadowsocks: &Shadowsocks) -> Shadowsocks {
+        let hostname = self.merge_hostname(&shadowsocks.hostname);
         let ip = self.ip.unwrap_or(shadowsocks.endpoint.ip());
         let port = self.port.unwrap_or(shadowsocks.endpoint.port());
-        let password = self.password.unwrap_or(shadowsocks.password.to_owned());
+        let password = self
+            .password
+            .unwrap_or(shadowsocks.password.expose().clone());
         let cipher = self.cipher.unwrap_or(shadowsocks.cipher.to_owned());
-        Shadowsocks::new((ip, port), cipher, password)
+        Shadowsocks::new((ip, port), cipher, password).with_hostname(hostname)
+    }
+
+    /// A new hostname replaces the previous one. A new IP without a hostname means that the proxy
+    /// is no longer referenced by hostname.
+    fn merge_hostname(&self, hostname: &Option<String>) -> Option<String> {
+        match (&self.hostname, self.ip) {
+            (Some(hostname), _) => Some(hostname.clone()),
+            (None, Some(_)) => None,
+            (None, None) => hostname.clone(),
+        }
     }
 }
 
 pub mod pp {
     use crate::print_option;
+    use std::net::SocketAddr;
     use talpid_types::net::proxy::CustomProxy;
 
+    /// Formats the address of a remote proxy, preferring its hostname if it has one.
+    fn peer(endpoint: SocketAddr, hostname: &Option<String>) -> String {
+        match hostname {
+            Some(hostname) => format!("{hostname}:{}", endpoint.port()),
+            None => endpoint.to_string(),
+        }
+    }
+
     pub struct CustomProxyFormatter<'a> {
         pub custom_proxy: &'a CustomProxy,
     }
@@ -187,13 +252,13 @@ pub mod pp {
             match self.custom_proxy {
                 CustomProxy::Shadowsocks(shadowsocks) => {
                     print_option!("Protocol", format!("Shadowsocks [{}]", shadowsocks.cipher));
-                    print_option!("Peer", shadowsocks.endpoint);
-                    print_option!("Password", shadowsocks.password);
+                    print_option!("Peer", peer(shadowsocks.endpoint, &shadowsocks.hostname));
+                    print_option!("Password", shadowsocks.password.expose());
                     Ok(())
                 }
                 CustomProxy::Socks5Remote(remote) => {
                     print_option!("Protocol", "Socks5");
-                    print_option!("Peer", remote.endpoint);
+                    print_option!("Peer", peer(remote.endpoint, &remote.hostname));
                     match &remote.auth {
                         Some(credentials) => {
                             print_option!("Username", credentials.username());
@@ -1,22 +1,27 @@
 use anyhow::{anyhow, bail, Context, Result};
-use clap::Subcommand;
+use clap::{Args, Subcommand};
+use futures::TryStreamExt;
 use itertools::Itertools;
 use mullvad_management_interface::MullvadProxyClient;
 use mullvad_types::{
     constraints::{Constraint, Match},
     location::{CountryCode, Location},
     relay_constraints::{
-        GeographicLocationConstraint, LocationConstraint, LocationConstraintFormatter,
-        OpenVpnConstraints, Ownership, Provider, Providers, RelayConstraints, RelayOverride,
-        RelaySettings, TransportPort, WireguardConstraints,
+        GeographicLocationConstraint, IpVersionPreference, LocationConstraint,
+        LocationConstraintFormatter, OpenVpnConstraints, Ownership, PortRange, Provider, Providers,
+        RelayConstraints, RelayOverride, RelaySettings, RemovedRelayPolicy, TransportPort,
+        WireguardConstraints,
     },
-    relay_list::{RelayEndpointData, RelayListCountry},
+    relay_list::{RelayEndpointData, RelayLatency, RelayListCountry},
     ConnectionConfig, CustomTunnelEndpoint,
 };
 use std::{
+    cmp::Ordering,
     collections::HashMap,
     io::BufRead,
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::PathBuf,
+    str::FromStr,
 };
 use talpid_types::net::{
     all_of_the_internet, openvpn, wireguard, Endpoint, IpVersion, TransportProtocol, TunnelType,
@@ -40,6 +45,28 @@ pub enum Relay {
     /// Update the relay list
     Update,
 
+    /// Measure the latency to WireGuard relays by timing a handshake with each of them. This is
+    /// only possible while disconnected. The results are used by the 'max-latency' constraint
+    Ping {
+        /// Only ping relays in this country, such as 'se'
+        #[arg(long, short = 'c')]
+        country: Option<CountryCode>,
+    },
+
+    /// Import a relay list that was obtained out-of-band, for use on networks where the API can't
+    /// be reached before connecting. The relay list must be signed by Mullvad, and must have been
+    /// created after the relay list that is currently in use
+    Import {
+        /// Relay list file: a JSON object with the time it was created, `created`, and the relay
+        /// list in the format served by the API, `relay_list`
+        file: PathBuf,
+
+        /// File containing the hex encoded signature of the relay list. Defaults to the relay
+        /// list path with ".sig" appended
+        #[arg(long, short = 's')]
+        signature: Option<PathBuf>,
+    },
+
     /// Override options for individual relays/servers
     #[clap(subcommand)]
     Override(OverrideCommands),
@@ -99,6 +126,14 @@ pub enum SetCommands {
     /// Set tunnel protocol to use: 'any', 'wireguard', or 'openvpn'.
     TunnelProtocol { protocol: Constraint<TunnelType> },
 
+    /// Set what to do if the relay that is selected by its hostname is removed from the relay
+    /// list. By default, the tunnel blocks traffic until another location is selected.
+    RemovedRelayPolicy { policy: RemovedRelayPolicy },
+
+    /// Set which IP version to connect to relays over, unless it is constrained by 'tunnel
+    /// wireguard --ip-version'. The other IP version is still used if the preferred one fails.
+    IpVersionPreference { preference: IpVersionPreference },
+
     /// Set a custom VPN relay to use
     #[clap(subcommand)]
     Custom(SetCustomCommands),
@@ -125,6 +160,11 @@ pub enum SetTunnelCommands {
         #[arg(long, short = 'p')]
         port: Option<Constraint<u16>>,
 
+        /// Range of ports to pick a random port from on every connection attempt, such as
+        /// '1000-2000', or 'any'. Only used if the port is 'any'
+        #[arg(long)]
+        port_range: Option<Constraint<PortRange>>,
+
         /// IP protocol to use, or 'any'
         #[arg(long, short = 'i')]
         ip_version: Option<Constraint<IpVersion>>,
@@ -134,11 +174,61 @@ pub enum SetTunnelCommands {
         #[arg(long, short = 'm')]
         use_multihop: Option<BooleanOption>,
 
+        #[clap(flatten)]
+        required_features: RequiredFeatureArgs,
+
+        /// Only use relays that responded within this many milliseconds the last time they were
+        /// pinged with 'mullvad relay ping', or 'any'. Relays that have not been pinged may still
+        /// be used
+        #[arg(long)]
+        max_latency: Option<Constraint<u16>>,
+
+        #[clap(flatten)]
+        multihop_diversity: MultihopDiversityArgs,
+
+        /// Base64 encoded public key of the relay to use, or of the exit relay if multihop is
+        /// enabled, or 'any'. Unlike the hostname, the key stays the same if the relay is renamed
+        #[arg(long, value_parser = Constraint::<wireguard::PublicKey>::from_str)]
+        public_key: Option<Constraint<wireguard::PublicKey>>,
+
+        /// Base64 encoded public key of the entry relay to use if multihop is enabled, or 'any'
+        #[arg(long, value_parser = Constraint::<wireguard::PublicKey>::from_str)]
+        entry_public_key: Option<Constraint<wireguard::PublicKey>>,
+
         #[clap(subcommand)]
         entry: Option<EntryCommands>,
     },
 }
 
+/// Features that relays must support to be selected. Relays without them are never used.
+#[derive(Args, Debug, Clone)]
+pub struct RequiredFeatureArgs {
+    /// Only use relays that support DAITA, even if DAITA is disabled
+    #[arg(long)]
+    require_daita: Option<BooleanOption>,
+
+    /// Only use relays that accept WireGuard over QUIC
+    #[arg(long)]
+    require_quic: Option<BooleanOption>,
+
+    /// Only use relays that are booted with stboot
+    #[arg(long)]
+    require_stboot: Option<BooleanOption>,
+}
+
+/// How the entry and exit relays should differ when multihop is used and neither of their
+/// locations is constrained. If no relays differ in these ways, any relays may be used.
+#[derive(Args, Debug, Clone)]
+pub struct MultihopDiversityArgs {
+    /// Prefer entry and exit relays in different countries
+    #[arg(long)]
+    diverse_countries: Option<BooleanOption>,
+
+    /// Prefer entry and exit relays run by different providers
+    #[arg(long)]
+    diverse_providers: Option<BooleanOption>,
+}
+
 #[derive(Subcommand, Debug, Clone)]
 pub enum EntryCommands {
     /// Set wireguard entry relay constraints
@@ -264,6 +354,8 @@ impl Relay {
             Relay::Get => Self::get().await,
             Relay::List => Self::list().await,
             Relay::Update => Self::update().await,
+            Relay::Ping { country } => Self::ping(country).await,
+            Relay::Import { file, signature } => Self::import(file, signature).await,
             Relay::Set(subcmd) => Self::set(subcmd).await,
             Relay::Override(subcmd) => Self::r#override(subcmd).await,
         }
@@ -297,6 +389,8 @@ impl Relay {
 
                 print_option!("Provider(s)", constraints.providers,);
                 print_option!("Ownership", constraints.ownership,);
+                print_option!("If relay is removed", settings.removed_relay_policy,);
+                print_option!("IP version preference", settings.ip_version_preference,);
 
                 println!("OpenVPN constraints");
 
@@ -314,6 +408,7 @@ impl Relay {
                 println!("WireGuard constraints");
 
                 print_option!("Port", constraints.wireguard_constraints.port,);
+                print_option!("Port range", constraints.wireguard_constraints.port_range,);
 
                 print_option!("IP protocol", constraints.wireguard_constraints.ip_version,);
 
@@ -336,6 +431,36 @@ impl Relay {
                             custom_lists: &settings.custom_lists
                         }),
                 );
+
+                let required_features = constraints.wireguard_constraints.required_features.names();
+                if required_features.is_empty() {
+                    print_option!("Required features", "none",);
+                } else {
+                    print_option!("Required features", required_features.join(", "),);
+                }
+                print_option!(
+                    "Max latency",
+                    constraints
+                        .wireguard_constraints
+                        .max_latency
+                        .map(|max_latency| format!("{max_latency} ms")),
+                );
+
+                let multihop_diversity =
+                    constraints.wireguard_constraints.multihop_diversity.names();
+                if multihop_diversity.is_empty() {
+                    print_option!("Multihop diversity", "none",);
+                } else {
+                    print_option!("Multihop diversity", multihop_diversity.join(", "),);
+                }
+                print_option!(
+                    "Relay public key",
+                    constraints.wireguard_constraints.public_key,
+                );
+                print_option!(
+                    "Entry public key",
+                    constraints.wireguard_constraints.entry_public_key,
+                );
             }
         }
 
@@ -358,11 +483,28 @@ impl Relay {
                     city.name, city.code, city.latitude, city.longitude
                 );
                 for relay in &city.relays {
-                    let support_msg = match relay.endpoint_data {
+                    let mut features = vec![];
+                    let support_msg = match &relay.endpoint_data {
                         RelayEndpointData::Openvpn => "OpenVPN",
-                        RelayEndpointData::Wireguard(_) => "WireGuard",
+                        RelayEndpointData::Wireguard(data) => {
+                            if data.daita {
+                                features.push("DAITA");
+                            }
+                            if data.quic {
+                                features.push("QUIC");
+                            }
+                            "WireGuard"
+                        }
                         _ => unreachable!("Bug in relay filtering earlier on"),
                     };
+                    if relay.stboot {
+                        features.push("stboot");
+                    }
+                    let support_msg = if features.is_empty() {
+                        support_msg.to_owned()
+                    } else {
+                        format!("{support_msg} ({})", features.join(", "))
+                    };
                     let ownership = if relay.owned {
                         "Mullvad-owned"
                     } else {
@@ -395,6 +537,52 @@ impl Relay {
         Ok(())
     }
 
+    async fn ping(country: Option<CountryCode>) -> Result<()> {
+        let mut rpc = MullvadProxyClient::new().await?;
+        let latencies = rpc.ping_relays(country).await?;
+        println!("Pinging relays...");
+        let mut latencies: Vec<RelayLatency> = latencies.try_collect().await?;
+
+        // Fastest relays first, and the ones that did not respond last
+        latencies.sort_by(|l1, l2| match (l1.latency, l2.latency) {
+            (Some(l1), Some(l2)) => l1.cmp(&l2),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => natord::compare_ignore_case(&l1.hostname, &l2.hostname),
+        });
+        for relay in &latencies {
+            match relay.latency {
+                Some(latency) => println!("{:<24}{} ms", relay.hostname, latency.as_millis()),
+                None => println!("{:<24}no response", relay.hostname),
+            }
+        }
+        Ok(())
+    }
+
+    async fn import(file: PathBuf, signature: Option<PathBuf>) -> Result<()> {
+        let signature_file = signature.unwrap_or_else(|| {
+            let mut path = file.clone().into_os_string();
+            path.push(".sig");
+            PathBuf::from(path)
+        });
+        let relay_list = tokio::fs::read_to_string(&file)
+            .await
+            .with_context(|| format!("Failed to read relay list from {}", file.display()))?;
+        let signature = tokio::fs::read_to_string(&signature_file)
+            .await
+            .with_context(|| {
+                format!("Failed to read signature from {}", signature_file.display())
+            })?;
+
+        MullvadProxyClient::new()
+            .await?
+            .import_relay_list(relay_list, signature)
+            .await
+            .context("Failed to import relay list")?;
+        println!("Imported relay list");
+        Ok(())
+    }
+
     /// Get active relays which are not bridges.
 
     async fn update_constraints(update_fn: impl FnOnce(&mut RelayConstraints)) -> Result<()> {
@@ -427,6 +615,18 @@ impl Relay {
             SetCommands::Ownership { ownership } => Self::set_ownership(ownership).await,
             SetCommands::Tunnel(subcmd) => Self::set_tunnel(subcmd).await,
             SetCommands::TunnelProtocol { protocol } => Self::set_tunnel_protocol(protocol).await,
+            SetCommands::RemovedRelayPolicy { policy } => {
+                let mut rpc = MullvadProxyClient::new().await?;
+                rpc.set_removed_relay_policy(policy).await?;
+                println!("Removed relay policy updated");
+                Ok(())
+            }
+            SetCommands::IpVersionPreference { preference } => {
+                let mut rpc = MullvadProxyClient::new().await?;
+                rpc.set_ip_version_preference(preference).await?;
+                println!("IP version preference updated");
+                Ok(())
+            }
         }
     }
 
@@ -438,12 +638,30 @@ impl Relay {
             } => Self::set_openvpn_constraints(port, transport_protocol).await,
             SetTunnelCommands::Wireguard {
                 port,
+                port_range,
                 ip_version,
                 use_multihop,
+                required_features,
+                max_latency,
+                multihop_diversity,
+                public_key,
+                entry_public_key,
                 entry,
             } => {
                 let entry = entry.map(|EntryCommands::Entry(entry)| entry);
-                Self::set_wireguard_constraints(port, ip_version, use_multihop, entry).await
+                Self::set_wireguard_constraints(
+                    port,
+                    port_range,
+                    ip_version,
+                    use_multihop,
+                    required_features,
+                    max_latency,
+                    multihop_diversity,
+                    public_key,
+                    entry_public_key,
+                    entry,
+                )
+                .await
             }
         }
     }
@@ -499,8 +717,8 @@ impl Relay {
                     SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port),
                     protocol,
                 ),
-                username,
-                password,
+                username: username.into(),
+                password: password.into(),
             }),
         }
     }
@@ -541,11 +759,13 @@ impl Relay {
                     public_key: peer_pubkey,
                     allowed_ips: all_of_the_internet(),
                     endpoint: SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port),
+                    alternate_endpoint: None,
                     psk: None,
                     #[cfg(target_os = "windows")]
                     constant_packet_size: false,
                 },
                 exit_peer: None,
+                candidate_peer: None,
                 ipv4_gateway,
                 ipv6_gateway,
                 // NOTE: Ignored in gRPC
@@ -652,14 +872,22 @@ impl Relay {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn set_wireguard_constraints(
         port: Option<Constraint<u16>>,
+        port_range: Option<Constraint<PortRange>>,
         ip_version: Option<Constraint<IpVersion>>,
         use_multihop: Option<BooleanOption>,
+        required_features: RequiredFeatureArgs,
+        max_latency: Option<Constraint<u16>>,
+        multihop_diversity: MultihopDiversityArgs,
+        public_key: Option<Constraint<wireguard::PublicKey>>,
+        entry_public_key: Option<Constraint<wireguard::PublicKey>>,
         entry_location: Option<EntryArgs>,
     ) -> Result<()> {
         let mut rpc = MullvadProxyClient::new().await?;
-        let wireguard = rpc.get_relay_locations().await?.wireguard;
+        let relay_list = rpc.get_relay_locations().await?;
+        let wireguard = &relay_list.wireguard;
         let mut wireguard_constraints = Self::get_wireguard_constraints(&mut rpc).await?;
 
         if let Some(port) = port {
@@ -668,8 +896,8 @@ impl Relay {
                 Constraint::Only(specific_port) => {
                     let is_valid_port = wireguard
                         .port_ranges
-                        .into_iter()
-                        .any(|(first, last)| first <= specific_port && specific_port <= last);
+                        .iter()
+                        .any(|&(first, last)| first <= specific_port && specific_port <= last);
                     if !is_valid_port {
                         return Err(anyhow!("The specified port is invalid"));
                     }
@@ -678,12 +906,65 @@ impl Relay {
             }
         }
 
+        if let Some(port_range) = port_range {
+            if let Constraint::Only(range) = port_range {
+                let has_valid_port = wireguard
+                    .port_ranges
+                    .iter()
+                    .any(|&(first, last)| first <= range.end() && range.start() <= last);
+                if !has_valid_port {
+                    return Err(anyhow!("The specified port range contains no valid ports"));
+                }
+            }
+            wireguard_constraints.port_range = port_range;
+        }
         if let Some(ipv) = ip_version {
             wireguard_constraints.ip_version = ipv;
         }
         if let Some(use_multihop) = use_multihop {
             wireguard_constraints.use_multihop(*use_multihop);
         }
+        if let Some(require_daita) = required_features.require_daita {
+            wireguard_constraints.required_features.daita = *require_daita;
+        }
+        if let Some(require_quic) = required_features.require_quic {
+            wireguard_constraints.required_features.quic = *require_quic;
+        }
+        if let Some(require_stboot) = required_features.require_stboot {
+            wireguard_constraints.required_features.stboot = *require_stboot;
+        }
+        if let Some(max_latency) = max_latency {
+            wireguard_constraints.max_latency = max_latency;
+        }
+        if let Some(diverse_countries) = multihop_diversity.diverse_countries {
+            wireguard_constraints.multihop_diversity.country = *diverse_countries;
+        }
+        if let Some(diverse_providers) = multihop_diversity.diverse_providers {
+            wireguard_constraints.multihop_diversity.provider = *diverse_providers;
+        }
+        let has_relay_with_key = |key: &wireguard::PublicKey| {
+            relay_list.relays().any(|relay| {
+                matches!(
+                    &relay.endpoint_data,
+                    RelayEndpointData::Wireguard(data) if data.public_key == *key
+                )
+            })
+        };
+        for key in [&public_key, &entry_public_key]
+            .into_iter()
+            .flatten()
+            .filter_map(|key| key.as_ref().option())
+        {
+            if !has_relay_with_key(key) {
+                bail!("No WireGuard relay has the public key {key}");
+            }
+        }
+        if let Some(public_key) = public_key {
+            wireguard_constraints.public_key = public_key;
+        }
+        if let Some(entry_public_key) = entry_public_key {
+            wireguard_constraints.entry_public_key = entry_public_key;
+        }
         match entry_location {
             Some(EntryArgs::Location(location_args)) => {
                 let relay_filter = |relay: &mullvad_types::relay_list::Relay| {
@@ -0,0 +1,104 @@
+use anyhow::{bail, Context, Result};
+use clap::Subcommand;
+use mullvad_management_interface::MullvadProxyClient;
+use std::io::{stdin, stdout, Write};
+
+#[derive(Subcommand, Debug)]
+pub enum SettingsLock {
+    /// Display whether the settings lock is enabled, and whether it is unlocked
+    Get,
+    /// Require a passphrase to disconnect, to disable lockdown mode or to change any setting.
+    /// If the lock is already enabled, this changes the passphrase, which requires the settings
+    /// to be unlocked. The passphrase is read from standard input
+    Enable,
+    /// Stop requiring a passphrase. The passphrase is read from standard input
+    Disable,
+    /// Run a command with the settings unlocked. The settings are only unlocked for that command.
+    /// The passphrase is read from standard input
+    Unlock {
+        /// The command to run, such as 'disconnect' or 'lockdown-mode set off'
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Lock the settings again for every client that has unlocked them
+    Lock,
+}
+
+impl SettingsLock {
+    pub async fn handle(self) -> Result<()> {
+        match self {
+            SettingsLock::Get => Self::get().await,
+            SettingsLock::Enable => {
+                let passphrase = read_passphrase("New passphrase: ").await?;
+                if passphrase != read_passphrase("Repeat passphrase: ").await? {
+                    bail!("The passphrases do not match");
+                }
+                MullvadProxyClient::new()
+                    .await?
+                    .enable_settings_lock(passphrase)
+                    .await?;
+                println!("Enabled settings lock");
+                Ok(())
+            }
+            SettingsLock::Disable => {
+                let passphrase = read_passphrase("Passphrase: ").await?;
+                MullvadProxyClient::new()
+                    .await?
+                    .disable_settings_lock(passphrase)
+                    .await?;
+                println!("Disabled settings lock");
+                Ok(())
+            }
+            // The command needs to be parsed as a command line of its own
+            SettingsLock::Unlock { .. } => unreachable!("unlock is handled by the caller"),
+            SettingsLock::Lock => {
+                MullvadProxyClient::new().await?.lock_settings().await?;
+                println!("Locked settings");
+                Ok(())
+            }
+        }
+    }
+
+    async fn get() -> Result<()> {
+        let state = MullvadProxyClient::new()
+            .await?
+            .get_settings_lock_state()
+            .await?;
+        match (state.enabled, state.unlocked_for) {
+            (false, _) => println!("Settings lock: off"),
+            (true, None) => println!("Settings lock: locked"),
+            (true, Some(unlocked_for)) => println!(
+                "Settings lock: unlocked for {} more seconds",
+                unlocked_for.as_secs()
+            ),
+        }
+        Ok(())
+    }
+}
+
+/// Read the passphrase and check it against the daemon. Every client created by this process
+/// afterwards unlocks the settings with it.
+pub async fn unlock() -> Result<()> {
+    let passphrase = read_passphrase("Passphrase: ").await?;
+    MullvadProxyClient::new()
+        .await?
+        .unlock_settings(passphrase.clone())
+        .await?;
+    MullvadProxyClient::unlock_new_clients(passphrase);
+    Ok(())
+}
+
+/// Read a line from standard input, without the line break.
+async fn read_passphrase(prompt: &'static str) -> Result<String> {
+    print!("{prompt}");
+    let _ = stdout().flush();
+    tokio::task::spawn_blocking(|| {
+        let mut passphrase = String::new();
+        stdin()
+            .read_line(&mut passphrase)
+            .context("Failed to read passphrase")?;
+        Ok(passphrase.trim_end_matches(['\r', '\n']).to_owned())
+    })
+    .await
+    .unwrap()
+}
@@ -0,0 +1,74 @@
+use anyhow::Result;
+use mullvad_management_interface::MullvadProxyClient;
+
+pub async fn print() -> Result<()> {
+    let mut rpc = MullvadProxyClient::new().await?;
+    let stats = rpc.get_traffic_stats().await?;
+
+    if stats.months().is_empty() && stats.obfuscation().is_empty() {
+        println!("No tunnel traffic has been recorded");
+        return Ok(());
+    }
+
+    if !stats.months().is_empty() {
+        println!(
+            "{:<10}{:<16}{:>14}{:>14}",
+            "Month", "Interface", "Received", "Sent"
+        );
+        for month in stats.months() {
+            println!(
+                "{:<10}{:<16}{:>14}{:>14}",
+                format!("{}-{:02}", month.year, month.month),
+                month.interface,
+                format_bytes(month.rx_bytes),
+                format_bytes(month.tx_bytes),
+            );
+        }
+    }
+
+    if !stats.obfuscation().is_empty() {
+        if !stats.months().is_empty() {
+            println!();
+        }
+        println!(
+            "{:<10}{:<16}{:>14}{:>14}{:>10}",
+            "Month", "Obfuscation", "Payload", "On the wire", "Overhead"
+        );
+        for month in stats.obfuscation() {
+            let payload = month
+                .payload_rx_bytes
+                .saturating_add(month.payload_tx_bytes);
+            let wire = month.wire_rx_bytes.saturating_add(month.wire_tx_bytes);
+            let overhead = if payload == 0 {
+                0.0
+            } else {
+                month.overhead_bytes() as f64 * 100.0 / payload as f64
+            };
+            println!(
+                "{:<10}{:<16}{:>14}{:>14}{:>9.1}%",
+                format!("{}-{:02}", month.year, month.month),
+                month.method,
+                format_bytes(payload),
+                format_bytes(wire),
+                overhead,
+            );
+        }
+    }
+    Ok(())
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} {}", UNITS[0])
+    } else {
+        format!("{value:.2} {}", UNITS[unit])
+    }
+}
@@ -2,7 +2,10 @@ use anyhow::Result;
 use clap::{Args, Subcommand};
 use futures::StreamExt;
 use mullvad_management_interface::{client::DaemonEvent, MullvadProxyClient};
-use mullvad_types::{device::DeviceState, states::TunnelState};
+use mullvad_types::{
+    device::DeviceState,
+    states::{LockdownCountdown, TunnelState},
+};
 
 use crate::format;
 
@@ -10,6 +13,13 @@ use crate::format;
 pub enum Status {
     /// Listen for tunnel state changes
     Listen,
+
+    /// Listen for notifications, which are the events that the daemon considers worth telling
+    /// the user about
+    Notifications,
+
+    /// Look up the location again, such as after it failed and the last known location is shown
+    RefreshLocation,
 }
 
 #[derive(Args, Debug)]
@@ -72,6 +82,13 @@ impl Status {
                         println!("New relay list: {relay_list:#?}");
                     }
                 }
+                DaemonEvent::RelayListDiff(diff) => {
+                    if args.debug {
+                        println!("Relay list changes: {diff:#?}");
+                    } else {
+                        format::print_relay_list_diff(&diff);
+                    }
+                }
                 DaemonEvent::AppVersionInfo(app_version_info) => {
                     if args.debug {
                         println!("New app version info: {app_version_info:#?}");
@@ -92,6 +109,79 @@ impl Status {
                         println!("New access method: {access_method:#?}");
                     }
                 }
+                DaemonEvent::ConnectionVerified(verification) => {
+                    if args.debug {
+                        println!("Connection verified: {verification:#?}");
+                    } else {
+                        format::print_connection_verification(&verification);
+                    }
+                }
+                DaemonEvent::ConnectEscalation(escalation) => {
+                    if args.debug {
+                        println!("Connect escalation: {escalation:#?}");
+                    } else {
+                        println!(
+                            "Did not connect to {} in time. Trying the next transport",
+                            escalation.endpoint
+                        );
+                    }
+                }
+                DaemonEvent::ProblemReportProgress(progress) => {
+                    if args.debug {
+                        println!("Problem report progress: {progress:#?}");
+                    }
+                }
+                DaemonEvent::ConnectProgress(progress) => {
+                    if args.debug {
+                        println!("Connect progress: {progress:#?}");
+                    } else if args.verbose {
+                        let event = if progress.finished {
+                            "Finished"
+                        } else {
+                            "Started"
+                        };
+                        println!(
+                            "{event} {} after {} ms",
+                            progress.phase.to_string().to_lowercase(),
+                            progress.elapsed.as_millis()
+                        );
+                    }
+                }
+                DaemonEvent::ClockSkewDetected(skew) => {
+                    if args.debug {
+                        println!("Clock skew detected: {skew:#?}");
+                    } else {
+                        println!("Warning: {skew} compared to the Mullvad API");
+                    }
+                }
+                DaemonEvent::LockdownCountdown(countdown) => {
+                    if args.debug {
+                        println!("Lockdown countdown: {countdown:#?}");
+                    } else {
+                        match countdown {
+                            LockdownCountdown::Remaining(seconds) => {
+                                println!("Blocking traffic in {seconds} seconds")
+                            }
+                            LockdownCountdown::Enabled => println!("Lockdown mode enabled"),
+                            LockdownCountdown::Cancelled => {
+                                println!("Lockdown grace period cancelled")
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn notifications(mut rpc: MullvadProxyClient, args: StatusArgs) -> Result<()> {
+        let mut notifications = rpc.notifications().await?;
+        while let Some(notification) = notifications.next().await {
+            let notification = notification?;
+            if args.debug {
+                println!("Notification: {notification:#?}");
+            } else {
+                println!("[{}] {}", notification.severity, notification.message);
             }
         }
         Ok(())
@@ -112,8 +202,14 @@ pub async fn handle(cmd: Option<Status>, args: StatusArgs) -> Result<()> {
         format::print_location(&state);
     }
 
-    if cmd == Some(Status::Listen) {
-        Status::listen(rpc, args).await?;
+    match cmd {
+        Some(Status::Listen) => Status::listen(rpc, args).await?,
+        Some(Status::Notifications) => Status::notifications(rpc, args).await?,
+        Some(Status::RefreshLocation) => {
+            rpc.refresh_location().await?;
+            println!("Refreshing location");
+        }
+        None => (),
     }
     Ok(())
 }
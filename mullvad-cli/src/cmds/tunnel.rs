@@ -1,24 +1,40 @@
 use anyhow::Result;
 use clap::Subcommand;
+use futures::StreamExt;
+#[cfg(target_os = "linux")]
+use ipnetwork::IpNetwork;
 use mullvad_management_interface::MullvadProxyClient;
 #[cfg(target_os = "windows")]
 use mullvad_types::wireguard::DaitaSettings;
 use mullvad_types::{
     constraints::Constraint,
-    wireguard::{QuantumResistantState, RotationInterval, DEFAULT_ROTATION_INTERVAL},
+    wireguard::{
+        KeyRotationProgress, QuantumResistantState, RotationInterval, DEFAULT_ROTATION_INTERVAL,
+    },
 };
 
+#[cfg(target_os = "linux")]
+use std::str::FromStr;
+
 use super::BooleanOption;
 use crate::print_option;
 
 #[derive(Subcommand, Debug)]
 pub enum Tunnel {
-    /// Show current tunnel options
-    Get,
+    /// Show current tunnel options, or the value of a single option
+    Get {
+        #[clap(subcommand)]
+        option: Option<TunnelOption>,
+    },
 
     /// Set tunnel options
     #[clap(subcommand)]
     Set(TunnelOptions),
+
+    /// Print an OpenVPN configuration file for the current relay. It does not contain any
+    /// credentials, so OpenVPN will ask for a username and password. Use your account number as
+    /// the username and any non-empty password
+    ExportOpenvpnConfig,
 }
 
 #[derive(Subcommand, Debug, Clone)]
@@ -29,6 +45,19 @@ pub enum TunnelOptions {
         /// Configure the mssfix parameter, or 'any'
         #[arg(long, short = 'm')]
         mssfix: Option<Constraint<u16>>,
+        /// Seconds to wait for a response from the server, or 'any' to use a default that
+        /// depends on the transport protocol
+        #[arg(long)]
+        connect_timeout: Option<Constraint<u16>>,
+        /// Seconds to wait before resending a control packet that the server has not
+        /// acknowledged, or 'any' to use a default that depends on the transport protocol
+        #[arg(long)]
+        retry_timeout: Option<Constraint<u16>>,
+        /// Configure whether to confine the OpenVPN process with a seccomp filter. Only disable
+        /// this if the filter prevents OpenVPN from connecting
+        #[cfg(target_os = "linux")]
+        #[arg(long)]
+        sandbox: Option<BooleanOption>,
     },
 
     /// Manage options for WireGuard tunnels
@@ -47,14 +76,100 @@ pub enum TunnelOptions {
         /// The key rotation interval. Number of hours, or 'any'
         #[arg(long)]
         rotation_interval: Option<Constraint<RotationInterval>>,
+        /// Only route these comma-separated networks through the tunnel, or 'any' to route all
+        /// traffic through it. Traffic to other destinations will leave outside the tunnel
+        #[cfg(target_os = "linux")]
+        #[arg(long, value_parser = parse_allowed_ips)]
+        allowed_ips: Option<Constraint<Vec<IpNetwork>>>,
+        /// Reach the relays through this network interface instead of the one that the default
+        /// route uses, or 'any' to use the default route
+        #[cfg(target_os = "linux")]
+        #[arg(long, value_parser = Constraint::<String>::from_str)]
+        egress_interface: Option<Constraint<String>>,
         /// Rotate WireGuard key
         #[clap(subcommand)]
         rotate_key: Option<RotateKey>,
     },
 
-    /// Enable or disable IPv6 in the tunnel
+    /// Enable or disable IPv6 in the tunnel, for both OpenVPN and WireGuard. When disabled, the
+    /// tunnel gets no IPv6 address or routes, and IPv6 traffic is blocked by the firewall
     #[clap(arg_required_else_help = true)]
     Ipv6 { state: BooleanOption },
+
+    /// Set how many seconds to wait for a connection attempt before moving on to the next
+    /// transport, such as another port, TCP or obfuscation. Use 'any' to let each attempt run
+    /// until it fails
+    #[clap(arg_required_else_help = true)]
+    ConnectDeadline { deadline: Constraint<u16> },
+
+    /// Fall back to OpenVPN on networks where WireGuard fails to connect. This only applies when
+    /// the tunnel protocol is 'any'. The networks where OpenVPN is used are remembered, so that it
+    /// is used right away the next time
+    #[clap(arg_required_else_help = true)]
+    OpenvpnFallback {
+        state: BooleanOption,
+        /// Number of failed WireGuard attempts in a row after which OpenVPN is used
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        attempts: Option<u32>,
+    },
+}
+
+/// A single tunnel option to show. The value is printed in the same form that `tunnel set`
+/// accepts
+#[derive(Subcommand, Debug, Clone)]
+pub enum TunnelOption {
+    /// Show an option for OpenVPN tunnels
+    #[clap(subcommand)]
+    Openvpn(OpenvpnOption),
+
+    /// Show an option for WireGuard tunnels
+    #[clap(subcommand)]
+    Wireguard(WireguardOption),
+
+    /// Show whether IPv6 is enabled in the tunnel
+    Ipv6,
+
+    /// Show the connect deadline in seconds, or 'any'
+    ConnectDeadline,
+
+    /// Show whether to fall back to OpenVPN on networks where WireGuard fails to connect
+    OpenvpnFallback {
+        /// Show the number of failed WireGuard attempts after which OpenVPN is used instead
+        #[arg(long)]
+        attempts: bool,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum OpenvpnOption {
+    /// Show the mssfix parameter, or 'any'
+    Mssfix,
+    /// Show the connect timeout in seconds, or 'any'
+    ConnectTimeout,
+    /// Show the retry timeout in seconds, or 'any'
+    RetryTimeout,
+    /// Show whether the OpenVPN process is confined with a seccomp filter
+    #[cfg(target_os = "linux")]
+    Sandbox,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum WireguardOption {
+    /// Show the tunnel MTU, or 'any'
+    Mtu,
+    /// Show the quantum-resistant key exchange setting
+    QuantumResistant,
+    /// Show whether DAITA is enabled
+    #[cfg(target_os = "windows")]
+    Daita,
+    /// Show the key rotation interval in hours, or 'any'
+    RotationInterval,
+    /// Show the comma-separated networks that are routed through the tunnel, or 'any'
+    #[cfg(target_os = "linux")]
+    AllowedIps,
+    /// Show the network interface used to reach the relays, or 'any'
+    #[cfg(target_os = "linux")]
+    EgressInterface,
 }
 
 #[derive(Subcommand, Debug, Clone)]
@@ -66,14 +181,19 @@ pub enum RotateKey {
 impl Tunnel {
     pub async fn handle(self) -> Result<()> {
         match self {
-            Tunnel::Get => Self::get().await,
+            Tunnel::Get { option: None } => Self::get().await,
+            Tunnel::Get {
+                option: Some(option),
+            } => Self::get_option(option).await,
             Tunnel::Set(options) => Self::set(options).await,
+            Tunnel::ExportOpenvpnConfig => Self::export_openvpn_config().await,
         }
     }
 
     async fn get() -> Result<()> {
         let mut rpc = MullvadProxyClient::new().await?;
-        let tunnel_options = rpc.get_settings().await?.tunnel_options;
+        let settings = rpc.get_settings().await?;
+        let tunnel_options = settings.tunnel_options;
 
         println!("OpenVPN options");
 
@@ -85,6 +205,32 @@ impl Tunnel {
                 .map(|val| val.to_string())
                 .unwrap_or("unset".to_string()),
         );
+        print_option!(
+            "Connect timeout",
+            tunnel_options
+                .openvpn
+                .connect_timeout
+                .map(|val| format!("{val} s"))
+                .unwrap_or("unset".to_string()),
+        );
+        print_option!(
+            "Retry timeout",
+            tunnel_options
+                .openvpn
+                .retry_timeout
+                .map(|val| format!("{val} s"))
+                .unwrap_or("unset".to_string()),
+        );
+
+        #[cfg(target_os = "linux")]
+        print_option!(
+            "Sandbox",
+            if tunnel_options.openvpn.sandbox {
+                "on"
+            } else {
+                "off"
+            }
+        );
 
         println!("WireGuard options");
 
@@ -104,6 +250,27 @@ impl Tunnel {
         #[cfg(target_os = "windows")]
         print_option!("DAITA", tunnel_options.wireguard.daita.enabled);
 
+        #[cfg(target_os = "linux")]
+        print_option!(
+            "Allowed IPs",
+            match tunnel_options.wireguard.allowed_ips {
+                Some(allowed_ips) => allowed_ips
+                    .iter()
+                    .map(|network| network.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                None => "all traffic".to_string(),
+            },
+        );
+        #[cfg(target_os = "linux")]
+        print_option!(
+            "Egress interface",
+            tunnel_options
+                .wireguard
+                .egress_interface
+                .unwrap_or("default route".to_string()),
+        );
+
         let key = rpc.get_wireguard_key().await?;
         print_option!("Public key", key.key,);
         print_option!(format_args!(
@@ -128,19 +295,127 @@ impl Tunnel {
                 "off"
             }
         );
+        print_option!(
+            "Connect deadline",
+            tunnel_options
+                .connect_deadline
+                .map(|val| format!("{val} s"))
+                .unwrap_or("unset".to_string()),
+        );
+        let fallback = settings.tunnel_protocol_fallback;
+        print_option!(
+            "OpenVPN fallback",
+            if fallback.enabled {
+                format!("after {} WireGuard attempts", fallback.wireguard_attempts)
+            } else {
+                "off".to_string()
+            }
+        );
 
         Ok(())
     }
 
+    async fn get_option(option: TunnelOption) -> Result<()> {
+        let mut rpc = MullvadProxyClient::new().await?;
+        let settings = rpc.get_settings().await?;
+        let tunnel_options = settings.tunnel_options;
+
+        let value = match option {
+            TunnelOption::Openvpn(OpenvpnOption::Mssfix) => {
+                format_constraint(tunnel_options.openvpn.mssfix)
+            }
+            TunnelOption::Openvpn(OpenvpnOption::ConnectTimeout) => {
+                format_constraint(tunnel_options.openvpn.connect_timeout)
+            }
+            TunnelOption::Openvpn(OpenvpnOption::RetryTimeout) => {
+                format_constraint(tunnel_options.openvpn.retry_timeout)
+            }
+            #[cfg(target_os = "linux")]
+            TunnelOption::Openvpn(OpenvpnOption::Sandbox) => {
+                BooleanOption::from(tunnel_options.openvpn.sandbox).to_string()
+            }
+            TunnelOption::Wireguard(WireguardOption::Mtu) => {
+                format_constraint(tunnel_options.wireguard.mtu)
+            }
+            TunnelOption::Wireguard(WireguardOption::QuantumResistant) => {
+                tunnel_options.wireguard.quantum_resistant.to_string()
+            }
+            #[cfg(target_os = "windows")]
+            TunnelOption::Wireguard(WireguardOption::Daita) => {
+                BooleanOption::from(tunnel_options.wireguard.daita.enabled).to_string()
+            }
+            TunnelOption::Wireguard(WireguardOption::RotationInterval) => format_constraint(
+                tunnel_options
+                    .wireguard
+                    .rotation_interval
+                    .map(|interval| interval.as_duration().as_secs() / 60 / 60),
+            ),
+            #[cfg(target_os = "linux")]
+            TunnelOption::Wireguard(WireguardOption::AllowedIps) => {
+                match tunnel_options.wireguard.allowed_ips {
+                    Some(allowed_ips) => allowed_ips
+                        .iter()
+                        .map(|network| network.to_string())
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    None => "any".to_owned(),
+                }
+            }
+            #[cfg(target_os = "linux")]
+            TunnelOption::Wireguard(WireguardOption::EgressInterface) => {
+                format_constraint(tunnel_options.wireguard.egress_interface)
+            }
+            TunnelOption::Ipv6 => {
+                BooleanOption::from(tunnel_options.generic.enable_ipv6).to_string()
+            }
+            TunnelOption::ConnectDeadline => format_constraint(tunnel_options.connect_deadline),
+            TunnelOption::OpenvpnFallback { attempts: false } => {
+                BooleanOption::from(settings.tunnel_protocol_fallback.enabled).to_string()
+            }
+            TunnelOption::OpenvpnFallback { attempts: true } => settings
+                .tunnel_protocol_fallback
+                .wireguard_attempts
+                .to_string(),
+        };
+        println!("{value}");
+
+        Ok(())
+    }
+
+    async fn export_openvpn_config() -> Result<()> {
+        let mut rpc = MullvadProxyClient::new().await?;
+        print!("{}", rpc.export_openvpn_config().await?);
+        Ok(())
+    }
+
     async fn set(options: TunnelOptions) -> Result<()> {
         match options {
-            TunnelOptions::Openvpn { mssfix } => Self::handle_openvpn(mssfix).await,
+            TunnelOptions::Openvpn {
+                mssfix,
+                connect_timeout,
+                retry_timeout,
+                #[cfg(target_os = "linux")]
+                sandbox,
+            } => {
+                Self::handle_openvpn(
+                    mssfix,
+                    connect_timeout,
+                    retry_timeout,
+                    #[cfg(target_os = "linux")]
+                    sandbox,
+                )
+                .await
+            }
             TunnelOptions::Wireguard {
                 mtu,
                 quantum_resistant,
                 #[cfg(target_os = "windows")]
                 daita,
                 rotation_interval,
+                #[cfg(target_os = "linux")]
+                allowed_ips,
+                #[cfg(target_os = "linux")]
+                egress_interface,
                 rotate_key,
             } => {
                 Self::handle_wireguard(
@@ -149,11 +424,21 @@ impl Tunnel {
                     #[cfg(target_os = "windows")]
                     daita,
                     rotation_interval,
+                    #[cfg(target_os = "linux")]
+                    allowed_ips,
+                    #[cfg(target_os = "linux")]
+                    egress_interface,
                     rotate_key,
                 )
                 .await
             }
             TunnelOptions::Ipv6 { state } => Self::handle_ipv6(state).await,
+            TunnelOptions::ConnectDeadline { deadline } => {
+                Self::handle_connect_deadline(deadline).await
+            }
+            TunnelOptions::OpenvpnFallback { state, attempts } => {
+                Self::handle_openvpn_fallback(state, attempts).await
+            }
         }
     }
 
@@ -164,7 +449,31 @@ impl Tunnel {
         Ok(())
     }
 
-    async fn handle_openvpn(mssfix: Option<Constraint<u16>>) -> Result<()> {
+    async fn handle_connect_deadline(deadline: Constraint<u16>) -> Result<()> {
+        let mut rpc = MullvadProxyClient::new().await?;
+        rpc.set_connect_deadline(deadline.option()).await?;
+        println!("Connect deadline has been updated");
+        Ok(())
+    }
+
+    async fn handle_openvpn_fallback(state: BooleanOption, attempts: Option<u32>) -> Result<()> {
+        let mut rpc = MullvadProxyClient::new().await?;
+        let mut fallback = rpc.get_settings().await?.tunnel_protocol_fallback;
+        fallback.enabled = *state;
+        if let Some(attempts) = attempts {
+            fallback.wireguard_attempts = attempts;
+        }
+        rpc.set_tunnel_protocol_fallback(fallback).await?;
+        println!("OpenVPN fallback: {state}");
+        Ok(())
+    }
+
+    async fn handle_openvpn(
+        mssfix: Option<Constraint<u16>>,
+        connect_timeout: Option<Constraint<u16>>,
+        retry_timeout: Option<Constraint<u16>>,
+        #[cfg(target_os = "linux")] sandbox: Option<BooleanOption>,
+    ) -> Result<()> {
         let mut rpc = MullvadProxyClient::new().await?;
 
         if let Some(mssfix) = mssfix {
@@ -172,6 +481,24 @@ impl Tunnel {
             println!("mssfix parameter has been updated");
         }
 
+        if let Some(connect_timeout) = connect_timeout {
+            rpc.set_openvpn_connect_timeout(connect_timeout.option())
+                .await?;
+            println!("Connect timeout has been updated");
+        }
+
+        if let Some(retry_timeout) = retry_timeout {
+            rpc.set_openvpn_retry_timeout(retry_timeout.option())
+                .await?;
+            println!("Retry timeout has been updated");
+        }
+
+        #[cfg(target_os = "linux")]
+        if let Some(sandbox) = sandbox {
+            rpc.set_openvpn_sandbox(*sandbox).await?;
+            println!("Sandbox setting has been updated");
+        }
+
         Ok(())
     }
 
@@ -180,6 +507,8 @@ impl Tunnel {
         quantum_resistant: Option<QuantumResistantState>,
         #[cfg(target_os = "windows")] daita: Option<BooleanOption>,
         rotation_interval: Option<Constraint<RotationInterval>>,
+        #[cfg(target_os = "linux")] allowed_ips: Option<Constraint<Vec<IpNetwork>>>,
+        #[cfg(target_os = "linux")] egress_interface: Option<Constraint<String>>,
         rotate_key: Option<RotateKey>,
     ) -> Result<()> {
         let mut rpc = MullvadProxyClient::new().await?;
@@ -201,6 +530,19 @@ impl Tunnel {
             println!("DAITA setting has been updated");
         }
 
+        #[cfg(target_os = "linux")]
+        if let Some(allowed_ips) = allowed_ips {
+            rpc.set_wireguard_allowed_ips(allowed_ips.option()).await?;
+            println!("Allowed IPs have been updated");
+        }
+
+        #[cfg(target_os = "linux")]
+        if let Some(egress_interface) = egress_interface {
+            rpc.set_wireguard_egress_interface(egress_interface.option())
+                .await?;
+            println!("Egress interface has been updated");
+        }
+
         if let Some(interval) = rotation_interval {
             match interval {
                 Constraint::Only(interval) => {
@@ -218,10 +560,39 @@ impl Tunnel {
         }
 
         if matches!(rotate_key, Some(RotateKey::RotateKey)) {
-            rpc.rotate_wireguard_key().await?;
-            println!("Rotated WireGuard key");
+            let mut progress = rpc.rotate_wireguard_key_now().await?;
+            while let Some(step) = progress.next().await {
+                match step? {
+                    KeyRotationProgress::Uploading => println!("Uploading new WireGuard key"),
+                    KeyRotationProgress::Replaced(public_key) => {
+                        println!("Rotated WireGuard key");
+                        println!("Public key: {}", public_key.key);
+                    }
+                    KeyRotationProgress::Reconnecting => {
+                        println!("Reconnecting to start using the new key")
+                    }
+                }
+            }
         }
 
         Ok(())
     }
 }
+
+/// Format an optional value the way it is passed to `tunnel set`, with 'any' for no value.
+fn format_constraint<T: std::fmt::Display>(value: Option<T>) -> String {
+    Constraint::<T>::from(value).to_string()
+}
+
+/// Parse a comma-separated list of networks, or 'any'.
+#[cfg(target_os = "linux")]
+fn parse_allowed_ips(value: &str) -> Result<Constraint<Vec<IpNetwork>>, ipnetwork::IpNetworkError> {
+    if value.eq_ignore_ascii_case("any") {
+        return Ok(Constraint::Any);
+    }
+    value
+        .split(',')
+        .map(|network| network.trim().parse())
+        .collect::<Result<_, _>>()
+        .map(Constraint::Only)
+}
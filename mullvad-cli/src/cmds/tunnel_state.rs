@@ -1,8 +1,21 @@
 use crate::format;
 use anyhow::{anyhow, Result};
 use futures::{Stream, StreamExt};
-use mullvad_management_interface::{client::DaemonEvent, MullvadProxyClient};
+use mullvad_management_interface::{client::DaemonEvent, types::ErrorCode, MullvadProxyClient};
 use mullvad_types::{device::DeviceState, states::TunnelState};
+use std::time::Duration;
+use talpid_types::tunnel::ErrorStateCause;
+
+/// The tunnel entered the error state while waiting for it to connect.
+#[derive(thiserror::Error, Debug)]
+#[error("The tunnel entered the error state: {0}")]
+pub struct TunnelErrorState(ErrorStateCause);
+
+impl TunnelErrorState {
+    pub fn error_code(&self) -> ErrorCode {
+        ErrorCode::from(&self.0)
+    }
+}
 
 pub async fn connect(wait: bool) -> Result<()> {
     let mut rpc = MullvadProxyClient::new().await?;
@@ -20,7 +33,10 @@ pub async fn connect(wait: bool) -> Result<()> {
         if let Some(receiver) = listener {
             wait_for_tunnel_state(receiver, |state| match state {
                 TunnelState::Connected { .. } => Ok(true),
-                TunnelState::Error(_) => Err(anyhow!("Failed to connect")),
+                TunnelState::Error(error_state) => Err(anyhow::Error::new(TunnelErrorState(
+                    error_state.cause().clone(),
+                ))
+                .context("Failed to connect")),
                 _ => Ok(false),
             })
             .await?;
@@ -64,7 +80,10 @@ pub async fn reconnect(wait: bool) -> Result<()> {
         if let Some(receiver) = listener {
             wait_for_tunnel_state(receiver, |state| match state {
                 TunnelState::Connected { .. } => Ok(true),
-                TunnelState::Error(_) => Err(anyhow!("Failed to reconnect")),
+                TunnelState::Error(error_state) => Err(anyhow::Error::new(TunnelErrorState(
+                    error_state.cause().clone(),
+                ))
+                .context("Failed to reconnect")),
                 _ => Ok(false),
             })
             .await?;
@@ -74,6 +93,57 @@ pub async fn reconnect(wait: bool) -> Result<()> {
     Ok(())
 }
 
+/// Tunnel state that `mullvad wait` can wait for.
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+pub enum WaitState {
+    Connected,
+    Connecting,
+    Disconnected,
+    Disconnecting,
+    Error,
+}
+
+impl WaitState {
+    fn matches(self, state: &TunnelState) -> bool {
+        match self {
+            WaitState::Connected => state.is_connected(),
+            WaitState::Connecting => matches!(state, TunnelState::Connecting { .. }),
+            WaitState::Disconnected => state.is_disconnected(),
+            WaitState::Disconnecting => matches!(state, TunnelState::Disconnecting(..)),
+            WaitState::Error => state.is_in_error_state(),
+        }
+    }
+}
+
+/// Wait until the tunnel is in `state`, which may already be the case. Fails if `timeout` seconds
+/// pass first.
+pub async fn wait(state: WaitState, timeout: Option<u64>) -> Result<()> {
+    let mut rpc = MullvadProxyClient::new().await?;
+
+    // Subscribe before reading the current state, so that no transition is missed
+    let mut event_stream = rpc.events_listen().await?;
+    if state.matches(&rpc.get_tunnel_state().await?) {
+        return Ok(());
+    }
+
+    let reached = async {
+        while let Some(event) = event_stream.next().await {
+            if let DaemonEvent::TunnelState(new_state) = event? {
+                if state.matches(&new_state) {
+                    return Ok(());
+                }
+            }
+        }
+        Err(anyhow!("Failed to wait for expected tunnel state"))
+    };
+    match timeout {
+        Some(timeout) => tokio::time::timeout(Duration::from_secs(timeout), reached)
+            .await
+            .map_err(|_| anyhow!("Timed out waiting for the tunnel state"))?,
+        None => reached.await,
+    }
+}
+
 async fn wait_for_tunnel_state(
     mut event_stream: impl Stream<Item = std::result::Result<DaemonEvent, mullvad_management_interface::Error>>
         + Unpin,
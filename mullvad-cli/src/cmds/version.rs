@@ -1,5 +1,6 @@
 use anyhow::{Context, Result};
 use mullvad_management_interface::MullvadProxyClient;
+use mullvad_types::version::UpdateChannel;
 
 pub async fn print() -> Result<()> {
     println!("{:22}: {}", "Current version", mullvad_version::VERSION);
@@ -24,7 +25,18 @@ pub async fn print() -> Result<()> {
     println!("{:22}: {}", "Is supported", version_info.supported);
 
     if let Some(suggested_upgrade) = version_info.suggested_upgrade {
-        println!("{:22}: {}", "Suggested upgrade", suggested_upgrade);
+        match suggested_upgrade.channel {
+            UpdateChannel::Stable => {
+                println!("{:22}: {}", "Suggested upgrade", suggested_upgrade.version)
+            }
+            UpdateChannel::Beta => println!(
+                "{:22}: {} (beta)",
+                "Suggested upgrade", suggested_upgrade.version
+            ),
+        }
+        if let Some(changelog_url) = suggested_upgrade.changelog_url {
+            println!("{:22}: {}", "Changelog", changelog_url);
+        }
     } else {
         println!("{:22}: none", "Suggested upgrade");
     }
@@ -0,0 +1,60 @@
+use anyhow::{anyhow, Result};
+use clap::Subcommand;
+use mullvad_management_interface::MullvadProxyClient;
+use mullvad_types::settings::WebhookSettings;
+use std::io::{self, IsTerminal, Write};
+
+#[derive(Subcommand, Debug)]
+pub enum Webhook {
+    /// Show the URL that tunnel state changes are posted to
+    Get,
+
+    /// Post tunnel state changes as JSON to a URL on this machine. You are asked for a secret,
+    /// which is sent in the `X-Mullvad-Webhook-Secret` header so that the receiver can tell that
+    /// events come from the daemon. It can also be piped to stdin
+    Set {
+        /// URL to post to. Must use http and point to localhost or a loopback address
+        url: String,
+    },
+
+    /// Stop posting tunnel state changes
+    Clear,
+}
+
+impl Webhook {
+    pub async fn handle(self) -> Result<()> {
+        let mut rpc = MullvadProxyClient::new().await?;
+        match self {
+            Webhook::Get => match rpc.get_settings().await?.webhook {
+                Some(webhook) => println!("Webhook: {}", webhook.url),
+                None => println!("Webhook: off"),
+            },
+            Webhook::Set { url } => {
+                let secret = tokio::task::spawn_blocking(read_secret).await.unwrap()?;
+                rpc.set_webhook(WebhookSettings { url, secret }).await?;
+                println!("Updated webhook");
+            }
+            Webhook::Clear => {
+                rpc.clear_webhook().await?;
+                println!("Webhook: off");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Read the webhook secret from stdin. It is not taken as an argument, since the arguments of a
+/// process can be seen by other users.
+fn read_secret() -> Result<String> {
+    if io::stdin().is_terminal() {
+        print!("Enter the webhook secret: ");
+        let _ = io::stdout().flush();
+    }
+    let mut secret = String::new();
+    io::stdin().read_line(&mut secret)?;
+    let secret = secret.trim_end_matches(['\r', '\n']);
+    if secret.is_empty() {
+        return Err(anyhow!("The webhook secret must not be empty"));
+    }
+    Ok(secret.to_owned())
+}
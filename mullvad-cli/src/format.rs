@@ -1,7 +1,14 @@
-use mullvad_types::{auth_failed::AuthFailed, location::GeoIpLocation, states::TunnelState};
+use mullvad_types::{
+    access_method::TestResult,
+    auth_failed::AuthFailed,
+    location::{ConnectionVerification, GeoIpLocation},
+    relay_list::{RelayDiffEntry, RelayListDiff},
+    states::TunnelState,
+};
+use std::collections::BTreeMap;
 use talpid_types::{
     net::{Endpoint, TunnelEndpoint},
-    tunnel::ErrorState,
+    tunnel::{ConnectTrace, ErrorState},
 };
 
 #[macro_export]
@@ -19,7 +26,11 @@ pub fn print_state(state: &TunnelState, verbose: bool) {
 
     match state {
         Error(error) => print_error_state(error),
-        Connected { endpoint, location } => {
+        Connected {
+            endpoint,
+            location,
+            connect_trace,
+        } => {
             println!(
                 "Connected to {}",
                 format_relay_connection(endpoint, location.as_ref(), verbose)
@@ -28,6 +39,13 @@ pub fn print_state(state: &TunnelState, verbose: bool) {
                 if let Some(tunnel_interface) = &endpoint.tunnel_interface {
                     println!("Tunnel interface: {tunnel_interface}")
                 }
+                if let Some(transport) = &endpoint.ephemeral_peer_transport {
+                    println!("Ephemeral peer negotiated over: {transport}")
+                }
+                if endpoint.data_channel_offload {
+                    println!("OpenVPN data channel offload: enabled")
+                }
+                print_connect_trace(connect_trace);
             }
         }
         Connecting { endpoint, location } => {
@@ -51,6 +69,74 @@ pub fn print_state(state: &TunnelState, verbose: bool) {
     }
 }
 
+pub fn print_connect_trace(trace: &ConnectTrace) {
+    println!("Connected in {} ms", trace.total.as_millis());
+    for (phase, duration) in &trace.phases {
+        println!(
+            "{:<4}{:<24}{} ms",
+            "",
+            format!("{phase}:"),
+            duration.as_millis()
+        );
+    }
+}
+
+pub fn print_access_method_test_result(result: &TestResult) {
+    for step in &result.steps {
+        let outcome = match &step.error {
+            None => format!("{} ms", step.duration.as_millis()),
+            Some(error) => format!("failed after {} ms: {error}", step.duration.as_millis()),
+        };
+        println!("{:<4}{:<24}{outcome}", "", format!("{}:", step.step));
+    }
+    if let Some(status) = result.http_status {
+        println!("{:<4}{:<24}{status}", "", "HTTP status:");
+    }
+    println!("{:<4}{:<24}{} ms", "", "Total:", result.total.as_millis());
+}
+
+pub fn print_connection_verification(verification: &ConnectionVerification) {
+    if verification.is_verified() {
+        println!("Connection verified");
+    } else {
+        println!("Connection could not be verified");
+    }
+    let yes_no = |value: bool| if value { "yes" } else { "no" };
+    let unknown = |value: Option<bool>| value.map(yes_no).unwrap_or("unknown");
+    print_option!("Exit IP", verification.exit_ip);
+    print_option!("Mullvad exit IP", yes_no(verification.mullvad_exit_ip));
+    print_option!(
+        "Exit relay matches",
+        unknown(verification.exit_relay_matches)
+    );
+    print_option!("DNS hijacked", unknown(verification.dns_hijacked));
+}
+
+/// Print one line per kind of change and city, e.g. "3 relays added in se-sto".
+pub fn print_relay_list_diff(diff: &RelayListDiff) {
+    for (entries, action) in [
+        (&diff.added, "added"),
+        (&diff.removed, "removed"),
+        (&diff.changed, "changed"),
+    ] {
+        let mut cities = BTreeMap::<_, usize>::new();
+        for RelayDiffEntry {
+            country_code,
+            city_code,
+            ..
+        } in entries
+        {
+            *cities.entry((country_code, city_code)).or_default() += 1;
+        }
+        for ((country_code, city_code), count) in cities {
+            println!(
+                "{count} {} {action} in {country_code}-{city_code}",
+                if count == 1 { "relay" } else { "relays" }
+            );
+        }
+    }
+}
+
 pub fn print_location(state: &TunnelState) {
     let location = match state {
         TunnelState::Disconnected {
@@ -71,6 +157,9 @@ pub fn print_location(state: &TunnelState) {
         if let Some(ipv6) = location.ipv6 {
             print!(", IPv6: {ipv6}");
         }
+        if location.stale {
+            print!(" (last known location)");
+        }
         println!();
     }
 }
@@ -185,9 +274,20 @@ fn format_relay_connection(
     #[cfg(not(target_os = "windows"))]
     let daita = "";
 
+    let mut provider = String::new();
     let mut bridge_type = String::new();
     let mut obfuscator_type = String::new();
     if verbose {
+        if let Some(location) = location {
+            if let Some(name) = &location.provider {
+                provider = format!("\nProvider: {name}");
+            }
+            match location.owned {
+                Some(true) => provider.push_str("\nOwnership: Mullvad owned"),
+                Some(false) => provider.push_str("\nOwnership: rented"),
+                None => (),
+            }
+        }
         if let Some(bridge) = &endpoint.proxy {
             bridge_type = format!("\nBridge type: {}", bridge.proxy_type);
         }
@@ -197,7 +297,7 @@ fn format_relay_connection(
     }
 
     format!(
-        "{exit_endpoint}{first_hop}{bridge}{obfuscator}{tunnel_type}{quantum_resistant}{daita}{bridge_type}{obfuscator_type}",
+        "{exit_endpoint}{first_hop}{bridge}{obfuscator}{tunnel_type}{quantum_resistant}{daita}{provider}{bridge_type}{obfuscator_type}",
         first_hop = first_hop.unwrap_or_default(),
         bridge = bridge.unwrap_or_default(),
         obfuscator = obfuscator.unwrap_or_default(),
@@ -225,6 +325,11 @@ fn print_error_state(error_state: &ErrorState) {
             println!("Blocked: {cause}");
             println!("Your kernel might be terribly out of date or missing nftables");
         }
+        #[cfg(target_os = "linux")]
+        cause @ talpid_types::tunnel::ErrorStateCause::UnsupportedDnsManager(_) => {
+            println!("Blocked: {cause}");
+            println!("Let systemd-resolved, NetworkManager or resolvconf manage /etc/resolv.conf, or run \"mullvad dns unsupported-manager warn\" to connect anyway");
+        }
         talpid_types::tunnel::ErrorStateCause::AuthFailed(Some(auth_failed)) => {
             println!(
                 "Blocked: Authentication with remote server failed: {}",
@@ -233,6 +338,13 @@ fn print_error_state(error_state: &ErrorState) {
         }
         cause => println!("Blocked: {cause}"),
     }
+
+    if !error_state.conflicting_software().is_empty() {
+        println!(
+            "The following software may interfere with the tunnel: {}",
+            error_state.conflicting_software().join(", ")
+        );
+    }
 }
 
 const fn get_auth_failed_message(auth_failed: AuthFailed) -> &'static str {
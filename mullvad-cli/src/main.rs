@@ -1,5 +1,7 @@
 use anyhow::Result;
 use clap::Parser;
+use mullvad_management_interface::types::ErrorCode;
+use std::process::ExitCode;
 
 mod cmds;
 mod format;
@@ -7,6 +9,9 @@ use cmds::*;
 
 pub const BIN_NAME: &str = env!("CARGO_BIN_NAME");
 
+/// Exit status for failures that have no [ErrorCode].
+const GENERIC_FAILURE_EXIT_CODE: u8 = 1;
+
 #[derive(Debug, Parser)]
 #[command(author, version = mullvad_version::VERSION, about, long_about = None)]
 #[command(propagate_version = true)]
@@ -27,6 +32,10 @@ enum Cli {
     #[clap(subcommand)]
     LockdownMode(lockdown::LockdownMode),
 
+    /// Require a passphrase to disconnect, to disable lockdown mode or to change any setting
+    #[clap(subcommand)]
+    SettingsLock(settings_lock::SettingsLock),
+
     /// Debug commands used for internal testing of the app.
     ///
     /// These commands will likely set the app in an invalid state, which is
@@ -63,6 +72,18 @@ enum Cli {
         wait: bool,
     },
 
+    /// Wait until the tunnel is in a given state. Exits with a non-zero status if the timeout
+    /// passes first
+    Wait {
+        /// State to wait for
+        #[arg(long, short = 's')]
+        state: tunnel_state::WaitState,
+
+        /// Seconds to wait at most. Waits indefinitely by default
+        #[arg(long, short = 't')]
+        timeout: Option<u64>,
+    },
+
     /// Manage use of bridges, socks proxies and Shadowsocks for OpenVPN.
     /// Can make OpenVPN tunnels use Shadowsocks via one of the Mullvad bridge servers.
     /// Can also make OpenVPN connect through any custom SOCKS5 proxy.
@@ -90,6 +111,10 @@ enum Cli {
     #[clap(subcommand)]
     ApiAccess(api_access::ApiAccess),
 
+    /// Use a self-hosted API deployment instead of the Mullvad API, such as a test API
+    #[clap(subcommand)]
+    ApiEndpoint(api_endpoint::ApiEndpoint),
+
     /// Manage use of obfuscation protocols for WireGuard.
     /// Can make WireGuard traffic look like something else on the network.
     /// Helps circumvent censorship and to establish a tunnel when on restricted networks
@@ -99,6 +124,11 @@ enum Cli {
     #[clap(subcommand)]
     SplitTunnel(split_tunnel::SplitTunnel),
 
+    /// Manage the service used to look up the exit location, and the check of the connection
+    /// that runs every time the tunnel comes up
+    #[clap(subcommand)]
+    ConnectionCheck(connection_check::ConnectionCheck),
+
     /// Return the state of the VPN tunnel
     Status {
         #[clap(subcommand)]
@@ -108,14 +138,42 @@ enum Cli {
         args: status::StatusArgs,
     },
 
+    /// Show the amount of traffic sent and received through the tunnel each month
+    Stats,
+
+    /// Check the status of the subsystems of the daemon. Exits with a non-zero status if any of
+    /// them have failed
+    Health,
+
     /// Manage tunnel options
     #[clap(subcommand)]
     Tunnel(tunnel::Tunnel),
 
+    /// Send a problem report collected by 'mullvad-problem-report collect' to Mullvad support.
+    /// The report is uploaded by the daemon, which sends it again if the connection fails
+    #[clap(arg_required_else_help = true)]
+    SendProblemReport {
+        /// Problem report to send
+        report: std::path::PathBuf,
+
+        /// Email address to reply to
+        #[arg(long, short = 'e', default_value = "")]
+        email: String,
+
+        /// Description of the problem
+        #[arg(long, short = 'm', default_value = "")]
+        message: String,
+    },
+
     /// Show information about the current Mullvad version
     /// and available versions
     Version,
 
+    /// Post tunnel state changes to a local HTTP endpoint, for use by home automation and
+    /// monitoring tools
+    #[clap(subcommand)]
+    Webhook(webhook::Webhook),
+
     /// Generate completion scripts for the specified shell
     #[cfg(all(unix, not(target_os = "android")))]
     #[command(hide = true)]
@@ -135,6 +193,23 @@ enum Cli {
     #[clap(subcommand)]
     CustomList(custom_list::CustomList),
 
+    /// Manage named sets of relay, obfuscation and DNS settings that can be switched between
+    #[clap(subcommand)]
+    Profile(profile::Profile),
+
+    /// Replace the settings with those in a YAML or JSON document. If any setting in the
+    /// document is invalid, no settings are changed
+    #[clap(arg_required_else_help = true)]
+    Apply {
+        /// File to read from. If this is "-", read from standard input
+        #[arg(long, short = 'f')]
+        file: String,
+
+        /// Only check that the document is valid
+        #[arg(long)]
+        dry_run: bool,
+    },
+
     /// Apply a JSON patch generated by 'export-settings'
     #[clap(arg_required_else_help = true)]
     ImportSettings {
@@ -151,28 +226,76 @@ enum Cli {
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    match Cli::parse() {
+async fn main() -> ExitCode {
+    match run(Cli::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {error:?}");
+            ExitCode::from(exit_code(&error))
+        }
+    }
+}
+
+/// Return the exit status for `error`. Failed RPCs and error states exit with their management
+/// interface [ErrorCode], so that scripts can tell failures apart.
+fn exit_code(error: &anyhow::Error) -> u8 {
+    let error_code = error.chain().find_map(|cause| {
+        if let Some(error) = cause.downcast_ref::<mullvad_management_interface::Error>() {
+            return error.error_code();
+        }
+        cause
+            .downcast_ref::<tunnel_state::TunnelErrorState>()
+            .map(tunnel_state::TunnelErrorState::error_code)
+    });
+    match error_code {
+        Some(ErrorCode::UnknownError) | None => GENERIC_FAILURE_EXIT_CODE,
+        Some(error_code) => {
+            u8::try_from(i32::from(error_code)).unwrap_or(GENERIC_FAILURE_EXIT_CODE)
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    match cli {
         Cli::Account(cmd) => cmd.handle().await,
         Cli::Bridge(cmd) => cmd.handle().await,
         Cli::Connect { wait } => tunnel_state::connect(wait).await,
         Cli::Reconnect { wait } => tunnel_state::reconnect(wait).await,
         Cli::Debug(cmd) => cmd.handle().await,
         Cli::Disconnect { wait } => tunnel_state::disconnect(wait).await,
+        Cli::Wait { state, timeout } => tunnel_state::wait(state, timeout).await,
         Cli::AutoConnect(cmd) => cmd.handle().await,
         Cli::BetaProgram(cmd) => cmd.handle().await,
         Cli::LockdownMode(cmd) => cmd.handle().await,
+        Cli::SettingsLock(settings_lock::SettingsLock::Unlock { command }) => {
+            settings_lock::unlock().await?;
+            let cli = Cli::parse_from(std::iter::once(BIN_NAME.to_owned()).chain(command));
+            Box::pin(run(cli)).await
+        }
+        Cli::SettingsLock(cmd) => cmd.handle().await,
         Cli::Dns(cmd) => cmd.handle().await,
         Cli::Lan(cmd) => cmd.handle().await,
         Cli::Obfuscation(cmd) => cmd.handle().await,
         Cli::ApiAccess(cmd) => cmd.handle().await,
+        Cli::ApiEndpoint(cmd) => cmd.handle().await,
         Cli::Version => version::print().await,
+        Cli::Webhook(cmd) => cmd.handle().await,
+        Cli::ConnectionCheck(cmd) => cmd.handle().await,
         Cli::FactoryReset => reset::handle().await,
         Cli::Relay(cmd) => cmd.handle().await,
         Cli::Tunnel(cmd) => cmd.handle().await,
+        Cli::SendProblemReport {
+            report,
+            email,
+            message,
+        } => problem_report::send(report, email, message).await,
         Cli::SplitTunnel(cmd) => cmd.handle().await,
         Cli::Status { cmd, args } => status::handle(cmd, args).await,
+        Cli::Stats => stats::print().await,
+        Cli::Health => health::print().await,
         Cli::CustomList(cmd) => cmd.handle().await,
+        Cli::Profile(cmd) => cmd.handle().await,
+        Cli::Apply { file, dry_run } => apply::apply(file, dry_run).await,
         Cli::ImportSettings { file } => patch::import(file).await,
         Cli::ExportSettings { file } => patch::export(file).await,
 
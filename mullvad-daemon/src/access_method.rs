@@ -1,9 +1,10 @@
-use crate::{api, settings, Daemon, EventListener};
-use mullvad_api::{proxy::ApiConnectionMode, rest, ApiProxy};
+use crate::{api, host_cache, settings, Daemon, EventListener};
+use mullvad_api::{proxy::ApiConnectionMode, rest};
 use mullvad_types::{
-    access_method::{self, AccessMethod, AccessMethodSetting},
+    access_method::{self, AccessMethod, AccessMethodSetting, TestResult},
     settings::Settings,
 };
+use std::future::Future;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -26,6 +27,9 @@ pub enum Error {
     /// Access methods settings error
     #[error("Settings error")]
     Settings(#[from] settings::Error),
+    /// The hostname of a custom access method could not be resolved
+    #[error("Failed to resolve hostname of access method")]
+    ResolveHostname(#[from] host_cache::Error),
 }
 
 impl<L> Daemon<L>
@@ -153,6 +157,9 @@ where
     /// different kinds of testing contexts, such as testing
     /// [`AccessMethodSetting`]s or on the fly testing of
     /// [`talpid_types::net::proxy::CustomProxy`]s.
+    ///
+    /// `test` is run while `proxy` is allowed through the firewall. See
+    /// [`Self::create_connection_test`].
     pub(crate) async fn test_access_method(
         proxy: talpid_types::net::AllowedEndpoint,
         access_method_selector: api::AccessModeSelectorHandle,
@@ -160,8 +167,8 @@ where
             api::AccessMethodEvent,
             futures::channel::oneshot::Sender<()>,
         )>,
-        api_proxy: ApiProxy,
-    ) -> Result<bool, Error> {
+        test: impl Future<Output = TestResult>,
+    ) -> Result<TestResult, Error> {
         let reset = access_method_selector
             .get_current()
             .await
@@ -171,30 +178,22 @@ where
             .send(daemon_event_sender.clone())
             .await?;
 
-        let result = Self::perform_api_request(api_proxy).await;
+        let result = test.await;
 
         api::AccessMethodEvent::Allow { endpoint: reset }
             .send(daemon_event_sender)
             .await?;
 
-        result
-    }
-
-    /// Create an [`ApiProxy`] which will perform all REST requests against one
-    /// specific endpoint `connection_mode`.
-    pub fn create_limited_api_proxy(&mut self, connection_mode: ApiConnectionMode) -> ApiProxy {
-        let rest_handle = self
-            .api_runtime
-            .mullvad_rest_handle(connection_mode.into_provider());
-        ApiProxy::new(rest_handle)
+        Ok(result)
     }
 
-    /// Perform some REST request against the Mullvad API.
-    ///
-    /// * Returns `Ok(true)` if the API returned the expected result
-    /// * Returns `Ok(false)` if the API returned an unexpected result
-    /// * Returns `Err(..)` if the API could not be reached
-    async fn perform_api_request(api_proxy: ApiProxy) -> Result<bool, Error> {
-        Ok(api_proxy.api_addrs_available().await?)
+    /// Create a test of whether the API can be reached using one specific
+    /// endpoint `connection_mode`. The test reports how far it got, such as
+    /// whether the TCP connection or the TLS handshake failed.
+    pub fn create_connection_test(
+        &self,
+        connection_mode: ApiConnectionMode,
+    ) -> impl Future<Output = TestResult> + Send + 'static {
+        self.api_runtime.test_connection_mode(connection_mode)
     }
 }
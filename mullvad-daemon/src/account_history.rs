@@ -64,7 +64,7 @@ impl AccountHistory {
         let mut buffer = String::new();
         let (token, should_save): (Option<AccountToken>, bool) =
             match reader.read_to_string(&mut buffer).await {
-                Ok(_) if ACCOUNT_REGEX.is_match(&buffer) => (Some(buffer), false),
+                Ok(_) if ACCOUNT_REGEX.is_match(&buffer) => (Some(buffer.into()), false),
                 Ok(0) => (current_token, true),
                 Ok(_) | Err(_) => {
                     log::warn!("Failed to parse account history");
@@ -110,7 +110,7 @@ impl AccountHistory {
             .map_err(Error::Write)?;
         if let Some(ref token) = self.token {
             self.file
-                .write_all(token.as_bytes())
+                .write_all(token.expose_str().as_bytes())
                 .await
                 .map_err(Error::Write)?;
         }
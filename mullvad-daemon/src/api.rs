@@ -5,7 +5,7 @@
 //! establishing connections when performing API requests.
 #[cfg(target_os = "android")]
 use crate::DaemonCommand;
-use crate::DaemonEventSender;
+use crate::{host_cache::HostCache, DaemonEventSender};
 use futures::{
     channel::{mpsc, oneshot},
     StreamExt,
@@ -21,8 +21,10 @@ use mullvad_types::access_method::{
 };
 use std::{net::SocketAddr, path::PathBuf};
 use talpid_core::mpsc::Sender;
-use talpid_types::net::{
-    AllowedClients, AllowedEndpoint, Connectivity, Endpoint, TransportProtocol,
+use talpid_net::SocketProtection;
+use talpid_types::{
+    net::{AllowedClients, AllowedEndpoint, Connectivity, Endpoint, TransportProtocol},
+    ErrorExt,
 };
 
 pub enum Message {
@@ -244,6 +246,8 @@ pub struct AccessModeSelector {
     cache_dir: PathBuf,
     /// Used for selecting a Bridge when the `Mullvad Bridges` access method is used.
     relay_selector: RelaySelector,
+    /// Used for resolving custom access methods that are referenced by hostname.
+    host_cache: HostCache,
     access_method_settings: Settings,
     address_cache: AddressCache,
     access_method_event_sender: DaemonEventSender<(AccessMethodEvent, oneshot::Sender<()>)>,
@@ -257,25 +261,22 @@ impl AccessModeSelector {
     pub(crate) async fn spawn(
         cache_dir: PathBuf,
         relay_selector: RelaySelector,
-        #[cfg_attr(not(feature = "api-override"), allow(unused_mut))]
+        host_cache: HostCache,
         mut access_method_settings: Settings,
         access_method_event_sender: DaemonEventSender<(AccessMethodEvent, oneshot::Sender<()>)>,
         address_cache: AddressCache,
     ) -> Result<(AccessModeSelectorHandle, AccessModeConnectionModeProvider)> {
         let (cmd_tx, cmd_rx) = mpsc::unbounded();
 
-        #[cfg(feature = "api-override")]
-        {
-            if mullvad_api::API.force_direct {
-                access_method_settings
-                    .update(|setting| setting.is_direct(), |setting| setting.enable());
-            }
+        if Self::force_direct() {
+            access_method_settings
+                .update(|setting| setting.is_direct(), |setting| setting.enable());
         }
 
         // Always start looking from the position of `Direct`.
         let (index, next) = Self::find_next_active(0, &access_method_settings);
         let initial_connection_mode =
-            Self::resolve_inner(next, &relay_selector, &address_cache).await;
+            Self::resolve_inner(next, &relay_selector, &host_cache, &address_cache).await;
 
         let (change_tx, change_rx) = mpsc::unbounded();
 
@@ -285,6 +286,7 @@ impl AccessModeSelector {
             cmd_rx,
             cache_dir,
             relay_selector,
+            host_cache,
             access_method_settings,
             address_cache,
             access_method_event_sender,
@@ -342,14 +344,6 @@ impl AccessModeSelector {
 
     /// Set and announce the specified access method as the current one.
     async fn use_access_method(&mut self, id: Id) {
-        #[cfg(feature = "api-override")]
-        {
-            if mullvad_api::API.force_direct {
-                log::debug!("API proxies are disabled");
-                return;
-            }
-        }
-
         let Some((index, method)) = self
             .access_method_settings
             .iter()
@@ -359,6 +353,11 @@ impl AccessModeSelector {
             return;
         };
 
+        if Self::force_direct() && !method.is_direct() {
+            log::debug!("API proxies are disabled");
+            return;
+        }
+
         self.index = index;
         self.set_current(method.to_owned()).await;
     }
@@ -369,17 +368,21 @@ impl AccessModeSelector {
     }
 
     async fn next_connection_mode(&mut self) -> Result<ApiConnectionMode> {
+        if Self::force_direct() {
+            log::debug!("API proxies are disabled");
+            return Ok(ApiConnectionMode::Direct);
+        }
+
         #[cfg(feature = "api-override")]
-        {
-            if mullvad_api::API.force_direct {
-                log::debug!("API proxies are disabled");
-                return Ok(ApiConnectionMode::Direct);
-            }
+        log::debug!(
+            "The `api-override` feature is enabled, but a direct connection \
+             is not enforced. Selecting API access methods as normal"
+        );
 
-            log::debug!(
-                "The `api-override` feature is enabled, but a direct connection \
-                 is not enforced. Selecting API access methods as normal"
-            );
+        // If the API could not be reached directly, the address itself may be blocked. Try the
+        // next known API address the next time a direct connection is attempted.
+        if matches!(self.current.connection_mode, ApiConnectionMode::Direct) {
+            self.address_cache.next_address().await;
         }
 
         let (next_index, next) =
@@ -389,6 +392,15 @@ impl AccessModeSelector {
         Ok(self.current.connection_mode.clone())
     }
 
+    /// Whether the API must be reached directly, regardless of the access method settings.
+    fn force_direct() -> bool {
+        #[cfg(feature = "api-override")]
+        if mullvad_api::API.force_direct {
+            return true;
+        }
+        mullvad_api::custom_endpoint::force_direct()
+    }
+
     async fn set_current(&mut self, access_method: AccessMethodSetting) {
         let resolved = self.resolve(access_method).await;
 
@@ -501,16 +513,27 @@ impl AccessModeSelector {
     }
 
     async fn resolve(&mut self, access_method: AccessMethodSetting) -> ResolvedConnectionMode {
-        Self::resolve_inner(access_method, &self.relay_selector, &self.address_cache).await
+        Self::resolve_inner(
+            access_method,
+            &self.relay_selector,
+            &self.host_cache,
+            &self.address_cache,
+        )
+        .await
     }
 
     async fn resolve_inner(
         access_method: AccessMethodSetting,
         relay_selector: &RelaySelector,
+        host_cache: &HostCache,
         address_cache: &AddressCache,
     ) -> ResolvedConnectionMode {
-        let connection_mode =
-            resolve_connection_mode(access_method.access_method.clone(), relay_selector);
+        let connection_mode = resolve_connection_mode(
+            access_method.access_method.clone(),
+            relay_selector,
+            host_cache,
+        )
+        .await;
         let endpoint =
             resolve_allowed_endpoint(&connection_mode, address_cache.get_address().await);
         ResolvedConnectionMode {
@@ -522,12 +545,13 @@ impl AccessModeSelector {
 }
 
 /// Ad-hoc version of [`std::convert::From::from`], but since some
-/// [`ApiConnectionMode`]s require extra logic/data from [`RelaySelector`] to be
-/// instantiated the standard [`std::convert::From`] trait can not be
-/// implemented.
-fn resolve_connection_mode(
+/// [`ApiConnectionMode`]s require extra logic/data from [`RelaySelector`] or
+/// [`HostCache`] to be instantiated the standard [`std::convert::From`] trait
+/// can not be implemented.
+async fn resolve_connection_mode(
     access_method: AccessMethod,
     relay_selector: &RelaySelector,
+    host_cache: &HostCache,
 ) -> ApiConnectionMode {
     match access_method {
         AccessMethod::BuiltIn(BuiltInAccessMethod::Direct) => ApiConnectionMode::Direct,
@@ -541,7 +565,30 @@ fn resolve_connection_mode(
                 );
                 ApiConnectionMode::Direct
             }),
-        AccessMethod::Custom(config) => ApiConnectionMode::Proxied(ProxyConfig::from(config)),
+        AccessMethod::BuiltIn(BuiltInAccessMethod::SystemProxy) => {
+            #[cfg(not(target_os = "android"))]
+            let system_proxy = crate::system_proxy::detect().await;
+            #[cfg(target_os = "android")]
+            let system_proxy = None;
+            system_proxy
+                .map(ApiConnectionMode::Proxied)
+                .unwrap_or_else(|| {
+                    log::warn!("No system proxy was found. Defaulting to direct API connection");
+                    ApiConnectionMode::Direct
+                })
+        }
+        AccessMethod::Custom(mut config) => match host_cache.resolve_proxy(&mut config).await {
+            Ok(()) => ApiConnectionMode::Proxied(ProxyConfig::from(config)),
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg(
+                        "Failed to resolve hostname of access method. Defaulting to direct API connection"
+                    )
+                );
+                ApiConnectionMode::Direct
+            }
+        },
     }
 }
 
@@ -607,10 +654,10 @@ pub(crate) fn forward_offline_state(
     });
 }
 
+/// How to keep API connections outside of the tunnel. On Android, the daemon excludes the sockets
+/// from the tunnel, but only while it is not connected.
 #[cfg(target_os = "android")]
-pub(crate) fn create_bypass_tx(
-    event_sender: &DaemonEventSender,
-) -> Option<mpsc::Sender<mullvad_api::SocketBypassRequest>> {
+pub(crate) fn socket_protection(event_sender: &DaemonEventSender) -> SocketProtection {
     let (bypass_tx, mut bypass_rx) = mpsc::channel(1);
     let daemon_tx = event_sender.to_specialized_sender();
     tokio::spawn(async move {
@@ -624,5 +671,13 @@ pub(crate) fn create_bypass_tx(
             }
         }
     });
-    Some(bypass_tx)
+    SocketProtection::with_bypass_tx(Some(bypass_tx))
+}
+
+/// API connections are not kept outside of the tunnel on other platforms. They go through the
+/// tunnel while it is up, and the firewall allows the API endpoint whenever it blocks other
+/// traffic.
+#[cfg(not(target_os = "android"))]
+pub(crate) fn socket_protection(_event_sender: &DaemonEventSender) -> SocketProtection {
+    SocketProtection::default()
 }
@@ -24,16 +24,21 @@ pub async fn run_api_address_fetcher(address_cache: AddressCache, handle: Mullva
             log::error!("Failed while waiting for API: {}", error);
             continue;
         }
+        // The addresses of the Mullvad API are of no use to a custom API endpoint
+        if mullvad_api::custom_endpoint::is_set() {
+            next_delay = API_IP_CHECK_INTERVAL;
+            continue;
+        }
         match api_proxy.clone().get_api_addrs().await {
             Ok(new_addrs) => {
-                if let Some(addr) = new_addrs.first() {
+                if !new_addrs.is_empty() {
                     log::debug!(
-                        "Fetched new API address {:?}. Fetching again in {} hours",
-                        addr,
+                        "Fetched new API addresses {:?}. Fetching again in {} hours",
+                        new_addrs,
                         API_IP_CHECK_INTERVAL.as_secs() / (60 * 60)
                     );
-                    if let Err(err) = address_cache.set_address(*addr).await {
-                        log::error!("Failed to save newly updated API address: {}", err);
+                    if let Err(err) = address_cache.set_addresses(&new_addrs).await {
+                        log::error!("Failed to save newly updated API addresses: {}", err);
                     }
                 } else {
                     log::error!("API returned no API addresses");
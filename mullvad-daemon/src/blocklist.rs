@@ -0,0 +1,474 @@
+//! Downloads and reads the custom DNS blocklists in the settings, and hands the combined list to
+//! the DNS filter in the tunnel state machine. Lists are refreshed periodically. Downloaded lists
+//! are cached, so that they can be used even if they cannot be downloaded again.
+
+use crate::{DaemonEventSender, InternalDaemonEvent};
+use futures::{channel::mpsc, StreamExt};
+use hyper::{body::HttpBody, header, Body, Request, StatusCode, Uri};
+use mullvad_types::settings::BlocklistSource;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use talpid_core::mpsc::Sender;
+use talpid_types::{net::dns::Blocklist, ErrorExt};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+    time::Instant,
+};
+use tokio_rustls::{
+    rustls::{self, ClientConfig, ServerName},
+    TlsConnector,
+};
+
+/// Directory in the cache directory where downloaded lists are stored.
+const CACHE_DIR_NAME: &str = "blocklists";
+
+/// How often to refresh the lists.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+/// How long to wait before trying again if a list could not be downloaded.
+const RETRY_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_REDIRECTS: usize = 5;
+/// Largest list that will be downloaded.
+const MAX_LIST_SIZE: usize = 64 * 1024 * 1024;
+
+/// Locations of the system CA certificate bundle, which is used to verify HTTPS servers.
+#[cfg(target_os = "linux")]
+const CA_BUNDLE_PATHS: &[&str] = &[
+    "/etc/ssl/certs/ca-certificates.crt",
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/etc/ssl/ca-bundle.pem",
+    "/etc/ssl/cert.pem",
+];
+#[cfg(target_os = "macos")]
+const CA_BUNDLE_PATHS: &[&str] = &["/etc/ssl/cert.pem"];
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Failed to parse blocklist URL")]
+    ParseUrl(#[source] hyper::http::uri::InvalidUri),
+
+    #[error("Blocklist URL must use https")]
+    UnsupportedScheme,
+
+    #[error("Failed to connect to blocklist server")]
+    Connect(#[source] io::Error),
+
+    #[error("No system root certificates are available for HTTPS")]
+    NoRootCertificates,
+
+    #[error("Failed to create blocklist request")]
+    CreateRequest(#[source] hyper::http::Error),
+
+    #[error("Failed to download blocklist")]
+    Download(#[source] hyper::Error),
+
+    #[error("Timed out downloading blocklist")]
+    Timeout,
+
+    #[error("Too many redirects")]
+    TooManyRedirects,
+
+    #[error("Blocklist server redirected to another host or to a URL that is not https")]
+    InvalidRedirect,
+
+    #[error("Blocklist server responded with {0}")]
+    ErrorResponse(StatusCode),
+
+    #[error("Blocklist is larger than {} MiB", MAX_LIST_SIZE / 1024 / 1024)]
+    TooLarge,
+
+    #[error("Blocklist is not valid UTF-8")]
+    InvalidEncoding,
+
+    #[error("Failed to read blocklist file")]
+    ReadFile(#[source] io::Error),
+
+    #[error("Failed to write blocklist to cache")]
+    WriteCache(#[source] io::Error),
+}
+
+#[derive(Clone)]
+pub(crate) struct BlocklistUpdaterHandle {
+    tx: mpsc::UnboundedSender<Vec<BlocklistSource>>,
+}
+
+impl BlocklistUpdaterHandle {
+    /// Set the lists to block domains from. The lists are read or downloaded again if they
+    /// changed.
+    pub fn set_sources(&self, sources: Vec<BlocklistSource>) {
+        if self.tx.unbounded_send(sources).is_err() {
+            log::error!("Blocklist updater already down");
+        }
+    }
+}
+
+pub(crate) struct BlocklistUpdater {
+    sources: Vec<BlocklistSource>,
+    cache_dir: PathBuf,
+    daemon_tx: DaemonEventSender,
+}
+
+impl BlocklistUpdater {
+    pub fn spawn(
+        sources: Vec<BlocklistSource>,
+        cache_dir: &Path,
+        daemon_tx: DaemonEventSender,
+    ) -> BlocklistUpdaterHandle {
+        let (tx, rx) = mpsc::unbounded();
+        let updater = BlocklistUpdater {
+            sources,
+            cache_dir: cache_dir.join(CACHE_DIR_NAME),
+            daemon_tx,
+        };
+        tokio::spawn(updater.run(rx));
+        BlocklistUpdaterHandle { tx }
+    }
+
+    async fn run(mut self, mut rx: mpsc::UnboundedReceiver<Vec<BlocklistSource>>) {
+        // Start out with the cached lists, since downloading may take a while or be impossible
+        // until the tunnel is up.
+        self.update(false).await;
+        let mut next_update = Instant::now();
+
+        loop {
+            match tokio::time::timeout_at(next_update, rx.next()).await {
+                Ok(Some(sources)) => {
+                    if sources == self.sources {
+                        continue;
+                    }
+                    self.sources = sources;
+                }
+                Ok(None) => break,
+                Err(_elapsed) => (),
+            }
+            let interval = self.update(true).await;
+            next_update = Instant::now() + interval;
+        }
+    }
+
+    /// Read all lists and send the result to the tunnel state machine. Returns when the lists
+    /// should be updated next.
+    async fn update(&self, download: bool) -> Duration {
+        let mut interval = UPDATE_INTERVAL;
+        let mut lists = Vec::with_capacity(self.sources.len());
+
+        for source in &self.sources {
+            let list = match source {
+                BlocklistSource::Url(url) => {
+                    let cache_path = self.cache_path(url);
+                    let downloaded = if download {
+                        self.download_to_cache(url, &cache_path)
+                            .await
+                            .inspect_err(|error| {
+                                log::warn!(
+                                    "{}",
+                                    error.display_chain_with_msg(&format!(
+                                        "Failed to download blocklist from {url}"
+                                    ))
+                                );
+                                interval = RETRY_INTERVAL;
+                            })
+                            .ok()
+                    } else {
+                        None
+                    };
+                    match downloaded {
+                        Some(list) => Some(list),
+                        None => tokio::fs::read_to_string(&cache_path).await.ok(),
+                    }
+                }
+                BlocklistSource::File(path) => tokio::fs::read_to_string(path)
+                    .await
+                    .map_err(Error::ReadFile)
+                    .inspect_err(|error| {
+                        log::warn!(
+                            "{}",
+                            error.display_chain_with_msg(&format!(
+                                "Failed to read blocklist {}",
+                                path.display()
+                            ))
+                        );
+                    })
+                    .ok(),
+            };
+            lists.extend(list);
+        }
+
+        if download {
+            self.remove_unused_cache_files().await;
+        }
+
+        let blocklist = tokio::task::spawn_blocking(move || {
+            let mut blocklist = Blocklist::default();
+            for list in lists {
+                blocklist.add_list(&list);
+            }
+            blocklist
+        })
+        .await
+        .unwrap();
+
+        let blocklist = if blocklist.is_empty() {
+            None
+        } else {
+            log::info!(
+                "Blocking {} domains from custom blocklists",
+                blocklist.len()
+            );
+            Some(Arc::new(blocklist))
+        };
+        let _ = self
+            .daemon_tx
+            .send(InternalDaemonEvent::DnsBlocklist(blocklist));
+
+        interval
+    }
+
+    async fn download_to_cache(&self, url: &str, cache_path: &Path) -> Result<String, Error> {
+        let list = tokio::time::timeout(DOWNLOAD_TIMEOUT, download(url))
+            .await
+            .map_err(|_| Error::Timeout)??;
+        if let Err(error) = write_cache(cache_path, &list).await {
+            log::warn!(
+                "{}",
+                error.display_chain_with_msg("Failed to cache blocklist")
+            );
+        }
+        Ok(list)
+    }
+
+    fn cache_path(&self, url: &str) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        url.hash(&mut hasher);
+        self.cache_dir.join(format!("{:016x}.txt", hasher.finish()))
+    }
+
+    /// Remove cached lists that are no longer in the settings.
+    async fn remove_unused_cache_files(&self) {
+        let Ok(mut entries) = tokio::fs::read_dir(&self.cache_dir).await else {
+            return;
+        };
+        let in_use: Vec<_> = self
+            .sources
+            .iter()
+            .filter_map(|source| match source {
+                BlocklistSource::Url(url) => Some(self.cache_path(url)),
+                BlocklistSource::File(_) => None,
+            })
+            .collect();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if !in_use.contains(&entry.path()) {
+                let _ = tokio::fs::remove_file(entry.path()).await;
+            }
+        }
+    }
+}
+
+async fn write_cache(path: &Path, list: &str) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(Error::WriteCache)?;
+    }
+    tokio::fs::write(path, list)
+        .await
+        .map_err(Error::WriteCache)
+}
+
+/// Download a list over HTTPS, following redirects within the same host.
+async fn download(url: &str) -> Result<String, Error> {
+    let mut uri: Uri = url.parse().map_err(Error::ParseUrl)?;
+    for _ in 0..=MAX_REDIRECTS {
+        match fetch(&uri).await? {
+            Fetched::Body(list) => return Ok(list),
+            Fetched::Redirect(location) => {
+                log::debug!("Blocklist {url} redirected to {location}");
+                uri = location;
+            }
+        }
+    }
+    Err(Error::TooManyRedirects)
+}
+
+enum Fetched {
+    Body(String),
+    Redirect(Uri),
+}
+
+async fn fetch(uri: &Uri) -> Result<Fetched, Error> {
+    if uri.scheme_str() != Some("https") {
+        return Err(Error::UnsupportedScheme);
+    }
+    let host = uri.host().ok_or(Error::UnsupportedScheme)?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = uri.port_u16().unwrap_or(443);
+
+    let server_name = ServerName::try_from(host).map_err(|_| {
+        Error::Connect(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid hostname \"{host}\""),
+        ))
+    })?;
+    let tls_connector = tls_connector().await?;
+    let stream = TcpStream::connect((host, port))
+        .await
+        .map_err(Error::Connect)?;
+    let stream = tls_connector
+        .connect(server_name, stream)
+        .await
+        .map_err(Error::Connect)?;
+    request(stream, uri).await
+}
+
+async fn request<S>(stream: S, uri: &Uri) -> Result<Fetched, Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = hyper::client::conn::handshake(stream)
+        .await
+        .map_err(Error::Download)?;
+    tokio::spawn(async move {
+        if let Err(error) = connection.await {
+            log::trace!("Blocklist connection closed: {error}");
+        }
+    });
+
+    let authority = uri.authority().map(|authority| authority.as_str());
+    let path = uri
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or("/");
+    let request = Request::get(path)
+        .header(header::HOST, authority.unwrap_or_default())
+        .header(header::USER_AGENT, "mullvad-daemon")
+        .body(Body::empty())
+        .map_err(Error::CreateRequest)?;
+    let response = sender
+        .send_request(request)
+        .await
+        .map_err(Error::Download)?;
+
+    let status = response.status();
+    if status.is_redirection() {
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .and_then(|location| resolve_location(uri, location))
+            .ok_or(Error::InvalidRedirect)?;
+        return Ok(Fetched::Redirect(location));
+    }
+    if !status.is_success() {
+        return Err(Error::ErrorResponse(status));
+    }
+
+    let mut body = response.into_body();
+    let mut list = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(Error::Download)?;
+        if list.len() + chunk.len() > MAX_LIST_SIZE {
+            return Err(Error::TooLarge);
+        }
+        list.extend_from_slice(&chunk);
+    }
+    String::from_utf8(list)
+        .map(Fetched::Body)
+        .map_err(|_| Error::InvalidEncoding)
+}
+
+/// Returns the URI that a `Location` header points to. It may be relative to `uri`. Redirects to
+/// other hosts, or to anything but https, are not followed, since they would let the server send
+/// the daemon somewhere that the user did not choose.
+fn resolve_location(uri: &Uri, location: &str) -> Option<Uri> {
+    let location: Uri = location.parse().ok()?;
+    if location.scheme().is_some() {
+        let same_host =
+            location.scheme_str() == Some("https") && location.authority() == uri.authority();
+        return same_host.then_some(location);
+    }
+    let mut parts = location.into_parts();
+    parts.scheme = uri.scheme().cloned();
+    parts.authority = uri.authority().cloned();
+    Uri::from_parts(parts).ok()
+}
+
+/// Returns a TLS connector that trusts the root certificates of the system.
+async fn tls_connector() -> Result<TlsConnector, Error> {
+    let root_store = root_certificates().await;
+    if root_store.is_empty() {
+        return Err(Error::NoRootCertificates);
+    }
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// Returns the root certificates in the first CA bundle that can be read.
+#[cfg(not(windows))]
+async fn root_certificates() -> rustls::RootCertStore {
+    let mut root_store = rustls::RootCertStore::empty();
+    for path in CA_BUNDLE_PATHS {
+        let Ok(pem) = tokio::fs::read(path).await else {
+            continue;
+        };
+        if let Ok(certs) = rustls_pemfile::certs(&mut &pem[..]) {
+            root_store.add_parsable_certificates(&certs);
+        }
+        if !root_store.is_empty() {
+            break;
+        }
+    }
+    root_store
+}
+
+/// Returns the root certificates trusted by Mozilla, since Windows has no CA bundle file.
+#[cfg(windows)]
+async fn root_certificates() -> rustls::RootCertStore {
+    let mut root_store = rustls::RootCertStore::empty();
+    root_store.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+        rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+            anchor.subject,
+            anchor.spki,
+            anchor.name_constraints,
+        )
+    }));
+    root_store
+}
+
+#[cfg(test)]
+mod test {
+    use super::resolve_location;
+    use hyper::Uri;
+
+    #[test]
+    fn test_resolve_location() {
+        let uri = Uri::from_static("https://example.com/lists/hosts");
+        assert_eq!(
+            resolve_location(&uri, "/v2/hosts"),
+            Some(Uri::from_static("https://example.com/v2/hosts"))
+        );
+        assert_eq!(
+            resolve_location(&uri, "https://example.com/v3/hosts"),
+            Some(Uri::from_static("https://example.com/v3/hosts"))
+        );
+        assert_eq!(resolve_location(&uri, "http://example.com/hosts"), None);
+        assert_eq!(
+            resolve_location(&uri, "https://mirror.example.net/hosts"),
+            None
+        );
+        assert_eq!(
+            resolve_location(&uri, "https://example.com:8443/hosts"),
+            None
+        );
+    }
+}
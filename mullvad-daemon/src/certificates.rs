@@ -0,0 +1,130 @@
+//! Applies certificate updates received through the version check, and keeps them across
+//! restarts.
+
+use mullvad_api::certificates::{CertificateBundle, SignedCertificateUpdate};
+use std::{
+    io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+use talpid_types::ErrorExt;
+use tokio::io::AsyncWriteExt;
+
+const CERTIFICATE_UPDATE_FILENAME: &str = "certificate-update.json";
+const OPENVPN_CA_FILENAME: &str = "openvpn-ca.crt";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Invalid certificate update")]
+    Verify(#[source] mullvad_api::certificates::Error),
+
+    #[error("Failed to read the certificate update cache")]
+    ReadCache(#[source] io::Error),
+
+    #[error("Failed to write the certificate update cache")]
+    WriteCache(#[source] io::Error),
+
+    #[error("Failed to serialize the certificate update")]
+    Serialize(#[source] serde_json::Error),
+
+    #[error("Failed to deserialize the certificate update")]
+    Deserialize(#[source] serde_json::Error),
+}
+
+/// Keeps track of the certificates that are used instead of the bundled ones.
+#[derive(Clone)]
+pub(crate) struct CertificateStore {
+    cache_dir: PathBuf,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Serial of the update in use. The bundled certificates have serial 0.
+    serial: u64,
+    openvpn_ca: Option<PathBuf>,
+}
+
+impl CertificateStore {
+    /// Load and apply the last certificate update, if there is one.
+    pub async fn load(cache_dir: PathBuf) -> Self {
+        let store = CertificateStore {
+            cache_dir,
+            state: Arc::default(),
+        };
+        let result = match store.read_cache().await {
+            Ok(Some(update)) => match update.verify() {
+                Ok(bundle) => store.apply(bundle).await,
+                Err(error) => Err(Error::Verify(error)),
+            },
+            Ok(None) => Ok(()),
+            Err(error) => Err(error),
+        };
+        if let Err(error) = result {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to load certificate update")
+            );
+        }
+        store
+    }
+
+    /// Apply `update` and save it to the cache, unless it is older than the update in use.
+    pub async fn update(&self, update: SignedCertificateUpdate) -> Result<(), Error> {
+        let bundle = update.verify().map_err(Error::Verify)?;
+        if bundle.serial <= self.state.lock().unwrap().serial {
+            return Ok(());
+        }
+        let buf = serde_json::to_vec_pretty(&update).map_err(Error::Serialize)?;
+        write_atomic(self.cache_dir.join(CERTIFICATE_UPDATE_FILENAME), &buf)
+            .await
+            .map_err(Error::WriteCache)?;
+        self.apply(bundle).await
+    }
+
+    /// Path to the CA certificate that OpenVPN relays should be verified with, or `None` if the
+    /// bundled one should be used.
+    pub fn openvpn_ca(&self) -> Option<PathBuf> {
+        self.state.lock().unwrap().openvpn_ca.clone()
+    }
+
+    async fn apply(&self, bundle: CertificateBundle) -> Result<(), Error> {
+        mullvad_api::certificates::set_api_root_certificates(bundle.api_root_certs.as_deref())
+            .map_err(Error::Verify)?;
+
+        let openvpn_ca = match bundle.openvpn_ca {
+            Some(pem) => {
+                // OpenVPN may be reading the file while it is replaced
+                let path = self.cache_dir.join(OPENVPN_CA_FILENAME);
+                write_atomic(path.clone(), pem.as_bytes())
+                    .await
+                    .map_err(Error::WriteCache)?;
+                Some(path)
+            }
+            None => None,
+        };
+
+        let mut state = self.state.lock().unwrap();
+        state.serial = bundle.serial;
+        state.openvpn_ca = openvpn_ca;
+        log::info!("Using certificates from update {}", bundle.serial);
+        Ok(())
+    }
+
+    async fn read_cache(&self) -> Result<Option<SignedCertificateUpdate>, Error> {
+        let path = self.cache_dir.join(CERTIFICATE_UPDATE_FILENAME);
+        match tokio::fs::read(&path).await {
+            Ok(content) => serde_json::from_slice(&content)
+                .map(Some)
+                .map_err(Error::Deserialize),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(Error::ReadCache(error)),
+        }
+    }
+}
+
+async fn write_atomic(path: PathBuf, content: &[u8]) -> io::Result<()> {
+    let mut file = mullvad_fs::AtomicFile::new(path).await?;
+    file.write_all(content).await?;
+    file.finalize().await
+}
@@ -1,5 +1,8 @@
+use crate::daemon_config::DaemonConfig;
 use clap::{Args, Parser};
+use mullvad_daemon::logging::RotationPolicy;
 use once_cell::sync::Lazy;
+use talpid_types::ErrorExt;
 
 static ENV_DESC: Lazy<String> = Lazy::new(|| {
     format!(
@@ -13,13 +16,19 @@ static ENV_DESC: Lazy<String> = Lazy::new(|| {
     MULLVAD_RPC_SOCKET_PATH    Location of the management interface device.
                                It refers to Unix domain socket on Unix based platforms, and named pipe on Windows.
                                [Default: {}]
+    MULLVAD_DAEMON_CONFIG      Location of the daemon config file, which is read before the settings.
+                               [Default: {}]
+    MULLVAD_INSTANCE           Number from 1 to 99 that lets several daemons run side by side. Changes the
+                               defaults above, and on Linux the tunnel interface, firewall mark and routing table.
+                               Only one instance at a time may be connected or block traffic.
 
 ",
         mullvad_paths::get_default_resource_dir().display(),
         mullvad_paths::get_default_settings_dir().map(|dir| dir.display().to_string()).unwrap_or_else(|_| "N/A".to_string()),
         mullvad_paths::get_default_cache_dir().map(|dir| dir.display().to_string()).unwrap_or_else(|_| "N/A".to_string()),
         mullvad_paths::get_default_log_dir().map(|dir| dir.display().to_string()).unwrap_or_else(|_| "N/A".to_string()),
-        mullvad_paths::get_default_rpc_socket_path().display()
+        mullvad_paths::get_default_rpc_socket_path().display(),
+        mullvad_paths::get_default_settings_dir().map(|dir| dir.join("daemon.json").display().to_string()).unwrap_or_else(|_| "N/A".to_string()),
 )
 });
 
@@ -69,6 +78,9 @@ pub struct Config {
     pub log_level: log::LevelFilter,
     pub log_to_file: bool,
     pub log_stdout_timestamps: bool,
+    pub log_rotation: RotationPolicy,
+    /// Enable lockdown mode when the daemon starts
+    pub lockdown_at_boot: bool,
 
     pub command: Command,
 }
@@ -126,9 +138,20 @@ pub fn get_config() -> &'static Config {
 fn create_config() -> Config {
     let app = Cli::parse();
 
-    let log_level = match app.verbosity {
-        0 => log::LevelFilter::Info,
-        1 => log::LevelFilter::Debug,
+    let daemon_config = DaemonConfig::load().unwrap_or_else(|error| {
+        eprintln!("{}", error.display_chain());
+        std::process::exit(1);
+    });
+    daemon_config.set_env_vars();
+    crate::instance::set_env_vars().unwrap_or_else(|error| {
+        eprintln!("{}", error.display_chain());
+        std::process::exit(1);
+    });
+
+    let log_level = match (app.verbosity, daemon_config.log_level) {
+        (0, Some(log_level)) => log_level.into(),
+        (0, None) => log::LevelFilter::Info,
+        (1, _) => log::LevelFilter::Debug,
         _ => log::LevelFilter::Trace,
     };
 
@@ -136,6 +159,8 @@ fn create_config() -> Config {
         log_level,
         log_to_file: !app.disable_log_to_file,
         log_stdout_timestamps: !app.disable_stdout_timestamps,
+        log_rotation: daemon_config.log_rotation.policy(),
+        lockdown_at_boot: daemon_config.lockdown_at_boot,
         command: app.command.into(),
     }
 }
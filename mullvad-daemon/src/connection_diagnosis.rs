@@ -0,0 +1,79 @@
+//! Runs the probes that [`mullvad_types::connection_diagnosis::analyze`] explains a failure to
+//! connect with. The probes are sent outside of any tunnel, so they can only run while the
+//! firewall lets traffic through.
+
+use crate::relay_ping;
+use futures::StreamExt;
+use mullvad_api::{rest, ApiProxy};
+use mullvad_types::{
+    connection_diagnosis::{ApiProbe, ProbeResults},
+    relay_list::RelayList,
+};
+use rand::seq::SliceRandom;
+use std::time::Duration;
+use talpid_types::{net::wireguard::PrivateKey, ErrorExt};
+
+/// Number of relays to send a WireGuard handshake to. UDP is considered blocked if none of them
+/// respond.
+const UDP_PROBE_RELAYS: usize = 5;
+/// Time to wait for the system resolver.
+const DNS_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Run every probe concurrently. The UDP probe is skipped if there is no WireGuard key to
+/// handshake with.
+pub(crate) async fn run(
+    api_handle: rest::MullvadRestHandle,
+    relay_list: RelayList,
+    private_key: Option<PrivateKey>,
+) -> ProbeResults {
+    let (udp_reachable, dns_hijacked, api) = tokio::join!(
+        probe_udp(&relay_list, private_key),
+        probe_dns(),
+        probe_api(api_handle),
+    );
+    ProbeResults {
+        udp_reachable,
+        dns_hijacked,
+        api: Some(api),
+    }
+}
+
+/// Send a WireGuard handshake to a few random relays.
+async fn probe_udp(relay_list: &RelayList, private_key: Option<PrivateKey>) -> Option<bool> {
+    let private_key = private_key?;
+    let mut targets = relay_ping::targets(relay_list, None);
+    if targets.is_empty() {
+        return None;
+    }
+    targets.shuffle(&mut rand::thread_rng());
+    targets.truncate(UDP_PROBE_RELAYS);
+    let reachable = relay_ping::ping(targets, private_key)
+        .any(|latency| async move { latency.latency.is_some() })
+        .await;
+    Some(reachable)
+}
+
+/// Resolve a random hostname under the API domain, which does not exist. Some networks answer
+/// such queries with an address of their own, to redirect the user to a captive portal or a search
+/// page.
+async fn probe_dns() -> Option<bool> {
+    let hostname = format!("{:016x}.{}", rand::random::<u64>(), mullvad_api::API.host());
+    match tokio::time::timeout(DNS_PROBE_TIMEOUT, tokio::net::lookup_host((hostname, 0))).await {
+        Ok(Ok(mut addrs)) => Some(addrs.next().is_some()),
+        // The hostname does not exist, as expected
+        Ok(Err(_)) => Some(false),
+        Err(_) => None,
+    }
+}
+
+async fn probe_api(api_handle: rest::MullvadRestHandle) -> ApiProbe {
+    match ApiProxy::new(api_handle).api_addrs_available().await {
+        // Any response means that the API can be reached
+        Ok(_) | Err(rest::Error::ApiError(..)) => ApiProbe::Reachable,
+        Err(error) if error.is_certificate_time_error() => ApiProbe::CertificateTimeInvalid,
+        Err(error) => {
+            log::debug!("{}", error.display_chain_with_msg("API probe failed"));
+            ApiProbe::Unreachable
+        }
+    }
+}
@@ -45,7 +45,7 @@ where
 
             if self.change_should_cause_reconnect(Some(id)) {
                 log::info!("Initiating tunnel restart because a selected custom list was deleted");
-                self.reconnect_tunnel();
+                self.reconnect_tunnel_after_settings_change();
             }
         }
 
@@ -72,7 +72,7 @@ where
 
             if self.change_should_cause_reconnect(Some(list_id)) {
                 log::info!("Initiating tunnel restart because a selected custom list changed");
-                self.reconnect_tunnel();
+                self.reconnect_tunnel_after_settings_change();
             }
         }
 
@@ -96,7 +96,7 @@ where
 
             if self.change_should_cause_reconnect(None) {
                 log::info!("Initiating tunnel restart because a selected custom list was deleted");
-                self.reconnect_tunnel();
+                self.reconnect_tunnel_after_settings_change();
             }
         }
 
@@ -128,6 +128,7 @@ where
         | TunnelState::Connected {
             endpoint,
             location: _,
+            ..
         } = &self.tunnel_state
         {
             match endpoint.tunnel_type {
@@ -0,0 +1,463 @@
+//! Options that must be known before the settings are loaded, read from a file that only root (or
+//! an administrator) may edit. This lets packagers and administrators preconfigure the daemon
+//! without editing its service definition.
+//!
+//! Most options are passed on as the environment variables that they correspond to. A variable
+//! that is already set takes precedence over the file.
+
+use mullvad_daemon::logging::RotationPolicy;
+use serde::Deserialize;
+use std::{
+    ffi::OsStr,
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+const DAEMON_CONFIG_FILENAME: &str = "daemon.json";
+const DAEMON_CONFIG_PATH_VAR: &str = "MULLVAD_DAEMON_CONFIG";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Failed to find the daemon config directory")]
+    Path(#[source] mullvad_paths::Error),
+
+    #[error("Failed to read daemon config file {0}")]
+    Read(PathBuf, #[source] io::Error),
+
+    #[error("Failed to parse daemon config file {0}")]
+    Parse(PathBuf, #[source] serde_json::Error),
+
+    #[error("Daemon config file {0} must be owned by root and not be writable by other users")]
+    InsecurePermissions(PathBuf),
+}
+
+/// Contents of the daemon config file. Every option is optional.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    /// Log level used unless a verbosity is given on the command line.
+    pub log_level: Option<LogLevel>,
+    pub log_rotation: LogRotationConfig,
+    pub management_socket: ManagementSocketConfig,
+    pub api_override: ApiOverrideConfig,
+    /// Block all traffic when the daemon starts, until the user disables lockdown mode.
+    pub lockdown_at_boot: bool,
+    /// Same as `MULLVAD_ALLOW_CUSTOM_API_ENDPOINT`. Lets the management interface select an API
+    /// endpoint other than the Mullvad API.
+    pub allow_custom_api_endpoint: bool,
+    /// Same as `TALPID_TUNNEL_INTERFACE_NAME`. Name of the WireGuard tunnel interface on Linux,
+    /// where `%d` is replaced by the lowest number that gives an unused name.
+    pub tunnel_interface_name: Option<String>,
+    /// Same as `TALPID_TUNNEL_NETNS`. Network namespace on Linux that the WireGuard tunnel
+    /// interface is moved to, given by name or as an absolute path.
+    pub tunnel_netns: Option<String>,
+    /// Same as `MULLVAD_TUNNEL_FWMARK`. Firewall mark of traffic that bypasses the tunnel on
+    /// Linux. Change it if it is used by another program.
+    pub tunnel_fwmark: Option<u32>,
+    /// Same as `MULLVAD_TUNNEL_TABLE_ID`. Routing table that is used for the tunnel on Linux.
+    pub tunnel_table_id: Option<u32>,
+    /// Same as `TALPID_FIREWALL_CLAMP_MSS`. Clamps the MSS of TCP connections through the tunnel
+    /// on Linux.
+    pub clamp_mss: Option<MssClamp>,
+    /// Same as `MULLVAD_SETTINGS_FSYNC`. Whether to wait for the settings to reach the storage
+    /// device when they are written.
+    pub settings_fsync: Option<bool>,
+    /// Same as `MULLVAD_DEVICE_KEY_PROVIDER`. Either `"software"` or `"tpm"`. The TPM can only be
+    /// used on Windows.
+    pub device_key_provider: Option<String>,
+    pub wireguard_go: WireguardGoConfig,
+    pub openvpn_tls: OpenVpnTlsConfig,
+    pub udp2tcp: Udp2TcpConfig,
+}
+
+/// Either `"pmtu"` or a number of bytes. The value is validated by the firewall.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum MssClamp {
+    Bytes(u16),
+    Other(String),
+}
+
+impl std::fmt::Display for MssClamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MssClamp::Bytes(mss) => mss.fmt(f),
+            MssClamp::Other(value) => f.write_str(value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for log::LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => log::LevelFilter::Error,
+            LogLevel::Warn => log::LevelFilter::Warn,
+            LogLevel::Info => log::LevelFilter::Info,
+            LogLevel::Debug => log::LevelFilter::Debug,
+            LogLevel::Trace => log::LevelFilter::Trace,
+        }
+    }
+}
+
+/// Rotation and retention of the daemon and tunnel logs. Options that are not set keep their
+/// default values.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogRotationConfig {
+    /// Rotate the daemon log once it has grown to this many MiB. 0 disables rotation by size.
+    pub max_size_mib: Option<u64>,
+    /// Rotate the daemon log after this many hours. 0 disables rotation by time.
+    pub max_age_hours: Option<u64>,
+    /// Number of rotated logs to keep of each log, besides the most recent one.
+    pub keep: Option<usize>,
+    /// Delete rotated logs that are older than this many days. 0 keeps them regardless of age.
+    pub keep_days: Option<u64>,
+    /// Compress rotated logs with gzip, except for the most recent one.
+    pub compress: Option<bool>,
+}
+
+impl LogRotationConfig {
+    pub fn policy(&self) -> RotationPolicy {
+        const HOUR: u64 = 60 * 60;
+        let default = RotationPolicy::default();
+        let nonzero = |value: Option<u64>, default| match value {
+            Some(0) => None,
+            Some(value) => Some(value),
+            None => default,
+        };
+        RotationPolicy {
+            max_size: nonzero(
+                self.max_size_mib.map(|mib| mib * 1024 * 1024),
+                default.max_size,
+            ),
+            max_age: nonzero(
+                self.max_age_hours.map(|hours| hours * HOUR),
+                default.max_age.map(|age| age.as_secs()),
+            )
+            .map(Duration::from_secs),
+            keep: self.keep.unwrap_or(default.keep),
+            keep_for: nonzero(
+                self.keep_days.map(|days| days * 24 * HOUR),
+                default.keep_for.map(|age| age.as_secs()),
+            )
+            .map(Duration::from_secs),
+            compress: self.compress.unwrap_or(default.compress),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ManagementSocketConfig {
+    /// Same as `MULLVAD_RPC_SOCKET_PATH`.
+    pub path: Option<PathBuf>,
+    /// Same as `MULLVAD_MANAGEMENT_SOCKET_GROUP`. Only members of this group may use the socket.
+    pub group: Option<String>,
+}
+
+/// Only has an effect in builds with the `api-override` feature.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiOverrideConfig {
+    /// Same as `MULLVAD_API_HOST`.
+    pub host: Option<String>,
+    /// Same as `MULLVAD_API_ADDR`.
+    pub address: Option<String>,
+}
+
+/// Tuning of the userspace WireGuard implementation on Linux, for links that a single thread
+/// cannot keep up with.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WireguardGoConfig {
+    /// Same as `TALPID_WIREGUARD_GO_QUEUES`.
+    pub queues: Option<u16>,
+    /// Same as `TALPID_WIREGUARD_GO_THREADS`.
+    pub threads: Option<u16>,
+    /// Same as `TALPID_WIREGUARD_GO_CPUS`. A list of CPUs such as `"0-3,8"`.
+    pub cpus: Option<String>,
+}
+
+/// TLS policy of the OpenVPN control channel. Only meant for servers that do not support the
+/// default policy. Values are validated by `talpid-openvpn`, which ignores unsupported ones.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct OpenVpnTlsConfig {
+    /// Same as `TALPID_OPENVPN_TLS_VERSION_MIN`. Either `"1.2"` or `"1.3"`.
+    pub version_min: Option<String>,
+    /// Same as `TALPID_OPENVPN_TLS_CIPHERSUITES`.
+    pub ciphersuites: Option<Vec<String>>,
+}
+
+/// Options of the TCP connection of the udp2tcp obfuscation. Only meant for links where the
+/// defaults perform badly, such as long-distance links.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Udp2TcpConfig {
+    /// Same as `TALPID_UDP2TCP_NODELAY`. Enabled by default.
+    pub nodelay: Option<bool>,
+    /// Same as `TALPID_UDP2TCP_CONGESTION`. Congestion control algorithm on Linux, such as
+    /// `"bbr"`.
+    pub congestion: Option<String>,
+    /// Same as `TALPID_UDP2TCP_RECV_BUFFER`. In bytes.
+    pub recv_buffer_size: Option<u32>,
+    /// Same as `TALPID_UDP2TCP_SEND_BUFFER`. In bytes.
+    pub send_buffer_size: Option<u32>,
+}
+
+impl DaemonConfig {
+    /// Read the daemon config file, if it exists.
+    pub fn load() -> Result<Self, Error> {
+        let path = config_path()?;
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(error) => return Err(Error::Read(path, error)),
+        };
+        check_permissions(&path)?;
+        serde_json::from_str(&contents).map_err(|error| Error::Parse(path, error))
+    }
+
+    /// Export the options that correspond to environment variables, unless the variables are
+    /// already set. This must be done before any other threads are started.
+    pub fn set_env_vars(&self) {
+        let tunnel_fwmark = self.tunnel_fwmark.map(|mark| mark.to_string());
+        let tunnel_table_id = self.tunnel_table_id.map(|id| id.to_string());
+        let clamp_mss = self.clamp_mss.as_ref().map(|clamp| clamp.to_string());
+        let wireguard_go_queues = self.wireguard_go.queues.map(|queues| queues.to_string());
+        let wireguard_go_threads = self.wireguard_go.threads.map(|threads| threads.to_string());
+        let openvpn_tls_ciphersuites = self
+            .openvpn_tls
+            .ciphersuites
+            .as_ref()
+            .map(|ciphersuites| ciphersuites.join(":"));
+        let udp2tcp_recv_buffer = self.udp2tcp.recv_buffer_size.map(|size| size.to_string());
+        let udp2tcp_send_buffer = self.udp2tcp.send_buffer_size.map(|size| size.to_string());
+        let vars = [
+            (
+                "MULLVAD_RPC_SOCKET_PATH",
+                self.management_socket.path.as_deref().map(Path::as_os_str),
+            ),
+            (
+                "MULLVAD_MANAGEMENT_SOCKET_GROUP",
+                self.management_socket.group.as_deref().map(OsStr::new),
+            ),
+            (
+                "MULLVAD_ALLOW_CUSTOM_API_ENDPOINT",
+                self.allow_custom_api_endpoint.then_some(OsStr::new("1")),
+            ),
+            (
+                "TALPID_TUNNEL_INTERFACE_NAME",
+                self.tunnel_interface_name.as_deref().map(OsStr::new),
+            ),
+            (
+                "TALPID_TUNNEL_NETNS",
+                self.tunnel_netns.as_deref().map(OsStr::new),
+            ),
+            (
+                "MULLVAD_TUNNEL_FWMARK",
+                tunnel_fwmark.as_deref().map(OsStr::new),
+            ),
+            (
+                "MULLVAD_TUNNEL_TABLE_ID",
+                tunnel_table_id.as_deref().map(OsStr::new),
+            ),
+            (
+                "TALPID_FIREWALL_CLAMP_MSS",
+                clamp_mss.as_deref().map(OsStr::new),
+            ),
+            (
+                "TALPID_WIREGUARD_GO_QUEUES",
+                wireguard_go_queues.as_deref().map(OsStr::new),
+            ),
+            (
+                "TALPID_WIREGUARD_GO_THREADS",
+                wireguard_go_threads.as_deref().map(OsStr::new),
+            ),
+            (
+                "TALPID_WIREGUARD_GO_CPUS",
+                self.wireguard_go.cpus.as_deref().map(OsStr::new),
+            ),
+            (
+                "TALPID_OPENVPN_TLS_VERSION_MIN",
+                self.openvpn_tls.version_min.as_deref().map(OsStr::new),
+            ),
+            (
+                "TALPID_OPENVPN_TLS_CIPHERSUITES",
+                openvpn_tls_ciphersuites.as_deref().map(OsStr::new),
+            ),
+            (
+                "TALPID_UDP2TCP_NODELAY",
+                self.udp2tcp
+                    .nodelay
+                    .map(|nodelay| OsStr::new(if nodelay { "1" } else { "0" })),
+            ),
+            (
+                "TALPID_UDP2TCP_CONGESTION",
+                self.udp2tcp.congestion.as_deref().map(OsStr::new),
+            ),
+            (
+                "TALPID_UDP2TCP_RECV_BUFFER",
+                udp2tcp_recv_buffer.as_deref().map(OsStr::new),
+            ),
+            (
+                "TALPID_UDP2TCP_SEND_BUFFER",
+                udp2tcp_send_buffer.as_deref().map(OsStr::new),
+            ),
+            (
+                "MULLVAD_SETTINGS_FSYNC",
+                self.settings_fsync
+                    .map(|fsync| OsStr::new(if fsync { "1" } else { "0" })),
+            ),
+            (
+                "MULLVAD_DEVICE_KEY_PROVIDER",
+                self.device_key_provider.as_deref().map(OsStr::new),
+            ),
+            (
+                "MULLVAD_API_HOST",
+                self.api_override.host.as_deref().map(OsStr::new),
+            ),
+            (
+                "MULLVAD_API_ADDR",
+                self.api_override.address.as_deref().map(OsStr::new),
+            ),
+        ];
+        for (key, value) in vars {
+            if let Some(value) = value.filter(|_| std::env::var_os(key).is_none()) {
+                std::env::set_var(key, value);
+            }
+        }
+    }
+}
+
+fn config_path() -> Result<PathBuf, Error> {
+    match std::env::var_os(DAEMON_CONFIG_PATH_VAR) {
+        Some(path) => Ok(PathBuf::from(path)),
+        None => mullvad_paths::get_default_settings_dir()
+            .map(|dir| dir.join(DAEMON_CONFIG_FILENAME))
+            .map_err(Error::Path),
+    }
+}
+
+#[cfg(unix)]
+fn check_permissions(path: &Path) -> Result<(), Error> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::metadata(path).map_err(|error| Error::Read(path.to_owned(), error))?;
+    if metadata.uid() != 0 || metadata.mode() & 0o022 != 0 {
+        return Err(Error::InsecurePermissions(path.to_owned()));
+    }
+    Ok(())
+}
+
+/// The default location is only writable by administrators on Windows.
+#[cfg(windows)]
+fn check_permissions(_path: &Path) -> Result<(), Error> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config: DaemonConfig = serde_json::from_str(
+            r#"{
+                "log_level": "debug",
+                "log_rotation": { "max_size_mib": 5, "keep": 2 },
+                "management_socket": { "group": "mullvad" },
+                "lockdown_at_boot": true,
+                "tunnel_table_id": 1000,
+                "clamp_mss": 1360,
+                "settings_fsync": false,
+                "device_key_provider": "tpm",
+                "wireguard_go": { "queues": 4, "cpus": "0-3" },
+                "openvpn_tls": { "version_min": "1.2" },
+                "udp2tcp": { "congestion": "bbr" }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config,
+            DaemonConfig {
+                log_level: Some(LogLevel::Debug),
+                log_rotation: LogRotationConfig {
+                    max_size_mib: Some(5),
+                    keep: Some(2),
+                    ..LogRotationConfig::default()
+                },
+                management_socket: ManagementSocketConfig {
+                    path: None,
+                    group: Some("mullvad".to_owned()),
+                },
+                api_override: ApiOverrideConfig::default(),
+                lockdown_at_boot: true,
+                allow_custom_api_endpoint: false,
+                tunnel_interface_name: None,
+                tunnel_netns: None,
+                tunnel_fwmark: None,
+                tunnel_table_id: Some(1000),
+                clamp_mss: Some(MssClamp::Bytes(1360)),
+                settings_fsync: Some(false),
+                device_key_provider: Some("tpm".to_owned()),
+                wireguard_go: WireguardGoConfig {
+                    queues: Some(4),
+                    threads: None,
+                    cpus: Some("0-3".to_owned()),
+                },
+                openvpn_tls: OpenVpnTlsConfig {
+                    version_min: Some("1.2".to_owned()),
+                    ciphersuites: None,
+                },
+                udp2tcp: Udp2TcpConfig {
+                    congestion: Some("bbr".to_owned()),
+                    ..Udp2TcpConfig::default()
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_log_rotation_policy() {
+        assert_eq!(
+            LogRotationConfig::default().policy(),
+            RotationPolicy::default()
+        );
+
+        let config = LogRotationConfig {
+            max_size_mib: Some(0),
+            max_age_hours: Some(24),
+            keep: Some(1),
+            keep_days: Some(7),
+            compress: Some(false),
+        };
+        assert_eq!(
+            config.policy(),
+            RotationPolicy {
+                max_size: None,
+                max_age: Some(Duration::from_secs(24 * 60 * 60)),
+                keep: 1,
+                keep_for: Some(Duration::from_secs(7 * 24 * 60 * 60)),
+                compress: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_reject_unknown_options() {
+        assert!(serde_json::from_str::<DaemonConfig>(r#"{ "log_levle": "debug" }"#).is_err());
+    }
+}
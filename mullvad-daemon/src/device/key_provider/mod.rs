@@ -0,0 +1,235 @@
+//! Generation of the private key of the device, and how it is stored in the device cache.
+//!
+//! WireGuard implementations are given the private key itself whenever a tunnel is set up, so the
+//! key can't be kept inside a TPM or a secure enclave, and the Curve25519 operations can't be
+//! delegated to one either. What a [`KeyProvider`] can do is seal the key in the device cache with
+//! a key that never leaves the hardware, so that a copy of the cache is useless on any other
+//! machine.
+//!
+//! The provider is selected with `MULLVAD_DEVICE_KEY_PROVIDER`. A key that was sealed by one
+//! provider is unsealed by that provider, even after switching to another one, and is sealed by
+//! the new provider the next time the device cache is written.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{fmt, str::FromStr, sync::Arc};
+use talpid_types::net::wireguard::PrivateKey;
+
+#[cfg(windows)]
+mod tpm;
+
+/// Location of the private key in a serialized [`super::PrivateDeviceState`].
+const PRIVATE_KEY_POINTER: &str = "/logged_in/device/wg_data/private_key";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("The {0} key provider is not available on this platform")]
+    Unavailable(KeyProviderKind),
+
+    #[error("The stored device key is malformed")]
+    Malformed,
+
+    #[cfg(windows)]
+    #[error("The TPM failed to seal or unseal the device key")]
+    Tpm(#[source] std::io::Error),
+}
+
+/// Identifies a [`KeyProvider`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyProviderKind {
+    /// The key is stored as is. It is protected by the permissions of the device cache only.
+    #[default]
+    Software,
+    /// The key is sealed with an RSA key that is held by the TPM. Only available on Windows.
+    Tpm,
+}
+
+impl fmt::Display for KeyProviderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyProviderKind::Software => f.write_str("software"),
+            KeyProviderKind::Tpm => f.write_str("tpm"),
+        }
+    }
+}
+
+impl FromStr for KeyProviderKind {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "software" => Ok(KeyProviderKind::Software),
+            "tpm" => Ok(KeyProviderKind::Tpm),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A private key as it is stored in the device cache by a provider that seals it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealedKey {
+    pub provider: KeyProviderKind,
+    /// Hex encoded output of the provider.
+    pub sealed: String,
+}
+
+/// Generates the private key of the device and protects it at rest.
+pub trait KeyProvider: Send + Sync {
+    fn kind(&self) -> KeyProviderKind;
+
+    /// Generate a new private key for the device.
+    fn generate(&self) -> PrivateKey {
+        PrivateKey::new_from_random()
+    }
+
+    /// Seal `key` so that only this provider can unseal it, or return `None` if the key should be
+    /// stored as is.
+    fn seal(&self, key: &PrivateKey) -> Result<Option<SealedKey>, Error>;
+
+    /// Unseal a key that was sealed by [`KeyProvider::seal`].
+    fn unseal(&self, sealed: &SealedKey) -> Result<PrivateKey, Error>;
+}
+
+/// Stores keys as is.
+pub struct SoftwareKeyProvider;
+
+impl KeyProvider for SoftwareKeyProvider {
+    fn kind(&self) -> KeyProviderKind {
+        KeyProviderKind::Software
+    }
+
+    fn seal(&self, _key: &PrivateKey) -> Result<Option<SealedKey>, Error> {
+        Ok(None)
+    }
+
+    fn unseal(&self, _sealed: &SealedKey) -> Result<PrivateKey, Error> {
+        Err(Error::Malformed)
+    }
+}
+
+/// Return the provider identified by `kind`.
+pub fn provider(kind: KeyProviderKind) -> Result<Arc<dyn KeyProvider>, Error> {
+    match kind {
+        KeyProviderKind::Software => Ok(Arc::new(SoftwareKeyProvider)),
+        #[cfg(windows)]
+        KeyProviderKind::Tpm => Ok(Arc::new(tpm::TpmKeyProvider)),
+        #[cfg(not(windows))]
+        KeyProviderKind::Tpm => Err(Error::Unavailable(kind)),
+    }
+}
+
+/// Return the provider that is selected by `MULLVAD_DEVICE_KEY_PROVIDER`. The software provider
+/// is used if the selected one is not available.
+pub fn from_config() -> Arc<dyn KeyProvider> {
+    let kind = crate::runtime_config::get().device_key_provider;
+    provider(kind).unwrap_or_else(|error| {
+        log::error!("{error}. Storing the device key without sealing it");
+        Arc::new(SoftwareKeyProvider)
+    })
+}
+
+/// Replace the private key in `state`, a serialized device state, by the key sealed by
+/// `provider`. Nothing is done if there is no key.
+pub fn seal_device_state(provider: &dyn KeyProvider, state: &mut Value) -> Result<(), Error> {
+    let Some(stored) = state.pointer_mut(PRIVATE_KEY_POINTER) else {
+        return Ok(());
+    };
+    let key: PrivateKey = serde_json::from_value(stored.clone()).map_err(|_| Error::Malformed)?;
+    if let Some(sealed) = provider.seal(&key)? {
+        *stored = serde_json::to_value(sealed).map_err(|_| Error::Malformed)?;
+    }
+    Ok(())
+}
+
+/// Replace a sealed private key in `state`, a serialized device state, by the key itself. Returns
+/// the provider that the key was stored by, or `None` if there is no key.
+pub fn unseal_device_state(state: &mut Value) -> Result<Option<KeyProviderKind>, Error> {
+    let Some(stored) = state.pointer_mut(PRIVATE_KEY_POINTER) else {
+        return Ok(None);
+    };
+    if stored.is_string() {
+        return Ok(Some(KeyProviderKind::Software));
+    }
+    let sealed: SealedKey = serde_json::from_value(stored.clone()).map_err(|_| Error::Malformed)?;
+    let key = provider(sealed.provider)?.unseal(&sealed)?;
+    *stored = serde_json::to_value(&key).map_err(|_| Error::Malformed)?;
+    Ok(Some(sealed.provider))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Stands in for a hardware provider. The key is only reversed, which is enough to tell
+    /// whether it was sealed.
+    struct ReversingKeyProvider;
+
+    impl KeyProvider for ReversingKeyProvider {
+        fn kind(&self) -> KeyProviderKind {
+            KeyProviderKind::Tpm
+        }
+
+        fn seal(&self, key: &PrivateKey) -> Result<Option<SealedKey>, Error> {
+            let mut bytes = key.to_bytes();
+            bytes.reverse();
+            Ok(Some(SealedKey {
+                provider: KeyProviderKind::Tpm,
+                sealed: hex::encode(bytes),
+            }))
+        }
+
+        fn unseal(&self, _sealed: &SealedKey) -> Result<PrivateKey, Error> {
+            unreachable!()
+        }
+    }
+
+    fn device_state(key: &PrivateKey) -> Value {
+        serde_json::json!({
+            "logged_in": {
+                "account_token": "1234",
+                "device": {
+                    "wg_data": {
+                        "private_key": key,
+                    },
+                },
+            },
+        })
+    }
+
+    #[test]
+    fn test_seal_device_state() {
+        let key = PrivateKey::new_from_random();
+
+        let mut state = device_state(&key);
+        seal_device_state(&SoftwareKeyProvider, &mut state).unwrap();
+        assert_eq!(state, device_state(&key));
+        assert_eq!(
+            unseal_device_state(&mut state).unwrap(),
+            Some(KeyProviderKind::Software)
+        );
+        assert_eq!(state, device_state(&key));
+
+        seal_device_state(&ReversingKeyProvider, &mut state).unwrap();
+        let sealed: SealedKey =
+            serde_json::from_value(state.pointer(PRIVATE_KEY_POINTER).unwrap().clone()).unwrap();
+        assert_eq!(sealed.provider, KeyProviderKind::Tpm);
+        assert_ne!(sealed.sealed, hex::encode(key.to_bytes()));
+
+        let mut logged_out = serde_json::json!("logged_out");
+        seal_device_state(&ReversingKeyProvider, &mut logged_out).unwrap();
+        assert_eq!(unseal_device_state(&mut logged_out).unwrap(), None);
+        assert_eq!(logged_out, serde_json::json!("logged_out"));
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_unseal_unavailable_provider() {
+        let mut state = device_state(&PrivateKey::new_from_random());
+        seal_device_state(&ReversingKeyProvider, &mut state).unwrap();
+        assert!(matches!(
+            unseal_device_state(&mut state),
+            Err(Error::Unavailable(KeyProviderKind::Tpm))
+        ));
+    }
+}
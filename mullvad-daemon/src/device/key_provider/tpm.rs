@@ -0,0 +1,154 @@
+//! Seals the device key with an RSA key that is created by and held in the TPM, through the
+//! Microsoft Platform Crypto Provider.
+
+use super::{Error, KeyProvider, KeyProviderKind, SealedKey};
+use std::{io, ptr};
+use talpid_types::net::wireguard::PrivateKey;
+use windows_sys::{
+    core::{w, HRESULT, PCWSTR},
+    Win32::{
+        Foundation::NTE_BAD_KEYSET,
+        Security::Cryptography::{
+            NCryptCreatePersistedKey, NCryptDecrypt, NCryptEncrypt, NCryptFinalizeKey,
+            NCryptFreeObject, NCryptOpenKey, NCryptOpenStorageProvider, BCRYPT_OAEP_PADDING_INFO,
+            BCRYPT_RSA_ALGORITHM, BCRYPT_SHA1_ALGORITHM, MS_PLATFORM_CRYPTO_PROVIDER, NCRYPT_FLAGS,
+            NCRYPT_HANDLE, NCRYPT_KEY_HANDLE, NCRYPT_MACHINE_KEY_FLAG, NCRYPT_PAD_OAEP_FLAG,
+            NCRYPT_SILENT_FLAG,
+        },
+    },
+};
+
+/// Name of the persisted TPM key. It is a machine key, since the daemon runs as SYSTEM.
+const KEY_NAME: PCWSTR = w!("Mullvad VPN device key");
+
+pub struct TpmKeyProvider;
+
+impl KeyProvider for TpmKeyProvider {
+    fn kind(&self) -> KeyProviderKind {
+        KeyProviderKind::Tpm
+    }
+
+    fn seal(&self, key: &PrivateKey) -> Result<Option<SealedKey>, Error> {
+        let tpm_key = open_or_create_key().map_err(Error::Tpm)?;
+        let sealed = crypt(NCryptEncrypt, &tpm_key, &key.to_bytes()).map_err(Error::Tpm)?;
+        Ok(Some(SealedKey {
+            provider: KeyProviderKind::Tpm,
+            sealed: hex::encode(sealed),
+        }))
+    }
+
+    fn unseal(&self, sealed: &SealedKey) -> Result<PrivateKey, Error> {
+        let sealed = hex::decode(&sealed.sealed).map_err(|_| Error::Malformed)?;
+        let tpm_key = open_or_create_key().map_err(Error::Tpm)?;
+        let key = crypt(NCryptDecrypt, &tpm_key, &sealed).map_err(Error::Tpm)?;
+        let key = <[u8; 32]>::try_from(key.as_slice()).map_err(|_| Error::Malformed)?;
+        Ok(PrivateKey::from(key))
+    }
+}
+
+/// An NCrypt provider or key handle, which is freed on drop.
+struct Handle(NCRYPT_HANDLE);
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        // SAFETY: The handle was returned by NCrypt and is not used after this
+        unsafe { NCryptFreeObject(self.0) };
+    }
+}
+
+fn open_or_create_key() -> io::Result<Handle> {
+    let mut provider = 0;
+    // SAFETY: `provider` is valid for writes and the provider name is a null-terminated string
+    check(unsafe { NCryptOpenStorageProvider(&mut provider, MS_PLATFORM_CRYPTO_PROVIDER, 0) })?;
+    let provider = Handle(provider);
+
+    let flags = NCRYPT_MACHINE_KEY_FLAG | NCRYPT_SILENT_FLAG;
+    let mut key: NCRYPT_KEY_HANDLE = 0;
+    // SAFETY: `key` is valid for writes and the key name is a null-terminated string
+    let result = unsafe { NCryptOpenKey(provider.0, &mut key, KEY_NAME, 0, flags) };
+    if result != NTE_BAD_KEYSET {
+        check(result)?;
+        return Ok(Handle(key));
+    }
+
+    log::debug!("Creating the device key sealing key in the TPM");
+    // SAFETY: `key` is valid for writes and the algorithm and key names are null-terminated
+    // strings
+    check(unsafe {
+        NCryptCreatePersistedKey(
+            provider.0,
+            &mut key,
+            BCRYPT_RSA_ALGORITHM,
+            KEY_NAME,
+            0,
+            flags,
+        )
+    })?;
+    let key = Handle(key);
+    // SAFETY: `key` is a key handle that has not been finalized
+    check(unsafe { NCryptFinalizeKey(key.0, NCRYPT_SILENT_FLAG) })?;
+    Ok(key)
+}
+
+type CryptFn = unsafe extern "system" fn(
+    NCRYPT_KEY_HANDLE,
+    *const u8,
+    u32,
+    *const std::ffi::c_void,
+    *mut u8,
+    u32,
+    *mut u32,
+    NCRYPT_FLAGS,
+) -> HRESULT;
+
+/// Encrypt or decrypt `input` with RSA-OAEP, using `function`.
+fn crypt(function: CryptFn, key: &Handle, input: &[u8]) -> io::Result<Vec<u8>> {
+    let padding = BCRYPT_OAEP_PADDING_INFO {
+        pszAlgId: BCRYPT_SHA1_ALGORITHM,
+        pbLabel: ptr::null_mut(),
+        cbLabel: 0,
+    };
+    let padding_ptr = &padding as *const _ as *const _;
+    let flags = NCRYPT_PAD_OAEP_FLAG | NCRYPT_SILENT_FLAG;
+    let input_len = u32::try_from(input.len()).unwrap();
+
+    let mut output_len = 0;
+    // SAFETY: A null output buffer makes the function return the required size in `output_len`
+    check(unsafe {
+        function(
+            key.0,
+            input.as_ptr(),
+            input_len,
+            padding_ptr,
+            ptr::null_mut(),
+            0,
+            &mut output_len,
+            flags,
+        )
+    })?;
+
+    let mut output = vec![0u8; usize::try_from(output_len).unwrap()];
+    // SAFETY: `output` is valid for writes of `output_len` bytes
+    check(unsafe {
+        function(
+            key.0,
+            input.as_ptr(),
+            input_len,
+            padding_ptr,
+            output.as_mut_ptr(),
+            output_len,
+            &mut output_len,
+            flags,
+        )
+    })?;
+    output.truncate(usize::try_from(output_len).unwrap());
+    Ok(output)
+}
+
+fn check(result: HRESULT) -> io::Result<()> {
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::from_raw_os_error(result))
+    }
+}
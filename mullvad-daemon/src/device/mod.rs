@@ -36,9 +36,12 @@ use tokio::{
 };
 
 mod api;
+pub(crate) mod key_provider;
 mod service;
 pub(crate) use service::{AccountService, DeviceService};
 
+use key_provider::{KeyProvider, KeyProviderKind};
+
 /// File that used to store account and device data.
 const DEVICE_CACHE_FILENAME: &str = "device.json";
 
@@ -70,6 +73,8 @@ pub enum Error {
     DeviceIoError(#[from] Arc<io::Error>),
     #[error("Failed parse device cache")]
     ParseDeviceCache(#[from] Arc<serde_json::Error>),
+    #[error("Failed to seal or unseal the device key")]
+    KeyProvider(#[from] Arc<key_provider::Error>),
     #[error("Unexpected HTTP request error")]
     OtherRestError(#[from] rest::Error),
     #[error("The device update task is not running")]
@@ -92,6 +97,7 @@ macro_rules! impl_into_arc_err {
 
 impl_into_arc_err!(io::Error);
 impl_into_arc_err!(serde_json::Error);
+impl_into_arc_err!(key_provider::Error);
 
 /// Contains the current device state.
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize, PartialEq, Eq)]
@@ -165,6 +171,8 @@ impl From<PrivateAccountAndDevice> for AccountAndDevice {
     fn from(config: PrivateAccountAndDevice) -> Self {
         AccountAndDevice {
             account_token: config.account_token,
+            key_created: config.device.wg_data.created,
+            tunnel_addresses: config.device.wg_data.addresses.clone(),
             device: Device::from(config.device),
         }
     }
@@ -423,7 +431,8 @@ impl AccountManager {
         initial_rotation_interval: RotationInterval,
         listener_tx: impl Sender<AccountEvent> + Send + 'static,
     ) -> Result<(AccountManagerHandle, PrivateDeviceState), Error> {
-        let (cacher, data) = DeviceCacher::new(settings_dir).await?;
+        let key_provider = key_provider::from_config();
+        let (cacher, data) = DeviceCacher::new(settings_dir, key_provider.clone()).await?;
         let token = data.device().map(|state| state.account_token.clone());
         let api_availability = rest_handle.availability.clone();
         let account_service =
@@ -431,7 +440,7 @@ impl AccountManager {
 
         let (cmd_tx, cmd_rx) = mpsc::unbounded();
 
-        let device_service = DeviceService::new(rest_handle, api_availability);
+        let device_service = DeviceService::new(rest_handle, api_availability, key_provider);
         let manager = AccountManager {
             cacher,
             account_service: account_service.clone(),
@@ -1145,10 +1154,14 @@ impl AccountManager {
 pub struct DeviceCacher {
     file: io::BufWriter<fs::File>,
     path: std::path::PathBuf,
+    key_provider: Arc<dyn KeyProvider>,
 }
 
 impl DeviceCacher {
-    pub async fn new(settings_dir: &Path) -> Result<(DeviceCacher, PrivateDeviceState), Error> {
+    pub async fn new(
+        settings_dir: &Path,
+        key_provider: Arc<dyn KeyProvider>,
+    ) -> Result<(DeviceCacher, PrivateDeviceState), Error> {
         let path = settings_dir.join(DEVICE_CACHE_FILENAME);
         let cache_exists = path.is_file();
         let mut should_save = false;
@@ -1165,14 +1178,21 @@ impl DeviceCacher {
             let mut buffer = String::new();
             reader.read_to_string(&mut buffer).await?;
             if !buffer.is_empty() {
-                serde_json::from_str(&buffer).unwrap_or_else(|error| {
-                    should_save = true;
-                    log::error!(
-                        "{}",
-                        error.display_chain_with_msg("Wiping device config due to an error")
-                    );
-                    PrivateDeviceState::LoggedOut
-                })
+                Self::parse(buffer, key_provider.kind())
+                    .await
+                    .map(|(device, is_sealed_by_provider)| {
+                        // Reseal the key if another provider has been selected
+                        should_save = !is_sealed_by_provider;
+                        device
+                    })
+                    .unwrap_or_else(|error| {
+                        should_save = true;
+                        log::error!(
+                            "{}",
+                            error.display_chain_with_msg("Wiping device config due to an error")
+                        );
+                        PrivateDeviceState::LoggedOut
+                    })
             } else {
                 should_save = true;
                 PrivateDeviceState::LoggedOut
@@ -1185,6 +1205,7 @@ impl DeviceCacher {
         let mut store = DeviceCacher {
             file: io::BufWriter::new(file),
             path,
+            key_provider,
         };
 
         if should_save {
@@ -1194,6 +1215,23 @@ impl DeviceCacher {
         Ok((store, device))
     }
 
+    /// Parse the device cache, unsealing the private key. Also returns whether the key is stored
+    /// by the provider of kind `kind`. Unsealing may block on hardware, so it is done in a blocking
+    /// task.
+    async fn parse(
+        buffer: String,
+        kind: KeyProviderKind,
+    ) -> Result<(PrivateDeviceState, bool), Error> {
+        tokio::task::spawn_blocking(move || {
+            let mut value: serde_json::Value = serde_json::from_str(&buffer)?;
+            let stored_by = key_provider::unseal_device_state(&mut value)?;
+            let device = serde_json::from_value(value)?;
+            Ok((device, stored_by.unwrap_or(kind) == kind))
+        })
+        .await
+        .map_err(|_| Error::Cancelled)?
+    }
+
     fn file_options() -> std::fs::OpenOptions {
         let mut options = std::fs::OpenOptions::new();
         #[cfg(unix)]
@@ -1211,7 +1249,15 @@ impl DeviceCacher {
     }
 
     pub async fn write(&mut self, device: &PrivateDeviceState) -> Result<(), Error> {
-        let data = serde_json::to_vec_pretty(&device).unwrap();
+        let mut value = serde_json::to_value(device)?;
+        let provider = self.key_provider.clone();
+        // Sealing may block on hardware
+        let value = tokio::task::spawn_blocking(move || {
+            key_provider::seal_device_state(&*provider, &mut value).map(|()| value)
+        })
+        .await
+        .map_err(|_| Error::Cancelled)??;
+        let data = serde_json::to_vec_pretty(&value).unwrap();
 
         self.file.get_mut().set_len(0).await?;
         self.file.seek(io::SeekFrom::Start(0)).await?;
@@ -1224,7 +1270,7 @@ impl DeviceCacher {
 
     pub async fn remove(self) -> Result<(), Error> {
         let path = {
-            let DeviceCacher { path, file } = self;
+            let DeviceCacher { path, file, .. } = self;
             let std_file = file.into_inner().into_std().await;
             let _ = tokio::task::spawn_blocking(move || drop(std_file)).await;
             path
@@ -1317,7 +1363,7 @@ impl TunnelStateChangeHandler {
                 retry_attempt.wrapping_add(1)
             }
             // Only reset the counter if we managed to connect to a Wireguard relay
-            TunnelStateTransition::Connected(endpoint) if wireguard(endpoint) => 0,
+            TunnelStateTransition::Connected(endpoint, _) if wireguard(endpoint) => 0,
             // Any other state transition doesn't affect the counter
             _ => retry_attempt,
         }
@@ -1332,7 +1378,7 @@ impl TunnelStateChangeHandler {
     fn update_retry_bool(new_state: &TunnelStateTransition, can_retry: Arc<AtomicBool>) {
         match new_state {
             TunnelStateTransition::Disconnected { .. }
-            | TunnelStateTransition::Connected(_)
+            | TunnelStateTransition::Connected(..)
             | TunnelStateTransition::Error(_) => {
                 can_retry.store(true, Ordering::SeqCst);
             }
@@ -1,4 +1,4 @@
-use std::{future::Future, time::Duration};
+use std::{future::Future, sync::Arc, time::Duration};
 
 use chrono::Utc;
 use futures::future::{abortable, AbortHandle};
@@ -9,9 +9,8 @@ use mullvad_types::{
     device::{Device, DeviceId},
     wireguard::WireguardData,
 };
-use talpid_types::net::wireguard::PrivateKey;
 
-use super::{Error, PrivateAccountAndDevice, PrivateDevice};
+use super::{key_provider::KeyProvider, Error, PrivateAccountAndDevice, PrivateDevice};
 use mullvad_api::{
     availability::ApiAvailabilityHandle,
     rest::{self, MullvadRestHandle},
@@ -30,13 +29,19 @@ const RETRY_BACKOFF_STRATEGY: Jittered<ExponentialBackoff> = Jittered::jitter(
 pub struct DeviceService {
     api_availability: ApiAvailabilityHandle,
     proxy: DevicesProxy,
+    key_provider: Arc<dyn KeyProvider>,
 }
 
 impl DeviceService {
-    pub fn new(handle: rest::MullvadRestHandle, api_availability: ApiAvailabilityHandle) -> Self {
+    pub fn new(
+        handle: rest::MullvadRestHandle,
+        api_availability: ApiAvailabilityHandle,
+        key_provider: Arc<dyn KeyProvider>,
+    ) -> Self {
         Self {
             proxy: DevicesProxy::new(handle),
             api_availability,
+            key_provider,
         }
     }
 
@@ -45,7 +50,7 @@ impl DeviceService {
         &self,
         account_token: AccountToken,
     ) -> impl Future<Output = Result<PrivateAccountAndDevice, Error>> + Send {
-        let private_key = PrivateKey::new_from_random();
+        let private_key = self.key_provider.generate();
         let pubkey = private_key.public_key();
 
         let proxy = self.proxy.clone();
@@ -78,7 +83,7 @@ impl DeviceService {
         &self,
         account_token: AccountToken,
     ) -> Result<PrivateAccountAndDevice, Error> {
-        let private_key = PrivateKey::new_from_random();
+        let private_key = self.key_provider.generate();
         let pubkey = private_key.public_key();
 
         let proxy = self.proxy.clone();
@@ -158,7 +163,7 @@ impl DeviceService {
         token: AccountToken,
         device: DeviceId,
     ) -> Result<WireguardData, Error> {
-        let private_key = PrivateKey::new_from_random();
+        let private_key = self.key_provider.generate();
 
         let proxy = self.proxy.clone();
         let api_handle = self.api_availability.clone();
@@ -183,7 +188,7 @@ impl DeviceService {
         token: AccountToken,
         device: DeviceId,
     ) -> Result<WireguardData, Error> {
-        let private_key = PrivateKey::new_from_random();
+        let private_key = self.key_provider.generate();
 
         let proxy = self.proxy.clone();
         let api_handle = self.api_availability.clone();
@@ -367,7 +372,7 @@ impl AccountService {
 
 pub fn spawn_account_service(
     api_handle: MullvadRestHandle,
-    token: Option<String>,
+    token: Option<AccountToken>,
     api_availability: ApiAvailabilityHandle,
 ) -> AccountService {
     let accounts_proxy = AccountsProxy::new(api_handle);
@@ -1,3 +1,5 @@
+#[cfg(not(target_os = "android"))]
+use mullvad_types::settings::BlocklistSource;
 use mullvad_types::settings::{DnsOptions, DnsState};
 use std::net::{IpAddr, Ipv4Addr};
 
@@ -57,3 +59,17 @@ pub fn addresses_from_options(options: &DnsOptions) -> Option<Vec<IpAddr>> {
         }
     }
 }
+
+/// Return the custom blocklists to block domains from. They are only used together with the
+/// default DNS servers, like the other content blockers.
+#[cfg(not(target_os = "android"))]
+pub fn blocklists_from_options(options: &DnsOptions) -> Vec<BlocklistSource> {
+    match options.state {
+        DnsState::Default => options
+            .default_options
+            .enabled_blocklists()
+            .cloned()
+            .collect(),
+        DnsState::Custom => vec![],
+    }
+}
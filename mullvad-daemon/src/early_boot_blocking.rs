@@ -0,0 +1,122 @@
+//! Blocks traffic during early boot on macOS while lockdown mode is enabled. launchd does not let
+//! us start the daemon before other launch daemons, so a separate launch daemon loads a blocking
+//! ruleset into pf as soon as the system starts. The rules are kept in the anchor that the
+//! firewall uses, so they are replaced once the daemon applies its first policy. This serves the
+//! same purpose as the persistent filters that are left behind on Windows.
+
+use futures::{channel::mpsc, StreamExt};
+use mullvad_types::settings::Settings;
+use std::{io, path::Path};
+use talpid_core::firewall::Firewall;
+use talpid_types::ErrorExt;
+
+const LAUNCH_DAEMON_LABEL: &str = "net.mullvad.early-boot-blocking";
+const LAUNCH_DAEMON_PATH: &str = "/Library/LaunchDaemons/net.mullvad.early-boot-blocking.plist";
+const RULESET_PATH: &str = "/etc/pf.anchors/net.mullvad.early-boot-blocking";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Failed to write {0}")]
+    Write(&'static str, #[source] io::Error),
+
+    #[error("Failed to remove {0}")]
+    Remove(&'static str, #[source] io::Error),
+
+    #[error("The early boot blocking ruleset is rejected by pf")]
+    InvalidRuleset(#[source] io::Error),
+}
+
+/// Settings that the early boot blocking depends on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    pub lockdown_mode: bool,
+    pub allow_lan: bool,
+}
+
+impl From<&Settings> for Config {
+    fn from(settings: &Settings) -> Self {
+        Config {
+            lockdown_mode: settings.block_when_disconnected,
+            allow_lan: settings.allow_lan,
+        }
+    }
+}
+
+/// Install or remove the early boot blocking whenever a new config is received.
+pub async fn run(mut config_rx: mpsc::UnboundedReceiver<Config>) {
+    let mut current_config = None;
+    while let Some(config) = config_rx.next().await {
+        if current_config == Some(config) {
+            continue;
+        }
+        current_config = Some(config);
+        if let Err(error) = update(config).await {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to update early boot blocking")
+            );
+        }
+    }
+}
+
+async fn update(config: Config) -> Result<(), Error> {
+    if config.lockdown_mode {
+        log::debug!("Installing early boot blocking");
+        write(RULESET_PATH, Firewall::early_boot_ruleset(config.allow_lan)).await?;
+        // The launch daemon is not loaded now, since it would replace the rules of the running
+        // daemon. launchd loads it during the next boot.
+        write(LAUNCH_DAEMON_PATH, launch_daemon_plist()).await
+    } else {
+        // Remove the launch daemon first, so that it never refers to a missing ruleset
+        remove(LAUNCH_DAEMON_PATH).await?;
+        remove(RULESET_PATH).await
+    }
+}
+
+/// Return whether early boot blocking is installed. Fails if the installed ruleset is rejected by
+/// pf, since nothing would be blocked during boot then.
+pub async fn is_installed() -> Result<bool, Error> {
+    if !Path::new(LAUNCH_DAEMON_PATH).exists() || !Path::new(RULESET_PATH).exists() {
+        return Ok(false);
+    }
+    Firewall::check_ruleset(RULESET_PATH)
+        .await
+        .map_err(Error::InvalidRuleset)?;
+    Ok(true)
+}
+
+fn launch_daemon_plist() -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{LAUNCH_DAEMON_LABEL}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>/sbin/pfctl</string>
+        <string>-E</string>
+        <string>-f</string>
+        <string>{RULESET_PATH}</string>
+    </array>
+    <key>RunAtLoad</key>
+    <true/>
+</dict>
+</plist>
+"#
+    )
+}
+
+async fn write(path: &'static str, contents: String) -> Result<(), Error> {
+    tokio::fs::write(path, contents)
+        .await
+        .map_err(|error| Error::Write(path, error))
+}
+
+async fn remove(path: &'static str) -> Result<(), Error> {
+    match tokio::fs::remove_file(path).await {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(Error::Remove(path, error)),
+        _ => Ok(()),
+    }
+}
@@ -6,6 +6,9 @@ pub enum Error {
     #[error("Failed to initialize firewall")]
     Firewall(#[from] firewall::Error),
 
+    #[error("Failed to apply firewall policy")]
+    ApplyPolicy(#[from] firewall::ApplyPolicyError),
+
     #[error("Failed to get settings path")]
     Path(#[from] mullvad_paths::Error),
 
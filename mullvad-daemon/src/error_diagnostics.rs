@@ -0,0 +1,208 @@
+//! Takes a snapshot of the network configuration when the tunnel state machine enters the error
+//! state. Errors are often caused by something else on the machine, and are gone by the time
+//! anyone looks into them, so the snapshot is kept until the next error to make them debuggable
+//! after the fact.
+
+use chrono::Local;
+use std::{
+    fmt::Write,
+    fs,
+    process::Command,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use talpid_types::tunnel::ErrorState;
+use tokio::task::JoinHandle;
+
+/// Maximum size of the output of a single probe. Longer output is truncated.
+const MAX_SECTION_LEN: usize = 16 * 1024;
+
+/// Give up on probes that have not finished after this long.
+const COLLECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Something that is included in the snapshot.
+struct Probe {
+    title: &'static str,
+    source: Source,
+}
+
+enum Source {
+    Command(&'static str, &'static [&'static str]),
+    File(&'static str),
+}
+
+#[cfg(target_os = "linux")]
+const PROBES: &[Probe] = &[
+    Probe {
+        title: "Routes",
+        source: Source::Command("ip", &["route", "show", "table", "all"]),
+    },
+    Probe {
+        title: "IPv6 routes",
+        source: Source::Command("ip", &["-6", "route", "show", "table", "all"]),
+    },
+    Probe {
+        title: "Routing rules",
+        source: Source::Command("ip", &["rule", "show"]),
+    },
+    Probe {
+        title: "Firewall rules",
+        source: Source::Command("nft", &["list", "table", "inet", "mullvad"]),
+    },
+    Probe {
+        title: "Resolver",
+        source: Source::File("/etc/resolv.conf"),
+    },
+    Probe {
+        title: "systemd-resolved",
+        source: Source::Command("resolvectl", &["status"]),
+    },
+    Probe {
+        title: "Interfaces",
+        source: Source::Command("ip", &["address", "show"]),
+    },
+];
+
+#[cfg(target_os = "macos")]
+const PROBES: &[Probe] = &[
+    Probe {
+        title: "Routes",
+        source: Source::Command("/usr/sbin/netstat", &["-rn"]),
+    },
+    Probe {
+        title: "Firewall rules",
+        source: Source::Command("/sbin/pfctl", &["-a", "mullvad", "-s", "rules"]),
+    },
+    Probe {
+        title: "Resolver",
+        source: Source::Command("/usr/sbin/scutil", &["--dns"]),
+    },
+    Probe {
+        title: "Interfaces",
+        source: Source::Command("/sbin/ifconfig", &[]),
+    },
+];
+
+#[cfg(windows)]
+const PROBES: &[Probe] = &[
+    Probe {
+        title: "Routes",
+        source: Source::Command("route", &["print"]),
+    },
+    Probe {
+        title: "Firewall",
+        source: Source::Command("netsh", &["advfirewall", "show", "allprofiles"]),
+    },
+    Probe {
+        title: "Resolver",
+        source: Source::Command("netsh", &["interface", "ip", "show", "dnsservers"]),
+    },
+    Probe {
+        title: "Interfaces",
+        source: Source::Command("ipconfig", &["/all"]),
+    },
+];
+
+/// Keeps the snapshot taken when the error state was last entered.
+#[derive(Default)]
+pub struct ErrorDiagnostics {
+    last_report: Arc<Mutex<Option<String>>>,
+    collect_task: Option<JoinHandle<()>>,
+}
+
+impl ErrorDiagnostics {
+    /// Take a new snapshot in the background. It replaces the previous one when it is done.
+    pub fn collect(&mut self, error_state: &ErrorState) {
+        if let Some(task) = self.collect_task.take() {
+            task.abort();
+        }
+
+        let header = format!(
+            "Entered the error state at {}\nCause: {}\nBlocking: {}\n",
+            Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+            error_state.cause(),
+            if error_state.is_blocking() {
+                "yes"
+            } else {
+                "no"
+            },
+        );
+        let last_report = self.last_report.clone();
+        self.collect_task = Some(tokio::spawn(async move {
+            let sections =
+                tokio::time::timeout(COLLECT_TIMEOUT, tokio::task::spawn_blocking(run_probes));
+            let sections = match sections.await {
+                Ok(Ok(sections)) => sections,
+                Ok(Err(error)) => format!("\nFailed to collect diagnostics: {error}\n"),
+                Err(_) => "\nTimed out while collecting diagnostics\n".to_owned(),
+            };
+            *last_report.lock().unwrap() = Some(header + &sections);
+        }));
+    }
+
+    /// Return the last complete snapshot, if the error state has been entered.
+    pub fn last_report(&self) -> Option<String> {
+        self.last_report.lock().unwrap().clone()
+    }
+}
+
+fn run_probes() -> String {
+    let mut report = String::new();
+    for probe in PROBES {
+        let (description, output) = match &probe.source {
+            Source::Command(program, args) => {
+                let description = format!("$ {program} {}", args.join(" "));
+                (description, run_command(program, args))
+            }
+            Source::File(path) => (format!("$ cat {path}"), fs::read_to_string(path)),
+        };
+        let output = output.unwrap_or_else(|error| format!("Failed: {error}\n"));
+        let _ = write!(
+            report,
+            "\n## {}\n{}\n{}",
+            probe.title,
+            description.trim_end(),
+            truncate(&output, MAX_SECTION_LEN)
+        );
+    }
+    report
+}
+
+fn run_command(program: &str, args: &[&str]) -> std::io::Result<String> {
+    let output = Command::new(program).args(args).output()?;
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    if !output.status.success() {
+        let _ = write!(
+            text,
+            "{}\n({})\n",
+            String::from_utf8_lossy(&output.stderr).trim_end(),
+            output.status
+        );
+    }
+    Ok(text)
+}
+
+/// Shorten `text` to at most `max_len` bytes, and note that it was truncated.
+fn truncate(text: &str, max_len: usize) -> String {
+    if text.len() <= max_len {
+        return text.to_owned();
+    }
+    let mut end = max_len;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}\n... (truncated)\n", &text[..end])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("short\n", 16), "short\n");
+        assert_eq!(truncate("0123456789", 4), "0123\n... (truncated)\n");
+        // Never split a character
+        assert_eq!(truncate("ååå", 3), "å\n... (truncated)\n");
+    }
+}
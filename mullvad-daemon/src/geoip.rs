@@ -1,47 +1,79 @@
-use std::time::Duration;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
 
 use futures::join;
 use mullvad_api::rest::{Error, RequestServiceHandle};
-use mullvad_types::location::{AmIMullvad, GeoIpLocation, LocationEventData};
-use once_cell::sync::Lazy;
+use mullvad_types::{
+    location::{
+        AmIMullvad, ConnectionVerification, DnsLeakResolver, GeoIpLocation, LocationEventData,
+    },
+    settings::ConnectionCheckSettings,
+};
 use talpid_core::mpsc::Sender;
 use talpid_future::retry::{retry_future, ExponentialBackoff, Jittered};
 use talpid_types::ErrorExt;
 
-use crate::{DaemonEventSender, InternalDaemonEvent};
-
-// Define the Mullvad connection checking api endpoint.
-//
-// In a development build the host name for the connection checking endpoint can
-// be overriden by defining the env variable `MULLVAD_CONNCHECK_HOST`.
-//
-// If `MULLVAD_CONNCHECK_HOST` is set when running `mullvad-daemon` in a
-// production build, a warning will be logged and the env variable *won´t* have
-// any effect on the api call. The default host name `am.i.mullvad.net` will
-// always be used in release mode.
-static MULLVAD_CONNCHECK_HOST: Lazy<String> = Lazy::new(|| {
-    const DEFAULT_CONNCHECK_HOST: &str = "am.i.mullvad.net";
-    let conncheck_host_var = std::env::var("MULLVAD_CONNCHECK_HOST").ok();
-    let host = if cfg!(feature = "api-override") {
-        match conncheck_host_var.as_deref() {
-            Some(host) => {
-                log::debug!("Overriding conncheck endpoint. Using {}", &host);
-                host
-            }
-            None => DEFAULT_CONNCHECK_HOST,
-        }
-    } else {
-        if conncheck_host_var.is_some() {
-            log::warn!("These variables are ignored in production builds: MULLVAD_CONNCHECK_HOST");
-        };
-        DEFAULT_CONNCHECK_HOST
-    };
-    host.to_string()
-});
+use crate::{runtime_config, DaemonEventSender, InternalDaemonEvent};
+
+/// Returns the host of the connection checking api endpoint.
+fn conncheck_host(settings: &ConnectionCheckSettings) -> String {
+    runtime_config::get()
+        .conncheck_host
+        .clone()
+        .unwrap_or_else(|| settings.host.clone())
+}
+
+/// Returns whether `host` is a plain host name that can be used as the connection checking api
+/// endpoint. Schemes, ports and paths are not accepted.
+pub fn is_valid_host(host: &str) -> bool {
+    let subdomain = format!("ipv4.{host}");
+    !host.is_empty()
+        && format!("https://{subdomain}/json")
+            .parse::<hyper::Uri>()
+            .is_ok_and(|uri| uri.host() == Some(subdomain.as_str()))
+}
 
 const LOCATION_RETRY_STRATEGY: Jittered<ExponentialBackoff> =
     Jittered::jitter(ExponentialBackoff::new(Duration::from_secs(1), 4));
 
+/// Cached locations older than this are not served when the lookup fails.
+const MAX_CACHED_LOCATION_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The last location that was looked up successfully.
+struct CachedLocation {
+    location: GeoIpLocation,
+    fetched_at: SystemTime,
+    /// Exit relay that the location was looked up through, or `None` if it was looked up outside
+    /// of the tunnel.
+    exit_hostname: Option<String>,
+}
+
+impl CachedLocation {
+    /// Return the cached location marked as stale, if it was looked up through the same exit relay
+    /// and is recent enough.
+    fn stale_location(&self, exit_hostname: Option<&str>) -> Option<GeoIpLocation> {
+        // A clock that was turned back makes the location look fresh, which is harmless
+        let age = self.fetched_at.elapsed().unwrap_or_default();
+        if self.exit_hostname.as_deref() != exit_hostname || age > MAX_CACHED_LOCATION_AGE {
+            return None;
+        }
+        Some(GeoIpLocation {
+            stale: true,
+            ..self.location.clone()
+        })
+    }
+}
+
+/// What the tunnel is expected to look like from the outside. Used to verify the connection.
+pub(crate) struct ExpectedConnection {
+    /// Hostname of the selected exit relay, if it is known.
+    pub exit_hostname: Option<String>,
+    /// Whether all DNS lookups should be answered by Mullvad resolvers.
+    pub mullvad_dns: bool,
+}
+
 /// Handler for request to am.i.mullvad.net, manages in-flight request and validity of responses.
 pub(crate) struct GeoIpHandler {
     /// Unique ID for each request. If the ID attached to the
@@ -50,6 +82,7 @@ pub(crate) struct GeoIpHandler {
     pub request_id: usize,
     rest_service: RequestServiceHandle,
     location_sender: DaemonEventSender,
+    cache: Arc<Mutex<Option<CachedLocation>>>,
 }
 
 impl GeoIpHandler {
@@ -58,28 +91,90 @@ impl GeoIpHandler {
             request_id: 0,
             rest_service,
             location_sender,
+            cache: Arc::default(),
         }
     }
 
     /// Send a location request to am.i.mullvad.net. When it arrives, send an
     /// [`InternalDaemonEvent::LocationEvent`], which triggers an update of the current
     /// tunnel state with the `ipv4` and/or `ipv6` fields filled in.
-    pub fn send_geo_location_request(&mut self, use_ipv6: bool) {
+    ///
+    /// If the request fails, the last location that was looked up through `exit_hostname` is sent
+    /// instead, marked as stale.
+    pub fn send_geo_location_request(
+        &mut self,
+        use_ipv6: bool,
+        settings: &ConnectionCheckSettings,
+        exit_hostname: Option<String>,
+    ) {
         // Increment request ID
         self.request_id = self.request_id.wrapping_add(1);
 
         self.abort_current_request();
 
         let request_id = self.request_id;
+        let host = conncheck_host(settings);
         let rest_service = self.rest_service.clone();
         let location_sender = self.location_sender.clone();
+        let cache = self.cache.clone();
         tokio::spawn(async move {
-            if let Ok(location) = get_geo_location_with_retry(use_ipv6, rest_service).await {
-                let _ =
-                    location_sender.send(InternalDaemonEvent::LocationEvent(LocationEventData {
+            let location = match get_geo_location_with_retry(use_ipv6, host, rest_service).await {
+                Ok(location) => {
+                    *cache.lock().unwrap() = Some(CachedLocation {
+                        location: location.clone(),
+                        fetched_at: SystemTime::now(),
+                        exit_hostname,
+                    });
+                    location
+                }
+                Err(error) if error.is_aborted() => return,
+                Err(_) => {
+                    let cached = cache
+                        .lock()
+                        .unwrap()
+                        .as_ref()
+                        .and_then(|cached| cached.stale_location(exit_hostname.as_deref()));
+                    let Some(location) = cached else {
+                        return;
+                    };
+                    log::debug!("Failed to look up the location, using the last known location");
+                    location
+                }
+            };
+            let _ = location_sender.send(InternalDaemonEvent::LocationEvent(LocationEventData {
+                request_id,
+                location,
+            }));
+        });
+    }
+
+    /// Check the connection through the tunnel against am.i.mullvad.net. When the result arrives,
+    /// send an [`InternalDaemonEvent::ConnectionVerified`]. The request belongs to the same
+    /// tunnel state as the last location request.
+    pub fn send_connection_verification_request(
+        &self,
+        settings: &ConnectionCheckSettings,
+        expected: ExpectedConnection,
+    ) {
+        let request_id = self.request_id;
+        let host = conncheck_host(settings);
+        let rest_service = self.rest_service.clone();
+        let event_sender = self.location_sender.clone();
+        tokio::spawn(async move {
+            match verify_connection(host, expected, rest_service).await {
+                Ok(verification) => {
+                    let _ = event_sender.send(InternalDaemonEvent::ConnectionVerified {
                         request_id,
-                        location,
-                    }));
+                        verification,
+                    });
+                }
+                Err(error) if !error.is_aborted() => {
+                    log::warn!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to verify the connection")
+                    );
+                }
+                Err(_) => (),
             }
         });
     }
@@ -93,11 +188,12 @@ impl GeoIpHandler {
 /// Fetch the current `GeoIpLocation` from am.i.mullvad.net. Handles retries on network errors.
 async fn get_geo_location_with_retry(
     use_ipv6: bool,
+    host: String,
     rest_service: RequestServiceHandle,
 ) -> Result<GeoIpLocation, Error> {
     log::debug!("Fetching GeoIpLocation");
     retry_future(
-        move || send_location_request(rest_service.clone(), use_ipv6),
+        move || send_location_request(rest_service.clone(), use_ipv6, host.clone()),
         move |result| match result {
             Err(error) => error.is_network_error(),
             _ => false,
@@ -110,17 +206,18 @@ async fn get_geo_location_with_retry(
 async fn send_location_request(
     request_sender: RequestServiceHandle,
     use_ipv6: bool,
+    host: String,
 ) -> Result<GeoIpLocation, Error> {
     let v4_sender = request_sender.clone();
-    let v4_future = async move {
-        let uri_v4 = format!("https://ipv4.{}/json", *MULLVAD_CONNCHECK_HOST);
+    let v4_future = async {
+        let uri_v4 = format!("https://ipv4.{host}/json");
         let location = send_location_request_internal(&uri_v4, v4_sender).await?;
         Ok::<GeoIpLocation, Error>(GeoIpLocation::from(location))
     };
     let v6_sender = request_sender.clone();
-    let v6_future = async move {
+    let v6_future = async {
         if use_ipv6 {
-            let uri_v6 = format!("https://ipv6.{}/json", *MULLVAD_CONNCHECK_HOST);
+            let uri_v6 = format!("https://ipv6.{host}/json");
             let location = send_location_request_internal(&uri_v6, v6_sender).await;
             Some(location.map(GeoIpLocation::from))
         } else {
@@ -149,6 +246,61 @@ async fn send_location_request(
     }
 }
 
+/// Fetch the exit IP and relay from am.i.mullvad.net, along with the DNS resolvers that were used
+/// if they are all expected to be Mullvad resolvers, and compare them with what is expected.
+async fn verify_connection(
+    host: String,
+    expected: ExpectedConnection,
+    rest_service: RequestServiceHandle,
+) -> Result<ConnectionVerification, Error> {
+    log::debug!("Verifying connection");
+    let exit_service = rest_service.clone();
+    let exit_host = host.clone();
+    let exit_future = retry_future(
+        move || {
+            let uri = format!("https://ipv4.{exit_host}/json");
+            let service = exit_service.clone();
+            async move { send_location_request_internal(&uri, service).await }
+        },
+        move |result| match result {
+            Err(error) => error.is_network_error(),
+            _ => false,
+        },
+        LOCATION_RETRY_STRATEGY,
+    );
+    let dns_future = async {
+        if !expected.mullvad_dns {
+            return None;
+        }
+        let uri = format!("https://{host}/dnsleak");
+        match send_dns_leak_request(&uri, rest_service.clone()).await {
+            Ok(resolvers) => Some(resolvers),
+            Err(error) => {
+                log::debug!(
+                    "{}",
+                    error.display_chain_with_msg("Unable to fetch DNS resolvers")
+                );
+                None
+            }
+        }
+    };
+
+    let (exit, resolvers) = join!(exit_future, dns_future);
+    Ok(ConnectionVerification::new(
+        &exit?,
+        expected.exit_hostname.as_deref(),
+        resolvers.as_deref(),
+    ))
+}
+
+async fn send_dns_leak_request(
+    uri: &str,
+    service: RequestServiceHandle,
+) -> Result<Vec<DnsLeakResolver>, Error> {
+    let request = mullvad_api::rest::Request::get(uri)?;
+    service.request(request).await?.deserialize().await
+}
+
 async fn send_location_request_internal(
     uri: &str,
     service: RequestServiceHandle,
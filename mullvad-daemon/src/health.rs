@@ -0,0 +1,152 @@
+//! Checks of the subsystems of the daemon, as reported by the `GetDaemonHealth` RPC.
+
+use crate::device::PrivateDeviceState;
+use chrono::Utc;
+use mullvad_api::{rest::MullvadRestHandle, ApiProxy};
+use mullvad_types::{health::ComponentHealth, states::TunnelState, wireguard::RotationInterval};
+use std::time::{Duration, SystemTime};
+use talpid_types::{tunnel::ErrorStateCause, ErrorExt};
+
+/// How long to wait for the API to respond before considering it unreachable.
+const API_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The relay list is considered stale once it is older than this. It is normally updated every
+/// hour.
+const RELAY_LIST_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Keys are rotated some time after the rotation interval has passed. A key that is older than
+/// the rotation interval plus this margin is considered overdue for rotation.
+const KEY_ROTATION_MARGIN: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Check whether the API can be reached.
+pub(crate) async fn api_connectivity(handle: MullvadRestHandle, offline: bool) -> ComponentHealth {
+    if offline {
+        return ComponentHealth::failed("The device is offline");
+    }
+    let check = ApiProxy::new(handle).api_addrs_available();
+    match tokio::time::timeout(API_CHECK_TIMEOUT, check).await {
+        Ok(Ok(true)) => ComponentHealth::ok("The API is reachable"),
+        Ok(Ok(false)) => ComponentHealth::degraded("The API responded with an error"),
+        Ok(Err(error)) => {
+            ComponentHealth::failed(error.display_chain_with_msg("The API is unreachable"))
+        }
+        Err(_) => ComponentHealth::failed("Timed out waiting for the API"),
+    }
+}
+
+/// Check that the relay list has been updated recently.
+pub(crate) fn relay_list(last_updated: SystemTime, now: SystemTime) -> ComponentHealth {
+    let age = now.duration_since(last_updated).unwrap_or_default();
+    let details = format!(
+        "The relay list was updated {} minutes ago",
+        age.as_secs() / 60
+    );
+    if age > RELAY_LIST_MAX_AGE {
+        ComponentHealth::degraded(details)
+    } else {
+        ComponentHealth::ok(details)
+    }
+}
+
+/// Check that there is a WireGuard key, and that it has been rotated as expected.
+pub(crate) fn wireguard_key(
+    device: &PrivateDeviceState,
+    rotation_interval: RotationInterval,
+) -> ComponentHealth {
+    let device = match device {
+        PrivateDeviceState::LoggedIn(device) => device,
+        PrivateDeviceState::LoggedOut => return ComponentHealth::failed("Not logged in"),
+        PrivateDeviceState::Revoked => return ComponentHealth::failed("The device was revoked"),
+    };
+    let age = (Utc::now() - device.device.wg_data.created)
+        .to_std()
+        .unwrap_or_default();
+    let details = format!(
+        "The key was created {} hours ago",
+        age.as_secs() / (60 * 60)
+    );
+    if age > *rotation_interval.as_duration() + KEY_ROTATION_MARGIN {
+        ComponentHealth::degraded(format!("{details}, and is overdue for rotation"))
+    } else {
+        ComponentHealth::ok(details)
+    }
+}
+
+/// Check that the firewall policy of the current tunnel state was applied.
+pub(crate) fn firewall(tunnel_state: &TunnelState) -> ComponentHealth {
+    match tunnel_state {
+        TunnelState::Error(error_state) if error_state.block_failure().is_some() => {
+            ComponentHealth::failed("Failed to block traffic")
+        }
+        TunnelState::Error(error_state) => match error_state.cause() {
+            cause @ ErrorStateCause::SetFirewallPolicyError(_) => {
+                ComponentHealth::failed(cause.to_string())
+            }
+            _ => ComponentHealth::ok("Traffic is blocked"),
+        },
+        _ => ComponentHealth::ok("The firewall policy is applied"),
+    }
+}
+
+/// Check that the DNS config of the current tunnel state was applied.
+pub(crate) fn dns(tunnel_state: &TunnelState) -> ComponentHealth {
+    match tunnel_state {
+        TunnelState::Error(error_state) => match error_state.cause() {
+            cause @ ErrorStateCause::SetDnsError { .. } => {
+                ComponentHealth::failed(cause.to_string())
+            }
+            #[cfg(target_os = "linux")]
+            cause @ ErrorStateCause::UnsupportedDnsManager(_) => {
+                ComponentHealth::failed(cause.to_string())
+            }
+            _ => ComponentHealth::ok("The DNS config is applied"),
+        },
+        _ => ComponentHealth::ok("The DNS config is applied"),
+    }
+}
+
+/// Check that the route manager is running.
+pub(crate) fn route_manager(alive: bool) -> ComponentHealth {
+    if alive {
+        ComponentHealth::ok("The route manager is running")
+    } else {
+        ComponentHealth::failed("The route manager has stopped")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mullvad_types::health::HealthStatus;
+    use talpid_types::tunnel::ErrorState;
+
+    #[test]
+    fn test_stale_relay_list() {
+        let now = SystemTime::now();
+        assert_eq!(relay_list(now, now).status, HealthStatus::Ok);
+        assert_eq!(
+            relay_list(now - RELAY_LIST_MAX_AGE * 2, now).status,
+            HealthStatus::Degraded
+        );
+    }
+
+    #[test]
+    fn test_error_state_components() {
+        let dns_error = TunnelState::Error(ErrorState::new(
+            ErrorStateCause::SetDnsError {
+                mechanism: None,
+                details: Default::default(),
+            },
+            None,
+        ));
+        assert_eq!(dns(&dns_error).status, HealthStatus::Failed);
+        assert_eq!(firewall(&dns_error).status, HealthStatus::Ok);
+
+        let disconnected = TunnelState::Disconnected {
+            location: None,
+            locked_down: false,
+        };
+        assert_eq!(dns(&disconnected).status, HealthStatus::Ok);
+        assert_eq!(firewall(&disconnected).status, HealthStatus::Ok);
+    }
+}
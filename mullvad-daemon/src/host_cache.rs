@@ -0,0 +1,268 @@
+//! Resolves the hostnames of custom relays, custom bridges and API access methods, and remembers
+//! the results. An address is reused for as long as its DNS records are valid. After that, the last known address is still used if the
+//! host cannot be resolved, for example because the firewall blocks DNS before a tunnel is up.
+//! The cache is stored on disk so that it survives restarts of the daemon.
+
+use chrono::{DateTime, Utc};
+use hickory_resolver::TokioAsyncResolver;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use talpid_types::{net::proxy::CustomProxy, ErrorExt};
+use tokio::io::AsyncWriteExt;
+
+const CACHE_FILENAME: &str = "resolved-hosts.json";
+
+/// How long to keep an address for when the TTL of its records is unknown.
+const DEFAULT_TTL: Duration = Duration::from_secs(5 * 60);
+/// How long to wait for a lookup before giving up. This is kept short since lookups are expected
+/// to time out when DNS is blocked.
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Failed to resolve host {0}")]
+    Resolve(String, #[source] io::Error),
+
+    #[error("Timed out resolving host {0}")]
+    Timeout(String),
+
+    #[error("Host {0} has no addresses")]
+    NoAddresses(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct CachedHost {
+    address: IpAddr,
+    expires: DateTime<Utc>,
+}
+
+impl CachedHost {
+    fn is_valid(&self) -> bool {
+        Utc::now() < self.expires
+    }
+}
+
+/// Handle to the cache. Clones share the same cache. The cache is only locked while it is read or
+/// updated, never while a host is being resolved.
+#[derive(Clone)]
+pub struct HostCache {
+    hosts: Arc<Mutex<HashMap<String, CachedHost>>>,
+    cache_path: Arc<PathBuf>,
+    /// Held while the cache is written to disk, so that an older version of the cache can't
+    /// replace a newer one.
+    save_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl HostCache {
+    /// Creates a cache that is stored in `cache_dir`. Previously resolved addresses are loaded
+    /// from there, if there are any.
+    pub async fn new(cache_dir: &Path) -> Self {
+        let cache_path = cache_dir.join(CACHE_FILENAME);
+        let hosts = match tokio::fs::read(&cache_path).await {
+            Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|error| {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to parse resolved hosts cache")
+                );
+                HashMap::new()
+            }),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to read resolved hosts cache")
+                );
+                HashMap::new()
+            }
+        };
+        Self {
+            hosts: Arc::new(Mutex::new(hosts)),
+            cache_path: Arc::new(cache_path),
+            save_lock: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
+
+    /// Returns an address for `host`. A cached address is returned if it has not expired.
+    /// Otherwise, the host is resolved again. If that fails, the last known address is returned.
+    pub async fn resolve(&self, host: &str) -> Result<IpAddr, Error> {
+        if let Ok(address) = host.parse() {
+            return Ok(address);
+        }
+        if let Some(cached) = self.get(host).filter(|cached| cached.is_valid()) {
+            return Ok(cached.address);
+        }
+
+        match self.lookup_and_store(host).await {
+            Ok(address) => Ok(address),
+            Err(error) => match self.get(host) {
+                Some(cached) => {
+                    log::warn!(
+                        "{}",
+                        error.display_chain_with_msg("Using last known address for host")
+                    );
+                    Ok(cached.address)
+                }
+                None => Err(error),
+            },
+        }
+    }
+
+    /// Resolves `host` ahead of time, unless there is already a valid address for it. Failures
+    /// are only logged, since the host will be resolved again when it is needed.
+    pub async fn pre_resolve(&self, host: &str) {
+        if host.parse::<IpAddr>().is_ok() || self.get(host).is_some_and(|cached| cached.is_valid())
+        {
+            return;
+        }
+        if let Err(error) = self.lookup_and_store(host).await {
+            log::debug!(
+                "{}",
+                error.display_chain_with_msg("Failed to pre-resolve host")
+            );
+        }
+    }
+
+    /// Replaces the IP of `proxy` with an address of its hostname, if it has one.
+    pub async fn resolve_proxy(&self, proxy: &mut CustomProxy) -> Result<(), Error> {
+        let (endpoint, hostname) = match proxy {
+            CustomProxy::Shadowsocks(shadowsocks) => {
+                (&mut shadowsocks.endpoint, &shadowsocks.hostname)
+            }
+            CustomProxy::Socks5Remote(remote) => (&mut remote.endpoint, &remote.hostname),
+            CustomProxy::Socks5Local(_) => return Ok(()),
+        };
+        if let Some(hostname) = hostname {
+            endpoint.set_ip(self.resolve(hostname).await?);
+        }
+        Ok(())
+    }
+
+    fn get(&self, host: &str) -> Option<CachedHost> {
+        self.hosts.lock().unwrap().get(host).copied()
+    }
+
+    async fn lookup_and_store(&self, host: &str) -> Result<IpAddr, Error> {
+        let (address, ttl) = tokio::time::timeout(LOOKUP_TIMEOUT, lookup(host))
+            .await
+            .map_err(|_| Error::Timeout(host.to_owned()))??;
+        log::debug!("Resolved {host} to {address}, valid for {}s", ttl.as_secs());
+
+        let expires = Utc::now()
+            + chrono::Duration::from_std(ttl).unwrap_or_else(|_| chrono::Duration::zero());
+        let entry = CachedHost { address, expires };
+        let changed = self.hosts.lock().unwrap().insert(host.to_owned(), entry) != Some(entry);
+        if changed {
+            if let Err(error) = self.save().await {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to save resolved hosts cache")
+                );
+            }
+        }
+        Ok(address)
+    }
+
+    async fn save(&self) -> io::Result<()> {
+        let _save_guard = self.save_lock.lock().await;
+        let contents =
+            serde_json::to_vec_pretty(&*self.hosts.lock().unwrap()).map_err(io::Error::from)?;
+        let mut file = mullvad_fs::AtomicFile::new(&*self.cache_path).await?;
+        file.write_all(&contents).await?;
+        file.finalize().await
+    }
+}
+
+/// Resolves `host` and returns an address for it along with how long the address may be cached
+/// for. IPv4 addresses are preferred over IPv6 addresses.
+///
+/// The system resolver configuration is read on every lookup, since it changes as the tunnel
+/// comes up and goes down. If it cannot be read, the host is resolved using `getaddrinfo`, whose
+/// results do not include a TTL.
+async fn lookup(host: &str) -> Result<(IpAddr, Duration), Error> {
+    let (addresses, ttl): (Vec<IpAddr>, _) = match TokioAsyncResolver::tokio_from_system_conf() {
+        Ok(resolver) => {
+            let lookup = resolver
+                .lookup_ip(host)
+                .await
+                .map_err(|error| Error::Resolve(host.to_owned(), error.into()))?;
+            let ttl = lookup
+                .valid_until()
+                .saturating_duration_since(Instant::now());
+            (lookup.iter().collect(), ttl)
+        }
+        Err(_) => {
+            let addresses = tokio::net::lookup_host((host, 0))
+                .await
+                .map_err(|error| Error::Resolve(host.to_owned(), error))?
+                .map(|addr| addr.ip())
+                .collect();
+            (addresses, DEFAULT_TTL)
+        }
+    };
+
+    addresses
+        .iter()
+        .find(|address| address.is_ipv4())
+        .or_else(|| addresses.first())
+        .map(|address| (*address, ttl))
+        .ok_or_else(|| Error::NoAddresses(host.to_owned()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static DUMMY_CACHE_PATH: &str = "host-cache-test.json";
+    const HOST: &str = "relay.invalid";
+
+    fn cache_with_entry(address: IpAddr, expires: DateTime<Utc>) -> HostCache {
+        HostCache {
+            hosts: Arc::new(Mutex::new(HashMap::from([(
+                HOST.to_owned(),
+                CachedHost { address, expires },
+            )]))),
+            cache_path: Arc::new(PathBuf::from(DUMMY_CACHE_PATH)),
+            save_lock: Arc::new(tokio::sync::Mutex::new(())),
+        }
+    }
+
+    /// An address is returned from the cache as long as it has not expired.
+    #[tokio::test]
+    async fn test_valid_cached_address() {
+        let address: IpAddr = "192.0.2.1".parse().unwrap();
+        let cache = cache_with_entry(address, Utc::now() + chrono::Duration::minutes(5));
+        assert_eq!(cache.resolve(HOST).await.unwrap(), address);
+    }
+
+    /// If an expired host cannot be resolved again, the last known address is used.
+    #[tokio::test]
+    async fn test_fallback_to_expired_address() {
+        let address: IpAddr = "2001:db8::1".parse().unwrap();
+        let cache = cache_with_entry(address, Utc::now() - chrono::Duration::minutes(1));
+        assert_eq!(cache.resolve(HOST).await.unwrap(), address);
+    }
+
+    /// The placeholder IP of a proxy that is referenced by hostname is replaced.
+    #[tokio::test]
+    async fn test_resolve_proxy() {
+        use talpid_types::net::proxy::Socks5Remote;
+
+        let address: IpAddr = "192.0.2.1".parse().unwrap();
+        let cache = cache_with_entry(address, Utc::now() + chrono::Duration::minutes(5));
+        let mut proxy = CustomProxy::from(
+            Socks5Remote::new(([0, 0, 0, 0], 1080)).with_hostname(Some(HOST.to_owned())),
+        );
+        cache.resolve_proxy(&mut proxy).await.unwrap();
+        assert_eq!(
+            proxy.get_remote_endpoint().endpoint.address,
+            (address, 1080).into()
+        );
+    }
+}
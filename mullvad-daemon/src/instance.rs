@@ -0,0 +1,71 @@
+//! Derives the resources of a daemon instance from its ID in `MULLVAD_INSTANCE`, so that several
+//! daemons can run side by side on one host. The paths are derived by `mullvad-paths`. On Linux,
+//! the tunnel interface name, firewall mark, routing table and firewall table are derived here.
+//!
+//! Instances are not isolated from each other's firewall. Every firewall table drops tunnel
+//! traffic that does not carry the firewall mark of its own instance, so only one instance at a
+//! time may be connected or block traffic. The net_cls cgroup and class ID used for split
+//! tunneling are not derived from the ID either, so all instances share the excluded processes.
+
+use mullvad_paths::instance::{parse_instance_id, INSTANCE_VAR, MAX_INSTANCE_ID};
+use std::env;
+
+#[derive(thiserror::Error, Debug)]
+#[error("Invalid {INSTANCE_VAR} value \"{0}\". It must be a number from 1 to {MAX_INSTANCE_ID}")]
+pub struct InvalidInstanceId(String);
+
+/// Check the instance ID and export the variables that it implies, unless they are already set.
+/// This must be done before any other threads are started, and after the daemon config file has
+/// been applied, so that the file takes precedence.
+pub fn set_env_vars() -> Result<(), InvalidInstanceId> {
+    let Some(value) = env::var_os(INSTANCE_VAR) else {
+        return Ok(());
+    };
+    let value = value.to_string_lossy();
+    let id = parse_instance_id(&value).ok_or_else(|| InvalidInstanceId(value.into_owned()))?;
+    for (key, value) in derived_vars(id) {
+        if env::var_os(key).is_none() {
+            env::set_var(key, value);
+        }
+    }
+    Ok(())
+}
+
+fn derived_vars(id: u8) -> Vec<(&'static str, String)> {
+    #[cfg(not(target_os = "linux"))]
+    let _ = id;
+    vec![
+        #[cfg(target_os = "linux")]
+        ("TALPID_TUNNEL_INTERFACE_NAME", format!("wg{id}-mullvad")),
+        #[cfg(target_os = "linux")]
+        (
+            "MULLVAD_TUNNEL_FWMARK",
+            (mullvad_types::TUNNEL_FWMARK + u32::from(id)).to_string(),
+        ),
+        #[cfg(target_os = "linux")]
+        (
+            "MULLVAD_TUNNEL_TABLE_ID",
+            (mullvad_types::TUNNEL_TABLE_ID + u32::from(id)).to_string(),
+        ),
+        #[cfg(target_os = "linux")]
+        ("TALPID_FIREWALL_TABLE_NAME", format!("mullvad{id}")),
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_derived_vars() {
+        let vars = derived_vars(2);
+        assert!(vars.contains(&("TALPID_TUNNEL_INTERFACE_NAME", "wg2-mullvad".to_owned())));
+        assert!(vars.contains(&("MULLVAD_TUNNEL_FWMARK", "1836018791".to_owned())));
+        assert!(vars.contains(&("TALPID_FIREWALL_TABLE_NAME", "mullvad2".to_owned())));
+
+        // The longest interface name must be valid
+        let vars = derived_vars(MAX_INSTANCE_ID);
+        assert!(talpid_tunnel::interface_name::NameTemplate::new(&vars[0].1).is_ok());
+    }
+}
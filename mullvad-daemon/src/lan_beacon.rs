@@ -0,0 +1,257 @@
+//! Announces the tunnel state on the local network over multicast DNS, so that home automation
+//! hubs can react to it. Only the state is announced, as the TXT record of a DNS-SD service. The
+//! service instance name is the same on every device, so that nothing identifies the device or
+//! the account.
+//!
+//! The announcements are only sent while local network sharing is allowed, since they are blocked
+//! by the firewall otherwise.
+
+use futures::{channel::mpsc, StreamExt};
+use mullvad_types::states::TunnelState;
+use nix::{ifaddrs::getifaddrs, net::if_::InterfaceFlags, sys::socket::SockAddr};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    time::Duration,
+};
+use talpid_types::ErrorExt;
+
+use crate::webhook::state_name;
+
+/// Service type that hubs can browse for.
+const SERVICE_TYPE: &str = "_mullvad-vpn._udp.local";
+const INSTANCE_NAME: &str = "Mullvad VPN";
+
+const MDNS_ADDR: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(224, 0, 0, 251), 5353);
+/// Required by RFC 6762, so that receivers can tell that the packet was sent on the local link.
+const MDNS_HOP_LIMIT: u32 = 255;
+
+/// How long receivers may cache the announced state.
+const RECORD_TTL: Duration = Duration::from_secs(120);
+/// The state is announced again at this interval, so that hubs that start later learn it.
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(60);
+
+const DNS_TYPE_PTR: u16 = 12;
+const DNS_TYPE_TXT: u16 = 16;
+const DNS_CLASS_IN: u16 = 1;
+/// Tells receivers to replace any cached records of the same name and type.
+const DNS_CACHE_FLUSH: u16 = 0x8000;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Failed to list network interfaces")]
+    ListInterfaces(#[source] nix::Error),
+
+    #[error("Failed to create socket for interface {0}")]
+    Socket(String, #[source] io::Error),
+
+    #[error("Failed to send announcement on interface {0}")]
+    Send(String, #[source] io::Error),
+}
+
+enum BeaconCommand {
+    SetEnabled(bool),
+    TunnelState(TunnelState),
+}
+
+#[derive(Clone)]
+pub(crate) struct LanBeaconHandle {
+    tx: mpsc::UnboundedSender<BeaconCommand>,
+}
+
+impl LanBeaconHandle {
+    /// Start or stop announcing the tunnel state.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.send(BeaconCommand::SetEnabled(enabled));
+    }
+
+    /// Announce a new tunnel state, if the beacon is enabled.
+    pub fn notify_tunnel_state(&self, tunnel_state: &TunnelState) {
+        self.send(BeaconCommand::TunnelState(tunnel_state.clone()));
+    }
+
+    fn send(&self, command: BeaconCommand) {
+        if self.tx.unbounded_send(command).is_err() {
+            log::error!("LAN beacon already down");
+        }
+    }
+}
+
+pub(crate) struct LanBeacon {
+    enabled: bool,
+    state: &'static str,
+}
+
+impl LanBeacon {
+    pub fn spawn(enabled: bool) -> LanBeaconHandle {
+        let (tx, rx) = mpsc::unbounded();
+        let beacon = LanBeacon {
+            enabled,
+            state: "disconnected",
+        };
+        tokio::spawn(beacon.run(rx));
+        LanBeaconHandle { tx }
+    }
+
+    async fn run(mut self, mut rx: mpsc::UnboundedReceiver<BeaconCommand>) {
+        let mut interval = tokio::time::interval(ANNOUNCE_INTERVAL);
+        loop {
+            tokio::select! {
+                command = rx.next() => match command {
+                    Some(BeaconCommand::SetEnabled(enabled)) => {
+                        if self.enabled == enabled {
+                            continue;
+                        }
+                        self.enabled = enabled;
+                        if enabled {
+                            self.announce(RECORD_TTL);
+                        } else {
+                            // Tell receivers to forget the state right away
+                            self.announce_unconditionally(Duration::ZERO);
+                        }
+                    }
+                    Some(BeaconCommand::TunnelState(tunnel_state)) => {
+                        self.state = state_name(&tunnel_state);
+                        self.announce(RECORD_TTL);
+                    }
+                    None => break,
+                },
+                _ = interval.tick() => self.announce(RECORD_TTL),
+            }
+        }
+    }
+
+    fn announce(&self, ttl: Duration) {
+        if self.enabled {
+            self.announce_unconditionally(ttl);
+        }
+    }
+
+    fn announce_unconditionally(&self, ttl: Duration) {
+        let packet = announcement(self.state, ttl);
+        if let Err(error) = send_on_lan_interfaces(&packet) {
+            log::warn!(
+                "{}",
+                error.display_chain_with_msg("Failed to announce tunnel state on the LAN")
+            );
+        }
+    }
+}
+
+/// Send `packet` to the mDNS group on every interface that is connected to a local network.
+/// Tunnel interfaces are point-to-point, so they are skipped.
+fn send_on_lan_interfaces(packet: &[u8]) -> Result<(), Error> {
+    let mut result = Ok(());
+    for interface in getifaddrs().map_err(Error::ListInterfaces)? {
+        let lan_flags = InterfaceFlags::IFF_UP | InterfaceFlags::IFF_MULTICAST;
+        let excluded_flags = InterfaceFlags::IFF_LOOPBACK | InterfaceFlags::IFF_POINTOPOINT;
+        if !interface.flags.contains(lan_flags) || interface.flags.intersects(excluded_flags) {
+            continue;
+        }
+        let Some(SockAddr::Inet(address)) = interface.address else {
+            continue;
+        };
+        let SocketAddr::V4(address) = address.to_std() else {
+            continue;
+        };
+        // Keep announcing on the other interfaces if one fails
+        if let Err(error) = send_on_interface(packet, *address.ip(), &interface.interface_name) {
+            result = Err(error);
+        }
+    }
+    result
+}
+
+fn send_on_interface(packet: &[u8], address: Ipv4Addr, interface: &str) -> Result<(), Error> {
+    let socket_error = |error| Error::Socket(interface.to_owned(), error);
+    let socket =
+        Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).map_err(socket_error)?;
+    socket.set_multicast_if_v4(&address).map_err(socket_error)?;
+    socket
+        .set_multicast_ttl_v4(MDNS_HOP_LIMIT)
+        .map_err(socket_error)?;
+    socket.set_multicast_loop_v4(false).map_err(socket_error)?;
+    socket
+        .send_to(packet, &SocketAddr::from(MDNS_ADDR).into())
+        .map_err(|error| Error::Send(interface.to_owned(), error))?;
+    Ok(())
+}
+
+/// Build an unsolicited mDNS response that advertises the service, with `state` in its TXT record.
+/// A TTL of zero withdraws the records.
+fn announcement(state: &str, ttl: Duration) -> Vec<u8> {
+    let instance = format!("{INSTANCE_NAME}.{SERVICE_TYPE}");
+    let ttl = u32::try_from(ttl.as_secs()).unwrap_or(u32::MAX);
+
+    let mut packet = vec![];
+    // ID, flags (authoritative response), and the number of questions, answers, authority records
+    // and additional records
+    for field in [0u16, 0x8400, 0, 2, 0, 0] {
+        packet.extend_from_slice(&field.to_be_bytes());
+    }
+
+    let mut ptr_data = vec![];
+    encode_name(&mut ptr_data, &instance);
+    encode_record(
+        &mut packet,
+        SERVICE_TYPE,
+        DNS_TYPE_PTR,
+        DNS_CLASS_IN,
+        ttl,
+        &ptr_data,
+    );
+
+    let mut txt_data = vec![];
+    let entry = format!("state={state}");
+    txt_data.push(u8::try_from(entry.len()).expect("TXT entry is short"));
+    txt_data.extend_from_slice(entry.as_bytes());
+    encode_record(
+        &mut packet,
+        &instance,
+        DNS_TYPE_TXT,
+        DNS_CLASS_IN | DNS_CACHE_FLUSH,
+        ttl,
+        &txt_data,
+    );
+
+    packet
+}
+
+fn encode_record(packet: &mut Vec<u8>, name: &str, ty: u16, class: u16, ttl: u32, data: &[u8]) {
+    encode_name(packet, name);
+    packet.extend_from_slice(&ty.to_be_bytes());
+    packet.extend_from_slice(&class.to_be_bytes());
+    packet.extend_from_slice(&ttl.to_be_bytes());
+    packet.extend_from_slice(&u16::try_from(data.len()).unwrap().to_be_bytes());
+    packet.extend_from_slice(data);
+}
+
+/// Encode a domain name as a sequence of length-prefixed labels. The instance name contains a
+/// space, which is allowed in DNS-SD.
+fn encode_name(packet: &mut Vec<u8>, name: &str) {
+    for label in name.split('.') {
+        packet.push(u8::try_from(label.len()).expect("label is short"));
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_announcement() {
+        let packet = announcement("connected", RECORD_TTL);
+
+        // One authoritative answer for the PTR record and one for the TXT record
+        assert_eq!(packet[..12], [0, 0, 0x84, 0, 0, 0, 0, 2, 0, 0, 0, 0]);
+
+        let mut txt_record = vec![];
+        encode_name(&mut txt_record, "Mullvad VPN._mullvad-vpn._udp.local");
+        txt_record.extend_from_slice(&[0, 16, 0x80, 1, 0, 0, 0, 120, 0, 16, 15]);
+        txt_record.extend_from_slice(b"state=connected");
+        assert!(packet.ends_with(&txt_record));
+    }
+}
@@ -6,27 +6,54 @@ pub mod account_history;
 mod api;
 mod api_address_updater;
 #[cfg(not(target_os = "android"))]
+mod blocklist;
+mod certificates;
+#[cfg(not(target_os = "android"))]
 mod cleanup;
+mod connection_diagnosis;
 mod custom_list;
 pub mod device;
 mod dns;
+#[cfg(target_os = "macos")]
+mod early_boot_blocking;
+#[cfg(not(target_os = "android"))]
+mod error_diagnostics;
 pub mod exception_logging;
 mod geoip;
+mod health;
+mod host_cache;
+#[cfg(all(unix, not(target_os = "android")))]
+mod lan_beacon;
 pub mod logging;
 #[cfg(target_os = "macos")]
 mod macos;
 pub mod management_interface;
 mod migrations;
+mod notifications;
+mod problem_report;
+mod profile;
+mod reconnect_coalescer;
 mod relay_list;
+mod relay_ping;
 #[cfg(not(target_os = "android"))]
 pub mod rpc_uniqueness_check;
 pub mod runtime;
+mod runtime_config;
 pub mod settings;
+mod settings_lock;
 pub mod shutdown;
+mod shutdown_report;
+#[cfg(not(target_os = "android"))]
+mod system_proxy;
 mod target_state;
+mod traffic_accounting;
 mod tunnel;
+#[cfg(not(target_os = "android"))]
+mod tunnel_protocol_fallback;
+mod upgrade_handoff;
 pub mod version;
 mod version_check;
+mod webhook;
 
 use crate::target_state::PersistentTargetState;
 use api::AccessMethodEvent;
@@ -36,7 +63,10 @@ use futures::{
     future::{abortable, AbortHandle, Future, LocalBoxFuture},
     StreamExt,
 };
-use geoip::GeoIpHandler;
+use geoip::{ExpectedConnection, GeoIpHandler};
+#[cfg(target_os = "linux")]
+use ipnetwork::IpNetwork;
+use mullvad_management_interface::ConnectionId;
 use mullvad_relay_selector::{
     AdditionalRelayConstraints, AdditionalWireguardConstraints, RelaySelector, SelectorConfig,
 };
@@ -50,18 +80,34 @@ use mullvad_types::{
     access_method::{AccessMethod, AccessMethodSetting},
     account::{AccountData, AccountToken, VoucherSubmission},
     auth_failed::AuthFailed,
+    connection_diagnosis::{BlockedReason, ClockSkew},
     custom_list::CustomList,
-    device::{Device, DeviceEvent, DeviceEventCause, DeviceId, DeviceState, RemoveDeviceEvent},
-    location::{GeoIpLocation, LocationEventData},
+    device::{
+        Device, DeviceEvent, DeviceEventCause, DeviceId, DeviceState, LoginOutcome,
+        RemoveDeviceEvent,
+    },
+    health::DaemonHealth,
+    location::{ConnectionVerification, CountryCode, GeoIpLocation, LocationEventData},
+    problem_report::{ProblemReportProgress, ProblemReportRequest},
     relay_constraints::{
-        BridgeSettings, BridgeState, BridgeType, ObfuscationSettings, RelayOverride, RelaySettings,
+        BridgeSettings, BridgeState, BridgeType, GeographicLocationConstraint, IpVersionPreference,
+        ObfuscationSettings, RelayOverride, RelaySettings, RemovedRelayPolicy,
+    },
+    relay_list::{RelayLatency, RelayList, RelayListDiff},
+    settings::{
+        ConnectionCheckSettings, CustomApiEndpoint, DnsOptions, DnsState, Settings, SettingsLock,
+        SettingsLockState, TunnelProtocolFallback, WebhookSettings, MIN_CONNECT_DEADLINE,
+    },
+    shutdown::ShutdownBehavior,
+    states::{
+        ConnectEscalation, LastConnectedRelay, LockdownCountdown, PersistedTarget, ShutdownReport,
+        TargetState, TunnelState,
     },
-    relay_list::RelayList,
-    settings::{DnsOptions, Settings},
-    states::{TargetState, TunnelState},
+    traffic::TrafficStats,
     version::{AppVersion, AppVersionInfo},
-    wireguard::{PublicKey, QuantumResistantState, RotationInterval},
+    wireguard::{KeyRotationProgress, PublicKey, QuantumResistantState, RotationInterval},
 };
+use reconnect_coalescer::{ReconnectAction, ReconnectCoalescer};
 use relay_list::{RelayListUpdater, RelayListUpdaterHandle, RELAYS_FILENAME};
 use settings::SettingsPersister;
 #[cfg(any(windows, target_os = "android", target_os = "macos"))]
@@ -69,12 +115,14 @@ use std::collections::HashSet;
 #[cfg(target_os = "android")]
 use std::os::unix::io::RawFd;
 use std::{
+    collections::{BTreeMap, HashMap},
     marker::PhantomData,
     mem,
+    net::SocketAddr,
     path::PathBuf,
     pin::Pin,
     sync::{Arc, Weak},
-    time::Duration,
+    time::{Duration, Instant, SystemTime},
 };
 use talpid_core::split_tunnel;
 use talpid_core::{
@@ -83,20 +131,25 @@ use talpid_core::{
 };
 #[cfg(target_os = "android")]
 use talpid_types::android::AndroidContext;
+#[cfg(not(target_os = "android"))]
+use talpid_types::self_test::SelfTestStep;
 #[cfg(target_os = "windows")]
 use talpid_types::split_tunnel::ExcludedProcess;
 use talpid_types::{
-    net::{IpVersion, TunnelEndpoint, TunnelType},
-    tunnel::{ErrorStateCause, TunnelStateTransition},
+    net::{wireguard::PrivateKey, IpVersion, TunnelEndpoint, TunnelType},
+    tunnel::{ConnectProgress, ConnectTrace, ErrorStateCause, TunnelStateTransition},
     ErrorExt,
 };
 #[cfg(any(target_os = "macos", target_os = "linux"))]
 use tokio::fs;
 use tokio::io;
 
-/// Delay between generating a new WireGuard key and reconnecting
+/// Delay between generating a new WireGuard key and using it
 const WG_RECONNECT_DELAY: Duration = Duration::from_secs(4 * 60);
 
+/// Longest allowed duration of a debug log capture.
+const MAX_DEBUG_CAPTURE_DURATION: Duration = Duration::from_secs(5 * 60);
+
 pub type ResponseTx<T, E> = oneshot::Sender<Result<T, E>>;
 
 #[derive(thiserror::Error, Debug)]
@@ -182,13 +235,84 @@ pub enum Error {
 
     #[error("API connection mode error")]
     ApiConnectionModeError(#[source] api::Error),
+
+    #[error("Traffic accounting error")]
+    TrafficAccountingError(#[source] traffic_accounting::Error),
+
+    #[error("Debug log capture failed")]
+    DebugCaptureError(#[source] logging::Error),
+
     #[error("No custom bridge has been specified")]
     NoCustomProxySaved,
 
+    #[error("Invalid custom bridge chain")]
+    InvalidProxyChain(#[source] talpid_types::net::proxy::Error),
+
+    #[error("Failed to import the relay list")]
+    ImportRelayList(#[source] mullvad_api::RelayListImportError),
+
+    #[error("Relay list updater error")]
+    RelayListUpdater(#[source] relay_list::Error),
+
+    #[error("Invalid webhook")]
+    InvalidWebhook(#[source] webhook::Error),
+
+    #[error("Invalid connection check host: {0}")]
+    InvalidConnectionCheckHost(String),
+
+    #[error("Custom API endpoints are not allowed by the daemon config")]
+    CustomApiEndpointNotAllowed,
+
+    #[error("Invalid custom API endpoint")]
+    InvalidCustomApiEndpoint(#[source] mullvad_api::custom_endpoint::Error),
+
+    #[error(
+        "The connect deadline must be at least {} seconds",
+        MIN_CONNECT_DEADLINE
+    )]
+    InvalidConnectDeadline,
+
+    #[error("Invalid settings document")]
+    InvalidSettingsDocument(#[source] settings::apply::Error),
+
+    #[error("Relays can only be pinged while disconnected and not in lockdown mode")]
+    PingRelaysUnavailable,
+
+    #[cfg(not(target_os = "android"))]
+    #[error("The self test can only run while disconnected")]
+    SelfTestUnavailable,
+
+    #[error("The connection can only be diagnosed while disconnected and not in lockdown mode")]
+    DiagnosisUnavailable,
+
+    #[cfg(not(target_os = "android"))]
+    #[error("Failed to select an OpenVPN relay")]
+    SelectOpenVpnRelay(#[source] mullvad_relay_selector::Error),
+
+    #[cfg(not(target_os = "android"))]
+    #[error("OpenVPN configurations cannot be exported for custom relays")]
+    ExportCustomRelay,
+
+    #[cfg(not(target_os = "android"))]
+    #[error("Failed to export the OpenVPN configuration")]
+    ExportOpenVpnConfig(#[source] talpid_openvpn::Error),
+
     #[cfg(target_os = "macos")]
     #[error("Failed to set exclusion group")]
     GroupIdError(#[source] io::Error),
 
+    #[cfg(windows)]
+    #[error("Failed to remove stale firewall filters")]
+    CleanupFirewall(#[source] talpid_core::firewall::Error),
+
+    #[cfg(target_os = "macos")]
+    #[error("Failed to read the loaded firewall rules")]
+    ReadFirewallRules(#[source] io::Error),
+
+    #[cfg(target_os = "macos")]
+    #[error("Failed to check early boot blocking")]
+    EarlyBootBlocking(#[source] early_boot_blocking::Error),
+
     #[cfg(target_os = "android")]
     #[error("Failed to initialize play purchase")]
     InitPlayPurchase(#[source] device::Error),
@@ -206,7 +330,15 @@ pub enum DaemonCommand {
     Reconnect(oneshot::Sender<bool>),
     /// Request the current state.
     GetState(oneshot::Sender<TunnelState>),
-    CreateNewAccount(ResponseTx<String, Error>),
+    /// Request the timings of the most recent connection attempt that succeeded.
+    GetConnectTrace(oneshot::Sender<Option<ConnectTrace>>),
+    /// Request the diagnostics that were collected when the error state was last entered.
+    GetLastErrorDiagnostics(oneshot::Sender<Option<String>>),
+    /// Request the target state and relay that a restarted daemon would resume.
+    GetPersistedTarget(oneshot::Sender<PersistedTarget>),
+    /// Request how restoring the network went when the daemon last shut down.
+    GetLastShutdownReport(oneshot::Sender<Option<ShutdownReport>>),
+    CreateNewAccount(ResponseTx<AccountToken, Error>),
     /// Request the metadata for an account.
     GetAccountData(
         ResponseTx<AccountData, mullvad_api::rest::Error>,
@@ -225,8 +357,20 @@ pub enum DaemonCommand {
     /// Trigger an asynchronous relay list update. This returns before the relay list is actually
     /// updated.
     UpdateRelayLocations,
+    /// Look up the location of the current tunnel state again, without verifying the connection.
+    RefreshLocation,
+    /// Replace the relay list with a signed one that was obtained out-of-band. The first string
+    /// is the relay list, and the second one is its signature.
+    ImportRelayList(ResponseTx<(), Error>, String, String),
     /// Log in with a given account and create a new device.
     LoginAccount(ResponseTx<(), Error>, AccountToken),
+    /// Log in with a given account and create a new device, after removing the given device from
+    /// the account. If the account has too many devices, they are returned instead.
+    LoginAndRegisterDevice(
+        ResponseTx<LoginOutcome, Error>,
+        AccountToken,
+        Option<DeviceId>,
+    ),
     /// Log out of the current account and remove the device, if they exist.
     LogoutAccount(ResponseTx<(), Error>),
     /// Return the current device configuration.
@@ -243,23 +387,58 @@ pub enum DaemonCommand {
     SetAllowLan(ResponseTx<(), settings::Error>, bool),
     /// Set the beta program setting.
     SetShowBetaReleases(ResponseTx<(), settings::Error>, bool),
+    /// Set where to post tunnel state changes. `None` disables the webhook.
+    SetWebhook(ResponseTx<(), Error>, Option<WebhookSettings>),
+    /// Set whether to announce the tunnel state on the local network.
+    SetLanBeacon(ResponseTx<(), settings::Error>, bool),
+    /// Set the API deployment to use instead of the Mullvad API. `None` goes back to the Mullvad
+    /// API.
+    SetCustomApiEndpoint(ResponseTx<(), Error>, Option<CustomApiEndpoint>),
+    /// Set the service used to look up the exit location and to check the connection.
+    SetConnectionCheckSettings(ResponseTx<(), Error>, ConnectionCheckSettings),
+    /// Set whether to fall back to OpenVPN on networks where WireGuard fails to connect.
+    SetTunnelProtocolFallback(ResponseTx<(), settings::Error>, TunnelProtocolFallback),
     /// Set the block_when_disconnected setting.
     SetBlockWhenDisconnected(ResponseTx<(), settings::Error>, bool),
+    /// Enable the block_when_disconnected setting after a grace period in seconds, if the tunnel
+    /// is disconnected.
+    EnableBlockWhenDisconnectedAfter(ResponseTx<(), settings::Error>, u32),
     /// Set the auto-connect setting.
     SetAutoConnect(ResponseTx<(), settings::Error>, bool),
     /// Set the mssfix argument for OpenVPN
     SetOpenVpnMssfix(ResponseTx<(), settings::Error>, Option<u16>),
+    /// Set the time to wait for a response from OpenVPN servers, in seconds
+    SetOpenVpnConnectTimeout(ResponseTx<(), settings::Error>, Option<u16>),
+    /// Set the time to wait before resending unacknowledged control packets to OpenVPN servers,
+    /// in seconds
+    SetOpenVpnRetryTimeout(ResponseTx<(), settings::Error>, Option<u16>),
+    /// Enable or disable the seccomp filter that confines OpenVPN
+    #[cfg(target_os = "linux")]
+    SetOpenVpnSandbox(ResponseTx<(), settings::Error>, bool),
     /// Set proxy details for OpenVPN
     SetBridgeSettings(ResponseTx<(), Error>, BridgeSettings),
     /// Set proxy state
     SetBridgeState(ResponseTx<(), settings::Error>, BridgeState),
+    /// Set what to do when the relay that is selected by its hostname is removed
+    SetRemovedRelayPolicy(ResponseTx<(), settings::Error>, RemovedRelayPolicy),
+    /// Set which IP version to connect to relays over, unless it is constrained
+    SetIpVersionPreference(ResponseTx<(), settings::Error>, IpVersionPreference),
     /// Set if IPv6 should be enabled in the tunnel
     SetEnableIpv6(ResponseTx<(), settings::Error>, bool),
+    /// Set how many seconds to wait for a connection attempt before moving on to the next
+    /// transport. `None` disables the deadline.
+    SetConnectDeadline(ResponseTx<(), Error>, Option<u16>),
     /// Set whether to enable PQ PSK exchange in the tunnel
     SetQuantumResistantTunnel(ResponseTx<(), settings::Error>, QuantumResistantState),
     /// Set DAITA settings for the tunnel
     #[cfg(target_os = "windows")]
     SetDaitaSettings(ResponseTx<(), settings::Error>, DaitaSettings),
+    /// Set the networks to route through the WireGuard tunnel. `None` routes everything
+    #[cfg(target_os = "linux")]
+    SetWireguardAllowedIps(ResponseTx<(), settings::Error>, Option<Vec<IpNetwork>>),
+    /// Set the interface to reach the relays through. `None` uses the default route
+    #[cfg(target_os = "linux")]
+    SetWireguardEgressInterface(ResponseTx<(), settings::Error>, Option<String>),
     /// Set DNS options or servers to use
     SetDnsOptions(ResponseTx<(), settings::Error>, DnsOptions),
     /// Set override options to use for a given relay
@@ -275,6 +454,8 @@ pub enum DaemonCommand {
     GetSettings(oneshot::Sender<Settings>),
     /// Generate new wireguard key
     RotateWireguardKey(ResponseTx<(), Error>),
+    /// Rotate wireguard key, reporting each step of the rotation
+    RotateWireguardKeyNow(mpsc::UnboundedSender<Result<KeyRotationProgress, Error>>),
     /// Return a public key of the currently set wireguard private key, if there is one
     GetWireguardKey(ResponseTx<Option<PublicKey>, Error>),
     /// Create custom list
@@ -285,6 +466,12 @@ pub enum DaemonCommand {
     UpdateCustomList(ResponseTx<(), Error>, CustomList),
     /// Remove all custom lists
     ClearCustomLists(ResponseTx<(), Error>),
+    /// Save the current relay, obfuscation and DNS settings as a named profile
+    SaveProfile(ResponseTx<(), Error>, String),
+    /// Apply the settings in a named profile
+    UseProfile(ResponseTx<(), Error>, String),
+    /// Delete a named profile
+    DeleteProfile(ResponseTx<(), Error>, String),
     /// Add API access methods
     AddApiAccessMethod(
         ResponseTx<mullvad_types::access_method::Id, Error>,
@@ -302,19 +489,39 @@ pub enum DaemonCommand {
     ClearCustomApiAccessMethods(ResponseTx<(), Error>),
     /// Get the currently used API access method
     GetCurrentAccessMethod(ResponseTx<AccessMethodSetting, Error>),
+    /// Get the address currently used to reach the API directly
+    GetApiAddress(oneshot::Sender<SocketAddr>),
     /// Test an API access method
-    TestApiAccessMethodById(ResponseTx<bool, Error>, mullvad_types::access_method::Id),
+    TestApiAccessMethodById(
+        ResponseTx<mullvad_types::access_method::TestResult, Error>,
+        mullvad_types::access_method::Id,
+    ),
     /// Test a custom API access method
     TestCustomApiAccessMethod(
-        ResponseTx<bool, Error>,
+        ResponseTx<mullvad_types::access_method::TestResult, Error>,
         talpid_types::net::proxy::CustomProxy,
     ),
     /// Get information about the currently running and latest app versions
     GetVersionInfo(oneshot::Sender<Option<AppVersionInfo>>),
     /// Return whether the daemon is performing post-upgrade tasks
     IsPerformingPostUpgrade(oneshot::Sender<bool>),
+    /// Check the status of the subsystems of the daemon
+    GetDaemonHealth(oneshot::Sender<DaemonHealth>),
     /// Get current version of the app
     GetCurrentVersion(oneshot::Sender<AppVersion>),
+    /// Start uploading a problem report. Returns the ID of the upload, which identifies its
+    /// progress events.
+    SendProblemReport(oneshot::Sender<u32>, ProblemReportRequest),
+    /// Return whether the settings lock is enabled, and for how long it is unlocked for the given
+    /// connection
+    GetSettingsLockState(oneshot::Sender<SettingsLockState>, Option<ConnectionId>),
+    /// Enable or disable the settings lock. The settings are locked right away
+    SetSettingsLock(ResponseTx<(), settings::Error>, Option<SettingsLock>),
+    /// Unlock the settings for a while for the given connection, if the lock has not been changed
+    /// since it was checked against the passphrase. Returns whether the settings were unlocked
+    UnlockSettings(oneshot::Sender<bool>, SettingsLock, ConnectionId),
+    /// Lock the settings for all connections before their unlocks expire
+    LockSettings(oneshot::Sender<()>),
     /// Remove settings and clear the cache
     #[cfg(not(target_os = "android"))]
     FactoryReset(ResponseTx<(), Error>),
@@ -348,11 +555,22 @@ pub enum DaemonCommand {
     /// Notify the split tunnel monitor that a volume was mounted or dismounted
     #[cfg(target_os = "windows")]
     CheckVolumes(ResponseTx<(), Error>),
+    /// Remove firewall filters left behind by older versions of the app
+    #[cfg(target_os = "windows")]
+    CleanupFirewall(ResponseTx<usize, Error>),
+    /// Return the firewall rules that are currently loaded
+    #[cfg(target_os = "macos")]
+    GetFirewallRules(ResponseTx<String, Error>),
+    /// Return whether traffic is blocked during early boot
+    #[cfg(target_os = "macos")]
+    IsEarlyBootBlockingInstalled(ResponseTx<bool, Error>),
     /// Register settings for WireGuard obfuscator
     SetObfuscationSettings(ResponseTx<(), settings::Error>, ObfuscationSettings),
     /// Saves the target tunnel state and enters a blocking state. The state is restored
     /// upon restart.
     PrepareRestart,
+    /// Shut the daemon down, leaving the tunnel and firewall as described by the behavior
+    Shutdown(ResponseTx<(), Error>, ShutdownBehavior),
     /// Causes a socket to bypass the tunnel. This has no effect when connected. It is only used
     /// to bypass the tunnel in blocking states.
     #[cfg(target_os = "android")]
@@ -363,10 +581,34 @@ pub enum DaemonCommand {
     /// Verify that a google play payment was successful through the API.
     #[cfg(target_os = "android")]
     VerifyPlayPurchase(ResponseTx<(), Error>, PlayPurchase),
+    /// Get the number of bytes sent and received over the tunnel interface, per month
+    GetTrafficStats(ResponseTx<TrafficStats, Error>),
     /// Patch the settings using a JSON patch
     ApplyJsonSettings(ResponseTx<(), settings::patch::Error>, String),
+    /// Replace the settings with those described by a JSON document, or only validate the
+    /// document if the boolean is true
+    ApplySettings(ResponseTx<(), Error>, String, bool),
     /// Return a JSON blob containing all overridable settings, if there are any
     ExportJsonSettings(ResponseTx<String, settings::patch::Error>),
+    /// Log the given modules at trace level for the given duration, and return the redacted
+    /// output. All modules are captured if none are given.
+    CaptureDebugLog(ResponseTx<String, Error>, Vec<String>, Duration),
+    /// Return an OpenVPN configuration file for the current relay, without any credentials
+    #[cfg(not(target_os = "android"))]
+    ExportOpenVpnConfig(ResponseTx<String, Error>),
+    /// Measure the latency to every active WireGuard relay, or to those in the given country.
+    /// Each result is stored in the relay selector and sent as soon as it is known.
+    PingRelays(
+        mpsc::UnboundedSender<Result<RelayLatency, Error>>,
+        Option<CountryCode>,
+    ),
+    /// Test the tunnel device, WireGuard, routing, DNS and firewall integration, and return the
+    /// outcome of each step
+    #[cfg(not(target_os = "android"))]
+    RunSelfTest(ResponseTx<Vec<SelfTestStep>, Error>),
+    /// Probe UDP, DNS and the API outside of any tunnel, and return the likely reasons that
+    /// connecting fails
+    DiagnoseConnection(ResponseTx<Vec<BlockedReason>, Error>),
 }
 
 /// All events that can happen in the daemon. Sent from various threads and exposed interfaces.
@@ -391,9 +633,32 @@ pub(crate) enum InternalDaemonEvent {
     DeviceMigrationEvent(Result<PrivateAccountAndDevice, device::Error>),
     /// A geographical location has has been received from am.i.mullvad.net
     LocationEvent(LocationEventData),
+    /// The connection has been checked against am.i.mullvad.net after connecting.
+    ConnectionVerified {
+        request_id: usize,
+        verification: ConnectionVerification,
+    },
     /// The split tunnel paths or state were updated.
     #[cfg(any(windows, target_os = "android", target_os = "macos"))]
     ExcludedPathsEvent(ExcludedPathsUpdate, oneshot::Sender<Result<(), Error>>),
+    /// A reconnect that was delayed to coalesce rapid settings changes is due.
+    DelayedSettingsReconnect,
+    /// The tunnel has been connecting to the endpoint for longer than the connect deadline.
+    ConnectDeadlineExpired(TunnelEndpoint),
+    /// The time that the tunnel was kept up for during shutdown has passed.
+    ShutdownGracePeriodElapsed,
+    /// A second of the grace period before lockdown mode is enabled has passed.
+    LockdownGracePeriodTick {
+        /// ID of the countdown that the tick belongs to.
+        id: u32,
+        /// Number of seconds that remain.
+        remaining: u32,
+    },
+    /// The relay list was updated, and some relays were added, removed or changed.
+    RelayListUpdated(RelayListDiff),
+    /// The custom DNS blocklists were updated.
+    #[cfg(not(target_os = "android"))]
+    DnsBlocklist(Option<Arc<talpid_types::net::dns::Blocklist>>),
 }
 
 #[cfg(any(windows, target_os = "android", target_os = "macos"))]
@@ -596,6 +861,17 @@ pub trait EventListener {
     /// Notify that the relay list changed.
     fn notify_relay_list(&self, relay_list: RelayList);
 
+    /// Notify which relays were added, removed or changed by a relay list update.
+    fn notify_relay_list_diff(&self, diff: RelayListDiff);
+
+    /// Notify that a relay that was selected by its hostname was removed from the relay list.
+    /// `replacement` is the location that is used instead, if the location was changed.
+    fn notify_pinned_relay_removed(
+        &self,
+        hostname: String,
+        replacement: Option<GeographicLocationConstraint>,
+    );
+
     /// Notify that info about the latest available app version changed.
     /// Or some flag about the currently running version is changed.
     fn notify_app_version(&self, app_version_info: AppVersionInfo);
@@ -608,10 +884,39 @@ pub trait EventListener {
 
     /// Notify that the api access method changed.
     fn notify_new_access_method_event(&self, new_access_method: AccessMethodSetting);
+
+    /// Notify that the connection was checked after connecting.
+    fn notify_connection_verified(&self, verification: ConnectionVerification);
+
+    /// Notify that a connection attempt was abandoned because it exceeded the connect deadline.
+    fn notify_connect_escalation(&self, escalation: ConnectEscalation);
+
+    /// Notify that a problem report upload made progress or changed state.
+    fn notify_problem_report_progress(&self, progress: ProblemReportProgress);
+
+    /// Notify that a phase of the current connection attempt was started or finished.
+    fn notify_connect_progress(&self, progress: ConnectProgress);
+
+    /// Notify that the system clock was found to differ from the clock of the API.
+    fn notify_clock_skew_detected(&self, skew: ClockSkew);
+
+    /// Notify that the grace period before lockdown mode is enabled progressed, ended or was
+    /// cancelled.
+    fn notify_lockdown_countdown(&self, countdown: LockdownCountdown);
 }
 
 pub struct Daemon<L: EventListener> {
     tunnel_state: TunnelState,
+    last_connect_trace: Option<ConnectTrace>,
+    #[cfg(not(target_os = "android"))]
+    error_diagnostics: error_diagnostics::ErrorDiagnostics,
+    /// How restoring the network went when the previous instance of the daemon shut down.
+    last_shutdown_report: Option<ShutdownReport>,
+    /// Whether traffic is blocked because the previous daemon was upgraded, until the target state
+    /// has been entered.
+    adopted_upgrade_handoff: bool,
+    /// Whether the daemon is about to be upgraded, in which case traffic is blocked on shutdown.
+    upgrade_handoff_prepared: bool,
     target_state: PersistentTargetState,
     state: DaemonExecutionState,
     #[cfg(target_os = "linux")]
@@ -619,6 +924,16 @@ pub struct Daemon<L: EventListener> {
     rx: mpsc::UnboundedReceiver<InternalDaemonEvent>,
     tx: DaemonEventSender,
     reconnection_job: Option<AbortHandle>,
+    settings_reconnects: ReconnectCoalescer,
+    connect_deadline_job: Option<AbortHandle>,
+    /// Number of connection attempts abandoned since the tunnel started connecting.
+    connect_escalations: u32,
+    relay_ping_job: Option<AbortHandle>,
+    /// ID of the next problem report upload.
+    next_problem_report_id: u32,
+    /// Connections that have unlocked the settings with the passphrase, and when the settings lock
+    /// takes effect again for each of them.
+    settings_unlocked_until: HashMap<ConnectionId, Instant>,
     event_listener: L,
     migration_complete: migrations::MigrationComplete,
     settings: SettingsPersister,
@@ -632,11 +947,29 @@ pub struct Daemon<L: EventListener> {
     relay_selector: RelaySelector,
     relay_list_updater: RelayListUpdaterHandle,
     parameters_generator: tunnel::ParametersGenerator,
+    host_cache: host_cache::HostCache,
+    #[cfg(not(target_os = "android"))]
+    resource_dir: PathBuf,
+    cache_dir: PathBuf,
     shutdown_tasks: Vec<Pin<Box<dyn Future<Output = ()>>>>,
+    /// Used when the daemon is shut down by a signal rather than a `Shutdown` command.
+    shutdown_behavior: ShutdownBehavior,
+    shutdown_grace_period: Option<AbortHandle>,
+    /// Counts down the grace period before lockdown mode is enabled. Holds the ID of the
+    /// countdown, since ticks from a cancelled countdown may already be queued.
+    lockdown_grace_period: Option<(u32, AbortHandle)>,
+    /// ID of the next lockdown grace period countdown.
+    next_lockdown_grace_period_id: u32,
     tunnel_state_machine_handle: TunnelStateMachineHandle,
     #[cfg(target_os = "windows")]
     volume_update_tx: mpsc::UnboundedSender<()>,
     location_handler: GeoIpHandler,
+    traffic_accountant: traffic_accounting::TrafficAccountantHandle,
+    webhook_notifier: webhook::WebhookNotifierHandle,
+    #[cfg(all(unix, not(target_os = "android")))]
+    lan_beacon: lan_beacon::LanBeaconHandle,
+    #[cfg(not(target_os = "android"))]
+    certificate_store: certificates::CertificateStore,
 }
 
 impl<L> Daemon<L>
@@ -655,15 +988,21 @@ where
         #[cfg(target_os = "macos")]
         macos::bump_filehandle_limit();
 
+        for env_override in runtime_config::active_overrides().overrides {
+            log::info!("Environment override: {env_override}");
+        }
+
         mullvad_api::proxy::ApiConnectionMode::try_delete_cache(&cache_dir).await;
 
         let (internal_event_tx, internal_event_rx) = command_channel.destructure();
 
+        // Must be loaded before the API is used, since the update may replace its root certificates
+        let certificate_store = certificates::CertificateStore::load(cache_dir.clone()).await;
+
         let api_runtime = mullvad_api::Runtime::with_cache(
             &cache_dir,
             true,
-            #[cfg(target_os = "android")]
-            api::create_bypass_tx(&internal_event_tx),
+            api::socket_protection(&internal_event_tx),
         )
         .await
         .map_err(Error::InitRpcFactory)?;
@@ -671,6 +1010,7 @@ where
         let api_availability = api_runtime.availability_handle();
         api_availability.suspend();
 
+        SettingsPersister::restore_if_corrupt(&settings_dir).await;
         let migration_data = migrations::migrate_all(&cache_dir, &settings_dir)
             .await
             .unwrap_or_else(|error| {
@@ -688,6 +1028,16 @@ where
             settings_event_listener.notify_settings(settings.to_owned());
         });
 
+        if let Some(endpoint) = &settings.custom_api_endpoint {
+            match validate_custom_api_endpoint(endpoint) {
+                Ok(endpoint) => mullvad_api::custom_endpoint::set(Some(endpoint)),
+                Err(error) => log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Ignoring the custom API endpoint")
+                ),
+            }
+        }
+
         let initial_selector_config = new_selector_config(&settings);
         let relay_selector = RelaySelector::new(
             initial_selector_config,
@@ -703,9 +1053,12 @@ where
                 .set_config(new_selector_config(settings));
         });
 
+        let host_cache = host_cache::HostCache::new(&cache_dir).await;
+
         let (access_mode_handler, access_mode_provider) = api::AccessModeSelector::spawn(
             cache_dir.clone(),
             relay_selector.clone(),
+            host_cache.clone(),
             settings.api_access_methods.clone(),
             internal_event_tx.to_specialized_sender(),
             api_runtime.address_cache().clone(),
@@ -784,8 +1137,52 @@ where
             account_manager.clone(),
             relay_selector.clone(),
             settings.tunnel_options.clone(),
+            #[cfg(not(target_os = "android"))]
+            certificate_store.clone(),
+            host_cache.clone(),
+            #[cfg(not(target_os = "android"))]
+            tunnel_protocol_fallback::FallbackSelector::new(
+                &cache_dir,
+                settings.wireguard_attempts_before_fallback(),
+            )
+            .await,
+            target_state
+                .relay()
+                .filter(|_| *target_state == TargetState::Secured)
+                .cloned(),
         );
 
+        let webhook_notifier = webhook::WebhookNotifier::spawn(settings.webhook.clone());
+        let settings_webhook_notifier = webhook_notifier.clone();
+        settings.register_change_listener(move |settings| {
+            settings_webhook_notifier.set_settings(settings.webhook.clone());
+        });
+
+        #[cfg(all(unix, not(target_os = "android")))]
+        let lan_beacon = {
+            let lan_beacon =
+                lan_beacon::LanBeacon::spawn(settings.lan_beacon && settings.allow_lan);
+            let settings_lan_beacon = lan_beacon.clone();
+            settings.register_change_listener(move |settings| {
+                settings_lan_beacon.set_enabled(settings.lan_beacon && settings.allow_lan);
+            });
+            lan_beacon
+        };
+
+        #[cfg(not(target_os = "android"))]
+        {
+            let blocklist_updater = blocklist::BlocklistUpdater::spawn(
+                dns::blocklists_from_options(&settings.tunnel_options.dns_options),
+                &cache_dir,
+                internal_event_tx.clone(),
+            );
+            settings.register_change_listener(move |settings| {
+                blocklist_updater.set_sources(dns::blocklists_from_options(
+                    &settings.tunnel_options.dns_options,
+                ));
+            });
+        }
+
         let param_gen = parameters_generator.clone();
         let (param_gen_tx, mut param_gen_rx) = mpsc::unbounded();
         tokio::spawn(async move {
@@ -797,46 +1194,126 @@ where
             let _ = param_gen_tx.unbounded_send(settings.tunnel_options.to_owned());
         });
 
+        #[cfg(not(target_os = "android"))]
+        {
+            let param_gen = parameters_generator.clone();
+            let (fallback_tx, mut fallback_rx) = mpsc::unbounded();
+            tokio::spawn(async move {
+                while let Some(wireguard_attempts) = fallback_rx.next().await {
+                    param_gen
+                        .set_wireguard_attempts_before_fallback(wireguard_attempts)
+                        .await;
+                }
+            });
+            settings.register_change_listener(move |settings| {
+                let _ = fallback_tx.unbounded_send(settings.wireguard_attempts_before_fallback());
+            });
+        }
+
+        #[cfg(target_os = "macos")]
+        {
+            let (early_boot_tx, early_boot_rx) = mpsc::unbounded();
+            tokio::spawn(early_boot_blocking::run(early_boot_rx));
+            let _ = early_boot_tx.unbounded_send(early_boot_blocking::Config::from(&*settings));
+            settings.register_change_listener(move |settings| {
+                let _ = early_boot_tx.unbounded_send(early_boot_blocking::Config::from(settings));
+            });
+        }
+
+        let pre_resolve_cache = host_cache.clone();
+        let (pre_resolve_tx, mut pre_resolve_rx) = mpsc::unbounded();
+        tokio::spawn(async move {
+            while let Some(hosts) = pre_resolve_rx.next().await {
+                for host in hosts {
+                    pre_resolve_cache.pre_resolve(&host).await;
+                }
+            }
+        });
+        let send_hostnames = move |settings: &Settings| {
+            let _ = pre_resolve_tx.unbounded_send(hostnames_to_pre_resolve(settings));
+        };
+        send_hostnames(&settings);
+        settings.register_change_listener(send_hostnames);
+
+        // Keep blocking traffic if the previous daemon was blocking it while it was upgraded
+        let adopted_upgrade_handoff = upgrade_handoff::adopt(&cache_dir).await;
+
         let (offline_state_tx, offline_state_rx) = mpsc::unbounded();
+        let (connect_progress_tx, mut connect_progress_rx) = mpsc::unbounded();
         #[cfg(target_os = "windows")]
         let (volume_update_tx, volume_update_rx) = mpsc::unbounded();
         let tunnel_state_machine_handle = tunnel_state_machine::spawn(
             tunnel_state_machine::InitialTunnelState {
                 allow_lan: settings.allow_lan,
-                block_when_disconnected: settings.block_when_disconnected,
+                block_when_disconnected: settings.block_when_disconnected
+                    || adopted_upgrade_handoff,
                 dns_servers: dns::addresses_from_options(&settings.tunnel_options.dns_options),
                 allowed_endpoint: access_mode_handler
                     .get_current()
                     .await
                     .map_err(Error::ApiConnectionModeError)?
                     .endpoint,
-                reset_firewall: *target_state != TargetState::Secured,
+                reset_firewall: *target_state != TargetState::Secured && !adopted_upgrade_handoff,
+                #[cfg(target_os = "linux")]
+                block_on_unsupported_dns_manager: settings
+                    .tunnel_options
+                    .dns_options
+                    .block_on_unsupported_manager,
                 #[cfg(any(windows, target_os = "android", target_os = "macos"))]
                 exclude_paths,
             },
             parameters_generator.clone(),
+            Arc::new(talpid_core::tunnel::DefaultTunnelProvider),
             log_dir,
             resource_dir.clone(),
             internal_event_tx.to_specialized_sender(),
             offline_state_tx,
+            connect_progress_tx,
             #[cfg(target_os = "windows")]
             volume_update_rx,
             #[cfg(target_os = "android")]
             android_context,
             #[cfg(target_os = "linux")]
             tunnel_state_machine::LinuxNetworkingIdentifiers {
-                fwmark: mullvad_types::TUNNEL_FWMARK,
-                table_id: mullvad_types::TUNNEL_TABLE_ID,
+                fwmark: runtime_config::get().tunnel_fwmark,
+                table_id: runtime_config::get().tunnel_table_id,
             },
         )
         .await
         .map_err(Error::TunnelError)?;
 
+        #[cfg(not(target_os = "android"))]
+        parameters_generator
+            .set_route_manager(tunnel_state_machine_handle.route_manager().clone())
+            .await;
+
         api::forward_offline_state(api_availability.clone(), offline_state_rx);
 
+        let connect_progress_listener = event_listener.clone();
+        tokio::spawn(async move {
+            while let Some(progress) = connect_progress_rx.next().await {
+                connect_progress_listener.notify_connect_progress(progress);
+            }
+        });
+
+        let clock_skew_listener = event_listener.clone();
+        let mut clock_skew_rx = mullvad_api::clock_skew::subscribe();
+        tokio::spawn(async move {
+            while clock_skew_rx.changed().await.is_ok() {
+                let skew = *clock_skew_rx.borrow_and_update();
+                if let Some(skew) = skew {
+                    clock_skew_listener.notify_clock_skew_detected(skew);
+                }
+            }
+        });
+
         let relay_list_listener = event_listener.clone();
-        let on_relay_list_update = move |relay_list: &RelayList| {
+        let relay_list_event_tx = internal_event_tx.clone();
+        let on_relay_list_update = move |relay_list: &RelayList, diff: RelayListDiff| {
             relay_list_listener.notify_relay_list(relay_list.clone());
+            if !diff.is_empty() {
+                let _ = relay_list_event_tx.send(InternalDaemonEvent::RelayListUpdated(diff));
+            }
         };
 
         let mut relay_list_updater = RelayListUpdater::spawn(
@@ -851,7 +1328,8 @@ where
             api_availability.clone(),
             cache_dir.clone(),
             internal_event_tx.to_specialized_sender(),
-            settings.show_beta_releases,
+            version_check::update_channel(settings.show_beta_releases),
+            certificate_store.clone(),
         )
         .await;
 
@@ -863,11 +1341,20 @@ where
             internal_event_tx.clone().to_specialized_sender(),
         );
 
+        let traffic_accountant = traffic_accounting::TrafficAccountant::spawn(&cache_dir).await;
+        let last_shutdown_report = shutdown_report::load(&cache_dir).await;
+
         let daemon = Daemon {
             tunnel_state: TunnelState::Disconnected {
                 location: None,
-                locked_down: settings.block_when_disconnected,
+                locked_down: settings.block_when_disconnected || adopted_upgrade_handoff,
             },
+            last_connect_trace: None,
+            #[cfg(not(target_os = "android"))]
+            error_diagnostics: error_diagnostics::ErrorDiagnostics::default(),
+            last_shutdown_report,
+            adopted_upgrade_handoff,
+            upgrade_handoff_prepared: false,
             target_state,
             state: DaemonExecutionState::Running,
             #[cfg(target_os = "linux")]
@@ -875,6 +1362,12 @@ where
             rx: internal_event_rx,
             tx: internal_event_tx,
             reconnection_job: None,
+            settings_reconnects: ReconnectCoalescer::from_env(),
+            connect_deadline_job: None,
+            connect_escalations: 0,
+            relay_ping_job: None,
+            next_problem_report_id: 0,
+            settings_unlocked_until: HashMap::new(),
             event_listener,
             migration_complete,
             settings,
@@ -888,11 +1381,25 @@ where
             relay_selector,
             relay_list_updater,
             parameters_generator,
+            host_cache,
+            #[cfg(not(target_os = "android"))]
+            resource_dir,
+            cache_dir,
             shutdown_tasks: vec![],
+            shutdown_behavior: runtime_config::get().shutdown_behavior,
+            shutdown_grace_period: None,
+            lockdown_grace_period: None,
+            next_lockdown_grace_period_id: 0,
             tunnel_state_machine_handle,
             #[cfg(target_os = "windows")]
             volume_update_tx,
             location_handler,
+            traffic_accountant,
+            webhook_notifier,
+            #[cfg(all(unix, not(target_os = "android")))]
+            lan_beacon,
+            #[cfg(not(target_os = "android"))]
+            certificate_store,
         };
 
         api_availability.unsuspend();
@@ -914,6 +1421,9 @@ where
                 self.fetch_am_i_mullvad()
             }
         }
+        if self.adopted_upgrade_handoff {
+            self.release_upgrade_handoff().await;
+        }
 
         while let Some(event) = self.rx.next().await {
             self.handle_event(event).await;
@@ -926,14 +1436,28 @@ where
         Ok(())
     }
 
+    /// Stop blocking traffic on behalf of the previous daemon. The policy of the target state has
+    /// been requested by now, so lockdown mode can be restored to what the settings say without
+    /// letting anything through in between.
+    async fn release_upgrade_handoff(&mut self) {
+        self.adopted_upgrade_handoff = false;
+        if !self.settings.block_when_disconnected {
+            let (tx, _rx) = oneshot::channel();
+            self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(false, tx));
+        }
+        upgrade_handoff::release(&self.cache_dir).await;
+    }
+
     async fn finalize(self) {
-        let (event_listener, shutdown_tasks, api_runtime, tunnel_state_machine_handle) =
+        let (event_listener, shutdown_tasks, api_runtime, tunnel_state_machine_handle, cache_dir) =
             self.shutdown();
         for future in shutdown_tasks {
             future.await;
         }
 
-        tunnel_state_machine_handle.try_join().await;
+        if let Some(teardown) = tunnel_state_machine_handle.try_join().await {
+            shutdown_report::save(&cache_dir, teardown).await;
+        }
 
         drop(event_listener);
         drop(api_runtime);
@@ -955,6 +1479,7 @@ where
         Vec<LocalBoxFuture<'a, ()>>,
         mullvad_api::Runtime,
         TunnelStateMachineHandle,
+        PathBuf,
     ) {
         let Daemon {
             event_listener,
@@ -963,6 +1488,7 @@ where
             tunnel_state_machine_handle,
             target_state,
             account_manager,
+            cache_dir,
             ..
         } = self;
 
@@ -974,6 +1500,7 @@ where
             shutdown_tasks,
             api_runtime,
             tunnel_state_machine_handle,
+            cache_dir,
         )
     }
 
@@ -984,7 +1511,9 @@ where
                 self.handle_tunnel_state_transition(transition).await
             }
             Command(command) => self.handle_command(command).await,
-            TriggerShutdown(user_init_shutdown) => self.trigger_shutdown_event(user_init_shutdown),
+            TriggerShutdown(user_init_shutdown) => {
+                self.trigger_shutdown_event(user_init_shutdown, self.shutdown_behavior)
+            }
             NewAppVersionInfo(app_version_info) => {
                 self.handle_new_app_version_info(app_version_info);
             }
@@ -995,8 +1524,25 @@ where
             } => self.handle_access_method_event(event, endpoint_active_tx),
             DeviceMigrationEvent(event) => self.handle_device_migration_event(event),
             LocationEvent(location_data) => self.handle_location_event(location_data),
+            ConnectionVerified {
+                request_id,
+                verification,
+            } => self.handle_connection_verified(request_id, verification),
             #[cfg(any(windows, target_os = "android", target_os = "macos"))]
             ExcludedPathsEvent(update, tx) => self.handle_new_excluded_paths(update, tx).await,
+            DelayedSettingsReconnect => self.handle_delayed_settings_reconnect(),
+            ConnectDeadlineExpired(endpoint) => self.handle_connect_deadline_expired(endpoint),
+            ShutdownGracePeriodElapsed => {
+                log::debug!("Shutting down after keeping the tunnel up");
+                self.shutdown_grace_period = None;
+                self.trigger_shutdown_event(true, ShutdownBehavior::Block);
+            }
+            LockdownGracePeriodTick { id, remaining } => {
+                self.handle_lockdown_grace_period_tick(id, remaining).await
+            }
+            RelayListUpdated(diff) => self.handle_relay_list_updated(diff).await,
+            #[cfg(not(target_os = "android"))]
+            DnsBlocklist(blocklist) => self.handle_dns_blocklist(blocklist),
         }
     }
 
@@ -1014,12 +1560,20 @@ where
                 locked_down,
             },
             TunnelStateTransition::Connecting(endpoint) => TunnelState::Connecting {
+                location: self.parameters_generator.get_last_location(&endpoint).await,
                 endpoint,
-                location: self.parameters_generator.get_last_location().await,
             },
-            TunnelStateTransition::Connected(endpoint) => {
-                let location = self.parameters_generator.get_last_location().await;
-                TunnelState::Connected { endpoint, location }
+            TunnelStateTransition::Connected(endpoint, connect_trace) => {
+                let location = self.parameters_generator.get_last_location(&endpoint).await;
+                self.last_connect_trace = Some(connect_trace.clone());
+                self.target_state
+                    .set_relay(LastConnectedRelay::new(&endpoint, location.as_ref()))
+                    .await;
+                TunnelState::Connected {
+                    endpoint,
+                    location,
+                    connect_trace,
+                }
             }
             TunnelStateTransition::Disconnecting(after_disconnect) => {
                 TunnelState::Disconnecting(after_disconnect)
@@ -1034,11 +1588,43 @@ where
             self.unschedule_reconnect();
         }
 
+        match &tunnel_state {
+            TunnelState::Connecting { endpoint, .. } => {
+                self.schedule_connect_deadline(endpoint.clone())
+            }
+            // Abandoning an attempt passes through the disconnecting state
+            TunnelState::Disconnecting(_) => self.unschedule_connect_deadline(),
+            _ => {
+                self.unschedule_connect_deadline();
+                self.connect_escalations = 0;
+            }
+        }
+
         log::debug!("New tunnel state: {:?}", tunnel_state);
 
+        let tunnel_interface = match &tunnel_state {
+            TunnelState::Connected { endpoint, .. } => endpoint.tunnel_interface.clone(),
+            _ => None,
+        };
+        self.traffic_accountant
+            .set_interface(tunnel_interface)
+            .await;
+
+        if !matches!(
+            tunnel_state,
+            TunnelState::Disconnected {
+                locked_down: false,
+                ..
+            }
+        ) {
+            // Probes would be blocked by the firewall, making every relay look unreachable
+            self.abort_relay_ping();
+        }
+
         match tunnel_state {
             TunnelState::Disconnected { .. } => {
                 self.api_handle.availability.reset_inactivity_timer();
+                self.pre_resolve_hostnames();
             }
             _ => {
                 self.api_handle.availability.stop_inactivity_timer();
@@ -1063,6 +1649,9 @@ where
                     );
                 }
 
+                #[cfg(not(target_os = "android"))]
+                self.error_diagnostics.collect(error_state);
+
                 if let ErrorStateCause::AuthFailed(_) = error_state.cause() {
                     // If time is added outside of the app, no notifications
                     // are received. So we must continually try to reconnect.
@@ -1073,6 +1662,10 @@ where
         }
 
         self.tunnel_state = tunnel_state.clone();
+        self.webhook_notifier
+            .notify_tunnel_state(tunnel_state.clone());
+        #[cfg(all(unix, not(target_os = "android")))]
+        self.lan_beacon.notify_tunnel_state(&tunnel_state);
         self.event_listener.notify_new_state(tunnel_state);
         self.fetch_am_i_mullvad();
     }
@@ -1083,6 +1676,30 @@ where
     ///
     /// See [`Daemon::handle_location_event()`]
     fn fetch_am_i_mullvad(&mut self) {
+        self.fetch_location();
+
+        let settings = &self.settings.connection_check;
+        // Custom relays are not Mullvad relays, so there is nothing to verify them against
+        if let TunnelState::Connected { location, .. } = &self.tunnel_state {
+            if settings.verify_after_connect
+                && matches!(self.settings.relay_settings, RelaySettings::Normal(_))
+            {
+                let expected = ExpectedConnection {
+                    exit_hostname: location
+                        .as_ref()
+                        .and_then(|location| location.hostname.clone()),
+                    mullvad_dns: self.settings.tunnel_options.dns_options.state
+                        == DnsState::Default,
+                };
+                self.location_handler
+                    .send_connection_verification_request(settings, expected);
+            }
+        }
+    }
+
+    /// Get the geographical location from am.i.mullvad.net, falling back to the last known
+    /// location if it cannot be reached.
+    fn fetch_location(&mut self) {
         // Always abort any ongoing request when entering a new tunnel state
         self.location_handler.abort_current_request();
 
@@ -1108,7 +1725,17 @@ where
             _ => return,
         };
 
-        self.location_handler.send_geo_location_request(use_ipv6);
+        let exit_hostname = match &self.tunnel_state {
+            TunnelState::Connected { location, .. } => location
+                .as_ref()
+                .and_then(|location| location.hostname.clone()),
+            _ => None,
+        };
+        self.location_handler.send_geo_location_request(
+            use_ipv6,
+            &self.settings.connection_check,
+            exit_hostname,
+        );
     }
 
     /// Receives and handles the geographical exit location received from am.i.mullvad.net, i.e. the
@@ -1135,6 +1762,7 @@ where
                 *location = Some(GeoIpLocation {
                     ipv4: fetched_location.ipv4,
                     ipv6: fetched_location.ipv6,
+                    stale: fetched_location.stale,
                     ..location.clone().unwrap_or(fetched_location)
                 })
             }
@@ -1145,13 +1773,33 @@ where
             .notify_new_state(self.tunnel_state.clone());
     }
 
+    /// Receives the result of checking the connection after connecting, i.e. the
+    /// [`InternalDaemonEvent::ConnectionVerified`] event.
+    fn handle_connection_verified(
+        &mut self,
+        request_id: usize,
+        verification: ConnectionVerification,
+    ) {
+        if self.location_handler.request_id != request_id || !self.tunnel_state.is_connected() {
+            log::debug!("Connection verification belongs to an outdated tunnel state");
+            return;
+        }
+
+        if verification.is_verified() {
+            log::info!("Connection verified, exit IP: {}", verification.exit_ip);
+        } else {
+            log::warn!("Connection could not be verified: {:?}", verification);
+        }
+        self.event_listener.notify_connection_verified(verification);
+    }
+
     fn reset_rpc_sockets_on_tunnel_state_transition(
         &mut self,
         tunnel_state_transition: &TunnelStateTransition,
     ) {
         match (&self.tunnel_state, &tunnel_state_transition) {
             // Only reset the API sockets when entering or leaving the connected state
-            (&TunnelState::Connected { .. }, _) | (_, &TunnelStateTransition::Connected(_)) => {
+            (&TunnelState::Connected { .. }, _) | (_, &TunnelStateTransition::Connected(..)) => {
                 self.api_handle.service().reset();
             }
             _ => (),
@@ -1175,57 +1823,279 @@ where
         self.reconnection_job = Some(abort_handle);
     }
 
+    /// Give the tunnel a new private key after `delay`, once the relays know about it. If the
+    /// running tunnel can't switch keys, the daemon reconnects instead.
+    fn schedule_key_update(&mut self, private_key: PrivateKey, delay: Duration) {
+        self.unschedule_reconnect();
+
+        // Don't keep the state machine alive during shutdown
+        let tunnel_command_tx = Arc::downgrade(self.tunnel_state_machine_handle.command_tx());
+        let daemon_command_tx = self.tx.to_specialized_sender();
+        let (future, abort_handle) = abortable(Box::pin(async move {
+            tokio::time::sleep(delay).await;
+            let Some(tunnel_command_tx) = tunnel_command_tx.upgrade() else {
+                return;
+            };
+            let (result_tx, result_rx) = oneshot::channel();
+            let command = TunnelCommand::SetPrivateKey(private_key, result_tx);
+            let sent = tunnel_command_tx.unbounded_send(command).is_ok();
+            drop(tunnel_command_tx);
+            if sent && result_rx.await == Ok(true) {
+                return;
+            }
+            log::debug!("Attempting to reconnect to use the new key");
+            let (tx, rx) = oneshot::channel();
+            let _ = daemon_command_tx.send(DaemonCommand::Reconnect(tx));
+            // suppress "unable to send" warning:
+            let _ = rx.await;
+        }));
+
+        tokio::spawn(future);
+        self.reconnection_job = Some(abort_handle);
+    }
+
     fn unschedule_reconnect(&mut self) {
         if let Some(job) = self.reconnection_job.take() {
             job.abort();
         }
     }
 
-    async fn handle_command(&mut self, command: DaemonCommand) {
-        use self::DaemonCommand::*;
-        if !self.state.is_running() {
-            log::trace!("Dropping daemon command because the daemon is shutting down",);
+    /// Abandon the connection attempt if the tunnel has not connected to `endpoint` once the
+    /// connect deadline has passed. Does nothing if no deadline is set.
+    fn schedule_connect_deadline(&mut self, endpoint: TunnelEndpoint) {
+        self.unschedule_connect_deadline();
+
+        let Some(deadline) = self.settings.tunnel_options.connect_deadline else {
             return;
+        };
+        let daemon_tx = self.tx.clone();
+        let (future, abort_handle) = abortable(async move {
+            tokio::time::sleep(Duration::from_secs(u64::from(deadline))).await;
+            let _ = daemon_tx.send(InternalDaemonEvent::ConnectDeadlineExpired(endpoint));
+        });
+
+        tokio::spawn(future);
+        self.connect_deadline_job = Some(abort_handle);
+    }
+
+    fn unschedule_connect_deadline(&mut self) {
+        if let Some(job) = self.connect_deadline_job.take() {
+            job.abort();
         }
+    }
 
-        if self.tunnel_state.is_disconnected() {
-            self.api_handle.availability.reset_inactivity_timer();
+    /// Moves on to the next transport in the retry order, i.e. the next attempt of the same
+    /// connect request, since the tunnel did not connect to `endpoint` in time.
+    fn handle_connect_deadline_expired(&mut self, endpoint: TunnelEndpoint) {
+        self.connect_deadline_job = None;
+        match &self.tunnel_state {
+            TunnelState::Connecting {
+                endpoint: current, ..
+            } if *current == endpoint => (),
+            _ => {
+                log::debug!("Ignoring connect deadline of an outdated connection attempt");
+                return;
+            }
         }
 
-        match command {
-            SetTargetState(tx, state) => self.on_set_target_state(tx, state).await,
-            Reconnect(tx) => self.on_reconnect(tx),
-            GetState(tx) => self.on_get_state(tx),
-            CreateNewAccount(tx) => self.on_create_new_account(tx),
-            GetAccountData(tx, account_token) => self.on_get_account_data(tx, account_token),
-            GetWwwAuthToken(tx) => self.on_get_www_auth_token(tx).await,
-            SubmitVoucher(tx, voucher) => self.on_submit_voucher(tx, voucher),
-            GetRelayLocations(tx) => self.on_get_relay_locations(tx),
-            UpdateRelayLocations => self.on_update_relay_locations().await,
-            LoginAccount(tx, account_token) => self.on_login_account(tx, account_token),
-            LogoutAccount(tx) => self.on_logout_account(tx),
-            GetDevice(tx) => self.on_get_device(tx),
-            UpdateDevice(tx) => self.on_update_device(tx),
-            ListDevices(tx, account_token) => self.on_list_devices(tx, account_token),
-            RemoveDevice(tx, account_token, device_id) => {
-                self.on_remove_device(tx, account_token, device_id)
+        self.connect_escalations += 1;
+        log::info!(
+            "Failed to connect to {endpoint} within the connect deadline. Trying the next transport"
+        );
+        self.send_tunnel_command(TunnelCommand::Escalate);
+        self.event_listener
+            .notify_connect_escalation(ConnectEscalation {
+                endpoint,
+                escalation: self.connect_escalations,
+            });
+    }
+
+    /// Reconnects if a relay that the tunnel uses was removed or changed by a relay list update,
+    /// so that the relay selector can pick a valid relay instead of waiting for the connection to
+    /// fail.
+    async fn handle_relay_list_updated(&mut self, diff: RelayListDiff) {
+        log::info!(
+            "Relay list update added {}, removed {} and changed {} relays",
+            diff.added.len(),
+            diff.removed.len(),
+            diff.changed.len()
+        );
+        let migrated = self.migrate_removed_relays(&diff).await;
+
+        let location = match &self.tunnel_state {
+            TunnelState::Connecting { location, .. } | TunnelState::Connected { location, .. } => {
+                location.as_ref()
             }
-            GetAccountHistory(tx) => self.on_get_account_history(tx),
-            ClearAccountHistory(tx) => self.on_clear_account_history(tx).await,
-            SetRelaySettings(tx, update) => self.on_set_relay_settings(tx, update).await,
-            SetAllowLan(tx, allow_lan) => self.on_set_allow_lan(tx, allow_lan).await,
-            SetShowBetaReleases(tx, enabled) => self.on_set_show_beta_releases(tx, enabled).await,
-            SetBlockWhenDisconnected(tx, block_when_disconnected) => {
-                self.on_set_block_when_disconnected(tx, block_when_disconnected)
-                    .await
+            _ => None,
+        };
+        let invalidated = location.and_then(|location| {
+            [
+                &location.hostname,
+                &location.entry_hostname,
+                &location.bridge_hostname,
+                &location.obfuscator_hostname,
+            ]
+            .into_iter()
+            .flatten()
+            .find(|hostname| diff.invalidates(hostname))
+            .cloned()
+        });
+        self.event_listener.notify_relay_list_diff(diff);
+
+        if migrated {
+            return;
+        }
+        if let Some(hostname) = invalidated {
+            log::info!("Reconnecting since {hostname} was removed or changed in the relay list");
+            self.reconnect_tunnel();
+        }
+    }
+
+    /// Applies the removed relay policy if a relay that is selected by its hostname was removed.
+    /// Returns whether the location was changed, in which case the tunnel reconnects to the new
+    /// location.
+    async fn migrate_removed_relays(&mut self, diff: &RelayListDiff) -> bool {
+        let RelaySettings::Normal(mut constraints) = self.settings.get_relay_settings() else {
+            return false;
+        };
+        let policy = self.settings.removed_relay_policy;
+        let removed = constraints.migrate_removed_relays(policy, |hostname| {
+            diff.removed.iter().any(|entry| entry.hostname == hostname)
+        });
+        if removed.is_empty() {
+            return false;
+        }
+
+        let replaced = removed.iter().any(|(_, replacement)| replacement.is_some());
+        for (hostname, replacement) in removed {
+            match &replacement {
+                Some(location) => log::info!("{hostname} was removed. Using {location} instead"),
+                None => log::warn!("{hostname} was removed. Keeping it selected"),
+            }
+            self.event_listener
+                .notify_pinned_relay_removed(hostname, replacement);
+        }
+        if !replaced {
+            return false;
+        }
+
+        match self
+            .settings
+            .update(move |settings| settings.set_relay_settings(RelaySettings::Normal(constraints)))
+            .await
+        {
+            Ok(settings_changed) => {
+                if settings_changed {
+                    self.reconnect_tunnel_after_settings_change();
+                }
+                settings_changed
+            }
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to replace the removed relay")
+                );
+                false
+            }
+        }
+    }
+
+    /// Refreshes the addresses of the custom relay, custom bridges and access methods that are
+    /// referenced by hostname, while DNS is likely to be reachable.
+    fn pre_resolve_hostnames(&self) {
+        let host_cache = self.host_cache.clone();
+        let hosts = hostnames_to_pre_resolve(&self.settings);
+        tokio::spawn(async move {
+            for host in hosts {
+                host_cache.pre_resolve(&host).await;
+            }
+        });
+    }
+
+    async fn handle_command(&mut self, command: DaemonCommand) {
+        use self::DaemonCommand::*;
+        if !self.state.is_running() {
+            log::trace!("Dropping daemon command because the daemon is shutting down",);
+            return;
+        }
+
+        if self.tunnel_state.is_disconnected() {
+            self.api_handle.availability.reset_inactivity_timer();
+        }
+
+        match command {
+            SetTargetState(tx, state) => self.on_set_target_state(tx, state).await,
+            Reconnect(tx) => self.on_reconnect(tx),
+            GetState(tx) => self.on_get_state(tx),
+            GetConnectTrace(tx) => self.on_get_connect_trace(tx),
+            GetPersistedTarget(tx) => self.on_get_persisted_target(tx),
+            GetLastShutdownReport(tx) => self.on_get_last_shutdown_report(tx),
+            GetLastErrorDiagnostics(tx) => self.on_get_last_error_diagnostics(tx),
+            CreateNewAccount(tx) => self.on_create_new_account(tx),
+            GetAccountData(tx, account_token) => self.on_get_account_data(tx, account_token),
+            GetWwwAuthToken(tx) => self.on_get_www_auth_token(tx).await,
+            SubmitVoucher(tx, voucher) => self.on_submit_voucher(tx, voucher),
+            GetRelayLocations(tx) => self.on_get_relay_locations(tx),
+            UpdateRelayLocations => self.on_update_relay_locations().await,
+            RefreshLocation => self.fetch_location(),
+            ImportRelayList(tx, relay_list, signature) => {
+                self.on_import_relay_list(tx, relay_list, signature)
+            }
+            LoginAccount(tx, account_token) => self.on_login_account(tx, account_token),
+            LoginAndRegisterDevice(tx, account_token, revoke_device) => {
+                self.on_login_and_register_device(tx, account_token, revoke_device)
+            }
+            LogoutAccount(tx) => self.on_logout_account(tx),
+            GetDevice(tx) => self.on_get_device(tx),
+            UpdateDevice(tx) => self.on_update_device(tx),
+            ListDevices(tx, account_token) => self.on_list_devices(tx, account_token),
+            RemoveDevice(tx, account_token, device_id) => {
+                self.on_remove_device(tx, account_token, device_id)
+            }
+            GetAccountHistory(tx) => self.on_get_account_history(tx),
+            ClearAccountHistory(tx) => self.on_clear_account_history(tx).await,
+            SetRelaySettings(tx, update) => self.on_set_relay_settings(tx, update).await,
+            SetAllowLan(tx, allow_lan) => self.on_set_allow_lan(tx, allow_lan).await,
+            SetShowBetaReleases(tx, enabled) => self.on_set_show_beta_releases(tx, enabled).await,
+            SetWebhook(tx, webhook) => self.on_set_webhook(tx, webhook).await,
+            SetLanBeacon(tx, enabled) => self.on_set_lan_beacon(tx, enabled).await,
+            SetCustomApiEndpoint(tx, endpoint) => {
+                self.on_set_custom_api_endpoint(tx, endpoint).await
+            }
+            SetConnectionCheckSettings(tx, settings) => {
+                self.on_set_connection_check_settings(tx, settings).await
+            }
+            SetTunnelProtocolFallback(tx, fallback) => {
+                self.on_set_tunnel_protocol_fallback(tx, fallback).await
+            }
+            SetBlockWhenDisconnected(tx, block_when_disconnected) => {
+                self.on_set_block_when_disconnected(tx, block_when_disconnected)
+                    .await
+            }
+            EnableBlockWhenDisconnectedAfter(tx, grace_period) => {
+                self.on_enable_block_when_disconnected_after(tx, grace_period)
+                    .await
             }
             SetAutoConnect(tx, auto_connect) => self.on_set_auto_connect(tx, auto_connect).await,
             SetOpenVpnMssfix(tx, mssfix_arg) => self.on_set_openvpn_mssfix(tx, mssfix_arg).await,
+            SetOpenVpnConnectTimeout(tx, timeout) => {
+                self.on_set_openvpn_connect_timeout(tx, timeout).await
+            }
+            SetOpenVpnRetryTimeout(tx, timeout) => {
+                self.on_set_openvpn_retry_timeout(tx, timeout).await
+            }
+            #[cfg(target_os = "linux")]
+            SetOpenVpnSandbox(tx, enabled) => self.on_set_openvpn_sandbox(tx, enabled).await,
             SetBridgeSettings(tx, bridge_settings) => {
                 self.on_set_bridge_settings(tx, bridge_settings).await
             }
             SetBridgeState(tx, bridge_state) => self.on_set_bridge_state(tx, bridge_state).await,
+            SetRemovedRelayPolicy(tx, policy) => self.on_set_removed_relay_policy(tx, policy).await,
+            SetIpVersionPreference(tx, preference) => {
+                self.on_set_ip_version_preference(tx, preference).await
+            }
             SetEnableIpv6(tx, enable_ipv6) => self.on_set_enable_ipv6(tx, enable_ipv6).await,
+            SetConnectDeadline(tx, deadline) => self.on_set_connect_deadline(tx, deadline).await,
             SetQuantumResistantTunnel(tx, quantum_resistant_state) => {
                 self.on_set_quantum_resistant_tunnel(tx, quantum_resistant_state)
                     .await
@@ -1234,6 +2104,14 @@ where
             SetDaitaSettings(tx, daita_settings) => {
                 self.on_set_daita_settings(tx, daita_settings).await
             }
+            #[cfg(target_os = "linux")]
+            SetWireguardAllowedIps(tx, allowed_ips) => {
+                self.on_set_wireguard_allowed_ips(tx, allowed_ips).await
+            }
+            #[cfg(target_os = "linux")]
+            SetWireguardEgressInterface(tx, interface) => {
+                self.on_set_wireguard_egress_interface(tx, interface).await
+            }
             SetDnsOptions(tx, dns_servers) => self.on_set_dns_options(tx, dns_servers).await,
             SetRelayOverride(tx, relay_override) => {
                 self.on_set_relay_override(tx, relay_override).await
@@ -1245,11 +2123,15 @@ where
             }
             GetSettings(tx) => self.on_get_settings(tx),
             RotateWireguardKey(tx) => self.on_rotate_wireguard_key(tx),
+            RotateWireguardKeyNow(progress_tx) => self.on_rotate_wireguard_key_now(progress_tx),
             GetWireguardKey(tx) => self.on_get_wireguard_key(tx).await,
             CreateCustomList(tx, name) => self.on_create_custom_list(tx, name).await,
             DeleteCustomList(tx, id) => self.on_delete_custom_list(tx, id).await,
             UpdateCustomList(tx, update) => self.on_update_custom_list(tx, update).await,
             ClearCustomLists(tx) => self.on_clear_custom_lists(tx).await,
+            SaveProfile(tx, name) => self.on_save_profile(tx, name).await,
+            UseProfile(tx, name) => self.use_profile(tx, name).await,
+            DeleteProfile(tx, name) => self.on_delete_profile(tx, name).await,
             GetVersionInfo(tx) => self.on_get_version_info(tx),
             AddApiAccessMethod(tx, name, enabled, access_method) => {
                 self.on_add_access_method(tx, name, enabled, access_method)
@@ -1259,11 +2141,20 @@ where
             UpdateApiAccessMethod(tx, method) => self.on_update_api_access_method(tx, method).await,
             ClearCustomApiAccessMethods(tx) => self.on_clear_custom_api_access_methods(tx).await,
             GetCurrentAccessMethod(tx) => self.on_get_current_api_access_method(tx),
+            GetApiAddress(tx) => self.on_get_api_address(tx),
             SetApiAccessMethod(tx, method) => self.on_set_api_access_method(tx, method).await,
             TestApiAccessMethodById(tx, method) => self.on_test_api_access_method(tx, method).await,
-            TestCustomApiAccessMethod(tx, proxy) => self.on_test_proxy_as_access_method(tx, proxy),
+            TestCustomApiAccessMethod(tx, proxy) => {
+                self.on_test_proxy_as_access_method(tx, proxy).await
+            }
             IsPerformingPostUpgrade(tx) => self.on_is_performing_post_upgrade(tx),
+            GetDaemonHealth(tx) => self.on_get_daemon_health(tx),
             GetCurrentVersion(tx) => self.on_get_current_version(tx),
+            SendProblemReport(tx, request) => self.on_send_problem_report(tx, request),
+            GetSettingsLockState(tx, connection) => self.on_get_settings_lock_state(tx, connection),
+            SetSettingsLock(tx, lock) => self.on_set_settings_lock(tx, lock).await,
+            UnlockSettings(tx, lock, connection) => self.on_unlock_settings(tx, lock, connection),
+            LockSettings(tx) => self.on_lock_settings(tx),
             #[cfg(not(target_os = "android"))]
             FactoryReset(tx) => self.on_factory_reset(tx).await,
             #[cfg(target_os = "linux")]
@@ -1286,10 +2177,17 @@ where
             GetSplitTunnelProcesses(tx) => self.on_get_split_tunnel_processes(tx),
             #[cfg(target_os = "windows")]
             CheckVolumes(tx) => self.on_check_volumes(tx),
+            #[cfg(target_os = "windows")]
+            CleanupFirewall(tx) => self.on_cleanup_firewall(tx),
+            #[cfg(target_os = "macos")]
+            GetFirewallRules(tx) => self.on_get_firewall_rules(tx),
+            #[cfg(target_os = "macos")]
+            IsEarlyBootBlockingInstalled(tx) => self.on_is_early_boot_blocking_installed(tx),
             SetObfuscationSettings(tx, settings) => {
                 self.on_set_obfuscation_settings(tx, settings).await
             }
-            PrepareRestart => self.on_prepare_restart(),
+            PrepareRestart => self.on_prepare_restart().await,
+            Shutdown(tx, behavior) => self.on_shutdown(tx, behavior),
             #[cfg(target_os = "android")]
             BypassSocket(fd, tx) => self.on_bypass_socket(fd, tx),
             #[cfg(target_os = "android")]
@@ -1298,8 +2196,21 @@ where
             VerifyPlayPurchase(tx, play_purchase) => {
                 self.on_verify_play_purchase(tx, play_purchase)
             }
+            GetTrafficStats(tx) => self.on_get_traffic_stats(tx),
             ApplyJsonSettings(tx, blob) => self.on_apply_json_settings(tx, blob).await,
+            ApplySettings(tx, document, dry_run) => {
+                self.on_apply_settings(tx, document, dry_run).await
+            }
             ExportJsonSettings(tx) => self.on_export_json_settings(tx),
+            CaptureDebugLog(tx, modules, duration) => {
+                self.on_capture_debug_log(tx, modules, duration)
+            }
+            #[cfg(not(target_os = "android"))]
+            ExportOpenVpnConfig(tx) => self.on_export_openvpn_config(tx),
+            PingRelays(tx, country) => self.on_ping_relays(tx, country).await,
+            #[cfg(not(target_os = "android"))]
+            RunSelfTest(tx) => self.on_run_self_test(tx),
+            DiagnoseConnection(tx) => self.on_diagnose_connection(tx),
         }
     }
 
@@ -1307,6 +2218,12 @@ where
         self.event_listener.notify_app_version(app_version_info);
     }
 
+    #[cfg(not(target_os = "android"))]
+    fn handle_dns_blocklist(&mut self, blocklist: Option<Arc<talpid_types::net::dns::Blocklist>>) {
+        let (tx, _rx) = oneshot::channel();
+        self.send_tunnel_command(TunnelCommand::DnsBlocklist(blocklist, tx));
+    }
+
     async fn handle_device_event(&mut self, event: AccountEvent) {
         match &event {
             AccountEvent::Device(PrivateDeviceEvent::Login(device)) => {
@@ -1332,9 +2249,12 @@ where
                     self.connect_tunnel();
                 }
             }
-            AccountEvent::Device(PrivateDeviceEvent::RotatedKey(_)) => {
+            AccountEvent::Device(PrivateDeviceEvent::RotatedKey(config)) => {
                 if self.get_target_tunnel_type() == Some(TunnelType::Wireguard) {
-                    self.schedule_reconnect(WG_RECONNECT_DELAY);
+                    self.schedule_key_update(
+                        config.device.wg_data.private_key.clone(),
+                        WG_RECONNECT_DELAY,
+                    );
                 }
             }
             AccountEvent::Expiry(expiry) if *self.target_state == TargetState::Secured => {
@@ -1472,6 +2392,7 @@ where
 
     fn on_reconnect(&mut self, tx: oneshot::Sender<bool>) {
         if *self.target_state == TargetState::Secured || self.tunnel_state.is_in_error_state() {
+            self.api_handle.service().cancel_background_requests();
             self.connect_tunnel();
             Self::oneshot_send(tx, true, "reconnect issued");
         } else {
@@ -1484,12 +2405,80 @@ where
         Self::oneshot_send(tx, self.tunnel_state.clone(), "current state");
     }
 
+    fn on_get_connect_trace(&self, tx: oneshot::Sender<Option<ConnectTrace>>) {
+        Self::oneshot_send(tx, self.last_connect_trace.clone(), "connect trace");
+    }
+
+    fn on_get_persisted_target(&self, tx: oneshot::Sender<PersistedTarget>) {
+        Self::oneshot_send(tx, self.target_state.persisted(), "persisted target");
+    }
+
+    fn on_get_last_shutdown_report(&self, tx: oneshot::Sender<Option<ShutdownReport>>) {
+        Self::oneshot_send(
+            tx,
+            self.last_shutdown_report.clone(),
+            "last shutdown report",
+        );
+    }
+
+    fn on_get_last_error_diagnostics(&self, tx: oneshot::Sender<Option<String>>) {
+        #[cfg(not(target_os = "android"))]
+        let report = self.error_diagnostics.last_report();
+        #[cfg(target_os = "android")]
+        let report = None;
+        Self::oneshot_send(tx, report, "last error diagnostics");
+    }
+
     fn on_is_performing_post_upgrade(&self, tx: oneshot::Sender<bool>) {
         let performing_post_upgrade = !self.migration_complete.is_complete();
         Self::oneshot_send(tx, performing_post_upgrade, "performing post upgrade");
     }
 
-    fn on_create_new_account(&mut self, tx: ResponseTx<String, Error>) {
+    fn on_get_daemon_health(&self, tx: oneshot::Sender<DaemonHealth>) {
+        use mullvad_types::health::{
+            ComponentHealth, API_CONNECTIVITY, DNS, FIREWALL, RELAY_LIST, ROUTE_MANAGER,
+            WIREGUARD_KEY,
+        };
+
+        let route_manager_alive = self.tunnel_state_machine_handle.route_manager().is_alive();
+        let mut components = BTreeMap::new();
+        components.insert(
+            RELAY_LIST.to_owned(),
+            health::relay_list(self.relay_selector.last_updated(), SystemTime::now()),
+        );
+        components.insert(FIREWALL.to_owned(), health::firewall(&self.tunnel_state));
+        components.insert(DNS.to_owned(), health::dns(&self.tunnel_state));
+        components.insert(
+            ROUTE_MANAGER.to_owned(),
+            health::route_manager(route_manager_alive),
+        );
+
+        let rotation_interval = self
+            .settings
+            .tunnel_options
+            .wireguard
+            .rotation_interval
+            .unwrap_or_default();
+        let offline = self.api_handle.availability.get_state().is_offline();
+        let api_handle = self.api_handle.clone();
+        let account_manager = self.account_manager.clone();
+        tokio::spawn(async move {
+            let wireguard_key = match account_manager.data().await {
+                Ok(device) => health::wireguard_key(&device, rotation_interval),
+                Err(error) => ComponentHealth::failed(
+                    error.display_chain_with_msg("Failed to read the device state"),
+                ),
+            };
+            components.insert(WIREGUARD_KEY.to_owned(), wireguard_key);
+            components.insert(
+                API_CONNECTIVITY.to_owned(),
+                health::api_connectivity(api_handle, offline).await,
+            );
+            Self::oneshot_send(tx, DaemonHealth { components }, "daemon health");
+        });
+    }
+
+    fn on_create_new_account(&mut self, tx: ResponseTx<AccountToken, Error>) {
         let account_manager = self.account_manager.clone();
         tokio::spawn(async move {
             let result = async {
@@ -1575,7 +2564,28 @@ where
         self.relay_list_updater.update().await;
     }
 
-    fn on_login_account(&mut self, tx: ResponseTx<(), Error>, account_token: String) {
+    fn on_import_relay_list(
+        &mut self,
+        tx: ResponseTx<(), Error>,
+        relay_list: String,
+        signature: String,
+    ) {
+        let mut relay_list_updater = self.relay_list_updater.clone();
+        tokio::spawn(async move {
+            let result = async {
+                let imported =
+                    mullvad_api::parse_signed_relay_list(relay_list.as_bytes(), &signature)
+                        .map_err(Error::ImportRelayList)?;
+                relay_list_updater
+                    .import(imported.relay_list, imported.created)
+                    .await
+                    .map_err(Error::RelayListUpdater)
+            };
+            Self::oneshot_send(tx, result.await, "import_relay_list response");
+        });
+    }
+
+    fn on_login_account(&mut self, tx: ResponseTx<(), Error>, account_token: AccountToken) {
         let account_manager = self.account_manager.clone();
         let availability = self.api_runtime.availability_handle();
         tokio::spawn(async move {
@@ -1596,6 +2606,51 @@ where
         });
     }
 
+    fn on_login_and_register_device(
+        &mut self,
+        tx: ResponseTx<LoginOutcome, Error>,
+        account_token: AccountToken,
+        revoke_device: Option<DeviceId>,
+    ) {
+        let account_manager = self.account_manager.clone();
+        let device_service = self.account_manager.device_service.clone();
+        let event_listener = self.event_listener.clone();
+        let availability = self.api_runtime.availability_handle();
+        tokio::spawn(async move {
+            let result = async {
+                if let Some(device_id) = revoke_device {
+                    let new_devices = device_service
+                        .remove_device(account_token.clone(), device_id)
+                        .await
+                        .map_err(Error::RemoveDeviceError)?;
+                    event_listener.notify_remove_device_event(RemoveDeviceEvent {
+                        account_token: account_token.clone(),
+                        new_devices,
+                    });
+                }
+
+                match account_manager.login(account_token.clone()).await {
+                    Ok(()) => {
+                        availability.resume_background();
+                        Ok(LoginOutcome::LoggedIn)
+                    }
+                    Err(device::Error::MaxDevicesReached) => {
+                        let devices = device_service
+                            .list_devices(account_token)
+                            .await
+                            .map_err(Error::ListDevicesError)?;
+                        Ok(LoginOutcome::TooManyDevices(devices))
+                    }
+                    Err(error) => {
+                        log::error!("{}", error.display_chain_with_msg("Login failed"));
+                        Err(Error::LoginError(error))
+                    }
+                }
+            };
+            Self::oneshot_send(tx, result.await, "login_and_register_device response");
+        });
+    }
+
     fn on_logout_account(&mut self, tx: ResponseTx<(), Error>) {
         let account_manager = self.account_manager.clone();
         tokio::spawn(async move {
@@ -1727,6 +2782,83 @@ where
         );
     }
 
+    fn on_send_problem_report(&mut self, tx: oneshot::Sender<u32>, request: ProblemReportRequest) {
+        let id = self.next_problem_report_id;
+        self.next_problem_report_id = self.next_problem_report_id.wrapping_add(1);
+
+        let proxy = mullvad_api::ProblemReportProxy::new(self.api_handle.clone());
+        let event_listener = self.event_listener.clone();
+        tokio::spawn(problem_report::upload(
+            proxy,
+            id,
+            request,
+            move |progress| event_listener.notify_problem_report_progress(progress),
+        ));
+        Self::oneshot_send(tx, id, "send_problem_report response");
+    }
+
+    fn on_get_settings_lock_state(
+        &mut self,
+        tx: oneshot::Sender<SettingsLockState>,
+        connection: Option<ConnectionId>,
+    ) {
+        let now = Instant::now();
+        self.settings_unlocked_until.retain(|_, until| *until > now);
+        let unlocked_for = connection
+            .and_then(|connection| self.settings_unlocked_until.get(&connection))
+            .map(|until| until.duration_since(now));
+        let state = SettingsLockState {
+            enabled: self.settings.settings_lock.is_some(),
+            unlocked_for: unlocked_for.filter(|_| self.settings.settings_lock.is_some()),
+        };
+        Self::oneshot_send(tx, state, "get_settings_lock_state response");
+    }
+
+    async fn on_set_settings_lock(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        lock: Option<SettingsLock>,
+    ) {
+        let enabled = lock.is_some();
+        let result = self
+            .settings
+            .update(move |settings| settings.settings_lock = lock)
+            .await;
+        if let Err(error) = &result {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Unable to save settings")
+            );
+        } else {
+            log::info!(
+                "Settings lock {}",
+                if enabled { "enabled" } else { "disabled" }
+            );
+            self.settings_unlocked_until.clear();
+        }
+        Self::oneshot_send(tx, result.map(|_| ()), "set_settings_lock response");
+    }
+
+    fn on_unlock_settings(
+        &mut self,
+        tx: oneshot::Sender<bool>,
+        lock: SettingsLock,
+        connection: ConnectionId,
+    ) {
+        let unlocked = self.settings.settings_lock.as_ref() == Some(&lock);
+        if unlocked {
+            log::info!("Unlocking settings for {connection:?}");
+            self.settings_unlocked_until
+                .insert(connection, Instant::now() + settings_lock::UNLOCK_DURATION);
+        }
+        Self::oneshot_send(tx, unlocked, "unlock_settings response");
+    }
+
+    fn on_lock_settings(&mut self, tx: oneshot::Sender<()>) {
+        self.settings_unlocked_until.clear();
+        Self::oneshot_send(tx, (), "lock_settings response");
+    }
+
     #[cfg(not(target_os = "android"))]
     async fn on_factory_reset(&mut self, tx: ResponseTx<(), Error>) {
         let mut last_error = Ok(());
@@ -1752,7 +2884,7 @@ where
         }
 
         // Shut the daemon down.
-        self.trigger_shutdown_event(false);
+        self.trigger_shutdown_event(false, ShutdownBehavior::Auto);
 
         self.shutdown_tasks.push(Box::pin(async move {
             if let Err(e) = cleanup::clear_directories().await {
@@ -2009,6 +3141,38 @@ where
         }
     }
 
+    #[cfg(windows)]
+    fn on_cleanup_firewall(&self, tx: ResponseTx<usize, Error>) {
+        tokio::task::spawn_blocking(move || {
+            let result = talpid_core::firewall::Firewall::remove_stale_filters()
+                .map_err(Error::CleanupFirewall);
+            if let Ok(num_removed) = result {
+                log::info!("Removed {num_removed} stale firewall filters");
+            }
+            Self::oneshot_send(tx, result, "cleanup_firewall response");
+        });
+    }
+
+    #[cfg(target_os = "macos")]
+    fn on_get_firewall_rules(&self, tx: ResponseTx<String, Error>) {
+        tokio::spawn(async move {
+            let result = talpid_core::firewall::Firewall::loaded_rules()
+                .await
+                .map_err(Error::ReadFirewallRules);
+            Self::oneshot_send(tx, result, "get_firewall_rules response");
+        });
+    }
+
+    #[cfg(target_os = "macos")]
+    fn on_is_early_boot_blocking_installed(&self, tx: ResponseTx<bool, Error>) {
+        tokio::spawn(async move {
+            let result = early_boot_blocking::is_installed()
+                .await
+                .map_err(Error::EarlyBootBlocking);
+            Self::oneshot_send(tx, result, "is_early_boot_blocking_installed response");
+        });
+    }
+
     async fn on_set_relay_settings(
         &mut self,
         tx: ResponseTx<(), settings::Error>,
@@ -2023,7 +3187,7 @@ where
                 Self::oneshot_send(tx, Ok(()), "set_relay_settings response");
                 if settings_changed {
                     log::info!("Initiating tunnel restart because the relay settings changed");
-                    self.reconnect_tunnel();
+                    self.reconnect_tunnel_after_settings_change();
                 }
             }
             Err(e) => {
@@ -2072,7 +3236,9 @@ where
                 Self::oneshot_send(tx, Ok(()), "set_show_beta_releases response");
                 if settings_changed {
                     let mut handle = self.version_updater_handle.clone();
-                    handle.set_show_beta_releases(enabled).await;
+                    handle
+                        .set_update_channel(version_check::update_channel(enabled))
+                        .await;
                 }
             }
             Err(e) => {
@@ -2082,59 +3248,282 @@ where
         }
     }
 
-    async fn on_set_block_when_disconnected(
+    async fn on_set_webhook(
         &mut self,
-        tx: ResponseTx<(), settings::Error>,
-        block_when_disconnected: bool,
+        tx: ResponseTx<(), Error>,
+        webhook: Option<WebhookSettings>,
     ) {
-        match self
-            .settings
-            .update(move |settings| settings.block_when_disconnected = block_when_disconnected)
-            .await
-        {
-            Ok(settings_changed) => {
-                if settings_changed {
-                    self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(
-                        block_when_disconnected,
-                        oneshot_map(tx, |tx, ()| {
-                            Self::oneshot_send(tx, Ok(()), "set_block_when_disconnected response");
-                        }),
-                    ));
-                } else {
-                    Self::oneshot_send(tx, Ok(()), "set_block_when_disconnected response");
-                }
-            }
-            Err(e) => {
-                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
-                Self::oneshot_send(tx, Err(e), "set_block_when_disconnected response");
+        if let Some(webhook) = &webhook {
+            if let Err(error) = webhook::parse_url(&webhook.url) {
+                Self::oneshot_send(
+                    tx,
+                    Err(Error::InvalidWebhook(error)),
+                    "set_webhook response",
+                );
+                return;
             }
         }
+        let result = self
+            .settings
+            .update(move |settings| settings.webhook = webhook)
+            .await
+            .map(|_| ())
+            .map_err(|error| {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Unable to save settings")
+                );
+                Error::SettingsError(error)
+            });
+        Self::oneshot_send(tx, result, "set_webhook response");
     }
 
-    async fn on_set_auto_connect(
-        &mut self,
-        tx: ResponseTx<(), settings::Error>,
-        auto_connect: bool,
-    ) {
-        match self
+    async fn on_set_lan_beacon(&mut self, tx: ResponseTx<(), settings::Error>, enabled: bool) {
+        // The beacon itself is updated by a settings change listener
+        let result = self
             .settings
-            .update(move |settings| settings.auto_connect = auto_connect)
+            .update(move |settings| settings.lan_beacon = enabled)
             .await
-        {
-            Ok(_settings_changed) => {
-                Self::oneshot_send(tx, Ok(()), "set auto-connect response");
-            }
-            Err(e) => {
-                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
-                Self::oneshot_send(tx, Err(e), "set auto-connect response");
-            }
-        }
+            .map(|_| ())
+            .inspect_err(|error| {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Unable to save settings")
+                );
+            });
+        Self::oneshot_send(tx, result, "set_lan_beacon response");
     }
 
-    async fn on_set_openvpn_mssfix(
+    async fn on_set_custom_api_endpoint(
         &mut self,
-        tx: ResponseTx<(), settings::Error>,
-        mssfix: Option<u16>,
+        tx: ResponseTx<(), Error>,
+        endpoint: Option<CustomApiEndpoint>,
+    ) {
+        let validated = match endpoint.as_ref().map(validate_custom_api_endpoint) {
+            Some(Ok(validated)) => Some(validated),
+            Some(Err(error)) => {
+                Self::oneshot_send(tx, Err(error), "set_custom_api_endpoint response");
+                return;
+            }
+            None => None,
+        };
+        let result = self
+            .settings
+            .update(move |settings| settings.custom_api_endpoint = endpoint)
+            .await;
+        let result = match result {
+            Ok(true) => {
+                mullvad_api::custom_endpoint::set(validated);
+                // Drop connections to the previous endpoint, and announce the new address to
+                // the firewall by selecting the access method again.
+                self.api_handle.service().reset();
+                self.reselect_api_access_method()
+                    .await
+                    .map_err(Error::ApiConnectionModeError)
+            }
+            Ok(false) => Ok(()),
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Unable to save settings")
+                );
+                Err(Error::SettingsError(error))
+            }
+        };
+        Self::oneshot_send(tx, result, "set_custom_api_endpoint response");
+    }
+
+    /// Select the current API access method again, or the direct one if it must be used, so that
+    /// the new API address is allowed by the firewall.
+    async fn reselect_api_access_method(&self) -> Result<(), api::Error> {
+        let access_method = if mullvad_api::custom_endpoint::force_direct() {
+            self.settings.api_access_methods.direct().get_id()
+        } else {
+            self.access_mode_handler
+                .get_current()
+                .await?
+                .setting
+                .get_id()
+        };
+        self.access_mode_handler
+            .use_access_method(access_method)
+            .await
+    }
+
+    async fn on_set_connection_check_settings(
+        &mut self,
+        tx: ResponseTx<(), Error>,
+        connection_check: ConnectionCheckSettings,
+    ) {
+        if !geoip::is_valid_host(&connection_check.host) {
+            Self::oneshot_send(
+                tx,
+                Err(Error::InvalidConnectionCheckHost(connection_check.host)),
+                "set_connection_check_settings response",
+            );
+            return;
+        }
+        let result = self
+            .settings
+            .update(move |settings| settings.connection_check = connection_check)
+            .await
+            .map(|_| ())
+            .map_err(|error| {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Unable to save settings")
+                );
+                Error::SettingsError(error)
+            });
+        Self::oneshot_send(tx, result, "set_connection_check_settings response");
+    }
+
+    async fn on_set_tunnel_protocol_fallback(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        fallback: TunnelProtocolFallback,
+    ) {
+        let result = self
+            .settings
+            .update(move |settings| settings.tunnel_protocol_fallback = fallback)
+            .await
+            .map(|_| ())
+            .inspect_err(|error| {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Unable to save settings")
+                );
+            });
+        Self::oneshot_send(tx, result, "set_tunnel_protocol_fallback response");
+    }
+
+    async fn on_set_block_when_disconnected(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        block_when_disconnected: bool,
+    ) {
+        self.cancel_lockdown_grace_period();
+        match self
+            .settings
+            .update(move |settings| settings.block_when_disconnected = block_when_disconnected)
+            .await
+        {
+            Ok(settings_changed) => {
+                if settings_changed {
+                    self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(
+                        block_when_disconnected,
+                        oneshot_map(tx, |tx, ()| {
+                            Self::oneshot_send(tx, Ok(()), "set_block_when_disconnected response");
+                        }),
+                    ));
+                } else {
+                    Self::oneshot_send(tx, Ok(()), "set_block_when_disconnected response");
+                }
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_block_when_disconnected response");
+            }
+        }
+    }
+
+    /// Enable lockdown mode once `grace_period` seconds have passed, so that the user can finish
+    /// what they are doing before traffic is blocked. Lockdown mode is enabled right away unless
+    /// the tunnel is disconnected, since it does not block anything otherwise.
+    async fn on_enable_block_when_disconnected_after(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        grace_period: u32,
+    ) {
+        if grace_period == 0
+            || self.settings.block_when_disconnected
+            || !self.tunnel_state.is_disconnected()
+        {
+            self.on_set_block_when_disconnected(tx, true).await;
+            return;
+        }
+
+        self.cancel_lockdown_grace_period();
+        log::info!("Enabling lockdown mode in {grace_period} seconds");
+        let id = self.next_lockdown_grace_period_id;
+        self.next_lockdown_grace_period_id = self.next_lockdown_grace_period_id.wrapping_add(1);
+        let daemon_tx = self.tx.clone();
+        let (future, abort_handle) = abortable(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+            for remaining in (0..=grace_period).rev() {
+                interval.tick().await;
+                let _ =
+                    daemon_tx.send(InternalDaemonEvent::LockdownGracePeriodTick { id, remaining });
+            }
+        });
+        tokio::spawn(future);
+        self.lockdown_grace_period = Some((id, abort_handle));
+        Self::oneshot_send(tx, Ok(()), "enable_block_when_disconnected_after response");
+    }
+
+    async fn handle_lockdown_grace_period_tick(&mut self, id: u32, remaining: u32) {
+        if !matches!(self.lockdown_grace_period, Some((current_id, _)) if current_id == id) {
+            log::debug!("Ignoring countdown of a cancelled lockdown grace period");
+            return;
+        }
+        if remaining > 0 {
+            self.event_listener
+                .notify_lockdown_countdown(LockdownCountdown::Remaining(remaining));
+            return;
+        }
+
+        self.lockdown_grace_period = None;
+        log::info!("Enabling lockdown mode after the grace period");
+        match self
+            .settings
+            .update(|settings| settings.block_when_disconnected = true)
+            .await
+        {
+            Ok(settings_changed) => {
+                if settings_changed {
+                    let (tx, _rx) = oneshot::channel();
+                    self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(true, tx));
+                }
+                self.event_listener
+                    .notify_lockdown_countdown(LockdownCountdown::Enabled);
+            }
+            Err(e) => log::error!("{}", e.display_chain_with_msg("Unable to save settings")),
+        }
+    }
+
+    fn cancel_lockdown_grace_period(&mut self) {
+        if let Some((_, job)) = self.lockdown_grace_period.take() {
+            job.abort();
+            log::debug!("Cancelled the lockdown grace period");
+            self.event_listener
+                .notify_lockdown_countdown(LockdownCountdown::Cancelled);
+        }
+    }
+
+    async fn on_set_auto_connect(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        auto_connect: bool,
+    ) {
+        match self
+            .settings
+            .update(move |settings| settings.auto_connect = auto_connect)
+            .await
+        {
+            Ok(_settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set auto-connect response");
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set auto-connect response");
+            }
+        }
+    }
+
+    async fn on_set_openvpn_mssfix(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        mssfix: Option<u16>,
     ) {
         match self
             .settings
@@ -2147,7 +3536,7 @@ where
                     log::info!(
                         "Initiating tunnel restart because the OpenVPN mssfix setting changed"
                     );
-                    self.reconnect_tunnel();
+                    self.reconnect_tunnel_after_settings_change();
                 }
             }
             Err(e) => {
@@ -2157,6 +3546,81 @@ where
         }
     }
 
+    async fn on_set_openvpn_connect_timeout(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        timeout: Option<u16>,
+    ) {
+        match self
+            .settings
+            .update(move |settings| settings.tunnel_options.openvpn.connect_timeout = timeout)
+            .await
+        {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_openvpn_connect_timeout response");
+                if settings_changed && self.get_target_tunnel_type() == Some(TunnelType::OpenVpn) {
+                    log::info!(
+                        "Initiating tunnel restart because the OpenVPN connect timeout changed"
+                    );
+                    self.reconnect_tunnel_after_settings_change();
+                }
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_openvpn_connect_timeout response");
+            }
+        }
+    }
+
+    async fn on_set_openvpn_retry_timeout(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        timeout: Option<u16>,
+    ) {
+        match self
+            .settings
+            .update(move |settings| settings.tunnel_options.openvpn.retry_timeout = timeout)
+            .await
+        {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_openvpn_retry_timeout response");
+                if settings_changed && self.get_target_tunnel_type() == Some(TunnelType::OpenVpn) {
+                    log::info!(
+                        "Initiating tunnel restart because the OpenVPN retry timeout changed"
+                    );
+                    self.reconnect_tunnel_after_settings_change();
+                }
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_openvpn_retry_timeout response");
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn on_set_openvpn_sandbox(&mut self, tx: ResponseTx<(), settings::Error>, enabled: bool) {
+        match self
+            .settings
+            .update(move |settings| settings.tunnel_options.openvpn.sandbox = enabled)
+            .await
+        {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_openvpn_sandbox response");
+                if settings_changed && self.get_target_tunnel_type() == Some(TunnelType::OpenVpn) {
+                    log::info!(
+                        "Initiating tunnel restart because the OpenVPN sandbox setting changed"
+                    );
+                    self.reconnect_tunnel_after_settings_change();
+                }
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_openvpn_sandbox response");
+            }
+        }
+    }
+
     async fn on_set_bridge_settings(
         &mut self,
         tx: ResponseTx<(), Error>,
@@ -2172,6 +3636,16 @@ where
             return;
         }
 
+        if let Some(Err(error)) = new_settings.custom_proxy_chain() {
+            log::info!("Rejecting invalid custom bridge chain: {error}");
+            Self::oneshot_send(
+                tx,
+                Err(Error::InvalidProxyChain(error)),
+                "set_bridge_settings response",
+            );
+            return;
+        }
+
         match self
             .settings
             .update(move |settings| settings.bridge_settings = new_settings)
@@ -2185,7 +3659,7 @@ where
                             log::error!("Failed to rotate API endpoint: {error}");
                         }
                     });
-                    self.reconnect_tunnel();
+                    self.reconnect_tunnel_after_settings_change();
                 };
                 Self::oneshot_send(tx, Ok(()), "set_bridge_settings");
             }
@@ -2212,7 +3686,7 @@ where
         {
             Ok(settings_changed) => {
                 if settings_changed {
-                    self.reconnect_tunnel();
+                    self.reconnect_tunnel_after_settings_change();
                 }
                 Self::oneshot_send(tx, Ok(()), "set_obfuscation_settings");
             }
@@ -2239,7 +3713,7 @@ where
             Ok(settings_changed) => {
                 if settings_changed {
                     log::info!("Initiating tunnel restart because bridge state changed");
-                    self.reconnect_tunnel();
+                    self.reconnect_tunnel_after_settings_change();
                 }
                 Ok(())
             }
@@ -2254,6 +3728,51 @@ where
         Self::oneshot_send(tx, result, "on_set_bridge_state response");
     }
 
+    async fn on_set_removed_relay_policy(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        policy: RemovedRelayPolicy,
+    ) {
+        let result = self
+            .settings
+            .update(move |settings| settings.removed_relay_policy = policy)
+            .await
+            .map(|_| ())
+            .inspect_err(|error| {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to set removed relay policy")
+                );
+            });
+        Self::oneshot_send(tx, result, "on_set_removed_relay_policy response");
+    }
+
+    async fn on_set_ip_version_preference(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        preference: IpVersionPreference,
+    ) {
+        match self
+            .settings
+            .update(move |settings| settings.ip_version_preference = preference)
+            .await
+        {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_ip_version_preference response");
+                if settings_changed {
+                    log::info!(
+                        "Initiating tunnel restart because the IP version preference changed"
+                    );
+                    self.reconnect_tunnel_after_settings_change();
+                }
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_ip_version_preference response");
+            }
+        }
+    }
+
     async fn on_set_enable_ipv6(&mut self, tx: ResponseTx<(), settings::Error>, enable_ipv6: bool) {
         match self
             .settings
@@ -2264,7 +3783,7 @@ where
                 Self::oneshot_send(tx, Ok(()), "set_enable_ipv6 response");
                 if settings_changed {
                     log::info!("Initiating tunnel restart because the enable IPv6 setting changed");
-                    self.reconnect_tunnel();
+                    self.reconnect_tunnel_after_settings_change();
                 }
             }
             Err(e) => {
@@ -2274,6 +3793,31 @@ where
         }
     }
 
+    async fn on_set_connect_deadline(&mut self, tx: ResponseTx<(), Error>, deadline: Option<u16>) {
+        if deadline.is_some_and(|deadline| deadline < MIN_CONNECT_DEADLINE) {
+            Self::oneshot_send(
+                tx,
+                Err(Error::InvalidConnectDeadline),
+                "set_connect_deadline response",
+            );
+            return;
+        }
+        // The new deadline applies from the next connection attempt
+        let result = self
+            .settings
+            .update(move |settings| settings.tunnel_options.connect_deadline = deadline)
+            .await
+            .map(|_| ())
+            .map_err(|error| {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Unable to save settings")
+                );
+                Error::SettingsError(error)
+            });
+        Self::oneshot_send(tx, result, "set_connect_deadline response");
+    }
+
     async fn on_set_quantum_resistant_tunnel(
         &mut self,
         tx: ResponseTx<(), settings::Error>,
@@ -2291,7 +3835,7 @@ where
                 if settings_changed && self.get_target_tunnel_type() == Some(TunnelType::Wireguard)
                 {
                     log::info!("Reconnecting because the PQ safety setting changed");
-                    self.reconnect_tunnel();
+                    self.reconnect_tunnel_after_settings_change();
                 }
             }
             Err(e) => {
@@ -2324,7 +3868,7 @@ where
                         .set_config(new_selector_config(&self.settings));
                     if self.get_target_tunnel_type() == Some(TunnelType::Wireguard) {
                         log::info!("Reconnecting because DAITA settings changed");
-                        self.reconnect_tunnel();
+                        self.reconnect_tunnel_after_settings_change();
                     }
                 }
             }
@@ -2347,6 +3891,8 @@ where
         {
             Ok(settings_changed) => {
                 if settings_changed {
+                    #[cfg(target_os = "linux")]
+                    self.send_dns_manager_policy();
                     let settings = self.settings.to_settings();
                     let resolvers =
                         dns::addresses_from_options(&settings.tunnel_options.dns_options);
@@ -2380,7 +3926,7 @@ where
             Ok(settings_changed) => {
                 Self::oneshot_send(tx, Ok(()), "set_relay_override response");
                 if settings_changed {
-                    self.reconnect_tunnel();
+                    self.reconnect_tunnel_after_settings_change();
                 }
             }
             Err(e) => {
@@ -2399,7 +3945,7 @@ where
             Ok(settings_changed) => {
                 Self::oneshot_send(tx, Ok(()), "clear_all_relay_overrides response");
                 if settings_changed {
-                    self.reconnect_tunnel();
+                    self.reconnect_tunnel_after_settings_change();
                 }
             }
             Err(e) => {
@@ -2426,7 +3972,7 @@ where
                         log::info!(
                             "Initiating tunnel restart because the WireGuard MTU setting changed"
                         );
-                        self.reconnect_tunnel();
+                        self.reconnect_tunnel_after_settings_change();
                     }
                 }
             }
@@ -2437,6 +3983,58 @@ where
         }
     }
 
+    #[cfg(target_os = "linux")]
+    async fn on_set_wireguard_allowed_ips(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        allowed_ips: Option<Vec<IpNetwork>>,
+    ) {
+        match self
+            .settings
+            .update(move |settings| settings.tunnel_options.wireguard.allowed_ips = allowed_ips)
+            .await
+        {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_wireguard_allowed_ips response");
+                if settings_changed && self.get_target_tunnel_type() == Some(TunnelType::Wireguard)
+                {
+                    log::info!("Reconnecting because the WireGuard allowed IPs changed");
+                    self.reconnect_tunnel_after_settings_change();
+                }
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_wireguard_allowed_ips response");
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn on_set_wireguard_egress_interface(
+        &mut self,
+        tx: ResponseTx<(), settings::Error>,
+        interface: Option<String>,
+    ) {
+        match self
+            .settings
+            .update(move |settings| settings.tunnel_options.wireguard.egress_interface = interface)
+            .await
+        {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_wireguard_egress_interface response");
+                if settings_changed && self.get_target_tunnel_type() == Some(TunnelType::Wireguard)
+                {
+                    log::info!("Reconnecting because the WireGuard egress interface changed");
+                    self.reconnect_tunnel_after_settings_change();
+                }
+            }
+            Err(e) => {
+                log::error!("{}", e.display_chain_with_msg("Unable to save settings"));
+                Self::oneshot_send(tx, Err(e), "set_wireguard_egress_interface response");
+            }
+        }
+    }
+
     async fn on_set_wireguard_rotation_interval(
         &mut self,
         tx: ResponseTx<(), settings::Error>,
@@ -2481,6 +4079,36 @@ where
         });
     }
 
+    fn on_rotate_wireguard_key_now(
+        &self,
+        progress_tx: mpsc::UnboundedSender<Result<KeyRotationProgress, Error>>,
+    ) {
+        let manager = self.account_manager.clone();
+        // The tunnel is reconnected by `handle_device_event` once the key has been rotated.
+        let reconnects = self.get_target_tunnel_type() == Some(TunnelType::Wireguard);
+        tokio::spawn(async move {
+            let _ = progress_tx.unbounded_send(Ok(KeyRotationProgress::Uploading));
+            if let Err(error) = manager.rotate_key().await {
+                let _ = progress_tx.unbounded_send(Err(Error::KeyRotationError(error)));
+                return;
+            }
+            match manager.data().await.map(|state| state.into_device()) {
+                Ok(Some(config)) => {
+                    let public_key = config.device.wg_data.get_public_key();
+                    let _ =
+                        progress_tx.unbounded_send(Ok(KeyRotationProgress::Replaced(public_key)));
+                }
+                _ => {
+                    let _ = progress_tx.unbounded_send(Err(Error::NoAccountToken));
+                    return;
+                }
+            }
+            if reconnects {
+                let _ = progress_tx.unbounded_send(Ok(KeyRotationProgress::Reconnecting));
+            }
+        });
+    }
+
     async fn on_get_wireguard_key(&self, tx: ResponseTx<Option<PublicKey>, Error>) {
         let result =
             if let Ok(Some(config)) = self.account_manager.data().await.map(|s| s.into_device()) {
@@ -2519,6 +4147,16 @@ where
         Self::oneshot_send(tx, result, "clear_custom_lists response");
     }
 
+    async fn on_save_profile(&mut self, tx: ResponseTx<(), Error>, name: String) {
+        let result = self.save_profile(name).await;
+        Self::oneshot_send(tx, result, "save_profile response");
+    }
+
+    async fn on_delete_profile(&mut self, tx: ResponseTx<(), Error>, name: String) {
+        let result = self.delete_profile(name).await;
+        Self::oneshot_send(tx, result, "delete_profile response");
+    }
+
     async fn on_add_access_method(
         &mut self,
         tx: ResponseTx<mullvad_types::access_method::Id, Error>,
@@ -2589,16 +4227,29 @@ where
         });
     }
 
-    fn on_test_proxy_as_access_method(
+    fn on_get_api_address(&mut self, tx: oneshot::Sender<SocketAddr>) {
+        let address_cache = self.api_runtime.address_cache().clone();
+        tokio::spawn(async move {
+            let address = address_cache.get_address().await;
+            Self::oneshot_send(tx, address, "get_api_address response");
+        });
+    }
+
+    async fn on_test_proxy_as_access_method(
         &mut self,
-        tx: ResponseTx<bool, Error>,
-        proxy: talpid_types::net::proxy::CustomProxy,
+        tx: ResponseTx<mullvad_types::access_method::TestResult, Error>,
+        mut proxy: talpid_types::net::proxy::CustomProxy,
     ) {
         use mullvad_api::proxy::{ApiConnectionMode, ProxyConfig};
         use talpid_types::net::AllowedEndpoint;
 
+        if let Err(error) = self.host_cache.resolve_proxy(&mut proxy).await {
+            let error = Error::AccessMethodError(access_method::Error::from(error));
+            Self::oneshot_send(tx, Err(error), "on_test_proxy_as_access_method response");
+            return;
+        }
         let connection_mode = ApiConnectionMode::Proxied(ProxyConfig::from(proxy.clone()));
-        let api_proxy = self.create_limited_api_proxy(connection_mode.clone());
+        let connection_test = self.create_connection_test(connection_mode.clone());
         let proxy_endpoint = AllowedEndpoint {
             endpoint: proxy.get_remote_endpoint().endpoint,
             clients: api::allowed_clients(&connection_mode),
@@ -2611,7 +4262,7 @@ where
                 proxy_endpoint,
                 access_method_selector,
                 daemon_event_sender,
-                api_proxy,
+                connection_test,
             )
             .await
             .map_err(Error::AccessMethodError);
@@ -2622,7 +4273,7 @@ where
 
     async fn on_test_api_access_method(
         &mut self,
-        tx: ResponseTx<bool, Error>,
+        tx: ResponseTx<mullvad_types::access_method::TestResult, Error>,
         access_method: mullvad_types::access_method::Id,
     ) {
         let reply =
@@ -2644,7 +4295,7 @@ where
             }
         };
 
-        let api_proxy = self.create_limited_api_proxy(test_subject.connection_mode);
+        let connection_test = self.create_connection_test(test_subject.connection_mode);
         let daemon_event_sender = self.tx.to_specialized_sender();
         let access_method_selector = self.access_mode_handler.clone();
 
@@ -2653,7 +4304,7 @@ where
                 test_subject.endpoint,
                 access_method_selector,
                 daemon_event_sender,
-                api_proxy,
+                connection_test,
             )
             .await
             .map_err(Error::AccessMethodError);
@@ -2661,9 +4312,15 @@ where
             log::debug!(
                 "API access method {method} {verdict}",
                 method = test_subject.setting.name,
-                verdict = match result {
-                    Ok(true) => "could successfully connect to the Mullvad API",
-                    _ => "could not connect to the Mullvad API",
+                verdict = match &result {
+                    Ok(result) if result.is_successful() =>
+                        "could successfully connect to the Mullvad API".to_owned(),
+                    Ok(result) => match result.failed_step() {
+                        Some(step) =>
+                            format!("could not connect to the Mullvad API: {} failed", step.step),
+                        None => "could not connect to the Mullvad API".to_owned(),
+                    },
+                    Err(_) => "could not connect to the Mullvad API".to_owned(),
                 }
             );
 
@@ -2681,22 +4338,70 @@ where
         }
     }
 
-    fn trigger_shutdown_event(&mut self, user_init_shutdown: bool) {
-        // Block all traffic before shutting down to ensure that no traffic can leak on boot or
-        // shutdown.
-        if !user_init_shutdown
-            && (*self.target_state == TargetState::Secured || self.settings.auto_connect)
-        {
-            log::debug!("Blocking firewall during shutdown since system is going down");
+    fn trigger_shutdown_event(&mut self, user_init_shutdown: bool, behavior: ShutdownBehavior) {
+        log::info!("Shutting down the daemon. Shutdown behavior: {behavior}");
+
+        let block = match behavior {
+            // Block all traffic before shutting down to ensure that no traffic can leak on boot
+            // or shutdown.
+            ShutdownBehavior::Auto => {
+                !user_init_shutdown
+                    && (*self.target_state == TargetState::Secured || self.settings.auto_connect)
+            }
+            ShutdownBehavior::Block => true,
+            ShutdownBehavior::Restore => false,
+            ShutdownBehavior::KeepTunnel(duration) => {
+                if self.shutdown_grace_period.is_some() {
+                    log::debug!("Already keeping the tunnel up before shutting down");
+                    return;
+                }
+                if !matches!(self.tunnel_state, TunnelState::Disconnected { .. }) {
+                    self.keep_tunnel_before_shutdown(duration);
+                    return;
+                }
+                true
+            }
+        };
+        // The upgraded daemon releases the blocking policy once it has started
+        let block = block || self.upgrade_handoff_prepared;
+        if let Some(grace_period) = self.shutdown_grace_period.take() {
+            grace_period.abort();
+        }
+
+        if block {
+            log::debug!("Blocking firewall during shutdown");
+        }
+        // Unless told to restore the network, lockdown mode is left as it is when not blocking.
+        if block || behavior == ShutdownBehavior::Restore {
             let (tx, _rx) = oneshot::channel();
-            self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(true, tx));
+            self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(block, tx));
         }
 
         self.state.shutdown(&self.tunnel_state);
         self.disconnect_tunnel();
     }
 
-    fn on_prepare_restart(&mut self) {
+    /// Keep the tunnel up for `duration` before shutting down.
+    fn keep_tunnel_before_shutdown(&mut self, duration: Duration) {
+        log::debug!(
+            "Keeping the tunnel up for {} seconds before shutting down",
+            duration.as_secs()
+        );
+        let daemon_tx = self.tx.clone();
+        let (future, abort_handle) = abortable(async move {
+            tokio::time::sleep(duration).await;
+            let _ = daemon_tx.send(InternalDaemonEvent::ShutdownGracePeriodElapsed);
+        });
+        tokio::spawn(future);
+        self.shutdown_grace_period = Some(abort_handle);
+    }
+
+    fn on_shutdown(&mut self, tx: ResponseTx<(), Error>, behavior: ShutdownBehavior) {
+        Self::oneshot_send(tx, Ok(()), "shutdown response");
+        self.trigger_shutdown_event(true, behavior);
+    }
+
+    async fn on_prepare_restart(&mut self) {
         // TODO: See if this can be made to also shut down the daemon
         //       without causing the service to be restarted.
 
@@ -2705,6 +4410,14 @@ where
             self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(true, tx));
         }
         self.target_state.lock();
+
+        // Hand the blocking policy over to the upgraded daemon, so that it is never lifted
+        if (*self.target_state == TargetState::Secured || self.settings.block_when_disconnected)
+            && !self.upgrade_handoff_prepared
+        {
+            upgrade_handoff::prepare(&self.cache_dir).await;
+            self.upgrade_handoff_prepared = true;
+        }
     }
 
     #[cfg(target_os = "android")]
@@ -2751,6 +4464,17 @@ where
         });
     }
 
+    fn on_get_traffic_stats(&self, tx: ResponseTx<TrafficStats, Error>) {
+        let traffic_accountant = self.traffic_accountant.clone();
+        tokio::spawn(async move {
+            let result = traffic_accountant
+                .get_stats()
+                .await
+                .map_err(Error::TrafficAccountingError);
+            Self::oneshot_send(tx, result, "get_traffic_stats response");
+        });
+    }
+
     async fn on_apply_json_settings(
         &mut self,
         tx: ResponseTx<(), settings::patch::Error>,
@@ -2758,16 +4482,343 @@ where
     ) {
         let result = settings::patch::merge_validate_patch(&mut self.settings, &blob).await;
         if result.is_ok() {
-            self.reconnect_tunnel();
+            self.reconnect_tunnel_after_settings_change();
         }
         Self::oneshot_send(tx, result, "apply_json_settings response");
     }
 
+    async fn on_apply_settings(
+        &mut self,
+        tx: ResponseTx<(), Error>,
+        document: String,
+        dry_run: bool,
+    ) {
+        let new_settings = match settings::apply::settings_from_document(&self.settings, &document)
+            .map_err(Error::InvalidSettingsDocument)
+            .and_then(|new_settings| {
+                Self::validate_settings(&new_settings)?;
+                Ok(new_settings)
+            }) {
+            Ok(new_settings) => new_settings,
+            Err(error) => {
+                log::info!(
+                    "{}",
+                    error.display_chain_with_msg("Rejecting settings document")
+                );
+                Self::oneshot_send(tx, Err(error), "apply_settings response");
+                return;
+            }
+        };
+        if dry_run {
+            Self::oneshot_send(tx, Ok(()), "apply_settings response");
+            return;
+        }
+
+        let old_settings = self.settings.to_settings();
+        match self
+            .settings
+            .update(move |settings| *settings = new_settings)
+            .await
+        {
+            Ok(true) => {
+                self.apply_settings_side_effects(&old_settings).await;
+                Self::oneshot_send(tx, Ok(()), "apply_settings response");
+            }
+            Ok(false) => Self::oneshot_send(tx, Ok(()), "apply_settings response"),
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Unable to save settings")
+                );
+                Self::oneshot_send(
+                    tx,
+                    Err(Error::SettingsError(error)),
+                    "apply_settings response",
+                );
+            }
+        }
+    }
+
+    /// Perform the checks that the individual setters do, which cannot be expressed in the types.
+    fn validate_settings(settings: &Settings) -> Result<(), Error> {
+        if let Some(webhook) = &settings.webhook {
+            webhook::parse_url(&webhook.url).map_err(Error::InvalidWebhook)?;
+        }
+        if !geoip::is_valid_host(&settings.connection_check.host) {
+            return Err(Error::InvalidConnectionCheckHost(
+                settings.connection_check.host.clone(),
+            ));
+        }
+        if settings
+            .tunnel_options
+            .connect_deadline
+            .is_some_and(|deadline| deadline < MIN_CONNECT_DEADLINE)
+        {
+            return Err(Error::InvalidConnectDeadline);
+        }
+        let bridge_settings = &settings.bridge_settings;
+        if bridge_settings.custom.is_none() && bridge_settings.bridge_type == BridgeType::Custom {
+            return Err(Error::NoCustomProxySaved);
+        }
+        if let Some(Err(error)) = bridge_settings.custom_proxy_chain() {
+            return Err(Error::InvalidProxyChain(error));
+        }
+        Ok(())
+    }
+
+    /// Notify everything that is not a settings change listener of the difference between
+    /// `old_settings` and the current settings.
+    async fn apply_settings_side_effects(&mut self, old_settings: &Settings) {
+        let settings = self.settings.to_settings();
+
+        if settings.allow_lan != old_settings.allow_lan {
+            let (tx, _rx) = oneshot::channel();
+            self.send_tunnel_command(TunnelCommand::AllowLan(settings.allow_lan, tx));
+        }
+        if settings.block_when_disconnected != old_settings.block_when_disconnected {
+            let (tx, _rx) = oneshot::channel();
+            self.send_tunnel_command(TunnelCommand::BlockWhenDisconnected(
+                settings.block_when_disconnected,
+                tx,
+            ));
+        }
+        let dns_options = &settings.tunnel_options.dns_options;
+        if *dns_options != old_settings.tunnel_options.dns_options {
+            #[cfg(target_os = "linux")]
+            self.send_dns_manager_policy();
+            let (tx, _rx) = oneshot::channel();
+            self.send_tunnel_command(TunnelCommand::Dns(
+                dns::addresses_from_options(dns_options),
+                tx,
+            ));
+        }
+        if settings.show_beta_releases != old_settings.show_beta_releases {
+            let mut handle = self.version_updater_handle.clone();
+            handle
+                .set_update_channel(version_check::update_channel(settings.show_beta_releases))
+                .await;
+        }
+        let rotation_interval = settings.tunnel_options.wireguard.rotation_interval;
+        if rotation_interval != old_settings.tunnel_options.wireguard.rotation_interval {
+            if let Err(error) = self
+                .account_manager
+                .set_rotation_interval(rotation_interval.unwrap_or_default())
+                .await
+            {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to update rotation interval")
+                );
+            }
+        }
+        if settings.bridge_settings != old_settings.bridge_settings {
+            let access_mode_handler = self.access_mode_handler.clone();
+            tokio::spawn(async move {
+                if let Err(error) = access_mode_handler.rotate().await {
+                    log::error!("Failed to rotate API endpoint: {error}");
+                }
+            });
+        }
+        if settings.relay_settings != old_settings.relay_settings
+            || settings.bridge_settings != old_settings.bridge_settings
+            || settings.bridge_state != old_settings.bridge_state
+            || settings.obfuscation_settings != old_settings.obfuscation_settings
+            || settings.custom_lists != old_settings.custom_lists
+            || settings.relay_overrides != old_settings.relay_overrides
+            || settings.tunnel_options != old_settings.tunnel_options
+            || settings.ip_version_preference != old_settings.ip_version_preference
+            || settings.removed_relay_policy != old_settings.removed_relay_policy
+            || settings.tunnel_protocol_fallback != old_settings.tunnel_protocol_fallback
+        {
+            self.reconnect_tunnel_after_settings_change();
+        }
+    }
+
     fn on_export_json_settings(&mut self, tx: ResponseTx<String, settings::patch::Error>) {
         let result = settings::patch::export_settings(&self.settings);
         Self::oneshot_send(tx, result, "export_json_settings response");
     }
 
+    fn on_capture_debug_log(
+        &self,
+        tx: ResponseTx<String, Error>,
+        modules: Vec<String>,
+        duration: Duration,
+    ) {
+        if let Err(error) = logging::start_debug_capture(modules) {
+            Self::oneshot_send(
+                tx,
+                Err(Error::DebugCaptureError(error)),
+                "capture_debug_log response",
+            );
+            return;
+        }
+        let duration = duration.min(MAX_DEBUG_CAPTURE_DURATION);
+        log::info!("Capturing debug log for {} seconds", duration.as_secs());
+        tokio::spawn(async move {
+            tokio::time::sleep(duration).await;
+            let output = logging::stop_debug_capture().unwrap_or_default();
+            log::info!("Debug log capture finished");
+            let output = mullvad_problem_report::redact(&output);
+            Self::oneshot_send(tx, Ok(output), "capture_debug_log response");
+        });
+    }
+
+    #[cfg(not(target_os = "android"))]
+    fn on_export_openvpn_config(&self, tx: ResponseTx<String, Error>) {
+        let result = self.export_openvpn_config();
+        Self::oneshot_send(tx, result, "export_openvpn_config response");
+    }
+
+    /// Render an OpenVPN configuration file for the relay that the tunnel is connected to, or
+    /// for a relay matching the current constraints if the tunnel is not using OpenVPN.
+    #[cfg(not(target_os = "android"))]
+    fn export_openvpn_config(&self) -> Result<String, Error> {
+        use mullvad_relay_selector::GetRelay;
+        use talpid_types::net::openvpn;
+
+        let current_endpoint = match &self.tunnel_state {
+            TunnelState::Connecting { endpoint, .. } | TunnelState::Connected { endpoint, .. }
+                if endpoint.tunnel_type == TunnelType::OpenVpn =>
+            {
+                Some(endpoint.endpoint)
+            }
+            _ => None,
+        };
+        let endpoint = match current_endpoint {
+            Some(endpoint) => endpoint,
+            None => match self
+                .relay_selector
+                .get_openvpn_relay_from_settings()
+                .map_err(Error::SelectOpenVpnRelay)?
+            {
+                GetRelay::OpenVpn { endpoint, .. } => endpoint,
+                GetRelay::Wireguard { .. } | GetRelay::Custom(_) => {
+                    return Err(Error::ExportCustomRelay)
+                }
+            },
+        };
+
+        let tunnel_options = &self.settings.tunnel_options;
+        let params = openvpn::TunnelParameters {
+            // Credentials are never included in the exported configuration.
+            config: openvpn::ConnectionConfig::new(endpoint, String::new(), String::new()),
+            options: tunnel_options.openvpn.clone(),
+            generic_options: tunnel_options.generic.clone(),
+            proxy: None,
+            ca: self.certificate_store.openvpn_ca(),
+            #[cfg(unix)]
+            privilege_drop: None,
+            #[cfg(target_os = "linux")]
+            fwmark: runtime_config::get().tunnel_fwmark,
+        };
+        talpid_openvpn::export_config(&params, &self.resource_dir)
+            .map_err(Error::ExportOpenVpnConfig)
+    }
+
+    #[cfg(not(target_os = "android"))]
+    fn on_run_self_test(&mut self, tx: ResponseTx<Vec<SelfTestStep>, Error>) {
+        // The test changes the routes, DNS and firewall, which would disrupt an active tunnel
+        if !matches!(self.tunnel_state, TunnelState::Disconnected { .. }) {
+            Self::oneshot_send(
+                tx,
+                Err(Error::SelfTestUnavailable),
+                "run_self_test response",
+            );
+            return;
+        }
+        log::info!("Running self test");
+        let (result_tx, result_rx) = oneshot::channel();
+        self.send_tunnel_command(TunnelCommand::SelfTest(result_tx));
+        tokio::spawn(async move {
+            // The state machine drops the sender if it has left the disconnected state
+            let result = result_rx.await.map_err(|_| Error::SelfTestUnavailable);
+            log::info!("Self test finished");
+            Self::oneshot_send(tx, result, "run_self_test response");
+        });
+    }
+
+    fn on_diagnose_connection(&mut self, tx: ResponseTx<Vec<BlockedReason>, Error>) {
+        // The firewall blocks the probes in every other state
+        if !matches!(
+            self.tunnel_state,
+            TunnelState::Disconnected {
+                locked_down: false,
+                ..
+            }
+        ) {
+            Self::oneshot_send(
+                tx,
+                Err(Error::DiagnosisUnavailable),
+                "diagnose_connection response",
+            );
+            return;
+        }
+        let api_handle = self.api_handle.clone();
+        let relay_list = self.relay_selector.get_relays();
+        let account_manager = self.account_manager.clone();
+        tokio::spawn(async move {
+            let private_key = match account_manager.data().await.map(|s| s.into_device()) {
+                Ok(Some(config)) => Some(config.device.wg_data.private_key),
+                _ => None,
+            };
+            let results = connection_diagnosis::run(api_handle, relay_list, private_key).await;
+            log::info!("Connection diagnosis probes: {results:?}");
+            let reasons = mullvad_types::connection_diagnosis::analyze(&results);
+            Self::oneshot_send(tx, Ok(reasons), "diagnose_connection response");
+        });
+    }
+
+    async fn on_ping_relays(
+        &mut self,
+        tx: mpsc::UnboundedSender<Result<RelayLatency, Error>>,
+        country: Option<CountryCode>,
+    ) {
+        // Probing the relay of an active tunnel would move its endpoint away from the tunnel, and
+        // the firewall blocks the probes in every other state anyway.
+        if !matches!(
+            self.tunnel_state,
+            TunnelState::Disconnected {
+                locked_down: false,
+                ..
+            }
+        ) {
+            let _ = tx.unbounded_send(Err(Error::PingRelaysUnavailable));
+            return;
+        }
+        let private_key = match self.account_manager.data().await.map(|s| s.into_device()) {
+            Ok(Some(config)) => config.device.wg_data.private_key,
+            _ => {
+                let _ = tx.unbounded_send(Err(Error::NoAccountToken));
+                return;
+            }
+        };
+
+        let targets = relay_ping::targets(&self.relay_selector.get_relays(), country.as_deref());
+        log::info!("Pinging {} relays", targets.len());
+
+        self.abort_relay_ping();
+        let relay_selector = self.relay_selector.clone();
+        let (future, abort_handle) = abortable(async move {
+            let latencies = relay_ping::ping(targets, private_key);
+            tokio::pin!(latencies);
+            while let Some(latency) = latencies.next().await {
+                relay_selector.set_relay_latencies([latency.clone()]);
+                if tx.unbounded_send(Ok(latency)).is_err() {
+                    break;
+                }
+            }
+        });
+        tokio::spawn(future);
+        self.relay_ping_job = Some(abort_handle);
+    }
+
+    fn abort_relay_ping(&mut self) {
+        if let Some(job) = self.relay_ping_job.take() {
+            job.abort();
+        }
+    }
+
     /// Set the target state of the client. If it changed trigger the operations needed to
     /// progress towards that state.
     /// Returns a bool representing whether or not a state change was initiated.
@@ -2798,10 +4849,36 @@ where
 
     fn reconnect_tunnel(&mut self) {
         if *self.target_state == TargetState::Secured {
+            // Background requests can be sent again once the new tunnel is up
+            self.api_handle.service().cancel_background_requests();
             self.connect_tunnel();
         }
     }
 
+    /// Reconnect because the settings changed. Rapid changes are coalesced so that they cause at
+    /// most one reconnect per quiet period.
+    fn reconnect_tunnel_after_settings_change(&mut self) {
+        match self.settings_reconnects.request_reconnect(Instant::now()) {
+            ReconnectAction::Now => self.reconnect_tunnel(),
+            ReconnectAction::After(delay) => {
+                log::debug!("Delaying reconnect by {delay:?} to coalesce settings changes");
+                let daemon_tx = self.tx.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    let _ = daemon_tx.send(InternalDaemonEvent::DelayedSettingsReconnect);
+                });
+            }
+            ReconnectAction::Pending => (),
+        }
+    }
+
+    fn handle_delayed_settings_reconnect(&mut self) {
+        self.settings_reconnects
+            .on_delayed_reconnect(Instant::now());
+        log::debug!("Reconnecting after coalesced settings changes");
+        self.reconnect_tunnel();
+    }
+
     fn get_connected_tunnel_type(&self) -> Option<TunnelType> {
         if let TunnelState::Connected {
             endpoint: TunnelEndpoint { tunnel_type, .. },
@@ -2835,6 +4912,19 @@ where
             .expect("Tunnel state machine has stopped");
     }
 
+    /// Tell the tunnel state machine whether to block if DNS is managed by unsupported software.
+    #[cfg(target_os = "linux")]
+    fn send_dns_manager_policy(&self) {
+        let (tx, _rx) = oneshot::channel();
+        self.send_tunnel_command(TunnelCommand::BlockOnUnsupportedDnsManager(
+            self.settings
+                .tunnel_options
+                .dns_options
+                .block_on_unsupported_manager,
+            tx,
+        ));
+    }
+
     pub fn shutdown_handle(&self) -> DaemonShutdownHandle {
         DaemonShutdownHandle {
             tx: self.tx.clone(),
@@ -2855,6 +4945,17 @@ impl DaemonShutdownHandle {
     }
 }
 
+/// Check that `endpoint` is valid, and that custom API endpoints are allowed at all.
+fn validate_custom_api_endpoint(
+    endpoint: &CustomApiEndpoint,
+) -> Result<mullvad_api::custom_endpoint::CustomEndpoint, Error> {
+    if !cfg!(feature = "api-override") && !runtime_config::get().allow_custom_api_endpoint {
+        return Err(Error::CustomApiEndpointNotAllowed);
+    }
+    mullvad_api::custom_endpoint::CustomEndpoint::new(endpoint)
+        .map_err(Error::InvalidCustomApiEndpoint)
+}
+
 fn new_selector_config(settings: &Settings) -> SelectorConfig {
     let additional_constraints = AdditionalRelayConstraints {
         wireguard: AdditionalWireguardConstraints {
@@ -2873,9 +4974,37 @@ fn new_selector_config(settings: &Settings) -> SelectorConfig {
         obfuscation_settings: settings.obfuscation_settings.clone(),
         custom_lists: settings.custom_lists.clone(),
         relay_overrides: settings.relay_overrides.clone(),
+        ip_version_preference: settings.ip_version_preference,
     }
 }
 
+/// Returns the hostnames that are used by the custom relay, the custom bridges and the enabled
+/// custom access methods in `settings`.
+fn hostnames_to_pre_resolve(settings: &Settings) -> Vec<String> {
+    let relay_host = match &settings.relay_settings {
+        RelaySettings::CustomTunnelEndpoint(endpoint) => Some(endpoint.host.clone()),
+        RelaySettings::Normal(_) => None,
+    };
+    let bridges = settings
+        .bridge_settings
+        .custom
+        .iter()
+        .chain(&settings.bridge_settings.custom_chain);
+    let access_methods = settings
+        .api_access_methods
+        .iter_custom()
+        .filter(|method| method.enabled())
+        .filter_map(|method| method.as_custom());
+    relay_host
+        .into_iter()
+        .chain(
+            bridges
+                .chain(access_methods)
+                .filter_map(|proxy| proxy.hostname().map(str::to_owned)),
+        )
+        .collect()
+}
+
 /// Consume a oneshot sender of `T1` and return a sender that takes a different type `T2`.
 /// `forwarder` should map `T1` back to `T2` and send the result back to the original receiver.
 fn oneshot_map<T1: Send + 'static, T2: Send + 'static>(
@@ -5,9 +5,13 @@ use fern::{
 use std::{
     fmt, io,
     path::PathBuf,
-    sync::atomic::{AtomicBool, Ordering},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
 };
-use talpid_core::logging::rotate_log;
+pub use talpid_core::logging::RotationPolicy;
+use talpid_core::logging::{rotate_log, set_rotation_policy, RotatingLogFile};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -24,6 +28,12 @@ pub enum Error {
 
     #[error("Unable to set logger")]
     SetLoggerError(#[from] log::SetLoggerError),
+
+    #[error("Logging has not been initialized")]
+    LoggingDisabled,
+
+    #[error("A debug capture is already in progress")]
+    CaptureInProgress,
 }
 
 pub const WARNING_SILENCED_CRATES: &[&str] = &["netlink_proto"];
@@ -66,6 +76,9 @@ const LINE_SEPARATOR: &str = "\r\n";
 
 const DATE_TIME_FORMAT_STR: &str = "[%Y-%m-%d %H:%M:%S%.3f]";
 
+/// Maximum number of bytes buffered by a debug capture. Records beyond this are dropped.
+const MAX_CAPTURE_BYTES: usize = 10 * 1024 * 1024;
+
 /// Whether a [log] logger has been initialized.
 // the log crate doesn't provide a nice way to tell if a logger has been initialized :(
 static LOG_ENABLED: AtomicBool = AtomicBool::new(false);
@@ -75,11 +88,124 @@ pub fn is_enabled() -> bool {
     LOG_ENABLED.load(Ordering::SeqCst)
 }
 
+/// Whether a debug capture is in progress. Checked before locking [DEBUG_CAPTURE] so that
+/// logging is not slowed down when nothing is being captured.
+static CAPTURE_ACTIVE: AtomicBool = AtomicBool::new(false);
+static DEBUG_CAPTURE: Mutex<Option<DebugCapture>> = Mutex::new(None);
+
+/// Log records buffered during a debug capture.
+struct DebugCapture {
+    /// Log targets to capture. A target matches if it is equal to, or a submodule of, one of
+    /// these. If empty, all targets are captured.
+    modules: Vec<String>,
+    /// The max log level to restore once the capture is done.
+    previous_max_level: log::LevelFilter,
+    output: String,
+    truncated: bool,
+}
+
+impl DebugCapture {
+    fn matches(&self, target: &str) -> bool {
+        self.modules.is_empty()
+            || self.modules.iter().any(|module| {
+                target
+                    .strip_prefix(module.as_str())
+                    .map(|rest| rest.is_empty() || rest.starts_with("::"))
+                    .unwrap_or(false)
+            })
+    }
+
+    fn push(&mut self, line: String) {
+        if self.output.len() + line.len() + LINE_SEPARATOR.len() > MAX_CAPTURE_BYTES {
+            self.truncated = true;
+            return;
+        }
+        self.output.push_str(&line);
+        self.output.push_str(LINE_SEPARATOR);
+    }
+}
+
+/// Start capturing trace level logs for the given `modules`, in addition to what is normally
+/// logged. If `modules` is empty, all modules are captured. The captured output is returned by
+/// [stop_debug_capture].
+pub fn start_debug_capture(modules: Vec<String>) -> Result<(), Error> {
+    if !is_enabled() {
+        return Err(Error::LoggingDisabled);
+    }
+    let mut capture = DEBUG_CAPTURE.lock().unwrap();
+    if capture.is_some() {
+        return Err(Error::CaptureInProgress);
+    }
+    *capture = Some(DebugCapture {
+        modules,
+        previous_max_level: log::max_level(),
+        output: String::new(),
+        truncated: false,
+    });
+    CAPTURE_ACTIVE.store(true, Ordering::SeqCst);
+    log::set_max_level(log::LevelFilter::Trace);
+    Ok(())
+}
+
+/// Stop the current debug capture and return everything that was captured, or `None` if no
+/// capture is in progress.
+pub fn stop_debug_capture() -> Option<String> {
+    let capture = DEBUG_CAPTURE.lock().unwrap().take()?;
+    CAPTURE_ACTIVE.store(false, Ordering::SeqCst);
+    log::set_max_level(capture.previous_max_level);
+
+    let mut output = capture.output;
+    if capture.truncated {
+        output.push_str("[Capture truncated: size limit reached]");
+        output.push_str(LINE_SEPARATOR);
+    }
+    Some(output)
+}
+
+/// Wraps the regular logger and additionally feeds records to an ongoing debug capture.
+struct CapturingLogger {
+    inner: Box<dyn log::Log>,
+}
+
+impl log::Log for CapturingLogger {
+    fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+        self.inner.enabled(metadata) || CAPTURE_ACTIVE.load(Ordering::Relaxed)
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        self.inner.log(record);
+
+        if !CAPTURE_ACTIVE.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Some(capture) = DEBUG_CAPTURE.lock().unwrap().as_mut() {
+            if capture.matches(record.target()) {
+                capture.push(format!(
+                    "{}[{}][{}] {}",
+                    chrono::Local::now().format(DATE_TIME_FORMAT_STR),
+                    record.target(),
+                    record.level(),
+                    record.args(),
+                ));
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Initialize logging to stdout, and to `log_file` if given. `log_file` and tunnel logs are
+/// rotated according to `rotation`.
 pub fn init_logger(
     log_level: log::LevelFilter,
     log_file: Option<&PathBuf>,
     output_timestamp: bool,
+    rotation: RotationPolicy,
 ) -> Result<(), Error> {
+    set_rotation_policy(rotation);
+
     let mut top_dispatcher = fern::Dispatch::new().level(log_level);
     for silenced_crate in WARNING_SILENCED_CRATES {
         top_dispatcher = top_dispatcher.level_for(*silenced_crate, log::LevelFilter::Error);
@@ -106,13 +232,13 @@ pub fn init_logger(
             output_timestamp: true,
             output_color: false,
         };
-        let f = fern::log_file(log_file).map_err(|source| Error::WriteFile {
+        let f = RotatingLogFile::open(log_file, rotation).map_err(|source| Error::WriteFile {
             path: log_file.display().to_string(),
             source,
         })?;
         let file_dispatcher = fern::Dispatch::new()
             .format(move |out, message, record| file_formatter.output_msg(out, message, record))
-            .chain(Output::file(f, LINE_SEPARATOR));
+            .chain(Output::writer(Box::new(f), LINE_SEPARATOR));
         top_dispatcher = top_dispatcher.chain(file_dispatcher);
     }
     #[cfg(all(target_os = "android", debug_assertions))]
@@ -123,7 +249,10 @@ pub fn init_logger(
         ));
         top_dispatcher = top_dispatcher.chain(logger);
     }
-    top_dispatcher.apply().map_err(Error::SetLoggerError)?;
+    let (max_level, logger) = top_dispatcher.into_log();
+    log::set_boxed_logger(Box::new(CapturingLogger { inner: logger }))
+        .map_err(Error::SetLoggerError)?;
+    log::set_max_level(max_level);
 
     LOG_ENABLED.store(true, Ordering::SeqCst);
 
@@ -1,17 +1,25 @@
+use futures::channel::oneshot;
 #[cfg(not(windows))]
 use mullvad_daemon::cleanup_old_rpc_socket;
 use mullvad_daemon::{
     logging,
     management_interface::{ManagementInterfaceEventBroadcaster, ManagementInterfaceServer},
-    rpc_uniqueness_check, runtime, version, Daemon, DaemonCommandChannel, DaemonCommandSender,
+    rpc_uniqueness_check, runtime, version, Daemon, DaemonCommand, DaemonCommandChannel,
+    DaemonCommandSender,
+};
+use std::{
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
 };
-use std::{path::PathBuf, thread, time::Duration};
 use talpid_types::ErrorExt;
 
 mod cli;
+mod daemon_config;
 #[cfg(target_os = "linux")]
 mod early_boot_firewall;
 mod exception_logging;
+mod instance;
 #[cfg(target_os = "macos")]
 mod macos_launch_daemon;
 #[cfg(windows)]
@@ -143,6 +151,7 @@ fn init_logger(config: &cli::Config, log_file: Option<PathBuf>) -> Result<(), St
         config.log_level,
         log_file.as_ref(),
         config.log_stdout_timestamps,
+        config.log_rotation,
     )
     .map_err(|e| e.display_chain_with_msg("Unable to initialize logger"))?;
     log_panics::init();
@@ -199,9 +208,10 @@ async fn create_daemon(
         .map_err(|e| e.display_chain_with_msg("Unable to get cache dir"))?;
 
     let command_channel = DaemonCommandChannel::new();
-    let event_listener = spawn_management_interface(command_channel.sender())?;
+    let command_sender = command_channel.sender();
+    let event_listener = spawn_management_interface(command_sender.clone(), &settings_dir).await?;
 
-    Daemon::start(
+    let daemon = Daemon::start(
         log_dir,
         resource_dir,
         settings_dir,
@@ -210,16 +220,30 @@ async fn create_daemon(
         command_channel,
     )
     .await
-    .map_err(|e| e.display_chain_with_msg("Unable to initialize daemon"))
+    .map_err(|e| e.display_chain_with_msg("Unable to initialize daemon"))?;
+
+    if cli::get_config().lockdown_at_boot {
+        // Handled as soon as the daemon starts running, like any other command
+        log::info!("Enabling lockdown mode as configured in the daemon config file");
+        let (tx, _rx) = oneshot::channel();
+        command_sender
+            .send(DaemonCommand::SetBlockWhenDisconnected(tx, true))
+            .map_err(|e| e.display_chain_with_msg("Unable to enable lockdown mode"))?;
+    }
+
+    Ok(daemon)
 }
 
-fn spawn_management_interface(
+async fn spawn_management_interface(
     command_sender: DaemonCommandSender,
+    settings_dir: &Path,
 ) -> Result<ManagementInterfaceEventBroadcaster, String> {
-    let (socket_path, event_broadcaster) = ManagementInterfaceServer::start(command_sender)
-        .map_err(|error| {
-            error.display_chain_with_msg("Unable to start management interface server")
-        })?;
+    let (socket_path, event_broadcaster) =
+        ManagementInterfaceServer::start(command_sender, settings_dir)
+            .await
+            .map_err(|error| {
+                error.display_chain_with_msg("Unable to start management interface server")
+            })?;
 
     log::info!("Management interface listening on {}", socket_path);
 
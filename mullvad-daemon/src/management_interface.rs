@@ -1,26 +1,41 @@
-use crate::{account_history, device, DaemonCommand, DaemonCommandSender, EventListener};
+use crate::{
+    account_history, device, notifications::NotificationPolicy, runtime_config, settings_lock,
+    DaemonCommand, DaemonCommandSender, EventListener,
+};
 use futures::{
     channel::{mpsc, oneshot},
     StreamExt,
 };
 use mullvad_api::{rest::Error as RestError, StatusCode};
 use mullvad_management_interface::{
-    types::{self, daemon_event, management_service_server::ManagementService},
-    Code, Request, Response, Status,
+    error_code::StatusExt,
+    tcp,
+    types::{self, daemon_event, management_service_server::ManagementService, ErrorCode},
+    Code, ConnectionId, Request, Response, Status,
 };
 use mullvad_types::settings::DnsOptions;
 use mullvad_types::{
     account::AccountToken,
+    notification::Notification,
+    problem_report::{ProblemReportProgress, ProblemReportRequest},
     relay_constraints::{
-        BridgeSettings, BridgeState, ObfuscationSettings, RelayOverride, RelaySettings,
+        BridgeSettings, BridgeState, GeographicLocationConstraint, IpVersionPreference,
+        ObfuscationSettings, RelayOverride, RelaySettings, RemovedRelayPolicy,
+    },
+    relay_list::{RelayList, RelayListDiff},
+    settings::{
+        ConnectionCheckSettings, CustomApiEndpoint, Settings, SettingsLock, SettingsLockState,
+        TunnelProtocolFallback, WebhookSettings,
     },
-    relay_list::RelayList,
-    settings::Settings,
+    shutdown::ShutdownBehavior,
     states::{TargetState, TunnelState},
     version,
     wireguard::{RotationInterval, RotationIntervalError},
 };
 use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    path::Path,
     str::FromStr,
     sync::{Arc, Mutex},
     time::Duration,
@@ -33,16 +48,29 @@ pub enum Error {
     // Unable to start the management interface server
     #[error("Unable to start management interface server")]
     SetupError(#[source] mullvad_management_interface::Error),
+
+    #[error("Invalid management interface TCP port")]
+    InvalidTcpPort(#[source] runtime_config::InvalidValue),
+
+    #[error("Failed to read or create the management interface token")]
+    Token(#[source] io::Error),
 }
 
+/// File in the settings directory that holds the token that TCP clients authenticate with.
+const TOKEN_FILE: &str = "management-token";
+
+#[derive(Clone)]
 struct ManagementServiceImpl {
     daemon_tx: DaemonCommandSender,
     subscriptions: Arc<Mutex<Vec<EventsListenerSender>>>,
+    notification_subscriptions: Arc<Mutex<Vec<NotificationsSender>>>,
 }
 
 pub type ServiceResult<T> = std::result::Result<Response<T>, Status>;
 type EventsListenerReceiver = UnboundedReceiverStream<Result<types::DaemonEvent, Status>>;
 type EventsListenerSender = tokio::sync::mpsc::UnboundedSender<Result<types::DaemonEvent, Status>>;
+type NotificationsReceiver = UnboundedReceiverStream<Result<types::Notification, Status>>;
+type NotificationsSender = tokio::sync::mpsc::UnboundedSender<Result<types::Notification, Status>>;
 
 const INVALID_VOUCHER_MESSAGE: &str = "This voucher code is invalid";
 const USED_VOUCHER_MESSAGE: &str = "This voucher code has already been used";
@@ -51,6 +79,10 @@ const USED_VOUCHER_MESSAGE: &str = "This voucher code has already been used";
 impl ManagementService for ManagementServiceImpl {
     type GetSplitTunnelProcessesStream = UnboundedReceiverStream<Result<i32, Status>>;
     type EventsListenStream = EventsListenerReceiver;
+    type NotificationsStream = NotificationsReceiver;
+    type RotateWireguardKeyNowStream =
+        UnboundedReceiverStream<Result<types::KeyRotationProgress, Status>>;
+    type PingRelaysStream = UnboundedReceiverStream<Result<types::RelayLatency, Status>>;
 
     // Control and get the tunnel state
     //
@@ -64,9 +96,11 @@ impl ManagementService for ManagementServiceImpl {
         Ok(Response::new(connect_issued))
     }
 
-    async fn disconnect_tunnel(&self, _: Request<()>) -> ServiceResult<bool> {
+    async fn disconnect_tunnel(&self, request: Request<()>) -> ServiceResult<bool> {
         log::debug!("disconnect_tunnel");
 
+        let connection = ConnectionId::of(&request);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::SetTargetState(tx, TargetState::Unsecured))?;
         let disconnect_issued = self.wait_for_result(rx).await?;
@@ -89,6 +123,126 @@ impl ManagementService for ManagementServiceImpl {
         Ok(Response::new(types::TunnelState::from(state)))
     }
 
+    async fn refresh_location(&self, _: Request<()>) -> ServiceResult<()> {
+        log::debug!("refresh_location");
+        self.send_command_to_daemon(DaemonCommand::RefreshLocation)?;
+        Ok(Response::new(()))
+    }
+
+    async fn get_connect_trace(&self, _: Request<()>) -> ServiceResult<types::ConnectTrace> {
+        log::debug!("get_connect_trace");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetConnectTrace(tx))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(|trace| Response::new(types::ConnectTrace::from(trace)))
+            .ok_or_else(|| Status::not_found("no connection has been established"))
+    }
+
+    async fn get_persisted_target(&self, _: Request<()>) -> ServiceResult<types::PersistedTarget> {
+        log::debug!("get_persisted_target");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetPersistedTarget(tx))?;
+        let persisted = self.wait_for_result(rx).await?;
+        Ok(Response::new(types::PersistedTarget::from(persisted)))
+    }
+
+    async fn get_last_shutdown_report(
+        &self,
+        _: Request<()>,
+    ) -> ServiceResult<types::ShutdownReport> {
+        log::debug!("get_last_shutdown_report");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetLastShutdownReport(tx))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(|report| Response::new(types::ShutdownReport::from(report)))
+            .ok_or_else(|| Status::not_found("no shutdown has been recorded"))
+    }
+
+    async fn send_problem_report(
+        &self,
+        request: Request<types::ProblemReportRequest>,
+    ) -> ServiceResult<u32> {
+        log::debug!("send_problem_report");
+        let request = ProblemReportRequest::from(request.into_inner());
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SendProblemReport(tx, request))?;
+        let id = self.wait_for_result(rx).await?;
+        Ok(Response::new(id))
+    }
+
+    async fn get_settings_lock_state(
+        &self,
+        request: Request<()>,
+    ) -> ServiceResult<types::SettingsLockState> {
+        log::debug!("get_settings_lock_state");
+        let state = self.settings_lock_state(ConnectionId::of(&request)).await?;
+        Ok(Response::new(types::SettingsLockState::from(state)))
+    }
+
+    async fn enable_settings_lock(&self, request: Request<String>) -> ServiceResult<()> {
+        log::debug!("enable_settings_lock");
+        let connection = ConnectionId::of(&request);
+        // Changing the passphrase requires the settings to be unlocked
+        self.ensure_unlocked(connection).await?;
+        let passphrase = request.into_inner();
+        let lock = tokio::task::spawn_blocking(move || settings_lock::new_lock(&passphrase))
+            .await
+            .map_err(|_| Status::internal("failed to hash passphrase"))?
+            .map_err(|error| match error {
+                settings_lock::Error::EmptyPassphrase => {
+                    Status::invalid_argument(error.to_string())
+                        .with_error_code(ErrorCode::InvalidArgument)
+                }
+                settings_lock::Error::Hash(_) => Status::internal(error.to_string()),
+            })?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetSettingsLock(tx, Some(lock)))?;
+        self.wait_for_result(rx).await??;
+        Ok(Response::new(()))
+    }
+
+    async fn disable_settings_lock(&self, request: Request<String>) -> ServiceResult<()> {
+        log::debug!("disable_settings_lock");
+        self.verify_passphrase(request.into_inner()).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetSettingsLock(tx, None))?;
+        self.wait_for_result(rx).await??;
+        Ok(Response::new(()))
+    }
+
+    async fn unlock_settings(&self, request: Request<String>) -> ServiceResult<()> {
+        log::debug!("unlock_settings");
+        let connection = ConnectionId::of(&request)
+            .ok_or_else(|| Status::internal("unknown management interface connection"))?;
+        let lock = self.verify_passphrase(request.into_inner()).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::UnlockSettings(tx, lock, connection))?;
+        if !self.wait_for_result(rx).await? {
+            return Err(Status::aborted("The settings lock was changed"));
+        }
+        Ok(Response::new(()))
+    }
+
+    async fn lock_settings(&self, _: Request<()>) -> ServiceResult<()> {
+        log::debug!("lock_settings");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::LockSettings(tx))?;
+        self.wait_for_result(rx).await?;
+        Ok(Response::new(()))
+    }
+
+    async fn get_last_error_diagnostics(&self, _: Request<()>) -> ServiceResult<String> {
+        log::debug!("get_last_error_diagnostics");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetLastErrorDiagnostics(tx))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .ok_or_else(|| Status::not_found("the error state has not been entered"))
+    }
+
     // Control the daemon and receive events
     //
 
@@ -101,16 +255,42 @@ impl ManagementService for ManagementServiceImpl {
         Ok(Response::new(UnboundedReceiverStream::new(rx)))
     }
 
+    async fn notifications(&self, _: Request<()>) -> ServiceResult<Self::NotificationsStream> {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let mut subscriptions = self.notification_subscriptions.lock().unwrap();
+        subscriptions.push(tx);
+
+        Ok(Response::new(UnboundedReceiverStream::new(rx)))
+    }
+
     async fn prepare_restart(&self, _: Request<()>) -> ServiceResult<()> {
         log::debug!("prepare_restart");
         self.send_command_to_daemon(DaemonCommand::PrepareRestart)?;
         Ok(Response::new(()))
     }
 
-    async fn factory_reset(&self, _: Request<()>) -> ServiceResult<()> {
+    async fn shutdown(&self, request: Request<types::ShutdownBehavior>) -> ServiceResult<()> {
+        log::debug!("shutdown");
+        let connection = ConnectionId::of(&request);
+        let behavior = ShutdownBehavior::try_from(request.into_inner())?;
+        self.ensure_unlocked(connection).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::Shutdown(tx, behavior))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn factory_reset(
+        &self,
+        #[cfg_attr(target_os = "android", allow(unused_variables))] request: Request<()>,
+    ) -> ServiceResult<()> {
         #[cfg(not(target_os = "android"))]
         {
             log::debug!("factory_reset");
+            self.ensure_unlocked(ConnectionId::of(&request)).await?;
             let (tx, rx) = oneshot::channel();
             self.send_command_to_daemon(DaemonCommand::FactoryReset(tx))?;
             self.wait_for_result(rx)
@@ -151,6 +331,14 @@ impl ManagementService for ManagementServiceImpl {
         Ok(Response::new(self.wait_for_result(rx).await?))
     }
 
+    async fn get_daemon_health(&self, _: Request<()>) -> ServiceResult<types::DaemonHealth> {
+        log::debug!("get_daemon_health");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetDaemonHealth(tx))?;
+        let health = self.wait_for_result(rx).await?;
+        Ok(Response::new(types::DaemonHealth::from(health)))
+    }
+
     // Relays and tunnel constraints
     //
 
@@ -160,11 +348,32 @@ impl ManagementService for ManagementServiceImpl {
         Ok(Response::new(()))
     }
 
+    async fn import_relay_list(
+        &self,
+        request: Request<types::SignedRelayList>,
+    ) -> ServiceResult<()> {
+        log::debug!("import_relay_list");
+        let connection = ConnectionId::of(&request);
+        let types::SignedRelayList {
+            relay_list,
+            signature,
+        } = request.into_inner();
+        self.ensure_unlocked(connection).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::ImportRelayList(tx, relay_list, signature))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
     async fn set_relay_settings(
         &self,
         request: Request<types::RelaySettings>,
     ) -> ServiceResult<()> {
         log::debug!("set_relay_settings");
+        let connection = ConnectionId::of(&request);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         let constraints_update =
             RelaySettings::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
@@ -189,11 +398,13 @@ impl ManagementService for ManagementServiceImpl {
         &self,
         request: Request<types::BridgeSettings>,
     ) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
         let settings =
             BridgeSettings::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
 
         log::debug!("set_bridge_settings({:?})", settings);
 
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::SetBridgeSettings(tx, settings))?;
         self.wait_for_result(rx).await?.map_err(map_daemon_error)?;
@@ -204,9 +415,11 @@ impl ManagementService for ManagementServiceImpl {
         &self,
         request: Request<types::ObfuscationSettings>,
     ) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
         let settings =
             ObfuscationSettings::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
         log::debug!("set_obfuscation_settings({:?})", settings);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::SetObfuscationSettings(tx, settings))?;
         self.wait_for_result(rx).await??;
@@ -214,16 +427,91 @@ impl ManagementService for ManagementServiceImpl {
     }
 
     async fn set_bridge_state(&self, request: Request<types::BridgeState>) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
         let bridge_state =
             BridgeState::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
 
         log::debug!("set_bridge_state({:?})", bridge_state);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::SetBridgeState(tx, bridge_state))?;
         self.wait_for_result(rx).await??;
         Ok(Response::new(()))
     }
 
+    async fn set_removed_relay_policy(
+        &self,
+        request: Request<types::RemovedRelayPolicy>,
+    ) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
+        let policy =
+            RemovedRelayPolicy::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
+
+        log::debug!("set_removed_relay_policy({:?})", policy);
+        self.ensure_unlocked(connection).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetRemovedRelayPolicy(tx, policy))?;
+        self.wait_for_result(rx).await??;
+        Ok(Response::new(()))
+    }
+
+    async fn set_ip_version_preference(
+        &self,
+        request: Request<types::IpVersionPreference>,
+    ) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
+        let preference =
+            IpVersionPreference::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
+
+        log::debug!("set_ip_version_preference({:?})", preference);
+        self.ensure_unlocked(connection).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetIpVersionPreference(tx, preference))?;
+        self.wait_for_result(rx).await??;
+        Ok(Response::new(()))
+    }
+
+    #[cfg(not(target_os = "android"))]
+    async fn export_openvpn_config(&self, _: Request<()>) -> ServiceResult<String> {
+        log::debug!("export_openvpn_config");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::ExportOpenVpnConfig(tx))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    #[cfg(target_os = "android")]
+    async fn export_openvpn_config(&self, _: Request<()>) -> ServiceResult<String> {
+        Err(Status::unimplemented("OpenVPN is not supported on Android")
+            .with_error_code(ErrorCode::NotSupported))
+    }
+
+    async fn ping_relays(
+        &self,
+        request: Request<types::PingRelaysRequest>,
+    ) -> ServiceResult<Self::PingRelaysStream> {
+        let country = request.into_inner().country;
+        log::debug!("ping_relays({:?})", country);
+        let (latency_tx, mut latency_rx) = mpsc::unbounded();
+        self.send_command_to_daemon(DaemonCommand::PingRelays(latency_tx, country))?;
+
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(latency) = latency_rx.next().await {
+                let latency = latency
+                    .map(types::RelayLatency::from)
+                    .map_err(map_daemon_error);
+                if tx.send(latency).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(UnboundedReceiverStream::new(rx)))
+    }
+
     // Settings
     //
 
@@ -237,8 +525,10 @@ impl ManagementService for ManagementServiceImpl {
     }
 
     async fn set_allow_lan(&self, request: Request<bool>) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
         let allow_lan = request.into_inner();
         log::debug!("set_allow_lan({})", allow_lan);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::SetAllowLan(tx, allow_lan))?;
         self.wait_for_result(rx).await??;
@@ -246,17 +536,122 @@ impl ManagementService for ManagementServiceImpl {
     }
 
     async fn set_show_beta_releases(&self, request: Request<bool>) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
         let enabled = request.into_inner();
         log::debug!("set_show_beta_releases({})", enabled);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::SetShowBetaReleases(tx, enabled))?;
         self.wait_for_result(rx).await??;
         Ok(Response::new(()))
     }
 
+    async fn set_webhook(&self, request: Request<types::WebhookSettings>) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
+        let webhook = WebhookSettings::from(request.into_inner());
+        log::debug!("set_webhook({})", webhook.url);
+        self.ensure_unlocked(connection).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetWebhook(tx, Some(webhook)))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn clear_webhook(&self, request: Request<()>) -> ServiceResult<()> {
+        log::debug!("clear_webhook");
+        let connection = ConnectionId::of(&request);
+        self.ensure_unlocked(connection).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetWebhook(tx, None))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn set_lan_beacon(&self, request: Request<bool>) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
+        let enabled = request.into_inner();
+        log::debug!("set_lan_beacon({})", enabled);
+        self.ensure_unlocked(connection).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetLanBeacon(tx, enabled))?;
+        self.wait_for_result(rx).await??;
+        Ok(Response::new(()))
+    }
+
+    async fn set_custom_api_endpoint(
+        &self,
+        request: Request<types::CustomApiEndpoint>,
+    ) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
+        let endpoint = CustomApiEndpoint::try_from(request.into_inner())?;
+        log::debug!(
+            "set_custom_api_endpoint({}, {})",
+            endpoint.host,
+            endpoint.address
+        );
+        self.ensure_unlocked(connection).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetCustomApiEndpoint(tx, Some(endpoint)))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn clear_custom_api_endpoint(&self, request: Request<()>) -> ServiceResult<()> {
+        log::debug!("clear_custom_api_endpoint");
+        let connection = ConnectionId::of(&request);
+        self.ensure_unlocked(connection).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetCustomApiEndpoint(tx, None))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn set_connection_check_settings(
+        &self,
+        request: Request<types::ConnectionCheckSettings>,
+    ) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
+        let settings = ConnectionCheckSettings::from(request.into_inner());
+        log::debug!("set_connection_check_settings({:?})", settings);
+        self.ensure_unlocked(connection).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetConnectionCheckSettings(tx, settings))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn set_tunnel_protocol_fallback(
+        &self,
+        request: Request<types::TunnelProtocolFallback>,
+    ) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
+        let fallback = TunnelProtocolFallback::from(request.into_inner());
+        log::debug!("set_tunnel_protocol_fallback({:?})", fallback);
+        self.ensure_unlocked(connection).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetTunnelProtocolFallback(tx, fallback))?;
+        self.wait_for_result(rx).await??;
+        Ok(Response::new(()))
+    }
+
     async fn set_block_when_disconnected(&self, request: Request<bool>) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
         let block_when_disconnected = request.into_inner();
         log::debug!("set_block_when_disconnected({})", block_when_disconnected);
+        // Only disabling lockdown mode is prevented by the settings lock
+        if !block_when_disconnected {
+            self.ensure_unlocked(connection).await?;
+        }
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::SetBlockWhenDisconnected(
             tx,
@@ -266,9 +661,26 @@ impl ManagementService for ManagementServiceImpl {
         Ok(Response::new(()))
     }
 
+    async fn enable_block_when_disconnected_after(
+        &self,
+        request: Request<u32>,
+    ) -> ServiceResult<()> {
+        let grace_period = request.into_inner();
+        log::debug!("enable_block_when_disconnected_after({grace_period})");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::EnableBlockWhenDisconnectedAfter(
+            tx,
+            grace_period,
+        ))?;
+        self.wait_for_result(rx).await??;
+        Ok(Response::new(()))
+    }
+
     async fn set_auto_connect(&self, request: Request<bool>) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
         let auto_connect = request.into_inner();
         log::debug!("set_auto_connect({})", auto_connect);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::SetAutoConnect(tx, auto_connect))?;
         self.wait_for_result(rx).await??;
@@ -276,6 +688,7 @@ impl ManagementService for ManagementServiceImpl {
     }
 
     async fn set_openvpn_mssfix(&self, request: Request<u32>) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
         let mssfix = request.into_inner();
         let mssfix = if mssfix != 0 {
             Some(mssfix as u16)
@@ -283,16 +696,98 @@ impl ManagementService for ManagementServiceImpl {
             None
         };
         log::debug!("set_openvpn_mssfix({:?})", mssfix);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::SetOpenVpnMssfix(tx, mssfix))?;
         self.wait_for_result(rx).await??;
         Ok(Response::new(()))
     }
 
+    async fn set_connect_deadline(&self, request: Request<u32>) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
+        let deadline = request.into_inner();
+        let deadline = if deadline != 0 {
+            Some(
+                u16::try_from(deadline)
+                    .map_err(|_| Status::invalid_argument("connect deadline is too large"))?,
+            )
+        } else {
+            None
+        };
+        log::debug!("set_connect_deadline({deadline:?})");
+        self.ensure_unlocked(connection).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetConnectDeadline(tx, deadline))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn set_openvpn_connect_timeout(&self, request: Request<u32>) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
+        let timeout = request.into_inner();
+        let timeout = if timeout != 0 {
+            Some(
+                u16::try_from(timeout)
+                    .map_err(|_| Status::invalid_argument("connect timeout is too large"))?,
+            )
+        } else {
+            None
+        };
+        log::debug!("set_openvpn_connect_timeout({timeout:?})");
+        self.ensure_unlocked(connection).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetOpenVpnConnectTimeout(tx, timeout))?;
+        self.wait_for_result(rx).await??;
+        Ok(Response::new(()))
+    }
+
+    async fn set_openvpn_retry_timeout(&self, request: Request<u32>) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
+        let timeout = request.into_inner();
+        let timeout = if timeout != 0 {
+            Some(
+                u16::try_from(timeout)
+                    .map_err(|_| Status::invalid_argument("retry timeout is too large"))?,
+            )
+        } else {
+            None
+        };
+        log::debug!("set_openvpn_retry_timeout({timeout:?})");
+        self.ensure_unlocked(connection).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetOpenVpnRetryTimeout(tx, timeout))?;
+        self.wait_for_result(rx).await??;
+        Ok(Response::new(()))
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn set_openvpn_sandbox(&self, request: Request<bool>) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
+        let enabled = request.into_inner();
+        log::debug!("set_openvpn_sandbox({enabled})");
+        self.ensure_unlocked(connection).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetOpenVpnSandbox(tx, enabled))?;
+        self.wait_for_result(rx).await??;
+        Ok(Response::new(()))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    async fn set_openvpn_sandbox(&self, _: Request<bool>) -> ServiceResult<()> {
+        Err(
+            Status::unimplemented("Confining OpenVPN is only supported on Linux")
+                .with_error_code(ErrorCode::NotSupported),
+        )
+    }
+
     async fn set_wireguard_mtu(&self, request: Request<u32>) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
         let mtu = request.into_inner();
         let mtu = if mtu != 0 { Some(mtu as u16) } else { None };
         log::debug!("set_wireguard_mtu({:?})", mtu);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::SetWireguardMtu(tx, mtu))?;
         self.wait_for_result(rx).await??;
@@ -300,8 +795,10 @@ impl ManagementService for ManagementServiceImpl {
     }
 
     async fn set_enable_ipv6(&self, request: Request<bool>) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
         let enable_ipv6 = request.into_inner();
         log::debug!("set_enable_ipv6({})", enable_ipv6);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::SetEnableIpv6(tx, enable_ipv6))?;
         self.wait_for_result(rx).await??;
@@ -312,10 +809,12 @@ impl ManagementService for ManagementServiceImpl {
         &self,
         request: Request<types::QuantumResistantState>,
     ) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
         let state = mullvad_types::wireguard::QuantumResistantState::try_from(request.into_inner())
             .map_err(map_protobuf_type_err)?;
 
         log::debug!("set_quantum_resistant_tunnel({state:?})");
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::SetQuantumResistantTunnel(tx, state))?;
         self.wait_for_result(rx).await??;
@@ -327,9 +826,11 @@ impl ManagementService for ManagementServiceImpl {
         &self,
         request: Request<types::DaitaSettings>,
     ) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
         let state = mullvad_types::wireguard::DaitaSettings::from(request.into_inner());
 
         log::debug!("set_daita_settings({state:?})");
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::SetDaitaSettings(tx, state))?;
         self.wait_for_result(rx).await?.map(Response::new)?;
@@ -341,10 +842,65 @@ impl ManagementService for ManagementServiceImpl {
         Ok(Response::new(()))
     }
 
+    #[cfg(target_os = "linux")]
+    async fn set_wireguard_allowed_ips(
+        &self,
+        request: Request<types::WireguardAllowedIps>,
+    ) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
+        let allowed_ips = Option::<Vec<ipnetwork::IpNetwork>>::try_from(request.into_inner())
+            .map_err(map_protobuf_type_err)?;
+
+        log::debug!("set_wireguard_allowed_ips({allowed_ips:?})");
+        self.ensure_unlocked(connection).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetWireguardAllowedIps(tx, allowed_ips))?;
+        self.wait_for_result(rx).await??;
+        Ok(Response::new(()))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    async fn set_wireguard_allowed_ips(
+        &self,
+        _: Request<types::WireguardAllowedIps>,
+    ) -> ServiceResult<()> {
+        Err(
+            Status::unimplemented("Narrowing the allowed IPs is only supported on Linux")
+                .with_error_code(ErrorCode::NotSupported),
+        )
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn set_wireguard_egress_interface(&self, request: Request<String>) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
+        let interface = request.into_inner();
+        let interface = if interface.is_empty() {
+            None
+        } else {
+            Some(interface)
+        };
+        log::debug!("set_wireguard_egress_interface({interface:?})");
+        self.ensure_unlocked(connection).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SetWireguardEgressInterface(tx, interface))?;
+        self.wait_for_result(rx).await??;
+        Ok(Response::new(()))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    async fn set_wireguard_egress_interface(&self, _: Request<String>) -> ServiceResult<()> {
+        Err(
+            Status::unimplemented("Choosing the egress interface is only supported on Linux")
+                .with_error_code(ErrorCode::NotSupported),
+        )
+    }
+
     async fn set_dns_options(&self, request: Request<types::DnsOptions>) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
         let options = DnsOptions::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
         log::debug!("set_dns_options({:?})", options);
 
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::SetDnsOptions(tx, options))?;
         self.wait_for_result(rx).await??;
@@ -355,17 +911,21 @@ impl ManagementService for ManagementServiceImpl {
         &self,
         request: Request<types::RelayOverride>,
     ) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
         let relay_override =
             RelayOverride::try_from(request.into_inner()).map_err(map_protobuf_type_err)?;
         log::debug!("set_relay_override");
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::SetRelayOverride(tx, relay_override))?;
         self.wait_for_result(rx).await??;
         Ok(Response::new(()))
     }
 
-    async fn clear_all_relay_overrides(&self, _: Request<()>) -> ServiceResult<()> {
+    async fn clear_all_relay_overrides(&self, request: Request<()>) -> ServiceResult<()> {
         log::debug!("clear_all_relay_overrides");
+        let connection = ConnectionId::of(&request);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::ClearAllRelayOverrides(tx))?;
         self.wait_for_result(rx).await??;
@@ -375,19 +935,23 @@ impl ManagementService for ManagementServiceImpl {
     // Account management
     //
 
-    async fn create_new_account(&self, _: Request<()>) -> ServiceResult<String> {
+    async fn create_new_account(&self, request: Request<()>) -> ServiceResult<String> {
         log::debug!("create_new_account");
+        let connection = ConnectionId::of(&request);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::CreateNewAccount(tx))?;
         self.wait_for_result(rx)
             .await?
-            .map(Response::new)
+            .map(|token| Response::new(token.expose().clone()))
             .map_err(map_daemon_error)
     }
 
-    async fn login_account(&self, request: Request<AccountToken>) -> ServiceResult<()> {
+    async fn login_account(&self, request: Request<String>) -> ServiceResult<()> {
         log::debug!("login_account");
-        let account_token = request.into_inner();
+        let connection = ConnectionId::of(&request);
+        let account_token = AccountToken::from(request.into_inner());
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::LoginAccount(tx, account_token))?;
         self.wait_for_result(rx)
@@ -396,8 +960,30 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_daemon_error)
     }
 
-    async fn logout_account(&self, _: Request<()>) -> ServiceResult<()> {
+    async fn login_and_register_device(
+        &self,
+        request: Request<types::LoginRequest>,
+    ) -> ServiceResult<types::LoginOutcome> {
+        log::debug!("login_and_register_device");
+        let connection = ConnectionId::of(&request);
+        let request = request.into_inner();
+        self.ensure_unlocked(connection).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::LoginAndRegisterDevice(
+            tx,
+            AccountToken::from(request.account_token),
+            request.revoke_device_id,
+        ))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(|outcome| Response::new(types::LoginOutcome::from(outcome)))
+            .map_err(map_daemon_error)
+    }
+
+    async fn logout_account(&self, request: Request<()>) -> ServiceResult<()> {
         log::debug!("logout_account");
+        let connection = ConnectionId::of(&request);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::LogoutAccount(tx))?;
         self.wait_for_result(rx)
@@ -408,10 +994,10 @@ impl ManagementService for ManagementServiceImpl {
 
     async fn get_account_data(
         &self,
-        request: Request<AccountToken>,
+        request: Request<String>,
     ) -> ServiceResult<types::AccountData> {
         log::debug!("get_account_data");
-        let account_token = request.into_inner();
+        let account_token = AccountToken::from(request.into_inner());
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::GetAccountData(tx, account_token))?;
         let result = self.wait_for_result(rx).await?;
@@ -430,13 +1016,17 @@ impl ManagementService for ManagementServiceImpl {
         log::debug!("get_account_history");
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::GetAccountHistory(tx))?;
-        self.wait_for_result(rx)
-            .await
-            .map(|history| Response::new(types::AccountHistory { token: history }))
+        self.wait_for_result(rx).await.map(|history| {
+            Response::new(types::AccountHistory {
+                token: history.map(|token| token.expose().clone()),
+            })
+        })
     }
 
-    async fn clear_account_history(&self, _: Request<()>) -> ServiceResult<()> {
+    async fn clear_account_history(&self, request: Request<()>) -> ServiceResult<()> {
         log::debug!("clear_account_history");
+        let connection = ConnectionId::of(&request);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::ClearAccountHistory(tx))?;
         self.wait_for_result(rx)
@@ -492,13 +1082,10 @@ impl ManagementService for ManagementServiceImpl {
             .map(Response::new)
     }
 
-    async fn list_devices(
-        &self,
-        request: Request<AccountToken>,
-    ) -> ServiceResult<types::DeviceList> {
+    async fn list_devices(&self, request: Request<String>) -> ServiceResult<types::DeviceList> {
         log::debug!("list_devices");
         let (tx, rx) = oneshot::channel();
-        let token = request.into_inner();
+        let token = AccountToken::from(request.into_inner());
         self.send_command_to_daemon(DaemonCommand::ListDevices(tx, token))?;
         let device = self.wait_for_result(rx).await?.map_err(map_daemon_error)?;
         Ok(Response::new(types::DeviceList::from(device)))
@@ -506,11 +1093,13 @@ impl ManagementService for ManagementServiceImpl {
 
     async fn remove_device(&self, request: Request<types::DeviceRemoval>) -> ServiceResult<()> {
         log::debug!("remove_device");
+        let connection = ConnectionId::of(&request);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         let removal = request.into_inner();
         self.send_command_to_daemon(DaemonCommand::RemoveDevice(
             tx,
-            removal.account_token,
+            AccountToken::from(removal.account_token),
             removal.device_id,
         ))?;
         self.wait_for_result(rx).await?.map_err(map_daemon_error)?;
@@ -524,6 +1113,7 @@ impl ManagementService for ManagementServiceImpl {
         &self,
         request: Request<types::Duration>,
     ) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
         let interval: RotationInterval = Duration::try_from(request.into_inner())
             .map_err(|_| Status::invalid_argument("unexpected negative rotation interval"))?
             .try_into()
@@ -532,6 +1122,7 @@ impl ManagementService for ManagementServiceImpl {
             })?;
 
         log::debug!("set_wireguard_rotation_interval({:?})", interval);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::SetWireguardRotationInterval(
             tx,
@@ -541,16 +1132,20 @@ impl ManagementService for ManagementServiceImpl {
         Ok(Response::new(()))
     }
 
-    async fn reset_wireguard_rotation_interval(&self, _: Request<()>) -> ServiceResult<()> {
+    async fn reset_wireguard_rotation_interval(&self, request: Request<()>) -> ServiceResult<()> {
         log::debug!("reset_wireguard_rotation_interval");
+        let connection = ConnectionId::of(&request);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::SetWireguardRotationInterval(tx, None))?;
         self.wait_for_result(rx).await??;
         Ok(Response::new(()))
     }
 
-    async fn rotate_wireguard_key(&self, _: Request<()>) -> ServiceResult<()> {
+    async fn rotate_wireguard_key(&self, request: Request<()>) -> ServiceResult<()> {
         log::debug!("rotate_wireguard_key");
+        let connection = ConnectionId::of(&request);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::RotateWireguardKey(tx))?;
         self.wait_for_result(rx)
@@ -559,6 +1154,31 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_daemon_error)
     }
 
+    async fn rotate_wireguard_key_now(
+        &self,
+        request: Request<()>,
+    ) -> ServiceResult<Self::RotateWireguardKeyNowStream> {
+        log::debug!("rotate_wireguard_key_now");
+        let connection = ConnectionId::of(&request);
+        self.ensure_unlocked(connection).await?;
+        let (progress_tx, mut progress_rx) = mpsc::unbounded();
+        self.send_command_to_daemon(DaemonCommand::RotateWireguardKeyNow(progress_tx))?;
+
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(progress) = progress_rx.next().await {
+                let progress = progress
+                    .map(types::KeyRotationProgress::from)
+                    .map_err(map_daemon_error);
+                if tx.send(progress).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(UnboundedReceiverStream::new(rx)))
+    }
+
     async fn get_wireguard_key(&self, _: Request<()>) -> ServiceResult<types::PublicKey> {
         log::debug!("get_wireguard_key");
         let (tx, rx) = oneshot::channel();
@@ -575,6 +1195,8 @@ impl ManagementService for ManagementServiceImpl {
 
     async fn create_custom_list(&self, request: Request<String>) -> ServiceResult<String> {
         log::debug!("create_custom_list");
+        let connection = ConnectionId::of(&request);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::CreateCustomList(tx, request.into_inner()))?;
         self.wait_for_result(rx)
@@ -585,6 +1207,8 @@ impl ManagementService for ManagementServiceImpl {
 
     async fn delete_custom_list(&self, request: Request<String>) -> ServiceResult<()> {
         log::debug!("delete_custom_list");
+        let connection = ConnectionId::of(&request);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::DeleteCustomList(
             tx,
@@ -599,7 +1223,9 @@ impl ManagementService for ManagementServiceImpl {
 
     async fn update_custom_list(&self, request: Request<types::CustomList>) -> ServiceResult<()> {
         log::debug!("update_custom_list");
+        let connection = ConnectionId::of(&request);
         let custom_list = mullvad_types::custom_list::CustomList::try_from(request.into_inner())?;
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::UpdateCustomList(tx, custom_list))?;
         self.wait_for_result(rx)
@@ -608,8 +1234,10 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_daemon_error)
     }
 
-    async fn clear_custom_lists(&self, _: Request<()>) -> ServiceResult<()> {
+    async fn clear_custom_lists(&self, request: Request<()>) -> ServiceResult<()> {
         log::debug!("clear_custom_lists");
+        let connection = ConnectionId::of(&request);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::ClearCustomLists(tx))?;
         self.wait_for_result(rx)
@@ -618,6 +1246,45 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_daemon_error)
     }
 
+    // Profiles
+    //
+
+    async fn save_profile(&self, request: Request<String>) -> ServiceResult<()> {
+        log::debug!("save_profile");
+        let connection = ConnectionId::of(&request);
+        self.ensure_unlocked(connection).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::SaveProfile(tx, request.into_inner()))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn use_profile(&self, request: Request<String>) -> ServiceResult<()> {
+        log::debug!("use_profile");
+        let connection = ConnectionId::of(&request);
+        self.ensure_unlocked(connection).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::UseProfile(tx, request.into_inner()))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    async fn delete_profile(&self, request: Request<String>) -> ServiceResult<()> {
+        log::debug!("delete_profile");
+        let connection = ConnectionId::of(&request);
+        self.ensure_unlocked(connection).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::DeleteProfile(tx, request.into_inner()))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
     // Access Methods
 
     async fn add_api_access_method(
@@ -625,7 +1292,9 @@ impl ManagementService for ManagementServiceImpl {
         request: Request<types::NewAccessMethodSetting>,
     ) -> ServiceResult<types::Uuid> {
         log::debug!("add_api_access_method");
+        let connection = ConnectionId::of(&request);
         let request = request.into_inner();
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::AddApiAccessMethod(
             tx,
@@ -645,7 +1314,9 @@ impl ManagementService for ManagementServiceImpl {
 
     async fn remove_api_access_method(&self, request: Request<types::Uuid>) -> ServiceResult<()> {
         log::debug!("remove_api_access_method");
+        let connection = ConnectionId::of(&request);
         let api_access_method = mullvad_types::access_method::Id::try_from(request.into_inner())?;
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::RemoveApiAccessMethod(tx, api_access_method))?;
         self.wait_for_result(rx)
@@ -656,7 +1327,9 @@ impl ManagementService for ManagementServiceImpl {
 
     async fn set_api_access_method(&self, request: Request<types::Uuid>) -> ServiceResult<()> {
         log::debug!("set_api_access_method");
+        let connection = ConnectionId::of(&request);
         let api_access_method = mullvad_types::access_method::Id::try_from(request.into_inner())?;
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::SetApiAccessMethod(tx, api_access_method))?;
         self.wait_for_result(rx)
@@ -670,8 +1343,10 @@ impl ManagementService for ManagementServiceImpl {
         request: Request<types::AccessMethodSetting>,
     ) -> ServiceResult<()> {
         log::debug!("update_api_access_method");
+        let connection = ConnectionId::of(&request);
         let access_method_update =
             mullvad_types::access_method::AccessMethodSetting::try_from(request.into_inner())?;
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::UpdateApiAccessMethod(
             tx,
@@ -683,8 +1358,10 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_daemon_error)
     }
 
-    async fn clear_custom_api_access_methods(&self, _: Request<()>) -> ServiceResult<()> {
+    async fn clear_custom_api_access_methods(&self, request: Request<()>) -> ServiceResult<()> {
         log::debug!("clear_custom_api_access_methods");
+        let connection = ConnectionId::of(&request);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::ClearCustomApiAccessMethods(tx))?;
         self.wait_for_result(rx)
@@ -709,24 +1386,32 @@ impl ManagementService for ManagementServiceImpl {
             .map_err(map_daemon_error)
     }
 
+    async fn get_api_address(&self, _: Request<()>) -> ServiceResult<String> {
+        log::debug!("get_api_address");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetApiAddress(tx))?;
+        let address = self.wait_for_result(rx).await?;
+        Ok(Response::new(address.to_string()))
+    }
+
     async fn test_custom_api_access_method(
         &self,
         config: Request<types::CustomProxy>,
-    ) -> ServiceResult<bool> {
+    ) -> ServiceResult<types::AccessMethodTestResult> {
         log::debug!("test_custom_api_access_method");
         let (tx, rx) = oneshot::channel();
         let proxy = talpid_types::net::proxy::CustomProxy::try_from(config.into_inner())?;
         self.send_command_to_daemon(DaemonCommand::TestCustomApiAccessMethod(tx, proxy))?;
         self.wait_for_result(rx)
             .await?
-            .map(Response::new)
+            .map(|result| Response::new(types::AccessMethodTestResult::from(result)))
             .map_err(map_daemon_error)
     }
 
     async fn test_api_access_method_by_id(
         &self,
         request: Request<types::Uuid>,
-    ) -> ServiceResult<bool> {
+    ) -> ServiceResult<types::AccessMethodTestResult> {
         log::debug!("test_api_access_method_by_id");
         let (tx, rx) = oneshot::channel();
         let api_access_method = mullvad_types::access_method::Id::try_from(request.into_inner())?;
@@ -736,7 +1421,7 @@ impl ManagementService for ManagementServiceImpl {
         ))?;
         self.wait_for_result(rx)
             .await?
-            .map(Response::new)
+            .map(|result| Response::new(types::AccessMethodTestResult::from(result)))
             .map_err(map_daemon_error)
     }
 
@@ -775,8 +1460,10 @@ impl ManagementService for ManagementServiceImpl {
 
     #[cfg(target_os = "linux")]
     async fn add_split_tunnel_process(&self, request: Request<i32>) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
         let pid = request.into_inner();
         log::debug!("add_split_tunnel_process");
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::AddSplitTunnelProcess(tx, pid))?;
         self.wait_for_result(rx)
@@ -791,8 +1478,10 @@ impl ManagementService for ManagementServiceImpl {
 
     #[cfg(target_os = "linux")]
     async fn remove_split_tunnel_process(&self, request: Request<i32>) -> ServiceResult<()> {
+        let connection = ConnectionId::of(&request);
         let pid = request.into_inner();
         log::debug!("remove_split_tunnel_process");
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::RemoveSplitTunnelProcess(tx, pid))?;
         self.wait_for_result(rx)
@@ -805,10 +1494,14 @@ impl ManagementService for ManagementServiceImpl {
         Ok(Response::new(()))
     }
 
-    async fn clear_split_tunnel_processes(&self, _: Request<()>) -> ServiceResult<()> {
+    async fn clear_split_tunnel_processes(
+        &self,
+        #[cfg_attr(not(target_os = "linux"), allow(unused_variables))] request: Request<()>,
+    ) -> ServiceResult<()> {
         #[cfg(target_os = "linux")]
         {
             log::debug!("clear_split_tunnel_processes");
+            self.ensure_unlocked(ConnectionId::of(&request)).await?;
             let (tx, rx) = oneshot::channel();
             self.send_command_to_daemon(DaemonCommand::ClearSplitTunnelProcesses(tx))?;
             self.wait_for_result(rx)
@@ -825,8 +1518,10 @@ impl ManagementService for ManagementServiceImpl {
     #[cfg(any(windows, target_os = "android", target_os = "macos"))]
     async fn add_split_tunnel_app(&self, request: Request<String>) -> ServiceResult<()> {
         use mullvad_types::settings::SplitApp;
+        let connection = ConnectionId::of(&request);
         log::debug!("add_split_tunnel_app");
         let path = SplitApp::from(request.into_inner());
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::AddSplitTunnelApp(tx, path))?;
         self.wait_for_result(rx)
@@ -843,8 +1538,10 @@ impl ManagementService for ManagementServiceImpl {
     #[cfg(any(windows, target_os = "android", target_os = "macos"))]
     async fn remove_split_tunnel_app(&self, request: Request<String>) -> ServiceResult<()> {
         use mullvad_types::settings::SplitApp;
+        let connection = ConnectionId::of(&request);
         log::debug!("remove_split_tunnel_app");
         let path = SplitApp::from(request.into_inner());
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::RemoveSplitTunnelApp(tx, path))?;
         self.wait_for_result(rx)
@@ -858,8 +1555,10 @@ impl ManagementService for ManagementServiceImpl {
     }
 
     #[cfg(any(windows, target_os = "android", target_os = "macos"))]
-    async fn clear_split_tunnel_apps(&self, _: Request<()>) -> ServiceResult<()> {
+    async fn clear_split_tunnel_apps(&self, request: Request<()>) -> ServiceResult<()> {
         log::debug!("clear_split_tunnel_apps");
+        let connection = ConnectionId::of(&request);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::ClearSplitTunnelApps(tx))?;
         self.wait_for_result(rx)
@@ -875,7 +1574,9 @@ impl ManagementService for ManagementServiceImpl {
     #[cfg(any(windows, target_os = "android", target_os = "macos"))]
     async fn set_split_tunnel_state(&self, request: Request<bool>) -> ServiceResult<()> {
         log::debug!("set_split_tunnel_state");
+        let connection = ConnectionId::of(&request);
         let enabled = request.into_inner();
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::SetSplitTunnelState(tx, enabled))?;
         self.wait_for_result(rx)
@@ -937,12 +1638,34 @@ impl ManagementService for ManagementServiceImpl {
 
     async fn apply_json_settings(&self, blob: Request<String>) -> ServiceResult<()> {
         log::debug!("apply_json_settings");
+        let connection = ConnectionId::of(&blob);
+        self.ensure_unlocked(connection).await?;
         let (tx, rx) = oneshot::channel();
         self.send_command_to_daemon(DaemonCommand::ApplyJsonSettings(tx, blob.into_inner()))?;
         self.wait_for_result(rx).await??;
         Ok(Response::new(()))
     }
 
+    async fn apply_settings(
+        &self,
+        request: Request<types::ApplySettingsRequest>,
+    ) -> ServiceResult<()> {
+        log::debug!("apply_settings");
+        let connection = ConnectionId::of(&request);
+        let request = request.into_inner();
+        if !request.dry_run {
+            self.ensure_unlocked(connection).await?;
+        }
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::ApplySettings(
+            tx,
+            request.document,
+            request.dry_run,
+        ))?;
+        self.wait_for_result(rx).await?.map_err(map_daemon_error)?;
+        Ok(Response::new(()))
+    }
+
     async fn export_json_settings(&self, _: Request<()>) -> ServiceResult<String> {
         log::debug!("export_json_settings");
         let (tx, rx) = oneshot::channel();
@@ -951,6 +1674,139 @@ impl ManagementService for ManagementServiceImpl {
         Ok(Response::new(blob))
     }
 
+    async fn get_traffic_stats(&self, _: Request<()>) -> ServiceResult<types::TrafficStats> {
+        log::debug!("get_traffic_stats");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetTrafficStats(tx))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(|stats| Response::new(types::TrafficStats::from(stats)))
+            .map_err(map_daemon_error)
+    }
+
+    async fn get_runtime_config(&self, _: Request<()>) -> ServiceResult<types::RuntimeConfig> {
+        log::debug!("get_runtime_config");
+        Ok(Response::new(types::RuntimeConfig::from(
+            runtime_config::active_overrides(),
+        )))
+    }
+
+    async fn capture_debug_log(
+        &self,
+        request: Request<types::DebugLogCaptureRequest>,
+    ) -> ServiceResult<String> {
+        let request = request.into_inner();
+        let duration = request
+            .duration
+            .ok_or_else(|| Status::invalid_argument("missing capture duration"))
+            .and_then(|duration| {
+                Duration::try_from(duration)
+                    .map_err(|_| Status::invalid_argument("unexpected negative capture duration"))
+            })?;
+        log::debug!("capture_debug_log({:?}, {:?})", request.modules, duration);
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::CaptureDebugLog(
+            tx,
+            request.modules,
+            duration,
+        ))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    #[cfg(windows)]
+    async fn cleanup_firewall(&self, request: Request<()>) -> ServiceResult<u32> {
+        log::debug!("cleanup_firewall");
+        let connection = ConnectionId::of(&request);
+        self.ensure_unlocked(connection).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::CleanupFirewall(tx))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(|num_removed| Response::new(u32::try_from(num_removed).unwrap_or(u32::MAX)))
+            .map_err(map_daemon_error)
+    }
+
+    #[cfg(not(windows))]
+    async fn cleanup_firewall(&self, _: Request<()>) -> ServiceResult<u32> {
+        Err(
+            Status::unimplemented("Firewall cleanup is only supported on Windows")
+                .with_error_code(ErrorCode::NotSupported),
+        )
+    }
+
+    #[cfg(target_os = "macos")]
+    async fn get_firewall_rules(&self, _: Request<()>) -> ServiceResult<String> {
+        log::debug!("get_firewall_rules");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetFirewallRules(tx))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    async fn get_firewall_rules(&self, _: Request<()>) -> ServiceResult<String> {
+        Err(
+            Status::unimplemented("Reading the firewall rules is only supported on macOS")
+                .with_error_code(ErrorCode::NotSupported),
+        )
+    }
+
+    #[cfg(target_os = "macos")]
+    async fn is_early_boot_blocking_installed(&self, _: Request<()>) -> ServiceResult<bool> {
+        log::debug!("is_early_boot_blocking_installed");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::IsEarlyBootBlockingInstalled(tx))?;
+        self.wait_for_result(rx)
+            .await?
+            .map(Response::new)
+            .map_err(map_daemon_error)
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    async fn is_early_boot_blocking_installed(&self, _: Request<()>) -> ServiceResult<bool> {
+        Err(
+            Status::unimplemented("Early boot blocking can only be checked on macOS")
+                .with_error_code(ErrorCode::NotSupported),
+        )
+    }
+
+    #[cfg(not(target_os = "android"))]
+    async fn run_self_test(&self, request: Request<()>) -> ServiceResult<types::SelfTestResult> {
+        log::debug!("run_self_test");
+        let connection = ConnectionId::of(&request);
+        self.ensure_unlocked(connection).await?;
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::RunSelfTest(tx))?;
+        let steps = self.wait_for_result(rx).await?.map_err(map_daemon_error)?;
+        Ok(Response::new(types::SelfTestResult {
+            steps: steps.into_iter().map(types::SelfTestStep::from).collect(),
+        }))
+    }
+
+    #[cfg(target_os = "android")]
+    async fn run_self_test(&self, _: Request<()>) -> ServiceResult<types::SelfTestResult> {
+        Err(
+            Status::unimplemented("The self test is not supported on Android")
+                .with_error_code(ErrorCode::NotSupported),
+        )
+    }
+
+    async fn diagnose_connection(
+        &self,
+        _: Request<()>,
+    ) -> ServiceResult<types::ConnectionDiagnosis> {
+        log::debug!("diagnose_connection");
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::DiagnoseConnection(tx))?;
+        let reasons = self.wait_for_result(rx).await?.map_err(map_daemon_error)?;
+        Ok(Response::new(types::ConnectionDiagnosis::from(reasons)))
+    }
+
     #[cfg(target_os = "android")]
     async fn init_play_purchase(
         &self,
@@ -1009,23 +1865,79 @@ impl ManagementService for ManagementServiceImpl {
 impl ManagementServiceImpl {
     /// Sends a command to the daemon and maps the error to an RPC error.
     fn send_command_to_daemon(&self, command: DaemonCommand) -> Result<(), Status> {
-        self.daemon_tx
-            .send(command)
-            .map_err(|_| Status::internal("the daemon channel receiver has been dropped"))
+        self.daemon_tx.send(command).map_err(|_| {
+            Status::internal("the daemon channel receiver has been dropped")
+                .with_error_code(ErrorCode::DaemonUnavailable)
+        })
     }
 
     async fn wait_for_result<T>(&self, rx: oneshot::Receiver<T>) -> Result<T, Status> {
-        rx.await.map_err(|_| Status::internal("sender was dropped"))
+        rx.await.map_err(|_| {
+            Status::internal("sender was dropped").with_error_code(ErrorCode::DaemonUnavailable)
+        })
+    }
+
+    /// Returns the state of the settings lock for `connection`.
+    async fn settings_lock_state(
+        &self,
+        connection: Option<ConnectionId>,
+    ) -> Result<SettingsLockState, Status> {
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetSettingsLockState(tx, connection))?;
+        self.wait_for_result(rx).await
+    }
+
+    /// Fails unless the settings lock is disabled or has been unlocked by `connection`. Called by
+    /// every RPC that disconnects, disables lockdown mode, changes the settings or rotates the
+    /// WireGuard key. RPCs that only refresh data from the API, such as `update_device` and
+    /// `update_relay_locations`, are allowed while locked since they neither change the settings
+    /// nor weaken the protection of the tunnel.
+    async fn ensure_unlocked(&self, connection: Option<ConnectionId>) -> Result<(), Status> {
+        if self.settings_lock_state(connection).await?.is_locked() {
+            return Err(Status::permission_denied(
+                "The settings are locked. Unlock them using the passphrase",
+            )
+            .with_error_code(ErrorCode::SettingsLocked));
+        }
+        Ok(())
+    }
+
+    /// Returns the settings lock if `passphrase` is its passphrase.
+    async fn verify_passphrase(&self, passphrase: String) -> Result<SettingsLock, Status> {
+        let (tx, rx) = oneshot::channel();
+        self.send_command_to_daemon(DaemonCommand::GetSettings(tx))?;
+        let lock = self
+            .wait_for_result(rx)
+            .await?
+            .settings_lock
+            .ok_or_else(|| Status::failed_precondition("The settings lock is not enabled"))?;
+
+        let verified = tokio::task::spawn_blocking(move || {
+            settings_lock::verify(&lock, &passphrase).then_some(lock)
+        })
+        .await
+        .map_err(|_| Status::internal("failed to verify passphrase"))?;
+        match verified {
+            Some(lock) => Ok(lock),
+            None => {
+                log::warn!("Rejecting wrong settings lock passphrase");
+                tokio::time::sleep(settings_lock::WRONG_PASSPHRASE_DELAY).await;
+                Err(Status::permission_denied("Wrong passphrase")
+                    .with_error_code(ErrorCode::WrongPassphrase))
+            }
+        }
     }
 }
 
 pub struct ManagementInterfaceServer(());
 
 impl ManagementInterfaceServer {
-    pub fn start(
+    pub async fn start(
         tunnel_tx: DaemonCommandSender,
+        settings_dir: &Path,
     ) -> Result<(String, ManagementInterfaceEventBroadcaster), Error> {
         let subscriptions = Arc::<Mutex<Vec<EventsListenerSender>>>::default();
+        let notification_subscriptions = Arc::<Mutex<Vec<NotificationsSender>>>::default();
 
         let socket_path = mullvad_paths::get_rpc_socket_path()
             .to_string_lossy()
@@ -1035,39 +1947,110 @@ impl ManagementInterfaceServer {
         let server = ManagementServiceImpl {
             daemon_tx: tunnel_tx,
             subscriptions: subscriptions.clone(),
+            notification_subscriptions: notification_subscriptions.clone(),
         };
+        // The management interface is only served over TCP if a port is set. This is off by default.
+        let tcp_port = runtime_config::get()
+            .management_tcp_port
+            .clone()
+            .map_err(Error::InvalidTcpPort)?;
+        let tcp_close_handle = match tcp_port {
+            Some(port) => {
+                let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+                let token = load_or_create_token(settings_dir).await?;
+                let (tcp_abort_tx, tcp_abort_rx) = mpsc::channel(0);
+                let join_handle = mullvad_management_interface::spawn_tcp_rpc_server(
+                    server.clone(),
+                    address,
+                    token,
+                    async move {
+                        tcp_abort_rx.into_future().await;
+                    },
+                )
+                .await
+                .map_err(Error::SetupError)?;
+                log::info!(
+                    "Management interface listening on {address}. Clients authenticate with the \
+                    token in {}",
+                    settings_dir.join(TOKEN_FILE).display()
+                );
+                Self::log_shutdown(join_handle);
+                Some(tcp_abort_tx)
+            }
+            None => None,
+        };
+
         let join_handle = mullvad_management_interface::spawn_rpc_server(server, async move {
             server_abort_rx.into_future().await;
         })
         .map_err(Error::SetupError)?;
-
-        tokio::spawn(async move {
-            if let Err(error) = join_handle.await {
-                log::error!("Management server panic: {}", error);
-            }
-            log::info!("Management interface shut down");
-        });
+        Self::log_shutdown(join_handle);
 
         Ok((
             socket_path,
             ManagementInterfaceEventBroadcaster {
                 subscriptions,
+                notification_subscriptions,
+                notification_policy: Arc::default(),
                 _close_handle: server_abort_tx,
+                _tcp_close_handle: tcp_close_handle,
             },
         ))
     }
+
+    fn log_shutdown(join_handle: mullvad_management_interface::ServerJoinHandle) {
+        tokio::spawn(async move {
+            if let Err(error) = join_handle.await {
+                log::error!("Management server panic: {}", error);
+            }
+            log::info!("Management interface shut down");
+        });
+    }
+}
+
+/// Read the token that TCP clients must authenticate with, or create a new one if there is none.
+/// The token is kept across restarts, so that it doesn't have to be shared again.
+async fn load_or_create_token(settings_dir: &Path) -> Result<String, Error> {
+    let path = settings_dir.join(TOKEN_FILE);
+    match tokio::fs::read_to_string(&path).await {
+        Ok(token) if tcp::is_valid_token(token.trim()) => return Ok(token.trim().to_owned()),
+        Ok(_) => log::warn!("Replacing invalid management interface token"),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => (),
+        Err(error) => return Err(Error::Token(error)),
+    }
+
+    let token: String = rand::random::<[u8; tcp::TOKEN_LEN / 2]>()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(&path).await.map_err(Error::Token)?;
+    tokio::io::AsyncWriteExt::write_all(&mut file, token.as_bytes())
+        .await
+        .map_err(Error::Token)?;
+
+    Ok(token)
 }
 
 /// A handle that allows broadcasting messages to all subscribers of the management interface.
 #[derive(Clone)]
 pub struct ManagementInterfaceEventBroadcaster {
     subscriptions: Arc<Mutex<Vec<EventsListenerSender>>>,
+    notification_subscriptions: Arc<Mutex<Vec<NotificationsSender>>>,
+    notification_policy: Arc<Mutex<NotificationPolicy>>,
     _close_handle: mpsc::Sender<()>,
+    _tcp_close_handle: Option<mpsc::Sender<()>>,
 }
 
 impl EventListener for ManagementInterfaceEventBroadcaster {
     /// Sends a new state update to all `new_state` subscribers of the management interface.
     fn notify_new_state(&self, new_state: TunnelState) {
+        let notification = self.notification_policy().tunnel_state(&new_state);
+        self.notify_user(notification);
         self.notify(types::DaemonEvent {
             event: Some(daemon_event::Event::TunnelState(types::TunnelState::from(
                 new_state,
@@ -1095,8 +2078,30 @@ impl EventListener for ManagementInterfaceEventBroadcaster {
         })
     }
 
+    fn notify_relay_list_diff(&self, diff: RelayListDiff) {
+        log::debug!("Broadcasting relay list changes");
+        self.notify(types::DaemonEvent {
+            event: Some(daemon_event::Event::RelayListDiff(
+                types::RelayListDiff::from(diff),
+            )),
+        })
+    }
+
+    fn notify_pinned_relay_removed(
+        &self,
+        hostname: String,
+        replacement: Option<GeographicLocationConstraint>,
+    ) {
+        let notification = self
+            .notification_policy()
+            .pinned_relay_removed(&hostname, replacement.as_ref());
+        self.notify_user(notification);
+    }
+
     fn notify_app_version(&self, app_version_info: version::AppVersionInfo) {
         log::debug!("Broadcasting new app version info");
+        let notification = self.notification_policy().app_version(&app_version_info);
+        self.notify_user(notification);
         self.notify(types::DaemonEvent {
             event: Some(daemon_event::Event::VersionInfo(
                 types::AppVersionInfo::from(app_version_info),
@@ -1106,6 +2111,8 @@ impl EventListener for ManagementInterfaceEventBroadcaster {
 
     fn notify_device_event(&self, device: mullvad_types::device::DeviceEvent) {
         log::debug!("Broadcasting device event");
+        let notification = self.notification_policy().device_event(&device);
+        self.notify_user(notification);
         self.notify(types::DaemonEvent {
             event: Some(daemon_event::Event::Device(types::DeviceEvent::from(
                 device,
@@ -1133,6 +2140,67 @@ impl EventListener for ManagementInterfaceEventBroadcaster {
             )),
         })
     }
+
+    fn notify_connection_verified(
+        &self,
+        verification: mullvad_types::location::ConnectionVerification,
+    ) {
+        log::debug!("Broadcasting connection verification");
+        let notification = self
+            .notification_policy()
+            .connection_verified(&verification);
+        self.notify_user(notification);
+        self.notify(types::DaemonEvent {
+            event: Some(daemon_event::Event::ConnectionVerified(
+                types::ConnectionVerification::from(verification),
+            )),
+        })
+    }
+
+    fn notify_connect_escalation(&self, escalation: mullvad_types::states::ConnectEscalation) {
+        log::debug!("Broadcasting connect escalation");
+        self.notify(types::DaemonEvent {
+            event: Some(daemon_event::Event::ConnectEscalation(
+                types::ConnectEscalation::from(escalation),
+            )),
+        })
+    }
+
+    fn notify_problem_report_progress(&self, progress: ProblemReportProgress) {
+        log::trace!("Broadcasting problem report progress");
+        self.notify(types::DaemonEvent {
+            event: Some(daemon_event::Event::ProblemReportProgress(
+                types::ProblemReportProgress::from(progress),
+            )),
+        })
+    }
+
+    fn notify_connect_progress(&self, progress: talpid_types::tunnel::ConnectProgress) {
+        log::trace!("Broadcasting connect progress");
+        self.notify(types::DaemonEvent {
+            event: Some(daemon_event::Event::ConnectProgress(
+                types::ConnectProgress::from(progress),
+            )),
+        })
+    }
+
+    fn notify_clock_skew_detected(&self, skew: mullvad_types::connection_diagnosis::ClockSkew) {
+        log::debug!("Broadcasting clock skew");
+        self.notify(types::DaemonEvent {
+            event: Some(daemon_event::Event::ClockSkewDetected(
+                types::ClockSkew::from(skew),
+            )),
+        })
+    }
+
+    fn notify_lockdown_countdown(&self, countdown: mullvad_types::states::LockdownCountdown) {
+        log::trace!("Broadcasting lockdown countdown");
+        self.notify(types::DaemonEvent {
+            event: Some(daemon_event::Event::LockdownCountdown(
+                types::LockdownCountdown::from(countdown),
+            )),
+        })
+    }
 }
 
 impl ManagementInterfaceEventBroadcaster {
@@ -1140,6 +2208,25 @@ impl ManagementInterfaceEventBroadcaster {
         let mut subscriptions = self.subscriptions.lock().unwrap();
         subscriptions.retain(|tx| tx.send(Ok(value.clone())).is_ok());
     }
+
+    fn notification_policy(&self) -> std::sync::MutexGuard<'_, NotificationPolicy> {
+        self.notification_policy.lock().unwrap()
+    }
+
+    /// Sends `notification`, if any, to all `notifications` subscribers.
+    fn notify_user(&self, notification: Option<Notification>) {
+        let Some(notification) = notification else {
+            return;
+        };
+        log::debug!(
+            "Broadcasting {} notification: {}",
+            notification.severity,
+            notification.message
+        );
+        let notification = types::Notification::from(notification);
+        let mut subscriptions = self.notification_subscriptions.lock().unwrap();
+        subscriptions.retain(|tx| tx.send(Ok(notification.clone())).is_ok());
+    }
 }
 
 /// Converts [`crate::Error`] into a tonic status.
@@ -1149,7 +2236,9 @@ fn map_daemon_error(error: crate::Error) -> Status {
     match error {
         DaemonError::RestError(error) => map_rest_error(&error),
         DaemonError::SettingsError(error) => Status::from(error),
-        DaemonError::AlreadyLoggedIn => Status::already_exists(error.to_string()),
+        DaemonError::AlreadyLoggedIn => {
+            Status::already_exists(error.to_string()).with_error_code(ErrorCode::AlreadyLoggedIn)
+        }
         DaemonError::LoginError(error) => map_device_error(&error),
         DaemonError::LogoutError(error) => map_device_error(&error),
         DaemonError::KeyRotationError(error) => map_device_error(&error),
@@ -1161,8 +2250,36 @@ fn map_daemon_error(error: crate::Error) -> Status {
         DaemonError::SplitTunnelError(error) => map_split_tunnel_error(error),
         DaemonError::AccountHistory(error) => map_account_history_error(error),
         DaemonError::NoAccountToken | DaemonError::NoAccountTokenHistory => {
-            Status::unauthenticated(error.to_string())
+            Status::unauthenticated(error.to_string()).with_error_code(ErrorCode::NotLoggedIn)
+        }
+        DaemonError::AccessMethodError(crate::access_method::Error::NoSuchMethod(_)) => {
+            Status::not_found(error.to_string()).with_error_code(ErrorCode::AccessMethodNotFound)
         }
+        DaemonError::DebugCaptureError(crate::logging::Error::CaptureInProgress) => {
+            Status::already_exists(error.to_string())
+        }
+        DaemonError::ImportRelayList(_)
+        | DaemonError::RelayListUpdater(crate::relay_list::Error::Outdated)
+        | DaemonError::InvalidWebhook(_)
+        | DaemonError::InvalidConnectionCheckHost(_)
+        | DaemonError::InvalidCustomApiEndpoint(_)
+        | DaemonError::InvalidConnectDeadline
+        | DaemonError::InvalidSettingsDocument(_)
+        | DaemonError::InvalidProxyChain(_) => Status::invalid_argument(error.display_chain())
+            .with_error_code(ErrorCode::InvalidArgument),
+        #[cfg(not(target_os = "android"))]
+        DaemonError::SelectOpenVpnRelay(_) => {
+            Status::not_found(error.to_string()).with_error_code(ErrorCode::NoMatchingRelay)
+        }
+        #[cfg(not(target_os = "android"))]
+        DaemonError::ExportCustomRelay => {
+            Status::failed_precondition(error.to_string()).with_error_code(ErrorCode::NotSupported)
+        }
+        DaemonError::CustomApiEndpointNotAllowed => Status::permission_denied(error.to_string()),
+        DaemonError::PingRelaysUnavailable => Status::failed_precondition(error.to_string()),
+        #[cfg(not(target_os = "android"))]
+        DaemonError::SelfTestUnavailable => Status::failed_precondition(error.to_string()),
+        DaemonError::DiagnosisUnavailable => Status::failed_precondition(error.to_string()),
         error => Status::unknown(error.to_string()),
     }
 }
@@ -1196,10 +2313,13 @@ fn map_rest_error(error: &RestError) -> Status {
         RestError::ApiError(status, message)
             if *status == StatusCode::UNAUTHORIZED || *status == StatusCode::FORBIDDEN =>
         {
-            Status::new(Code::Unauthenticated, message)
+            Status::new(Code::Unauthenticated, message).with_error_code(ErrorCode::InvalidAccount)
+        }
+        RestError::TimeoutError => Status::deadline_exceeded("API request timed out")
+            .with_error_code(ErrorCode::ApiTimeout),
+        RestError::HyperError(_) => {
+            Status::unavailable("Cannot reach the API").with_error_code(ErrorCode::ApiUnreachable)
         }
-        RestError::TimeoutError => Status::deadline_exceeded("API request timed out"),
-        RestError::HyperError(_) => Status::unavailable("Cannot reach the API"),
         error => Status::unknown(format!("REST error: {error}")),
     }
 }
@@ -1207,13 +2327,18 @@ fn map_rest_error(error: &RestError) -> Status {
 /// Converts an instance of [`crate::device::Error`] into a tonic status.
 fn map_device_error(error: &device::Error) -> Status {
     match error {
-        device::Error::MaxDevicesReached => Status::new(Code::ResourceExhausted, error.to_string()),
-        device::Error::InvalidAccount => Status::new(Code::Unauthenticated, error.to_string()),
+        device::Error::MaxDevicesReached => Status::new(Code::ResourceExhausted, error.to_string())
+            .with_error_code(ErrorCode::TooManyDevices),
+        device::Error::InvalidAccount => Status::new(Code::Unauthenticated, error.to_string())
+            .with_error_code(ErrorCode::InvalidAccount),
         device::Error::InvalidDevice | device::Error::NoDevice => {
             Status::new(Code::NotFound, error.to_string())
+                .with_error_code(ErrorCode::DeviceNotFound)
         }
-        device::Error::InvalidVoucher => Status::new(Code::NotFound, INVALID_VOUCHER_MESSAGE),
-        device::Error::UsedVoucher => Status::new(Code::ResourceExhausted, USED_VOUCHER_MESSAGE),
+        device::Error::InvalidVoucher => Status::new(Code::NotFound, INVALID_VOUCHER_MESSAGE)
+            .with_error_code(ErrorCode::InvalidVoucher),
+        device::Error::UsedVoucher => Status::new(Code::ResourceExhausted, USED_VOUCHER_MESSAGE)
+            .with_error_code(ErrorCode::UsedVoucher),
         device::Error::DeviceIoError(ref _error) => {
             Status::new(Code::Unavailable, error.to_string())
         }
@@ -1227,6 +2352,7 @@ fn map_account_history_error(error: account_history::Error) -> Status {
     match error {
         account_history::Error::Read(..) | account_history::Error::Write(..) => {
             Status::new(Code::FailedPrecondition, error.to_string())
+                .with_error_code(ErrorCode::SettingsIoFailed)
         }
         account_history::Error::Serialize(..) | account_history::Error::WriteCancelled(..) => {
             Status::new(Code::Internal, error.to_string())
@@ -96,7 +96,7 @@ async fn write_format_v3(mut file: File, token: Option<AccountToken>) -> Result<
         .await
         .map_err(Error::WriteHistory)?;
     if let Some(token) = token {
-        file.write_all(token.as_bytes())
+        file.write_all(token.expose_str().as_bytes())
             .await
             .map_err(Error::WriteHistory)?;
     }
@@ -345,7 +345,7 @@ mod test {
             super::migrate_formats_inner(ACCOUNT_HISTORY_V2.as_bytes(), &mut old_settings).unwrap();
 
         assert_eq!(&old_settings, &new_settings);
-        assert_eq!(token, Some("1234".to_string()));
+        assert_eq!(token, Some(super::AccountToken::from("1234")));
 
         // Test whether empty histories are handled correctly
         let mut old_settings = serde_json::from_str(OLD_SETTINGS).unwrap();
@@ -359,7 +359,7 @@ mod test {
     #[test]
     fn test_v1() {
         let token = super::try_format_v1(ACCOUNT_HISTORY_V1.as_bytes()).unwrap();
-        assert_eq!(token, Some("1234".to_string()));
+        assert_eq!(token, Some(super::AccountToken::from("1234")));
         let token = super::try_format_v1(ACCOUNT_HISTORY_V1_EMPTY.as_bytes()).unwrap();
         assert_eq!(token, None);
     }
@@ -39,8 +39,9 @@ pub(crate) fn generate_device(
         });
 
         let api_handle = rest_handle.availability.clone();
-        let service = DeviceService::new(rest_handle, api_handle);
-        let result = match (migration_data.token, wg_data) {
+        let service =
+            DeviceService::new(rest_handle, api_handle, device::key_provider::from_config());
+        let result = match (AccountToken::from(migration_data.token), wg_data) {
             (token, Some(wg_data)) => {
                 log::info!("Creating a new device cache from previous settings");
                 cache_from_wireguard_key(service, token, wg_data).await
@@ -71,6 +71,9 @@ pub enum Error {
     #[error("Unable to serialize settings to JSON")]
     Serialize(#[source] serde_json::Error),
 
+    #[error("Unable to lock the settings")]
+    Lock(#[source] io::Error),
+
     #[error("Unable to open settings for writing")]
     Open(#[source] io::Error),
 
@@ -155,17 +158,17 @@ pub async fn migrate_all(cache_dir: &Path, settings_dir: &Path) -> Result<Option
 
     let buffer = serde_json::to_string_pretty(&settings).map_err(Error::Serialize)?;
 
-    let mut file = fs::OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open(&path)
+    let _lock = mullvad_fs::FileLock::lock(settings_dir.join(crate::settings::LOCK_FILE))
         .await
-        .map_err(Error::Open)?;
+        .map_err(Error::Lock)?;
+    let mut file =
+        mullvad_fs::AtomicFile::with_fsync_policy(&path, crate::settings::fsync_policy())
+            .await
+            .map_err(Error::Open)?;
     file.write_all(&buffer.into_bytes())
         .await
         .map_err(Error::Write)?;
-    file.sync_data().await.map_err(Error::SyncSettings)?;
+    file.finalize().await.map_err(Error::SyncSettings)?;
 
     log::debug!("Migrated settings. Wrote settings to {}", path.display());
 
@@ -228,6 +228,7 @@ fn migrate_bridge_settings(settings: &mut serde_json::Value) -> Result<()> {
                     .try_into()
                     .map_err(|_| Error::InvalidSettingsContent)?,
             })),
+            custom_chain: vec![],
         }
     } else if let Some(custom_bridge_remote) = settings
         .get_mut("bridge_settings")
@@ -246,7 +247,9 @@ fn migrate_bridge_settings(settings: &mut serde_json::Value) -> Result<()> {
                     let password = auth.get("password")?.to_string();
                     SocksAuth::new(username, password).ok()
                 }),
+                hostname: None,
             })),
+            custom_chain: vec![],
         }
     } else if let Some(custom_bridge_shadowsocks) = settings
         .get_mut("bridge_settings")
@@ -260,9 +263,11 @@ fn migrate_bridge_settings(settings: &mut serde_json::Value) -> Result<()> {
                 endpoint: extract_str(custom_bridge_shadowsocks.get("peer"))?
                     .parse()
                     .map_err(|_| Error::InvalidSettingsContent)?,
-                password: extract_str(custom_bridge_shadowsocks.get("password"))?.to_string(),
+                password: extract_str(custom_bridge_shadowsocks.get("password"))?.into(),
                 cipher: extract_str(custom_bridge_shadowsocks.get("cipher"))?.to_string(),
+                hostname: None,
             })),
+            custom_chain: vec![],
         }
     } else if let Some(normal_bridge) = settings
         .get_mut("bridge_settings")
@@ -272,6 +277,7 @@ fn migrate_bridge_settings(settings: &mut serde_json::Value) -> Result<()> {
             bridge_type: BridgeType::Normal,
             normal: serde_json::from_value(normal_bridge.clone()).map_err(Error::Serialize)?,
             custom: None,
+            custom_chain: vec![],
         }
     } else {
         return Ok(());
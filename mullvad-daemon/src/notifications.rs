@@ -0,0 +1,281 @@
+//! Decides which daemon events the user should be told about. Events are classified by severity,
+//! and a notification is dropped if it repeats the previous one on the same topic. To keep a
+//! flapping tunnel from flooding the user, only a few notifications per topic are let through
+//! within a short period. Critical notifications are never held back by that limit.
+
+use mullvad_types::{
+    device::{DeviceEvent, DeviceEventCause},
+    location::ConnectionVerification,
+    notification::{Notification, NotificationKind, NotificationSeverity},
+    relay_constraints::GeographicLocationConstraint,
+    states::TunnelState,
+    version::AppVersionInfo,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// Period over which notifications on a topic are counted.
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+/// Number of non-critical notifications on a topic that are let through per
+/// [`RATE_LIMIT_WINDOW`].
+const MAX_NOTIFICATIONS_PER_WINDOW: usize = 3;
+
+/// Notifications on the same topic replace each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Topic {
+    Tunnel,
+    ConnectionCheck,
+    Device,
+    Version,
+    RelayList,
+}
+
+impl From<NotificationKind> for Topic {
+    fn from(kind: NotificationKind) -> Self {
+        match kind {
+            NotificationKind::Connected
+            | NotificationKind::Disconnected
+            | NotificationKind::Blocking
+            | NotificationKind::Leaking => Topic::Tunnel,
+            NotificationKind::ConnectionNotVerified => Topic::ConnectionCheck,
+            NotificationKind::DeviceRevoked => Topic::Device,
+            NotificationKind::UnsupportedVersion | NotificationKind::UpdateAvailable => {
+                Topic::Version
+            }
+            NotificationKind::PinnedRelayRemoved => Topic::RelayList,
+        }
+    }
+}
+
+#[derive(Default)]
+struct TopicState {
+    /// The last notification that was let through.
+    last: Option<Notification>,
+    /// When notifications were let through within the last [`RATE_LIMIT_WINDOW`].
+    recent: VecDeque<Instant>,
+}
+
+#[derive(Default)]
+pub struct NotificationPolicy {
+    topics: HashMap<Topic, TopicState>,
+}
+
+impl NotificationPolicy {
+    pub fn tunnel_state(&mut self, state: &TunnelState) -> Option<Notification> {
+        let notification = match state {
+            TunnelState::Connected { .. } => Notification {
+                kind: NotificationKind::Connected,
+                severity: NotificationSeverity::Info,
+                message: "Secured connection".to_owned(),
+            },
+            TunnelState::Disconnected {
+                locked_down: true, ..
+            } => Notification {
+                kind: NotificationKind::Disconnected,
+                severity: NotificationSeverity::Info,
+                message: "Disconnected. Internet access is blocked by lockdown mode".to_owned(),
+            },
+            TunnelState::Disconnected { .. } => Notification {
+                kind: NotificationKind::Disconnected,
+                severity: NotificationSeverity::Info,
+                message: "Disconnected. The connection is not secured".to_owned(),
+            },
+            TunnelState::Error(error_state) if error_state.is_blocking() => Notification {
+                kind: NotificationKind::Blocking,
+                severity: NotificationSeverity::Warning,
+                message: format!("Blocking internet: {}", error_state.cause()),
+            },
+            TunnelState::Error(error_state) => Notification {
+                kind: NotificationKind::Leaking,
+                severity: NotificationSeverity::Critical,
+                message: format!("Failed to block internet: {}", error_state.cause()),
+            },
+            // Transitions in progress are not worth interrupting the user for
+            TunnelState::Connecting { .. } | TunnelState::Disconnecting(_) => return None,
+        };
+        self.filter(notification, Instant::now())
+    }
+
+    pub fn connection_verified(
+        &mut self,
+        verification: &ConnectionVerification,
+    ) -> Option<Notification> {
+        if verification.is_verified() {
+            // Tell the user again if a later check fails in the same way
+            self.topics.remove(&Topic::ConnectionCheck);
+            return None;
+        }
+
+        let mut problems = vec![];
+        if !verification.mullvad_exit_ip {
+            problems.push(format!(
+                "traffic exits through {}, which is not a Mullvad relay",
+                verification.exit_ip
+            ));
+        }
+        if verification.exit_relay_matches == Some(false) {
+            problems.push("traffic exits through another relay than the selected one".to_owned());
+        }
+        if verification.dns_hijacked == Some(true) {
+            problems.push("DNS lookups are answered by servers other than Mullvad's".to_owned());
+        }
+        let notification = Notification {
+            kind: NotificationKind::ConnectionNotVerified,
+            severity: if verification.mullvad_exit_ip {
+                NotificationSeverity::Warning
+            } else {
+                NotificationSeverity::Critical
+            },
+            message: format!("Connection check failed: {}", problems.join(", ")),
+        };
+        self.filter(notification, Instant::now())
+    }
+
+    pub fn device_event(&mut self, event: &DeviceEvent) -> Option<Notification> {
+        match event.cause {
+            DeviceEventCause::Revoked => (),
+            DeviceEventCause::LoggedIn => {
+                // Tell the user again if the new device is also revoked
+                self.topics.remove(&Topic::Device);
+                return None;
+            }
+            _ => return None,
+        }
+        let notification = Notification {
+            kind: NotificationKind::DeviceRevoked,
+            severity: NotificationSeverity::Critical,
+            message: "This device was removed from the account. Log in again to continue"
+                .to_owned(),
+        };
+        self.filter(notification, Instant::now())
+    }
+
+    /// `replacement` is the location that is used instead of the removed relay, if any.
+    pub fn pinned_relay_removed(
+        &mut self,
+        hostname: &str,
+        replacement: Option<&GeographicLocationConstraint>,
+    ) -> Option<Notification> {
+        let notification = match replacement {
+            Some(location) => Notification {
+                kind: NotificationKind::PinnedRelayRemoved,
+                severity: NotificationSeverity::Info,
+                message: format!(
+                    "The selected relay {hostname} no longer exists. Using {location} instead"
+                ),
+            },
+            None => Notification {
+                kind: NotificationKind::PinnedRelayRemoved,
+                severity: NotificationSeverity::Warning,
+                message: format!(
+                    "The selected relay {hostname} no longer exists. Select another location"
+                ),
+            },
+        };
+        self.filter(notification, Instant::now())
+    }
+
+    pub fn app_version(&mut self, version_info: &AppVersionInfo) -> Option<Notification> {
+        let notification = if !version_info.supported {
+            Notification {
+                kind: NotificationKind::UnsupportedVersion,
+                severity: NotificationSeverity::Warning,
+                message: "This version of the app is no longer supported. Please upgrade"
+                    .to_owned(),
+            }
+        } else if let Some(upgrade) = &version_info.suggested_upgrade {
+            Notification {
+                kind: NotificationKind::UpdateAvailable,
+                severity: NotificationSeverity::Info,
+                message: format!("Version {} is available", upgrade.version),
+            }
+        } else {
+            return None;
+        };
+        self.filter(notification, Instant::now())
+    }
+
+    /// Returns `notification` unless it repeats the previous notification on its topic, or too
+    /// many notifications have been let through on the topic recently.
+    fn filter(&mut self, notification: Notification, now: Instant) -> Option<Notification> {
+        let topic = self
+            .topics
+            .entry(Topic::from(notification.kind))
+            .or_default();
+        if topic.last.as_ref() == Some(&notification) {
+            return None;
+        }
+
+        while topic
+            .recent
+            .front()
+            .is_some_and(|sent| now.saturating_duration_since(*sent) >= RATE_LIMIT_WINDOW)
+        {
+            topic.recent.pop_front();
+        }
+        if notification.severity < NotificationSeverity::Critical
+            && topic.recent.len() >= MAX_NOTIFICATIONS_PER_WINDOW
+        {
+            log::debug!("Suppressing notification: {}", notification.message);
+            return None;
+        }
+
+        topic.recent.push_back(now);
+        topic.last = Some(notification.clone());
+        Some(notification)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn notification(kind: NotificationKind, severity: NotificationSeverity) -> Notification {
+        Notification {
+            kind,
+            severity,
+            message: format!("{kind:?}"),
+        }
+    }
+
+    /// A notification that repeats the previous one on its topic is dropped.
+    #[test]
+    fn test_deduplicate() {
+        let mut policy = NotificationPolicy::default();
+        let now = Instant::now();
+        let connected = notification(NotificationKind::Connected, NotificationSeverity::Info);
+        let disconnected = notification(NotificationKind::Disconnected, NotificationSeverity::Info);
+
+        assert!(policy.filter(connected.clone(), now).is_some());
+        assert!(policy.filter(connected.clone(), now).is_none());
+        assert!(policy.filter(disconnected, now).is_some());
+        assert!(policy.filter(connected, now).is_some());
+    }
+
+    /// Only a few non-critical notifications per topic are let through within the window.
+    #[test]
+    fn test_rate_limit() {
+        let mut policy = NotificationPolicy::default();
+        let now = Instant::now();
+        let connected = notification(NotificationKind::Connected, NotificationSeverity::Info);
+        let blocking = notification(NotificationKind::Blocking, NotificationSeverity::Warning);
+        let leaking = notification(NotificationKind::Leaking, NotificationSeverity::Critical);
+
+        assert!(policy.filter(connected.clone(), now).is_some());
+        assert!(policy.filter(blocking.clone(), now).is_some());
+        assert!(policy.filter(connected.clone(), now).is_some());
+        assert!(policy.filter(blocking.clone(), now).is_none());
+        // Other topics are not affected
+        let update = notification(
+            NotificationKind::UpdateAvailable,
+            NotificationSeverity::Info,
+        );
+        assert!(policy.filter(update, now).is_some());
+        // Critical notifications are always let through
+        assert!(policy.filter(leaking, now).is_some());
+
+        assert!(policy.filter(blocking, now + RATE_LIMIT_WINDOW).is_some());
+    }
+}
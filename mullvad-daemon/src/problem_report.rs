@@ -0,0 +1,109 @@
+//! Uploads problem reports from the daemon, and sends the report again if the connection fails.
+//! The uploads use the same API connection as the rest of the daemon, including any access method
+//! that is used to reach the API.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use mullvad_api::{rest, ProblemReportProxy};
+use mullvad_types::problem_report::{ProblemReportProgress, ProblemReportRequest, UploadState};
+use talpid_future::retry::{ExponentialBackoff, Jittered};
+use talpid_types::ErrorExt;
+
+/// Number of network errors in a row after which the upload is abandoned.
+const MAX_FAILED_ATTEMPTS: u32 = 6;
+
+const RETRY_STRATEGY: Jittered<ExponentialBackoff> = Jittered::jitter(
+    ExponentialBackoff::new(Duration::from_secs(2), 2).max_delay(Some(Duration::from_secs(60))),
+);
+
+struct Upload<'a, F> {
+    id: u32,
+    proxy: ProblemReportProxy,
+    request: &'a ProblemReportRequest,
+    report: String,
+    metadata: BTreeMap<String, String>,
+    notify: F,
+}
+
+/// Uploads `request` and reports the progress to `notify`. `id` identifies the upload in the
+/// progress events.
+pub(crate) async fn upload(
+    proxy: ProblemReportProxy,
+    id: u32,
+    request: ProblemReportRequest,
+    notify: impl Fn(ProblemReportProgress),
+) {
+    let (report, metadata) = mullvad_problem_report::prepare_report(&request.report);
+    let upload = Upload {
+        id,
+        proxy,
+        request: &request,
+        report,
+        metadata,
+        notify,
+    };
+    upload.notify(UploadState::Uploading);
+
+    match upload.run().await {
+        Ok(()) => {
+            log::info!("Sent problem report {id}");
+            upload.notify(UploadState::Completed);
+        }
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg(&format!("Failed to send problem report {id}"))
+            );
+            upload.notify(UploadState::Failed(error.to_string()));
+        }
+    }
+}
+
+impl<F: Fn(ProblemReportProgress)> Upload<'_, F> {
+    fn notify(&self, state: UploadState) {
+        (self.notify)(ProblemReportProgress {
+            id: self.id,
+            report_bytes: self.report.len() as u64,
+            state,
+        });
+    }
+
+    /// Sends the whole report until it is received, or until it fails for a reason other than
+    /// the connection.
+    async fn run(&self) -> Result<(), rest::Error> {
+        let mut failed_attempts = 0;
+        let mut delays = RETRY_STRATEGY;
+
+        loop {
+            let Err(error) = self.send().await else {
+                return Ok(());
+            };
+            failed_attempts += 1;
+            if !error.is_network_error() || failed_attempts >= MAX_FAILED_ATTEMPTS {
+                return Err(error);
+            }
+            log::warn!(
+                "{}",
+                error.display_chain_with_msg("Failed to send problem report")
+            );
+            let delay = delays.next().unwrap_or_default();
+            self.notify(UploadState::Retrying {
+                attempt: failed_attempts,
+                delay,
+            });
+            tokio::time::sleep(delay).await;
+            self.notify(UploadState::Uploading);
+        }
+    }
+
+    async fn send(&self) -> Result<(), rest::Error> {
+        self.proxy
+            .problem_report(
+                &self.request.email,
+                &self.request.message,
+                &self.report,
+                &self.metadata,
+            )
+            .await
+    }
+}
@@ -0,0 +1,85 @@
+use crate::{dns, oneshot_map, Daemon, Error, EventListener, ResponseTx};
+use mullvad_types::profile::{self, Profile};
+use talpid_core::tunnel_state_machine::TunnelCommand;
+use talpid_types::ErrorExt;
+
+impl<L> Daemon<L>
+where
+    L: EventListener + Clone + Send + 'static,
+{
+    /// Save the current relay, obfuscation and DNS settings as a profile.
+    ///
+    /// An existing profile with the same name is replaced.
+    pub async fn save_profile(&mut self, name: String) -> Result<(), Error> {
+        self.settings
+            .try_update(|settings| {
+                let profile = Profile::from_settings(name, settings)?;
+                settings.profiles.save(profile);
+                Ok::<(), profile::Error>(())
+            })
+            .await
+            .map_err(Error::SettingsError)?;
+        Ok(())
+    }
+
+    /// Delete a profile.
+    ///
+    /// Returns an error if the profile doesn't exist.
+    pub async fn delete_profile(&mut self, name: String) -> Result<(), Error> {
+        self.settings
+            .try_update(|settings| settings.profiles.remove(&name))
+            .await
+            .map_err(Error::SettingsError)?;
+        Ok(())
+    }
+
+    /// Replace the relay, obfuscation and DNS settings with the ones in a profile. All settings
+    /// are changed at once, so the tunnel is reconnected at most one time.
+    ///
+    /// Returns an error if the profile doesn't exist.
+    pub(crate) async fn use_profile(&mut self, tx: ResponseTx<(), Error>, name: String) {
+        let old_settings = self.settings.to_settings();
+        let result = self
+            .settings
+            .try_update(|settings| {
+                let profile = settings.profiles.get(&name)?.clone();
+                profile.apply(settings);
+                Ok::<(), profile::Error>(())
+            })
+            .await;
+
+        match result {
+            Ok(true) => {
+                log::info!("Switched to profile \"{name}\"");
+
+                if self.settings.relay_settings != old_settings.relay_settings
+                    || self.settings.obfuscation_settings != old_settings.obfuscation_settings
+                    || self.settings.bridge_state != old_settings.bridge_state
+                {
+                    log::info!("Initiating tunnel restart because a profile was used");
+                    self.reconnect_tunnel_after_settings_change();
+                }
+
+                let dns_options = &self.settings.tunnel_options.dns_options;
+                if *dns_options != old_settings.tunnel_options.dns_options {
+                    #[cfg(target_os = "linux")]
+                    self.send_dns_manager_policy();
+                    let resolvers = dns::addresses_from_options(dns_options);
+                    self.send_tunnel_command(TunnelCommand::Dns(
+                        resolvers,
+                        oneshot_map(tx, |tx, ()| {
+                            Self::oneshot_send(tx, Ok(()), "use_profile response");
+                        }),
+                    ));
+                } else {
+                    Self::oneshot_send(tx, Ok(()), "use_profile response");
+                }
+            }
+            Ok(false) => Self::oneshot_send(tx, Ok(()), "use_profile response"),
+            Err(error) => {
+                log::error!("{}", error.display_chain_with_msg("Failed to use profile"));
+                Self::oneshot_send(tx, Err(Error::SettingsError(error)), "use_profile response");
+            }
+        }
+    }
+}
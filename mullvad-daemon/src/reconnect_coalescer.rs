@@ -0,0 +1,119 @@
+//! Coalesces reconnects caused by settings changes. A client that changes a setting many times in
+//! quick succession, such as a GUI slider, would otherwise cause the tunnel to reconnect for every
+//! single change.
+
+use crate::runtime_config;
+use std::time::{Duration, Instant};
+
+/// What to do about a reconnect requested by a settings change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ReconnectAction {
+    /// Reconnect immediately.
+    Now,
+    /// Reconnect after the given delay, and then call
+    /// [`ReconnectCoalescer::on_delayed_reconnect`].
+    After(Duration),
+    /// A delayed reconnect is already pending and will cover this change as well.
+    Pending,
+}
+
+/// Makes sure that settings changes cause at most one reconnect per quiet period. The first
+/// change reconnects immediately. Further changes within the quiet period are batched into a
+/// single reconnect at the end of it.
+pub(crate) struct ReconnectCoalescer {
+    quiet_period: Duration,
+    last_reconnect: Option<Instant>,
+    delayed_reconnect_pending: bool,
+}
+
+impl ReconnectCoalescer {
+    pub fn new(quiet_period: Duration) -> Self {
+        Self {
+            quiet_period,
+            last_reconnect: None,
+            delayed_reconnect_pending: false,
+        }
+    }
+
+    /// Create a coalescer using the quiet period set by the environment, or the default one.
+    pub fn from_env() -> Self {
+        Self::new(runtime_config::get().settings_reconnect_quiet_period)
+    }
+
+    /// Register a settings change that requires a reconnect at time `now`.
+    pub fn request_reconnect(&mut self, now: Instant) -> ReconnectAction {
+        if self.delayed_reconnect_pending {
+            return ReconnectAction::Pending;
+        }
+        let elapsed = self
+            .last_reconnect
+            .map(|last_reconnect| now.saturating_duration_since(last_reconnect));
+        match elapsed {
+            Some(elapsed) if elapsed < self.quiet_period => {
+                self.delayed_reconnect_pending = true;
+                ReconnectAction::After(self.quiet_period - elapsed)
+            }
+            _ => {
+                self.last_reconnect = Some(now);
+                ReconnectAction::Now
+            }
+        }
+    }
+
+    /// Register that a delayed reconnect, as returned by [`Self::request_reconnect`], happened at
+    /// time `now`.
+    pub fn on_delayed_reconnect(&mut self, now: Instant) {
+        self.delayed_reconnect_pending = false;
+        self.last_reconnect = Some(now);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const QUIET_PERIOD: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn test_coalesce_rapid_changes() {
+        let mut coalescer = ReconnectCoalescer::new(QUIET_PERIOD);
+        let start = Instant::now();
+
+        assert_eq!(coalescer.request_reconnect(start), ReconnectAction::Now);
+        assert_eq!(
+            coalescer.request_reconnect(start + Duration::from_millis(300)),
+            ReconnectAction::After(Duration::from_millis(700))
+        );
+        assert_eq!(
+            coalescer.request_reconnect(start + Duration::from_millis(600)),
+            ReconnectAction::Pending
+        );
+
+        coalescer.on_delayed_reconnect(start + QUIET_PERIOD);
+        assert_eq!(
+            coalescer.request_reconnect(start + Duration::from_millis(1500)),
+            ReconnectAction::After(Duration::from_millis(500))
+        );
+    }
+
+    #[test]
+    fn test_reconnect_immediately_after_quiet_period() {
+        let mut coalescer = ReconnectCoalescer::new(QUIET_PERIOD);
+        let start = Instant::now();
+
+        assert_eq!(coalescer.request_reconnect(start), ReconnectAction::Now);
+        assert_eq!(
+            coalescer.request_reconnect(start + QUIET_PERIOD),
+            ReconnectAction::Now
+        );
+    }
+
+    #[test]
+    fn test_no_quiet_period() {
+        let mut coalescer = ReconnectCoalescer::new(Duration::ZERO);
+        let start = Instant::now();
+
+        assert_eq!(coalescer.request_reconnect(start), ReconnectAction::Now);
+        assert_eq!(coalescer.request_reconnect(start), ReconnectAction::Now);
+    }
+}
@@ -1,7 +1,7 @@
 //! Relay list updater
 
 use futures::{
-    channel::mpsc,
+    channel::{mpsc, oneshot},
     future::{Fuse, FusedFuture},
     Future, FutureExt, SinkExt, StreamExt,
 };
@@ -13,7 +13,7 @@ use tokio::fs::File;
 
 use mullvad_api::{availability::ApiAvailabilityHandle, rest::MullvadRestHandle, RelayListProxy};
 use mullvad_relay_selector::RelaySelector;
-use mullvad_types::relay_list::RelayList;
+use mullvad_types::relay_list::{RelayList, RelayListDiff};
 use talpid_future::retry::{retry_future, ExponentialBackoff, Jittered};
 use talpid_types::ErrorExt;
 
@@ -39,18 +39,29 @@ pub enum Error {
 
     #[error("Mullvad relay selector error")]
     RelaySelector(#[from] mullvad_relay_selector::Error),
+
+    #[error("The imported relay list is older than the current one")]
+    Outdated,
+}
+
+enum UpdaterCommand {
+    /// Download a new relay list
+    Update,
+    /// Replace the relay list with one that was obtained out-of-band and created at the given
+    /// time
+    Import(RelayList, SystemTime, oneshot::Sender<Result<(), Error>>),
 }
 
 #[derive(Clone)]
 pub struct RelayListUpdaterHandle {
-    tx: mpsc::Sender<()>,
+    tx: mpsc::Sender<UpdaterCommand>,
 }
 
 impl RelayListUpdaterHandle {
     pub async fn update(&mut self) {
         if let Err(error) = self
             .tx
-            .send(())
+            .send(UpdaterCommand::Update)
             .await
             .map_err(|_| Error::DownloaderShutdown)
         {
@@ -60,13 +71,30 @@ impl RelayListUpdaterHandle {
             );
         }
     }
+
+    /// Use `relay_list`, created at `created`, until the next time the relay list is downloaded.
+    /// The returned future completes once the relay list is in use. Lists that were created
+    /// before the current relay list was obtained are rejected, so that an old list cannot be
+    /// replayed.
+    pub async fn import(
+        &mut self,
+        relay_list: RelayList,
+        created: SystemTime,
+    ) -> Result<(), Error> {
+        let (done_tx, done_rx) = oneshot::channel();
+        self.tx
+            .send(UpdaterCommand::Import(relay_list, created, done_tx))
+            .await
+            .map_err(|_| Error::DownloaderShutdown)?;
+        done_rx.await.map_err(|_| Error::DownloaderShutdown)?
+    }
 }
 
 pub struct RelayListUpdater {
     api_client: RelayListProxy,
     cache_path: PathBuf,
     relay_selector: RelaySelector,
-    on_update: Box<dyn Fn(&RelayList) + Send + 'static>,
+    on_update: Box<dyn Fn(&RelayList, RelayListDiff) + Send + 'static>,
     last_check: SystemTime,
     api_availability: ApiAvailabilityHandle,
 }
@@ -76,7 +104,7 @@ impl RelayListUpdater {
         selector: RelaySelector,
         api_handle: MullvadRestHandle,
         cache_dir: &Path,
-        on_update: impl Fn(&RelayList) + Send + 'static,
+        on_update: impl Fn(&RelayList, RelayListDiff) + Send + 'static,
     ) -> RelayListUpdaterHandle {
         let (tx, cmd_rx) = mpsc::channel(1);
         let api_availability = api_handle.availability.clone();
@@ -95,7 +123,7 @@ impl RelayListUpdater {
         RelayListUpdaterHandle { tx }
     }
 
-    async fn run(mut self, mut cmd_rx: mpsc::Receiver<()>) {
+    async fn run(mut self, mut cmd_rx: mpsc::Receiver<UpdaterCommand>) {
         let mut download_future = Box::pin(Fuse::terminated());
         loop {
             let next_check = tokio::time::sleep(UPDATE_CHECK_INTERVAL).fuse();
@@ -116,11 +144,14 @@ impl RelayListUpdater {
 
                 cmd = cmd_rx.next() => {
                     match cmd {
-                        Some(()) => {
+                        Some(UpdaterCommand::Update) => {
                             let tag = self.relay_selector.etag();
                             download_future = Box::pin(Self::download_relay_list(self.api_availability.clone(), self.api_client.clone(), tag).fuse());
                             self.last_check = SystemTime::now();
                         },
+                        Some(UpdaterCommand::Import(relay_list, created, done_tx)) => {
+                            let _ = done_tx.send(self.import_relay_list(relay_list, created).await);
+                        },
                         None => {
                             log::trace!("Relay list updater shutting down");
                             return;
@@ -183,6 +214,21 @@ impl RelayListUpdater {
         )
     }
 
+    async fn import_relay_list(
+        &mut self,
+        relay_list: RelayList,
+        created: SystemTime,
+    ) -> Result<(), Error> {
+        if created <= self.relay_selector.last_updated() {
+            return Err(Error::Outdated);
+        }
+        log::info!("Using imported relay list");
+        if let Err(err) = self.update_cache(relay_list).await {
+            log::error!("Failed to update relay list cache: {}", err);
+        }
+        Ok(())
+    }
+
     async fn update_cache(&mut self, new_relay_list: RelayList) -> Result<(), Error> {
         if let Err(error) = Self::cache_relays(&self.cache_path, &new_relay_list).await {
             log::error!(
@@ -191,8 +237,9 @@ impl RelayListUpdater {
             );
         }
 
+        let diff = RelayListDiff::new(&self.relay_selector.get_relays(), &new_relay_list);
         self.relay_selector.set_relays(new_relay_list.clone());
-        (self.on_update)(&new_relay_list);
+        (self.on_update)(&new_relay_list, diff);
         Ok(())
     }
 
@@ -0,0 +1,116 @@
+//! Measures the latency to WireGuard relays before connecting, by timing a handshake with each of
+//! them. The results are used by the latency constraint of the relay selector.
+//!
+//! Relays are pinged concurrently, but only a limited number at a time and at a limited rate, so
+//! that the probes do not congest the network and skew each other's results.
+
+use futures::{stream, Stream, StreamExt};
+use mullvad_types::relay_list::{RelayEndpointData, RelayLatency, RelayList};
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+use talpid_net::SocketProtection;
+use talpid_types::net::wireguard::{PrivateKey, PublicKey};
+use talpid_wireguard::probe;
+use tokio::time::Instant;
+
+/// Maximum number of relays that are pinged at the same time.
+const MAX_CONCURRENT_PROBES: usize = 16;
+/// Shortest time between starting two probes.
+const PROBE_INTERVAL: Duration = Duration::from_millis(20);
+/// Time to wait for a relay to respond before it is considered unreachable.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Port to ping if the relay list does not specify any WireGuard ports.
+const DEFAULT_PORT: u16 = 51820;
+
+/// A relay to ping.
+#[derive(Debug, Clone)]
+pub(crate) struct Target {
+    hostname: String,
+    endpoint: SocketAddr,
+    public_key: PublicKey,
+}
+
+/// Return the active WireGuard relays in `relay_list`, optionally only those in `country`.
+pub(crate) fn targets(relay_list: &RelayList, country: Option<&str>) -> Vec<Target> {
+    let port = relay_list
+        .wireguard
+        .port_ranges
+        .first()
+        .map(|(start, _end)| *start)
+        .unwrap_or(DEFAULT_PORT);
+
+    relay_list
+        .countries
+        .iter()
+        .filter(|relay_country| country.map_or(true, |code| relay_country.code == code))
+        .flat_map(|relay_country| relay_country.cities.iter())
+        .flat_map(|city| city.relays.iter())
+        .filter(|relay| relay.active)
+        .filter_map(|relay| match &relay.endpoint_data {
+            RelayEndpointData::Wireguard(data) => Some(Target {
+                hostname: relay.hostname.clone(),
+                endpoint: SocketAddr::new(IpAddr::V4(relay.ipv4_addr_in), port),
+                public_key: data.public_key.clone(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Ping every target and yield the latency to each one as soon as it is known. A relay that does
+/// not respond within [`PROBE_TIMEOUT`] has no latency.
+pub(crate) fn ping(
+    targets: Vec<Target>,
+    private_key: PrivateKey,
+) -> impl Stream<Item = RelayLatency> {
+    let start = Instant::now();
+    let protection = socket_protection();
+    stream::iter(targets.into_iter().zip(0u32..))
+        .map(move |(target, index)| {
+            let private_key = private_key.clone();
+            let protection = protection.clone();
+            async move {
+                tokio::time::sleep_until(start + PROBE_INTERVAL * index).await;
+                let latency = match probe::handshake_latency(
+                    target.endpoint,
+                    &private_key,
+                    &target.public_key,
+                    PROBE_TIMEOUT,
+                    &protection,
+                )
+                .await
+                {
+                    Ok(latency) => Some(latency),
+                    Err(probe::Error::Timeout) => None,
+                    Err(error) => {
+                        log::debug!("Failed to ping {}: {error}", target.hostname);
+                        None
+                    }
+                };
+                RelayLatency {
+                    hostname: target.hostname,
+                    latency,
+                }
+            }
+        })
+        .buffer_unordered(MAX_CONCURRENT_PROBES)
+}
+
+/// Probes are sent outside of the tunnel, so that they measure the latency to the relays and not
+/// the latency through the current one.
+#[cfg(target_os = "linux")]
+fn socket_protection() -> SocketProtection {
+    SocketProtection::with_fwmark(Some(crate::runtime_config::get().tunnel_fwmark))
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+fn socket_protection() -> SocketProtection {
+    SocketProtection::bind_to_physical_interface()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn socket_protection() -> SocketProtection {
+    SocketProtection::default()
+}
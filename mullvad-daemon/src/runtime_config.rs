@@ -0,0 +1,268 @@
+//! Environment variables that override the behavior of the daemon.
+//!
+//! The variables that are read by the daemon itself are parsed once into [`Overrides`]. Every
+//! variable that is read anywhere in the service, including by the crates that the daemon uses, is
+//! listed in [`KNOWN_OVERRIDES`], so that the active ones can be logged and reported by the
+//! management interface. Hidden overrides should not be able to explain a bug report.
+
+use crate::device::key_provider::KeyProviderKind;
+use mullvad_types::{
+    runtime_config::{EnvOverride, RuntimeConfig},
+    shutdown::ShutdownBehavior,
+};
+use once_cell::sync::Lazy;
+use std::{str::FromStr, time::Duration};
+
+const SHUTDOWN_BEHAVIOR_VAR: &str = "MULLVAD_SHUTDOWN_BEHAVIOR";
+const SETTINGS_RECONNECT_QUIET_PERIOD_VAR: &str = "MULLVAD_SETTINGS_RECONNECT_QUIET_PERIOD_MS";
+const CONNCHECK_HOST_VAR: &str = "MULLVAD_CONNCHECK_HOST";
+const MANAGEMENT_TCP_PORT_VAR: &str = "MULLVAD_MANAGEMENT_TCP_PORT";
+const ALLOW_CUSTOM_API_ENDPOINT_VAR: &str = "MULLVAD_ALLOW_CUSTOM_API_ENDPOINT";
+const TUNNEL_FWMARK_VAR: &str = "MULLVAD_TUNNEL_FWMARK";
+const TUNNEL_TABLE_ID_VAR: &str = "MULLVAD_TUNNEL_TABLE_ID";
+const SETTINGS_FSYNC_VAR: &str = "MULLVAD_SETTINGS_FSYNC";
+const DEVICE_KEY_PROVIDER_VAR: &str = "MULLVAD_DEVICE_KEY_PROVIDER";
+
+/// Shortest time between two reconnects caused by settings changes.
+const DEFAULT_SETTINGS_RECONNECT_QUIET_PERIOD: Duration = Duration::from_secs(1);
+
+/// An environment variable that is read by the service.
+struct KnownOverride {
+    name: &'static str,
+    /// Only read by builds with the `api-override` feature.
+    dev_only: bool,
+}
+
+impl KnownOverride {
+    const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            dev_only: false,
+        }
+    }
+
+    const fn dev_only(name: &'static str) -> Self {
+        Self {
+            name,
+            dev_only: true,
+        }
+    }
+}
+
+/// Every environment variable that is read by the service. Keep this up to date when adding one.
+const KNOWN_OVERRIDES: &[KnownOverride] = &[
+    KnownOverride::new("MULLVAD_RESOURCE_DIR"),
+    KnownOverride::new("MULLVAD_SETTINGS_DIR"),
+    KnownOverride::new("MULLVAD_CACHE_DIR"),
+    KnownOverride::new("MULLVAD_LOG_DIR"),
+    KnownOverride::new("MULLVAD_RPC_SOCKET_PATH"),
+    KnownOverride::new("MULLVAD_DAEMON_CONFIG"),
+    KnownOverride::new(mullvad_paths::instance::INSTANCE_VAR),
+    KnownOverride::new("MULLVAD_MANAGEMENT_SOCKET_GROUP"),
+    KnownOverride::new(MANAGEMENT_TCP_PORT_VAR),
+    KnownOverride::new(SHUTDOWN_BEHAVIOR_VAR),
+    KnownOverride::new(SETTINGS_RECONNECT_QUIET_PERIOD_VAR),
+    KnownOverride::new(ALLOW_CUSTOM_API_ENDPOINT_VAR),
+    KnownOverride::new(TUNNEL_FWMARK_VAR),
+    KnownOverride::new(TUNNEL_TABLE_ID_VAR),
+    KnownOverride::new(SETTINGS_FSYNC_VAR),
+    KnownOverride::new(DEVICE_KEY_PROVIDER_VAR),
+    KnownOverride::dev_only(mullvad_api::env::API_HOST_VAR),
+    KnownOverride::dev_only(mullvad_api::env::API_ADDR_VAR),
+    KnownOverride::dev_only(mullvad_api::env::API_FORCE_DIRECT_VAR),
+    KnownOverride::dev_only(mullvad_api::env::DISABLE_TLS_VAR),
+    KnownOverride::new(mullvad_api::env::CLOCK_SKEW_TOLERANCE_VAR),
+    KnownOverride::dev_only(CONNCHECK_HOST_VAR),
+    KnownOverride::dev_only("MULLVAD_TEST_RELAYS"),
+    KnownOverride::new("TALPID_FIREWALL_DEBUG"),
+    KnownOverride::new("TALPID_FIREWALL_DONT_SET_SRC_VALID_MARK"),
+    KnownOverride::new("TALPID_FIREWALL_CLAMP_MSS"),
+    KnownOverride::new("TALPID_FIREWALL_TABLE_NAME"),
+    KnownOverride::new("TALPID_DNS_MODULE"),
+    KnownOverride::new("TALPID_FORCE_USERSPACE_WIREGUARD"),
+    KnownOverride::new("TALPID_DISABLE_OFFLINE_MONITOR"),
+    KnownOverride::new("TALPID_DISABLE_OPENVPN_DCO"),
+    KnownOverride::new("TALPID_OPENVPN_TLS_VERSION_MIN"),
+    KnownOverride::new("TALPID_OPENVPN_TLS_CIPHERSUITES"),
+    KnownOverride::new("TALPID_NET_CLS_MOUNT_DIR"),
+    KnownOverride::new("TALPID_TUNNEL_INTERFACE_NAME"),
+    KnownOverride::new("TALPID_TUNNEL_NETNS"),
+    KnownOverride::new("TALPID_WIREGUARD_GO_QUEUES"),
+    KnownOverride::new("TALPID_WIREGUARD_GO_THREADS"),
+    KnownOverride::new("TALPID_WIREGUARD_GO_CPUS"),
+    KnownOverride::new("TALPID_UDP2TCP_NODELAY"),
+    KnownOverride::new("TALPID_UDP2TCP_CONGESTION"),
+    KnownOverride::new("TALPID_UDP2TCP_RECV_BUFFER"),
+    KnownOverride::new("TALPID_UDP2TCP_SEND_BUFFER"),
+];
+
+/// An environment variable with a value that could not be parsed.
+#[derive(thiserror::Error, Debug, Clone)]
+#[error("Invalid value for {name}: {value}")]
+pub struct InvalidValue {
+    name: &'static str,
+    value: String,
+}
+
+/// Overrides that are read by the daemon itself.
+#[derive(Debug)]
+pub(crate) struct Overrides {
+    /// What to leave behind when the daemon is stopped by a signal, such as when systemd stops
+    /// the service. Shutting down using the management interface lets the caller choose instead.
+    pub shutdown_behavior: ShutdownBehavior,
+    /// Shortest time between two reconnects caused by settings changes. Zero disables
+    /// coalescing, so that every settings change reconnects immediately. Mostly useful for tests.
+    pub settings_reconnect_quiet_period: Duration,
+    /// Host to use for connection checks instead of the one in the settings. Always `None` in
+    /// production builds.
+    pub conncheck_host: Option<String>,
+    /// Port on localhost to serve the management interface on, in addition to the socket.
+    pub management_tcp_port: Result<Option<u16>, InvalidValue>,
+    /// Whether a custom API endpoint may be set. Always allowed in builds with the
+    /// `api-override` feature.
+    pub allow_custom_api_endpoint: bool,
+    /// Whether to wait for the settings to reach the storage device when they are written.
+    pub settings_fsync: bool,
+    /// How the private key of the device is generated and stored.
+    pub device_key_provider: KeyProviderKind,
+    /// Firewall mark of traffic that must not be routed into the tunnel.
+    #[cfg(target_os = "linux")]
+    pub tunnel_fwmark: u32,
+    /// Routing table that traffic without the firewall mark is routed by.
+    #[cfg(target_os = "linux")]
+    pub tunnel_table_id: u32,
+}
+
+static OVERRIDES: Lazy<Overrides> = Lazy::new(Overrides::from_env);
+
+/// Return the overrides that are read by the daemon.
+pub(crate) fn get() -> &'static Overrides {
+    &OVERRIDES
+}
+
+impl Overrides {
+    fn from_env() -> Self {
+        Self {
+            shutdown_behavior: parse_or_default(SHUTDOWN_BEHAVIOR_VAR, ShutdownBehavior::default()),
+            settings_reconnect_quiet_period: Duration::from_millis(parse_or_default(
+                SETTINGS_RECONNECT_QUIET_PERIOD_VAR,
+                DEFAULT_SETTINGS_RECONNECT_QUIET_PERIOD.as_millis() as u64,
+            )),
+            conncheck_host: Self::conncheck_host(),
+            management_tcp_port: parse(MANAGEMENT_TCP_PORT_VAR).transpose(),
+            allow_custom_api_endpoint: read_var(ALLOW_CUSTOM_API_ENDPOINT_VAR)
+                .is_some_and(|value| value != "0"),
+            settings_fsync: !read_var(SETTINGS_FSYNC_VAR).is_some_and(|value| value == "0"),
+            device_key_provider: parse_or_default(
+                DEVICE_KEY_PROVIDER_VAR,
+                KeyProviderKind::default(),
+            ),
+            #[cfg(target_os = "linux")]
+            tunnel_fwmark: parse_or_default::<LinuxId>(
+                TUNNEL_FWMARK_VAR,
+                LinuxId(mullvad_types::TUNNEL_FWMARK),
+            )
+            .0,
+            #[cfg(target_os = "linux")]
+            tunnel_table_id: parse_or_default::<LinuxId>(
+                TUNNEL_TABLE_ID_VAR,
+                LinuxId(mullvad_types::TUNNEL_TABLE_ID),
+            )
+            .0,
+        }
+    }
+
+    fn conncheck_host() -> Option<String> {
+        let host = read_var(CONNCHECK_HOST_VAR);
+        if cfg!(feature = "api-override") {
+            if let Some(host) = &host {
+                log::debug!("Overriding conncheck endpoint. Using {host}");
+            }
+            host
+        } else {
+            if host.is_some() {
+                log::warn!(
+                    "These variables are ignored in production builds: {CONNCHECK_HOST_VAR}"
+                );
+            }
+            None
+        }
+    }
+}
+
+/// A firewall mark or routing table ID, in decimal or in hexadecimal with a `0x` prefix. Zero and
+/// the tables that are reserved by the kernel are not allowed.
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LinuxId(u32);
+
+#[cfg(target_os = "linux")]
+impl FromStr for LinuxId {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let id = match value.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => value.parse(),
+        }
+        .map_err(|_| ())?;
+        // 253, 254 and 255 are the default, main and local tables.
+        if id == 0 || (253..=255).contains(&id) {
+            return Err(());
+        }
+        Ok(Self(id))
+    }
+}
+
+/// Return every known environment variable that is set.
+pub(crate) fn active_overrides() -> RuntimeConfig {
+    let mut overrides = KNOWN_OVERRIDES
+        .iter()
+        .filter_map(|known| {
+            Some(EnvOverride {
+                name: known.name.to_owned(),
+                value: std::env::var_os(known.name)?.to_string_lossy().into_owned(),
+                ignored: known.dev_only && !cfg!(feature = "api-override"),
+            })
+        })
+        .collect::<Vec<_>>();
+    overrides.sort_by(|a, b| a.name.cmp(&b.name));
+    RuntimeConfig { overrides }
+}
+
+fn read_var(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
+fn parse<T: FromStr>(name: &'static str) -> Option<Result<T, InvalidValue>> {
+    let value = read_var(name)?;
+    Some(value.parse().map_err(|_| InvalidValue { name, value }))
+}
+
+/// Parse the variable `name`, or return `default` if it is not set or invalid.
+fn parse_or_default<T: FromStr>(name: &'static str, default: T) -> T {
+    match parse(name) {
+        Some(Ok(value)) => value,
+        Some(Err(error)) => {
+            log::warn!("Ignoring environment variable. {error}");
+            default
+        }
+        None => default,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_parse_linux_id() {
+        assert_eq!("1836018789".parse(), Ok(LinuxId(0x6d6f6c65)));
+        assert_eq!("0x6d6f6c65".parse(), Ok(LinuxId(0x6d6f6c65)));
+        assert_eq!("0".parse::<LinuxId>(), Err(()));
+        assert_eq!("254".parse::<LinuxId>(), Err(()));
+        assert_eq!("0x".parse::<LinuxId>(), Err(()));
+        assert_eq!("-1".parse::<LinuxId>(), Err(()));
+    }
+}
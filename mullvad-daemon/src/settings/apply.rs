@@ -0,0 +1,233 @@
+//! This module turns a settings document into a complete [Settings] instance that can replace the
+//! current settings in one step. Unlike a [patch](super::patch), a document may contain any
+//! setting, and is meant for provisioning many machines with the same configuration.
+//!
+//! Each top-level key in the document replaces the corresponding setting in its entirety. Keys
+//! that are missing from a nested object take their default values, and top-level keys that are
+//! missing from the document keep their current values. The document is rejected as a whole if
+//! any key is unknown or any value is invalid, in which case nothing is changed.
+
+use mullvad_types::settings::{Settings, CURRENT_SETTINGS_VERSION};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    /// Failed to parse document
+    #[error("Failed to parse settings document")]
+    ParseDocument(#[source] serde_json::Error),
+    /// The document is not a JSON object
+    #[error("The settings document must be an object")]
+    NotAnObject,
+    /// Unknown key
+    #[error("Unknown key: {0}")]
+    UnknownKey(String),
+    /// Key that cannot be set using a document
+    #[error("Setting cannot be applied from a document: {0}")]
+    ProhibitedKey(&'static str),
+    /// The document was written for another version of the settings
+    #[error("Unsupported settings version: {0}")]
+    UnsupportedVersion(serde_json::Value),
+    /// Failed to deserialize the resulting settings
+    #[error("Invalid settings document")]
+    DeserializeSettings(#[source] serde_json::Error),
+    /// Failed to serialize settings
+    #[error("Failed to serialize current settings")]
+    SerializeSettings(#[source] serde_json::Error),
+    /// Recursion limit reached
+    #[error("Maximum JSON object depth reached")]
+    RecursionLimit,
+}
+
+/// Settings that are kept in sync with state outside of the settings, or that require a passphrase,
+/// and must be edited using their own commands. A document may only contain them if they are left
+/// unchanged, so that exported settings can be applied.
+const PROHIBITED_KEYS: &[&str] = &["split_tunnel", "custom_api_endpoint", "settings_lock"];
+
+/// Prohibit stack overflow via excessive recursion.
+const RECURSE_LIMIT: usize = 15;
+
+/// Return the settings that result from applying `document` to `current`.
+pub fn settings_from_document(current: &Settings, document: &str) -> Result<Settings, Error> {
+    let document: serde_json::Value =
+        serde_json::from_str(document).map_err(Error::ParseDocument)?;
+    let document = document.as_object().ok_or(Error::NotAnObject)?;
+
+    let mut settings_value = serde_json::to_value(current).map_err(Error::SerializeSettings)?;
+
+    if let Some(key) = PROHIBITED_KEYS.iter().find(|key| {
+        document
+            .get(**key)
+            .is_some_and(|value| Some(value) != settings_value.get(**key))
+    }) {
+        return Err(Error::ProhibitedKey(key));
+    }
+    if let Some(version) = document.get("settings_version") {
+        let current_version =
+            serde_json::to_value(CURRENT_SETTINGS_VERSION).map_err(Error::SerializeSettings)?;
+        if *version != current_version {
+            return Err(Error::UnsupportedVersion(version.to_owned()));
+        }
+    }
+
+    let settings_map = settings_value
+        .as_object_mut()
+        .expect("settings must serialize to an object");
+    for (key, value) in document {
+        settings_map.insert(key.to_owned(), value.to_owned());
+    }
+
+    let new_settings: Settings =
+        serde_json::from_value(settings_value).map_err(Error::DeserializeSettings)?;
+
+    // Serde ignores unknown fields, so compare the document with what was actually read
+    let new_value = serde_json::to_value(&new_settings).map_err(Error::SerializeSettings)?;
+    for (key, value) in document {
+        find_unknown_key(value, new_value.get(key), key.to_owned(), 0)?;
+    }
+
+    Ok(new_settings)
+}
+
+/// Return an error if `document` contains an object key that is not present in `parsed`.
+/// Null and empty values are ignored, since these may be omitted when serializing.
+fn find_unknown_key(
+    document: &serde_json::Value,
+    parsed: Option<&serde_json::Value>,
+    path: String,
+    recurse_level: usize,
+) -> Result<(), Error> {
+    if recurse_level >= RECURSE_LIMIT {
+        return Err(Error::RecursionLimit);
+    }
+    let is_empty = match document {
+        serde_json::Value::Null => true,
+        serde_json::Value::Array(array) => array.is_empty(),
+        serde_json::Value::Object(object) => object.is_empty(),
+        _ => false,
+    };
+    if is_empty {
+        return Ok(());
+    }
+    let Some(parsed) = parsed else {
+        return Err(Error::UnknownKey(path));
+    };
+
+    match (document, parsed) {
+        (serde_json::Value::Object(document), serde_json::Value::Object(parsed)) => {
+            for (key, value) in document {
+                find_unknown_key(
+                    value,
+                    parsed.get(key),
+                    format!("{path}.{key}"),
+                    recurse_level + 1,
+                )?;
+            }
+        }
+        (serde_json::Value::Array(document), serde_json::Value::Array(parsed)) => {
+            for (index, value) in document.iter().enumerate() {
+                find_unknown_key(
+                    value,
+                    parsed.get(index),
+                    format!("{path}[{index}]"),
+                    recurse_level + 1,
+                )?;
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_missing_keys_are_kept() {
+        let current = Settings {
+            allow_lan: true,
+            ..Settings::default()
+        };
+        let new = settings_from_document(&current, r#"{ "auto_connect": true }"#).unwrap();
+
+        assert!(new.allow_lan);
+        assert!(new.auto_connect);
+    }
+
+    #[test]
+    fn test_nested_keys_are_replaced() {
+        let mut current = Settings::default();
+        current.tunnel_options.generic.enable_ipv6 = true;
+
+        let document = r#"{ "tunnel_options": { "dns_options": { "state": "custom" } } }"#;
+        let new = settings_from_document(&current, document).unwrap();
+
+        assert_eq!(new.tunnel_options, {
+            let mut options = Settings::default().tunnel_options;
+            options.dns_options.state = mullvad_types::settings::DnsState::Custom;
+            options
+        });
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        let current = Settings::default();
+
+        assert!(matches!(
+            settings_from_document(&current, r#"{ "allow_lan": true, "not_a_setting": 1 }"#),
+            Err(Error::UnknownKey(key)) if key == "not_a_setting"
+        ));
+        assert!(matches!(
+            settings_from_document(&current, r#"{ "tunnel_options": { "mtu": 1280 } }"#),
+            Err(Error::UnknownKey(key)) if key == "tunnel_options.mtu"
+        ));
+    }
+
+    #[test]
+    fn test_invalid_documents() {
+        let current = Settings::default();
+
+        assert!(matches!(
+            settings_from_document(&current, "[]"),
+            Err(Error::NotAnObject)
+        ));
+        assert!(matches!(
+            settings_from_document(&current, r#"{ "allow_lan": "yes" }"#),
+            Err(Error::DeserializeSettings(_))
+        ));
+        assert!(matches!(
+            settings_from_document(&current, r#"{ "settings_version": 1 }"#),
+            Err(Error::UnsupportedVersion(_))
+        ));
+        assert!(matches!(
+            settings_from_document(&current, r#"{ "split_tunnel": {} }"#),
+            Err(Error::ProhibitedKey("split_tunnel"))
+        ));
+        assert!(matches!(
+            settings_from_document(
+                &current,
+                r#"{ "custom_api_endpoint": { "host": "api.example.com", "address": "10.0.0.1:443" } }"#
+            ),
+            Err(Error::ProhibitedKey("custom_api_endpoint"))
+        ));
+        assert!(settings_from_document(&current, r#"{ "settings_lock": null }"#).is_ok());
+        assert!(matches!(
+            settings_from_document(
+                &current,
+                r#"{ "settings_lock": { "passphrase_hash": "$argon2id$" } }"#
+            ),
+            Err(Error::ProhibitedKey("settings_lock"))
+        ));
+    }
+
+    /// Exported settings must be accepted as they are
+    #[test]
+    fn test_current_settings_roundtrip() {
+        let current = Settings::default();
+        let document = serde_json::to_string(&current).unwrap();
+        let mut document: serde_json::Value = serde_json::from_str(&document).unwrap();
+        document.as_object_mut().unwrap().remove("split_tunnel");
+
+        let new = settings_from_document(&current, &document.to_string()).unwrap();
+        assert_eq!(new, current);
+    }
+}
@@ -1,6 +1,7 @@
 #[cfg(not(target_os = "android"))]
 use futures::TryFutureExt;
 use mullvad_types::custom_list::Error as CustomListError;
+use mullvad_types::profile::Error as ProfileError;
 use mullvad_types::{
     relay_constraints::{RelayConstraints, RelaySettings, WireguardConstraints},
     settings::{DnsState, Settings},
@@ -17,9 +18,16 @@ use tokio::{
     io::{self, AsyncWriteExt},
 };
 
+pub mod apply;
 pub mod patch;
 
 const SETTINGS_FILE: &str = "settings.json";
+/// Copy of the settings that were last written successfully.
+const SNAPSHOT_FILE: &str = "settings.json.bak";
+/// Where a settings file that is replaced by the snapshot is kept, for troubleshooting.
+const CORRUPT_FILE: &str = "settings.json.corrupt";
+/// Advisory lock that is held while the settings files are written.
+pub(crate) const LOCK_FILE: &str = "settings.lock";
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -38,6 +46,9 @@ pub enum Error {
     #[error("Unable to write settings to {0}")]
     WriteError(String, #[source] io::Error),
 
+    #[error("Unable to lock {0}")]
+    LockError(String, #[source] io::Error),
+
     #[error("Failed to apply settings update")]
     UpdateFailed(Box<dyn std::error::Error + Send + Sync>),
 }
@@ -45,11 +56,13 @@ pub enum Error {
 /// Converts an [Error] to a management interface status
 impl From<Error> for mullvad_management_interface::Status {
     fn from(error: Error) -> mullvad_management_interface::Status {
-        use mullvad_management_interface::{Code, Status};
+        use mullvad_management_interface::{error_code::StatusExt, types::ErrorCode, Code, Status};
         match error {
-            Error::DeleteError(..) | Error::WriteError(..) | Error::ReadError(..) => {
-                Status::new(Code::FailedPrecondition, error.to_string())
-            }
+            Error::DeleteError(..)
+            | Error::WriteError(..)
+            | Error::ReadError(..)
+            | Error::LockError(..) => Status::new(Code::FailedPrecondition, error.to_string())
+                .with_error_code(ErrorCode::SettingsIoFailed),
             Error::UpdateFailed(err)
                 if err
                     .downcast_ref::<mullvad_types::custom_list::Error>()
@@ -58,6 +71,14 @@ impl From<Error> for mullvad_management_interface::Status {
                 let custom_list_err = *err.downcast::<CustomListError>().unwrap();
                 handle_custom_list_error(custom_list_err)
             }
+            Error::UpdateFailed(err)
+                if err
+                    .downcast_ref::<mullvad_types::profile::Error>()
+                    .is_some() =>
+            {
+                let profile_err = *err.downcast::<ProfileError>().unwrap();
+                handle_profile_error(profile_err)
+            }
             Error::SerializeError(..) | Error::ParseError(..) | Error::UpdateFailed(..) => {
                 Status::new(Code::Internal, error.to_string())
             }
@@ -68,7 +89,7 @@ impl From<Error> for mullvad_management_interface::Status {
 fn handle_custom_list_error(
     custom_list_err: CustomListError,
 ) -> mullvad_management_interface::Status {
-    use mullvad_management_interface::{Code, Status};
+    use mullvad_management_interface::{error_code::StatusExt, types::ErrorCode, Code, Status};
     match custom_list_err {
         error @ CustomListError::ListExists | error @ CustomListError::DuplicateName => {
             Status::with_details(
@@ -76,17 +97,33 @@ fn handle_custom_list_error(
                 error.to_string(),
                 mullvad_management_interface::CUSTOM_LIST_LIST_EXISTS_DETAILS.into(),
             )
+            .with_error_code(ErrorCode::CustomListExists)
         }
         error @ CustomListError::NameTooLong => Status::with_details(
             Code::InvalidArgument,
             error.to_string(),
             mullvad_management_interface::CUSTOM_LIST_LIST_NAME_TOO_LONG_DETAILS.into(),
-        ),
+        )
+        .with_error_code(ErrorCode::CustomListNameTooLong),
         error @ CustomListError::ListNotFound => Status::with_details(
             Code::NotFound,
             error.to_string(),
             mullvad_management_interface::CUSTOM_LIST_LIST_NOT_FOUND_DETAILS.into(),
-        ),
+        )
+        .with_error_code(ErrorCode::CustomListNotFound),
+    }
+}
+
+fn handle_profile_error(profile_err: ProfileError) -> mullvad_management_interface::Status {
+    use mullvad_management_interface::{error_code::StatusExt, types::ErrorCode, Status};
+    match profile_err {
+        error @ ProfileError::NameTooLong | error @ ProfileError::EmptyName => {
+            Status::invalid_argument(error.to_string())
+                .with_error_code(ErrorCode::ProfileNameInvalid)
+        }
+        error @ ProfileError::ProfileNotFound => {
+            Status::not_found(error.to_string()).with_error_code(ErrorCode::ProfileNotFound)
+        }
     }
 }
 
@@ -124,7 +161,11 @@ impl SettingsPersister {
             on_change_listeners: vec![],
         };
 
-        if should_save {
+        // Settings written by older versions have no snapshot until they are changed
+        let missing_snapshot = fs::metadata(settings_dir.join(SNAPSHOT_FILE))
+            .await
+            .is_err();
+        if should_save || missing_snapshot {
             if let Err(error) = persister.save().await {
                 log::error!(
                     "{}",
@@ -136,6 +177,48 @@ impl SettingsPersister {
         persister
     }
 
+    /// Replaces the settings file with the snapshot of the settings that were last written
+    /// successfully, if the settings file is corrupt, for example because it was truncated by a
+    /// power loss. This must be done before the settings are migrated, since the snapshot may be
+    /// older than the settings file.
+    pub async fn restore_if_corrupt(settings_dir: &Path) {
+        let path = settings_dir.join(SETTINGS_FILE);
+        // A missing or unreadable file is handled when the settings are loaded
+        let Ok(settings_bytes) = fs::read(&path).await else {
+            return;
+        };
+        if !is_corrupt(&settings_bytes) {
+            return;
+        }
+        let snapshot = match fs::read(settings_dir.join(SNAPSHOT_FILE)).await {
+            Ok(snapshot) if !is_corrupt(&snapshot) => snapshot,
+            _ => {
+                log::warn!(
+                    "Settings file {} is corrupt, and there is no snapshot to restore",
+                    path.display()
+                );
+                return;
+            }
+        };
+        log::warn!(
+            "Settings file {} is corrupt. Restoring the last snapshot",
+            path.display()
+        );
+
+        let result = async {
+            let _lock = lock(settings_dir).await?;
+            write_file(&settings_dir.join(CORRUPT_FILE), &settings_bytes).await?;
+            write_file(&path, &snapshot).await
+        }
+        .await;
+        if let Err(error) = result {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to restore settings snapshot")
+            );
+        }
+    }
+
     /// Loads user settings, returning default settings if it should fail.
     ///
     /// `load_settings` allows the caller to decide how to load [`Settings`]
@@ -201,20 +284,22 @@ impl SettingsPersister {
         Self::save_inner(&self.path, &self.settings).await
     }
 
-    /// Serializes the settings and saves them to the given file.
+    /// Serializes the settings and saves them to the given file, and to the snapshot next to it.
     async fn save_inner(path: &Path, settings: &Settings) -> Result<(), Error> {
         log::debug!("Writing settings to {}", path.display());
 
         let buffer = serde_json::to_string_pretty(settings).map_err(Error::SerializeError)?;
-        let mut file = mullvad_fs::AtomicFile::new(path)
-            .await
-            .map_err(|e| Error::WriteError(path.display().to_string(), e))?;
-        file.write_all(&buffer.into_bytes())
-            .await
-            .map_err(|e| Error::WriteError(path.display().to_string(), e))?;
-        file.finalize()
-            .await
-            .map_err(|e| Error::WriteError(path.display().to_string(), e))?;
+        let _lock = lock(path.parent().unwrap_or(Path::new(""))).await?;
+        write_file(path, buffer.as_bytes()).await?;
+        // The snapshot is only replaced once the settings file is complete, so that one of them is
+        // always intact
+        if let Err(error) = write_file(&path.with_file_name(SNAPSHOT_FILE), buffer.as_bytes()).await
+        {
+            log::warn!(
+                "{}",
+                error.display_chain_with_msg("Failed to update settings snapshot")
+            );
+        }
 
         Ok(())
     }
@@ -364,6 +449,41 @@ impl SettingsPersister {
     }
 }
 
+/// Returns how the settings files should be written, as configured by `MULLVAD_SETTINGS_FSYNC`.
+pub(crate) fn fsync_policy() -> mullvad_fs::FsyncPolicy {
+    if crate::runtime_config::get().settings_fsync {
+        mullvad_fs::FsyncPolicy::Always
+    } else {
+        mullvad_fs::FsyncPolicy::Never
+    }
+}
+
+/// Takes the advisory lock on the settings files in `settings_dir`.
+async fn lock(settings_dir: &Path) -> Result<mullvad_fs::FileLock, Error> {
+    let lock_path = settings_dir.join(LOCK_FILE);
+    mullvad_fs::FileLock::lock(&lock_path)
+        .await
+        .map_err(|e| Error::LockError(lock_path.display().to_string(), e))
+}
+
+/// Atomically replaces the content of `path`. The caller must hold the lock.
+async fn write_file(path: &Path, content: &[u8]) -> Result<(), Error> {
+    let write_error = |e: io::Error| Error::WriteError(path.display().to_string(), e);
+    let mut file = mullvad_fs::AtomicFile::with_fsync_policy(path, fsync_policy())
+        .await
+        .map_err(write_error)?;
+    file.write_all(content).await.map_err(write_error)?;
+    file.finalize().await.map_err(write_error)
+}
+
+/// Returns whether `bytes` cannot be a settings file of any version.
+fn is_corrupt(bytes: &[u8]) -> bool {
+    !matches!(
+        serde_json::from_slice::<serde_json::Value>(bytes),
+        Ok(serde_json::Value::Object(_))
+    )
+}
+
 struct LoadSettingsResult {
     settings: Settings,
     should_save: bool,
@@ -655,4 +775,14 @@ mod test {
             "The daemon should block the internet if settings are corrupt"
         );
     }
+
+    #[test]
+    fn test_detect_corrupt_settings() {
+        assert!(!is_corrupt(br#"{ "settings_version": 9 }"#));
+        // Truncated by a power loss
+        assert!(is_corrupt(br#"{ "settings_version": 9, "allow_lan": tr"#));
+        assert!(is_corrupt(b""));
+        assert!(is_corrupt(b"\0\0\0\0"));
+        assert!(is_corrupt(b"[]"));
+    }
 }
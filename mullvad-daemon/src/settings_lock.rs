@@ -0,0 +1,72 @@
+//! An optional passphrase that is required to disconnect, to disable lockdown mode and to change
+//! the settings, for parental control and kiosk setups. Giving the passphrase unlocks the settings
+//! for [`UNLOCK_DURATION`], or until they are locked again.
+//!
+//! Hashing is slow by design, so these functions should not be called on the daemon's event loop.
+
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use mullvad_types::settings::SettingsLock;
+use std::time::Duration;
+
+/// How long the settings stay unlocked after the passphrase has been given.
+pub const UNLOCK_DURATION: Duration = Duration::from_secs(5 * 60);
+
+/// How long to wait before answering after a wrong passphrase, to slow down guessing.
+pub const WRONG_PASSPHRASE_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("The passphrase must not be empty")]
+    EmptyPassphrase,
+
+    #[error("Failed to hash passphrase: {0}")]
+    Hash(argon2::password_hash::Error),
+}
+
+/// Hash `passphrase` with a new random salt.
+pub fn new_lock(passphrase: &str) -> Result<SettingsLock, Error> {
+    if passphrase.is_empty() {
+        return Err(Error::EmptyPassphrase);
+    }
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(passphrase.as_bytes(), &salt)
+        .map_err(Error::Hash)?;
+    Ok(SettingsLock {
+        passphrase_hash: hash.to_string(),
+    })
+}
+
+/// Returns whether `passphrase` is the passphrase of `lock`.
+pub fn verify(lock: &SettingsLock, passphrase: &str) -> bool {
+    match PasswordHash::new(&lock.passphrase_hash) {
+        Ok(hash) => Argon2::default()
+            .verify_password(passphrase.as_bytes(), &hash)
+            .is_ok(),
+        Err(error) => {
+            log::error!("Invalid settings lock passphrase hash: {error}");
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let lock = new_lock("correct horse").unwrap();
+        assert!(lock.passphrase_hash.starts_with("$argon2id$"));
+        assert!(verify(&lock, "correct horse"));
+        assert!(!verify(&lock, "battery staple"));
+        assert!(!verify(&lock, ""));
+
+        assert!(matches!(new_lock(""), Err(Error::EmptyPassphrase)));
+        // The salt is random
+        assert_ne!(new_lock("correct horse").unwrap(), lock);
+    }
+}
@@ -30,8 +30,14 @@ mod platform {
 /// Returns true if systemd successfully reported that the machine is not shutting down or entering
 /// maintenance. If obtaining this information fails, the return value will be `false` and it will
 /// be assumed that the machine is shutting down.
+///
+/// Outside of the host network namespace, such as in a container, the state of the host does not
+/// say whether the daemon is stopped on purpose, so the shutdown is assumed to be user initiated.
 #[cfg(target_os = "linux")]
 pub fn is_shutdown_user_initiated() -> bool {
+    if !talpid_tunnel::netns::is_host_namespace() {
+        return true;
+    }
     match talpid_dbus::systemd::is_host_running() {
         Ok(is_host_running) => is_host_running,
         Err(err) => {
@@ -0,0 +1,72 @@
+//! Persists how restoring the network went when the daemon shut down, so that it can be inspected
+//! after the daemon has been started again.
+
+use mullvad_types::states::ShutdownReport;
+use std::path::Path;
+use talpid_types::{tunnel::TeardownReport, ErrorExt};
+use tokio::{fs, io};
+
+const LAST_SHUTDOWN_REPORT_FILE: &str = "last-shutdown-report.json";
+
+/// Read the report that the previous instance of the daemon left behind, if any.
+pub async fn load(cache_dir: &Path) -> Option<ShutdownReport> {
+    let content = match fs::read_to_string(cache_dir.join(LAST_SHUTDOWN_REPORT_FILE)).await {
+        Ok(content) => content,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return None,
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to read last shutdown report")
+            );
+            return None;
+        }
+    };
+    match serde_json::from_str::<ShutdownReport>(&content) {
+        Ok(report) => {
+            if !report.teardown.succeeded() {
+                log::warn!(
+                    "Restoring the network failed when the daemon last shut down:\n{}",
+                    report.teardown
+                );
+            }
+            Some(report)
+        }
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to parse last shutdown report")
+            );
+            None
+        }
+    }
+}
+
+/// Log `teardown` and save it so that it can be inspected after a restart.
+pub async fn save(cache_dir: &Path, teardown: TeardownReport) {
+    if teardown.succeeded() {
+        log::debug!("Restored the network:\n{teardown}");
+    } else {
+        log::error!("Failed to restore the network:\n{teardown}");
+    }
+
+    let report = ShutdownReport {
+        time: chrono::Utc::now(),
+        teardown,
+    };
+    let data = match serde_json::to_string(&report) {
+        Ok(data) => data,
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to serialize shutdown report")
+            );
+            return;
+        }
+    };
+    if let Err(error) = fs::write(cache_dir.join(LAST_SHUTDOWN_REPORT_FILE), data).await {
+        log::error!(
+            "{}",
+            error.display_chain_with_msg("Failed to save shutdown report")
+        );
+    }
+}
@@ -0,0 +1,64 @@
+//! Reads the GNOME proxy settings using `gsettings`. Since the daemon runs as root, this returns
+//! the system-wide defaults that have been set by an administrator in dconf.
+
+use super::{ProxyKind, SystemProxy};
+use std::process::Command;
+
+const SCHEMA: &str = "org.gnome.system.proxy";
+
+pub fn read_system_proxy() -> Option<SystemProxy> {
+    if parse_string(&gsettings(SCHEMA, "mode")?) != "manual" {
+        return None;
+    }
+    read_proxy("https", ProxyKind::Http).or_else(|| read_proxy("socks", ProxyKind::Socks))
+}
+
+fn read_proxy(protocol: &str, kind: ProxyKind) -> Option<SystemProxy> {
+    let schema = format!("{SCHEMA}.{protocol}");
+    let host = parse_string(&gsettings(&schema, "host")?).to_owned();
+    let port = parse_port(&gsettings(&schema, "port")?)?;
+    if host.is_empty() {
+        return None;
+    }
+    Some(SystemProxy { kind, host, port })
+}
+
+fn gsettings(schema: &str, key: &str) -> Option<String> {
+    let output = Command::new("gsettings")
+        .args(["get", schema, key])
+        .output()
+        .map_err(|error| log::trace!("Failed to run gsettings: {error}"))
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+/// Strip the quotes from a string printed by `gsettings`.
+fn parse_string(value: &str) -> &str {
+    let value = value.trim();
+    value
+        .strip_prefix('\'')
+        .and_then(|value| value.strip_suffix('\''))
+        .unwrap_or(value)
+}
+
+/// Parse a port printed by `gsettings`. The value may be prefixed by its type, e.g. `int32 0`.
+fn parse_port(value: &str) -> Option<u16> {
+    let port = value.split_whitespace().last()?.parse().ok()?;
+    (port != 0).then_some(port)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_gsettings_output() {
+        assert_eq!(parse_string("'manual'\n"), "manual");
+        assert_eq!(parse_string("''\n"), "");
+        assert_eq!(parse_port("3128\n"), Some(3128));
+        assert_eq!(parse_port("int32 0\n"), None);
+    }
+}
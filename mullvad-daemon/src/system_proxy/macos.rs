@@ -0,0 +1,37 @@
+//! Reads the proxy settings of the primary network service from the dynamic store.
+
+use super::{ProxyKind, SystemProxy};
+use system_configuration::{
+    core_foundation::{base::CFType, dictionary::CFDictionary, number::CFNumber, string::CFString},
+    dynamic_store::SCDynamicStoreBuilder,
+};
+
+pub fn read_system_proxy() -> Option<SystemProxy> {
+    let store = SCDynamicStoreBuilder::new("mullvad-system-proxy").build();
+    let proxies = store.get_proxies()?;
+    read_proxy(&proxies, "HTTPS", ProxyKind::Http)
+        .or_else(|| read_proxy(&proxies, "SOCKS", ProxyKind::Socks))
+}
+
+fn read_proxy(
+    proxies: &CFDictionary<CFString, CFType>,
+    prefix: &str,
+    kind: ProxyKind,
+) -> Option<SystemProxy> {
+    if get_number(proxies, &format!("{prefix}Enable"))? == 0 {
+        return None;
+    }
+    let host = proxies
+        .find(CFString::new(&format!("{prefix}Proxy")))?
+        .downcast::<CFString>()?
+        .to_string();
+    let port = u16::try_from(get_number(proxies, &format!("{prefix}Port"))?).ok()?;
+    Some(SystemProxy { kind, host, port })
+}
+
+fn get_number(proxies: &CFDictionary<CFString, CFType>, key: &str) -> Option<i32> {
+    proxies
+        .find(CFString::new(key))?
+        .downcast::<CFNumber>()?
+        .to_i32()
+}
@@ -0,0 +1,63 @@
+//! Looks up the proxy that is configured in the OS, so that it can be used to reach the API. This
+//! is used by the built-in "System proxy" access method.
+//!
+//! The daemon runs as a system service, so it sees the system-wide proxy settings rather than the
+//! settings of any particular user.
+
+use mullvad_api::proxy::ProxyConfig;
+use talpid_types::net::proxy::Socks5Remote;
+
+#[cfg(target_os = "linux")]
+#[path = "linux.rs"]
+mod imp;
+
+#[cfg(target_os = "macos")]
+#[path = "macos.rs"]
+mod imp;
+
+#[cfg(windows)]
+#[path = "windows.rs"]
+mod imp;
+
+/// Kind of proxy configured in the OS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyKind {
+    /// An HTTP proxy that supports the `CONNECT` method.
+    Http,
+    Socks,
+}
+
+/// A proxy read from the settings of the OS. The host has not been resolved yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemProxy {
+    pub kind: ProxyKind,
+    pub host: String,
+    pub port: u16,
+}
+
+/// Return the proxy configured in the OS, if any. An HTTPS proxy is preferred over a SOCKS proxy,
+/// since it is the one that other applications use to reach HTTPS servers.
+pub async fn detect() -> Option<ProxyConfig> {
+    let proxy = match tokio::task::spawn_blocking(imp::read_system_proxy).await {
+        Ok(Some(proxy)) => proxy,
+        Ok(None) => return None,
+        Err(error) => {
+            log::error!("Failed to read system proxy settings: {error}");
+            return None;
+        }
+    };
+    log::debug!("Found system proxy: {proxy:?}");
+
+    let endpoint = match tokio::net::lookup_host((proxy.host.as_str(), proxy.port)).await {
+        Ok(mut addrs) => addrs.next()?,
+        Err(error) => {
+            log::error!("Failed to resolve system proxy {}: {error}", proxy.host);
+            return None;
+        }
+    };
+
+    Some(match proxy.kind {
+        ProxyKind::Http => ProxyConfig::HttpConnect(endpoint),
+        ProxyKind::Socks => ProxyConfig::Socks5Remote(Socks5Remote::new(endpoint)),
+    })
+}
@@ -0,0 +1,136 @@
+//! Reads the machine-wide WinHTTP proxy, as configured with `netsh winhttp set proxy`. The proxy
+//! settings in the Internet Options apply to a single user, and are not visible to the service.
+
+use super::{ProxyKind, SystemProxy};
+use std::{ffi::OsString, mem, os::windows::ffi::OsStringExt, slice};
+use windows_sys::Win32::{
+    Foundation::GlobalFree,
+    Networking::WinHttp::{
+        WinHttpGetDefaultProxyConfiguration, WINHTTP_ACCESS_TYPE_NAMED_PROXY, WINHTTP_PROXY_INFO,
+    },
+};
+
+pub fn read_system_proxy() -> Option<SystemProxy> {
+    // SAFETY: `WINHTTP_PROXY_INFO` is a plain C struct, so it may be zeroed.
+    let mut info: WINHTTP_PROXY_INFO = unsafe { mem::zeroed() };
+    // SAFETY: `info` is a valid pointer to a `WINHTTP_PROXY_INFO`.
+    if unsafe { WinHttpGetDefaultProxyConfiguration(&mut info) } == 0 {
+        log::debug!(
+            "Failed to read WinHTTP proxy: {}",
+            std::io::Error::last_os_error()
+        );
+        return None;
+    }
+
+    // SAFETY: The strings are either null or valid, and must be freed by the caller.
+    let proxy_list = unsafe {
+        let proxy_list = wide_string(info.lpszProxy);
+        free(info.lpszProxy);
+        free(info.lpszProxyBypass);
+        proxy_list
+    };
+
+    if info.dwAccessType != WINHTTP_ACCESS_TYPE_NAMED_PROXY {
+        return None;
+    }
+    parse_proxy_list(&proxy_list?)
+}
+
+/// Parse a WinHTTP proxy list, e.g. `proxy:3128` or `http=proxy:3128;socks=proxy:1080`.
+fn parse_proxy_list(proxy_list: &str) -> Option<SystemProxy> {
+    let entries: Vec<_> = proxy_list
+        .split(|c: char| c == ';' || c.is_whitespace())
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once('=') {
+            Some((scheme, address)) => (Some(scheme), address),
+            None => (None, entry),
+        })
+        .collect();
+
+    [
+        (Some("https"), ProxyKind::Http),
+        (None, ProxyKind::Http),
+        (Some("http"), ProxyKind::Http),
+        (Some("socks"), ProxyKind::Socks),
+    ]
+    .into_iter()
+    .find_map(|(scheme, kind)| {
+        let (_, address) = entries
+            .iter()
+            .find(|(entry_scheme, _)| *entry_scheme == scheme)?;
+        parse_address(address, kind)
+    })
+}
+
+fn parse_address(address: &str, kind: ProxyKind) -> Option<SystemProxy> {
+    let address = address
+        .strip_prefix("http://")
+        .or_else(|| address.strip_prefix("socks://"))
+        .unwrap_or(address);
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) => (host, port.trim_end_matches('/').parse().ok()?),
+        None => (address, default_port(kind)),
+    };
+    Some(SystemProxy {
+        kind,
+        host: host.to_owned(),
+        port,
+    })
+}
+
+fn default_port(kind: ProxyKind) -> u16 {
+    match kind {
+        ProxyKind::Http => 80,
+        ProxyKind::Socks => 1080,
+    }
+}
+
+/// # Safety
+///
+/// `string` must be null or point to a null-terminated UTF-16 string.
+unsafe fn wide_string(string: *const u16) -> Option<String> {
+    if string.is_null() {
+        return None;
+    }
+    let len = (0..).take_while(|&i| *string.add(i) != 0).count();
+    let string = OsString::from_wide(slice::from_raw_parts(string, len));
+    string.into_string().ok()
+}
+
+/// # Safety
+///
+/// `string` must be null or have been allocated by WinHTTP.
+unsafe fn free(string: *mut u16) {
+    if !string.is_null() {
+        GlobalFree(string as _);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_proxy_list() {
+        let proxy = |kind, host: &str, port| {
+            Some(SystemProxy {
+                kind,
+                host: host.to_owned(),
+                port,
+            })
+        };
+        assert_eq!(
+            parse_proxy_list("proxy.corp:3128"),
+            proxy(ProxyKind::Http, "proxy.corp", 3128)
+        );
+        assert_eq!(
+            parse_proxy_list("socks=10.0.0.2:1080;http=10.0.0.1"),
+            proxy(ProxyKind::Http, "10.0.0.1", 80)
+        );
+        assert_eq!(
+            parse_proxy_list("socks=10.0.0.2"),
+            proxy(ProxyKind::Socks, "10.0.0.2", 1080)
+        );
+        assert_eq!(parse_proxy_list(""), None);
+    }
+}
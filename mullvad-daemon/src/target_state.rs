@@ -1,4 +1,4 @@
-use mullvad_types::states::TargetState;
+use mullvad_types::states::{LastConnectedRelay, PersistedTarget, TargetState};
 use std::{
     future::Future,
     ops::Deref,
@@ -10,11 +10,16 @@ use tokio::{fs, io};
 /// State to use by default if there is no cache.
 const DEFAULT_TARGET_STATE: TargetState = TargetState::Unsecured;
 const TARGET_START_STATE_FILE: &str = "target-start-state.json";
+const LAST_RELAY_FILE: &str = "last-relay.json";
 
 /// Persists the target state to a file, which is only removed if the instance is dropped cleanly.
+/// The relay that was last connected to is persisted alongside it, so that a restarted daemon can
+/// resume the same connection.
 pub struct PersistentTargetState {
     state: TargetState,
     cache_path: PathBuf,
+    relay: Option<LastConnectedRelay>,
+    relay_cache_path: PathBuf,
     locked: bool,
 }
 
@@ -26,9 +31,12 @@ impl PersistentTargetState {
             state,
             update_cache,
         } = Self::read_target_state(&cache_path, fs::read_to_string).await;
+        let relay_cache_path = cache_dir.join(LAST_RELAY_FILE);
         let state = PersistentTargetState {
             state,
             cache_path,
+            relay: Self::read_relay(&relay_cache_path).await,
+            relay_cache_path,
             locked: false,
         };
         if update_cache {
@@ -97,15 +105,46 @@ impl PersistentTargetState {
     /// Override the current target state, if there is one
     pub async fn force(cache_dir: &Path, state: TargetState) -> Self {
         let cache_path = cache_dir.join(TARGET_START_STATE_FILE);
+        let relay_cache_path = cache_dir.join(LAST_RELAY_FILE);
         let state = PersistentTargetState {
             state,
             cache_path,
+            relay: Self::read_relay(&relay_cache_path).await,
+            relay_cache_path,
             locked: false,
         };
         state.save().await;
         state
     }
 
+    /// Read the relay that was last connected to, if it was persisted by the previous instance.
+    async fn read_relay(cache: &Path) -> Option<LastConnectedRelay> {
+        let content = match fs::read_to_string(cache).await {
+            Ok(content) => content,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return None,
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to read cached last relay")
+                );
+                return None;
+            }
+        };
+        match serde_json::from_str(&content) {
+            Ok(relay) => {
+                log::info!("Loaded cached last relay \"{relay}\"");
+                Some(relay)
+            }
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to parse cached last relay")
+                );
+                None
+            }
+        }
+    }
+
     pub async fn set(&mut self, new_state: TargetState) {
         if new_state != self.state {
             self.state = new_state;
@@ -113,6 +152,47 @@ impl PersistentTargetState {
         }
     }
 
+    /// Returns the relay that the tunnel was last connected to, if it is known.
+    pub fn relay(&self) -> Option<&LastConnectedRelay> {
+        self.relay.as_ref()
+    }
+
+    /// Remember the relay that the tunnel is connected to, or forget it if it is not a relay from
+    /// the relay list.
+    pub async fn set_relay(&mut self, relay: Option<LastConnectedRelay>) {
+        if relay == self.relay {
+            return;
+        }
+        self.relay = relay;
+        let result = match &self.relay {
+            Some(relay) => match serde_json::to_string(relay) {
+                Ok(data) => fs::write(&self.relay_cache_path, data).await,
+                Err(error) => {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to serialize last relay cache")
+                    );
+                    return;
+                }
+            },
+            None => remove_file(&self.relay_cache_path).await,
+        };
+        if let Err(error) = result {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to update last relay cache")
+            );
+        }
+    }
+
+    /// Returns what a restarted daemon would resume.
+    pub fn persisted(&self) -> PersistedTarget {
+        PersistedTarget {
+            target_state: self.state,
+            relay: self.relay.clone(),
+        }
+    }
+
     /// Prevent the file from being removed when the instance is dropped.
     pub fn lock(&mut self) {
         self.locked = true;
@@ -123,14 +203,14 @@ impl PersistentTargetState {
         if self.locked {
             return;
         }
-        let _ = fs::remove_file(&self.cache_path).await.map_err(|error| {
-            if error.kind() != io::ErrorKind::NotFound {
+        for path in [&self.cache_path, &self.relay_cache_path] {
+            let _ = remove_file(path).await.map_err(|error| {
                 log::error!(
                     "{}",
                     error.display_chain_with_msg("Cannot delete target tunnel state cache")
                 );
-            }
-        });
+            });
+        }
         // prevent the sync destructor from running
         self.locked = true;
     }
@@ -164,14 +244,24 @@ impl Drop for PersistentTargetState {
         if self.locked {
             return;
         }
-        let _ = std::fs::remove_file(&self.cache_path).map_err(|error| {
-            if error.kind() != io::ErrorKind::NotFound {
-                log::error!(
-                    "{}",
-                    error.display_chain_with_msg("Cannot delete target tunnel state cache")
-                );
-            }
-        });
+        for path in [&self.cache_path, &self.relay_cache_path] {
+            let _ = std::fs::remove_file(path).map_err(|error| {
+                if error.kind() != io::ErrorKind::NotFound {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Cannot delete target tunnel state cache")
+                    );
+                }
+            });
+        }
+    }
+}
+
+/// Remove `path`, ignoring that it does not exist.
+async fn remove_file(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path).await {
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
     }
 }
 
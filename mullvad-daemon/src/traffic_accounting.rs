@@ -0,0 +1,326 @@
+//! Keeps track of how many bytes have been sent and received over the tunnel interface and by the
+//! obfuscators, and persists monthly totals so that they survive daemon restarts.
+
+use futures::{
+    channel::{mpsc, oneshot},
+    FutureExt, SinkExt, StreamExt,
+};
+use mullvad_types::traffic::TrafficStats;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use talpid_types::ErrorExt;
+use tokio::fs;
+use tunnel_obfuscation::ByteCounts;
+
+const TRAFFIC_STATS_FILENAME: &str = "traffic-stats.json";
+
+/// How often the counters of the tunnel interface and the obfuscators are sampled.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Failed to read traffic stats cache")]
+    Read(#[source] io::Error),
+
+    #[error("Failed to write traffic stats cache")]
+    Write(#[source] io::Error),
+
+    #[error("Failed to parse traffic stats cache")]
+    Deserialize(#[source] serde_json::Error),
+
+    #[error("Failed to serialize traffic stats")]
+    Serialize(#[source] serde_json::Error),
+
+    #[error("Traffic accountant is down")]
+    AccountantDown,
+}
+
+enum TrafficAccountantCommand {
+    SetInterface(Option<String>),
+    GetStats(oneshot::Sender<TrafficStats>),
+}
+
+#[derive(Clone)]
+pub(crate) struct TrafficAccountantHandle {
+    tx: mpsc::Sender<TrafficAccountantCommand>,
+}
+
+impl TrafficAccountantHandle {
+    /// Set the tunnel interface to account traffic for. `None` stops the accounting until a new
+    /// interface is set.
+    pub async fn set_interface(&self, interface: Option<String>) {
+        if self
+            .tx
+            .clone()
+            .send(TrafficAccountantCommand::SetInterface(interface))
+            .await
+            .is_err()
+        {
+            log::error!("Traffic accountant already down, can't set tunnel interface");
+        }
+    }
+
+    /// Return the monthly totals, including traffic on the current interface up until now.
+    pub async fn get_stats(&self) -> Result<TrafficStats, Error> {
+        let (tx, rx) = oneshot::channel();
+        self.tx
+            .clone()
+            .send(TrafficAccountantCommand::GetStats(tx))
+            .await
+            .map_err(|_| Error::AccountantDown)?;
+        rx.await.map_err(|_| Error::AccountantDown)
+    }
+}
+
+/// Byte counters of a network interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct InterfaceCounters {
+    rx_bytes: u64,
+    tx_bytes: u64,
+}
+
+impl InterfaceCounters {
+    /// Return the number of bytes transferred since `previous`. If a counter has decreased, the
+    /// interface has been recreated and the counter is assumed to have restarted from zero.
+    fn delta(self, previous: InterfaceCounters) -> InterfaceCounters {
+        let delta = |new: u64, old: u64| new.checked_sub(old).unwrap_or(new);
+        InterfaceCounters {
+            rx_bytes: delta(self.rx_bytes, previous.rx_bytes),
+            tx_bytes: delta(self.tx_bytes, previous.tx_bytes),
+        }
+    }
+}
+
+struct MonitoredInterface {
+    name: String,
+    last_sample: Option<InterfaceCounters>,
+}
+
+pub(crate) struct TrafficAccountant {
+    stats: TrafficStats,
+    cache_path: PathBuf,
+    interface: Option<MonitoredInterface>,
+    /// Counters of the obfuscators at the last sample.
+    obfuscation_sample: [(&'static str, ByteCounts); 2],
+}
+
+impl TrafficAccountant {
+    pub async fn spawn(cache_dir: &Path) -> TrafficAccountantHandle {
+        let cache_path = cache_dir.join(TRAFFIC_STATS_FILENAME);
+        let stats = match load_cache(&cache_path).await {
+            Ok(stats) => stats,
+            Err(Error::Read(error)) if error.kind() == io::ErrorKind::NotFound => {
+                TrafficStats::default()
+            }
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Discarding traffic stats cache")
+                );
+                TrafficStats::default()
+            }
+        };
+
+        let (tx, rx) = mpsc::channel(1);
+        let accountant = TrafficAccountant {
+            stats,
+            cache_path,
+            interface: None,
+            obfuscation_sample: tunnel_obfuscation::byte_counts(),
+        };
+        tokio::spawn(accountant.run(rx));
+
+        TrafficAccountantHandle { tx }
+    }
+
+    async fn run(mut self, mut cmd_rx: mpsc::Receiver<TrafficAccountantCommand>) {
+        loop {
+            let next_sample = tokio::time::sleep(SAMPLE_INTERVAL).fuse();
+            tokio::pin!(next_sample);
+
+            futures::select! {
+                _ = next_sample => {
+                    if self.sample().await {
+                        self.save().await;
+                    }
+                }
+                cmd = cmd_rx.next() => match cmd {
+                    Some(TrafficAccountantCommand::SetInterface(name)) => {
+                        self.set_interface(name).await;
+                    }
+                    Some(TrafficAccountantCommand::GetStats(tx)) => {
+                        self.sample().await;
+                        let _ = tx.send(self.stats.clone());
+                    }
+                    None => {
+                        log::trace!("Traffic accountant shutting down");
+                        if self.sample().await {
+                            self.save().await;
+                        }
+                        return;
+                    }
+                },
+            }
+        }
+    }
+
+    async fn set_interface(&mut self, name: Option<String>) {
+        let current_name = self.interface.as_ref().map(|interface| &interface.name);
+        if current_name == name.as_ref() {
+            return;
+        }
+        // Account for any remaining traffic on the old interface before switching
+        if self.sample().await {
+            self.save().await;
+        }
+        self.interface = name.map(|name| MonitoredInterface {
+            name,
+            last_sample: None,
+        });
+        self.sample().await;
+    }
+
+    /// Read the counters of the current interface and of the obfuscators, and add the difference
+    /// since the last sample to the stats. Returns whether the stats changed.
+    async fn sample(&mut self) -> bool {
+        let obfuscation_changed = self.sample_obfuscation();
+        self.sample_interface().await || obfuscation_changed
+    }
+
+    async fn sample_interface(&mut self) -> bool {
+        let Some(interface) = self.interface.as_mut() else {
+            return false;
+        };
+        let counters = match read_interface_counters(&interface.name).await {
+            Ok(counters) => counters,
+            Err(error) => {
+                log::trace!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to read tunnel interface counters")
+                );
+                return false;
+            }
+        };
+        let previous = interface.last_sample.replace(counters);
+        let Some(previous) = previous else {
+            // The first sample only establishes a baseline. Traffic sent before the interface was
+            // monitored belongs to the connection attempt and is not counted.
+            return false;
+        };
+        let delta = counters.delta(previous);
+        if delta.rx_bytes == 0 && delta.tx_bytes == 0 {
+            return false;
+        }
+        self.stats.record(
+            chrono::Utc::now(),
+            &interface.name,
+            delta.rx_bytes,
+            delta.tx_bytes,
+        );
+        true
+    }
+
+    /// The obfuscators count from when the daemon started, so unlike the interface counters, the
+    /// first sample is not just a baseline.
+    fn sample_obfuscation(&mut self) -> bool {
+        let counts = tunnel_obfuscation::byte_counts();
+        let now = chrono::Utc::now();
+        let mut changed = false;
+        for ((method, counts), (_, previous)) in counts.iter().zip(&self.obfuscation_sample) {
+            if counts == previous {
+                continue;
+            }
+            self.stats.record_obfuscation(
+                now,
+                method,
+                (
+                    counts.payload_rx - previous.payload_rx,
+                    counts.payload_tx - previous.payload_tx,
+                ),
+                (
+                    counts.wire_rx - previous.wire_rx,
+                    counts.wire_tx - previous.wire_tx,
+                ),
+            );
+            changed = true;
+        }
+        self.obfuscation_sample = counts;
+        changed
+    }
+
+    async fn save(&self) {
+        if let Err(error) = write_cache(&self.cache_path, &self.stats).await {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to save traffic stats")
+            );
+        }
+    }
+}
+
+async fn load_cache(cache_path: &Path) -> Result<TrafficStats, Error> {
+    let content = fs::read_to_string(cache_path).await.map_err(Error::Read)?;
+    serde_json::from_str(&content).map_err(Error::Deserialize)
+}
+
+async fn write_cache(cache_path: &Path, stats: &TrafficStats) -> Result<(), Error> {
+    let content = serde_json::to_string_pretty(stats).map_err(Error::Serialize)?;
+    fs::write(cache_path, content).await.map_err(Error::Write)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+async fn read_interface_counters(interface: &str) -> io::Result<InterfaceCounters> {
+    let stats_dir = Path::new("/sys/class/net")
+        .join(interface)
+        .join("statistics");
+    let read_counter = |name: &'static str| {
+        let path = stats_dir.join(name);
+        async move {
+            let value = fs::read_to_string(path).await?;
+            value
+                .trim()
+                .parse::<u64>()
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+        }
+    };
+    Ok(InterfaceCounters {
+        rx_bytes: read_counter("rx_bytes").await?,
+        tx_bytes: read_counter("tx_bytes").await?,
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+async fn read_interface_counters(_interface: &str) -> io::Result<InterfaceCounters> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Reading interface counters is not supported on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::InterfaceCounters;
+
+    #[test]
+    fn test_counter_delta() {
+        let previous = InterfaceCounters {
+            rx_bytes: 100,
+            tx_bytes: 50,
+        };
+        let current = InterfaceCounters {
+            rx_bytes: 150,
+            tx_bytes: 20,
+        };
+        // tx has decreased, so the interface must have been recreated
+        assert_eq!(
+            current.delta(previous),
+            InterfaceCounters {
+                rx_bytes: 50,
+                tx_bytes: 20,
+            }
+        );
+    }
+}
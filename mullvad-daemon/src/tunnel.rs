@@ -11,21 +11,30 @@ use tokio::sync::Mutex;
 use mullvad_relay_selector::{GetRelay, RelaySelector, RuntimeParameters, WireguardConfig};
 use mullvad_types::{
     endpoint::MullvadWireguardEndpoint, location::GeoIpLocation, relay_list::Relay,
-    settings::TunnelOptions,
+    settings::TunnelOptions, states::LastConnectedRelay,
 };
 use once_cell::sync::Lazy;
 use talpid_core::tunnel_state_machine::TunnelParametersGenerator;
 #[cfg(not(target_os = "android"))]
 use talpid_types::net::{
-    obfuscation::ObfuscatorConfig, openvpn, proxy::CustomProxy, wireguard, Endpoint,
+    obfuscation::ObfuscatorConfig, openvpn, proxy::ProxyChain, wireguard, Endpoint, TunnelEndpoint,
     TunnelParameters,
 };
 #[cfg(target_os = "android")]
-use talpid_types::net::{obfuscation::ObfuscatorConfig, wireguard, TunnelParameters};
+use talpid_types::net::{
+    obfuscation::ObfuscatorConfig, wireguard, TunnelEndpoint, TunnelParameters,
+};
 
 use talpid_types::{tunnel::ParameterGenerationError, ErrorExt};
 
-use crate::device::{AccountManagerHandle, PrivateAccountAndDevice};
+#[cfg(not(target_os = "android"))]
+use crate::certificates::CertificateStore;
+#[cfg(not(target_os = "android"))]
+use crate::tunnel_protocol_fallback::FallbackSelector;
+use crate::{
+    device::{AccountManagerHandle, PrivateAccountAndDevice},
+    host_cache::HostCache,
+};
 
 /// The IP-addresses that the client uses when it connects to a server that supports the
 /// "Same IP" functionality. This means all clients have the same in-tunnel IP on these
@@ -39,6 +48,10 @@ static SAME_IP_V6: Lazy<IpAddr> = Lazy::new(|| {
         .into()
 });
 
+/// Unprivileged user that OpenVPN switches to once the tunnel is up.
+#[cfg(target_os = "linux")]
+const OPENVPN_USER: &str = "nobody";
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("Not logged in on a valid device")]
@@ -49,6 +62,46 @@ pub enum Error {
 
     #[error("Failed to resolve hostname for custom relay")]
     ResolveCustomHostname,
+
+    #[cfg(not(target_os = "android"))]
+    #[error("Failed to resolve hostname for custom bridge")]
+    ResolveBridgeHostname,
+}
+
+/// Returns the user and group that OpenVPN should run as once the tunnel is up, or `None` if no
+/// unprivileged user is available.
+#[cfg(target_os = "linux")]
+fn openvpn_privilege_drop() -> Option<openvpn::PrivilegeDrop> {
+    use nix::unistd::{Group, User};
+
+    let privilege_drop = User::from_name(OPENVPN_USER)
+        .ok()
+        .flatten()
+        .and_then(|user| Group::from_gid(user.gid).ok().flatten())
+        .map(|group| openvpn::PrivilegeDrop {
+            user: OPENVPN_USER.to_owned(),
+            group: group.name,
+        });
+    if privilege_drop.is_none() {
+        log::warn!("Failed to look up user \"{OPENVPN_USER}\". OpenVPN will keep running as root");
+    }
+    privilege_drop
+}
+
+/// OpenVPN adds and removes its own routes on macOS, so it has to keep its privileges.
+#[cfg(target_os = "macos")]
+fn openvpn_privilege_drop() -> Option<openvpn::PrivilegeDrop> {
+    None
+}
+
+/// Custom tunnel configs keep the firewall mark that was current when they were saved, so it is
+/// replaced by the configured one.
+#[cfg(target_os = "linux")]
+fn set_fwmark(parameters: &mut TunnelParameters, fwmark: u32) {
+    match parameters {
+        TunnelParameters::OpenVpn(parameters) => parameters.fwmark = fwmark,
+        TunnelParameters::Wireguard(parameters) => parameters.connection.fwmark = Some(fwmark),
+    }
 }
 
 #[derive(Clone)]
@@ -58,8 +111,16 @@ struct InnerParametersGenerator {
     relay_selector: RelaySelector,
     tunnel_options: TunnelOptions,
     account_manager: AccountManagerHandle,
+    #[cfg(not(target_os = "android"))]
+    certificate_store: CertificateStore,
+    host_cache: HostCache,
+    #[cfg(not(target_os = "android"))]
+    tunnel_protocol_fallback: FallbackSelector,
 
     last_generated_relays: Option<LastSelectedRelays>,
+    /// Relay that the previous instance of the daemon was connected to. It is tried once, on the
+    /// first attempt to connect.
+    resume_relay: Option<LastConnectedRelay>,
 }
 
 impl ParametersGenerator {
@@ -68,14 +129,24 @@ impl ParametersGenerator {
         account_manager: AccountManagerHandle,
         relay_selector: RelaySelector,
         tunnel_options: TunnelOptions,
+        #[cfg(not(target_os = "android"))] certificate_store: CertificateStore,
+        host_cache: HostCache,
+        #[cfg(not(target_os = "android"))] tunnel_protocol_fallback: FallbackSelector,
+        resume_relay: Option<LastConnectedRelay>,
     ) -> Self {
         Self(Arc::new(Mutex::new(InnerParametersGenerator {
             tunnel_options,
             relay_selector,
 
             account_manager,
+            #[cfg(not(target_os = "android"))]
+            certificate_store,
+            host_cache,
+            #[cfg(not(target_os = "android"))]
+            tunnel_protocol_fallback,
 
             last_generated_relays: None,
+            resume_relay,
         })))
     }
 
@@ -84,17 +155,40 @@ impl ParametersGenerator {
         self.0.lock().await.tunnel_options = tunnel_options.clone();
     }
 
-    /// Gets the location associated with the last generated tunnel parameters.
-    pub async fn get_last_location(&self) -> Option<GeoIpLocation> {
+    /// Sets the number of failed WireGuard attempts after which OpenVPN is used, or `None` if the
+    /// relay selector should pick the tunnel protocol by itself.
+    #[cfg(not(target_os = "android"))]
+    pub async fn set_wireguard_attempts_before_fallback(&self, wireguard_attempts: Option<u32>) {
+        self.0
+            .lock()
+            .await
+            .tunnel_protocol_fallback
+            .set_wireguard_attempts(wireguard_attempts)
+            .await;
+    }
+
+    /// Sets the route manager that is used to tell networks apart when falling back to OpenVPN.
+    #[cfg(not(target_os = "android"))]
+    pub async fn set_route_manager(&self, route_manager: talpid_routing::RouteManagerHandle) {
+        self.0
+            .lock()
+            .await
+            .tunnel_protocol_fallback
+            .set_route_manager(route_manager);
+    }
+
+    /// Gets the location associated with the last generated tunnel parameters. `endpoint` is the
+    /// endpoint that the tunnel connects to, which tells which relay is used if a candidate relay
+    /// was raced against the selected one.
+    pub async fn get_last_location(&self, endpoint: &TunnelEndpoint) -> Option<GeoIpLocation> {
         let inner = self.0.lock().await;
 
         let relays = inner.last_generated_relays.as_ref()?;
 
-        let hostname;
+        let exit_relay;
         let bridge_hostname;
         let entry_hostname;
         let obfuscator_hostname;
-        let location;
         let take_hostname =
             |relay: &Option<Relay>| relay.as_ref().map(|relay| relay.hostname.clone());
 
@@ -103,22 +197,30 @@ impl ParametersGenerator {
                 wg_entry: entry,
                 wg_exit: exit,
                 obfuscator,
+                candidate,
             } => {
+                let connected_ip = endpoint.endpoint.address.ip();
+                let exit = candidate
+                    .as_ref()
+                    .filter(|candidate| {
+                        IpAddr::from(candidate.ipv4_addr_in) == connected_ip
+                            || candidate.ipv6_addr_in.map(IpAddr::from) == Some(connected_ip)
+                    })
+                    .unwrap_or(exit);
                 entry_hostname = take_hostname(entry);
-                hostname = exit.hostname.clone();
+                exit_relay = exit;
                 obfuscator_hostname = take_hostname(obfuscator);
                 bridge_hostname = None;
-                location = exit.location.as_ref().cloned().unwrap();
             }
             #[cfg(not(target_os = "android"))]
             LastSelectedRelays::OpenVpn { relay, bridge } => {
-                hostname = relay.hostname.clone();
+                exit_relay = relay;
                 bridge_hostname = take_hostname(bridge);
                 entry_hostname = None;
                 obfuscator_hostname = None;
-                location = relay.location.as_ref().cloned().unwrap();
             }
         };
+        let location = exit_relay.location.as_ref().cloned().unwrap();
 
         Some(GeoIpLocation {
             ipv4: None,
@@ -128,10 +230,13 @@ impl ParametersGenerator {
             latitude: location.latitude,
             longitude: location.longitude,
             mullvad_exit_ip: true,
-            hostname: Some(hostname),
+            hostname: Some(exit_relay.hostname.clone()),
             bridge_hostname,
             entry_hostname,
             obfuscator_hostname,
+            provider: Some(exit_relay.provider.clone()),
+            owned: Some(exit_relay.owned),
+            stale: false,
         })
     }
 }
@@ -141,11 +246,41 @@ impl InnerParametersGenerator {
         &mut self,
         retry_attempt: u32,
         ipv6: bool,
+        require_obfuscation: bool,
     ) -> Result<TunnelParameters, Error> {
         let data = self.device().await?;
-        let selected_relay = self
-            .relay_selector
-            .get_relay(retry_attempt as usize, RuntimeParameters { ipv6 })?;
+        let runtime_params = RuntimeParameters {
+            ipv6,
+            require_obfuscation,
+        };
+        let resumed_relay = self
+            .resume_relay
+            .take()
+            .filter(|_| retry_attempt == 0)
+            .and_then(|last_relay| {
+                match self
+                    .relay_selector
+                    .get_resumed_relay(&last_relay, runtime_params.clone())
+                {
+                    Ok(relay) => {
+                        log::info!("Resuming connection to {last_relay}");
+                        Some(relay)
+                    }
+                    Err(error) => {
+                        log::info!(
+                            "{}",
+                            error.display_chain_with_msg(&format!(
+                                "Not resuming connection to {last_relay}"
+                            ))
+                        );
+                        None
+                    }
+                }
+            });
+        let selected_relay = match resumed_relay {
+            Some(relay) => relay,
+            None => self.select_relay(retry_attempt, runtime_params).await?,
+        };
 
         match selected_relay {
             #[cfg(not(target_os = "android"))]
@@ -159,13 +294,17 @@ impl InnerParametersGenerator {
                     relay: exit.clone(),
                     bridge: bridge_relay.cloned(),
                 });
-                let bridge_settings = bridge.as_ref().map(|bridge| bridge.settings());
-                Ok(self.create_openvpn_tunnel_parameters(endpoint, data, bridge_settings.cloned()))
+                let bridge_settings = match bridge.as_ref() {
+                    Some(bridge) => Some(self.resolve_proxy_chain(bridge.settings()).await?),
+                    None => None,
+                };
+                Ok(self.create_openvpn_tunnel_parameters(endpoint, data, bridge_settings))
             }
             GetRelay::Wireguard {
                 endpoint,
                 obfuscator,
                 inner,
+                candidate,
             } => {
                 let (obfuscator_relay, obfuscator_config) = match obfuscator {
                     Some(obfuscator) => (Some(obfuscator.relay), Some(obfuscator.config)),
@@ -180,37 +319,100 @@ impl InnerParametersGenerator {
                     wg_entry,
                     wg_exit,
                     obfuscator: obfuscator_relay,
+                    candidate,
                 });
 
                 Ok(self.create_wireguard_tunnel_parameters(endpoint, data, obfuscator_config))
             }
             GetRelay::Custom(custom_relay) => {
                 self.last_generated_relays = None;
-                custom_relay
-                     // TODO: generate proxy settings for custom tunnels
-                     .to_tunnel_parameters(self.tunnel_options.clone(), None)
-                     .map_err(|e| {
-                         log::error!("Failed to resolve hostname for custom tunnel config: {}", e);
-                         Error::ResolveCustomHostname
-                     })
+                let ip = self
+                    .host_cache
+                    .resolve(&custom_relay.host)
+                    .await
+                    .map_err(|e| {
+                        log::error!(
+                            "{}",
+                            e.display_chain_with_msg(
+                                "Failed to resolve hostname for custom tunnel config"
+                            )
+                        );
+                        Error::ResolveCustomHostname
+                    })?;
+                // TODO: generate proxy settings for custom tunnels
+                #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
+                let mut parameters =
+                    custom_relay.to_tunnel_parameters(ip, self.tunnel_options.clone(), None);
+                #[cfg(target_os = "linux")]
+                set_fwmark(&mut parameters, crate::runtime_config::get().tunnel_fwmark);
+                Ok(parameters)
             }
         }
     }
 
+    /// Selects a relay for `retry_attempt`, using only the tunnel protocol that the fallback to
+    /// OpenVPN picks, if any.
+    #[cfg_attr(target_os = "android", allow(clippy::unused_async))]
+    async fn select_relay(
+        &mut self,
+        retry_attempt: u32,
+        runtime_params: RuntimeParameters,
+    ) -> Result<GetRelay, Error> {
+        #[cfg(not(target_os = "android"))]
+        if let Some(tunnel_type) = self
+            .tunnel_protocol_fallback
+            .tunnel_type(retry_attempt)
+            .await
+        {
+            return Ok(self.relay_selector.get_relay_by_tunnel_type(
+                retry_attempt as usize,
+                tunnel_type,
+                runtime_params,
+            )?);
+        }
+        Ok(self
+            .relay_selector
+            .get_relay(retry_attempt as usize, runtime_params)?)
+    }
+
+    /// Replaces the IPs of the proxies in `chain` that are referenced by hostname.
+    #[cfg(not(target_os = "android"))]
+    async fn resolve_proxy_chain(&self, chain: ProxyChain) -> Result<ProxyChain, Error> {
+        let mut hops = Vec::from(chain);
+        for hop in &mut hops {
+            self.host_cache.resolve_proxy(hop).await.map_err(|error| {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to resolve hostname for custom bridge")
+                );
+                Error::ResolveBridgeHostname
+            })?;
+        }
+        Ok(ProxyChain::new(hops)
+            .expect("Resolving hostnames does not change the proxies of a chain"))
+    }
+
     #[cfg(not(target_os = "android"))]
     fn create_openvpn_tunnel_parameters(
         &self,
         endpoint: Endpoint,
         data: PrivateAccountAndDevice,
-        bridge_settings: Option<CustomProxy>,
+        bridge_settings: Option<ProxyChain>,
     ) -> TunnelParameters {
         openvpn::TunnelParameters {
-            config: openvpn::ConnectionConfig::new(endpoint, data.account_token, "-".to_string()),
+            config: openvpn::ConnectionConfig::new(
+                endpoint,
+                data.account_token.expose().clone(),
+                "-".to_string(),
+            ),
             options: self.tunnel_options.openvpn.clone(),
             generic_options: self.tunnel_options.generic.clone(),
             proxy: bridge_settings,
+            ca: self.certificate_store.openvpn_ca(),
+            #[cfg(unix)]
+            privilege_drop: openvpn_privilege_drop(),
             #[cfg(target_os = "linux")]
-            fwmark: mullvad_types::TUNNEL_FWMARK,
+            fwmark: crate::runtime_config::get().tunnel_fwmark,
         }
         .into()
     }
@@ -235,15 +437,33 @@ impl InnerParametersGenerator {
             log::debug!("Same IP is NOT being used");
         }
 
+        #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
+        let (mut peer, mut exit_peer) = (endpoint.peer, endpoint.exit_peer);
+        #[cfg(target_os = "linux")]
+        if let Some(allowed_ips) = &self.tunnel_options.wireguard.allowed_ips {
+            // The gateways are always routed through the tunnel, so that the relay can still be
+            // used for DNS and connectivity checks.
+            let exit = exit_peer.as_mut().unwrap_or(&mut peer);
+            exit.allowed_ips = allowed_ips
+                .iter()
+                .copied()
+                .chain([
+                    ipnetwork::IpNetwork::from(IpAddr::from(endpoint.ipv4_gateway)),
+                    ipnetwork::IpNetwork::from(IpAddr::from(endpoint.ipv6_gateway)),
+                ])
+                .collect();
+        }
+
         wireguard::TunnelParameters {
             connection: wireguard::ConnectionConfig {
                 tunnel,
-                peer: endpoint.peer,
-                exit_peer: endpoint.exit_peer,
+                peer,
+                exit_peer,
+                candidate_peer: endpoint.candidate_peer,
                 ipv4_gateway: endpoint.ipv4_gateway,
                 ipv6_gateway: Some(endpoint.ipv6_gateway),
                 #[cfg(target_os = "linux")]
-                fwmark: Some(mullvad_types::TUNNEL_FWMARK),
+                fwmark: Some(crate::runtime_config::get().tunnel_fwmark),
             },
             options: self
                 .tunnel_options
@@ -272,17 +492,20 @@ impl TunnelParametersGenerator for ParametersGenerator {
         &mut self,
         retry_attempt: u32,
         ipv6: bool,
+        require_obfuscation: bool,
     ) -> Pin<Box<dyn Future<Output = Result<TunnelParameters, ParameterGenerationError>>>> {
         let generator = self.0.clone();
         Box::pin(async move {
             let mut inner = generator.lock().await;
             inner
-                .generate(retry_attempt, ipv6)
+                .generate(retry_attempt, ipv6, require_obfuscation)
                 .await
                 .map_err(|error| match error {
                     Error::SelectRelay(mullvad_relay_selector::Error::NoBridge) => {
                         ParameterGenerationError::NoMatchingBridgeRelay
                     }
+                    #[cfg(not(target_os = "android"))]
+                    Error::ResolveBridgeHostname => ParameterGenerationError::NoMatchingBridgeRelay,
                     Error::ResolveCustomHostname => {
                         ParameterGenerationError::CustomTunnelHostResultionError
                     }
@@ -309,6 +532,9 @@ enum LastSelectedRelays {
         wg_entry: Option<Relay>,
         wg_exit: Relay,
         obfuscator: Option<Relay>,
+        /// Relay that was connected to in parallel with `wg_exit`. It replaces `wg_exit` if it
+        /// responded first.
+        candidate: Option<Relay>,
     },
     /// Represents all relays generated for an OpenVPN tunnel.
     /// The traffic flows like this:
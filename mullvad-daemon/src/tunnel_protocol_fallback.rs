@@ -0,0 +1,179 @@
+//! Falls back to OpenVPN on networks where WireGuard fails to connect, when the tunnel protocol is
+//! automatic. The networks where OpenVPN has been used are remembered for a while, so that
+//! WireGuard is not tried on them again every time the tunnel is connected. They are stored on
+//! disk so that they survive restarts of the daemon, and are forgotten when the fallback settings
+//! change.
+
+use chrono::{DateTime, Utc};
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+};
+use talpid_core::tunnel_state_machine;
+use talpid_routing::RouteManagerHandle;
+use talpid_types::{net::TunnelType, ErrorExt};
+use tokio::io::AsyncWriteExt;
+
+const CACHE_FILENAME: &str = "tunnel-protocol-fallback.json";
+
+/// How long to keep using OpenVPN on a network before WireGuard is tried there again.
+const REMEMBER_DURATION: chrono::Duration = chrono::Duration::days(30);
+
+pub struct FallbackSelector {
+    /// Number of failed WireGuard attempts after which OpenVPN is used, if falling back is
+    /// enabled.
+    wireguard_attempts: Option<u32>,
+    /// Networks where OpenVPN is used, and when WireGuard should be tried on them again.
+    networks: HashMap<String, DateTime<Utc>>,
+    cache_path: PathBuf,
+    route_manager: Option<RouteManagerHandle>,
+}
+
+impl FallbackSelector {
+    /// Creates a selector whose remembered networks are stored in `cache_dir`. Previously
+    /// remembered networks are loaded from there, if there are any.
+    pub async fn new(cache_dir: &Path, wireguard_attempts: Option<u32>) -> Self {
+        let cache_path = cache_dir.join(CACHE_FILENAME);
+        let networks = match tokio::fs::read(&cache_path).await {
+            Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|error| {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to parse tunnel protocol fallback cache")
+                );
+                HashMap::new()
+            }),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to read tunnel protocol fallback cache")
+                );
+                HashMap::new()
+            }
+        };
+        Self {
+            wireguard_attempts,
+            networks,
+            cache_path,
+            route_manager: None,
+        }
+    }
+
+    /// Sets the route manager that the current network is identified with. Until it is set, the
+    /// fallback still applies but no networks are remembered.
+    pub fn set_route_manager(&mut self, route_manager: RouteManagerHandle) {
+        self.route_manager = Some(route_manager);
+    }
+
+    /// Sets the number of failed WireGuard attempts after which OpenVPN is used, or `None` to
+    /// disable the fallback. Remembered networks are forgotten if this changes.
+    pub async fn set_wireguard_attempts(&mut self, wireguard_attempts: Option<u32>) {
+        if self.wireguard_attempts == wireguard_attempts {
+            return;
+        }
+        self.wireguard_attempts = wireguard_attempts;
+        if !self.networks.is_empty() {
+            log::debug!("Forgetting networks where OpenVPN was used");
+            self.networks.clear();
+            self.save().await;
+        }
+    }
+
+    /// Returns the tunnel protocol to use for `retry_attempt`, or `None` if the relay selector
+    /// should decide.
+    pub async fn tunnel_type(&mut self, retry_attempt: u32) -> Option<TunnelType> {
+        let wireguard_attempts = self.wireguard_attempts?;
+        let network = self.current_network().await;
+        let now = Utc::now();
+        let remembered = network
+            .as_ref()
+            .and_then(|network| self.networks.get(network))
+            .is_some_and(|retry_wireguard_at| now < *retry_wireguard_at);
+
+        let tunnel_type = select_tunnel_type(remembered, retry_attempt, wireguard_attempts);
+        if tunnel_type == TunnelType::OpenVpn && !remembered {
+            if retry_attempt == wireguard_attempts {
+                log::info!(
+                    "Falling back to OpenVPN after {wireguard_attempts} failed WireGuard attempts"
+                );
+            }
+            if let Some(network) = network {
+                self.networks.insert(network, now + REMEMBER_DURATION);
+                self.save().await;
+            }
+        }
+        Some(tunnel_type)
+    }
+
+    async fn current_network(&self) -> Option<String> {
+        tunnel_state_machine::current_network(
+            self.route_manager.as_ref()?,
+            #[cfg(target_os = "linux")]
+            Some(crate::runtime_config::get().tunnel_fwmark),
+        )
+        .await
+    }
+
+    async fn save(&mut self) {
+        let now = Utc::now();
+        self.networks
+            .retain(|_, retry_wireguard_at| now < *retry_wireguard_at);
+        if let Err(error) = self.write().await {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to save tunnel protocol fallback cache")
+            );
+        }
+    }
+
+    async fn write(&self) -> io::Result<()> {
+        let contents = serde_json::to_vec_pretty(&self.networks).map_err(io::Error::from)?;
+        let mut file = mullvad_fs::AtomicFile::new(&self.cache_path).await?;
+        file.write_all(&contents).await?;
+        file.finalize().await
+    }
+}
+
+/// WireGuard is used until it has failed `wireguard_attempts` times in a row, unless OpenVPN is
+/// already known to be needed on the network.
+fn select_tunnel_type(remembered: bool, retry_attempt: u32, wireguard_attempts: u32) -> TunnelType {
+    if remembered || retry_attempt >= wireguard_attempts {
+        TunnelType::OpenVpn
+    } else {
+        TunnelType::Wireguard
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_select_tunnel_type() {
+        assert_eq!(select_tunnel_type(false, 0, 3), TunnelType::Wireguard);
+        assert_eq!(select_tunnel_type(false, 2, 3), TunnelType::Wireguard);
+        assert_eq!(select_tunnel_type(false, 3, 3), TunnelType::OpenVpn);
+        assert_eq!(select_tunnel_type(false, 0, 0), TunnelType::OpenVpn);
+        assert_eq!(select_tunnel_type(true, 0, 3), TunnelType::OpenVpn);
+    }
+
+    /// Without a route manager, the network is unknown. OpenVPN is still used after enough
+    /// failures, but it is not remembered.
+    #[tokio::test]
+    async fn test_fallback_without_network() {
+        let mut fallback = FallbackSelector {
+            wireguard_attempts: None,
+            networks: HashMap::new(),
+            cache_path: PathBuf::from("tunnel-protocol-fallback-test.json"),
+            route_manager: None,
+        };
+        assert_eq!(fallback.tunnel_type(5).await, None);
+
+        fallback.set_wireguard_attempts(Some(2)).await;
+        assert_eq!(fallback.tunnel_type(1).await, Some(TunnelType::Wireguard));
+        assert_eq!(fallback.tunnel_type(2).await, Some(TunnelType::OpenVpn));
+        assert!(fallback.networks.is_empty());
+        assert_eq!(fallback.tunnel_type(0).await, Some(TunnelType::Wireguard));
+    }
+}
@@ -0,0 +1,128 @@
+//! Hands the blocking firewall policy over from a daemon that is about to be upgraded to the
+//! daemon that replaces it, so that traffic is never let through while the package is installed.
+//!
+//! When the installer prepares the restart, the outgoing daemon writes a handoff file to the cache
+//! directory. From then on it blocks traffic when shutting down, regardless of the shutdown
+//! behavior. The incoming daemon adopts the blocking policy when it starts, and only releases it
+//! and removes the file once it has entered its own target state.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{path::Path, time::Duration};
+use talpid_types::ErrorExt;
+use tokio::{fs, io};
+
+const UPGRADE_HANDOFF_FILE: &str = "upgrade-handoff.json";
+
+/// Handoffs older than this are ignored, since the upgrade was most likely aborted.
+const HANDOFF_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+/// Contents of the handoff file.
+#[derive(Debug, Serialize, Deserialize)]
+struct UpgradeHandoff {
+    /// When the outgoing daemon prepared the restart.
+    created: DateTime<Utc>,
+    /// Version of the outgoing daemon.
+    version: String,
+}
+
+impl UpgradeHandoff {
+    fn is_fresh(&self, now: DateTime<Utc>) -> bool {
+        match (now - self.created).to_std() {
+            Ok(age) => age <= HANDOFF_TIMEOUT,
+            // The clock was set back since the handoff was created
+            Err(_) => true,
+        }
+    }
+}
+
+/// Write the handoff file, before the daemon is shut down for an upgrade.
+pub async fn prepare(cache_dir: &Path) {
+    let handoff = UpgradeHandoff {
+        created: Utc::now(),
+        version: mullvad_version::VERSION.to_owned(),
+    };
+    let data = match serde_json::to_string(&handoff) {
+        Ok(data) => data,
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to serialize upgrade handoff")
+            );
+            return;
+        }
+    };
+    match fs::write(cache_dir.join(UPGRADE_HANDOFF_FILE), data).await {
+        Ok(()) => log::info!("Prepared handing the firewall policy over to the upgraded daemon"),
+        Err(error) => log::error!(
+            "{}",
+            error.display_chain_with_msg("Failed to write upgrade handoff")
+        ),
+    }
+}
+
+/// Returns whether the previous daemon handed its blocking firewall policy over to this one. Stale
+/// handoffs are removed.
+pub async fn adopt(cache_dir: &Path) -> bool {
+    let path = cache_dir.join(UPGRADE_HANDOFF_FILE);
+    let content = match fs::read_to_string(&path).await {
+        Ok(content) => content,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return false,
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to read upgrade handoff")
+            );
+            return false;
+        }
+    };
+    match serde_json::from_str::<UpgradeHandoff>(&content) {
+        Ok(handoff) if handoff.is_fresh(Utc::now()) => {
+            log::info!(
+                "Adopting the blocking firewall policy of the previous daemon, version {}",
+                handoff.version
+            );
+            return true;
+        }
+        Ok(handoff) => log::warn!(
+            "Ignoring upgrade handoff from {}, since it is too old",
+            handoff.created
+        ),
+        Err(error) => log::error!(
+            "{}",
+            error.display_chain_with_msg("Failed to parse upgrade handoff")
+        ),
+    }
+    release(cache_dir).await;
+    false
+}
+
+/// Remove the handoff file, once the daemon that adopted it has entered its target state.
+pub async fn release(cache_dir: &Path) {
+    match fs::remove_file(cache_dir.join(UPGRADE_HANDOFF_FILE)).await {
+        Ok(()) => (),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => (),
+        Err(error) => log::error!(
+            "{}",
+            error.display_chain_with_msg("Failed to remove upgrade handoff")
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_handoff_expiry() {
+        let now = Utc::now();
+        let handoff = |age| UpgradeHandoff {
+            created: now - age,
+            version: mullvad_version::VERSION.to_owned(),
+        };
+
+        assert!(handoff(chrono::Duration::minutes(1)).is_fresh(now));
+        assert!(handoff(chrono::Duration::minutes(-1)).is_fresh(now));
+        assert!(!handoff(chrono::Duration::hours(1)).is_fresh(now));
+    }
+}
@@ -1,15 +1,21 @@
-use crate::{version::is_beta_version, DaemonEventSender};
+use crate::{certificates::CertificateStore, version::is_beta_version, DaemonEventSender};
 use futures::{
     channel::{mpsc, oneshot},
     future::{BoxFuture, FusedFuture},
     FutureExt, SinkExt, StreamExt, TryFutureExt,
 };
-use mullvad_api::{availability::ApiAvailabilityHandle, rest::MullvadRestHandle, AppVersionProxy};
-use mullvad_types::version::{AppVersionInfo, ParsedAppVersion};
+use mullvad_api::{
+    availability::ApiAvailabilityHandle, certificates::SignedCertificateUpdate,
+    rest::MullvadRestHandle, version::VersionMetadata, AppVersionProxy,
+};
+use mullvad_types::version::{
+    AppUpgradeAvailable, AppVersion, AppVersionInfo, ParsedAppVersion, UpdateChannel,
+};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use std::{
     cmp::max,
+    collections::BTreeMap,
     future::Future,
     io,
     path::{Path, PathBuf},
@@ -79,6 +85,9 @@ pub enum Error {
     #[error("Failed to check the latest app version")]
     Download(#[source] mullvad_api::rest::Error),
 
+    #[error("Failed to verify the version check response")]
+    Verify(#[source] mullvad_api::version::Error),
+
     #[error("API availability check failed")]
     ApiCheck(#[source] mullvad_api::availability::Error),
 
@@ -98,7 +107,9 @@ pub(crate) struct VersionUpdater;
 struct VersionUpdaterInner {
     /// The last known [AppVersionInfo], along with the time it was determined.
     last_app_version_info: Option<(AppVersionInfo, SystemTime)>,
-    show_beta_releases: bool,
+    update_channel: UpdateChannel,
+    /// Changelog links from the last version check.
+    changelog_urls: BTreeMap<AppVersion, String>,
     /// Oneshot channels for responding to [VersionUpdaterCommand::GetVersionInfo].
     get_version_info_responders: Vec<oneshot::Sender<AppVersionInfo>>,
 }
@@ -109,21 +120,34 @@ pub(crate) struct VersionUpdaterHandle {
 }
 
 enum VersionUpdaterCommand {
-    SetShowBetaReleases(bool),
+    SetUpdateChannel(UpdateChannel),
     GetVersionInfo(oneshot::Sender<AppVersionInfo>),
 }
 
+/// A version check response whose version metadata has been verified.
+struct VersionCheckResponse {
+    metadata: VersionMetadata,
+    certificates: Option<SignedCertificateUpdate>,
+}
+
+/// Return the update channel selected by the `show_beta_releases` setting.
+pub(crate) fn update_channel(show_beta_releases: bool) -> UpdateChannel {
+    if show_beta_releases {
+        UpdateChannel::Beta
+    } else {
+        UpdateChannel::Stable
+    }
+}
+
 impl VersionUpdaterHandle {
-    pub async fn set_show_beta_releases(&mut self, show_beta_releases: bool) {
+    pub async fn set_update_channel(&mut self, update_channel: UpdateChannel) {
         if self
             .tx
-            .send(VersionUpdaterCommand::SetShowBetaReleases(
-                show_beta_releases,
-            ))
+            .send(VersionUpdaterCommand::SetUpdateChannel(update_channel))
             .await
             .is_err()
         {
-            log::error!("Version updater already down, can't send new `show_beta_releases` state");
+            log::error!("Version updater already down, can't send new update channel");
         }
     }
 
@@ -152,7 +176,8 @@ impl VersionUpdater {
         availability_handle: ApiAvailabilityHandle,
         cache_dir: PathBuf,
         update_sender: DaemonEventSender<AppVersionInfo>,
-        show_beta_releases: bool,
+        update_channel: UpdateChannel,
+        certificate_store: CertificateStore,
     ) -> VersionUpdaterHandle {
         // load the last known AppVersionInfo from cache
         let last_app_version_info = load_cache(&cache_dir).await;
@@ -167,7 +192,8 @@ impl VersionUpdater {
         tokio::spawn(
             VersionUpdaterInner {
                 last_app_version_info,
-                show_beta_releases,
+                update_channel,
+                changelog_urls: BTreeMap::new(),
                 get_version_info_responders: vec![],
             }
             .run(
@@ -181,6 +207,7 @@ impl VersionUpdater {
                     version_proxy,
                     platform_version,
                 },
+                certificate_store,
             ),
         );
 
@@ -194,22 +221,57 @@ impl VersionUpdaterInner {
         self.last_app_version_info.as_ref().map(|(info, _)| info)
     }
 
-    /// Convert a [mullvad_api::AppVersionResponse] to an [AppVersionInfo].
-    fn response_to_version_info(
+    /// The channel to suggest upgrades from. Beta versions always get beta upgrades.
+    fn effective_update_channel(&self) -> UpdateChannel {
+        if is_beta_version() {
+            UpdateChannel::Beta
+        } else {
+            self.update_channel
+        }
+    }
+
+    /// Return the upgrade to suggest given the latest releases, if any.
+    fn suggest_upgrade(
         &self,
-        response: mullvad_api::AppVersionResponse,
-    ) -> AppVersionInfo {
-        let suggested_upgrade = suggested_upgrade(
+        latest_stable: &Option<String>,
+        latest_beta: &str,
+    ) -> Option<AppUpgradeAvailable> {
+        let version = suggested_upgrade(
             &APP_VERSION,
-            &response.latest_stable,
-            &response.latest_beta,
-            self.show_beta_releases || is_beta_version(),
-        );
+            latest_stable,
+            latest_beta,
+            self.effective_update_channel() == UpdateChannel::Beta,
+        )?;
+        let channel = ParsedAppVersion::from_str(&version)
+            .map(|version| version.channel())
+            .unwrap_or_default();
+        // The changelog links are not cached, but the link of a previous suggestion is
+        let changelog_url = self.changelog_urls.get(&version).cloned().or_else(|| {
+            self.last_app_version_info()?
+                .suggested_upgrade
+                .as_ref()
+                .filter(|upgrade| upgrade.version == version)?
+                .changelog_url
+                .clone()
+        });
+
+        Some(AppUpgradeAvailable {
+            version,
+            channel,
+            changelog_url,
+        })
+    }
+
+    /// Convert verified [VersionMetadata] to an [AppVersionInfo].
+    fn metadata_to_version_info(&mut self, metadata: VersionMetadata) -> AppVersionInfo {
+        self.changelog_urls = metadata.changelog_urls;
+        let suggested_upgrade =
+            self.suggest_upgrade(&metadata.latest_stable, &metadata.latest_beta);
 
         AppVersionInfo {
-            supported: response.supported,
-            latest_stable: response.latest_stable.unwrap_or_else(|| "".to_owned()),
-            latest_beta: response.latest_beta,
+            supported: metadata.supported,
+            latest_stable: metadata.latest_stable.unwrap_or_else(|| "".to_owned()),
+            latest_beta: metadata.latest_beta,
             suggested_upgrade,
         }
     }
@@ -270,6 +332,7 @@ impl VersionUpdaterInner {
         mut rx: mpsc::Receiver<VersionUpdaterCommand>,
         update: UpdateContext,
         api: ApiContext,
+        certificate_store: CertificateStore,
     ) {
         // If this is a dev build, there's no need to pester the API for version checks.
         if *IS_DEV_BUILD {
@@ -284,8 +347,14 @@ impl VersionUpdaterInner {
         }
 
         let update = |info| Box::pin(update.update(info)) as BoxFuture<'static, _>;
-        let do_version_check = || do_version_check(api.clone());
-        let do_version_check_in_background = || do_version_check_in_background(api.clone());
+        let do_version_check =
+            || with_certificate_update(certificate_store.clone(), do_version_check(api.clone()));
+        let do_version_check_in_background = || {
+            with_certificate_update(
+                certificate_store.clone(),
+                do_version_check_in_background(api.clone()),
+            )
+        };
 
         self.run_inner(rx, update, do_version_check, do_version_check_in_background)
             .await
@@ -295,11 +364,10 @@ impl VersionUpdaterInner {
         mut self,
         mut rx: mpsc::Receiver<VersionUpdaterCommand>,
         update: impl Fn(AppVersionInfo) -> BoxFuture<'static, Result<(), Error>>,
-        do_version_check: impl Fn()
-            -> BoxFuture<'static, Result<mullvad_api::AppVersionResponse, Error>>,
+        do_version_check: impl Fn() -> BoxFuture<'static, Result<VersionCheckResponse, Error>>,
         do_version_check_in_background: impl Fn() -> BoxFuture<
             'static,
-            Result<mullvad_api::AppVersionResponse, Error>,
+            Result<VersionCheckResponse, Error>,
         >,
     ) {
         let mut version_is_stale = self.wait_until_version_is_stale();
@@ -308,18 +376,16 @@ impl VersionUpdaterInner {
         loop {
             futures::select! {
                 command = rx.next() => match command {
-                    Some(VersionUpdaterCommand::SetShowBetaReleases(show_beta_releases)) => {
-                        self.show_beta_releases = show_beta_releases;
+                    Some(VersionUpdaterCommand::SetUpdateChannel(update_channel)) => {
+                        self.update_channel = update_channel;
 
                         if let Some(last_app_version_info) = self
                             .last_app_version_info()
                             .cloned()
                         {
-                            let suggested_upgrade = suggested_upgrade(
-                                &APP_VERSION,
+                            let suggested_upgrade = self.suggest_upgrade(
                                 &Some(last_app_version_info.latest_stable.clone()),
                                 &last_app_version_info.latest_beta,
-                                self.show_beta_releases || is_beta_version(),
                             );
 
                             self.update_version_info(&update, AppVersionInfo {
@@ -363,9 +429,9 @@ impl VersionUpdaterInner {
 
                 response = version_check => {
                     match response {
-                        Ok(version_info_response) => {
+                        Ok(version_check_response) => {
                             let new_version_info =
-                                self.response_to_version_info(version_info_response);
+                                self.metadata_to_version_info(version_check_response.metadata);
 
                             // Respond to all pending GetVersionInfo commands
                             for done_tx in self.get_version_info_responders.drain(..) {
@@ -419,9 +485,7 @@ struct ApiContext {
 }
 
 /// Immediately query the API for the latest [AppVersionInfo].
-fn do_version_check(
-    api: ApiContext,
-) -> BoxFuture<'static, Result<mullvad_api::AppVersionResponse, Error>> {
+fn do_version_check(api: ApiContext) -> BoxFuture<'static, Result<VersionCheckResponse, Error>> {
     let download_future_factory = move || {
         api.version_proxy
             .version_check(
@@ -430,6 +494,7 @@ fn do_version_check(
                 api.platform_version.clone(),
             )
             .map_err(Error::Download)
+            .map(|result| result.and_then(verify_response))
     };
 
     // retry immediately on network errors (unless we're offline)
@@ -456,7 +521,7 @@ fn do_version_check(
 /// On any error, this function retries repeatedly every [UPDATE_INTERVAL_ERROR] until success.
 fn do_version_check_in_background(
     api: ApiContext,
-) -> BoxFuture<'static, Result<mullvad_api::AppVersionResponse, Error>> {
+) -> BoxFuture<'static, Result<VersionCheckResponse, Error>> {
     let download_future_factory = move || {
         let when_available = api.api_handle.wait_background();
         let request = api.version_proxy.version_check(
@@ -466,7 +531,7 @@ fn do_version_check_in_background(
         );
         async move {
             when_available.await.map_err(Error::ApiCheck)?;
-            request.await.map_err(Error::Download)
+            verify_response(request.await.map_err(Error::Download)?)
         }
     };
 
@@ -477,6 +542,38 @@ fn do_version_check_in_background(
     ))
 }
 
+/// Verify the signed version metadata in `response`, if it has any.
+fn verify_response(
+    response: mullvad_api::AppVersionResponse,
+) -> Result<VersionCheckResponse, Error> {
+    let metadata = response
+        .verify_metadata(mullvad_version::VERSION)
+        .map_err(Error::Verify)?;
+    Ok(VersionCheckResponse {
+        metadata,
+        certificates: response.certificates,
+    })
+}
+
+/// Apply the certificate update in the response to `version_check`, if there is one.
+fn with_certificate_update(
+    certificate_store: CertificateStore,
+    version_check: BoxFuture<'static, Result<VersionCheckResponse, Error>>,
+) -> BoxFuture<'static, Result<VersionCheckResponse, Error>> {
+    Box::pin(async move {
+        let response = version_check.await?;
+        if let Some(update) = &response.certificates {
+            if let Err(error) = certificate_store.update(update.clone()).await {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to apply certificate update")
+                );
+            }
+        }
+        Ok(response)
+    })
+}
+
 /// Read the app version cache from the provided directory.
 ///
 /// Returns the [AppVersionInfo] along with the modification time of the cache file,
@@ -706,12 +803,11 @@ mod test {
         }
     }
 
-    fn fake_version_check() -> BoxFuture<'static, Result<mullvad_api::AppVersionResponse, Error>> {
+    fn fake_version_check() -> BoxFuture<'static, Result<VersionCheckResponse, Error>> {
         Box::pin(async { Ok(fake_version_response()) })
     }
 
-    fn fake_version_check_err() -> BoxFuture<'static, Result<mullvad_api::AppVersionResponse, Error>>
-    {
+    fn fake_version_check_err() -> BoxFuture<'static, Result<VersionCheckResponse, Error>> {
         Box::pin(retry_future(
             || async { Err(Error::Download(mullvad_api::rest::Error::TimeoutError)) },
             |_| true,
@@ -719,12 +815,16 @@ mod test {
         ))
     }
 
-    fn fake_version_response() -> mullvad_api::AppVersionResponse {
-        mullvad_api::AppVersionResponse {
-            supported: true,
-            latest: "2024.1".to_owned(),
-            latest_stable: None,
-            latest_beta: "2024.1-beta1".to_owned(),
+    fn fake_version_response() -> VersionCheckResponse {
+        VersionCheckResponse {
+            metadata: VersionMetadata {
+                app_version: mullvad_version::VERSION.to_owned(),
+                supported: true,
+                latest_stable: None,
+                latest_beta: "2024.1-beta1".to_owned(),
+                changelog_urls: BTreeMap::new(),
+            },
+            certificates: None,
         }
     }
 
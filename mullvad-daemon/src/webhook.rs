@@ -0,0 +1,199 @@
+//! Posts tunnel state changes as JSON to an HTTP endpoint on the local machine, so that other
+//! programs can react to them without talking to the management interface.
+
+use futures::{channel::mpsc, StreamExt};
+use hyper::{client::HttpConnector, header, Body, Client, Method, Request, StatusCode, Uri};
+use mullvad_types::{settings::WebhookSettings, states::TunnelState};
+use serde::Serialize;
+use std::{net::IpAddr, time::Duration};
+use talpid_types::ErrorExt;
+
+/// Header that holds the secret from [`WebhookSettings`].
+const SECRET_HEADER: &str = "x-mullvad-webhook-secret";
+
+/// How long to wait for the receiver to respond to an event.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Failed to parse webhook URL")]
+    ParseUrl(#[source] hyper::http::uri::InvalidUri),
+
+    #[error("Webhook URL must use http and point to the loopback interface")]
+    NotLocal,
+
+    #[error("Failed to create webhook request")]
+    CreateRequest(#[source] hyper::http::Error),
+
+    #[error("Failed to serialize webhook event")]
+    Serialize(#[source] serde_json::Error),
+
+    #[error("Failed to send webhook event")]
+    Send(#[source] hyper::Error),
+
+    #[error("Timed out sending webhook event")]
+    Timeout,
+
+    #[error("Webhook receiver responded with {0}")]
+    ErrorResponse(StatusCode),
+}
+
+/// Parses `url`, and checks that it can be used for a webhook. Events must not leave the machine,
+/// so only `http` URLs whose host is `localhost` or a loopback address are accepted.
+pub fn parse_url(url: &str) -> Result<Uri, Error> {
+    let uri: Uri = url.parse().map_err(Error::ParseUrl)?;
+    if uri.scheme_str() != Some("http") {
+        return Err(Error::NotLocal);
+    }
+    let host = uri.host().ok_or(Error::NotLocal)?;
+    let is_loopback = host.eq_ignore_ascii_case("localhost")
+        || host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .map(|addr| addr.is_loopback())
+            .unwrap_or(false);
+    if !is_loopback {
+        return Err(Error::NotLocal);
+    }
+    Ok(uri)
+}
+
+/// Body of the requests sent to the webhook.
+#[derive(Serialize)]
+struct Event<'a> {
+    event: &'static str,
+    /// Short name of the new tunnel state, such as `connected`.
+    state: &'static str,
+    tunnel_state: &'a TunnelState,
+}
+
+enum WebhookCommand {
+    SetSettings(Option<WebhookSettings>),
+    TunnelState(TunnelState),
+}
+
+#[derive(Clone)]
+pub(crate) struct WebhookNotifierHandle {
+    tx: mpsc::UnboundedSender<WebhookCommand>,
+}
+
+impl WebhookNotifierHandle {
+    /// Set where to post events. `None` stops posting events.
+    pub fn set_settings(&self, settings: Option<WebhookSettings>) {
+        self.send(WebhookCommand::SetSettings(settings));
+    }
+
+    /// Post a new tunnel state, if a webhook is configured.
+    pub fn notify_tunnel_state(&self, tunnel_state: TunnelState) {
+        self.send(WebhookCommand::TunnelState(tunnel_state));
+    }
+
+    fn send(&self, command: WebhookCommand) {
+        if self.tx.unbounded_send(command).is_err() {
+            log::error!("Webhook notifier already down");
+        }
+    }
+}
+
+pub(crate) struct WebhookNotifier {
+    settings: Option<WebhookSettings>,
+    client: Client<HttpConnector>,
+}
+
+impl WebhookNotifier {
+    pub fn spawn(settings: Option<WebhookSettings>) -> WebhookNotifierHandle {
+        let (tx, rx) = mpsc::unbounded();
+        let notifier = WebhookNotifier {
+            settings,
+            client: Client::new(),
+        };
+        tokio::spawn(notifier.run(rx));
+        WebhookNotifierHandle { tx }
+    }
+
+    async fn run(mut self, mut rx: mpsc::UnboundedReceiver<WebhookCommand>) {
+        // Events are posted one at a time, so that they arrive in order.
+        while let Some(command) = rx.next().await {
+            match command {
+                WebhookCommand::SetSettings(settings) => self.settings = settings,
+                WebhookCommand::TunnelState(tunnel_state) => {
+                    let Some(settings) = &self.settings else {
+                        continue;
+                    };
+                    if let Err(error) = self.post_tunnel_state(settings, &tunnel_state).await {
+                        log::warn!(
+                            "{}",
+                            error.display_chain_with_msg("Failed to post tunnel state to webhook")
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    async fn post_tunnel_state(
+        &self,
+        settings: &WebhookSettings,
+        tunnel_state: &TunnelState,
+    ) -> Result<(), Error> {
+        let event = Event {
+            event: "tunnel_state",
+            state: state_name(tunnel_state),
+            tunnel_state,
+        };
+        let body = serde_json::to_vec(&event).map_err(Error::Serialize)?;
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(parse_url(&settings.url)?)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(SECRET_HEADER, &settings.secret)
+            .body(Body::from(body))
+            .map_err(Error::CreateRequest)?;
+
+        let response = tokio::time::timeout(REQUEST_TIMEOUT, self.client.request(request))
+            .await
+            .map_err(|_| Error::Timeout)?
+            .map_err(Error::Send)?;
+        if !response.status().is_success() {
+            return Err(Error::ErrorResponse(response.status()));
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn state_name(tunnel_state: &TunnelState) -> &'static str {
+    match tunnel_state {
+        TunnelState::Disconnected { .. } => "disconnected",
+        TunnelState::Connecting { .. } => "connecting",
+        TunnelState::Connected { .. } => "connected",
+        TunnelState::Disconnecting(_) => "disconnecting",
+        TunnelState::Error(_) => "error",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_url, Error};
+
+    #[test]
+    fn test_parse_url() {
+        for url in [
+            "http://localhost:8123/api/webhook/vpn",
+            "http://127.0.0.1/",
+            "http://[::1]:8080",
+        ] {
+            assert!(parse_url(url).is_ok(), "{url} should be accepted");
+        }
+        for url in [
+            "https://localhost/",
+            "http://192.168.1.10/",
+            "http://example.com/",
+        ] {
+            assert!(
+                matches!(parse_url(url), Err(Error::NotLocal)),
+                "{url} should be rejected"
+            );
+        }
+    }
+}
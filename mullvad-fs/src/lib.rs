@@ -3,7 +3,22 @@ use std::{
     path::{Path, PathBuf},
 };
 use talpid_types::ErrorExt;
-use tokio::{fs, io};
+use tokio::{
+    fs,
+    io::{self, AsyncWriteExt},
+};
+
+/// Whether an [`AtomicFile`] waits for its content to reach the storage device.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FsyncPolicy {
+    /// Flush the file before it replaces the target, and the directory after. After a power
+    /// loss, the target has either its old or its new content.
+    #[default]
+    Always,
+    /// Do not wait for the storage device. This is faster, but depending on the file system, the
+    /// target may be empty or missing after a power loss.
+    Never,
+}
 
 /// Stores content in a temporary file before moving it to the
 /// final destination, ensuring that consumers of the file never
@@ -12,27 +27,43 @@ pub struct AtomicFile {
     file: Option<fs::File>,
     temp_path: PathBuf,
     target_path: PathBuf,
+    fsync_policy: FsyncPolicy,
 }
 
 impl AtomicFile {
     pub async fn new<P: Into<PathBuf>>(target_path: P) -> io::Result<Self> {
+        Self::with_fsync_policy(target_path, FsyncPolicy::default()).await
+    }
+
+    pub async fn with_fsync_policy<P: Into<PathBuf>>(
+        target_path: P,
+        fsync_policy: FsyncPolicy,
+    ) -> io::Result<Self> {
         let target_path = target_path.into();
         let temp_path = target_path.with_file_name(uuid::Uuid::new_v4().to_string());
         Ok(Self {
             file: Some(fs::File::create(&temp_path).await?),
             temp_path,
             target_path,
+            fsync_policy,
         })
     }
 
     /// Flushes and moves the file to `self.target_path`, replacing it if it exists.
     pub async fn finalize(mut self) -> io::Result<()> {
         let result = async {
-            let file = self.file.take().unwrap();
-            file.sync_all().await?;
+            let mut file = self.file.take().unwrap();
+            match self.fsync_policy {
+                FsyncPolicy::Always => file.sync_all().await?,
+                FsyncPolicy::Never => file.flush().await?,
+            }
             let std_file = file.into_std().await;
             let _ = tokio::task::spawn_blocking(move || drop(std_file)).await;
-            fs::rename(&self.temp_path, &self.target_path).await
+            fs::rename(&self.temp_path, &self.target_path).await?;
+            if self.fsync_policy == FsyncPolicy::Always {
+                sync_parent_dir(&self.target_path).await?;
+            }
+            Ok(())
         }
         .await;
         if result.is_err() {
@@ -51,6 +82,21 @@ impl Drop for AtomicFile {
     }
 }
 
+/// Flushes the directory entry of `path`, so that a rename survives a power loss.
+#[cfg(unix)]
+async fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => fs::File::open(dir).await?.sync_all().await,
+        _ => Ok(()),
+    }
+}
+
+/// Directories cannot be opened as files on Windows, and `MoveFileEx` is synchronous there.
+#[cfg(not(unix))]
+async fn sync_parent_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
 fn try_remove_file(temp_path: &Path) {
     if let Err(error) = std::fs::remove_file(temp_path) {
         let msg = format!("Failed to delete temp file: {}", temp_path.display());
@@ -71,3 +117,73 @@ impl DerefMut for AtomicFile {
         self.file.as_mut().unwrap()
     }
 }
+
+/// An exclusive advisory lock on a file, which is released when it is dropped. It only excludes
+/// others that lock the same file, and does not prevent anyone from writing to it.
+pub struct FileLock {
+    _file: std::fs::File,
+}
+
+impl FileLock {
+    /// Locks `path`, creating it if it does not exist. Waits until the lock is released by anyone
+    /// else who holds it.
+    pub async fn lock<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let path = path.into();
+        tokio::task::spawn_blocking(move || {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&path)?;
+            lock_exclusive(&file)?;
+            Ok(Self { _file: file })
+        })
+        .await
+        .map_err(io::Error::other)?
+    }
+}
+
+#[cfg(unix)]
+fn lock_exclusive(file: &std::fs::File) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    loop {
+        // SAFETY: The file descriptor is valid for as long as `file` is.
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+            return Ok(());
+        }
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+}
+
+#[cfg(windows)]
+fn lock_exclusive(file: &std::fs::File) -> io::Result<()> {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::{
+        Foundation::HANDLE,
+        Storage::FileSystem::{LockFileEx, LOCKFILE_EXCLUSIVE_LOCK},
+        System::IO::OVERLAPPED,
+    };
+
+    // SAFETY: All-zero is a valid `OVERLAPPED`, and means that the range starts at offset 0.
+    let mut overlapped: OVERLAPPED = unsafe { std::mem::zeroed() };
+    // SAFETY: The handle is valid for as long as `file` is, and `overlapped` outlives the call,
+    // which is synchronous since the file was not opened for overlapped I/O.
+    let result = unsafe {
+        LockFileEx(
+            file.as_raw_handle() as HANDLE,
+            LOCKFILE_EXCLUSIVE_LOCK,
+            0,
+            u32::MAX,
+            u32::MAX,
+            &mut overlapped,
+        )
+    };
+    if result == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -212,8 +212,13 @@ fn start_logging(log_dir: &Path) -> Result<(), String> {
 fn initialize_logging(log_dir: &Path) -> Result<(), String> {
     let log_file = log_dir.join(LOG_FILENAME);
 
-    logging::init_logger(log::LevelFilter::Debug, Some(&log_file), true)
-        .map_err(|error| error.display_chain_with_msg("Failed to start logger"))?;
+    logging::init_logger(
+        log::LevelFilter::Debug,
+        Some(&log_file),
+        true,
+        logging::RotationPolicy::default(),
+    )
+    .map_err(|error| error.display_chain_with_msg("Failed to start logger"))?;
     exception_logging::enable();
     log_panics::init();
 
@@ -275,6 +280,9 @@ fn spawn_daemon(
         .map_err(Error::CreateGlobalReference)?;
     let (tx, rx) = mpsc::channel();
 
+    // The app keeps the settings in its files directory, together with the resources and logs.
+    let settings_dir = resource_dir.clone();
+
     let runtime = new_multi_thread()
         .build()
         .map_err(Error::InitializeTokioRuntime)?;
@@ -300,9 +308,10 @@ fn spawn_daemon(
 
         runtime.block_on(cleanup_old_rpc_socket());
 
-        let event_listener = match runtime
-            .block_on(async { spawn_management_interface(command_channel.sender()) })
-        {
+        let event_listener = match runtime.block_on(spawn_management_interface(
+            command_channel.sender(),
+            &settings_dir,
+        )) {
             Ok(event_listener) => event_listener,
             Err(error) => {
                 let _ = tx.send(Err(error));
@@ -312,8 +321,8 @@ fn spawn_daemon(
 
         let daemon = runtime.block_on(Daemon::start(
             Some(resource_dir.clone()),
-            resource_dir.clone(),
             resource_dir,
+            settings_dir,
             cache_dir,
             event_listener,
             command_channel,
@@ -346,17 +355,20 @@ use mullvad_daemon::{
     DaemonCommandSender,
 };
 
-fn spawn_management_interface(
+async fn spawn_management_interface(
     command_sender: DaemonCommandSender,
+    settings_dir: &Path,
 ) -> Result<ManagementInterfaceEventBroadcaster, Error> {
-    let (socket_path, event_broadcaster) = ManagementInterfaceServer::start(command_sender)
-        .map_err(|error| {
-            log::error!(
-                "{}",
-                error.display_chain_with_msg("Unable to start management interface server")
-            );
-            Error::SpawnManagementInterface(error)
-        })?;
+    let (socket_path, event_broadcaster) =
+        ManagementInterfaceServer::start(command_sender, settings_dir)
+            .await
+            .map_err(|error| {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Unable to start management interface server")
+                );
+                Error::SpawnManagementInterface(error)
+            })?;
 
     log::info!("Management interface listening on {}", socket_path);
 
@@ -1,3 +1,15 @@
 fn main() {
-    tonic_build::compile_protos("proto/management_interface.proto").unwrap();
+    let out_dir = std::path::PathBuf::from(std::env::var_os("OUT_DIR").unwrap());
+    tonic_build::configure()
+        // Served by the reflection service
+        .file_descriptor_set_path(out_dir.join("management_interface_descriptor.bin"))
+        .compile(
+            &[
+                "proto/management_interface.proto",
+                "proto/health.proto",
+                "proto/reflection.proto",
+            ],
+            &["proto"],
+        )
+        .unwrap();
 }
@@ -7,38 +7,71 @@ use mullvad_types::wireguard::DaitaSettings;
 use mullvad_types::{
     access_method::{self, AccessMethod, AccessMethodSetting},
     account::{AccountData, AccountToken, VoucherSubmission},
+    connection_diagnosis::{BlockedReason, ClockSkew},
     custom_list::{CustomList, Id},
-    device::{Device, DeviceEvent, DeviceId, DeviceState, RemoveDeviceEvent},
+    device::{Device, DeviceEvent, DeviceId, DeviceState, LoginOutcome, RemoveDeviceEvent},
+    health::DaemonHealth,
+    location::ConnectionVerification,
+    notification::Notification,
+    problem_report::{ProblemReportProgress, ProblemReportRequest},
     relay_constraints::{
-        BridgeSettings, BridgeState, ObfuscationSettings, RelayOverride, RelaySettings,
+        BridgeSettings, BridgeState, IpVersionPreference, ObfuscationSettings, RelayOverride,
+        RelaySettings, RemovedRelayPolicy,
     },
-    relay_list::RelayList,
-    settings::{DnsOptions, Settings},
-    states::TunnelState,
+    relay_list::{RelayLatency, RelayList, RelayListDiff},
+    runtime_config::RuntimeConfig,
+    settings::{
+        ConnectionCheckSettings, CustomApiEndpoint, DnsOptions, Settings, SettingsLockState,
+        TunnelProtocolFallback, WebhookSettings,
+    },
+    shutdown::ShutdownBehavior,
+    states::{ConnectEscalation, LockdownCountdown, PersistedTarget, ShutdownReport, TunnelState},
+    traffic::TrafficStats,
     version::AppVersionInfo,
-    wireguard::{PublicKey, QuantumResistantState, RotationInterval},
+    wireguard::{KeyRotationProgress, PublicKey, QuantumResistantState, RotationInterval},
 };
-use std::{path::Path, str::FromStr};
+use std::{net::SocketAddr, path::Path, str::FromStr, sync::OnceLock, time::Duration};
 #[cfg(target_os = "windows")]
 use talpid_types::split_tunnel::ExcludedProcess;
+use talpid_types::{
+    self_test::SelfTestStep,
+    tunnel::{ConnectProgress, ConnectTrace},
+};
 use tonic::{Code, Status};
 
 type Error = super::Error;
 
 pub type Result<T> = std::result::Result<T, super::Error>;
 
+/// Passphrase that new clients unlock the settings with. See
+/// [`MullvadProxyClient::unlock_new_clients`].
+static SETTINGS_PASSPHRASE: OnceLock<String> = OnceLock::new();
+
 #[derive(Debug, Clone)]
 pub struct MullvadProxyClient(crate::ManagementServiceClient);
 
 #[derive(Debug)]
 pub enum DaemonEvent {
     TunnelState(TunnelState),
-    Settings(Settings),
+    Settings(Box<Settings>),
     RelayList(RelayList),
+    /// The relays that changed in the last relay list update. Sent after [`DaemonEvent::RelayList`]
+    /// if any relay was added, removed or changed.
+    RelayListDiff(RelayListDiff),
     AppVersionInfo(AppVersionInfo),
     Device(DeviceEvent),
     RemoveDevice(RemoveDeviceEvent),
     NewAccessMethod(AccessMethodSetting),
+    ConnectionVerified(ConnectionVerification),
+    ConnectEscalation(ConnectEscalation),
+    ProblemReportProgress(ProblemReportProgress),
+    /// A phase of the current connection attempt was started or finished. Only sent while
+    /// connecting.
+    ConnectProgress(ConnectProgress),
+    /// The system clock differs from the clock of the API by more than a minute.
+    ClockSkewDetected(ClockSkew),
+    /// Lockdown mode is being enabled after a grace period. Sent every second until it ends.
+    LockdownCountdown(LockdownCountdown),
 }
 
 impl TryFrom<types::daemon_event::Event> for DaemonEvent {
@@ -50,11 +83,14 @@ impl TryFrom<types::daemon_event::Event> for DaemonEvent {
                 .map(DaemonEvent::TunnelState)
                 .map_err(Error::InvalidResponse),
             types::daemon_event::Event::Settings(settings) => Settings::try_from(settings)
-                .map(DaemonEvent::Settings)
+                .map(|settings| DaemonEvent::Settings(Box::new(settings)))
                 .map_err(Error::InvalidResponse),
             types::daemon_event::Event::RelayList(list) => RelayList::try_from(list)
                 .map(DaemonEvent::RelayList)
                 .map_err(Error::InvalidResponse),
+            types::daemon_event::Event::RelayListDiff(diff) => {
+                Ok(DaemonEvent::RelayListDiff(RelayListDiff::from(diff)))
+            }
             types::daemon_event::Event::VersionInfo(info) => {
                 Ok(DaemonEvent::AppVersionInfo(AppVersionInfo::from(info)))
             }
@@ -69,16 +105,68 @@ impl TryFrom<types::daemon_event::Event> for DaemonEvent {
                     .map(DaemonEvent::NewAccessMethod)
                     .map_err(Error::InvalidResponse)
             }
+            types::daemon_event::Event::ConnectionVerified(verification) => {
+                ConnectionVerification::try_from(verification)
+                    .map(DaemonEvent::ConnectionVerified)
+                    .map_err(Error::InvalidResponse)
+            }
+            types::daemon_event::Event::ConnectEscalation(escalation) => {
+                ConnectEscalation::try_from(escalation)
+                    .map(DaemonEvent::ConnectEscalation)
+                    .map_err(Error::InvalidResponse)
+            }
+            types::daemon_event::Event::ProblemReportProgress(progress) => {
+                ProblemReportProgress::try_from(progress)
+                    .map(DaemonEvent::ProblemReportProgress)
+                    .map_err(Error::InvalidResponse)
+            }
+            types::daemon_event::Event::ConnectProgress(progress) => {
+                ConnectProgress::try_from(progress)
+                    .map(DaemonEvent::ConnectProgress)
+                    .map_err(Error::InvalidResponse)
+            }
+            types::daemon_event::Event::ClockSkewDetected(skew) => {
+                Ok(DaemonEvent::ClockSkewDetected(ClockSkew::from(skew)))
+            }
+            types::daemon_event::Event::LockdownCountdown(countdown) => {
+                LockdownCountdown::try_from(countdown)
+                    .map(DaemonEvent::LockdownCountdown)
+                    .map_err(Error::InvalidResponse)
+            }
         }
     }
 }
 
 impl MullvadProxyClient {
+    /// Connect to the daemon. If [`crate::tcp::TCP_ADDRESS_ENV`] is set, the connection is made
+    /// over TCP and authenticated with the token in [`crate::tcp::TOKEN_ENV`]. Otherwise, the
+    /// Unix socket or named pipe is used.
     pub async fn new() -> Result<Self> {
+        let mut client = Self::connect().await?;
+        if let Some(passphrase) = SETTINGS_PASSPHRASE.get() {
+            client.unlock_settings(passphrase.clone()).await?;
+        }
+        Ok(client)
+    }
+
+    async fn connect() -> Result<Self> {
+        if let Ok(address) = std::env::var(crate::tcp::TCP_ADDRESS_ENV) {
+            let address = address.parse().map_err(Error::InvalidTcpAddress)?;
+            let token = std::env::var(crate::tcp::TOKEN_ENV).map_err(|_| Error::InvalidToken)?;
+            return super::new_tcp_rpc_client(address, token).await.map(Self);
+        }
         #[allow(deprecated)]
         super::new_rpc_client().await.map(Self)
     }
 
+    /// Unlock the settings with `passphrase` on every client that is created with
+    /// [`MullvadProxyClient::new`] from now on. Since the settings are only unlocked for the
+    /// connection that gave the passphrase, this lets a program that creates several clients
+    /// change locked settings. Only the first passphrase is used.
+    pub fn unlock_new_clients(passphrase: String) {
+        let _ = SETTINGS_PASSPHRASE.set(passphrase);
+    }
+
     pub fn from_rpc_client(client: crate::ManagementServiceClient) -> Self {
         Self(client)
     }
@@ -120,6 +208,59 @@ impl MullvadProxyClient {
         TunnelState::try_from(state).map_err(Error::InvalidResponse)
     }
 
+    /// Look up the location of the current tunnel state again. This returns before the location
+    /// has been looked up.
+    pub async fn refresh_location(&mut self) -> Result<()> {
+        self.0.refresh_location(()).await.map_err(Error::Rpc)?;
+        Ok(())
+    }
+
+    /// Returns how long each phase of the most recent successful connection attempt took, or
+    /// `None` if the daemon hasn't connected yet.
+    pub async fn get_connect_trace(&mut self) -> Result<Option<ConnectTrace>> {
+        let trace = match self.0.get_connect_trace(()).await {
+            Ok(trace) => trace.into_inner(),
+            Err(error) if error.code() == Code::NotFound => return Ok(None),
+            Err(error) => return Err(Error::Rpc(error)),
+        };
+        ConnectTrace::try_from(trace)
+            .map(Some)
+            .map_err(Error::InvalidResponse)
+    }
+
+    /// Returns a snapshot of the network configuration that was taken when the error state was
+    /// last entered, or `None` if it hasn't been entered since the daemon started.
+    pub async fn get_last_error_diagnostics(&mut self) -> Result<Option<String>> {
+        match self.0.get_last_error_diagnostics(()).await {
+            Ok(report) => Ok(Some(report.into_inner())),
+            Err(error) if error.code() == Code::NotFound => Ok(None),
+            Err(error) => Err(Error::Rpc(error)),
+        }
+    }
+
+    /// Returns the target state and the relay that the daemon would resume if it was restarted.
+    pub async fn get_persisted_target(&mut self) -> Result<PersistedTarget> {
+        let persisted = self
+            .0
+            .get_persisted_target(())
+            .await
+            .map_err(Error::Rpc)?
+            .into_inner();
+        PersistedTarget::try_from(persisted).map_err(Error::InvalidResponse)
+    }
+
+    /// Returns how restoring the network went when the daemon last shut down, if it has been
+    /// recorded.
+    pub async fn get_last_shutdown_report(&mut self) -> Result<Option<ShutdownReport>> {
+        match self.0.get_last_shutdown_report(()).await {
+            Ok(report) => ShutdownReport::try_from(report.into_inner())
+                .map(Some)
+                .map_err(Error::InvalidResponse),
+            Err(error) if error.code() == Code::NotFound => Ok(None),
+            Err(error) => Err(Error::Rpc(error)),
+        }
+    }
+
     pub async fn events_listen(&mut self) -> Result<impl Stream<Item = Result<DaemonEvent>>> {
         let listener = self
             .0
@@ -137,11 +278,34 @@ impl MullvadProxyClient {
         }))
     }
 
+    /// Listen for events that the user should be told about. Repeated and overly frequent
+    /// notifications are filtered out by the daemon.
+    pub async fn notifications(&mut self) -> Result<impl Stream<Item = Result<Notification>>> {
+        let listener = self
+            .0
+            .notifications(())
+            .await
+            .map_err(Error::Rpc)?
+            .into_inner();
+
+        Ok(listener.map(|item| {
+            Notification::try_from(item.map_err(Error::Rpc)?).map_err(Error::InvalidResponse)
+        }))
+    }
+
     pub async fn prepare_restart(&mut self) -> Result<()> {
         self.0.prepare_restart(()).await.map_err(Error::Rpc)?;
         Ok(())
     }
 
+    pub async fn shutdown(&mut self, behavior: ShutdownBehavior) -> Result<()> {
+        self.0
+            .shutdown(types::ShutdownBehavior::from(behavior))
+            .await
+            .map_err(Error::Rpc)?;
+        Ok(())
+    }
+
     pub async fn factory_reset(&mut self) -> Result<()> {
         self.0.factory_reset(()).await.map_err(Error::Rpc)?;
         Ok(())
@@ -166,6 +330,71 @@ impl MullvadProxyClient {
         Ok(AppVersionInfo::from(version_info))
     }
 
+    pub async fn get_daemon_health(&mut self) -> Result<DaemonHealth> {
+        let health = self
+            .0
+            .get_daemon_health(())
+            .await
+            .map_err(Error::Rpc)?
+            .into_inner();
+        DaemonHealth::try_from(health).map_err(Error::InvalidResponse)
+    }
+
+    /// Start uploading a problem report. The returned ID identifies the progress of the upload
+    /// in [`DaemonEvent::ProblemReportProgress`] events.
+    pub async fn send_problem_report(&mut self, request: ProblemReportRequest) -> Result<u32> {
+        Ok(self
+            .0
+            .send_problem_report(types::ProblemReportRequest::from(request))
+            .await
+            .map_err(Error::Rpc)?
+            .into_inner())
+    }
+
+    pub async fn get_settings_lock_state(&mut self) -> Result<SettingsLockState> {
+        let state = self
+            .0
+            .get_settings_lock_state(())
+            .await
+            .map_err(Error::Rpc)?
+            .into_inner();
+        SettingsLockState::try_from(state).map_err(Error::InvalidResponse)
+    }
+
+    /// Require `passphrase` to disconnect, to disable lockdown mode or to change the settings. If
+    /// the lock is already enabled, the settings must be unlocked to change the passphrase.
+    pub async fn enable_settings_lock(&mut self, passphrase: String) -> Result<()> {
+        self.0
+            .enable_settings_lock(passphrase)
+            .await
+            .map_err(Error::Rpc)?;
+        Ok(())
+    }
+
+    pub async fn disable_settings_lock(&mut self, passphrase: String) -> Result<()> {
+        self.0
+            .disable_settings_lock(passphrase)
+            .await
+            .map_err(Error::Rpc)?;
+        Ok(())
+    }
+
+    /// Unlock the settings for a few minutes. Only requests made by this client, or clones of it,
+    /// may change the settings while they are unlocked.
+    pub async fn unlock_settings(&mut self, passphrase: String) -> Result<()> {
+        self.0
+            .unlock_settings(passphrase)
+            .await
+            .map_err(Error::Rpc)?;
+        Ok(())
+    }
+
+    /// Lock the settings again for every client that has unlocked them.
+    pub async fn lock_settings(&mut self) -> Result<()> {
+        self.0.lock_settings(()).await.map_err(Error::Rpc)?;
+        Ok(())
+    }
+
     pub async fn get_relay_locations(&mut self) -> Result<RelayList> {
         let list = self
             .0
@@ -215,25 +444,43 @@ impl MullvadProxyClient {
             })
     }
 
-    pub async fn test_api_access_method(&mut self, id: access_method::Id) -> Result<bool> {
+    /// Return the address that the daemon currently uses to reach the API directly.
+    pub async fn get_api_address(&mut self) -> Result<SocketAddr> {
+        self.0
+            .get_api_address(())
+            .await
+            .map_err(Error::Rpc)?
+            .into_inner()
+            .parse()
+            .map_err(|_| {
+                Error::InvalidResponse(types::FromProtobufTypeError::InvalidArgument(
+                    "invalid API address",
+                ))
+            })
+    }
+
+    pub async fn test_api_access_method(
+        &mut self,
+        id: access_method::Id,
+    ) -> Result<access_method::TestResult> {
         let result = self
             .0
             .test_api_access_method_by_id(types::Uuid::from(id))
             .await
             .map_err(Error::Rpc)?;
-        Ok(result.into_inner())
+        access_method::TestResult::try_from(result.into_inner()).map_err(Error::InvalidResponse)
     }
 
     pub async fn test_custom_api_access_method(
         &mut self,
         config: talpid_types::net::proxy::CustomProxy,
-    ) -> Result<bool> {
+    ) -> Result<access_method::TestResult> {
         let result = self
             .0
             .test_custom_api_access_method(types::CustomProxy::from(config))
             .await
             .map_err(Error::Rpc)?;
-        Ok(result.into_inner())
+        access_method::TestResult::try_from(result.into_inner()).map_err(Error::InvalidResponse)
     }
 
     pub async fn update_relay_locations(&mut self) -> Result<()> {
@@ -244,6 +491,19 @@ impl MullvadProxyClient {
         Ok(())
     }
 
+    /// Replace the relay list with one that was obtained out-of-band. It is only accepted if
+    /// `signature` is a valid signature of `relay_list` made by Mullvad.
+    pub async fn import_relay_list(&mut self, relay_list: String, signature: String) -> Result<()> {
+        self.0
+            .import_relay_list(types::SignedRelayList {
+                relay_list,
+                signature,
+            })
+            .await
+            .map_err(Error::Rpc)?;
+        Ok(())
+    }
+
     pub async fn set_relay_settings(&mut self, update: RelaySettings) -> Result<()> {
         let update = types::RelaySettings::from(update);
         self.0
@@ -268,6 +528,27 @@ impl MullvadProxyClient {
         Ok(())
     }
 
+    pub async fn set_removed_relay_policy(&mut self, policy: RemovedRelayPolicy) -> Result<()> {
+        let policy = types::RemovedRelayPolicy::from(policy);
+        self.0
+            .set_removed_relay_policy(policy)
+            .await
+            .map_err(Error::Rpc)?;
+        Ok(())
+    }
+
+    pub async fn set_ip_version_preference(
+        &mut self,
+        preference: IpVersionPreference,
+    ) -> Result<()> {
+        let preference = types::IpVersionPreference::from(preference);
+        self.0
+            .set_ip_version_preference(preference)
+            .await
+            .map_err(Error::Rpc)?;
+        Ok(())
+    }
+
     pub async fn set_obfuscation_settings(&mut self, settings: ObfuscationSettings) -> Result<()> {
         let settings = types::ObfuscationSettings::from(&settings);
         self.0
@@ -277,6 +558,33 @@ impl MullvadProxyClient {
         Ok(())
     }
 
+    pub async fn export_openvpn_config(&mut self) -> Result<String> {
+        Ok(self
+            .0
+            .export_openvpn_config(())
+            .await
+            .map_err(Error::Rpc)?
+            .into_inner())
+    }
+
+    /// Measure the latency to every active WireGuard relay, or to those in `country`. The stream
+    /// yields each result as soon as it is known and ends once all relays have been pinged.
+    pub async fn ping_relays(
+        &mut self,
+        country: Option<String>,
+    ) -> Result<impl Stream<Item = Result<RelayLatency>>> {
+        let latencies = self
+            .0
+            .ping_relays(types::PingRelaysRequest { country })
+            .await
+            .map_err(Error::Rpc)?
+            .into_inner();
+
+        Ok(latencies.map(|item| {
+            RelayLatency::try_from(item.map_err(Error::Rpc)?).map_err(Error::InvalidResponse)
+        }))
+    }
+
     pub async fn get_settings(&mut self) -> Result<Settings> {
         let settings = self
             .0
@@ -300,6 +608,64 @@ impl MullvadProxyClient {
         Ok(())
     }
 
+    /// Post tunnel state changes to `webhook`.
+    pub async fn set_webhook(&mut self, webhook: WebhookSettings) -> Result<()> {
+        self.0
+            .set_webhook(types::WebhookSettings::from(webhook))
+            .await
+            .map_err(Error::Rpc)?;
+        Ok(())
+    }
+
+    pub async fn clear_webhook(&mut self) -> Result<()> {
+        self.0.clear_webhook(()).await.map_err(Error::Rpc)?;
+        Ok(())
+    }
+
+    /// Announce the tunnel state on the local network while local network sharing is allowed.
+    pub async fn set_lan_beacon(&mut self, enabled: bool) -> Result<()> {
+        self.0.set_lan_beacon(enabled).await.map_err(Error::Rpc)?;
+        Ok(())
+    }
+
+    pub async fn set_connection_check_settings(
+        &mut self,
+        settings: ConnectionCheckSettings,
+    ) -> Result<()> {
+        self.0
+            .set_connection_check_settings(types::ConnectionCheckSettings::from(settings))
+            .await
+            .map_err(Error::Rpc)?;
+        Ok(())
+    }
+
+    pub async fn set_tunnel_protocol_fallback(
+        &mut self,
+        fallback: TunnelProtocolFallback,
+    ) -> Result<()> {
+        self.0
+            .set_tunnel_protocol_fallback(types::TunnelProtocolFallback::from(fallback))
+            .await
+            .map_err(Error::Rpc)?;
+        Ok(())
+    }
+
+    pub async fn set_custom_api_endpoint(&mut self, endpoint: CustomApiEndpoint) -> Result<()> {
+        self.0
+            .set_custom_api_endpoint(types::CustomApiEndpoint::from(endpoint))
+            .await
+            .map_err(Error::Rpc)?;
+        Ok(())
+    }
+
+    pub async fn clear_custom_api_endpoint(&mut self) -> Result<()> {
+        self.0
+            .clear_custom_api_endpoint(())
+            .await
+            .map_err(Error::Rpc)?;
+        Ok(())
+    }
+
     pub async fn set_block_when_disconnected(&mut self, state: bool) -> Result<()> {
         self.0
             .set_block_when_disconnected(state)
@@ -308,6 +674,20 @@ impl MullvadProxyClient {
         Ok(())
     }
 
+    /// Enable lockdown mode once `grace_period` has passed, if the tunnel is disconnected.
+    /// Otherwise, it is enabled right away.
+    pub async fn enable_block_when_disconnected_after(
+        &mut self,
+        grace_period: Duration,
+    ) -> Result<()> {
+        let seconds = u32::try_from(grace_period.as_secs()).unwrap_or(u32::MAX);
+        self.0
+            .enable_block_when_disconnected_after(seconds)
+            .await
+            .map_err(Error::Rpc)?;
+        Ok(())
+    }
+
     pub async fn set_auto_connect(&mut self, state: bool) -> Result<()> {
         self.0.set_auto_connect(state).await.map_err(Error::Rpc)?;
         Ok(())
@@ -321,6 +701,36 @@ impl MullvadProxyClient {
         Ok(())
     }
 
+    /// Set the number of seconds to wait for a response from OpenVPN servers. `None` uses a
+    /// default that depends on the transport protocol.
+    pub async fn set_openvpn_connect_timeout(&mut self, timeout: Option<u16>) -> Result<()> {
+        self.0
+            .set_openvpn_connect_timeout(timeout.map(u32::from).unwrap_or(0))
+            .await
+            .map_err(Error::Rpc)?;
+        Ok(())
+    }
+
+    /// Set the number of seconds to wait before resending an unacknowledged control packet to
+    /// OpenVPN servers. `None` uses a default that depends on the transport protocol.
+    pub async fn set_openvpn_retry_timeout(&mut self, timeout: Option<u16>) -> Result<()> {
+        self.0
+            .set_openvpn_retry_timeout(timeout.map(u32::from).unwrap_or(0))
+            .await
+            .map_err(Error::Rpc)?;
+        Ok(())
+    }
+
+    /// Enable or disable the seccomp filter that confines the OpenVPN process.
+    #[cfg(target_os = "linux")]
+    pub async fn set_openvpn_sandbox(&mut self, enabled: bool) -> Result<()> {
+        self.0
+            .set_openvpn_sandbox(enabled)
+            .await
+            .map_err(Error::Rpc)?;
+        Ok(())
+    }
+
     pub async fn set_wireguard_mtu(&mut self, mtu: Option<u16>) -> Result<()> {
         self.0
             .set_wireguard_mtu(mtu.map(u32::from).unwrap_or(0))
@@ -334,6 +744,16 @@ impl MullvadProxyClient {
         Ok(())
     }
 
+    /// Set how many seconds to wait for a connection attempt before moving on to the next
+    /// transport. `None` disables the deadline.
+    pub async fn set_connect_deadline(&mut self, deadline: Option<u16>) -> Result<()> {
+        self.0
+            .set_connect_deadline(deadline.map(u32::from).unwrap_or(0))
+            .await
+            .map_err(Error::Rpc)?;
+        Ok(())
+    }
+
     pub async fn set_quantum_resistant_tunnel(
         &mut self,
         state: QuantumResistantState,
@@ -356,6 +776,34 @@ impl MullvadProxyClient {
         Ok(())
     }
 
+    /// Route only `allowed_ips` through the tunnel. `None` routes all traffic through it.
+    #[cfg(target_os = "linux")]
+    pub async fn set_wireguard_allowed_ips(
+        &mut self,
+        allowed_ips: Option<Vec<ipnetwork::IpNetwork>>,
+    ) -> Result<()> {
+        let allowed_ips = types::WireguardAllowedIps::from(allowed_ips.as_deref());
+        self.0
+            .set_wireguard_allowed_ips(allowed_ips)
+            .await
+            .map_err(Error::Rpc)?;
+        Ok(())
+    }
+
+    /// Reach the relays through the interface named `interface`. `None` uses the interface of the
+    /// default route.
+    #[cfg(target_os = "linux")]
+    pub async fn set_wireguard_egress_interface(
+        &mut self,
+        interface: Option<String>,
+    ) -> Result<()> {
+        self.0
+            .set_wireguard_egress_interface(interface.unwrap_or_default())
+            .await
+            .map_err(Error::Rpc)?;
+        Ok(())
+    }
+
     pub async fn set_dns_options(&mut self, options: DnsOptions) -> Result<()> {
         let options = types::DnsOptions::from(&options);
         self.0.set_dns_options(options).await.map_err(Error::Rpc)?;
@@ -385,17 +833,38 @@ impl MullvadProxyClient {
             .create_new_account(())
             .await
             .map_err(map_device_error)?
-            .into_inner())
+            .into_inner()
+            .into())
     }
 
     pub async fn login_account(&mut self, account: AccountToken) -> Result<()> {
         self.0
-            .login_account(account)
+            .login_account(account.expose().clone())
             .await
             .map_err(map_device_error)?;
         Ok(())
     }
 
+    /// Log in and create a device. If the account has too many devices, no device is created and
+    /// the devices of the account are returned. Pass the ID of one of them as `revoke_device` to
+    /// remove it before logging in.
+    pub async fn login_and_register_device(
+        &mut self,
+        account: AccountToken,
+        revoke_device: Option<DeviceId>,
+    ) -> Result<LoginOutcome> {
+        let outcome = self
+            .0
+            .login_and_register_device(types::LoginRequest {
+                account_token: account.expose().clone(),
+                revoke_device_id: revoke_device,
+            })
+            .await
+            .map_err(map_device_error)?
+            .into_inner();
+        LoginOutcome::try_from(outcome).map_err(Error::InvalidResponse)
+    }
+
     pub async fn logout_account(&mut self) -> Result<()> {
         self.0.logout_account(()).await.map_err(Error::Rpc)?;
         Ok(())
@@ -404,7 +873,7 @@ impl MullvadProxyClient {
     pub async fn get_account_data(&mut self, account: AccountToken) -> Result<AccountData> {
         let data = self
             .0
-            .get_account_data(account)
+            .get_account_data(account.expose().clone())
             .await
             .map_err(Error::Rpc)?
             .into_inner();
@@ -418,7 +887,7 @@ impl MullvadProxyClient {
             .await
             .map_err(Error::Rpc)?
             .into_inner();
-        Ok(history.token)
+        Ok(history.token.map(AccountToken::from))
     }
 
     pub async fn clear_account_history(&mut self) -> Result<()> {
@@ -460,7 +929,7 @@ impl MullvadProxyClient {
     pub async fn list_devices(&mut self, account: AccountToken) -> Result<Vec<Device>> {
         let list = self
             .0
-            .list_devices(account)
+            .list_devices(account.expose().clone())
             .await
             .map_err(map_device_error)?
             .into_inner();
@@ -477,7 +946,7 @@ impl MullvadProxyClient {
     ) -> Result<()> {
         self.0
             .remove_device(types::DeviceRemoval {
-                account_token: account,
+                account_token: account.expose().clone(),
                 device_id,
             })
             .await
@@ -511,6 +980,23 @@ impl MullvadProxyClient {
         Ok(())
     }
 
+    /// Rotate the WireGuard key and wait for it to be replaced. The stream yields each step of
+    /// the rotation and ends once it is done.
+    pub async fn rotate_wireguard_key_now(
+        &mut self,
+    ) -> Result<impl Stream<Item = Result<KeyRotationProgress>>> {
+        let progress = self
+            .0
+            .rotate_wireguard_key_now(())
+            .await
+            .map_err(Error::Rpc)?
+            .into_inner();
+
+        Ok(progress.map(|item| {
+            KeyRotationProgress::try_from(item.map_err(Error::Rpc)?).map_err(Error::InvalidResponse)
+        }))
+    }
+
     pub async fn get_wireguard_key(&mut self) -> Result<PublicKey> {
         let key = self
             .0
@@ -556,6 +1042,24 @@ impl MullvadProxyClient {
         Ok(())
     }
 
+    /// Save the current relay, obfuscation and DNS settings as a profile named `name`. An
+    /// existing profile with the same name is replaced.
+    pub async fn save_profile(&mut self, name: String) -> Result<()> {
+        self.0.save_profile(name).await.map_err(Error::Rpc)?;
+        Ok(())
+    }
+
+    /// Replace the relay, obfuscation and DNS settings with the ones in the profile `name`.
+    pub async fn use_profile(&mut self, name: String) -> Result<()> {
+        self.0.use_profile(name).await.map_err(Error::Rpc)?;
+        Ok(())
+    }
+
+    pub async fn delete_profile(&mut self, name: String) -> Result<()> {
+        self.0.delete_profile(name).await.map_err(Error::Rpc)?;
+        Ok(())
+    }
+
     pub async fn add_access_method(
         &mut self,
         name: String,
@@ -706,10 +1210,115 @@ impl MullvadProxyClient {
         Ok(())
     }
 
+    pub async fn apply_settings(&mut self, document: String, dry_run: bool) -> Result<()> {
+        self.0
+            .apply_settings(types::ApplySettingsRequest { document, dry_run })
+            .await
+            .map_err(Error::Rpc)?;
+        Ok(())
+    }
+
     pub async fn export_json_settings(&mut self) -> Result<String> {
         let blob = self.0.export_json_settings(()).await.map_err(Error::Rpc)?;
         Ok(blob.into_inner())
     }
+
+    pub async fn get_traffic_stats(&mut self) -> Result<TrafficStats> {
+        let stats = self
+            .0
+            .get_traffic_stats(())
+            .await
+            .map_err(Error::Rpc)?
+            .into_inner();
+        Ok(TrafficStats::from(stats))
+    }
+
+    /// Return the environment variables that override the behavior of the daemon.
+    pub async fn get_runtime_config(&mut self) -> Result<RuntimeConfig> {
+        let config = self
+            .0
+            .get_runtime_config(())
+            .await
+            .map_err(Error::Rpc)?
+            .into_inner();
+        Ok(RuntimeConfig::from(config))
+    }
+
+    /// Log `modules` at trace level for `duration`, and return the redacted output. All modules
+    /// are captured if `modules` is empty.
+    pub async fn capture_debug_log(
+        &mut self,
+        modules: Vec<String>,
+        duration: Duration,
+    ) -> Result<String> {
+        let duration = types::Duration::try_from(duration).map_err(|_| Error::DurationTooLarge)?;
+        let request = types::DebugLogCaptureRequest {
+            modules,
+            duration: Some(duration),
+        };
+        Ok(self
+            .0
+            .capture_debug_log(request)
+            .await
+            .map_err(Error::Rpc)?
+            .into_inner())
+    }
+
+    /// Remove firewall filters left behind by older versions of the app. Returns the number of
+    /// removed filters.
+    pub async fn cleanup_firewall(&mut self) -> Result<u32> {
+        Ok(self
+            .0
+            .cleanup_firewall(())
+            .await
+            .map_err(Error::Rpc)?
+            .into_inner())
+    }
+
+    /// Return the firewall rules that are currently loaded by the daemon.
+    pub async fn get_firewall_rules(&mut self) -> Result<String> {
+        Ok(self
+            .0
+            .get_firewall_rules(())
+            .await
+            .map_err(Error::Rpc)?
+            .into_inner())
+    }
+
+    /// Return whether traffic is blocked during early boot, before the daemon has started.
+    pub async fn is_early_boot_blocking_installed(&mut self) -> Result<bool> {
+        Ok(self
+            .0
+            .is_early_boot_blocking_installed(())
+            .await
+            .map_err(Error::Rpc)?
+            .into_inner())
+    }
+
+    /// Test the platform integration that tunnels depend on, and return the outcome of each step.
+    pub async fn run_self_test(&mut self) -> Result<Vec<SelfTestStep>> {
+        self.0
+            .run_self_test(())
+            .await
+            .map_err(Error::Rpc)?
+            .into_inner()
+            .steps
+            .into_iter()
+            .map(|step| SelfTestStep::try_from(step).map_err(Error::InvalidResponse))
+            .collect()
+    }
+
+    /// Probe the network outside of any tunnel, and return the likely reasons that connecting
+    /// fails, most actionable first.
+    pub async fn diagnose_connection(&mut self) -> Result<Vec<BlockedReason>> {
+        let diagnosis = self
+            .0
+            .diagnose_connection(())
+            .await
+            .map_err(Error::Rpc)?
+            .into_inner();
+        Vec::try_from(diagnosis).map_err(Error::InvalidResponse)
+    }
 }
 
 fn map_device_error(status: Status) -> Error {
@@ -0,0 +1,102 @@
+//! Stable error codes that let clients branch on failures without matching on error messages.
+
+use crate::types::ErrorCode;
+use tonic::{metadata::MetadataValue, Code, Status};
+
+/// Metadata key of the entry that holds the name of the [`ErrorCode`] of a failed RPC.
+pub const ERROR_CODE_METADATA_KEY: &str = "mullvad-error-code";
+
+/// Extension trait for attaching an [`ErrorCode`] to a [`Status`] and reading it back.
+pub trait StatusExt {
+    /// Attach `error_code` to the status.
+    fn with_error_code(self, error_code: ErrorCode) -> Self;
+
+    /// Return the error code attached to the status. If there is none, the code is derived from
+    /// the gRPC status code.
+    fn error_code(&self) -> ErrorCode;
+}
+
+impl StatusExt for Status {
+    fn with_error_code(mut self, error_code: ErrorCode) -> Self {
+        self.metadata_mut().insert(
+            ERROR_CODE_METADATA_KEY,
+            MetadataValue::from_static(error_code.as_str_name()),
+        );
+        self
+    }
+
+    fn error_code(&self) -> ErrorCode {
+        self.metadata()
+            .get(ERROR_CODE_METADATA_KEY)
+            .and_then(|value| value.to_str().ok())
+            .and_then(ErrorCode::from_str_name)
+            .unwrap_or_else(|| ErrorCode::from(self.code()))
+    }
+}
+
+impl From<Code> for ErrorCode {
+    fn from(code: Code) -> Self {
+        match code {
+            Code::InvalidArgument | Code::OutOfRange => ErrorCode::InvalidArgument,
+            Code::NotFound => ErrorCode::NotFound,
+            Code::AlreadyExists => ErrorCode::AlreadyExists,
+            Code::DeadlineExceeded => ErrorCode::TimedOut,
+            Code::Internal | Code::DataLoss => ErrorCode::InternalError,
+            Code::Unimplemented => ErrorCode::NotSupported,
+            Code::Unavailable => ErrorCode::DaemonUnavailable,
+            _ => ErrorCode::UnknownError,
+        }
+    }
+}
+
+impl From<&talpid_types::tunnel::ErrorStateCause> for ErrorCode {
+    fn from(cause: &talpid_types::tunnel::ErrorStateCause) -> Self {
+        use talpid_types::tunnel::{ErrorStateCause, ParameterGenerationError};
+
+        match cause {
+            ErrorStateCause::AuthFailed(_) => ErrorCode::AuthFailed,
+            ErrorStateCause::Ipv6Unavailable => ErrorCode::Ipv6Unavailable,
+            ErrorStateCause::SetFirewallPolicyError(_) => ErrorCode::SetFirewallPolicyFailed,
+            ErrorStateCause::SetDnsError { .. } => ErrorCode::SetDnsFailed,
+            #[cfg(target_os = "android")]
+            ErrorStateCause::InvalidDnsServers(_) => ErrorCode::InvalidDnsServers,
+            #[cfg(target_os = "windows")]
+            ErrorStateCause::CreateTunnelDevice { .. } => ErrorCode::CreateTunnelDeviceFailed,
+            ErrorStateCause::StartTunnelError => ErrorCode::StartTunnelFailed,
+            ErrorStateCause::TunnelParameterError(error) => match error {
+                ParameterGenerationError::NoMatchingRelay => ErrorCode::NoMatchingRelay,
+                ParameterGenerationError::NoMatchingBridgeRelay => ErrorCode::NoMatchingBridgeRelay,
+                ParameterGenerationError::NoWireguardKey => ErrorCode::NoWireguardKey,
+                ParameterGenerationError::CustomTunnelHostResultionError => {
+                    ErrorCode::CustomTunnelHostResolutionFailed
+                }
+            },
+            ErrorStateCause::IsOffline => ErrorCode::Offline,
+            #[cfg(target_os = "android")]
+            ErrorStateCause::VpnPermissionDenied => ErrorCode::VpnPermissionDenied,
+            #[cfg(any(target_os = "windows", target_os = "macos"))]
+            ErrorStateCause::SplitTunnelError => ErrorCode::SplitTunnelFailed,
+            #[cfg(target_os = "macos")]
+            ErrorStateCause::NeedFullDiskPermissions => ErrorCode::NeedFullDiskPermissions,
+            #[cfg(target_os = "linux")]
+            ErrorStateCause::UnsupportedDnsManager(_) => ErrorCode::UnsupportedDnsManager,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_attached_error_code() {
+        let status = Status::not_found("no such device").with_error_code(ErrorCode::DeviceNotFound);
+        assert_eq!(status.error_code(), ErrorCode::DeviceNotFound);
+    }
+
+    #[test]
+    fn test_fallback_error_code() {
+        let status = Status::not_found("no such thing");
+        assert_eq!(status.error_code(), ErrorCode::NotFound);
+    }
+}
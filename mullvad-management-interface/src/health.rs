@@ -0,0 +1,97 @@
+//! The standard gRPC health checking service, so that generic tooling can probe the daemon
+//! without knowing the management interface.
+
+use futures::{stream, Stream, StreamExt};
+use std::pin::Pin;
+use tonic::{Request, Response, Status};
+
+mod proto {
+    tonic::include_proto!("grpc.health.v1");
+}
+
+pub use proto::health_server::HealthServer;
+use proto::{
+    health_check_response::ServingStatus, health_server::Health, HealthCheckRequest,
+    HealthCheckResponse,
+};
+
+/// Services whose health can be checked. The empty name refers to the server as a whole.
+const SERVICES: &[&str] = &[
+    "",
+    "mullvad_daemon.management_interface.ManagementService",
+    "grpc.health.v1.Health",
+    "grpc.reflection.v1alpha.ServerReflection",
+];
+
+/// Reports every service as serving. The services are only unavailable when the server is not
+/// running, in which case the health check fails to connect.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HealthService;
+
+impl HealthService {
+    pub fn server() -> HealthServer<Self> {
+        HealthServer::new(Self)
+    }
+}
+
+fn status(service: &str) -> ServingStatus {
+    if SERVICES.contains(&service) {
+        ServingStatus::Serving
+    } else {
+        ServingStatus::ServiceUnknown
+    }
+}
+
+#[tonic::async_trait]
+impl Health for HealthService {
+    async fn check(
+        &self,
+        request: Request<HealthCheckRequest>,
+    ) -> Result<Response<HealthCheckResponse>, Status> {
+        match status(&request.get_ref().service) {
+            ServingStatus::ServiceUnknown => Err(Status::not_found("unknown service")),
+            status => Ok(Response::new(HealthCheckResponse {
+                status: i32::from(status),
+            })),
+        }
+    }
+
+    type WatchStream = Pin<Box<dyn Stream<Item = Result<HealthCheckResponse, Status>> + Send>>;
+
+    async fn watch(
+        &self,
+        request: Request<HealthCheckRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        let response = HealthCheckResponse {
+            status: i32::from(status(&request.get_ref().service)),
+        };
+        // The status never changes while the server is running, so it is only sent once
+        let updates = stream::once(async move { Ok(response) }).chain(stream::pending());
+        Ok(Response::new(Box::pin(updates)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_check() {
+        let check = |service: &str| {
+            HealthService.check(Request::new(HealthCheckRequest {
+                service: service.to_owned(),
+            }))
+        };
+        let response = check("").await.unwrap().into_inner();
+        assert_eq!(response.status(), ServingStatus::Serving);
+        let response = check("mullvad_daemon.management_interface.ManagementService")
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.status(), ServingStatus::Serving);
+        assert_eq!(
+            check("mullvad_daemon.Unknown").await.unwrap_err().code(),
+            tonic::Code::NotFound
+        );
+    }
+}
@@ -1,4 +1,8 @@
 pub mod client;
+pub mod error_code;
+pub mod health;
+pub mod reflection;
+pub mod tcp;
 pub mod types;
 
 use parity_tokio_ipc::Endpoint as IpcEndpoint;
@@ -7,10 +11,15 @@ use std::{env, fs, os::unix::fs::PermissionsExt};
 use std::{
     future::Future,
     io,
+    net::SocketAddr,
     pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
     task::{Context, Poll},
 };
-use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream},
+};
 use tonic::transport::{server::Connected, Endpoint, Server, Uri};
 use tower::service_fn;
 
@@ -38,6 +47,15 @@ pub enum Error {
     #[error("Failed to start IPC pipe/socket")]
     StartServerError(#[source] io::Error),
 
+    #[error("Failed to listen on {0}")]
+    StartTcpServerError(SocketAddr, #[source] io::Error),
+
+    #[error("Invalid management interface address in {}", tcp::TCP_ADDRESS_ENV)]
+    InvalidTcpAddress(#[source] std::net::AddrParseError),
+
+    #[error("{} must be set to a valid token to connect over TCP", tcp::TOKEN_ENV)]
+    InvalidToken,
+
     #[error("Failed to initialize pipe/socket security attributes")]
     SecurityAttributes(#[source] io::Error),
 
@@ -111,6 +129,29 @@ pub enum Error {
     ApiAccessMethodNotFound,
 }
 
+impl Error {
+    /// Return the [`types::ErrorCode`] that describes this error, if it was caused by a failed
+    /// RPC.
+    pub fn error_code(&self) -> Option<types::ErrorCode> {
+        use error_code::StatusExt;
+        use types::ErrorCode;
+
+        match self {
+            Error::Rpc(status) => Some(status.error_code()),
+            Error::InvalidVoucher => Some(ErrorCode::InvalidVoucher),
+            Error::UsedVoucher => Some(ErrorCode::UsedVoucher),
+            Error::TooManyDevices => Some(ErrorCode::TooManyDevices),
+            Error::AlreadyLoggedIn => Some(ErrorCode::AlreadyLoggedIn),
+            Error::InvalidAccount => Some(ErrorCode::InvalidAccount),
+            Error::DeviceNotFound => Some(ErrorCode::DeviceNotFound),
+            Error::CustomListExists => Some(ErrorCode::CustomListExists),
+            Error::CustomListListNotFound => Some(ErrorCode::CustomListNotFound),
+            Error::ApiAccessMethodNotFound => Some(ErrorCode::AccessMethodNotFound),
+            _ => None,
+        }
+    }
+}
+
 #[deprecated(note = "Prefer MullvadProxyClient")]
 pub async fn new_rpc_client() -> Result<ManagementServiceClient, Error> {
     let ipc_path = mullvad_paths::get_rpc_socket_path();
@@ -126,6 +167,31 @@ pub async fn new_rpc_client() -> Result<ManagementServiceClient, Error> {
     Ok(ManagementServiceClient::new(channel))
 }
 
+/// Connect to the management interface over TCP, and authenticate with `token`.
+pub async fn new_tcp_rpc_client(
+    address: SocketAddr,
+    token: String,
+) -> Result<ManagementServiceClient, Error> {
+    if !tcp::is_valid_token(&token) {
+        return Err(Error::InvalidToken);
+    }
+
+    // The URI will be ignored
+    let channel = Endpoint::from_static("http://[::]:50051")
+        .connect_with_connector(service_fn(move |_: Uri| {
+            let token = token.clone();
+            async move {
+                let mut stream = TcpStream::connect(address).await?;
+                tcp::send_token(&mut stream, &token).await?;
+                Ok::<_, io::Error>(stream)
+            }
+        }))
+        .await
+        .map_err(Error::GrpcTransportError)?;
+
+    Ok(ManagementServiceClient::new(channel))
+}
+
 pub use client::MullvadProxyClient;
 
 pub type ServerJoinHandle = tokio::task::JoinHandle<Result<(), Error>>;
@@ -161,19 +227,106 @@ pub fn spawn_rpc_server<T: ManagementService, F: Future<Output = ()> + Send + 's
     Ok(tokio::spawn(async move {
         Server::builder()
             .add_service(ManagementServiceServer::new(service))
-            .serve_with_incoming_shutdown(incoming.map_ok(StreamBox), abort_rx)
+            .add_service(health::HealthService::server())
+            .add_service(reflection::ReflectionService::server())
+            .serve_with_incoming_shutdown(incoming.map_ok(StreamBox::new), abort_rx)
             .await
             .map_err(Error::GrpcTransportError)
     }))
 }
 
+/// Serve the management interface on `address`, in addition to the Unix socket or named pipe.
+/// Clients must authenticate with `token` before they can make any calls.
+pub async fn spawn_tcp_rpc_server<T: ManagementService, F: Future<Output = ()> + Send + 'static>(
+    service: T,
+    address: SocketAddr,
+    token: String,
+    abort_rx: F,
+) -> std::result::Result<ServerJoinHandle, Error> {
+    let listener = TcpListener::bind(address)
+        .await
+        .map_err(|error| Error::StartTcpServerError(address, error))?;
+
+    let (incoming_tx, incoming_rx) = futures::channel::mpsc::unbounded();
+    let accept_task = tokio::spawn(async move {
+        loop {
+            let (mut stream, peer) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(error) => {
+                    log::error!("Failed to accept management interface connection: {error}");
+                    continue;
+                }
+            };
+            let token = token.clone();
+            let incoming_tx = incoming_tx.clone();
+            // Authenticate clients concurrently, so that a slow client can't hold up others.
+            tokio::spawn(async move {
+                match tcp::authenticate_client(&mut stream, &token).await {
+                    Ok(true) => {
+                        let _ =
+                            incoming_tx.unbounded_send(Ok::<_, io::Error>(StreamBox::new(stream)));
+                    }
+                    Ok(false) => {
+                        log::warn!("Rejected management interface client {peer}: invalid token")
+                    }
+                    Err(error) => {
+                        log::warn!(
+                            "Failed to authenticate management interface client {peer}: {error}"
+                        )
+                    }
+                }
+            });
+        }
+    });
+
+    Ok(tokio::spawn(async move {
+        let result = Server::builder()
+            .add_service(ManagementServiceServer::new(service))
+            .add_service(health::HealthService::server())
+            .add_service(reflection::ReflectionService::server())
+            .serve_with_incoming_shutdown(incoming_rx, abort_rx)
+            .await
+            .map_err(Error::GrpcTransportError);
+        accept_task.abort();
+        result
+    }))
+}
+
+/// Identifies a connection to the management interface. Every request that the server receives
+/// carries the connection it was received on, which can be read with [`ConnectionId::of`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectionId(u64);
+
+impl ConnectionId {
+    fn next() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        ConnectionId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Returns the connection that `request` was received on.
+    pub fn of<T>(request: &Request<T>) -> Option<Self> {
+        request.extensions().get::<Self>().copied()
+    }
+}
+
 #[derive(Debug)]
-struct StreamBox<T: AsyncRead + AsyncWrite>(pub T);
+struct StreamBox<T: AsyncRead + AsyncWrite> {
+    stream: T,
+    id: ConnectionId,
+}
+impl<T: AsyncRead + AsyncWrite> StreamBox<T> {
+    fn new(stream: T) -> Self {
+        StreamBox {
+            stream,
+            id: ConnectionId::next(),
+        }
+    }
+}
 impl<T: AsyncRead + AsyncWrite> Connected for StreamBox<T> {
-    type ConnectInfo = Option<()>;
+    type ConnectInfo = ConnectionId;
 
     fn connect_info(&self) -> Self::ConnectInfo {
-        None
+        self.id
     }
 }
 impl<T: AsyncRead + AsyncWrite + Unpin> AsyncRead for StreamBox<T> {
@@ -182,7 +335,7 @@ impl<T: AsyncRead + AsyncWrite + Unpin> AsyncRead for StreamBox<T> {
         cx: &mut Context<'_>,
         buf: &mut ReadBuf<'_>,
     ) -> Poll<io::Result<()>> {
-        Pin::new(&mut self.0).poll_read(cx, buf)
+        Pin::new(&mut self.stream).poll_read(cx, buf)
     }
 }
 impl<T: AsyncRead + AsyncWrite + Unpin> AsyncWrite for StreamBox<T> {
@@ -191,14 +344,14 @@ impl<T: AsyncRead + AsyncWrite + Unpin> AsyncWrite for StreamBox<T> {
         cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
-        Pin::new(&mut self.0).poll_write(cx, buf)
+        Pin::new(&mut self.stream).poll_write(cx, buf)
     }
 
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        Pin::new(&mut self.0).poll_flush(cx)
+        Pin::new(&mut self.stream).poll_flush(cx)
     }
 
     fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        Pin::new(&mut self.0).poll_shutdown(cx)
+        Pin::new(&mut self.stream).poll_shutdown(cx)
     }
 }
@@ -0,0 +1,242 @@
+//! The standard gRPC server reflection service, so that generic tooling such as `grpcurl` can
+//! list and call the methods of the management interface without its proto files.
+
+use futures::{Stream, TryStreamExt};
+use prost::Message;
+use prost_types::{DescriptorProto, FileDescriptorProto, FileDescriptorSet};
+use std::{collections::HashMap, pin::Pin, sync::Arc};
+use tonic::{Code, Request, Response, Status, Streaming};
+
+mod proto {
+    tonic::include_proto!("grpc.reflection.v1alpha");
+}
+
+pub use proto::server_reflection_server::ServerReflectionServer;
+use proto::{
+    server_reflection_request::MessageRequest, server_reflection_response::MessageResponse,
+    server_reflection_server::ServerReflection, ErrorResponse, FileDescriptorResponse,
+    ListServiceResponse, ServerReflectionRequest, ServerReflectionResponse, ServiceResponse,
+};
+
+/// Descriptors of every proto file that is served, including the files that they import.
+const FILE_DESCRIPTOR_SET: &[u8] =
+    tonic::include_file_descriptor_set!("management_interface_descriptor");
+
+/// Describes the management interface, and the health and reflection services.
+#[derive(Debug, Clone)]
+pub struct ReflectionService {
+    files: Arc<Files>,
+}
+
+#[derive(Debug)]
+struct Files {
+    by_name: HashMap<String, FileDescriptorProto>,
+    /// Name of the file that declares each fully qualified symbol.
+    symbols: HashMap<String, String>,
+    services: Vec<String>,
+}
+
+impl ReflectionService {
+    pub fn server() -> ServerReflectionServer<Self> {
+        ServerReflectionServer::new(Self::new())
+    }
+
+    fn new() -> Self {
+        let set = FileDescriptorSet::decode(FILE_DESCRIPTOR_SET)
+            .expect("file descriptor set is generated by the build script");
+        let mut files = Files {
+            by_name: HashMap::new(),
+            symbols: HashMap::new(),
+            services: vec![],
+        };
+        for file in set.file {
+            let name = file.name().to_owned();
+            let mut add_symbol = |symbol: String| {
+                files.symbols.insert(symbol, name.clone());
+            };
+            let prefix = match file.package() {
+                "" => String::new(),
+                package => format!("{package}."),
+            };
+            for service in &file.service {
+                let service_name = format!("{prefix}{}", service.name());
+                for method in &service.method {
+                    add_symbol(format!("{service_name}.{}", method.name()));
+                }
+                add_symbol(service_name.clone());
+                files.services.push(service_name);
+            }
+            for message in &file.message_type {
+                add_message_symbols(&prefix, message, &mut add_symbol);
+            }
+            for enumeration in &file.enum_type {
+                add_symbol(format!("{prefix}{}", enumeration.name()));
+            }
+            files.by_name.insert(name, file);
+        }
+        Self {
+            files: Arc::new(files),
+        }
+    }
+}
+
+/// Add the names of `message` and of the messages and enums that are nested in it.
+fn add_message_symbols(prefix: &str, message: &DescriptorProto, add: &mut impl FnMut(String)) {
+    let name = format!("{prefix}{}", message.name());
+    let nested_prefix = format!("{name}.");
+    for nested in &message.nested_type {
+        add_message_symbols(&nested_prefix, nested, add);
+    }
+    for enumeration in &message.enum_type {
+        add(format!("{nested_prefix}{}", enumeration.name()));
+    }
+    add(name);
+}
+
+impl Files {
+    fn respond(&self, request: ServerReflectionRequest) -> ServerReflectionResponse {
+        let response = match &request.message_request {
+            Some(MessageRequest::ListServices(_)) => {
+                MessageResponse::ListServicesResponse(ListServiceResponse {
+                    service: self
+                        .services
+                        .iter()
+                        .map(|name| ServiceResponse { name: name.clone() })
+                        .collect(),
+                })
+            }
+            Some(MessageRequest::FileByFilename(name)) => self.file_response(name),
+            Some(MessageRequest::FileContainingSymbol(symbol)) => match self.symbols.get(symbol) {
+                Some(name) => self.file_response(name),
+                None => error_response(Code::NotFound, format!("Unknown symbol: {symbol}")),
+            },
+            // Extensions are not used by proto3
+            Some(MessageRequest::FileContainingExtension(_))
+            | Some(MessageRequest::AllExtensionNumbersOfType(_)) => {
+                error_response(Code::NotFound, "Extensions are not supported".to_owned())
+            }
+            None => error_response(Code::InvalidArgument, "Empty request".to_owned()),
+        };
+        ServerReflectionResponse {
+            valid_host: request.host.clone(),
+            original_request: Some(request),
+            message_response: Some(response),
+        }
+    }
+
+    /// Returns the file called `name`, followed by every file that it imports, directly or
+    /// indirectly.
+    fn file_response(&self, name: &str) -> MessageResponse {
+        if !self.by_name.contains_key(name) {
+            return error_response(Code::NotFound, format!("Unknown file: {name}"));
+        }
+        let mut names = vec![name];
+        let mut index = 0;
+        while let Some(file) = names.get(index).and_then(|name| self.by_name.get(*name)) {
+            for dependency in &file.dependency {
+                if !names.contains(&dependency.as_str()) {
+                    names.push(dependency);
+                }
+            }
+            index += 1;
+        }
+        MessageResponse::FileDescriptorResponse(FileDescriptorResponse {
+            file_descriptor_proto: names
+                .into_iter()
+                .filter_map(|name| self.by_name.get(name))
+                .map(Message::encode_to_vec)
+                .collect(),
+        })
+    }
+}
+
+fn error_response(code: Code, error_message: String) -> MessageResponse {
+    MessageResponse::ErrorResponse(ErrorResponse {
+        error_code: code as i32,
+        error_message,
+    })
+}
+
+#[tonic::async_trait]
+impl ServerReflection for ReflectionService {
+    type ServerReflectionInfoStream =
+        Pin<Box<dyn Stream<Item = Result<ServerReflectionResponse, Status>> + Send>>;
+
+    async fn server_reflection_info(
+        &self,
+        request: Request<Streaming<ServerReflectionRequest>>,
+    ) -> Result<Response<Self::ServerReflectionInfoStream>, Status> {
+        let files = self.files.clone();
+        let responses = request
+            .into_inner()
+            .map_ok(move |request| files.respond(request));
+        Ok(Response::new(Box::pin(responses)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn respond(request: MessageRequest) -> MessageResponse {
+        ReflectionService::new()
+            .files
+            .respond(ServerReflectionRequest {
+                host: String::new(),
+                message_request: Some(request),
+            })
+            .message_response
+            .unwrap()
+    }
+
+    fn file_names(response: MessageResponse) -> Vec<String> {
+        let MessageResponse::FileDescriptorResponse(response) = response else {
+            panic!("Unexpected response: {response:?}");
+        };
+        response
+            .file_descriptor_proto
+            .iter()
+            .map(|file| {
+                FileDescriptorProto::decode(&file[..])
+                    .unwrap()
+                    .name()
+                    .to_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_list_services() {
+        let MessageResponse::ListServicesResponse(response) =
+            respond(MessageRequest::ListServices(String::new()))
+        else {
+            panic!("Unexpected response");
+        };
+        let services: Vec<_> = response.service.into_iter().map(|s| s.name).collect();
+        assert!(services.contains(&"mullvad_daemon.management_interface.ManagementService".into()));
+        assert!(services.contains(&"grpc.health.v1.Health".into()));
+    }
+
+    #[test]
+    fn test_file_containing_symbol() {
+        for symbol in [
+            "mullvad_daemon.management_interface.ManagementService",
+            "mullvad_daemon.management_interface.ManagementService.GetTunnelState",
+            "mullvad_daemon.management_interface.TunnelState",
+            "mullvad_daemon.management_interface.TunnelType",
+        ] {
+            let names = file_names(respond(MessageRequest::FileContainingSymbol(
+                symbol.to_owned(),
+            )));
+            assert_eq!(names[0], "management_interface.proto", "{symbol}");
+            assert!(names.contains(&"google/protobuf/empty.proto".to_owned()));
+        }
+
+        assert!(matches!(
+            respond(MessageRequest::FileContainingSymbol(
+                "mullvad_daemon.Unknown".to_owned()
+            )),
+            MessageResponse::ErrorResponse(_)
+        ));
+    }
+}
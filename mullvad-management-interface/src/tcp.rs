@@ -0,0 +1,74 @@
+//! Authentication of management interface connections made over TCP. Unlike the Unix socket or
+//! named pipe, a TCP port on localhost can be reached by every process on the machine, and from
+//! containers that share its network. Clients must therefore start every connection by sending a
+//! token that is only known to the daemon and to the users that it has been shared with.
+
+use std::{io, time::Duration};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Environment variable that holds the address of the TCP listener. If it is set, clients connect
+/// to it instead of the Unix socket or named pipe.
+pub const TCP_ADDRESS_ENV: &str = "MULLVAD_MANAGEMENT_TCP_ADDRESS";
+
+/// Environment variable that holds the token that clients authenticate with.
+pub const TOKEN_ENV: &str = "MULLVAD_MANAGEMENT_TOKEN";
+
+/// Number of characters in a token.
+pub const TOKEN_LEN: usize = 64;
+
+/// How long the server waits for a client to send its token.
+const AUTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Returns whether `token` has the expected format: [`TOKEN_LEN`] hex digits.
+pub fn is_valid_token(token: &str) -> bool {
+    token.len() == TOKEN_LEN && token.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// Sends `token` to the server. This must be done before anything else is sent.
+pub async fn send_token<S: AsyncWrite + Unpin>(stream: &mut S, token: &str) -> io::Result<()> {
+    stream.write_all(token.as_bytes()).await?;
+    stream.write_all(b"\n").await?;
+    stream.flush().await
+}
+
+/// Reads a token from a newly connected client, and returns whether it matches `token`.
+pub async fn authenticate_client<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    token: &str,
+) -> io::Result<bool> {
+    let mut received = [0u8; TOKEN_LEN + 1];
+    tokio::time::timeout(AUTH_TIMEOUT, stream.read_exact(&mut received))
+        .await
+        .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+
+    let expected = token.as_bytes();
+    if expected.len() != TOKEN_LEN || received[TOKEN_LEN] != b'\n' {
+        return Ok(false);
+    }
+    // Compare every byte, so that the time taken doesn't reveal how much of the token matched.
+    let difference = received[..TOKEN_LEN]
+        .iter()
+        .zip(expected)
+        .fold(0u8, |acc, (a, b)| acc | (a ^ b));
+    Ok(difference == 0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TOKEN: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+
+    #[tokio::test]
+    async fn test_authenticate_client() {
+        assert!(is_valid_token(TOKEN));
+
+        let (mut client, mut server) = tokio::io::duplex(128);
+        send_token(&mut client, TOKEN).await.unwrap();
+        assert!(authenticate_client(&mut server, TOKEN).await.unwrap());
+
+        let wrong_token = TOKEN.replace('0', "1");
+        send_token(&mut client, &wrong_token).await.unwrap();
+        assert!(!authenticate_client(&mut server, TOKEN).await.unwrap());
+    }
+}
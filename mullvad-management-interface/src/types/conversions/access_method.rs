@@ -10,6 +10,7 @@ mod settings {
             Self {
                 direct: Some(settings.direct().clone().into()),
                 mullvad_bridges: Some(settings.mullvad_bridges().clone().into()),
+                system_proxy: Some(settings.system_proxy().clone().into()),
                 custom: settings
                     .iter_custom()
                     .cloned()
@@ -37,6 +38,13 @@ mod settings {
                 ))
                 .and_then(access_method::AccessMethodSetting::try_from)?;
 
+            let system_proxy = settings
+                .system_proxy
+                .ok_or(FromProtobufTypeError::InvalidArgument(
+                    "Could not deserialize System Proxy Access Method from protobuf",
+                ))
+                .and_then(access_method::AccessMethodSetting::try_from)?;
+
             let custom = settings
                 .custom
                 .iter()
@@ -46,6 +54,7 @@ mod settings {
             Ok(access_method::Settings::new(
                 direct,
                 mullvad_bridges,
+                system_proxy,
                 custom,
             ))
         }
@@ -118,6 +127,9 @@ mod data {
             Ok(match access_method {
                 proto::access_method::AccessMethod::Direct(direct) => AccessMethod::from(direct),
                 proto::access_method::AccessMethod::Bridges(bridge) => AccessMethod::from(bridge),
+                proto::access_method::AccessMethod::SystemProxy(system_proxy) => {
+                    AccessMethod::from(system_proxy)
+                }
                 proto::access_method::AccessMethod::Custom(custom) => {
                     CustomProxy::try_from(custom).map(AccessMethod::from)?
                 }
@@ -146,6 +158,12 @@ mod data {
         }
     }
 
+    impl From<proto::access_method::SystemProxy> for AccessMethod {
+        fn from(_value: proto::access_method::SystemProxy) -> Self {
+            AccessMethod::from(BuiltInAccessMethod::SystemProxy)
+        }
+    }
+
     impl TryFrom<proto::Socks5Local> for AccessMethod {
         type Error = FromProtobufTypeError;
 
@@ -187,6 +205,11 @@ mod data {
                 mullvad_types::access_method::BuiltInAccessMethod::Bridge => {
                     proto::access_method::AccessMethod::Bridges(proto::access_method::Bridges {})
                 }
+                mullvad_types::access_method::BuiltInAccessMethod::SystemProxy => {
+                    proto::access_method::AccessMethod::SystemProxy(
+                        proto::access_method::SystemProxy {},
+                    )
+                }
             }
         }
     }
@@ -237,3 +260,95 @@ mod data {
         }
     }
 }
+
+/// Implements conversions for the auxiliary
+/// [`crate::types::proto::AccessMethodTestResult`] type to the internal
+/// [`mullvad_types::access_method::TestResult`] data type.
+mod test_result {
+    use crate::types::{proto, FromProtobufTypeError};
+    use mullvad_types::access_method::{TestResult, TestStep, TestStepResult};
+    use proto::access_method_test_result::Step;
+
+    impl From<TestResult> for proto::AccessMethodTestResult {
+        fn from(result: TestResult) -> Self {
+            let to_proto_duration = |duration| {
+                prost_types::Duration::try_from(duration)
+                    .expect("Failed to convert std::time::Duration to prost_types::Duration")
+            };
+
+            Self {
+                successful: result.is_successful(),
+                steps: result
+                    .steps
+                    .into_iter()
+                    .map(|step| {
+                        let kind = match step.step {
+                            TestStep::ResolveAddress => Step::ResolveAddress,
+                            TestStep::TcpConnect => Step::TcpConnect,
+                            TestStep::ProxyHandshake => Step::ProxyHandshake,
+                            TestStep::TlsHandshake => Step::TlsHandshake,
+                            TestStep::HttpRequest => Step::HttpRequest,
+                        };
+                        proto::access_method_test_result::StepResult {
+                            step: i32::from(kind),
+                            duration: Some(to_proto_duration(step.duration)),
+                            error: step.error,
+                        }
+                    })
+                    .collect(),
+                http_status: result.http_status.map(u32::from),
+                total: Some(to_proto_duration(result.total)),
+            }
+        }
+    }
+
+    impl TryFrom<proto::AccessMethodTestResult> for TestResult {
+        type Error = FromProtobufTypeError;
+
+        fn try_from(result: proto::AccessMethodTestResult) -> Result<Self, Self::Error> {
+            let from_proto_duration = |duration: Option<prost_types::Duration>| {
+                duration
+                    .map(std::time::Duration::try_from)
+                    .transpose()
+                    .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid duration"))
+                    .map(Option::unwrap_or_default)
+            };
+
+            let steps = result
+                .steps
+                .into_iter()
+                .map(|step| {
+                    let kind = match Step::try_from(step.step) {
+                        Ok(Step::ResolveAddress) => TestStep::ResolveAddress,
+                        Ok(Step::TcpConnect) => TestStep::TcpConnect,
+                        Ok(Step::ProxyHandshake) => TestStep::ProxyHandshake,
+                        Ok(Step::TlsHandshake) => TestStep::TlsHandshake,
+                        Ok(Step::HttpRequest) => TestStep::HttpRequest,
+                        Err(_) => {
+                            return Err(FromProtobufTypeError::InvalidArgument(
+                                "invalid access method test step",
+                            ))
+                        }
+                    };
+                    Ok(TestStepResult {
+                        step: kind,
+                        duration: from_proto_duration(step.duration)?,
+                        error: step.error,
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+
+            let http_status = result
+                .http_status
+                .map(u16::try_from)
+                .transpose()
+                .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid HTTP status"))?;
+
+            Ok(TestResult {
+                steps,
+                http_status,
+                total: from_proto_duration(result.total)?,
+            })
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use super::FromProtobufTypeError;
+use crate::types::proto;
+use mullvad_types::connection_diagnosis::{BlockedReason, ClockSkew};
+
+impl From<BlockedReason> for proto::connection_diagnosis::BlockedReason {
+    fn from(reason: BlockedReason) -> Self {
+        match reason {
+            BlockedReason::ClockSkew => Self::ClockSkew,
+            BlockedReason::DnsHijack => Self::DnsHijack,
+            BlockedReason::UdpBlocked => Self::UdpBlocked,
+            BlockedReason::ApiUnreachable => Self::ApiUnreachable,
+        }
+    }
+}
+
+impl From<Vec<BlockedReason>> for proto::ConnectionDiagnosis {
+    fn from(reasons: Vec<BlockedReason>) -> Self {
+        Self {
+            reasons: reasons
+                .into_iter()
+                .map(|reason| i32::from(proto::connection_diagnosis::BlockedReason::from(reason)))
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<proto::ConnectionDiagnosis> for Vec<BlockedReason> {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(diagnosis: proto::ConnectionDiagnosis) -> Result<Self, Self::Error> {
+        use proto::connection_diagnosis::BlockedReason as ProtoReason;
+
+        diagnosis
+            .reasons
+            .into_iter()
+            .map(|reason| {
+                let reason = ProtoReason::try_from(reason).map_err(|_| {
+                    FromProtobufTypeError::InvalidArgument("invalid blocked reason")
+                })?;
+                Ok(match reason {
+                    ProtoReason::ClockSkew => BlockedReason::ClockSkew,
+                    ProtoReason::DnsHijack => BlockedReason::DnsHijack,
+                    ProtoReason::UdpBlocked => BlockedReason::UdpBlocked,
+                    ProtoReason::ApiUnreachable => BlockedReason::ApiUnreachable,
+                })
+            })
+            .collect()
+    }
+}
+
+impl From<ClockSkew> for proto::ClockSkew {
+    fn from(skew: ClockSkew) -> Self {
+        Self {
+            offset_seconds: skew.offset_secs,
+        }
+    }
+}
+
+impl From<proto::ClockSkew> for ClockSkew {
+    fn from(skew: proto::ClockSkew) -> Self {
+        Self {
+            offset_secs: skew.offset_seconds,
+        }
+    }
+}
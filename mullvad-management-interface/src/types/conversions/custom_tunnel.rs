@@ -30,8 +30,8 @@ impl TryFrom<proto::ConnectionConfig> for mullvad_types::ConnectionConfig {
                             address,
                             protocol: super::net::try_transport_protocol_from_i32(config.protocol)?,
                         },
-                        username: config.username,
-                        password: config.password,
+                        username: config.username.into(),
+                        password: config.password.into(),
                     },
                 ))
             }
@@ -90,11 +90,13 @@ impl TryFrom<proto::ConnectionConfig> for mullvad_types::ConnectionConfig {
                             public_key,
                             allowed_ips,
                             endpoint,
+                            alternate_endpoint: None,
                             psk: None,
                             #[cfg(target_os = "windows")]
                             constant_packet_size: false,
                         },
                         exit_peer: None,
+                        candidate_peer: None,
                         ipv4_gateway,
                         ipv6_gateway,
                         #[cfg(target_os = "linux")]
@@ -118,8 +120,8 @@ impl From<mullvad_types::ConnectionConfig> for proto::ConnectionConfig {
                         protocol: i32::from(proto::TransportProtocol::from(
                             config.endpoint.protocol,
                         )),
-                        username: config.username,
-                        password: config.password,
+                        username: config.username.expose().clone(),
+                        password: config.password.expose().clone(),
                     })
                 }
                 mullvad_types::ConnectionConfig::Wireguard(config) => {
@@ -53,17 +53,8 @@ impl TryFrom<proto::DeviceState> for mullvad_types::device::DeviceState {
                 let account = state.device.ok_or(FromProtobufTypeError::InvalidArgument(
                     "missing account data",
                 ))?;
-                let device = account
-                    .device
-                    .ok_or(FromProtobufTypeError::InvalidArgument(
-                        "missing device data",
-                    ))?;
-
                 Ok(mullvad_types::device::DeviceState::LoggedIn(
-                    mullvad_types::device::AccountAndDevice {
-                        account_token: account.account_token,
-                        device: mullvad_types::device::Device::try_from(device)?,
-                    },
+                    mullvad_types::device::AccountAndDevice::try_from(account)?,
                 ))
             }
             proto::device_state::State::Revoked => Ok(mullvad_types::device::DeviceState::Revoked),
@@ -78,10 +69,7 @@ impl From<mullvad_types::device::DeviceState> for proto::DeviceState {
     fn from(state: mullvad_types::device::DeviceState) -> Self {
         proto::DeviceState {
             state: proto::device_state::State::from(&state) as i32,
-            device: state.into_device().map(|device| proto::AccountAndDevice {
-                account_token: device.account_token,
-                device: Some(proto::Device::from(device.device)),
-            }),
+            device: state.into_device().map(proto::AccountAndDevice::from),
         }
     }
 }
@@ -151,7 +139,7 @@ impl From<proto::device_event::Cause> for mullvad_types::device::DeviceEventCaus
 impl From<mullvad_types::device::RemoveDeviceEvent> for proto::RemoveDeviceEvent {
     fn from(event: mullvad_types::device::RemoveDeviceEvent) -> Self {
         proto::RemoveDeviceEvent {
-            account_token: event.account_token,
+            account_token: event.account_token.expose().clone(),
             new_device_list: event
                 .new_devices
                 .into_iter()
@@ -171,17 +159,60 @@ impl TryFrom<proto::RemoveDeviceEvent> for mullvad_types::device::RemoveDeviceEv
             .map(mullvad_types::device::Device::try_from)
             .collect::<Result<Vec<_>, FromProtobufTypeError>>()?;
         Ok(mullvad_types::device::RemoveDeviceEvent {
-            account_token: event.account_token,
+            account_token: event.account_token.into(),
             new_devices,
         })
     }
 }
 
+impl TryFrom<proto::AccountAndDevice> for mullvad_types::device::AccountAndDevice {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(account: proto::AccountAndDevice) -> Result<Self, Self::Error> {
+        let device = account
+            .device
+            .ok_or(FromProtobufTypeError::InvalidArgument(
+                "missing device data",
+            ))?;
+
+        let key_created_seconds = account
+            .key_created
+            .ok_or(FromProtobufTypeError::InvalidArgument(
+                "missing 'key_created' field",
+            ))?
+            .seconds;
+        let key_created = DateTime::from_timestamp(key_created_seconds, 0)
+            .ok_or(FromProtobufTypeError::InvalidArgument("invalid timestamp"))?;
+
+        let tunnel_addresses = mullvad_types::wireguard::AssociatedAddresses {
+            ipv4_address: account.ipv4_address.parse().map_err(|_| {
+                FromProtobufTypeError::InvalidArgument("invalid IPv4 tunnel address")
+            })?,
+            ipv6_address: account.ipv6_address.parse().map_err(|_| {
+                FromProtobufTypeError::InvalidArgument("invalid IPv6 tunnel address")
+            })?,
+        };
+
+        Ok(mullvad_types::device::AccountAndDevice {
+            account_token: account.account_token.into(),
+            device: mullvad_types::device::Device::try_from(device)?,
+            key_created,
+            tunnel_addresses,
+        })
+    }
+}
+
 impl From<mullvad_types::device::AccountAndDevice> for proto::AccountAndDevice {
     fn from(device: mullvad_types::device::AccountAndDevice) -> Self {
         proto::AccountAndDevice {
-            account_token: device.account_token,
+            account_token: device.account_token.expose().clone(),
             device: Some(proto::Device::from(device.device)),
+            key_created: Some(Timestamp {
+                seconds: device.key_created.timestamp(),
+                nanos: 0,
+            }),
+            ipv4_address: device.tunnel_addresses.ipv4_address.to_string(),
+            ipv6_address: device.tunnel_addresses.ipv6_address.to_string(),
         }
     }
 }
@@ -193,3 +224,43 @@ impl From<Vec<mullvad_types::device::Device>> for proto::DeviceList {
         }
     }
 }
+
+impl From<mullvad_types::device::LoginOutcome> for proto::LoginOutcome {
+    fn from(outcome: mullvad_types::device::LoginOutcome) -> Self {
+        use mullvad_types::device::LoginOutcome;
+
+        let outcome = match outcome {
+            LoginOutcome::LoggedIn => proto::login_outcome::Outcome::LoggedIn(()),
+            LoginOutcome::TooManyDevices(devices) => {
+                proto::login_outcome::Outcome::TooManyDevices(proto::DeviceList::from(devices))
+            }
+        };
+        proto::LoginOutcome {
+            outcome: Some(outcome),
+        }
+    }
+}
+
+impl TryFrom<proto::LoginOutcome> for mullvad_types::device::LoginOutcome {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(outcome: proto::LoginOutcome) -> Result<Self, Self::Error> {
+        use mullvad_types::device::LoginOutcome;
+
+        match outcome
+            .outcome
+            .ok_or(FromProtobufTypeError::InvalidArgument(
+                "missing login outcome",
+            ))? {
+            proto::login_outcome::Outcome::LoggedIn(()) => Ok(LoginOutcome::LoggedIn),
+            proto::login_outcome::Outcome::TooManyDevices(list) => {
+                let devices = list
+                    .devices
+                    .into_iter()
+                    .map(mullvad_types::device::Device::try_from)
+                    .collect::<Result<Vec<_>, FromProtobufTypeError>>()?;
+                Ok(LoginOutcome::TooManyDevices(devices))
+            }
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use super::FromProtobufTypeError;
+use crate::types::proto;
+use mullvad_types::health::{ComponentHealth, DaemonHealth, HealthStatus};
+
+impl From<DaemonHealth> for proto::DaemonHealth {
+    fn from(health: DaemonHealth) -> Self {
+        Self {
+            components: health
+                .components
+                .into_iter()
+                .map(|(name, component)| (name, proto::ComponentHealth::from(component)))
+                .collect(),
+        }
+    }
+}
+
+impl From<ComponentHealth> for proto::ComponentHealth {
+    fn from(component: ComponentHealth) -> Self {
+        let status = match component.status {
+            HealthStatus::Ok => proto::component_health::Status::Ok,
+            HealthStatus::Degraded => proto::component_health::Status::Degraded,
+            HealthStatus::Failed => proto::component_health::Status::Failed,
+        };
+        Self {
+            status: i32::from(status),
+            details: component.details,
+        }
+    }
+}
+
+impl TryFrom<proto::DaemonHealth> for DaemonHealth {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(health: proto::DaemonHealth) -> Result<Self, Self::Error> {
+        let components = health
+            .components
+            .into_iter()
+            .map(|(name, component)| Ok((name, ComponentHealth::try_from(component)?)))
+            .collect::<Result<_, FromProtobufTypeError>>()?;
+        Ok(Self { components })
+    }
+}
+
+impl TryFrom<proto::ComponentHealth> for ComponentHealth {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(component: proto::ComponentHealth) -> Result<Self, Self::Error> {
+        let status = match proto::component_health::Status::try_from(component.status)
+            .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid health status"))?
+        {
+            proto::component_health::Status::Ok => HealthStatus::Ok,
+            proto::component_health::Status::Degraded => HealthStatus::Degraded,
+            proto::component_health::Status::Failed => HealthStatus::Failed,
+        };
+        Ok(Self {
+            status,
+            details: component.details,
+        })
+    }
+}
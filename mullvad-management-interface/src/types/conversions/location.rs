@@ -14,6 +14,9 @@ impl From<mullvad_types::location::GeoIpLocation> for proto::GeoIpLocation {
             bridge_hostname: geoip.bridge_hostname,
             entry_hostname: geoip.entry_hostname,
             obfuscator_hostname: geoip.obfuscator_hostname,
+            provider: geoip.provider,
+            owned: geoip.owned,
+            stale: geoip.stale,
         }
     }
 }
@@ -40,6 +43,33 @@ impl TryFrom<proto::GeoIpLocation> for mullvad_types::location::GeoIpLocation {
             bridge_hostname: geoip.bridge_hostname,
             entry_hostname: geoip.entry_hostname,
             obfuscator_hostname: geoip.obfuscator_hostname,
+            provider: geoip.provider,
+            owned: geoip.owned,
+            stale: geoip.stale,
+        })
+    }
+}
+
+impl From<mullvad_types::location::ConnectionVerification> for proto::ConnectionVerification {
+    fn from(verification: mullvad_types::location::ConnectionVerification) -> Self {
+        proto::ConnectionVerification {
+            exit_ip: verification.exit_ip.to_string(),
+            mullvad_exit_ip: verification.mullvad_exit_ip,
+            exit_relay_matches: verification.exit_relay_matches,
+            dns_hijacked: verification.dns_hijacked,
+        }
+    }
+}
+
+impl TryFrom<proto::ConnectionVerification> for mullvad_types::location::ConnectionVerification {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(verification: proto::ConnectionVerification) -> Result<Self, Self::Error> {
+        Ok(mullvad_types::location::ConnectionVerification {
+            exit_ip: arg_from_str(&verification.exit_ip, "invalid exit IP address")?,
+            mullvad_exit_ip: verification.mullvad_exit_ip,
+            exit_relay_matches: verification.exit_relay_matches,
+            dns_hijacked: verification.dns_hijacked,
         })
     }
 }
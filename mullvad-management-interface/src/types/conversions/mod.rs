@@ -2,17 +2,26 @@ use std::str::FromStr;
 
 mod access_method;
 mod account;
+mod connection_diagnosis;
 mod custom_list;
 mod custom_tunnel;
 mod device;
+mod health;
 mod location;
 mod net;
+mod notification;
+mod problem_report;
+mod profile;
 pub mod relay_constraints;
 mod relay_list;
+mod runtime_config;
+mod self_test;
 mod settings;
+mod shutdown;
 #[cfg(target_os = "windows")]
 mod split_tunnel;
 mod states;
+mod traffic;
 mod version;
 mod wireguard;
 
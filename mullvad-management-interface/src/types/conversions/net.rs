@@ -30,6 +30,7 @@ impl From<talpid_types::net::TunnelEndpoint> for proto::TunnelEndpoint {
                     )),
                     obfuscation_type: match obfuscation_endpoint.obfuscation_type {
                         net::ObfuscationType::Udp2Tcp => i32::from(proto::ObfuscationType::Udp2tcp),
+                        net::ObfuscationType::Tls => i32::from(proto::ObfuscationType::Tls),
                     },
                 }
             }),
@@ -37,9 +38,15 @@ impl From<talpid_types::net::TunnelEndpoint> for proto::TunnelEndpoint {
                 address: entry.address.to_string(),
                 protocol: i32::from(proto::TransportProtocol::from(entry.protocol)),
             }),
-            tunnel_metadata: endpoint
-                .tunnel_interface
-                .map(|tunnel_interface| proto::TunnelMetadata { tunnel_interface }),
+            tunnel_metadata: endpoint.tunnel_interface.map(|tunnel_interface| {
+                proto::TunnelMetadata {
+                    tunnel_interface,
+                    ephemeral_peer_transport: endpoint
+                        .ephemeral_peer_transport
+                        .map(|protocol| i32::from(proto::TransportProtocol::from(protocol))),
+                    data_channel_offload: endpoint.data_channel_offload,
+                }
+            }),
             #[cfg(target_os = "windows")]
             daita: endpoint.daita,
             #[cfg(not(target_os = "windows"))]
@@ -106,6 +113,7 @@ impl TryFrom<proto::TunnelEndpoint> for talpid_types::net::TunnelEndpoint {
                             Ok(proto::ObfuscationType::Udp2tcp) => {
                                 talpid_net::ObfuscationType::Udp2Tcp
                             }
+                            Ok(proto::ObfuscationType::Tls) => talpid_net::ObfuscationType::Tls,
                             Err(_) => {
                                 return Err(FromProtobufTypeError::InvalidArgument(
                                     "unknown obfuscation type",
@@ -126,7 +134,17 @@ impl TryFrom<proto::TunnelEndpoint> for talpid_types::net::TunnelEndpoint {
                 .transpose()?,
             tunnel_interface: endpoint
                 .tunnel_metadata
-                .map(|tunnel_metadata| tunnel_metadata.tunnel_interface),
+                .as_ref()
+                .map(|tunnel_metadata| tunnel_metadata.tunnel_interface.clone()),
+            data_channel_offload: endpoint
+                .tunnel_metadata
+                .as_ref()
+                .is_some_and(|tunnel_metadata| tunnel_metadata.data_channel_offload),
+            ephemeral_peer_transport: endpoint
+                .tunnel_metadata
+                .and_then(|tunnel_metadata| tunnel_metadata.ephemeral_peer_transport)
+                .map(try_transport_protocol_from_i32)
+                .transpose()?,
             #[cfg(target_os = "windows")]
             daita: endpoint.daita,
         })
@@ -256,7 +274,7 @@ mod proxy {
                 None => Socks5Remote::new((ip, port)),
             };
 
-            Ok(socks)
+            Ok(socks.with_hostname(value.hostname))
         }
     }
 
@@ -270,11 +288,10 @@ mod proxy {
                 )
             })?;
 
-            Ok(Shadowsocks::new(
-                (ip, value.port as u16),
-                value.cipher,
-                value.password,
-            ))
+            Ok(
+                Shadowsocks::new((ip, value.port as u16), value.cipher, value.password)
+                    .with_hostname(value.hostname),
+            )
         }
     }
 
@@ -307,8 +324,9 @@ mod proxy {
             proto::Shadowsocks {
                 ip: value.endpoint.ip().to_string(),
                 port: value.endpoint.port() as u32,
-                password: value.password,
+                password: value.password.expose().clone(),
                 cipher: value.cipher,
+                hostname: value.hostname,
             }
         }
     }
@@ -332,6 +350,7 @@ mod proxy {
                 ip: value.endpoint.ip().to_string(),
                 port: value.endpoint.port() as u32,
                 auth: value.auth.map(proto::SocksAuth::from),
+                hostname: value.hostname,
             }
         }
     }
@@ -0,0 +1,65 @@
+use super::FromProtobufTypeError;
+use crate::types::proto;
+use mullvad_types::notification::{Notification, NotificationKind, NotificationSeverity};
+
+impl From<Notification> for proto::Notification {
+    fn from(notification: Notification) -> Self {
+        use proto::notification::{Kind, Severity};
+
+        let kind = match notification.kind {
+            NotificationKind::Connected => Kind::Connected,
+            NotificationKind::Disconnected => Kind::Disconnected,
+            NotificationKind::Blocking => Kind::Blocking,
+            NotificationKind::Leaking => Kind::Leaking,
+            NotificationKind::ConnectionNotVerified => Kind::ConnectionNotVerified,
+            NotificationKind::DeviceRevoked => Kind::DeviceRevoked,
+            NotificationKind::UnsupportedVersion => Kind::UnsupportedVersion,
+            NotificationKind::UpdateAvailable => Kind::UpdateAvailable,
+            NotificationKind::PinnedRelayRemoved => Kind::PinnedRelayRemoved,
+        };
+        let severity = match notification.severity {
+            NotificationSeverity::Info => Severity::Info,
+            NotificationSeverity::Warning => Severity::Warning,
+            NotificationSeverity::Critical => Severity::Critical,
+        };
+        Self {
+            kind: i32::from(kind),
+            severity: i32::from(severity),
+            message: notification.message,
+        }
+    }
+}
+
+impl TryFrom<proto::Notification> for Notification {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(notification: proto::Notification) -> Result<Self, Self::Error> {
+        use proto::notification::{Kind, Severity};
+
+        let kind = match Kind::try_from(notification.kind)
+            .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid notification kind"))?
+        {
+            Kind::Connected => NotificationKind::Connected,
+            Kind::Disconnected => NotificationKind::Disconnected,
+            Kind::Blocking => NotificationKind::Blocking,
+            Kind::Leaking => NotificationKind::Leaking,
+            Kind::ConnectionNotVerified => NotificationKind::ConnectionNotVerified,
+            Kind::DeviceRevoked => NotificationKind::DeviceRevoked,
+            Kind::UnsupportedVersion => NotificationKind::UnsupportedVersion,
+            Kind::UpdateAvailable => NotificationKind::UpdateAvailable,
+            Kind::PinnedRelayRemoved => NotificationKind::PinnedRelayRemoved,
+        };
+        let severity = match Severity::try_from(notification.severity)
+            .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid notification severity"))?
+        {
+            Severity::Info => NotificationSeverity::Info,
+            Severity::Warning => NotificationSeverity::Warning,
+            Severity::Critical => NotificationSeverity::Critical,
+        };
+        Ok(Self {
+            kind,
+            severity,
+            message: notification.message,
+        })
+    }
+}
@@ -0,0 +1,83 @@
+use super::FromProtobufTypeError;
+use crate::types::proto;
+use mullvad_types::problem_report::{ProblemReportProgress, ProblemReportRequest, UploadState};
+
+impl From<ProblemReportRequest> for proto::ProblemReportRequest {
+    fn from(request: ProblemReportRequest) -> Self {
+        Self {
+            email: request.email,
+            message: request.message,
+            report: request.report,
+        }
+    }
+}
+
+impl From<proto::ProblemReportRequest> for ProblemReportRequest {
+    fn from(request: proto::ProblemReportRequest) -> Self {
+        Self {
+            email: request.email,
+            message: request.message,
+            report: request.report,
+        }
+    }
+}
+
+impl From<ProblemReportProgress> for proto::ProblemReportProgress {
+    fn from(progress: ProblemReportProgress) -> Self {
+        use proto::problem_report_progress::State;
+
+        let mut proto_progress = Self {
+            id: progress.id,
+            report_bytes: progress.report_bytes,
+            ..Default::default()
+        };
+        let state = match progress.state {
+            UploadState::Uploading => State::Uploading,
+            UploadState::Retrying { attempt, delay } => {
+                proto_progress.retry_attempt = attempt;
+                proto_progress.retry_delay = Some(
+                    prost_types::Duration::try_from(delay)
+                        .expect("Failed to convert std::time::Duration to prost_types::Duration"),
+                );
+                State::Retrying
+            }
+            UploadState::Completed => State::Completed,
+            UploadState::Failed(error) => {
+                proto_progress.error = error;
+                State::Failed
+            }
+        };
+        proto_progress.state = i32::from(state);
+        proto_progress
+    }
+}
+
+impl TryFrom<proto::ProblemReportProgress> for ProblemReportProgress {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(progress: proto::ProblemReportProgress) -> Result<Self, Self::Error> {
+        use proto::problem_report_progress::State;
+
+        let state = match State::try_from(progress.state)
+            .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid upload state"))?
+        {
+            State::Uploading => UploadState::Uploading,
+            State::Retrying => UploadState::Retrying {
+                attempt: progress.retry_attempt,
+                delay: progress
+                    .retry_delay
+                    .map(std::time::Duration::try_from)
+                    .transpose()
+                    .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid retry delay"))?
+                    .unwrap_or_default(),
+            },
+            State::Completed => UploadState::Completed,
+            State::Failed => UploadState::Failed(progress.error),
+        };
+        Ok(Self {
+            id: progress.id,
+            report_bytes: progress.report_bytes,
+            state,
+        })
+    }
+}
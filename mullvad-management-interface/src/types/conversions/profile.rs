@@ -0,0 +1,72 @@
+use crate::types::{proto, FromProtobufTypeError};
+use mullvad_types::profile::Profile;
+
+impl From<mullvad_types::profile::ProfilesSettings> for proto::ProfileSettings {
+    fn from(settings: mullvad_types::profile::ProfilesSettings) -> Self {
+        Self {
+            profiles: settings.into_iter().map(proto::Profile::from).collect(),
+        }
+    }
+}
+
+impl TryFrom<proto::ProfileSettings> for mullvad_types::profile::ProfilesSettings {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(settings: proto::ProfileSettings) -> Result<Self, Self::Error> {
+        Ok(Self::from(
+            settings
+                .profiles
+                .into_iter()
+                .map(Profile::try_from)
+                .collect::<Result<Vec<Profile>, _>>()?,
+        ))
+    }
+}
+
+impl From<Profile> for proto::Profile {
+    fn from(profile: Profile) -> Self {
+        Self {
+            name: profile.name,
+            relay_settings: Some(proto::RelaySettings::from(profile.relay_settings)),
+            obfuscation_settings: Some(proto::ObfuscationSettings::from(
+                &profile.obfuscation_settings,
+            )),
+            dns_options: Some(proto::DnsOptions::from(&profile.dns_options)),
+        }
+    }
+}
+
+impl TryFrom<proto::Profile> for Profile {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(profile: proto::Profile) -> Result<Self, Self::Error> {
+        let relay_settings =
+            profile
+                .relay_settings
+                .ok_or(FromProtobufTypeError::InvalidArgument(
+                    "missing relay settings",
+                ))?;
+        let obfuscation_settings =
+            profile
+                .obfuscation_settings
+                .ok_or(FromProtobufTypeError::InvalidArgument(
+                    "missing obfuscation settings",
+                ))?;
+        let dns_options = profile
+            .dns_options
+            .ok_or(FromProtobufTypeError::InvalidArgument(
+                "missing DNS options",
+            ))?;
+
+        Ok(Profile {
+            name: profile.name,
+            relay_settings: mullvad_types::relay_constraints::RelaySettings::try_from(
+                relay_settings,
+            )?,
+            obfuscation_settings: mullvad_types::relay_constraints::ObfuscationSettings::try_from(
+                obfuscation_settings,
+            )?,
+            dns_options: mullvad_types::settings::DnsOptions::try_from(dns_options)?,
+        })
+    }
+}
@@ -1,4 +1,7 @@
-use crate::types::{conversions::net::try_tunnel_type_from_i32, proto, FromProtobufTypeError};
+use crate::types::{
+    conversions::{bytes_to_pubkey, net::try_tunnel_type_from_i32},
+    proto, FromProtobufTypeError,
+};
 use mullvad_types::{
     constraints::Constraint, custom_list::Id, relay_constraints::GeographicLocationConstraint,
 };
@@ -25,8 +28,14 @@ impl TryFrom<&proto::WireguardConstraints>
             None => None,
         };
 
+        let port_range = match &constraints.port_range {
+            Some(range) => Some(mullvad_constraints::PortRange::try_from(range)?),
+            None => None,
+        };
+
         Ok(mullvad_constraints::WireguardConstraints {
             port: Constraint::from(constraints.port.map(|port| port as u16)),
+            port_range: Constraint::from(port_range),
             ip_version: Constraint::from(ip_version),
             use_multihop: constraints.use_multihop,
             entry_location: constraints
@@ -39,10 +48,102 @@ impl TryFrom<&proto::WireguardConstraints>
                     .ok()
                 })
                 .unwrap_or(Constraint::Any),
+            required_features: constraints
+                .required_features
+                .as_ref()
+                .map(mullvad_constraints::RequiredRelayFeatures::from)
+                .unwrap_or_default(),
+            max_latency: Constraint::from(
+                constraints
+                    .max_latency
+                    .map(|max_latency| u16::try_from(max_latency).unwrap_or(u16::MAX)),
+            ),
+            multihop_diversity: constraints
+                .multihop_diversity
+                .as_ref()
+                .map(mullvad_constraints::MultihopDiversity::from)
+                .unwrap_or_default(),
+            public_key: Constraint::from(
+                constraints
+                    .public_key
+                    .as_deref()
+                    .map(bytes_to_pubkey)
+                    .transpose()?,
+            ),
+            entry_public_key: Constraint::from(
+                constraints
+                    .entry_public_key
+                    .as_deref()
+                    .map(bytes_to_pubkey)
+                    .transpose()?,
+            ),
         })
     }
 }
 
+impl From<&proto::RequiredRelayFeatures>
+    for mullvad_types::relay_constraints::RequiredRelayFeatures
+{
+    fn from(features: &proto::RequiredRelayFeatures) -> Self {
+        Self {
+            daita: features.daita,
+            quic: features.quic,
+            stboot: features.stboot,
+        }
+    }
+}
+
+impl From<mullvad_types::relay_constraints::RequiredRelayFeatures>
+    for proto::RequiredRelayFeatures
+{
+    fn from(features: mullvad_types::relay_constraints::RequiredRelayFeatures) -> Self {
+        Self {
+            daita: features.daita,
+            quic: features.quic,
+            stboot: features.stboot,
+        }
+    }
+}
+
+impl From<&proto::MultihopDiversity> for mullvad_types::relay_constraints::MultihopDiversity {
+    fn from(diversity: &proto::MultihopDiversity) -> Self {
+        Self {
+            country: diversity.country,
+            provider: diversity.provider,
+        }
+    }
+}
+
+impl From<mullvad_types::relay_constraints::MultihopDiversity> for proto::MultihopDiversity {
+    fn from(diversity: mullvad_types::relay_constraints::MultihopDiversity) -> Self {
+        Self {
+            country: diversity.country,
+            provider: diversity.provider,
+        }
+    }
+}
+
+impl TryFrom<&proto::PortRange> for mullvad_types::relay_constraints::PortRange {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(range: &proto::PortRange) -> Result<Self, Self::Error> {
+        let start = u16::try_from(range.first)
+            .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid port"))?;
+        let end = u16::try_from(range.last)
+            .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid port"))?;
+        Self::new(start, end).ok_or(FromProtobufTypeError::InvalidArgument("invalid port range"))
+    }
+}
+
+impl From<mullvad_types::relay_constraints::PortRange> for proto::PortRange {
+    fn from(range: mullvad_types::relay_constraints::PortRange) -> Self {
+        proto::PortRange {
+            first: u32::from(range.start()),
+            last: u32::from(range.end()),
+        }
+    }
+}
+
 impl TryFrom<&proto::OpenvpnConstraints> for mullvad_types::relay_constraints::OpenVpnConstraints {
     type Error = FromProtobufTypeError;
 
@@ -152,10 +253,12 @@ impl From<&mullvad_types::relay_constraints::ObfuscationSettings> for proto::Obf
             SelectedObfuscation::Udp2Tcp => {
                 proto::obfuscation_settings::SelectedObfuscation::Udp2tcp
             }
+            SelectedObfuscation::Tls => proto::obfuscation_settings::SelectedObfuscation::Tls,
         });
         Self {
             selected_obfuscation,
             udp2tcp: Some(proto::Udp2TcpObfuscationSettings::from(&settings.udp2tcp)),
+            tls: Some(proto::TlsObfuscationSettings::from(&settings.tls)),
         }
     }
 }
@@ -176,6 +279,16 @@ impl From<&mullvad_types::relay_constraints::Udp2TcpObfuscationSettings>
     }
 }
 
+impl From<&mullvad_types::relay_constraints::TlsObfuscationSettings>
+    for proto::TlsObfuscationSettings
+{
+    fn from(settings: &mullvad_types::relay_constraints::TlsObfuscationSettings) -> Self {
+        Self {
+            sni: settings.sni.clone(),
+        }
+    }
+}
+
 impl From<mullvad_types::relay_constraints::BridgeSettings> for proto::BridgeSettings {
     fn from(settings: mullvad_types::relay_constraints::BridgeSettings) -> Self {
         use proto::bridge_settings;
@@ -201,11 +314,17 @@ impl From<mullvad_types::relay_constraints::BridgeSettings> for proto::BridgeSet
         };
 
         let custom = settings.custom.map(proto::CustomProxy::from);
+        let custom_chain = settings
+            .custom_chain
+            .into_iter()
+            .map(proto::CustomProxy::from)
+            .collect();
 
         proto::BridgeSettings {
             bridge_type: i32::from(mode),
             normal: Some(normal),
             custom,
+            custom_chain,
         }
     }
 }
@@ -248,6 +367,11 @@ impl From<mullvad_types::relay_constraints::RelaySettings> for proto::RelaySetti
                             .port
                             .map(u32::from)
                             .option(),
+                        port_range: constraints
+                            .wireguard_constraints
+                            .port_range
+                            .option()
+                            .map(proto::PortRange::from),
                         ip_version: constraints
                             .wireguard_constraints
                             .ip_version
@@ -259,6 +383,29 @@ impl From<mullvad_types::relay_constraints::RelaySettings> for proto::RelaySetti
                             .entry_location
                             .option()
                             .map(proto::LocationConstraint::from),
+                        required_features: Some(proto::RequiredRelayFeatures::from(
+                            constraints.wireguard_constraints.required_features,
+                        )),
+                        max_latency: constraints
+                            .wireguard_constraints
+                            .max_latency
+                            .map(u32::from)
+                            .option(),
+                        multihop_diversity: Some(proto::MultihopDiversity::from(
+                            constraints.wireguard_constraints.multihop_diversity,
+                        )),
+                        public_key: constraints
+                            .wireguard_constraints
+                            .public_key
+                            .as_ref()
+                            .option()
+                            .map(|key| key.as_bytes().to_vec()),
+                        entry_public_key: constraints
+                            .wireguard_constraints
+                            .entry_public_key
+                            .as_ref()
+                            .option()
+                            .map(|key| key.as_bytes().to_vec()),
                     }),
 
                     openvpn_constraints: Some(proto::OpenvpnConstraints {
@@ -418,11 +565,17 @@ impl TryFrom<proto::BridgeSettings> for mullvad_types::relay_constraints::Bridge
 
         // convert custom bridge settings
         let custom = settings.custom.map(CustomProxy::try_from).transpose()?;
+        let custom_chain = settings
+            .custom_chain
+            .into_iter()
+            .map(CustomProxy::try_from)
+            .collect::<Result<_, _>>()?;
 
         Ok(BridgeSettings {
             bridge_type: try_bridge_mode_from_i32(settings.bridge_type)?,
             normal,
             custom,
+            custom_chain,
         })
     }
 }
@@ -438,6 +591,7 @@ impl TryFrom<proto::ObfuscationSettings> for mullvad_types::relay_constraints::O
                 Ok(IpcSelectedObfuscation::Auto) => SelectedObfuscation::Auto,
                 Ok(IpcSelectedObfuscation::Off) => SelectedObfuscation::Off,
                 Ok(IpcSelectedObfuscation::Udp2tcp) => SelectedObfuscation::Udp2Tcp,
+                Ok(IpcSelectedObfuscation::Tls) => SelectedObfuscation::Tls,
                 Err(_) => {
                     return Err(FromProtobufTypeError::InvalidArgument(
                         "invalid selected obfuscator",
@@ -456,9 +610,16 @@ impl TryFrom<proto::ObfuscationSettings> for mullvad_types::relay_constraints::O
             }
         };
 
+        // Older clients do not know about TLS obfuscation
+        let tls = settings
+            .tls
+            .map(mullvad_types::relay_constraints::TlsObfuscationSettings::from)
+            .unwrap_or_default();
+
         Ok(Self {
             selected_obfuscation,
             udp2tcp,
+            tls,
         })
     }
 }
@@ -475,6 +636,18 @@ impl TryFrom<&proto::Udp2TcpObfuscationSettings>
     }
 }
 
+impl From<proto::TlsObfuscationSettings>
+    for mullvad_types::relay_constraints::TlsObfuscationSettings
+{
+    fn from(settings: proto::TlsObfuscationSettings) -> Self {
+        // An empty string is how an unset field is represented
+        if settings.sni.is_empty() {
+            return Self::default();
+        }
+        Self { sni: settings.sni }
+    }
+}
+
 impl TryFrom<proto::BridgeState> for mullvad_types::relay_constraints::BridgeState {
     type Error = FromProtobufTypeError;
 
@@ -496,6 +669,74 @@ impl TryFrom<proto::BridgeState> for mullvad_types::relay_constraints::BridgeSta
     }
 }
 
+impl From<mullvad_types::relay_constraints::RemovedRelayPolicy> for proto::RemovedRelayPolicy {
+    fn from(policy: mullvad_types::relay_constraints::RemovedRelayPolicy) -> Self {
+        use mullvad_types::relay_constraints::RemovedRelayPolicy;
+        Self {
+            policy: i32::from(match policy {
+                RemovedRelayPolicy::StayDisconnected => {
+                    proto::removed_relay_policy::Policy::StayDisconnected
+                }
+                RemovedRelayPolicy::SameCity => proto::removed_relay_policy::Policy::SameCity,
+                RemovedRelayPolicy::SameCountry => proto::removed_relay_policy::Policy::SameCountry,
+            }),
+        }
+    }
+}
+
+impl TryFrom<proto::RemovedRelayPolicy> for mullvad_types::relay_constraints::RemovedRelayPolicy {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(policy: proto::RemovedRelayPolicy) -> Result<Self, Self::Error> {
+        use mullvad_types::relay_constraints::RemovedRelayPolicy;
+        match proto::removed_relay_policy::Policy::try_from(policy.policy) {
+            Ok(proto::removed_relay_policy::Policy::StayDisconnected) => {
+                Ok(RemovedRelayPolicy::StayDisconnected)
+            }
+            Ok(proto::removed_relay_policy::Policy::SameCity) => Ok(RemovedRelayPolicy::SameCity),
+            Ok(proto::removed_relay_policy::Policy::SameCountry) => {
+                Ok(RemovedRelayPolicy::SameCountry)
+            }
+            Err(_) => Err(FromProtobufTypeError::InvalidArgument(
+                "invalid removed relay policy",
+            )),
+        }
+    }
+}
+
+impl From<mullvad_types::relay_constraints::IpVersionPreference> for proto::IpVersionPreference {
+    fn from(preference: mullvad_types::relay_constraints::IpVersionPreference) -> Self {
+        use mullvad_types::relay_constraints::IpVersionPreference;
+        Self {
+            preference: i32::from(match preference {
+                IpVersionPreference::Automatic => {
+                    proto::ip_version_preference::Preference::Automatic
+                }
+                IpVersionPreference::Ipv4 => proto::ip_version_preference::Preference::Ipv4,
+                IpVersionPreference::Ipv6 => proto::ip_version_preference::Preference::Ipv6,
+            }),
+        }
+    }
+}
+
+impl TryFrom<proto::IpVersionPreference> for mullvad_types::relay_constraints::IpVersionPreference {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(preference: proto::IpVersionPreference) -> Result<Self, Self::Error> {
+        use mullvad_types::relay_constraints::IpVersionPreference;
+        match proto::ip_version_preference::Preference::try_from(preference.preference) {
+            Ok(proto::ip_version_preference::Preference::Automatic) => {
+                Ok(IpVersionPreference::Automatic)
+            }
+            Ok(proto::ip_version_preference::Preference::Ipv4) => Ok(IpVersionPreference::Ipv4),
+            Ok(proto::ip_version_preference::Preference::Ipv6) => Ok(IpVersionPreference::Ipv6),
+            Err(_) => Err(FromProtobufTypeError::InvalidArgument(
+                "invalid IP version preference",
+            )),
+        }
+    }
+}
+
 impl TryFrom<proto::TransportPort> for mullvad_types::relay_constraints::TransportPort {
     type Error = FromProtobufTypeError;
 
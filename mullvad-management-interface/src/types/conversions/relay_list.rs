@@ -112,6 +112,7 @@ impl From<mullvad_types::relay_list::Relay> for proto::Relay {
             owned: relay.owned,
             provider: relay.provider,
             weight: relay.weight,
+            stboot: relay.stboot,
             endpoint_type: match &relay.endpoint_data {
                 MullvadEndpointData::Openvpn => proto::relay::RelayType::Openvpn as i32,
                 MullvadEndpointData::Bridge => proto::relay::RelayType::Bridge as i32,
@@ -123,6 +124,11 @@ impl From<mullvad_types::relay_list::Relay> for proto::Relay {
                     proto::WireguardRelayEndpointData {
                         public_key: data.public_key.as_bytes().to_vec(),
                         daita: data.daita,
+                        quic: data.quic,
+                        tls: data.tls.map(|tls| proto::TlsEndpointData {
+                            port: u32::from(tls.port),
+                            certificate_sha256: tls.certificate_sha256.to_vec(),
+                        }),
                     },
                 )),
                 _ => None,
@@ -238,6 +244,11 @@ impl TryFrom<proto::Relay> for mullvad_types::relay_list::Relay {
                     mullvad_types::relay_list::WireguardRelayEndpointData {
                         public_key: bytes_to_pubkey(&data.public_key)?,
                         daita: data.daita,
+                        quic: data.quic,
+                        tls: data
+                            .tls
+                            .map(mullvad_types::relay_list::TlsEndpointData::try_from)
+                            .transpose()?,
                     },
                 )
             }
@@ -268,6 +279,7 @@ impl TryFrom<proto::Relay> for mullvad_types::relay_list::Relay {
             owned: relay.owned,
             provider: relay.provider,
             weight: relay.weight,
+            stboot: relay.stboot,
             endpoint_data,
             location: relay.location.map(|location| MullvadLocation {
                 country: location.country,
@@ -377,3 +389,96 @@ impl TryFrom<proto::WireguardEndpointData> for mullvad_types::relay_list::Wiregu
         })
     }
 }
+
+impl From<mullvad_types::relay_list::RelayLatency> for proto::RelayLatency {
+    fn from(latency: mullvad_types::relay_list::RelayLatency) -> Self {
+        Self {
+            hostname: latency.hostname,
+            latency: latency.latency.map(|latency| {
+                prost_types::Duration::try_from(latency)
+                    .expect("Failed to convert std::time::Duration to prost_types::Duration")
+            }),
+        }
+    }
+}
+
+impl TryFrom<proto::RelayLatency> for mullvad_types::relay_list::RelayLatency {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(latency: proto::RelayLatency) -> Result<Self, Self::Error> {
+        Ok(Self {
+            hostname: latency.hostname,
+            latency: latency
+                .latency
+                .map(std::time::Duration::try_from)
+                .transpose()
+                .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid latency"))?,
+        })
+    }
+}
+
+impl From<mullvad_types::relay_list::RelayListDiff> for proto::RelayListDiff {
+    fn from(diff: mullvad_types::relay_list::RelayListDiff) -> Self {
+        let convert = |entries: Vec<mullvad_types::relay_list::RelayDiffEntry>| {
+            entries
+                .into_iter()
+                .map(proto::RelayDiffEntry::from)
+                .collect()
+        };
+        Self {
+            added: convert(diff.added),
+            removed: convert(diff.removed),
+            changed: convert(diff.changed),
+        }
+    }
+}
+
+impl From<mullvad_types::relay_list::RelayDiffEntry> for proto::RelayDiffEntry {
+    fn from(entry: mullvad_types::relay_list::RelayDiffEntry) -> Self {
+        Self {
+            hostname: entry.hostname,
+            country_code: entry.country_code,
+            city_code: entry.city_code,
+        }
+    }
+}
+
+impl From<proto::RelayListDiff> for mullvad_types::relay_list::RelayListDiff {
+    fn from(diff: proto::RelayListDiff) -> Self {
+        let convert = |entries: Vec<proto::RelayDiffEntry>| {
+            entries
+                .into_iter()
+                .map(mullvad_types::relay_list::RelayDiffEntry::from)
+                .collect()
+        };
+        Self {
+            added: convert(diff.added),
+            removed: convert(diff.removed),
+            changed: convert(diff.changed),
+        }
+    }
+}
+
+impl From<proto::RelayDiffEntry> for mullvad_types::relay_list::RelayDiffEntry {
+    fn from(entry: proto::RelayDiffEntry) -> Self {
+        Self {
+            hostname: entry.hostname,
+            country_code: entry.country_code,
+            city_code: entry.city_code,
+        }
+    }
+}
+
+impl TryFrom<proto::TlsEndpointData> for mullvad_types::relay_list::TlsEndpointData {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(tls: proto::TlsEndpointData) -> Result<Self, Self::Error> {
+        Ok(Self {
+            port: u16::try_from(tls.port)
+                .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid TLS port"))?,
+            certificate_sha256: <[u8; 32]>::try_from(tls.certificate_sha256).map_err(|_| {
+                FromProtobufTypeError::InvalidArgument("invalid TLS certificate digest")
+            })?,
+        })
+    }
+}
@@ -0,0 +1,46 @@
+use crate::types::proto;
+use mullvad_types::runtime_config::{EnvOverride, RuntimeConfig};
+
+impl From<RuntimeConfig> for proto::RuntimeConfig {
+    fn from(config: RuntimeConfig) -> Self {
+        Self {
+            overrides: config
+                .overrides
+                .into_iter()
+                .map(proto::EnvOverride::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<EnvOverride> for proto::EnvOverride {
+    fn from(env_override: EnvOverride) -> Self {
+        Self {
+            name: env_override.name,
+            value: env_override.value,
+            ignored: env_override.ignored,
+        }
+    }
+}
+
+impl From<proto::RuntimeConfig> for RuntimeConfig {
+    fn from(config: proto::RuntimeConfig) -> Self {
+        Self {
+            overrides: config
+                .overrides
+                .into_iter()
+                .map(EnvOverride::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<proto::EnvOverride> for EnvOverride {
+    fn from(env_override: proto::EnvOverride) -> Self {
+        Self {
+            name: env_override.name,
+            value: env_override.value,
+            ignored: env_override.ignored,
+        }
+    }
+}
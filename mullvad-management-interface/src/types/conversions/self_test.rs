@@ -0,0 +1,40 @@
+use super::FromProtobufTypeError;
+use crate::types::proto;
+use talpid_types::self_test::{SelfTestStep, StepOutcome};
+
+impl From<SelfTestStep> for proto::SelfTestStep {
+    fn from(step: SelfTestStep) -> Self {
+        use proto::self_test_step::Outcome;
+
+        let (outcome, details) = match step.outcome {
+            StepOutcome::Passed => (Outcome::Passed, String::new()),
+            StepOutcome::Failed(error) => (Outcome::Failed, error),
+            StepOutcome::Skipped(reason) => (Outcome::Skipped, reason),
+        };
+        Self {
+            name: step.name,
+            outcome: i32::from(outcome),
+            details,
+        }
+    }
+}
+
+impl TryFrom<proto::SelfTestStep> for SelfTestStep {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(step: proto::SelfTestStep) -> Result<Self, Self::Error> {
+        use proto::self_test_step::Outcome;
+
+        let outcome = match Outcome::try_from(step.outcome)
+            .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid self test outcome"))?
+        {
+            Outcome::Passed => StepOutcome::Passed,
+            Outcome::Failed => StepOutcome::Failed(step.details),
+            Outcome::Skipped => StepOutcome::Skipped(step.details),
+        };
+        Ok(Self {
+            name: step.name,
+            outcome,
+        })
+    }
+}
@@ -48,6 +48,32 @@ impl From<&mullvad_types::settings::Settings> for proto::Settings {
             api_access_methods: Some(proto::ApiAccessMethodSettings::from(
                 settings.api_access_methods.clone(),
             )),
+            profiles: Some(proto::ProfileSettings::from(settings.profiles.clone())),
+            // The secret is left out, since it must not be readable by every client.
+            webhook: settings
+                .webhook
+                .as_ref()
+                .map(|webhook| proto::WebhookSettings {
+                    url: webhook.url.clone(),
+                    secret: String::new(),
+                }),
+            lan_beacon: settings.lan_beacon,
+            connection_check: Some(proto::ConnectionCheckSettings::from(
+                settings.connection_check.clone(),
+            )),
+            tunnel_protocol_fallback: Some(proto::TunnelProtocolFallback::from(
+                settings.tunnel_protocol_fallback,
+            )),
+            custom_api_endpoint: settings
+                .custom_api_endpoint
+                .clone()
+                .map(proto::CustomApiEndpoint::from),
+            removed_relay_policy: Some(proto::RemovedRelayPolicy::from(
+                settings.removed_relay_policy,
+            )),
+            ip_version_preference: Some(proto::IpVersionPreference::from(
+                settings.ip_version_preference,
+            )),
             relay_overrides: settings
                 .relay_overrides
                 .iter()
@@ -74,6 +100,12 @@ impl From<&mullvad_types::settings::DnsOptions> for proto::DnsOptions {
                 block_adult_content: options.default_options.block_adult_content,
                 block_gambling: options.default_options.block_gambling,
                 block_social_media: options.default_options.block_social_media,
+                custom_blocklists: options
+                    .default_options
+                    .custom_blocklists
+                    .iter()
+                    .map(proto::CustomBlocklist::from)
+                    .collect(),
             }),
             custom_options: Some(proto::CustomDnsOptions {
                 addresses: options
@@ -83,6 +115,22 @@ impl From<&mullvad_types::settings::DnsOptions> for proto::DnsOptions {
                     .map(|addr| addr.to_string())
                     .collect(),
             }),
+            block_on_unsupported_manager: options.block_on_unsupported_manager,
+        }
+    }
+}
+
+impl From<&mullvad_types::settings::CustomBlocklist> for proto::CustomBlocklist {
+    fn from(blocklist: &mullvad_types::settings::CustomBlocklist) -> Self {
+        use mullvad_types::settings::BlocklistSource;
+        use proto::custom_blocklist::Source;
+
+        proto::CustomBlocklist {
+            source: Some(match &blocklist.source {
+                BlocklistSource::Url(url) => Source::Url(url.clone()),
+                BlocklistSource::File(path) => Source::Path(path.to_string_lossy().into_owned()),
+            }),
+            enabled: blocklist.enabled,
         }
     }
 }
@@ -92,6 +140,12 @@ impl From<&mullvad_types::settings::TunnelOptions> for proto::TunnelOptions {
         Self {
             openvpn: Some(proto::tunnel_options::OpenvpnOptions {
                 mssfix: options.openvpn.mssfix.map(u32::from),
+                connect_timeout: options.openvpn.connect_timeout.map(u32::from),
+                retry_timeout: options.openvpn.retry_timeout.map(u32::from),
+                #[cfg(target_os = "linux")]
+                sandbox: Some(options.openvpn.sandbox),
+                #[cfg(not(target_os = "linux"))]
+                sandbox: None,
             }),
             wireguard: Some(proto::tunnel_options::WireguardOptions {
                 mtu: options.wireguard.mtu.map(u32::from),
@@ -104,11 +158,22 @@ impl From<&mullvad_types::settings::TunnelOptions> for proto::TunnelOptions {
                 daita: Some(proto::DaitaSettings::from(options.wireguard.daita.clone())),
                 #[cfg(not(target_os = "windows"))]
                 daita: None,
+                #[cfg(target_os = "linux")]
+                allowed_ips: Some(proto::WireguardAllowedIps::from(
+                    options.wireguard.allowed_ips.as_deref(),
+                )),
+                #[cfg(not(target_os = "linux"))]
+                allowed_ips: None,
+                #[cfg(target_os = "linux")]
+                egress_interface: options.wireguard.egress_interface.clone(),
+                #[cfg(not(target_os = "linux"))]
+                egress_interface: None,
             }),
             generic: Some(proto::tunnel_options::GenericOptions {
                 enable_ipv6: options.generic.enable_ipv6,
             }),
             dns_options: Some(proto::DnsOptions::from(&options.dns_options)),
+            connect_deadline: options.connect_deadline.map(u32::from),
         }
     }
 }
@@ -159,6 +224,11 @@ impl TryFrom<proto::Settings> for mullvad_types::settings::Settings {
                 .ok_or(FromProtobufTypeError::InvalidArgument(
                     "missing api access methods settings",
                 ))?;
+        let profiles_settings = settings
+            .profiles
+            .ok_or(FromProtobufTypeError::InvalidArgument(
+                "missing profiles settings",
+            ))?;
         #[cfg(any(windows, target_os = "android", target_os = "macos"))]
         let split_tunnel = settings
             .split_tunnel
@@ -183,6 +253,16 @@ impl TryFrom<proto::Settings> for mullvad_types::settings::Settings {
                 .into_iter()
                 .map(mullvad_types::relay_constraints::RelayOverride::try_from)
                 .collect::<Result<Vec<_>, _>>()?,
+            removed_relay_policy: settings
+                .removed_relay_policy
+                .map(mullvad_types::relay_constraints::RemovedRelayPolicy::try_from)
+                .transpose()?
+                .unwrap_or_default(),
+            ip_version_preference: settings
+                .ip_version_preference
+                .map(mullvad_types::relay_constraints::IpVersionPreference::try_from)
+                .transpose()?
+                .unwrap_or_default(),
             show_beta_releases: settings.show_beta_releases,
             #[cfg(any(windows, target_os = "android", target_os = "macos"))]
             split_tunnel: mullvad_types::settings::SplitTunnelSettings::from(split_tunnel),
@@ -198,6 +278,134 @@ impl TryFrom<proto::Settings> for mullvad_types::settings::Settings {
             api_access_methods: mullvad_types::access_method::Settings::try_from(
                 api_access_methods_settings,
             )?,
+            profiles: mullvad_types::profile::ProfilesSettings::try_from(profiles_settings)?,
+            webhook: settings
+                .webhook
+                .map(mullvad_types::settings::WebhookSettings::from),
+            lan_beacon: settings.lan_beacon,
+            connection_check: settings
+                .connection_check
+                .map(mullvad_types::settings::ConnectionCheckSettings::from)
+                .unwrap_or_default(),
+            tunnel_protocol_fallback: settings
+                .tunnel_protocol_fallback
+                .map(mullvad_types::settings::TunnelProtocolFallback::from)
+                .unwrap_or_default(),
+            custom_api_endpoint: settings
+                .custom_api_endpoint
+                .map(mullvad_types::settings::CustomApiEndpoint::try_from)
+                .transpose()?,
+            // The passphrase hash is never sent to clients. See `GetSettingsLockState`.
+            settings_lock: None,
+        })
+    }
+}
+
+impl From<mullvad_types::settings::SettingsLockState> for proto::SettingsLockState {
+    fn from(state: mullvad_types::settings::SettingsLockState) -> Self {
+        Self {
+            enabled: state.enabled,
+            unlocked_for: state.unlocked_for.map(|duration| {
+                prost_types::Duration::try_from(duration)
+                    .expect("Failed to convert std::time::Duration to prost_types::Duration")
+            }),
+        }
+    }
+}
+
+impl TryFrom<proto::SettingsLockState> for mullvad_types::settings::SettingsLockState {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(state: proto::SettingsLockState) -> Result<Self, Self::Error> {
+        Ok(Self {
+            enabled: state.enabled,
+            unlocked_for: state
+                .unlocked_for
+                .map(std::time::Duration::try_from)
+                .transpose()
+                .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid unlock duration"))?,
+        })
+    }
+}
+
+impl From<mullvad_types::settings::WebhookSettings> for proto::WebhookSettings {
+    fn from(webhook: mullvad_types::settings::WebhookSettings) -> Self {
+        Self {
+            url: webhook.url,
+            secret: webhook.secret,
+        }
+    }
+}
+
+impl From<proto::WebhookSettings> for mullvad_types::settings::WebhookSettings {
+    fn from(webhook: proto::WebhookSettings) -> Self {
+        Self {
+            url: webhook.url,
+            secret: webhook.secret,
+        }
+    }
+}
+
+impl From<mullvad_types::settings::ConnectionCheckSettings> for proto::ConnectionCheckSettings {
+    fn from(settings: mullvad_types::settings::ConnectionCheckSettings) -> Self {
+        Self {
+            host: settings.host,
+            verify_after_connect: settings.verify_after_connect,
+        }
+    }
+}
+
+impl From<proto::ConnectionCheckSettings> for mullvad_types::settings::ConnectionCheckSettings {
+    fn from(settings: proto::ConnectionCheckSettings) -> Self {
+        Self {
+            host: settings.host,
+            verify_after_connect: settings.verify_after_connect,
+        }
+    }
+}
+
+impl From<mullvad_types::settings::TunnelProtocolFallback> for proto::TunnelProtocolFallback {
+    fn from(fallback: mullvad_types::settings::TunnelProtocolFallback) -> Self {
+        Self {
+            enabled: fallback.enabled,
+            wireguard_attempts: fallback.wireguard_attempts,
+        }
+    }
+}
+
+impl From<proto::TunnelProtocolFallback> for mullvad_types::settings::TunnelProtocolFallback {
+    fn from(fallback: proto::TunnelProtocolFallback) -> Self {
+        Self {
+            enabled: fallback.enabled,
+            wireguard_attempts: fallback.wireguard_attempts,
+        }
+    }
+}
+
+impl From<mullvad_types::settings::CustomApiEndpoint> for proto::CustomApiEndpoint {
+    fn from(endpoint: mullvad_types::settings::CustomApiEndpoint) -> Self {
+        Self {
+            host: endpoint.host,
+            address: endpoint.address.to_string(),
+            root_certificates: endpoint.root_certificates,
+            disable_tls: endpoint.disable_tls,
+            force_direct: endpoint.force_direct,
+        }
+    }
+}
+
+impl TryFrom<proto::CustomApiEndpoint> for mullvad_types::settings::CustomApiEndpoint {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(endpoint: proto::CustomApiEndpoint) -> Result<Self, Self::Error> {
+        Ok(Self {
+            host: endpoint.host,
+            address: endpoint.address.parse().map_err(|_| {
+                FromProtobufTypeError::InvalidArgument("invalid custom API endpoint address")
+            })?,
+            root_certificates: endpoint.root_certificates,
+            disable_tls: endpoint.disable_tls,
+            force_direct: endpoint.force_direct,
         })
     }
 }
@@ -260,6 +468,16 @@ impl TryFrom<proto::TunnelOptions> for mullvad_types::settings::TunnelOptions {
         Ok(Self {
             openvpn: net::openvpn::TunnelOptions {
                 mssfix: openvpn_options.mssfix.map(|mssfix| mssfix as u16),
+                connect_timeout: openvpn_options
+                    .connect_timeout
+                    .map(|timeout| timeout as u16),
+                retry_timeout: openvpn_options.retry_timeout.map(|timeout| timeout as u16),
+                #[cfg(target_os = "linux")]
+                sandbox: openvpn_options
+                    .sandbox
+                    .ok_or(FromProtobufTypeError::InvalidArgument(
+                        "missing OpenVPN sandbox setting",
+                    ))?,
             },
             wireguard: mullvad_types::wireguard::TunnelOptions {
                 mtu: wireguard_options.mtu.map(|mtu| mtu as u16),
@@ -290,11 +508,43 @@ impl TryFrom<proto::TunnelOptions> for mullvad_types::settings::TunnelOptions {
                     .ok_or(FromProtobufTypeError::InvalidArgument(
                         "missing daita settings",
                     ))?,
+                #[cfg(target_os = "linux")]
+                allowed_ips: wireguard_options
+                    .allowed_ips
+                    .map(Option::<Vec<ipnetwork::IpNetwork>>::try_from)
+                    .transpose()?
+                    .flatten(),
+                #[cfg(target_os = "linux")]
+                egress_interface: wireguard_options.egress_interface,
             },
             generic: net::GenericTunnelOptions {
                 enable_ipv6: generic_options.enable_ipv6,
             },
             dns_options: mullvad_types::settings::DnsOptions::try_from(dns_options)?,
+            connect_deadline: options.connect_deadline.map(|deadline| deadline as u16),
+        })
+    }
+}
+
+impl TryFrom<proto::CustomBlocklist> for mullvad_types::settings::CustomBlocklist {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(blocklist: proto::CustomBlocklist) -> Result<Self, Self::Error> {
+        use mullvad_types::settings::BlocklistSource;
+        use proto::custom_blocklist::Source;
+
+        let source = match blocklist.source {
+            Some(Source::Url(url)) => BlocklistSource::Url(url),
+            Some(Source::Path(path)) => BlocklistSource::File(path.into()),
+            None => {
+                return Err(FromProtobufTypeError::InvalidArgument(
+                    "missing blocklist source",
+                ))
+            }
+        };
+        Ok(mullvad_types::settings::CustomBlocklist {
+            source,
+            enabled: blocklist.enabled,
         })
     }
 }
@@ -304,7 +554,7 @@ impl TryFrom<proto::DnsOptions> for mullvad_types::settings::DnsOptions {
 
     fn try_from(options: proto::DnsOptions) -> Result<Self, Self::Error> {
         use mullvad_types::settings::{
-            CustomDnsOptions as MullvadCustomDnsOptions,
+            CustomBlocklist as MullvadCustomBlocklist, CustomDnsOptions as MullvadCustomDnsOptions,
             DefaultDnsOptions as MullvadDefaultDnsOptions, DnsOptions as MullvadDnsOptions,
             DnsState as MullvadDnsState,
         };
@@ -341,6 +591,11 @@ impl TryFrom<proto::DnsOptions> for mullvad_types::settings::DnsOptions {
                 block_adult_content: default_options.block_adult_content,
                 block_gambling: default_options.block_gambling,
                 block_social_media: default_options.block_social_media,
+                custom_blocklists: default_options
+                    .custom_blocklists
+                    .into_iter()
+                    .map(MullvadCustomBlocklist::try_from)
+                    .collect::<Result<Vec<_>, _>>()?,
             },
             custom_options: MullvadCustomDnsOptions {
                 addresses: custom_options
@@ -353,6 +608,7 @@ impl TryFrom<proto::DnsOptions> for mullvad_types::settings::DnsOptions {
                     })
                     .collect::<Result<Vec<_>, _>>()?,
             },
+            block_on_unsupported_manager: options.block_on_unsupported_manager,
         })
     }
 }
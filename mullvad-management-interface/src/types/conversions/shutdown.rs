@@ -0,0 +1,54 @@
+use super::FromProtobufTypeError;
+use crate::types::proto;
+use mullvad_types::shutdown::ShutdownBehavior;
+
+impl From<ShutdownBehavior> for proto::ShutdownBehavior {
+    fn from(behavior: ShutdownBehavior) -> Self {
+        use proto::shutdown_behavior::Behavior;
+
+        let (behavior, keep_tunnel_for) = match behavior {
+            ShutdownBehavior::Auto => (Behavior::Auto, None),
+            ShutdownBehavior::Block => (Behavior::Block, None),
+            ShutdownBehavior::Restore => (Behavior::Restore, None),
+            ShutdownBehavior::KeepTunnel(duration) => (
+                Behavior::KeepTunnel,
+                Some(
+                    prost_types::Duration::try_from(duration)
+                        .expect("Failed to convert std::time::Duration to prost_types::Duration"),
+                ),
+            ),
+        };
+        proto::ShutdownBehavior {
+            behavior: i32::from(behavior),
+            keep_tunnel_for,
+        }
+    }
+}
+
+impl TryFrom<proto::ShutdownBehavior> for ShutdownBehavior {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(behavior: proto::ShutdownBehavior) -> Result<Self, Self::Error> {
+        use proto::shutdown_behavior::Behavior;
+
+        match Behavior::try_from(behavior.behavior) {
+            Ok(Behavior::Auto) => Ok(ShutdownBehavior::Auto),
+            Ok(Behavior::Block) => Ok(ShutdownBehavior::Block),
+            Ok(Behavior::Restore) => Ok(ShutdownBehavior::Restore),
+            Ok(Behavior::KeepTunnel) => {
+                let duration =
+                    behavior
+                        .keep_tunnel_for
+                        .ok_or(FromProtobufTypeError::InvalidArgument(
+                            "missing duration to keep the tunnel for",
+                        ))?;
+                let duration = std::time::Duration::try_from(duration)
+                    .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid duration"))?;
+                Ok(ShutdownBehavior::KeepTunnel(duration))
+            }
+            Err(_) => Err(FromProtobufTypeError::InvalidArgument(
+                "invalid shutdown behavior",
+            )),
+        }
+    }
+}
@@ -12,8 +12,9 @@ impl From<mullvad_types::states::TunnelState> for proto::TunnelState {
 
         let map_firewall_error =
             |firewall_error: &talpid_tunnel::FirewallPolicyError| match firewall_error {
-                talpid_tunnel::FirewallPolicyError::Generic => FirewallPolicyError {
+                talpid_tunnel::FirewallPolicyError::Generic(details) => FirewallPolicyError {
                     r#type: i32::from(PolicyErrorType::Generic),
+                    details: Some(proto::error_state::ErrorDetails::from(details.clone())),
                     ..Default::default()
                 },
                 #[cfg(windows)]
@@ -27,6 +28,7 @@ impl From<mullvad_types::states::TunnelState> for proto::TunnelState {
                         r#type: i32::from(PolicyErrorType::Locked),
                         lock_pid,
                         lock_name,
+                        details: None,
                     }
                 }
             };
@@ -47,14 +49,17 @@ impl From<mullvad_types::states::TunnelState> for proto::TunnelState {
                     }),
                 })
             }
-            MullvadTunnelState::Connected { endpoint, location } => {
-                proto::tunnel_state::State::Connected(proto::tunnel_state::Connected {
-                    relay_info: Some(proto::TunnelStateRelayInfo {
-                        tunnel_endpoint: Some(proto::TunnelEndpoint::from(endpoint)),
-                        location: location.map(proto::GeoIpLocation::from),
-                    }),
-                })
-            }
+            MullvadTunnelState::Connected {
+                endpoint,
+                location,
+                connect_trace,
+            } => proto::tunnel_state::State::Connected(proto::tunnel_state::Connected {
+                relay_info: Some(proto::TunnelStateRelayInfo {
+                    tunnel_endpoint: Some(proto::TunnelEndpoint::from(endpoint)),
+                    location: location.map(proto::GeoIpLocation::from),
+                }),
+                connect_trace: Some(proto::ConnectTrace::from(connect_trace)),
+            }),
             MullvadTunnelState::Disconnecting(after_disconnect) => {
                 proto::tunnel_state::State::Disconnecting(proto::tunnel_state::Disconnecting {
                     after_disconnect: match after_disconnect {
@@ -83,7 +88,7 @@ impl From<mullvad_types::states::TunnelState> for proto::TunnelState {
                             talpid_tunnel::ErrorStateCause::SetFirewallPolicyError(_) => {
                                 i32::from(Cause::SetFirewallPolicyError)
                             }
-                            talpid_tunnel::ErrorStateCause::SetDnsError => {
+                            talpid_tunnel::ErrorStateCause::SetDnsError { .. } => {
                                 i32::from(Cause::SetDnsError)
                             }
                             talpid_tunnel::ErrorStateCause::StartTunnelError => {
@@ -115,6 +120,10 @@ impl From<mullvad_types::states::TunnelState> for proto::TunnelState {
                             talpid_tunnel::ErrorStateCause::NeedFullDiskPermissions => {
                                 i32::from(Cause::NeedFullDiskPermissions)
                             }
+                            #[cfg(target_os = "linux")]
+                            talpid_tunnel::ErrorStateCause::UnsupportedDnsManager(_) => {
+                                i32::from(Cause::UnsupportedDnsManager)
+                            }
                         },
                         blocking_error: error_state.block_failure().map(map_firewall_error),
                         auth_failed_error: mullvad_types::auth_failed::AuthFailed::try_from(
@@ -163,6 +172,32 @@ impl From<mullvad_types::states::TunnelState> for proto::TunnelState {
                             }
                             _ => None,
                         },
+                        error_code: i32::from(proto::ErrorCode::from(error_state.cause())),
+                        conflicting_software: error_state.conflicting_software().to_vec(),
+                        #[cfg(not(target_os = "linux"))]
+                        dns_manager: None,
+                        #[cfg(target_os = "linux")]
+                        dns_manager: match error_state.cause() {
+                            talpid_tunnel::ErrorStateCause::UnsupportedDnsManager(manager) => {
+                                Some(manager.clone())
+                            }
+                            _ => None,
+                        },
+                        dns_error: match error_state.cause() {
+                            talpid_tunnel::ErrorStateCause::SetDnsError { mechanism, details } => {
+                                Some(proto::error_state::DnsError {
+                                    mechanism: mechanism.map(|mechanism| {
+                                        i32::from(proto::error_state::dns_error::Mechanism::from(
+                                            mechanism,
+                                        ))
+                                    }),
+                                    details: Some(proto::error_state::ErrorDetails::from(
+                                        details.clone(),
+                                    )),
+                                })
+                            }
+                            _ => None,
+                        },
                     }),
                 })
             }
@@ -241,11 +276,16 @@ impl TryFrom<proto::TunnelState> for mullvad_types::states::TunnelState {
                         tunnel_endpoint: Some(tunnel_endpoint),
                         location,
                     }),
+                connect_trace,
             })) => MullvadState::Connected {
                 endpoint: talpid_net::TunnelEndpoint::try_from(tunnel_endpoint)?,
                 location: location
                     .map(mullvad_types::location::GeoIpLocation::try_from)
                     .transpose()?,
+                connect_trace: connect_trace
+                    .map(talpid_types::tunnel::ConnectTrace::try_from)
+                    .transpose()?
+                    .unwrap_or_default(),
             },
             Some(proto::tunnel_state::State::Disconnecting(
                 proto::tunnel_state::Disconnecting { after_disconnect },
@@ -276,10 +316,16 @@ impl TryFrom<proto::TunnelState> for mullvad_types::states::TunnelState {
                         parameter_error,
                         policy_error,
                         create_tunnel_error,
+                        error_code: _,
+                        conflicting_software,
+                        dns_manager,
+                        dns_error,
                     }),
             })) => {
                 #[cfg(not(target_os = "windows"))]
                 let _ = create_tunnel_error;
+                #[cfg(not(target_os = "linux"))]
+                let _ = dns_manager;
 
                 let cause = match proto::error_state::Cause::try_from(cause) {
                     Ok(proto::error_state::Cause::AuthFailed) => {
@@ -295,17 +341,23 @@ impl TryFrom<proto::TunnelState> for mullvad_types::states::TunnelState {
                         talpid_tunnel::ErrorStateCause::IsOffline
                     }
                     Ok(proto::error_state::Cause::SetDnsError) => {
-                        talpid_tunnel::ErrorStateCause::SetDnsError
+                        let dns_error = dns_error.unwrap_or_default();
+                        talpid_tunnel::ErrorStateCause::SetDnsError {
+                            mechanism: dns_error
+                                .mechanism
+                                .map(try_dns_mechanism_from_i32)
+                                .transpose()?,
+                            details: dns_error
+                                .details
+                                .map(talpid_tunnel::ErrorDetails::from)
+                                .unwrap_or_default(),
+                        }
                     }
                     Ok(proto::error_state::Cause::SetFirewallPolicyError) => {
                         let policy_error = policy_error.ok_or(
                             FromProtobufTypeError::InvalidArgument("missing firewall policy error"),
                         )?;
-                        let policy_error = try_firewall_policy_error_from_i32(
-                            policy_error.r#type,
-                            policy_error.lock_pid,
-                            policy_error.lock_name,
-                        )?;
+                        let policy_error = try_firewall_policy_error(policy_error)?;
                         talpid_tunnel::ErrorStateCause::SetFirewallPolicyError(policy_error)
                     }
                     Ok(proto::error_state::Cause::StartTunnelError) => {
@@ -341,6 +393,12 @@ impl TryFrom<proto::TunnelState> for mullvad_types::states::TunnelState {
                     Ok(proto::error_state::Cause::NeedFullDiskPermissions) => {
                         talpid_tunnel::ErrorStateCause::NeedFullDiskPermissions
                     }
+                    #[cfg(target_os = "linux")]
+                    Ok(proto::error_state::Cause::UnsupportedDnsManager) => {
+                        talpid_tunnel::ErrorStateCause::UnsupportedDnsManager(dns_manager.ok_or(
+                            FromProtobufTypeError::InvalidArgument("missing DNS manager"),
+                        )?)
+                    }
                     _ => {
                         return Err(FromProtobufTypeError::InvalidArgument(
                             "invalid error cause",
@@ -348,17 +406,12 @@ impl TryFrom<proto::TunnelState> for mullvad_types::states::TunnelState {
                     }
                 };
 
-                let block_failure = blocking_error
-                    .map(|blocking_error| {
-                        try_firewall_policy_error_from_i32(
-                            blocking_error.r#type,
-                            blocking_error.lock_pid,
-                            blocking_error.lock_name,
-                        )
-                    })
-                    .transpose()?;
+                let block_failure = blocking_error.map(try_firewall_policy_error).transpose()?;
 
-                MullvadState::Error(talpid_tunnel::ErrorState::new(cause, block_failure))
+                MullvadState::Error(
+                    talpid_tunnel::ErrorState::new(cause, block_failure)
+                        .with_conflicting_software(conflicting_software),
+                )
             }
             _ => {
                 return Err(FromProtobufTypeError::InvalidArgument(
@@ -371,22 +424,348 @@ impl TryFrom<proto::TunnelState> for mullvad_types::states::TunnelState {
     }
 }
 
-#[cfg_attr(not(target_os = "windows"), allow(unused_variables))]
-fn try_firewall_policy_error_from_i32(
-    policy_error: i32,
-    lock_pid: u32,
-    lock_name: Option<String>,
+impl From<talpid_types::tunnel::ConnectTrace> for proto::ConnectTrace {
+    fn from(trace: talpid_types::tunnel::ConnectTrace) -> Self {
+        proto::ConnectTrace {
+            total: Some(to_proto_duration(trace.total)),
+            phases: trace
+                .phases
+                .into_iter()
+                .map(|(phase, duration)| proto::connect_trace::PhaseTiming {
+                    phase: i32::from(proto::connect_trace::Phase::from(phase)),
+                    duration: Some(to_proto_duration(duration)),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<proto::ConnectTrace> for talpid_types::tunnel::ConnectTrace {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(trace: proto::ConnectTrace) -> Result<Self, Self::Error> {
+        let phases = trace
+            .phases
+            .into_iter()
+            .map(|timing| {
+                Ok((
+                    try_connect_phase_from_i32(timing.phase)?,
+                    from_proto_duration(timing.duration)?,
+                ))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(talpid_types::tunnel::ConnectTrace {
+            total: from_proto_duration(trace.total)?,
+            phases,
+        })
+    }
+}
+
+impl From<talpid_types::tunnel::ConnectProgress> for proto::ConnectProgress {
+    fn from(progress: talpid_types::tunnel::ConnectProgress) -> Self {
+        proto::ConnectProgress {
+            phase: i32::from(proto::connect_trace::Phase::from(progress.phase)),
+            finished: progress.finished,
+            elapsed: Some(to_proto_duration(progress.elapsed)),
+        }
+    }
+}
+
+impl TryFrom<proto::ConnectProgress> for talpid_types::tunnel::ConnectProgress {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(progress: proto::ConnectProgress) -> Result<Self, Self::Error> {
+        Ok(talpid_types::tunnel::ConnectProgress {
+            phase: try_connect_phase_from_i32(progress.phase)?,
+            finished: progress.finished,
+            elapsed: from_proto_duration(progress.elapsed)?,
+        })
+    }
+}
+
+impl From<talpid_types::tunnel::ConnectPhase> for proto::connect_trace::Phase {
+    fn from(phase: talpid_types::tunnel::ConnectPhase) -> Self {
+        use talpid_types::tunnel::ConnectPhase;
+
+        match phase {
+            ConnectPhase::RelaySelection => Self::RelaySelection,
+            ConnectPhase::Obfuscation => Self::Obfuscation,
+            ConnectPhase::TunnelCreation => Self::TunnelCreation,
+            ConnectPhase::Handshake => Self::Handshake,
+            ConnectPhase::Routes => Self::Routes,
+            ConnectPhase::Dns => Self::Dns,
+            ConnectPhase::Firewall => Self::Firewall,
+        }
+    }
+}
+
+fn try_connect_phase_from_i32(
+    phase: i32,
+) -> Result<talpid_types::tunnel::ConnectPhase, FromProtobufTypeError> {
+    use proto::connect_trace::Phase;
+    use talpid_types::tunnel::ConnectPhase;
+
+    match Phase::try_from(phase) {
+        Ok(Phase::RelaySelection) => Ok(ConnectPhase::RelaySelection),
+        Ok(Phase::Obfuscation) => Ok(ConnectPhase::Obfuscation),
+        Ok(Phase::TunnelCreation) => Ok(ConnectPhase::TunnelCreation),
+        Ok(Phase::Handshake) => Ok(ConnectPhase::Handshake),
+        Ok(Phase::Routes) => Ok(ConnectPhase::Routes),
+        Ok(Phase::Dns) => Ok(ConnectPhase::Dns),
+        Ok(Phase::Firewall) => Ok(ConnectPhase::Firewall),
+        Err(_) => Err(FromProtobufTypeError::InvalidArgument(
+            "invalid connect phase",
+        )),
+    }
+}
+
+fn to_proto_duration(duration: std::time::Duration) -> prost_types::Duration {
+    prost_types::Duration::try_from(duration)
+        .expect("Failed to convert std::time::Duration to prost_types::Duration")
+}
+
+fn from_proto_duration(
+    duration: Option<prost_types::Duration>,
+) -> Result<std::time::Duration, FromProtobufTypeError> {
+    duration
+        .map(std::time::Duration::try_from)
+        .transpose()
+        .map_err(|_| FromProtobufTypeError::InvalidArgument("invalid duration"))
+        .map(Option::unwrap_or_default)
+}
+
+impl From<mullvad_types::states::ShutdownReport> for proto::ShutdownReport {
+    fn from(report: mullvad_types::states::ShutdownReport) -> Self {
+        proto::ShutdownReport {
+            time: Some(prost_types::Timestamp {
+                seconds: report.time.timestamp(),
+                nanos: 0,
+            }),
+            steps: report
+                .teardown
+                .steps
+                .into_iter()
+                .map(|result| proto::shutdown_report::StepResult {
+                    step: i32::from(proto::shutdown_report::TeardownStep::from(result.step)),
+                    duration: Some(to_proto_duration(result.duration)),
+                    error: result.error.map(proto::error_state::ErrorDetails::from),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<proto::ShutdownReport> for mullvad_types::states::ShutdownReport {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(report: proto::ShutdownReport) -> Result<Self, Self::Error> {
+        let time = report
+            .time
+            .and_then(|time| chrono::DateTime::from_timestamp(time.seconds, 0))
+            .ok_or(FromProtobufTypeError::InvalidArgument("invalid timestamp"))?;
+        let steps = report
+            .steps
+            .into_iter()
+            .map(|result| {
+                Ok(talpid_types::tunnel::TeardownStepResult {
+                    step: try_teardown_step_from_i32(result.step)?,
+                    duration: from_proto_duration(result.duration)?,
+                    error: result.error.map(talpid_types::tunnel::ErrorDetails::from),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(mullvad_types::states::ShutdownReport {
+            time,
+            teardown: talpid_types::tunnel::TeardownReport { steps },
+        })
+    }
+}
+
+impl From<talpid_types::tunnel::TeardownStep> for proto::shutdown_report::TeardownStep {
+    fn from(step: talpid_types::tunnel::TeardownStep) -> Self {
+        use talpid_types::tunnel::TeardownStep;
+
+        match step {
+            TeardownStep::TunnelStop => Self::TunnelStop,
+            TeardownStep::RouteRestore => Self::RouteRestore,
+            TeardownStep::DnsRestore => Self::DnsRestore,
+            TeardownStep::FirewallReset => Self::FirewallReset,
+        }
+    }
+}
+
+fn try_teardown_step_from_i32(
+    step: i32,
+) -> Result<talpid_types::tunnel::TeardownStep, FromProtobufTypeError> {
+    use proto::shutdown_report::TeardownStep as ProtoStep;
+    use talpid_types::tunnel::TeardownStep;
+
+    match ProtoStep::try_from(step) {
+        Ok(ProtoStep::TunnelStop) => Ok(TeardownStep::TunnelStop),
+        Ok(ProtoStep::RouteRestore) => Ok(TeardownStep::RouteRestore),
+        Ok(ProtoStep::DnsRestore) => Ok(TeardownStep::DnsRestore),
+        Ok(ProtoStep::FirewallReset) => Ok(TeardownStep::FirewallReset),
+        Err(_) => Err(FromProtobufTypeError::InvalidArgument(
+            "invalid teardown step",
+        )),
+    }
+}
+
+impl From<mullvad_types::states::ConnectEscalation> for proto::ConnectEscalation {
+    fn from(escalation: mullvad_types::states::ConnectEscalation) -> Self {
+        proto::ConnectEscalation {
+            endpoint: Some(proto::TunnelEndpoint::from(escalation.endpoint)),
+            escalation: escalation.escalation,
+        }
+    }
+}
+
+impl TryFrom<proto::ConnectEscalation> for mullvad_types::states::ConnectEscalation {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(escalation: proto::ConnectEscalation) -> Result<Self, Self::Error> {
+        let endpoint = escalation
+            .endpoint
+            .ok_or(FromProtobufTypeError::InvalidArgument(
+                "missing tunnel endpoint",
+            ))?;
+        Ok(mullvad_types::states::ConnectEscalation {
+            endpoint: talpid_types::net::TunnelEndpoint::try_from(endpoint)?,
+            escalation: escalation.escalation,
+        })
+    }
+}
+
+impl From<mullvad_types::states::LockdownCountdown> for proto::LockdownCountdown {
+    fn from(countdown: mullvad_types::states::LockdownCountdown) -> Self {
+        use mullvad_types::states::LockdownCountdown;
+        use proto::lockdown_countdown::State;
+
+        let (state, remaining_seconds) = match countdown {
+            LockdownCountdown::Remaining(seconds) => (State::CountingDown, seconds),
+            LockdownCountdown::Enabled => (State::Enabled, 0),
+            LockdownCountdown::Cancelled => (State::Cancelled, 0),
+        };
+        proto::LockdownCountdown {
+            state: i32::from(state),
+            remaining_seconds,
+        }
+    }
+}
+
+impl TryFrom<proto::LockdownCountdown> for mullvad_types::states::LockdownCountdown {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(countdown: proto::LockdownCountdown) -> Result<Self, Self::Error> {
+        use proto::lockdown_countdown::State;
+
+        match State::try_from(countdown.state) {
+            Ok(State::CountingDown) => Ok(Self::Remaining(countdown.remaining_seconds)),
+            Ok(State::Enabled) => Ok(Self::Enabled),
+            Ok(State::Cancelled) => Ok(Self::Cancelled),
+            Err(_) => Err(FromProtobufTypeError::InvalidArgument(
+                "invalid lockdown countdown state",
+            )),
+        }
+    }
+}
+
+impl From<mullvad_types::states::PersistedTarget> for proto::PersistedTarget {
+    fn from(persisted: mullvad_types::states::PersistedTarget) -> Self {
+        use mullvad_types::states::TargetState;
+        use proto::persisted_target::TargetState as ProtoTargetState;
+
+        Self {
+            target_state: i32::from(match persisted.target_state {
+                TargetState::Unsecured => ProtoTargetState::Unsecured,
+                TargetState::Secured => ProtoTargetState::Secured,
+            }),
+            relay: persisted.relay.map(|relay| proto::LastConnectedRelay {
+                tunnel_type: i32::from(match relay.tunnel_type {
+                    talpid_types::net::TunnelType::Wireguard => proto::TunnelType::Wireguard,
+                    talpid_types::net::TunnelType::OpenVpn => proto::TunnelType::Openvpn,
+                }),
+                hostname: relay.hostname,
+                entry_hostname: relay.entry_hostname,
+                endpoint: Some(proto::Endpoint {
+                    address: relay.endpoint.address.to_string(),
+                    protocol: i32::from(proto::TransportProtocol::from(relay.endpoint.protocol)),
+                }),
+                obfuscated: relay.obfuscated,
+            }),
+        }
+    }
+}
+
+impl TryFrom<proto::PersistedTarget> for mullvad_types::states::PersistedTarget {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(persisted: proto::PersistedTarget) -> Result<Self, Self::Error> {
+        use mullvad_types::states::{LastConnectedRelay, TargetState};
+        use proto::persisted_target::TargetState as ProtoTargetState;
+
+        let target_state = match ProtoTargetState::try_from(persisted.target_state) {
+            Ok(ProtoTargetState::Unsecured) => TargetState::Unsecured,
+            Ok(ProtoTargetState::Secured) => TargetState::Secured,
+            Err(_) => {
+                return Err(FromProtobufTypeError::InvalidArgument(
+                    "invalid target state",
+                ))
+            }
+        };
+        let relay = persisted
+            .relay
+            .map(|relay| {
+                let endpoint = relay
+                    .endpoint
+                    .ok_or(FromProtobufTypeError::InvalidArgument(
+                        "missing relay endpoint",
+                    ))?;
+                Ok(LastConnectedRelay {
+                    tunnel_type: super::net::try_tunnel_type_from_i32(relay.tunnel_type)?,
+                    hostname: relay.hostname,
+                    entry_hostname: relay.entry_hostname,
+                    endpoint: talpid_types::net::Endpoint {
+                        address: super::arg_from_str(
+                            &endpoint.address,
+                            "invalid relay endpoint address",
+                        )?,
+                        protocol: super::net::try_transport_protocol_from_i32(endpoint.protocol)?,
+                    },
+                    obfuscated: relay.obfuscated,
+                })
+            })
+            .transpose()?;
+        Ok(Self {
+            target_state,
+            relay,
+        })
+    }
+}
+
+fn try_firewall_policy_error(
+    policy_error: proto::error_state::FirewallPolicyError,
 ) -> Result<talpid_types::tunnel::FirewallPolicyError, FromProtobufTypeError> {
-    match proto::error_state::firewall_policy_error::ErrorType::try_from(policy_error) {
+    match proto::error_state::firewall_policy_error::ErrorType::try_from(policy_error.r#type) {
         Ok(proto::error_state::firewall_policy_error::ErrorType::Generic) => {
-            Ok(talpid_types::tunnel::FirewallPolicyError::Generic)
+            Ok(talpid_types::tunnel::FirewallPolicyError::Generic(
+                policy_error
+                    .details
+                    .map(talpid_types::tunnel::ErrorDetails::from)
+                    .unwrap_or_default(),
+            ))
         }
         #[cfg(windows)]
         Ok(proto::error_state::firewall_policy_error::ErrorType::Locked) => {
-            let blocking_app = lock_name.map(|name| talpid_types::tunnel::BlockingApplication {
-                pid: lock_pid,
-                name,
-            });
+            let blocking_app =
+                policy_error
+                    .lock_name
+                    .map(|name| talpid_types::tunnel::BlockingApplication {
+                        pid: policy_error.lock_pid,
+                        name,
+                    });
             Ok(talpid_types::tunnel::FirewallPolicyError::Locked(
                 blocking_app,
             ))
@@ -396,3 +775,58 @@ fn try_firewall_policy_error_from_i32(
         )),
     }
 }
+
+impl From<talpid_types::tunnel::ErrorDetails> for proto::error_state::ErrorDetails {
+    fn from(details: talpid_types::tunnel::ErrorDetails) -> Self {
+        proto::error_state::ErrorDetails {
+            os_error: details.os_error,
+            message: details.message,
+        }
+    }
+}
+
+impl From<proto::error_state::ErrorDetails> for talpid_types::tunnel::ErrorDetails {
+    fn from(details: proto::error_state::ErrorDetails) -> Self {
+        talpid_types::tunnel::ErrorDetails {
+            os_error: details.os_error,
+            message: details.message,
+        }
+    }
+}
+
+impl From<talpid_types::tunnel::DnsMechanism> for proto::error_state::dns_error::Mechanism {
+    fn from(mechanism: talpid_types::tunnel::DnsMechanism) -> Self {
+        use proto::error_state::dns_error::Mechanism;
+        use talpid_types::tunnel::DnsMechanism;
+        match mechanism {
+            DnsMechanism::SystemdResolved => Mechanism::SystemdResolved,
+            DnsMechanism::NetworkManager => Mechanism::NetworkManager,
+            DnsMechanism::Resolvconf => Mechanism::Resolvconf,
+            DnsMechanism::StaticResolvConf => Mechanism::StaticResolvConf,
+            DnsMechanism::SystemConfiguration => Mechanism::SystemConfiguration,
+            DnsMechanism::Iphlpapi => Mechanism::Iphlpapi,
+            DnsMechanism::Netsh => Mechanism::Netsh,
+            DnsMechanism::Tcpip => Mechanism::Tcpip,
+        }
+    }
+}
+
+fn try_dns_mechanism_from_i32(
+    mechanism: i32,
+) -> Result<talpid_types::tunnel::DnsMechanism, FromProtobufTypeError> {
+    use proto::error_state::dns_error::Mechanism;
+    use talpid_types::tunnel::DnsMechanism;
+    match Mechanism::try_from(mechanism) {
+        Ok(Mechanism::SystemdResolved) => Ok(DnsMechanism::SystemdResolved),
+        Ok(Mechanism::NetworkManager) => Ok(DnsMechanism::NetworkManager),
+        Ok(Mechanism::Resolvconf) => Ok(DnsMechanism::Resolvconf),
+        Ok(Mechanism::StaticResolvConf) => Ok(DnsMechanism::StaticResolvConf),
+        Ok(Mechanism::SystemConfiguration) => Ok(DnsMechanism::SystemConfiguration),
+        Ok(Mechanism::Iphlpapi) => Ok(DnsMechanism::Iphlpapi),
+        Ok(Mechanism::Netsh) => Ok(DnsMechanism::Netsh),
+        Ok(Mechanism::Tcpip) => Ok(DnsMechanism::Tcpip),
+        Err(_) => Err(FromProtobufTypeError::InvalidArgument(
+            "invalid DNS mechanism",
+        )),
+    }
+}
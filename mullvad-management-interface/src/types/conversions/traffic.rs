@@ -0,0 +1,83 @@
+use crate::types::proto;
+use mullvad_types::traffic::{MonthlyObfuscationTraffic, MonthlyTraffic, TrafficStats};
+
+impl From<TrafficStats> for proto::TrafficStats {
+    fn from(stats: TrafficStats) -> Self {
+        let (months, obfuscation) = stats.into_parts();
+        Self {
+            months: months
+                .into_iter()
+                .map(proto::MonthlyTraffic::from)
+                .collect(),
+            obfuscation: obfuscation
+                .into_iter()
+                .map(proto::MonthlyObfuscationTraffic::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<MonthlyTraffic> for proto::MonthlyTraffic {
+    fn from(traffic: MonthlyTraffic) -> Self {
+        Self {
+            year: traffic.year,
+            month: traffic.month,
+            interface: traffic.interface,
+            rx_bytes: traffic.rx_bytes,
+            tx_bytes: traffic.tx_bytes,
+        }
+    }
+}
+
+impl From<MonthlyObfuscationTraffic> for proto::MonthlyObfuscationTraffic {
+    fn from(traffic: MonthlyObfuscationTraffic) -> Self {
+        Self {
+            year: traffic.year,
+            month: traffic.month,
+            method: traffic.method,
+            payload_rx_bytes: traffic.payload_rx_bytes,
+            payload_tx_bytes: traffic.payload_tx_bytes,
+            wire_rx_bytes: traffic.wire_rx_bytes,
+            wire_tx_bytes: traffic.wire_tx_bytes,
+        }
+    }
+}
+
+impl From<proto::TrafficStats> for TrafficStats {
+    fn from(stats: proto::TrafficStats) -> Self {
+        TrafficStats::new(
+            stats.months.into_iter().map(MonthlyTraffic::from).collect(),
+            stats
+                .obfuscation
+                .into_iter()
+                .map(MonthlyObfuscationTraffic::from)
+                .collect(),
+        )
+    }
+}
+
+impl From<proto::MonthlyTraffic> for MonthlyTraffic {
+    fn from(traffic: proto::MonthlyTraffic) -> Self {
+        Self {
+            year: traffic.year,
+            month: traffic.month,
+            interface: traffic.interface,
+            rx_bytes: traffic.rx_bytes,
+            tx_bytes: traffic.tx_bytes,
+        }
+    }
+}
+
+impl From<proto::MonthlyObfuscationTraffic> for MonthlyObfuscationTraffic {
+    fn from(traffic: proto::MonthlyObfuscationTraffic) -> Self {
+        Self {
+            year: traffic.year,
+            month: traffic.month,
+            method: traffic.method,
+            payload_rx_bytes: traffic.payload_rx_bytes,
+            payload_tx_bytes: traffic.payload_tx_bytes,
+            wire_rx_bytes: traffic.wire_rx_bytes,
+            wire_tx_bytes: traffic.wire_tx_bytes,
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::types::proto;
+use mullvad_types::version::{AppUpgradeAvailable, UpdateChannel};
 
 impl From<mullvad_types::version::AppVersionInfo> for proto::AppVersionInfo {
     fn from(version_info: mullvad_types::version::AppVersionInfo) -> Self {
@@ -6,7 +7,9 @@ impl From<mullvad_types::version::AppVersionInfo> for proto::AppVersionInfo {
             supported: version_info.supported,
             latest_stable: version_info.latest_stable,
             latest_beta: version_info.latest_beta,
-            suggested_upgrade: version_info.suggested_upgrade,
+            suggested_upgrade: version_info
+                .suggested_upgrade
+                .map(proto::AppUpgradeAvailable::from),
         }
     }
 }
@@ -17,7 +20,42 @@ impl From<proto::AppVersionInfo> for mullvad_types::version::AppVersionInfo {
             supported: version_info.supported,
             latest_stable: version_info.latest_stable,
             latest_beta: version_info.latest_beta,
-            suggested_upgrade: version_info.suggested_upgrade,
+            suggested_upgrade: version_info
+                .suggested_upgrade
+                .map(AppUpgradeAvailable::from),
+        }
+    }
+}
+
+impl From<AppUpgradeAvailable> for proto::AppUpgradeAvailable {
+    fn from(upgrade: AppUpgradeAvailable) -> Self {
+        use proto::app_upgrade_available::UpdateChannel as ProtoUpdateChannel;
+
+        let channel = match upgrade.channel {
+            UpdateChannel::Stable => ProtoUpdateChannel::Stable,
+            UpdateChannel::Beta => ProtoUpdateChannel::Beta,
+        };
+        Self {
+            version: upgrade.version,
+            channel: i32::from(channel),
+            changelog_url: upgrade.changelog_url,
+        }
+    }
+}
+
+impl From<proto::AppUpgradeAvailable> for AppUpgradeAvailable {
+    fn from(upgrade: proto::AppUpgradeAvailable) -> Self {
+        use proto::app_upgrade_available::UpdateChannel as ProtoUpdateChannel;
+
+        // Unknown channels are treated as stable, since that is the most conservative choice
+        let channel = match ProtoUpdateChannel::try_from(upgrade.channel) {
+            Ok(ProtoUpdateChannel::Beta) => UpdateChannel::Beta,
+            Ok(ProtoUpdateChannel::Stable) | Err(_) => UpdateChannel::Stable,
+        };
+        Self {
+            version: upgrade.version,
+            channel,
+            changelog_url: upgrade.changelog_url,
         }
     }
 }
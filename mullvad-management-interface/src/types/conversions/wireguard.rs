@@ -36,6 +36,50 @@ impl TryFrom<proto::PublicKey> for mullvad_types::wireguard::PublicKey {
     }
 }
 
+impl From<mullvad_types::wireguard::KeyRotationProgress> for proto::KeyRotationProgress {
+    fn from(progress: mullvad_types::wireguard::KeyRotationProgress) -> Self {
+        use mullvad_types::wireguard::KeyRotationProgress;
+        use proto::key_rotation_progress::Stage;
+
+        let (stage, public_key) = match progress {
+            KeyRotationProgress::Uploading => (Stage::Uploading, None),
+            KeyRotationProgress::Replaced(public_key) => {
+                (Stage::Replaced, Some(proto::PublicKey::from(public_key)))
+            }
+            KeyRotationProgress::Reconnecting => (Stage::Reconnecting, None),
+        };
+        proto::KeyRotationProgress {
+            stage: i32::from(stage),
+            public_key,
+        }
+    }
+}
+
+impl TryFrom<proto::KeyRotationProgress> for mullvad_types::wireguard::KeyRotationProgress {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(progress: proto::KeyRotationProgress) -> Result<Self, Self::Error> {
+        use mullvad_types::wireguard::{KeyRotationProgress, PublicKey};
+        use proto::key_rotation_progress::Stage;
+
+        match Stage::try_from(progress.stage) {
+            Ok(Stage::Uploading) => Ok(KeyRotationProgress::Uploading),
+            Ok(Stage::Replaced) => {
+                let public_key = progress
+                    .public_key
+                    .ok_or(FromProtobufTypeError::InvalidArgument("missing public key"))?;
+                Ok(KeyRotationProgress::Replaced(PublicKey::try_from(
+                    public_key,
+                )?))
+            }
+            Ok(Stage::Reconnecting) => Ok(KeyRotationProgress::Reconnecting),
+            Err(_) => Err(FromProtobufTypeError::InvalidArgument(
+                "invalid key rotation stage",
+            )),
+        }
+    }
+}
+
 impl From<mullvad_types::wireguard::QuantumResistantState> for proto::QuantumResistantState {
     fn from(state: mullvad_types::wireguard::QuantumResistantState) -> Self {
         match state {
@@ -90,3 +134,35 @@ impl From<proto::DaitaSettings> for mullvad_types::wireguard::DaitaSettings {
         }
     }
 }
+
+impl From<Option<&[ipnetwork::IpNetwork]>> for proto::WireguardAllowedIps {
+    fn from(allowed_ips: Option<&[ipnetwork::IpNetwork]>) -> Self {
+        proto::WireguardAllowedIps {
+            networks: allowed_ips
+                .unwrap_or_default()
+                .iter()
+                .map(|network| network.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<proto::WireguardAllowedIps> for Option<Vec<ipnetwork::IpNetwork>> {
+    type Error = FromProtobufTypeError;
+
+    fn try_from(allowed_ips: proto::WireguardAllowedIps) -> Result<Self, Self::Error> {
+        if allowed_ips.networks.is_empty() {
+            return Ok(None);
+        }
+        allowed_ips
+            .networks
+            .iter()
+            .map(|network| {
+                network.parse().map_err(|_| {
+                    FromProtobufTypeError::InvalidArgument("invalid allowed IP network")
+                })
+            })
+            .collect::<Result<_, _>>()
+            .map(Some)
+    }
+}
@@ -38,7 +38,7 @@ pub fn get_default_cache_dir() -> Result<PathBuf> {
         {
             dir = std::path::Path::new("/Library/Caches").join(crate::PRODUCT_NAME);
         }
-        Ok(dir)
+        Ok(crate::instance::instance_dir(dir))
     }
     #[cfg(target_os = "android")]
     {
@@ -0,0 +1,51 @@
+use std::{env, ffi::OsString, path::PathBuf};
+
+/// Environment variable that holds the ID of the daemon instance. This lets several daemons run
+/// side by side on one host, for example in tests. Each instance gets its own settings, cache and
+/// log directories and its own RPC socket by default.
+pub const INSTANCE_VAR: &str = "MULLVAD_INSTANCE";
+
+/// Highest instance ID. IDs are kept small, since names such as the tunnel interface name are
+/// derived from them.
+pub const MAX_INSTANCE_ID: u8 = 99;
+
+/// Returns the ID of this instance, from 1 to [`MAX_INSTANCE_ID`], or `None` for the default
+/// instance. Invalid IDs are ignored.
+pub fn instance_id() -> Option<u8> {
+    let value = env::var_os(INSTANCE_VAR)?;
+    let id = parse_instance_id(&value.to_string_lossy());
+    #[cfg(not(target_os = "android"))]
+    if id.is_none() {
+        log::warn!("Ignoring invalid {INSTANCE_VAR} value: {value:?}");
+    }
+    id
+}
+
+/// Parses an instance ID from 1 to [`MAX_INSTANCE_ID`].
+pub fn parse_instance_id(value: &str) -> Option<u8> {
+    value
+        .parse()
+        .ok()
+        .filter(|id| (1..=MAX_INSTANCE_ID).contains(id))
+}
+
+/// Returns the directory of this instance inside `dir`, or `dir` itself for the default instance.
+pub(crate) fn instance_dir(dir: PathBuf) -> PathBuf {
+    match instance_id() {
+        Some(id) => dir.join(format!("instance-{id}")),
+        None => dir,
+    }
+}
+
+/// Returns `path` with the instance ID appended, or `path` itself for the default instance. This
+/// is for paths that are files rather than directories, such as the RPC socket.
+pub(crate) fn instance_path(path: PathBuf) -> PathBuf {
+    match instance_id() {
+        Some(id) => {
+            let mut path = OsString::from(path);
+            path.push(format!("-instance-{id}"));
+            PathBuf::from(path)
+        }
+        None => path,
+    }
+}
@@ -69,6 +69,8 @@ fn create_and_return(
     Ok(dir)
 }
 
+pub mod instance;
+
 mod cache;
 pub use crate::cache::{cache_dir, get_cache_dir, get_default_cache_dir};
 
@@ -38,7 +38,7 @@ pub fn get_default_log_dir() -> Result<PathBuf> {
         {
             dir = crate::get_allusersprofile_dir();
         }
-        dir.map(|dir| dir.join(crate::PRODUCT_NAME))
+        dir.map(|dir| crate::instance::instance_dir(dir.join(crate::PRODUCT_NAME)))
     }
     #[cfg(target_os = "android")]
     {
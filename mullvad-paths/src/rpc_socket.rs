@@ -9,15 +9,15 @@ pub fn get_rpc_socket_path() -> PathBuf {
 
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 pub fn get_default_rpc_socket_path() -> PathBuf {
-    PathBuf::from("/var/run/mullvad-vpn")
+    crate::instance::instance_path(PathBuf::from("/var/run/mullvad-vpn"))
 }
 
 #[cfg(windows)]
 pub fn get_default_rpc_socket_path() -> PathBuf {
-    PathBuf::from("//./pipe/Mullvad VPN")
+    crate::instance::instance_path(PathBuf::from("//./pipe/Mullvad VPN"))
 }
 
 #[cfg(target_os = "android")]
 pub fn get_default_rpc_socket_path() -> PathBuf {
-    PathBuf::from(format!("{}/rpc-socket", crate::APP_PATH))
+    crate::instance::instance_path(PathBuf::from(format!("{}/rpc-socket", crate::APP_PATH)))
 }
@@ -36,7 +36,7 @@ pub fn get_default_settings_dir() -> Result<PathBuf> {
             crate::Error::FindDirError
         })
     }
-    dir.map(|dir| dir.join(crate::PRODUCT_NAME))
+    dir.map(|dir| crate::instance::instance_dir(dir.join(crate::PRODUCT_NAME)))
 }
 
 #[cfg(target_os = "android")]
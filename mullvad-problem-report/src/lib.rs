@@ -282,6 +282,18 @@ pub fn send_problem_report(
     ))
 }
 
+/// Prepares a report that was read by someone else for sending. Only the last part of the report
+/// is kept if it is too large. Returns the report and its metadata.
+pub fn prepare_report(report: &str) -> (String, BTreeMap<String, String>) {
+    let mut start = report.len().saturating_sub(REPORT_MAX_SIZE);
+    while !report.is_char_boundary(start) {
+        start += 1;
+    }
+    let report = normalize_newlines(report[start..].to_owned());
+    let metadata = ProblemReport::parse_metadata(&report).unwrap_or_else(metadata::collect);
+    (report, metadata)
+}
+
 async fn send_problem_report_inner(
     user_email: &str,
     user_message: &str,
@@ -292,8 +304,7 @@ async fn send_problem_report_inner(
     let api_runtime = mullvad_api::Runtime::with_cache(
         cache_dir,
         false,
-        #[cfg(target_os = "android")]
-        None,
+        mullvad_api::SocketProtection::default(),
     )
     .await
     .map_err(Error::CreateRpcClientError)?;
@@ -396,11 +407,7 @@ impl ProblemReport {
     }
 
     fn redact(&self, input: &str) -> String {
-        let out1 = Self::redact_account_number(input);
-        let out2 = Self::redact_home_dir(&out1);
-        let out3 = Self::redact_network_info(&out2);
-        let out4 = Self::redact_guids(&out3);
-        self.redact_custom_strings(&out4).to_string()
+        self.redact_custom_strings(&redact(input)).to_string()
     }
 
     fn redact_account_number(input: &str) -> Cow<'_, str> {
@@ -487,6 +494,14 @@ impl ProblemReport {
     }
 }
 
+/// Remove account numbers, the home directory, IP and MAC addresses, and GUIDs from `input`.
+pub fn redact(input: &str) -> String {
+    let out1 = ProblemReport::redact_account_number(input);
+    let out2 = ProblemReport::redact_home_dir(&out1);
+    let out3 = ProblemReport::redact_network_info(&out2);
+    ProblemReport::redact_guids(&out3).into_owned()
+}
+
 fn redact_home_dir_inner(input: &str, home_dir: Option<PathBuf>) -> Cow<'_, str> {
     match home_dir {
         Some(home) => {
@@ -707,4 +722,12 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn prepare_report_keeps_end() {
+        let report = format!("å{}", "a".repeat(REPORT_MAX_SIZE - 1));
+        let (prepared, _) = prepare_report(&report);
+        assert_eq!(prepared.len(), REPORT_MAX_SIZE - 1);
+        assert!(prepared.chars().all(|c| c == 'a'));
+    }
 }
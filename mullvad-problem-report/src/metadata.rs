@@ -9,5 +9,13 @@ pub fn collect() -> BTreeMap<String, String> {
     );
     metadata.insert("os".to_owned(), talpid_platform_metadata::version());
     metadata.extend(talpid_platform_metadata::extra_metadata());
+
+    let conflicting_software = talpid_platform_metadata::find_conflicting_software();
+    if !conflicting_software.is_empty() {
+        metadata.insert(
+            "conflicting-software".to_owned(),
+            conflicting_software.join(", "),
+        );
+    }
     metadata
 }
@@ -3,8 +3,12 @@
 #![allow(rustdoc::private_intra_doc_links)]
 mod constants;
 mod error;
+#[cfg(feature = "relay-filter-hook")]
+pub mod relay_filter;
 #[cfg_attr(target_os = "android", allow(unused))]
 mod relay_selector;
+#[cfg(feature = "test-relays")]
+pub mod test_relays;
 
 // Re-exports
 pub use error::Error;
@@ -0,0 +1,119 @@
+//! A hook for a compiled-in filter that can veto or boost relays. It lets distributors of the app
+//! enforce their own policy on which relays are used, without changing the selection logic.
+//!
+//! The filter is only consulted for relays and bridges that match all other constraints, so it
+//! can not make the relay selector pick a relay that the user has excluded. If the filter vetoes
+//! every candidate, no relay is selected.
+
+use mullvad_types::relay_list::Relay;
+use once_cell::sync::OnceCell;
+
+static RELAY_FILTER: OnceCell<Box<dyn RelayFilter>> = OnceCell::new();
+
+/// What to do with a relay that matches all constraints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Select the relay as usual.
+    Keep,
+    /// Never select the relay.
+    Veto,
+    /// Multiply the weight of the relay by the given factor. A factor above 1 makes the relay
+    /// more likely to be selected, and 0 makes it a last resort.
+    Boost(u64),
+}
+
+/// Decides whether relays may be selected. Any `Fn(&Relay) -> Verdict` can be used.
+pub trait RelayFilter: Send + Sync {
+    /// Decide what to do with `relay`. This is called on every relay selection, so it should be
+    /// fast.
+    fn evaluate(&self, relay: &Relay) -> Verdict;
+}
+
+impl<F: Fn(&Relay) -> Verdict + Send + Sync> RelayFilter for F {
+    fn evaluate(&self, relay: &Relay) -> Verdict {
+        self(relay)
+    }
+}
+
+/// Returned by [`register`] if a filter has already been registered.
+#[derive(thiserror::Error, Debug)]
+#[error("A relay filter has already been registered")]
+pub struct AlreadyRegistered;
+
+/// Register the filter to apply to every relay selection in this process. Only one filter can be
+/// registered, and it can not be replaced, so this should be called once before the daemon starts.
+pub fn register(filter: impl RelayFilter + 'static) -> Result<(), AlreadyRegistered> {
+    RELAY_FILTER
+        .set(Box::new(filter))
+        .map_err(|_| AlreadyRegistered)
+}
+
+/// Apply the registered filter, if there is one, to `relays`.
+pub(crate) fn apply(relays: Vec<Relay>) -> Vec<Relay> {
+    match RELAY_FILTER.get() {
+        Some(filter) => apply_filter(filter.as_ref(), relays),
+        None => relays,
+    }
+}
+
+fn apply_filter(filter: &dyn RelayFilter, relays: Vec<Relay>) -> Vec<Relay> {
+    relays
+        .into_iter()
+        .filter_map(|mut relay| match filter.evaluate(&relay) {
+            Verdict::Keep => Some(relay),
+            Verdict::Veto => {
+                log::trace!("Relay {} was vetoed by the relay filter", relay.hostname);
+                None
+            }
+            Verdict::Boost(factor) => {
+                relay.weight = relay.weight.saturating_mul(factor);
+                Some(relay)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mullvad_types::relay_list::RelayEndpointData;
+
+    fn relay(hostname: &str, provider: &str) -> Relay {
+        Relay {
+            hostname: hostname.to_owned(),
+            ipv4_addr_in: "10.0.0.1".parse().unwrap(),
+            ipv6_addr_in: None,
+            include_in_country: true,
+            active: true,
+            owned: true,
+            provider: provider.to_owned(),
+            weight: 10,
+            stboot: false,
+            endpoint_data: RelayEndpointData::Openvpn,
+            location: None,
+        }
+    }
+
+    #[test]
+    fn test_apply_filter() {
+        let filter = |relay: &Relay| match relay.provider.as_str() {
+            "vetoed" => Verdict::Veto,
+            "boosted" => Verdict::Boost(3),
+            _ => Verdict::Keep,
+        };
+        let relays = apply_filter(
+            &filter,
+            vec![
+                relay("se-got-001", "vetoed"),
+                relay("se-got-002", "boosted"),
+                relay("se-got-003", "other"),
+            ],
+        );
+
+        let weights: Vec<_> = relays
+            .iter()
+            .map(|relay| (relay.hostname.as_str(), relay.weight))
+            .collect();
+        assert_eq!(weights, [("se-got-002", 30), ("se-got-003", 10)]);
+    }
+}
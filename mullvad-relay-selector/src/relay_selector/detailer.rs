@@ -13,7 +13,7 @@ use ipnetwork::IpNetwork;
 use mullvad_types::{
     constraints::Constraint,
     endpoint::MullvadWireguardEndpoint,
-    relay_constraints::TransportPort,
+    relay_constraints::{PortRange, TransportPort},
     relay_list::{
         BridgeEndpointData, OpenVpnEndpoint, OpenVpnEndpointData, Relay, RelayEndpointData,
         WireguardEndpointData,
@@ -43,6 +43,10 @@ pub enum Error {
     NoIPv6(Box<Relay>),
     #[error("Invalid port argument: port {0} is not in any valid Wireguard port range")]
     PortNotInRange(u16),
+    #[error("Port {port} is not in the allowed port range {range}")]
+    PortNotInAllowedRange { port: u16, range: PortRange },
+    #[error("No valid Wireguard port is in the allowed port range {0}")]
+    NoPortInAllowedRange(PortRange),
     #[error("Port selection algorithm is broken")]
     PortSelectionAlgorithm,
 }
@@ -73,26 +77,45 @@ fn wireguard_singlehop_endpoint(
     data: &WireguardEndpointData,
     exit: &Relay,
 ) -> Result<MullvadWireguardEndpoint, Error> {
-    let endpoint = {
-        let host = get_address_for_wireguard_relay(query, exit)?;
-        let port = get_port_for_wireguard_relay(query, data)?;
-        SocketAddr::new(host, port)
-    };
-    let peer_config = PeerConfig {
-        public_key: get_public_key(exit)?.clone(),
+    let port = get_port_for_wireguard_relay(query, data)?;
+    Ok(MullvadWireguardEndpoint {
+        peer: wireguard_singlehop_peer(query, exit, port)?,
+        exit_peer: None,
+        candidate_peer: None,
+        ipv4_gateway: data.ipv4_gateway,
+        ipv6_gateway: data.ipv6_gateway,
+    })
+}
+
+/// Configure a peer for `candidate`, a relay to connect to in parallel with the entry peer of
+/// `endpoint`. The candidate is reached on the same port as the entry peer.
+pub fn wireguard_candidate_peer(
+    query: &WireguardRelayQuery,
+    endpoint: &MullvadWireguardEndpoint,
+    candidate: &Relay,
+) -> Result<PeerConfig, Error> {
+    wireguard_singlehop_peer(query, candidate, endpoint.peer.endpoint.port())
+}
+
+/// Configure a peer that routes all traffic through `relay`.
+fn wireguard_singlehop_peer(
+    query: &WireguardRelayQuery,
+    relay: &Relay,
+    port: u16,
+) -> Result<PeerConfig, Error> {
+    let endpoint = SocketAddr::new(get_address_for_wireguard_relay(query, relay)?, port);
+    let alternate_endpoint = get_alternate_address_for_wireguard_relay(query, relay)
+        .map(|host| SocketAddr::new(host, port));
+    Ok(PeerConfig {
+        public_key: get_public_key(relay)?.clone(),
         endpoint,
+        alternate_endpoint,
         allowed_ips: all_of_the_internet(),
         // This will be filled in later, not the relay selector's problem
         psk: None,
         // This will be filled in later
         #[cfg(target_os = "windows")]
         constant_packet_size: false,
-    };
-    Ok(MullvadWireguardEndpoint {
-        peer: peer_config,
-        exit_peer: None,
-        ipv4_gateway: data.ipv4_gateway,
-        ipv6_gateway: data.ipv6_gateway,
     })
 }
 
@@ -120,6 +143,7 @@ fn wireguard_multihop_endpoint(
     let exit = PeerConfig {
         public_key: get_public_key(exit)?.clone(),
         endpoint: exit_endpoint,
+        alternate_endpoint: None,
         // The exit peer should be able to route incoming VPN traffic to the rest of
         // the internet.
         allowed_ips: all_of_the_internet(),
@@ -135,9 +159,12 @@ fn wireguard_multihop_endpoint(
         let port = get_port_for_wireguard_relay(query, data)?;
         SocketAddr::from((host, port))
     };
+    let alternate_endpoint = get_alternate_address_for_wireguard_relay(query, entry)
+        .map(|host| SocketAddr::new(host, entry_endpoint.port()));
     let entry = PeerConfig {
         public_key: get_public_key(entry)?.clone(),
         endpoint: entry_endpoint,
+        alternate_endpoint,
         // The entry peer should only be able to route incoming VPN traffic to the
         // exit peer.
         allowed_ips: vec![IpNetwork::from(exit.endpoint.ip())],
@@ -151,6 +178,7 @@ fn wireguard_multihop_endpoint(
     Ok(MullvadWireguardEndpoint {
         peer: entry,
         exit_peer: Some(exit),
+        candidate_peer: None,
         ipv4_gateway: data.ipv4_gateway,
         ipv6_gateway: data.ipv6_gateway,
     })
@@ -170,14 +198,40 @@ fn get_address_for_wireguard_relay(
     }
 }
 
+/// Get the IPv6 address of the given relay if the query allows either IP version, so that the
+/// tunnel can switch to it if the path over IPv4 stops working.
+fn get_alternate_address_for_wireguard_relay(
+    query: &WireguardRelayQuery,
+    relay: &Relay,
+) -> Option<IpAddr> {
+    match query.ip_version {
+        Constraint::Any => relay.ipv6_addr_in.map(IpAddr::from),
+        Constraint::Only(_) => None,
+    }
+}
+
 /// Try to pick a valid Wireguard port.
 fn get_port_for_wireguard_relay(
     query: &WireguardRelayQuery,
     data: &WireguardEndpointData,
 ) -> Result<u16, Error> {
     match query.port {
-        Constraint::Any => select_random_port(&data.port_ranges),
+        Constraint::Any => match query.port_range {
+            Constraint::Any => select_random_port(&data.port_ranges),
+            Constraint::Only(range) => {
+                let port_ranges = restrict_port_ranges(&data.port_ranges, range);
+                if port_ranges.is_empty() {
+                    return Err(Error::NoPortInAllowedRange(range));
+                }
+                select_random_port(&port_ranges)
+            }
+        },
         Constraint::Only(port) => {
+            if let Constraint::Only(range) = query.port_range {
+                if !range.contains(port) {
+                    return Err(Error::PortNotInAllowedRange { port, range });
+                }
+            }
             if data
                 .port_ranges
                 .iter()
@@ -200,6 +254,15 @@ const fn get_public_key(relay: &Relay) -> Result<&PublicKey, Error> {
     }
 }
 
+/// Returns the parts of `port_ranges` that are within `allowed`.
+fn restrict_port_ranges(port_ranges: &[(u16, u16)], allowed: PortRange) -> Vec<(u16, u16)> {
+    port_ranges
+        .iter()
+        .map(|&(start, end)| (start.max(allowed.start()), end.min(allowed.end())))
+        .filter(|(start, end)| start <= end)
+        .collect()
+}
+
 /// Selects a random port number from a list of provided port ranges.
 ///
 /// This function iterates over a list of port ranges, each represented as a tuple (u16, u16)
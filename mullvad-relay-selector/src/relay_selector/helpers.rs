@@ -3,8 +3,12 @@
 use std::net::SocketAddr;
 
 use mullvad_types::{
-    constraints::Constraint, endpoint::MullvadWireguardEndpoint,
-    relay_constraints::Udp2TcpObfuscationSettings, relay_list::Relay,
+    constraints::Constraint,
+    endpoint::MullvadWireguardEndpoint,
+    relay_constraints::{
+        MultihopDiversity, TlsObfuscationSettings, Udp2TcpObfuscationSettings, DEFAULT_TLS_SNI,
+    },
+    relay_list::{Relay, RelayEndpointData, WireguardRelayEndpointData},
 };
 use rand::{seq::SliceRandom, thread_rng, Rng};
 use talpid_types::net::obfuscation::ObfuscatorConfig;
@@ -57,6 +61,35 @@ pub fn pick_random_relay_weighted<RelayType>(
     }
 }
 
+/// Pick an exit and an entry relay that differ in the ways given by `diversity`, according to the
+/// weights of the relays. Returns `None` if no such pair of relays exists.
+pub fn pick_diverse_relays<'a>(
+    exits: &'a [Relay],
+    entries: &'a [Relay],
+    diversity: MultihopDiversity,
+) -> Option<(&'a Relay, &'a Relay)> {
+    let is_diverse = |exit: &Relay, entry: &Relay| {
+        let same_country = match (&exit.location, &entry.location) {
+            (Some(exit), Some(entry)) => exit.country_code == entry.country_code,
+            _ => true,
+        };
+        !(diversity.country && same_country
+            || diversity.provider && exit.provider == entry.provider)
+    };
+
+    let exits: Vec<&Relay> = exits
+        .iter()
+        .filter(|exit| entries.iter().any(|entry| is_diverse(exit, entry)))
+        .collect();
+    let exit = *pick_random_relay_weighted(&exits, |relay| relay.weight)?;
+    let entries: Vec<&Relay> = entries
+        .iter()
+        .filter(|entry| is_diverse(exit, entry))
+        .collect();
+    let entry = *pick_random_relay_weighted(&entries, |relay| relay.weight)?;
+    Some((exit, entry))
+}
+
 pub fn get_udp2tcp_obfuscator(
     obfuscation_settings_constraint: &Constraint<Udp2TcpObfuscationSettings>,
     udp2tcp_ports: &[u16],
@@ -87,3 +120,28 @@ pub fn get_udp2tcp_obfuscator_port(
         Constraint::Any | Constraint::Only(_) => udp2tcp_ports.choose(&mut thread_rng()).copied(),
     }
 }
+
+/// Returns a TLS obfuscator for `relay`, or `None` if the relay does not accept TLS obfuscated
+/// tunnels.
+pub fn get_tls_obfuscator(
+    obfuscation_settings_constraint: &Constraint<TlsObfuscationSettings>,
+    relay: Relay,
+    endpoint: &MullvadWireguardEndpoint,
+) -> Option<SelectedObfuscator> {
+    let RelayEndpointData::Wireguard(WireguardRelayEndpointData { tls: Some(tls), .. }) =
+        &relay.endpoint_data
+    else {
+        return None;
+    };
+    let sni = match obfuscation_settings_constraint {
+        Constraint::Only(obfuscation_settings) => obfuscation_settings.sni.clone(),
+        Constraint::Any => DEFAULT_TLS_SNI.to_owned(),
+    };
+    let config = ObfuscatorConfig::Tls {
+        endpoint: SocketAddr::new(endpoint.peer.endpoint.ip(), tls.port),
+        sni,
+        certificate_sha256: tls.certificate_sha256,
+    };
+
+    Some(SelectedObfuscator { config, relay })
+}
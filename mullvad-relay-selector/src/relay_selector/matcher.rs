@@ -1,24 +1,25 @@
 //! This module is responsible for filtering the whole relay list based on queries.
-use std::collections::HashSet;
+use std::{collections::HashSet, time::Duration};
 
 use mullvad_types::{
     constraints::{Constraint, Match},
     custom_list::CustomListsSettings,
     relay_constraints::{
         GeographicLocationConstraint, InternalBridgeConstraints, LocationConstraint, Ownership,
-        Providers,
+        Providers, SelectedObfuscation,
     },
     relay_list::{Relay, RelayEndpointData, WireguardRelayEndpointData},
 };
-use talpid_types::net::TunnelType;
+use talpid_types::net::{wireguard, TunnelType};
 
-use super::query::RelayQuery;
+use super::{parsed_relays::RelayLatencies, query::RelayQuery};
 
 /// Filter a list of relays and their endpoints based on constraints.
 /// Only relays with (and including) matching endpoints are returned.
 pub fn filter_matching_relay_list<'a, R: Iterator<Item = &'a Relay> + Clone>(
     query: &RelayQuery,
     relays: R,
+    latencies: &RelayLatencies,
     custom_lists: &CustomListsSettings,
 ) -> Vec<Relay> {
     let locations = ResolvedLocationConstraint::from_constraint(&query.location, custom_lists);
@@ -34,14 +35,26 @@ pub fn filter_matching_relay_list<'a, R: Iterator<Item = &'a Relay> + Clone>(
             // Filter by providers
             .filter(|relay| filter_on_providers(&query.providers, relay))
             // Filter by DAITA support
-            .filter(|relay| filter_on_daita(&query.wireguard_constraints.daita, relay));
+            .filter(|relay| filter_on_daita(&query.wireguard_constraints.daita, relay))
+            // Filter by QUIC support
+            .filter(|relay| filter_on_quic(&query.wireguard_constraints.quic, relay))
+            // Filter by TLS obfuscation support
+            .filter(|relay| filter_on_tls(&query.wireguard_constraints.obfuscation, relay))
+            // Filter by stboot
+            .filter(|relay| filter_on_stboot(&query.wireguard_constraints.stboot, relay))
+            // Filter by measured latency
+            .filter(|relay| {
+                filter_on_latency(&query.wireguard_constraints.max_latency, latencies, relay)
+            })
+            // Filter by public key
+            .filter(|relay| filter_on_public_key(&query.wireguard_constraints.public_key, relay));
 
     // The last filtering to be done is on the `include_in_country` attribute found on each
     // relay. When the location constraint is based on country, a relay which has
     // `include_in_country` set to true should always be prioritized over relays which has this
     // flag set to false. We should only consider relays with `include_in_country` set to false
     // if there are no other candidates left.
-    match &locations {
+    let relays = match &locations {
         Constraint::Any => shortlist.cloned().collect(),
         Constraint::Only(locations) => {
             let mut included = HashSet::new();
@@ -59,7 +72,8 @@ pub fn filter_matching_relay_list<'a, R: Iterator<Item = &'a Relay> + Clone>(
                 included.into_iter().cloned().collect()
             }
         }
-    }
+    };
+    apply_relay_filter(relays)
 }
 
 pub fn filter_matching_bridges<'a, R: Iterator<Item = &'a Relay> + Clone>(
@@ -69,7 +83,7 @@ pub fn filter_matching_bridges<'a, R: Iterator<Item = &'a Relay> + Clone>(
 ) -> Vec<Relay> {
     let locations =
         ResolvedLocationConstraint::from_constraint(&constraints.location, custom_lists);
-    relays
+    let bridges = relays
             // Filter on active relays
             .filter(|relay| filter_on_active(relay))
             // Filter on bridge type
@@ -81,7 +95,19 @@ pub fn filter_matching_bridges<'a, R: Iterator<Item = &'a Relay> + Clone>(
             // Filter by providers
             .filter(|relay| filter_on_providers(&constraints.providers, relay))
             .cloned()
-            .collect()
+            .collect();
+    apply_relay_filter(bridges)
+}
+
+/// Let the compiled-in relay filter veto or boost the relays that matched all constraints.
+#[cfg(feature = "relay-filter-hook")]
+fn apply_relay_filter(relays: Vec<Relay>) -> Vec<Relay> {
+    crate::relay_filter::apply(relays)
+}
+
+#[cfg(not(feature = "relay-filter-hook"))]
+const fn apply_relay_filter(relays: Vec<Relay>) -> Vec<Relay> {
+    relays
 }
 
 // --- Define relay filters as simple functions / predicates ---
@@ -123,6 +149,65 @@ pub fn filter_on_daita(filter: &Constraint<bool>, relay: &Relay) -> bool {
     }
 }
 
+/// Returns whether `relay` satisfy the QUIC constraint posed by `filter`.
+pub fn filter_on_quic(filter: &Constraint<bool>, relay: &Relay) -> bool {
+    match (filter, &relay.endpoint_data) {
+        (
+            Constraint::Only(true),
+            RelayEndpointData::Wireguard(WireguardRelayEndpointData { quic, .. }),
+        ) => *quic,
+        // If we don't require QUIC, any relay works.
+        _ => true,
+    }
+}
+
+/// Returns whether `relay` can be used with the obfuscation method `obfuscation`. Only relays that
+/// publish a TLS endpoint can be used with TLS obfuscation.
+pub fn filter_on_tls(obfuscation: &SelectedObfuscation, relay: &Relay) -> bool {
+    match (obfuscation, &relay.endpoint_data) {
+        (
+            SelectedObfuscation::Tls,
+            RelayEndpointData::Wireguard(WireguardRelayEndpointData { tls, .. }),
+        ) => tls.is_some(),
+        _ => true,
+    }
+}
+
+/// Returns whether `relay` satisfy the stboot constraint posed by `filter`.
+pub const fn filter_on_stboot(filter: &Constraint<bool>, relay: &Relay) -> bool {
+    match filter {
+        Constraint::Only(true) => relay.stboot,
+        _ => true,
+    }
+}
+
+/// Returns whether `relay` satisfy the latency constraint posed by `filter`. Relays that have not
+/// been pinged are assumed to satisfy it.
+pub fn filter_on_latency(
+    filter: &Constraint<u16>,
+    latencies: &RelayLatencies,
+    relay: &Relay,
+) -> bool {
+    match (filter, latencies.get(&relay.hostname)) {
+        (Constraint::Only(max_latency), Some(latency)) => {
+            latency.is_some_and(|latency| latency <= Duration::from_millis(u64::from(*max_latency)))
+        }
+        _ => true,
+    }
+}
+
+/// Returns whether `relay` satisfy the public key constraint posed by `filter`. Only WireGuard
+/// relays have public keys, so other relays always satisfy it.
+pub fn filter_on_public_key(filter: &Constraint<wireguard::PublicKey>, relay: &Relay) -> bool {
+    match (filter, &relay.endpoint_data) {
+        (
+            Constraint::Only(key),
+            RelayEndpointData::Wireguard(WireguardRelayEndpointData { public_key, .. }),
+        ) => public_key == key,
+        _ => true,
+    }
+}
+
 /// Returns whether the relay is an OpenVPN relay.
 pub const fn filter_openvpn(relay: &Relay) -> bool {
     matches!(relay.endpoint_data, RelayEndpointData::Openvpn)
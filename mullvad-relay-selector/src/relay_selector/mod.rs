@@ -5,6 +5,7 @@ mod helpers;
 mod matcher;
 mod parsed_relays;
 pub mod query;
+mod relay_index;
 
 use chrono::{DateTime, Local};
 use itertools::Itertools;
@@ -22,17 +23,21 @@ use mullvad_types::{
     endpoint::MullvadWireguardEndpoint,
     location::{Coordinates, Location},
     relay_constraints::{
-        BridgeSettings, BridgeState, InternalBridgeConstraints, ObfuscationSettings,
-        OpenVpnConstraints, RelayConstraints, RelayOverride, RelaySettings, ResolvedBridgeSettings,
-        SelectedObfuscation, WireguardConstraints,
+        BridgeSettings, BridgeState, GeographicLocationConstraint, InternalBridgeConstraints,
+        IpVersionPreference, LocationConstraint, ObfuscationSettings, OpenVpnConstraints,
+        RelayConstraints, RelayOverride, RelaySettings, ResolvedBridgeSettings,
+        SelectedObfuscation, TransportPort, WireguardConstraints,
     },
-    relay_list::{Relay, RelayEndpointData, RelayList},
+    relay_list::{Relay, RelayEndpointData, RelayLatency, RelayList},
     settings::Settings,
+    states::LastConnectedRelay,
     CustomTunnelEndpoint, Intersection,
 };
 use talpid_types::{
     net::{
-        obfuscation::ObfuscatorConfig, proxy::CustomProxy, Endpoint, TransportProtocol, TunnelType,
+        obfuscation::ObfuscatorConfig,
+        proxy::{CustomProxy, ProxyChain},
+        Endpoint, IpVersion, TransportProtocol, TunnelType,
     },
     ErrorExt,
 };
@@ -40,7 +45,7 @@ use talpid_types::{
 use crate::error::{EndpointErrorDetails, Error};
 
 use self::{
-    detailer::{openvpn_endpoint, wireguard_endpoint},
+    detailer::{openvpn_endpoint, wireguard_candidate_peer, wireguard_endpoint},
     matcher::{filter_matching_bridges, filter_matching_relay_list},
     parsed_relays::ParsedRelays,
     query::{BridgeQuery, OpenVpnRelayQuery, RelayQuery, WireguardRelayQuery},
@@ -102,6 +107,7 @@ pub struct SelectorConfig {
     pub additional_constraints: AdditionalRelayConstraints,
     pub custom_lists: CustomListsSettings,
     pub relay_overrides: Vec<RelayOverride>,
+    pub ip_version_preference: IpVersionPreference,
     // Wireguard specific data
     pub obfuscation_settings: ObfuscationSettings,
     // OpenVPN specific data
@@ -128,6 +134,10 @@ pub struct AdditionalWireguardConstraints {
 pub struct RuntimeParameters {
     /// Whether IPv6 is available
     pub ipv6: bool,
+    /// Whether the tunnel should be obfuscated, e.g. because an ephemeral peer could not be
+    /// negotiated over plain UDP. Ignored if no obfuscated relay is compatible with the user's
+    /// settings.
+    pub require_obfuscation: bool,
 }
 
 impl RuntimeParameters {
@@ -145,6 +155,16 @@ impl RuntimeParameters {
                 return false;
             }
         }
+        if self.require_obfuscation {
+            let obfuscated = matches!(
+                query.wireguard_constraints.obfuscation,
+                SelectedObfuscation::Udp2Tcp | SelectedObfuscation::Tls
+            );
+            if !obfuscated {
+                log::trace!("{query:?} is incompatible with {self:?} due to not being obfuscated");
+                return false;
+            }
+        }
         true
     }
 }
@@ -154,7 +174,10 @@ impl RuntimeParameters {
 #[allow(clippy::derivable_impls)]
 impl Default for RuntimeParameters {
     fn default() -> Self {
-        RuntimeParameters { ipv6: false }
+        RuntimeParameters {
+            ipv6: false,
+            require_obfuscation: false,
+        }
     }
 }
 
@@ -188,6 +211,7 @@ struct NormalSelectorConfig<'a> {
     user_preferences: &'a RelayConstraints,
     additional_preferences: &'a AdditionalRelayConstraints,
     custom_lists: &'a CustomListsSettings,
+    ip_version_preference: IpVersionPreference,
     // Wireguard specific data
     obfuscation_settings: &'a ObfuscationSettings,
     // OpenVPN specific data
@@ -202,6 +226,9 @@ pub enum GetRelay {
         endpoint: MullvadWireguardEndpoint,
         obfuscator: Option<SelectedObfuscator>,
         inner: WireguardConfig,
+        /// Relay that is connected to in parallel with the selected one. Its peer config is
+        /// [`MullvadWireguardEndpoint::candidate_peer`].
+        candidate: Option<Relay>,
     },
     #[cfg(not(target_os = "android"))]
     OpenVpn {
@@ -258,15 +285,15 @@ impl WireguardConfig {
 #[derive(Clone, Debug)]
 pub enum SelectedBridge {
     Normal { settings: CustomProxy, relay: Relay },
-    Custom(CustomProxy),
+    Custom(ProxyChain),
 }
 
 impl SelectedBridge {
-    /// Get the bridge settings.
-    pub fn settings(&self) -> &CustomProxy {
+    /// Get the proxies to pass through, in order.
+    pub fn settings(&self) -> ProxyChain {
         match self {
-            SelectedBridge::Normal { settings, .. } => settings,
-            SelectedBridge::Custom(settings) => settings,
+            SelectedBridge::Normal { settings, .. } => ProxyChain::from(settings.clone()),
+            SelectedBridge::Custom(chain) => chain.clone(),
         }
     }
 
@@ -297,6 +324,7 @@ impl Default for SelectorConfig {
             bridge_state: default_settings.bridge_state,
             custom_lists: default_settings.custom_lists,
             relay_overrides: default_settings.relay_overrides,
+            ip_version_preference: default_settings.ip_version_preference,
         }
     }
 }
@@ -315,6 +343,7 @@ impl<'a> From<&'a SelectorConfig> for SpecializedSelectorConfig<'a> {
                     bridge_state: &value.bridge_state,
                     bridge_settings: &value.bridge_settings,
                     custom_lists: &value.custom_lists,
+                    ip_version_preference: value.ip_version_preference,
                 })
             }
         }
@@ -332,19 +361,38 @@ impl<'a> From<NormalSelectorConfig<'a>> for RelayQuery {
         ) -> WireguardRelayQuery {
             let WireguardConstraints {
                 port,
+                port_range,
                 ip_version,
                 use_multihop,
                 entry_location,
+                required_features,
+                max_latency,
+                multihop_diversity,
+                public_key,
+                entry_public_key,
             } = wireguard_constraints;
             let AdditionalWireguardConstraints { daita } = additional_constraints;
             WireguardRelayQuery {
                 port,
+                // A port chosen by the user takes precedence over the range
+                port_range: if port.is_only() {
+                    Constraint::Any
+                } else {
+                    port_range
+                },
                 ip_version,
                 use_multihop: Constraint::Only(use_multihop),
                 entry_location,
                 obfuscation: obfuscation_settings.selected_obfuscation,
                 udp2tcp_port: Constraint::Only(obfuscation_settings.udp2tcp.clone()),
-                daita: Constraint::Only(daita),
+                tls: Constraint::Only(obfuscation_settings.tls.clone()),
+                daita: Constraint::Only(daita || required_features.daita),
+                quic: Constraint::Only(required_features.quic),
+                stboot: Constraint::Only(required_features.stboot),
+                max_latency,
+                multihop_diversity: Constraint::Only(multihop_diversity),
+                public_key,
+                entry_public_key,
             }
         }
 
@@ -362,7 +410,14 @@ impl<'a> From<NormalSelectorConfig<'a>> for RelayQuery {
                             Constraint::Only(BridgeQuery::Normal(bridge_settings.normal.clone()))
                         }
                         mullvad_types::relay_constraints::BridgeType::Custom => {
-                            Constraint::Only(BridgeQuery::Custom(bridge_settings.custom.clone()))
+                            let chain = bridge_settings.custom_proxy_chain().and_then(|chain| {
+                                chain
+                                    .inspect_err(|error| {
+                                        log::error!("Invalid custom bridge chain: {error}")
+                                    })
+                                    .ok()
+                            });
+                            Constraint::Only(BridgeQuery::Custom(chain))
                         }
                     },
                     BridgeState::Auto => Constraint::Only(BridgeQuery::Auto),
@@ -444,6 +499,14 @@ impl RelaySelector {
         parsed_relays.update(relays);
     }
 
+    /// Store latencies measured by pinging relays, to be used by the latency constraint.
+    pub fn set_relay_latencies(&self, latencies: impl IntoIterator<Item = RelayLatency>) {
+        let mut parsed_relays = self.parsed_relays.lock().unwrap();
+        for latency in latencies {
+            parsed_relays.set_latency(latency);
+        }
+    }
+
     fn set_overrides(&mut self, relay_overrides: &[RelayOverride]) {
         let mut parsed_relays = self.parsed_relays.lock().unwrap();
         parsed_relays.set_overrides(relay_overrides);
@@ -517,6 +580,124 @@ impl RelaySelector {
         }
     }
 
+    /// Returns the relay that the tunnel was connected to before the daemon restarted, using the
+    /// same port and transport protocol. Fails if the relay or the transport no longer matches the
+    /// constraints of the first connection attempt.
+    pub fn get_resumed_relay(
+        &self,
+        last_relay: &LastConnectedRelay,
+        runtime_params: RuntimeParameters,
+    ) -> Result<GetRelay, Error> {
+        let config_guard = self.config.lock().unwrap();
+        let config = SpecializedSelectorConfig::from(&*config_guard);
+        let SpecializedSelectorConfig::Normal(normal_config) = config else {
+            return Err(Error::NoRelay);
+        };
+        let parsed_relays = &self.parsed_relays.lock().unwrap();
+        let query = Self::pick_and_merge_query(
+            0,
+            &RETRY_ORDER,
+            runtime_params,
+            &normal_config,
+            parsed_relays,
+        )?;
+        let query =
+            Self::resume_query(query, last_relay, parsed_relays, normal_config.custom_lists)
+                .ok_or(Error::NoRelay)?;
+        Self::get_relay_inner(&query, parsed_relays, normal_config.custom_lists)
+    }
+
+    /// Narrow down `query` to the relays and transport of `last_relay`, or return `None` if they
+    /// do not match it.
+    fn resume_query(
+        query: RelayQuery,
+        last_relay: &LastConnectedRelay,
+        parsed_relays: &ParsedRelays,
+        custom_lists: &CustomListsSettings,
+    ) -> Option<RelayQuery> {
+        let find_relay = |hostname: &str| parsed_relays.relay(hostname);
+        let port = last_relay.endpoint.address.port();
+        let ip_version = if last_relay.endpoint.address.is_ipv4() {
+            IpVersion::V4
+        } else {
+            IpVersion::V6
+        };
+
+        let mut pinned = RelayQuery::new();
+        pinned.tunnel_protocol = Constraint::Only(last_relay.tunnel_type);
+        match last_relay.tunnel_type {
+            TunnelType::Wireguard => {
+                let wireguard = &mut pinned.wireguard_constraints;
+                wireguard.ip_version = Constraint::Only(ip_version);
+                wireguard.use_multihop = Constraint::Only(last_relay.entry_hostname.is_some());
+                if !last_relay.obfuscated {
+                    wireguard.port = Constraint::Only(port);
+                }
+                if let Some(entry_hostname) = &last_relay.entry_hostname {
+                    let RelayEndpointData::Wireguard(entry) =
+                        &find_relay(entry_hostname)?.endpoint_data
+                    else {
+                        return None;
+                    };
+                    wireguard.entry_public_key = Constraint::Only(entry.public_key.clone());
+                }
+            }
+            TunnelType::OpenVpn => {
+                pinned.openvpn_constraints.port = Constraint::Only(TransportPort {
+                    protocol: last_relay.endpoint.protocol,
+                    port: Constraint::Only(port),
+                });
+            }
+        }
+        let mut query = query.intersection(pinned)?;
+
+        // The exit relay must still match the other constraints before it replaces the location.
+        // DAITA, QUIC, obfuscation and latency only concern the entry relay when multihop is used.
+        let mut exit_query = query.clone();
+        if last_relay.entry_hostname.is_some() {
+            exit_query.wireguard_constraints.daita = Constraint::Any;
+            exit_query.wireguard_constraints.quic = Constraint::Any;
+            exit_query.wireguard_constraints.obfuscation = SelectedObfuscation::Off;
+            exit_query.wireguard_constraints.max_latency = Constraint::Any;
+        }
+        let exit = filter_matching_relay_list(
+            &exit_query,
+            parsed_relays.candidates(&exit_query, custom_lists),
+            parsed_relays.latencies(),
+            custom_lists,
+        )
+        .into_iter()
+        .find(|relay| relay.hostname == last_relay.hostname)?;
+        let location = exit.location.as_ref()?;
+        query.location = Constraint::Only(LocationConstraint::Location(
+            GeographicLocationConstraint::Hostname(
+                location.country_code.clone(),
+                location.city_code.clone(),
+                exit.hostname.clone(),
+            ),
+        ));
+        Some(query)
+    }
+
+    /// Returns a random OpenVPN relay and relay endpoint matching the current constraints,
+    /// regardless of which tunnel protocol is preferred.
+    #[cfg(not(target_os = "android"))]
+    pub fn get_openvpn_relay_from_settings(&self) -> Result<GetRelay, Error> {
+        let config_guard = self.config.lock().unwrap();
+        let config = SpecializedSelectorConfig::from(&*config_guard);
+        match config {
+            SpecializedSelectorConfig::Custom(custom_config) => {
+                Ok(GetRelay::Custom(custom_config.clone()))
+            }
+            SpecializedSelectorConfig::Normal(normal_config) => {
+                let parsed_relays = &self.parsed_relays.lock().unwrap();
+                let mut query = RelayQuery::from(normal_config.clone());
+                query.tunnel_protocol = Constraint::Only(TunnelType::OpenVpn);
+                Self::get_relay_inner(&query, parsed_relays, normal_config.custom_lists)
+            }
+        }
+    }
+
     /// Returns a random relay and relay endpoint matching the current constraints corresponding to
     /// `retry_attempt` in [`RETRY_ORDER`] while considering [runtime_params][`RuntimeParameters`].
     ///
@@ -529,6 +710,26 @@ impl RelaySelector {
         self.get_relay_with_custom_params(retry_attempt, &RETRY_ORDER, runtime_params)
     }
 
+    /// Returns a random relay and relay endpoint like [`RelaySelector::get_relay`], but only using
+    /// `tunnel_type`. The queries in [`RETRY_ORDER`] that use the other tunnel protocol are
+    /// skipped.
+    ///
+    /// [`RETRY_ORDER`]: crate::RETRY_ORDER
+    pub fn get_relay_by_tunnel_type(
+        &self,
+        retry_attempt: usize,
+        tunnel_type: TunnelType,
+        runtime_params: RuntimeParameters,
+    ) -> Result<GetRelay, Error> {
+        let mut protocol = RelayQuery::new();
+        protocol.tunnel_protocol = Constraint::Only(tunnel_type);
+        let retry_order: Vec<_> = RETRY_ORDER
+            .iter()
+            .filter_map(|query| query.clone().intersection(protocol.clone()))
+            .collect();
+        self.get_relay_with_custom_params(retry_attempt, &retry_order, runtime_params)
+    }
+
     /// Returns a random relay and relay endpoint matching the current constraints defined by
     /// `retry_order` corresponding to `retry_attempt`.
     pub fn get_relay_with_custom_params(
@@ -584,7 +785,12 @@ impl RelaySelector {
     ) -> Result<RelayQuery, Error> {
         let user_query = RelayQuery::from(user_config.clone());
         log::trace!("Merging user preferences {user_query:?} with default retry strategy");
-        retry_order
+        let preferred_order = Self::apply_ip_version_preference(
+            retry_order,
+            user_config.ip_version_preference,
+            &runtime_params,
+        );
+        let query = preferred_order
             .iter()
             // Remove candidate queries based on runtime parameters before trying to merge user
             // settings
@@ -592,8 +798,59 @@ impl RelaySelector {
             .filter_map(|query| query.clone().intersection(user_query.clone()))
             .filter(|query| Self::get_relay_inner(query, parsed_relays, user_config.custom_lists).is_ok())
             .cycle() // If the above filters remove all relays, cycle will also return an empty iterator
-            .nth(retry_attempt)
-            .ok_or(Error::NoRelay)
+            .nth(retry_attempt);
+
+        match query {
+            Some(query) => Ok(query),
+            None if runtime_params.require_obfuscation => {
+                log::debug!("No obfuscated relay matches the current settings. Ignoring the obfuscation requirement");
+                let runtime_params = RuntimeParameters {
+                    require_obfuscation: false,
+                    ..runtime_params
+                };
+                Self::pick_and_merge_query(
+                    retry_attempt,
+                    retry_order,
+                    runtime_params,
+                    user_config,
+                    parsed_relays,
+                )
+            }
+            None => Err(Error::NoRelay),
+        }
+    }
+
+    /// Make the queries in `retry_order` use the IP version in `preference` first. Queries that
+    /// leave the IP version open are set to the preferred one. If IPv6 is preferred, the queries
+    /// that fall back to IPv6 fall back to IPv4 instead.
+    fn apply_ip_version_preference(
+        retry_order: &[RelayQuery],
+        preference: IpVersionPreference,
+        runtime_params: &RuntimeParameters,
+    ) -> Vec<RelayQuery> {
+        let preferred = match preference {
+            IpVersionPreference::Ipv4 => IpVersion::V4,
+            IpVersionPreference::Ipv6 if runtime_params.ipv6 => IpVersion::V6,
+            // There is no IPv6 to prefer, so the default fallback to IPv6 is filtered out anyway
+            IpVersionPreference::Ipv6 | IpVersionPreference::Automatic => {
+                return retry_order.to_vec()
+            }
+        };
+        retry_order
+            .iter()
+            .cloned()
+            .map(|mut query| {
+                let ip_version = &mut query.wireguard_constraints.ip_version;
+                *ip_version = match *ip_version {
+                    Constraint::Any => Constraint::Only(preferred),
+                    Constraint::Only(IpVersion::V6) if preferred == IpVersion::V6 => {
+                        Constraint::Only(IpVersion::V4)
+                    }
+                    constraint => constraint,
+                };
+                query
+            })
+            .collect()
     }
 
     /// "Execute" the given query, yielding a final set of relays and/or bridges which the VPN
@@ -684,17 +941,60 @@ impl RelaySelector {
         } else {
             Self::get_wireguard_multihop_config(query, custom_lists, parsed_relays)?
         };
-        let endpoint = Self::get_wireguard_endpoint(query, parsed_relays, &inner)?;
+        let mut endpoint = Self::get_wireguard_endpoint(query, parsed_relays, &inner)?;
         let obfuscator =
             Self::get_wireguard_obfuscator(query, inner.clone(), &endpoint, parsed_relays)?;
 
+        let candidate = match &inner {
+            WireguardConfig::Singlehop { exit } if obfuscator.is_none() => {
+                Self::get_wireguard_candidate(query, custom_lists, parsed_relays, exit)
+            }
+            _ => None,
+        };
+        let candidate = candidate.and_then(|candidate| {
+            let peer =
+                wireguard_candidate_peer(&query.wireguard_constraints, &endpoint, &candidate)
+                    .ok()?;
+            endpoint.candidate_peer = Some(peer);
+            Some(candidate)
+        });
+
         Ok(GetRelay::Wireguard {
             endpoint,
             obfuscator,
             inner,
+            candidate,
         })
     }
 
+    /// Select another relay than `exit` to connect to in parallel with it, so that the tunnel can
+    /// use whichever relay responds first. This is only done if the query matches more than one
+    /// relay, and never when DAITA is enabled, since it is not available for the candidate.
+    ///
+    /// # Returns
+    /// * `None` if there is no other matching relay
+    /// * `Some(relay)` otherwise
+    fn get_wireguard_candidate(
+        query: &RelayQuery,
+        custom_lists: &CustomListsSettings,
+        parsed_relays: &ParsedRelays,
+        exit: &Relay,
+    ) -> Option<Relay> {
+        if query.wireguard_constraints.daita == Constraint::Only(true) {
+            return None;
+        }
+        let candidates: Vec<Relay> = filter_matching_relay_list(
+            query,
+            parsed_relays.candidates(query, custom_lists),
+            parsed_relays.latencies(),
+            custom_lists,
+        )
+        .into_iter()
+        .filter(|relay| relay.hostname != exit.hostname)
+        .collect();
+        helpers::pick_random_relay(&candidates).cloned()
+    }
+
     /// Select a valid Wireguard exit relay.
     ///
     /// # Returns
@@ -705,7 +1005,12 @@ impl RelaySelector {
         custom_lists: &CustomListsSettings,
         parsed_relays: &ParsedRelays,
     ) -> Result<WireguardConfig, Error> {
-        let candidates = filter_matching_relay_list(query, parsed_relays.relays(), custom_lists);
+        let candidates = filter_matching_relay_list(
+            query,
+            parsed_relays.candidates(query, custom_lists),
+            parsed_relays.latencies(),
+            custom_lists,
+        );
         helpers::pick_random_relay(&candidates)
             .cloned()
             .map(WireguardConfig::singlehop)
@@ -719,6 +1024,9 @@ impl RelaySelector {
     /// * An `Err` if no entry relay can be chosen
     /// * An `Err` if the chosen entry and exit relays are the same
     /// * `Ok(WireguardInner::Multihop)` otherwise
+    ///
+    /// If neither the entry nor the exit location is constrained, relays that differ in the ways
+    /// given by the multihop diversity of `query` are preferred.
     fn get_wireguard_multihop_config(
         query: &RelayQuery,
         custom_lists: &CustomListsSettings,
@@ -730,16 +1038,48 @@ impl RelaySelector {
         // the query's multihop constraint.
         let mut entry_relay_query = query.clone();
         entry_relay_query.location = query.wireguard_constraints.entry_location.clone();
+        entry_relay_query.wireguard_constraints.public_key =
+            query.wireguard_constraints.entry_public_key.clone();
         // After we have our two queries (one for the exit relay & one for the entry relay),
         // we can query for all exit & entry candidates! All candidates are needed for the next
         // step.
         let mut exit_relay_query = query.clone();
-        // DAITA should only be enabled for the entry relay
+        // DAITA, QUIC, obfuscation and latency only concern the entry relay, since that is the one
+        // we connect to
         exit_relay_query.wireguard_constraints.daita = Constraint::Only(false);
-        let exit_candidates =
-            filter_matching_relay_list(&exit_relay_query, parsed_relays.relays(), custom_lists);
-        let entry_candidates =
-            filter_matching_relay_list(&entry_relay_query, parsed_relays.relays(), custom_lists);
+        exit_relay_query.wireguard_constraints.quic = Constraint::Only(false);
+        exit_relay_query.wireguard_constraints.obfuscation = SelectedObfuscation::Off;
+        exit_relay_query.wireguard_constraints.max_latency = Constraint::Any;
+        let exit_candidates = filter_matching_relay_list(
+            &exit_relay_query,
+            parsed_relays.candidates(&exit_relay_query, custom_lists),
+            parsed_relays.latencies(),
+            custom_lists,
+        );
+        let entry_candidates = filter_matching_relay_list(
+            &entry_relay_query,
+            parsed_relays.candidates(&entry_relay_query, custom_lists),
+            parsed_relays.latencies(),
+            custom_lists,
+        );
+
+        if let Constraint::Only(diversity) = query.wireguard_constraints.multihop_diversity {
+            let auto_selected = query.location.is_any()
+                && query.wireguard_constraints.entry_location.is_any()
+                && query.wireguard_constraints.public_key.is_any()
+                && query.wireguard_constraints.entry_public_key.is_any();
+            if auto_selected && diversity.is_enabled() {
+                if let Some((exit, entry)) =
+                    helpers::pick_diverse_relays(&exit_candidates, &entry_candidates, diversity)
+                {
+                    return Ok(WireguardConfig::multihop(exit.clone(), entry.clone()));
+                }
+                log::debug!(
+                    "No entry and exit relays differ by {}",
+                    diversity.names().join(" and ")
+                );
+            }
+        }
 
         fn pick_random_excluding<'a>(list: &'a [Relay], exclude: &'a Relay) -> Option<&'a Relay> {
             list.iter()
@@ -809,6 +1149,19 @@ impl RelaySelector {
                 .map(Some)
                 .ok_or(Error::NoObfuscator)
             }
+            SelectedObfuscation::Tls => {
+                let obfuscator_relay = match relay {
+                    WireguardConfig::Singlehop { exit } => exit,
+                    WireguardConfig::Multihop { entry, .. } => entry,
+                };
+                helpers::get_tls_obfuscator(
+                    &query.wireguard_constraints.tls,
+                    obfuscator_relay,
+                    endpoint,
+                )
+                .map(Some)
+                .ok_or(Error::NoObfuscator)
+            }
         }
     }
 
@@ -959,7 +1312,11 @@ impl RelaySelector {
         location: Option<T>,
         custom_lists: &CustomListsSettings,
     ) -> Result<(CustomProxy, Relay), Error> {
-        let bridges = filter_matching_bridges(constraints, parsed_relays.relays(), custom_lists);
+        let bridges = filter_matching_bridges(
+            constraints,
+            parsed_relays.bridge_candidates(constraints, custom_lists),
+            custom_lists,
+        );
         let bridge_data = &parsed_relays.parsed_list().bridge;
         let bridge = match location {
             Some(location) => Self::get_proximate_bridge(bridges, location),
@@ -1025,12 +1382,16 @@ impl RelaySelector {
             return None;
         }
 
-        let matching_locations: Vec<Location> =
-            filter_matching_relay_list(query, parsed_relays.relays(), custom_lists)
-                .into_iter()
-                .filter_map(|relay| relay.location)
-                .unique_by(|location| location.city.clone())
-                .collect();
+        let matching_locations: Vec<Location> = filter_matching_relay_list(
+            query,
+            parsed_relays.candidates(query, custom_lists),
+            parsed_relays.latencies(),
+            custom_lists,
+        )
+        .into_iter()
+        .filter_map(|relay| relay.location)
+        .unique_by(|location| location.city.clone())
+        .collect();
 
         matching_locations
             .is_empty()
@@ -1048,8 +1409,9 @@ impl RelaySelector {
         parsed_relays: &ParsedRelays,
     ) -> Option<Relay> {
         // Filter among all valid relays
-        let relays = parsed_relays.relays();
-        let candidates = filter_matching_relay_list(query, relays, custom_lists);
+        let relays = parsed_relays.candidates(query, custom_lists);
+        let candidates =
+            filter_matching_relay_list(query, relays, parsed_relays.latencies(), custom_lists);
         // Pick one of the valid relays.
         helpers::pick_random_relay(&candidates).cloned()
     }
@@ -11,37 +11,76 @@ use std::{
     collections::HashMap,
     io::{self, BufReader},
     path::Path,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use mullvad_types::{
+    custom_list::CustomListsSettings,
     location::Location,
-    relay_constraints::RelayOverride,
-    relay_list::{Relay, RelayList},
+    relay_constraints::{InternalBridgeConstraints, RelayOverride},
+    relay_list::{Relay, RelayLatency, RelayList},
 };
 
 use crate::{constants::UDP2TCP_PORTS, error::Error};
 
+use super::{
+    query::RelayQuery,
+    relay_index::{Candidates, RelayIndex},
+};
+
+/// The last measured handshake latency to each relay, by hostname. `None` means that the relay
+/// did not respond.
+pub(crate) type RelayLatencies = HashMap<String, Option<Duration>>;
+
 pub(crate) struct ParsedRelays {
     /// Tracks when the relay list was last updated.
     last_updated: SystemTime,
     /// The current list of relays, after applying [overrides][`RelayOverride`].
     parsed_list: RelayList,
+    /// Index of `parsed_list`, which is rebuilt along with it.
+    index: RelayIndex,
     /// The original list of relays, as returned by the Mullvad relays API.
     original_list: RelayList,
     overrides: Vec<RelayOverride>,
+    /// Latencies measured by pinging relays. These are kept when the relay list is updated.
+    latencies: RelayLatencies,
 }
 
 impl ParsedRelays {
     /// Return a flat iterator with all relays
     pub fn relays(&self) -> impl Iterator<Item = &Relay> + Clone + '_ {
-        self.parsed_list.relays()
+        self.index.relays()
+    }
+
+    /// Return the relay with the given hostname.
+    pub fn relay(&self, hostname: &str) -> Option<&Relay> {
+        self.index.relay(hostname)
+    }
+
+    /// Return the relays that may match `query`, which is usually far fewer than all relays.
+    pub fn candidates(
+        &self,
+        query: &RelayQuery,
+        custom_lists: &CustomListsSettings,
+    ) -> Candidates<'_> {
+        self.index.candidates(query, custom_lists)
+    }
+
+    /// Return the bridges that may match `constraints`.
+    pub fn bridge_candidates(
+        &self,
+        constraints: &InternalBridgeConstraints,
+        custom_lists: &CustomListsSettings,
+    ) -> Candidates<'_> {
+        self.index.bridge_candidates(constraints, custom_lists)
     }
 
     /// Replace `self` with a new [`ParsedRelays`] based on [new_relays][`ParsedRelays`],
     /// bumping `self.last_updated` to the current system time.
     pub fn update(&mut self, new_relays: RelayList) {
+        let latencies = std::mem::take(&mut self.latencies);
         *self = Self::from_relay_list(new_relays, SystemTime::now(), &self.overrides);
+        self.latencies = latencies;
 
         log::info!(
             "Updated relay inventory has {} relays",
@@ -70,10 +109,21 @@ impl ParsedRelays {
         &self.parsed_list
     }
 
+    /// The last measured latency to each relay.
+    pub const fn latencies(&self) -> &RelayLatencies {
+        &self.latencies
+    }
+
+    /// Store the latency to a relay, replacing any previous measurement.
+    pub(crate) fn set_latency(&mut self, latency: RelayLatency) {
+        self.latencies.insert(latency.hostname, latency.latency);
+    }
+
     /// Replace the previous set of [overrides][`RelayOverride`] with `new_overrides`.
     /// This will update `self.parsed_list` as a side-effect.
     pub(crate) fn set_overrides(&mut self, new_overrides: &[RelayOverride]) {
         self.parsed_list = Self::parse_relay_list(&self.original_list, new_overrides);
+        self.index = RelayIndex::new(&self.parsed_list);
         self.overrides = new_overrides.to_vec();
     }
 
@@ -81,8 +131,10 @@ impl ParsedRelays {
         ParsedRelays {
             last_updated: UNIX_EPOCH,
             parsed_list: RelayList::empty(),
+            index: RelayIndex::new(&RelayList::empty()),
             original_list: RelayList::empty(),
             overrides: vec![],
+            latencies: RelayLatencies::new(),
         }
     }
 
@@ -136,11 +188,14 @@ impl ParsedRelays {
         last_updated: SystemTime,
         overrides: &[RelayOverride],
     ) -> Self {
+        let parsed_list = Self::parse_relay_list(&relay_list, overrides);
         ParsedRelays {
             last_updated,
-            parsed_list: Self::parse_relay_list(&relay_list, overrides),
+            index: RelayIndex::new(&parsed_list),
+            parsed_list,
             original_list: relay_list,
             overrides: overrides.to_vec(),
+            latencies: RelayLatencies::new(),
         }
     }
 
@@ -157,6 +212,9 @@ impl ParsedRelays {
 
         let mut parsed_list = relay_list.clone();
 
+        #[cfg(feature = "test-relays")]
+        crate::test_relays::add_to(&mut parsed_list);
+
         // Append data for obfuscation protocols ourselves, since the API does not provide it.
         if parsed_list.wireguard.udp2tcp_ports.is_empty() {
             parsed_list.wireguard.udp2tcp_ports.extend(UDP2TCP_PORTS);
@@ -32,13 +32,13 @@ use crate::AdditionalWireguardConstraints;
 use mullvad_types::{
     constraints::Constraint,
     relay_constraints::{
-        BridgeConstraints, LocationConstraint, OpenVpnConstraints, Ownership, Providers,
-        RelayConstraints, SelectedObfuscation, TransportPort, Udp2TcpObfuscationSettings,
-        WireguardConstraints,
+        BridgeConstraints, LocationConstraint, MultihopDiversity, OpenVpnConstraints, Ownership,
+        PortRange, Providers, RelayConstraints, RequiredRelayFeatures, SelectedObfuscation,
+        TlsObfuscationSettings, TransportPort, Udp2TcpObfuscationSettings, WireguardConstraints,
     },
     Intersection,
 };
-use talpid_types::net::{proxy::CustomProxy, IpVersion, TunnelType};
+use talpid_types::net::{proxy::ProxyChain, wireguard, IpVersion, TunnelType};
 
 /// Represents a query for a relay based on various constraints.
 ///
@@ -141,12 +141,30 @@ impl From<RelayQuery> for RelayConstraints {
 #[derive(Debug, Clone, Eq, PartialEq, Intersection)]
 pub struct WireguardRelayQuery {
     pub port: Constraint<u16>,
+    /// Range that the port must be in. If `port` is [`Constraint::Any`], a random port is picked
+    /// from it.
+    pub port_range: Constraint<PortRange>,
     pub ip_version: Constraint<IpVersion>,
     pub use_multihop: Constraint<bool>,
     pub entry_location: Constraint<LocationConstraint>,
     pub obfuscation: SelectedObfuscation,
     pub udp2tcp_port: Constraint<Udp2TcpObfuscationSettings>,
+    pub tls: Constraint<TlsObfuscationSettings>,
     pub daita: Constraint<bool>,
+    /// If true, only select relays that accept WireGuard over QUIC.
+    pub quic: Constraint<bool>,
+    /// If true, only select relays that are booted with stboot.
+    pub stboot: Constraint<bool>,
+    /// Only select relays whose last measured handshake latency, in milliseconds, was at most
+    /// this. Relays that have not been pinged are not affected.
+    pub max_latency: Constraint<u16>,
+    /// How the entry and exit relays should differ when both of them are picked by the relay
+    /// selector. No particular relays are preferred if this is [`Constraint::Any`].
+    pub multihop_diversity: Constraint<MultihopDiversity>,
+    /// Only select the relay with this public key, or the exit relay with it if multihop is used.
+    pub public_key: Constraint<wireguard::PublicKey>,
+    /// Only select the entry relay with this public key when multihop is used.
+    pub entry_public_key: Constraint<wireguard::PublicKey>,
 }
 
 impl WireguardRelayQuery {
@@ -159,12 +177,20 @@ impl WireguardRelayQuery {
     pub const fn new() -> WireguardRelayQuery {
         WireguardRelayQuery {
             port: Constraint::Any,
+            port_range: Constraint::Any,
             ip_version: Constraint::Any,
             use_multihop: Constraint::Any,
             entry_location: Constraint::Any,
             obfuscation: SelectedObfuscation::Auto,
             udp2tcp_port: Constraint::Any,
+            tls: Constraint::Any,
             daita: Constraint::Any,
+            quic: Constraint::Any,
+            stboot: Constraint::Any,
+            max_latency: Constraint::Any,
+            multihop_diversity: Constraint::Any,
+            public_key: Constraint::Any,
+            entry_public_key: Constraint::Any,
         }
     }
 }
@@ -180,9 +206,20 @@ impl From<WireguardRelayQuery> for WireguardConstraints {
     fn from(value: WireguardRelayQuery) -> Self {
         WireguardConstraints {
             port: value.port,
+            port_range: value.port_range,
             ip_version: value.ip_version,
             entry_location: value.entry_location,
             use_multihop: value.use_multihop.unwrap_or(false),
+            // A required DAITA relay is covered by `AdditionalWireguardConstraints`
+            required_features: RequiredRelayFeatures {
+                daita: false,
+                quic: value.quic.unwrap_or(false),
+                stboot: value.stboot.unwrap_or(false),
+            },
+            max_latency: value.max_latency,
+            multihop_diversity: value.multihop_diversity.unwrap_or(MultihopDiversity::NONE),
+            public_key: value.public_key,
+            entry_public_key: value.entry_public_key,
         }
     }
 }
@@ -242,7 +279,7 @@ pub enum BridgeQuery {
     /// Bridges should be used.
     Normal(BridgeConstraints),
     /// Bridges should be used.
-    Custom(Option<CustomProxy>),
+    Custom(Option<ProxyChain>),
 }
 
 impl BridgeQuery {
@@ -298,17 +335,17 @@ pub mod builder {
     use mullvad_types::{
         constraints::Constraint,
         relay_constraints::{
-            BridgeConstraints, LocationConstraint, RelayConstraints, SelectedObfuscation,
-            TransportPort, Udp2TcpObfuscationSettings,
+            BridgeConstraints, LocationConstraint, PortRange, RelayConstraints,
+            SelectedObfuscation, TlsObfuscationSettings, TransportPort, Udp2TcpObfuscationSettings,
         },
     };
-    use talpid_types::net::TunnelType;
+    use talpid_types::net::{wireguard, TunnelType};
 
     use super::{BridgeQuery, RelayQuery};
 
     // Re-exports
     pub use mullvad_types::relay_constraints::{
-        GeographicLocationConstraint, Ownership, Providers,
+        GeographicLocationConstraint, MultihopDiversity, Ownership, Providers,
     };
     pub use talpid_types::net::{IpVersion, TransportProtocol};
 
@@ -435,12 +472,51 @@ pub mod builder {
             self
         }
 
+        /// Specify a range to pick a random port from when connecting to the
+        /// selected Wireguard relay.
+        pub const fn port_range(mut self, port_range: PortRange) -> Self {
+            self.query.wireguard_constraints.port_range = Constraint::Only(port_range);
+            self
+        }
+
         /// Set the [`IpVersion`] to use when connecting to the selected
         /// Wireguard relay.
         pub const fn ip_version(mut self, ip_version: IpVersion) -> Self {
             self.query.wireguard_constraints.ip_version = Constraint::Only(ip_version);
             self
         }
+
+        /// Only select Wireguard relays that accept WireGuard over QUIC.
+        pub const fn quic(mut self) -> Self {
+            self.query.wireguard_constraints.quic = Constraint::Only(true);
+            self
+        }
+
+        /// Only select Wireguard relays that are booted with stboot.
+        pub const fn stboot(mut self) -> Self {
+            self.query.wireguard_constraints.stboot = Constraint::Only(true);
+            self
+        }
+
+        /// Only select Wireguard relays that responded within `max_latency` milliseconds the last
+        /// time they were pinged.
+        pub const fn max_latency(mut self, max_latency: u16) -> Self {
+            self.query.wireguard_constraints.max_latency = Constraint::Only(max_latency);
+            self
+        }
+
+        /// Prefer entry and exit relays that differ in the ways given by `diversity`.
+        pub const fn multihop_diversity(mut self, diversity: MultihopDiversity) -> Self {
+            self.query.wireguard_constraints.multihop_diversity = Constraint::Only(diversity);
+            self
+        }
+
+        /// Only select the Wireguard relay with `public_key`, or the exit relay with it if
+        /// multihop is enabled.
+        pub fn public_key(mut self, public_key: wireguard::PublicKey) -> Self {
+            self.query.wireguard_constraints.public_key = Constraint::Only(public_key);
+            self
+        }
     }
 
     impl<Multihop, Obfuscation> RelayQueryBuilder<Wireguard<Multihop, Obfuscation, Any>> {
@@ -485,6 +561,12 @@ pub mod builder {
                 Constraint::Only(LocationConstraint::from(location));
             self
         }
+
+        /// Only select the entry relay with `public_key` in a multihop configuration.
+        pub fn entry_public_key(mut self, public_key: wireguard::PublicKey) -> Self {
+            self.query.wireguard_constraints.entry_public_key = Constraint::Only(public_key);
+            self
+        }
     }
 
     impl<Multihop, Daita> RelayQueryBuilder<Wireguard<Multihop, Any, Daita>> {
@@ -508,6 +590,24 @@ pub mod builder {
                 protocol,
             }
         }
+
+        /// Enable TLS obfuscation. The server name is left unconstrained, so that the one in the
+        /// user's settings is used.
+        pub fn tls(
+            mut self,
+        ) -> RelayQueryBuilder<Wireguard<Multihop, TlsObfuscationSettings, Daita>> {
+            let obfuscation = TlsObfuscationSettings::default();
+            let protocol = Wireguard {
+                multihop: self.protocol.multihop,
+                obfuscation,
+                daita: self.protocol.daita,
+            };
+            self.query.wireguard_constraints.obfuscation = SelectedObfuscation::Tls;
+            RelayQueryBuilder {
+                query: self.query,
+                protocol,
+            }
+        }
     }
 
     impl<Multihop, Daita> RelayQueryBuilder<Wireguard<Multihop, Udp2TcpObfuscationSettings, Daita>> {
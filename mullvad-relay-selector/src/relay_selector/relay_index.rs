@@ -0,0 +1,344 @@
+//! An index of the relay list, which sorts relays into buckets by location, tunnel type and
+//! capabilities. It is rebuilt whenever the relay list or the overrides change, so that a query
+//! only has to run the [matcher][`super::matcher`] on the smallest bucket that it is confined to,
+//! rather than on every relay.
+
+use std::{borrow::Cow, collections::HashMap};
+
+use mullvad_types::{
+    constraints::Constraint,
+    custom_list::CustomListsSettings,
+    relay_constraints::{GeographicLocationConstraint, InternalBridgeConstraints},
+    relay_list::{Relay, RelayEndpointData, RelayList},
+};
+use talpid_types::net::TunnelType;
+
+use super::{matcher::ResolvedLocationConstraint, query::RelayQuery};
+
+/// Relays by position in the relay list. Every bucket is sorted, so that candidates are returned
+/// in the order of the relay list.
+type Bucket = Vec<usize>;
+
+pub(crate) struct RelayIndex {
+    /// Every relay, in the order of the relay list.
+    relays: Vec<Relay>,
+    all: Bucket,
+    by_country: HashMap<String, Bucket>,
+    by_city: HashMap<(String, String), Bucket>,
+    by_hostname: HashMap<String, usize>,
+    wireguard: Bucket,
+    openvpn: Bucket,
+    bridges: Bucket,
+    daita: Bucket,
+    quic: Bucket,
+    stboot: Bucket,
+}
+
+impl RelayIndex {
+    pub fn new(relay_list: &RelayList) -> Self {
+        let mut index = RelayIndex {
+            relays: relay_list.relays().cloned().collect(),
+            all: Bucket::new(),
+            by_country: HashMap::new(),
+            by_city: HashMap::new(),
+            by_hostname: HashMap::new(),
+            wireguard: Bucket::new(),
+            openvpn: Bucket::new(),
+            bridges: Bucket::new(),
+            daita: Bucket::new(),
+            quic: Bucket::new(),
+            stboot: Bucket::new(),
+        };
+
+        for (position, relay) in index.relays.iter().enumerate() {
+            index.all.push(position);
+            if let Some(location) = &relay.location {
+                index
+                    .by_country
+                    .entry(location.country_code.clone())
+                    .or_default()
+                    .push(position);
+                index
+                    .by_city
+                    .entry((location.country_code.clone(), location.city_code.clone()))
+                    .or_default()
+                    .push(position);
+            }
+            index.by_hostname.insert(relay.hostname.clone(), position);
+            match &relay.endpoint_data {
+                RelayEndpointData::Wireguard(data) => {
+                    index.wireguard.push(position);
+                    if data.daita {
+                        index.daita.push(position);
+                    }
+                    if data.quic {
+                        index.quic.push(position);
+                    }
+                }
+                RelayEndpointData::Openvpn => index.openvpn.push(position),
+                RelayEndpointData::Bridge => index.bridges.push(position),
+            }
+            if relay.stboot {
+                index.stboot.push(position);
+            }
+        }
+        index
+    }
+
+    /// Return every relay, in the order of the relay list.
+    pub fn relays(&self) -> std::slice::Iter<'_, Relay> {
+        self.relays.iter()
+    }
+
+    /// Return the relay with the given hostname.
+    pub fn relay(&self, hostname: &str) -> Option<&Relay> {
+        self.by_hostname
+            .get(hostname)
+            .map(|&position| &self.relays[position])
+    }
+
+    /// Return a subset of the relays that includes every relay that matches `query`. The relays
+    /// must still be matched against the query.
+    pub fn candidates(
+        &self,
+        query: &RelayQuery,
+        custom_lists: &CustomListsSettings,
+    ) -> Candidates<'_> {
+        let locations = ResolvedLocationConstraint::from_constraint(&query.location, custom_lists);
+        let wireguard = &query.wireguard_constraints;
+
+        let mut buckets = vec![self.location_bucket(&locations)];
+        #[cfg(not(target_os = "android"))]
+        buckets.push(match query.tunnel_protocol {
+            Constraint::Any => Cow::Borrowed(self.all.as_slice()),
+            Constraint::Only(TunnelType::Wireguard) => Cow::Borrowed(self.wireguard.as_slice()),
+            Constraint::Only(TunnelType::OpenVpn) => Cow::Borrowed(self.openvpn.as_slice()),
+        });
+        // Only WireGuard relays are used on Android
+        #[cfg(target_os = "android")]
+        buckets.push(Cow::Borrowed(self.wireguard.as_slice()));
+        // DAITA and QUIC are only required of WireGuard relays
+        if query.tunnel_protocol == Constraint::Only(TunnelType::Wireguard) {
+            for (constraint, bucket) in [
+                (&wireguard.daita, &self.daita),
+                (&wireguard.quic, &self.quic),
+            ] {
+                if *constraint == Constraint::Only(true) {
+                    buckets.push(Cow::Borrowed(bucket.as_slice()));
+                }
+            }
+        }
+        if wireguard.stboot == Constraint::Only(true) {
+            buckets.push(Cow::Borrowed(self.stboot.as_slice()));
+        }
+        self.smallest(buckets)
+    }
+
+    /// Return a subset of the bridges that includes every bridge that matches `constraints`. The
+    /// bridges must still be matched against the constraints.
+    pub fn bridge_candidates(
+        &self,
+        constraints: &InternalBridgeConstraints,
+        custom_lists: &CustomListsSettings,
+    ) -> Candidates<'_> {
+        let locations =
+            ResolvedLocationConstraint::from_constraint(&constraints.location, custom_lists);
+        self.smallest(vec![
+            self.location_bucket(&locations),
+            Cow::Borrowed(self.bridges.as_slice()),
+        ])
+    }
+
+    /// Return the relays in any of the given locations.
+    fn location_bucket<'a>(
+        &'a self,
+        locations: &Constraint<ResolvedLocationConstraint<'_>>,
+    ) -> Cow<'a, [usize]> {
+        let Constraint::Only(locations) = locations else {
+            return Cow::Borrowed(self.all.as_slice());
+        };
+        let mut buckets = locations.into_iter().map(|location| match location {
+            GeographicLocationConstraint::Country(country) => self
+                .by_country
+                .get(country)
+                .map(Vec::as_slice)
+                .unwrap_or_default(),
+            GeographicLocationConstraint::City(country, city) => self
+                .by_city
+                .get(&(country.clone(), city.clone()))
+                .map(Vec::as_slice)
+                .unwrap_or_default(),
+            GeographicLocationConstraint::Hostname(_, _, hostname) => self
+                .by_hostname
+                .get(hostname)
+                .map(std::slice::from_ref)
+                .unwrap_or_default(),
+        });
+        match (buckets.next(), buckets.next()) {
+            (None, _) => Cow::Borrowed(&[]),
+            (Some(bucket), None) => Cow::Borrowed(bucket),
+            (Some(first), Some(second)) => {
+                let mut union: Bucket = [first, second]
+                    .into_iter()
+                    .chain(buckets)
+                    .flatten()
+                    .copied()
+                    .collect();
+                union.sort_unstable();
+                union.dedup();
+                Cow::Owned(union)
+            }
+        }
+    }
+
+    fn smallest<'a>(&'a self, buckets: Vec<Cow<'a, [usize]>>) -> Candidates<'a> {
+        let bucket = buckets
+            .into_iter()
+            .min_by_key(|bucket| bucket.len())
+            .unwrap_or(Cow::Borrowed(self.all.as_slice()));
+        Candidates {
+            relays: &self.relays,
+            bucket,
+            next: 0,
+        }
+    }
+}
+
+/// Iterator over the relays in a bucket of a [`RelayIndex`].
+#[derive(Clone)]
+pub(crate) struct Candidates<'a> {
+    relays: &'a [Relay],
+    bucket: Cow<'a, [usize]>,
+    next: usize,
+}
+
+impl<'a> Iterator for Candidates<'a> {
+    type Item = &'a Relay;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let position = *self.bucket.get(self.next)?;
+        self.next += 1;
+        Some(&self.relays[position])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.bucket.len() - self.next;
+        (remaining, Some(remaining))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mullvad_types::{
+        location::Location,
+        relay_constraints::{GeographicLocationConstraint, LocationConstraint},
+        relay_list::{RelayListCity, RelayListCountry, WireguardRelayEndpointData},
+    };
+    use talpid_types::net::wireguard::PrivateKey;
+
+    use super::*;
+
+    fn relay(hostname: &str, country: &str, endpoint_data: RelayEndpointData) -> Relay {
+        Relay {
+            hostname: hostname.to_owned(),
+            ipv4_addr_in: "10.0.0.1".parse().unwrap(),
+            ipv6_addr_in: None,
+            include_in_country: true,
+            active: true,
+            owned: true,
+            provider: "provider".to_owned(),
+            weight: 1,
+            stboot: false,
+            endpoint_data,
+            location: Some(Location {
+                country: country.to_owned(),
+                country_code: country.to_owned(),
+                city: "city".to_owned(),
+                city_code: "cty".to_owned(),
+                latitude: 0.0,
+                longitude: 0.0,
+            }),
+        }
+    }
+
+    fn wireguard(daita: bool) -> RelayEndpointData {
+        RelayEndpointData::Wireguard(WireguardRelayEndpointData {
+            public_key: PrivateKey::new_from_random().public_key(),
+            daita,
+            quic: false,
+            tls: None,
+        })
+    }
+
+    fn hostnames(candidates: Candidates<'_>) -> Vec<&str> {
+        candidates.map(|relay| relay.hostname.as_str()).collect()
+    }
+
+    #[test]
+    fn test_candidates() {
+        let country = |code: &str, relays| RelayListCountry {
+            name: code.to_owned(),
+            code: code.to_owned(),
+            cities: vec![RelayListCity {
+                name: "city".to_owned(),
+                code: "cty".to_owned(),
+                latitude: 0.0,
+                longitude: 0.0,
+                relays,
+            }],
+        };
+        let relay_list = RelayList {
+            countries: vec![
+                country(
+                    "se",
+                    vec![
+                        relay("se-wg-001", "se", wireguard(false)),
+                        relay("se-wg-002", "se", wireguard(true)),
+                        relay("se-ovpn-001", "se", RelayEndpointData::Openvpn),
+                        relay("se-br-001", "se", RelayEndpointData::Bridge),
+                    ],
+                ),
+                country(
+                    "de",
+                    vec![
+                        relay("de-wg-001", "de", wireguard(true)),
+                        relay("de-wg-002", "de", wireguard(false)),
+                    ],
+                ),
+            ],
+            ..RelayList::empty()
+        };
+        let index = RelayIndex::new(&relay_list);
+        let custom_lists = CustomListsSettings::default();
+
+        let mut query = RelayQuery::new();
+        assert_eq!(index.candidates(&query, &custom_lists).count(), 6);
+
+        query.tunnel_protocol = Constraint::Only(TunnelType::Wireguard);
+        query.location = Constraint::Only(LocationConstraint::from(
+            GeographicLocationConstraint::country("de"),
+        ));
+        assert_eq!(
+            hostnames(index.candidates(&query, &custom_lists)),
+            ["de-wg-001", "de-wg-002"]
+        );
+
+        query.location = Constraint::Any;
+        query.wireguard_constraints.daita = Constraint::Only(true);
+        assert_eq!(
+            hostnames(index.candidates(&query, &custom_lists)),
+            ["se-wg-002", "de-wg-001"]
+        );
+
+        let bridge_constraints = InternalBridgeConstraints {
+            location: Constraint::Any,
+            providers: Constraint::Any,
+            ownership: Constraint::Any,
+            transport_protocol: Constraint::Any,
+        };
+        assert_eq!(
+            hostnames(index.bridge_candidates(&bridge_constraints, &custom_lists)),
+            ["se-br-001"]
+        );
+    }
+}
@@ -0,0 +1,185 @@
+//! Adds synthetic relays to the relay list, so that end-to-end tests can connect to WireGuard and
+//! OpenVPN servers of their own instead of to Mullvad relays.
+//!
+//! The relays are read from the JSON file that [`TEST_RELAYS_VAR`] points to, and are added to
+//! every relay list that is loaded or fetched. They can be selected like any other relay, for
+//! example by constraining the location to their country or hostname.
+
+use mullvad_types::relay_list::{OpenVpnEndpoint, RelayList, RelayListCountry};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::{fs, path::Path};
+use talpid_types::ErrorExt;
+
+/// Path to a JSON file with the relays to add.
+pub const TEST_RELAYS_VAR: &str = "MULLVAD_TEST_RELAYS";
+
+static TEST_RELAYS: Lazy<Option<TestRelays>> = Lazy::new(|| {
+    let path = std::env::var_os(TEST_RELAYS_VAR)?;
+    match TestRelays::from_file(Path::new(&path)) {
+        Ok(test_relays) => {
+            log::warn!(
+                "Adding {} test relays from {}",
+                test_relays.relay_count(),
+                Path::new(&path).display()
+            );
+            Some(test_relays)
+        }
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg(&format!(
+                    "Failed to read test relays from {}",
+                    Path::new(&path).display()
+                ))
+            );
+            None
+        }
+    }
+});
+
+/// Contents of the test relays file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TestRelays {
+    /// Relays to add, in the same format as the relay list. Countries and cities that are already
+    /// in the relay list are merged.
+    countries: Vec<RelayListCountry>,
+    /// Ports that the test WireGuard servers listen on. A port is only selected if it is in the
+    /// relay list.
+    #[serde(default)]
+    wireguard_ports: Vec<u16>,
+    /// Ports and protocols that the test OpenVPN servers listen on.
+    #[serde(default)]
+    openvpn_ports: Vec<OpenVpnEndpoint>,
+}
+
+#[derive(thiserror::Error, Debug)]
+enum Error {
+    #[error("Failed to read file")]
+    Read(#[from] std::io::Error),
+    #[error("Failed to parse file")]
+    Parse(#[from] serde_json::Error),
+}
+
+impl TestRelays {
+    fn from_file(path: &Path) -> Result<Self, Error> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    fn relay_count(&self) -> usize {
+        self.countries
+            .iter()
+            .flat_map(|country| &country.cities)
+            .map(|city| city.relays.len())
+            .sum()
+    }
+
+    fn add_to(&self, relay_list: &mut RelayList) {
+        for test_country in &self.countries {
+            let Some(country) = relay_list
+                .countries
+                .iter_mut()
+                .find(|country| country.code == test_country.code)
+            else {
+                relay_list.countries.push(test_country.clone());
+                continue;
+            };
+            for test_city in &test_country.cities {
+                match country
+                    .cities
+                    .iter_mut()
+                    .find(|city| city.code == test_city.code)
+                {
+                    Some(city) => city.relays.extend(test_city.relays.iter().cloned()),
+                    None => country.cities.push(test_city.clone()),
+                }
+            }
+        }
+
+        let port_ranges = &mut relay_list.wireguard.port_ranges;
+        for port in &self.wireguard_ports {
+            if !port_ranges
+                .iter()
+                .any(|&(first, last)| (first..=last).contains(port))
+            {
+                port_ranges.push((*port, *port));
+            }
+        }
+
+        let openvpn_ports = &mut relay_list.openvpn.ports;
+        for endpoint in &self.openvpn_ports {
+            if !openvpn_ports.contains(endpoint) {
+                openvpn_ports.push(endpoint.clone());
+            }
+        }
+    }
+}
+
+/// Add the test relays, if any, to `relay_list`.
+pub(crate) fn add_to(relay_list: &mut RelayList) {
+    if let Some(test_relays) = &*TEST_RELAYS {
+        test_relays.add_to(relay_list);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_add_to() {
+        let test_relays: TestRelays = serde_json::from_str(
+            r#"{
+                "countries": [{
+                    "name": "Sweden",
+                    "code": "se",
+                    "cities": [{
+                        "name": "Test",
+                        "code": "test",
+                        "latitude": 0.0,
+                        "longitude": 0.0,
+                        "relays": [{
+                            "hostname": "se-test-wg-001",
+                            "ipv4_addr_in": "10.0.0.2",
+                            "ipv6_addr_in": null,
+                            "include_in_country": false,
+                            "active": true,
+                            "owned": true,
+                            "provider": "test",
+                            "weight": 1,
+                            "endpoint_data": {
+                                "wireguard": {
+                                    "public_key": "BLNHNoGO88LjV/wDBa7CUUwUzPq/fO2UwcGLy56hKy4="
+                                }
+                            },
+                            "location": null
+                        }]
+                    }]
+                }],
+                "wireguard_ports": [51820, 53]
+            }"#,
+        )
+        .unwrap();
+
+        let mut relay_list = RelayList::empty();
+        relay_list.wireguard.port_ranges = vec![(53, 53)];
+        test_relays.add_to(&mut relay_list);
+
+        assert_eq!(
+            relay_list
+                .relays()
+                .map(|relay| relay.hostname.as_str())
+                .collect::<Vec<_>>(),
+            ["se-test-wg-001"]
+        );
+        assert_eq!(relay_list.wireguard.port_ranges, [(53, 53), (51820, 51820)]);
+
+        // Existing countries and cities are merged, and ports are not added twice
+        test_relays.add_to(&mut relay_list);
+        assert_eq!(relay_list.countries.len(), 1);
+        assert_eq!(relay_list.countries[0].cities.len(), 1);
+        assert_eq!(relay_list.relays().count(), 2);
+        assert_eq!(relay_list.wireguard.port_ranges, [(53, 53), (51820, 51820)]);
+    }
+}
@@ -1,11 +1,11 @@
 //! Tests for verifying that the relay selector works as expected.
 
 use once_cell::sync::Lazy;
-use std::collections::HashSet;
+use std::{collections::HashSet, net::IpAddr, time::Duration};
 use talpid_types::net::{
     obfuscation::ObfuscatorConfig,
     wireguard::PublicKey,
-    Endpoint,
+    Endpoint, IpVersion,
     TransportProtocol::{Tcp, Udp},
     TunnelType,
 };
@@ -19,16 +19,21 @@ use mullvad_types::{
     constraints::Constraint,
     endpoint::MullvadEndpoint,
     relay_constraints::{
-        BridgeConstraints, BridgeState, GeographicLocationConstraint, Ownership, Providers,
-        SelectedObfuscation, TransportPort,
+        BridgeConstraints, BridgeState, GeographicLocationConstraint, IpVersionPreference,
+        MultihopDiversity, Ownership, PortRange, Providers, RequiredRelayFeatures,
+        SelectedObfuscation, TransportPort, DEFAULT_TLS_SNI,
     },
     relay_list::{
         BridgeEndpointData, OpenVpnEndpoint, OpenVpnEndpointData, Relay, RelayEndpointData,
-        RelayList, RelayListCity, RelayListCountry, ShadowsocksEndpointData, WireguardEndpointData,
-        WireguardRelayEndpointData,
+        RelayLatency, RelayList, RelayListCity, RelayListCountry, ShadowsocksEndpointData,
+        TlsEndpointData, WireguardEndpointData, WireguardRelayEndpointData,
     },
+    states::LastConnectedRelay,
 };
 
+/// Digest of the certificate of the only relay with TLS obfuscation in [`RELAYS`].
+const TLS_CERTIFICATE_SHA256: [u8; 32] = [0x5a; 32];
+
 static RELAYS: Lazy<RelayList> = Lazy::new(|| RelayList {
     etag: None,
     countries: vec![RelayListCountry {
@@ -49,12 +54,15 @@ static RELAYS: Lazy<RelayList> = Lazy::new(|| RelayList {
                     owned: true,
                     provider: "provider0".to_string(),
                     weight: 1,
+                    stboot: false,
                     endpoint_data: RelayEndpointData::Wireguard(WireguardRelayEndpointData {
                         public_key: PublicKey::from_base64(
                             "BLNHNoGO88LjV/wDBa7CUUwUzPq/fO2UwcGLy56hKy4=",
                         )
                         .unwrap(),
                         daita: false,
+                        quic: false,
+                        tls: None,
                     }),
                     location: None,
                 },
@@ -67,12 +75,18 @@ static RELAYS: Lazy<RelayList> = Lazy::new(|| RelayList {
                     owned: false,
                     provider: "provider1".to_string(),
                     weight: 1,
+                    stboot: false,
                     endpoint_data: RelayEndpointData::Wireguard(WireguardRelayEndpointData {
                         public_key: PublicKey::from_base64(
-                            "BLNHNoGO88LjV/wDBa7CUUwUzPq/fO2UwcGLy56hKy4=",
+                            "b6m+JD9aiTDqVWgmEcaU+FgXJK4oNf7U7VQnRWVfiYc=",
                         )
                         .unwrap(),
                         daita: false,
+                        quic: false,
+                        tls: Some(TlsEndpointData {
+                            port: 443,
+                            certificate_sha256: TLS_CERTIFICATE_SHA256,
+                        }),
                     }),
                     location: None,
                 },
@@ -85,6 +99,7 @@ static RELAYS: Lazy<RelayList> = Lazy::new(|| RelayList {
                     owned: true,
                     provider: "provider2".to_string(),
                     weight: 1,
+                    stboot: false,
                     endpoint_data: RelayEndpointData::Openvpn,
                     location: None,
                 },
@@ -97,6 +112,7 @@ static RELAYS: Lazy<RelayList> = Lazy::new(|| RelayList {
                     owned: true,
                     provider: "provider0".to_string(),
                     weight: 1,
+                    stboot: false,
                     endpoint_data: RelayEndpointData::Openvpn,
                     location: None,
                 },
@@ -109,6 +125,7 @@ static RELAYS: Lazy<RelayList> = Lazy::new(|| RelayList {
                     owned: true,
                     provider: "provider3".to_string(),
                     weight: 1,
+                    stboot: false,
                     endpoint_data: RelayEndpointData::Bridge,
                     location: None,
                 },
@@ -230,7 +247,7 @@ fn supports_daita(relay: &Relay) -> bool {
 /// [`RETRY_ORDER`].
 #[test]
 fn assert_retry_order() {
-    use talpid_types::net::{IpVersion, TransportProtocol};
+    use talpid_types::net::TransportProtocol;
     let expected_retry_order = vec![
         // 1
         RelayQueryBuilder::new().build(),
@@ -284,7 +301,13 @@ fn test_retry_order() {
     let relay_selector = default_relay_selector();
     for (retry_attempt, query) in RETRY_ORDER.iter().enumerate() {
         let relay = relay_selector
-            .get_relay(retry_attempt, RuntimeParameters { ipv6: true })
+            .get_relay(
+                retry_attempt,
+                RuntimeParameters {
+                    ipv6: true,
+                    require_obfuscation: false,
+                },
+            )
             .unwrap_or_else(|_| panic!("Retry attempt {retry_attempt} did not yield any relay"));
         // For each relay, cross-check that the it has the expected tunnel protocol
         let tunnel_type = tunnel_type(&unwrap_relay(relay.clone()));
@@ -314,7 +337,7 @@ fn test_retry_order() {
                 assert!(match query.wireguard_constraints.obfuscation {
                     SelectedObfuscation::Auto => true,
                     SelectedObfuscation::Off => obfuscator.is_none(),
-                    SelectedObfuscation::Udp2Tcp => obfuscator.is_some(),
+                    SelectedObfuscation::Udp2Tcp | SelectedObfuscation::Tls => obfuscator.is_some(),
                 });
             }
             GetRelay::OpenVpn {
@@ -431,12 +454,15 @@ fn test_wireguard_entry() {
                         owned: true,
                         provider: "provider0".to_string(),
                         weight: 1,
+                        stboot: false,
                         endpoint_data: RelayEndpointData::Wireguard(WireguardRelayEndpointData {
                             public_key: PublicKey::from_base64(
                                 "BLNHNoGO88LjV/wDBa7CUUwUzPq/fO2UwcGLy56hKy4=",
                             )
                             .unwrap(),
                             daita: false,
+                            quic: false,
+                            tls: None,
                         }),
                         location: None,
                     },
@@ -449,12 +475,15 @@ fn test_wireguard_entry() {
                         owned: false,
                         provider: "provider1".to_string(),
                         weight: 1,
+                        stboot: false,
                         endpoint_data: RelayEndpointData::Wireguard(WireguardRelayEndpointData {
                             public_key: PublicKey::from_base64(
                                 "BLNHNoGO88LjV/wDBa7CUUwUzPq/fO2UwcGLy56hKy4=",
                             )
                             .unwrap(),
                             daita: false,
+                            quic: false,
+                            tls: None,
                         }),
                         location: None,
                     },
@@ -579,6 +608,60 @@ fn test_wireguard_entry_hostname_collision() {
         .is_ok())
 }
 
+/// If neither hop is constrained, the relay selector should prefer entry and exit relays that
+/// differ in the ways given by the multihop diversity, and fall back to any pair of relays if no
+/// such pair exists.
+#[test]
+fn test_multihop_diversity() {
+    // se9-wireguard is run by provider0 and se10-wireguard by provider1. Add a relay in another
+    // country run by provider1, so that se9-wireguard and de1-wireguard is the only diverse pair.
+    let mut relays = RELAYS.clone();
+    let mut de1 = relays.countries[0].cities[0].relays[0].clone();
+    de1.hostname = "de1-wireguard".to_string();
+    de1.ipv4_addr_in = "185.213.155.1".parse().unwrap();
+    de1.provider = "provider1".to_string();
+    relays.countries.push(RelayListCountry {
+        name: "Germany".to_string(),
+        code: "de".to_string(),
+        cities: vec![RelayListCity {
+            name: "Frankfurt".to_string(),
+            code: "fra".to_string(),
+            latitude: 50.110924,
+            longitude: 8.682127,
+            relays: vec![de1],
+        }],
+    });
+    let relay_selector = RelaySelector::from_list(SelectorConfig::default(), relays);
+
+    let query = RelayQueryBuilder::new()
+        .wireguard()
+        .multihop()
+        .multihop_diversity(MultihopDiversity::default())
+        .build();
+    for _ in 0..100 {
+        let relay = relay_selector.get_relay_by_query(query.clone()).unwrap();
+        let exit = unwrap_relay(relay.clone());
+        let entry = unwrap_entry_relay(relay);
+        let hostnames = HashSet::from([exit.hostname, entry.hostname]);
+        assert_eq!(
+            hostnames,
+            HashSet::from(["se9-wireguard".to_string(), "de1-wireguard".to_string()])
+        );
+    }
+
+    // All relays in the default relay list are in the same country
+    let relay_selector = default_relay_selector();
+    let query = RelayQueryBuilder::new()
+        .wireguard()
+        .multihop()
+        .multihop_diversity(MultihopDiversity {
+            country: true,
+            provider: false,
+        })
+        .build();
+    assert!(relay_selector.get_relay_by_query(query).is_ok());
+}
+
 /// Test that the relay selector:
 /// * returns an OpenVPN relay given a constraint of a valid transport protocol + port combo
 /// * does *not* return an OpenVPN relay given a constraint of an *invalid* transport protocol +
@@ -733,6 +816,95 @@ fn test_selecting_wireguard_endpoint_with_udp2tcp_obfuscation() {
     }
 }
 
+/// Construct a query for a Wireguard configuration where TLS obfuscation is selected. Assert that
+/// only the relay that publishes a TLS endpoint is picked, and that the obfuscator connects to that
+/// endpoint, pins its certificate and uses the default server name.
+#[test]
+fn test_selecting_wireguard_endpoint_with_tls_obfuscation() {
+    let relay_selector = default_relay_selector();
+    let mut query = RelayQueryBuilder::new().wireguard().tls().build();
+    query.wireguard_constraints.use_multihop = Constraint::Only(false);
+
+    for _ in 0..100 {
+        let relay = relay_selector.get_relay_by_query(query.clone()).unwrap();
+        match relay {
+            GetRelay::Wireguard {
+                obfuscator,
+                inner: WireguardConfig::Singlehop { exit },
+                ..
+            } => {
+                assert_eq!(exit.hostname, "se10-wireguard");
+                let Some(obfuscator) = obfuscator else {
+                    panic!("Relay selector should have picked an obfuscator")
+                };
+                let ObfuscatorConfig::Tls {
+                    endpoint,
+                    sni,
+                    certificate_sha256,
+                } = obfuscator.config
+                else {
+                    panic!("Relay selector should have picked a TLS obfuscator")
+                };
+                assert_eq!(endpoint.port(), 443);
+                assert_eq!(sni, DEFAULT_TLS_SNI);
+                assert_eq!(certificate_sha256, TLS_CERTIFICATE_SHA256);
+            }
+            wrong_relay => panic!(
+                "Relay selector should have picked a Wireguard relay, instead chose {wrong_relay:?}"
+            ),
+        }
+    }
+}
+
+/// Assert that the relay selector only returns obfuscated Wireguard relays when obfuscation is
+/// required, e.g. after failing to negotiate an ephemeral peer over UDP.
+#[test]
+fn test_require_obfuscation() {
+    let relay_selector = default_relay_selector();
+    let runtime_params = RuntimeParameters {
+        ipv6: true,
+        require_obfuscation: true,
+    };
+
+    for retry_attempt in 0..RETRY_ORDER.len() {
+        let relay = relay_selector
+            .get_relay(retry_attempt, runtime_params.clone())
+            .unwrap();
+        match relay {
+            GetRelay::Wireguard { obfuscator, .. } => {
+                assert!(obfuscator.is_some_and(|obfuscator| matches!(
+                    obfuscator.config,
+                    ObfuscatorConfig::Udp2Tcp { .. } | ObfuscatorConfig::Tls { .. }
+                )))
+            }
+            wrong_relay => panic!(
+                "Relay selector should have picked a Wireguard relay, instead chose {wrong_relay:?}"
+            ),
+        }
+    }
+}
+
+/// Assert that requiring obfuscation does not prevent the relay selector from returning a relay
+/// when the user has disabled obfuscation.
+#[test]
+fn test_require_obfuscation_falls_back_when_obfuscation_is_off() {
+    let mut config = SelectorConfig::default();
+    config.obfuscation_settings.selected_obfuscation = SelectedObfuscation::Off;
+    let relay_selector = RelaySelector::from_list(config, RELAYS.clone());
+    let runtime_params = RuntimeParameters {
+        ipv6: false,
+        require_obfuscation: true,
+    };
+
+    let relay = relay_selector.get_relay(0, runtime_params).unwrap();
+    match relay {
+        GetRelay::Wireguard { obfuscator, .. } => assert!(obfuscator.is_none()),
+        wrong_relay => panic!(
+            "Relay selector should have picked a Wireguard relay, instead chose {wrong_relay:?}"
+        ),
+    }
+}
+
 /// Construct a query for a Wireguard configuration where UDP2TCP obfuscation is set to "Auto" and
 /// multihop is explicitly turned off. Assert that the relay selector does *not* return an
 /// obfuscator config.
@@ -783,6 +955,7 @@ fn test_selected_wireguard_endpoints_use_correct_port_ranges() {
                 assert!(match obfuscator.config {
                     ObfuscatorConfig::Udp2Tcp { endpoint } =>
                         TCP2UDP_PORTS.contains(&endpoint.port()),
+                    ObfuscatorConfig::Tls { .. } => false,
                 })
             }
             wrong_relay => panic!(
@@ -792,6 +965,154 @@ fn test_selected_wireguard_endpoints_use_correct_port_ranges() {
     }
 }
 
+/// Verify that a random port is picked from the allowed port range for every Wireguard endpoint.
+#[test]
+fn test_wireguard_port_range() {
+    let range = PortRange::new(4000, 4010).unwrap();
+    let relay_selector = default_relay_selector();
+    let query = RelayQueryBuilder::new()
+        .wireguard()
+        .port_range(range)
+        .build();
+
+    let mut ports = HashSet::new();
+    for _ in 0..100 {
+        let relay = relay_selector.get_relay_by_query(query.clone()).unwrap();
+        let port = unwrap_endpoint(relay).to_endpoint().address.port();
+        assert!(range.contains(port), "port {port} is not in {range}");
+        ports.insert(port);
+    }
+    assert!(ports.len() > 1, "the same port was picked every time");
+}
+
+/// Verify that the IPv6 address of a dual-stack relay is provided as an alternate endpoint, unless
+/// the IP version is constrained.
+#[test]
+fn test_wireguard_alternate_endpoint() {
+    let relay_selector = default_relay_selector();
+    let query = RelayQueryBuilder::new().wireguard().build();
+    let relay = relay_selector.get_relay_by_query(query).unwrap();
+    let GetRelay::Wireguard { endpoint, .. } = relay else {
+        panic!("Relay selector should have picked a Wireguard relay, instead chose {relay:?}");
+    };
+    let alternate = endpoint
+        .peer
+        .alternate_endpoint
+        .expect("Dual-stack relay should have an alternate endpoint");
+    assert!(endpoint.peer.endpoint.is_ipv4());
+    assert!(alternate.is_ipv6());
+    assert_eq!(alternate.port(), endpoint.peer.endpoint.port());
+
+    let query = RelayQueryBuilder::new()
+        .wireguard()
+        .ip_version(IpVersion::V4)
+        .build();
+    let relay = relay_selector.get_relay_by_query(query).unwrap();
+    let GetRelay::Wireguard { endpoint, .. } = relay else {
+        panic!("Relay selector should have picked a Wireguard relay, instead chose {relay:?}");
+    };
+    assert_eq!(endpoint.peer.alternate_endpoint, None);
+}
+
+/// Verify that another matching relay is picked as a candidate to connect to in parallel, but
+/// only if there is one.
+#[test]
+fn test_wireguard_candidate_relay() {
+    let relay_selector = default_relay_selector();
+    let query = RelayQueryBuilder::new().wireguard().build();
+    let relay = relay_selector.get_relay_by_query(query).unwrap();
+    let GetRelay::Wireguard {
+        endpoint,
+        inner: WireguardConfig::Singlehop { exit },
+        candidate: Some(candidate),
+        ..
+    } = relay
+    else {
+        panic!("Relay selector should have picked a candidate relay, instead chose {relay:?}");
+    };
+    assert_ne!(exit.hostname, candidate.hostname);
+    let candidate_peer = endpoint
+        .candidate_peer
+        .expect("Candidate relay should have a peer config");
+    assert_eq!(
+        candidate_peer.endpoint.ip(),
+        IpAddr::from(candidate.ipv4_addr_in)
+    );
+    assert_eq!(
+        candidate_peer.endpoint.port(),
+        endpoint.peer.endpoint.port()
+    );
+
+    let query = RelayQueryBuilder::new()
+        .wireguard()
+        .location(GeographicLocationConstraint::hostname(
+            "se",
+            "got",
+            "se9-wireguard",
+        ))
+        .build();
+    let relay = relay_selector.get_relay_by_query(query).unwrap();
+    let GetRelay::Wireguard {
+        endpoint,
+        candidate,
+        ..
+    } = relay
+    else {
+        panic!("Relay selector should have picked a Wireguard relay, instead chose {relay:?}");
+    };
+    assert!(candidate.is_none());
+    assert!(endpoint.candidate_peer.is_none());
+}
+
+/// Verify that retry attempts never pick a port outside of the allowed port range, and that a
+/// range without any valid Wireguard ports yields no relay.
+#[test]
+fn test_retry_order_respects_port_range() {
+    let range = PortRange::new(30000, 30100).unwrap();
+    let config = SelectorConfig {
+        relay_settings: RelayQueryBuilder::new()
+            .wireguard()
+            .port_range(range)
+            .into_constraint()
+            .into(),
+        ..SelectorConfig::default()
+    };
+    let relay_selector = RelaySelector::from_list(config, RELAYS.clone());
+    for retry_attempt in 0..RETRY_ORDER.len() {
+        let relay = relay_selector
+            .get_relay(retry_attempt, RuntimeParameters::default())
+            .unwrap();
+        let port = unwrap_endpoint(relay).to_endpoint().address.port();
+        assert!(range.contains(port), "port {port} is not in {range}");
+    }
+
+    let invalid_range = PortRange::new(60001, 60100).unwrap();
+    let query = RelayQueryBuilder::new()
+        .wireguard()
+        .port_range(invalid_range)
+        .build();
+    assert!(default_relay_selector().get_relay_by_query(query).is_err());
+}
+
+/// Verify that the retry order can be restricted to a single tunnel protocol when the tunnel
+/// protocol is automatic.
+#[test]
+fn test_relay_by_tunnel_type() {
+    let relay_selector = default_relay_selector();
+    for expected in [TunnelType::Wireguard, TunnelType::OpenVpn] {
+        for retry_attempt in 0..RETRY_ORDER.len() {
+            let relay = relay_selector
+                .get_relay_by_tunnel_type(retry_attempt, expected, RuntimeParameters::default())
+                .unwrap();
+            assert_eq!(
+                tunnel_type(&unwrap_relay(relay)),
+                expected,
+                "Retry attempt {retry_attempt} yielded an unexpected tunnel type"
+            );
+        }
+    }
+}
+
 /// Verify that any query which sets an explicit [`Ownership`] is respected by the relay selector.
 #[test]
 fn test_ownership() {
@@ -951,12 +1272,15 @@ fn test_include_in_country() {
                         owned: true,
                         provider: "31173".to_string(),
                         weight: 1,
+                        stboot: false,
                         endpoint_data: RelayEndpointData::Wireguard(WireguardRelayEndpointData {
                             public_key: PublicKey::from_base64(
                                 "BLNHNoGO88LjV/wDBa7CUUwUzPq/fO2UwcGLy56hKy4=",
                             )
                             .unwrap(),
                             daita: false,
+                            quic: false,
+                            tls: None,
                         }),
                         location: None,
                     },
@@ -969,12 +1293,15 @@ fn test_include_in_country() {
                         owned: false,
                         provider: "31173".to_string(),
                         weight: 1,
+                        stboot: false,
                         endpoint_data: RelayEndpointData::Wireguard(WireguardRelayEndpointData {
                             public_key: PublicKey::from_base64(
                                 "BLNHNoGO88LjV/wDBa7CUUwUzPq/fO2UwcGLy56hKy4=",
                             )
                             .unwrap(),
                             daita: false,
+                            quic: false,
+                            tls: None,
                         }),
                         location: None,
                     },
@@ -1169,12 +1496,15 @@ fn test_daita() {
                         owned: true,
                         provider: "31173".to_string(),
                         weight: 1,
+                        stboot: false,
                         endpoint_data: RelayEndpointData::Wireguard(WireguardRelayEndpointData {
                             public_key: PublicKey::from_base64(
                                 "BLNHNoGO88LjV/wDBa7CUUwUzPq/fO2UwcGLy56hKy4=",
                             )
                             .unwrap(),
                             daita: false,
+                            quic: false,
+                            tls: None,
                         }),
                         location: None,
                     },
@@ -1187,12 +1517,15 @@ fn test_daita() {
                         owned: false,
                         provider: "31173".to_string(),
                         weight: 1,
+                        stboot: false,
                         endpoint_data: RelayEndpointData::Wireguard(WireguardRelayEndpointData {
                             public_key: PublicKey::from_base64(
                                 "BLNHNoGO88LjV/wDBa7CUUwUzPq/fO2UwcGLy56hKy4=",
                             )
                             .unwrap(),
                             daita: true,
+                            quic: false,
+                            tls: None,
                         }),
                         location: None,
                     },
@@ -1297,6 +1630,228 @@ fn test_daita() {
     }
 }
 
+/// Relays that lack a feature that is required by the user's settings must never be selected, not
+/// even when the selector falls back to the default constraints.
+#[test]
+fn test_required_relay_features() {
+    let mut relay_list = RELAYS.clone();
+    for relay in &mut relay_list.countries[0].cities[0].relays {
+        if relay.hostname == "se10-wireguard" {
+            relay.stboot = true;
+            if let RelayEndpointData::Wireguard(data) = &mut relay.endpoint_data {
+                data.quic = true;
+            }
+        }
+    }
+
+    for user_constraints in [
+        RelayQueryBuilder::new()
+            .wireguard()
+            .quic()
+            .into_constraint(),
+        RelayQueryBuilder::new()
+            .wireguard()
+            .stboot()
+            .into_constraint(),
+    ] {
+        let config = SelectorConfig {
+            relay_settings: user_constraints.into(),
+            ..SelectorConfig::default()
+        };
+        let relay_selector = RelaySelector::from_list(config, relay_list.clone());
+        for retry_attempt in 0..RETRY_ORDER.len() {
+            let relay = relay_selector
+                .get_relay(retry_attempt, RuntimeParameters::default())
+                .map(unwrap_entry_relay)
+                .unwrap();
+            assert_eq!(relay.hostname, "se10-wireguard");
+        }
+    }
+
+    // None of the relays support DAITA
+    let mut user_constraints = RelayQueryBuilder::new().wireguard().into_constraint();
+    user_constraints.wireguard_constraints.required_features = RequiredRelayFeatures {
+        daita: true,
+        ..RequiredRelayFeatures::default()
+    };
+    let config = SelectorConfig {
+        relay_settings: user_constraints.into(),
+        ..SelectorConfig::default()
+    };
+    let relay_selector = RelaySelector::from_list(config, relay_list);
+    for retry_attempt in 0..RETRY_ORDER.len() {
+        relay_selector
+            .get_relay(retry_attempt, RuntimeParameters::default())
+            .expect_err("Expected to find no relay that supports DAITA");
+    }
+}
+
+/// Relays that were too slow to respond or did not respond at all the last time they were pinged
+/// must not be selected when there is a latency constraint. Relays that have not been pinged may
+/// still be selected.
+#[test]
+fn test_max_latency() {
+    let user_constraints = RelayQueryBuilder::new()
+        .wireguard()
+        .max_latency(100)
+        .into_constraint();
+    let config = SelectorConfig {
+        relay_settings: user_constraints.into(),
+        ..SelectorConfig::default()
+    };
+    let relay_selector = RelaySelector::from_list(config, RELAYS.clone());
+
+    relay_selector.set_relay_latencies([RelayLatency {
+        hostname: "se9-wireguard".to_string(),
+        latency: Some(Duration::from_millis(150)),
+    }]);
+    for retry_attempt in 0..RETRY_ORDER.len() {
+        let relay = relay_selector
+            .get_relay(retry_attempt, RuntimeParameters::default())
+            .map(unwrap_entry_relay)
+            .unwrap();
+        assert_eq!(relay.hostname, "se10-wireguard");
+    }
+
+    relay_selector.set_relay_latencies([
+        RelayLatency {
+            hostname: "se9-wireguard".to_string(),
+            latency: Some(Duration::from_millis(50)),
+        },
+        RelayLatency {
+            hostname: "se10-wireguard".to_string(),
+            latency: None,
+        },
+    ]);
+    for retry_attempt in 0..RETRY_ORDER.len() {
+        let relay = relay_selector
+            .get_relay(retry_attempt, RuntimeParameters::default())
+            .map(unwrap_entry_relay)
+            .unwrap();
+        assert_eq!(relay.hostname, "se9-wireguard");
+    }
+
+    // Latencies are kept when the relay list is updated
+    relay_selector.set_relays(RELAYS.clone());
+    relay_selector.set_relay_latencies([RelayLatency {
+        hostname: "se9-wireguard".to_string(),
+        latency: None,
+    }]);
+    for retry_attempt in 0..RETRY_ORDER.len() {
+        relay_selector
+            .get_relay(retry_attempt, RuntimeParameters::default())
+            .expect_err("Expected to find no relay that responded in time");
+    }
+}
+
+/// Relays that are pinned by their public keys must be selected, for both singlehop and multihop.
+#[test]
+fn test_public_key() {
+    let se9_key = PublicKey::from_base64("BLNHNoGO88LjV/wDBa7CUUwUzPq/fO2UwcGLy56hKy4=").unwrap();
+    let se10_key = PublicKey::from_base64("b6m+JD9aiTDqVWgmEcaU+FgXJK4oNf7U7VQnRWVfiYc=").unwrap();
+    let relay_selector = RelaySelector::from_list(SelectorConfig::default(), RELAYS.clone());
+
+    let query = RelayQueryBuilder::new()
+        .wireguard()
+        .public_key(se10_key.clone())
+        .build();
+    for _ in 0..100 {
+        let relay = relay_selector
+            .get_relay_by_query(query.clone())
+            .map(unwrap_relay)
+            .unwrap();
+        assert_eq!(relay.hostname, "se10-wireguard");
+    }
+
+    let query = RelayQueryBuilder::new()
+        .wireguard()
+        .public_key(se9_key.clone())
+        .multihop()
+        .entry_public_key(se10_key)
+        .build();
+    for _ in 0..100 {
+        let relay = relay_selector.get_relay_by_query(query.clone()).unwrap();
+        assert_eq!(unwrap_relay(relay.clone()).hostname, "se9-wireguard");
+        assert_eq!(unwrap_entry_relay(relay).hostname, "se10-wireguard");
+    }
+
+    // No relay has the key
+    let query = RelayQueryBuilder::new()
+        .wireguard()
+        .public_key(PublicKey::from([0; 32]))
+        .build();
+    relay_selector
+        .get_relay_by_query(query)
+        .expect_err("Expected to find no relay with the public key");
+}
+
+/// Verify that the relay that was connected to before a restart is resumed with the same
+/// transport, unless it no longer matches the constraints.
+#[test]
+fn test_resume_relay() {
+    let relay_selector = default_relay_selector();
+    let last_relay = LastConnectedRelay {
+        tunnel_type: TunnelType::Wireguard,
+        hostname: "se10-wireguard".to_string(),
+        entry_hostname: None,
+        endpoint: Endpoint::new("185.213.154.69".parse::<IpAddr>().unwrap(), 53, Udp),
+        obfuscated: false,
+    };
+    let relay = relay_selector
+        .get_resumed_relay(&last_relay, RuntimeParameters::default())
+        .unwrap();
+    assert_eq!(unwrap_relay(relay.clone()).hostname, "se10-wireguard");
+    let MullvadEndpoint::Wireguard(endpoint) = unwrap_endpoint(relay) else {
+        panic!("Expected a WireGuard endpoint");
+    };
+    assert_eq!(endpoint.peer.endpoint.port(), 53);
+
+    let last_openvpn_relay = LastConnectedRelay {
+        tunnel_type: TunnelType::OpenVpn,
+        hostname: "se-got-002".to_string(),
+        entry_hostname: None,
+        endpoint: Endpoint::new("1.2.3.4".parse::<IpAddr>().unwrap(), 443, Tcp),
+        obfuscated: false,
+    };
+    let relay = relay_selector
+        .get_resumed_relay(&last_openvpn_relay, RuntimeParameters::default())
+        .unwrap();
+    assert_eq!(unwrap_relay(relay.clone()).hostname, "se-got-002");
+    let MullvadEndpoint::OpenVpn(endpoint) = unwrap_endpoint(relay) else {
+        panic!("Expected an OpenVPN endpoint");
+    };
+    assert_eq!(
+        endpoint,
+        Endpoint::new("1.2.3.4".parse::<IpAddr>().unwrap(), 443, Tcp)
+    );
+
+    // The relay has been removed
+    let removed_relay = LastConnectedRelay {
+        hostname: "se11-wireguard".to_string(),
+        ..last_relay.clone()
+    };
+    relay_selector
+        .get_resumed_relay(&removed_relay, RuntimeParameters::default())
+        .expect_err("Expected a removed relay not to be resumed");
+
+    // The relay no longer matches the location constraint
+    let config = SelectorConfig {
+        relay_settings: RelayQueryBuilder::new()
+            .location(GeographicLocationConstraint::hostname(
+                "se",
+                "got",
+                "se9-wireguard",
+            ))
+            .into_constraint()
+            .into(),
+        ..SelectorConfig::default()
+    };
+    let relay_selector = RelaySelector::from_list(config, RELAYS.clone());
+    relay_selector
+        .get_resumed_relay(&last_relay, RuntimeParameters::default())
+        .expect_err("Expected a relay outside of the location not to be resumed");
+}
+
 /// Check that if  the original user query would yield a relay, the result of running the query
 /// which is the intersection between the user query and any of the default queries shall never
 /// fail.
@@ -1323,3 +1878,41 @@ fn valid_user_setting_should_yield_relay() {
         }
     }
 }
+
+/// Check that the IP version preference changes the IP version that is tried first, and that the
+/// other IP version is still used by a later retry attempt.
+#[test]
+fn test_ip_version_preference() {
+    let endpoint_ip = |relay_selector: &RelaySelector, retry_attempt, ipv6| {
+        let runtime_params = RuntimeParameters {
+            ipv6,
+            require_obfuscation: false,
+        };
+        match relay_selector
+            .get_relay(retry_attempt, runtime_params)
+            .unwrap()
+        {
+            GetRelay::Wireguard { endpoint, .. } => endpoint.peer.endpoint.ip(),
+            relay => panic!("Expected a WireGuard relay, got {relay:?}"),
+        }
+    };
+
+    let config = SelectorConfig {
+        ip_version_preference: IpVersionPreference::Ipv6,
+        ..SelectorConfig::default()
+    };
+    let relay_selector = RelaySelector::from_list(config, RELAYS.clone());
+    assert!(endpoint_ip(&relay_selector, 0, true).is_ipv6());
+    // The third attempt falls back to the other IP version
+    assert!(endpoint_ip(&relay_selector, 2, true).is_ipv4());
+    // IPv6 cannot be preferred if it is unavailable
+    assert!(endpoint_ip(&relay_selector, 0, false).is_ipv4());
+
+    let config = SelectorConfig {
+        ip_version_preference: IpVersionPreference::Ipv4,
+        ..SelectorConfig::default()
+    };
+    let relay_selector = RelaySelector::from_list(config, RELAYS.clone());
+    assert!(endpoint_ip(&relay_selector, 0, true).is_ipv4());
+    assert!(endpoint_ip(&relay_selector, 2, true).is_ipv6());
+}
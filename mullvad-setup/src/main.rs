@@ -4,7 +4,7 @@ use std::{path::PathBuf, process, str::FromStr, time::Duration};
 
 use mullvad_api::{proxy::ApiConnectionMode, DEVICE_NOT_FOUND};
 use mullvad_management_interface::MullvadProxyClient;
-use mullvad_types::version::ParsedAppVersion;
+use mullvad_types::{shutdown::ShutdownBehavior, version::ParsedAppVersion};
 use talpid_core::firewall::{self, Firewall};
 use talpid_future::retry::{retry_future, ConstantInterval};
 use talpid_types::ErrorExt;
@@ -76,8 +76,15 @@ pub enum Error {
     disable_version_flag = true
 )]
 enum Cli {
-    /// Move a running daemon into a blocking state and save its target state
+    /// Move a running daemon into a blocking state and save its target state. The blocking state
+    /// is handed over to the next daemon that starts, which releases it once it has started.
     PrepareRestart,
+    /// Shut the running daemon down
+    Shutdown {
+        /// What to leave behind: 'auto', 'block', 'restore' or 'keep-tunnel=<seconds>'
+        #[arg(long, default_value_t = ShutdownBehavior::Auto)]
+        behavior: ShutdownBehavior,
+    },
     /// Remove any firewall rules introduced by the daemon
     ResetFirewall,
     /// Remove the current device from the active account
@@ -96,6 +103,7 @@ async fn main() {
 
     let result = match Cli::parse() {
         Cli::PrepareRestart => prepare_restart().await,
+        Cli::Shutdown { behavior } => shutdown(behavior).await,
         Cli::ResetFirewall => reset_firewall().await,
         Cli::RemoveDevice => remove_device().await,
         Cli::IsOlderVersion { old_version } => {
@@ -132,6 +140,16 @@ async fn prepare_restart() -> Result<(), Error> {
     Ok(())
 }
 
+async fn shutdown(behavior: ShutdownBehavior) -> Result<(), Error> {
+    let mut rpc = MullvadProxyClient::new()
+        .await
+        .map_err(Error::RpcConnectionError)?;
+    rpc.shutdown(behavior)
+        .await
+        .map_err(Error::DaemonRpcError)?;
+    Ok(())
+}
+
 async fn reset_firewall() -> Result<(), Error> {
     // Ensure that the daemon isn't running
     if MullvadProxyClient::new().await.is_ok() {
@@ -153,9 +171,13 @@ async fn remove_device() -> Result<(), Error> {
         .await
         .map_err(Error::ReadDeviceCacheError)?;
     if let Some(device) = state.into_device() {
-        let api_runtime = mullvad_api::Runtime::with_cache(&cache_path, false)
-            .await
-            .map_err(Error::RpcInitializationError)?;
+        let api_runtime = mullvad_api::Runtime::with_cache(
+            &cache_path,
+            false,
+            mullvad_api::SocketProtection::default(),
+        )
+        .await
+        .map_err(Error::RpcInitializationError)?;
 
         let connection_mode = ApiConnectionMode::try_from_cache(&cache_path).await;
         let proxy = mullvad_api::DevicesProxy::new(
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use talpid_types::net::proxy::{CustomProxy, Shadowsocks, Socks5Local, Socks5Remote};
 
 /// Settings for API access methods.
@@ -6,6 +7,9 @@ use talpid_types::net::proxy::{CustomProxy, Shadowsocks, Socks5Local, Socks5Remo
 pub struct Settings {
     direct: AccessMethodSetting,
     mullvad_bridges: AccessMethodSetting,
+    /// Proxy detected from the settings of the OS. Disabled by default.
+    #[serde(default = "Settings::create_system_proxy")]
+    system_proxy: AccessMethodSetting,
     /// Custom API access methods.
     custom: Vec<AccessMethodSetting>,
 }
@@ -14,11 +18,13 @@ impl Settings {
     pub fn new(
         direct: AccessMethodSetting,
         mullvad_bridges: AccessMethodSetting,
+        system_proxy: AccessMethodSetting,
         custom: Vec<AccessMethodSetting>,
     ) -> Settings {
         Settings {
             direct,
             mullvad_bridges,
+            system_proxy,
             custom,
         }
     }
@@ -93,6 +99,7 @@ impl Settings {
         use std::iter::once;
         once(&self.direct)
             .chain(once(&self.mullvad_bridges))
+            .chain(once(&self.system_proxy))
             .chain(&self.custom)
     }
 
@@ -101,6 +108,7 @@ impl Settings {
         use std::iter::once;
         once(&mut self.direct)
             .chain(once(&mut self.mullvad_bridges))
+            .chain(once(&mut self.system_proxy))
             .chain(&mut self.custom)
     }
 
@@ -114,6 +122,7 @@ impl Settings {
     pub fn cardinality(&self) -> usize {
         1 + // 'Direct'
         1 + // 'Mullvad bridges'
+        1 + // 'System proxy'
         self.custom.len()
     }
 
@@ -125,6 +134,10 @@ impl Settings {
         &self.mullvad_bridges
     }
 
+    pub fn system_proxy(&self) -> &AccessMethodSetting {
+        &self.system_proxy
+    }
+
     fn create_direct() -> AccessMethodSetting {
         let method = BuiltInAccessMethod::Direct;
         AccessMethodSetting::new(method.canonical_name(), true, AccessMethod::from(method))
@@ -134,6 +147,11 @@ impl Settings {
         let method = BuiltInAccessMethod::Bridge;
         AccessMethodSetting::new(method.canonical_name(), true, AccessMethod::from(method))
     }
+
+    fn create_system_proxy() -> AccessMethodSetting {
+        let method = BuiltInAccessMethod::SystemProxy;
+        AccessMethodSetting::new(method.canonical_name(), false, AccessMethod::from(method))
+    }
 }
 
 impl Default for Settings {
@@ -141,6 +159,7 @@ impl Default for Settings {
         Self {
             direct: Settings::create_direct(),
             mullvad_bridges: Settings::create_mullvad_bridges(),
+            system_proxy: Settings::create_system_proxy(),
             custom: vec![],
         }
     }
@@ -271,6 +290,9 @@ impl AccessMethodSetting {
 pub enum BuiltInAccessMethod {
     Direct,
     Bridge,
+    /// The HTTP or SOCKS proxy configured in the OS, if any. It is looked up every time the
+    /// method is used.
+    SystemProxy,
 }
 
 impl AccessMethod {
@@ -287,6 +309,7 @@ impl BuiltInAccessMethod {
         match self {
             BuiltInAccessMethod::Direct => "Direct".to_string(),
             BuiltInAccessMethod::Bridge => "Mullvad Bridges".to_string(),
+            BuiltInAccessMethod::SystemProxy => "System proxy".to_string(),
         }
     }
 }
@@ -326,3 +349,61 @@ impl From<Shadowsocks> for AccessMethod {
         CustomProxy::Shadowsocks(value).into()
     }
 }
+
+/// Outcome of testing whether the API can be reached using an access method.
+///
+/// The steps of connecting are run in order, and testing stops at the first step that fails.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TestResult {
+    pub steps: Vec<TestStepResult>,
+    /// Status code of the response from the API, if a response was received.
+    pub http_status: Option<u16>,
+    /// Time from starting the test until it succeeded or failed.
+    pub total: Duration,
+}
+
+impl TestResult {
+    /// Returns whether every step succeeded and the API responded as expected.
+    pub fn is_successful(&self) -> bool {
+        self.http_status == Some(200) && self.steps.iter().all(|step| step.error.is_none())
+    }
+
+    /// Returns the first step that failed, if any.
+    pub fn failed_step(&self) -> Option<&TestStepResult> {
+        self.steps.iter().find(|step| step.error.is_some())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TestStepResult {
+    pub step: TestStep,
+    pub duration: Duration,
+    /// Why the step failed, or `None` if it succeeded.
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TestStep {
+    /// Look up the address of the API, in the address cache or using DNS.
+    ResolveAddress,
+    /// Open a TCP connection to the API, or to the proxy if one is used.
+    TcpConnect,
+    /// Ask the proxy to connect to the API. This is skipped when connecting directly.
+    ProxyHandshake,
+    TlsHandshake,
+    /// Send a request to the API and wait for the response.
+    HttpRequest,
+}
+
+impl std::fmt::Display for TestStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let step = match self {
+            TestStep::ResolveAddress => "Resolve API address",
+            TestStep::TcpConnect => "TCP connect",
+            TestStep::ProxyHandshake => "Proxy handshake",
+            TestStep::TlsHandshake => "TLS handshake",
+            TestStep::HttpRequest => "HTTP request",
+        };
+        f.write_str(step)
+    }
+}
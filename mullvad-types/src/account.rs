@@ -1,8 +1,10 @@
 use chrono::{offset::Utc, DateTime};
 use serde::{Deserialize, Serialize};
+use talpid_types::secret::SecretString;
 
-/// Account identifier used for authentication.
-pub type AccountToken = String;
+/// Account identifier used for authentication. It is redacted when printed, so use
+/// [`SecretString::expose_str`] where the account number itself is needed.
+pub type AccountToken = SecretString;
 
 /// Temporary authorization token derived from a Mullvad account.
 pub type AccessToken = String;
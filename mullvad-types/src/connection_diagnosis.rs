@@ -0,0 +1,127 @@
+//! Explains why a tunnel could not be established, based on probes that the daemon runs outside of
+//! any tunnel. Frontends use the result to suggest what the user can do about it.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Results of the connectivity probes. A probe that could not be run is `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProbeResults {
+    /// Whether any relay responded to a WireGuard handshake over UDP.
+    pub udp_reachable: Option<bool>,
+    /// Whether the system resolver returned an address for a hostname that does not exist.
+    pub dns_hijacked: Option<bool>,
+    /// Result of a request to the API, over TCP.
+    pub api: Option<ApiProbe>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiProbe {
+    Reachable,
+    /// The API did not respond, or the connection failed.
+    Unreachable,
+    /// The certificate of the API is expired or not yet valid according to the system clock.
+    CertificateTimeInvalid,
+}
+
+/// Likely reason that connecting failed, most actionable first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BlockedReason {
+    /// The system clock is wrong, which makes TLS connections fail.
+    ClockSkew,
+    /// DNS responses are rewritten by the network, such as by a captive portal.
+    DnsHijack,
+    /// The network lets TCP through but blocks UDP, so WireGuard can only connect using an
+    /// obfuscation method that runs over TCP.
+    UdpBlocked,
+    /// The API cannot be reached, so the network is likely down or blocks Mullvad entirely.
+    ApiUnreachable,
+}
+
+impl fmt::Display for BlockedReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockedReason::ClockSkew => f.write_str("the system clock is wrong"),
+            BlockedReason::DnsHijack => f.write_str("DNS is hijacked by the network"),
+            BlockedReason::UdpBlocked => f.write_str("UDP is blocked by the network"),
+            BlockedReason::ApiUnreachable => f.write_str("the API cannot be reached"),
+        }
+    }
+}
+
+/// Difference between the system clock and the clock of the API, as seen in the `Date` header of
+/// an API response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClockSkew {
+    /// Seconds that the system clock is ahead of the API. Negative if it is behind.
+    pub offset_secs: i64,
+}
+
+impl fmt::Display for ClockSkew {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let direction = if self.offset_secs < 0 {
+            "behind"
+        } else {
+            "ahead"
+        };
+        write!(
+            f,
+            "the system clock is {} seconds {direction}",
+            self.offset_secs.unsigned_abs()
+        )
+    }
+}
+
+/// Return the likely reasons that connecting failed, most actionable first. Empty if the probes
+/// found nothing wrong.
+pub fn analyze(results: &ProbeResults) -> Vec<BlockedReason> {
+    let mut reasons = vec![];
+    if results.api == Some(ApiProbe::CertificateTimeInvalid) {
+        reasons.push(BlockedReason::ClockSkew);
+    }
+    if results.dns_hijacked == Some(true) {
+        reasons.push(BlockedReason::DnsHijack);
+    }
+    match (results.udp_reachable, results.api) {
+        // UDP is only singled out if TCP is known to work. Otherwise, the network is more likely
+        // to be down altogether.
+        (Some(false), Some(ApiProbe::Reachable)) => reasons.push(BlockedReason::UdpBlocked),
+        (_, Some(ApiProbe::Unreachable)) => reasons.push(BlockedReason::ApiUnreachable),
+        _ => (),
+    }
+    reasons
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_analyze() {
+        assert_eq!(analyze(&ProbeResults::default()), []);
+
+        let results = ProbeResults {
+            udp_reachable: Some(false),
+            dns_hijacked: Some(false),
+            api: Some(ApiProbe::Reachable),
+        };
+        assert_eq!(analyze(&results), [BlockedReason::UdpBlocked]);
+
+        let results = ProbeResults {
+            udp_reachable: Some(false),
+            dns_hijacked: Some(true),
+            api: Some(ApiProbe::Unreachable),
+        };
+        assert_eq!(
+            analyze(&results),
+            [BlockedReason::DnsHijack, BlockedReason::ApiUnreachable]
+        );
+
+        let results = ProbeResults {
+            udp_reachable: Some(true),
+            dns_hijacked: None,
+            api: Some(ApiProbe::CertificateTimeInvalid),
+        };
+        assert_eq!(analyze(&results), [BlockedReason::ClockSkew]);
+    }
+}
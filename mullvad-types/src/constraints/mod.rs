@@ -138,7 +138,11 @@ impl_intersection_partialeq!(relay_constraints::Providers);
 // NOTE: should take actual intersection
 impl_intersection_partialeq!(relay_constraints::LocationConstraint);
 impl_intersection_partialeq!(relay_constraints::Ownership);
+impl_intersection_partialeq!(relay_constraints::PortRange);
+impl_intersection_partialeq!(relay_constraints::MultihopDiversity);
+impl_intersection_partialeq!(relay_constraints::TlsObfuscationSettings);
 // NOTE: it contains an inner constraint
 impl_intersection_partialeq!(talpid_types::net::TransportProtocol);
 impl_intersection_partialeq!(talpid_types::net::TunnelType);
 impl_intersection_partialeq!(talpid_types::net::IpVersion);
+impl_intersection_partialeq!(talpid_types::net::wireguard::PublicKey);
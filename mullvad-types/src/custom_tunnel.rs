@@ -1,19 +1,10 @@
 use crate::settings::TunnelOptions;
 use serde::{Deserialize, Serialize};
 use std::{
-    fmt, io,
-    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    fmt,
+    net::{IpAddr, SocketAddr},
 };
-use talpid_types::net::{openvpn, proxy::CustomProxy, wireguard, Endpoint, TunnelParameters};
-
-#[derive(thiserror::Error, Debug)]
-pub enum Error {
-    #[error("Invalid host/domain: {0}")]
-    InvalidHost(String, #[source] io::Error),
-
-    #[error("Host has no IPv4 address: {0}")]
-    HostHasNoIpv4(String),
-}
+use talpid_types::net::{openvpn, proxy::ProxyChain, wireguard, Endpoint, TunnelParameters};
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct CustomTunnelEndpoint {
@@ -33,21 +24,25 @@ impl CustomTunnelEndpoint {
         }
     }
 
+    /// Returns the tunnel parameters for this endpoint, using `ip` as the address of `host`.
     pub fn to_tunnel_parameters(
         &self,
+        ip: IpAddr,
         tunnel_options: TunnelOptions,
-        proxy: Option<CustomProxy>,
-    ) -> Result<TunnelParameters, Error> {
-        let ip = resolve_to_ip(&self.host)?;
+        proxy: Option<ProxyChain>,
+    ) -> TunnelParameters {
         let mut config = self.config.clone();
         config.set_ip(ip);
 
-        let parameters = match config {
+        match config {
             ConnectionConfig::OpenVpn(config) => openvpn::TunnelParameters {
                 config,
                 options: tunnel_options.openvpn,
                 generic_options: tunnel_options.generic,
                 proxy,
+                ca: None,
+                #[cfg(unix)]
+                privilege_drop: None,
                 #[cfg(target_os = "linux")]
                 fwmark: crate::TUNNEL_FWMARK,
             }
@@ -66,8 +61,7 @@ impl CustomTunnelEndpoint {
                 }
                 .into()
             }
-        };
-        Ok(parameters)
+        }
     }
 }
 
@@ -92,25 +86,6 @@ impl fmt::Display for CustomTunnelEndpoint {
     }
 }
 
-/// Does a DNS lookup if the host isn't an IP.
-/// Returns the first IPv4 address if one exists, otherwise the first IPv6 address.
-/// Rust only provides means to resolve a socket addr, not just a host, for some reason. So
-/// because of this we do the resolving with port zero and then pick out the IPs.
-fn resolve_to_ip(host: &str) -> Result<IpAddr, Error> {
-    let (mut ipv4, mut ipv6): (Vec<IpAddr>, Vec<IpAddr>) = (host, 0)
-        .to_socket_addrs()
-        .map_err(|e| Error::InvalidHost(host.to_owned(), e))?
-        .map(|addr| addr.ip())
-        .partition(|addr| addr.is_ipv4());
-
-    ipv4.pop()
-        .or_else(|| {
-            log::info!("No IPv4 for host {}", host);
-            ipv6.pop()
-        })
-        .ok_or_else(|| Error::HostHasNoIpv4(host.to_owned()))
-}
-
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 #[serde(rename = "connection_config")]
 pub enum ConnectionConfig {
@@ -1,4 +1,4 @@
-use crate::account::AccountToken;
+use crate::{account::AccountToken, wireguard::AssociatedAddresses};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use talpid_types::net::wireguard::PublicKey;
@@ -74,13 +74,24 @@ impl DeviceState {
 pub struct AccountAndDevice {
     pub account_token: AccountToken,
     pub device: Device,
+    /// When the current WireGuard key of the device was created.
+    pub key_created: DateTime<Utc>,
+    /// Addresses assigned to the device inside the tunnel.
+    pub tunnel_addresses: AssociatedAddresses,
 }
 
 impl AccountAndDevice {
-    pub fn new(account_token: AccountToken, device: Device) -> Self {
+    pub fn new(
+        account_token: AccountToken,
+        device: Device,
+        key_created: DateTime<Utc>,
+        tunnel_addresses: AssociatedAddresses,
+    ) -> Self {
         Self {
             account_token,
             device,
+            key_created,
+            tunnel_addresses,
         }
     }
 }
@@ -107,6 +118,16 @@ pub struct DeviceEvent {
     pub new_state: DeviceState,
 }
 
+/// Result of logging in and creating a device for the account in one go.
+#[derive(Clone, Debug)]
+pub enum LoginOutcome {
+    /// Logged in on a new device.
+    LoggedIn,
+    /// The account already has the maximum number of devices, so no device was created. One of
+    /// these devices must be revoked to log in.
+    TooManyDevices(Vec<Device>),
+}
+
 /// Emitted when a device is removed using the `RemoveDevice` RPC.
 /// This is not sent by a normal logout or when it is revoked remotely.
 #[derive(Clone, Debug)]
@@ -13,6 +13,8 @@ pub enum MullvadEndpoint {
 pub struct MullvadWireguardEndpoint {
     pub peer: wireguard::PeerConfig,
     pub exit_peer: Option<wireguard::PeerConfig>,
+    /// Another relay to connect to in parallel with `peer`, keeping whichever responds first.
+    pub candidate_peer: Option<wireguard::PeerConfig>,
     pub ipv4_gateway: Ipv4Addr,
     pub ipv6_gateway: Ipv6Addr,
 }
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt};
+
+/// Name of the component that reports whether the API can be reached.
+pub const API_CONNECTIVITY: &str = "api_connectivity";
+/// Name of the component that reports the age of the relay list.
+pub const RELAY_LIST: &str = "relay_list";
+/// Name of the component that reports whether the WireGuard key is valid.
+pub const WIREGUARD_KEY: &str = "wireguard_key";
+/// Name of the component that reports whether the firewall policy has been applied.
+pub const FIREWALL: &str = "firewall";
+/// Name of the component that reports whether the DNS config has been applied.
+pub const DNS: &str = "dns";
+/// Name of the component that reports whether the route manager is running.
+pub const ROUTE_MANAGER: &str = "route_manager";
+
+/// Status of the subsystems of the daemon, keyed by component name.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaemonHealth {
+    pub components: BTreeMap<String, ComponentHealth>,
+}
+
+impl DaemonHealth {
+    /// Returns the worst status of any component, or [`HealthStatus::Ok`] if there are none.
+    pub fn status(&self) -> HealthStatus {
+        self.components
+            .values()
+            .map(|component| component.status)
+            .max()
+            .unwrap_or(HealthStatus::Ok)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComponentHealth {
+    pub status: HealthStatus,
+    /// Human-readable explanation of the status.
+    pub details: String,
+}
+
+impl ComponentHealth {
+    pub fn ok(details: impl Into<String>) -> Self {
+        Self::new(HealthStatus::Ok, details)
+    }
+
+    pub fn degraded(details: impl Into<String>) -> Self {
+        Self::new(HealthStatus::Degraded, details)
+    }
+
+    pub fn failed(details: impl Into<String>) -> Self {
+        Self::new(HealthStatus::Failed, details)
+    }
+
+    fn new(status: HealthStatus, details: impl Into<String>) -> Self {
+        Self {
+            status,
+            details: details.into(),
+        }
+    }
+}
+
+/// Status of a component, ordered from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum HealthStatus {
+    Ok,
+    /// The component works, but needs attention.
+    Degraded,
+    Failed,
+}
+
+impl fmt::Display for HealthStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HealthStatus::Ok => f.write_str("ok"),
+            HealthStatus::Degraded => f.write_str("degraded"),
+            HealthStatus::Failed => f.write_str("failed"),
+        }
+    }
+}
@@ -1,15 +1,23 @@
 pub mod access_method;
 pub mod account;
 pub mod auth_failed;
+pub mod connection_diagnosis;
 pub mod constraints;
 pub mod custom_list;
 pub mod device;
 pub mod endpoint;
+pub mod health;
 pub mod location;
+pub mod notification;
+pub mod problem_report;
+pub mod profile;
 pub mod relay_constraints;
 pub mod relay_list;
+pub mod runtime_config;
 pub mod settings;
+pub mod shutdown;
 pub mod states;
+pub mod traffic;
 pub mod version;
 pub mod wireguard;
 
@@ -130,6 +130,64 @@ pub struct AmIMullvad {
     pub latitude: f64,
     pub longitude: f64,
     pub mullvad_exit_ip: bool,
+    /// Hostname of the relay that the traffic exited through, if it was a Mullvad relay.
+    #[serde(default)]
+    pub mullvad_exit_ip_hostname: Option<String>,
+}
+
+/// A DNS resolver that looked up names on behalf of the client, as reported by the DNS leak check
+/// of am.i.mullvad.net.
+#[derive(Debug, Deserialize)]
+pub struct DnsLeakResolver {
+    /// Whether the resolver is run by Mullvad.
+    pub mullvad_dns: bool,
+}
+
+/// The outcome of checking the connection through the tunnel after connecting.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectionVerification {
+    /// Exit IP as seen by the connection check service.
+    pub exit_ip: IpAddr,
+    /// Whether the exit IP belongs to a Mullvad relay.
+    pub mullvad_exit_ip: bool,
+    /// Whether traffic exits through the relay that was selected. `None` if either the selected
+    /// relay or the relay seen by the service is unknown.
+    pub exit_relay_matches: Option<bool>,
+    /// Whether DNS lookups were answered by other resolvers than the expected ones. `None` if this
+    /// could not be checked, for example because custom DNS servers are used.
+    pub dns_hijacked: Option<bool>,
+}
+
+impl ConnectionVerification {
+    /// Compares the response from the connection check service with what is expected of the
+    /// tunnel. `resolvers` should only be given if all lookups are expected to be answered by
+    /// Mullvad resolvers.
+    pub fn new(
+        response: &AmIMullvad,
+        expected_exit_hostname: Option<&str>,
+        resolvers: Option<&[DnsLeakResolver]>,
+    ) -> Self {
+        let exit_relay_matches = expected_exit_hostname
+            .zip(response.mullvad_exit_ip_hostname.as_deref())
+            .map(|(expected, actual)| expected.eq_ignore_ascii_case(actual));
+        let dns_hijacked = resolvers
+            .filter(|resolvers| !resolvers.is_empty())
+            .map(|resolvers| resolvers.iter().any(|resolver| !resolver.mullvad_dns));
+
+        Self {
+            exit_ip: response.ip,
+            mullvad_exit_ip: response.mullvad_exit_ip,
+            exit_relay_matches,
+            dns_hijacked,
+        }
+    }
+
+    /// Returns whether nothing suggests that traffic leaks outside of the tunnel or is redirected.
+    pub fn is_verified(&self) -> bool {
+        self.mullvad_exit_ip
+            && self.exit_relay_matches != Some(false)
+            && self.dns_hijacked != Some(true)
+    }
 }
 
 /// GeoIP information exposed from the daemon to frontends.
@@ -146,6 +204,13 @@ pub struct GeoIpLocation {
     pub bridge_hostname: Option<String>,
     pub entry_hostname: Option<String>,
     pub obfuscator_hostname: Option<String>,
+    /// Hosting provider of the exit relay.
+    pub provider: Option<String>,
+    /// Whether the exit relay is owned by Mullvad, as opposed to rented.
+    pub owned: Option<bool>,
+    /// Whether the location could not be looked up, so that this is the last known location.
+    #[serde(default)]
+    pub stale: bool,
 }
 
 impl From<AmIMullvad> for GeoIpLocation {
@@ -167,6 +232,9 @@ impl From<AmIMullvad> for GeoIpLocation {
             bridge_hostname: None,
             entry_hostname: None,
             obfuscator_hostname: None,
+            provider: None,
+            owned: None,
+            stale: false,
         }
     }
 }
@@ -180,7 +248,7 @@ pub struct LocationEventData {
 
 #[cfg(test)]
 mod tests {
-    use super::Coordinates;
+    use super::{AmIMullvad, ConnectionVerification, Coordinates, DnsLeakResolver};
 
     impl Coordinates {
         fn equal(&self, other: Coordinates) -> bool {
@@ -248,4 +316,49 @@ mod tests {
             longitude: 0.0,
         }));
     }
+
+    fn am_i_mullvad(hostname: Option<&str>) -> AmIMullvad {
+        AmIMullvad {
+            ip: "185.213.154.68".parse().unwrap(),
+            country: "Sweden".to_owned(),
+            city: Some("Gothenburg".to_owned()),
+            latitude: 57.70887,
+            longitude: 11.97456,
+            mullvad_exit_ip: hostname.is_some(),
+            mullvad_exit_ip_hostname: hostname.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn test_connection_verified() {
+        let response = am_i_mullvad(Some("se-got-wg-001"));
+        let resolvers = [DnsLeakResolver { mullvad_dns: true }];
+        let verification =
+            ConnectionVerification::new(&response, Some("SE-GOT-WG-001"), Some(&resolvers));
+
+        assert_eq!(verification.exit_relay_matches, Some(true));
+        assert_eq!(verification.dns_hijacked, Some(false));
+        assert!(verification.is_verified());
+    }
+
+    #[test]
+    fn test_connection_not_verified() {
+        let response = am_i_mullvad(Some("se-got-wg-002"));
+        let verification = ConnectionVerification::new(&response, Some("se-got-wg-001"), None);
+        assert_eq!(verification.exit_relay_matches, Some(false));
+        assert_eq!(verification.dns_hijacked, None);
+        assert!(!verification.is_verified());
+
+        let resolvers = [
+            DnsLeakResolver { mullvad_dns: true },
+            DnsLeakResolver { mullvad_dns: false },
+        ];
+        let verification = ConnectionVerification::new(&response, None, Some(&resolvers));
+        assert_eq!(verification.exit_relay_matches, None);
+        assert_eq!(verification.dns_hijacked, Some(true));
+        assert!(!verification.is_verified());
+
+        let verification = ConnectionVerification::new(&am_i_mullvad(None), None, None);
+        assert!(!verification.is_verified());
+    }
 }
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// How urgently a notification should be brought to the attention of the user, ordered from least
+/// to most urgent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum NotificationSeverity {
+    Info,
+    /// Something is wrong, but the device is not exposed.
+    Warning,
+    /// Traffic may be leaking, or the app cannot be used until the user acts.
+    Critical,
+}
+
+impl fmt::Display for NotificationSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotificationSeverity::Info => f.write_str("info"),
+            NotificationSeverity::Warning => f.write_str("warning"),
+            NotificationSeverity::Critical => f.write_str("critical"),
+        }
+    }
+}
+
+/// What a notification is about. Frontends may use this to word the notification themselves
+/// instead of showing the message from the daemon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NotificationKind {
+    /// The tunnel is up.
+    Connected,
+    /// The tunnel is down.
+    Disconnected,
+    /// An error occurred and all traffic is being blocked.
+    Blocking,
+    /// An error occurred and traffic could not be blocked, so it may leak.
+    Leaking,
+    /// The connection check found that traffic does not exit through the expected relay.
+    ConnectionNotVerified,
+    /// The device was removed from the account.
+    DeviceRevoked,
+    /// The running version is no longer supported.
+    UnsupportedVersion,
+    /// A newer version is available.
+    UpdateAvailable,
+    /// The relay that was selected by its hostname was removed from the relay list.
+    PinnedRelayRemoved,
+}
+
+/// An event that the user should know about, as classified by the daemon.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Notification {
+    pub kind: NotificationKind,
+    pub severity: NotificationSeverity,
+    /// Human-readable description of the event.
+    pub message: String,
+}
@@ -0,0 +1,57 @@
+//! Problem reports that are uploaded by the daemon.
+
+use std::{fmt, time::Duration};
+
+/// A collected problem report to send to support.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProblemReportRequest {
+    pub email: String,
+    pub message: String,
+    /// Report written by `mullvad-problem-report collect`.
+    pub report: String,
+}
+
+/// Sent as an event whenever an upload changes state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProblemReportProgress {
+    /// Identifies the upload. It is returned when the upload is started.
+    pub id: u32,
+    /// Size of the report that is sent.
+    pub report_bytes: u64,
+    pub state: UploadState,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UploadState {
+    Uploading,
+    /// Sending failed because of a network error. The report is sent again after `delay`.
+    /// `attempt` counts the failures in a row.
+    Retrying {
+        attempt: u32,
+        delay: Duration,
+    },
+    Completed,
+    Failed(String),
+}
+
+impl UploadState {
+    /// Returns whether no more progress will be reported.
+    pub fn is_finished(&self) -> bool {
+        matches!(self, UploadState::Completed | UploadState::Failed(_))
+    }
+}
+
+impl fmt::Display for UploadState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UploadState::Uploading => f.write_str("uploading"),
+            UploadState::Retrying { attempt, delay } => write!(
+                f,
+                "connection failed {attempt} times, retrying in {} s",
+                delay.as_secs()
+            ),
+            UploadState::Completed => f.write_str("sent"),
+            UploadState::Failed(error) => write!(f, "failed: {error}"),
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Profiles are named sets of relay, obfuscation and DNS settings that can be switched between
+//! in a single step.
+
+use crate::{
+    relay_constraints::{ObfuscationSettings, RelaySettings},
+    settings::{DnsOptions, Settings},
+};
+use serde::{Deserialize, Serialize};
+use std::ops::Deref;
+
+const PROFILE_NAME_MAX_SIZE: usize = 30;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Profile name too long")]
+    NameTooLong,
+    #[error("Profile name must not be empty")]
+    EmptyName,
+    #[error("Profile not found")]
+    ProfileNotFound,
+}
+
+/// Settings that are replaced when a profile is used.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    pub relay_settings: RelaySettings,
+    pub obfuscation_settings: ObfuscationSettings,
+    pub dns_options: DnsOptions,
+}
+
+impl Profile {
+    /// Create a profile from the relay, obfuscation and DNS settings in `settings`.
+    pub fn from_settings(name: String, settings: &Settings) -> Result<Self, Error> {
+        if name.is_empty() {
+            return Err(Error::EmptyName);
+        }
+        if name.chars().count() > PROFILE_NAME_MAX_SIZE {
+            return Err(Error::NameTooLong);
+        }
+        Ok(Profile {
+            name,
+            relay_settings: settings.relay_settings.clone(),
+            obfuscation_settings: settings.obfuscation_settings.clone(),
+            dns_options: settings.tunnel_options.dns_options.clone(),
+        })
+    }
+
+    /// Replace the relay, obfuscation and DNS settings in `settings` with the ones in this
+    /// profile.
+    pub fn apply(&self, settings: &mut Settings) {
+        settings.set_relay_settings(self.relay_settings.clone());
+        settings.obfuscation_settings = self.obfuscation_settings.clone();
+        settings.tunnel_options.dns_options = self.dns_options.clone();
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProfilesSettings {
+    profiles: Vec<Profile>,
+}
+
+impl From<Vec<Profile>> for ProfilesSettings {
+    fn from(profiles: Vec<Profile>) -> Self {
+        Self { profiles }
+    }
+}
+
+impl ProfilesSettings {
+    /// Add a profile, or replace the profile with the same name.
+    pub fn save(&mut self, profile: Profile) {
+        match self.profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => *existing = profile,
+            None => self.profiles.push(profile),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<(), Error> {
+        let len = self.profiles.len();
+        self.profiles.retain(|profile| profile.name != name);
+        if self.profiles.len() == len {
+            return Err(Error::ProfileNotFound);
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Profile, Error> {
+        self.profiles
+            .iter()
+            .find(|profile| profile.name == name)
+            .ok_or(Error::ProfileNotFound)
+    }
+}
+
+impl IntoIterator for ProfilesSettings {
+    type Item = Profile;
+    type IntoIter = <Vec<Profile> as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.profiles.into_iter()
+    }
+}
+
+impl Deref for ProfilesSettings {
+    type Target = [Profile];
+
+    fn deref(&self) -> &Self::Target {
+        &self.profiles
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        constraints::Constraint,
+        relay_constraints::{RelayConstraints, SelectedObfuscation},
+    };
+
+    #[test]
+    fn test_save_and_apply_profile() {
+        let mut settings = Settings::default();
+        settings.obfuscation_settings.selected_obfuscation = SelectedObfuscation::Udp2Tcp;
+        settings
+            .tunnel_options
+            .dns_options
+            .default_options
+            .block_ads = true;
+
+        let mut profiles = ProfilesSettings::default();
+        profiles.save(Profile::from_settings("work".to_owned(), &settings).unwrap());
+
+        let mut other_settings = Settings::default();
+        other_settings.set_relay_settings(RelaySettings::Normal(RelayConstraints {
+            location: Constraint::Any,
+            ..Default::default()
+        }));
+        profiles.get("work").unwrap().apply(&mut other_settings);
+
+        assert_eq!(other_settings.relay_settings, settings.relay_settings);
+        assert_eq!(
+            other_settings.obfuscation_settings,
+            settings.obfuscation_settings
+        );
+        assert_eq!(
+            other_settings.tunnel_options.dns_options,
+            settings.tunnel_options.dns_options
+        );
+
+        profiles.remove("work").unwrap();
+        assert!(matches!(profiles.get("work"), Err(Error::ProfileNotFound)));
+    }
+}
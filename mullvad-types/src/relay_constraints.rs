@@ -15,7 +15,10 @@ use std::{
     net::{Ipv4Addr, Ipv6Addr},
     str::FromStr,
 };
-use talpid_types::net::{proxy::CustomProxy, IpVersion, TransportProtocol, TunnelType};
+use talpid_types::net::{
+    proxy::{self, CustomProxy, ProxyChain},
+    wireguard, IpVersion, TransportProtocol, TunnelType,
+};
 
 /// Specifies a specific endpoint or [`RelayConstraints`] to use when `mullvad-daemon` selects a
 /// relay.
@@ -161,6 +164,40 @@ impl fmt::Display for RelayConstraintsFormatter<'_> {
     }
 }
 
+impl RelayConstraints {
+    /// Apply `policy` to the exit and entry locations if they pin a relay for which `is_removed`
+    /// returns true. Returns the hostnames of those relays, along with the locations that replaced
+    /// them, if any.
+    pub fn migrate_removed_relays(
+        &mut self,
+        policy: RemovedRelayPolicy,
+        is_removed: impl Fn(&str) -> bool,
+    ) -> Vec<(Hostname, Option<GeographicLocationConstraint>)> {
+        let mut removed = vec![];
+        for location in [
+            &mut self.location,
+            &mut self.wireguard_constraints.entry_location,
+        ] {
+            let Constraint::Only(LocationConstraint::Location(pinned)) = location else {
+                continue;
+            };
+            let GeographicLocationConstraint::Hostname(_, _, hostname) = &*pinned else {
+                continue;
+            };
+            if !is_removed(hostname) {
+                continue;
+            }
+            let hostname = hostname.clone();
+            let replacement = policy.replacement(pinned);
+            if let Some(replacement) = &replacement {
+                *pinned = replacement.clone();
+            }
+            removed.push((hostname, replacement));
+        }
+        removed
+    }
+}
+
 /// Limits the set of [`crate::relay_list::Relay`]s used by a `RelaySelector` based on
 /// location.
 #[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize, PartialOrd, Ord)]
@@ -375,9 +412,92 @@ impl fmt::Display for OpenVpnConstraints {
 #[serde(rename_all = "snake_case", default)]
 pub struct WireguardConstraints {
     pub port: Constraint<u16>,
+    /// Range to pick a random port from on every connection attempt. Used to make port-based
+    /// blocking less effective. It has no effect if `port` is set.
+    pub port_range: Constraint<PortRange>,
     pub ip_version: Constraint<IpVersion>,
     pub use_multihop: bool,
     pub entry_location: Constraint<LocationConstraint>,
+    /// Features that the relays must support. Relays without them are never selected, even if
+    /// that means that no relay can be selected at all.
+    pub required_features: RequiredRelayFeatures,
+    /// Highest handshake latency, in milliseconds, that a relay may have had the last time it was
+    /// pinged. Relays that have not been pinged are not affected.
+    pub max_latency: Constraint<u16>,
+    /// How the entry and exit relays should differ when multihop is used and neither of their
+    /// locations is constrained.
+    pub multihop_diversity: MultihopDiversity,
+    /// Public key of the relay to use, or of the exit relay if multihop is used. Unlike the
+    /// hostname, the key stays the same if the relay is renamed.
+    pub public_key: Constraint<wireguard::PublicKey>,
+    /// Public key of the entry relay to use when multihop is used.
+    pub entry_public_key: Constraint<wireguard::PublicKey>,
+}
+
+/// Features that a WireGuard relay can be required to support.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case", default)]
+pub struct RequiredRelayFeatures {
+    /// Only use relays that support DAITA, even if DAITA is disabled.
+    pub daita: bool,
+    /// Only use relays that accept WireGuard over QUIC.
+    pub quic: bool,
+    /// Only use relays that are booted with stboot.
+    pub stboot: bool,
+}
+
+impl RequiredRelayFeatures {
+    /// Return the names of the required features.
+    pub fn names(&self) -> Vec<&'static str> {
+        [
+            (self.daita, "DAITA"),
+            (self.quic, "QUIC"),
+            (self.stboot, "stboot"),
+        ]
+        .into_iter()
+        .filter_map(|(required, name)| required.then_some(name))
+        .collect()
+    }
+}
+
+/// Ways in which the entry and exit relays of a multihop circuit should differ. Relays that do
+/// are preferred, but if no such pair of relays exists, any pair may be selected.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case", default)]
+pub struct MultihopDiversity {
+    /// Prefer entry and exit relays in different countries.
+    pub country: bool,
+    /// Prefer entry and exit relays run by different providers.
+    pub provider: bool,
+}
+
+impl MultihopDiversity {
+    /// Don't prefer any relays over others.
+    pub const NONE: MultihopDiversity = MultihopDiversity {
+        country: false,
+        provider: false,
+    };
+
+    pub fn is_enabled(&self) -> bool {
+        *self != Self::NONE
+    }
+
+    /// Return the names of the properties that should differ.
+    pub fn names(&self) -> Vec<&'static str> {
+        [(self.country, "country"), (self.provider, "provider")]
+            .into_iter()
+            .filter_map(|(enabled, name)| enabled.then_some(name))
+            .collect()
+    }
+}
+
+impl Default for MultihopDiversity {
+    fn default() -> Self {
+        MultihopDiversity {
+            country: true,
+            provider: true,
+        }
+    }
 }
 
 impl WireguardConstraints {
@@ -392,6 +512,86 @@ impl WireguardConstraints {
     }
 }
 
+/// An inclusive range of ports.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
+pub struct PortRange {
+    start: u16,
+    end: u16,
+}
+
+impl PortRange {
+    /// Create a range of the ports from `start` to `end`, inclusive. Returns `None` if `end` is
+    /// less than `start`.
+    pub fn new(start: u16, end: u16) -> Option<Self> {
+        (start <= end).then_some(PortRange { start, end })
+    }
+
+    pub fn start(&self) -> u16 {
+        self.start
+    }
+
+    pub fn end(&self) -> u16 {
+        self.end
+    }
+
+    pub fn contains(&self, port: u16) -> bool {
+        self.start <= port && port <= self.end
+    }
+}
+
+impl<'de> Deserialize<'de> for PortRange {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct RawPortRange {
+            start: u16,
+            end: u16,
+        }
+
+        let range = RawPortRange::deserialize(deserializer)?;
+        PortRange::new(range.start, range.end).ok_or_else(|| {
+            serde::de::Error::invalid_value(
+                serde::de::Unexpected::Other("port range"),
+                &"a range whose start is not greater than its end",
+            )
+        })
+    }
+}
+
+impl fmt::Display for PortRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+impl FromStr for PortRange {
+    type Err = PortRangeParseError;
+
+    /// Parse a range such as `1000-2000`.
+    fn from_str(s: &str) -> Result<PortRange, Self::Err> {
+        let (start, end) = s.split_once('-').ok_or(PortRangeParseError)?;
+        let start = start.trim().parse().map_err(|_| PortRangeParseError)?;
+        let end = end.trim().parse().map_err(|_| PortRangeParseError)?;
+        PortRange::new(start, end).ok_or(PortRangeParseError)
+    }
+}
+
+#[cfg(feature = "clap")]
+impl clap::builder::ValueParserFactory for PortRange {
+    type Parser = fn(&str) -> Result<PortRange, PortRangeParseError>;
+
+    fn value_parser() -> Self::Parser {
+        PortRange::from_str
+    }
+}
+
+/// Returned when `PortRange::from_str` fails to convert a string into a [`PortRange`].
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("Not a valid port range. Expected START-END, e.g. 1000-2000")]
+pub struct PortRangeParseError;
+
 pub struct WireguardConstraintsFormatter<'a> {
     pub constraints: &'a WireguardConstraints,
     pub custom_lists: &'a CustomListsSettings,
@@ -399,9 +599,10 @@ pub struct WireguardConstraintsFormatter<'a> {
 
 impl fmt::Display for WireguardConstraintsFormatter<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self.constraints.port {
-            Constraint::Any => write!(f, "any port")?,
-            Constraint::Only(port) => write!(f, "port {}", port)?,
+        match (self.constraints.port, self.constraints.port_range) {
+            (Constraint::Only(port), _) => write!(f, "port {}", port)?,
+            (Constraint::Any, Constraint::Only(range)) => write!(f, "random port in {range}")?,
+            (Constraint::Any, Constraint::Any) => write!(f, "any port")?,
         }
         if let Constraint::Only(ip_version) = self.constraints.ip_version {
             write!(f, ", {},", ip_version)?;
@@ -414,6 +615,19 @@ impl fmt::Display for WireguardConstraintsFormatter<'_> {
                 }
             });
             write!(f, ", multihop entry {}", location)?;
+            if let Constraint::Only(key) = &self.constraints.entry_public_key {
+                write!(f, ", entry key {key}")?;
+            }
+        }
+        if let Constraint::Only(key) = &self.constraints.public_key {
+            write!(f, ", relay key {key}")?;
+        }
+        let required_features = self.constraints.required_features.names();
+        if !required_features.is_empty() {
+            write!(f, ", requires {}", required_features.join(", "))?;
+        }
+        if let Constraint::Only(max_latency) = self.constraints.max_latency {
+            write!(f, ", max latency {max_latency} ms")?;
         }
         Ok(())
     }
@@ -451,6 +665,10 @@ pub struct BridgeSettings {
     pub bridge_type: BridgeType,
     pub normal: BridgeConstraints,
     pub custom: Option<CustomProxy>,
+    /// Proxies that are passed through after `custom`, in order, on the way to the relay. This is
+    /// only supported by OpenVPN.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub custom_chain: Vec<CustomProxy>,
 }
 
 pub enum ResolvedBridgeSettings<'a> {
@@ -466,6 +684,16 @@ impl BridgeSettings {
             (BridgeType::Custom, None) => Err(MissingCustomBridgeSettings(())),
         }
     }
+
+    /// Returns the custom bridge followed by `custom_chain`, or `None` if there is no custom
+    /// bridge.
+    pub fn custom_proxy_chain(&self) -> Option<Result<ProxyChain, proxy::Error>> {
+        let custom = self.custom.clone()?;
+        let hops = std::iter::once(custom)
+            .chain(self.custom_chain.iter().cloned())
+            .collect();
+        Some(ProxyChain::new(hops))
+    }
 }
 
 #[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Deserialize, Serialize)]
@@ -477,6 +705,7 @@ pub enum SelectedObfuscation {
     Off,
     #[cfg_attr(feature = "clap", clap(name = "udp2tcp"))]
     Udp2Tcp,
+    Tls,
 }
 
 impl Intersection for SelectedObfuscation {
@@ -500,6 +729,7 @@ impl fmt::Display for SelectedObfuscation {
             SelectedObfuscation::Auto => "auto".fmt(f),
             SelectedObfuscation::Off => "off".fmt(f),
             SelectedObfuscation::Udp2Tcp => "udp2tcp".fmt(f),
+            SelectedObfuscation::Tls => "tls".fmt(f),
         }
     }
 }
@@ -519,6 +749,33 @@ impl fmt::Display for Udp2TcpObfuscationSettings {
     }
 }
 
+/// Server name sent in the TLS handshake when no other name has been set.
+pub const DEFAULT_TLS_SNI: &str = "www.google.com";
+
+/// Settings for wrapping the tunnel in a TLS session to the TLS endpoint of the relay.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(default)]
+pub struct TlsObfuscationSettings {
+    /// Server name sent in the TLS handshake. This should be a popular site, so that the
+    /// connection looks like ordinary HTTPS traffic.
+    pub sni: String,
+}
+
+impl Default for TlsObfuscationSettings {
+    fn default() -> Self {
+        Self {
+            sni: DEFAULT_TLS_SNI.to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for TlsObfuscationSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "server name {}", self.sni)
+    }
+}
+
 /// Contains obfuscation settings
 #[derive(Default, Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
@@ -526,6 +783,7 @@ impl fmt::Display for Udp2TcpObfuscationSettings {
 pub struct ObfuscationSettings {
     pub selected_obfuscation: SelectedObfuscation,
     pub udp2tcp: Udp2TcpObfuscationSettings,
+    pub tls: TlsObfuscationSettings,
 }
 
 /// Limits the set of bridge servers to use in `mullvad-daemon`.
@@ -594,6 +852,74 @@ impl fmt::Display for BridgeState {
     }
 }
 
+/// What to do when a relay that is selected by its hostname is removed from the relay list.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum RemovedRelayPolicy {
+    /// Keep the location, so that the tunnel blocks traffic instead of connecting until another
+    /// location is selected.
+    #[default]
+    StayDisconnected,
+    /// Select any relay in the same city instead.
+    SameCity,
+    /// Select any relay in the same country instead.
+    SameCountry,
+}
+
+impl RemovedRelayPolicy {
+    /// Return the location to use instead of `location`, or `None` if it should be kept.
+    pub fn replacement(
+        self,
+        location: &GeographicLocationConstraint,
+    ) -> Option<GeographicLocationConstraint> {
+        let GeographicLocationConstraint::Hostname(country, city, _) = location else {
+            return None;
+        };
+        match self {
+            RemovedRelayPolicy::StayDisconnected => None,
+            RemovedRelayPolicy::SameCity => Some(GeographicLocationConstraint::city(country, city)),
+            RemovedRelayPolicy::SameCountry => Some(GeographicLocationConstraint::country(country)),
+        }
+    }
+}
+
+impl fmt::Display for RemovedRelayPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RemovedRelayPolicy::StayDisconnected => "stay disconnected",
+            RemovedRelayPolicy::SameCity => "use the same city",
+            RemovedRelayPolicy::SameCountry => "use the same country",
+        })
+    }
+}
+
+/// Which IP version to connect to relays over, when the WireGuard IP version is not constrained.
+/// Unlike that constraint, the other IP version is still used if the preferred one fails.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum IpVersionPreference {
+    /// Connect over IPv4, and switch to IPv6 if the relay stops responding over IPv4.
+    #[default]
+    Automatic,
+    /// Connect over IPv4 first.
+    Ipv4,
+    /// Connect over IPv6 first, if it is available. Useful when the IPv4 path goes through a
+    /// carrier-grade NAT that performs much worse than native IPv6.
+    Ipv6,
+}
+
+impl fmt::Display for IpVersionPreference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            IpVersionPreference::Automatic => "automatic",
+            IpVersionPreference::Ipv4 => "prefer IPv4",
+            IpVersionPreference::Ipv6 => "prefer IPv6",
+        })
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
 pub struct InternalBridgeConstraints {
     pub location: Constraint<LocationConstraint>,
@@ -644,3 +970,60 @@ impl RelayOverride {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_migrate_removed_relays() {
+        let pinned = GeographicLocationConstraint::Hostname(
+            "se".to_owned(),
+            "sto".to_owned(),
+            "se-sto-wg-001".to_owned(),
+        );
+        let mut constraints = RelayConstraints {
+            location: Constraint::Only(LocationConstraint::from(pinned.clone())),
+            ..Default::default()
+        };
+
+        let removed = constraints
+            .migrate_removed_relays(RemovedRelayPolicy::StayDisconnected, |hostname| {
+                hostname == "se-sto-wg-001"
+            });
+        assert_eq!(removed, vec![("se-sto-wg-001".to_owned(), None)]);
+        assert_eq!(
+            constraints.location,
+            Constraint::Only(LocationConstraint::from(pinned.clone()))
+        );
+
+        let removed = constraints.migrate_removed_relays(RemovedRelayPolicy::SameCity, |_| false);
+        assert!(removed.is_empty());
+
+        let city = GeographicLocationConstraint::city("se", "sto");
+        let removed = constraints
+            .migrate_removed_relays(RemovedRelayPolicy::SameCity, |hostname| {
+                hostname == "se-sto-wg-001"
+            });
+        assert_eq!(
+            removed,
+            vec![("se-sto-wg-001".to_owned(), Some(city.clone()))]
+        );
+        assert_eq!(
+            constraints.location,
+            Constraint::Only(LocationConstraint::from(city))
+        );
+    }
+
+    #[test]
+    fn test_deserialize_port_range() {
+        let range: PortRange = serde_json::from_str(r#"{"start": 1000, "end": 2000}"#).unwrap();
+        assert_eq!(range, PortRange::new(1000, 2000).unwrap());
+
+        let single: PortRange = serde_json::from_str(r#"{"start": 53, "end": 53}"#).unwrap();
+        assert_eq!(single, PortRange::new(53, 53).unwrap());
+
+        serde_json::from_str::<PortRange>(r#"{"start": 2000, "end": 1000}"#)
+            .expect_err("start must not be greater than end");
+    }
+}
@@ -1,6 +1,10 @@
 use crate::location::{CityCode, CountryCode, Location};
 use serde::{Deserialize, Serialize};
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
 use talpid_types::net::{
     proxy::{CustomProxy, Shadowsocks},
     wireguard, TransportProtocol,
@@ -46,6 +50,110 @@ impl RelayList {
     }
 }
 
+/// The relays that differ between two versions of the relay list.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RelayListDiff {
+    pub added: Vec<RelayDiffEntry>,
+    pub removed: Vec<RelayDiffEntry>,
+    /// Relays that are in both lists, but with different addresses, keys, location or status.
+    /// Changes to the weight alone are ignored, since it only affects how likely the relay is to
+    /// be selected.
+    pub changed: Vec<RelayDiffEntry>,
+}
+
+/// A relay in a [`RelayListDiff`]. For removed relays, the location is the one in the old list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayDiffEntry {
+    pub hostname: String,
+    pub country_code: CountryCode,
+    pub city_code: CityCode,
+}
+
+impl RelayListDiff {
+    /// Compare `old` to `new`. Relays are identified by their hostnames.
+    pub fn new(old: &RelayList, new: &RelayList) -> Self {
+        let old_relays: HashMap<_, _> = relays_with_location(old)
+            .map(|entry| (entry.2.hostname.as_str(), entry))
+            .collect();
+        let new_relays: HashMap<_, _> = relays_with_location(new)
+            .map(|entry| (entry.2.hostname.as_str(), entry))
+            .collect();
+
+        let mut diff = Self::default();
+        for (hostname, new_entry) in &new_relays {
+            match old_relays.get(hostname) {
+                None => diff.added.push(RelayDiffEntry::new(new_entry)),
+                Some(old_entry) if relay_changed(old_entry, new_entry) => {
+                    diff.changed.push(RelayDiffEntry::new(new_entry))
+                }
+                Some(_) => (),
+            }
+        }
+        for (hostname, old_entry) in &old_relays {
+            if !new_relays.contains_key(hostname) {
+                diff.removed.push(RelayDiffEntry::new(old_entry));
+            }
+        }
+
+        diff.added.sort_by(|a, b| a.hostname.cmp(&b.hostname));
+        diff.removed.sort_by(|a, b| a.hostname.cmp(&b.hostname));
+        diff.changed.sort_by(|a, b| a.hostname.cmp(&b.hostname));
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Return whether the relay with `hostname` was removed or changed.
+    pub fn invalidates(&self, hostname: &str) -> bool {
+        self.removed
+            .iter()
+            .chain(&self.changed)
+            .any(|entry| entry.hostname == hostname)
+    }
+}
+
+impl RelayDiffEntry {
+    fn new((country_code, city_code, relay): &(&CountryCode, &CityCode, &Relay)) -> Self {
+        Self {
+            hostname: relay.hostname.clone(),
+            country_code: (*country_code).clone(),
+            city_code: (*city_code).clone(),
+        }
+    }
+}
+
+/// Return every relay in `relay_list` along with the codes of the country and city it is listed
+/// under.
+fn relays_with_location(
+    relay_list: &RelayList,
+) -> impl Iterator<Item = (&CountryCode, &CityCode, &Relay)> {
+    relay_list.countries.iter().flat_map(|country| {
+        country.cities.iter().flat_map(move |city| {
+            city.relays
+                .iter()
+                .map(move |relay| (&country.code, &city.code, relay))
+        })
+    })
+}
+
+fn relay_changed(
+    (old_country, old_city, old): &(&CountryCode, &CityCode, &Relay),
+    (new_country, new_city, new): &(&CountryCode, &CityCode, &Relay),
+) -> bool {
+    old_country != new_country
+        || old_city != new_city
+        || old.ipv4_addr_in != new.ipv4_addr_in
+        || old.ipv6_addr_in != new.ipv6_addr_in
+        || old.include_in_country != new.include_in_country
+        || old.active != new.active
+        || old.owned != new.owned
+        || old.provider != new.provider
+        || old.stboot != new.stboot
+        || old.endpoint_data != new.endpoint_data
+}
+
 /// A list of [`RelayListCity`]s within a country. Used by [`RelayList`].
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct RelayListCountry {
@@ -82,6 +190,10 @@ pub struct Relay {
     pub owned: bool,
     pub provider: String,
     pub weight: u64,
+    /// Whether the relay is booted with stboot, which verifies the signature of the operating
+    /// system and loads it into RAM without any persistent storage.
+    #[serde(default)]
+    pub stboot: bool,
     pub endpoint_data: RelayEndpointData,
     pub location: Option<Location>,
 }
@@ -104,12 +216,15 @@ impl PartialEq for Relay {
     ///     # owned: true,
     ///     # provider: "provider0".to_string(),
     ///     # weight: 1,
+    ///     # stboot: false,
     ///     # endpoint_data: RelayEndpointData::Wireguard(WireguardRelayEndpointData {
     ///     #   public_key: PublicKey::from_base64(
     ///     #       "BLNHNoGO88LjV/wDBa7CUUwUzPq/fO2UwcGLy56hKy4=",
     ///     #   )
     ///     #   .unwrap(),
     ///     #   daita: false,
+    ///     #   quic: false,
+    ///     #   tls: None,
     ///     # }),
     ///     # location: None,
     /// };
@@ -191,6 +306,29 @@ pub struct WireguardRelayEndpointData {
     /// Whether the server supports DAITA
     #[serde(default)]
     pub daita: bool,
+    /// Whether the server accepts WireGuard over QUIC
+    #[serde(default)]
+    pub quic: bool,
+    /// Where the server accepts WireGuard wrapped in TLS, if it does
+    #[serde(default)]
+    pub tls: Option<TlsEndpointData>,
+}
+
+/// Data needed to wrap a tunnel to a WireGuard relay in TLS.
+#[derive(Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Debug)]
+pub struct TlsEndpointData {
+    /// TCP port that the relay accepts TLS connections on
+    pub port: u16,
+    /// SHA-256 digest of the DER encoded certificate of the relay, which it is authenticated with
+    pub certificate_sha256: [u8; 32],
+}
+
+/// Latency to a WireGuard relay, measured by timing a handshake with it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayLatency {
+    pub hostname: String,
+    /// Round-trip time of the handshake, or `None` if the relay did not respond.
+    pub latency: Option<Duration>,
 }
 
 #[derive(Debug, Default, Clone, Deserialize, Serialize)]
@@ -211,8 +349,85 @@ impl ShadowsocksEndpointData {
     pub fn to_proxy_settings(&self, addr: IpAddr) -> CustomProxy {
         CustomProxy::Shadowsocks(Shadowsocks {
             endpoint: SocketAddr::new(addr, self.port),
-            password: self.password.clone(),
+            password: self.password.clone().into(),
             cipher: self.cipher.clone(),
+            hostname: None,
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn relay(hostname: &str) -> Relay {
+        Relay {
+            hostname: hostname.to_owned(),
+            ipv4_addr_in: "10.0.0.1".parse().unwrap(),
+            ipv6_addr_in: None,
+            include_in_country: true,
+            active: true,
+            owned: true,
+            provider: "provider0".to_owned(),
+            weight: 1,
+            stboot: false,
+            endpoint_data: RelayEndpointData::Openvpn,
+            location: None,
+        }
+    }
+
+    fn relay_list(cities: Vec<(&str, Vec<Relay>)>) -> RelayList {
+        RelayList {
+            countries: vec![RelayListCountry {
+                name: "Sweden".to_owned(),
+                code: "se".to_owned(),
+                cities: cities
+                    .into_iter()
+                    .map(|(code, relays)| RelayListCity {
+                        name: code.to_owned(),
+                        code: code.to_owned(),
+                        latitude: 0.0,
+                        longitude: 0.0,
+                        relays,
+                    })
+                    .collect(),
+            }],
+            ..RelayList::empty()
+        }
+    }
+
+    fn entry(hostname: &str, city_code: &str) -> RelayDiffEntry {
+        RelayDiffEntry {
+            hostname: hostname.to_owned(),
+            country_code: "se".to_owned(),
+            city_code: city_code.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_relay_list_diff() {
+        let old = relay_list(vec![
+            ("sto", vec![relay("se-sto-1"), relay("se-sto-2")]),
+            ("got", vec![relay("se-got-1"), relay("se-got-2")]),
+        ]);
+
+        let mut inactive = relay("se-sto-2");
+        inactive.active = false;
+        let mut reweighted = relay("se-got-1");
+        reweighted.weight = 100;
+        let new = relay_list(vec![
+            ("sto", vec![relay("se-sto-1"), inactive, relay("se-sto-3")]),
+            ("got", vec![reweighted]),
+        ]);
+
+        let diff = RelayListDiff::new(&old, &new);
+        assert_eq!(diff.added, vec![entry("se-sto-3", "sto")]);
+        assert_eq!(diff.removed, vec![entry("se-got-2", "got")]);
+        assert_eq!(diff.changed, vec![entry("se-sto-2", "sto")]);
+        assert!(diff.invalidates("se-sto-2"));
+        assert!(diff.invalidates("se-got-2"));
+        assert!(!diff.invalidates("se-got-1"));
+
+        assert!(RelayListDiff::new(&new, &new).is_empty());
+    }
+}
@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Environment variable that changes the behavior of the daemon, and that was set when the daemon
+/// started.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvOverride {
+    pub name: String,
+    pub value: String,
+    /// Whether the variable has no effect, because it is only read by development builds.
+    pub ignored: bool,
+}
+
+/// Configuration of the daemon that is not part of the settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuntimeConfig {
+    /// Active environment overrides, ordered by name.
+    pub overrides: Vec<EnvOverride>,
+}
+
+impl fmt::Display for EnvOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if self.ignored {
+            write!(f, " (ignored in production builds)")?;
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::net::IpAddr;
+use std::{fmt, net::IpAddr, path::PathBuf, str::FromStr};
 
 #[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
 #[serde(rename_all = "snake_case")]
@@ -16,6 +16,9 @@ pub struct DnsOptions {
     pub state: DnsState,
     pub default_options: DefaultDnsOptions,
     pub custom_options: CustomDnsOptions,
+    /// Block all traffic instead of only logging a warning if DNS is managed by software that
+    /// the daemon cannot configure. Only has an effect on Linux.
+    pub block_on_unsupported_manager: bool,
 }
 
 /// Default DNS config
@@ -28,6 +31,67 @@ pub struct DefaultDnsOptions {
     pub block_adult_content: bool,
     pub block_gambling: bool,
     pub block_social_media: bool,
+    /// Blocklists supplied by the user. Domains on enabled lists are blocked by a local DNS
+    /// filter, in addition to the categories above.
+    pub custom_blocklists: Vec<CustomBlocklist>,
+}
+
+impl DefaultDnsOptions {
+    /// Returns the sources of all enabled custom blocklists.
+    pub fn enabled_blocklists(&self) -> impl Iterator<Item = &BlocklistSource> {
+        self.custom_blocklists
+            .iter()
+            .filter(|list| list.enabled)
+            .map(|list| &list.source)
+    }
+}
+
+/// A user-supplied list of domains to block, in hosts file or plain domain list format.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub struct CustomBlocklist {
+    pub source: BlocklistSource,
+    pub enabled: bool,
+}
+
+/// Where a [`CustomBlocklist`] is read from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum BlocklistSource {
+    /// Downloaded by the daemon over HTTPS, and refreshed periodically.
+    Url(String),
+    /// Read by the daemon from a file on this machine.
+    File(PathBuf),
+}
+
+/// Returned when a string is not a valid [`BlocklistSource`].
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("Expected an 'https://' URL, or an absolute path")]
+pub struct BlocklistSourceParseError;
+
+impl FromStr for BlocklistSource {
+    type Err = BlocklistSourceParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("https://") {
+            return Ok(BlocklistSource::Url(s.to_owned()));
+        }
+        // The daemon does not share the working directory of the caller
+        let path = PathBuf::from(s);
+        if path.is_absolute() {
+            Ok(BlocklistSource::File(path))
+        } else {
+            Err(BlocklistSourceParseError)
+        }
+    }
+}
+
+impl fmt::Display for BlocklistSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlocklistSource::Url(url) => f.write_str(url),
+            BlocklistSource::File(path) => write!(f, "{}", path.display()),
+        }
+    }
 }
 
 /// Custom DNS config
@@ -35,3 +99,30 @@ pub struct DefaultDnsOptions {
 pub struct CustomDnsOptions {
     pub addresses: Vec<IpAddr>,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_blocklist_source() {
+        assert_eq!(
+            "https://example.com/hosts".parse(),
+            Ok(BlocklistSource::Url("https://example.com/hosts".to_owned()))
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            "/etc/mullvad-blocklist.txt".parse(),
+            Ok(BlocklistSource::File(PathBuf::from(
+                "/etc/mullvad-blocklist.txt"
+            )))
+        );
+        assert!("blocklist.txt".parse::<BlocklistSource>().is_err());
+        assert!("http://example.com/hosts"
+            .parse::<BlocklistSource>()
+            .is_err());
+        assert!("ftp://example.com/hosts"
+            .parse::<BlocklistSource>()
+            .is_err());
+    }
+}
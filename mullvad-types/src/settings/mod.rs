@@ -2,16 +2,18 @@ use crate::{
     access_method,
     constraints::Constraint,
     custom_list::CustomListsSettings,
+    profile::ProfilesSettings,
     relay_constraints::{
-        BridgeSettings, BridgeState, GeographicLocationConstraint, LocationConstraint,
-        ObfuscationSettings, RelayConstraints, RelayOverride, RelaySettings,
-        RelaySettingsFormatter, SelectedObfuscation, WireguardConstraints,
+        BridgeSettings, BridgeState, GeographicLocationConstraint, IpVersionPreference,
+        LocationConstraint, ObfuscationSettings, RelayConstraints, RelayOverride, RelaySettings,
+        RelaySettingsFormatter, RemovedRelayPolicy, SelectedObfuscation, WireguardConstraints,
     },
     wireguard,
 };
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 #[cfg(any(windows, target_os = "android", target_os = "macos"))]
 use std::collections::HashSet;
+use std::{fmt, net::SocketAddr, time::Duration};
 use talpid_types::net::{openvpn, GenericTunnelOptions};
 
 mod dns;
@@ -79,6 +81,8 @@ pub struct Settings {
     pub custom_lists: CustomListsSettings,
     /// API access methods
     pub api_access_methods: access_method::Settings,
+    /// Named sets of relay, obfuscation and DNS settings that can be switched between
+    pub profiles: ProfilesSettings,
     /// If the daemon should allow communication with private (LAN) networks.
     pub allow_lan: bool,
     /// Extra level of kill switch. When this setting is on, the disconnected state will block
@@ -91,15 +95,135 @@ pub struct Settings {
     pub tunnel_options: TunnelOptions,
     /// Overrides for relays
     pub relay_overrides: Vec<RelayOverride>,
+    /// What to do when a relay that is selected by its hostname is removed from the relay list.
+    pub removed_relay_policy: RemovedRelayPolicy,
+    /// Which IP version to connect to relays over, unless it is constrained.
+    pub ip_version_preference: IpVersionPreference,
     /// Whether to notify users of beta updates.
     pub show_beta_releases: bool,
+    /// Where to post tunnel state changes, if anywhere.
+    pub webhook: Option<WebhookSettings>,
+    /// Whether to announce the tunnel state on the local network. Only takes effect while local
+    /// network sharing is allowed.
+    pub lan_beacon: bool,
+    /// Service used to look up the exit location and to check the connection.
+    pub connection_check: ConnectionCheckSettings,
+    /// Whether to fall back to OpenVPN on networks where WireGuard fails to connect.
+    pub tunnel_protocol_fallback: TunnelProtocolFallback,
+    /// API deployment to use instead of the Mullvad API, such as a self-hosted test API.
+    pub custom_api_endpoint: Option<CustomApiEndpoint>,
     /// Split tunneling settings
     #[cfg(any(windows, target_os = "android", target_os = "macos"))]
     pub split_tunnel: SplitTunnelSettings,
+    /// Passphrase that is required to disconnect, to disable lockdown mode and to change the
+    /// settings, if any.
+    pub settings_lock: Option<SettingsLock>,
     /// Specifies settings schema version
     pub settings_version: SettingsVersion,
 }
 
+/// A passphrase that must be given to disconnect, to disable lockdown mode or to change the
+/// settings. Only a hash of the passphrase is stored.
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SettingsLock {
+    /// Argon2 hash of the passphrase, in the PHC string format.
+    pub passphrase_hash: String,
+}
+
+impl fmt::Debug for SettingsLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SettingsLock").finish_non_exhaustive()
+    }
+}
+
+/// Whether the settings lock is enabled, and for how long it is unlocked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SettingsLockState {
+    pub enabled: bool,
+    /// Time left until the settings are locked again, if they have been unlocked.
+    pub unlocked_for: Option<Duration>,
+}
+
+impl SettingsLockState {
+    /// Returns whether a passphrase is required to change the settings.
+    pub fn is_locked(&self) -> bool {
+        self.enabled && self.unlocked_for.is_none()
+    }
+}
+
+/// An HTTP endpoint on the local machine that tunnel state changes are posted to as JSON.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct WebhookSettings {
+    /// URL to post events to. Only `http` URLs that point to the loopback interface are used.
+    pub url: String,
+    /// Sent along with every event, so that the receiver can tell that the event came from the
+    /// daemon.
+    pub secret: String,
+}
+
+/// Host of the service that is used by default to look up the exit location and to check the
+/// connection.
+pub const DEFAULT_CONNECTION_CHECK_HOST: &str = "am.i.mullvad.net";
+
+/// Settings for the service that the exit location is looked up with. It is also used to check
+/// that traffic leaves through the selected relay after connecting.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct ConnectionCheckSettings {
+    /// Host of the service. It must serve the same API as [`DEFAULT_CONNECTION_CHECK_HOST`],
+    /// including the `ipv4.` and `ipv6.` subdomains.
+    pub host: String,
+    /// Whether to check the connection every time the tunnel comes up.
+    pub verify_after_connect: bool,
+}
+
+/// Falling back to OpenVPN when the tunnel protocol is automatic. WireGuard is tried first, and
+/// OpenVPN is used after `wireguard_attempts` failed attempts in a row. The networks where this
+/// happens are remembered, so that OpenVPN is used right away the next time.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct TunnelProtocolFallback {
+    pub enabled: bool,
+    /// Number of failed WireGuard attempts after which OpenVPN is used.
+    pub wireguard_attempts: u32,
+}
+
+impl Default for TunnelProtocolFallback {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            wireguard_attempts: 3,
+        }
+    }
+}
+
+/// An API deployment that serves the same API as the Mullvad API.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct CustomApiEndpoint {
+    /// Hostname of the API. It is used for SNI and certificate verification.
+    pub host: String,
+    /// Address of the API. The hostname is never resolved.
+    pub address: SocketAddr,
+    /// PEM encoded root certificates that the API certificate must be signed by. The certificates
+    /// that are used for the Mullvad API are trusted if this is `None`.
+    pub root_certificates: Option<String>,
+    /// Talk to the API over plain HTTP. Only allowed in builds with the `api-override` feature.
+    #[serde(default)]
+    pub disable_tls: bool,
+    /// Never use bridges or proxies to reach the API.
+    #[serde(default)]
+    pub force_direct: bool,
+}
+
+impl Default for ConnectionCheckSettings {
+    fn default() -> Self {
+        Self {
+            host: DEFAULT_CONNECTION_CHECK_HOST.to_owned(),
+            verify_after_connect: true,
+        }
+    }
+}
+
 #[cfg(any(windows, target_os = "android", target_os = "macos"))]
 #[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
 pub struct SplitTunnelSettings {
@@ -197,14 +321,23 @@ impl Default for Settings {
             bridge_state: BridgeState::Auto,
             custom_lists: CustomListsSettings::default(),
             api_access_methods: access_method::Settings::default(),
+            profiles: ProfilesSettings::default(),
             allow_lan: false,
             block_when_disconnected: false,
             auto_connect: false,
             tunnel_options: TunnelOptions::default(),
             relay_overrides: vec![],
+            removed_relay_policy: RemovedRelayPolicy::default(),
+            ip_version_preference: IpVersionPreference::default(),
             show_beta_releases: false,
+            webhook: None,
+            lan_beacon: false,
+            connection_check: ConnectionCheckSettings::default(),
+            tunnel_protocol_fallback: TunnelProtocolFallback::default(),
+            custom_api_endpoint: None,
             #[cfg(any(windows, target_os = "android", target_os = "macos"))]
             split_tunnel: SplitTunnelSettings::default(),
+            settings_lock: None,
             settings_version: CURRENT_SETTINGS_VERSION,
         }
     }
@@ -254,6 +387,21 @@ impl Settings {
             }
         }
     }
+
+    /// Returns the number of failed WireGuard attempts after which OpenVPN is used, or `None` if
+    /// the daemon should not fall back to OpenVPN. Falling back only applies when the tunnel
+    /// protocol is automatic.
+    pub fn wireguard_attempts_before_fallback(&self) -> Option<u32> {
+        match &self.relay_settings {
+            RelaySettings::Normal(constraints)
+                if self.tunnel_protocol_fallback.enabled
+                    && constraints.tunnel_protocol == Constraint::Any =>
+            {
+                Some(self.tunnel_protocol_fallback.wireguard_attempts)
+            }
+            _ => None,
+        }
+    }
 }
 
 /// TunnelOptions holds configuration data that applies to all kinds of tunnels.
@@ -268,9 +416,18 @@ pub struct TunnelOptions {
     pub generic: GenericTunnelOptions,
     /// DNS options.
     pub dns_options: DnsOptions,
+    /// Seconds to wait for a connection attempt to complete before moving on to the next
+    /// transport. If unset, each attempt runs until the tunnel itself gives up.
+    pub connect_deadline: Option<u16>,
 }
 
-pub use dns::{CustomDnsOptions, DefaultDnsOptions, DnsOptions, DnsState};
+/// Shortest connect deadline that can be set, in seconds. A shorter deadline would not leave
+/// enough time for a handshake on a slow network.
+pub const MIN_CONNECT_DEADLINE: u16 = 5;
+
+pub use dns::{
+    BlocklistSource, CustomBlocklist, CustomDnsOptions, DefaultDnsOptions, DnsOptions, DnsState,
+};
 
 impl Default for TunnelOptions {
     fn default() -> Self {
@@ -282,6 +439,7 @@ impl Default for TunnelOptions {
                 enable_ipv6: cfg!(target_os = "android"),
             },
             dns_options: DnsOptions::default(),
+            connect_deadline: None,
         }
     }
 }
@@ -0,0 +1,73 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+/// What the daemon does with the tunnel and the firewall when it shuts down.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ShutdownBehavior {
+    /// Block all traffic if the machine is shutting down and the daemon was connected or set to
+    /// auto-connect. Otherwise, restore the network.
+    #[default]
+    Auto,
+    /// Leave the firewall blocking all traffic until the daemon starts again.
+    Block,
+    /// Disconnect and remove all firewall rules.
+    Restore,
+    /// Keep the tunnel up for the given duration before disconnecting. Traffic is blocked after
+    /// that, as with [`ShutdownBehavior::Block`].
+    KeepTunnel(Duration),
+}
+
+/// Returned when a string is not a valid [`ShutdownBehavior`].
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("Expected 'auto', 'block', 'restore' or 'keep-tunnel=<seconds>'")]
+pub struct ShutdownBehaviorParseError;
+
+impl FromStr for ShutdownBehavior {
+    type Err = ShutdownBehaviorParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ShutdownBehavior::Auto),
+            "block" => Ok(ShutdownBehavior::Block),
+            "restore" => Ok(ShutdownBehavior::Restore),
+            _ => {
+                let secs = s
+                    .strip_prefix("keep-tunnel=")
+                    .ok_or(ShutdownBehaviorParseError)?;
+                let secs = secs.parse().map_err(|_| ShutdownBehaviorParseError)?;
+                Ok(ShutdownBehavior::KeepTunnel(Duration::from_secs(secs)))
+            }
+        }
+    }
+}
+
+impl fmt::Display for ShutdownBehavior {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShutdownBehavior::Auto => f.write_str("auto"),
+            ShutdownBehavior::Block => f.write_str("block"),
+            ShutdownBehavior::Restore => f.write_str("restore"),
+            ShutdownBehavior::KeepTunnel(duration) => {
+                write!(f, "keep-tunnel={}", duration.as_secs())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_shutdown_behavior() {
+        for behavior in [
+            ShutdownBehavior::Auto,
+            ShutdownBehavior::Block,
+            ShutdownBehavior::Restore,
+            ShutdownBehavior::KeepTunnel(Duration::from_secs(30)),
+        ] {
+            assert_eq!(behavior.to_string().parse(), Ok(behavior));
+        }
+        assert!("keep-tunnel".parse::<ShutdownBehavior>().is_err());
+        assert!("keep-tunnel=soon".parse::<ShutdownBehavior>().is_err());
+    }
+}
@@ -1,9 +1,10 @@
 use crate::location::GeoIpLocation;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use talpid_types::{
-    net::TunnelEndpoint,
-    tunnel::{ActionAfterDisconnect, ErrorState},
+    net::{Endpoint, TunnelEndpoint, TunnelType},
+    tunnel::{ActionAfterDisconnect, ConnectTrace, ErrorState, TeardownReport},
 };
 
 /// Represents the state the client strives towards.
@@ -42,6 +43,9 @@ pub enum TunnelState {
     Connected {
         endpoint: TunnelEndpoint,
         location: Option<GeoIpLocation>,
+        /// How long it took to connect.
+        #[serde(default)]
+        connect_trace: ConnectTrace,
     },
     Disconnecting(ActionAfterDisconnect),
     Error(ErrorState),
@@ -63,3 +67,91 @@ impl TunnelState {
         matches!(self, TunnelState::Disconnected { .. })
     }
 }
+
+/// A connection attempt that was abandoned because it did not complete within the connect
+/// deadline. The next attempt uses the next transport in the retry order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectEscalation {
+    /// Endpoint of the abandoned attempt.
+    pub endpoint: TunnelEndpoint,
+    /// Number of attempts that have been abandoned since the tunnel started connecting,
+    /// including this one.
+    pub escalation: u32,
+}
+
+/// Progress of the grace period before lockdown mode is enabled, when it was enabled while
+/// disconnected with a grace period.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LockdownCountdown {
+    /// Traffic is blocked in this many seconds.
+    Remaining(u32),
+    /// The grace period has ended and lockdown mode is enabled.
+    Enabled,
+    /// The grace period was cancelled, since lockdown mode was set before it ended.
+    Cancelled,
+}
+
+/// The relay that the tunnel was last connected to. It is persisted so that the daemon can resume
+/// the same connection after it restarts, rather than selecting a new relay.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LastConnectedRelay {
+    pub tunnel_type: TunnelType,
+    pub hostname: String,
+    /// Entry relay, if multihop was used.
+    pub entry_hostname: Option<String>,
+    /// Endpoint that the tunnel connected to. Only its port, transport protocol and IP version
+    /// are resumed, since the relay may have changed its address.
+    pub endpoint: Endpoint,
+    /// Whether obfuscation was used. The port is not resumed in that case.
+    pub obfuscated: bool,
+}
+
+impl LastConnectedRelay {
+    /// Returns the relay of a connected tunnel, or `None` if it is not a relay from the relay
+    /// list, such as a custom tunnel endpoint.
+    pub fn new(endpoint: &TunnelEndpoint, location: Option<&GeoIpLocation>) -> Option<Self> {
+        let location = location.filter(|location| location.mullvad_exit_ip)?;
+        Some(Self {
+            tunnel_type: endpoint.tunnel_type,
+            hostname: location.hostname.clone()?,
+            entry_hostname: location.entry_hostname.clone(),
+            endpoint: endpoint.entry_endpoint.unwrap_or(endpoint.endpoint),
+            obfuscated: endpoint.obfuscation.is_some(),
+        })
+    }
+}
+
+impl fmt::Display for LastConnectedRelay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.tunnel_type, self.hostname)?;
+        if let Some(entry_hostname) = &self.entry_hostname {
+            write!(f, " via {entry_hostname}")?;
+        }
+        write!(
+            f,
+            " ({} port {}",
+            self.endpoint.protocol,
+            self.endpoint.address.port()
+        )?;
+        if self.obfuscated {
+            write!(f, ", obfuscated")?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// What the daemon resumes when it restarts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersistedTarget {
+    pub target_state: TargetState,
+    /// Relay to reconnect to if the target state is [`TargetState::Secured`].
+    pub relay: Option<LastConnectedRelay>,
+}
+
+/// How restoring the network went when the daemon last shut down.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShutdownReport {
+    /// When the daemon shut down.
+    pub time: DateTime<Utc>,
+    pub teardown: TeardownReport,
+}
@@ -0,0 +1,250 @@
+use chrono::{DateTime, Datelike, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Number of bytes transferred over a tunnel interface during a calendar month (UTC).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MonthlyTraffic {
+    pub year: i32,
+    /// Month of the year, starting from 1.
+    pub month: u32,
+    /// Name of the tunnel interface that the traffic was sent or received on.
+    pub interface: String,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+/// Number of bytes carried by an obfuscation method during a calendar month (UTC). The payload is
+/// the WireGuard traffic that was obfuscated, and the wire bytes are what was actually sent to and
+/// received from the relay, not counting TCP and IP headers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MonthlyObfuscationTraffic {
+    pub year: i32,
+    /// Month of the year, starting from 1.
+    pub month: u32,
+    /// Name of the obfuscation method, such as `udp2tcp`.
+    pub method: String,
+    pub payload_rx_bytes: u64,
+    pub payload_tx_bytes: u64,
+    pub wire_rx_bytes: u64,
+    pub wire_tx_bytes: u64,
+}
+
+impl MonthlyObfuscationTraffic {
+    /// Total number of bytes, in both directions, that the obfuscation added to the payload.
+    pub fn overhead_bytes(&self) -> u64 {
+        let payload = self.payload_rx_bytes.saturating_add(self.payload_tx_bytes);
+        let wire = self.wire_rx_bytes.saturating_add(self.wire_tx_bytes);
+        wire.saturating_sub(payload)
+    }
+}
+
+/// Monthly traffic totals for every tunnel interface and obfuscation method that has been used,
+/// ordered chronologically.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "StoredTrafficStats")]
+pub struct TrafficStats {
+    months: Vec<MonthlyTraffic>,
+    obfuscation: Vec<MonthlyObfuscationTraffic>,
+}
+
+/// Formats that the traffic stats have been stored in.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredTrafficStats {
+    Current {
+        months: Vec<MonthlyTraffic>,
+        obfuscation: Vec<MonthlyObfuscationTraffic>,
+    },
+    /// Format used before obfuscation traffic was recorded.
+    InterfacesOnly(Vec<MonthlyTraffic>),
+}
+
+impl From<StoredTrafficStats> for TrafficStats {
+    fn from(stored: StoredTrafficStats) -> Self {
+        match stored {
+            StoredTrafficStats::Current {
+                months,
+                obfuscation,
+            } => Self::new(months, obfuscation),
+            StoredTrafficStats::InterfacesOnly(months) => Self::new(months, vec![]),
+        }
+    }
+}
+
+impl TrafficStats {
+    pub fn new(
+        mut months: Vec<MonthlyTraffic>,
+        mut obfuscation: Vec<MonthlyObfuscationTraffic>,
+    ) -> Self {
+        months.sort_by_key(|entry| (entry.year, entry.month));
+        obfuscation.sort_by_key(|entry| (entry.year, entry.month));
+        Self {
+            months,
+            obfuscation,
+        }
+    }
+
+    /// Add `rx_bytes` and `tx_bytes` to the total for `interface` in the month that contains
+    /// `time`.
+    pub fn record(&mut self, time: DateTime<Utc>, interface: &str, rx_bytes: u64, tx_bytes: u64) {
+        let (year, month) = (time.year(), time.month());
+        let existing = self.months.iter_mut().find(|entry| {
+            entry.year == year && entry.month == month && entry.interface == interface
+        });
+        match existing {
+            Some(entry) => {
+                entry.rx_bytes = entry.rx_bytes.saturating_add(rx_bytes);
+                entry.tx_bytes = entry.tx_bytes.saturating_add(tx_bytes);
+            }
+            None => {
+                self.months.push(MonthlyTraffic {
+                    year,
+                    month,
+                    interface: interface.to_owned(),
+                    rx_bytes,
+                    tx_bytes,
+                });
+                self.months.sort_by_key(|entry| (entry.year, entry.month));
+            }
+        }
+    }
+
+    /// Add the payload and wire bytes to the total for the obfuscation `method` in the month that
+    /// contains `time`.
+    pub fn record_obfuscation(
+        &mut self,
+        time: DateTime<Utc>,
+        method: &str,
+        (payload_rx_bytes, payload_tx_bytes): (u64, u64),
+        (wire_rx_bytes, wire_tx_bytes): (u64, u64),
+    ) {
+        let (year, month) = (time.year(), time.month());
+        let existing = self
+            .obfuscation
+            .iter_mut()
+            .find(|entry| entry.year == year && entry.month == month && entry.method == method);
+        match existing {
+            Some(entry) => {
+                entry.payload_rx_bytes = entry.payload_rx_bytes.saturating_add(payload_rx_bytes);
+                entry.payload_tx_bytes = entry.payload_tx_bytes.saturating_add(payload_tx_bytes);
+                entry.wire_rx_bytes = entry.wire_rx_bytes.saturating_add(wire_rx_bytes);
+                entry.wire_tx_bytes = entry.wire_tx_bytes.saturating_add(wire_tx_bytes);
+            }
+            None => {
+                self.obfuscation.push(MonthlyObfuscationTraffic {
+                    year,
+                    month,
+                    method: method.to_owned(),
+                    payload_rx_bytes,
+                    payload_tx_bytes,
+                    wire_rx_bytes,
+                    wire_tx_bytes,
+                });
+                self.obfuscation
+                    .sort_by_key(|entry| (entry.year, entry.month));
+            }
+        }
+    }
+
+    pub fn months(&self) -> &[MonthlyTraffic] {
+        &self.months
+    }
+
+    pub fn into_months(self) -> Vec<MonthlyTraffic> {
+        self.months
+    }
+
+    pub fn obfuscation(&self) -> &[MonthlyObfuscationTraffic] {
+        &self.obfuscation
+    }
+
+    pub fn into_parts(self) -> (Vec<MonthlyTraffic>, Vec<MonthlyObfuscationTraffic>) {
+        (self.months, self.obfuscation)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_record_accumulates_within_month() {
+        let mut stats = TrafficStats::default();
+        let time = Utc.with_ymd_and_hms(2024, 5, 3, 12, 0, 0).unwrap();
+        stats.record(time, "wg0-mullvad", 10, 20);
+        stats.record(time, "wg0-mullvad", 5, 5);
+
+        assert_eq!(
+            stats.months(),
+            &[MonthlyTraffic {
+                year: 2024,
+                month: 5,
+                interface: "wg0-mullvad".to_owned(),
+                rx_bytes: 15,
+                tx_bytes: 25,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_record_splits_months_and_interfaces() {
+        let mut stats = TrafficStats::default();
+        let june = Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+        let may = Utc.with_ymd_and_hms(2024, 5, 31, 23, 59, 59).unwrap();
+        stats.record(june, "wg0-mullvad", 1, 1);
+        stats.record(may, "wg0-mullvad", 2, 2);
+        stats.record(may, "tun0", 3, 3);
+
+        let months: Vec<_> = stats
+            .months()
+            .iter()
+            .map(|entry| (entry.month, entry.interface.as_str(), entry.rx_bytes))
+            .collect();
+        assert_eq!(
+            months,
+            vec![(5, "wg0-mullvad", 2), (5, "tun0", 3), (6, "wg0-mullvad", 1)]
+        );
+    }
+
+    #[test]
+    fn test_record_obfuscation() {
+        let mut stats = TrafficStats::default();
+        let time = Utc.with_ymd_and_hms(2024, 5, 3, 12, 0, 0).unwrap();
+        stats.record_obfuscation(time, "udp2tcp", (100, 50), (102, 52));
+        stats.record_obfuscation(time, "udp2tcp", (100, 50), (102, 52));
+
+        assert_eq!(
+            stats.obfuscation(),
+            &[MonthlyObfuscationTraffic {
+                year: 2024,
+                month: 5,
+                method: "udp2tcp".to_owned(),
+                payload_rx_bytes: 200,
+                payload_tx_bytes: 100,
+                wire_rx_bytes: 204,
+                wire_tx_bytes: 104,
+            }]
+        );
+        assert_eq!(stats.obfuscation()[0].overhead_bytes(), 8);
+    }
+
+    /// Stats stored before obfuscation traffic was recorded are a list of monthly totals.
+    #[test]
+    fn test_deserialize_interfaces_only() {
+        let stored =
+            r#"[{"year":2024,"month":5,"interface":"wg0-mullvad","rx_bytes":1,"tx_bytes":2}]"#;
+        let stats: TrafficStats = serde_json::from_str(stored).unwrap();
+        assert_eq!(stats.months().len(), 1);
+        assert!(stats.obfuscation().is_empty());
+
+        let mut stats = stats;
+        let time = Utc.with_ymd_and_hms(2024, 5, 3, 12, 0, 0).unwrap();
+        stats.record_obfuscation(time, "tls", (1, 1), (2, 2));
+        let serialized = serde_json::to_string(&stats).unwrap();
+        assert_eq!(
+            serde_json::from_str::<TrafficStats>(&serialized).unwrap(),
+            stats
+        );
+    }
+}
@@ -25,12 +25,33 @@ pub struct AppVersionInfo {
     /// Equal to `latest_stable` when the newest release is a stable release. But will contain
     /// beta versions when those are out for testing.
     pub latest_beta: AppVersion,
-    /// Whether should update to newer version
-    pub suggested_upgrade: Option<AppVersion>,
+    /// The upgrade that the user should install, if any.
+    pub suggested_upgrade: Option<AppUpgradeAvailable>,
 }
 
 pub type AppVersion = String;
 
+/// The kind of releases that the user wants to be offered upgrades to.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateChannel {
+    /// Only stable releases.
+    #[default]
+    Stable,
+    /// Stable releases and betas.
+    Beta,
+}
+
+/// A newer version of the app that the user can upgrade to.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AppUpgradeAvailable {
+    pub version: AppVersion,
+    /// The channel that `version` was released on.
+    pub channel: UpdateChannel,
+    /// Where to read about the changes in `version`, if known.
+    pub changelog_url: Option<String>,
+}
+
 /// Parses a version string into a type that can be used for comparisons.
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub enum ParsedAppVersion {
@@ -69,6 +90,17 @@ impl ParsedAppVersion {
     pub fn is_dev(&self) -> bool {
         matches!(self, ParsedAppVersion::Dev(..))
     }
+
+    /// The channel that this version is released on.
+    pub fn channel(&self) -> UpdateChannel {
+        match self {
+            ParsedAppVersion::Stable(..) => UpdateChannel::Stable,
+            ParsedAppVersion::Beta(..) | ParsedAppVersion::Dev(_, _, Some(_), _) => {
+                UpdateChannel::Beta
+            }
+            ParsedAppVersion::Dev(_, _, None, _) => UpdateChannel::Stable,
+        }
+    }
 }
 
 impl Ord for ParsedAppVersion {
@@ -1,5 +1,7 @@
 #![allow(clippy::identity_op)]
 use chrono::{offset::Utc, DateTime};
+#[cfg(target_os = "linux")]
+use ipnetwork::IpNetwork;
 use serde::{Deserialize, Deserializer, Serialize};
 use std::{fmt, str::FromStr, time::Duration};
 use talpid_types::net::wireguard;
@@ -199,6 +201,14 @@ pub struct TunnelOptions {
     pub daita: DaitaSettings,
     /// Interval used for automatic key rotation
     pub rotation_interval: Option<RotationInterval>,
+    /// Only route these networks through the tunnel, instead of all traffic. Traffic to other
+    /// destinations is allowed to leave the device outside the tunnel.
+    #[cfg(target_os = "linux")]
+    pub allowed_ips: Option<Vec<IpNetwork>>,
+    /// Name of the interface to reach the relays through. `None` uses the interface of the default
+    /// route.
+    #[cfg(target_os = "linux")]
+    pub egress_interface: Option<String>,
 }
 
 #[allow(clippy::derivable_impls)]
@@ -210,6 +220,10 @@ impl Default for TunnelOptions {
             #[cfg(target_os = "windows")]
             daita: DaitaSettings::default(),
             rotation_interval: None,
+            #[cfg(target_os = "linux")]
+            allowed_ips: None,
+            #[cfg(target_os = "linux")]
+            egress_interface: None,
         }
     }
 }
@@ -225,6 +239,8 @@ impl TunnelOptions {
             },
             #[cfg(target_os = "windows")]
             daita: self.daita.enabled,
+            #[cfg(target_os = "linux")]
+            egress_interface: self.egress_interface,
         }
     }
 }
@@ -236,6 +252,17 @@ pub struct PublicKey {
     pub created: DateTime<Utc>,
 }
 
+/// Progress of a key rotation that was requested by the user.
+#[derive(Clone, Debug)]
+pub enum KeyRotationProgress {
+    /// A new key has been generated and is being uploaded to the API.
+    Uploading,
+    /// The API has accepted the new key, which replaced the old one.
+    Replaced(PublicKey),
+    /// The tunnel is being reconnected to start using the new key.
+    Reconnecting,
+}
+
 /// Contains a pair of local link addresses that are paired with a specific wireguard
 /// public/private keypair.
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
@@ -0,0 +1,235 @@
+//! A DNS server on the loopback interface that refuses to resolve domains on a [`Blocklist`], and
+//! forwards all other queries to the DNS servers that would otherwise have been used.
+
+use hickory_server::{
+    authority::MessageResponseBuilder,
+    proto::op::{Header, MessageType, OpCode, ResponseCode},
+    resolver::{
+        config::{NameServerConfigGroup, ResolverConfig, ResolverOpts},
+        error::ResolveErrorKind,
+        TokioAsyncResolver,
+    },
+    server::{Request, RequestHandler, ResponseHandler, ResponseInfo},
+    ServerFuture,
+};
+use parking_lot::RwLock;
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use talpid_types::net::dns::Blocklist;
+
+/// Address that the filter listens on. This is what the system is configured to use as its DNS
+/// server while the filter is active.
+pub const FILTER_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 53);
+
+/// How long to wait for TCP clients to send a request.
+const TCP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// DNS filter errors
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    /// Failed to bind UDP socket
+    #[error("Failed to bind UDP socket")]
+    UdpBindError(#[source] io::Error),
+
+    /// Failed to bind TCP listener
+    #[error("Failed to bind TCP listener")]
+    TcpBindError(#[source] io::Error),
+}
+
+/// A running DNS filter. The server stops when this is dropped.
+pub struct DnsFilter {
+    state: Arc<RwLock<FilterState>>,
+    server_task: tokio::task::JoinHandle<()>,
+}
+
+struct FilterState {
+    blocklist: Arc<Blocklist>,
+    resolver: TokioAsyncResolver,
+}
+
+impl DnsFilter {
+    /// Start a DNS filter listening on [`FILTER_ADDR`].
+    pub async fn start(blocklist: Arc<Blocklist>, upstream: &[IpAddr]) -> Result<Self, Error> {
+        let (filter, _addr) = Self::bind(FILTER_ADDR, blocklist, upstream).await?;
+        Ok(filter)
+    }
+
+    async fn bind(
+        addr: SocketAddr,
+        blocklist: Arc<Blocklist>,
+        upstream: &[IpAddr],
+    ) -> Result<(Self, SocketAddr), Error> {
+        let state = Arc::new(RwLock::new(FilterState {
+            blocklist,
+            resolver: upstream_resolver(upstream),
+        }));
+
+        let udp_socket = tokio::net::UdpSocket::bind(addr)
+            .await
+            .map_err(Error::UdpBindError)?;
+        // Use the same port for TCP if an ephemeral one was requested
+        let addr = udp_socket.local_addr().map_err(Error::UdpBindError)?;
+        let tcp_listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(Error::TcpBindError)?;
+
+        let mut server = ServerFuture::new(FilterHandler {
+            state: state.clone(),
+        });
+        server.register_socket(udp_socket);
+        server.register_listener(tcp_listener, TCP_TIMEOUT);
+
+        let server_task = tokio::spawn(async move {
+            if let Err(error) = server.block_until_done().await {
+                log::error!("DNS filter unexpectedly stopped: {error}");
+            }
+        });
+
+        Ok((Self { state, server_task }, addr))
+    }
+
+    /// Replace the list of blocked domains.
+    pub fn set_blocklist(&self, blocklist: Arc<Blocklist>) {
+        self.state.write().blocklist = blocklist;
+    }
+
+    /// Forward queries that are not blocked to `upstream`.
+    pub fn set_upstream(&self, upstream: &[IpAddr]) {
+        self.state.write().resolver = upstream_resolver(upstream);
+    }
+}
+
+impl Drop for DnsFilter {
+    fn drop(&mut self) {
+        self.server_task.abort();
+    }
+}
+
+fn upstream_resolver(upstream: &[IpAddr]) -> TokioAsyncResolver {
+    let config = ResolverConfig::from_parts(
+        None,
+        vec![],
+        NameServerConfigGroup::from_ips_clear(upstream, 53, true),
+    );
+    TokioAsyncResolver::tokio(config, ResolverOpts::default())
+}
+
+/// An implementation of [hickory_server::server::RequestHandler] that answers queries for blocked
+/// domains with `NXDOMAIN`, and forwards the rest.
+struct FilterHandler {
+    state: Arc<RwLock<FilterState>>,
+}
+
+impl FilterHandler {
+    async fn respond<R: ResponseHandler>(&self, request: &Request, mut response_handle: R) {
+        let query = request.query();
+        let (blocklist, resolver) = {
+            let state = self.state.read();
+            (state.blocklist.clone(), state.resolver.clone())
+        };
+
+        let builder = MessageResponseBuilder::from_message_request(request);
+        let mut header = Header::response_from_request(request.header());
+        header.set_recursion_available(true);
+
+        let result = if blocklist.contains(&query.name().to_string()) {
+            log::trace!("Blocking DNS query for {}", query.name());
+            header.set_response_code(ResponseCode::NXDomain);
+            response_handle
+                .send_response(builder.build_no_records(header))
+                .await
+        } else {
+            match resolver.lookup(query.name(), query.query_type()).await {
+                Ok(lookup) => {
+                    let response = builder.build(
+                        header,
+                        lookup.record_iter(),
+                        std::iter::empty(),
+                        std::iter::empty(),
+                        std::iter::empty(),
+                    );
+                    response_handle.send_response(response).await
+                }
+                Err(error) => {
+                    let response_code = match error.kind() {
+                        ResolveErrorKind::NoRecordsFound { response_code, .. } => *response_code,
+                        _ => ResponseCode::ServFail,
+                    };
+                    header.set_response_code(response_code);
+                    response_handle
+                        .send_response(builder.build_no_records(header))
+                        .await
+                }
+            }
+        };
+
+        if let Err(error) = result {
+            log::error!("Failed to send DNS response: {error}");
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl RequestHandler for FilterHandler {
+    async fn handle_request<R: ResponseHandler>(
+        &self,
+        request: &Request,
+        response_handle: R,
+    ) -> ResponseInfo {
+        if !request.src().ip().is_loopback() {
+            log::error!("Dropping a stray request from outside: {}", request.src());
+            return Header::new().into();
+        }
+        if request.message_type() == MessageType::Query && request.op_code() == OpCode::Query {
+            self.respond(request, response_handle).await;
+        } else {
+            log::trace!("Dropping non-query request: {:?}", request);
+        }
+        Header::new().into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hickory_server::proto::rr::RecordType;
+
+    #[test]
+    fn test_blocked_lookup() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async {
+            let blocklist = Arc::new(Blocklist::parse("0.0.0.0 ads.example.com"));
+            // Nothing should be forwarded, so the upstream server does not need to exist
+            let (_filter, addr) = DnsFilter::bind(
+                SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0),
+                blocklist,
+                &[],
+            )
+            .await
+            .unwrap();
+
+            let config = ResolverConfig::from_parts(
+                None,
+                vec![],
+                NameServerConfigGroup::from_ips_clear(&[addr.ip()], addr.port(), true),
+            );
+            let test_resolver = TokioAsyncResolver::tokio(config, ResolverOpts::default());
+
+            let error = test_resolver
+                .lookup("tracker.ads.example.com.", RecordType::A)
+                .await
+                .expect_err("Blocked domain should not resolve");
+            assert!(matches!(
+                error.kind(),
+                ResolveErrorKind::NoRecordsFound {
+                    response_code: ResponseCode::NXDomain,
+                    ..
+                }
+            ));
+        });
+    }
+}
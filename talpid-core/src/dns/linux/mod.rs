@@ -2,6 +2,7 @@ mod network_manager;
 mod resolvconf;
 mod static_resolv_conf;
 mod systemd_resolved;
+mod unsupported_manager;
 
 use self::{
     network_manager::NetworkManager, resolvconf::Resolvconf, static_resolv_conf::StaticResolvConf,
@@ -9,6 +10,7 @@ use self::{
 };
 use std::{env, fmt, net::IpAddr};
 use talpid_routing::RouteManagerHandle;
+use talpid_tunnel::netns::{self, NetworkNamespace};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -34,12 +36,25 @@ pub enum Error {
     /// No suitable DNS monitor implementation detected
     #[error("No suitable DNS monitor implementation detected")]
     NoDnsMonitor,
+
+    /// DNS is managed by software that cannot be configured
+    #[error("DNS is managed by {0}, which is not supported")]
+    UnsupportedDnsManager(&'static str),
 }
 
 pub struct DnsMonitor {
     route_manager: RouteManagerHandle,
     handle: tokio::runtime::Handle,
     inner: Option<DnsMonitorHolder>,
+    block_on_unsupported_manager: bool,
+}
+
+impl DnsMonitor {
+    /// Set whether to fail instead of writing to /etc/resolv.conf directly when it belongs to a
+    /// DNS manager that cannot be configured. This takes effect the next time DNS is set.
+    pub fn set_block_on_unsupported_manager(&mut self, block: bool) {
+        self.block_on_unsupported_manager = block;
+    }
 }
 
 impl super::DnsMonitorT for DnsMonitor {
@@ -50,13 +65,23 @@ impl super::DnsMonitorT for DnsMonitor {
             route_manager,
             handle,
             inner: None,
+            block_on_unsupported_manager: false,
         })
     }
 
     fn set(&mut self, interface: &str, servers: &[IpAddr]) -> Result<()> {
         self.reset()?;
+        // The tunnel device does not exist in this namespace, and the resolver configuration of
+        // the namespace that it is in belongs to the user
+        if let Ok(Some(namespace)) = NetworkNamespace::from_env() {
+            log::debug!(
+                "Not setting DNS since the tunnel is in network namespace {}",
+                namespace.path().display()
+            );
+            return Ok(());
+        }
         // Creating a new DNS monitor for each set, in case the system changed how it manages DNS.
-        let mut inner = DnsMonitorHolder::new()?;
+        let mut inner = DnsMonitorHolder::new(self.block_on_unsupported_manager)?;
         if !servers.is_empty() {
             inner.set(&self.handle, &self.route_manager, interface, servers)?;
             self.inner = Some(inner);
@@ -93,7 +118,7 @@ impl fmt::Display for DnsMonitorHolder {
 }
 
 impl DnsMonitorHolder {
-    fn new() -> Result<Self> {
+    fn new(block_on_unsupported_manager: bool) -> Result<Self> {
         let dns_module = env::var_os("TALPID_DNS_MODULE");
 
         let manager = match dns_module.as_ref().and_then(|value| value.to_str()) {
@@ -101,14 +126,25 @@ impl DnsMonitorHolder {
             Some("resolvconf") => DnsMonitorHolder::Resolvconf(Resolvconf::new()?),
             Some("systemd") => DnsMonitorHolder::SystemdResolved(SystemdResolved::new()?),
             Some("network-manager") => DnsMonitorHolder::NetworkManager(NetworkManager::new()?),
-            Some(_) | None => Self::with_detected_dns_manager()?,
+            Some(_) | None => Self::with_detected_dns_manager(block_on_unsupported_manager)?,
         };
         log::debug!("Managing DNS via {}", manager);
         Ok(manager)
     }
 
-    fn with_detected_dns_manager() -> Result<Self> {
-        SystemdResolved::new()
+    fn with_detected_dns_manager(block_on_unsupported_manager: bool) -> Result<Self> {
+        // systemd-resolved and NetworkManager manage the host namespace only, even when their
+        // D-Bus services can be reached from another namespace
+        if !netns::is_host_namespace() {
+            log::debug!(
+                "Not in the host network namespace. Ignoring systemd-resolved and NetworkManager"
+            );
+            return Resolvconf::new()
+                .map(DnsMonitorHolder::Resolvconf)
+                .or_else(|_| Self::with_resolv_conf(block_on_unsupported_manager));
+        }
+
+        let supported_manager = SystemdResolved::new()
             .map(DnsMonitorHolder::SystemdResolved)
             .or_else(|err| {
                 match err {
@@ -121,8 +157,26 @@ impl DnsMonitorHolder {
                 }
                 NetworkManager::new().map(DnsMonitorHolder::NetworkManager)
             })
-            .or_else(|_| Resolvconf::new().map(DnsMonitorHolder::Resolvconf))
-            .or_else(|_| StaticResolvConf::new().map(DnsMonitorHolder::StaticResolvConf))
+            .or_else(|_| Resolvconf::new().map(DnsMonitorHolder::Resolvconf));
+        if let Ok(manager) = supported_manager {
+            return Ok(manager);
+        }
+        Self::with_resolv_conf(block_on_unsupported_manager)
+    }
+
+    /// Fall back on writing to /etc/resolv.conf, unless it belongs to something else.
+    fn with_resolv_conf(block_on_unsupported_manager: bool) -> Result<Self> {
+        if let Some(manager) = unsupported_manager::detect() {
+            if block_on_unsupported_manager {
+                return Err(Error::UnsupportedDnsManager(manager));
+            }
+            log::warn!(
+                "/etc/resolv.conf appears to be managed by {manager}, which is not supported. \
+                 DNS requests may leak"
+            );
+        }
+        StaticResolvConf::new()
+            .map(DnsMonitorHolder::StaticResolvConf)
             .map_err(|_| Error::NoDnsMonitor)
     }
 
@@ -164,6 +218,7 @@ impl DnsMonitorHolder {
 
 /// Returns true if DnsMonitor will use NetworkManager to manage DNS.
 pub fn will_use_nm() -> bool {
-    crate::dns::imp::SystemdResolved::new().is_err()
+    netns::is_host_namespace()
+        && crate::dns::imp::SystemdResolved::new().is_err()
         && crate::dns::imp::NetworkManager::new().is_ok()
 }
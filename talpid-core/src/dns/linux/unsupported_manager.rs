@@ -0,0 +1,66 @@
+//! Detects software that manages /etc/resolv.conf but that cannot be configured. While such
+//! software is running, writing to /etc/resolv.conf directly is unreliable, since the file may be
+//! overwritten at any time, or the servers in it may not even be used.
+
+use std::{borrow::Cow, fs, path::Path};
+
+const RESOLV_CONF_PATH: &str = "/etc/resolv.conf";
+
+/// Strings that identify an unsupported DNS manager, and the name of that manager. They are
+/// looked for in the path that /etc/resolv.conf links to, and in the comments at the top of it.
+const MARKERS: &[(&str, &str)] = &[
+    ("dnsmasq", "dnsmasq"),
+    ("connman", "ConnMan"),
+    ("Connection Manager", "ConnMan"),
+    ("netconfig", "netconfig"),
+    ("dhcpcd", "dhcpcd"),
+];
+
+/// Returns the name of the unsupported DNS manager that owns /etc/resolv.conf, if any.
+pub fn detect() -> Option<&'static str> {
+    let link_target = fs::read_link(RESOLV_CONF_PATH).ok();
+    let contents = fs::read_to_string(RESOLV_CONF_PATH).unwrap_or_default();
+    find_manager(link_target.as_deref(), &contents)
+}
+
+fn find_manager(link_target: Option<&Path>, contents: &str) -> Option<&'static str> {
+    let link_target = link_target
+        .map(Path::to_string_lossy)
+        .unwrap_or(Cow::Borrowed(""));
+    let header: Vec<&str> = contents
+        .lines()
+        .map(str::trim)
+        .take_while(|line| line.is_empty() || line.starts_with('#'))
+        .collect();
+
+    MARKERS
+        .iter()
+        .find(|(marker, _)| {
+            link_target.contains(marker) || header.iter().any(|line| line.contains(marker))
+        })
+        .map(|(_, name)| *name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::find_manager;
+    use std::path::Path;
+
+    #[test]
+    fn test_find_manager() {
+        assert_eq!(
+            find_manager(Some(Path::new("/run/dnsmasq/resolv.conf")), ""),
+            Some("dnsmasq")
+        );
+        assert_eq!(
+            find_manager(
+                None,
+                "# Generated by Connection Manager\nnameserver 127.0.0.1\n"
+            ),
+            Some("ConnMan")
+        );
+        // Only the header identifies the manager
+        assert_eq!(find_manager(None, "nameserver 10.0.0.1\n# dhcpcd\n"), None);
+        assert_eq!(find_manager(None, "nameserver 10.0.0.1\n"), None);
+    }
+}
@@ -1,6 +1,12 @@
 use std::net::IpAddr;
+#[cfg(all(
+    feature = "test-harness",
+    any(target_os = "linux", target_os = "macos")
+))]
+use std::sync::{Arc, Mutex};
 #[cfg(target_os = "linux")]
 use talpid_routing::RouteManagerHandle;
+use talpid_types::tunnel::{DnsMechanism, ErrorDetails, ErrorStateCause};
 
 #[cfg(target_os = "macos")]
 #[path = "macos.rs"]
@@ -23,9 +29,67 @@ mod imp;
 
 pub use self::imp::Error;
 
+impl From<&Error> for ErrorStateCause {
+    fn from(error: &Error) -> Self {
+        #[cfg(target_os = "linux")]
+        if let Error::UnsupportedDnsManager(manager) = error {
+            return ErrorStateCause::UnsupportedDnsManager(manager.to_string());
+        }
+        ErrorStateCause::SetDnsError {
+            mechanism: mechanism(error),
+            details: ErrorDetails::from_error(error),
+        }
+    }
+}
+
+/// Returns how DNS was being set when `error` occurred, if it is known.
+#[cfg(target_os = "linux")]
+fn mechanism(error: &Error) -> Option<DnsMechanism> {
+    match error {
+        Error::SystemdResolved(_) => Some(DnsMechanism::SystemdResolved),
+        Error::NetworkManager(_) => Some(DnsMechanism::NetworkManager),
+        Error::Resolvconf(_) => Some(DnsMechanism::Resolvconf),
+        Error::StaticResolvConf(_) => Some(DnsMechanism::StaticResolvConf),
+        Error::NoDnsMonitor | Error::UnsupportedDnsManager(_) => None,
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn mechanism(_error: &Error) -> Option<DnsMechanism> {
+    Some(DnsMechanism::SystemConfiguration)
+}
+
+#[cfg(windows)]
+fn mechanism(error: &Error) -> Option<DnsMechanism> {
+    match error {
+        Error::Iphlpapi(_) => Some(DnsMechanism::Iphlpapi),
+        Error::Netsh(_) => Some(DnsMechanism::Netsh),
+        Error::Tcpip(_) => Some(DnsMechanism::Tcpip),
+    }
+}
+
+#[cfg(target_os = "android")]
+fn mechanism(_error: &Error) -> Option<DnsMechanism> {
+    None
+}
+
+/// A local DNS server that blocks domains on a blocklist.
+#[cfg(not(target_os = "android"))]
+pub mod filter;
+
 /// Sets and monitors system DNS settings. Makes sure the desired DNS servers are being used.
 pub struct DnsMonitor {
-    inner: imp::DnsMonitor,
+    inner: DnsMonitorImpl,
+}
+
+enum DnsMonitorImpl {
+    Os(imp::DnsMonitor),
+    /// Only records the servers that should be used.
+    #[cfg(all(
+        feature = "test-harness",
+        any(target_os = "linux", target_os = "macos")
+    ))]
+    Mock(Arc<Mutex<Option<Vec<IpAddr>>>>),
 }
 
 impl DnsMonitor {
@@ -35,15 +99,38 @@ impl DnsMonitor {
         #[cfg(target_os = "linux")] route_manager: RouteManagerHandle,
     ) -> Result<Self, Error> {
         Ok(DnsMonitor {
-            inner: imp::DnsMonitor::new(
+            inner: DnsMonitorImpl::Os(imp::DnsMonitor::new(
                 #[cfg(target_os = "linux")]
                 handle,
                 #[cfg(target_os = "linux")]
                 route_manager,
-            )?,
+            )?),
         })
     }
 
+    /// Returns a `DnsMonitor` that does not modify the system. The servers that would have been
+    /// set are stored in `servers` instead.
+    #[cfg(all(
+        feature = "test-harness",
+        any(target_os = "linux", target_os = "macos")
+    ))]
+    pub(crate) fn mock(servers: Arc<Mutex<Option<Vec<IpAddr>>>>) -> Self {
+        DnsMonitor {
+            inner: DnsMonitorImpl::Mock(servers),
+        }
+    }
+
+    /// Set whether to fail to set DNS, rather than risk leaking DNS requests, if DNS is managed by
+    /// software that cannot be configured.
+    #[cfg(target_os = "linux")]
+    pub fn set_block_on_unsupported_manager(&mut self, block: bool) {
+        match &mut self.inner {
+            DnsMonitorImpl::Os(monitor) => monitor.set_block_on_unsupported_manager(block),
+            #[cfg(feature = "test-harness")]
+            DnsMonitorImpl::Mock(_) => (),
+        }
+    }
+
     /// Set DNS to the given servers. And start monitoring the system for changes.
     pub fn set(&mut self, interface: &str, servers: &[IpAddr]) -> Result<(), Error> {
         log::info!(
@@ -54,14 +141,34 @@ impl DnsMonitor {
                 .collect::<Vec<String>>()
                 .join(", ")
         );
-        self.inner.set(interface, servers)
+        match &mut self.inner {
+            DnsMonitorImpl::Os(monitor) => monitor.set(interface, servers),
+            #[cfg(all(
+                feature = "test-harness",
+                any(target_os = "linux", target_os = "macos")
+            ))]
+            DnsMonitorImpl::Mock(current_servers) => {
+                *current_servers.lock().unwrap() = Some(servers.to_vec());
+                Ok(())
+            }
+        }
     }
 
     /// Reset system DNS settings to what it was before being set by this instance.
     /// This succeeds if the interface does not exist.
     pub fn reset(&mut self) -> Result<(), Error> {
         log::info!("Resetting DNS");
-        self.inner.reset()
+        match &mut self.inner {
+            DnsMonitorImpl::Os(monitor) => monitor.reset(),
+            #[cfg(all(
+                feature = "test-harness",
+                any(target_os = "linux", target_os = "macos")
+            ))]
+            DnsMonitorImpl::Mock(current_servers) => {
+                *current_servers.lock().unwrap() = None;
+                Ok(())
+            }
+        }
     }
 
     /// Reset DNS settings to what they were before being set by this instance.
@@ -69,7 +176,17 @@ impl DnsMonitor {
     /// as the interface will be destroyed.
     pub fn reset_before_interface_removal(&mut self) -> Result<(), Error> {
         log::info!("Resetting DNS");
-        self.inner.reset_before_interface_removal()
+        match &mut self.inner {
+            DnsMonitorImpl::Os(monitor) => monitor.reset_before_interface_removal(),
+            #[cfg(all(
+                feature = "test-harness",
+                any(target_os = "linux", target_os = "macos")
+            ))]
+            DnsMonitorImpl::Mock(current_servers) => {
+                *current_servers.lock().unwrap() = None;
+                Ok(())
+            }
+        }
     }
 }
 
@@ -3,14 +3,17 @@ use crate::{split_tunnel, tunnel};
 use ipnetwork::IpNetwork;
 use nftnl::{
     expr::{self, IcmpCode, Payload, RejectionType, Verdict},
-    nft_expr, table, Batch, Chain, FinalizedBatch, ProtoFamily, Rule, Table,
+    nft_expr, nftnl_sys,
+    set::{Set, SetKey},
+    table, Batch, Chain, FinalizedBatch, ProtoFamily, Rule, Table,
 };
 use once_cell::sync::Lazy;
 use std::{
     env,
     ffi::{CStr, CString},
     fs, io,
-    net::{IpAddr, Ipv4Addr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
 };
 use talpid_types::net::{AllowedEndpoint, AllowedTunnelTraffic, Endpoint, TransportProtocol};
 
@@ -19,6 +22,18 @@ const MANGLE_CHAIN_PRIORITY: i32 = libc::NF_IP_PRI_MANGLE;
 const PREROUTING_CHAIN_PRIORITY: i32 = libc::NF_IP_PRI_CONNTRACK + 1;
 const PROC_SYS_NET_IPV4_CONF_SRC_VALID_MARK: &str = "/proc/sys/net/ipv4/conf/all/src_valid_mark";
 
+const TCP_FLAGS_OFFSET: u32 = 13;
+const TCP_FLAG_SYN: u8 = 0x02;
+const TCP_FLAG_RST: u8 = 0x04;
+/// Kind of the TCP maximum segment size option.
+const TCPOPT_MAXSEG: u8 = 2;
+/// Offset of the value in the TCP maximum segment size option.
+const TCPOPT_MAXSEG_VALUE_OFFSET: u32 = 2;
+const NFT_EXTHDR_OP_TCPOPT: u32 = 1;
+const NFT_RT_TCPMSS: u32 = 4;
+/// Smallest MSS that every IPv4 host must accept.
+const MIN_MSS: u16 = 536;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 /// Errors that can happen when interacting with Linux netfilter.
@@ -52,13 +67,23 @@ pub enum Error {
 
 /// TODO(linus): This crate is not supposed to be Mullvad-aware. So at some point this should be
 /// replaced by allowing the table name to be configured from the public API of this crate.
-static TABLE_NAME: Lazy<CString> = Lazy::new(|| CString::new("mullvad").unwrap());
+/// Name of the table that holds the rules. It can be changed with `TALPID_FIREWALL_TABLE_NAME`,
+/// so that daemons that run side by side do not replace each other's rules. The rules in one table
+/// still drop the tunnel traffic of other daemons, so only one of them may apply a policy at a time.
+static TABLE_NAME: Lazy<CString> = Lazy::new(|| {
+    let name = env::var("TALPID_FIREWALL_TABLE_NAME").unwrap_or_else(|_| "mullvad".to_owned());
+    CString::new(name).expect("environment variables cannot contain null bytes")
+});
 static IN_CHAIN_NAME: Lazy<CString> = Lazy::new(|| CString::new("input").unwrap());
 static OUT_CHAIN_NAME: Lazy<CString> = Lazy::new(|| CString::new("output").unwrap());
 static FORWARD_CHAIN_NAME: Lazy<CString> = Lazy::new(|| CString::new("forward").unwrap());
 static PREROUTING_CHAIN_NAME: Lazy<CString> = Lazy::new(|| CString::new("prerouting").unwrap());
 static MANGLE_CHAIN_NAME: Lazy<CString> = Lazy::new(|| CString::new("mangle").unwrap());
 static NAT_CHAIN_NAME: Lazy<CString> = Lazy::new(|| CString::new("nat").unwrap());
+static LAN_IPV4_SET_NAME: Lazy<CString> = Lazy::new(|| CString::new("allowed_lan_ipv4").unwrap());
+static LAN_IPV6_SET_NAME: Lazy<CString> = Lazy::new(|| CString::new("allowed_lan_ipv6").unwrap());
+const LAN_IPV4_SET_ID: u32 = 1;
+const LAN_IPV6_SET_ID: u32 = 2;
 
 /// Allows controlling whether firewall rules should have packet counters or not from an env
 /// variable. Useful for debugging the rules.
@@ -74,6 +99,43 @@ static DONT_SET_SRC_VALID_MARK: Lazy<bool> = Lazy::new(|| {
         .unwrap_or(false)
 });
 
+/// Clamps the MSS of TCP connections through the tunnel, if set. Useful when the path MTU is
+/// smaller than the tunnel MTU, such as behind PPPoE, and ICMP is filtered somewhere on the path.
+static CLAMP_MSS: Lazy<Option<MssClamp>> = Lazy::new(|| {
+    let value = env::var("TALPID_FIREWALL_CLAMP_MSS").ok()?;
+    match value.parse() {
+        Ok(clamp) => Some(clamp),
+        Err(()) => {
+            log::error!("Ignoring invalid TALPID_FIREWALL_CLAMP_MSS value: {value}");
+            None
+        }
+    }
+});
+
+/// The MSS to clamp TCP connections to.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum MssClamp {
+    /// Clamp to the MSS that fits the path MTU of the route. Only outgoing packets are clamped,
+    /// since incoming packets have not been routed yet.
+    Pmtu,
+    /// Clamp to a fixed number of bytes.
+    Value(u16),
+}
+
+impl FromStr for MssClamp {
+    type Err = ();
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        if value == "pmtu" {
+            return Ok(MssClamp::Pmtu);
+        }
+        match value.parse() {
+            Ok(mss) if mss >= MIN_MSS => Ok(MssClamp::Value(mss)),
+            _ => Err(()),
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 enum Direction {
     In,
@@ -102,7 +164,8 @@ impl Firewall {
 
     pub fn apply_policy(&mut self, policy: FirewallPolicy) -> Result<()> {
         let table = Table::new(&*TABLE_NAME, ProtoFamily::Inet);
-        let batch = PolicyBatch::new(&table).finalize(&policy, self.fwmark)?;
+        let batch =
+            PolicyBatch::new(&table, &*super::ALLOWED_LAN_NETS).finalize(&policy, self.fwmark)?;
         Self::send_and_process(&batch)?;
         Self::apply_kernel_config(&policy);
         self.verify_tables(&[&TABLE_NAME])
@@ -217,12 +280,13 @@ struct PolicyBatch<'a> {
     prerouting_chain: Chain<'a>,
     mangle_chain: Chain<'a>,
     nat_chain: Chain<'a>,
+    lan_sets: LanSets<'a>,
 }
 
 impl<'a> PolicyBatch<'a> {
     /// Bootstrap a new nftnl message batch object and add the initial messages creating the
-    /// table and chains.
-    pub fn new(table: &'a Table) -> Self {
+    /// table, chains and LAN sets.
+    pub fn new(table: &'a Table, allowed_lan_nets: &[IpNetwork]) -> Self {
         let mut batch = Batch::new();
 
         batch_deprecated_tables(&mut batch);
@@ -264,6 +328,9 @@ impl<'a> PolicyBatch<'a> {
         nat_chain.set_policy(nftnl::Policy::Accept);
         batch.add(&nat_chain, nftnl::MsgType::Add);
 
+        let lan_sets = LanSets::new(table, allowed_lan_nets);
+        lan_sets.batch_sets(&mut batch);
+
         PolicyBatch {
             batch,
             in_chain,
@@ -272,12 +339,14 @@ impl<'a> PolicyBatch<'a> {
             prerouting_chain,
             mangle_chain,
             nat_chain,
+            lan_sets,
         }
     }
 
     /// Finalize the nftnl message batch by adding every firewall rule needed to satisfy the given
     /// policy.
     pub fn finalize(mut self, policy: &FirewallPolicy, fwmark: u32) -> Result<FinalizedBatch> {
+        self.add_mss_clamping_rules(policy)?;
         self.add_loopback_rules()?;
         self.add_split_tunneling_rules(policy, fwmark)?;
         self.add_dhcp_client_rules();
@@ -389,6 +458,46 @@ impl<'a> PolicyBatch<'a> {
         Ok(())
     }
 
+    /// Clamp the MSS of TCP handshakes through the tunnel, if enabled. These rules have no verdict,
+    /// so they must come before any rule that accepts the packets.
+    fn add_mss_clamping_rules(&mut self, policy: &FirewallPolicy) -> Result<()> {
+        let Some(clamp) = *CLAMP_MSS else {
+            return Ok(());
+        };
+        let tunnel = match policy {
+            FirewallPolicy::Connecting {
+                tunnel: Some(tunnel),
+                ..
+            }
+            | FirewallPolicy::Connected { tunnel, .. } => tunnel,
+            _ => return Ok(()),
+        };
+
+        for chain in &[&self.mangle_chain, &self.forward_chain] {
+            let mut rule = Rule::new(chain);
+            check_iface(&mut rule, Direction::Out, &tunnel.interface)?;
+            check_tcp_syn(&mut rule);
+            match clamp {
+                MssClamp::Pmtu => rule.add_expr(&RtTcpMss),
+                MssClamp::Value(mss) => rule.add_expr(&nft_expr!(immediate data mss.to_be())),
+            }
+            rule.add_expr(&SetTcpMss);
+            self.batch.add(&rule, nftnl::MsgType::Add);
+        }
+
+        // A SYN proxy on the path answers the handshake with its own SYN-ACK, which advertises an
+        // MSS that does not account for the tunnel. So incoming handshakes are clamped as well.
+        if let MssClamp::Value(mss) = clamp {
+            let mut rule = Rule::new(&self.prerouting_chain);
+            check_iface(&mut rule, Direction::In, &tunnel.interface)?;
+            check_tcp_syn(&mut rule);
+            rule.add_expr(&nft_expr!(immediate data mss.to_be()));
+            rule.add_expr(&SetTcpMss);
+            self.batch.add(&rule, nftnl::MsgType::Add);
+        }
+        Ok(())
+    }
+
     fn add_loopback_rules(&mut self) -> Result<()> {
         const LOOPBACK_IFACE_NAME: &str = "lo";
         self.batch.add(
@@ -520,12 +629,16 @@ impl<'a> PolicyBatch<'a> {
         let allow_lan = match policy {
             FirewallPolicy::Connecting {
                 peer_endpoint,
+                candidate_endpoint,
                 tunnel,
                 allow_lan,
                 allowed_endpoint,
                 allowed_tunnel_traffic,
             } => {
                 self.add_allow_tunnel_endpoint_rules(peer_endpoint, fwmark);
+                if let Some(candidate_endpoint) = candidate_endpoint {
+                    self.add_allow_tunnel_endpoint_rules(candidate_endpoint, fwmark);
+                }
                 self.add_allow_endpoint_rules(allowed_endpoint);
 
                 // Important to block DNS after allow relay rule (so the relay can operate
@@ -557,6 +670,7 @@ impl<'a> PolicyBatch<'a> {
                 tunnel,
                 allow_lan,
                 dns_servers,
+                tunnel_destinations,
             } => {
                 self.add_allow_tunnel_endpoint_rules(peer_endpoint, fwmark);
                 self.add_allow_dns_rules(tunnel, dns_servers, TransportProtocol::Udp)?;
@@ -569,6 +683,11 @@ impl<'a> PolicyBatch<'a> {
                 if *allow_lan {
                     self.add_block_cve_2019_14899(tunnel);
                 }
+                // Must come after allowing the tunnel, since everything that is not rejected
+                // here is allowed outside of it.
+                if let Some(destinations) = tunnel_destinations {
+                    self.add_partial_tunnel_rules(destinations, *allow_lan);
+                }
                 *allow_lan
             }
             FirewallPolicy::Blocked {
@@ -822,6 +941,42 @@ impl<'a> PolicyBatch<'a> {
         Ok(())
     }
 
+    /// Allows traffic outside the tunnel, except to `tunnel_destinations`, which must only be
+    /// reachable through it. LAN traffic is only allowed if `allow_lan` is set.
+    fn add_partial_tunnel_rules(&mut self, tunnel_destinations: &[IpNetwork], allow_lan: bool) {
+        let mut rejected_nets = tunnel_destinations.to_vec();
+        if !allow_lan {
+            rejected_nets.extend(super::ALLOWED_LAN_MULTICAST_NETS.iter());
+        }
+        let reject_verdict = Verdict::Reject(RejectionType::Icmp(IcmpCode::PortUnreach));
+
+        for chain in &[&self.out_chain, &self.forward_chain] {
+            if !allow_lan {
+                self.lan_sets
+                    .add_rules(&mut self.batch, chain, End::Dst, &reject_verdict);
+            }
+            for net in &rejected_nets {
+                let mut reject_rule = Rule::new(chain);
+                check_net(&mut reject_rule, End::Dst, *net);
+                add_verdict(&mut reject_rule, &reject_verdict);
+                self.batch.add(&reject_rule, nftnl::MsgType::Add);
+            }
+
+            let mut allow_rule = Rule::new(chain);
+            add_verdict(&mut allow_rule, &Verdict::Accept);
+            self.batch.add(&allow_rule, nftnl::MsgType::Add);
+        }
+
+        // Only allow responses to connections made outside the tunnel
+        let mut established_rule = Rule::new(&self.in_chain);
+        established_rule.add_expr(&nft_expr!(ct state));
+        let allowed_states = nftnl::expr::ct::States::ESTABLISHED.bits();
+        established_rule.add_expr(&nft_expr!(bitwise mask allowed_states, xor 0u32));
+        established_rule.add_expr(&nft_expr!(cmp != 0u32));
+        add_verdict(&mut established_rule, &Verdict::Accept);
+        self.batch.add(&established_rule, nftnl::MsgType::Add);
+    }
+
     /// Adds rules for stopping [CVE-2019-14899](https://seclists.org/oss-sec/2019/q4/122).
     /// An attacker on the same local network as the VPN connected device could figure out
     /// the tunnel IP the device used if the device was set to not filter reverse path (rp_filter.)
@@ -840,12 +995,8 @@ impl<'a> PolicyBatch<'a> {
         // Output and forward chains
         for chain in &[&self.out_chain, &self.forward_chain] {
             // LAN -> LAN
-            for net in &*super::ALLOWED_LAN_NETS {
-                let mut out_rule = Rule::new(chain);
-                check_net(&mut out_rule, End::Dst, *net);
-                add_verdict(&mut out_rule, &Verdict::Accept);
-                self.batch.add(&out_rule, nftnl::MsgType::Add);
-            }
+            self.lan_sets
+                .add_rules(&mut self.batch, chain, End::Dst, &Verdict::Accept);
 
             // LAN -> Multicast
             for net in &*super::ALLOWED_LAN_MULTICAST_NETS {
@@ -858,12 +1009,8 @@ impl<'a> PolicyBatch<'a> {
 
         // Input chain
         // LAN -> LAN
-        for net in &*super::ALLOWED_LAN_NETS {
-            let mut in_rule = Rule::new(&self.in_chain);
-            check_net(&mut in_rule, End::Src, *net);
-            add_verdict(&mut in_rule, &Verdict::Accept);
-            self.batch.add(&in_rule, nftnl::MsgType::Add);
-        }
+        self.lan_sets
+            .add_rules(&mut self.batch, &self.in_chain, End::Src, &Verdict::Accept);
         self.add_dhcp_server_rules();
     }
 
@@ -892,6 +1039,151 @@ impl<'a> PolicyBatch<'a> {
     }
 }
 
+/// Named sets holding the networks that are allowed when LAN traffic is allowed. Rules look up
+/// addresses in these sets rather than matching each network.
+struct LanSets<'a> {
+    ipv4: Set<'a, Ipv4Addr>,
+    ipv6: Set<'a, Ipv6Addr>,
+}
+
+impl<'a> LanSets<'a> {
+    fn new(table: &'a Table, nets: &[IpNetwork]) -> Self {
+        let mut ipv4_ranges = vec![];
+        let mut ipv6_ranges = vec![];
+        for net in nets {
+            match net {
+                IpNetwork::V4(net) => {
+                    let first = u32::from(net.network());
+                    let last = first | !u32::from(net.mask());
+                    ipv4_ranges.push((u128::from(first), u128::from(last)));
+                }
+                IpNetwork::V6(net) => {
+                    let first = u128::from(net.network());
+                    let last = first | !u128::from(net.mask());
+                    ipv6_ranges.push((first, last));
+                }
+            }
+        }
+
+        let mut ipv4 = interval_set(&LAN_IPV4_SET_NAME, LAN_IPV4_SET_ID, table);
+        for (start, end) in merge_ranges(ipv4_ranges, u128::from(u32::MAX)) {
+            add_interval(&mut ipv4, start, end, |addr| Ipv4Addr::from(addr as u32));
+        }
+        let mut ipv6 = interval_set(&LAN_IPV6_SET_NAME, LAN_IPV6_SET_ID, table);
+        for (start, end) in merge_ranges(ipv6_ranges, u128::MAX) {
+            add_interval(&mut ipv6, start, end, Ipv6Addr::from);
+        }
+
+        LanSets { ipv4, ipv6 }
+    }
+
+    /// Adds messages creating the sets, including their elements, to `batch`.
+    fn batch_sets(&self, batch: &mut Batch) {
+        batch.add(&self.ipv4, nftnl::MsgType::Add);
+        batch.add(&self.ipv6, nftnl::MsgType::Add);
+        batch.add_iter(self.ipv4.elems_iter(), nftnl::MsgType::Add);
+        batch.add_iter(self.ipv6.elems_iter(), nftnl::MsgType::Add);
+    }
+
+    /// Adds rules to `chain` that apply `verdict` to packets whose source or destination address,
+    /// depending on `end`, is in one of the sets.
+    fn add_rules(&self, batch: &mut Batch, chain: &Chain<'_>, end: End, verdict: &Verdict) {
+        let mut ipv4_rule = Rule::new(chain);
+        check_l3proto(&mut ipv4_rule, IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        ipv4_rule.add_expr(&match end {
+            End::Src => nft_expr!(payload ipv4 saddr),
+            End::Dst => nft_expr!(payload ipv4 daddr),
+        });
+        ipv4_rule.add_expr(&expr::Lookup::new(&self.ipv4).expect("LAN sets are named"));
+        add_verdict(&mut ipv4_rule, verdict);
+        batch.add(&ipv4_rule, nftnl::MsgType::Add);
+
+        let mut ipv6_rule = Rule::new(chain);
+        check_l3proto(&mut ipv6_rule, IpAddr::V6(Ipv6Addr::UNSPECIFIED));
+        ipv6_rule.add_expr(&match end {
+            End::Src => nft_expr!(payload ipv6 saddr),
+            End::Dst => nft_expr!(payload ipv6 daddr),
+        });
+        ipv6_rule.add_expr(&expr::Lookup::new(&self.ipv6).expect("LAN sets are named"));
+        add_verdict(&mut ipv6_rule, verdict);
+        batch.add(&ipv6_rule, nftnl::MsgType::Add);
+    }
+}
+
+/// Creates a named set that can hold address ranges.
+fn interval_set<'a, K: SetKey>(name: &CStr, id: u32, table: &'a Table) -> Set<'a, K> {
+    let set = Set::new(name, id, table, ProtoFamily::Inet);
+    // `Set::new` creates constant, anonymous sets. Ours must be possible to update by name.
+    unsafe {
+        nftnl_sys::nftnl_set_set_u32(
+            set.as_ptr() as *mut _,
+            nftnl_sys::NFTNL_SET_FLAGS as u16,
+            libc::NFT_SET_INTERVAL as u32,
+        );
+    }
+    set
+}
+
+/// Adds the range of addresses from `start` up to, but not including, `end` to `set`. A range
+/// without an end extends to the last address.
+fn add_interval<K: SetKey>(
+    set: &mut Set<'_, K>,
+    start: u128,
+    end: Option<u128>,
+    to_key: impl Fn(u128) -> K,
+) {
+    add_interval_elem(set, &to_key(start), false);
+    if let Some(end) = end {
+        add_interval_elem(set, &to_key(end), true);
+    }
+}
+
+/// Adds an element to an interval set. Ranges are made up of an element holding the first address
+/// of the range, and an element flagged as the end of the interval, holding the first address
+/// after the range.
+fn add_interval_elem<K: SetKey>(set: &mut Set<'_, K>, key: &K, interval_end: bool) {
+    let data = key.data();
+    unsafe {
+        let elem = nftnl_sys::nftnl_set_elem_alloc();
+        assert!(!elem.is_null(), "Failed to allocate set element");
+        nftnl_sys::nftnl_set_elem_set(
+            elem,
+            nftnl_sys::NFTNL_SET_ELEM_KEY as u16,
+            data.as_ptr() as *const _,
+            data.len() as u32,
+        );
+        if interval_end {
+            nftnl_sys::nftnl_set_elem_set_u32(
+                elem,
+                nftnl_sys::NFTNL_SET_ELEM_FLAGS as u16,
+                libc::NFT_SET_ELEM_INTERVAL_END as u32,
+            );
+        }
+        // The set takes ownership of the element
+        nftnl_sys::nftnl_set_elem_add(set.as_ptr() as *mut _, elem);
+    }
+}
+
+/// Merges the inclusive `(first, last)` address ranges in `ranges` into sorted, non-overlapping
+/// `(start, end)` ranges, where `end` is the first address after the range. Interval sets must not
+/// contain overlapping ranges. `end` is `None` if a range ends at `max_addr`.
+fn merge_ranges(mut ranges: Vec<(u128, u128)>, max_addr: u128) -> Vec<(u128, Option<u128>)> {
+    ranges.sort_unstable();
+    let mut merged: Vec<(u128, u128)> = Vec::with_capacity(ranges.len());
+    for (first, last) in ranges {
+        match merged.last_mut() {
+            Some((_, prev_last)) if first <= prev_last.saturating_add(1) => {
+                *prev_last = (*prev_last).max(last);
+            }
+            _ => merged.push((first, last)),
+        }
+    }
+    merged
+        .into_iter()
+        .map(|(first, last)| (first, (last < max_addr).then(|| last + 1)))
+        .collect()
+}
+
 fn is_local_dns_address(tunnel: &tunnel::TunnelMetadata, server: &IpAddr) -> bool {
     super::is_local_address(server)
         && server != &tunnel.ipv4_gateway
@@ -1025,6 +1317,17 @@ fn check_port(rule: &mut Rule<'_>, protocol: TransportProtocol, end: End, port:
     rule.add_expr(&nft_expr!(cmp == port.to_be()));
 }
 
+/// Match TCP packets with SYN set and RST unset, i.e. both SYN and SYN-ACK.
+fn check_tcp_syn(rule: &mut Rule<'_>) {
+    check_l4proto(rule, TransportProtocol::Tcp);
+    rule.add_expr(&TransportPayload {
+        offset: TCP_FLAGS_OFFSET,
+        len: 1,
+    });
+    rule.add_expr(&nft_expr!(bitwise mask TCP_FLAG_SYN | TCP_FLAG_RST, xor 0u8));
+    rule.add_expr(&nft_expr!(cmp == TCP_FLAG_SYN));
+}
+
 fn check_l3proto(rule: &mut Rule<'_>, ip: IpAddr) {
     rule.add_expr(&nft_expr!(meta nfproto));
     rule.add_expr(&nft_expr!(cmp == l3proto(ip)));
@@ -1056,6 +1359,102 @@ fn add_verdict(rule: &mut Rule<'_>, verdict: &expr::Verdict) {
     rule.add_expr(verdict);
 }
 
+/// Loads `len` bytes at `offset` in the transport header into the first register. Unlike
+/// [`expr::Payload`], this can load fields other than the ports.
+struct TransportPayload {
+    offset: u32,
+    len: u32,
+}
+
+impl expr::Expression for TransportPayload {
+    fn to_expr(&self, _rule: &Rule<'_>) -> *mut nftnl_sys::nftnl_expr {
+        unsafe {
+            let expr = alloc_expr(b"payload\0");
+            nftnl_sys::nftnl_expr_set_u32(
+                expr,
+                nftnl_sys::NFTNL_EXPR_PAYLOAD_DREG as u16,
+                libc::NFT_REG_1 as u32,
+            );
+            nftnl_sys::nftnl_expr_set_u32(
+                expr,
+                nftnl_sys::NFTNL_EXPR_PAYLOAD_BASE as u16,
+                libc::NFT_PAYLOAD_TRANSPORT_HEADER as u32,
+            );
+            nftnl_sys::nftnl_expr_set_u32(
+                expr,
+                nftnl_sys::NFTNL_EXPR_PAYLOAD_OFFSET as u16,
+                self.offset,
+            );
+            nftnl_sys::nftnl_expr_set_u32(expr, nftnl_sys::NFTNL_EXPR_PAYLOAD_LEN as u16, self.len);
+            expr
+        }
+    }
+}
+
+/// Loads the MSS that fits the path MTU of the route of the packet into the first register. Same
+/// as `rt mtu` in nft.
+struct RtTcpMss;
+
+impl expr::Expression for RtTcpMss {
+    fn to_expr(&self, _rule: &Rule<'_>) -> *mut nftnl_sys::nftnl_expr {
+        unsafe {
+            let expr = alloc_expr(b"rt\0");
+            nftnl_sys::nftnl_expr_set_u32(expr, nftnl_sys::NFTNL_EXPR_RT_KEY as u16, NFT_RT_TCPMSS);
+            nftnl_sys::nftnl_expr_set_u32(
+                expr,
+                nftnl_sys::NFTNL_EXPR_RT_DREG as u16,
+                libc::NFT_REG_1 as u32,
+            );
+            expr
+        }
+    }
+}
+
+/// Sets the TCP MSS option to the value in the first register, unless the option is smaller
+/// already. Same as `tcp option maxseg size set` in nft.
+struct SetTcpMss;
+
+impl expr::Expression for SetTcpMss {
+    fn to_expr(&self, _rule: &Rule<'_>) -> *mut nftnl_sys::nftnl_expr {
+        unsafe {
+            let expr = alloc_expr(b"exthdr\0");
+            nftnl_sys::nftnl_expr_set_u32(
+                expr,
+                nftnl_sys::NFTNL_EXPR_EXTHDR_SREG as u16,
+                libc::NFT_REG_1 as u32,
+            );
+            nftnl_sys::nftnl_expr_set_u8(
+                expr,
+                nftnl_sys::NFTNL_EXPR_EXTHDR_TYPE as u16,
+                TCPOPT_MAXSEG,
+            );
+            nftnl_sys::nftnl_expr_set_u32(
+                expr,
+                nftnl_sys::NFTNL_EXPR_EXTHDR_OFFSET as u16,
+                TCPOPT_MAXSEG_VALUE_OFFSET,
+            );
+            nftnl_sys::nftnl_expr_set_u32(
+                expr,
+                nftnl_sys::NFTNL_EXPR_EXTHDR_LEN as u16,
+                std::mem::size_of::<u16>() as u32,
+            );
+            nftnl_sys::nftnl_expr_set_u32(
+                expr,
+                nftnl_sys::NFTNL_EXPR_EXTHDR_OP as u16,
+                NFT_EXTHDR_OP_TCPOPT,
+            );
+            expr
+        }
+    }
+}
+
+/// Allocates an expression. `name` must be null terminated.
+unsafe fn alloc_expr(name: &[u8]) -> *mut nftnl_sys::nftnl_expr {
+    let expr = nftnl_sys::nftnl_expr_alloc(name.as_ptr() as *const _);
+    assert!(!expr.is_null(), "Failed to allocate expression");
+    expr
+}
+
 fn set_src_valid_mark_sysctl() -> io::Result<()> {
     fs::write(PROC_SYS_NET_IPV4_CONF_SRC_VALID_MARK, b"1")
 }
@@ -126,6 +126,7 @@ impl Firewall {
         match policy {
             FirewallPolicy::Connecting {
                 peer_endpoint,
+                candidate_endpoint,
                 tunnel,
                 allow_lan,
                 allowed_endpoint,
@@ -133,6 +134,9 @@ impl Firewall {
                 redirect_interface,
             } => {
                 let mut rules = vec![self.get_allow_relay_rule(peer_endpoint)?];
+                if let Some(candidate_endpoint) = candidate_endpoint {
+                    rules.push(self.get_allow_relay_rule(candidate_endpoint)?);
+                }
                 rules.push(self.get_allowed_endpoint_rule(allowed_endpoint)?);
 
                 // Important to block DNS after allow relay rule (so the relay can operate
@@ -722,6 +726,96 @@ impl Firewall {
             .try_remove_anchor(ANCHOR_NAME, pfctl::AnchorKind::Redirect)?;
         Ok(())
     }
+
+    /// Returns the filter and redirect rules that are currently loaded into our anchor, as
+    /// printed by `pfctl`.
+    pub async fn loaded_rules() -> io::Result<String> {
+        let filter_rules = pfctl_show(&["-a", ANCHOR_NAME, "-s", "rules"]).await?;
+        let redirect_rules = pfctl_show(&["-a", ANCHOR_NAME, "-s", "nat"]).await?;
+        Ok(format!(
+            "# Filter rules\n{filter_rules}\n# Redirect rules\n{redirect_rules}"
+        ))
+    }
+
+    /// Returns a main ruleset, in the format of `pf.conf`, which blocks all traffic except on the
+    /// loopback interface, DHCP, NDP, and LAN traffic if `allow_lan` is set. The blocking rules are
+    /// put in our anchor, so they are replaced as soon as a policy is applied.
+    pub fn early_boot_ruleset(allow_lan: bool) -> String {
+        let link_local = *super::IPV6_LINK_LOCAL;
+        let mut rules = vec![
+            "pass quick on lo0 all".to_owned(),
+            format!(
+                "pass out quick inet proto udp from any port {} to {} port {}",
+                super::DHCPV4_CLIENT_PORT,
+                Ipv4Addr::BROADCAST,
+                super::DHCPV4_SERVER_PORT,
+            ),
+            format!(
+                "pass in quick inet proto udp from any port {} to any port {}",
+                super::DHCPV4_SERVER_PORT,
+                super::DHCPV4_CLIENT_PORT,
+            ),
+        ];
+        for dhcpv6_server in &*super::DHCPV6_SERVER_ADDRS {
+            rules.push(format!(
+                "pass out quick inet6 proto udp from {link_local} port {} to {dhcpv6_server} port {}",
+                super::DHCPV6_CLIENT_PORT,
+                super::DHCPV6_SERVER_PORT,
+            ));
+        }
+        rules.push(format!(
+            "pass in quick inet6 proto udp from {link_local} port {} to {link_local} port {}",
+            super::DHCPV6_SERVER_PORT,
+            super::DHCPV6_CLIENT_PORT,
+        ));
+        rules.push(
+            "pass quick inet6 proto ipv6-icmp icmp6-type \
+             { routersol, routeradv, neighbrsol, neighbradv, redir }"
+                .to_owned(),
+        );
+        if allow_lan {
+            for net in &*super::ALLOWED_LAN_NETS {
+                rules.push(format!("pass out quick from any to {net}"));
+                rules.push(format!("pass in quick from {net} to any"));
+            }
+            for multicast_net in &*super::ALLOWED_LAN_MULTICAST_NETS {
+                rules.push(format!("pass out quick from any to {multicast_net}"));
+            }
+        }
+        rules.push("block drop quick all".to_owned());
+
+        let mut ruleset = String::from(concat!(
+            "scrub-anchor \"com.apple/*\"\n",
+            "nat-anchor \"com.apple/*\"\n",
+            "rdr-anchor \"com.apple/*\"\n",
+            "anchor \"com.apple/*\"\n",
+            "load anchor \"com.apple\" from \"/etc/pf.anchors/com.apple\"\n",
+        ));
+        ruleset.push_str(&format!("anchor \"{ANCHOR_NAME}\" {{\n"));
+        for rule in rules {
+            ruleset.push_str(&format!("    {rule}\n"));
+        }
+        ruleset.push_str("}\n");
+        ruleset
+    }
+
+    /// Checks that pf accepts the ruleset at `path`, without loading it.
+    pub async fn check_ruleset(path: &str) -> io::Result<()> {
+        pfctl_show(&["-n", "-f", path]).await.map(|_| ())
+    }
+}
+
+/// Runs `pfctl` with the given arguments and returns what it printed to stdout.
+async fn pfctl_show(args: &[&str]) -> io::Result<String> {
+    let output = tokio::process::Command::new("/sbin/pfctl")
+        .args(args)
+        .output()
+        .await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!("pfctl failed: {}", stderr.trim())));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
 }
 
 fn as_pfctl_proto(protocol: net::TransportProtocol) -> pfctl::Proto {
@@ -1,5 +1,10 @@
 use ipnetwork::{IpNetwork, Ipv4Network, Ipv6Network};
 use once_cell::sync::Lazy;
+#[cfg(all(
+    feature = "test-harness",
+    any(target_os = "linux", target_os = "macos")
+))]
+use std::sync::{Arc, Mutex};
 use std::{
     fmt,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
@@ -115,6 +120,9 @@ pub enum FirewallPolicy {
     Connecting {
         /// The peer endpoint that should be allowed.
         peer_endpoint: AllowedEndpoint,
+        /// Endpoint of a relay that is connected to in parallel with the peer, if any.
+        #[cfg(not(target_os = "windows"))]
+        candidate_endpoint: Option<AllowedEndpoint>,
         /// Metadata about the tunnel and tunnel interface.
         tunnel: Option<crate::tunnel::TunnelMetadata>,
         /// Flag setting if communication with LAN networks should be possible.
@@ -142,6 +150,10 @@ pub enum FirewallPolicy {
         /// Interface to redirect (VPN tunnel) traffic to
         #[cfg(target_os = "macos")]
         redirect_interface: Option<String>,
+        /// Destinations that are routed through the tunnel, if not all of them are. Traffic to
+        /// these must never leave outside the tunnel, but other traffic is allowed.
+        #[cfg(target_os = "linux")]
+        tunnel_destinations: Option<Vec<IpNetwork>>,
     },
 
     /// Block all network traffic in and out from the computer.
@@ -233,10 +245,63 @@ impl fmt::Display for FirewallPolicy {
     }
 }
 
+/// Failure to apply a [`FirewallPolicy`]. Rather than leaving the policy partially applied, the
+/// firewall is rolled back to the last policy that was applied successfully. The firewall is never
+/// reset on failure, since that would let traffic through.
+#[derive(thiserror::Error, Debug)]
+pub enum ApplyPolicyError {
+    /// Failed to apply the policy. The previous policy is enforced again.
+    #[error("Failed to apply firewall policy. Rolled back to the previous policy")]
+    RolledBack(#[source] Error),
+
+    /// Failed to apply the policy, and no policy has been applied successfully before. The
+    /// firewall rules are left as they were, which may be rules that block all traffic since boot.
+    #[error("Failed to apply firewall policy. There is no previous policy to roll back to")]
+    NoPreviousPolicy(#[source] Error),
+
+    /// Failed to apply the policy, and then failed to roll back to the previous policy. The state
+    /// of the firewall is unknown.
+    #[error("Failed to apply firewall policy, and failed to roll back: {rollback_error}")]
+    RollbackFailed {
+        #[source]
+        error: Error,
+        rollback_error: Error,
+    },
+}
+
+impl ApplyPolicyError {
+    /// Returns the error that prevented the policy from being applied.
+    pub fn into_apply_error(self) -> Error {
+        match self {
+            ApplyPolicyError::RolledBack(error) => error,
+            ApplyPolicyError::NoPreviousPolicy(error) => error,
+            ApplyPolicyError::RollbackFailed { error, .. } => error,
+        }
+    }
+}
+
 /// Manages network security of the computer/device. Can apply and enforce firewall policies
 /// by manipulating the OS firewall and DNS settings.
 pub struct Firewall {
-    inner: imp::Firewall,
+    inner: FirewallImpl,
+    /// The last policy that was applied successfully, which is restored if applying a new policy
+    /// fails.
+    applied_policy: Option<FirewallPolicy>,
+}
+
+enum FirewallImpl {
+    Os(imp::Firewall),
+    /// Only records the policy that should be enforced.
+    #[cfg(all(
+        feature = "test-harness",
+        any(target_os = "linux", target_os = "macos")
+    ))]
+    Mock {
+        policy: Arc<Mutex<Option<FirewallPolicy>>>,
+        /// Policy that fails to apply, for testing rollbacks.
+        #[cfg(all(test, target_os = "linux"))]
+        failing_policy: Option<FirewallPolicy>,
+    },
 }
 
 /// Arguments required when first initializing the firewall.
@@ -262,32 +327,204 @@ pub enum InitialFirewallState {
 impl Firewall {
     /// Creates a firewall instance with the given arguments.
     pub fn from_args(args: FirewallArguments) -> Result<Self, Error> {
+        // The initial state is only entered during init on Windows
+        #[cfg(windows)]
+        let applied_policy = match &args.initial_state {
+            InitialFirewallState::None => None,
+            InitialFirewallState::Blocked(allowed_endpoint) => Some(FirewallPolicy::Blocked {
+                allow_lan: args.allow_lan,
+                allowed_endpoint: Some(allowed_endpoint.clone()),
+            }),
+        };
+        #[cfg(not(windows))]
+        let applied_policy = None;
         Ok(Firewall {
-            inner: imp::Firewall::from_args(args)?,
+            inner: FirewallImpl::Os(imp::Firewall::from_args(args)?),
+            applied_policy,
         })
     }
 
     /// Createsa new firewall instance.
     pub fn new(#[cfg(target_os = "linux")] fwmark: u32) -> Result<Self, Error> {
         Ok(Firewall {
-            inner: imp::Firewall::new(
+            inner: FirewallImpl::Os(imp::Firewall::new(
                 #[cfg(target_os = "linux")]
                 fwmark,
-            )?,
+            )?),
+            applied_policy: None,
         })
     }
 
+    /// Creates a firewall that does not modify the system. The policy that would have been
+    /// enforced is stored in `policy` instead.
+    #[cfg(all(
+        feature = "test-harness",
+        any(target_os = "linux", target_os = "macos")
+    ))]
+    pub(crate) fn mock(policy: Arc<Mutex<Option<FirewallPolicy>>>) -> Self {
+        Firewall {
+            inner: FirewallImpl::Mock {
+                policy,
+                #[cfg(all(test, target_os = "linux"))]
+                failing_policy: None,
+            },
+            applied_policy: None,
+        }
+    }
+
     /// Applies and starts enforcing the given `FirewallPolicy` Makes sure it is being kept in place
     /// until this method is called again with another policy, or until `reset_policy` is called.
-    pub fn apply_policy(&mut self, policy: FirewallPolicy) -> Result<(), Error> {
+    ///
+    /// If the policy cannot be applied, the previously applied policy is restored. If none was
+    /// applied, the firewall rules are left as they are.
+    pub fn apply_policy(&mut self, policy: FirewallPolicy) -> Result<(), ApplyPolicyError> {
         log::info!("Applying firewall policy: {}", policy);
-        self.inner.apply_policy(policy)
+        let error = match self.apply_policy_inner(policy.clone()) {
+            Ok(()) => {
+                self.applied_policy = Some(policy);
+                return Ok(());
+            }
+            Err(error) => error,
+        };
+
+        let Some(previous_policy) = self.applied_policy.clone() else {
+            log::info!(
+                "No firewall policy to roll back to. Leaving the firewall rules as they are"
+            );
+            return Err(ApplyPolicyError::NoPreviousPolicy(error));
+        };
+        log::info!("Rolling back to firewall policy: {}", previous_policy);
+        match self.apply_policy_inner(previous_policy) {
+            Ok(()) => Err(ApplyPolicyError::RolledBack(error)),
+            Err(rollback_error) => {
+                self.applied_policy = None;
+                Err(ApplyPolicyError::RollbackFailed {
+                    error,
+                    rollback_error,
+                })
+            }
+        }
+    }
+
+    fn apply_policy_inner(&mut self, policy: FirewallPolicy) -> Result<(), Error> {
+        match &mut self.inner {
+            FirewallImpl::Os(firewall) => firewall.apply_policy(policy),
+            #[cfg(all(
+                feature = "test-harness",
+                any(target_os = "linux", target_os = "macos")
+            ))]
+            FirewallImpl::Mock {
+                policy: current_policy,
+                #[cfg(all(test, target_os = "linux"))]
+                failing_policy,
+            } => {
+                #[cfg(all(test, target_os = "linux"))]
+                if failing_policy.as_ref() == Some(&policy) {
+                    return Err(Error::NetfilterTableNotSetError);
+                }
+                *current_policy.lock().unwrap() = Some(policy);
+                Ok(())
+            }
+        }
     }
 
     /// Resets/removes any currently enforced `FirewallPolicy`. Returns the system to the same state
     /// it had before any policy was applied through this `Firewall` instance.
     pub fn reset_policy(&mut self) -> Result<(), Error> {
         log::info!("Resetting firewall policy");
-        self.inner.reset_policy()
+        self.applied_policy = None;
+        self.reset_policy_inner()
+    }
+
+    fn reset_policy_inner(&mut self) -> Result<(), Error> {
+        match &mut self.inner {
+            FirewallImpl::Os(firewall) => firewall.reset_policy(),
+            #[cfg(all(
+                feature = "test-harness",
+                any(target_os = "linux", target_os = "macos")
+            ))]
+            FirewallImpl::Mock {
+                policy: current_policy,
+                ..
+            } => {
+                *current_policy.lock().unwrap() = None;
+                Ok(())
+            }
+        }
+    }
+
+    /// Removes firewall filters that were left behind by older versions of the app, for example
+    /// because they crashed. Filters that are in use by this version are never removed. Returns
+    /// the number of removed filters.
+    #[cfg(windows)]
+    pub fn remove_stale_filters() -> Result<usize, Error> {
+        imp::Firewall::remove_stale_filters()
+    }
+
+    /// Returns the rules that are currently loaded into the firewall by this crate, in the format
+    /// used by `pfctl`. This reads the rules from the kernel, so it shows what is actually being
+    /// enforced.
+    #[cfg(target_os = "macos")]
+    pub async fn loaded_rules() -> std::io::Result<String> {
+        imp::Firewall::loaded_rules().await
+    }
+
+    /// Returns a ruleset, in the format of `pf.conf`, that blocks traffic until a policy is
+    /// applied. It is meant to be loaded as the main ruleset during boot, before the daemon starts.
+    #[cfg(target_os = "macos")]
+    pub fn early_boot_ruleset(allow_lan: bool) -> String {
+        imp::Firewall::early_boot_ruleset(allow_lan)
+    }
+
+    /// Checks that pf accepts the ruleset at `path`, without loading it.
+    #[cfg(target_os = "macos")]
+    pub async fn check_ruleset(path: &str) -> std::io::Result<()> {
+        imp::Firewall::check_ruleset(path).await
+    }
+}
+
+#[cfg(all(test, feature = "test-harness", target_os = "linux"))]
+mod test {
+    use super::*;
+
+    fn blocked(allow_lan: bool) -> FirewallPolicy {
+        FirewallPolicy::Blocked {
+            allow_lan,
+            allowed_endpoint: None,
+        }
+    }
+
+    /// A policy that fails to apply must not replace the last one that was applied.
+    #[test]
+    fn test_rollback_to_previous_policy() {
+        let policy = Arc::new(Mutex::new(None));
+        let mut firewall = Firewall::mock(policy.clone());
+        firewall.apply_policy(blocked(false)).unwrap();
+
+        if let FirewallImpl::Mock { failing_policy, .. } = &mut firewall.inner {
+            *failing_policy = Some(blocked(true));
+        }
+        assert!(matches!(
+            firewall.apply_policy(blocked(true)),
+            Err(ApplyPolicyError::RolledBack(_))
+        ));
+        assert_eq!(*policy.lock().unwrap(), Some(blocked(false)));
+    }
+
+    /// If no policy has been applied, a policy that fails to apply must not cause the firewall to
+    /// be reset, since there may be rules in place that block traffic.
+    #[test]
+    fn test_no_reset_without_previous_policy() {
+        let policy = Arc::new(Mutex::new(Some(blocked(false))));
+        let mut firewall = Firewall::mock(policy.clone());
+
+        if let FirewallImpl::Mock { failing_policy, .. } = &mut firewall.inner {
+            *failing_policy = Some(blocked(true));
+        }
+        assert!(matches!(
+            firewall.apply_policy(blocked(true)),
+            Err(ApplyPolicyError::NoPreviousPolicy(_))
+        ));
+        assert_eq!(*policy.lock().unwrap(), Some(blocked(false)));
     }
 }
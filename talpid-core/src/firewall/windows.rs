@@ -7,6 +7,7 @@ use super::{FirewallArguments, FirewallPolicy, InitialFirewallState};
 use talpid_types::{
     net::{AllowedEndpoint, AllowedTunnelTraffic},
     tunnel::FirewallPolicyError,
+    ErrorExt,
 };
 use widestring::WideCString;
 use windows_sys::Win32::Globalization::{MultiByteToWideChar, CP_ACP};
@@ -37,6 +38,10 @@ pub enum Error {
     /// Failure to reset firewall policies
     #[error("Failed to reset firewall policies")]
     ResettingPolicy(#[source] FirewallPolicyError),
+
+    /// Failure to remove stale filters
+    #[error("Failed to remove stale firewall filters")]
+    RemovingStaleFilters(#[source] FirewallPolicyError),
 }
 
 /// Timeout for acquiring the WFP transaction lock
@@ -57,6 +62,7 @@ impl Firewall {
     }
 
     pub fn new() -> Result<Self, Error> {
+        Self::remove_stale_filters_on_startup();
         unsafe {
             WinFw_Initialize(
                 WINFW_TIMEOUT_SECONDS,
@@ -74,6 +80,7 @@ impl Firewall {
         allowed_endpoint: AllowedEndpoint,
         allow_lan: bool,
     ) -> Result<Self, Error> {
+        Self::remove_stale_filters_on_startup();
         let cfg = &WinFwSettings::new(allow_lan);
         let allowed_endpoint = WinFwAllowedEndpointContainer::from(allowed_endpoint);
         unsafe {
@@ -136,6 +143,31 @@ impl Firewall {
         Ok(())
     }
 
+    /// Removes filters that were added by an older version of the app, and that this version
+    /// doesn't know about. Returns the number of removed filters.
+    pub fn remove_stale_filters() -> Result<usize, Error> {
+        let mut num_removed = 0u32;
+        unsafe {
+            WinFw_RemoveStaleFilters(&mut num_removed)
+                .into_result()
+                .map_err(Error::RemovingStaleFilters)?
+        };
+        Ok(usize::try_from(num_removed).unwrap())
+    }
+
+    /// Stale filters can prevent the firewall module from replacing its sublayers and providers,
+    /// so they must be removed before it is initialized.
+    fn remove_stale_filters_on_startup() {
+        match Self::remove_stale_filters() {
+            Ok(0) => (),
+            Ok(num_removed) => log::info!("Removed {num_removed} stale firewall filters"),
+            Err(error) => log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to remove stale firewall filters")
+            ),
+        }
+    }
+
     fn set_connecting_state(
         &mut self,
         endpoint: &AllowedEndpoint,
@@ -582,7 +614,9 @@ mod winfw {
         pub fn into_result(self) -> Result<(), super::FirewallPolicyError> {
             match self {
                 WinFwPolicyStatus::Success => Ok(()),
-                WinFwPolicyStatus::GeneralFailure => Err(super::FirewallPolicyError::Generic),
+                WinFwPolicyStatus::GeneralFailure => {
+                    Err(super::FirewallPolicyError::Generic(Default::default()))
+                }
                 WinFwPolicyStatus::LockTimeout => {
                     // TODO: Obtain application name and string from WinFw
                     Err(super::FirewallPolicyError::Locked(None))
@@ -649,5 +683,8 @@ mod winfw {
 
         #[link_name = "WinFw_Reset"]
         pub fn WinFw_Reset() -> WinFwPolicyStatus;
+
+        #[link_name = "WinFw_RemoveStaleFilters"]
+        pub fn WinFw_RemoveStaleFilters(numRemoved: *mut u32) -> WinFwPolicyStatus;
     }
 }
@@ -14,6 +14,9 @@ pub mod window;
 
 mod offline;
 
+#[cfg(not(target_os = "android"))]
+mod link_monitor;
+
 /// Split tunneling
 pub mod split_tunnel;
 
@@ -0,0 +1,102 @@
+use super::{sleep::SleepMonitor, LinkEvent};
+use crate::offline::PUBLIC_INTERNET_ADDRESS_V4;
+use futures::{channel::mpsc::UnboundedSender, StreamExt};
+use talpid_routing::RouteManagerHandle;
+use talpid_types::ErrorExt;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("The route manager returned an error")]
+    RouteManagerError(#[source] talpid_routing::Error),
+}
+
+pub struct MonitorHandle {
+    _sleep_monitor: SleepMonitor,
+    interface_monitor_task: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for MonitorHandle {
+    fn drop(&mut self) {
+        self.interface_monitor_task.abort();
+    }
+}
+
+pub async fn spawn_monitor(
+    sender: UnboundedSender<LinkEvent>,
+    route_manager: RouteManagerHandle,
+    fwmark: Option<u32>,
+) -> Result<MonitorHandle, Error> {
+    let mut interface = default_interface(&route_manager, fwmark).await;
+
+    let mut listener = route_manager
+        .change_listener()
+        .await
+        .map_err(Error::RouteManagerError)?;
+
+    let interface_sender = sender.clone();
+    let interface_monitor_task = tokio::spawn(async move {
+        while let Some(_event) = listener.next().await {
+            let Some(new_interface) = default_interface(&route_manager, fwmark).await else {
+                // Losing the route entirely is handled by the offline monitor. Keep the last
+                // interface so that coming back on another one is still noticed.
+                continue;
+            };
+            let changed = interface
+                .as_ref()
+                .is_some_and(|interface| *interface != new_interface);
+            if changed {
+                log::info!("Default interface changed to {new_interface}");
+                if interface_sender
+                    .unbounded_send(LinkEvent::InterfaceChanged)
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            interface = Some(new_interface);
+        }
+    });
+
+    Ok(MonitorHandle {
+        _sleep_monitor: SleepMonitor::spawn(sender),
+        interface_monitor_task,
+    })
+}
+
+/// Returns the interface that traffic to the internet is routed through outside the tunnel.
+async fn default_interface(
+    route_manager: &RouteManagerHandle,
+    fwmark: Option<u32>,
+) -> Option<String> {
+    match route_manager
+        .get_destination_route(PUBLIC_INTERNET_ADDRESS_V4, fwmark)
+        .await
+    {
+        Ok(route) => route.and_then(|route| route.get_node().get_device().map(str::to_owned)),
+        Err(error) => {
+            log::trace!(
+                "{}",
+                error.display_chain_with_msg("Failed to get route to the internet")
+            );
+            None
+        }
+    }
+}
+
+/// Identifies the network by the interface and gateway of the route to the internet.
+pub async fn current_network(
+    route_manager: &RouteManagerHandle,
+    fwmark: Option<u32>,
+) -> Option<String> {
+    let route = route_manager
+        .get_destination_route(PUBLIC_INTERNET_ADDRESS_V4, fwmark)
+        .await
+        .ok()??;
+    let node = route.get_node();
+    let device = node.get_device()?;
+    // Gateways commonly have the same address on different networks, so the interface is included
+    match node.get_address() {
+        Some(gateway) => Some(format!("{device} via {gateway}")),
+        None => Some(device.to_owned()),
+    }
+}
@@ -0,0 +1,24 @@
+//! Only resuming from sleep is detected here. The offline monitor already synthesizes an offline
+//! state when the default route changes, which restarts the tunnel on network switches.
+
+use super::{sleep::SleepMonitor, LinkEvent};
+use futures::channel::mpsc::UnboundedSender;
+use std::convert::Infallible;
+use talpid_routing::RouteManagerHandle;
+
+pub type MonitorHandle = SleepMonitor;
+
+#[allow(clippy::unused_async)]
+pub async fn spawn_monitor(
+    sender: UnboundedSender<LinkEvent>,
+) -> Result<MonitorHandle, Infallible> {
+    Ok(SleepMonitor::spawn(sender))
+}
+
+/// Identifies the network by the hardware address of its gateway.
+pub async fn current_network(route_manager: &RouteManagerHandle) -> Option<String> {
+    let (v4_gateway, v6_gateway) = route_manager.get_default_gateway().await.ok()?;
+    v4_gateway
+        .or(v6_gateway)
+        .map(|gateway| gateway.mac_address.to_string())
+}
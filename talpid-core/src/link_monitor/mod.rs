@@ -0,0 +1,97 @@
+//! Detects events after which an established tunnel has most likely stopped working, even though
+//! the host is still online. This happens when the host resumes from sleep, since the relay will
+//! have forgotten about the session by then, or when traffic to the internet starts going through
+//! a different network interface, since the source address of the tunnel traffic changes. Unless
+//! the tunnel is restarted right away, it takes until keepalives time out for this to be noticed.
+//!
+//! Changes that cause the host to go offline in between are left to the offline monitor.
+
+use futures::channel::mpsc::UnboundedSender;
+use std::fmt;
+use talpid_routing::RouteManagerHandle;
+use talpid_types::ErrorExt;
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod sleep;
+
+#[cfg(target_os = "macos")]
+#[path = "macos.rs"]
+mod imp;
+
+#[cfg(target_os = "windows")]
+#[path = "windows.rs"]
+mod imp;
+
+#[cfg(target_os = "linux")]
+#[path = "linux.rs"]
+mod imp;
+
+/// Event that is likely to have broken an established tunnel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkEvent {
+    /// The host resumed from sleep.
+    Resumed,
+    /// Traffic to the internet is routed through a different network interface than before.
+    InterfaceChanged,
+}
+
+impl fmt::Display for LinkEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkEvent::Resumed => f.write_str("the host resumed from sleep"),
+            LinkEvent::InterfaceChanged => f.write_str("the default network interface changed"),
+        }
+    }
+}
+
+pub struct MonitorHandle(Option<imp::MonitorHandle>);
+
+impl MonitorHandle {
+    /// Returns a handle that does not monitor anything.
+    #[cfg(all(
+        feature = "test-harness",
+        any(target_os = "linux", target_os = "macos")
+    ))]
+    pub fn disabled() -> Self {
+        MonitorHandle(None)
+    }
+}
+
+pub async fn spawn_monitor(
+    sender: UnboundedSender<LinkEvent>,
+    #[cfg(any(target_os = "linux", target_os = "windows"))] route_manager: RouteManagerHandle,
+    #[cfg(target_os = "linux")] fwmark: Option<u32>,
+) -> MonitorHandle {
+    let monitor = imp::spawn_monitor(
+        sender,
+        #[cfg(any(target_os = "linux", target_os = "windows"))]
+        route_manager,
+        #[cfg(target_os = "linux")]
+        fwmark,
+    )
+    .await
+    .inspect_err(|error| {
+        log::warn!(
+            "{}",
+            error.display_chain_with_msg("Failed to spawn link monitor")
+        );
+    })
+    .ok();
+
+    MonitorHandle(monitor)
+}
+
+/// Identifies the network that traffic to the internet is routed through outside the tunnel, by
+/// the interface and gateway of the route that it takes. The identifier is only meant to be
+/// compared with earlier identifiers on the same host. Returns `None` if there is no such route.
+pub async fn current_network(
+    route_manager: &RouteManagerHandle,
+    #[cfg(target_os = "linux")] fwmark: Option<u32>,
+) -> Option<String> {
+    imp::current_network(
+        route_manager,
+        #[cfg(target_os = "linux")]
+        fwmark,
+    )
+    .await
+}
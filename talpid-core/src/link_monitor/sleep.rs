@@ -0,0 +1,50 @@
+//! Detects that the host has been asleep. The monotonic clock does not advance while the host is
+//! asleep, but the wall clock does, so the two drift apart by roughly the time spent asleep.
+
+use super::LinkEvent;
+use futures::channel::mpsc::UnboundedSender;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often to compare the clocks. This bounds how long it takes to notice that the host has
+/// resumed.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How much further the wall clock must have advanced than the monotonic clock between two polls
+/// for the host to be considered to have been asleep. This leaves room for the wall clock being
+/// adjusted slightly.
+const SLEEP_THRESHOLD: Duration = Duration::from_secs(5);
+
+pub struct SleepMonitor {
+    monitor_task: tokio::task::JoinHandle<()>,
+}
+
+impl Drop for SleepMonitor {
+    fn drop(&mut self) {
+        self.monitor_task.abort();
+    }
+}
+
+impl SleepMonitor {
+    pub fn spawn(sender: UnboundedSender<LinkEvent>) -> Self {
+        let monitor_task = tokio::spawn(async move {
+            loop {
+                let monotonic_start = Instant::now();
+                let wall_start = SystemTime::now();
+                tokio::time::sleep(POLL_INTERVAL).await;
+
+                // The wall clock may also have been set back, in which case there is nothing to
+                // compare.
+                let Ok(wall_elapsed) = wall_start.elapsed() else {
+                    continue;
+                };
+                let asleep_for = wall_elapsed.saturating_sub(monotonic_start.elapsed());
+                if asleep_for > SLEEP_THRESHOLD {
+                    log::info!("Host resumed after sleeping for {}s", asleep_for.as_secs());
+                    if sender.unbounded_send(LinkEvent::Resumed).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Self { monitor_task }
+    }
+}
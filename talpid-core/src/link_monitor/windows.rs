@@ -0,0 +1,64 @@
+//! Only changes of the default interface are detected here. The offline monitor already treats
+//! the host as offline while it is suspended, which restarts the tunnel once it has resumed.
+
+use super::LinkEvent;
+use futures::channel::mpsc::UnboundedSender;
+use parking_lot::Mutex;
+use talpid_routing::{get_best_default_route, CallbackHandle, EventType, RouteManagerHandle};
+use talpid_types::ErrorExt;
+use talpid_windows::net::AddressFamily;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Failed to register default route callback")]
+    RegisterCallback(#[source] talpid_routing::Error),
+}
+
+pub type MonitorHandle = CallbackHandle;
+
+pub async fn spawn_monitor(
+    sender: UnboundedSender<LinkEvent>,
+    route_manager: RouteManagerHandle,
+) -> Result<MonitorHandle, Error> {
+    let initial_interface = match get_best_default_route(AddressFamily::Ipv4) {
+        // SAFETY: The underlying type of both union fields is an u64
+        Ok(route) => route.map(|route| unsafe { route.iface.Value }),
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to get initial default interface")
+            );
+            None
+        }
+    };
+    let interface = Mutex::new(initial_interface);
+
+    route_manager
+        .add_default_route_change_callback(Box::new(move |event, family| {
+            if !matches!(family, AddressFamily::Ipv4) {
+                return;
+            }
+            // Losing the route entirely is handled by the offline monitor. The last interface is
+            // kept so that coming back on another one is still noticed.
+            let EventType::Updated(route) = event else {
+                return;
+            };
+            // SAFETY: The underlying type of both union fields is an u64
+            let new_interface = unsafe { route.iface.Value };
+            let old_interface = interface.lock().replace(new_interface);
+            if old_interface.is_some_and(|old_interface| old_interface != new_interface) {
+                log::info!("Default interface changed");
+                let _ = sender.unbounded_send(LinkEvent::InterfaceChanged);
+            }
+        }))
+        .await
+        .map_err(Error::RegisterCallback)
+}
+
+#[allow(clippy::unused_async)]
+pub async fn current_network(_route_manager: &RouteManagerHandle) -> Option<String> {
+    let route = get_best_default_route(AddressFamily::Ipv4).ok()??;
+    // SAFETY: The underlying type of both union fields is an u64
+    let interface = unsafe { route.iface.Value };
+    Some(format!("{interface:x} via {}", route.gateway.ip()))
+}
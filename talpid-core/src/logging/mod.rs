@@ -1,5 +1,9 @@
 use std::{fs, io, path::Path};
 
+mod rotation;
+
+pub use rotation::{set_rotation_policy, RotatingLogFile, RotationPolicy};
+
 /// Unable to create new log file
 #[derive(thiserror::Error, Debug)]
 #[error("Unable to create new log file")]
@@ -8,17 +12,11 @@ pub struct RotateLogError(#[from] io::Error);
 /// Create a new log file while backing up a previous version of it.
 ///
 /// A new log file is created with the given file name, but if a file with that name already exists
-/// it is backed up with the extension changed to `.old.log`.
+/// it is backed up with the extension changed to `.old.log`. Older backups are archived according
+/// to the [`RotationPolicy`] set by [`set_rotation_policy`].
 pub fn rotate_log(file: &Path) -> Result<(), RotateLogError> {
-    let backup = file.with_extension("old.log");
-    if let Err(error) = fs::rename(file, &backup) {
-        if error.kind() != io::ErrorKind::NotFound {
-            log::warn!(
-                "Failed to rotate log file to {}: {}",
-                backup.display(),
-                error
-            );
-        }
+    if let Err(error) = rotation::move_to_backup(file, rotation::rotation_policy()) {
+        log::warn!("Failed to rotate log file {}: {}", file.display(), error);
     }
 
     fs::File::create(file).map(|_| ()).map_err(RotateLogError)
@@ -0,0 +1,307 @@
+//! Rotation and retention of log files. When a log is rotated, the current file becomes the
+//! `.old.log` backup, and the previous backup is archived next to it with a timestamp in its name.
+//! Archives are compressed and deleted according to a [`RotationPolicy`] in a background thread,
+//! so that rotating never blocks logging for long.
+//!
+//! Archives do not have the `.log` extension, so they are not included in problem reports.
+
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Extension added to archives when they are compressed.
+const COMPRESSED_EXTENSION: &str = "gz";
+
+/// Format of the timestamp in the name of archives. It sorts chronologically.
+const ARCHIVE_TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%.3f";
+
+const DEFAULT_POLICY: RotationPolicy = RotationPolicy {
+    max_size: Some(20 * 1024 * 1024),
+    max_age: None,
+    keep: 5,
+    keep_for: None,
+    compress: true,
+};
+
+/// Policy used by [`super::rotate_log`].
+static POLICY: Mutex<RotationPolicy> = Mutex::new(DEFAULT_POLICY);
+
+/// Held while archives are compressed and deleted, so that two rotations do not work on the same
+/// files at once.
+static ARCHIVE_LOCK: Mutex<()> = Mutex::new(());
+
+/// When logs are rotated, and how many of them are kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RotationPolicy {
+    /// Rotate a log once it has grown to this many bytes.
+    pub max_size: Option<u64>,
+    /// Rotate a log once it has been written to for this long.
+    pub max_age: Option<Duration>,
+    /// Number of archives to keep of each log, besides the `.old.log` backup.
+    pub keep: usize,
+    /// Delete archives that are older than this.
+    pub keep_for: Option<Duration>,
+    /// Compress archives with gzip.
+    pub compress: bool,
+}
+
+impl Default for RotationPolicy {
+    fn default() -> Self {
+        DEFAULT_POLICY
+    }
+}
+
+/// Set the policy used to rotate logs from now on.
+pub fn set_rotation_policy(policy: RotationPolicy) {
+    *POLICY.lock().unwrap() = policy;
+}
+
+pub(super) fn rotation_policy() -> RotationPolicy {
+    *POLICY.lock().unwrap()
+}
+
+/// A log file that is rotated when it grows too large or too old. It is only rotated at the start
+/// of a line, so lines are never split between two files.
+pub struct RotatingLogFile {
+    path: PathBuf,
+    file: BufWriter<fs::File>,
+    size: u64,
+    opened_at: Instant,
+    at_line_start: bool,
+    policy: RotationPolicy,
+}
+
+impl RotatingLogFile {
+    /// Open the log at `path` for appending, creating it if it does not exist.
+    pub fn open(path: &Path, policy: RotationPolicy) -> io::Result<Self> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_owned(),
+            file: BufWriter::new(file),
+            size,
+            opened_at: Instant::now(),
+            at_line_start: true,
+            policy,
+        })
+    }
+
+    fn rotate_if_needed(&mut self) -> io::Result<()> {
+        let too_large = self.policy.max_size.is_some_and(|max| self.size >= max);
+        let too_old = self
+            .policy
+            .max_age
+            .is_some_and(|max| self.opened_at.elapsed() >= max);
+        if !self.at_line_start || (!too_large && !too_old) {
+            return Ok(());
+        }
+        self.file.flush()?;
+        move_to_backup(&self.path, self.policy)?;
+        self.file = BufWriter::new(fs::File::create(&self.path)?);
+        self.size = 0;
+        self.opened_at = Instant::now();
+        Ok(())
+    }
+}
+
+impl Write for RotatingLogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.rotate_if_needed()?;
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        if written > 0 {
+            self.at_line_start = buf[..written].ends_with(b"\n");
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Move `file` to its `.old.log` backup, archiving the previous backup. The archives are then
+/// compressed and pruned in the background.
+///
+/// This does not log anything, since it is called by the logger itself.
+pub(super) fn move_to_backup(file: &Path, policy: RotationPolicy) -> io::Result<()> {
+    let backup = file.with_extension("old.log");
+    if policy.keep > 0 {
+        let timestamp = chrono::Utc::now().format(ARCHIVE_TIMESTAMP_FORMAT);
+        let archive = append_extension(file, &timestamp.to_string());
+        ignore_not_found(fs::rename(&backup, archive))?;
+    }
+    ignore_not_found(fs::rename(file, &backup))?;
+
+    let file = file.to_owned();
+    std::thread::spawn(move || maintain_archives(&file, policy));
+    Ok(())
+}
+
+/// Compress the archives of `file` and delete those that should not be kept.
+fn maintain_archives(file: &Path, policy: RotationPolicy) {
+    let _guard = ARCHIVE_LOCK.lock().unwrap();
+
+    let mut archives = match list_archives(file) {
+        Ok(archives) => archives,
+        Err(error) => {
+            log::warn!(
+                "Failed to list archived logs of {}: {error}",
+                file.display()
+            );
+            return;
+        }
+    };
+
+    for path in archives_to_delete(&archives, policy, SystemTime::now()) {
+        if let Err(error) = fs::remove_file(&path) {
+            log::warn!("Failed to delete archived log {}: {error}", path.display());
+        }
+    }
+    archives.retain(|archive| archive.path.exists());
+
+    if policy.compress {
+        for archive in archives.iter().filter(|archive| !archive.is_compressed()) {
+            if let Err(error) = compress(&archive.path) {
+                log::warn!(
+                    "Failed to compress archived log {}: {error}",
+                    archive.path.display()
+                );
+            }
+        }
+    }
+}
+
+struct Archive {
+    path: PathBuf,
+    modified: SystemTime,
+}
+
+impl Archive {
+    fn is_compressed(&self) -> bool {
+        self.path.extension() == Some(COMPRESSED_EXTENSION.as_ref())
+    }
+}
+
+/// Return the archives of `file`, oldest first.
+fn list_archives(file: &Path) -> io::Result<Vec<Archive>> {
+    let (Some(dir), Some(file_name)) = (file.parent(), file.file_name()) else {
+        return Ok(vec![]);
+    };
+    let prefix = format!("{}.", file_name.to_string_lossy());
+
+    let mut archives = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let is_archive = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(&prefix))
+            .is_some_and(|timestamp| timestamp.starts_with(|c: char| c.is_ascii_digit()));
+        if is_archive {
+            archives.push(Archive {
+                path: entry.path(),
+                modified: entry.metadata()?.modified()?,
+            });
+        }
+    }
+    archives.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(archives)
+}
+
+/// Return the archives that exceed the number or age of archives to keep. `archives` must be
+/// sorted oldest first.
+fn archives_to_delete(
+    archives: &[Archive],
+    policy: RotationPolicy,
+    now: SystemTime,
+) -> Vec<PathBuf> {
+    let excess = archives.len().saturating_sub(policy.keep);
+    archives
+        .iter()
+        .enumerate()
+        .filter(|(index, archive)| {
+            let expired = policy.keep_for.is_some_and(|keep_for| {
+                now.duration_since(archive.modified)
+                    .is_ok_and(|age| age > keep_for)
+            });
+            *index < excess || expired
+        })
+        .map(|(_, archive)| archive.path.clone())
+        .collect()
+}
+
+fn compress(path: &Path) -> io::Result<()> {
+    let compressed_path = append_extension(path, COMPRESSED_EXTENSION);
+    let mut input = fs::File::open(path)?;
+    let output = BufWriter::new(fs::File::create(&compressed_path)?);
+    let mut encoder = libflate::gzip::Encoder::new(output)?;
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish().into_result()?.flush()?;
+    fs::remove_file(path)
+}
+
+fn append_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+fn ignore_not_found(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_archives_to_delete() {
+        let now = SystemTime::now();
+        let days = |days: u64| Duration::from_secs(days * 24 * 60 * 60);
+        let archives: Vec<_> = [10, 5, 2, 1]
+            .into_iter()
+            .enumerate()
+            .map(|(index, age)| Archive {
+                path: PathBuf::from(format!("daemon.log.{index}")),
+                modified: now - days(age),
+            })
+            .collect();
+        let policy = RotationPolicy {
+            keep: 3,
+            keep_for: None,
+            ..RotationPolicy::default()
+        };
+
+        assert_eq!(
+            archives_to_delete(&archives, policy, now),
+            [PathBuf::from("daemon.log.0")]
+        );
+        assert_eq!(
+            archives_to_delete(
+                &archives,
+                RotationPolicy {
+                    keep_for: Some(days(3)),
+                    ..policy
+                },
+                now
+            ),
+            [PathBuf::from("daemon.log.0"), PathBuf::from("daemon.log.1")]
+        );
+        assert_eq!(
+            archives_to_delete(&archives, RotationPolicy { keep: 0, ..policy }, now).len(),
+            4
+        );
+    }
+}
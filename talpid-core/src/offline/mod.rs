@@ -23,6 +23,9 @@ mod imp;
 #[path = "android.rs"]
 mod imp;
 
+#[cfg(target_os = "linux")]
+pub(crate) use imp::PUBLIC_INTERNET_ADDRESS_V4;
+
 /// Disables offline monitor
 static FORCE_DISABLE_OFFLINE_MONITOR: Lazy<bool> = Lazy::new(|| {
     std::env::var("TALPID_DISABLE_OFFLINE_MONITOR")
@@ -33,6 +36,15 @@ static FORCE_DISABLE_OFFLINE_MONITOR: Lazy<bool> = Lazy::new(|| {
 pub struct MonitorHandle(Option<imp::MonitorHandle>);
 
 impl MonitorHandle {
+    /// Returns a handle that does not monitor anything. The host is presumed to be online.
+    #[cfg(all(
+        feature = "test-harness",
+        any(target_os = "linux", target_os = "macos")
+    ))]
+    pub fn disabled() -> Self {
+        MonitorHandle(None)
+    }
+
     pub async fn connectivity(&self) -> Connectivity {
         match self.0.as_ref() {
             Some(monitor) => monitor.connectivity().await,
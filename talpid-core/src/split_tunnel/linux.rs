@@ -9,7 +9,8 @@ const DEFAULT_NET_CLS_DIR: &str = "/sys/fs/cgroup/net_cls";
 const NET_CLS_DIR_OVERRIDE_ENV_VAR: &str = "TALPID_NET_CLS_MOUNT_DIR";
 
 /// Identifies packets coming from the cgroup.
-/// This should be an arbitrary but unique integer.
+/// This should be an arbitrary but unique integer. It is shared by all daemon instances on the
+/// host, as is the cgroup.
 pub const NET_CLS_CLASSID: u32 = 0x4d9f41;
 /// Value used to mark packets and associated connections.
 /// This should be an arbitrary but unique integer.
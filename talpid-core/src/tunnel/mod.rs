@@ -1,14 +1,23 @@
 use crate::logging;
 #[cfg(not(target_os = "android"))]
 use futures::channel::oneshot;
-use std::path;
+use std::{path, sync::Mutex};
 #[cfg(not(target_os = "android"))]
 use talpid_routing::RouteManagerHandle;
+#[cfg(not(target_os = "android"))]
+use talpid_tunnel::ConnectTracer;
 pub use talpid_tunnel::{TunnelArgs, TunnelEvent, TunnelMetadata};
 #[cfg(not(target_os = "android"))]
 use talpid_types::net::openvpn as openvpn_types;
 use talpid_types::net::{wireguard as wireguard_types, TunnelParameters};
 
+pub use provider::{
+    DefaultTunnelProvider, RunningTunnel, TunnelEventCallback, TunnelProvider, TunnelProviderArgs,
+    TunnelStats,
+};
+
+mod provider;
+
 const OPENVPN_LOG_FILENAME: &str = "openvpn.log";
 const WIREGUARD_LOG_FILENAME: &str = "wireguard.log";
 
@@ -54,6 +63,14 @@ pub enum Error {
     /// Could not detect and assign the correct mtu
     #[error("Could not detect and assign a correct MTU for the Wireguard tunnel")]
     AssignMtuError,
+
+    /// The running tunnel can't apply the new parameters, so a new tunnel has to be started.
+    #[error("The tunnel cannot be reconfigured while it is running")]
+    ReconfigureUnsupported,
+
+    /// An error from a tunnel provider other than the built-in ones.
+    #[error("Tunnel provider error")]
+    ProviderError(#[source] Box<dyn std::error::Error + Send + Sync>),
 }
 
 impl Error {
@@ -80,7 +97,10 @@ impl Error {
 
 /// Abstraction for monitoring a generic VPN tunnel.
 pub struct TunnelMonitor {
-    monitor: InternalTunnelMonitor,
+    /// Taken by [`TunnelMonitor::wait`].
+    monitor: Mutex<Option<InternalTunnelMonitor>>,
+    /// Handle to the WireGuard tunnel, which stays usable while waiting for the tunnel to exit.
+    wireguard: Option<talpid_wireguard::TunnelHandle>,
 }
 
 // TODO(emilsp) move most of the openvpn tunnel details to OpenVpnTunnelMonitor
@@ -112,6 +132,7 @@ impl TunnelMonitor {
                 args.on_event,
                 args.tunnel_close_rx,
                 args.route_manager,
+                args.connect_trace,
             )),
             #[cfg(target_os = "android")]
             TunnelParameters::OpenVpn(_) => Err(Error::UnsupportedPlatform),
@@ -134,9 +155,14 @@ impl TunnelMonitor {
         let resource_dir = resource_dir.to_path_buf();
         match params {
             TunnelParameters::OpenVpn(params) => match &params.proxy {
-                Some(CustomProxy::Shadowsocks(_)) => Some(std::env::current_exe().unwrap()),
-                Some(CustomProxy::Socks5Local(_)) => None,
-                Some(CustomProxy::Socks5Remote(_)) | None => Some(resource_dir.join("openvpn.exe")),
+                Some(chain) => match chain.first_hop() {
+                    CustomProxy::Socks5Local(_) => None,
+                    // The hops are reached from the daemon's own proxy when chaining.
+                    _ if chain.is_chained() => Some(std::env::current_exe().unwrap()),
+                    CustomProxy::Shadowsocks(_) => Some(std::env::current_exe().unwrap()),
+                    CustomProxy::Socks5Remote(_) => Some(resource_dir.join("openvpn.exe")),
+                },
+                None => Some(resource_dir.join("openvpn.exe")),
             },
             _ => Some(std::env::current_exe().unwrap()),
         }
@@ -183,7 +209,8 @@ impl TunnelMonitor {
             args,
         )?;
         Ok(TunnelMonitor {
-            monitor: InternalTunnelMonitor::Wireguard(monitor),
+            wireguard: Some(monitor.handle()),
+            monitor: Mutex::new(Some(InternalTunnelMonitor::Wireguard(monitor))),
         })
     }
 
@@ -227,6 +254,7 @@ impl TunnelMonitor {
         on_event: L,
         tunnel_close_rx: oneshot::Receiver<()>,
         route_manager: RouteManagerHandle,
+        connect_trace: ConnectTracer,
     ) -> Result<Self>
     where
         L: (Fn(TunnelEvent) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>>)
@@ -240,6 +268,7 @@ impl TunnelMonitor {
             log,
             resource_dir,
             route_manager,
+            connect_trace,
         )
         .await?;
 
@@ -251,7 +280,8 @@ impl TunnelMonitor {
         });
 
         Ok(TunnelMonitor {
-            monitor: InternalTunnelMonitor::OpenVpn(monitor),
+            monitor: Mutex::new(Some(InternalTunnelMonitor::OpenVpn(monitor))),
+            wireguard: None,
         })
     }
 
@@ -305,9 +335,47 @@ impl TunnelMonitor {
         }
     }
 
-    /// Consumes the monitor and blocks until the tunnel exits or there is an error.
-    pub fn wait(self) -> Result<()> {
-        self.monitor.wait().map_err(Error::from)
+    /// Returns the amount of traffic that has passed through the tunnel. Only WireGuard tunnels
+    /// keep track of this.
+    pub fn stats(&self) -> Result<Option<TunnelStats>> {
+        let Some(tunnel) = &self.wireguard else {
+            return Ok(None);
+        };
+        let stats = tunnel
+            .stats()?
+            .into_values()
+            .fold(TunnelStats::default(), |total, peer| TunnelStats {
+                tx_bytes: total.tx_bytes + peer.tx_bytes,
+                rx_bytes: total.rx_bytes + peer.rx_bytes,
+            });
+        Ok(Some(stats))
+    }
+
+    /// Apply `parameters` to the running tunnel. Only the private key of a WireGuard tunnel can be
+    /// changed this way.
+    pub fn reconfigure(&self, parameters: &TunnelParameters) -> Result<()> {
+        match (&self.wireguard, parameters) {
+            (Some(tunnel), TunnelParameters::Wireguard(parameters)) => {
+                tunnel.reconfigure(parameters).map_err(|error| match error {
+                    talpid_wireguard::Error::ReconfigureUnsupported => {
+                        Error::ReconfigureUnsupported
+                    }
+                    error => Error::from(error),
+                })
+            }
+            _ => Err(Error::ReconfigureUnsupported),
+        }
+    }
+
+    /// Blocks until the tunnel exits or there is an error. Must only be called once.
+    pub fn wait(&self) -> Result<()> {
+        let monitor = self
+            .monitor
+            .lock()
+            .unwrap()
+            .take()
+            .expect("Tunnel monitor has already been waited for");
+        monitor.wait().map_err(Error::from)
     }
 }
 
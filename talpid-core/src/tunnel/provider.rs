@@ -0,0 +1,145 @@
+//! Extension point for the backends that the tunnel state machine starts tunnels with.
+//!
+//! The state machine takes care of the firewall, DNS and routing for every tunnel. A backend only
+//! has to bring the tunnel up, report [`TunnelEvent`]s as it goes and tear the tunnel down when
+//! asked to. Implementing [`TunnelProvider`] makes it possible to try out a new backend without
+//! changing the rest of the crate.
+
+use super::{Error, Result, TunnelMonitor};
+use futures::{channel::oneshot, future::BoxFuture};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use talpid_routing::RouteManagerHandle;
+use talpid_tunnel::{tun_provider::TunProvider, ConnectTracer, TunnelArgs, TunnelEvent};
+use talpid_types::net::TunnelParameters;
+
+/// Callback used to report tunnel events to the state machine. The returned future completes once
+/// the event has been handled, e.g. once the firewall has been updated for a new tunnel interface.
+pub type TunnelEventCallback = Arc<dyn Fn(TunnelEvent) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// Arguments passed to [`TunnelProvider::spawn`].
+pub struct TunnelProviderArgs<'a> {
+    /// Tokio runtime handle.
+    pub runtime: tokio::runtime::Handle,
+    /// Resource directory path.
+    pub resource_dir: &'a Path,
+    /// Directory to store the tunnel log file in, if any.
+    pub log_dir: &'a Option<PathBuf>,
+    /// Callback function called when an event happens.
+    pub on_event: TunnelEventCallback,
+    /// Resolves when the tunnel should be closed.
+    pub tunnel_close_rx: oneshot::Receiver<()>,
+    /// Provider of tunnel devices.
+    pub tun_provider: Arc<Mutex<TunProvider>>,
+    /// Connection retry attempts.
+    pub retry_attempt: u32,
+    /// Route manager handle.
+    pub route_manager: RouteManagerHandle,
+    /// Records how long each phase of establishing the tunnel takes.
+    pub connect_trace: ConnectTracer,
+}
+
+impl<'a> TunnelProviderArgs<'a> {
+    /// Convert the arguments into the ones taken by the built-in tunnel monitors.
+    pub fn into_tunnel_args(
+        self,
+    ) -> TunnelArgs<
+        'a,
+        impl Fn(TunnelEvent) -> BoxFuture<'static, ()> + Send + Sync + Clone + 'static,
+    > {
+        let on_event = self.on_event;
+        TunnelArgs {
+            runtime: self.runtime,
+            resource_dir: self.resource_dir,
+            on_event: move |event: TunnelEvent| on_event(event),
+            tunnel_close_rx: self.tunnel_close_rx,
+            tun_provider: self.tun_provider,
+            retry_attempt: self.retry_attempt,
+            route_manager: self.route_manager,
+            connect_trace: self.connect_trace,
+        }
+    }
+}
+
+/// A backend that can start VPN tunnels.
+pub trait TunnelProvider: Send + Sync {
+    /// Start a tunnel using `parameters`. This may block until the tunnel has been started, but
+    /// should not wait for it to become connected. Progress is reported through
+    /// [`TunnelProviderArgs::on_event`], and the tunnel must be closed once
+    /// [`TunnelProviderArgs::tunnel_close_rx`] resolves.
+    ///
+    /// The provider may update `parameters`, for example if it negotiates new keys.
+    fn spawn(
+        &self,
+        parameters: &mut TunnelParameters,
+        args: TunnelProviderArgs<'_>,
+    ) -> Result<Box<dyn RunningTunnel>>;
+
+    /// Return the path of the process that will send traffic to the relay, if any. The firewall
+    /// only lets that process reach the relay while the tunnel is being set up.
+    #[cfg(windows)]
+    fn relay_client(&self, resource_dir: &Path, parameters: &TunnelParameters) -> Option<PathBuf> {
+        TunnelMonitor::get_relay_client(resource_dir, parameters)
+    }
+}
+
+/// Total amount of traffic sent and received through a tunnel.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TunnelStats {
+    /// Number of bytes sent through the tunnel.
+    pub tx_bytes: u64,
+    /// Number of bytes received through the tunnel.
+    pub rx_bytes: u64,
+}
+
+/// A tunnel started by a [`TunnelProvider`].
+pub trait RunningTunnel: Send + Sync {
+    /// Return the amount of traffic that has passed through the tunnel, if the provider keeps
+    /// track of it.
+    fn stats(&self) -> Result<Option<TunnelStats>> {
+        Ok(None)
+    }
+
+    /// Apply new parameters to the running tunnel without restarting it. Providers that can't do
+    /// this return [`Error::ReconfigureUnsupported`].
+    fn reconfigure(&self, _parameters: &TunnelParameters) -> Result<()> {
+        Err(Error::ReconfigureUnsupported)
+    }
+
+    /// Block until the tunnel has been closed or has failed. This is called once, on a separate
+    /// thread, while [`RunningTunnel::stats`] and [`RunningTunnel::reconfigure`] may still be
+    /// called from the state machine.
+    fn wait(&self) -> Result<()>;
+}
+
+/// Provider of the built-in OpenVPN and WireGuard tunnels.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DefaultTunnelProvider;
+
+impl TunnelProvider for DefaultTunnelProvider {
+    fn spawn(
+        &self,
+        parameters: &mut TunnelParameters,
+        args: TunnelProviderArgs<'_>,
+    ) -> Result<Box<dyn RunningTunnel>> {
+        let log_dir = args.log_dir;
+        let monitor = TunnelMonitor::start(parameters, log_dir, args.into_tunnel_args())?;
+        Ok(Box::new(monitor))
+    }
+}
+
+impl RunningTunnel for TunnelMonitor {
+    fn stats(&self) -> Result<Option<TunnelStats>> {
+        TunnelMonitor::stats(self)
+    }
+
+    fn reconfigure(&self, parameters: &TunnelParameters) -> Result<()> {
+        TunnelMonitor::reconfigure(self, parameters)
+    }
+
+    fn wait(&self) -> Result<()> {
+        TunnelMonitor::wait(self)
+    }
+}
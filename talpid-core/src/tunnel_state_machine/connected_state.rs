@@ -4,6 +4,7 @@ use super::{
     TunnelStateTransition,
 };
 use crate::{
+    dns,
     firewall::FirewallPolicy,
     tunnel::{TunnelEvent, TunnelMetadata},
 };
@@ -13,16 +14,16 @@ use futures::{
     StreamExt,
 };
 use std::net::IpAddr;
+use talpid_tunnel::ConnectTracer;
 use talpid_types::{
-    net::{AllowedClients, AllowedEndpoint, TunnelParameters},
-    tunnel::{ErrorStateCause, FirewallPolicyError},
-    BoxedError, ErrorExt,
+    net::{
+        wireguard::PrivateKey, AllowedClients, AllowedEndpoint, TunnelEndpoint, TunnelParameters,
+    },
+    tunnel::{ConnectPhase, ConnectTrace, ErrorDetails, ErrorStateCause, FirewallPolicyError},
+    ErrorExt,
 };
 
-#[cfg(windows)]
-use crate::tunnel::TunnelMonitor;
-
-use super::connecting_state::TunnelCloseEvent;
+use super::connecting_state::{SharedTunnel, TunnelCloseEvent};
 
 pub(crate) type TunnelEventsReceiver =
     Fuse<mpsc::UnboundedReceiver<(TunnelEvent, oneshot::Sender<()>)>>;
@@ -34,6 +35,8 @@ pub struct ConnectedState {
     tunnel_parameters: TunnelParameters,
     tunnel_close_event: TunnelCloseEvent,
     tunnel_close_tx: oneshot::Sender<()>,
+    tunnel: SharedTunnel,
+    connect_trace: ConnectTrace,
 }
 
 impl ConnectedState {
@@ -45,39 +48,63 @@ impl ConnectedState {
         tunnel_parameters: TunnelParameters,
         tunnel_close_event: TunnelCloseEvent,
         tunnel_close_tx: oneshot::Sender<()>,
+        tunnel: SharedTunnel,
+        connect_trace: ConnectTracer,
     ) -> (Box<dyn TunnelState>, TunnelStateTransition) {
-        let connected_state = ConnectedState {
+        let mut connected_state = ConnectedState {
             metadata,
             tunnel_events,
             tunnel_parameters,
             tunnel_close_event,
             tunnel_close_tx,
+            tunnel,
+            connect_trace: ConnectTrace::default(),
         };
 
-        let tunnel_interface = Some(connected_state.metadata.interface.clone());
-        let tunnel_endpoint = talpid_types::net::TunnelEndpoint {
-            tunnel_interface,
-            ..connected_state.tunnel_parameters.get_tunnel_endpoint()
-        };
+        let tunnel_endpoint = connected_state.tunnel_endpoint();
 
-        if let Err(error) = connected_state.set_firewall_policy(shared_values) {
-            DisconnectingState::enter(
+        connect_trace.start(ConnectPhase::Firewall);
+        let firewall_result = connected_state.set_firewall_policy(shared_values);
+        connect_trace.finish(ConnectPhase::Firewall);
+        if let Err(error) = firewall_result {
+            return DisconnectingState::enter(
                 connected_state.tunnel_close_tx,
                 connected_state.tunnel_close_event,
                 AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError(error)),
-            )
-        } else if let Err(error) = connected_state.set_dns(shared_values) {
-            log::error!("{}", error.display_chain_with_msg("Failed to set DNS"));
-            DisconnectingState::enter(
+            );
+        }
+
+        connect_trace.start(ConnectPhase::Dns);
+        let dns_result = connected_state.set_dns(shared_values);
+        connect_trace.finish(ConnectPhase::Dns);
+        if let Err(cause) = dns_result {
+            return DisconnectingState::enter(
                 connected_state.tunnel_close_tx,
                 connected_state.tunnel_close_event,
-                AfterDisconnect::Block(ErrorStateCause::SetDnsError),
-            )
-        } else {
-            (
-                Box::new(connected_state),
-                TunnelStateTransition::Connected(tunnel_endpoint),
-            )
+                AfterDisconnect::Block(cause),
+            );
+        }
+
+        connected_state.connect_trace = connect_trace.trace();
+        log::debug!("Connected after {:?}", connected_state.connect_trace.total);
+        let connect_trace = connected_state.connect_trace.clone();
+        (
+            Box::new(connected_state),
+            TunnelStateTransition::Connected(tunnel_endpoint, connect_trace),
+        )
+    }
+
+    fn tunnel_endpoint(&self) -> TunnelEndpoint {
+        let tunnel_interface = Some(self.metadata.interface.clone());
+        let ephemeral_peer_transport = match &self.tunnel_parameters {
+            TunnelParameters::Wireguard(params) => params.ephemeral_peer_transport(),
+            TunnelParameters::OpenVpn(_) => None,
+        };
+        TunnelEndpoint {
+            tunnel_interface,
+            ephemeral_peer_transport,
+            data_channel_offload: self.metadata.data_channel_offload,
+            ..self.tunnel_parameters.get_tunnel_endpoint()
         }
     }
 
@@ -96,13 +123,11 @@ impl ConnectedState {
                         "Failed to apply firewall policy for connected state"
                     )
                 );
-                #[cfg(windows)]
-                match error {
+                match error.into_apply_error() {
+                    #[cfg(windows)]
                     crate::firewall::Error::ApplyingConnectedPolicy(policy_error) => policy_error,
-                    _ => FirewallPolicyError::Generic,
+                    error => FirewallPolicyError::Generic(ErrorDetails::from_error(&error)),
                 }
-                #[cfg(not(windows))]
-                FirewallPolicyError::Generic
             })
     }
 
@@ -129,12 +154,41 @@ impl ConnectedState {
         }
     }
 
+    /// Apply a new private key to the running tunnel. Returns whether the tunnel uses it.
+    fn set_private_key(&mut self, private_key: PrivateKey) -> bool {
+        let TunnelParameters::Wireguard(ref params) = self.tunnel_parameters else {
+            return false;
+        };
+        let Some(tunnel) = self.tunnel.lock().unwrap().clone() else {
+            return false;
+        };
+        let mut params = params.clone();
+        params.connection.tunnel.private_key = private_key;
+        let params = TunnelParameters::Wireguard(params);
+        match tunnel.reconfigure(&params) {
+            Ok(()) => {
+                log::info!("Applied the new private key to the tunnel");
+                self.tunnel_parameters = params;
+                true
+            }
+            Err(error) => {
+                log::debug!(
+                    "{}",
+                    error.display_chain_with_msg("Cannot apply the new private key to the tunnel")
+                );
+                false
+            }
+        }
+    }
+
     fn get_firewall_policy(&self, shared_values: &SharedTunnelStateValues) -> FirewallPolicy {
         let endpoint = self.tunnel_parameters.get_next_hop_endpoint();
 
         #[cfg(target_os = "windows")]
         let clients = AllowedClients::from(
-            TunnelMonitor::get_relay_client(&shared_values.resource_dir, &self.tunnel_parameters)
+            shared_values
+                .tunnel_provider
+                .relay_client(&shared_values.resource_dir, &self.tunnel_parameters)
                 .into_iter()
                 .collect::<Vec<_>>(),
         );
@@ -157,20 +211,71 @@ impl ConnectedState {
             .runtime
             .block_on(shared_values.split_tunnel.interface());
 
+        #[cfg(not(target_os = "android"))]
+        let dns_servers = {
+            let mut dns_servers = self.get_dns_servers(shared_values);
+            if shared_values.dns_blocklist.is_some() {
+                dns_servers.push(crate::dns::filter::FILTER_ADDR.ip());
+            }
+            dns_servers
+        };
+
         FirewallPolicy::Connected {
             peer_endpoint,
             tunnel: self.metadata.clone(),
             allow_lan: shared_values.allow_lan,
             #[cfg(not(target_os = "android"))]
-            dns_servers: self.get_dns_servers(shared_values),
+            dns_servers,
             #[cfg(target_os = "macos")]
             redirect_interface,
+            #[cfg(target_os = "linux")]
+            tunnel_destinations: match &self.tunnel_parameters {
+                TunnelParameters::Wireguard(params) => params
+                    .connection
+                    .partial_tunnel_destinations()
+                    .map(<[_]>::to_vec),
+                TunnelParameters::OpenVpn(_) => None,
+            },
         }
     }
 
-    fn set_dns(&self, shared_values: &mut SharedTunnelStateValues) -> Result<(), BoxedError> {
+    /// Point the system at the DNS servers of the tunnel, or at the DNS filter if there is a
+    /// blocklist. Returns the reason to block if that fails.
+    fn set_dns(&self, shared_values: &mut SharedTunnelStateValues) -> Result<(), ErrorStateCause> {
         let dns_ips = self.get_dns_servers(shared_values);
 
+        // The filter forwards queries that it does not block to the actual servers
+        #[cfg(not(target_os = "android"))]
+        let filter_ip = shared_values.update_dns_filter(&dns_ips).map_err(|error| {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to start DNS filter")
+            );
+            ErrorStateCause::SetDnsError {
+                mechanism: None,
+                details: ErrorDetails::from_error(&error),
+            }
+        })?;
+        #[cfg(target_os = "android")]
+        let filter_ip = None;
+
+        let result = match filter_ip {
+            Some(filter_ip) => shared_values
+                .dns_monitor
+                .set(&self.metadata.interface, &[filter_ip]),
+            None => self.set_dns_servers(shared_values, dns_ips),
+        };
+        result.map_err(|error| {
+            log::error!("{}", error.display_chain_with_msg("Failed to set DNS"));
+            ErrorStateCause::from(&error)
+        })
+    }
+
+    fn set_dns_servers(
+        &self,
+        shared_values: &mut SharedTunnelStateValues,
+        dns_ips: Vec<IpAddr>,
+    ) -> Result<(), dns::Error> {
         #[cfg(any(target_os = "linux", target_os = "windows"))]
         let dns_ips = dns_ips
             .into_iter()
@@ -184,15 +289,14 @@ impl ConnectedState {
         shared_values
             .dns_monitor
             .set(&self.metadata.interface, &dns_ips)
-            .map_err(BoxedError::new)?;
-
-        Ok(())
     }
 
     fn reset_dns(shared_values: &mut SharedTunnelStateValues) {
         if let Err(error) = shared_values.dns_monitor.reset_before_interface_removal() {
             log::error!("{}", error.display_chain_with_msg("Unable to reset DNS"));
         }
+        #[cfg(not(target_os = "android"))]
+        shared_values.stop_dns_filter();
     }
 
     fn reset_routes(
@@ -230,7 +334,7 @@ impl ConnectedState {
     }
 
     fn handle_commands(
-        self: Box<Self>,
+        mut self: Box<Self>,
         command: Option<TunnelCommand>,
         shared_values: &mut SharedTunnelStateValues,
     ) -> EventConsequence {
@@ -280,15 +384,8 @@ impl ConnectedState {
                             Ok(()) => self.disconnect(shared_values, AfterDisconnect::Reconnect(0)),
                             #[cfg(not(target_os = "android"))]
                             Ok(()) => SameState(self),
-                            Err(error) => {
-                                log::error!(
-                                    "{}",
-                                    error.display_chain_with_msg("Failed to set DNS")
-                                );
-                                self.disconnect(
-                                    shared_values,
-                                    AfterDisconnect::Block(ErrorStateCause::SetDnsError),
-                                )
+                            Err(cause) => {
+                                self.disconnect(shared_values, AfterDisconnect::Block(cause))
                             }
                         }
                     }
@@ -300,6 +397,35 @@ impl ConnectedState {
                 let _ = complete_tx.send(());
                 consequence
             }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::DnsBlocklist(blocklist, complete_tx)) => {
+                let consequence = if !shared_values.set_dns_blocklist(blocklist) {
+                    SameState(self)
+                } else if let Err(error) = self.set_firewall_policy(shared_values) {
+                    self.disconnect(
+                        shared_values,
+                        AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError(error)),
+                    )
+                } else if let Err(cause) = self.set_dns(shared_values) {
+                    self.disconnect(shared_values, AfterDisconnect::Block(cause))
+                } else {
+                    SameState(self)
+                };
+                let _ = complete_tx.send(());
+                consequence
+            }
+            #[cfg(target_os = "linux")]
+            Some(TunnelCommand::BlockOnUnsupportedDnsManager(block, complete_tx)) => {
+                let consequence = if !shared_values.set_block_on_unsupported_dns_manager(block) {
+                    SameState(self)
+                } else if let Err(cause) = self.set_dns(shared_values) {
+                    self.disconnect(shared_values, AfterDisconnect::Block(cause))
+                } else {
+                    SameState(self)
+                };
+                let _ = complete_tx.send(());
+                consequence
+            }
             Some(TunnelCommand::BlockWhenDisconnected(block_when_disconnected, complete_tx)) => {
                 shared_values.block_when_disconnected = block_when_disconnected;
                 let _ = complete_tx.send(());
@@ -316,15 +442,27 @@ impl ConnectedState {
                     SameState(self)
                 }
             }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::LinkChanged(event)) => {
+                log::info!("Reconnecting because {event}");
+                self.disconnect(shared_values, AfterDisconnect::Reconnect(0))
+            }
             Some(TunnelCommand::Connect) => {
                 self.disconnect(shared_values, AfterDisconnect::Reconnect(0))
             }
+            Some(TunnelCommand::Escalate) => SameState(self),
+            Some(TunnelCommand::SetPrivateKey(private_key, result_tx)) => {
+                let _ = result_tx.send(self.set_private_key(private_key));
+                SameState(self)
+            }
             Some(TunnelCommand::Disconnect) | None => {
                 self.disconnect(shared_values, AfterDisconnect::Nothing)
             }
             Some(TunnelCommand::Block(reason)) => {
                 self.disconnect(shared_values, AfterDisconnect::Block(reason))
             }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::SelfTest(_)) => SameState(self),
             #[cfg(target_os = "android")]
             Some(TunnelCommand::BypassSocket(fd, done_tx)) => {
                 shared_values.bypass_socket(fd, done_tx);
@@ -364,13 +502,37 @@ impl ConnectedState {
     }
 
     fn handle_tunnel_events(
-        self: Box<Self>,
+        mut self: Box<Self>,
         event: Option<(TunnelEvent, oneshot::Sender<()>)>,
         shared_values: &mut SharedTunnelStateValues,
     ) -> EventConsequence {
         use self::EventConsequence::*;
 
         match event {
+            Some((TunnelEvent::PeerEndpointChanged(endpoint), _done_tx)) => {
+                let TunnelParameters::Wireguard(ref mut params) = self.tunnel_parameters else {
+                    return SameState(self);
+                };
+                let peer = &mut params.connection.peer;
+                if peer.alternate_endpoint == Some(endpoint) {
+                    peer.switch_endpoint();
+                } else {
+                    peer.endpoint = endpoint;
+                }
+
+                if let Err(error) = self.set_firewall_policy(shared_values) {
+                    return self.disconnect(
+                        shared_values,
+                        AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError(error)),
+                    );
+                }
+                let tunnel_endpoint = self.tunnel_endpoint();
+                let connect_trace = self.connect_trace.clone();
+                NewState((
+                    self,
+                    TunnelStateTransition::Connected(tunnel_endpoint, connect_trace),
+                ))
+            }
             Some((TunnelEvent::Down, _)) | None => {
                 self.disconnect(shared_values, AfterDisconnect::Reconnect(0))
             }
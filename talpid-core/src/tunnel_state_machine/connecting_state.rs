@@ -5,7 +5,7 @@ use super::{
 };
 use crate::{
     firewall::FirewallPolicy,
-    tunnel::{self, TunnelMonitor},
+    tunnel::{self, RunningTunnel, TunnelProvider, TunnelProviderArgs},
 };
 use futures::{
     channel::{mpsc, oneshot},
@@ -14,24 +14,30 @@ use futures::{
 };
 use std::{
     path::{Path, PathBuf},
-    sync::{Arc, Mutex},
-    thread,
-    time::{Duration, Instant},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
 };
 use talpid_routing::RouteManagerHandle;
-use talpid_tunnel::{tun_provider::TunProvider, TunnelArgs, TunnelEvent, TunnelMetadata};
+use talpid_tunnel::{tun_provider::TunProvider, ConnectTracer, TunnelEvent, TunnelMetadata};
 use talpid_types::{
     net::{AllowedClients, AllowedEndpoint, AllowedTunnelTraffic, TunnelParameters},
-    tunnel::{ErrorStateCause, FirewallPolicyError},
+    tunnel::{ConnectPhase, ErrorDetails, ErrorStateCause, FirewallPolicyError},
     ErrorExt,
 };
 
 #[cfg(target_os = "android")]
 use talpid_tunnel::tun_provider;
+#[cfg(not(target_os = "windows"))]
+use talpid_types::net::{Endpoint, TransportProtocol};
 
 use super::connected_state::TunnelEventsReceiver;
 
 pub(crate) type TunnelCloseEvent = Fuse<oneshot::Receiver<Option<ErrorStateCause>>>;
+/// The running tunnel, once the provider has started it. It is cleared once the tunnel exits.
+pub(crate) type SharedTunnel = Arc<Mutex<Option<Arc<dyn RunningTunnel>>>>;
 
 #[cfg(target_os = "android")]
 const MAX_ATTEMPTS_WITH_SAME_TUN: u32 = 5;
@@ -49,7 +55,12 @@ pub struct ConnectingState {
     allowed_tunnel_traffic: AllowedTunnelTraffic,
     tunnel_close_event: TunnelCloseEvent,
     tunnel_close_tx: oneshot::Sender<()>,
+    tunnel: SharedTunnel,
     retry_attempt: u32,
+    /// Set by the tunnel monitor thread if the tunnel closed because an ephemeral peer could not
+    /// be negotiated.
+    ephemeral_peer_failed: Arc<AtomicBool>,
+    connect_trace: ConnectTracer,
 }
 
 impl ConnectingState {
@@ -66,11 +77,21 @@ impl ConnectingState {
             }
             return ErrorState::enter(shared_values, ErrorStateCause::IsOffline);
         }
-        match shared_values.runtime.block_on(
+        if retry_attempt == 0 {
+            shared_values.require_obfuscation = false;
+        }
+        let connect_trace = ConnectTracer::with_progress(shared_values.connect_progress_tx.clone());
+        connect_trace.start(ConnectPhase::RelaySelection);
+        let tunnel_parameters =
             shared_values
-                .tunnel_parameters_generator
-                .generate(retry_attempt, shared_values.connectivity.has_ipv6()),
-        ) {
+                .runtime
+                .block_on(shared_values.tunnel_parameters_generator.generate(
+                    retry_attempt,
+                    shared_values.connectivity.has_ipv6(),
+                    shared_values.require_obfuscation,
+                ));
+        connect_trace.finish(ConnectPhase::RelaySelection);
+        match tunnel_parameters {
             Err(err) => {
                 ErrorState::enter(shared_values, ErrorStateCause::TunnelParameterError(err))
             }
@@ -87,12 +108,15 @@ impl ConnectingState {
                     return ErrorState::enter(shared_values, ErrorStateCause::SplitTunnelError);
                 }
 
-                if let Err(error) = Self::set_firewall_policy(
+                connect_trace.start(ConnectPhase::Firewall);
+                let result = Self::set_firewall_policy(
                     shared_values,
                     &tunnel_parameters,
                     &None,
                     AllowedTunnelTraffic::None,
-                ) {
+                );
+                connect_trace.finish(ConnectPhase::Firewall);
+                if let Err(error) = result {
                     ErrorState::enter(
                         shared_values,
                         ErrorStateCause::SetFirewallPolicyError(error),
@@ -114,12 +138,14 @@ impl ConnectingState {
 
                     let connecting_state = Self::start_tunnel(
                         shared_values.runtime.clone(),
+                        shared_values.tunnel_provider.clone(),
                         tunnel_parameters,
                         &shared_values.log_dir,
                         &shared_values.resource_dir,
                         shared_values.tun_provider.clone(),
                         &shared_values.route_manager,
                         retry_attempt,
+                        connect_trace,
                     );
                     let params = connecting_state.tunnel_parameters.clone();
                     (
@@ -144,7 +170,9 @@ impl ConnectingState {
 
         #[cfg(target_os = "windows")]
         let clients = AllowedClients::from(
-            TunnelMonitor::get_relay_client(&shared_values.resource_dir, params)
+            shared_values
+                .tunnel_provider
+                .relay_client(&shared_values.resource_dir, params)
                 .into_iter()
                 .collect::<Vec<_>>(),
         );
@@ -156,6 +184,18 @@ impl ConnectingState {
             AllowedClients::Root
         };
 
+        // Traffic to a candidate relay is allowed while it is raced against the selected one
+        #[cfg(not(target_os = "windows"))]
+        let candidate_endpoint = match params {
+            TunnelParameters::Wireguard(params) => {
+                params.candidate_peer().map(|peer| AllowedEndpoint {
+                    endpoint: Endpoint::from_socket_address(peer.endpoint, TransportProtocol::Udp),
+                    clients: clients.clone(),
+                })
+            }
+            _ => None,
+        };
+
         let peer_endpoint = AllowedEndpoint { endpoint, clients };
 
         #[cfg(target_os = "macos")]
@@ -165,6 +205,8 @@ impl ConnectingState {
 
         let policy = FirewallPolicy::Connecting {
             peer_endpoint,
+            #[cfg(not(target_os = "windows"))]
+            candidate_endpoint,
             tunnel: tunnel_metadata.clone(),
             allow_lan: shared_values.allow_lan,
             allowed_endpoint: shared_values.allowed_endpoint.clone(),
@@ -182,22 +224,25 @@ impl ConnectingState {
                         "Failed to apply firewall policy for connecting state"
                     )
                 );
-                match error {
+                match error.into_apply_error() {
                     #[cfg(windows)]
                     crate::firewall::Error::ApplyingConnectingPolicy(policy_error) => policy_error,
-                    _ => FirewallPolicyError::Generic,
+                    error => FirewallPolicyError::Generic(ErrorDetails::from_error(&error)),
                 }
             })
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn start_tunnel(
         runtime: tokio::runtime::Handle,
+        tunnel_provider: Arc<dyn TunnelProvider>,
         parameters: TunnelParameters,
         log_dir: &Option<PathBuf>,
         resource_dir: &Path,
         tun_provider: Arc<Mutex<TunProvider>>,
         route_manager: &RouteManagerHandle,
         retry_attempt: u32,
+        connect_trace: ConnectTracer,
     ) -> Self {
         let (event_tx, event_rx) = mpsc::unbounded();
         let on_tunnel_event =
@@ -218,22 +263,38 @@ impl ConnectingState {
 
         let mut tunnel_parameters = parameters.clone();
 
+        let ephemeral_peer_failed = Arc::new(AtomicBool::new(false));
+        let monitor_ephemeral_peer_failed = ephemeral_peer_failed.clone();
+        let monitor_connect_trace = connect_trace.clone();
+        let tunnel = SharedTunnel::default();
+        let monitor_tunnel = tunnel.clone();
+
         tokio::task::spawn_blocking(move || {
-            let start = Instant::now();
+            // Use the tokio clock so that the wait below can be controlled in tests
+            let start = tokio::time::Instant::now();
 
-            let args = TunnelArgs {
-                runtime,
+            let args = TunnelProviderArgs {
+                runtime: runtime.clone(),
                 resource_dir: &resource_dir,
-                on_event: on_tunnel_event,
+                log_dir: &log_dir,
+                on_event: Arc::new(on_tunnel_event),
                 tunnel_close_rx,
                 tun_provider,
                 retry_attempt,
                 route_manager,
+                connect_trace: monitor_connect_trace,
             };
 
-            let block_reason = match TunnelMonitor::start(&mut tunnel_parameters, &log_dir, args) {
+            let block_reason = match tunnel_provider.spawn(&mut tunnel_parameters, args) {
                 Ok(monitor) => {
-                    let reason = Self::wait_for_tunnel_monitor(monitor, retry_attempt);
+                    let monitor: Arc<dyn RunningTunnel> = Arc::from(monitor);
+                    *monitor_tunnel.lock().unwrap() = Some(monitor.clone());
+                    let reason = Self::wait_for_tunnel_monitor(
+                        &*monitor,
+                        retry_attempt,
+                        &monitor_ephemeral_peer_failed,
+                    );
+                    monitor_tunnel.lock().unwrap().take();
                     log::debug!("Tunnel monitor exited with block reason: {:?}", reason);
                     reason
                 }
@@ -281,9 +342,7 @@ impl ConnectingState {
             };
 
             if block_reason.is_none() {
-                if let Some(remaining_time) = MIN_TUNNEL_ALIVE_TIME.checked_sub(start.elapsed()) {
-                    thread::sleep(remaining_time);
-                }
+                runtime.block_on(tokio::time::sleep_until(start + MIN_TUNNEL_ALIVE_TIME));
             }
 
             if tunnel_close_event_tx.send(block_reason).is_err() {
@@ -300,13 +359,17 @@ impl ConnectingState {
             allowed_tunnel_traffic: INITIAL_ALLOWED_TUNNEL_TRAFFIC,
             tunnel_close_event: tunnel_close_event_rx.fuse(),
             tunnel_close_tx,
+            tunnel,
             retry_attempt,
+            ephemeral_peer_failed,
+            connect_trace,
         }
     }
 
     fn wait_for_tunnel_monitor(
-        tunnel_monitor: TunnelMonitor,
+        tunnel_monitor: &dyn RunningTunnel,
         retry_attempt: u32,
+        ephemeral_peer_failed: &AtomicBool,
     ) -> Option<ErrorStateCause> {
         match tunnel_monitor.wait() {
             Ok(_) => None,
@@ -317,6 +380,16 @@ impl ConnectingState {
                     log::debug!("WireGuard tunnel timed out");
                     None
                 }
+                tunnel::Error::WireguardTunnelMonitoringError(error)
+                    if error.is_ephemeral_peer_negotiation_error() =>
+                {
+                    log::warn!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to negotiate ephemeral peer")
+                    );
+                    ephemeral_peer_failed.store(true, Ordering::SeqCst);
+                    None
+                }
                 error @ tunnel::Error::WireguardTunnelMonitoringError(..)
                     if !should_retry(&error, retry_attempt) =>
                 {
@@ -440,6 +513,20 @@ impl ConnectingState {
                 let _ = complete_tx.send(());
                 consequence
             }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::DnsBlocklist(blocklist, complete_tx)) => {
+                // The filter is started once connected
+                shared_values.set_dns_blocklist(blocklist);
+                let _ = complete_tx.send(());
+                SameState(self)
+            }
+            #[cfg(target_os = "linux")]
+            Some(TunnelCommand::BlockOnUnsupportedDnsManager(block, complete_tx)) => {
+                // DNS is set once connected
+                shared_values.set_block_on_unsupported_dns_manager(block);
+                let _ = complete_tx.send(());
+                SameState(self)
+            }
             Some(TunnelCommand::BlockWhenDisconnected(block_when_disconnected, complete_tx)) => {
                 shared_values.block_when_disconnected = block_when_disconnected;
                 let _ = complete_tx.send(());
@@ -456,15 +543,31 @@ impl ConnectingState {
                     SameState(self)
                 }
             }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::LinkChanged(event)) => {
+                log::info!("Reconnecting because {event}");
+                self.disconnect(shared_values, AfterDisconnect::Reconnect(0))
+            }
             Some(TunnelCommand::Connect) => {
                 self.disconnect(shared_values, AfterDisconnect::Reconnect(0))
             }
+            Some(TunnelCommand::Escalate) => {
+                let retry_attempt = self.retry_attempt + 1;
+                self.disconnect(shared_values, AfterDisconnect::Reconnect(retry_attempt))
+            }
+            Some(TunnelCommand::SetPrivateKey(_, result_tx)) => {
+                // The caller reconnects to start a tunnel with the new key
+                let _ = result_tx.send(false);
+                SameState(self)
+            }
             Some(TunnelCommand::Disconnect) | None => {
                 self.disconnect(shared_values, AfterDisconnect::Nothing)
             }
             Some(TunnelCommand::Block(reason)) => {
                 self.disconnect(shared_values, AfterDisconnect::Block(reason))
             }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::SelfTest(_)) => SameState(self),
             #[cfg(target_os = "android")]
             Some(TunnelCommand::BypassSocket(fd, done_tx)) => {
                 shared_values.bypass_socket(fd, done_tx);
@@ -546,12 +649,15 @@ impl ConnectingState {
                 self.allowed_tunnel_traffic = allowed_tunnel_traffic;
                 self.tunnel_metadata = Some(metadata);
 
-                match Self::set_firewall_policy(
+                self.connect_trace.start(ConnectPhase::Firewall);
+                let result = Self::set_firewall_policy(
                     shared_values,
                     &self.tunnel_parameters,
                     &self.tunnel_metadata,
                     self.allowed_tunnel_traffic.clone(),
-                ) {
+                );
+                self.connect_trace.finish(ConnectPhase::Firewall);
+                match result {
                     Ok(()) => SameState(self),
                     Err(error) => self.disconnect(
                         shared_values,
@@ -566,7 +672,44 @@ impl ConnectingState {
                 self.tunnel_parameters,
                 self.tunnel_close_event,
                 self.tunnel_close_tx,
+                self.tunnel,
+                self.connect_trace,
             )),
+            Some((TunnelEvent::PeerEndpointChanged(endpoint), _done_tx)) => {
+                // While connecting, the endpoint only changes if a candidate relay responded
+                // before the selected one. The other IP version of a relay is only switched to
+                // once the tunnel is up.
+                let TunnelParameters::Wireguard(ref mut params) = self.tunnel_parameters else {
+                    return SameState(self);
+                };
+                let connection = &mut params.connection;
+                let is_candidate = connection
+                    .candidate_peer
+                    .as_ref()
+                    .is_some_and(|peer| peer.endpoint == endpoint);
+                if !is_candidate || !connection.select_candidate_peer() {
+                    return SameState(self);
+                }
+
+                // Stop allowing traffic to the relay that lost the race
+                let result = Self::set_firewall_policy(
+                    shared_values,
+                    &self.tunnel_parameters,
+                    &self.tunnel_metadata,
+                    self.allowed_tunnel_traffic.clone(),
+                );
+                if let Err(error) = result {
+                    return self.disconnect(
+                        shared_values,
+                        AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError(error)),
+                    );
+                }
+                let params = self.tunnel_parameters.clone();
+                NewState((
+                    self,
+                    TunnelStateTransition::Connecting(params.get_tunnel_endpoint()),
+                ))
+            }
             Some((TunnelEvent::Down, _)) => {
                 // It is important to reset this before the tunnel device is down,
                 // or else commands that reapply the firewall rules will fail since
@@ -597,6 +740,11 @@ impl ConnectingState {
             return NewState(ErrorState::enter(shared_values, block_reason));
         }
 
+        if self.ephemeral_peer_failed.load(Ordering::SeqCst) && !shared_values.require_obfuscation {
+            log::info!("Retrying ephemeral peer negotiation over an obfuscated transport");
+            shared_values.require_obfuscation = true;
+        }
+
         log::info!(
             "Tunnel closed. Reconnecting, attempt {}.",
             self.retry_attempt + 1
@@ -8,9 +8,13 @@ use crate::{dns, tunnel_state_machine::ErrorState};
 use futures::StreamExt;
 #[cfg(target_os = "macos")]
 use std::net::Ipv4Addr;
+use std::time::Instant;
 #[cfg(target_os = "macos")]
 use talpid_types::tunnel::ErrorStateCause;
-use talpid_types::ErrorExt;
+use talpid_types::{
+    tunnel::{ErrorDetails, TeardownStep},
+    ErrorExt,
+};
 
 /// No tunnel is running.
 pub struct DisconnectedState(());
@@ -62,6 +66,7 @@ impl DisconnectedState {
         shared_values: &mut SharedTunnelStateValues,
         should_reset_firewall: bool,
     ) {
+        let started = Instant::now();
         let result = if shared_values.block_when_disconnected {
             let policy = FirewallPolicy::Blocked {
                 allow_lan: shared_values.allow_lan,
@@ -71,21 +76,31 @@ impl DisconnectedState {
             };
 
             shared_values.firewall.apply_policy(policy).map_err(|e| {
-                e.display_chain_with_msg(
-                    "Failed to apply blocking firewall policy for disconnected state",
-                )
+                log::error!(
+                    "{}",
+                    e.display_chain_with_msg(
+                        "Failed to apply blocking firewall policy for disconnected state",
+                    )
+                );
+                ErrorDetails::from_error(&e)
             })
         } else if should_reset_firewall {
-            shared_values
-                .firewall
-                .reset_policy()
-                .map_err(|e| e.display_chain_with_msg("Failed to reset firewall policy"))
+            shared_values.firewall.reset_policy().map_err(|e| {
+                log::error!(
+                    "{}",
+                    e.display_chain_with_msg("Failed to reset firewall policy")
+                );
+                ErrorDetails::from_error(&e)
+            })
         } else {
             Ok(())
         };
-        if let Err(error_chain) = result {
-            log::error!("{}", error_chain);
-        }
+        // The policy of the disconnected state is the one that is left behind when shutting down
+        shared_values.teardown_report.record(
+            TeardownStep::FirewallReset,
+            started.elapsed(),
+            result,
+        );
     }
 
     #[cfg(windows)]
@@ -110,6 +125,7 @@ impl DisconnectedState {
         }
     }
 
+    #[cfg(target_os = "macos")]
     fn reset_dns(shared_values: &mut SharedTunnelStateValues) {
         if let Err(error) = shared_values.dns_monitor.reset() {
             log::error!("{}", error.display_chain_with_msg("Unable to reset DNS"));
@@ -166,6 +182,18 @@ impl TunnelState for DisconnectedState {
                 let _ = complete_tx.send(());
                 SameState(self)
             }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::DnsBlocklist(blocklist, complete_tx)) => {
+                shared_values.set_dns_blocklist(blocklist);
+                let _ = complete_tx.send(());
+                SameState(self)
+            }
+            #[cfg(target_os = "linux")]
+            Some(TunnelCommand::BlockOnUnsupportedDnsManager(block, complete_tx)) => {
+                shared_values.set_block_on_unsupported_dns_manager(block);
+                let _ = complete_tx.send(());
+                SameState(self)
+            }
             Some(TunnelCommand::BlockWhenDisconnected(block_when_disconnected, complete_tx)) => {
                 if shared_values.block_when_disconnected != block_when_disconnected {
                     shared_values.block_when_disconnected = block_when_disconnected;
@@ -187,7 +215,7 @@ impl TunnelState for DisconnectedState {
                             );
                             return NewState(ErrorState::enter(
                                 shared_values,
-                                ErrorStateCause::SetDnsError,
+                                ErrorStateCause::from(&err),
                             ));
                         }
                     } else {
@@ -204,8 +232,21 @@ impl TunnelState for DisconnectedState {
                 shared_values.connectivity = connectivity;
                 SameState(self)
             }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::LinkChanged(_)) => SameState(self),
             Some(TunnelCommand::Connect) => NewState(ConnectingState::enter(shared_values, 0)),
+            Some(TunnelCommand::Escalate) => SameState(self),
+            Some(TunnelCommand::SetPrivateKey(_, result_tx)) => {
+                let _ = result_tx.send(false);
+                SameState(self)
+            }
             Some(TunnelCommand::Block(_reason)) => SameState(self),
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::SelfTest(result_tx)) => {
+                let _ = result_tx.send(super::self_test::run(shared_values));
+                // Restore the firewall and DNS configuration of the disconnected state
+                NewState(Self::enter(shared_values, true))
+            }
             #[cfg(target_os = "android")]
             Some(TunnelCommand::BypassSocket(fd, done_tx)) => {
                 shared_values.bypass_socket(fd, done_tx);
@@ -222,7 +263,16 @@ impl TunnelState for DisconnectedState {
                 SameState(self)
             }
             None => {
-                Self::reset_dns(shared_values);
+                let started = Instant::now();
+                let result = shared_values.dns_monitor.reset().map_err(|error| {
+                    log::error!("{}", error.display_chain_with_msg("Unable to reset DNS"));
+                    ErrorDetails::from_error(&error)
+                });
+                shared_values.teardown_report.record(
+                    TeardownStep::DnsRestore,
+                    started.elapsed(),
+                    result,
+                );
                 Finished
             }
             Some(_) => SameState(self),
@@ -4,13 +4,15 @@ use super::{
     TunnelState, TunnelStateTransition,
 };
 use futures::{channel::oneshot, future::FusedFuture, StreamExt};
-use talpid_types::tunnel::{ActionAfterDisconnect, ErrorStateCause};
+use std::time::Instant;
+use talpid_types::tunnel::{ActionAfterDisconnect, ErrorDetails, ErrorStateCause, TeardownStep};
 
 /// This state is active from when we manually trigger a tunnel kill until the tunnel wait
 /// operation (TunnelExit) returned.
 pub struct DisconnectingState {
     tunnel_close_event: TunnelCloseEvent,
     after_disconnect: AfterDisconnect,
+    started: Instant,
 }
 
 impl DisconnectingState {
@@ -26,6 +28,7 @@ impl DisconnectingState {
             Box::new(DisconnectingState {
                 tunnel_close_event,
                 after_disconnect,
+                started: Instant::now(),
             }),
             TunnelStateTransition::Disconnecting(action_after_disconnect),
         )
@@ -55,6 +58,18 @@ impl DisconnectingState {
                     let _ = complete_tx.send(());
                     AfterDisconnect::Nothing
                 }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::DnsBlocklist(blocklist, complete_tx)) => {
+                    shared_values.set_dns_blocklist(blocklist);
+                    let _ = complete_tx.send(());
+                    AfterDisconnect::Nothing
+                }
+                #[cfg(target_os = "linux")]
+                Some(TunnelCommand::BlockOnUnsupportedDnsManager(block, complete_tx)) => {
+                    shared_values.set_block_on_unsupported_dns_manager(block);
+                    let _ = complete_tx.send(());
+                    AfterDisconnect::Nothing
+                }
                 Some(TunnelCommand::BlockWhenDisconnected(
                     block_when_disconnected,
                     complete_tx,
@@ -67,8 +82,17 @@ impl DisconnectingState {
                     shared_values.connectivity = connectivity;
                     AfterDisconnect::Nothing
                 }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::LinkChanged(_)) => AfterDisconnect::Nothing,
                 Some(TunnelCommand::Connect) => AfterDisconnect::Reconnect(0),
-                Some(TunnelCommand::Disconnect) | Some(TunnelCommand::Block(_)) | None => {
+                Some(TunnelCommand::Escalate)
+                | Some(TunnelCommand::Disconnect)
+                | Some(TunnelCommand::Block(_))
+                | None => AfterDisconnect::Nothing,
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::SelfTest(_)) => AfterDisconnect::Nothing,
+                Some(TunnelCommand::SetPrivateKey(_, result_tx)) => {
+                    let _ = result_tx.send(false);
                     AfterDisconnect::Nothing
                 }
                 #[cfg(target_os = "android")]
@@ -103,6 +127,18 @@ impl DisconnectingState {
                     let _ = complete_tx.send(());
                     AfterDisconnect::Block(reason)
                 }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::DnsBlocklist(blocklist, complete_tx)) => {
+                    shared_values.set_dns_blocklist(blocklist);
+                    let _ = complete_tx.send(());
+                    AfterDisconnect::Block(reason)
+                }
+                #[cfg(target_os = "linux")]
+                Some(TunnelCommand::BlockOnUnsupportedDnsManager(block, complete_tx)) => {
+                    shared_values.set_block_on_unsupported_dns_manager(block);
+                    let _ = complete_tx.send(());
+                    AfterDisconnect::Block(reason)
+                }
                 Some(TunnelCommand::BlockWhenDisconnected(
                     block_when_disconnected,
                     complete_tx,
@@ -119,9 +155,18 @@ impl DisconnectingState {
                         AfterDisconnect::Block(reason)
                     }
                 }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::LinkChanged(_)) => AfterDisconnect::Block(reason),
                 Some(TunnelCommand::Connect) => AfterDisconnect::Reconnect(0),
+                Some(TunnelCommand::Escalate) => AfterDisconnect::Block(reason),
                 Some(TunnelCommand::Disconnect) => AfterDisconnect::Nothing,
                 Some(TunnelCommand::Block(new_reason)) => AfterDisconnect::Block(new_reason),
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::SelfTest(_)) => AfterDisconnect::Block(reason),
+                Some(TunnelCommand::SetPrivateKey(_, result_tx)) => {
+                    let _ = result_tx.send(false);
+                    AfterDisconnect::Block(reason)
+                }
                 #[cfg(target_os = "android")]
                 Some(TunnelCommand::BypassSocket(fd, done_tx)) => {
                     shared_values.bypass_socket(fd, done_tx);
@@ -155,6 +200,18 @@ impl DisconnectingState {
                     let _ = complete_tx.send(());
                     AfterDisconnect::Reconnect(retry_attempt)
                 }
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::DnsBlocklist(blocklist, complete_tx)) => {
+                    shared_values.set_dns_blocklist(blocklist);
+                    let _ = complete_tx.send(());
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                #[cfg(target_os = "linux")]
+                Some(TunnelCommand::BlockOnUnsupportedDnsManager(block, complete_tx)) => {
+                    shared_values.set_block_on_unsupported_dns_manager(block);
+                    let _ = complete_tx.send(());
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
                 Some(TunnelCommand::BlockWhenDisconnected(
                     block_when_disconnected,
                     complete_tx,
@@ -171,9 +228,19 @@ impl DisconnectingState {
                         AfterDisconnect::Reconnect(retry_attempt)
                     }
                 }
-                Some(TunnelCommand::Connect) => AfterDisconnect::Reconnect(retry_attempt),
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::LinkChanged(_)) => AfterDisconnect::Reconnect(retry_attempt),
+                Some(TunnelCommand::Connect) | Some(TunnelCommand::Escalate) => {
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
                 Some(TunnelCommand::Disconnect) | None => AfterDisconnect::Nothing,
                 Some(TunnelCommand::Block(reason)) => AfterDisconnect::Block(reason),
+                #[cfg(not(target_os = "android"))]
+                Some(TunnelCommand::SelfTest(_)) => AfterDisconnect::Reconnect(retry_attempt),
+                Some(TunnelCommand::SetPrivateKey(_, result_tx)) => {
+                    let _ = result_tx.send(false);
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
                 #[cfg(target_os = "android")]
                 Some(TunnelCommand::BypassSocket(fd, done_tx)) => {
                     shared_values.bypass_socket(fd, done_tx);
@@ -243,6 +310,24 @@ impl TunnelState for DisconnectingState {
         match result {
             EventResult::Command(command) => self.handle_commands(command, shared_values),
             EventResult::Close(result) => {
+                if commands.is_done() {
+                    let stop_result = match &result {
+                        Ok(_) => Ok(()),
+                        Err(_) => Err(ErrorDetails {
+                            os_error: None,
+                            message: Some(
+                                "The tunnel monitor stopped without reporting that the tunnel \
+                                closed"
+                                    .to_owned(),
+                            ),
+                        }),
+                    };
+                    shared_values.teardown_report.record(
+                        TeardownStep::TunnelStop,
+                        self.started.elapsed(),
+                        stop_result,
+                    );
+                }
                 let block_reason = result.unwrap_or(None);
                 NewState(self.after_disconnect(block_reason, shared_values))
             }
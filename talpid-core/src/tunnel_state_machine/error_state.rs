@@ -7,7 +7,7 @@ use futures::StreamExt;
 #[cfg(target_os = "macos")]
 use std::net::Ipv4Addr;
 use talpid_types::{
-    tunnel::{self as talpid_tunnel, ErrorStateCause, FirewallPolicyError},
+    tunnel::{self as talpid_tunnel, ErrorDetails, ErrorStateCause, FirewallPolicyError},
     ErrorExt,
 };
 
@@ -43,7 +43,7 @@ impl ErrorState {
                         "Failed to configure system to use filtering resolver"
                     )
                 );
-                return Self::enter(shared_values, ErrorStateCause::SetDnsError);
+                return Self::enter(shared_values, ErrorStateCause::from(&err));
             }
         };
 
@@ -52,21 +52,39 @@ impl ErrorState {
 
         #[cfg(target_os = "android")]
         let block_failure = if !Self::create_blocking_tun(shared_values) {
-            Some(FirewallPolicyError::Generic)
+            Some(FirewallPolicyError::Generic(ErrorDetails::default()))
         } else {
             None
         };
+
+        let conflicting_software = if block_reason.may_be_caused_by_conflicting_software() {
+            Self::find_conflicting_software()
+        } else {
+            vec![]
+        };
+
         (
             Box::new(ErrorState {
                 block_reason: block_reason.clone(),
             }),
-            TunnelStateTransition::Error(talpid_tunnel::ErrorState::new(
-                block_reason,
-                block_failure,
-            )),
+            TunnelStateTransition::Error(
+                talpid_tunnel::ErrorState::new(block_reason, block_failure)
+                    .with_conflicting_software(conflicting_software),
+            ),
         )
     }
 
+    fn find_conflicting_software() -> Vec<String> {
+        let conflicting_software = talpid_platform_metadata::find_conflicting_software();
+        if !conflicting_software.is_empty() {
+            log::warn!(
+                "Found software that may interfere with the tunnel: {}",
+                conflicting_software.join(", ")
+            );
+        }
+        conflicting_software
+    }
+
     fn set_firewall_policy(
         shared_values: &mut SharedTunnelStateValues,
     ) -> Result<(), FirewallPolicyError> {
@@ -90,10 +108,10 @@ impl ErrorState {
                         "Failed to apply firewall policy for blocked state"
                     )
                 );
-                match error {
+                match error.into_apply_error() {
                     #[cfg(windows)]
                     crate::firewall::Error::ApplyingBlockedPolicy(policy_error) => policy_error,
-                    _ => FirewallPolicyError::Generic,
+                    error => FirewallPolicyError::Generic(ErrorDetails::from_error(&error)),
                 }
             })
     }
@@ -159,7 +177,9 @@ impl TunnelState for ErrorState {
                         let _ = tx.send(());
                         return NewState(Self::enter(
                             shared_values,
-                            ErrorStateCause::SetFirewallPolicyError(FirewallPolicyError::Generic),
+                            ErrorStateCause::SetFirewallPolicyError(FirewallPolicyError::Generic(
+                                ErrorDetails::default(),
+                            )),
                         ));
                     }
                 }
@@ -176,6 +196,24 @@ impl TunnelState for ErrorState {
                 let _ = complete_tx.send(());
                 consequence
             }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::DnsBlocklist(blocklist, complete_tx)) => {
+                shared_values.set_dns_blocklist(blocklist);
+                let _ = complete_tx.send(());
+                SameState(self)
+            }
+            #[cfg(target_os = "linux")]
+            Some(TunnelCommand::BlockOnUnsupportedDnsManager(block, complete_tx)) => {
+                shared_values.set_block_on_unsupported_dns_manager(block);
+                let _ = complete_tx.send(());
+                if !block && matches!(self.block_reason, ErrorStateCause::UnsupportedDnsManager(_))
+                {
+                    Self::reset_dns(shared_values);
+                    NewState(ConnectingState::enter(shared_values, 0))
+                } else {
+                    SameState(self)
+                }
+            }
             Some(TunnelCommand::BlockWhenDisconnected(block_when_disconnected, complete_tx)) => {
                 shared_values.block_when_disconnected = block_when_disconnected;
                 let _ = complete_tx.send(());
@@ -192,11 +230,18 @@ impl TunnelState for ErrorState {
                     SameState(self)
                 }
             }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::LinkChanged(_)) => SameState(self),
             Some(TunnelCommand::Connect) => {
                 Self::reset_dns(shared_values);
 
                 NewState(ConnectingState::enter(shared_values, 0))
             }
+            Some(TunnelCommand::Escalate) => SameState(self),
+            Some(TunnelCommand::SetPrivateKey(_, result_tx)) => {
+                let _ = result_tx.send(false);
+                SameState(self)
+            }
             Some(TunnelCommand::Disconnect) | None => {
                 #[cfg(target_os = "linux")]
                 shared_values.reset_connectivity_check();
@@ -206,6 +251,8 @@ impl TunnelState for ErrorState {
             Some(TunnelCommand::Block(reason)) => {
                 NewState(ErrorState::enter(shared_values, reason))
             }
+            #[cfg(not(target_os = "android"))]
+            Some(TunnelCommand::SelfTest(_)) => SameState(self),
             #[cfg(target_os = "android")]
             Some(TunnelCommand::BypassSocket(fd, done_tx)) => {
                 shared_values.bypass_socket(fd, done_tx);
@@ -3,6 +3,13 @@ mod connecting_state;
 mod disconnected_state;
 mod disconnecting_state;
 mod error_state;
+#[cfg(not(target_os = "android"))]
+mod self_test;
+#[cfg(all(
+    feature = "test-harness",
+    any(target_os = "linux", target_os = "macos")
+))]
+pub mod test_harness;
 
 use self::{
     connected_state::ConnectedState,
@@ -11,6 +18,10 @@ use self::{
     disconnecting_state::{AfterDisconnect, DisconnectingState},
     error_state::ErrorState,
 };
+#[cfg(not(target_os = "android"))]
+use crate::dns::{self, filter::DnsFilter};
+#[cfg(not(target_os = "android"))]
+use crate::link_monitor;
 #[cfg(any(windows, target_os = "android", target_os = "macos"))]
 use crate::split_tunnel;
 use crate::{
@@ -18,6 +29,7 @@ use crate::{
     firewall::{Firewall, FirewallArguments, InitialFirewallState},
     mpsc::Sender,
     offline,
+    tunnel::TunnelProvider,
 };
 #[cfg(any(target_os = "windows", target_os = "macos"))]
 use std::ffi::OsString;
@@ -25,8 +37,6 @@ use talpid_routing::RouteManagerHandle;
 #[cfg(target_os = "macos")]
 use talpid_tunnel::TunnelMetadata;
 use talpid_tunnel::{tun_provider::TunProvider, TunnelEvent};
-#[cfg(target_os = "macos")]
-use talpid_types::ErrorExt;
 
 use futures::{
     channel::{mpsc, oneshot},
@@ -41,15 +51,24 @@ use std::{
     path::PathBuf,
     pin::Pin,
     sync::{Arc, Mutex},
-    time::Duration,
+    time::{Duration, Instant},
 };
 #[cfg(target_os = "android")]
-use talpid_types::{android::AndroidContext, ErrorExt};
+use talpid_types::android::AndroidContext;
+#[cfg(not(target_os = "android"))]
+use talpid_types::{net::dns::Blocklist, self_test::SelfTestStep};
 use talpid_types::{
-    net::{AllowedEndpoint, Connectivity, TunnelParameters},
-    tunnel::{ErrorStateCause, ParameterGenerationError, TunnelStateTransition},
+    net::{wireguard::PrivateKey, AllowedEndpoint, Connectivity, TunnelParameters},
+    tunnel::{
+        ConnectProgress, ErrorDetails, ErrorStateCause, ParameterGenerationError, TeardownReport,
+        TeardownStep, TunnelStateTransition,
+    },
+    ErrorExt,
 };
 
+#[cfg(not(target_os = "android"))]
+pub use crate::link_monitor::{current_network, LinkEvent};
+
 const TUNNEL_STATE_MACHINE_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// Errors that can happen when setting up or using the state machine.
@@ -99,6 +118,10 @@ pub struct InitialTunnelState {
     pub allowed_endpoint: AllowedEndpoint,
     /// Whether to reset any existing firewall rules when initializing the disconnected state.
     pub reset_firewall: bool,
+    /// Block traffic rather than risk leaking DNS if DNS is managed by software that cannot be
+    /// configured.
+    #[cfg(target_os = "linux")]
+    pub block_on_unsupported_dns_manager: bool,
     /// Programs to exclude from the tunnel using the split tunnel driver.
     #[cfg(any(target_os = "windows", target_os = "macos"))]
     pub exclude_paths: Vec<OsString>,
@@ -122,10 +145,12 @@ pub struct LinuxNetworkingIdentifiers {
 pub async fn spawn(
     initial_settings: InitialTunnelState,
     tunnel_parameters_generator: impl TunnelParametersGenerator,
+    tunnel_provider: Arc<dyn TunnelProvider>,
     log_dir: Option<PathBuf>,
     resource_dir: PathBuf,
     state_change_listener: impl Sender<TunnelStateTransition> + Send + 'static,
     offline_state_listener: mpsc::UnboundedSender<Connectivity>,
+    connect_progress_listener: mpsc::UnboundedSender<ConnectProgress>,
     #[cfg(target_os = "windows")] volume_update_rx: mpsc::UnboundedReceiver<()>,
     #[cfg(target_os = "android")] android_context: AndroidContext,
     #[cfg(target_os = "linux")] linux_ids: LinuxNetworkingIdentifiers,
@@ -158,7 +183,9 @@ pub async fn spawn(
         settings: initial_settings,
         command_tx: weak_command_tx,
         offline_state_tx: offline_state_listener,
+        connect_progress_tx: connect_progress_listener,
         tunnel_parameters_generator,
+        tunnel_provider,
         tun_provider,
         log_dir,
         resource_dir,
@@ -175,10 +202,11 @@ pub async fn spawn(
 
     #[cfg(windows)]
     let split_tunnel = state_machine.shared_values.split_tunnel.handle();
+    let route_manager = state_machine.shared_values.route_manager.clone();
 
     tokio::task::spawn_blocking(move || {
-        state_machine.run(state_change_listener);
-        if shutdown_tx.send(()).is_err() {
+        let teardown_report = state_machine.run(state_change_listener);
+        if shutdown_tx.send(teardown_report).is_err() {
             log::error!("Can't send shutdown completion to daemon");
         }
     });
@@ -186,6 +214,7 @@ pub async fn spawn(
     Ok(TunnelStateMachineHandle {
         command_tx,
         shutdown_rx,
+        route_manager,
         #[cfg(windows)]
         split_tunnel,
     })
@@ -201,16 +230,38 @@ pub enum TunnelCommand {
     AllowEndpoint(AllowedEndpoint, oneshot::Sender<()>),
     /// Set DNS servers to use.
     Dns(Option<Vec<IpAddr>>, oneshot::Sender<()>),
+    /// Set domains to block using a local DNS filter while connected. `None` disables the filter.
+    #[cfg(not(target_os = "android"))]
+    DnsBlocklist(Option<Arc<Blocklist>>, oneshot::Sender<()>),
     /// Enable or disable the block_when_disconnected feature.
     BlockWhenDisconnected(bool, oneshot::Sender<()>),
+    /// Set whether to block traffic rather than risk leaking DNS if DNS is managed by software
+    /// that cannot be configured.
+    #[cfg(target_os = "linux")]
+    BlockOnUnsupportedDnsManager(bool, oneshot::Sender<()>),
     /// Notify the state machine of the connectivity of the device.
     Connectivity(Connectivity),
+    /// Notify the state machine of an event that has likely broken the tunnel.
+    #[cfg(not(target_os = "android"))]
+    LinkChanged(LinkEvent),
     /// Open tunnel connection.
     Connect,
+    /// Abandon the current connection attempt and move on to the next one, which may use another
+    /// transport. Ignored unless connecting.
+    Escalate,
+    /// Replace the WireGuard private key of the running tunnel without reconnecting. `false` is
+    /// sent back if the tunnel isn't connected or can't be reconfigured, in which case the caller
+    /// has to reconnect to use the new key.
+    SetPrivateKey(PrivateKey, oneshot::Sender<bool>),
     /// Close tunnel connection.
     Disconnect,
     /// Block all network access unless tunnel is disconnecting or disconnected
     Block(ErrorStateCause),
+    /// Test the tunnel device, routing, DNS and firewall integration, undoing every change
+    /// afterwards. The test only runs in the disconnected state. In any other state, the sender is
+    /// dropped.
+    #[cfg(not(target_os = "android"))]
+    SelfTest(oneshot::Sender<Vec<SelfTestStep>>),
     /// Bypass a socket, allowing traffic to flow through outside the tunnel.
     #[cfg(target_os = "android")]
     BypassSocket(RawFd, oneshot::Sender<()>),
@@ -253,7 +304,9 @@ struct TunnelStateMachineInitArgs<G: TunnelParametersGenerator> {
     settings: InitialTunnelState,
     command_tx: std::sync::Weak<mpsc::UnboundedSender<TunnelCommand>>,
     offline_state_tx: mpsc::UnboundedSender<Connectivity>,
+    connect_progress_tx: mpsc::UnboundedSender<ConnectProgress>,
     tunnel_parameters_generator: G,
+    tunnel_provider: Arc<dyn TunnelProvider>,
     tun_provider: TunProvider,
     log_dir: Option<PathBuf>,
     resource_dir: PathBuf,
@@ -325,6 +378,28 @@ impl TunnelStateMachine {
         )
         .map_err(Error::InitDnsMonitorError)?;
 
+        #[cfg(not(target_os = "android"))]
+        let link_monitor = {
+            let (link_tx, mut link_rx) = mpsc::unbounded();
+            let command_tx = args.command_tx.clone();
+            tokio::spawn(async move {
+                while let Some(event) = link_rx.next().await {
+                    let Some(tx) = command_tx.upgrade() else {
+                        break;
+                    };
+                    let _ = tx.unbounded_send(TunnelCommand::LinkChanged(event));
+                }
+            });
+            link_monitor::spawn_monitor(
+                link_tx,
+                #[cfg(any(target_os = "linux", target_os = "windows"))]
+                route_manager.clone(),
+                #[cfg(target_os = "linux")]
+                Some(args.linux_ids.fwmark),
+            )
+            .await
+        };
+
         let (offline_tx, mut offline_rx) = mpsc::unbounded();
         let initial_offline_state_tx = args.offline_state_tx.clone();
         tokio::spawn(async move {
@@ -380,20 +455,37 @@ impl TunnelStateMachine {
             dns_monitor,
             route_manager,
             _offline_monitor: offline_monitor,
+            #[cfg(not(target_os = "android"))]
+            _link_monitor: link_monitor,
             allow_lan: args.settings.allow_lan,
             block_when_disconnected: args.settings.block_when_disconnected,
             connectivity,
             dns_servers: args.settings.dns_servers,
+            #[cfg(not(target_os = "android"))]
+            dns_blocklist: None,
+            #[cfg(not(target_os = "android"))]
+            dns_filter: None,
             allowed_endpoint: args.settings.allowed_endpoint,
             tunnel_parameters_generator: Box::new(args.tunnel_parameters_generator),
+            require_obfuscation: false,
+            tunnel_provider: args.tunnel_provider,
             tun_provider: Arc::new(Mutex::new(args.tun_provider)),
             log_dir: args.log_dir,
             resource_dir: args.resource_dir,
+            connect_progress_tx: Some(args.connect_progress_tx),
+            teardown_report: TeardownReport::default(),
+            #[cfg(target_os = "linux")]
+            manage_connectivity_check: talpid_tunnel::netns::is_host_namespace(),
             #[cfg(target_os = "linux")]
             connectivity_check_was_enabled: None,
             #[cfg(target_os = "macos")]
             filtering_resolver,
+            #[cfg(target_os = "linux")]
+            block_on_unsupported_dns_manager: false,
         };
+        #[cfg(target_os = "linux")]
+        shared_values
+            .set_block_on_unsupported_dns_manager(args.settings.block_on_unsupported_dns_manager);
 
         tokio::task::spawn_blocking(move || {
             let (initial_state, _) =
@@ -409,7 +501,12 @@ impl TunnelStateMachine {
         .unwrap()
     }
 
-    fn run(mut self, change_listener: impl Sender<TunnelStateTransition> + Send + 'static) {
+    /// Handle commands until the command channel is closed, and then restore the network. Returns
+    /// the outcome of each step of restoring it.
+    fn run(
+        mut self,
+        change_listener: impl Sender<TunnelStateTransition> + Send + 'static,
+    ) -> TeardownReport {
         use EventConsequence::*;
 
         let runtime = self.shared_values.runtime.clone();
@@ -438,7 +535,24 @@ impl TunnelStateMachine {
 
         #[cfg(target_os = "macos")]
         runtime.block_on(self.shared_values.split_tunnel.shutdown());
-        runtime.block_on(self.shared_values.route_manager.stop());
+
+        let started = Instant::now();
+        let result = runtime
+            .block_on(self.shared_values.route_manager.stop())
+            .map_err(|error| {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to stop route manager")
+                );
+                ErrorDetails::from_error(&error)
+            });
+        self.shared_values.teardown_report.record(
+            TeardownStep::RouteRestore,
+            started.elapsed(),
+            result,
+        );
+
+        self.shared_values.teardown_report
     }
 }
 
@@ -447,10 +561,14 @@ pub trait TunnelParametersGenerator: Send + 'static {
     /// Given the number of consecutive failed retry attempts, it should yield a `TunnelParameters`
     /// to establish a tunnel with.
     /// If this returns `None` then the state machine goes into the `Error` state.
+    ///
+    /// `require_obfuscation` is set if an ephemeral peer could not be negotiated during a previous
+    /// attempt, in which case the tunnel should preferably use an obfuscated (TCP) transport.
     fn generate(
         &mut self,
         retry_attempt: u32,
         ipv6: bool,
+        require_obfuscation: bool,
     ) -> Pin<Box<dyn Future<Output = Result<TunnelParameters, ParameterGenerationError>>>>;
 }
 
@@ -468,6 +586,8 @@ struct SharedTunnelStateValues {
     dns_monitor: DnsMonitor,
     route_manager: RouteManagerHandle,
     _offline_monitor: offline::MonitorHandle,
+    #[cfg(not(target_os = "android"))]
+    _link_monitor: link_monitor::MonitorHandle,
     /// Should LAN access be allowed outside the tunnel.
     allow_lan: bool,
     /// Should network access be allowed when in the disconnected state.
@@ -476,17 +596,37 @@ struct SharedTunnelStateValues {
     connectivity: Connectivity,
     /// DNS servers to use (overriding default).
     dns_servers: Option<Vec<IpAddr>>,
+    /// Domains to block using the DNS filter.
+    #[cfg(not(target_os = "android"))]
+    dns_blocklist: Option<Arc<Blocklist>>,
+    /// The DNS filter, if one is running.
+    #[cfg(not(target_os = "android"))]
+    dns_filter: Option<DnsFilter>,
     /// Endpoint that should not be blocked by the firewall.
     allowed_endpoint: AllowedEndpoint,
     /// The generator of new `TunnelParameter`s
     tunnel_parameters_generator: Box<dyn TunnelParametersGenerator>,
+    /// Whether new tunnel parameters should use an obfuscated transport, because an ephemeral
+    /// peer could not be negotiated over the previous one. Reset on a fresh connection attempt.
+    require_obfuscation: bool,
+    /// The backend used to start tunnels.
+    tunnel_provider: Arc<dyn TunnelProvider>,
     /// The provider of tunnel devices.
     tun_provider: Arc<Mutex<TunProvider>>,
     /// Directory to store tunnel log file.
     log_dir: Option<PathBuf>,
     /// Resource directory path.
     resource_dir: PathBuf,
-
+    /// Receives the progress of connection attempts.
+    connect_progress_tx: Option<mpsc::UnboundedSender<ConnectProgress>>,
+    /// Outcome of restoring the network, which is returned when the state machine exits.
+    teardown_report: TeardownReport,
+
+    /// Whether NetworkManager's connectivity check should be disabled while the firewall is
+    /// active. This is turned off when the system is mocked, and when the daemon runs in another
+    /// network namespace than the host, since NetworkManager only manages the host namespace.
+    #[cfg(target_os = "linux")]
+    manage_connectivity_check: bool,
     /// NetworkManager's connecitivity check state.
     #[cfg(target_os = "linux")]
     connectivity_check_was_enabled: Option<bool>,
@@ -494,6 +634,10 @@ struct SharedTunnelStateValues {
     /// Filtering resolver handle
     #[cfg(target_os = "macos")]
     filtering_resolver: crate::resolver::ResolverHandle,
+
+    /// Whether to fail to set DNS if DNS is managed by software that cannot be configured.
+    #[cfg(target_os = "linux")]
+    block_on_unsupported_dns_manager: bool,
 }
 
 impl SharedTunnelStateValues {
@@ -607,11 +751,68 @@ impl SharedTunnelStateValues {
         }
     }
 
+    /// Set whether to fail to set DNS, rather than risk leaking DNS requests, if DNS is managed by
+    /// software that cannot be configured. Returns whether the setting changed.
+    #[cfg(target_os = "linux")]
+    pub fn set_block_on_unsupported_dns_manager(&mut self, block: bool) -> bool {
+        self.dns_monitor.set_block_on_unsupported_manager(block);
+        let changed = self.block_on_unsupported_dns_manager != block;
+        self.block_on_unsupported_dns_manager = block;
+        changed
+    }
+
+    /// Set the domains to block with the DNS filter. Returns whether the blocklist changed.
+    #[cfg(not(target_os = "android"))]
+    pub fn set_dns_blocklist(&mut self, blocklist: Option<Arc<Blocklist>>) -> bool {
+        if self.dns_blocklist != blocklist {
+            self.dns_blocklist = blocklist;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Start or update the DNS filter if there is a blocklist, and stop it otherwise. Returns the
+    /// address that the system should use for DNS if the filter is running. Fails if the filter
+    /// can't be started, since falling back to the upstream servers would stop blocking.
+    #[cfg(not(target_os = "android"))]
+    pub fn update_dns_filter(
+        &mut self,
+        upstream: &[IpAddr],
+    ) -> Result<Option<IpAddr>, dns::filter::Error> {
+        let Some(blocklist) = self.dns_blocklist.clone() else {
+            self.stop_dns_filter();
+            return Ok(None);
+        };
+        if let Some(filter) = &self.dns_filter {
+            let _guard = self.runtime.enter();
+            filter.set_blocklist(blocklist);
+            filter.set_upstream(upstream);
+        } else {
+            log::debug!("Starting DNS filter with {} domains", blocklist.len());
+            let filter = self
+                .runtime
+                .block_on(DnsFilter::start(blocklist, upstream))?;
+            self.dns_filter = Some(filter);
+        }
+        Ok(Some(dns::filter::FILTER_ADDR.ip()))
+    }
+
+    #[cfg(not(target_os = "android"))]
+    pub fn stop_dns_filter(&mut self) {
+        if self.dns_filter.take().is_some() {
+            log::debug!("Stopped DNS filter");
+        }
+    }
+
     /// NetworkManager's connectivity check can get hung when DNS requests fail, thus the TSM
     /// should always disable it before applying firewall rules. The connectivity check should be
     /// reset whenever the firewall is cleared.
     #[cfg(target_os = "linux")]
     pub fn disable_connectivity_check(&mut self) {
+        if !self.manage_connectivity_check {
+            return;
+        }
         if self.connectivity_check_was_enabled.is_none() {
             if let Ok(nm) = talpid_dbus::network_manager::NetworkManager::new() {
                 self.connectivity_check_was_enabled = nm.disable_connectivity_check();
@@ -710,20 +911,27 @@ trait TunnelState: Send {
 /// Handle used to control the tunnel state machine.
 pub struct TunnelStateMachineHandle {
     command_tx: Arc<mpsc::UnboundedSender<TunnelCommand>>,
-    shutdown_rx: oneshot::Receiver<()>,
+    shutdown_rx: oneshot::Receiver<TeardownReport>,
+    route_manager: RouteManagerHandle,
     #[cfg(windows)]
     split_tunnel: split_tunnel::SplitTunnelHandle,
 }
 
 impl TunnelStateMachineHandle {
-    /// Waits for the tunnel state machine to shut down.
-    /// This may fail after a timeout of `TUNNEL_STATE_MACHINE_SHUTDOWN_TIMEOUT`.
-    pub async fn try_join(self) {
+    /// Waits for the tunnel state machine to shut down, and returns the outcome of restoring the
+    /// network. This may fail after a timeout of `TUNNEL_STATE_MACHINE_SHUTDOWN_TIMEOUT`.
+    pub async fn try_join(self) -> Option<TeardownReport> {
         drop(self.command_tx);
 
         match tokio::time::timeout(TUNNEL_STATE_MACHINE_SHUTDOWN_TIMEOUT, self.shutdown_rx).await {
-            Ok(_) => log::info!("Tunnel state machine shut down"),
-            Err(_) => log::error!("Tunnel state machine did not shut down gracefully"),
+            Ok(teardown_report) => {
+                log::info!("Tunnel state machine shut down");
+                teardown_report.ok()
+            }
+            Err(_) => {
+                log::error!("Tunnel state machine did not shut down gracefully");
+                None
+            }
         }
     }
 
@@ -732,6 +940,11 @@ impl TunnelStateMachineHandle {
         &self.command_tx
     }
 
+    /// Returns route manager handle.
+    pub fn route_manager(&self) -> &RouteManagerHandle {
+        &self.route_manager
+    }
+
     /// Returns split tunnel object handle.
     #[cfg(windows)]
     pub fn split_tunnel(&self) -> &split_tunnel::SplitTunnelHandle {
@@ -0,0 +1,240 @@
+//! Exercises the tunnel device, WireGuard, routing, DNS and firewall integration without connecting
+//! to a relay, so that problems with the kernel, drivers or security policies can be found in
+//! isolation. Only addresses reserved for documentation are used, and every change is undone
+//! before the test ends.
+
+use super::SharedTunnelStateValues;
+use crate::{dns::DnsMonitor, firewall::FirewallPolicy};
+use ipnetwork::Ipv4Network;
+use std::{
+    collections::HashSet,
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+use talpid_net::SocketProtection;
+use talpid_routing::{Node, RequiredRoute, RouteManagerHandle};
+#[cfg(unix)]
+use talpid_tunnel::tun_provider::{self, Tun, TunConfig};
+use talpid_types::{
+    net::wireguard::PrivateKey,
+    self_test::{SelfTestStep, StepOutcome},
+    ErrorExt,
+};
+use talpid_wireguard::probe;
+use tokio::net::UdpSocket;
+
+/// Address of the test tunnel device, from TEST-NET-1.
+#[cfg(unix)]
+const TUN_ADDRESS: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+/// Network that is routed through the test tunnel device (TEST-NET-2).
+const ROUTED_NETWORK: Ipv4Addr = Ipv4Addr::new(198, 51, 100, 0);
+/// DNS server that is set for the test tunnel device, from TEST-NET-3. Nothing is resolved with it.
+const DNS_SERVER: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 53);
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+const TUN_STEP: &str = "Create tunnel device";
+const HANDSHAKE_STEP: &str = "WireGuard handshake over loopback";
+const ROUTE_STEP: &str = "Add and remove route";
+const DNS_STEP: &str = "Set and restore DNS";
+const FIREWALL_STEP: &str = "Apply and roll back firewall policy";
+
+#[derive(thiserror::Error, Debug)]
+enum Error {
+    #[cfg(unix)]
+    #[error("Failed to create tunnel device")]
+    CreateTun(#[source] tun_provider::Error),
+
+    #[error("Failed to bind loopback socket")]
+    BindLoopback(#[source] io::Error),
+
+    #[error("Handshake failed")]
+    Handshake(#[source] probe::Error),
+
+    #[error("The responder received the wrong public key")]
+    WrongPublicKey,
+
+    #[error("Failed to add route")]
+    AddRoute(#[source] talpid_routing::Error),
+
+    #[error("Failed to remove route")]
+    RemoveRoute(#[source] talpid_routing::Error),
+
+    #[error("Failed to set DNS")]
+    SetDns(#[source] crate::dns::Error),
+
+    #[error("Failed to restore DNS")]
+    ResetDns(#[source] crate::dns::Error),
+
+    #[error("Failed to apply firewall policy")]
+    ApplyFirewall(#[source] crate::firewall::ApplyPolicyError),
+
+    #[error("Failed to roll back firewall policy")]
+    ResetFirewall(#[source] crate::firewall::Error),
+}
+
+/// Run every step of the self test. The caller must restore the firewall and DNS configuration of
+/// the current state afterwards.
+pub(super) fn run(shared_values: &mut SharedTunnelStateValues) -> Vec<SelfTestStep> {
+    let mut steps = Steps::default();
+
+    #[cfg(unix)]
+    let tun = steps.record(TUN_STEP, create_tun(shared_values));
+    #[cfg(unix)]
+    let interface = tun.as_ref().map(|tun| tun.interface_name().to_owned());
+    #[cfg(windows)]
+    let interface: Option<String> = {
+        steps.skip(
+            TUN_STEP,
+            "Tunnel devices are created by the tunnel backends on Windows",
+        );
+        None
+    };
+
+    let handshake = shared_values.runtime.block_on(loopback_handshake());
+    steps.record(HANDSHAKE_STEP, handshake);
+
+    match interface {
+        Some(interface) => {
+            let route = shared_values.runtime.block_on(add_and_remove_route(
+                &shared_values.route_manager,
+                &interface,
+            ));
+            steps.record(ROUTE_STEP, route);
+            steps.record(
+                DNS_STEP,
+                set_and_restore_dns(&mut shared_values.dns_monitor, &interface),
+            );
+        }
+        None => {
+            steps.skip(ROUTE_STEP, "There is no tunnel device to route through");
+            steps.skip(DNS_STEP, "There is no tunnel device to set DNS for");
+        }
+    }
+
+    steps.record(FIREWALL_STEP, apply_and_restore_firewall(shared_values));
+
+    #[cfg(unix)]
+    drop(tun);
+    steps.0
+}
+
+#[derive(Default)]
+struct Steps(Vec<SelfTestStep>);
+
+impl Steps {
+    fn record<T>(&mut self, name: &str, result: Result<T, Error>) -> Option<T> {
+        let (outcome, value) = match result {
+            Ok(value) => (StepOutcome::Passed, Some(value)),
+            Err(error) => {
+                let error = error.display_chain();
+                log::warn!("Self test step \"{name}\" failed: {error}");
+                (StepOutcome::Failed(error), None)
+            }
+        };
+        self.0.push(SelfTestStep {
+            name: name.to_owned(),
+            outcome,
+        });
+        value
+    }
+
+    fn skip(&mut self, name: &str, reason: &str) {
+        self.0.push(SelfTestStep {
+            name: name.to_owned(),
+            outcome: StepOutcome::Skipped(reason.to_owned()),
+        });
+    }
+}
+
+#[cfg(unix)]
+fn create_tun(shared_values: &SharedTunnelStateValues) -> Result<Tun, Error> {
+    shared_values
+        .tun_provider
+        .lock()
+        .unwrap()
+        .get_tun(TunConfig {
+            addresses: vec![TUN_ADDRESS.into()],
+            dns_servers: vec![],
+            routes: vec![],
+            mtu: 1280,
+            #[cfg(target_os = "linux")]
+            queues: 1,
+        })
+        .map_err(Error::CreateTun)
+}
+
+/// Perform a handshake between two keys that are generated for the test, with an in-process
+/// responder on the other end.
+async fn loopback_handshake() -> Result<(), Error> {
+    let responder_key = PrivateKey::new_from_random();
+    let initiator_key = PrivateKey::new_from_random();
+
+    let socket = UdpSocket::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))
+        .await
+        .map_err(Error::BindLoopback)?;
+    let endpoint = socket.local_addr().map_err(Error::BindLoopback)?;
+
+    let responder_public_key = responder_key.public_key();
+
+    let responder = probe::respond_to_handshake(&socket, &responder_key);
+    let initiator = probe::handshake_latency(
+        endpoint,
+        &initiator_key,
+        &responder_public_key,
+        HANDSHAKE_TIMEOUT,
+        &SocketProtection::default(),
+    );
+    // The initiator times out if the responder never answers
+    let (received_key, _latency) =
+        tokio::try_join!(responder, initiator).map_err(Error::Handshake)?;
+
+    if received_key != initiator_key.public_key() {
+        return Err(Error::WrongPublicKey);
+    }
+    Ok(())
+}
+
+async fn add_and_remove_route(
+    route_manager: &RouteManagerHandle,
+    interface: &str,
+) -> Result<(), Error> {
+    let network = Ipv4Network::new(ROUTED_NETWORK, 24).expect("valid prefix length");
+    let route = RequiredRoute::new(network.into(), Node::device(interface.to_owned()));
+    route_manager
+        .add_routes(HashSet::from([route]))
+        .await
+        .map_err(Error::AddRoute)?;
+    route_manager.clear_routes().map_err(Error::RemoveRoute)
+}
+
+fn set_and_restore_dns(dns_monitor: &mut DnsMonitor, interface: &str) -> Result<(), Error> {
+    dns_monitor
+        .set(interface, &[DNS_SERVER.into()])
+        .map_err(Error::SetDns)?;
+    dns_monitor.reset().map_err(Error::ResetDns)
+}
+
+/// Apply the blocking policy and then restore the policy of the disconnected state. With lockdown
+/// mode enabled, the blocking policy is the policy of the disconnected state, so it is left in
+/// place. Resetting the firewall would let traffic through until the state is entered again.
+fn apply_and_restore_firewall(shared_values: &mut SharedTunnelStateValues) -> Result<(), Error> {
+    let policy = FirewallPolicy::Blocked {
+        allow_lan: shared_values.allow_lan,
+        allowed_endpoint: Some(shared_values.allowed_endpoint.clone()),
+        #[cfg(target_os = "macos")]
+        dns_redirect_port: shared_values.filtering_resolver.listening_port(),
+    };
+    shared_values
+        .firewall
+        .apply_policy(policy)
+        .map_err(Error::ApplyFirewall)?;
+    if shared_values.block_when_disconnected {
+        return Ok(());
+    }
+    shared_values
+        .firewall
+        .reset_policy()
+        .map_err(Error::ResetFirewall)
+}
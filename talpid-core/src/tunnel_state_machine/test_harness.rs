@@ -0,0 +1,733 @@
+//! A driver for the tunnel state machine that does not touch the host. The firewall, DNS and
+//! routing are replaced with mocks that only record what would have been applied, and tunnels are
+//! started by a mock [`TunnelProvider`] that lets the test decide when they come up or go down.
+//! This makes it possible to test state transitions without root or a real network.
+//!
+//! The state machine only waits on the tokio clock. If the test runs with paused time, e.g. using
+//! `#[tokio::test(start_paused = true)]`, the clock does not move on its own while the state
+//! machine is running. Delays, such as the minimum time between two connection attempts, then
+//! only pass when [`TestHarness::advance`] is called.
+//!
+//! The DNS filter is not mocked, so setting a [`TunnelCommand::DnsBlocklist`] still requires
+//! permission to listen on port 53.
+
+use super::{
+    DisconnectedState, Error, InitialTunnelState, LinkEvent, SharedTunnelStateValues,
+    TunnelCommand, TunnelParametersGenerator, TunnelStateMachine,
+};
+#[cfg(target_os = "macos")]
+use crate::split_tunnel;
+use crate::{
+    dns::DnsMonitor,
+    firewall::{Firewall, FirewallPolicy},
+    link_monitor, offline,
+    tunnel::{self, RunningTunnel, TunnelEventCallback, TunnelProvider, TunnelProviderArgs},
+};
+use futures::{
+    channel::{mpsc, oneshot},
+    FutureExt, StreamExt,
+};
+use std::{
+    net::IpAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use talpid_routing::RouteManagerHandle;
+use talpid_tunnel::{tun_provider::TunProvider, TunnelEvent, TunnelMetadata};
+use talpid_types::{
+    net::{Connectivity, TunnelParameters},
+    tunnel::{TeardownReport, TunnelStateTransition},
+};
+
+/// Runs a tunnel state machine against mocked system components.
+pub struct TestHarness {
+    command_tx: Arc<mpsc::UnboundedSender<TunnelCommand>>,
+    transition_rx: mpsc::UnboundedReceiver<TunnelStateTransition>,
+    tunnel_rx: mpsc::UnboundedReceiver<MockTunnel>,
+    firewall_policy: Arc<Mutex<Option<FirewallPolicy>>>,
+    dns_servers: Arc<Mutex<Option<Vec<IpAddr>>>>,
+    shutdown_rx: oneshot::Receiver<TeardownReport>,
+}
+
+impl TestHarness {
+    /// Start a tunnel state machine in the disconnected state. Tunnels are started with
+    /// parameters from `tunnel_parameters_generator`.
+    pub async fn spawn(
+        initial_settings: InitialTunnelState,
+        tunnel_parameters_generator: impl TunnelParametersGenerator,
+    ) -> Result<Self, Error> {
+        let (command_tx, command_rx) = mpsc::unbounded();
+        let command_tx = Arc::new(command_tx);
+        let (transition_tx, transition_rx) = mpsc::unbounded();
+        let (tunnel_tx, tunnel_rx) = mpsc::unbounded();
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+
+        let firewall_policy = Arc::new(Mutex::new(None));
+        let dns_servers = Arc::new(Mutex::new(None));
+
+        let runtime = tokio::runtime::Handle::current();
+        let route_manager = RouteManagerHandle::spawn_mock();
+
+        #[cfg(target_os = "macos")]
+        let split_tunnel =
+            split_tunnel::SplitTunnel::spawn(Arc::downgrade(&command_tx), route_manager.clone());
+        #[cfg(target_os = "macos")]
+        let filtering_resolver = crate::resolver::start_resolver().await?;
+
+        let mut shared_values = SharedTunnelStateValues {
+            #[cfg(target_os = "macos")]
+            split_tunnel,
+            runtime,
+            firewall: Firewall::mock(firewall_policy.clone()),
+            dns_monitor: DnsMonitor::mock(dns_servers.clone()),
+            route_manager,
+            _offline_monitor: offline::MonitorHandle::disabled(),
+            _link_monitor: link_monitor::MonitorHandle::disabled(),
+            allow_lan: initial_settings.allow_lan,
+            block_when_disconnected: initial_settings.block_when_disconnected,
+            connectivity: Connectivity::PresumeOnline,
+            dns_servers: initial_settings.dns_servers,
+            dns_blocklist: None,
+            dns_filter: None,
+            allowed_endpoint: initial_settings.allowed_endpoint,
+            tunnel_parameters_generator: Box::new(tunnel_parameters_generator),
+            require_obfuscation: false,
+            tunnel_provider: Arc::new(MockTunnelProvider { tunnel_tx }),
+            tun_provider: Arc::new(Mutex::new(TunProvider::new())),
+            log_dir: None,
+            resource_dir: PathBuf::new(),
+            connect_progress_tx: None,
+            teardown_report: TeardownReport::default(),
+            #[cfg(target_os = "linux")]
+            manage_connectivity_check: false,
+            #[cfg(target_os = "linux")]
+            connectivity_check_was_enabled: None,
+            #[cfg(target_os = "macos")]
+            filtering_resolver,
+            #[cfg(target_os = "linux")]
+            block_on_unsupported_dns_manager: initial_settings.block_on_unsupported_dns_manager,
+        };
+
+        tokio::task::spawn_blocking(move || {
+            let (initial_state, _) =
+                DisconnectedState::enter(&mut shared_values, initial_settings.reset_firewall);
+            let state_machine = TunnelStateMachine {
+                current_state: Some(initial_state),
+                commands: command_rx.fuse(),
+                shared_values,
+            };
+            let _ = shutdown_tx.send(state_machine.run(transition_tx));
+        });
+
+        Ok(TestHarness {
+            command_tx,
+            transition_rx,
+            tunnel_rx,
+            firewall_policy,
+            dns_servers,
+            shutdown_rx,
+        })
+    }
+
+    /// Send a command to the state machine.
+    pub fn send(&self, command: TunnelCommand) {
+        self.command_tx
+            .unbounded_send(command)
+            .expect("Tunnel state machine has stopped");
+    }
+
+    /// Tell the state machine to connect.
+    pub fn connect(&self) {
+        self.send(TunnelCommand::Connect);
+    }
+
+    /// Tell the state machine to disconnect.
+    pub fn disconnect(&self) {
+        self.send(TunnelCommand::Disconnect);
+    }
+
+    /// Pretend that the connectivity of the host changed.
+    pub fn set_connectivity(&self, connectivity: Connectivity) {
+        self.send(TunnelCommand::Connectivity(connectivity));
+    }
+
+    /// Pretend that something happened that is likely to have broken the tunnel.
+    pub fn link_changed(&self, event: LinkEvent) {
+        self.send(TunnelCommand::LinkChanged(event));
+    }
+
+    /// Wait for the next state transition. Returns `None` if the state machine has stopped.
+    pub async fn next_transition(&mut self) -> Option<TunnelStateTransition> {
+        self.transition_rx.next().await
+    }
+
+    /// Wait for the state machine to start a tunnel. Returns `None` if the state machine has
+    /// stopped.
+    pub async fn next_tunnel(&mut self) -> Option<MockTunnel> {
+        self.tunnel_rx.next().await
+    }
+
+    /// Returns the firewall policy that is currently applied, if any.
+    pub fn firewall_policy(&self) -> Option<FirewallPolicy> {
+        self.firewall_policy.lock().unwrap().clone()
+    }
+
+    /// Returns the DNS servers that the host is currently configured to use, if they have been
+    /// changed.
+    pub fn dns_servers(&self) -> Option<Vec<IpAddr>> {
+        self.dns_servers.lock().unwrap().clone()
+    }
+
+    /// Move the clock forward by `duration`. This requires time to be paused.
+    pub async fn advance(&self, duration: Duration) {
+        tokio::time::advance(duration).await;
+    }
+
+    /// Stop the state machine and wait for it to exit. Returns the outcome of restoring the
+    /// network.
+    pub async fn shutdown(self) -> Option<TeardownReport> {
+        drop(self.command_tx);
+        self.shutdown_rx.await.ok()
+    }
+}
+
+/// Starts [`MockTunnel`]s and hands them to the [`TestHarness`].
+struct MockTunnelProvider {
+    tunnel_tx: mpsc::UnboundedSender<MockTunnel>,
+}
+
+impl TunnelProvider for MockTunnelProvider {
+    fn spawn(
+        &self,
+        parameters: &mut TunnelParameters,
+        args: TunnelProviderArgs<'_>,
+    ) -> tunnel::Result<Box<dyn RunningTunnel>> {
+        let (exit_tx, exit_rx) = oneshot::channel();
+        let reconfigured = Arc::new(Mutex::new(None));
+        let _ = self.tunnel_tx.unbounded_send(MockTunnel {
+            parameters: parameters.clone(),
+            retry_attempt: args.retry_attempt,
+            on_event: args.on_event,
+            exit_tx,
+            reconfigured: reconfigured.clone(),
+        });
+        Ok(Box::new(MockRunningTunnel {
+            runtime: args.runtime,
+            exit: Mutex::new(Some((args.tunnel_close_rx, exit_rx))),
+            reconfigured,
+        }))
+    }
+}
+
+type MockExit = (oneshot::Receiver<()>, oneshot::Receiver<tunnel::Result<()>>);
+
+struct MockRunningTunnel {
+    runtime: tokio::runtime::Handle,
+    exit: Mutex<Option<MockExit>>,
+    reconfigured: Arc<Mutex<Option<TunnelParameters>>>,
+}
+
+impl RunningTunnel for MockRunningTunnel {
+    fn reconfigure(&self, parameters: &TunnelParameters) -> tunnel::Result<()> {
+        *self.reconfigured.lock().unwrap() = Some(parameters.clone());
+        Ok(())
+    }
+
+    fn wait(&self) -> tunnel::Result<()> {
+        let (close_rx, exit_rx) = self
+            .exit
+            .lock()
+            .unwrap()
+            .take()
+            .expect("Tunnel has already been waited for");
+        self.runtime.block_on(async move {
+            futures::select! {
+                _ = close_rx.fuse() => Ok(()),
+                // The tunnel is also closed if the test drops it
+                result = exit_rx.fuse() => result.unwrap_or(Ok(())),
+            }
+        })
+    }
+}
+
+/// A tunnel started by the state machine. The test controls it by reporting tunnel events and
+/// by making it exit. Dropping it closes the tunnel.
+pub struct MockTunnel {
+    parameters: TunnelParameters,
+    retry_attempt: u32,
+    on_event: TunnelEventCallback,
+    exit_tx: oneshot::Sender<tunnel::Result<()>>,
+    reconfigured: Arc<Mutex<Option<TunnelParameters>>>,
+}
+
+impl MockTunnel {
+    /// Returns the parameters that the tunnel was started with.
+    pub fn parameters(&self) -> &TunnelParameters {
+        &self.parameters
+    }
+
+    /// Returns the parameters that the running tunnel was last reconfigured with, if any.
+    pub fn reconfigured_parameters(&self) -> Option<TunnelParameters> {
+        self.reconfigured.lock().unwrap().clone()
+    }
+
+    /// Returns the number of failed connection attempts that preceded this tunnel.
+    pub fn retry_attempt(&self) -> u32 {
+        self.retry_attempt
+    }
+
+    /// Report an event to the state machine and wait for it to be handled.
+    pub async fn send_event(&self, event: TunnelEvent) {
+        (self.on_event)(event).await;
+    }
+
+    /// Report that the tunnel is up and ready for traffic.
+    pub async fn up(&self, metadata: TunnelMetadata) {
+        self.send_event(TunnelEvent::Up(metadata)).await;
+    }
+
+    /// Make the tunnel exit with `result`, as if it had stopped on its own.
+    pub fn exit(self, result: tunnel::Result<()>) {
+        let _ = self.exit_tx.send(result);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{
+        future::Future,
+        net::{Ipv4Addr, SocketAddr},
+        pin::Pin,
+    };
+    use talpid_types::{
+        net::{
+            openvpn,
+            wireguard::{self, PrivateKey},
+            AllowedClients, AllowedEndpoint, Endpoint, GenericTunnelOptions, TransportProtocol,
+        },
+        tunnel::{ActionAfterDisconnect, ParameterGenerationError, TeardownStep},
+    };
+
+    struct StaticParameters(TunnelParameters);
+
+    impl TunnelParametersGenerator for StaticParameters {
+        fn generate(
+            &mut self,
+            _retry_attempt: u32,
+            _ipv6: bool,
+            _require_obfuscation: bool,
+        ) -> Pin<Box<dyn Future<Output = Result<TunnelParameters, ParameterGenerationError>>>>
+        {
+            let parameters = self.0.clone();
+            Box::pin(async move { Ok(parameters) })
+        }
+    }
+
+    fn parameters() -> TunnelParameters {
+        let endpoint = Endpoint {
+            address: SocketAddr::new(Ipv4Addr::new(192, 0, 2, 1).into(), 1194),
+            protocol: TransportProtocol::Udp,
+        };
+        TunnelParameters::OpenVpn(openvpn::TunnelParameters {
+            config: openvpn::ConnectionConfig::new(endpoint, String::new(), String::new()),
+            options: openvpn::TunnelOptions::default(),
+            generic_options: GenericTunnelOptions { enable_ipv6: false },
+            proxy: None,
+            ca: None,
+            privilege_drop: None,
+            #[cfg(target_os = "linux")]
+            fwmark: 0,
+        })
+    }
+
+    fn wireguard_parameters() -> TunnelParameters {
+        TunnelParameters::Wireguard(wireguard::TunnelParameters {
+            connection: wireguard::ConnectionConfig {
+                tunnel: wireguard::TunnelConfig {
+                    private_key: PrivateKey::new_from_random(),
+                    addresses: vec![Ipv4Addr::new(10, 64, 0, 2).into()],
+                },
+                peer: wireguard::PeerConfig {
+                    public_key: PrivateKey::new_from_random().public_key(),
+                    allowed_ips: vec!["0.0.0.0/0".parse().unwrap()],
+                    endpoint: SocketAddr::new(Ipv4Addr::new(192, 0, 2, 1).into(), 51820),
+                    alternate_endpoint: None,
+                    psk: None,
+                    #[cfg(target_os = "windows")]
+                    constant_packet_size: false,
+                },
+                exit_peer: None,
+                candidate_peer: None,
+                ipv4_gateway: Ipv4Addr::new(10, 64, 0, 1),
+                ipv6_gateway: None,
+                #[cfg(target_os = "linux")]
+                fwmark: None,
+            },
+            options: wireguard::TunnelOptions {
+                mtu: None,
+                quantum_resistant: false,
+                #[cfg(target_os = "windows")]
+                daita: false,
+                #[cfg(target_os = "linux")]
+                egress_interface: None,
+            },
+            generic_options: GenericTunnelOptions { enable_ipv6: false },
+            obfuscation: None,
+        })
+    }
+
+    fn metadata() -> TunnelMetadata {
+        TunnelMetadata {
+            interface: "tun0".to_owned(),
+            ips: vec![Ipv4Addr::new(10, 8, 0, 2).into()],
+            ipv4_gateway: Ipv4Addr::new(10, 8, 0, 1),
+            ipv6_gateway: None,
+            data_channel_offload: false,
+        }
+    }
+
+    fn initial_settings() -> InitialTunnelState {
+        InitialTunnelState {
+            allow_lan: false,
+            block_when_disconnected: false,
+            dns_servers: None,
+            allowed_endpoint: AllowedEndpoint {
+                endpoint: Endpoint {
+                    address: SocketAddr::new(Ipv4Addr::new(192, 0, 2, 2).into(), 443),
+                    protocol: TransportProtocol::Tcp,
+                },
+                clients: AllowedClients::Root,
+            },
+            reset_firewall: true,
+            #[cfg(target_os = "linux")]
+            block_on_unsupported_dns_manager: false,
+            #[cfg(target_os = "macos")]
+            exclude_paths: vec![],
+        }
+    }
+
+    fn paused_runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .start_paused(true)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_connect_and_disconnect() {
+        paused_runtime().block_on(connect_and_disconnect());
+    }
+
+    async fn connect_and_disconnect() {
+        let mut harness = TestHarness::spawn(initial_settings(), StaticParameters(parameters()))
+            .await
+            .unwrap();
+
+        harness.connect();
+        assert!(matches!(
+            harness.next_transition().await,
+            Some(TunnelStateTransition::Connecting(_))
+        ));
+        assert!(matches!(
+            harness.firewall_policy(),
+            Some(FirewallPolicy::Connecting { .. })
+        ));
+
+        let tunnel = harness.next_tunnel().await.unwrap();
+        assert_eq!(tunnel.retry_attempt(), 0);
+        tunnel.up(metadata()).await;
+        assert!(matches!(
+            harness.next_transition().await,
+            Some(TunnelStateTransition::Connected(..))
+        ));
+        assert!(matches!(
+            harness.firewall_policy(),
+            Some(FirewallPolicy::Connected { .. })
+        ));
+        assert_eq!(
+            harness.dns_servers(),
+            Some(vec![Ipv4Addr::new(10, 8, 0, 1).into()])
+        );
+
+        harness.disconnect();
+        assert!(matches!(
+            harness.next_transition().await,
+            Some(TunnelStateTransition::Disconnecting(_))
+        ));
+        // The tunnel is kept around for a minimum amount of time
+        harness.advance(Duration::from_secs(1)).await;
+        assert!(matches!(
+            harness.next_transition().await,
+            Some(TunnelStateTransition::Disconnected { .. })
+        ));
+        assert_eq!(harness.firewall_policy(), None);
+        assert_eq!(harness.dns_servers(), None);
+
+        harness.shutdown().await;
+    }
+
+    /// The tunnel is restarted right away when the host resumes from sleep.
+    #[test]
+    fn test_reconnect_on_resume() {
+        paused_runtime().block_on(reconnect_on_resume());
+    }
+
+    async fn reconnect_on_resume() {
+        let mut harness = TestHarness::spawn(initial_settings(), StaticParameters(parameters()))
+            .await
+            .unwrap();
+
+        harness.connect();
+        assert!(matches!(
+            harness.next_transition().await,
+            Some(TunnelStateTransition::Connecting(_))
+        ));
+        let tunnel = harness.next_tunnel().await.unwrap();
+        tunnel.up(metadata()).await;
+        assert!(matches!(
+            harness.next_transition().await,
+            Some(TunnelStateTransition::Connected(..))
+        ));
+
+        harness.link_changed(LinkEvent::Resumed);
+        assert!(matches!(
+            harness.next_transition().await,
+            Some(TunnelStateTransition::Disconnecting(
+                ActionAfterDisconnect::Reconnect
+            ))
+        ));
+        harness.advance(Duration::from_secs(1)).await;
+        assert!(matches!(
+            harness.next_transition().await,
+            Some(TunnelStateTransition::Connecting(_))
+        ));
+        assert_eq!(harness.next_tunnel().await.unwrap().retry_attempt(), 0);
+
+        harness.shutdown().await;
+    }
+
+    /// An abandoned connection attempt is followed by the next attempt rather than a new first
+    /// attempt.
+    #[test]
+    fn test_escalate_while_connecting() {
+        paused_runtime().block_on(escalate_while_connecting());
+    }
+
+    async fn escalate_while_connecting() {
+        let mut harness = TestHarness::spawn(initial_settings(), StaticParameters(parameters()))
+            .await
+            .unwrap();
+
+        harness.connect();
+        assert!(matches!(
+            harness.next_transition().await,
+            Some(TunnelStateTransition::Connecting(_))
+        ));
+        let _tunnel = harness.next_tunnel().await.unwrap();
+
+        harness.send(TunnelCommand::Escalate);
+        assert!(matches!(
+            harness.next_transition().await,
+            Some(TunnelStateTransition::Disconnecting(
+                ActionAfterDisconnect::Reconnect
+            ))
+        ));
+        harness.advance(Duration::from_secs(1)).await;
+        assert!(matches!(
+            harness.next_transition().await,
+            Some(TunnelStateTransition::Connecting(_))
+        ));
+        let tunnel = harness.next_tunnel().await.unwrap();
+        assert_eq!(tunnel.retry_attempt(), 1);
+
+        // Once connected, there is nothing to abandon
+        tunnel.up(metadata()).await;
+        assert!(matches!(
+            harness.next_transition().await,
+            Some(TunnelStateTransition::Connected(..))
+        ));
+        harness.send(TunnelCommand::Escalate);
+        harness.disconnect();
+        assert!(matches!(
+            harness.next_transition().await,
+            Some(TunnelStateTransition::Disconnecting(
+                ActionAfterDisconnect::Nothing
+            ))
+        ));
+
+        harness.shutdown().await;
+    }
+
+    /// Shutting down with a tunnel up reports every step of restoring the network.
+    #[test]
+    fn test_teardown_report() {
+        paused_runtime().block_on(teardown_report());
+    }
+
+    async fn teardown_report() {
+        let mut harness = TestHarness::spawn(initial_settings(), StaticParameters(parameters()))
+            .await
+            .unwrap();
+
+        harness.connect();
+        assert!(matches!(
+            harness.next_transition().await,
+            Some(TunnelStateTransition::Connecting(_))
+        ));
+        let tunnel = harness.next_tunnel().await.unwrap();
+        tunnel.up(metadata()).await;
+        assert!(matches!(
+            harness.next_transition().await,
+            Some(TunnelStateTransition::Connected(..))
+        ));
+
+        drop(harness.command_tx);
+        assert!(matches!(
+            harness.next_transition().await,
+            Some(TunnelStateTransition::Disconnecting(
+                ActionAfterDisconnect::Nothing
+            ))
+        ));
+        // The tunnel is kept around for a minimum amount of time
+        tokio::time::advance(Duration::from_secs(1)).await;
+        let report = harness.shutdown_rx.await.unwrap();
+
+        assert_eq!(
+            report
+                .steps
+                .iter()
+                .map(|result| result.step)
+                .collect::<Vec<_>>(),
+            [
+                TeardownStep::TunnelStop,
+                TeardownStep::FirewallReset,
+                TeardownStep::DnsRestore,
+                TeardownStep::RouteRestore,
+            ]
+        );
+        assert!(report.succeeded());
+        assert_eq!(*harness.firewall_policy.lock().unwrap(), None);
+    }
+
+    /// A new private key is applied to a connected WireGuard tunnel without reconnecting.
+    #[test]
+    fn test_set_private_key() {
+        paused_runtime().block_on(set_private_key());
+    }
+
+    async fn set_private_key() {
+        let mut harness =
+            TestHarness::spawn(initial_settings(), StaticParameters(wireguard_parameters()))
+                .await
+                .unwrap();
+
+        harness.connect();
+        assert!(matches!(
+            harness.next_transition().await,
+            Some(TunnelStateTransition::Connecting(_))
+        ));
+        let tunnel = harness.next_tunnel().await.unwrap();
+
+        // The caller has to reconnect until the tunnel is up
+        let (result_tx, result_rx) = oneshot::channel();
+        harness.send(TunnelCommand::SetPrivateKey(
+            PrivateKey::new_from_random(),
+            result_tx,
+        ));
+        assert!(!result_rx.await.unwrap());
+
+        tunnel.up(metadata()).await;
+        assert!(matches!(
+            harness.next_transition().await,
+            Some(TunnelStateTransition::Connected(..))
+        ));
+
+        let private_key = PrivateKey::new_from_random();
+        let (result_tx, result_rx) = oneshot::channel();
+        harness.send(TunnelCommand::SetPrivateKey(private_key.clone(), result_tx));
+        assert!(result_rx.await.unwrap());
+        let Some(TunnelParameters::Wireguard(parameters)) = tunnel.reconfigured_parameters() else {
+            panic!("Tunnel was not reconfigured");
+        };
+        assert_eq!(parameters.connection.tunnel.private_key, private_key);
+
+        harness.disconnect();
+        assert!(matches!(
+            harness.next_transition().await,
+            Some(TunnelStateTransition::Disconnecting(
+                ActionAfterDisconnect::Nothing
+            ))
+        ));
+
+        harness.shutdown().await;
+    }
+
+    /// Only WireGuard tunnels can be given a new private key.
+    #[test]
+    fn test_set_private_key_openvpn() {
+        paused_runtime().block_on(set_private_key_openvpn());
+    }
+
+    async fn set_private_key_openvpn() {
+        let mut harness = TestHarness::spawn(initial_settings(), StaticParameters(parameters()))
+            .await
+            .unwrap();
+
+        harness.connect();
+        assert!(matches!(
+            harness.next_transition().await,
+            Some(TunnelStateTransition::Connecting(_))
+        ));
+        let tunnel = harness.next_tunnel().await.unwrap();
+        tunnel.up(metadata()).await;
+        assert!(matches!(
+            harness.next_transition().await,
+            Some(TunnelStateTransition::Connected(..))
+        ));
+
+        let (result_tx, result_rx) = oneshot::channel();
+        harness.send(TunnelCommand::SetPrivateKey(
+            PrivateKey::new_from_random(),
+            result_tx,
+        ));
+        assert!(!result_rx.await.unwrap());
+        assert_eq!(tunnel.reconfigured_parameters(), None);
+
+        harness.shutdown().await;
+    }
+
+    /// A tunnel that fails with an error from its provider is retried.
+    #[test]
+    fn test_provider_error() {
+        paused_runtime().block_on(provider_error());
+    }
+
+    async fn provider_error() {
+        let mut harness = TestHarness::spawn(initial_settings(), StaticParameters(parameters()))
+            .await
+            .unwrap();
+
+        harness.connect();
+        assert!(matches!(
+            harness.next_transition().await,
+            Some(TunnelStateTransition::Connecting(_))
+        ));
+        let tunnel = harness.next_tunnel().await.unwrap();
+        tunnel.exit(Err(tunnel::Error::ProviderError(
+            "the backend went away".into(),
+        )));
+
+        // The tunnel is kept around for a minimum amount of time
+        harness.advance(Duration::from_secs(1)).await;
+        assert!(matches!(
+            harness.next_transition().await,
+            Some(TunnelStateTransition::Connecting(_))
+        ));
+        assert_eq!(harness.next_tunnel().await.unwrap().retry_attempt(), 1);
+
+        harness.shutdown().await;
+    }
+}
@@ -0,0 +1,133 @@
+//! Sockets that are kept outside of the tunnel, for traffic that the app itself sends to the
+//! internet, such as obfuscation, probes of relays and, on Android, API requests.
+//!
+//! How a socket is kept outside of the tunnel depends on the platform:
+//! - On Linux, the socket is given the firewall mark that the routing rules and the firewall
+//!   exempt from the tunnel. It may also be bound to a specific interface.
+//! - On Android, the socket is passed to `VpnService.protect()` by the daemon.
+//! - On macOS and Windows, the socket may be bound to the interface of the default route that does
+//!   not go through the tunnel. The firewall permits the traffic of the daemon and of its bundled
+//!   helpers to the relay based on the path of the executable.
+
+#[cfg(target_os = "android")]
+use futures::{
+    channel::{mpsc, oneshot},
+    SinkExt,
+};
+use socket2::SockRef;
+use std::io;
+#[cfg(target_os = "android")]
+use std::os::unix::io::{AsRawFd, RawFd};
+
+#[cfg(target_os = "macos")]
+#[path = "macos.rs"]
+mod imp;
+
+#[cfg(target_os = "windows")]
+#[path = "windows.rs"]
+mod imp;
+
+/// Request to exclude the socket with the given file descriptor from the tunnel. The sender is
+/// signalled when it is done.
+#[cfg(target_os = "android")]
+pub type SocketBypassRequest = (RawFd, oneshot::Sender<()>);
+
+/// Describes how to keep sockets outside of the tunnel. The default leaves sockets as they are.
+#[derive(Clone, Debug, Default)]
+pub struct SocketProtection {
+    #[cfg(target_os = "linux")]
+    fwmark: Option<u32>,
+    #[cfg(target_os = "linux")]
+    device: Option<String>,
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    physical_interface: bool,
+    #[cfg(target_os = "android")]
+    bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>,
+}
+
+impl SocketProtection {
+    /// Give sockets the firewall mark `fwmark`.
+    #[cfg(target_os = "linux")]
+    pub fn with_fwmark(fwmark: Option<u32>) -> Self {
+        Self {
+            fwmark,
+            device: None,
+        }
+    }
+
+    /// Also bind sockets to the interface named `device`, so that they never leave through any
+    /// other interface.
+    #[cfg(target_os = "linux")]
+    pub fn bind_to_device(self, device: Option<String>) -> Self {
+        Self { device, ..self }
+    }
+
+    /// The firewall mark that sockets are given, if any. This is for sockets that are created by
+    /// other libraries, which have to be given the mark by those libraries.
+    #[cfg(target_os = "linux")]
+    pub fn fwmark(&self) -> Option<u32> {
+        self.fwmark
+    }
+
+    /// Bind sockets to the interface of the default route that does not go through the tunnel.
+    /// The interface is looked up each time a socket is protected, so that it follows changes of
+    /// the default route.
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    pub fn bind_to_physical_interface() -> Self {
+        Self {
+            physical_interface: true,
+        }
+    }
+
+    /// Ask the daemon to exclude sockets from the tunnel, by sending them to `bypass_tx`.
+    #[cfg(target_os = "android")]
+    pub fn with_bypass_tx(bypass_tx: Option<mpsc::Sender<SocketBypassRequest>>) -> Self {
+        Self { bypass_tx }
+    }
+
+    /// Keep `socket` outside of the tunnel. This must be done before anything is sent on it.
+    #[cfg_attr(
+        not(any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "macos",
+            target_os = "windows"
+        )),
+        allow(unused_variables)
+    )]
+    // Only excluding sockets on Android has to wait for anything
+    #[cfg_attr(not(target_os = "android"), allow(clippy::unused_async))]
+    pub async fn protect<S>(&self, socket: &S) -> io::Result<()>
+    where
+        for<'s> SockRef<'s>: From<&'s S>,
+    {
+        #[cfg(target_os = "linux")]
+        if let Some(fwmark) = self.fwmark {
+            SockRef::from(socket).set_mark(fwmark)?;
+        }
+        #[cfg(target_os = "linux")]
+        if let Some(device) = &self.device {
+            SockRef::from(socket).bind_device(Some(device.as_bytes()))?;
+        }
+
+        #[cfg(any(target_os = "macos", target_os = "windows"))]
+        if self.physical_interface {
+            imp::bind_to_physical_interface(&SockRef::from(socket))?;
+        }
+
+        #[cfg(target_os = "android")]
+        if let Some(mut bypass_tx) = self.bypass_tx.clone() {
+            let fd = SockRef::from(socket).as_raw_fd();
+            let (done_tx, done_rx) = oneshot::channel();
+            let _ = bypass_tx.send((fd, done_tx)).await;
+            done_rx.await.map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::Other,
+                    "Failed to exclude socket from the tunnel",
+                )
+            })?;
+        }
+
+        Ok(())
+    }
+}
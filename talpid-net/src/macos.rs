@@ -0,0 +1,51 @@
+use nix::net::if_::if_nametoindex;
+use socket2::SockRef;
+use std::{io, num::NonZeroU32};
+use system_configuration::{
+    core_foundation::{
+        base::{CFType, TCFType, ToVoid},
+        dictionary::CFDictionary,
+        string::CFString,
+    },
+    dynamic_store::SCDynamicStoreBuilder,
+    sys::schema_definitions::kSCDynamicStorePropNetPrimaryInterface,
+};
+
+const STATE_IPV4_KEY: &str = "State:/Network/Global/IPv4";
+const STATE_IPV6_KEY: &str = "State:/Network/Global/IPv6";
+
+/// Bind `socket` to the primary interface for its address family. The tunnel interface is never
+/// the primary interface, since it is not a network service.
+pub fn bind_to_physical_interface(socket: &SockRef<'_>) -> io::Result<()> {
+    // The option differs between IPv4 and IPv6 sockets
+    let is_ipv6 = socket.local_addr()?.is_ipv6();
+    let index = primary_interface_index(is_ipv6)?;
+    if is_ipv6 {
+        socket.bind_device_by_index_v6(Some(index))
+    } else {
+        socket.bind_device_by_index_v4(Some(index))
+    }
+}
+
+fn primary_interface_index(is_ipv6: bool) -> io::Result<NonZeroU32> {
+    let key = if is_ipv6 {
+        STATE_IPV6_KEY
+    } else {
+        STATE_IPV4_KEY
+    };
+    let store = SCDynamicStoreBuilder::new("talpid-net").build();
+    let name = store
+        .get(key)
+        .and_then(|v| v.downcast_into::<CFDictionary>())
+        .and_then(|ip_dict| {
+            ip_dict
+                .find(unsafe { kSCDynamicStorePropNetPrimaryInterface }.to_void())
+                .map(|s| unsafe { CFType::wrap_under_get_rule(*s) })
+        })
+        .and_then(|s| s.downcast::<CFString>())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No primary interface"))?
+        .to_string();
+    let index = if_nametoindex(name.as_str())?;
+    NonZeroU32::new(index)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Invalid interface index"))
+}
@@ -0,0 +1,69 @@
+use socket2::SockRef;
+use std::{ffi::c_int, io, mem, os::windows::io::AsRawSocket};
+use talpid_routing::get_best_default_route;
+use talpid_windows::net::{index_from_luid, AddressFamily};
+use windows_sys::Win32::Networking::WinSock::{
+    getsockopt, setsockopt, AF_INET6, IPPROTO_IP, IPPROTO_IPV6, IPV6_UNICAST_IF, IP_UNICAST_IF,
+    SOCKET_ERROR, SOL_SOCKET, SO_PROTOCOL_INFOW, WSAPROTOCOL_INFOW,
+};
+
+/// Bind `socket` to the interface of the best default route that is not on a tunnel interface.
+pub fn bind_to_physical_interface(socket: &SockRef<'_>) -> io::Result<()> {
+    let raw_sock = usize::try_from(socket.as_raw_socket()).unwrap();
+    let family = address_family(raw_sock)?;
+    let route = get_best_default_route(family)
+        .map_err(|error| io::Error::new(io::ErrorKind::Other, error))?
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "No default route outside the tunnel",
+            )
+        })?;
+    let index = index_from_luid(&route.iface)?;
+
+    // The option differs between IPv4 and IPv6 sockets. For IPv4, the index is in network byte
+    // order.
+    let (level, option, index) = match family {
+        AddressFamily::Ipv4 => (IPPROTO_IP, IP_UNICAST_IF, index.to_be()),
+        AddressFamily::Ipv6 => (IPPROTO_IPV6, IPV6_UNICAST_IF, index),
+    };
+    // SAFETY: `index` is a valid u32 and the length passed is its size
+    let result = unsafe {
+        setsockopt(
+            raw_sock,
+            level,
+            option,
+            &index as *const _ as _,
+            c_int::try_from(mem::size_of_val(&index)).unwrap(),
+        )
+    };
+    if result == SOCKET_ERROR {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn address_family(raw_sock: usize) -> io::Result<AddressFamily> {
+    // SAFETY: WSAPROTOCOL_INFOW only contains integers and arrays of integers, for which all zeroes
+    // is a valid value
+    let mut info: WSAPROTOCOL_INFOW = unsafe { mem::zeroed() };
+    let mut len = c_int::try_from(mem::size_of_val(&info)).unwrap();
+    // SAFETY: `info` is valid for writes of `len` bytes
+    let result = unsafe {
+        getsockopt(
+            raw_sock,
+            SOL_SOCKET,
+            SO_PROTOCOL_INFOW,
+            &mut info as *mut _ as _,
+            &mut len,
+        )
+    };
+    if result == SOCKET_ERROR {
+        return Err(io::Error::last_os_error());
+    }
+    if info.iAddressFamily == i32::from(AF_INET6) {
+        Ok(AddressFamily::Ipv6)
+    } else {
+        Ok(AddressFamily::Ipv4)
+    }
+}
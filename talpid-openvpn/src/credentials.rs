@@ -0,0 +1,123 @@
+//! Credentials that OpenVPN reads from a file, such as the `--auth-user-pass` file.
+//!
+//! On Unix, the credentials are written to a pipe that OpenVPN inherits and opens through
+//! `/dev/fd`, so they never touch the disk. On Windows, they are written to a temporary file that
+//! is removed once the tunnel is up.
+
+#[cfg(windows)]
+use crate::mktemp::TempFile;
+#[cfg(windows)]
+use std::fs;
+#[cfg(unix)]
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+};
+
+/// A username and password that OpenVPN can read from [`Credentials::path`].
+#[derive(Debug)]
+pub struct Credentials {
+    /// Read end of the pipe that holds the credentials. It must be inherited by OpenVPN.
+    #[cfg(unix)]
+    read_end: OwnedFd,
+    #[cfg(windows)]
+    file: TempFile,
+}
+
+impl Credentials {
+    /// Writes `username` and `password` to a new pipe.
+    #[cfg(unix)]
+    pub fn new(username: &str, password: &str) -> io::Result<Self> {
+        let (read_end, write_end) = pipe()?;
+        // Fail rather than block if the credentials don't fit in the pipe, since nothing reads
+        // from it until OpenVPN is running.
+        set_nonblocking(&write_end)?;
+        let mut writer = std::fs::File::from(write_end);
+        write!(writer, "{username}\n{password}\n")?;
+        Ok(Credentials { read_end })
+    }
+
+    /// Writes `username` and `password` to a new temporary file.
+    #[cfg(windows)]
+    pub fn new(username: &str, password: &str) -> io::Result<Self> {
+        let file = TempFile::new();
+        log::debug!("Writing credentials to {}", file.as_ref().display());
+        let mut writer = fs::File::create(&file)?;
+        // TODO(linus): Lock permissions correctly on Windows.
+        write!(writer, "{username}\n{password}\n")?;
+        Ok(Credentials { file })
+    }
+
+    /// Path that OpenVPN should read the credentials from.
+    pub fn path(&self) -> PathBuf {
+        #[cfg(unix)]
+        {
+            PathBuf::from(format!("/dev/fd/{}", self.read_end.as_raw_fd()))
+        }
+        #[cfg(windows)]
+        {
+            self.file.to_path_buf()
+        }
+    }
+
+    /// File descriptor that OpenVPN has to inherit in order to read from [`Credentials::path`].
+    #[cfg(unix)]
+    pub fn fd(&self) -> RawFd {
+        self.read_end.as_raw_fd()
+    }
+}
+
+/// Creates a pipe whose ends are closed on `exec`. Returns the read and write ends.
+#[cfg(unix)]
+fn pipe() -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+
+    #[cfg(target_os = "linux")]
+    // SAFETY: `fds` has room for the two file descriptors.
+    let result = unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) };
+    #[cfg(not(target_os = "linux"))]
+    // SAFETY: `fds` has room for the two file descriptors.
+    let result = unsafe { libc::pipe(fds.as_mut_ptr()) };
+
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: The file descriptors were just created and are not owned by anything else.
+    let (read_end, write_end) =
+        unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+
+    #[cfg(not(target_os = "linux"))]
+    for fd in [&read_end, &write_end] {
+        // SAFETY: `fd` is a valid file descriptor.
+        if unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok((read_end, write_end))
+}
+
+#[cfg(unix)]
+fn set_nonblocking(fd: &OwnedFd) -> io::Result<()> {
+    // SAFETY: `fd` is a valid file descriptor.
+    unsafe {
+        let flags = libc::fcntl(fd.as_raw_fd(), libc::F_GETFL);
+        if flags < 0 || libc::fcntl(fd.as_raw_fd(), libc::F_SETFL, flags | libc::O_NONBLOCK) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::Credentials;
+
+    #[test]
+    fn test_read_credentials() {
+        let credentials = Credentials::new("user", "secret").unwrap();
+        let content = std::fs::read_to_string(credentials.path()).unwrap();
+        assert_eq!(content, "user\nsecret\n");
+    }
+}
@@ -0,0 +1,52 @@
+//! OpenVPN data channel offload (DCO), which moves the encryption and decryption of packets from
+//! the OpenVPN process to the `ovpn-dco` kernel driver. The OpenVPN process still performs the
+//! handshake and renegotiates keys, but does not have to copy every packet to and from userspace.
+
+use once_cell::sync::Lazy;
+use talpid_types::net::{openvpn, TransportProtocol};
+
+/// Prevents DCO from being used even if the driver is available.
+static FORCE_DISABLE_DCO: Lazy<bool> = Lazy::new(|| {
+    std::env::var("TALPID_DISABLE_OPENVPN_DCO")
+        .map(|v| v != "0")
+        .unwrap_or(false)
+});
+
+/// Return whether DCO should be used for a tunnel with the given parameters.
+pub fn should_use(params: &openvpn::TunnelParameters) -> bool {
+    if *FORCE_DISABLE_DCO {
+        return false;
+    }
+    is_supported(params) && is_available()
+}
+
+/// Return whether DCO can handle the data channel of a tunnel with the given parameters. Traffic
+/// through a proxy must pass through the OpenVPN process.
+fn is_supported(params: &openvpn::TunnelParameters) -> bool {
+    if params.proxy.is_some() {
+        return false;
+    }
+    // The Windows driver only supports UDP
+    !cfg!(windows) || params.config.endpoint.protocol == TransportProtocol::Udp
+}
+
+/// Return whether the DCO driver is loaded.
+#[cfg(target_os = "linux")]
+fn is_available() -> bool {
+    std::path::Path::new("/sys/module/ovpn_dco_v2").exists()
+}
+
+/// Return whether the DCO driver is installed.
+#[cfg(windows)]
+fn is_available() -> bool {
+    use winreg::{enums::HKEY_LOCAL_MACHINE, RegKey};
+
+    RegKey::predef(HKEY_LOCAL_MACHINE)
+        .open_subkey(r"SYSTEM\CurrentControlSet\Services\ovpn-dco")
+        .is_ok()
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn is_available() -> bool {
+    false
+}
@@ -2,7 +2,7 @@
 
 #![deny(missing_docs)]
 
-use crate::proxy::ProxyMonitor;
+use crate::{credentials::Credentials, proxy::ProxyMonitor};
 #[cfg(windows)]
 use once_cell::sync::Lazy;
 use process::openvpn::{OpenVpnCommand, OpenVpnProcHandle};
@@ -11,17 +11,20 @@ use std::collections::{HashMap, HashSet};
 #[cfg(target_os = "windows")]
 use std::{ffi::OsString, sync::Arc};
 use std::{
-    fs,
-    io::{self, Write},
+    fs, io,
     path::{Path, PathBuf},
     process::ExitStatus,
     time::Duration,
 };
 #[cfg(target_os = "linux")]
 use talpid_routing::RequiredRoute;
-use talpid_tunnel::TunnelEvent;
+use talpid_tunnel::{ConnectTracer, TunnelEvent};
 use talpid_types::{
-    net::{openvpn, proxy::CustomProxy},
+    net::{
+        openvpn,
+        proxy::{CustomProxy, ProxyChain},
+    },
+    tunnel::ConnectPhase,
     ErrorExt,
 };
 use tokio::task;
@@ -34,6 +37,9 @@ use windows_sys::{core::GUID, Win32::NetworkManagement::Ndis::NET_LUID_LH};
 #[cfg(windows)]
 mod wintun;
 
+mod credentials;
+mod dco;
+#[cfg(windows)]
 mod mktemp;
 mod process;
 mod proxy;
@@ -100,10 +106,14 @@ pub enum Error {
     #[error("No OpenVPN plugin found at {0}")]
     PluginNotFound(String),
 
-    /// Error while writing credentials to temporary file.
-    #[error("Error while writing credentials to temporary file")]
+    /// Error while passing credentials to OpenVPN.
+    #[error("Error while passing credentials to OpenVPN")]
     CredentialsWriteError(#[source] io::Error),
 
+    /// Failed to read the CA certificate.
+    #[error("Failed to read the CA certificate")]
+    ReadCaError(#[source] io::Error),
+
     /// Failures related to the proxy service.
     #[error("Proxy service failed")]
     ProxyError(#[source] proxy::Error),
@@ -163,16 +173,36 @@ const OPENVPN_BIN_FILENAME: &str = "openvpn";
 #[cfg(windows)]
 const OPENVPN_BIN_FILENAME: &str = "openvpn.exe";
 
+/// Renders an OpenVPN configuration file for connecting to the relay in `params`, for use outside
+/// of the app. Credentials, bridges and other proxies are not included.
+pub fn export_config(params: &openvpn::TunnelParameters, resource_dir: &Path) -> Result<String> {
+    let ca = fs::read_to_string(ca_path(params, resource_dir)).map_err(Error::ReadCaError)?;
+    let mut cmd = OpenVpnCommand::new(OPENVPN_BIN_FILENAME);
+    cmd.remote(params.config.endpoint)
+        .tunnel_options(&params.options)
+        .enable_ipv6(params.generic_options.enable_ipv6);
+    Ok(cmd.config_file(&ca))
+}
+
+/// Returns the path to the CA certificate to verify the server in `params` with.
+fn ca_path(params: &openvpn::TunnelParameters, resource_dir: &Path) -> PathBuf {
+    params
+        .ca
+        .clone()
+        .unwrap_or_else(|| resource_dir.join("ca.crt"))
+}
+
 /// Struct for monitoring an OpenVPN process.
 #[derive(Debug)]
 pub struct OpenVpnMonitor<C: OpenVpnBuilder = OpenVpnCommand> {
     prepare_task: tokio::task::JoinHandle<io::Result<C::ProcessHandle>>,
 
     proxy_monitor: Option<Box<dyn ProxyMonitor>>,
-    /// Keep the `TempFile` for the user-pass file in the struct, so it's removed on drop.
-    _user_pass_file: mktemp::TempFile,
-    /// Keep the 'TempFile' for the proxy user-pass file in the struct, so it's removed on drop.
-    _proxy_auth_file: Option<mktemp::TempFile>,
+    /// Keep the credentials in the struct, so that they're available until OpenVPN has started
+    /// and are removed on drop.
+    _user_pass: Credentials,
+    /// Keep the proxy credentials in the struct, for the same reason as `_user_pass`.
+    _proxy_auth: Option<Credentials>,
 
     event_server_abort_tx: triggered::Trigger,
     server_join_handle: task::JoinHandle<std::result::Result<(), event_server::Error>>,
@@ -180,8 +210,9 @@ pub struct OpenVpnMonitor<C: OpenVpnBuilder = OpenVpnCommand> {
     monitor_abort_tx: triggered::Trigger,
     monitor_abort_rx: triggered::Listener,
 
+    /// Not used with DCO, in which case OpenVPN uses an adapter of the DCO driver instead.
     #[cfg(windows)]
-    _wintun: Arc<Box<dyn WintunContext>>,
+    _wintun: Option<Arc<Box<dyn WintunContext>>>,
 }
 
 #[cfg(windows)]
@@ -250,6 +281,7 @@ impl OpenVpnMonitor<OpenVpnCommand> {
         log_path: Option<PathBuf>,
         resource_dir: &Path,
         route_manager: talpid_routing::RouteManagerHandle,
+        connect_trace: ConnectTracer,
     ) -> Result<Self>
     where
         L: (Fn(TunnelEvent) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>>)
@@ -257,33 +289,49 @@ impl OpenVpnMonitor<OpenVpnCommand> {
             + Sync
             + 'static,
     {
-        let user_pass_file =
-            Self::create_credentials_file(&params.config.username, &params.config.password)
-                .map_err(Error::CredentialsWriteError)?;
-        let proxy_auth_file =
-            Self::create_proxy_auth_file(&params.proxy).map_err(Error::CredentialsWriteError)?;
-        let user_pass_file_path = user_pass_file.to_path_buf();
-        let proxy_auth_file_path = proxy_auth_file.as_ref().map(|file| file.to_path_buf());
+        let user_pass = Credentials::new(
+            params.config.username.expose_str(),
+            params.config.password.expose_str(),
+        )
+        .map_err(Error::CredentialsWriteError)?;
+        let proxy_auth =
+            Self::create_proxy_auth(&params.proxy).map_err(Error::CredentialsWriteError)?;
+        #[cfg(windows)]
+        let user_pass_file_path = user_pass.path();
+        #[cfg(windows)]
+        let proxy_auth_file_path = proxy_auth.as_ref().map(Credentials::path);
 
+        if params.proxy.is_some() {
+            connect_trace.start(ConnectPhase::Obfuscation);
+        }
         let proxy_monitor = Self::start_proxy(
             &params.proxy,
             #[cfg(target_os = "linux")]
             params.fwmark,
         )
         .await?;
+        connect_trace.finish(ConnectPhase::Obfuscation);
+
+        let data_channel_offload = dco::should_use(params);
+        log::debug!("OpenVPN data channel offload: {data_channel_offload}");
 
         #[cfg(windows)]
-        let wintun = Self::new_wintun_context(params, resource_dir)?;
+        let wintun = if data_channel_offload {
+            None
+        } else {
+            Some(Self::new_wintun_context(params, resource_dir)?)
+        };
 
-        let cmd = Self::create_openvpn_cmd(
+        let mut cmd = Self::create_openvpn_cmd(
             params,
-            user_pass_file.as_ref(),
-            proxy_auth_file.as_ref().map(AsRef::as_ref),
+            &user_pass,
+            proxy_auth.as_ref(),
             resource_dir,
             &proxy_monitor,
             #[cfg(windows)]
-            wintun.alias().to_os_string(),
+            wintun.as_ref().map(|wintun| wintun.alias().to_os_string()),
         )?;
+        cmd.data_channel_offload(data_channel_offload);
 
         let plugin_path = Self::get_plugin_path(resource_dir)?;
 
@@ -297,28 +345,33 @@ impl OpenVpnMonitor<OpenVpnCommand> {
             event_server_abort_rx,
             plugin_path,
             log_path,
-            user_pass_file,
-            proxy_auth_file,
+            user_pass,
+            proxy_auth,
             proxy_monitor,
             #[cfg(target_os = "linux")]
             fwmark: params.fwmark,
+            connect_trace: connect_trace.clone(),
         };
         Self::new_internal(
             cmd,
             openvpn_init_args,
             event_server::OpenvpnEventProxyImpl {
                 on_event,
-                user_pass_file_path: user_pass_file_path.clone(),
-                proxy_auth_file_path: proxy_auth_file_path.clone(),
+                #[cfg(windows)]
+                user_pass_file_path,
+                #[cfg(windows)]
+                proxy_auth_file_path,
                 abort_server_tx: event_server_abort_tx,
                 #[cfg(any(target_os = "macos", target_os = "windows"))]
                 proxy: params.proxy.clone(),
                 route_manager,
                 #[cfg(target_os = "linux")]
                 ipv6_enabled,
+                data_channel_offload,
+                connect_trace,
             },
             #[cfg(windows)]
-            Box::new(wintun),
+            wintun.map(|wintun| Box::new(wintun) as Box<dyn WintunContext>),
         )
     }
 
@@ -362,11 +415,12 @@ struct OpenVpnTunnelInitArgs {
     event_server_abort_rx: triggered::Listener,
     plugin_path: PathBuf,
     log_path: Option<PathBuf>,
-    user_pass_file: mktemp::TempFile,
-    proxy_auth_file: Option<mktemp::TempFile>,
+    user_pass: Credentials,
+    proxy_auth: Option<Credentials>,
     proxy_monitor: Option<Box<dyn ProxyMonitor>>,
     #[cfg(target_os = "linux")]
     fwmark: u32,
+    connect_trace: ConnectTracer,
 }
 
 impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
@@ -374,7 +428,7 @@ impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
         mut cmd: C,
         init_args: OpenVpnTunnelInitArgs,
         on_event: L,
-        #[cfg(windows)] wintun: Box<dyn WintunContext>,
+        #[cfg(windows)] wintun: Option<Box<dyn WintunContext>>,
     ) -> Result<OpenVpnMonitor<C>>
     where
         L: event_server::OpenvpnEventProxy + Send + Sync + 'static,
@@ -383,15 +437,15 @@ impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
         let event_server_abort_rx = init_args.event_server_abort_rx;
         let plugin_path = init_args.plugin_path;
         let log_path = init_args.log_path;
-        let user_pass_file = init_args.user_pass_file;
-        let proxy_auth_file = init_args.proxy_auth_file;
+        let user_pass = init_args.user_pass;
+        let proxy_auth = init_args.proxy_auth;
         let proxy_monitor = init_args.proxy_monitor;
 
         let (server_join_handle, ipc_path) = event_server::start(on_event, event_server_abort_rx)
             .map_err(Error::EventDispatcherError)?;
 
         #[cfg(windows)]
-        let wintun = Arc::new(wintun);
+        let wintun = wintun.map(Arc::new);
 
         #[cfg(target_os = "linux")]
         cmd.fwmark(init_args.fwmark);
@@ -400,6 +454,7 @@ impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
             .log(log_path.as_deref());
         let prepare_task = tokio::spawn(Self::prepare_process(
             cmd,
+            init_args.connect_trace,
             #[cfg(windows)]
             wintun.clone(),
         ));
@@ -409,8 +464,8 @@ impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
         let monitor = OpenVpnMonitor {
             prepare_task,
             proxy_monitor,
-            _user_pass_file: user_pass_file,
-            _proxy_auth_file: proxy_auth_file,
+            _user_pass: user_pass,
+            _proxy_auth: proxy_auth,
 
             event_server_abort_tx,
             server_join_handle,
@@ -428,15 +483,21 @@ impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
     #[cfg_attr(not(windows), allow(clippy::unused_async))]
     async fn prepare_process(
         cmd: C,
-        #[cfg(windows)] wintun: Arc<Box<dyn WintunContext>>,
+        connect_trace: ConnectTracer,
+        #[cfg(windows)] wintun: Option<Arc<Box<dyn WintunContext>>>,
     ) -> io::Result<C::ProcessHandle> {
+        connect_trace.start(ConnectPhase::TunnelCreation);
         #[cfg(windows)]
-        {
+        if let Some(wintun) = wintun {
             log::debug!("Wait for IP interfaces");
             wintun.wait_for_interfaces().await?;
             wintun.prepare_interface();
         }
-        cmd.start()
+        let handle = cmd.start()?;
+        connect_trace.finish(ConnectPhase::TunnelCreation);
+        // The handshake is over once OpenVPN reports that the interface is up.
+        connect_trace.start(ConnectPhase::Handshake);
+        Ok(handle)
     }
 
     /// Creates a handle to this monitor, allowing the tunnel to be closed while some other
@@ -544,12 +605,17 @@ impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
         join_return_first(kill_child, kill_event_dispatcher).await
     }
 
-    fn create_proxy_auth_file(
-        proxy_settings: &Option<CustomProxy>,
-    ) -> std::result::Result<Option<mktemp::TempFile>, io::Error> {
-        if let Some(CustomProxy::Socks5Remote(ref remote_proxy)) = proxy_settings {
+    fn create_proxy_auth(
+        proxy_settings: &Option<ProxyChain>,
+    ) -> std::result::Result<Option<Credentials>, io::Error> {
+        // When chaining, OpenVPN connects to a local proxy that handles authentication itself.
+        let proxy = proxy_settings
+            .as_ref()
+            .filter(|chain| !chain.is_chained())
+            .map(ProxyChain::first_hop);
+        if let Some(CustomProxy::Socks5Remote(remote_proxy)) = proxy {
             if let Some(ref proxy_auth) = remote_proxy.auth {
-                return Ok(Some(Self::create_credentials_file(
+                return Ok(Some(Credentials::new(
                     proxy_auth.username(),
                     proxy_auth.password(),
                 )?));
@@ -560,7 +626,7 @@ impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
 
     /// Starts a proxy service, as applicable.
     async fn start_proxy(
-        proxy_settings: &Option<CustomProxy>,
+        proxy_settings: &Option<ProxyChain>,
         #[cfg(target_os = "linux")] fwmark: u32,
     ) -> Result<Option<Box<dyn ProxyMonitor>>> {
         if let Some(ref settings) = proxy_settings {
@@ -576,27 +642,6 @@ impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
         Ok(None)
     }
 
-    fn create_credentials_file(username: &str, password: &str) -> io::Result<mktemp::TempFile> {
-        let temp_file = mktemp::TempFile::new();
-        log::debug!("Writing credentials to {}", temp_file.as_ref().display());
-        let mut file = fs::File::create(&temp_file)?;
-        Self::set_user_pass_file_permissions(&file)?;
-        write!(file, "{username}\n{password}\n")?;
-        Ok(temp_file)
-    }
-
-    #[cfg(unix)]
-    fn set_user_pass_file_permissions(file: &fs::File) -> io::Result<()> {
-        use std::os::unix::fs::PermissionsExt;
-        file.set_permissions(PermissionsExt::from_mode(0o400))
-    }
-
-    #[cfg(windows)]
-    fn set_user_pass_file_permissions(_file: &fs::File) -> io::Result<()> {
-        // TODO(linus): Lock permissions correctly on Windows.
-        Ok(())
-    }
-
     fn get_plugin_path(resource_dir: &Path) -> Result<PathBuf> {
         let path = resource_dir.join(OPENVPN_PLUGIN_FILENAME);
         if path.exists() {
@@ -609,28 +654,42 @@ impl<C: OpenVpnBuilder + Send + 'static> OpenVpnMonitor<C> {
 
     fn create_openvpn_cmd(
         params: &openvpn::TunnelParameters,
-        user_pass_file: &Path,
-        proxy_auth_file: Option<&Path>,
+        user_pass: &Credentials,
+        proxy_auth: Option<&Credentials>,
         resource_dir: &Path,
         proxy_monitor: &Option<Box<dyn ProxyMonitor>>,
-        #[cfg(windows)] alias: OsString,
+        #[cfg(windows)] alias: Option<OsString>,
     ) -> Result<OpenVpnCommand> {
         let mut cmd = OpenVpnCommand::new(Self::get_openvpn_bin(resource_dir)?);
         if let Some(config) = Self::get_config_path(resource_dir) {
             cmd.config(config);
         }
         cmd.remote(params.config.endpoint)
-            .user_pass(user_pass_file)
+            .user_pass(user_pass.path())
             .tunnel_options(&params.options)
             .enable_ipv6(params.generic_options.enable_ipv6)
-            .ca(resource_dir.join("ca.crt"));
+            .ca(ca_path(params, resource_dir));
+        if let Some(connect_timeout) = params.options.connect_timeout {
+            cmd.connect_timeout(Duration::from_secs(u64::from(connect_timeout)));
+        }
+        if let Some(retry_timeout) = params.options.retry_timeout {
+            cmd.retry_timeout(Duration::from_secs(u64::from(retry_timeout)));
+        }
         #[cfg(windows)]
-        cmd.tunnel_alias(Some(alias));
+        cmd.tunnel_alias(alias);
+        #[cfg(unix)]
+        if let Some(privilege_drop) = &params.privilege_drop {
+            cmd.user(&privilege_drop.user).group(&privilege_drop.group);
+        }
         if let Some(proxy_settings) = params.proxy.clone().take() {
             cmd.proxy_settings(proxy_settings);
         }
-        if let Some(proxy_auth_file) = proxy_auth_file {
-            cmd.proxy_auth(proxy_auth_file);
+        #[cfg(unix)]
+        cmd.inherit_fd(user_pass.fd());
+        if let Some(proxy_auth) = proxy_auth {
+            cmd.proxy_auth(proxy_auth.path());
+            #[cfg(unix)]
+            cmd.inherit_fd(proxy_auth.fd());
         }
         if let Some(proxy) = proxy_monitor {
             cmd.proxy_port(proxy.port());
@@ -776,10 +835,10 @@ mod event_server {
         pin::Pin,
         task::{Context, Poll},
     };
-    use talpid_tunnel::TunnelMetadata;
+    use talpid_tunnel::{ConnectTracer, TunnelMetadata};
     #[cfg(any(target_os = "macos", target_os = "windows"))]
-    use talpid_types::net::proxy::CustomProxy;
-    use talpid_types::ErrorExt;
+    use talpid_types::net::proxy::{CustomProxy, ProxyChain};
+    use talpid_types::{tunnel::ConnectPhase, ErrorExt};
     use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
     use tonic::{
         transport::{server::Connected, Server},
@@ -816,14 +875,18 @@ mod event_server {
             + 'static,
     > {
         pub on_event: L,
+        #[cfg(windows)]
         pub user_pass_file_path: super::PathBuf,
+        #[cfg(windows)]
         pub proxy_auth_file_path: Option<super::PathBuf>,
         pub abort_server_tx: triggered::Trigger,
         #[cfg(any(target_os = "macos", target_os = "windows"))]
-        pub proxy: Option<CustomProxy>,
+        pub proxy: Option<ProxyChain>,
         pub route_manager: talpid_routing::RouteManagerHandle,
         #[cfg(target_os = "linux")]
         pub ipv6_enabled: bool,
+        pub data_channel_offload: bool,
+        pub connect_trace: ConnectTracer,
     }
 
     impl<
@@ -841,8 +904,9 @@ mod event_server {
             request: Request<EventDetails>,
         ) -> std::result::Result<Response<()>, tonic::Status> {
             let env = request.into_inner().env;
+            self.connect_trace.finish(ConnectPhase::Handshake);
             (self.on_event)(talpid_tunnel::TunnelEvent::InterfaceUp(
-                Self::get_tunnel_metadata(&env)?,
+                self.get_tunnel_metadata(&env)?,
                 talpid_types::net::AllowedTunnelTraffic::All,
             ))
             .await;
@@ -855,14 +919,20 @@ mod event_server {
         ) -> std::result::Result<Response<()>, tonic::Status> {
             let env = request.into_inner().env;
 
-            let _ = tokio::fs::remove_file(&self.user_pass_file_path).await;
-            if let Some(ref file_path) = &self.proxy_auth_file_path {
-                let _ = tokio::fs::remove_file(file_path).await;
+            #[cfg(windows)]
+            {
+                let _ = tokio::fs::remove_file(&self.user_pass_file_path).await;
+                if let Some(ref file_path) = &self.proxy_auth_file_path {
+                    let _ = tokio::fs::remove_file(file_path).await;
+                }
             }
 
+            self.connect_trace.start(ConnectPhase::Routes);
             let mut routes = HashSet::new();
             #[cfg(not(target_os = "linux"))]
-            if let Some(CustomProxy::Socks5Local(proxy_settings)) = &self.proxy {
+            if let Some(CustomProxy::Socks5Local(proxy_settings)) =
+                self.proxy.as_ref().map(ProxyChain::first_hop)
+            {
                 let network = proxy_settings.remote_endpoint.address.ip().into();
                 let node = talpid_routing::NetNode::DefaultNode;
                 let route = talpid_routing::RequiredRoute::new(network, node);
@@ -889,7 +959,7 @@ mod event_server {
                 routes.extend(extracted_routes);
             }
 
-            let metadata = Self::get_tunnel_metadata(&env)?;
+            let metadata = self.get_tunnel_metadata(&env)?;
 
             #[cfg(windows)]
             {
@@ -914,6 +984,7 @@ mod event_server {
                 log::error!("{}", error.display_chain());
                 return Err(tonic::Status::failed_precondition("Failed to add routes"));
             }
+            self.connect_trace.finish(ConnectPhase::Routes);
 
             (self.on_event)(talpid_tunnel::TunnelEvent::Up(metadata)).await;
 
@@ -921,6 +992,7 @@ mod event_server {
         }
 
         fn get_tunnel_metadata(
+            &self,
             env: &HashMap<String, String>,
         ) -> std::result::Result<TunnelMetadata, tonic::Status> {
             let tunnel_alias = env
@@ -964,6 +1036,7 @@ mod event_server {
                 ips,
                 ipv4_gateway,
                 ipv6_gateway,
+                data_channel_offload: self.data_channel_offload,
             })
         }
     }
@@ -1091,7 +1164,6 @@ mod event_server {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::mktemp::TempFile;
     use std::sync::{Arc, Mutex};
 
     #[cfg(windows)]
@@ -1203,11 +1275,12 @@ mod tests {
             event_server_abort_rx,
             plugin_path,
             log_path,
-            user_pass_file: TempFile::new(),
-            proxy_auth_file: None,
+            user_pass: Credentials::new("", "").unwrap(),
+            proxy_auth: None,
             proxy_monitor: None,
             #[cfg(target_os = "linux")]
             fwmark: 0,
+            connect_trace: ConnectTracer::new(),
         }
     }
 
@@ -1224,7 +1297,7 @@ mod tests {
             openvpn_init_args,
             TestOpenvpnEventProxy {},
             #[cfg(windows)]
-            Box::new(TestWintunContext {}),
+            Some(Box::new(TestWintunContext {})),
         );
         assert_eq!(
             Some(PathBuf::from("./my_test_plugin")),
@@ -1242,7 +1315,7 @@ mod tests {
             openvpn_init_args,
             TestOpenvpnEventProxy {},
             #[cfg(windows)]
-            Box::new(TestWintunContext {}),
+            Some(Box::new(TestWintunContext {})),
         );
         assert_eq!(
             Some(PathBuf::from("./my_test_log_file")),
@@ -1262,7 +1335,7 @@ mod tests {
             openvpn_init_args,
             TestOpenvpnEventProxy {},
             #[cfg(windows)]
-            Box::new(TestWintunContext {}),
+            Some(Box::new(TestWintunContext {})),
         )
         .unwrap();
         assert!(testee.wait().await.is_ok());
@@ -1280,7 +1353,7 @@ mod tests {
             openvpn_init_args,
             TestOpenvpnEventProxy {},
             #[cfg(windows)]
-            Box::new(TestWintunContext {}),
+            Some(Box::new(TestWintunContext {})),
         )
         .unwrap();
         assert!(testee.wait().await.is_err());
@@ -1298,7 +1371,7 @@ mod tests {
             openvpn_init_args,
             TestOpenvpnEventProxy {},
             #[cfg(windows)]
-            Box::new(TestWintunContext {}),
+            Some(Box::new(TestWintunContext {})),
         )
         .unwrap();
 
@@ -1317,7 +1390,7 @@ mod tests {
             openvpn_init_args,
             TestOpenvpnEventProxy {},
             #[cfg(windows)]
-            Box::new(TestWintunContext {}),
+            Some(Box::new(TestWintunContext {})),
         )
         .unwrap();
         match result.wait().await {
@@ -1,3 +1,5 @@
 /// A module for all OpenVPN related process management.
 #[cfg(not(target_os = "android"))]
 pub mod openvpn;
+#[cfg(target_os = "linux")]
+mod sandbox;
@@ -1,4 +1,7 @@
 use futures::channel::oneshot;
+use once_cell::sync::Lazy;
+#[cfg(unix)]
+use std::os::fd::RawFd;
 use std::{
     ffi::{OsStr, OsString},
     fmt, io,
@@ -6,7 +9,10 @@ use std::{
     process::Stdio,
     time::Duration,
 };
-use talpid_types::net::{self, proxy::CustomProxy};
+use talpid_types::net::{
+    self,
+    proxy::{CustomProxy, ProxyChain},
+};
 
 static BASE_ARGUMENTS: &[&[&str]] = &[
     &["--client"],
@@ -19,7 +25,6 @@ static BASE_ARGUMENTS: &[&[&str]] = &[
     &["--dev-type", "tun"],
     &["--ping", "4"],
     &["--ping-exit", "25"],
-    &["--connect-timeout", "30"],
     &["--connect-retry", "0", "0"],
     &["--connect-retry-max", "1"],
     &["--remote-cert-tls", "server"],
@@ -27,7 +32,6 @@ static BASE_ARGUMENTS: &[&[&str]] = &[
     &["--sndbuf", "1048576"],
     &["--fast-io"],
     &["--data-ciphers-fallback", "AES-256-GCM"],
-    &["--tls-version-min", "1.3"],
     &["--verb", "3"],
     #[cfg(windows)]
     &[
@@ -44,13 +48,138 @@ static BASE_ARGUMENTS: &[&[&str]] = &[
     &["--route-noexec"],
     #[cfg(windows)]
     &["--ip-win32", "ipapi"],
-    #[cfg(windows)]
-    &["--windows-driver", "wintun"],
 ];
 
+/// Options that only make sense for the OpenVPN process managed by the daemon on this platform,
+/// and that are left out of exported configuration files.
+static PLATFORM_SPECIFIC_OPTIONS: &[&str] = &[
+    "route-noexec",
+    "route-gateway",
+    "route",
+    "ip-win32",
+    "windows-driver",
+    "disable-dco",
+];
+
+/// Time to wait for a response from the server when connecting over UDP.
+const UDP_CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+/// Time to wait for a response from the server when connecting over TCP. This also includes
+/// setting up the TCP connection.
+const TCP_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Time to wait for a response from the server when connecting through a proxy, which adds at
+/// least one more hop.
+const PROXY_CONNECT_TIMEOUT: Duration = Duration::from_secs(45);
+
+/// Time to wait before resending an unacknowledged control channel packet over UDP. This is the
+/// OpenVPN default.
+const UDP_RETRY_TIMEOUT: Duration = Duration::from_secs(2);
+/// Time to wait before resending an unacknowledged control channel packet over TCP. Packets are
+/// not lost over TCP, so resending them early only adds to the congestion.
+const TCP_RETRY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Time to wait before resending an unacknowledged control channel packet through a proxy.
+const PROXY_RETRY_TIMEOUT: Duration = Duration::from_secs(10);
+
 static ALLOWED_TLS1_3_CIPHERS: &[&str] =
     &["TLS_AES_256_GCM_SHA384", "TLS_CHACHA20_POLY1305_SHA256"];
 
+/// TLS 1.3 ciphersuites that may be selected using `TALPID_OPENVPN_TLS_CIPHERSUITES`.
+static SUPPORTED_TLS1_3_CIPHERS: &[&str] = &[
+    "TLS_AES_256_GCM_SHA384",
+    "TLS_CHACHA20_POLY1305_SHA256",
+    "TLS_AES_128_GCM_SHA256",
+];
+
+/// TLS 1.2 ciphers that are allowed if TLS 1.2 is enabled. Only ECDHE key exchange and AEAD ciphers
+/// are allowed, like in TLS 1.3.
+static ALLOWED_TLS1_2_CIPHERS: &[&str] = &[
+    "TLS-ECDHE-RSA-WITH-AES-256-GCM-SHA384",
+    "TLS-ECDHE-RSA-WITH-CHACHA20-POLY1305-SHA256",
+    "TLS-ECDHE-ECDSA-WITH-AES-256-GCM-SHA384",
+    "TLS-ECDHE-ECDSA-WITH-CHACHA20-POLY1305-SHA256",
+];
+
+/// TLS policy of the control channel. Only meant for testing and for servers that do not support
+/// TLS 1.3 yet.
+static TLS_POLICY: Lazy<TlsPolicy> = Lazy::new(|| {
+    TlsPolicy::parse(
+        std::env::var("TALPID_OPENVPN_TLS_VERSION_MIN")
+            .ok()
+            .as_deref(),
+        std::env::var("TALPID_OPENVPN_TLS_CIPHERSUITES")
+            .ok()
+            .as_deref(),
+    )
+});
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TlsPolicy {
+    /// Either "1.2" or "1.3".
+    version_min: &'static str,
+    /// TLS 1.3 ciphersuites, a subset of [`SUPPORTED_TLS1_3_CIPHERS`].
+    ciphersuites: Vec<&'static str>,
+}
+
+impl Default for TlsPolicy {
+    fn default() -> Self {
+        TlsPolicy {
+            version_min: "1.3",
+            ciphersuites: ALLOWED_TLS1_3_CIPHERS.to_vec(),
+        }
+    }
+}
+
+impl TlsPolicy {
+    /// Parse the minimum TLS version and a colon separated list of TLS 1.3 ciphersuites. Invalid
+    /// values are ignored in favor of the defaults, so that the policy can never be weakened
+    /// beyond what is supported.
+    fn parse(version_min: Option<&str>, ciphersuites: Option<&str>) -> Self {
+        let mut policy = TlsPolicy::default();
+        match version_min {
+            None => (),
+            Some("1.2") => policy.version_min = "1.2",
+            Some("1.3") => policy.version_min = "1.3",
+            Some(version) => {
+                log::warn!("Ignoring unsupported minimum TLS version for OpenVPN: {version}")
+            }
+        }
+        if let Some(ciphersuites) = ciphersuites {
+            let parsed: Option<Vec<_>> = ciphersuites
+                .split(':')
+                .map(|cipher| {
+                    SUPPORTED_TLS1_3_CIPHERS
+                        .iter()
+                        .find(|supported| supported.eq_ignore_ascii_case(cipher.trim()))
+                        .copied()
+                })
+                .collect();
+            match parsed {
+                Some(parsed) if !parsed.is_empty() => policy.ciphersuites = parsed,
+                _ => {
+                    log::warn!("Ignoring unsupported TLS ciphersuites for OpenVPN: {ciphersuites}")
+                }
+            }
+        }
+        if policy != TlsPolicy::default() {
+            log::warn!("Using non-default TLS policy for OpenVPN: {policy:?}");
+        }
+        policy
+    }
+
+    fn arguments(&self) -> Vec<String> {
+        let mut args = vec![
+            "--tls-version-min".to_owned(),
+            self.version_min.to_owned(),
+            "--tls-ciphersuites".to_owned(),
+            self.ciphersuites.join(":"),
+        ];
+        if self.version_min == "1.2" {
+            args.push("--tls-cipher".to_owned());
+            args.push(ALLOWED_TLS1_2_CIPHERS.join(":"));
+        }
+        args
+    }
+}
+
 /// An OpenVPN process builder, providing control over the different arguments that the OpenVPN
 /// binary accepts.
 #[derive(Clone)]
@@ -58,6 +187,8 @@ pub struct OpenVpnCommand {
     openvpn_bin: OsString,
     config: Option<PathBuf>,
     remote: Option<net::Endpoint>,
+    connect_timeout: Option<Duration>,
+    retry_timeout: Option<Duration>,
     user_pass_path: Option<PathBuf>,
     proxy_auth_path: Option<PathBuf>,
     ca: Option<PathBuf>,
@@ -65,10 +196,17 @@ pub struct OpenVpnCommand {
     plugin: Option<(PathBuf, Vec<String>)>,
     log: Option<PathBuf>,
     tunnel_options: net::openvpn::TunnelOptions,
-    proxy_settings: Option<CustomProxy>,
+    proxy_settings: Option<ProxyChain>,
     tunnel_alias: Option<OsString>,
     enable_ipv6: bool,
     proxy_port: Option<u16>,
+    data_channel_offload: bool,
+    #[cfg(unix)]
+    user: Option<OsString>,
+    #[cfg(unix)]
+    group: Option<OsString>,
+    #[cfg(unix)]
+    inherited_fds: Vec<RawFd>,
     #[cfg(target_os = "linux")]
     fwmark: Option<u32>,
 }
@@ -81,6 +219,8 @@ impl OpenVpnCommand {
             openvpn_bin: OsString::from(openvpn_bin.as_ref()),
             config: None,
             remote: None,
+            connect_timeout: None,
+            retry_timeout: None,
             user_pass_path: None,
             proxy_auth_path: None,
             ca: None,
@@ -92,6 +232,13 @@ impl OpenVpnCommand {
             tunnel_alias: None,
             enable_ipv6: true,
             proxy_port: None,
+            data_channel_offload: false,
+            #[cfg(unix)]
+            user: None,
+            #[cfg(unix)]
+            group: None,
+            #[cfg(unix)]
+            inherited_fds: vec![],
             #[cfg(target_os = "linux")]
             fwmark: None,
         }
@@ -104,6 +251,30 @@ impl OpenVpnCommand {
         self
     }
 
+    /// Sets the user that OpenVPN switches to once the tunnel is up. See the `--user` OpenVPN
+    /// documentation for details.
+    #[cfg(unix)]
+    pub fn user(&mut self, user: impl AsRef<OsStr>) -> &mut Self {
+        self.user = Some(user.as_ref().to_os_string());
+        self
+    }
+
+    /// Sets the group that OpenVPN switches to once the tunnel is up. See the `--group` OpenVPN
+    /// documentation for details.
+    #[cfg(unix)]
+    pub fn group(&mut self, group: impl AsRef<OsStr>) -> &mut Self {
+        self.group = Some(group.as_ref().to_os_string());
+        self
+    }
+
+    /// Lets OpenVPN inherit `fd` even if it is closed on `exec`, so that it can be opened through
+    /// `/dev/fd`.
+    #[cfg(unix)]
+    pub fn inherit_fd(&mut self, fd: RawFd) -> &mut Self {
+        self.inherited_fds.push(fd);
+        self
+    }
+
     /// Sets what configuration file will be given to OpenVPN
     pub fn config(&mut self, path: impl AsRef<Path>) -> &mut Self {
         self.config = Some(path.as_ref().to_path_buf());
@@ -116,6 +287,21 @@ impl OpenVpnCommand {
         self
     }
 
+    /// Sets how long to wait for a response from the server. By default, this depends on the
+    /// transport protocol and on whether a proxy is used.
+    pub fn connect_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long to wait before resending a control channel packet that the server has not
+    /// acknowledged. By default, this depends on the transport protocol and on whether a proxy is
+    /// used.
+    pub fn retry_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.retry_timeout = Some(timeout);
+        self
+    }
+
     /// Sets the path to the file where the username and password for user-pass authentication
     /// is stored. See the `--auth-user-pass` OpenVPN documentation for details.
     pub fn user_pass(&mut self, path: impl AsRef<Path>) -> &mut Self {
@@ -173,6 +359,13 @@ impl OpenVpnCommand {
         self
     }
 
+    /// Configures if data channel offload (DCO) should be used. OpenVPN otherwise encrypts and
+    /// decrypts packets itself, even if the DCO driver is available.
+    pub fn data_channel_offload(&mut self, data_channel_offload: bool) -> &mut Self {
+        self.data_channel_offload = data_channel_offload;
+        self
+    }
+
     /// Sets the local proxy port bound to.
     /// In case of dynamic port selection, this will only be known after the proxy has been started.
     pub fn proxy_port(&mut self, proxy_port: u16) -> &mut Self {
@@ -181,7 +374,7 @@ impl OpenVpnCommand {
     }
 
     /// Sets the proxy settings.
-    pub fn proxy_settings(&mut self, proxy_settings: CustomProxy) -> &mut Self {
+    pub fn proxy_settings(&mut self, proxy_settings: ProxyChain) -> &mut Self {
         self.proxy_settings = Some(proxy_settings);
         self
     }
@@ -191,9 +384,85 @@ impl OpenVpnCommand {
         log::debug!("Building expression: {}", &self);
         let mut handle = tokio::process::Command::new(&self.openvpn_bin);
         handle.args(self.get_arguments());
+        #[cfg(unix)]
+        if !self.inherited_fds.is_empty() {
+            let fds = self.inherited_fds.clone();
+            // SAFETY: `fcntl` is async-signal-safe, so it may be called between `fork` and `exec`.
+            unsafe {
+                handle.pre_exec(move || {
+                    for fd in &fds {
+                        if libc::fcntl(*fd, libc::F_SETFD, 0) != 0 {
+                            return Err(io::Error::last_os_error());
+                        }
+                    }
+                    Ok(())
+                });
+            }
+        }
+        #[cfg(target_os = "linux")]
+        if self.tunnel_options.sandbox {
+            super::sandbox::confine(&mut handle);
+        }
         handle
     }
 
+    /// Renders the command as an OpenVPN configuration file with `ca` inlined as the CA
+    /// certificate. Options referring to local files, such as credentials, plugins and logs, are
+    /// left out, and OpenVPN will prompt for the username and password instead.
+    pub fn config_file(&self, ca: &str) -> String {
+        let mut cmd = self.clone();
+        cmd.config = None;
+        cmd.user_pass_path = None;
+        cmd.proxy_auth_path = None;
+        cmd.ca = None;
+        cmd.crl = None;
+        cmd.plugin = None;
+        cmd.log = None;
+        cmd.tunnel_alias = None;
+        #[cfg(unix)]
+        {
+            cmd.user = None;
+            cmd.group = None;
+        }
+        #[cfg(target_os = "linux")]
+        {
+            cmd.fwmark = None;
+        }
+
+        let mut lines: Vec<String> = vec![];
+        for arg in cmd.get_arguments() {
+            let arg = arg.to_string_lossy();
+            match (arg.strip_prefix("--"), lines.last_mut()) {
+                (Some(option), _) => lines.push(option.to_owned()),
+                (None, Some(line)) => {
+                    line.push(' ');
+                    line.push_str(&arg);
+                }
+                (None, None) => log::error!("Ignoring argument without an option: {arg}"),
+            }
+        }
+
+        let mut config = String::new();
+        for line in lines {
+            let option = line.split(' ').next().unwrap_or_default();
+            if PLATFORM_SPECIFIC_OPTIONS.contains(&option) {
+                continue;
+            }
+            // `--dev-type` is only used on Windows, where the device is selected by `--dev-node`.
+            let line = match line.strip_prefix("dev-type ") {
+                Some(dev_type) => format!("dev {dev_type}"),
+                None => line,
+            };
+            config.push_str(&line);
+            config.push('\n');
+        }
+        config.push_str("auth-user-pass\n");
+        config.push_str("<ca>\n");
+        config.push_str(ca.trim_end());
+        config.push_str("\n</ca>\n");
+        config
+    }
+
     /// Returns all arguments that the subprocess would be spawned with.
     fn get_arguments(&self) -> Vec<OsString> {
         let mut args: Vec<OsString> = Self::base_arguments().iter().map(OsString::from).collect();
@@ -204,6 +473,14 @@ impl OpenVpnCommand {
         }
 
         args.extend(self.remote_arguments().iter().map(OsString::from));
+        args.push(OsString::from("--connect-timeout"));
+        args.push(OsString::from(
+            self.get_connect_timeout().as_secs().to_string(),
+        ));
+        args.push(OsString::from("--tls-timeout"));
+        args.push(OsString::from(
+            self.get_retry_timeout().as_secs().to_string(),
+        ));
         args.extend(self.authentication_arguments());
 
         if let Some(ref ca) = self.ca {
@@ -246,9 +523,18 @@ impl OpenVpnCommand {
             args.push(tunnel_device.clone());
         }
 
+        args.extend(
+            self.data_channel_offload_arguments()
+                .iter()
+                .map(OsString::from),
+        );
+
         args.extend(Self::tls_cipher_arguments().iter().map(OsString::from));
         args.extend(self.proxy_arguments().iter().map(OsString::from));
 
+        #[cfg(unix)]
+        args.extend(self.privilege_drop_arguments());
+
         #[cfg(target_os = "linux")]
         if let Some(mark) = &self.fwmark {
             args.extend(["--mark", &mark.to_string()].iter().map(OsString::from));
@@ -268,10 +554,7 @@ impl OpenVpnCommand {
     }
 
     fn tls_cipher_arguments() -> Vec<String> {
-        vec![
-            "--tls-ciphersuites".to_owned(),
-            ALLOWED_TLS1_3_CIPHERS.join(":"),
-        ]
+        TLS_POLICY.arguments()
     }
 
     fn remote_arguments(&self) -> Vec<String> {
@@ -289,6 +572,32 @@ impl OpenVpnCommand {
         args
     }
 
+    fn get_connect_timeout(&self) -> Duration {
+        if let Some(timeout) = self.connect_timeout {
+            return timeout;
+        }
+        if self.proxy_settings.is_some() {
+            return PROXY_CONNECT_TIMEOUT;
+        }
+        match self.remote.map(|endpoint| endpoint.protocol) {
+            Some(net::TransportProtocol::Udp) => UDP_CONNECT_TIMEOUT,
+            Some(net::TransportProtocol::Tcp) | None => TCP_CONNECT_TIMEOUT,
+        }
+    }
+
+    fn get_retry_timeout(&self) -> Duration {
+        if let Some(timeout) = self.retry_timeout {
+            return timeout;
+        }
+        if self.proxy_settings.is_some() {
+            return PROXY_RETRY_TIMEOUT;
+        }
+        match self.remote.map(|endpoint| endpoint.protocol) {
+            Some(net::TransportProtocol::Udp) => UDP_RETRY_TIMEOUT,
+            Some(net::TransportProtocol::Tcp) | None => TCP_RETRY_TIMEOUT,
+        }
+    }
+
     fn authentication_arguments(&self) -> Vec<OsString> {
         let mut args = vec![];
         if let Some(ref user_pass_path) = self.user_pass_path {
@@ -298,10 +607,75 @@ impl OpenVpnCommand {
         args
     }
 
+    /// Once privileges have been dropped, OpenVPN can no longer reopen the tunnel device or
+    /// reread the keys, so both are kept across restarts.
+    #[cfg(unix)]
+    fn privilege_drop_arguments(&self) -> Vec<OsString> {
+        let mut args = vec![];
+        if let Some(ref user) = self.user {
+            args.push(OsString::from("--user"));
+            args.push(user.clone());
+        }
+        if let Some(ref group) = self.group {
+            args.push(OsString::from("--group"));
+            args.push(group.clone());
+        }
+        if !args.is_empty() {
+            args.push(OsString::from("--persist-tun"));
+            args.push(OsString::from("--persist-key"));
+        }
+        args
+    }
+
+    /// Without DCO, the Wintun driver is used on Windows.
+    fn data_channel_offload_arguments(&self) -> Vec<&'static str> {
+        let mut args = vec![];
+        #[cfg(windows)]
+        {
+            args.push("--windows-driver");
+            args.push(if self.data_channel_offload {
+                "ovpn-dco"
+            } else {
+                "wintun"
+            });
+        }
+        #[cfg(any(target_os = "linux", windows))]
+        if !self.data_channel_offload {
+            args.push("--disable-dco");
+        }
+        args
+    }
+
     fn proxy_arguments(&self) -> Vec<String> {
         let mut args = vec![];
-        match self.proxy_settings {
-            Some(CustomProxy::Socks5Local(ref local_proxy)) => {
+        let Some(ref proxy_settings) = self.proxy_settings else {
+            return args;
+        };
+        if proxy_settings.is_chained() {
+            args.push("--socks-proxy".to_owned());
+            args.push("127.0.0.1".to_owned());
+
+            if let Some(ref proxy_port) = self.proxy_port {
+                args.push(proxy_port.to_string());
+            } else {
+                panic!("Dynamic proxy port was not registered with OpenVpnCommand");
+            }
+
+            args.push("--route".to_owned());
+            args.push(
+                proxy_settings
+                    .get_remote_endpoint()
+                    .endpoint
+                    .address
+                    .ip()
+                    .to_string(),
+            );
+            args.push("255.255.255.255".to_owned());
+            args.push("net_gateway".to_owned());
+            return args;
+        }
+        match proxy_settings.first_hop() {
+            CustomProxy::Socks5Local(local_proxy) => {
                 args.push("--socks-proxy".to_owned());
                 args.push("127.0.0.1".to_owned());
                 args.push(local_proxy.local_port.to_string());
@@ -310,7 +684,7 @@ impl OpenVpnCommand {
                 args.push("255.255.255.255".to_owned());
                 args.push("net_gateway".to_owned());
             }
-            Some(CustomProxy::Socks5Remote(ref remote_proxy)) => {
+            CustomProxy::Socks5Remote(remote_proxy) => {
                 args.push("--socks-proxy".to_owned());
                 args.push(remote_proxy.endpoint.ip().to_string());
                 args.push(remote_proxy.endpoint.port().to_string());
@@ -328,7 +702,7 @@ impl OpenVpnCommand {
                 args.push("255.255.255.255".to_owned());
                 args.push("net_gateway".to_owned());
             }
-            Some(CustomProxy::Shadowsocks(ref ss)) => {
+            CustomProxy::Shadowsocks(ss) => {
                 args.push("--socks-proxy".to_owned());
                 args.push("127.0.0.1".to_owned());
 
@@ -343,7 +717,6 @@ impl OpenVpnCommand {
                 args.push("255.255.255.255".to_owned());
                 args.push("net_gateway".to_owned());
             }
-            None => {}
         };
         args
     }
@@ -455,8 +828,8 @@ impl OpenVpnProcHandle {
 
 #[cfg(test)]
 mod tests {
-    use super::OpenVpnCommand;
-    use std::{ffi::OsString, net::Ipv4Addr};
+    use super::{OpenVpnCommand, TlsPolicy};
+    use std::{ffi::OsString, net::Ipv4Addr, time::Duration};
     use talpid_types::net::{Endpoint, TransportProtocol};
 
     #[test]
@@ -470,6 +843,48 @@ mod tests {
         assert!(testee_args.contains(&OsString::from("3333")));
     }
 
+    fn argument_value(cmd: &OpenVpnCommand, name: &str) -> OsString {
+        let args = cmd.get_arguments();
+        let index = args.iter().position(|arg| arg == name).unwrap();
+        args[index + 1].clone()
+    }
+
+    #[test]
+    fn connect_timeout_depends_on_transport() {
+        let connect_timeout = |cmd: &mut OpenVpnCommand| argument_value(cmd, "--connect-timeout");
+        let udp = Endpoint::new(Ipv4Addr::LOCALHOST, 1194, TransportProtocol::Udp);
+        let tcp = Endpoint::new(Ipv4Addr::LOCALHOST, 443, TransportProtocol::Tcp);
+
+        assert_eq!(connect_timeout(OpenVpnCommand::new("").remote(udp)), "15");
+        assert_eq!(connect_timeout(OpenVpnCommand::new("").remote(tcp)), "30");
+        assert_eq!(
+            connect_timeout(
+                OpenVpnCommand::new("")
+                    .remote(udp)
+                    .connect_timeout(Duration::from_secs(60))
+            ),
+            "60"
+        );
+    }
+
+    #[test]
+    fn retry_timeout_depends_on_transport() {
+        let retry_timeout = |cmd: &mut OpenVpnCommand| argument_value(cmd, "--tls-timeout");
+        let udp = Endpoint::new(Ipv4Addr::LOCALHOST, 1194, TransportProtocol::Udp);
+        let tcp = Endpoint::new(Ipv4Addr::LOCALHOST, 443, TransportProtocol::Tcp);
+
+        assert_eq!(retry_timeout(OpenVpnCommand::new("").remote(udp)), "2");
+        assert_eq!(retry_timeout(OpenVpnCommand::new("").remote(tcp)), "5");
+        assert_eq!(
+            retry_timeout(
+                OpenVpnCommand::new("")
+                    .remote(tcp)
+                    .retry_timeout(Duration::from_secs(20))
+            ),
+            "20"
+        );
+    }
+
     #[test]
     fn passes_plugin_path() {
         let path = "./a/path";
@@ -477,6 +892,22 @@ mod tests {
         assert!(testee_args.contains(&OsString::from("./a/path")));
     }
 
+    #[test]
+    fn config_file_excludes_credentials() {
+        let remote = Endpoint::new(Ipv4Addr::new(127, 0, 0, 1), 1194, TransportProtocol::Udp);
+        let config = OpenVpnCommand::new("")
+            .remote(remote)
+            .user_pass("/tmp/user-pass")
+            .ca("/tmp/ca.crt")
+            .config_file("CERTIFICATE\n");
+
+        assert!(config.contains("\nremote 127.0.0.1 1194\n"));
+        assert!(config.contains("\nauth-user-pass\n"));
+        assert!(config.ends_with("<ca>\nCERTIFICATE\n</ca>\n"));
+        assert!(!config.contains("/tmp/"));
+        assert!(!config.contains("route-noexec"));
+    }
+
     #[test]
     fn passes_plugin_args() {
         let args = vec![String::from("123"), String::from("cde")];
@@ -484,4 +915,51 @@ mod tests {
         assert!(testee_args.contains(&OsString::from("123")));
         assert!(testee_args.contains(&OsString::from("cde")));
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn passes_privilege_drop() {
+        let testee_args = OpenVpnCommand::new("").get_arguments();
+        assert!(!testee_args.contains(&OsString::from("--persist-tun")));
+
+        let testee_args = OpenVpnCommand::new("")
+            .user("nobody")
+            .group("nogroup")
+            .get_arguments();
+        assert!(testee_args.contains(&OsString::from("nobody")));
+        assert!(testee_args.contains(&OsString::from("nogroup")));
+        assert!(testee_args.contains(&OsString::from("--persist-tun")));
+        assert!(testee_args.contains(&OsString::from("--persist-key")));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn disables_dco_unless_enabled() {
+        let disable_dco = OsString::from("--disable-dco");
+
+        let testee_args = OpenVpnCommand::new("").get_arguments();
+        assert!(testee_args.contains(&disable_dco));
+
+        let testee_args = OpenVpnCommand::new("")
+            .data_channel_offload(true)
+            .get_arguments();
+        assert!(!testee_args.contains(&disable_dco));
+    }
+
+    #[test]
+    fn parses_tls_policy() {
+        assert_eq!(TlsPolicy::parse(None, None), TlsPolicy::default());
+        assert_eq!(
+            TlsPolicy::parse(Some("1.0"), Some("TLS_NULL_WITH_NULL_NULL")),
+            TlsPolicy::default()
+        );
+
+        let policy = TlsPolicy::parse(Some("1.2"), Some("tls_aes_128_gcm_sha256"));
+        assert_eq!(policy.version_min, "1.2");
+        assert_eq!(policy.ciphersuites, ["TLS_AES_128_GCM_SHA256"]);
+        assert!(policy.arguments().contains(&"--tls-cipher".to_owned()));
+        assert!(!TlsPolicy::default()
+            .arguments()
+            .contains(&"--tls-cipher".to_owned()));
+    }
 }
@@ -0,0 +1,202 @@
+//! Seccomp filter that confines the OpenVPN process. The filter is installed between `fork` and
+//! `exec`, and denies system calls that OpenVPN never needs, such as loading kernel modules or
+//! tracing other processes. This limits what an attacker can do with a compromised process.
+
+use std::io;
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_003e);
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: Option<u32> = Some(0xc000_00b7);
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const AUDIT_ARCH: Option<u32> = None;
+
+/// Set in the numbers of x32 system calls, which must not be used to get around the filter.
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: Option<u32> = Some(0x4000_0000);
+#[cfg(not(target_arch = "x86_64"))]
+const X32_SYSCALL_BIT: Option<u32> = None;
+
+/// Offsets of the fields in `struct seccomp_data`.
+const SECCOMP_DATA_NR_OFFSET: u32 = 0;
+const SECCOMP_DATA_ARCH_OFFSET: u32 = 4;
+
+/// System calls that fail with `EPERM` in the confined process.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+const DENIED_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_acct,
+    libc::SYS_add_key,
+    libc::SYS_bpf,
+    libc::SYS_chroot,
+    libc::SYS_clock_adjtime,
+    libc::SYS_clock_settime,
+    libc::SYS_delete_module,
+    libc::SYS_fanotify_init,
+    libc::SYS_finit_module,
+    libc::SYS_init_module,
+    libc::SYS_kexec_file_load,
+    libc::SYS_kexec_load,
+    libc::SYS_keyctl,
+    libc::SYS_mount,
+    libc::SYS_name_to_handle_at,
+    libc::SYS_open_by_handle_at,
+    libc::SYS_perf_event_open,
+    libc::SYS_pivot_root,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    libc::SYS_ptrace,
+    libc::SYS_quotactl,
+    libc::SYS_reboot,
+    libc::SYS_request_key,
+    libc::SYS_sethostname,
+    libc::SYS_setdomainname,
+    libc::SYS_setns,
+    libc::SYS_settimeofday,
+    libc::SYS_swapoff,
+    libc::SYS_swapon,
+    libc::SYS_umount2,
+    libc::SYS_unshare,
+    libc::SYS_userfaultfd,
+];
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const DENIED_SYSCALLS: &[libc::c_long] = &[];
+
+/// A compiled seccomp filter.
+#[derive(Clone)]
+pub struct Filter(Vec<libc::sock_filter>);
+
+impl Filter {
+    /// Compiles the filter, or returns `None` if filters aren't supported on this architecture.
+    pub fn new() -> Option<Self> {
+        let arch = AUDIT_ARCH?;
+
+        let mut syscall_checks = vec![];
+        if let Some(x32_syscall_bit) = X32_SYSCALL_BIT {
+            syscall_checks.push((libc::BPF_JGE, x32_syscall_bit));
+        }
+        syscall_checks.extend(
+            DENIED_SYSCALLS
+                .iter()
+                .map(|syscall| (libc::BPF_JEQ, *syscall as u32)),
+        );
+
+        let mut program = vec![
+            statement(
+                libc::BPF_LD | libc::BPF_W | libc::BPF_ABS,
+                SECCOMP_DATA_ARCH_OFFSET,
+            ),
+            // Deny everything if the system call is made for a different architecture.
+            jump(libc::BPF_JEQ, arch, 0, syscall_checks.len() as u8 + 2),
+            statement(
+                libc::BPF_LD | libc::BPF_W | libc::BPF_ABS,
+                SECCOMP_DATA_NR_OFFSET,
+            ),
+        ];
+        let num_checks = syscall_checks.len();
+        for (i, (condition, value)) in syscall_checks.into_iter().enumerate() {
+            // Jump past the remaining checks and the allow statement.
+            let to_deny = (num_checks - i) as u8;
+            program.push(jump(condition, value, to_deny, 0));
+        }
+        program.push(statement(
+            libc::BPF_RET | libc::BPF_K,
+            libc::SECCOMP_RET_ALLOW,
+        ));
+        program.push(statement(
+            libc::BPF_RET | libc::BPF_K,
+            libc::SECCOMP_RET_ERRNO | (libc::EPERM as u32 & libc::SECCOMP_RET_DATA),
+        ));
+
+        Some(Filter(program))
+    }
+
+    /// Installs the filter in the current process. The filter is inherited by child processes and
+    /// kept across `exec`, and can't be removed.
+    ///
+    /// This only makes async-signal-safe system calls, so it may be called between `fork` and
+    /// `exec`.
+    pub fn apply(&self) -> io::Result<()> {
+        let program = libc::sock_fprog {
+            len: self.0.len() as libc::c_ushort,
+            filter: self.0.as_ptr() as *mut libc::sock_filter,
+        };
+        // SAFETY: `program` points to a valid filter that outlives the calls.
+        unsafe {
+            if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER,
+                &program as *const libc::sock_fprog,
+            ) != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Confines the process spawned by `cmd` with a seccomp filter. Nothing is done if filters
+/// aren't supported on this architecture.
+pub fn confine(cmd: &mut tokio::process::Command) {
+    let Some(filter) = Filter::new() else {
+        log::warn!(
+            "Not confining OpenVPN since seccomp filters are unsupported on this architecture"
+        );
+        return;
+    };
+    // SAFETY: `Filter::apply` is safe to call between `fork` and `exec`.
+    unsafe {
+        cmd.pre_exec(move || filter.apply());
+    }
+}
+
+const fn statement(code: u32, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+const fn jump(condition: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter {
+        code: (libc::BPF_JMP | condition | libc::BPF_K) as u16,
+        jt,
+        jf,
+        k,
+    }
+}
+
+#[cfg(all(test, any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod test {
+    use super::Filter;
+    use nix::sched::{unshare, CloneFlags};
+    use std::{io, os::unix::process::CommandExt};
+
+    #[test]
+    fn test_filter_denies_syscalls() {
+        let filter = Filter::new().unwrap();
+        // Make `syscall` in a confined child process before it runs `true`. The error returned by
+        // `syscall`, if any, is returned when spawning the child.
+        let run_confined = |syscall: fn() -> io::Result<()>| {
+            let filter = filter.clone();
+            let mut cmd = std::process::Command::new("true");
+            // SAFETY: `Filter::apply` and `syscall` are safe to call between `fork` and `exec`.
+            unsafe {
+                cmd.pre_exec(move || {
+                    filter.apply()?;
+                    syscall()
+                })
+            };
+            cmd.status()
+        };
+
+        assert!(run_confined(|| Ok(())).unwrap().success());
+        let error = run_confined(|| Ok(unshare(CloneFlags::CLONE_NEWUSER)?)).unwrap_err();
+        assert_eq!(error.raw_os_error(), Some(libc::EPERM));
+    }
+}
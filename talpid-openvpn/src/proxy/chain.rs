@@ -0,0 +1,579 @@
+//! A local SOCKS5 server that forwards each connection through a chain of proxies.
+
+use async_trait::async_trait;
+use futures::future::{abortable, AbortHandle, Aborted};
+use shadowsocks_service::shadowsocks::{
+    config::{ServerConfig, ServerType},
+    context::{Context as SsContext, SharedContext},
+    relay::tcprelay::ProxyClientStream,
+};
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+};
+use talpid_types::{
+    net::proxy::{CustomProxy, ProxyChain},
+    ErrorExt,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpSocket, TcpStream},
+    task::JoinHandle,
+};
+
+use super::{Error, ProxyMonitor, ProxyMonitorCloseHandle};
+
+const SOCKS_VERSION: u8 = 0x05;
+const NO_AUTHENTICATION: u8 = 0x00;
+const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+const CMD_CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_IPV6: u8 = 0x04;
+const REPLY_SUCCEEDED: u8 = 0x00;
+const REPLY_GENERAL_FAILURE: u8 = 0x01;
+const REPLY_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+const REPLY_ADDRESS_TYPE_NOT_SUPPORTED: u8 = 0x08;
+
+trait ProxyStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> ProxyStream for T {}
+
+pub struct ChainProxyMonitor {
+    port: u16,
+    server_join_handle: Option<JoinHandle<Result<io::Result<()>, Aborted>>>,
+    server_abort_handle: AbortHandle,
+}
+
+impl ChainProxyMonitor {
+    pub async fn start(
+        chain: &ProxyChain,
+        #[cfg(target_os = "linux")] fwmark: u32,
+    ) -> super::Result<Self> {
+        Self::spawn(Chain {
+            hops: chain.hops().to_vec(),
+            context: SsContext::new_shared(ServerType::Local),
+            #[cfg(target_os = "linux")]
+            fwmark: Some(fwmark),
+        })
+        .await
+    }
+
+    /// Accept SOCKS5 clients on a random port on localhost and forward them through `chain`.
+    async fn spawn(chain: Chain) -> super::Result<Self> {
+        let listener = TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))
+            .await
+            .map_err(Error::Io)?;
+        let port = listener.local_addr().map_err(Error::Io)?.port();
+        let chain = Arc::new(chain);
+
+        let (fut, server_abort_handle) = abortable(async move {
+            let result = Self::serve(listener, chain).await;
+            if let Err(error) = &result {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Proxy chain stopped with an error")
+                );
+            }
+            result
+        });
+        let server_join_handle = tokio::spawn(fut);
+
+        Ok(Self {
+            port,
+            server_join_handle: Some(server_join_handle),
+            server_abort_handle,
+        })
+    }
+
+    async fn serve(listener: TcpListener, chain: Arc<Chain>) -> io::Result<()> {
+        loop {
+            let (client, _) = listener.accept().await?;
+            let chain = chain.clone();
+            tokio::spawn(async move {
+                if let Err(error) = chain.handle_client(client).await {
+                    log::warn!(
+                        "{}",
+                        error.display_chain_with_msg("Failed to proxy connection through chain")
+                    );
+                }
+            });
+        }
+    }
+}
+
+struct Chain {
+    hops: Vec<CustomProxy>,
+    context: SharedContext,
+    /// Mark of the connection to the first hop. Setting it requires `CAP_NET_ADMIN`, so the tests
+    /// leave it unset.
+    #[cfg(target_os = "linux")]
+    fwmark: Option<u32>,
+}
+
+impl Chain {
+    async fn handle_client(&self, mut client: TcpStream) -> io::Result<()> {
+        let target = match accept_connect(&mut client).await? {
+            Some(target) => target,
+            None => return Ok(()),
+        };
+
+        let mut upstream = match self.connect(target).await {
+            Ok(upstream) => upstream,
+            Err(error) => {
+                let _ = send_reply(&mut client, REPLY_GENERAL_FAILURE).await;
+                return Err(error);
+            }
+        };
+        send_reply(&mut client, REPLY_SUCCEEDED).await?;
+
+        tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+        Ok(())
+    }
+
+    /// Connect to `target` by tunneling through every hop in turn.
+    async fn connect(&self, target: SocketAddr) -> io::Result<Box<dyn ProxyStream>> {
+        let mut stream: Box<dyn ProxyStream> = Box::new(self.connect_first_hop().await?);
+
+        for (index, hop) in self.hops.iter().enumerate() {
+            let next_addr = match self.hops.get(index + 1) {
+                Some(next_hop) => next_hop.get_remote_endpoint().endpoint.address,
+                None => target,
+            };
+            stream = self.tunnel(stream, hop, next_addr).await?;
+        }
+
+        Ok(stream)
+    }
+
+    async fn connect_first_hop(&self) -> io::Result<TcpStream> {
+        let addr = match &self.hops[0] {
+            CustomProxy::Socks5Local(local) => {
+                SocketAddr::from((Ipv4Addr::LOCALHOST, local.local_port))
+            }
+            CustomProxy::Socks5Remote(remote) => remote.endpoint,
+            CustomProxy::Shadowsocks(shadowsocks) => shadowsocks.endpoint,
+        };
+
+        let socket = match addr {
+            SocketAddr::V4(_) => TcpSocket::new_v4()?,
+            SocketAddr::V6(_) => TcpSocket::new_v6()?,
+        };
+        #[cfg(target_os = "linux")]
+        if let Some(fwmark) = self.fwmark {
+            socket2::SockRef::from(&socket).set_mark(fwmark)?;
+        }
+
+        socket.connect(addr).await
+    }
+
+    /// Ask `hop`, which `stream` is connected to, to open a connection to `next_addr`.
+    async fn tunnel(
+        &self,
+        stream: Box<dyn ProxyStream>,
+        hop: &CustomProxy,
+        next_addr: SocketAddr,
+    ) -> io::Result<Box<dyn ProxyStream>> {
+        match hop {
+            CustomProxy::Socks5Local(_) => socks_connect(stream, next_addr, None).await,
+            CustomProxy::Socks5Remote(remote) => {
+                let auth = remote
+                    .auth
+                    .as_ref()
+                    .map(|auth| (auth.username(), auth.password()));
+                socks_connect(stream, next_addr, auth).await
+            }
+            CustomProxy::Shadowsocks(shadowsocks) => {
+                let cipher = shadowsocks.cipher.parse().map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::Other,
+                        format!("Invalid cipher: {}", shadowsocks.cipher),
+                    )
+                })?;
+                let config = ServerConfig::new(
+                    shadowsocks.endpoint,
+                    shadowsocks.password.expose().clone(),
+                    cipher,
+                );
+                Ok(Box::new(ProxyClientStream::from_stream(
+                    self.context.clone(),
+                    stream,
+                    &config,
+                    next_addr,
+                )))
+            }
+        }
+    }
+}
+
+async fn socks_connect(
+    stream: Box<dyn ProxyStream>,
+    next_addr: SocketAddr,
+    auth: Option<(&str, &str)>,
+) -> io::Result<Box<dyn ProxyStream>> {
+    let result = match auth {
+        None => tokio_socks::tcp::Socks5Stream::connect_with_socket(stream, next_addr).await,
+        Some((username, password)) => {
+            tokio_socks::tcp::Socks5Stream::connect_with_password_and_socket(
+                stream, next_addr, username, password,
+            )
+            .await
+        }
+    };
+    let stream = result
+        .map_err(|error| io::Error::new(io::ErrorKind::Other, format!("SOCKS error: {error}")))?;
+    Ok(Box::new(stream))
+}
+
+/// Perform the server side of a SOCKS5 handshake. Returns the address that the client asked to
+/// connect to, or `None` if the request was rejected.
+async fn accept_connect(client: &mut TcpStream) -> io::Result<Option<SocketAddr>> {
+    let mut header = [0u8; 2];
+    client.read_exact(&mut header).await?;
+    if header[0] != SOCKS_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Unsupported SOCKS version",
+        ));
+    }
+    let mut methods = vec![0u8; usize::from(header[1])];
+    client.read_exact(&mut methods).await?;
+    if !methods.contains(&NO_AUTHENTICATION) {
+        client
+            .write_all(&[SOCKS_VERSION, NO_ACCEPTABLE_METHODS])
+            .await?;
+        return Ok(None);
+    }
+    client
+        .write_all(&[SOCKS_VERSION, NO_AUTHENTICATION])
+        .await?;
+
+    let mut request = [0u8; 4];
+    client.read_exact(&mut request).await?;
+    if request[1] != CMD_CONNECT {
+        send_reply(client, REPLY_COMMAND_NOT_SUPPORTED).await?;
+        return Ok(None);
+    }
+    let ip = match request[3] {
+        ATYP_IPV4 => {
+            let mut octets = [0u8; 4];
+            client.read_exact(&mut octets).await?;
+            IpAddr::from(Ipv4Addr::from(octets))
+        }
+        ATYP_IPV6 => {
+            let mut octets = [0u8; 16];
+            client.read_exact(&mut octets).await?;
+            IpAddr::from(Ipv6Addr::from(octets))
+        }
+        _ => {
+            send_reply(client, REPLY_ADDRESS_TYPE_NOT_SUPPORTED).await?;
+            return Ok(None);
+        }
+    };
+    let port = client.read_u16().await?;
+
+    Ok(Some(SocketAddr::new(ip, port)))
+}
+
+async fn send_reply(client: &mut TcpStream, reply: u8) -> io::Result<()> {
+    // The bound address is not meaningful to the client, so it is always reported as 0.0.0.0:0.
+    client
+        .write_all(&[SOCKS_VERSION, reply, 0x00, ATYP_IPV4, 0, 0, 0, 0, 0, 0])
+        .await
+}
+
+impl Drop for ChainProxyMonitor {
+    fn drop(&mut self) {
+        self.server_abort_handle.abort();
+    }
+}
+
+#[async_trait]
+impl ProxyMonitor for ChainProxyMonitor {
+    fn close_handle(&mut self) -> Box<dyn ProxyMonitorCloseHandle> {
+        Box::new(ChainProxyMonitorCloseHandle {
+            server_abort_handle: self.server_abort_handle.clone(),
+        })
+    }
+
+    async fn wait(mut self: Box<Self>) -> super::Result<()> {
+        if let Some(join_handle) = self.server_join_handle.take() {
+            match join_handle.await {
+                Ok(Err(Aborted)) => Ok(()),
+
+                Err(join_err) if join_err.is_cancelled() => Ok(()),
+                Err(_) => Err(Error::UnexpectedExit(
+                    "Proxy chain task panicked".to_string(),
+                )),
+
+                Ok(Ok(result)) => match result {
+                    Ok(()) => Err(Error::UnexpectedExit("Exited without error".to_string())),
+                    Err(error) => Err(Error::UnexpectedExit(format!(
+                        "Error: {}",
+                        error.display_chain()
+                    ))),
+                },
+            }
+        } else {
+            Ok(())
+        }
+    }
+
+    fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+struct ChainProxyMonitorCloseHandle {
+    server_abort_handle: AbortHandle,
+}
+
+impl ProxyMonitorCloseHandle for ChainProxyMonitorCloseHandle {
+    fn close(self: Box<Self>) -> super::Result<()> {
+        self.server_abort_handle.abort();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::{channel::mpsc, FutureExt, StreamExt};
+    use talpid_types::net::proxy::{Socks5Remote, SocksAuth};
+    use tokio_socks::tcp::Socks5Stream;
+
+    const USERNAME_PASSWORD: u8 = 0x02;
+    const USERNAME_PASSWORD_VERSION: u8 = 0x01;
+    const CMD_BIND: u8 = 0x02;
+
+    /// Start a SOCKS5 proxy that requires `credentials`, if any, and connects to any IPv4 address
+    /// that it is asked to. The requested addresses are sent on the returned channel.
+    async fn spawn_mock_proxy(
+        credentials: Option<(&'static str, &'static str)>,
+    ) -> (SocketAddr, mpsc::UnboundedReceiver<SocketAddr>) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (targets_tx, targets_rx) = mpsc::unbounded();
+        tokio::spawn(async move {
+            loop {
+                let (client, _) = listener.accept().await.unwrap();
+                let targets_tx = targets_tx.clone();
+                tokio::spawn(mock_proxy_connection(client, credentials, targets_tx));
+            }
+        });
+        (addr, targets_rx)
+    }
+
+    async fn mock_proxy_connection(
+        mut client: TcpStream,
+        credentials: Option<(&str, &str)>,
+        targets_tx: mpsc::UnboundedSender<SocketAddr>,
+    ) -> io::Result<()> {
+        let mut header = [0u8; 2];
+        client.read_exact(&mut header).await?;
+        let mut methods = vec![0u8; usize::from(header[1])];
+        client.read_exact(&mut methods).await?;
+
+        if let Some((username, password)) = credentials {
+            client
+                .write_all(&[SOCKS_VERSION, USERNAME_PASSWORD])
+                .await?;
+            assert_eq!(client.read_u8().await?, USERNAME_PASSWORD_VERSION);
+            let mut received_username = vec![0u8; usize::from(client.read_u8().await?)];
+            client.read_exact(&mut received_username).await?;
+            let mut received_password = vec![0u8; usize::from(client.read_u8().await?)];
+            client.read_exact(&mut received_password).await?;
+
+            let accepted = received_username == username.as_bytes()
+                && received_password == password.as_bytes();
+            client
+                .write_all(&[USERNAME_PASSWORD_VERSION, u8::from(!accepted)])
+                .await?;
+            if !accepted {
+                return Ok(());
+            }
+        } else {
+            client
+                .write_all(&[SOCKS_VERSION, NO_AUTHENTICATION])
+                .await?;
+        }
+
+        let mut request = [0u8; 4];
+        client.read_exact(&mut request).await?;
+        assert_eq!(request[1], CMD_CONNECT);
+        assert_eq!(request[3], ATYP_IPV4);
+        let mut octets = [0u8; 4];
+        client.read_exact(&mut octets).await?;
+        let target = SocketAddr::from((octets, client.read_u16().await?));
+        let _ = targets_tx.unbounded_send(target);
+
+        let Ok(mut upstream) = TcpStream::connect(target).await else {
+            return send_reply(&mut client, REPLY_GENERAL_FAILURE).await;
+        };
+        send_reply(&mut client, REPLY_SUCCEEDED).await?;
+        tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+        Ok(())
+    }
+
+    /// Start a server that sends back everything it receives.
+    async fn spawn_echo_server() -> SocketAddr {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut client, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let (mut reader, mut writer) = client.split();
+                    let _ = tokio::io::copy(&mut reader, &mut writer).await;
+                });
+            }
+        });
+        addr
+    }
+
+    /// Return an address that nothing listens on.
+    async fn unused_addr() -> SocketAddr {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    async fn start_chain(hops: Vec<CustomProxy>) -> ChainProxyMonitor {
+        ChainProxyMonitor::spawn(Chain {
+            hops,
+            context: SsContext::new_shared(ServerType::Local),
+            #[cfg(target_os = "linux")]
+            fwmark: None,
+        })
+        .await
+        .unwrap()
+    }
+
+    fn chain_addr(monitor: &ChainProxyMonitor) -> SocketAddr {
+        SocketAddr::from((Ipv4Addr::LOCALHOST, monitor.port()))
+    }
+
+    async fn assert_echoes(stream: &mut Socks5Stream<TcpStream>) {
+        stream.write_all(b"ping").await.unwrap();
+        let mut response = [0u8; 4];
+        stream.read_exact(&mut response).await.unwrap();
+        assert_eq!(&response, b"ping");
+    }
+
+    #[tokio::test]
+    async fn test_handshake() {
+        let echo_addr = spawn_echo_server().await;
+        let (proxy_addr, mut proxy_targets) = spawn_mock_proxy(None).await;
+        let monitor = start_chain(vec![CustomProxy::Socks5Remote(Socks5Remote::new(
+            proxy_addr,
+        ))])
+        .await;
+
+        let mut stream = Socks5Stream::connect(chain_addr(&monitor), echo_addr)
+            .await
+            .unwrap();
+        assert_echoes(&mut stream).await;
+        assert_eq!(proxy_targets.next().await, Some(echo_addr));
+    }
+
+    #[tokio::test]
+    async fn test_rejected_handshake() {
+        let (proxy_addr, _) = spawn_mock_proxy(None).await;
+        let monitor = start_chain(vec![CustomProxy::Socks5Remote(Socks5Remote::new(
+            proxy_addr,
+        ))])
+        .await;
+
+        let mut client = TcpStream::connect(chain_addr(&monitor)).await.unwrap();
+        client
+            .write_all(&[SOCKS_VERSION, 1, USERNAME_PASSWORD])
+            .await
+            .unwrap();
+        let mut response = [0u8; 2];
+        client.read_exact(&mut response).await.unwrap();
+        assert_eq!(response, [SOCKS_VERSION, NO_ACCEPTABLE_METHODS]);
+
+        let mut client = TcpStream::connect(chain_addr(&monitor)).await.unwrap();
+        client
+            .write_all(&[SOCKS_VERSION, 1, NO_AUTHENTICATION])
+            .await
+            .unwrap();
+        client.read_exact(&mut response).await.unwrap();
+        assert_eq!(response, [SOCKS_VERSION, NO_AUTHENTICATION]);
+        client
+            .write_all(&[
+                SOCKS_VERSION,
+                CMD_BIND,
+                0x00,
+                ATYP_IPV4,
+                127,
+                0,
+                0,
+                1,
+                0,
+                80,
+            ])
+            .await
+            .unwrap();
+        let mut reply = [0u8; 10];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply[1], REPLY_COMMAND_NOT_SUPPORTED);
+    }
+
+    #[tokio::test]
+    async fn test_two_hops() {
+        let echo_addr = spawn_echo_server().await;
+        let (first_addr, mut first_targets) = spawn_mock_proxy(None).await;
+        let (second_addr, mut second_targets) =
+            spawn_mock_proxy(Some(("username", "password"))).await;
+        let auth = SocksAuth::new("username".to_owned(), "password".to_owned()).unwrap();
+        let monitor = start_chain(vec![
+            CustomProxy::Socks5Remote(Socks5Remote::new(first_addr)),
+            CustomProxy::Socks5Remote(Socks5Remote::new_with_authentication(second_addr, auth)),
+        ])
+        .await;
+
+        let mut stream = Socks5Stream::connect(chain_addr(&monitor), echo_addr)
+            .await
+            .unwrap();
+        assert_echoes(&mut stream).await;
+        assert_eq!(first_targets.next().await, Some(second_addr));
+        assert_eq!(second_targets.next().await, Some(echo_addr));
+    }
+
+    #[tokio::test]
+    async fn test_failing_auth() {
+        let echo_addr = spawn_echo_server().await;
+        let (proxy_addr, mut proxy_targets) =
+            spawn_mock_proxy(Some(("username", "password"))).await;
+        let auth = SocksAuth::new("username".to_owned(), "wrong".to_owned()).unwrap();
+        let monitor = start_chain(vec![CustomProxy::Socks5Remote(
+            Socks5Remote::new_with_authentication(proxy_addr, auth),
+        )])
+        .await;
+
+        let result = Socks5Stream::connect(chain_addr(&monitor), echo_addr).await;
+        assert!(matches!(
+            result,
+            Err(tokio_socks::Error::GeneralSocksServerFailure)
+        ));
+        assert!(proxy_targets.next().now_or_never().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_hop() {
+        let echo_addr = spawn_echo_server().await;
+        let (first_addr, mut first_targets) = spawn_mock_proxy(None).await;
+        let unreachable_addr = unused_addr().await;
+        let monitor = start_chain(vec![
+            CustomProxy::Socks5Remote(Socks5Remote::new(first_addr)),
+            CustomProxy::Socks5Remote(Socks5Remote::new(unreachable_addr)),
+        ])
+        .await;
+
+        let result = Socks5Stream::connect(chain_addr(&monitor), echo_addr).await;
+        assert!(matches!(
+            result,
+            Err(tokio_socks::Error::GeneralSocksServerFailure)
+        ));
+        assert_eq!(first_targets.next().await, Some(unreachable_addr));
+    }
+}
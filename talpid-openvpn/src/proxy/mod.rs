@@ -1,10 +1,11 @@
+mod chain;
 mod noop;
 mod shadowsocks;
 
-use self::shadowsocks::ShadowsocksProxyMonitor;
+use self::{chain::ChainProxyMonitor, shadowsocks::ShadowsocksProxyMonitor};
 use async_trait::async_trait;
 use std::{fmt, io};
-use talpid_types::net::proxy::CustomProxy;
+use talpid_types::net::proxy::{CustomProxy, ProxyChain};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -40,10 +41,22 @@ pub trait ProxyMonitorCloseHandle: Send {
 }
 
 pub async fn start_proxy(
-    settings: &CustomProxy,
+    settings: &ProxyChain,
     #[cfg(target_os = "linux")] fwmark: u32,
 ) -> Result<Box<dyn ProxyMonitor>> {
-    match settings {
+    if settings.is_chained() {
+        // OpenVPN can only use a single proxy, so it's given a local one that forwards
+        // connections through the whole chain.
+        return Ok(Box::new(
+            ChainProxyMonitor::start(
+                settings,
+                #[cfg(target_os = "linux")]
+                fwmark,
+            )
+            .await?,
+        ));
+    }
+    match settings.first_hop() {
         CustomProxy::Socks5Local(local_settings) => {
             // These are generic proxy settings with the proxy client not managed by us.
             Ok(Box::new(noop::NoopProxyMonitor::start(
@@ -60,7 +60,7 @@ impl ShadowsocksProxyMonitor {
 
         let server = ServerConfig::new(
             settings.endpoint,
-            settings.password.clone(),
+            settings.password.expose().clone(),
             settings.cipher.parse().map_err(|_| {
                 io::Error::new(
                     io::ErrorKind::Other,
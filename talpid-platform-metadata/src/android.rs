@@ -1,7 +1,6 @@
 use std::collections::HashMap;
 
-mod command;
-use command::command_stdout_lossy;
+use crate::command::command_stdout_lossy;
 
 pub fn version() -> String {
     let version = os_version();
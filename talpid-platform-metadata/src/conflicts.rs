@@ -0,0 +1,201 @@
+//! Detection of other VPN software that is known to interfere with tunnel devices and routes.
+
+use std::collections::BTreeSet;
+
+/// Software that is known to conflict with the app.
+struct KnownSoftware {
+    name: &'static str,
+    /// Names of processes belonging to the software, compared case-insensitively.
+    processes: &'static [&'static str],
+    /// Prefixes of network interfaces created by the software.
+    interface_prefixes: &'static [&'static str],
+}
+
+const KNOWN_SOFTWARE: &[KnownSoftware] = &[
+    KnownSoftware {
+        name: "NordVPN",
+        processes: &["nordvpnd", "nordvpn", "nordvpn-service.exe", "nordvpn.exe"],
+        interface_prefixes: &["nordlynx", "nordtun"],
+    },
+    KnownSoftware {
+        name: "ExpressVPN",
+        processes: &[
+            "expressvpnd",
+            "expressvpn",
+            "expressvpnservice.exe",
+            "expressvpn.exe",
+        ],
+        interface_prefixes: &[],
+    },
+    KnownSoftware {
+        name: "Proton VPN",
+        processes: &["protonvpn", "protonvpnservice.exe", "protonvpn.exe"],
+        interface_prefixes: &["proton"],
+    },
+    KnownSoftware {
+        name: "Private Internet Access",
+        processes: &["pia-daemon", "pia-service.exe", "pia-client.exe"],
+        interface_prefixes: &["wgpia"],
+    },
+    KnownSoftware {
+        name: "Surfshark",
+        processes: &["surfshark", "surfshark.service.exe", "surfshark.exe"],
+        interface_prefixes: &["surfshark"],
+    },
+    KnownSoftware {
+        name: "Windscribe",
+        processes: &["windscribe", "windscribeservice.exe", "windscribe.exe"],
+        interface_prefixes: &[],
+    },
+    KnownSoftware {
+        name: "Tailscale",
+        processes: &["tailscaled", "tailscaled.exe", "tailscale-ipn.exe"],
+        interface_prefixes: &["tailscale"],
+    },
+    KnownSoftware {
+        name: "ZeroTier",
+        processes: &[
+            "zerotier-one",
+            "zerotier-one_x64.exe",
+            "zerotier_desktop_ui.exe",
+        ],
+        interface_prefixes: &["zt"],
+    },
+    KnownSoftware {
+        name: "Cisco AnyConnect",
+        processes: &["vpnagentd", "vpnagent.exe"],
+        interface_prefixes: &["cscotun"],
+    },
+    KnownSoftware {
+        name: "GlobalProtect",
+        processes: &["pangpd", "pangps", "pangps.exe", "pangpa.exe"],
+        interface_prefixes: &["gpd"],
+    },
+];
+
+/// Returns the names of running software that is known to interfere with the tunnel, such as
+/// other VPN clients. This is a best-effort scan, and software that cannot be detected is
+/// ignored.
+pub fn find_conflicting_software() -> Vec<String> {
+    find_matches(&imp::process_names(), &imp::interface_names())
+}
+
+fn find_matches(processes: &[String], interfaces: &[String]) -> Vec<String> {
+    let processes: BTreeSet<String> = processes
+        .iter()
+        .map(|process| process.trim().to_lowercase())
+        .collect();
+
+    KNOWN_SOFTWARE
+        .iter()
+        .filter(|software| {
+            let has_process = software
+                .processes
+                .iter()
+                .any(|process| processes.contains(*process));
+            let has_interface = interfaces.iter().any(|interface| {
+                software
+                    .interface_prefixes
+                    .iter()
+                    .any(|prefix| interface.starts_with(prefix))
+            });
+            has_process || has_interface
+        })
+        .map(|software| software.name.to_owned())
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::fs;
+
+    pub fn process_names() -> Vec<String> {
+        let Ok(entries) = fs::read_dir("/proc") else {
+            return vec![];
+        };
+        entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_name().to_string_lossy().parse::<u32>().is_ok())
+            .filter_map(|entry| fs::read_to_string(entry.path().join("comm")).ok())
+            .collect()
+    }
+
+    pub fn interface_names() -> Vec<String> {
+        let Ok(entries) = fs::read_dir("/sys/class/net") else {
+            return vec![];
+        };
+        entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect()
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use crate::command::command_stdout_lossy;
+
+    /// Lists the names of all processes. Other VPN clients own their utun devices through these.
+    pub fn process_names() -> Vec<String> {
+        command_stdout_lossy("ps", &["-A", "-c", "-o", "comm="])
+            .map(|output| output.lines().map(str::to_owned).collect())
+            .unwrap_or_default()
+    }
+
+    /// utun devices are not named after their owner, so they are matched by process instead.
+    pub fn interface_names() -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use crate::command::command_stdout_lossy;
+
+    /// Lists the image names of all processes, including those of services.
+    pub fn process_names() -> Vec<String> {
+        command_stdout_lossy("tasklist", &["/fo", "csv", "/nh"])
+            .map(|output| {
+                output
+                    .lines()
+                    .filter_map(|line| line.split(',').next())
+                    .map(|name| name.trim_matches('"').to_owned())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Adapters are matched by their driver's service process instead.
+    pub fn interface_names() -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(target_os = "android")]
+mod imp {
+    /// Android only allows one VPN app to be active at a time, so there is nothing to look for.
+    pub fn process_names() -> Vec<String> {
+        vec![]
+    }
+
+    pub fn interface_names() -> Vec<String> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::find_matches;
+
+    #[test]
+    fn test_find_matches() {
+        let processes = ["systemd", "NordVPN.exe", "tailscaled\n"].map(String::from);
+        let interfaces = ["lo", "wg0-mullvad", "zt5u4y6ejv"].map(String::from);
+
+        assert_eq!(
+            find_matches(&processes, &interfaces),
+            ["NordVPN", "Tailscale", "ZeroTier"]
+        );
+        assert!(find_matches(&processes[..1], &interfaces[..2]).is_empty());
+    }
+}
@@ -1,3 +1,6 @@
+mod command;
+mod conflicts;
+
 #[cfg(target_os = "linux")]
 #[path = "linux.rs"]
 mod imp;
@@ -19,3 +22,4 @@ pub use self::imp::MacosVersion;
 #[cfg(windows)]
 pub use self::imp::WindowsVersion;
 pub use self::imp::{extra_metadata, short_version, version};
+pub use conflicts::find_conflicting_software;
@@ -180,6 +180,7 @@ impl RouteManagerImpl {
         use netlink_packet_route::constants::*;
 
         self.clear_routing_rules().await?;
+        self.warn_about_conflicting_rules().await;
 
         for rule in all_rules(self.fwmark, self.table_id)
             .iter()
@@ -239,6 +240,32 @@ impl RouteManagerImpl {
         Ok(())
     }
 
+    /// Log the routing rules that were added by someone else but use our firewall mark or routing
+    /// table. Traffic that they match may bypass the tunnel or be routed into it by mistake.
+    async fn warn_about_conflicting_rules(&mut self) {
+        let rules = match self.get_rules().await {
+            Ok(rules) => rules,
+            Err(error) => {
+                log::debug!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to check for conflicting routing rules")
+                );
+                return;
+            }
+        };
+        let conflicts = conflicting_rules(&rules, self.fwmark, self.table_id);
+        if !conflicts.is_empty() {
+            log::warn!(
+                "Routing rules that use fwmark {:#x} or table {} were added by another program. \
+                 Choose another fwmark or routing table in the daemon config if the tunnel does \
+                 not work as expected:\n{}",
+                self.fwmark,
+                self.table_id,
+                conflicts.join("\n")
+            );
+        }
+    }
+
     async fn get_rules(&mut self) -> Result<Vec<RuleMessage>> {
         use netlink_packet_route::constants::*;
 
@@ -299,11 +326,54 @@ impl RouteManagerImpl {
             }
         }
 
-        for normal_route in required_normal_routes.into_iter() {
-            self.add_route(normal_route).await?;
+        self.add_routes(required_normal_routes.into_iter().collect())
+            .await
+    }
+
+    /// Add a set of routes, replacing any existing routes with the same destination. Either every
+    /// route is added, or none of the routes that were not already added are left behind.
+    async fn add_routes(&mut self, routes: Vec<Route>) -> Result<()> {
+        // Need to construct the request manually to set the correct flags to be able to replace
+        // any existing routes - self.handle.route().add().execute() sets the NLM_F_EXCL flag which
+        // will make the request fail if a route with the same destination already exists.
+        use netlink_packet_route::constants::*;
+        let requests = routes
+            .iter()
+            .map(|route| {
+                let mut req =
+                    NetlinkMessage::from(RtnlMessage::NewRoute(self.route_add_message(route)));
+                req.header.flags = NLM_F_REQUEST | NLM_F_ACK | NLM_F_CREATE | NLM_F_REPLACE;
+                req
+            })
+            .collect();
+
+        let mut first_error = None;
+        let mut added = vec![];
+        for (route, result) in routes.into_iter().zip(self.send_batch(requests).await) {
+            match result {
+                Ok(()) => added.push(route),
+                Err(error) => {
+                    log::error!("Failed to add route: {}: {}", route, error);
+                    first_error.get_or_insert(error);
+                }
+            }
         }
 
-        Ok(())
+        match first_error {
+            None => {
+                self.added_routes.extend(added);
+                Ok(())
+            }
+            Some(error) => {
+                // Routes that were added by an earlier batch are still wanted
+                let rollback = added
+                    .into_iter()
+                    .filter(|route| !self.added_routes.contains(route))
+                    .collect();
+                self.delete_routes(rollback).await;
+                Err(error)
+            }
+        }
     }
 
     async fn initialize_link_map(
@@ -333,13 +403,54 @@ impl RouteManagerImpl {
     }
 
     async fn cleanup_routes(&mut self) {
-        for route in self.added_routes.drain().collect::<Vec<_>>().iter() {
-            if let Err(e) = self.delete_route_if_exists(route).await {
-                log::error!("Failed to remove route: {}: {}", route, e);
+        let routes = self.added_routes.drain().collect();
+        self.delete_routes(routes).await;
+    }
+
+    /// Delete a set of routes. Routes that no longer exist are ignored.
+    async fn delete_routes(&mut self, routes: Vec<Route>) {
+        use netlink_packet_route::constants::*;
+        let requests = routes
+            .iter()
+            .map(|route| {
+                let mut req =
+                    NetlinkMessage::from(RtnlMessage::DelRoute(self.route_del_message(route)));
+                req.header.flags = NLM_F_REQUEST | NLM_F_ACK;
+                req
+            })
+            .collect();
+
+        for (route, result) in routes.iter().zip(self.send_batch(requests).await) {
+            match result {
+                Err(Error::Netlink(rtnetlink::Error::NetlinkError(msg)))
+                    if msg.code == -libc::ESRCH => {}
+                Err(error) => log::error!("Failed to remove route: {}: {}", route, error),
+                Ok(()) => (),
             }
         }
     }
 
+    /// Send every request before awaiting any of the acknowledgements, so that a set of routes is
+    /// applied in a single round trip rather than one per route. The result of each request is
+    /// returned in the order of `requests`.
+    async fn send_batch(&mut self, requests: Vec<NetlinkMessage<RtnlMessage>>) -> Vec<Result<()>> {
+        let responses: Vec<_> = requests
+            .into_iter()
+            .map(|req| self.handle.request(req).map_err(Error::Netlink))
+            .collect();
+
+        futures::future::join_all(responses.into_iter().map(|response| async move {
+            let mut response = response?;
+            while let Some(message) = response.next().await {
+                if let NetlinkPayload::Error(err) = message.payload {
+                    return Err(Error::Netlink(rtnetlink::Error::NetlinkError(err)));
+                }
+            }
+            Ok(())
+        }))
+        .await
+    }
+
     pub(crate) async fn run(
         mut self,
         manage_rx: UnboundedReceiver<RouteManagerCommand>,
@@ -384,6 +495,9 @@ impl RouteManagerImpl {
             RouteManagerCommand::GetDestinationRoute(destination, mark, result_tx) => {
                 let _ = result_tx.send(self.get_destination_route(&destination, mark).await);
             }
+            RouteManagerCommand::GetInterfaceRoute(destination, interface, result_tx) => {
+                let _ = result_tx.send(self.get_interface_route(&destination, &interface).await);
+            }
             RouteManagerCommand::GetMtuForRoute(ip, result_tx) => {
                 let _ = result_tx.send(self.get_mtu_for_route(ip).await);
             }
@@ -562,20 +676,7 @@ impl RouteManagerImpl {
         }
     }
 
-    async fn delete_route_if_exists(&self, route: &Route) -> Result<()> {
-        if let Err(error) = self.delete_route(route).await {
-            if let Error::Netlink(rtnetlink::Error::NetlinkError(msg)) = &error {
-                if msg.code == -libc::ESRCH {
-                    return Ok(());
-                }
-            }
-            Err(error)
-        } else {
-            Ok(())
-        }
-    }
-
-    async fn delete_route(&self, route: &Route) -> Result<()> {
+    fn route_del_message(&self, route: &Route) -> RouteMessage {
         let compat_table = compat_table_id(route.table_id);
         let scope = match route.prefix {
             IpNetwork::V4(v4_prefix) => {
@@ -635,15 +736,10 @@ impl RouteManagerImpl {
             route_message.nlas.push(RouteNla::Priority(metric));
         }
 
-        self.handle
-            .route()
-            .del(route_message)
-            .execute()
-            .await
-            .map_err(Error::Netlink)
+        route_message
     }
 
-    async fn add_route_direct(&mut self, route: Route) -> Result<()> {
+    fn route_add_message(&self, route: &Route) -> RouteMessage {
         let mut add_message = match &route.prefix {
             IpNetwork::V4(v4_prefix) => {
                 let mut add_message = self
@@ -712,27 +808,7 @@ impl RouteManagerImpl {
             add_message.nlas.push(RouteNla::Metrics(Metrics::Mtu(mtu)));
         }
 
-        // Need to modify the request in place to set the correct flags to be able to replace any
-        // existing routes - self.handle.route().add_v4().execute() sets the NLM_F_EXCL flag which
-        // will make the request fail if a route with the same destination already exists.
-        use netlink_packet_route::constants::*;
-        let mut req = NetlinkMessage::from(RtnlMessage::NewRoute(add_message));
-        req.header.flags = NLM_F_REQUEST | NLM_F_ACK | NLM_F_CREATE | NLM_F_REPLACE;
-
-        let mut response = self.handle.request(req).map_err(Error::Netlink)?;
-
-        while let Some(message) = response.next().await {
-            if let NetlinkPayload::Error(err) = message.payload {
-                return Err(Error::Netlink(rtnetlink::Error::NetlinkError(err)));
-            }
-        }
-        Ok(())
-    }
-
-    async fn add_route(&mut self, route: Route) -> Result<()> {
-        self.add_route_direct(route.clone()).await?;
-        self.added_routes.insert(route);
-        Ok(())
+        add_message
     }
 
     fn listen(&mut self) -> UnboundedReceiver<CallbackMessage> {
@@ -820,6 +896,30 @@ impl RouteManagerImpl {
         &self,
         destination: &IpAddr,
         fwmark: Option<u32>,
+    ) -> Result<Option<Route>> {
+        self.get_route(destination, fwmark, None).await
+    }
+
+    /// Return the route to `destination` via `interface`, ignoring routes through other
+    /// interfaces. Routing rules are looked up as for traffic with our firewall mark. There is no
+    /// route if the interface does not exist.
+    async fn get_interface_route(
+        &self,
+        destination: &IpAddr,
+        interface: &str,
+    ) -> Result<Option<Route>> {
+        let Some(iface_idx) = self.find_iface_idx(interface) else {
+            return Ok(None);
+        };
+        self.get_route(destination, Some(self.fwmark), Some(iface_idx))
+            .await
+    }
+
+    async fn get_route(
+        &self,
+        destination: &IpAddr,
+        fwmark: Option<u32>,
+        iface_idx: Option<u32>,
     ) -> Result<Option<Route>> {
         let mut request = self.handle.route().get(get_ip_version(destination));
         let octets = match destination {
@@ -830,6 +930,9 @@ impl RouteManagerImpl {
         if let Some(mark) = fwmark {
             message.nlas.push(RouteNla::Mark(mark));
         }
+        if let Some(iface_idx) = iface_idx {
+            message.nlas.push(RouteNla::Oif(iface_idx));
+        }
         message.header.destination_prefix_length = 8u8 * (octets.len() as u8);
         message.header.flags = RouteFlags::RTM_F_FIB_MATCH;
         message.nlas.push(RouteNla::Destination(octets));
@@ -845,6 +948,56 @@ impl RouteManagerImpl {
     }
 }
 
+/// Return a description of every rule in `rules` that matches `fwmark` or looks up `table`. Our own
+/// rules must have been removed beforehand.
+fn conflicting_rules(rules: &[RuleMessage], fwmark: u32, table: u32) -> Vec<String> {
+    rules
+        .iter()
+        .filter(|rule| {
+            rule_table(rule) == table
+                || rule
+                    .nlas
+                    .iter()
+                    .any(|nla| matches!(nla, RuleNla::FwMark(mark) if *mark == fwmark))
+        })
+        .map(describe_rule)
+        .collect()
+}
+
+fn rule_table(rule: &RuleMessage) -> u32 {
+    rule.nlas
+        .iter()
+        .find_map(|nla| match nla {
+            RuleNla::Table(table) => Some(*table),
+            _ => None,
+        })
+        .unwrap_or(u32::from(rule.header.table))
+}
+
+/// Describe `rule` in roughly the same way as `ip rule list`.
+fn describe_rule(rule: &RuleMessage) -> String {
+    let mut description = if i32::from(rule.header.family) == AF_INET6 {
+        String::from("IPv6")
+    } else {
+        String::from("IPv4")
+    };
+    for nla in &rule.nlas {
+        if let RuleNla::Priority(priority) = nla {
+            description.push_str(&format!(" {priority}:"));
+        }
+    }
+    if rule.header.flags & FIB_RULE_INVERT != 0 {
+        description.push_str(" not");
+    }
+    for nla in &rule.nlas {
+        if let RuleNla::FwMark(mark) = nla {
+            description.push_str(&format!(" fwmark {mark:#x}"));
+        }
+    }
+    description.push_str(&format!(" lookup {}", rule_table(rule)));
+    description
+}
+
 fn ip_to_bytes(addr: IpAddr) -> Vec<u8> {
     match addr {
         IpAddr::V4(addr) => addr.octets().to_vec(),
@@ -910,6 +1063,30 @@ impl NetworkInterface {
 mod test {
     use super::*;
 
+    #[test]
+    fn test_conflicting_rules() {
+        let mut other_mark = no_fwmark_rule_v4(0x1234, 1000);
+        other_mark.nlas.push(RuleNla::Priority(100));
+        let rules = [
+            other_mark,
+            no_fwmark_rule_v6(0x1234, 2000),
+            SUPPRESS_RULE_V4.clone(),
+        ];
+
+        assert_eq!(
+            conflicting_rules(&rules, 0x1234, 3000),
+            [
+                "IPv4 100: not fwmark 0x1234 lookup 1000",
+                "IPv6 not fwmark 0x1234 lookup 2000"
+            ]
+        );
+        assert_eq!(
+            conflicting_rules(&rules, 0x5678, 2000),
+            ["IPv6 not fwmark 0x1234 lookup 2000"]
+        );
+        assert!(conflicting_rules(&rules, 0x5678, 3000).is_empty());
+    }
+
     /// Tests if dropping inside a tokio runtime panics
     #[test]
     fn test_drop_in_executor() {
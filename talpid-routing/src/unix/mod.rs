@@ -91,6 +91,12 @@ pub(crate) enum RouteManagerCommand {
         Option<Fwmark>,
         oneshot::Sender<Result<Option<Route>, PlatformError>>,
     ),
+    /// Attempt to fetch a route for the given destination via the given interface.
+    GetInterfaceRoute(
+        IpAddr,
+        String,
+        oneshot::Sender<Result<Option<Route>, PlatformError>>,
+    ),
 }
 
 /// Commands for the underlying route manager object.
@@ -174,13 +180,90 @@ impl RouteManagerHandle {
         Ok(Self { tx: manage_tx })
     }
 
-    /// Stop route manager and revert all changes to routing
-    pub async fn stop(&self) {
+    /// Construct a route manager that never touches the routing table. Routes are accepted but
+    /// not applied, and there are no default routes. Meant for testing code that depends on a
+    /// route manager without requiring root.
+    #[cfg(all(
+        feature = "test-harness",
+        any(target_os = "linux", target_os = "macos")
+    ))]
+    pub fn spawn_mock() -> Self {
+        use futures::StreamExt;
+
+        let (manage_tx, mut manage_rx) = mpsc::unbounded();
+        tokio::spawn(async move {
+            while let Some(command) = manage_rx.next().await {
+                match command {
+                    RouteManagerCommand::AddRoutes(_routes, result_tx) => {
+                        let _ = result_tx.send(Ok(()));
+                    }
+                    RouteManagerCommand::ClearRoutes => (),
+                    RouteManagerCommand::Shutdown(done_tx) => {
+                        let _ = done_tx.send(());
+                        break;
+                    }
+                    #[cfg(target_os = "linux")]
+                    RouteManagerCommand::CreateRoutingRules(_enable_ipv6, result_tx) => {
+                        let _ = result_tx.send(Ok(()));
+                    }
+                    #[cfg(target_os = "linux")]
+                    RouteManagerCommand::ClearRoutingRules(result_tx) => {
+                        let _ = result_tx.send(Ok(()));
+                    }
+                    #[cfg(target_os = "linux")]
+                    RouteManagerCommand::NewChangeListener(result_tx) => {
+                        let (_listener_tx, listener_rx) = mpsc::unbounded();
+                        let _ = result_tx.send(listener_rx);
+                    }
+                    #[cfg(target_os = "linux")]
+                    RouteManagerCommand::GetMtuForRoute(_ip, result_tx) => {
+                        let _ = result_tx.send(Ok(1500));
+                    }
+                    #[cfg(target_os = "linux")]
+                    RouteManagerCommand::GetDestinationRoute(_ip, _fwmark, result_tx) => {
+                        let _ = result_tx.send(Ok(None));
+                    }
+                    #[cfg(target_os = "linux")]
+                    RouteManagerCommand::GetInterfaceRoute(_ip, _interface, result_tx) => {
+                        let _ = result_tx.send(Ok(None));
+                    }
+                    #[cfg(target_os = "macos")]
+                    RouteManagerCommand::RefreshRoutes => (),
+                    #[cfg(target_os = "macos")]
+                    RouteManagerCommand::NewDefaultRouteListener(result_tx) => {
+                        let (_listener_tx, listener_rx) = mpsc::unbounded();
+                        let _ = result_tx.send(listener_rx);
+                    }
+                    #[cfg(target_os = "macos")]
+                    RouteManagerCommand::GetDefaultRoutes(result_tx) => {
+                        let _ = result_tx.send((None, None));
+                    }
+                    #[cfg(target_os = "macos")]
+                    RouteManagerCommand::GetDefaultGateway(result_tx) => {
+                        let _ = result_tx.send((None, None));
+                    }
+                }
+            }
+        });
+
+        Self {
+            tx: Arc::new(manage_tx),
+        }
+    }
+
+    /// Returns whether the route manager is still running.
+    pub fn is_alive(&self) -> bool {
+        !self.tx.is_closed()
+    }
+
+    /// Stop route manager and revert all changes to routing. Fails if the route manager stopped
+    /// before it could revert them.
+    pub async fn stop(&self) -> Result<(), Error> {
         let (wait_tx, wait_rx) = oneshot::channel();
-        let _ = self
-            .tx
-            .unbounded_send(RouteManagerCommand::Shutdown(wait_tx));
-        let _ = wait_rx.await;
+        self.tx
+            .unbounded_send(RouteManagerCommand::Shutdown(wait_tx))
+            .map_err(|_| Error::RouteManagerDown)?;
+        wait_rx.await.map_err(|_| Error::ManagerChannelDown)
     }
 
     /// Applies the given routes until they are cleared
@@ -305,6 +388,28 @@ impl RouteManagerHandle {
             .map_err(Error::PlatformError)
     }
 
+    /// Return the route to `destination` via the interface named `interface`, regardless of
+    /// which interface the default route uses.
+    #[cfg(target_os = "linux")]
+    pub async fn get_interface_route(
+        &self,
+        destination: IpAddr,
+        interface: String,
+    ) -> Result<Option<Route>, Error> {
+        let (response_tx, response_rx) = oneshot::channel();
+        self.tx
+            .unbounded_send(RouteManagerCommand::GetInterfaceRoute(
+                destination,
+                interface,
+                response_tx,
+            ))
+            .map_err(|_| Error::RouteManagerDown)?;
+        response_rx
+            .await
+            .map_err(|_| Error::ManagerChannelDown)?
+            .map_err(Error::PlatformError)
+    }
+
     /// Listen for route changes.
     #[cfg(target_os = "linux")]
     pub async fn get_mtu_for_route(&self, ip: IpAddr) -> Result<u16, Error> {
@@ -123,6 +123,11 @@ impl RouteManagerHandle {
         Ok(handle)
     }
 
+    /// Returns whether the route manager is still running.
+    pub fn is_alive(&self) -> bool {
+        !self.tx.is_closed()
+    }
+
     /// Add a callback which will be called if the default route changes.
     pub async fn add_default_route_change_callback(
         &self,
@@ -156,13 +161,14 @@ impl RouteManagerHandle {
         response_rx.await.map_err(|_| Error::RouteManagerDown)?
     }
 
-    /// Stop the routing manager actor and revert all changes to routing
-    pub async fn stop(&self) {
+    /// Stop the routing manager actor and revert all changes to routing. Fails if the route
+    /// manager stopped before it could revert them.
+    pub async fn stop(&self) -> Result<()> {
         let (result_tx, result_rx) = oneshot::channel();
-        _ = self
-            .tx
-            .unbounded_send(RouteManagerCommand::Shutdown(result_tx));
-        _ = result_rx.await;
+        self.tx
+            .unbounded_send(RouteManagerCommand::Shutdown(result_tx))
+            .map_err(|_| Error::RouteManagerDown)?;
+        result_rx.await.map_err(|_| Error::RouteManagerDown)
     }
 
     /// Removes all routes previously applied in [`RouteManagerInternal::add_routes`].
@@ -2,7 +2,7 @@
 //! Useful to test this crate's implementation.
 
 use std::net::IpAddr;
-use talpid_types::net::wireguard::{PrivateKey, PublicKey};
+use talpid_types::net::wireguard::{PresharedKey, PrivateKey, PublicKey};
 
 #[tokio::main]
 async fn main() {
@@ -28,6 +28,10 @@ async fn main() {
     .await
     .unwrap();
 
-    println!("private key: {private_key:?}");
-    println!("psk: {:?}", ephemeral_peer.psk);
+    // Debug output of keys is redacted
+    println!("private key: {}", private_key.to_base64());
+    println!(
+        "psk: {:?}",
+        ephemeral_peer.psk.as_ref().map(PresharedKey::as_bytes)
+    );
 }
@@ -0,0 +1,150 @@
+//! Names of WireGuard tunnel interfaces on Linux.
+//!
+//! By default, the kernel names tun devices and the kernel WireGuard device is called
+//! `wg0-mullvad`. Names like `wg0` and `tun0` are also used by other tools and by containers, so
+//! a template can be given in [`INTERFACE_NAME_VAR`] instead. `%d` in the template is replaced by
+//! the lowest number that gives a name that is not in use. An interface that already exists is
+//! never reused, since it may belong to someone else.
+
+use std::iter;
+
+/// Environment variable that holds the interface name template.
+pub const INTERFACE_NAME_VAR: &str = "TALPID_TUNNEL_INTERFACE_NAME";
+
+/// Placeholder that is replaced by a number.
+const NUMBER_PLACEHOLDER: &str = "%d";
+
+/// Number of names to try for a template before giving up.
+const MAX_ATTEMPTS: usize = 100;
+
+/// Maximum length of an interface name, excluding the null terminator.
+const MAX_NAME_LEN: usize = nix::libc::IFNAMSIZ - 1;
+
+/// Errors that can occur while choosing an interface name.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The template does not produce valid interface names.
+    #[error("Invalid tunnel interface name template \"{0}\"")]
+    InvalidTemplate(String),
+
+    /// All names that the template produces are in use.
+    #[error("Every tunnel interface name that matches \"{0}\" is in use")]
+    NoUnusedName(String),
+}
+
+/// A tunnel interface name, or a template for one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameTemplate(String);
+
+impl NameTemplate {
+    /// Parse `template`. It may contain `%d` at most once.
+    pub fn new(template: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidTemplate(template.to_owned());
+
+        let longest_name_len = template
+            .replace(NUMBER_PLACEHOLDER, &(MAX_ATTEMPTS - 1).to_string())
+            .len();
+        if template.matches(NUMBER_PLACEHOLDER).count() > 1
+            || template.replace(NUMBER_PLACEHOLDER, "").contains('%')
+            || template.is_empty()
+            || longest_name_len > MAX_NAME_LEN
+            || template == "."
+            || template == ".."
+            || template
+                .chars()
+                .any(|c| c == '/' || c == ':' || c.is_whitespace() || !c.is_ascii())
+        {
+            return Err(invalid());
+        }
+        Ok(Self(template.to_owned()))
+    }
+
+    /// Read the template from [`INTERFACE_NAME_VAR`], if it is set.
+    pub fn from_env() -> Result<Option<Self>, Error> {
+        match std::env::var(INTERFACE_NAME_VAR) {
+            Ok(template) => Self::new(&template).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Return the names that the template produces, in the order in which they should be tried.
+    pub fn candidates(&self) -> Box<dyn Iterator<Item = String> + '_> {
+        if self.0.contains(NUMBER_PLACEHOLDER) {
+            Box::new(
+                (0..MAX_ATTEMPTS)
+                    .map(|number| self.0.replacen(NUMBER_PLACEHOLDER, &number.to_string(), 1)),
+            )
+        } else {
+            Box::new(iter::once(self.0.clone()))
+        }
+    }
+
+    /// Return the first name that is not used by any interface.
+    pub fn first_unused(&self) -> Result<String, Error> {
+        self.candidates()
+            .find(|name| !is_in_use(name))
+            .ok_or_else(|| Error::NoUnusedName(self.0.clone()))
+    }
+
+    /// Create an interface using `create`, with the first name that is not in use. If creating the
+    /// interface fails because the name was taken in the meantime, the next name is tried.
+    pub fn create<T, E: From<Error>>(
+        &self,
+        mut create: impl FnMut(&str) -> Result<T, E>,
+    ) -> Result<T, E> {
+        for name in self.candidates().filter(|name| !is_in_use(name)) {
+            match create(&name) {
+                Ok(interface) => return Ok(interface),
+                Err(_) if is_in_use(&name) => {
+                    log::debug!("Tunnel interface name {name} was taken. Trying the next one");
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        Err(E::from(Error::NoUnusedName(self.0.clone())))
+    }
+}
+
+impl std::fmt::Display for NameTemplate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Return whether an interface called `name` exists.
+pub fn is_in_use(name: &str) -> bool {
+    nix::net::if_::if_nametoindex(name).is_ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_template() {
+        assert!(NameTemplate::new("mlvd%d").is_ok());
+        assert!(NameTemplate::new("wg-mullvad").is_ok());
+        assert!(NameTemplate::new("").is_err());
+        assert!(NameTemplate::new("mlvd%d%d").is_err());
+        assert!(NameTemplate::new("mlvd%s").is_err());
+        assert!(NameTemplate::new("mlvd 0").is_err());
+        assert!(NameTemplate::new("mlvd/0").is_err());
+        // Room is needed for two digits
+        assert!(NameTemplate::new("mullvad-tunne%d").is_ok());
+        assert!(NameTemplate::new("mullvad-tunnel%d").is_err());
+        assert!(NameTemplate::new("mullvad-tunnel0").is_ok());
+        assert!(NameTemplate::new("mullvad-tunnel00").is_err());
+    }
+
+    #[test]
+    fn test_candidates() {
+        let template = NameTemplate::new("mlvd%d").unwrap();
+        let candidates: Vec<_> = template.candidates().take(3).collect();
+        assert_eq!(candidates, ["mlvd0", "mlvd1", "mlvd2"]);
+        assert_eq!(template.candidates().count(), MAX_ATTEMPTS);
+
+        let template = NameTemplate::new("wg-mullvad").unwrap();
+        let candidates: Vec<_> = template.candidates().collect();
+        assert_eq!(candidates, ["wg-mullvad"]);
+    }
+}
@@ -1,17 +1,29 @@
 use std::{
-    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     path::Path,
     sync::{Arc, Mutex},
+    time::Instant,
 };
 
 #[cfg(windows)]
 #[path = "windows.rs"]
 pub mod network_interface;
 
+#[cfg(target_os = "linux")]
+pub mod interface_name;
+#[cfg(target_os = "linux")]
+pub mod netns;
 pub mod tun_provider;
-use futures::{channel::oneshot, future::BoxFuture};
+use futures::{
+    channel::{mpsc, oneshot},
+    future::BoxFuture,
+};
 use talpid_routing::RouteManagerHandle;
-use talpid_types::net::AllowedTunnelTraffic;
+use talpid_types::{
+    net::AllowedTunnelTraffic,
+    tunnel::{ConnectPhase, ConnectProgress, ConnectTrace},
+};
 use tun_provider::TunProvider;
 
 /// Size of IPv4 header in bytes
@@ -46,6 +58,88 @@ where
     pub retry_attempt: u32,
     /// Route manager handle.
     pub route_manager: RouteManagerHandle,
+    /// Records how long each phase of establishing the tunnel takes.
+    pub connect_trace: ConnectTracer,
+}
+
+/// Measures how long each phase of establishing a tunnel takes. Clones share the same
+/// measurements.
+#[derive(Clone, Debug)]
+pub struct ConnectTracer {
+    inner: Arc<Mutex<ConnectTracerInner>>,
+}
+
+#[derive(Debug)]
+struct ConnectTracerInner {
+    start: Instant,
+    started_phases: HashMap<ConnectPhase, Instant>,
+    trace: ConnectTrace,
+    progress_tx: Option<mpsc::UnboundedSender<ConnectProgress>>,
+}
+
+impl ConnectTracer {
+    /// Starts measuring the total time it takes to connect.
+    pub fn new() -> Self {
+        Self::with_progress(None)
+    }
+
+    /// Starts measuring the total time it takes to connect, and sends a [`ConnectProgress`] on
+    /// `progress_tx` whenever a phase is started or finished.
+    pub fn with_progress(progress_tx: Option<mpsc::UnboundedSender<ConnectProgress>>) -> Self {
+        ConnectTracer {
+            inner: Arc::new(Mutex::new(ConnectTracerInner {
+                start: Instant::now(),
+                started_phases: HashMap::new(),
+                trace: ConnectTrace::default(),
+                progress_tx,
+            })),
+        }
+    }
+
+    /// Marks the start of `phase`.
+    pub fn start(&self, phase: ConnectPhase) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.started_phases.insert(phase, Instant::now());
+        inner.send_progress(phase, false);
+    }
+
+    /// Marks the end of `phase`, and adds the time since it was started to the trace. Nothing is
+    /// recorded if the phase was never started.
+    pub fn finish(&self, phase: ConnectPhase) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(start) = inner.started_phases.remove(&phase) {
+            inner.trace.add(phase, start.elapsed());
+            inner.send_progress(phase, true);
+        }
+    }
+
+    /// Returns the measurements so far, with the total time set to the time since the tracer was
+    /// created.
+    pub fn trace(&self) -> ConnectTrace {
+        let inner = self.inner.lock().unwrap();
+        ConnectTrace {
+            total: inner.start.elapsed(),
+            ..inner.trace.clone()
+        }
+    }
+}
+
+impl ConnectTracerInner {
+    fn send_progress(&self, phase: ConnectPhase, finished: bool) {
+        if let Some(progress_tx) = &self.progress_tx {
+            let _ = progress_tx.unbounded_send(ConnectProgress {
+                phase,
+                finished,
+                elapsed: self.start.elapsed(),
+            });
+        }
+    }
+}
+
+impl Default for ConnectTracer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// Information about a VPN tunnel.
@@ -59,6 +153,9 @@ pub struct TunnelMetadata {
     pub ipv4_gateway: Ipv4Addr,
     /// The IP to the IPv6 default gateway on the tunnel interface.
     pub ipv6_gateway: Option<Ipv6Addr>,
+    /// Whether packets are encrypted and decrypted in the kernel by OpenVPN data channel offload
+    /// (DCO), rather than by the OpenVPN process.
+    pub data_channel_offload: bool,
 }
 
 /// Possible events from the VPN tunnel and the child process managing it.
@@ -70,6 +167,11 @@ pub enum TunnelEvent {
     InterfaceUp(TunnelMetadata, AllowedTunnelTraffic),
     /// Sent when the tunnel comes up and is ready for traffic.
     Up(TunnelMetadata),
+    /// Sent when the tunnel is about to switch to another address of the relay, because the
+    /// current one stopped working. Traffic to the new address must be allowed before the
+    /// event is acknowledged. Also sent while connecting if the candidate relay responded before
+    /// the selected one, in which case the candidate is used from then on.
+    PeerEndpointChanged(SocketAddr),
     /// Sent when the tunnel goes down, but before destroying the tunnel device.
     Down,
 }
@@ -0,0 +1,132 @@
+//! Network namespaces on Linux.
+//!
+//! The daemon may run in a network namespace other than the one of the host, for example in a
+//! container. Host-wide integrations such as systemd-resolved and NetworkManager are then not
+//! reachable, or would configure the host rather than the namespace, so they are skipped.
+//!
+//! The tunnel device can also be moved to another namespace than the one of the daemon by naming
+//! it in [`TUNNEL_NETNS_VAR`]. Only programs in that namespace then use the tunnel, while the
+//! daemon and the rest of the system keep their own network configuration.
+
+use nix::sched::{setns, CloneFlags};
+use std::{
+    fs::File,
+    io,
+    os::unix::{fs::MetadataExt, io::AsRawFd},
+    path::{Path, PathBuf},
+};
+
+/// Environment variable that holds the namespace to move the tunnel device to.
+pub const TUNNEL_NETNS_VAR: &str = "TALPID_TUNNEL_NETNS";
+
+/// Directory in which `ip netns` keeps named namespaces.
+const NAMED_NETNS_DIR: &str = "/run/netns";
+
+/// Errors that can occur while using a network namespace.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    /// The namespace is neither a name nor an absolute path.
+    #[error("Invalid network namespace \"{0}\"")]
+    InvalidNamespace(String),
+
+    /// The namespace could not be opened.
+    #[error("Failed to open network namespace {0}")]
+    Open(PathBuf, #[source] io::Error),
+
+    /// The current thread could not enter the namespace.
+    #[error("Failed to enter network namespace {0}")]
+    Enter(PathBuf, #[source] nix::Error),
+}
+
+/// Return whether the current process shares its network namespace with init, i.e. runs in the
+/// network namespace of the host. The host namespace is assumed if this cannot be determined.
+pub fn is_host_namespace() -> bool {
+    let namespace_id =
+        |path: &str| std::fs::metadata(path).map(|metadata| (metadata.dev(), metadata.ino()));
+    match (
+        namespace_id("/proc/self/ns/net"),
+        namespace_id("/proc/1/ns/net"),
+    ) {
+        (Ok(own), Ok(init)) => own == init,
+        _ => true,
+    }
+}
+
+/// A network namespace, identified by a file such as `/run/netns/NAME`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkNamespace(PathBuf);
+
+impl NetworkNamespace {
+    /// Parse `namespace`, which is either the name of a namespace created by `ip netns add`, or
+    /// the absolute path to a namespace file, such as `/proc/PID/ns/net`.
+    pub fn new(namespace: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidNamespace(namespace.to_owned());
+
+        let path = Path::new(namespace);
+        if path.is_absolute() {
+            return Ok(Self(path.to_owned()));
+        }
+        if namespace.is_empty() || namespace == "." || namespace == ".." || namespace.contains('/')
+        {
+            return Err(invalid());
+        }
+        Ok(Self(Path::new(NAMED_NETNS_DIR).join(namespace)))
+    }
+
+    /// Read the namespace from [`TUNNEL_NETNS_VAR`], if it is set.
+    pub fn from_env() -> Result<Option<Self>, Error> {
+        match std::env::var(TUNNEL_NETNS_VAR) {
+            Ok(namespace) => Self::new(&namespace).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Path to the namespace file.
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Open the namespace file. The file can be used to move devices into the namespace.
+    pub fn open(&self) -> Result<File, Error> {
+        File::open(&self.0).map_err(|error| Error::Open(self.0.clone(), error))
+    }
+
+    /// Run `f` on a thread that has entered the namespace, and return its result. Sockets that
+    /// `f` opens belong to the namespace, even when they are later used from other threads.
+    pub fn run<T: Send>(&self, f: impl FnOnce() -> T + Send) -> Result<T, Error> {
+        let file = self.open()?;
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    setns(file.as_raw_fd(), CloneFlags::CLONE_NEWNET)
+                        .map_err(|error| Error::Enter(self.0.clone(), error))?;
+                    Ok(f())
+                })
+                .join()
+                .expect("network namespace thread panicked")
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_namespace_path() {
+        assert_eq!(
+            NetworkNamespace::new("vpn").unwrap().path(),
+            Path::new("/run/netns/vpn")
+        );
+        assert_eq!(
+            NetworkNamespace::new("/proc/1234/ns/net").unwrap().path(),
+            Path::new("/proc/1234/ns/net")
+        );
+        for invalid in ["", ".", "..", "a/b"] {
+            assert!(matches!(
+                NetworkNamespace::new(invalid),
+                Err(Error::InvalidNamespace(_))
+            ));
+        }
+    }
+}
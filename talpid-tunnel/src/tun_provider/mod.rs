@@ -60,6 +60,11 @@ pub struct TunConfig {
     /// Maximum Transmission Unit in the tunnel.
     #[cfg_attr(target_os = "android", jnix(map = "|mtu| mtu as i32"))]
     pub mtu: u16,
+
+    /// Number of queues to open the tunnel device with. More than one queue lets packets be read
+    /// from the device on several threads.
+    #[cfg(target_os = "linux")]
+    pub queues: usize,
 }
 
 #[cfg(target_os = "android")]
@@ -22,6 +22,11 @@ pub enum Error {
     /// Failure to set the tunnel device as up.
     #[error("Failed to set the tunnel device as up")]
     SetUp(#[source] NetworkInterfaceError),
+
+    /// Failure to choose a name for the tunnel device.
+    #[cfg(target_os = "linux")]
+    #[error("Failed to choose a name for the tunnel device")]
+    InterfaceName(#[from] crate::interface_name::Error),
 }
 
 /// Factory of tunnel devices on Unix systems.
@@ -39,7 +44,18 @@ impl UnixTunProvider {
     }
 
     pub fn get_tun(&mut self, config: TunConfig) -> Result<UnixTun, Error> {
-        let mut tunnel_device = TunnelDevice::new().map_err(Error::CreateTunnelDevice)?;
+        #[cfg(target_os = "linux")]
+        let mut tunnel_device = match crate::interface_name::NameTemplate::from_env()? {
+            Some(template) => template.create(|name| {
+                TunnelDevice::with_queues(Some(name), config.queues)
+                    .map_err(Error::CreateTunnelDevice)
+            })?,
+            None => {
+                TunnelDevice::with_queues(None, config.queues).map_err(Error::CreateTunnelDevice)?
+            }
+        };
+        #[cfg(not(target_os = "linux"))]
+        let mut tunnel_device = TunnelDevice::new(None).map_err(Error::CreateTunnelDevice)?;
 
         for ip in config.addresses.iter() {
             tunnel_device
@@ -63,6 +79,13 @@ impl UnixTun {
     pub fn interface_name(&self) -> &str {
         self.get_name()
     }
+
+    /// Retrieve the file descriptors of all queues of the device, starting with the one returned
+    /// by `as_raw_fd`.
+    #[cfg(target_os = "linux")]
+    pub fn queue_fds(&mut self) -> Vec<RawFd> {
+        self.0.queue_fds()
+    }
 }
 
 impl Deref for UnixTun {
@@ -122,18 +145,48 @@ pub struct TunnelDevice {
 }
 
 impl TunnelDevice {
-    /// Creates a new Tunnel device
+    /// Creates a new Tunnel device. The name is chosen by the OS unless `name` is given.
+    pub fn new(name: Option<&str>) -> Result<Self, NetworkInterfaceError> {
+        Self::with_queues(name, 1)
+    }
+
+    /// Creates a new Tunnel device with `queues` queues. The name is chosen by the OS unless
+    /// `name` is given. Only Linux supports more than one queue.
     #[allow(unused_mut)]
-    pub fn new() -> Result<Self, NetworkInterfaceError> {
+    pub fn with_queues(name: Option<&str>, queues: usize) -> Result<Self, NetworkInterfaceError> {
         let mut config = Configuration::default();
+        if let Some(name) = name {
+            config.name(name);
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            config.platform(|config| {
+                config.packet_information(true);
+            });
+            config.queues(queues.max(1));
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = queues;
 
+        let dev = platform::create(&config).map_err(NetworkInterfaceError::CreateDevice)?;
+        let mut device = Self { dev };
         #[cfg(target_os = "linux")]
-        config.platform(|config| {
-            config.packet_information(true);
-        });
-        let mut dev = platform::create(&config).map_err(NetworkInterfaceError::CreateDevice)?;
-        apply_async_flags(dev.as_raw_fd()).map_err(NetworkInterfaceError::SetDeviceAsync)?;
-        Ok(Self { dev })
+        let fds = device.queue_fds();
+        #[cfg(not(target_os = "linux"))]
+        let fds = [device.as_raw_fd()];
+        for fd in fds {
+            apply_async_flags(fd).map_err(NetworkInterfaceError::SetDeviceAsync)?;
+        }
+        Ok(device)
+    }
+
+    /// Retrieve the file descriptors of all queues of the device.
+    #[cfg(target_os = "linux")]
+    fn queue_fds(&mut self) -> Vec<RawFd> {
+        (0..)
+            .map_while(|index| self.dev.queue(index).map(|queue| queue.as_raw_fd()))
+            .collect()
     }
 }
 
@@ -1,6 +1,8 @@
 #[cfg(target_os = "android")]
 pub mod android;
 pub mod net;
+pub mod secret;
+pub mod self_test;
 pub mod tunnel;
 
 #[cfg(target_os = "linux")]
@@ -0,0 +1,134 @@
+use std::{collections::HashSet, net::IpAddr};
+
+/// Names that commonly appear in hosts files, but which should never be blocked.
+const IGNORED_NAMES: &[&str] = &[
+    "localhost",
+    "localhost.localdomain",
+    "local",
+    "broadcasthost",
+    "ip6-localhost",
+    "ip6-loopback",
+];
+
+/// A set of domains that should not be resolved. A domain that is on the list also blocks all of
+/// its subdomains.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Blocklist {
+    domains: HashSet<String>,
+}
+
+impl Blocklist {
+    /// Parse a single blocklist. See [`Blocklist::add_list`] for the accepted formats.
+    pub fn parse(list: &str) -> Self {
+        let mut blocklist = Self::default();
+        blocklist.add_list(list);
+        blocklist
+    }
+
+    /// Add the domains in `list` to the blocklist. Each line may either be a hosts file entry
+    /// (`0.0.0.0 example.com`), a single domain (`example.com`), or a basic adblock rule
+    /// (`||example.com^`). Comments starting with `#` or `!` and lines that cannot be parsed
+    /// are ignored.
+    pub fn add_list(&mut self, list: &str) {
+        for line in list.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.starts_with('!') {
+                continue;
+            }
+            let mut fields = line.split_whitespace().peekable();
+            if fields
+                .peek()
+                .map(|field| field.parse::<IpAddr>().is_ok())
+                .unwrap_or(false)
+            {
+                // Hosts file entries may have several names
+                fields.next();
+                for name in fields {
+                    self.add_domain(name);
+                }
+            } else if let Some(field) = fields.next() {
+                let name = field
+                    .strip_prefix("||")
+                    .and_then(|rule| rule.strip_suffix('^'))
+                    .unwrap_or(field);
+                self.add_domain(name);
+            }
+        }
+    }
+
+    fn add_domain(&mut self, name: &str) {
+        let name = name.trim_end_matches('.').to_ascii_lowercase();
+        let is_valid = name.contains('.')
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_'))
+            && name.parse::<IpAddr>().is_err()
+            && !IGNORED_NAMES.contains(&name.as_str());
+        if is_valid {
+            self.domains.insert(name);
+        }
+    }
+
+    /// Add all domains on `other` to this blocklist.
+    pub fn merge(&mut self, other: Blocklist) {
+        self.domains.extend(other.domains);
+    }
+
+    /// Returns whether `name`, or any domain that `name` is a subdomain of, is blocked.
+    pub fn contains(&self, name: &str) -> bool {
+        let name = name.trim_end_matches('.').to_ascii_lowercase();
+        let mut domain = name.as_str();
+        loop {
+            if self.domains.contains(domain) {
+                return true;
+            }
+            match domain.split_once('.') {
+                Some((_, parent)) => domain = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// Returns the number of blocked domains.
+    pub fn len(&self) -> usize {
+        self.domains.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.domains.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Blocklist;
+
+    #[test]
+    fn test_parse_blocklist() {
+        let blocklist = Blocklist::parse(
+            "# A comment\n\
+             127.0.0.1 localhost\n\
+             0.0.0.0 ads.example.com tracker.example.com # trailing comment\n\
+             ::1 ip6-localhost\n\
+             Malware.Example.NET.\n\
+             ||adblock.example.org^\n\
+             ! adblock comment\n\
+             com\n\
+             not a domain\n\
+             1.2.3.4\n",
+        );
+
+        assert_eq!(blocklist.len(), 4);
+        assert!(blocklist.contains("ads.example.com"));
+        assert!(blocklist.contains("tracker.example.com."));
+        assert!(blocklist.contains("malware.example.net"));
+        assert!(blocklist.contains("adblock.example.org"));
+
+        assert!(blocklist.contains("sub.ADS.example.com"));
+        assert!(!blocklist.contains("example.com"));
+        assert!(!blocklist.contains("notads.example.com"));
+        assert!(!blocklist.contains("localhost"));
+        assert!(!blocklist.contains("mullvad.net"));
+    }
+}
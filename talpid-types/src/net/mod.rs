@@ -10,6 +10,7 @@ use std::{
 
 use self::proxy::{CustomProxy, Socks5Local};
 
+pub mod dns;
 pub mod obfuscation;
 pub mod openvpn;
 pub mod proxy;
@@ -38,6 +39,8 @@ impl TunnelParameters {
                 obfuscation: None,
                 entry_endpoint: None,
                 tunnel_interface: None,
+                ephemeral_peer_transport: None,
+                data_channel_offload: false,
                 #[cfg(target_os = "windows")]
                 daita: false,
             },
@@ -55,6 +58,8 @@ impl TunnelParameters {
                     .get_exit_endpoint()
                     .map(|_| params.connection.get_endpoint()),
                 tunnel_interface: None,
+                ephemeral_peer_transport: None,
+                data_channel_offload: false,
                 #[cfg(target_os = "windows")]
                 daita: params.options.daita,
             },
@@ -79,10 +84,12 @@ impl TunnelParameters {
 
     fn get_obfuscator_endpoint(obfuscator: &ObfuscatorConfig) -> Endpoint {
         match obfuscator {
-            ObfuscatorConfig::Udp2Tcp { endpoint } => Endpoint {
-                address: *endpoint,
-                protocol: TransportProtocol::Tcp,
-            },
+            ObfuscatorConfig::Udp2Tcp { endpoint } | ObfuscatorConfig::Tls { endpoint, .. } => {
+                Endpoint {
+                    address: *endpoint,
+                    protocol: TransportProtocol::Tcp,
+                }
+            }
         }
     }
 
@@ -107,7 +114,7 @@ impl TunnelParameters {
                 params
                     .proxy
                     .as_ref()
-                    .and_then(|proxy_settings| match proxy_settings {
+                    .and_then(|proxy_chain| match proxy_chain.first_hop() {
                         CustomProxy::Socks5Local(local_settings) => Some(local_settings),
                         _ => None,
                     })
@@ -179,6 +186,12 @@ pub struct TunnelEndpoint {
     pub obfuscation: Option<ObfuscationEndpoint>,
     pub entry_endpoint: Option<Endpoint>,
     pub tunnel_interface: Option<String>,
+    /// Transport protocol that the ephemeral peer was negotiated over, if one was negotiated.
+    /// Only known once connected.
+    pub ephemeral_peer_transport: Option<TransportProtocol>,
+    /// Whether OpenVPN data channel offload (DCO) is used. Only known once connected.
+    #[serde(default)]
+    pub data_channel_offload: bool,
     #[cfg(target_os = "windows")]
     pub daita: bool,
 }
@@ -214,12 +227,15 @@ impl fmt::Display for TunnelEndpoint {
 pub enum ObfuscationType {
     #[serde(rename = "udp2tcp")]
     Udp2Tcp,
+    #[serde(rename = "tls")]
+    Tls,
 }
 
 impl fmt::Display for ObfuscationType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         match self {
             ObfuscationType::Udp2Tcp => "Udp2Tcp".fmt(f),
+            ObfuscationType::Tls => "TLS".fmt(f),
         }
     }
 }
@@ -241,6 +257,13 @@ impl From<&ObfuscatorConfig> for ObfuscationEndpoint {
                 },
                 ObfuscationType::Udp2Tcp,
             ),
+            ObfuscatorConfig::Tls { endpoint, .. } => (
+                Endpoint {
+                    address: *endpoint,
+                    protocol: TransportProtocol::Tcp,
+                },
+                ObfuscationType::Tls,
+            ),
         };
 
         ObfuscationEndpoint {
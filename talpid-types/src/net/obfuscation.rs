@@ -3,5 +3,14 @@ use std::net::SocketAddr;
 
 #[derive(Clone, Eq, PartialEq, Deserialize, Serialize, Debug)]
 pub enum ObfuscatorConfig {
-    Udp2Tcp { endpoint: SocketAddr },
+    Udp2Tcp {
+        endpoint: SocketAddr,
+    },
+    /// Wraps the tunnel in a TLS session with the server name `sni`. The server must present the
+    /// certificate with the SHA-256 digest `certificate_sha256`.
+    Tls {
+        endpoint: SocketAddr,
+        sni: String,
+        certificate_sha256: [u8; 32],
+    },
 }
@@ -1,7 +1,11 @@
-use crate::net::{Endpoint, GenericTunnelOptions};
+use crate::{
+    net::{Endpoint, GenericTunnelOptions},
+    secret::SecretString,
+};
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
-use super::proxy::CustomProxy;
+use super::proxy::ProxyChain;
 
 /// Information needed by `OpenVpnMonitor` to establish a tunnel connection.
 /// See [`crate::net::TunnelParameters`].
@@ -10,25 +14,40 @@ pub struct TunnelParameters {
     pub config: ConnectionConfig,
     pub options: TunnelOptions,
     pub generic_options: GenericTunnelOptions,
-    pub proxy: Option<CustomProxy>,
+    pub proxy: Option<ProxyChain>,
+    /// CA certificate to verify the server with. The one in the resource directory is used if
+    /// this is `None`.
+    pub ca: Option<PathBuf>,
+    /// User and group that OpenVPN switches to once the tunnel is up. OpenVPN keeps running as
+    /// the current user if this is `None`.
+    #[cfg(unix)]
+    pub privilege_drop: Option<PrivilegeDrop>,
     #[cfg(target_os = "linux")]
     pub fwmark: u32,
 }
 
+/// Unprivileged user and group to run OpenVPN as. See [`TunnelParameters::privilege_drop`].
+#[cfg(unix)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
+pub struct PrivilegeDrop {
+    pub user: String,
+    pub group: String,
+}
+
 /// Connection configuration used by [`TunnelParameters`].
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
 pub struct ConnectionConfig {
     pub endpoint: Endpoint,
-    pub username: String,
-    pub password: String,
+    pub username: SecretString,
+    pub password: SecretString,
 }
 
 impl ConnectionConfig {
     pub fn new(endpoint: Endpoint, username: String, password: String) -> ConnectionConfig {
         Self {
             endpoint,
-            username,
-            password,
+            username: SecretString::from(username),
+            password: SecretString::from(password),
         }
     }
 }
@@ -37,9 +56,39 @@ impl ConnectionConfig {
 /// irrespective of the relay parameters - i.e. have nothing to do with the particular
 /// OpenVPN server, but do affect the connection.
 /// Stored in [`TunnelParameters`].
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TunnelOptions {
     /// Optional argument for openvpn to try and limit TCP packet size,
     /// as discussed [here](https://openvpn.net/archive/openvpn-users/2003-11/msg00154.html)
     pub mssfix: Option<u16>,
+    /// Seconds to wait for a response from the server. A default that depends on the transport
+    /// protocol is used if this is `None`.
+    #[serde(default)]
+    pub connect_timeout: Option<u16>,
+    /// Seconds to wait before resending a control channel packet that the server has not
+    /// acknowledged. A default that depends on the transport protocol is used if this is `None`.
+    #[serde(default)]
+    pub retry_timeout: Option<u16>,
+    /// Confine the OpenVPN process with a seccomp filter. This should only be disabled if the
+    /// filter prevents OpenVPN from working.
+    #[cfg(target_os = "linux")]
+    #[serde(default = "default_sandbox")]
+    pub sandbox: bool,
+}
+
+impl Default for TunnelOptions {
+    fn default() -> Self {
+        Self {
+            mssfix: None,
+            connect_timeout: None,
+            retry_timeout: None,
+            #[cfg(target_os = "linux")]
+            sandbox: default_sandbox(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn default_sandbox() -> bool {
+    true
 }
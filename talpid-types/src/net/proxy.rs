@@ -1,4 +1,4 @@
-use crate::net::Endpoint;
+use crate::{net::Endpoint, secret::SecretString};
 use serde::{Deserialize, Serialize};
 use std::{fmt, net::SocketAddr};
 
@@ -9,8 +9,22 @@ pub enum Error {
     /// Validation of SOCKS5 username or password failed.
     #[error("Invalid SOCKS5 authentication credentials: {0}")]
     InvalidSocksAuthValues(&'static str),
+
+    /// A proxy chain must contain at least one proxy.
+    #[error("Proxy chain is empty")]
+    EmptyProxyChain,
+
+    /// A local SOCKS5 proxy can only be reached from this machine.
+    #[error("A local SOCKS5 proxy can only be the first proxy in a chain")]
+    LocalProxyNotFirst,
+
+    #[error("Proxy chain has more than {} proxies", MAX_PROXY_CHAIN_LEN)]
+    ProxyChainTooLong,
 }
 
+/// Maximum number of proxies in a [`ProxyChain`].
+pub const MAX_PROXY_CHAIN_LEN: usize = 4;
+
 /// Types of bridges that can be used to proxy a connection to a tunnel
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -63,6 +77,105 @@ impl CustomProxy {
             },
         }
     }
+
+    /// Returns the hostname of the proxy, if it is referenced by one.
+    pub fn hostname(&self) -> Option<&str> {
+        match self {
+            CustomProxy::Shadowsocks(settings) => settings.hostname.as_deref(),
+            CustomProxy::Socks5Remote(settings) => settings.hostname.as_deref(),
+            CustomProxy::Socks5Local(_) => None,
+        }
+    }
+}
+
+/// Proxies that a connection passes through, in order. Only the first proxy is connected to
+/// directly. Every following proxy is reached through the ones before it, so a blocked proxy can
+/// be reached through one that isn't.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(try_from = "Vec<CustomProxy>", into = "Vec<CustomProxy>")]
+pub struct ProxyChain {
+    hops: Vec<CustomProxy>,
+}
+
+impl ProxyChain {
+    /// Create a chain that passes through `hops`, starting with the first one.
+    ///
+    /// # Examples
+    ///
+    /// A local SOCKS5 proxy can only be the first hop, since it can't be reached through other
+    /// proxies.
+    ///
+    /// ```
+    /// use talpid_types::net::proxy::{ProxyChain, Shadowsocks, Socks5Local, Socks5Remote};
+    ///
+    /// let shadowsocks = Shadowsocks::new(
+    ///     ([192, 0, 2, 1], 443),
+    ///     "aes-256-gcm".to_string(),
+    ///     "mullvad".to_string(),
+    /// );
+    /// let local = Socks5Local::new(([192, 0, 2, 2], 1080), 1080);
+    /// let remote = Socks5Remote::new(([192, 0, 2, 3], 1080));
+    ///
+    /// assert!(ProxyChain::new(vec![shadowsocks.clone().into(), remote.into()]).is_ok());
+    /// assert!(ProxyChain::new(vec![shadowsocks.into(), local.into()]).is_err());
+    /// assert!(ProxyChain::new(vec![]).is_err());
+    /// ```
+    pub fn new(hops: Vec<CustomProxy>) -> Result<Self, Error> {
+        if hops.is_empty() {
+            return Err(Error::EmptyProxyChain);
+        }
+        if hops.len() > MAX_PROXY_CHAIN_LEN {
+            return Err(Error::ProxyChainTooLong);
+        }
+        if hops
+            .iter()
+            .skip(1)
+            .any(|hop| matches!(hop, CustomProxy::Socks5Local(_)))
+        {
+            return Err(Error::LocalProxyNotFirst);
+        }
+        Ok(ProxyChain { hops })
+    }
+
+    /// The proxy that is connected to directly.
+    pub fn first_hop(&self) -> &CustomProxy {
+        &self.hops[0]
+    }
+
+    pub fn hops(&self) -> &[CustomProxy] {
+        &self.hops
+    }
+
+    /// Returns whether the chain has more than one proxy.
+    pub fn is_chained(&self) -> bool {
+        self.hops.len() > 1
+    }
+
+    /// The endpoint that must be reachable for the chain to work. This is the remote endpoint of
+    /// the first hop, since the other hops are reached through it.
+    pub fn get_remote_endpoint(&self) -> ProxyEndpoint {
+        self.first_hop().get_remote_endpoint()
+    }
+}
+
+impl From<CustomProxy> for ProxyChain {
+    fn from(proxy: CustomProxy) -> Self {
+        ProxyChain { hops: vec![proxy] }
+    }
+}
+
+impl TryFrom<Vec<CustomProxy>> for ProxyChain {
+    type Error = Error;
+
+    fn try_from(hops: Vec<CustomProxy>) -> Result<Self, Self::Error> {
+        ProxyChain::new(hops)
+    }
+}
+
+impl From<ProxyChain> for Vec<CustomProxy> {
+    fn from(chain: ProxyChain) -> Self {
+        chain.hops
+    }
 }
 
 impl From<Socks5Remote> for CustomProxy {
@@ -86,10 +199,14 @@ impl From<Shadowsocks> for CustomProxy {
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct Shadowsocks {
     pub endpoint: SocketAddr,
-    pub password: String,
+    pub password: SecretString,
     /// One of [`SHADOWSOCKS_CIPHERS`].
     /// Gets validated at a later stage. Is assumed to be valid.
     pub cipher: String,
+    /// Hostname of the proxy. If set, the IP of `endpoint` is replaced by an address of this host
+    /// before the proxy is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
@@ -103,6 +220,10 @@ pub struct Socks5Local {
 pub struct Socks5Remote {
     pub endpoint: SocketAddr,
     pub auth: Option<SocksAuth>,
+    /// Hostname of the proxy. If set, the IP of `endpoint` is replaced by an address of this host
+    /// before the proxy is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
 }
 
 /// A valid SOCKS5 username/password authentication according to
@@ -110,7 +231,7 @@ pub struct Socks5Remote {
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct SocksAuth {
     username: String,
-    password: String,
+    password: SecretString,
 }
 
 impl SocksAuth {
@@ -170,7 +291,10 @@ impl SocksAuth {
             ));
         }
 
-        Ok(SocksAuth { username, password })
+        Ok(SocksAuth {
+            username,
+            password: SecretString::from(password),
+        })
     }
 
     /// Read the username.
@@ -180,7 +304,7 @@ impl SocksAuth {
 
     /// Read the password.
     pub fn password(&self) -> &str {
-        &self.password
+        self.password.expose_str()
     }
 }
 
@@ -188,10 +312,16 @@ impl Shadowsocks {
     pub fn new<I: Into<SocketAddr>>(endpoint: I, cipher: String, password: String) -> Self {
         Shadowsocks {
             endpoint: endpoint.into(),
-            password,
+            password: SecretString::from(password),
             cipher,
+            hostname: None,
         }
     }
+
+    /// Sets the hostname of the proxy. See [`Shadowsocks::hostname`].
+    pub fn with_hostname(self, hostname: Option<String>) -> Self {
+        Self { hostname, ..self }
+    }
 }
 
 impl Socks5Local {
@@ -219,6 +349,7 @@ impl Socks5Remote {
         Self {
             endpoint: endpoint.into(),
             auth: None,
+            hostname: None,
         }
     }
 
@@ -229,8 +360,14 @@ impl Socks5Remote {
         Self {
             endpoint: endpoint.into(),
             auth: Some(authentication),
+            hostname: None,
         }
     }
+
+    /// Sets the hostname of the proxy. See [`Socks5Remote::hostname`].
+    pub fn with_hostname(self, hostname: Option<String>) -> Self {
+        Self { hostname, ..self }
+    }
 }
 
 /// List of ciphers usable by a Shadowsocks proxy.
@@ -6,6 +6,7 @@ use std::{
     cmp, fmt,
     hash::{Hash, Hasher},
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
 };
 use zeroize::{Zeroize, ZeroizeOnDrop};
 
@@ -19,12 +20,58 @@ pub struct TunnelParameters {
     pub obfuscation: Option<super::obfuscation::ObfuscatorConfig>,
 }
 
+impl TunnelParameters {
+    /// Returns the transport protocol that the ephemeral peer is negotiated over, or `None` if
+    /// no ephemeral peer is negotiated.
+    pub fn ephemeral_peer_transport(&self) -> Option<TransportProtocol> {
+        #[cfg(target_os = "windows")]
+        let daita = self.options.daita;
+        #[cfg(not(target_os = "windows"))]
+        let daita = false;
+
+        if !self.options.quantum_resistant && !daita {
+            return None;
+        }
+        let transport = match &self.obfuscation {
+            Some(obfuscation) => {
+                super::ObfuscationEndpoint::from(obfuscation)
+                    .endpoint
+                    .protocol
+            }
+            None => TransportProtocol::Udp,
+        };
+        Some(transport)
+    }
+
+    /// Returns the candidate peer to race against the entry peer while connecting, or `None` if
+    /// there is none or it cannot be raced with these parameters. Racing is only done for
+    /// single-hop tunnels that connect directly to the relay without negotiating an ephemeral
+    /// peer.
+    pub fn candidate_peer(&self) -> Option<&PeerConfig> {
+        // The Windows firewall only allows traffic to a single relay while connecting
+        if cfg!(target_os = "windows") {
+            return None;
+        }
+        if self.connection.exit_peer.is_some()
+            || self.obfuscation.is_some()
+            || self.ephemeral_peer_transport().is_some()
+        {
+            return None;
+        }
+        self.connection.candidate_peer.as_ref()
+    }
+}
+
 /// Connection-specific configuration in [`TunnelParameters`].
 #[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub struct ConnectionConfig {
     pub tunnel: TunnelConfig,
     pub peer: PeerConfig,
     pub exit_peer: Option<PeerConfig>,
+    /// Another relay to connect to in parallel with `peer`. Whichever responds to a handshake
+    /// first is used as the entry peer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub candidate_peer: Option<PeerConfig>,
     /// Gateway used by the tunnel (a private address).
     pub ipv4_gateway: Ipv4Addr,
     pub ipv6_gateway: Option<Ipv6Addr>,
@@ -46,6 +93,31 @@ impl ConnectionConfig {
             protocol: TransportProtocol::Udp,
         })
     }
+
+    /// Returns the destinations that are routed through the tunnel, or `None` if all traffic
+    /// is routed through it.
+    pub fn partial_tunnel_destinations(&self) -> Option<&[IpNetwork]> {
+        let allowed_ips = &self.exit_peer.as_ref().unwrap_or(&self.peer).allowed_ips;
+        let routes_everything = super::all_of_the_internet()
+            .iter()
+            .all(|default_route| allowed_ips.contains(default_route));
+        if routes_everything {
+            None
+        } else {
+            Some(allowed_ips)
+        }
+    }
+
+    /// Replaces `peer` with `candidate_peer`, which takes over the allowed IPs of `peer`.
+    /// Returns `false` if there is no candidate peer.
+    pub fn select_candidate_peer(&mut self) -> bool {
+        let Some(mut candidate) = self.candidate_peer.take() else {
+            return false;
+        };
+        candidate.allowed_ips = std::mem::take(&mut self.peer.allowed_ips);
+        self.peer = candidate;
+        true
+    }
 }
 
 #[derive(Clone, Eq, PartialEq, Deserialize, Serialize, Debug, Hash)]
@@ -56,6 +128,10 @@ pub struct PeerConfig {
     pub allowed_ips: Vec<IpNetwork>,
     /// IP address of the WireGuard server.
     pub endpoint: SocketAddr,
+    /// Address of the same server in the other IP family, if it has one. The tunnel may switch
+    /// to this address if the path to `endpoint` stops working.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alternate_endpoint: Option<SocketAddr>,
     /// Preshared key (PSK). The PSK should never be persisted, so it does not serialize
     /// or deserialize. A PSK is only used with quantum-resistant tunnels and are then
     /// ephemeral and living in memory only.
@@ -67,6 +143,17 @@ pub struct PeerConfig {
     pub constant_packet_size: bool,
 }
 
+impl PeerConfig {
+    /// Switches to `alternate_endpoint` and keeps the current endpoint as the alternate one.
+    /// Returns the new endpoint, or `None` if there is no alternate endpoint.
+    pub fn switch_endpoint(&mut self) -> Option<SocketAddr> {
+        let alternate = self.alternate_endpoint?;
+        self.alternate_endpoint = Some(self.endpoint);
+        self.endpoint = alternate;
+        Some(alternate)
+    }
+}
+
 #[derive(Clone, Eq, PartialEq, Deserialize, Serialize, Debug)]
 pub struct TunnelConfig {
     pub private_key: PrivateKey,
@@ -84,6 +171,10 @@ pub struct TunnelOptions {
     /// Enable DAITA during tunnel config
     #[cfg(target_os = "windows")]
     pub daita: bool,
+    /// Send all traffic to the relays through this interface, instead of the one that the default
+    /// route uses.
+    #[cfg(target_os = "linux")]
+    pub egress_interface: Option<String>,
 }
 
 /// Wireguard x25519 private key
@@ -130,7 +221,7 @@ impl cmp::Eq for PrivateKey {}
 
 impl fmt::Debug for PrivateKey {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", &self)
+        f.write_str(crate::secret::REDACTED)
     }
 }
 
@@ -186,6 +277,15 @@ impl PublicKey {
     }
 }
 
+impl FromStr for PublicKey {
+    type Err = InvalidKey;
+
+    /// Parse a base64 encoded key.
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        Self::from_base64(key)
+    }
+}
+
 impl<'a> From<&'a x25519_dalek::StaticSecret> for PublicKey {
     fn from(private_key: &'a x25519_dalek::StaticSecret) -> PublicKey {
         PublicKey(x25519_dalek::PublicKey::from(private_key))
@@ -273,7 +373,7 @@ impl From<Box<[u8; 32]>> for PresharedKey {
 
 impl fmt::Debug for PresharedKey {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", &STANDARD.encode(self.as_bytes()))
+        f.write_str(crate::secret::REDACTED)
     }
 }
 
@@ -0,0 +1,127 @@
+//! Wrapper for secret values, such as account numbers, passwords and private keys.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, hash};
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+/// What is printed in place of a secret value.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// A secret value that is zeroed when dropped and never printed, neither by `Debug` nor by
+/// `Display`. The value is (de)serialized as is, since secrets must still be persisted and sent to
+/// other processes. Use [`Secret::expose`] to read the value.
+pub struct Secret<T: Zeroize>(T);
+
+/// A secret string, such as an account number or a password.
+pub type SecretString = Secret<String>;
+
+impl<T: Zeroize> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Secret(value)
+    }
+
+    /// Read the secret value. Take care not to log or otherwise leak it.
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl SecretString {
+    /// Read the secret string. Take care not to log or otherwise leak it.
+    pub fn expose_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl<T: Zeroize> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Secret(value)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(value: &str) -> Self {
+        Secret(value.to_owned())
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<T: Zeroize> ZeroizeOnDrop for Secret<T> {}
+
+impl<T: Zeroize + Clone> Clone for Secret<T> {
+    fn clone(&self) -> Self {
+        Secret(self.0.clone())
+    }
+}
+
+impl<T: Zeroize + PartialEq> PartialEq for Secret<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<T: Zeroize + Eq> Eq for Secret<T> {}
+
+impl<T: Zeroize + hash::Hash> hash::Hash for Secret<T> {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl<T: Zeroize + Default> Default for Secret<T> {
+    fn default() -> Self {
+        Secret(T::default())
+    }
+}
+
+impl<T: Zeroize> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T: Zeroize> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T: Zeroize + Serialize> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, T: Zeroize + Deserialize<'de>> Deserialize<'de> for Secret<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Secret)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_redacted() {
+        let secret = SecretString::from("hunter2");
+        assert_eq!(format!("{secret}"), REDACTED);
+        assert_eq!(format!("{secret:?}"), REDACTED);
+        assert_eq!(format!("{:?}", Some(secret.clone())), "Some([REDACTED])");
+        assert_eq!(secret.expose_str(), "hunter2");
+    }
+
+    #[test]
+    fn test_serde_transparent() {
+        let secret = SecretString::from("hunter2");
+        let json = serde_json::to_string(&secret).unwrap();
+        assert_eq!(json, r#""hunter2""#);
+        let deserialized: SecretString = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, secret);
+    }
+}
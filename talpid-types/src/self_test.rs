@@ -0,0 +1,19 @@
+//! Results of the self test, which checks that the parts of the system that tunnels depend on work
+//! without connecting to a relay.
+
+/// A step of the self test and how it went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfTestStep {
+    /// What the step tests.
+    pub name: String,
+    pub outcome: StepOutcome,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepOutcome {
+    Passed,
+    /// The step failed. Contains the error chain.
+    Failed(String),
+    /// The step was not run. Contains the reason.
+    Skipped(String),
+}
@@ -1,8 +1,8 @@
 use crate::net::TunnelEndpoint;
 use serde::{Deserialize, Serialize};
-use std::fmt;
 #[cfg(target_os = "android")]
 use std::net::IpAddr;
+use std::{error::Error, fmt, io, time::Duration};
 
 /// Event emitted from the states in `talpid_core::tunnel_state_machine` when the tunnel state
 /// machine enters a new state.
@@ -15,14 +15,176 @@ pub enum TunnelStateTransition {
     },
     /// Network is secured but tunnel is still connecting.
     Connecting(TunnelEndpoint),
-    /// Tunnel is connected.
-    Connected(TunnelEndpoint),
+    /// Tunnel is connected. Includes how long it took to connect.
+    Connected(TunnelEndpoint, ConnectTrace),
     /// Disconnecting tunnel.
     Disconnecting(ActionAfterDisconnect),
     /// Tunnel is disconnected but usually secured by blocking all connections.
     Error(ErrorState),
 }
 
+/// A phase of establishing a tunnel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectPhase {
+    /// Selecting a relay and generating the tunnel parameters.
+    RelaySelection,
+    /// Starting a local obfuscator or proxy.
+    Obfuscation,
+    /// Creating the tunnel device or starting the tunnel process.
+    TunnelCreation,
+    /// Waiting for the relay to respond, including any ephemeral peer negotiation.
+    Handshake,
+    /// Adding routes.
+    Routes,
+    /// Applying DNS settings.
+    Dns,
+    /// Applying firewall policies.
+    Firewall,
+}
+
+impl fmt::Display for ConnectPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let phase = match self {
+            ConnectPhase::RelaySelection => "Relay selection",
+            ConnectPhase::Obfuscation => "Obfuscation",
+            ConnectPhase::TunnelCreation => "Tunnel creation",
+            ConnectPhase::Handshake => "Handshake",
+            ConnectPhase::Routes => "Routes",
+            ConnectPhase::Dns => "DNS",
+            ConnectPhase::Firewall => "Firewall",
+        };
+        f.write_str(phase)
+    }
+}
+
+/// Time spent in each phase of establishing a tunnel, to help diagnose slow connects.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectTrace {
+    /// Time from starting to connect until the tunnel was up.
+    pub total: Duration,
+    /// Total time spent in each phase, in the order that the phases were first entered. Phases
+    /// that are entered several times, such as applying firewall policies, are added up.
+    pub phases: Vec<(ConnectPhase, Duration)>,
+}
+
+impl ConnectTrace {
+    /// Add `duration` to the time spent in `phase`.
+    pub fn add(&mut self, phase: ConnectPhase, duration: Duration) {
+        match self
+            .phases
+            .iter_mut()
+            .find(|(existing, _)| *existing == phase)
+        {
+            Some((_, total)) => *total += duration,
+            None => self.phases.push((phase, duration)),
+        }
+    }
+
+    /// Returns the time spent in `phase`, or `None` if it was never entered.
+    pub fn get(&self, phase: ConnectPhase) -> Option<Duration> {
+        self.phases
+            .iter()
+            .find(|(existing, _)| *existing == phase)
+            .map(|(_, duration)| *duration)
+    }
+}
+
+/// A phase of a connection attempt that was started or finished, emitted while connecting so that
+/// the progress can be shown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectProgress {
+    /// The phase that was started or finished.
+    pub phase: ConnectPhase,
+    /// Whether the phase was finished rather than started.
+    pub finished: bool,
+    /// Time since the connection attempt started.
+    pub elapsed: Duration,
+}
+
+/// A step of restoring the network when the tunnel state machine shuts down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TeardownStep {
+    /// Stopping the tunnel.
+    TunnelStop,
+    /// Removing the routes and routing rules that were added.
+    RouteRestore,
+    /// Restoring the DNS settings of the system.
+    DnsRestore,
+    /// Resetting the firewall, or leaving it blocking if traffic should be blocked after exiting.
+    FirewallReset,
+}
+
+impl fmt::Display for TeardownStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let step = match self {
+            TeardownStep::TunnelStop => "Tunnel stop",
+            TeardownStep::RouteRestore => "Route restore",
+            TeardownStep::DnsRestore => "DNS restore",
+            TeardownStep::FirewallReset => "Firewall reset",
+        };
+        f.write_str(step)
+    }
+}
+
+/// Outcome of a [`TeardownStep`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TeardownStepResult {
+    pub step: TeardownStep,
+    /// Time that the step took.
+    pub duration: Duration,
+    /// Why the step failed, or `None` if it succeeded.
+    pub error: Option<ErrorDetails>,
+}
+
+/// Outcome of each step of restoring the network when the tunnel state machine shut down, to help
+/// diagnose a network that is broken after the daemon has stopped. Steps that were not needed,
+/// such as stopping the tunnel when none was running, are left out.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TeardownReport {
+    /// Steps in the order that they were taken.
+    pub steps: Vec<TeardownStepResult>,
+}
+
+impl TeardownReport {
+    /// Record the outcome of `step`, replacing any earlier outcome of it.
+    pub fn record(
+        &mut self,
+        step: TeardownStep,
+        duration: Duration,
+        result: Result<(), ErrorDetails>,
+    ) {
+        self.steps.retain(|existing| existing.step != step);
+        self.steps.push(TeardownStepResult {
+            step,
+            duration,
+            error: result.err(),
+        });
+    }
+
+    /// Returns whether every step succeeded.
+    pub fn succeeded(&self) -> bool {
+        self.steps.iter().all(|step| step.error.is_none())
+    }
+}
+
+impl fmt::Display for TeardownReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, result) in self.steps.iter().enumerate() {
+            if i > 0 {
+                f.write_str("\n")?;
+            }
+            let millis = result.duration.as_millis();
+            match &result.error {
+                None => write!(f, "{}: ok ({millis} ms)", result.step)?,
+                Some(error) => write!(f, "{}: failed after {millis} ms: {error}", result.step)?,
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Action that will be taken after disconnection is complete.
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -44,6 +206,9 @@ pub struct ErrorState {
     /// An error value means there was a serious error and the intended security properties are not
     /// being upheld.
     block_failure: Option<FirewallPolicyError>,
+    /// Names of running software that may have caused the error, such as other VPN clients.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    conflicting_software: Vec<String>,
 }
 
 impl ErrorState {
@@ -51,9 +216,15 @@ impl ErrorState {
         Self {
             cause,
             block_failure,
+            conflicting_software: vec![],
         }
     }
 
+    pub fn with_conflicting_software(mut self, conflicting_software: Vec<String>) -> Self {
+        self.conflicting_software = conflicting_software;
+        self
+    }
+
     pub fn is_blocking(&self) -> bool {
         self.block_failure.is_none()
     }
@@ -65,6 +236,10 @@ impl ErrorState {
     pub fn block_failure(&self) -> Option<&FirewallPolicyError> {
         self.block_failure.as_ref()
     }
+
+    pub fn conflicting_software(&self) -> &[String] {
+        &self.conflicting_software
+    }
 }
 
 /// Reason for the tunnel state machine entering an [`ErrorState`].
@@ -79,7 +254,11 @@ pub enum ErrorStateCause {
     /// Failed to set firewall policy.
     SetFirewallPolicyError(FirewallPolicyError),
     /// Failed to set system DNS server.
-    SetDnsError,
+    SetDnsError {
+        /// How DNS was being set, if known.
+        mechanism: Option<DnsMechanism>,
+        details: ErrorDetails,
+    },
     /// Android has rejected one or more DNS server addresses.
     #[cfg(target_os = "android")]
     InvalidDnsServers(Vec<IpAddr>),
@@ -101,12 +280,110 @@ pub enum ErrorStateCause {
     /// Missing permissions required by macOS split tunneling.
     #[cfg(target_os = "macos")]
     NeedFullDiskPermissions,
+    /// DNS is managed by software that cannot be configured, so DNS requests could leak.
+    #[cfg(target_os = "linux")]
+    UnsupportedDnsManager(String),
 }
 
 impl ErrorStateCause {
+    /// Returns whether the error may be caused by other software that manages tunnel devices or
+    /// routes, such as another VPN client.
+    pub fn may_be_caused_by_conflicting_software(&self) -> bool {
+        match self {
+            Self::StartTunnelError => true,
+            #[cfg(target_os = "windows")]
+            Self::CreateTunnelDevice { .. } => true,
+            _ => false,
+        }
+    }
+
     #[cfg(target_os = "macos")]
     pub fn prevents_filtering_resolver(&self) -> bool {
-        matches!(self, Self::SetDnsError)
+        matches!(self, Self::SetDnsError { .. })
+    }
+}
+
+/// Way of setting the system DNS servers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DnsMechanism {
+    /// systemd-resolved, on Linux.
+    SystemdResolved,
+    /// NetworkManager, on Linux.
+    NetworkManager,
+    /// The resolvconf program, on Linux.
+    Resolvconf,
+    /// Writing /etc/resolv.conf directly, on Linux.
+    StaticResolvConf,
+    /// The dynamic store of the System Configuration framework, on macOS.
+    SystemConfiguration,
+    /// The iphlpapi module, on Windows.
+    Iphlpapi,
+    /// The netsh program, on Windows.
+    Netsh,
+    /// The registry settings of the TCP/IP stack, on Windows.
+    Tcpip,
+}
+
+impl fmt::Display for DnsMechanism {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mechanism = match self {
+            DnsMechanism::SystemdResolved => "systemd-resolved",
+            DnsMechanism::NetworkManager => "NetworkManager",
+            DnsMechanism::Resolvconf => "resolvconf",
+            DnsMechanism::StaticResolvConf => "/etc/resolv.conf",
+            DnsMechanism::SystemConfiguration => "System Configuration",
+            DnsMechanism::Iphlpapi => "iphlpapi",
+            DnsMechanism::Netsh => "netsh",
+            DnsMechanism::Tcpip => "TCP/IP registry settings",
+        };
+        f.write_str(mechanism)
+    }
+}
+
+/// Details of an error reported by the platform, so that failures to set DNS or firewall policies
+/// can be diagnosed without the logs.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorDetails {
+    /// Error code reported by the OS, if any.
+    pub os_error: Option<i32>,
+    /// Description of the error and all of its causes.
+    pub message: Option<String>,
+}
+
+impl ErrorDetails {
+    /// Collects the details of `error`. The error code is taken from the first I/O error in its
+    /// chain of causes that has one.
+    pub fn from_error(error: &(dyn Error + 'static)) -> Self {
+        let mut message = error.to_string();
+        let mut os_error = None;
+        let mut source = Some(error);
+        while let Some(error) = source {
+            if os_error.is_none() {
+                os_error = error
+                    .downcast_ref::<io::Error>()
+                    .and_then(io::Error::raw_os_error);
+            }
+            source = error.source();
+            if let Some(source) = source {
+                message.push_str(&format!(": {source}"));
+            }
+        }
+        Self {
+            os_error,
+            message: Some(message),
+        }
+    }
+}
+
+impl fmt::Display for ErrorDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The message of an I/O error already includes the error code
+        match (&self.message, self.os_error) {
+            (Some(message), _) => f.write_str(message),
+            (None, Some(os_error)) => write!(f, "os error {os_error}"),
+            (None, None) => f.write_str("no details"),
+        }
     }
 }
 
@@ -143,7 +420,7 @@ pub struct BlockingApplication {
 pub enum FirewallPolicyError {
     /// General firewall failure
     #[error("Failed to set firewall policy")]
-    Generic,
+    Generic(ErrorDetails),
     /// An application prevented the firewall policy from being set
     #[cfg(windows)]
     #[error("An application prevented the firewall policy from being set")]
@@ -171,10 +448,27 @@ impl fmt::Display for ErrorStateCause {
                     FirewallPolicyError::Locked(Some(value)) => {
                         write!(f, "{}: {} (pid {})", err, value.name, value.pid)
                     }
+                    FirewallPolicyError::Generic(details)
+                        if *details != ErrorDetails::default() =>
+                    {
+                        write!(f, "{err}: {details}")
+                    }
                     _ => write!(f, "{err}"),
                 };
             }
-            SetDnsError => "Failed to set system DNS server",
+            SetDnsError {
+                ref mechanism,
+                ref details,
+            } => {
+                write!(f, "Failed to set system DNS server")?;
+                if let Some(mechanism) = mechanism {
+                    write!(f, " using {mechanism}")?;
+                }
+                if *details != ErrorDetails::default() {
+                    write!(f, ": {details}")?;
+                }
+                return Ok(());
+            }
             #[cfg(target_os = "android")]
             InvalidDnsServers(ref addresses) => {
                 return write!(
@@ -206,8 +500,91 @@ impl fmt::Display for ErrorStateCause {
             SplitTunnelError => "The split tunneling module reported an error",
             #[cfg(target_os = "macos")]
             NeedFullDiskPermissions => "Need full disk access to enable split tunneling",
+            #[cfg(target_os = "linux")]
+            UnsupportedDnsManager(ref manager) => {
+                return write!(f, "DNS is managed by {manager}, which is not supported");
+            }
         };
 
         write!(f, "{description}")
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        ConnectPhase, ConnectTrace, DnsMechanism, ErrorDetails, ErrorStateCause, TeardownReport,
+        TeardownStep,
+    };
+    use std::{io, time::Duration};
+
+    #[test]
+    fn test_connect_trace_adds_up_phases() {
+        let mut trace = ConnectTrace::default();
+        trace.add(ConnectPhase::Firewall, Duration::from_millis(5));
+        trace.add(ConnectPhase::RelaySelection, Duration::from_millis(20));
+        trace.add(ConnectPhase::Firewall, Duration::from_millis(10));
+
+        assert_eq!(
+            trace.phases,
+            [
+                (ConnectPhase::Firewall, Duration::from_millis(15)),
+                (ConnectPhase::RelaySelection, Duration::from_millis(20)),
+            ]
+        );
+        assert_eq!(trace.get(ConnectPhase::Dns), None);
+    }
+
+    #[test]
+    fn test_dns_error_details() {
+        #[derive(thiserror::Error, Debug)]
+        #[error("Error in systemd-resolved DNS monitor")]
+        struct MonitorError(#[source] io::Error);
+
+        let error = MonitorError(io::Error::from_raw_os_error(13));
+        let cause = ErrorStateCause::SetDnsError {
+            mechanism: Some(DnsMechanism::SystemdResolved),
+            details: ErrorDetails::from_error(&error),
+        };
+
+        let ErrorStateCause::SetDnsError { details, .. } = &cause else {
+            unreachable!();
+        };
+        assert_eq!(details.os_error, Some(13));
+        assert_eq!(
+            cause.to_string(),
+            format!(
+                "Failed to set system DNS server using systemd-resolved: \
+                Error in systemd-resolved DNS monitor: {}",
+                io::Error::from_raw_os_error(13)
+            )
+        );
+    }
+
+    #[test]
+    fn test_teardown_report_replaces_steps() {
+        let mut report = TeardownReport::default();
+        let error = ErrorDetails {
+            os_error: None,
+            message: Some("Failed to reset firewall policy".to_owned()),
+        };
+        report.record(
+            TeardownStep::FirewallReset,
+            Duration::from_millis(3),
+            Err(error),
+        );
+        report.record(TeardownStep::DnsRestore, Duration::from_millis(5), Ok(()));
+        assert!(!report.succeeded());
+
+        report.record(
+            TeardownStep::FirewallReset,
+            Duration::from_millis(2),
+            Ok(()),
+        );
+        assert!(report.succeeded());
+        assert_eq!(
+            report.to_string(),
+            "DNS restore: ok (5 ms)\nFirewall reset: ok (2 ms)"
+        );
+    }
+}
@@ -5,6 +5,11 @@ use std::{
 };
 use talpid_types::net::{obfuscation::ObfuscatorConfig, wireguard, GenericTunnelOptions};
 
+/// Persistent keepalive interval, in seconds, used while racing the entry peer against a
+/// candidate peer. Keepalives make both peers initiate a handshake without any traffic being
+/// routed to them.
+const CANDIDATE_RACE_KEEPALIVE_INTERVAL: u16 = 1;
+
 /// Config required to set up a single WireGuard tunnel
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -14,6 +19,9 @@ pub struct Config {
     pub entry_peer: wireguard::PeerConfig,
     /// Multihop exit peer
     pub exit_peer: Option<wireguard::PeerConfig>,
+    /// Peer that is raced against the entry peer while connecting. It has no allowed IPs until
+    /// it replaces the entry peer.
+    pub candidate_peer: Option<wireguard::PeerConfig>,
     /// IPv4 gateway
     pub ipv4_gateway: Ipv4Addr,
     /// IPv6 gateway
@@ -26,6 +34,9 @@ pub struct Config {
     /// Enable IPv6 routing rules
     #[cfg(target_os = "linux")]
     pub enable_ipv6: bool,
+    /// Interface to reach the peers through, instead of the one that the default route uses
+    #[cfg(target_os = "linux")]
+    pub egress_interface: Option<String>,
     /// Obfuscator config to be used for reaching the relay.
     pub obfuscator_config: Option<ObfuscatorConfig>,
     /// Enable quantum-resistant PSK exchange
@@ -54,6 +65,7 @@ impl Config {
     ) -> Result<Config, Error> {
         Self::new(
             &params.connection,
+            params.candidate_peer(),
             &params.options,
             &params.generic_options,
             &params.obfuscation,
@@ -64,6 +76,7 @@ impl Config {
     /// Constructs a new Config struct
     fn new(
         connection: &wireguard::ConnectionConfig,
+        candidate_peer: Option<&wireguard::PeerConfig>,
         wg_options: &wireguard::TunnelOptions,
         generic_options: &GenericTunnelOptions,
         obfuscator_config: &Option<ObfuscatorConfig>,
@@ -88,6 +101,7 @@ impl Config {
             tunnel,
             entry_peer: connection.peer.clone(),
             exit_peer: connection.exit_peer.clone(),
+            candidate_peer: None,
             ipv4_gateway: connection.ipv4_gateway,
             ipv6_gateway,
             mtu,
@@ -95,6 +109,8 @@ impl Config {
             fwmark: connection.fwmark,
             #[cfg(target_os = "linux")]
             enable_ipv6: generic_options.enable_ipv6,
+            #[cfg(target_os = "linux")]
+            egress_interface: wg_options.egress_interface.clone(),
             obfuscator_config: obfuscator_config.to_owned(),
             quantum_resistant: wg_options.quantum_resistant,
             #[cfg(target_os = "windows")]
@@ -111,6 +127,11 @@ impl Config {
             }
         }
 
+        config.candidate_peer = candidate_peer.map(|peer| wireguard::PeerConfig {
+            allowed_ips: vec![],
+            ..peer.clone()
+        });
+
         Ok(config)
     }
 
@@ -138,6 +159,12 @@ impl Config {
             if let Some(ref psk) = peer.psk {
                 wg_conf.add("preshared_key", psk.as_bytes().as_ref());
             }
+            if let Some(interval) = self.persistent_keepalive() {
+                wg_conf.add(
+                    "persistent_keepalive_interval",
+                    interval.to_string().as_str(),
+                );
+            }
             for addr in &peer.allowed_ips {
                 wg_conf.add("allowed_ip", addr.to_string().as_str());
             }
@@ -160,15 +187,35 @@ impl Config {
         &mut self.entry_peer
     }
 
-    /// Return an iterator over all peers.
+    /// Return an iterator over all peers, including the candidate peer.
     pub fn peers(&self) -> impl Iterator<Item = &wireguard::PeerConfig> {
         self.exit_peer
             .as_ref()
             .into_iter()
             .chain(std::iter::once(&self.entry_peer))
+            .chain(self.candidate_peer.as_ref())
+    }
+
+    /// Return the persistent keepalive interval to configure for all peers, if any.
+    /// Keepalives are only sent while racing the entry peer against a candidate peer.
+    pub fn persistent_keepalive(&self) -> Option<u16> {
+        self.candidate_peer
+            .as_ref()
+            .map(|_| CANDIDATE_RACE_KEEPALIVE_INTERVAL)
+    }
+
+    /// Replace the entry peer with the candidate peer, which takes over the allowed IPs of the
+    /// entry peer. Returns `false` if there is no candidate peer.
+    pub fn select_candidate_peer(&mut self) -> bool {
+        let Some(mut candidate) = self.candidate_peer.take() else {
+            return false;
+        };
+        candidate.allowed_ips = std::mem::take(&mut self.entry_peer.allowed_ips);
+        self.entry_peer = candidate;
+        true
     }
 
-    /// Return a mutable iterator over all peers.
+    /// Return a mutable iterator over all peers, excluding the candidate peer.
     pub fn peers_mut(&mut self) -> impl Iterator<Item = &mut wireguard::PeerConfig> {
         self.exit_peer
             .as_mut()
@@ -225,3 +272,70 @@ impl WgConfigBuffer {
         self.buf
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn connection_config() -> wireguard::ConnectionConfig {
+        let private_key = wireguard::PrivateKey::new_from_random();
+        wireguard::ConnectionConfig {
+            tunnel: wireguard::TunnelConfig {
+                addresses: vec![
+                    "10.64.0.2".parse().unwrap(),
+                    "fc00:bbbb::2".parse().unwrap(),
+                ],
+                private_key: private_key.clone(),
+            },
+            peer: wireguard::PeerConfig {
+                public_key: private_key.public_key(),
+                allowed_ips: talpid_types::net::all_of_the_internet(),
+                endpoint: "1.2.3.4:51820".parse().unwrap(),
+                alternate_endpoint: None,
+                psk: None,
+                #[cfg(target_os = "windows")]
+                constant_packet_size: false,
+            },
+            exit_peer: None,
+            candidate_peer: None,
+            ipv4_gateway: "10.64.0.1".parse().unwrap(),
+            ipv6_gateway: Some("fc00:bbbb::1".parse().unwrap()),
+            #[cfg(target_os = "linux")]
+            fwmark: None,
+        }
+    }
+
+    fn new_config(enable_ipv6: bool) -> Config {
+        Config::new(
+            &connection_config(),
+            None,
+            &wireguard::TunnelOptions {
+                mtu: None,
+                quantum_resistant: false,
+                #[cfg(target_os = "windows")]
+                daita: false,
+                #[cfg(target_os = "linux")]
+                egress_interface: None,
+            },
+            &GenericTunnelOptions { enable_ipv6 },
+            &None,
+            1380,
+        )
+        .unwrap()
+    }
+
+    /// Without IPv6, the tunnel must not get an IPv6 address, gateway or route
+    #[test]
+    fn test_ipv6_disabled() {
+        let config = new_config(false);
+        assert!(config.tunnel.addresses.iter().all(|ip| ip.is_ipv4()));
+        assert_eq!(config.ipv6_gateway, None);
+        assert!(config.entry_peer.allowed_ips.iter().all(|ip| ip.is_ipv4()));
+        assert!(!config.entry_peer.allowed_ips.is_empty());
+
+        let config = new_config(true);
+        assert!(config.tunnel.addresses.iter().any(|ip| ip.is_ipv6()));
+        assert!(config.ipv6_gateway.is_some());
+        assert!(config.entry_peer.allowed_ips.iter().any(|ip| ip.is_ipv6()));
+    }
+}
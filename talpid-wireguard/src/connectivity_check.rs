@@ -145,10 +145,24 @@ impl ConnectivityMonitor {
         Ok(false)
     }
 
-    pub(super) fn run(&mut self) -> Result<(), Error> {
+    /// Monitors the connection until it is lost or the monitor is shut down. Returns true if the
+    /// connection was lost.
+    pub(super) fn run(&mut self) -> Result<bool, Error> {
         self.wait_loop(REGULAR_LOOP_SLEEP)
     }
 
+    /// Assume that the connection works again after the peer endpoint has been changed. If it
+    /// doesn't, [`ConnectivityMonitor::run`] will detect it after `PING_TIMEOUT`.
+    pub(super) fn reset_after_endpoint_change(&mut self, now: Instant) {
+        self.reset_pinger();
+        self.conn_state.reset_after_suspension(now);
+    }
+
+    /// Returns true if traffic has been received after `instant`.
+    pub(super) fn received_traffic_since(&self, instant: Instant) -> bool {
+        self.conn_state.rx_timestamp() > Some(instant)
+    }
+
     /// Returns true if monitor should be shut down
     fn should_shut_down(&mut self, timeout: Duration) -> bool {
         match self.close_receiver.recv_timeout(timeout) {
@@ -157,14 +171,14 @@ impl ConnectivityMonitor {
         }
     }
 
-    fn wait_loop(&mut self, iter_delay: Duration) -> Result<(), Error> {
+    fn wait_loop(&mut self, iter_delay: Duration) -> Result<bool, Error> {
         let mut last_iteration = Instant::now();
         while !self.should_shut_down(iter_delay) {
             let mut current_iteration = Instant::now();
             let time_slept = current_iteration - last_iteration;
             if time_slept < (iter_delay * 2) {
                 if !self.check_connectivity(Instant::now())? {
-                    return Ok(());
+                    return Ok(true);
                 }
 
                 let end = Instant::now();
@@ -179,7 +193,7 @@ impl ConnectivityMonitor {
             }
             last_iteration = current_iteration;
         }
-        Ok(())
+        Ok(false)
     }
 
     /// Returns true if connection is established
@@ -391,6 +405,13 @@ impl ConnState {
     pub fn connected(&self) -> bool {
         matches!(self, ConnState::Connected { .. })
     }
+
+    pub fn rx_timestamp(&self) -> Option<Instant> {
+        match self {
+            ConnState::Connecting { .. } => None,
+            ConnState::Connected { rx_timestamp, .. } => Some(*rx_timestamp),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -703,6 +724,35 @@ mod test {
         assert!(monitor.check_connectivity(now).unwrap())
     }
 
+    #[test]
+    /// Verify that the connection is assumed to work after the endpoint has been changed, and that
+    /// received traffic is only detected once it has been observed.
+    fn test_reset_after_endpoint_change() {
+        let (_tunnel_anchor, tunnel) = MockTunnel::never_incrementing().into_locked();
+        let (_tx, rx) = mpsc::channel();
+        let pinger = MockPinger::default();
+        let now = Instant::now();
+        let start = now
+            .checked_sub(BYTES_RX_TIMEOUT + PING_TIMEOUT + Duration::from_secs(10))
+            .unwrap();
+        let mut monitor = mock_monitor(start, Box::new(pinger), tunnel, rx);
+
+        monitor.conn_state = connected_state(start);
+        monitor.maybe_send_ping(start).unwrap();
+        assert!(!monitor.check_connectivity(now).unwrap());
+
+        let changed = Instant::now();
+        monitor.reset_after_endpoint_change(changed);
+        assert!(monitor.check_connectivity(Instant::now()).unwrap());
+        assert!(!monitor.received_traffic_since(changed));
+
+        let (_tunnel_anchor, tunnel) = MockTunnel::always_incrementing().into_locked();
+        monitor.tunnel_handle = tunnel;
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(monitor.check_connectivity(Instant::now()).unwrap());
+        assert!(monitor.received_traffic_since(changed));
+    }
+
     #[test]
     /// Verify that the connectivity monitor doesn't fail if the tunnel constantly sends traffic,
     /// and it shuts down properly.
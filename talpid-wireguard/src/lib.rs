@@ -3,9 +3,11 @@
 #![deny(missing_docs)]
 
 use self::config::Config;
-#[cfg(windows)]
-use futures::channel::mpsc;
-use futures::future::{abortable, AbortHandle as FutureAbortHandle, BoxFuture, Future};
+use futures::{
+    channel::{mpsc, oneshot},
+    future::{abortable, AbortHandle as FutureAbortHandle, BoxFuture, Future},
+    FutureExt, StreamExt,
+};
 #[cfg(target_os = "linux")]
 use once_cell::sync::Lazy;
 #[cfg(target_os = "android")]
@@ -15,15 +17,19 @@ use std::env;
 #[cfg(windows)]
 use std::io;
 use std::{
+    collections::HashSet,
     convert::Infallible,
     net::IpAddr,
     path::Path,
     pin::Pin,
     sync::{mpsc as sync_mpsc, Arc, Mutex},
-    time::Duration,
+    time::{Duration, Instant},
 };
+use talpid_net::SocketProtection;
 use talpid_routing as routing;
 use talpid_routing::{self, RequiredRoute};
+#[cfg(target_os = "linux")]
+use talpid_tunnel::netns::{self, NetworkNamespace};
 #[cfg(not(windows))]
 use talpid_tunnel::tun_provider;
 use talpid_tunnel::{tun_provider::TunProvider, TunnelArgs, TunnelEvent, TunnelMetadata};
@@ -32,14 +38,16 @@ use ipnetwork::IpNetwork;
 use talpid_types::{
     net::{
         obfuscation::ObfuscatorConfig,
-        wireguard::{PresharedKey, PrivateKey, PublicKey},
+        wireguard::{self, PeerConfig, PresharedKey, PrivateKey, PublicKey},
         AllowedTunnelTraffic, Endpoint, TransportProtocol,
     },
+    tunnel::ConnectPhase,
     BoxedError, ErrorExt,
 };
 use tokio::sync::Mutex as AsyncMutex;
 use tunnel_obfuscation::{
-    create_obfuscator, Error as ObfuscationError, Settings as ObfuscationSettings, Udp2TcpSettings,
+    create_obfuscator, Error as ObfuscationError, Settings as ObfuscationSettings, TlsSettings,
+    Udp2TcpSettings,
 };
 
 /// WireGuard config data-types
@@ -47,7 +55,11 @@ pub mod config;
 mod connectivity_check;
 mod logging;
 mod ping_monitor;
-mod stats;
+/// Latency measurements to WireGuard relays
+pub mod probe;
+/// Traffic statistics of WireGuard tunnels
+pub mod stats;
+mod udp2tcp_tuning;
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 mod unix;
 #[cfg(wireguard_go)]
@@ -97,6 +109,15 @@ pub enum Error {
     #[error("Failed to negotiate PQ PSK")]
     PskNegotiationError(#[source] talpid_tunnel_config_client::Error),
 
+    /// Timed out while negotiating PQ PSK
+    #[error("Timed out while negotiating PQ PSK")]
+    PskNegotiationTimeout,
+
+    /// There is no route to a peer through the egress interface.
+    #[cfg(target_os = "linux")]
+    #[error("No route to {0} through interface {1}")]
+    NoEgressRouteError(IpAddr, String),
+
     /// Failed to set up IP interfaces.
     #[cfg(windows)]
     #[error("Failed to set up IP interfaces")]
@@ -106,6 +127,14 @@ pub enum Error {
     #[cfg(target_os = "windows")]
     #[error("Failed to set IP addresses on WireGuard interface")]
     SetIpAddressesError(#[source] talpid_windows::net::Error),
+
+    /// Only the private key of a running tunnel can be changed
+    #[error("Only the private key of a running tunnel can be changed")]
+    ReconfigureUnsupported,
+
+    /// The tunnel has already been closed
+    #[error("The tunnel has already been closed")]
+    TunnelClosed,
 }
 
 impl Error {
@@ -115,9 +144,13 @@ impl Error {
             Error::CreateObfuscatorError(_) => true,
             Error::ObfuscatorError(_) => true,
             Error::PskNegotiationError(_) => true,
+            Error::PskNegotiationTimeout => true,
             Error::TunnelError(TunnelError::RecoverableStartWireguardError) => true,
 
             Error::SetupRoutingError(error) => error.is_recoverable(),
+            // The interface may be down for now
+            #[cfg(target_os = "linux")]
+            Error::NoEgressRouteError(..) => true,
 
             #[cfg(target_os = "android")]
             Error::TunnelError(TunnelError::BypassError(_)) => true,
@@ -130,6 +163,14 @@ impl Error {
         }
     }
 
+    /// Return whether the tunnel was closed because an ephemeral peer could not be negotiated.
+    pub fn is_ephemeral_peer_negotiation_error(&self) -> bool {
+        matches!(
+            self,
+            Error::PskNegotiationError(_) | Error::PskNegotiationTimeout
+        )
+    }
+
     /// Get the inner tunnel device error, if there is one
     #[cfg(windows)]
     pub fn get_tunnel_device_error(&self) -> Option<&io::Error> {
@@ -150,12 +191,75 @@ pub struct WireguardMonitor {
     close_msg_receiver: sync_mpsc::Receiver<CloseMsg>,
     pinger_stop_sender: sync_mpsc::Sender<()>,
     obfuscator: Arc<AsyncMutex<Option<ObfuscatorHandle>>>,
+    /// Config that the tunnel was started with
+    initial_config: Arc<Config>,
+    private_key_tx: mpsc::UnboundedSender<PrivateKeyRequest>,
+}
+
+/// A new private key for the tunnel, and where to report whether it was applied.
+type PrivateKeyRequest = (PrivateKey, oneshot::Sender<Result<()>>);
+
+/// Handle to a running tunnel. Unlike [`WireguardMonitor`], it can be used while
+/// [`WireguardMonitor::wait`] blocks.
+#[derive(Clone)]
+pub struct TunnelHandle {
+    runtime: tokio::runtime::Handle,
+    tunnel: Arc<Mutex<Option<Box<dyn Tunnel>>>>,
+    initial_config: Arc<Config>,
+    private_key_tx: mpsc::UnboundedSender<PrivateKeyRequest>,
+}
+
+impl TunnelHandle {
+    /// Returns the traffic statistics of the tunnel, per peer. The map is empty once the tunnel
+    /// has been stopped.
+    pub fn stats(&self) -> Result<stats::StatsMap> {
+        match &*self.tunnel.lock().expect("Tunnel lock poisoned") {
+            Some(tunnel) => tunnel.get_tunnel_stats().map_err(Error::TunnelError),
+            None => Ok(stats::StatsMap::new()),
+        }
+    }
+
+    /// Apply `params` to the running tunnel, and block until the tunnel uses them. Only the
+    /// private key can be changed, since the routes, the firewall rules and any ephemeral peer
+    /// are set up for the peers and addresses that the tunnel was started with.
+    pub fn reconfigure(&self, params: &wireguard::TunnelParameters) -> Result<()> {
+        let initial = &self.initial_config;
+        // The private key of the tunnel is replaced with an ephemeral one in these cases
+        if initial.quantum_resistant || initial.daita {
+            return Err(Error::ReconfigureUnsupported);
+        }
+        let config = Config::from_parameters(params, initial.mtu)
+            .map_err(|_| Error::ReconfigureUnsupported)?;
+        let only_key_changed = config.tunnel.addresses == initial.tunnel.addresses
+            && config.peers().eq(initial.peers())
+            && config.obfuscator_config == initial.obfuscator_config
+            && config.quantum_resistant == initial.quantum_resistant
+            && config.daita == initial.daita
+            && config.mtu == initial.mtu;
+        if !only_key_changed {
+            return Err(Error::ReconfigureUnsupported);
+        }
+
+        let (result_tx, result_rx) = oneshot::channel();
+        self.private_key_tx
+            .unbounded_send((config.tunnel.private_key, result_tx))
+            .map_err(|_| Error::TunnelClosed)?;
+        self.runtime
+            .block_on(result_rx)
+            .unwrap_or(Err(Error::TunnelClosed))
+    }
 }
 
 const INITIAL_PSK_EXCHANGE_TIMEOUT: Duration = Duration::from_secs(8);
 const MAX_PSK_EXCHANGE_TIMEOUT: Duration = Duration::from_secs(48);
 const PSK_EXCHANGE_TIMEOUT_MULTIPLIER: u32 = 2;
 
+/// Time to wait for the entry peer or the candidate peer to respond to a handshake. This covers
+/// one handshake retransmission.
+const CANDIDATE_RACE_TIMEOUT: Duration = Duration::from_secs(6);
+/// How often to check whether a peer has responded while racing.
+const CANDIDATE_RACE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
 /// Simple wrapper that automatically cancels the future which runs an obfuscator.
 struct ObfuscatorHandle {
     abort_handle: FutureAbortHandle,
@@ -204,48 +308,69 @@ async fn maybe_create_obfuscator(
     close_msg_sender: sync_mpsc::Sender<CloseMsg>,
 ) -> Result<Option<ObfuscatorHandle>> {
     if let Some(ref obfuscator_config) = config.obfuscator_config {
-        match obfuscator_config {
+        #[cfg(target_os = "linux")]
+        let protection = SocketProtection::with_fwmark(config.fwmark)
+            .bind_to_device(config.egress_interface.clone());
+        #[cfg(any(target_os = "macos", target_os = "windows"))]
+        let protection = SocketProtection::bind_to_physical_interface();
+        // On Android, the remote socket is excluded from the tunnel once the tunnel is created
+        #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+        let protection = SocketProtection::default();
+        let settings = match obfuscator_config {
             ObfuscatorConfig::Udp2Tcp { endpoint } => {
                 log::trace!("Connecting to Udp2Tcp endpoint {:?}", *endpoint);
-                let settings = Udp2TcpSettings {
+                ObfuscationSettings::Udp2Tcp(Udp2TcpSettings {
                     peer: *endpoint,
-                    #[cfg(target_os = "linux")]
-                    fwmark: config.fwmark,
-                };
-                let obfuscator = create_obfuscator(&ObfuscationSettings::Udp2Tcp(settings))
-                    .await
-                    .map_err(Error::CreateObfuscatorError)?;
-                let endpoint = obfuscator.endpoint();
+                    protection,
+                    tuning: udp2tcp_tuning::from_env(),
+                })
+            }
+            ObfuscatorConfig::Tls {
+                endpoint,
+                sni,
+                certificate_sha256,
+            } => {
+                log::trace!("Connecting to TLS endpoint {:?} as {sni}", *endpoint);
+                ObfuscationSettings::Tls(TlsSettings {
+                    peer: *endpoint,
+                    sni: sni.clone(),
+                    certificate_sha256: *certificate_sha256,
+                    protection,
+                })
+            }
+        };
+        let obfuscator = create_obfuscator(&settings)
+            .await
+            .map_err(Error::CreateObfuscatorError)?;
+        let endpoint = obfuscator.endpoint();
 
-                log::trace!("Patching first WireGuard peer to become {:?}", endpoint);
-                config.entry_peer.endpoint = endpoint;
+        log::trace!("Patching first WireGuard peer to become {:?}", endpoint);
+        config.entry_peer.endpoint = endpoint;
 
-                #[cfg(target_os = "android")]
-                let remote_socket_fd = obfuscator.remote_socket_fd();
+        #[cfg(target_os = "android")]
+        let remote_socket_fd = obfuscator.remote_socket_fd();
 
-                let (runner, abort_handle) = abortable(async move {
-                    match obfuscator.run().await {
-                        Ok(_) => {
-                            let _ = close_msg_sender.send(CloseMsg::ObfuscatorExpired);
-                        }
-                        Err(error) => {
-                            log::error!(
-                                "{}",
-                                error.display_chain_with_msg("Obfuscation controller failed")
-                            );
-                            let _ = close_msg_sender
-                                .send(CloseMsg::ObfuscatorFailed(Error::ObfuscatorError(error)));
-                        }
-                    }
-                });
-                tokio::spawn(runner);
-                return Ok(Some(ObfuscatorHandle::new(
-                    abort_handle,
-                    #[cfg(target_os = "android")]
-                    remote_socket_fd,
-                )));
+        let (runner, abort_handle) = abortable(async move {
+            match obfuscator.run().await {
+                Ok(_) => {
+                    let _ = close_msg_sender.send(CloseMsg::ObfuscatorExpired);
+                }
+                Err(error) => {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("Obfuscation controller failed")
+                    );
+                    let _ = close_msg_sender
+                        .send(CloseMsg::ObfuscatorFailed(Error::ObfuscatorError(error)));
+                }
             }
-        }
+        });
+        tokio::spawn(runner);
+        return Ok(Some(ObfuscatorHandle::new(
+            abort_handle,
+            #[cfg(target_os = "android")]
+            remote_socket_fd,
+        )));
     }
     Ok(None)
 }
@@ -265,23 +390,50 @@ impl WireguardMonitor {
         args: TunnelArgs<'_, F>,
     ) -> Result<WireguardMonitor> {
         let on_event = args.on_event.clone();
+        let connect_trace = args.connect_trace.clone();
+        let initial_config = Arc::new(config.clone());
+        let (private_key_tx, private_key_rx) = mpsc::unbounded();
+        let mut private_key_rx = private_key_rx.fuse();
 
-        let endpoint_addrs: Vec<IpAddr> = config.peers().map(|peer| peer.endpoint.ip()).collect();
+        // Routes through a tunnel in another network namespace are added by the tunnel itself
+        #[cfg(target_os = "linux")]
+        let tunnel_namespace = NetworkNamespace::from_env()
+            .map_err(|error| TunnelError::NetworkNamespaceError(BoxedError::new(error)))
+            .map_err(Error::TunnelError)?;
+        #[cfg(target_os = "linux")]
+        let manage_routes = tunnel_namespace.is_none();
+        #[cfg(not(target_os = "linux"))]
+        let manage_routes = true;
+
+        // Routes to the alternate endpoints are added up front, so that the tunnel can switch to
+        // them without touching the routes.
+        let endpoint_addrs: Vec<IpAddr> = config
+            .peers()
+            .flat_map(|peer| std::iter::once(peer.endpoint).chain(peer.alternate_endpoint))
+            .map(|endpoint| endpoint.ip())
+            .collect();
 
         let (close_obfs_sender, close_obfs_listener) = sync_mpsc::channel();
+        if config.obfuscator_config.is_some() {
+            connect_trace.start(ConnectPhase::Obfuscation);
+        }
         let obfuscator = args.runtime.block_on(maybe_create_obfuscator(
             &mut config,
             close_obfs_sender.clone(),
         ))?;
+        connect_trace.finish(ConnectPhase::Obfuscation);
 
         #[cfg(target_os = "windows")]
         let (setup_done_tx, setup_done_rx) = mpsc::channel(0);
+        connect_trace.start(ConnectPhase::TunnelCreation);
         let tunnel = Self::open_tunnel(
             args.runtime.clone(),
             &config,
             log_path,
             args.resource_dir,
             args.tun_provider.clone(),
+            #[cfg(target_os = "linux")]
+            tunnel_namespace.as_ref(),
             #[cfg(target_os = "android")]
             config.quantum_resistant,
             #[cfg(target_os = "windows")]
@@ -289,6 +441,7 @@ impl WireguardMonitor {
             #[cfg(target_os = "windows")]
             setup_done_tx,
         )?;
+        connect_trace.finish(ConnectPhase::TunnelCreation);
         let iface_name = tunnel.get_interface_name();
 
         #[cfg(target_os = "android")]
@@ -311,6 +464,8 @@ impl WireguardMonitor {
             close_msg_receiver: close_obfs_listener,
             pinger_stop_sender: pinger_tx,
             obfuscator,
+            initial_config,
+            private_key_tx,
         };
 
         let gateway = config.ipv4_gateway;
@@ -347,23 +502,42 @@ impl WireguardMonitor {
             (on_event)(TunnelEvent::InterfaceUp(metadata.clone(), allowed_traffic)).await;
 
             // Add non-default routes before establishing the tunnel.
-            #[cfg(target_os = "linux")]
-            args.route_manager
-                .create_routing_rules(config.enable_ipv6)
-                .await
-                .map_err(Error::SetupRoutingError)
-                .map_err(CloseMsg::SetupError)?;
+            connect_trace.start(ConnectPhase::Routes);
+            if manage_routes {
+                #[cfg(target_os = "linux")]
+                args.route_manager
+                    .create_routing_rules(config.enable_ipv6)
+                    .await
+                    .map_err(Error::SetupRoutingError)
+                    .map_err(CloseMsg::SetupError)?;
 
-            let routes = Self::get_pre_tunnel_routes(&iface_name, &config)
-                .chain(Self::get_endpoint_routes(&endpoint_addrs))
-                .collect();
+                #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
+                let mut routes: HashSet<_> = Self::get_pre_tunnel_routes(&iface_name, &config)
+                    .chain(Self::get_endpoint_routes(&endpoint_addrs))
+                    .collect();
+                #[cfg(target_os = "linux")]
+                if let Some(interface) = &config.egress_interface {
+                    routes.extend(
+                        Self::get_egress_interface_routes(
+                            &args.route_manager,
+                            interface,
+                            &endpoint_addrs,
+                        )
+                        .await
+                        .map_err(CloseMsg::SetupError)?,
+                    );
+                }
 
-            args.route_manager
-                .add_routes(routes)
-                .await
-                .map_err(Error::SetupRoutingError)
-                .map_err(CloseMsg::SetupError)?;
+                args.route_manager
+                    .add_routes(routes)
+                    .await
+                    .map_err(Error::SetupRoutingError)
+                    .map_err(CloseMsg::SetupError)?;
+            }
+            connect_trace.finish(ConnectPhase::Routes);
 
+            connect_trace.start(ConnectPhase::Handshake);
+            Self::race_candidate_peer(&tunnel, &mut config, &on_event).await?;
             let ephemeral_obfs_sender = close_obfs_sender.clone();
             if config.quantum_resistant || config.daita {
                 Self::config_ephemeral_peers(
@@ -429,27 +603,70 @@ impl WireguardMonitor {
             })
             .await
             .unwrap()?;
+            connect_trace.finish(ConnectPhase::Handshake);
 
             // Add any default route(s) that may exist.
-            args.route_manager
-                .add_routes(Self::get_post_tunnel_routes(&iface_name, &config).collect())
-                .await
-                .map_err(Error::SetupRoutingError)
-                .map_err(CloseMsg::SetupError)?;
+            connect_trace.start(ConnectPhase::Routes);
+            if manage_routes {
+                args.route_manager
+                    .add_routes(Self::get_post_tunnel_routes(&iface_name, &config).collect())
+                    .await
+                    .map_err(Error::SetupRoutingError)
+                    .map_err(CloseMsg::SetupError)?;
+            }
+            connect_trace.finish(ConnectPhase::Routes);
 
             let metadata = Self::tunnel_metadata(&iface_name, &config);
             (on_event)(TunnelEvent::Up(metadata)).await;
 
-            tokio::task::spawn_blocking(move || {
-                if let Err(error) = connectivity_monitor.run() {
-                    log::error!(
-                        "{}",
-                        error.display_chain_with_msg("Connectivity monitor failed")
-                    );
+            let mut last_endpoint_change = None;
+            loop {
+                let mut run = tokio::task::spawn_blocking(move || {
+                    let result = connectivity_monitor.run();
+                    (result, connectivity_monitor)
+                })
+                .fuse();
+                // New private keys are applied while the connectivity monitor runs
+                let (result, monitor) = loop {
+                    futures::select! {
+                        joined = run => break joined.unwrap(),
+                        request = private_key_rx.next() => {
+                            if let Some((private_key, result_tx)) = request {
+                                let result =
+                                    Self::set_private_key(&tunnel, &mut config, private_key).await;
+                                let _ = result_tx.send(result);
+                            }
+                        }
+                    }
+                };
+                connectivity_monitor = monitor;
+
+                match result {
+                    Ok(true) => (),
+                    Ok(false) => break,
+                    Err(error) => {
+                        log::error!(
+                            "{}",
+                            error.display_chain_with_msg("Connectivity monitor failed")
+                        );
+                        break;
+                    }
                 }
-            })
-            .await
-            .unwrap();
+
+                // Don't switch back unless the previous switch helped, since the relay may be
+                // unreachable over both IP versions.
+                if let Some(changed) = last_endpoint_change {
+                    if !connectivity_monitor.received_traffic_since(changed) {
+                        break;
+                    }
+                }
+                if !Self::switch_entry_endpoint(&tunnel, &mut config, &on_event).await? {
+                    break;
+                }
+                let now = Instant::now();
+                connectivity_monitor.reset_after_endpoint_change(now);
+                last_endpoint_change = Some(now);
+            }
 
             Err::<Infallible, CloseMsg>(CloseMsg::PingErr)
         };
@@ -598,6 +815,144 @@ impl WireguardMonitor {
         Ok(())
     }
 
+    /// Switches the entry peer to the relay's address in the other IP version, if it has one.
+    /// Returns false if there is no address to switch to.
+    async fn switch_entry_endpoint<F>(
+        tunnel: &Arc<Mutex<Option<Box<dyn Tunnel>>>>,
+        config: &mut Config,
+        on_event: &F,
+    ) -> std::result::Result<bool, CloseMsg>
+    where
+        F: (Fn(TunnelEvent) -> Pin<Box<dyn std::future::Future<Output = ()> + Send>>)
+            + Send
+            + Sync
+            + Clone
+            + 'static,
+    {
+        // The obfuscator is what connects to the relay, so the peer endpoint is local.
+        if config.obfuscator_config.is_some() {
+            return Ok(false);
+        }
+        let old_endpoint = config.entry_peer.endpoint;
+        let Some(endpoint) = config.entry_peer.switch_endpoint() else {
+            return Ok(false);
+        };
+        log::warn!("Lost connectivity to relay at {old_endpoint}. Switching to {endpoint}");
+
+        // Wait for traffic to the new endpoint to be allowed before using it.
+        (on_event)(TunnelEvent::PeerEndpointChanged(endpoint)).await;
+
+        let set_config_future = tunnel
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|tunnel| tunnel.set_config(config.clone()));
+        if let Some(f) = set_config_future {
+            f.await
+                .map_err(Error::TunnelError)
+                .map_err(CloseMsg::SetupError)?;
+        }
+        Ok(true)
+    }
+
+    /// Waits for the entry peer or the candidate peer to respond to a handshake, and keeps
+    /// whichever responds first as the entry peer. The candidate peer is removed from the tunnel
+    /// either way. If neither responds in time, the entry peer is kept and the connectivity check
+    /// decides whether the tunnel works.
+    async fn race_candidate_peer<F>(
+        tunnel: &Arc<Mutex<Option<Box<dyn Tunnel>>>>,
+        config: &mut Config,
+        on_event: &F,
+    ) -> std::result::Result<(), CloseMsg>
+    where
+        F: (Fn(TunnelEvent) -> Pin<Box<dyn std::future::Future<Output = ()> + Send>>)
+            + Send
+            + Sync
+            + Clone
+            + 'static,
+    {
+        let Some(candidate) = config.candidate_peer.as_ref() else {
+            return Ok(());
+        };
+        log::debug!(
+            "Connecting to {} and {} in parallel",
+            config.entry_peer.endpoint,
+            candidate.endpoint
+        );
+
+        let started = Instant::now();
+        let candidate_won = loop {
+            let stats = tunnel
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|tunnel| tunnel.get_tunnel_stats());
+            if let Some(stats) = stats {
+                let stats = stats
+                    .map_err(Error::TunnelError)
+                    .map_err(CloseMsg::SetupError)?;
+                let responded = |peer: &PeerConfig| {
+                    stats
+                        .get(peer.public_key.as_bytes())
+                        .is_some_and(|stats| stats.rx_bytes > 0)
+                };
+                if responded(&config.entry_peer) {
+                    break false;
+                }
+                if responded(candidate) {
+                    break true;
+                }
+            }
+            if started.elapsed() >= CANDIDATE_RACE_TIMEOUT {
+                log::debug!("Neither relay responded in time. Keeping the selected relay");
+                break false;
+            }
+            tokio::time::sleep(CANDIDATE_RACE_POLL_INTERVAL).await;
+        };
+
+        if candidate_won {
+            config.select_candidate_peer();
+            log::info!(
+                "Candidate relay at {} responded first. Switching to it",
+                config.entry_peer.endpoint
+            );
+            (on_event)(TunnelEvent::PeerEndpointChanged(config.entry_peer.endpoint)).await;
+        } else {
+            config.candidate_peer = None;
+        }
+
+        let set_config_future = tunnel
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|tunnel| tunnel.set_config(config.clone()));
+        if let Some(f) = set_config_future {
+            f.await
+                .map_err(Error::TunnelError)
+                .map_err(CloseMsg::SetupError)?;
+        }
+        Ok(())
+    }
+
+    /// Replace the private key of the running tunnel.
+    async fn set_private_key(
+        tunnel: &Arc<Mutex<Option<Box<dyn Tunnel>>>>,
+        config: &mut Config,
+        private_key: PrivateKey,
+    ) -> Result<()> {
+        log::debug!("Replacing the private key of the tunnel");
+        config.tunnel.private_key = private_key;
+        let set_config_future = tunnel
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|tunnel| tunnel.set_config(config.clone()));
+        if let Some(f) = set_config_future {
+            f.await.map_err(Error::TunnelError)?;
+        }
+        Ok(())
+    }
+
     /// Reconfigures the tunnel to use the provided config while potentially modifying the config
     /// and restarting the obfuscation provider. Returns the new config used by the new tunnel.
     async fn reconfigure_tunnel(
@@ -680,8 +1035,6 @@ impl WireguardMonitor {
         addresses: &[IpAddr],
         mut setup_done_rx: mpsc::Receiver<std::result::Result<(), BoxedError>>,
     ) -> std::result::Result<(), CloseMsg> {
-        use futures::StreamExt;
-
         setup_done_rx
             .next()
             .await
@@ -752,12 +1105,23 @@ impl WireguardMonitor {
         log_path: Option<&Path>,
         resource_dir: &Path,
         tun_provider: Arc<Mutex<TunProvider>>,
+        #[cfg(target_os = "linux")] namespace: Option<&NetworkNamespace>,
         #[cfg(target_os = "android")] gateway_only: bool,
         #[cfg(windows)] route_manager: crate::routing::RouteManagerHandle,
         #[cfg(windows)] setup_done_tx: mpsc::Sender<std::result::Result<(), BoxedError>>,
     ) -> Result<Box<dyn Tunnel>> {
         log::debug!("Tunnel MTU: {}", config.mtu);
 
+        // Only kernel WireGuard devices can be moved to another namespace. There is no fallback,
+        // since a tun device would route the traffic of the daemon's namespace instead.
+        #[cfg(target_os = "linux")]
+        if let Some(namespace) = namespace {
+            return wireguard_kernel::NetlinkTunnel::new_in_namespace(runtime, config, namespace)
+                .map(|tunnel| Box::new(tunnel) as Box<dyn Tunnel>)
+                .map_err(|error| TunnelError::NetworkNamespaceError(BoxedError::new(error)))
+                .map_err(Error::TunnelError);
+        }
+
         #[cfg(target_os = "linux")]
         if !*FORCE_USERSPACE_WIREGUARD {
             if will_nm_manage_dns() {
@@ -823,10 +1187,21 @@ impl WireguardMonitor {
         }
     }
 
+    /// Returns a handle to the tunnel, which can be used while waiting for the tunnel to exit.
+    pub fn handle(&self) -> TunnelHandle {
+        TunnelHandle {
+            runtime: self.runtime.clone(),
+            tunnel: self.tunnel.clone(),
+            initial_config: self.initial_config.clone(),
+            private_key_tx: self.private_key_tx.clone(),
+        }
+    }
+
     /// Blocks the current thread until tunnel disconnects
     pub fn wait(mut self) -> Result<()> {
         let wait_result = match self.close_msg_receiver.recv() {
-            Ok(CloseMsg::PskNegotiationTimeout) | Ok(CloseMsg::PingErr) => Err(Error::TimeoutError),
+            Ok(CloseMsg::PskNegotiationTimeout) => Err(Error::PskNegotiationTimeout),
+            Ok(CloseMsg::PingErr) => Err(Error::TimeoutError),
             Ok(CloseMsg::Stop) | Ok(CloseMsg::ObfuscatorExpired) => Ok(()),
             Ok(CloseMsg::SetupError(error)) => Err(error),
             Ok(CloseMsg::ObfuscatorFailed(error)) => Err(error),
@@ -873,6 +1248,30 @@ impl WireguardMonitor {
         })
     }
 
+    /// Returns routes that send traffic to the peer endpoints through `interface`, instead of the
+    /// interface that the default route uses. They are added to the main table, which is where
+    /// traffic with our firewall mark is routed, including the traffic of the tunnel itself.
+    #[cfg(target_os = "linux")]
+    async fn get_egress_interface_routes(
+        route_manager: &routing::RouteManagerHandle,
+        interface: &str,
+        endpoints: &[IpAddr],
+    ) -> Result<Vec<RequiredRoute>> {
+        let mut routes = vec![];
+        for endpoint in endpoints {
+            let route = route_manager
+                .get_interface_route(*endpoint, interface.to_owned())
+                .await
+                .map_err(Error::SetupRoutingError)?
+                .ok_or_else(|| Error::NoEgressRouteError(*endpoint, interface.to_owned()))?;
+            routes.push(RequiredRoute::new(
+                ipnetwork::IpNetwork::from(*endpoint),
+                route.get_node().clone(),
+            ));
+        }
+        Ok(routes)
+    }
+
     #[cfg_attr(not(target_os = "windows"), allow(unused_variables))]
     fn get_tunnel_nodes(iface_name: &str, config: &Config) -> (routing::Node, routing::Node) {
         #[cfg(windows)]
@@ -1007,6 +1406,7 @@ impl WireguardMonitor {
             ips: config.tunnel.addresses.clone(),
             ipv4_gateway: config.ipv4_gateway,
             ipv6_gateway: config.ipv6_gateway,
+            data_channel_offload: false,
         }
     }
 }
@@ -1107,12 +1507,23 @@ pub enum TunnelError {
     /// Failure to set up logging
     #[error("Failed to set up logging")]
     LoggingError(#[source] logging::Error),
+
+    /// Failed to create the tunnel device in the network namespace given by
+    /// `TALPID_TUNNEL_NETNS`.
+    #[cfg(target_os = "linux")]
+    #[error("Failed to create tunnel device in network namespace")]
+    NetworkNamespaceError(#[source] BoxedError),
 }
 
 #[cfg(target_os = "linux")]
 fn will_nm_manage_dns() -> bool {
     use talpid_dbus::network_manager::NetworkManager;
 
+    // NetworkManager manages the host namespace only
+    if !netns::is_host_namespace() {
+        return false;
+    }
+
     if talpid_dbus::systemd_resolved::SystemdResolved::new().is_ok() {
         return false;
     }
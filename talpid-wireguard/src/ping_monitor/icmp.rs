@@ -1,6 +1,8 @@
 use byteorder::{NetworkEndian, WriteBytesExt};
 use rand::Rng;
 use socket2::{Domain, Protocol, Socket, Type};
+#[cfg(target_os = "linux")]
+use talpid_tunnel::netns::{self, NetworkNamespace};
 
 use std::{
     io::{self, Write},
@@ -47,6 +49,11 @@ pub enum Error {
     /// Interface name contains null bytes
     #[error("Interface name contains a null byte")]
     InterfaceNameContainsNull,
+
+    /// Failed to open the socket in the network namespace of the tunnel
+    #[cfg(target_os = "linux")]
+    #[error("Failed to open ICMP socket in network namespace")]
+    Namespace(#[source] netns::Error),
 }
 
 type Result<T> = std::result::Result<T, Error>;
@@ -65,13 +72,18 @@ impl Pinger {
         #[cfg(not(target_os = "windows"))] interface_name: String,
     ) -> Result<Self> {
         let addr = SocketAddr::new(addr.into(), 0);
-        let sock =
-            Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4)).map_err(Error::Open)?;
-        sock.set_nonblocking(true).map_err(Error::Open)?;
 
+        // The socket must be opened in the namespace of the tunnel device to bind to it
         #[cfg(target_os = "linux")]
-        sock.bind_device(Some(interface_name.as_bytes()))
-            .map_err(Error::SocketOp)?;
+        let sock = match NetworkNamespace::from_env().map_err(Error::Namespace)? {
+            Some(namespace) => namespace
+                .run(|| Self::open_socket(&interface_name))
+                .map_err(Error::Namespace)??,
+            None => Self::open_socket(&interface_name)?,
+        };
+
+        #[cfg(not(target_os = "linux"))]
+        let sock = Self::open_socket()?;
 
         #[cfg(target_os = "macos")]
         Self::set_device_index(&sock, &interface_name)?;
@@ -84,6 +96,18 @@ impl Pinger {
         })
     }
 
+    fn open_socket(#[cfg(target_os = "linux")] interface_name: &str) -> Result<Socket> {
+        let sock =
+            Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4)).map_err(Error::Open)?;
+        sock.set_nonblocking(true).map_err(Error::Open)?;
+
+        #[cfg(target_os = "linux")]
+        sock.bind_device(Some(interface_name.as_bytes()))
+            .map_err(Error::SocketOp)?;
+
+        Ok(sock)
+    }
+
     #[cfg(target_os = "macos")]
     fn set_device_index(socket: &Socket, interface_name: &str) -> Result<()> {
         let index = nix::net::if_::if_nametoindex(interface_name).map_err(Error::DeviceIdx)?;
@@ -0,0 +1,496 @@
+//! Measures the latency to a WireGuard relay by timing a handshake with it. Unlike a ping over
+//! ICMP, this shows that the relay accepts WireGuard traffic on the port, and it is not affected
+//! by networks that drop or deprioritize ICMP.
+//!
+//! Only the handshake initiation is sent. The session that the relay sets up in response is never
+//! used.
+//!
+//! The responding side of the handshake is also implemented, so that the handshake can be tested
+//! over loopback without a relay.
+
+use blake2::{
+    digest::{consts::U16, Mac},
+    Blake2s256, Blake2sMac, Digest,
+};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305,
+};
+use hmac::SimpleHmac;
+use std::{
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use talpid_net::SocketProtection;
+use talpid_types::net::wireguard::{PrivateKey, PublicKey};
+use tokio::net::UdpSocket;
+use x25519_dalek::StaticSecret;
+
+const CONSTRUCTION: &[u8] = b"Noise_IKpsk2_25519_ChaChaPoly_BLAKE2s";
+const IDENTIFIER: &[u8] = b"WireGuard v1 zx2c4 Jason@zx2c4.com";
+const LABEL_MAC1: &[u8] = b"mac1----";
+
+const HANDSHAKE_INITIATION: u8 = 1;
+const HANDSHAKE_RESPONSE: u8 = 2;
+const COOKIE_REPLY: u8 = 3;
+
+const INITIATION_LEN: usize = 148;
+const RESPONSE_LEN: usize = 92;
+const COOKIE_REPLY_LEN: usize = 64;
+/// The first MAC of the initiation covers everything before it.
+const MAC1_OFFSET: usize = 116;
+/// The first MAC of the response covers everything before it.
+const RESPONSE_MAC1_OFFSET: usize = 60;
+
+/// Label of the TAI64 epoch, which is 10 seconds before the Unix epoch.
+const TAI64_BASE: u64 = 0x400000000000000a;
+
+/// Errors that can occur while probing a relay.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    /// Failed to bind a socket to send the handshake from.
+    #[error("Failed to bind UDP socket")]
+    Bind(#[source] io::Error),
+    /// Failed to keep the socket outside of the tunnel.
+    #[error("Failed to protect UDP socket")]
+    Protect(#[source] io::Error),
+    /// Failed to send the handshake initiation.
+    #[error("Failed to send handshake initiation")]
+    Send(#[source] io::Error),
+    /// Failed to receive the response.
+    #[error("Failed to receive handshake response")]
+    Receive(#[source] io::Error),
+    /// The relay did not respond in time.
+    #[error("Timed out waiting for a handshake response")]
+    Timeout,
+    /// Failed to receive a handshake initiation to respond to.
+    #[error("Failed to receive handshake initiation")]
+    ReceiveInitiation(#[source] io::Error),
+    /// Failed to send the handshake response.
+    #[error("Failed to send handshake response")]
+    SendResponse(#[source] io::Error),
+}
+
+/// Send a handshake initiation to the relay at `endpoint` and return the time until it responds.
+/// `peer` is the public key of the relay. The relay only responds if `private_key` belongs to a
+/// device that it knows about. The handshake is sent from a socket that is protected as described by
+/// `protection`.
+pub async fn handshake_latency(
+    endpoint: SocketAddr,
+    private_key: &PrivateKey,
+    peer: &PublicKey,
+    timeout: Duration,
+    protection: &SocketProtection,
+) -> Result<Duration, Error> {
+    let bind_addr: SocketAddr = if endpoint.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(bind_addr).await.map_err(Error::Bind)?;
+    protection.protect(&socket).await.map_err(Error::Protect)?;
+
+    let sender_index = rand::random();
+    let initiation = handshake_initiation(
+        sender_index,
+        &StaticSecret::from(private_key.to_bytes()),
+        peer,
+        &StaticSecret::random(),
+        SystemTime::now(),
+    );
+
+    let start = Instant::now();
+    socket
+        .send_to(&initiation, endpoint)
+        .await
+        .map_err(Error::Send)?;
+    tokio::time::timeout(timeout, wait_for_response(&socket, endpoint, sender_index))
+        .await
+        .map_err(|_| Error::Timeout)??;
+    Ok(start.elapsed())
+}
+
+async fn wait_for_response(
+    socket: &UdpSocket,
+    endpoint: SocketAddr,
+    sender_index: u32,
+) -> Result<(), Error> {
+    let mut buffer = [0u8; RESPONSE_LEN];
+    loop {
+        let (len, from) = socket
+            .recv_from(&mut buffer)
+            .await
+            .map_err(Error::Receive)?;
+        if from == endpoint && is_response_to(&buffer[..len], sender_index) {
+            return Ok(());
+        }
+    }
+}
+
+/// Wait for a handshake initiation on `socket` and answer it like a WireGuard peer with
+/// `private_key` would. Returns the public key of the initiator. Packets that are not valid
+/// initiations for this peer are ignored.
+pub async fn respond_to_handshake(
+    socket: &UdpSocket,
+    private_key: &PrivateKey,
+) -> Result<PublicKey, Error> {
+    let static_secret = StaticSecret::from(private_key.to_bytes());
+    let mut buffer = [0u8; INITIATION_LEN];
+    loop {
+        let (len, from) = socket
+            .recv_from(&mut buffer)
+            .await
+            .map_err(Error::ReceiveInitiation)?;
+        let Some((response, initiator)) = handshake_response(
+            &buffer[..len],
+            rand::random(),
+            &static_secret,
+            &StaticSecret::random(),
+        ) else {
+            continue;
+        };
+        socket
+            .send_to(&response, from)
+            .await
+            .map_err(Error::SendResponse)?;
+        return Ok(initiator);
+    }
+}
+
+/// Returns whether `packet` answers the initiation sent with `sender_index`. A relay that is
+/// under load answers with a cookie reply instead of a handshake response, which is just as good
+/// for measuring the latency.
+fn is_response_to(packet: &[u8], sender_index: u32) -> bool {
+    let receiver_index = match packet.first() {
+        Some(&HANDSHAKE_RESPONSE) if packet.len() == RESPONSE_LEN => &packet[8..12],
+        Some(&COOKIE_REPLY) if packet.len() == COOKIE_REPLY_LEN => &packet[4..8],
+        _ => return false,
+    };
+    receiver_index == sender_index.to_le_bytes()
+}
+
+/// Build the first message of the Noise IK handshake that WireGuard uses, as described in
+/// section 5.4.2 of the WireGuard whitepaper.
+fn handshake_initiation(
+    sender_index: u32,
+    static_secret: &StaticSecret,
+    peer: &PublicKey,
+    ephemeral_secret: &StaticSecret,
+    now: SystemTime,
+) -> [u8; INITIATION_LEN] {
+    let peer = x25519_dalek::PublicKey::from(*peer.as_bytes());
+    let static_public = x25519_dalek::PublicKey::from(static_secret);
+    let ephemeral_public = x25519_dalek::PublicKey::from(ephemeral_secret);
+
+    let chaining_key = hash(&[CONSTRUCTION]);
+    let handshake_hash = hash(&[&chaining_key, IDENTIFIER]);
+    let handshake_hash = hash(&[&handshake_hash, peer.as_bytes()]);
+
+    let chaining_key = kdf1(&chaining_key, ephemeral_public.as_bytes());
+    let handshake_hash = hash(&[&handshake_hash, ephemeral_public.as_bytes()]);
+
+    let (chaining_key, key) = kdf2(
+        &chaining_key,
+        ephemeral_secret.diffie_hellman(&peer).as_bytes(),
+    );
+    let encrypted_static = seal(&key, static_public.as_bytes(), &handshake_hash);
+    let handshake_hash = hash(&[&handshake_hash, &encrypted_static]);
+
+    let (_, key) = kdf2(
+        &chaining_key,
+        static_secret.diffie_hellman(&peer).as_bytes(),
+    );
+    let encrypted_timestamp = seal(&key, &tai64n(now), &handshake_hash);
+
+    let mut message = [0u8; INITIATION_LEN];
+    message[0] = HANDSHAKE_INITIATION;
+    message[4..8].copy_from_slice(&sender_index.to_le_bytes());
+    message[8..40].copy_from_slice(ephemeral_public.as_bytes());
+    message[40..88].copy_from_slice(&encrypted_static);
+    message[88..MAC1_OFFSET].copy_from_slice(&encrypted_timestamp);
+
+    let mac1_key = hash(&[LABEL_MAC1, peer.as_bytes()]);
+    let mac1 = mac(&mac1_key, &message[..MAC1_OFFSET]);
+    message[MAC1_OFFSET..MAC1_OFFSET + 16].copy_from_slice(&mac1);
+    // The second MAC is left empty, since it is only checked once the relay has sent a cookie
+
+    message
+}
+
+/// Consume a handshake initiation meant for the peer with `static_secret` and build the second
+/// message of the handshake, as described in section 5.4.3 of the WireGuard whitepaper. Returns
+/// the response and the public key of the initiator, or `None` if the initiation is malformed,
+/// has an invalid MAC or cannot be decrypted.
+fn handshake_response(
+    initiation: &[u8],
+    sender_index: u32,
+    static_secret: &StaticSecret,
+    ephemeral_secret: &StaticSecret,
+) -> Option<([u8; RESPONSE_LEN], PublicKey)> {
+    if initiation.len() != INITIATION_LEN || initiation[0] != HANDSHAKE_INITIATION {
+        return None;
+    }
+    let static_public = x25519_dalek::PublicKey::from(static_secret);
+    let mac1_key = hash(&[LABEL_MAC1, static_public.as_bytes()]);
+    if mac(&mac1_key, &initiation[..MAC1_OFFSET])[..] != initiation[MAC1_OFFSET..MAC1_OFFSET + 16] {
+        return None;
+    }
+
+    let receiver_index = &initiation[4..8];
+    let initiator_ephemeral: [u8; 32] = initiation[8..40].try_into().ok()?;
+    let initiator_ephemeral = x25519_dalek::PublicKey::from(initiator_ephemeral);
+    let encrypted_static = &initiation[40..88];
+    let encrypted_timestamp = &initiation[88..MAC1_OFFSET];
+
+    let chaining_key = hash(&[CONSTRUCTION]);
+    let handshake_hash = hash(&[&chaining_key, IDENTIFIER]);
+    let handshake_hash = hash(&[&handshake_hash, static_public.as_bytes()]);
+
+    let chaining_key = kdf1(&chaining_key, initiator_ephemeral.as_bytes());
+    let handshake_hash = hash(&[&handshake_hash, initiator_ephemeral.as_bytes()]);
+
+    let (chaining_key, key) = kdf2(
+        &chaining_key,
+        static_secret
+            .diffie_hellman(&initiator_ephemeral)
+            .as_bytes(),
+    );
+    let initiator_static: [u8; 32] = open(&key, encrypted_static, &handshake_hash)?
+        .try_into()
+        .ok()?;
+    let initiator_static = x25519_dalek::PublicKey::from(initiator_static);
+    let handshake_hash = hash(&[&handshake_hash, encrypted_static]);
+
+    let (chaining_key, key) = kdf2(
+        &chaining_key,
+        static_secret.diffie_hellman(&initiator_static).as_bytes(),
+    );
+    // The timestamp only protects against replays, which do not matter here
+    open(&key, encrypted_timestamp, &handshake_hash)?;
+    let handshake_hash = hash(&[&handshake_hash, encrypted_timestamp]);
+
+    let ephemeral_public = x25519_dalek::PublicKey::from(ephemeral_secret);
+    let chaining_key = kdf1(&chaining_key, ephemeral_public.as_bytes());
+    let handshake_hash = hash(&[&handshake_hash, ephemeral_public.as_bytes()]);
+    let chaining_key = kdf1(
+        &chaining_key,
+        ephemeral_secret
+            .diffie_hellman(&initiator_ephemeral)
+            .as_bytes(),
+    );
+    let chaining_key = kdf1(
+        &chaining_key,
+        ephemeral_secret
+            .diffie_hellman(&initiator_static)
+            .as_bytes(),
+    );
+    // No preshared key is used, which is the same as an all-zero one
+    let (_, tau, key) = kdf3(&chaining_key, &[0; 32]);
+    let handshake_hash = hash(&[&handshake_hash, &tau]);
+    let encrypted_nothing = seal(&key, &[], &handshake_hash);
+
+    let mut message = [0u8; RESPONSE_LEN];
+    message[0] = HANDSHAKE_RESPONSE;
+    message[4..8].copy_from_slice(&sender_index.to_le_bytes());
+    message[8..12].copy_from_slice(receiver_index);
+    message[12..44].copy_from_slice(ephemeral_public.as_bytes());
+    message[44..RESPONSE_MAC1_OFFSET].copy_from_slice(&encrypted_nothing);
+
+    let mac1_key = hash(&[LABEL_MAC1, initiator_static.as_bytes()]);
+    let mac1 = mac(&mac1_key, &message[..RESPONSE_MAC1_OFFSET]);
+    message[RESPONSE_MAC1_OFFSET..RESPONSE_MAC1_OFFSET + 16].copy_from_slice(&mac1);
+
+    Some((message, PublicKey::from(initiator_static.to_bytes())))
+}
+
+fn hash(inputs: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Blake2s256::new();
+    for input in inputs {
+        Digest::update(&mut hasher, input);
+    }
+    hasher.finalize().into()
+}
+
+fn hmac(key: &[u8], inputs: &[&[u8]]) -> [u8; 32] {
+    let mut hmac = <SimpleHmac<Blake2s256> as Mac>::new_from_slice(key)
+        .expect("HMAC accepts keys of any size");
+    for input in inputs {
+        hmac.update(input);
+    }
+    hmac.finalize().into_bytes().into()
+}
+
+fn mac(key: &[u8; 32], input: &[u8]) -> [u8; 16] {
+    let mut mac =
+        <Blake2sMac<U16> as Mac>::new_from_slice(key).expect("BLAKE2s accepts 32-byte keys");
+    mac.update(input);
+    mac.finalize().into_bytes().into()
+}
+
+fn kdf1(key: &[u8; 32], input: &[u8]) -> [u8; 32] {
+    let secret = hmac(key, &[input]);
+    hmac(&secret, &[&[1]])
+}
+
+fn kdf2(key: &[u8; 32], input: &[u8]) -> ([u8; 32], [u8; 32]) {
+    let secret = hmac(key, &[input]);
+    let first = hmac(&secret, &[&[1]]);
+    let second = hmac(&secret, &[&first, &[2]]);
+    (first, second)
+}
+
+fn kdf3(key: &[u8; 32], input: &[u8]) -> ([u8; 32], [u8; 32], [u8; 32]) {
+    let secret = hmac(key, &[input]);
+    let first = hmac(&secret, &[&[1]]);
+    let second = hmac(&secret, &[&first, &[2]]);
+    let third = hmac(&secret, &[&second, &[3]]);
+    (first, second, third)
+}
+
+/// Encrypt `plaintext` with a zero nonce, which is safe since every key is only used once.
+fn seal(key: &[u8; 32], plaintext: &[u8], associated_data: &[u8]) -> Vec<u8> {
+    ChaCha20Poly1305::new(key.into())
+        .encrypt(
+            &Default::default(),
+            Payload {
+                msg: plaintext,
+                aad: associated_data,
+            },
+        )
+        .expect("Encryption of a short message cannot fail")
+}
+
+/// Decrypt `ciphertext` that was encrypted with [`seal`].
+fn open(key: &[u8; 32], ciphertext: &[u8], associated_data: &[u8]) -> Option<Vec<u8>> {
+    ChaCha20Poly1305::new(key.into())
+        .decrypt(
+            &Default::default(),
+            Payload {
+                msg: ciphertext,
+                aad: associated_data,
+            },
+        )
+        .ok()
+}
+
+fn tai64n(time: SystemTime) -> [u8; 12] {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let mut timestamp = [0u8; 12];
+    timestamp[..8].copy_from_slice(&(TAI64_BASE + since_epoch.as_secs()).to_be_bytes());
+    timestamp[8..].copy_from_slice(&since_epoch.subsec_nanos().to_be_bytes());
+    timestamp
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The initiation must match one built independently from the whitepaper.
+    #[test]
+    fn test_handshake_initiation() {
+        let static_secret = StaticSecret::from([1; 32]);
+        let peer = PublicKey::from(&StaticSecret::from([2; 32]));
+        let ephemeral_secret = StaticSecret::from([3; 32]);
+        let now = UNIX_EPOCH + Duration::new(1_700_000_000, 123);
+
+        let initiation =
+            handshake_initiation(0x01020304, &static_secret, &peer, &ephemeral_secret, now);
+
+        assert_eq!(
+            hex::encode(initiation),
+            "01000000040302015dfedd3b6bd47f6fa28ee15d969d5bb0ea53774d488bdaf9df1c6e0124b3ef220a2659\
+             9fb2188bb723276fc173af67616c817fc32fd04d686d87ec152fac10eed2bda3bb3b6f1eded69776840822\
+             84d817fb46b3a99b6be3bd273ead8378ec9f09ccd3a292e6f7a294136ac343416d7a2676100784debcec8a\
+             393c8d00000000000000000000000000000000"
+        );
+    }
+
+    #[test]
+    fn test_handshake_response() {
+        let initiator_secret = StaticSecret::from([1; 32]);
+        let responder_secret = StaticSecret::from([2; 32]);
+        let responder = PublicKey::from(&responder_secret);
+        let now = UNIX_EPOCH + Duration::new(1_700_000_000, 123);
+        let mut initiation = handshake_initiation(
+            7,
+            &initiator_secret,
+            &responder,
+            &StaticSecret::from([3; 32]),
+            now,
+        );
+
+        let (response, initiator) = handshake_response(
+            &initiation,
+            9,
+            &responder_secret,
+            &StaticSecret::from([4; 32]),
+        )
+        .expect("valid initiation was rejected");
+        assert_eq!(initiator, PublicKey::from(&initiator_secret));
+        assert!(is_response_to(&response, 7));
+        // Built independently from the whitepaper, like the initiation
+        assert_eq!(
+            hex::encode(response),
+            "020000000900000007000000ac01b2209e86354fb853237b5de0f4fab13c7fcbf433a61c019369617fecf10b\
+             3be9b87124fcd72849560adba2f6a68da251bcea53b264d2ae4e86cd704f688f00000000000000000000000000000000"
+        );
+
+        // An initiation meant for another peer has an invalid MAC
+        let other_secret = StaticSecret::from([5; 32]);
+        assert!(
+            handshake_response(&initiation, 9, &other_secret, &StaticSecret::from([4; 32]))
+                .is_none()
+        );
+
+        // Tampering with the encrypted fields is caught even if the MAC is recomputed
+        initiation[50] ^= 1;
+        let mac1_key = hash(&[LABEL_MAC1, responder.as_bytes()]);
+        let mac1 = mac(&mac1_key, &initiation[..MAC1_OFFSET]);
+        initiation[MAC1_OFFSET..MAC1_OFFSET + 16].copy_from_slice(&mac1);
+        assert!(handshake_response(
+            &initiation,
+            9,
+            &responder_secret,
+            &StaticSecret::from([4; 32])
+        )
+        .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_loopback_handshake() {
+        let responder_key = PrivateKey::new_from_random();
+        let initiator_key = PrivateKey::new_from_random();
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let endpoint = socket.local_addr().unwrap();
+        let responder = responder_key.public_key();
+
+        let (received_key, _latency) = tokio::try_join!(
+            respond_to_handshake(&socket, &responder_key),
+            handshake_latency(
+                endpoint,
+                &initiator_key,
+                &responder,
+                Duration::from_secs(5),
+                &SocketProtection::default(),
+            )
+        )
+        .unwrap();
+        assert_eq!(received_key, initiator_key.public_key());
+    }
+
+    #[test]
+    fn test_is_response_to() {
+        let mut response = [0u8; RESPONSE_LEN];
+        response[0] = HANDSHAKE_RESPONSE;
+        response[8..12].copy_from_slice(&7u32.to_le_bytes());
+        assert!(is_response_to(&response, 7));
+        assert!(!is_response_to(&response, 8));
+        assert!(!is_response_to(&response[..RESPONSE_LEN - 1], 7));
+
+        let mut cookie_reply = [0u8; COOKIE_REPLY_LEN];
+        cookie_reply[0] = COOKIE_REPLY;
+        cookie_reply[4..8].copy_from_slice(&7u32.to_le_bytes());
+        assert!(is_response_to(&cookie_reply, 7));
+    }
+}
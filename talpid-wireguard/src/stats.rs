@@ -1,7 +1,9 @@
 /// Contains bytes sent and received through a tunnel
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
 pub struct Stats {
+    /// Number of bytes sent to the peer.
     pub tx_bytes: u64,
+    /// Number of bytes received from the peer.
     pub rx_bytes: u64,
 }
 
@@ -0,0 +1,78 @@
+//! Reads the options of the TCP connection of udp2tcp from the environment. Which options work best
+//! depends on the link. Long-distance links in particular tend to do much better with BBR than with
+//! the default congestion control.
+
+use std::env;
+use tunnel_obfuscation::Udp2TcpTuning;
+
+const NODELAY_VAR: &str = "TALPID_UDP2TCP_NODELAY";
+#[cfg(target_os = "linux")]
+const CONGESTION_VAR: &str = "TALPID_UDP2TCP_CONGESTION";
+const RECV_BUFFER_VAR: &str = "TALPID_UDP2TCP_RECV_BUFFER";
+const SEND_BUFFER_VAR: &str = "TALPID_UDP2TCP_SEND_BUFFER";
+
+/// Read the tuning from the environment. Invalid values are logged and ignored.
+pub fn from_env() -> Udp2TcpTuning {
+    let default = Udp2TcpTuning::default();
+    let tuning = Udp2TcpTuning {
+        nodelay: read_var(NODELAY_VAR, parse_bool).unwrap_or(default.nodelay),
+        #[cfg(target_os = "linux")]
+        congestion: read_var(CONGESTION_VAR, parse_congestion),
+        recv_buffer_size: read_var(RECV_BUFFER_VAR, parse_buffer_size),
+        send_buffer_size: read_var(SEND_BUFFER_VAR, parse_buffer_size),
+    };
+    if tuning != default {
+        log::debug!("Tuning udp2tcp: {tuning:?}");
+    }
+    tuning
+}
+
+fn read_var<T>(var: &str, parse: impl FnOnce(&str) -> Option<T>) -> Option<T> {
+    let value = env::var(var).ok()?;
+    let parsed = parse(value.trim());
+    if parsed.is_none() {
+        log::error!("Ignoring invalid {var} value: {value}");
+    }
+    parsed
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "1" => Some(true),
+        "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// The kernel limits the name of a congestion control algorithm to 15 characters.
+#[cfg(target_os = "linux")]
+fn parse_congestion(value: &str) -> Option<String> {
+    let valid = (1..16).contains(&value.len())
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    valid.then(|| value.to_owned())
+}
+
+fn parse_buffer_size(value: &str) -> Option<u32> {
+    value.parse().ok().filter(|size| *size > 0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_values() {
+        assert_eq!(parse_bool("0"), Some(false));
+        assert_eq!(parse_bool("yes"), None);
+        assert_eq!(parse_buffer_size("4194304"), Some(4194304));
+        assert_eq!(parse_buffer_size("0"), None);
+        #[cfg(target_os = "linux")]
+        {
+            assert_eq!(parse_congestion("bbr"), Some("bbr".to_owned()));
+            assert_eq!(parse_congestion(""), None);
+            assert_eq!(parse_congestion("bbr; reboot"), None);
+        }
+    }
+}
@@ -17,10 +17,9 @@ use zeroize::Zeroize;
 #[cfg(target_os = "android")]
 use talpid_tunnel::tun_provider;
 
-use std::{
-    net::IpAddr,
-    os::unix::io::{AsRawFd, RawFd},
-};
+#[cfg(not(target_os = "linux"))]
+use std::os::unix::io::AsRawFd;
+use std::{net::IpAddr, os::unix::io::RawFd};
 use talpid_tunnel::tun_provider::{Tun, TunConfig};
 
 type Result<T> = std::result::Result<T, TunnelError>;
@@ -59,8 +58,17 @@ impl WgGoTunnel {
         #[cfg(target_os = "android")]
         let tun_provider_clone = tun_provider.clone();
 
+        #[cfg(target_os = "linux")]
+        let tuning = tuning::Tuning::from_env();
+
         #[cfg_attr(not(target_os = "android"), allow(unused_mut))]
-        let (mut tunnel_device, tunnel_fd) = Self::get_tunnel(tun_provider, config, routes)?;
+        let (mut tunnel_device, tunnel_fds) = Self::get_tunnel(
+            tun_provider,
+            config,
+            routes,
+            #[cfg(target_os = "linux")]
+            tuning.queues,
+        )?;
 
         let interface_name: String = tunnel_device.interface_name().to_string();
         let wg_config_str = config.to_userspace_format();
@@ -70,12 +78,30 @@ impl WgGoTunnel {
 
         #[cfg(not(target_os = "android"))]
         let mtu = config.mtu as isize;
+        #[cfg(target_os = "linux")]
+        let handle = {
+            let cpus: Vec<i32> = tuning.cpus.iter().map(|&cpu| cpu as i32).collect();
+            unsafe {
+                wgTurnOnMultiQueue(
+                    mtu,
+                    wg_config_str.as_ptr() as _,
+                    tunnel_fds.as_ptr(),
+                    tunnel_fds.len() as isize,
+                    tuning.threads as isize,
+                    cpus.as_ptr(),
+                    cpus.len() as isize,
+                    Some(logging::wg_go_logging_callback),
+                    logging_context.0 as *mut c_void,
+                )
+            }
+        };
+        #[cfg(not(target_os = "linux"))]
         let handle = unsafe {
             wgTurnOn(
                 #[cfg(not(target_os = "android"))]
                 mtu,
                 wg_config_str.as_ptr() as _,
-                tunnel_fd,
+                tunnel_fds[0],
                 Some(logging::wg_go_logging_callback),
                 logging_context.0 as *mut c_void,
             )
@@ -100,6 +126,7 @@ impl WgGoTunnel {
         config: &Config,
         routes: impl Iterator<Item = IpNetwork>,
         #[cfg(target_os = "android")] excluded_apps: Vec<String>,
+        #[cfg(target_os = "linux")] queues: usize,
     ) -> TunConfig {
         let mut dns_servers = vec![IpAddr::V4(config.ipv4_gateway)];
         dns_servers.extend(config.ipv6_gateway.map(IpAddr::V6));
@@ -113,6 +140,8 @@ impl WgGoTunnel {
             #[cfg(target_os = "android")]
             excluded_packages: excluded_apps,
             mtu: config.mtu,
+            #[cfg(target_os = "linux")]
+            queues,
         }
     }
 
@@ -152,11 +181,14 @@ impl WgGoTunnel {
         Ok(())
     }
 
+    /// Create the tunnel device, and return it along with duplicates of the file descriptors of
+    /// its queues, which are handed over to wireguard-go.
     fn get_tunnel(
         tun_provider: Arc<Mutex<TunProvider>>,
         config: &Config,
         routes: impl Iterator<Item = IpNetwork>,
-    ) -> Result<(Tun, RawFd)> {
+        #[cfg(target_os = "linux")] queues: usize,
+    ) -> Result<(Tun, Vec<RawFd>)> {
         let mut last_error = None;
         let mut tun_provider = tun_provider.lock().unwrap();
 
@@ -165,15 +197,18 @@ impl WgGoTunnel {
             routes,
             #[cfg(target_os = "android")]
             tun_provider.get_excluded_apps().collect(),
+            #[cfg(target_os = "linux")]
+            queues,
         );
 
         for _ in 1..=MAX_PREPARE_TUN_ATTEMPTS {
-            let tunnel_device = tun_provider
+            #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
+            let mut tunnel_device = tun_provider
                 .get_tun(tunnel_config.clone())
                 .map_err(TunnelError::SetupTunnelDevice)?;
 
-            match nix::unistd::dup(tunnel_device.as_raw_fd()) {
-                Ok(fd) => return Ok((tunnel_device, fd)),
+            match Self::dup_queue_fds(&mut tunnel_device) {
+                Ok(fds) => return Ok((tunnel_device, fds)),
                 #[cfg(not(target_os = "macos"))]
                 Err(error @ nix::errno::Errno::EBADFD) => last_error = Some(error),
                 Err(error @ nix::errno::Errno::EBADF) => last_error = Some(error),
@@ -185,6 +220,27 @@ impl WgGoTunnel {
             last_error.expect("Should be collected in loop"),
         ))
     }
+
+    fn dup_queue_fds(tunnel_device: &mut Tun) -> std::result::Result<Vec<RawFd>, nix::Error> {
+        #[cfg(target_os = "linux")]
+        let fds = tunnel_device.queue_fds();
+        #[cfg(not(target_os = "linux"))]
+        let fds = [tunnel_device.as_raw_fd()];
+
+        let mut duplicates = Vec::with_capacity(fds.len());
+        for fd in fds {
+            match nix::unistd::dup(fd) {
+                Ok(duplicate) => duplicates.push(duplicate),
+                Err(error) => {
+                    for duplicate in duplicates {
+                        let _ = nix::unistd::close(duplicate);
+                    }
+                    return Err(error);
+                }
+            }
+        }
+        Ok(duplicates)
+    }
 }
 
 impl Drop for WgGoTunnel {
@@ -289,7 +345,7 @@ extern "C" {
     ///
     /// Positive return values are tunnel handles for this specific wireguard tunnel instance.
     /// Negative return values signify errors. All error codes are opaque.
-    #[cfg(not(target_os = "android"))]
+    #[cfg(target_os = "macos")]
     fn wgTurnOn(
         mtu: isize,
         settings: *const i8,
@@ -298,6 +354,23 @@ extern "C" {
         logging_context: *mut c_void,
     ) -> i32;
 
+    /// Same as `wgTurnOn`, but takes the file descriptors of all queues of a multi-queue tunnel
+    /// device. Each queue is read on its own thread, which is pinned to one of `cpus` in turn if
+    /// any are given. If `threads` is positive, it limits the number of threads that run Go code
+    /// at once.
+    #[cfg(target_os = "linux")]
+    fn wgTurnOnMultiQueue(
+        mtu: isize,
+        settings: *const i8,
+        fds: *const Fd,
+        fd_count: isize,
+        threads: isize,
+        cpus: *const i32,
+        cpu_count: isize,
+        logging_callback: Option<logging::LoggingCallback>,
+        logging_context: *mut c_void,
+    ) -> i32;
+
     // Android
     #[cfg(target_os = "android")]
     fn wgTurnOn(
@@ -328,6 +401,107 @@ extern "C" {
     fn wgGetSocketV6(handle: i32) -> Fd;
 }
 
+/// Tuning of wireguard-go for high throughput, such as on 10 Gbit/s links where a single queue
+/// cannot keep up.
+#[cfg(target_os = "linux")]
+mod tuning {
+    use std::env;
+
+    const QUEUES_VAR: &str = "TALPID_WIREGUARD_GO_QUEUES";
+    const THREADS_VAR: &str = "TALPID_WIREGUARD_GO_THREADS";
+    const CPUS_VAR: &str = "TALPID_WIREGUARD_GO_CPUS";
+
+    /// The kernel does not allow more queues than this on a tun device.
+    const MAX_QUEUES: usize = 256;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Tuning {
+        /// Number of queues of the tunnel device. Each queue is read on its own thread.
+        pub queues: usize,
+        /// Maximum number of threads that run Go code at once, or 0 to use one per CPU.
+        pub threads: usize,
+        /// CPUs that the threads reading from the queues are pinned to, in turn.
+        pub cpus: Vec<usize>,
+    }
+
+    impl Default for Tuning {
+        fn default() -> Self {
+            Self {
+                queues: 1,
+                threads: 0,
+                cpus: vec![],
+            }
+        }
+    }
+
+    impl Tuning {
+        /// Read the tuning from the environment. Invalid values are logged and ignored.
+        pub fn from_env() -> Self {
+            let default = Self::default();
+            let tuning = Self {
+                queues: read_var(QUEUES_VAR, |value| {
+                    parse_number(value).filter(|queues| (1..=MAX_QUEUES).contains(queues))
+                })
+                .unwrap_or(default.queues),
+                threads: read_var(THREADS_VAR, parse_number).unwrap_or(default.threads),
+                cpus: read_var(CPUS_VAR, parse_cpu_list).unwrap_or(default.cpus),
+            };
+            if tuning != Self::default() {
+                log::debug!("Tuning wireguard-go: {tuning:?}");
+            }
+            tuning
+        }
+    }
+
+    fn read_var<T>(var: &str, parse: impl FnOnce(&str) -> Option<T>) -> Option<T> {
+        let value = env::var(var).ok()?;
+        let parsed = parse(value.trim());
+        if parsed.is_none() {
+            log::error!("Ignoring invalid {var} value: {value}");
+        }
+        parsed
+    }
+
+    fn parse_number(value: &str) -> Option<usize> {
+        value.parse().ok()
+    }
+
+    /// Parse a list of CPUs in the format used by `taskset --cpu-list`, such as `0-3,8`.
+    fn parse_cpu_list(value: &str) -> Option<Vec<usize>> {
+        let mut cpus = vec![];
+        for range in value.split(',') {
+            let (first, last) = match range.split_once('-') {
+                Some((first, last)) => (parse_number(first)?, parse_number(last)?),
+                None => {
+                    let cpu = parse_number(range)?;
+                    (cpu, cpu)
+                }
+            };
+            if first > last {
+                return None;
+            }
+            cpus.extend(first..=last);
+        }
+        Some(cpus)
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::parse_cpu_list;
+
+        #[test]
+        fn test_parse_cpu_list() {
+            assert_eq!(parse_cpu_list("3"), Some(vec![3]));
+            assert_eq!(parse_cpu_list("0-3,8"), Some(vec![0, 1, 2, 3, 8]));
+            assert_eq!(parse_cpu_list("4,2-2"), Some(vec![4, 2]));
+
+            for invalid in ["", "1,", "3-1", "a", "1-", "-1"] {
+                assert_eq!(parse_cpu_list(invalid), None, "{invalid}");
+            }
+        }
+    }
+}
+
 mod stats {
     use super::{Stats, StatsMap};
 
@@ -1,11 +1,12 @@
 use super::{Config, Tunnel, TunnelError};
 use futures::future::{abortable, AbortHandle};
+use ipnetwork::IpNetwork;
 use netlink_packet_core::{constants::*, NetlinkDeserializable};
 use netlink_packet_route::{
     rtnl::{
         address::nlas::Nla as AddressNla,
         link::nlas::{Info, InfoKind, Nla as LinkNla},
-        AddressMessage, LinkMessage, RtnlMessage, RT_SCOPE_UNIVERSE,
+        AddressMessage, LinkMessage, RtnlMessage, RT_SCOPE_LINK, RT_SCOPE_UNIVERSE,
     },
     NetlinkMessage, NetlinkPayload,
 };
@@ -14,7 +15,8 @@ use netlink_proto::{
     sys::{protocols::NETLINK_GENERIC, SocketAddr},
     ConnectionHandle, Error as NetlinkError,
 };
-use std::{ffi::CString, net::IpAddr};
+use std::{ffi::CString, fs::File, net::IpAddr, os::unix::io::AsRawFd};
+use talpid_tunnel::netns::{self, NetworkNamespace};
 use tokio_stream::StreamExt;
 
 mod parsers;
@@ -68,6 +70,12 @@ pub enum Error {
     #[error("Interface name too long")]
     InterfaceName,
 
+    #[error("Failed to choose a name for the WireGuard device")]
+    InterfaceNameTemplate(#[source] talpid_tunnel::interface_name::Error),
+
+    #[error("Interface {0} already exists")]
+    InterfaceExists(String),
+
     #[error("Send request error")]
     SendRequest(#[source] NetlinkError<DeviceMessage>),
 
@@ -80,6 +88,18 @@ pub enum Error {
     #[error("Failed to delete device")]
     DeleteDevice(#[source] rtnetlink::Error),
 
+    #[error("Failed to use the network namespace of the tunnel")]
+    TunnelNamespace(#[source] netns::Error),
+
+    #[error("Failed to move device to another network namespace")]
+    MoveDevice(#[source] rtnetlink::Error),
+
+    #[error("Failed to bring up device")]
+    SetUp(#[source] rtnetlink::Error),
+
+    #[error("Failed to add route through device")]
+    AddRoute(#[source] rtnetlink::Error),
+
     #[error("NetworkManager error")]
     NetworkManager(#[source] nm_tunnel::Error),
 }
@@ -97,6 +117,24 @@ pub struct Handle {
 impl Handle {
     pub async fn connect() -> Result<Self, Error> {
         let message_type = Self::get_wireguard_message_type().await?;
+        Self::open(message_type)
+    }
+
+    /// Connect to the kernel in `namespace` rather than in the namespace of the current process.
+    pub async fn connect_in(namespace: &NetworkNamespace) -> Result<Self, Error> {
+        // Generic netlink family IDs are the same in every namespace
+        let message_type = Self::get_wireguard_message_type().await?;
+        let runtime = tokio::runtime::Handle::current();
+        namespace
+            .run(move || {
+                let _guard = runtime.enter();
+                Self::open(message_type)
+            })
+            .map_err(Error::TunnelNamespace)?
+    }
+
+    /// Open the netlink sockets in the namespace of the current thread.
+    fn open(message_type: u16) -> Result<Self, Error> {
         let (conn, wireguard_connection, _messages) =
             netlink_proto::new_connection(NETLINK_GENERIC).map_err(Error::NetlinkSocket)?;
         let wg_handle = WireguardConnection {
@@ -154,7 +192,14 @@ impl Handle {
     }
 
     // create a wireguard device with the given name.
-    pub async fn create_device(&mut self, name: String, mtu: u32) -> Result<u32, Error> {
+    /// Create a WireGuard device called `name`, and return its index. If the name is taken by
+    /// another WireGuard device, that device is used instead if `reuse_existing` is true.
+    pub async fn create_device(
+        &mut self,
+        name: String,
+        mtu: u32,
+        reuse_existing: bool,
+    ) -> Result<u32, Error> {
         let mut message = LinkMessage::default();
 
         // set link to be up
@@ -180,6 +225,9 @@ impl Handle {
             .map_err(Error::NetlinkCreateDevice)?;
         while let Some(response_message) = response.next().await {
             if let NetlinkPayload::Error(err) = response_message.payload {
+                if -err.code == libc::EEXIST && !reuse_existing {
+                    return Err(Error::InterfaceExists(name));
+                }
                 // if the device exists, verify that it's a wireguard device
                 if -err.code != libc::EEXIST {
                     return Err(Error::NetlinkCreateDevice(rtnetlink::Error::NetlinkError(
@@ -190,8 +238,13 @@ impl Handle {
         }
 
         // fetch interface index of new device
-        let new_device = self.wg_handle.get_by_name(name).await?;
-        for nla in new_device.nlas {
+        self.get_device_index(name).await
+    }
+
+    /// Return the index of the WireGuard device called `name`.
+    pub async fn get_device_index(&mut self, name: String) -> Result<u32, Error> {
+        let device = self.wg_handle.get_by_name(name).await?;
+        for nla in device.nlas {
             if let DeviceNla::IfIndex(index) = nla {
                 return Ok(index);
             }
@@ -200,6 +253,87 @@ impl Handle {
         Err(Error::NoDevice)
     }
 
+    /// Move a device to the network namespace that `namespace` refers to. The device is brought
+    /// down and loses its addresses and routes, and it may be given another index.
+    pub async fn move_device(&mut self, index: u32, namespace: &File) -> Result<(), Error> {
+        let mut link_message = LinkMessage::default();
+        link_message.header.index = index;
+        link_message
+            .nlas
+            .push(LinkNla::NetNsFd(namespace.as_raw_fd()));
+
+        let mut request = NetlinkMessage::from(RtnlMessage::SetLink(link_message));
+        request.header.flags = NLM_F_REQUEST | NLM_F_ACK;
+
+        let mut response = self
+            .route_handle
+            .request(request)
+            .map_err(Error::MoveDevice)?;
+        while let Some(message) = response.next().await {
+            consume_netlink_error(message, Error::MoveDevice)?;
+        }
+
+        Ok(())
+    }
+
+    /// Bring up a device.
+    pub async fn set_up(&mut self, index: u32) -> Result<(), Error> {
+        let mut link_message = LinkMessage::default();
+        link_message.header.index = index;
+        link_message.header.flags = netlink_packet_route::IFF_UP;
+        link_message.header.change_mask = netlink_packet_route::IFF_UP;
+
+        let mut request = NetlinkMessage::from(RtnlMessage::SetLink(link_message));
+        request.header.flags = NLM_F_REQUEST | NLM_F_ACK;
+
+        let mut response = self.route_handle.request(request).map_err(Error::SetUp)?;
+        while let Some(message) = response.next().await {
+            consume_netlink_error(message, Error::SetUp)?;
+        }
+
+        Ok(())
+    }
+
+    /// Route `network` through a device in the main routing table, replacing any existing route
+    /// to it.
+    pub async fn add_device_route(&mut self, index: u32, network: IpNetwork) -> Result<(), Error> {
+        let message = match network {
+            IpNetwork::V4(network) => self
+                .route_handle
+                .route()
+                .add()
+                .v4()
+                .destination_prefix(network.ip(), network.prefix())
+                .scope(RT_SCOPE_LINK)
+                .output_interface(index)
+                .message_mut()
+                .clone(),
+            IpNetwork::V6(network) => self
+                .route_handle
+                .route()
+                .add()
+                .v6()
+                .destination_prefix(network.ip(), network.prefix())
+                .scope(RT_SCOPE_LINK)
+                .output_interface(index)
+                .message_mut()
+                .clone(),
+        };
+
+        let mut request = NetlinkMessage::from(RtnlMessage::NewRoute(message));
+        request.header.flags = NLM_F_REQUEST | NLM_F_ACK | NLM_F_CREATE | NLM_F_REPLACE;
+
+        let mut response = self
+            .route_handle
+            .request(request)
+            .map_err(Error::AddRoute)?;
+        while let Some(message) = response.next().await {
+            consume_netlink_error(message, Error::AddRoute)?;
+        }
+
+        Ok(())
+    }
+
     pub async fn set_ip_address(&mut self, index: u32, addr: IpAddr) -> Result<(), Error> {
         let address_message = add_ip_addr_message(index, addr);
         let mut request = NetlinkMessage::from(RtnlMessage::NewAddress(address_message));
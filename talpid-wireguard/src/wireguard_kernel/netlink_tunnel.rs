@@ -1,11 +1,15 @@
 use std::pin::Pin;
 
 use futures::Future;
+use talpid_tunnel::{
+    interface_name::{self, NameTemplate},
+    netns::NetworkNamespace,
+};
 
 use super::{
     super::stats::{Stats, StatsMap},
     wg_message::DeviceNla,
-    Config, Error, Handle, Tunnel, TunnelError, MULLVAD_INTERFACE_NAME,
+    Config, Error, Handle, Tunnel, TunnelError, WireguardConnection, MULLVAD_INTERFACE_NAME,
 };
 
 pub struct NetlinkTunnel {
@@ -18,34 +22,145 @@ impl NetlinkTunnel {
     pub fn new(tokio_handle: tokio::runtime::Handle, config: &Config) -> Result<Self, Error> {
         tokio_handle.clone().block_on(async {
             let mut netlink_connections = Handle::connect().await?;
-            let interface_index = netlink_connections
-                .create_device(MULLVAD_INTERFACE_NAME.to_string(), config.mtu as u32)
-                .await?;
+            let interface_index = Self::create_device(&mut netlink_connections, config).await?;
 
             let mut tunnel = Self {
                 interface_index,
                 netlink_connections,
                 tokio_handle,
             };
+            tunnel.setup_or_delete(config, false).await?;
 
-            if let Err(err) = tunnel.setup(config).await {
-                if let Err(teardown_err) = tunnel
-                    .netlink_connections
-                    .delete_device(interface_index)
-                    .await
-                {
-                    log::error!(
-                        "Failed to tear down WireGuard interface after failing to apply config: {}",
-                        teardown_err
-                    );
+            Ok(tunnel)
+        })
+    }
+
+    /// Create the device in the current namespace and then move it to `namespace`. The encrypted
+    /// traffic is still sent from the current namespace, but only programs in `namespace` can use
+    /// the tunnel. Routes through the tunnel are added to the main table of `namespace`.
+    pub fn new_in_namespace(
+        tokio_handle: tokio::runtime::Handle,
+        config: &Config,
+        namespace: &NetworkNamespace,
+    ) -> Result<Self, Error> {
+        tokio_handle.clone().block_on(async {
+            let mut netlink_connections = Handle::connect().await?;
+            let interface_index = Self::create_device(&mut netlink_connections, config).await?;
+
+            let moved =
+                Self::move_device(&mut netlink_connections, interface_index, namespace).await;
+            let (netlink_connections, interface_index) = match moved {
+                Ok(moved) => moved,
+                Err(err) => {
+                    if let Err(teardown_err) =
+                        netlink_connections.delete_device(interface_index).await
+                    {
+                        log::error!(
+                            "Failed to tear down WireGuard interface after failing to move it: {}",
+                            teardown_err
+                        );
+                    }
+                    return Err(err);
                 }
-                return Err(err);
-            }
+            };
+            log::debug!(
+                "Moved WireGuard interface to network namespace {}",
+                namespace.path().display()
+            );
+
+            let mut tunnel = Self {
+                interface_index,
+                netlink_connections,
+                tokio_handle,
+            };
+            tunnel.setup_or_delete(config, true).await?;
 
             Ok(tunnel)
         })
     }
 
+    async fn create_device(
+        netlink_connections: &mut Handle,
+        config: &Config,
+    ) -> Result<u32, Error> {
+        let template = NameTemplate::from_env().map_err(Error::InterfaceNameTemplate)?;
+        match template {
+            Some(template) => {
+                Self::create_device_from_template(netlink_connections, &template, config.mtu as u32)
+                    .await
+            }
+            None => {
+                netlink_connections
+                    .create_device(MULLVAD_INTERFACE_NAME.to_string(), config.mtu as u32, true)
+                    .await
+            }
+        }
+    }
+
+    /// Move the device to `namespace`, and return connections to that namespace along with the
+    /// index of the device in it.
+    async fn move_device(
+        netlink_connections: &mut Handle,
+        interface_index: u32,
+        namespace: &NetworkNamespace,
+    ) -> Result<(Handle, u32), Error> {
+        let name = device_name(&mut netlink_connections.wg_handle, interface_index).await?;
+        let namespace_file = namespace.open().map_err(Error::TunnelNamespace)?;
+        netlink_connections
+            .move_device(interface_index, &namespace_file)
+            .await?;
+
+        let mut namespace_connections = Handle::connect_in(namespace).await?;
+        let interface_index = namespace_connections.get_device_index(name).await?;
+        namespace_connections.set_up(interface_index).await?;
+        Ok((namespace_connections, interface_index))
+    }
+
+    /// Apply `config` to the device, and delete the device if that fails.
+    async fn setup_or_delete(&mut self, config: &Config, add_routes: bool) -> Result<(), Error> {
+        let mut result = self.setup(config).await;
+        if result.is_ok() && add_routes {
+            result = self.add_routes(config).await;
+        }
+        if let Err(err) = result {
+            if let Err(teardown_err) = self
+                .netlink_connections
+                .delete_device(self.interface_index)
+                .await
+            {
+                log::error!(
+                    "Failed to tear down WireGuard interface after failing to apply config: {}",
+                    teardown_err
+                );
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Create a device with the first name that matches `template` and is not in use. Unlike the
+    /// default name, an existing device is never reused, since it may belong to another tool.
+    async fn create_device_from_template(
+        netlink_connections: &mut Handle,
+        template: &NameTemplate,
+        mtu: u32,
+    ) -> Result<u32, Error> {
+        for name in template
+            .candidates()
+            .filter(|name| !interface_name::is_in_use(name))
+        {
+            match netlink_connections.create_device(name, mtu, false).await {
+                Err(Error::InterfaceExists(name)) => {
+                    log::debug!("WireGuard interface name {name} was taken. Trying the next one");
+                }
+                result => return result,
+            }
+        }
+        Err(Error::InterfaceNameTemplate(
+            interface_name::Error::NoUnusedName(template.to_string()),
+        ))
+    }
+
     async fn setup(&mut self, config: &Config) -> Result<(), Error> {
         self.netlink_connections
             .wg_handle
@@ -60,23 +175,48 @@ impl NetlinkTunnel {
 
         Ok(())
     }
+
+    /// Route the allowed IPs of every peer through the device. Networks in a family that the
+    /// tunnel has no address in are skipped.
+    async fn add_routes(&mut self, config: &Config) -> Result<(), Error> {
+        let networks = config
+            .peers()
+            .flat_map(|peer| peer.allowed_ips.iter())
+            .filter(|network| {
+                config
+                    .tunnel
+                    .addresses
+                    .iter()
+                    .any(|address| address.is_ipv4() == network.is_ipv4())
+            });
+        for network in networks {
+            self.netlink_connections
+                .add_device_route(self.interface_index, *network)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+async fn device_name(wg: &mut WireguardConnection, interface_index: u32) -> Result<String, Error> {
+    let device = wg.get_by_index(interface_index).await?;
+    for nla in device.nlas {
+        if let DeviceNla::IfName(name) = nla {
+            return Ok(name.to_string_lossy().to_string());
+        }
+    }
+    Err(Error::Truncated)
 }
 
 impl Tunnel for NetlinkTunnel {
     fn get_interface_name(&self) -> String {
         let mut wg = self.netlink_connections.wg_handle.clone();
-        let result = self.tokio_handle.block_on(async move {
-            let device = wg.get_by_index(self.interface_index).await?;
-            for nla in device.nlas {
-                if let DeviceNla::IfName(name) = nla {
-                    return Ok(name);
-                }
-            }
-            Err(Error::Truncated)
-        });
+        let result = self
+            .tokio_handle
+            .block_on(device_name(&mut wg, self.interface_index));
 
         match result {
-            Ok(name) => name.to_string_lossy().to_string(),
+            Ok(name) => name,
             Err(err) => {
                 log::error!("Failed to deduce interface name at runtime, will attempt to use the default name. {}", err);
                 MULLVAD_INTERFACE_NAME.to_string()
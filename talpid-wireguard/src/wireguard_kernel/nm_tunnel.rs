@@ -11,6 +11,7 @@ use talpid_dbus::{
         WireguardTunnel,
     },
 };
+use talpid_tunnel::interface_name::NameTemplate;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -37,7 +38,11 @@ impl NetworkManagerTunnel {
         let network_manager = NetworkManager::new()
             .map_err(Error::NetworkManager)
             .map_err(WgKernelError::NetworkManager)?;
-        let config_map = convert_config_to_dbus(config);
+        let interface_name = NameTemplate::from_env()
+            .and_then(|template| template.map(|template| template.first_unused()).transpose())
+            .map_err(WgKernelError::InterfaceNameTemplate)?
+            .unwrap_or_else(|| MULLVAD_INTERFACE_NAME.to_string());
+        let config_map = convert_config_to_dbus(config, &interface_name);
         let tunnel = network_manager
             .create_wg_tunnel(&config_map)
             .map_err(|err| WgKernelError::NetworkManager(err.into()))?;
@@ -46,7 +51,7 @@ impl NetworkManagerTunnel {
             Ok(name) => name,
             Err(error) => {
                 log::error!("Failed to fetch interface name from NM: {}", error);
-                MULLVAD_INTERFACE_NAME.to_string()
+                interface_name
             }
         };
         let netlink_connections = tokio_handle.block_on(Handle::connect())?;
@@ -112,7 +117,7 @@ impl Tunnel for NetworkManagerTunnel {
     }
 }
 
-fn convert_config_to_dbus(config: &Config) -> DeviceConfig {
+fn convert_config_to_dbus(config: &Config, interface_name: &str) -> DeviceConfig {
     let mut ipv6_config: VariantMap = HashMap::new();
     let mut ipv4_config: VariantMap = HashMap::new();
     let mut wireguard_config: VariantMap = HashMap::new();
@@ -147,6 +152,12 @@ fn convert_config_to_dbus(config: &Config) -> DeviceConfig {
             "public-key".into(),
             Variant(Box::new(peer.public_key.to_base64())),
         );
+        if let Some(interval) = config.persistent_keepalive() {
+            peer_config.insert(
+                "persistent-keepalive".into(),
+                Variant(Box::new(u32::from(interval))),
+            );
+        }
 
         peer_configs.push(peer_config);
     }
@@ -159,7 +170,7 @@ fn convert_config_to_dbus(config: &Config) -> DeviceConfig {
     );
     connection_config.insert(
         "interface-name".into(),
-        Variant(Box::new(MULLVAD_INTERFACE_NAME.to_string())),
+        Variant(Box::new(interface_name.to_string())),
     );
     connection_config.insert("autoconnect".into(), Variant(Box::new(true)));
 
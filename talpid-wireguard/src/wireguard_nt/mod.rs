@@ -1144,11 +1144,13 @@ mod tests {
             public_key: WG_PUBLIC_KEY.clone(),
             allowed_ips: vec!["1.3.3.0/24".parse().unwrap()],
             endpoint: "1.2.3.4:1234".parse().unwrap(),
+            alternate_endpoint: None,
             psk: None,
             #[cfg(target_os = "windows")]
             constant_packet_size: false,
         },
         exit_peer: None,
+        candidate_peer: None,
         ipv4_gateway: "0.0.0.0".parse().unwrap(),
         ipv6_gateway: None,
         mtu: 0,
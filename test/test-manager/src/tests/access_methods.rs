@@ -70,11 +70,14 @@ async fn assert_access_method_works(
     mut mullvad_client: MullvadProxyClient,
     access_method: impl Into<CustomProxy> + std::fmt::Debug,
 ) -> anyhow::Result<()> {
-    let successful = mullvad_client
+    let result = mullvad_client
         .test_custom_api_access_method(access_method.into())
         .await
         .context("Failed to test custom API access method")?;
 
-    ensure!(successful, "Failed while testing access method");
+    ensure!(
+        result.is_successful(),
+        "Failed while testing access method: {result:?}"
+    );
     Ok(())
 }
@@ -73,7 +73,7 @@ pub async fn test_too_many_devices(
         let pubkey = wireguard::PrivateKey::new_from_random().public_key();
 
         match device_client
-            .create(TEST_CONFIG.account_number.clone(), pubkey)
+            .create(TEST_CONFIG.account_number.clone().into(), pubkey)
             .await
         {
             Ok(_) => (),
@@ -155,7 +155,7 @@ pub async fn test_revoked_device(
         .await
         .context("Failed to create device client")?;
     retry_if_throttled(|| {
-        device_client.remove(TEST_CONFIG.account_number.clone(), device_id.clone())
+        device_client.remove(TEST_CONFIG.account_number.clone().into(), device_id.clone())
     })
     .await
     .expect("failed to revoke device");
@@ -210,7 +210,7 @@ pub async fn clear_devices(device_client: &DevicesProxy) -> anyhow::Result<()> {
 
     for dev in list_devices_with_retries(device_client).await?.into_iter() {
         if let Err(error) = device_client
-            .remove(TEST_CONFIG.account_number.clone(), dev.id)
+            .remove(TEST_CONFIG.account_number.clone().into(), dev.id)
             .await
         {
             log::warn!("Failed to remove device: {error}");
@@ -246,7 +246,7 @@ pub async fn new_device_client() -> anyhow::Result<DevicesProxy> {
 pub async fn list_devices_with_retries(
     device_client: &DevicesProxy,
 ) -> Result<Vec<Device>, mullvad_api::rest::Error> {
-    retry_if_throttled(|| device_client.list(TEST_CONFIG.account_number.clone())).await
+    retry_if_throttled(|| device_client.list(TEST_CONFIG.account_number.clone().into())).await
 }
 
 pub async fn retry_if_throttled<
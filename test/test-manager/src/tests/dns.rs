@@ -649,10 +649,12 @@ async fn connect_local_wg_relay(mullvad_client: &mut MullvadProxyClient) -> Resu
                 public_key: wireguard::PublicKey::from(CUSTOM_TUN_REMOTE_PUBKEY),
                 allowed_ips: vec!["0.0.0.0/0".parse().unwrap()],
                 endpoint: peer_addr,
+                alternate_endpoint: None,
                 psk: None,
             },
             ipv4_gateway: CUSTOM_TUN_GATEWAY,
             exit_peer: None,
+            candidate_peer: None,
             #[cfg(target_os = "linux")]
             fwmark: None,
             ipv6_gateway: None,
@@ -259,7 +259,7 @@ pub async fn login_with_retries(
 ) -> Result<(), mullvad_management_interface::Error> {
     loop {
         match mullvad_client
-            .login_account(TEST_CONFIG.account_number.clone())
+            .login_account(TEST_CONFIG.account_number.clone().into())
             .await
         {
             Err(mullvad_management_interface::Error::Rpc(status))
@@ -516,9 +516,11 @@ pub fn unreachable_wireguard_tunnel() -> talpid_types::net::wireguard::Connectio
                 "::0/0".parse().expect("Failed to parse ipv6 network"),
             ],
             endpoint: "1.3.3.7:1234".parse().unwrap(),
+            alternate_endpoint: None,
             psk: None,
         },
         exit_peer: None,
+        candidate_peer: None,
         ipv4_gateway: Ipv4Addr::new(10, 64, 10, 1),
         ipv6_gateway: None,
         #[cfg(target_os = "linux")]
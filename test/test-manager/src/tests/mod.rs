@@ -90,12 +90,15 @@ pub async fn cleanup_after_test(
         bridge_state,
         custom_lists,
         api_access_methods,
+        profiles,
         allow_lan,
         block_when_disconnected,
         auto_connect,
         tunnel_options,
         relay_overrides,
         show_beta_releases,
+        webhook,
+        connection_check,
         #[cfg(target_os = "macos")]
             split_tunnel: _,
         settings_version: _, // N/A
@@ -138,6 +141,17 @@ pub async fn cleanup_after_test(
         .await
         .context("Could not set show beta releases in cleanup")?;
 
+    let _ = webhook;
+    mullvad_client
+        .clear_webhook()
+        .await
+        .context("Could not clear webhook in cleanup")?;
+
+    mullvad_client
+        .set_connection_check_settings(connection_check)
+        .await
+        .context("Could not set connection check settings in cleanup")?;
+
     mullvad_client
         .set_bridge_state(bridge_state)
         .await
@@ -178,12 +192,28 @@ pub async fn cleanup_after_test(
         .await
         .context("Could not clear PQ options in cleanup")?;
 
+    mullvad_client
+        .set_connect_deadline(tunnel_options.connect_deadline)
+        .await
+        .context("Could not clear connect deadline in cleanup")?;
+
     let _ = custom_lists;
     mullvad_client
         .clear_custom_lists()
         .await
         .context("Could not remove custom list")?;
 
+    for profile in mullvad_client.get_settings().await?.profiles.iter() {
+        mullvad_client
+            .delete_profile(profile.name.clone())
+            .await
+            .context("Could not remove profile")?;
+    }
+    anyhow::ensure!(
+        mullvad_client.get_settings().await?.profiles == profiles,
+        "Profiles remain after cleanup"
+    );
+
     Ok(())
 }
 
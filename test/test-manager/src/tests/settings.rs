@@ -5,6 +5,7 @@ use super::{
 };
 
 use mullvad_management_interface::MullvadProxyClient;
+use mullvad_types::relay_constraints::{ObfuscationSettings, SelectedObfuscation};
 use std::net::SocketAddr;
 use test_macro::test_function;
 use test_rpc::ServiceClient;
@@ -186,3 +187,54 @@ pub async fn test_lockdown(
 
     Ok(())
 }
+
+/// Save the settings as a profile, change them and switch back to the profile. This test succeeds
+/// if the profile holds the settings that it was saved with, using it restores them, and deleting
+/// it removes it.
+#[test_function]
+pub async fn test_profiles(
+    _: TestContext,
+    _rpc: ServiceClient,
+    mut mullvad_client: MullvadProxyClient,
+) -> Result<(), Error> {
+    const PROFILE: &str = "udp2tcp";
+
+    let udp2tcp = ObfuscationSettings {
+        selected_obfuscation: SelectedObfuscation::Udp2Tcp,
+        ..Default::default()
+    };
+    mullvad_client
+        .set_obfuscation_settings(udp2tcp.clone())
+        .await?;
+    mullvad_client.save_profile(PROFILE.to_owned()).await?;
+
+    let settings = mullvad_client.get_settings().await?;
+    let profile = settings
+        .profiles
+        .get(PROFILE)
+        .expect("profile was not saved");
+    assert_eq!(profile.obfuscation_settings, udp2tcp);
+    assert_eq!(profile.relay_settings, settings.relay_settings);
+    assert_eq!(profile.dns_options, settings.tunnel_options.dns_options);
+
+    mullvad_client
+        .set_obfuscation_settings(ObfuscationSettings {
+            selected_obfuscation: SelectedObfuscation::Off,
+            ..Default::default()
+        })
+        .await?;
+    mullvad_client.use_profile(PROFILE.to_owned()).await?;
+    assert_eq!(
+        mullvad_client.get_settings().await?.obfuscation_settings,
+        udp2tcp,
+        "using the profile did not restore its settings"
+    );
+
+    mullvad_client.delete_profile(PROFILE.to_owned()).await?;
+    assert!(
+        mullvad_client.get_settings().await?.profiles.is_empty(),
+        "the profile was not deleted"
+    );
+
+    Ok(())
+}
@@ -151,6 +151,7 @@ pub async fn test_udp2tcp_tunnel(
             udp2tcp: Udp2TcpObfuscationSettings {
                 port: Constraint::Any,
             },
+            ..Default::default()
         })
         .await
         .expect("failed to enable udp2tcp");
@@ -546,6 +547,7 @@ pub async fn test_quantum_resistant_multihop_udp2tcp_tunnel(
             udp2tcp: Udp2TcpObfuscationSettings {
                 port: Constraint::Any,
             },
+            ..Default::default()
         })
         .await
         .expect("Failed to enable obfuscation");
@@ -592,6 +594,7 @@ pub async fn test_remote_socks_bridge(
                 crate::vm::network::NON_TUN_GATEWAY,
                 crate::vm::network::SOCKS5_PORT,
             )))),
+            custom_chain: vec![],
         })
         .await
         .expect("failed to update bridge settings");
@@ -692,6 +695,7 @@ pub async fn test_local_socks_bridge(
                     TransportProtocol::Tcp,
                 ),
             )),
+            custom_chain: vec![],
         })
         .await
         .expect("failed to update bridge settings");
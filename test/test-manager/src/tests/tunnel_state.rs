@@ -369,6 +369,8 @@ pub async fn test_connected_state(
                     obfuscation: None,
                     entry_endpoint: None,
                     tunnel_interface: _,
+                    ephemeral_peer_transport: _,
+                    data_channel_offload: _,
                 },
             ..
         } => {
@@ -191,7 +191,7 @@ async fn test_custom_access_methods_gui(
             ("SHADOWSOCKS_SERVER_CIPHER", access_method.cipher.as_ref()),
             (
                 "SHADOWSOCKS_SERVER_PASSWORD",
-                access_method.password.as_ref(),
+                access_method.password.expose_str(),
             ),
         ],
     )
@@ -255,7 +255,7 @@ async fn test_custom_bridge_gui(
             ("SHADOWSOCKS_SERVER_CIPHER", custom_proxy.cipher.as_ref()),
             (
                 "SHADOWSOCKS_SERVER_PASSWORD",
-                custom_proxy.password.as_ref(),
+                custom_proxy.password.expose_str(),
             ),
         ],
     )
@@ -1,8 +1,12 @@
 use async_trait::async_trait;
 use std::net::SocketAddr;
 
+mod stats;
+mod tls;
 mod udp2tcp;
-pub use udp2tcp::Udp2TcpSettings;
+pub use stats::{byte_counts, ByteCounts};
+pub use tls::TlsSettings;
+pub use udp2tcp::{Udp2TcpSettings, Udp2TcpTuning};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -13,6 +17,12 @@ pub enum Error {
 
     #[error("Failed to run Udp2Tcp obfuscator")]
     RunUdp2TcpObfuscator(#[source] udp2tcp::Error),
+
+    #[error("Failed to create TLS obfuscator")]
+    CreateTlsObfuscator(#[source] tls::Error),
+
+    #[error("Failed to run TLS obfuscator")]
+    RunTlsObfuscator(#[source] tls::Error),
 }
 
 #[async_trait]
@@ -29,6 +39,7 @@ pub trait Obfuscator: Send {
 
 pub enum Settings {
     Udp2Tcp(Udp2TcpSettings),
+    Tls(TlsSettings),
 }
 
 pub async fn create_obfuscator(settings: &Settings) -> Result<Box<dyn Obfuscator>> {
@@ -36,5 +47,8 @@ pub async fn create_obfuscator(settings: &Settings) -> Result<Box<dyn Obfuscator
         Settings::Udp2Tcp(s) => udp2tcp::create_obfuscator(s)
             .await
             .map_err(Error::CreateUdp2TcpObfuscator),
+        Settings::Tls(s) => tls::create_obfuscator(s)
+            .await
+            .map_err(Error::CreateTlsObfuscator),
     }
 }
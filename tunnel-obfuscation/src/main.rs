@@ -1,5 +1,8 @@
 use std::{env::args, net::SocketAddr};
-use tunnel_obfuscation::{create_obfuscator, Obfuscator, Settings, Udp2TcpSettings};
+use talpid_net::SocketProtection;
+use tunnel_obfuscation::{
+    create_obfuscator, Obfuscator, Settings, TlsSettings, Udp2TcpSettings, Udp2TcpTuning,
+};
 
 #[tokio::main]
 async fn main() {
@@ -21,16 +24,39 @@ async fn instantiate_requested(obfuscator_type: &str) -> Box<dyn Obfuscator> {
         "udp2tcp" => {
             let settings = Udp2TcpSettings {
                 peer: SocketAddr::new("127.0.0.1".parse().unwrap(), 3030),
-                #[cfg(target_os = "linux")]
-                fwmark: Some(1337),
+                protection: protection(),
+                tuning: Udp2TcpTuning::default(),
             };
 
             create_obfuscator(&Settings::Udp2Tcp(settings))
                 .await
                 .expect("Creating obfuscator failed")
         }
+        "tls" => {
+            let settings = TlsSettings {
+                peer: SocketAddr::new("127.0.0.1".parse().unwrap(), 443),
+                sni: "www.example.com".to_owned(),
+                // Digest of the certificate of the local test server
+                certificate_sha256: [0; 32],
+                protection: protection(),
+            };
+
+            create_obfuscator(&Settings::Tls(settings))
+                .await
+                .expect("Creating obfuscator failed")
+        }
         _ => {
             unimplemented!()
         }
     }
 }
+
+#[cfg(target_os = "linux")]
+fn protection() -> SocketProtection {
+    SocketProtection::with_fwmark(Some(1337))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn protection() -> SocketProtection {
+    SocketProtection::default()
+}
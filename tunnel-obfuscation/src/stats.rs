@@ -0,0 +1,150 @@
+//! Byte counters of the obfuscators. The WireGuard datagrams that pass through an obfuscator are
+//! counted as payload, and the bytes of the stream that carries them to the relay are counted as
+//! wire bytes, so that the overhead of each kind of obfuscation can be worked out. Headers of the
+//! underlying TCP and IP packets are not included.
+//!
+//! The counters are shared by every obfuscator of the same kind in the process, and only ever
+//! increase.
+
+use std::{
+    io,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+pub(crate) static UDP2TCP: Counters = Counters::new();
+pub(crate) static TLS: Counters = Counters::new();
+
+/// Bytes carried by the obfuscators of one kind.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ByteCounts {
+    pub payload_rx: u64,
+    pub payload_tx: u64,
+    pub wire_rx: u64,
+    pub wire_tx: u64,
+}
+
+/// Returns the bytes carried by each kind of obfuscator since the process started, along with the
+/// name of the kind.
+pub fn byte_counts() -> [(&'static str, ByteCounts); 2] {
+    [("udp2tcp", UDP2TCP.get()), ("tls", TLS.get())]
+}
+
+pub(crate) struct Counters {
+    payload_rx: AtomicU64,
+    payload_tx: AtomicU64,
+    wire_rx: AtomicU64,
+    wire_tx: AtomicU64,
+}
+
+impl Counters {
+    const fn new() -> Self {
+        Self {
+            payload_rx: AtomicU64::new(0),
+            payload_tx: AtomicU64::new(0),
+            wire_rx: AtomicU64::new(0),
+            wire_tx: AtomicU64::new(0),
+        }
+    }
+
+    fn get(&self) -> ByteCounts {
+        ByteCounts {
+            payload_rx: self.payload_rx.load(Ordering::Relaxed),
+            payload_tx: self.payload_tx.load(Ordering::Relaxed),
+            wire_rx: self.wire_rx.load(Ordering::Relaxed),
+            wire_tx: self.wire_tx.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn add_payload_rx(&self, bytes: usize) {
+        self.payload_rx.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_payload_tx(&self, bytes: usize) {
+        self.payload_tx.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_wire_rx(&self, bytes: usize) {
+        self.wire_rx.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_wire_tx(&self, bytes: usize) {
+        self.wire_tx.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+/// Counts the bytes that are read from and written to `inner` as wire bytes.
+pub(crate) struct CountingStream<S> {
+    inner: S,
+    counters: &'static Counters,
+}
+
+impl<S> CountingStream<S> {
+    pub(crate) fn new(inner: S, counters: &'static Counters) -> Self {
+        Self { inner, counters }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for CountingStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        self.counters.add_wire_rx(buf.filled().len() - filled);
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for CountingStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = result {
+            self.counters.add_wire_tx(written);
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_counting_stream() {
+        static COUNTERS: Counters = Counters::new();
+        let (client, mut server) = tokio::io::duplex(64);
+        let mut client = CountingStream::new(client, &COUNTERS);
+
+        client.write_all(b"hello").await.unwrap();
+        server.write_all(b"hi").await.unwrap();
+        let mut received = [0u8; 2];
+        client.read_exact(&mut received).await.unwrap();
+
+        assert_eq!(
+            COUNTERS.get(),
+            ByteCounts {
+                wire_rx: 2,
+                wire_tx: 5,
+                ..ByteCounts::default()
+            }
+        );
+    }
+}
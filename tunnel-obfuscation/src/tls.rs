@@ -0,0 +1,267 @@
+//! Wraps WireGuard traffic in a TLS 1.3 session, so that the tunnel looks like HTTPS to anything
+//! inspecting it. Each datagram is sent as a 16-bit big-endian length followed by the datagram,
+//! which is the same framing as used by udp2tcp.
+//!
+//! The server is authenticated by pinning its certificate, whose digest is published in the relay
+//! list. The certificate is not checked against the server name, since the server name is only
+//! there to blend in with other traffic and will usually not match the certificate.
+
+use crate::{
+    stats::{self, CountingStream},
+    Obfuscator,
+};
+use async_trait::async_trait;
+use ring::digest;
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    Certificate, ClientConfig, ServerName,
+};
+use std::{
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::SystemTime,
+};
+use talpid_net::SocketProtection;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpSocket, UdpSocket},
+};
+use tokio_rustls::TlsConnector;
+
+/// Protocols offered in the handshake. These are the ones a browser would offer to an HTTPS
+/// server.
+const ALPN_PROTOCOLS: &[&[u8]] = &[b"h2", b"http/1.1"];
+
+/// Size of the header that precedes each datagram in the TLS stream.
+const HEADER_SIZE: usize = 2;
+const MAX_DATAGRAM_SIZE: usize = u16::MAX as usize;
+
+pub struct TlsSettings {
+    pub peer: SocketAddr,
+    /// Server name to send in the TLS handshake.
+    pub sni: String,
+    /// SHA-256 digest of the DER encoded certificate that the server must present.
+    pub certificate_sha256: [u8; 32],
+    /// How to keep the connection to the server outside of the tunnel.
+    pub protection: SocketProtection,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    /// The server name is not a valid DNS name
+    #[error("Invalid server name: {0}")]
+    InvalidServerName(String),
+
+    /// Failed to create TLS client configuration
+    #[error("Failed to create TLS client configuration")]
+    CreateTlsConfig(#[source] rustls::Error),
+
+    /// Failed to bind the local UDP socket
+    #[error("Failed to bind local UDP socket")]
+    BindUdpSocket(#[source] io::Error),
+
+    /// Failed to create the TCP socket
+    #[error("Failed to create TCP socket")]
+    CreateTcpSocket(#[source] io::Error),
+
+    /// Failed to keep the TCP socket outside of the tunnel
+    #[error("Failed to protect TCP socket")]
+    ProtectTcpSocket(#[source] io::Error),
+
+    /// Failed to connect to the server
+    #[error("Failed to connect to server")]
+    Connect(#[source] io::Error),
+
+    /// The TLS handshake failed
+    #[error("TLS handshake failed")]
+    Handshake(#[source] io::Error),
+
+    /// Failed to forward traffic between WireGuard and the server
+    #[error("Failed to forward traffic")]
+    Forward(#[source] io::Error),
+}
+
+struct Tls {
+    local_addr: SocketAddr,
+    udp_socket: UdpSocket,
+    tcp_socket: TcpSocket,
+    peer: SocketAddr,
+    server_name: ServerName,
+    config: Arc<ClientConfig>,
+}
+
+impl Tls {
+    pub async fn new(settings: &TlsSettings) -> Result<Self> {
+        let server_name = ServerName::try_from(settings.sni.as_str())
+            .map_err(|_| Error::InvalidServerName(settings.sni.clone()))?;
+
+        let (listen_addr, tcp_socket) = if settings.peer.is_ipv4() {
+            (
+                SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0),
+                TcpSocket::new_v4(),
+            )
+        } else {
+            (
+                SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 0),
+                TcpSocket::new_v6(),
+            )
+        };
+        let tcp_socket = tcp_socket.map_err(Error::CreateTcpSocket)?;
+        settings
+            .protection
+            .protect(&tcp_socket)
+            .await
+            .map_err(Error::ProtectTcpSocket)?;
+
+        let udp_socket = UdpSocket::bind(listen_addr)
+            .await
+            .map_err(Error::BindUdpSocket)?;
+        let local_addr = udp_socket.local_addr().map_err(Error::BindUdpSocket)?;
+
+        Ok(Self {
+            local_addr,
+            udp_socket,
+            tcp_socket,
+            peer: settings.peer,
+            server_name,
+            config: tls_config(settings.certificate_sha256)?,
+        })
+    }
+
+    async fn forward(self) -> Result<()> {
+        let stream = self
+            .tcp_socket
+            .connect(self.peer)
+            .await
+            .map_err(Error::Connect)?;
+        // Disables the Nagle algorithm on the TCP socket. Improves performance
+        stream.set_nodelay(true).map_err(Error::Connect)?;
+        let stream = TlsConnector::from(self.config)
+            .connect(self.server_name, CountingStream::new(stream, &stats::TLS))
+            .await
+            .map_err(Error::Handshake)?;
+        let (tls_read, mut tls_write) = tokio::io::split(stream);
+
+        // The first datagram reveals the address of WireGuard, which is where traffic from the
+        // server is sent.
+        let mut frame = vec![0u8; HEADER_SIZE + MAX_DATAGRAM_SIZE];
+        let (len, wireguard_addr) = self
+            .udp_socket
+            .recv_from(&mut frame[HEADER_SIZE..])
+            .await
+            .map_err(Error::Forward)?;
+        self.udp_socket
+            .connect(wireguard_addr)
+            .await
+            .map_err(Error::Forward)?;
+        stats::TLS.add_payload_tx(len);
+        write_frame(&mut tls_write, &mut frame, len)
+            .await
+            .map_err(Error::Forward)?;
+
+        let udp_socket = Arc::new(self.udp_socket);
+        let result = tokio::select! {
+            result = udp_to_tls(&udp_socket, tls_write, frame) => result,
+            result = tls_to_udp(tls_read, &udp_socket) => result,
+        };
+        result.map_err(Error::Forward)
+    }
+}
+
+#[async_trait]
+impl Obfuscator for Tls {
+    fn endpoint(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    async fn run(self: Box<Self>) -> crate::Result<()> {
+        self.forward().await.map_err(crate::Error::RunTlsObfuscator)
+    }
+
+    #[cfg(target_os = "android")]
+    fn remote_socket_fd(&self) -> std::os::unix::io::RawFd {
+        use std::os::unix::io::AsRawFd;
+        self.tcp_socket.as_raw_fd()
+    }
+}
+
+/// Sends the `len` bytes after the header in `frame` as one frame.
+async fn write_frame(
+    stream: &mut (impl AsyncWrite + Unpin),
+    frame: &mut [u8],
+    len: usize,
+) -> io::Result<()> {
+    frame[..HEADER_SIZE].copy_from_slice(&(len as u16).to_be_bytes());
+    stream.write_all(&frame[..HEADER_SIZE + len]).await?;
+    stream.flush().await
+}
+
+async fn udp_to_tls(
+    udp_socket: &UdpSocket,
+    mut tls_write: impl AsyncWrite + Unpin,
+    mut frame: Vec<u8>,
+) -> io::Result<()> {
+    loop {
+        let len = udp_socket.recv(&mut frame[HEADER_SIZE..]).await?;
+        stats::TLS.add_payload_tx(len);
+        write_frame(&mut tls_write, &mut frame, len).await?;
+    }
+}
+
+async fn tls_to_udp(
+    mut tls_read: impl AsyncRead + Unpin,
+    udp_socket: &UdpSocket,
+) -> io::Result<()> {
+    let mut datagram = vec![0u8; MAX_DATAGRAM_SIZE];
+    loop {
+        let len = usize::from(tls_read.read_u16().await?);
+        tls_read.read_exact(&mut datagram[..len]).await?;
+        stats::TLS.add_payload_rx(len);
+        udp_socket.send(&datagram[..len]).await?;
+    }
+}
+
+fn tls_config(certificate_sha256: [u8; 32]) -> Result<Arc<ClientConfig>> {
+    let mut config = ClientConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .map_err(Error::CreateTlsConfig)?
+        .with_custom_certificate_verifier(Arc::new(PinnedCertificate(certificate_sha256)))
+        .with_no_client_auth();
+    config.alpn_protocols = ALPN_PROTOCOLS
+        .iter()
+        .map(|protocol| protocol.to_vec())
+        .collect();
+    Ok(Arc::new(config))
+}
+
+/// Accepts only the server certificate with the given SHA-256 digest.
+struct PinnedCertificate([u8; 32]);
+
+impl ServerCertVerifier for PinnedCertificate {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        if digest::digest(&digest::SHA256, &end_entity.0).as_ref() == self.0 {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(
+                rustls::CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+}
+
+pub async fn create_obfuscator(settings: &TlsSettings) -> Result<Box<dyn Obfuscator>> {
+    Ok(Box::new(Tls::new(settings).await?))
+}
@@ -1,15 +1,55 @@
-use crate::Obfuscator;
+//! Runs udp2tcp behind a local UDP relay. WireGuard sends its datagrams to the relay, which
+//! forwards them to udp2tcp and counts them, since udp2tcp does not report how much traffic it
+//! carries.
+
+use crate::{stats, Obfuscator};
 use async_trait::async_trait;
-use std::net::SocketAddr;
+use std::{io, net::SocketAddr};
+use talpid_net::SocketProtection;
+use tokio::net::UdpSocket;
 use udp_over_tcp::{
     udp2tcp::{self, Udp2Tcp as Udp2TcpImpl},
     TcpOptions,
 };
 
+/// Size of the header that udp2tcp adds to each datagram in the TCP stream.
+const HEADER_SIZE: usize = 2;
+const MAX_DATAGRAM_SIZE: usize = u16::MAX as usize;
+
 pub struct Udp2TcpSettings {
     pub peer: SocketAddr,
+    /// How to keep the connection to the server outside of the tunnel. The TCP socket is created
+    /// by udp2tcp, so only the firewall mark is applied.
+    pub protection: SocketProtection,
+    pub tuning: Udp2TcpTuning,
+}
+
+/// Options of the TCP connection to the server. Which ones work best depends on the link, in
+/// particular on its latency and loss.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Udp2TcpTuning {
+    /// Disable the Nagle algorithm, so that datagrams are sent right away.
+    pub nodelay: bool,
+    /// Congestion control algorithm, such as `bbr` or `cubic`. `None` uses the system default.
     #[cfg(target_os = "linux")]
-    pub fwmark: Option<u32>,
+    pub congestion: Option<String>,
+    /// Size of the receive buffer of the socket, in bytes. `None` uses the system default.
+    pub recv_buffer_size: Option<u32>,
+    /// Size of the send buffer of the socket, in bytes. `None` uses the system default.
+    pub send_buffer_size: Option<u32>,
+}
+
+impl Default for Udp2TcpTuning {
+    fn default() -> Self {
+        Self {
+            // Improves performance, since WireGuard does its own batching
+            nodelay: true,
+            #[cfg(target_os = "linux")]
+            congestion: None,
+            recv_buffer_size: None,
+            send_buffer_size: None,
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -20,17 +60,34 @@ pub enum Error {
     #[error("Failed to create obfuscator")]
     CreateObfuscator(#[source] udp2tcp::Error),
 
+    /// Failed to set the congestion control algorithm of the TCP socket
+    #[cfg(target_os = "linux")]
+    #[error("Failed to set congestion control algorithm \"{0}\"")]
+    SetCongestion(String, #[source] io::Error),
+
     /// Failed to determine UDP socket details
     #[error("Failed to determine UDP socket details")]
     GetUdpSocketDetails(#[source] std::io::Error),
 
+    /// Failed to bind the sockets of the relay
+    #[error("Failed to bind relay socket")]
+    BindRelaySocket(#[source] io::Error),
+
     /// Failed to run obfuscator
     #[error("Failed to run obfuscator")]
     RunObfuscator(#[source] udp2tcp::Error),
+
+    /// Failed to relay traffic between WireGuard and udp2tcp
+    #[error("Failed to relay traffic")]
+    Relay(#[source] io::Error),
 }
 
 struct Udp2Tcp {
     local_addr: SocketAddr,
+    /// Socket that WireGuard sends its datagrams to.
+    wireguard_socket: UdpSocket,
+    /// Socket that the datagrams are forwarded to udp2tcp from.
+    udp2tcp_socket: UdpSocket,
     instance: Udp2TcpImpl,
 }
 
@@ -46,26 +103,105 @@ impl Udp2Tcp {
             listen_addr,
             settings.peer,
             TcpOptions {
+                // The socket is created by udp2tcp, so it can only be given the mark. Routes keep
+                // it on the right interface instead.
                 #[cfg(target_os = "linux")]
-                fwmark: settings.fwmark,
-                // Disables the Nagle algorithm on the TCP socket. Improves performance
-                nodelay: true,
+                fwmark: settings.protection.fwmark(),
+                nodelay: settings.tuning.nodelay,
+                recv_buffer_size: settings.tuning.recv_buffer_size,
+                send_buffer_size: settings.tuning.send_buffer_size,
                 ..TcpOptions::default()
             },
         )
         .await
         .map_err(Error::CreateObfuscator)?;
-        let local_addr = instance
+        // The socket is not connected until the first datagram is sent, so this applies from the
+        // start of the connection
+        #[cfg(target_os = "linux")]
+        if let Some(congestion) = &settings.tuning.congestion {
+            set_congestion(&instance, congestion)
+                .map_err(|error| Error::SetCongestion(congestion.clone(), error))?;
+        }
+        let udp2tcp_addr = instance
             .local_udp_addr()
             .map_err(Error::GetUdpSocketDetails)?;
 
+        let wireguard_socket = UdpSocket::bind(listen_addr)
+            .await
+            .map_err(Error::BindRelaySocket)?;
+        let local_addr = wireguard_socket
+            .local_addr()
+            .map_err(Error::BindRelaySocket)?;
+        let udp2tcp_socket = UdpSocket::bind(listen_addr)
+            .await
+            .map_err(Error::BindRelaySocket)?;
+        udp2tcp_socket
+            .connect(udp2tcp_addr)
+            .await
+            .map_err(Error::BindRelaySocket)?;
+
         Ok(Self {
             local_addr,
+            wireguard_socket,
+            udp2tcp_socket,
             instance,
         })
     }
 }
 
+#[cfg(target_os = "linux")]
+fn set_congestion(instance: &Udp2TcpImpl, congestion: &str) -> io::Result<()> {
+    use std::os::fd::BorrowedFd;
+
+    // SAFETY: The socket is owned by `instance`, which outlives the borrow
+    let socket = unsafe { BorrowedFd::borrow_raw(instance.remote_tcp_fd()) };
+    socket2::SockRef::from(&socket).set_tcp_congestion(congestion.as_bytes())
+}
+
+/// Forwards datagrams between WireGuard and udp2tcp until either side fails.
+async fn relay(wireguard_socket: UdpSocket, udp2tcp_socket: UdpSocket) -> io::Result<()> {
+    // The first datagram reveals the address of WireGuard, which is where traffic from udp2tcp
+    // is sent.
+    let mut datagram = vec![0u8; MAX_DATAGRAM_SIZE];
+    let (len, wireguard_addr) = wireguard_socket.recv_from(&mut datagram).await?;
+    wireguard_socket.connect(wireguard_addr).await?;
+    count_tx(len);
+    udp2tcp_socket.send(&datagram[..len]).await?;
+
+    tokio::select! {
+        result = forward(&wireguard_socket, &udp2tcp_socket, datagram, count_tx) => result,
+        result = forward(
+            &udp2tcp_socket,
+            &wireguard_socket,
+            vec![0u8; MAX_DATAGRAM_SIZE],
+            count_rx,
+        ) => result,
+    }
+}
+
+async fn forward(
+    from: &UdpSocket,
+    to: &UdpSocket,
+    mut datagram: Vec<u8>,
+    count: fn(usize),
+) -> io::Result<()> {
+    loop {
+        let len = from.recv(&mut datagram).await?;
+        count(len);
+        to.send(&datagram[..len]).await?;
+    }
+}
+
+fn count_tx(len: usize) {
+    stats::UDP2TCP.add_payload_tx(len);
+    stats::UDP2TCP.add_wire_tx(HEADER_SIZE + len);
+}
+
+fn count_rx(len: usize) {
+    stats::UDP2TCP.add_payload_rx(len);
+    stats::UDP2TCP.add_wire_rx(HEADER_SIZE + len);
+}
+
 #[async_trait]
 impl Obfuscator for Udp2Tcp {
     fn endpoint(&self) -> SocketAddr {
@@ -73,11 +209,19 @@ impl Obfuscator for Udp2Tcp {
     }
 
     async fn run(self: Box<Self>) -> crate::Result<()> {
-        self.instance
-            .run()
-            .await
-            .map_err(Error::RunObfuscator)
-            .map_err(crate::Error::RunUdp2TcpObfuscator)
+        let Self {
+            wireguard_socket,
+            udp2tcp_socket,
+            instance,
+            ..
+        } = *self;
+        let result = tokio::select! {
+            result = instance.run() => result.map_err(Error::RunObfuscator),
+            result = relay(wireguard_socket, udp2tcp_socket) => {
+                result.map_err(Error::Relay)
+            }
+        };
+        result.map_err(crate::Error::RunUdp2TcpObfuscator)
     }
 
     #[cfg(target_os = "android")]
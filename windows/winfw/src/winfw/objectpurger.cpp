@@ -5,7 +5,12 @@
 #include "libwfp/filterengine.h"
 #include "libwfp/objectdeleter.h"
 #include "libwfp/transaction.h"
+#include <libcommon/error.h>
+#include <libcommon/memory.h>
+#include <fwpmu.h>
 #include <algorithm>
+#include <unordered_set>
+#include <vector>
 
 namespace
 {
@@ -22,6 +27,8 @@ void RemoveRange(wfp::FilterEngine &engine, ObjectDeleter deleter, TRange range)
 	});
 }
 
+constexpr UINT32 FILTER_ENUM_BATCH_SIZE = 100;
+
 } // anonymous namespace
 
 //static
@@ -82,3 +89,86 @@ bool ObjectPurger::Execute(RemovalFunctor f)
 
 	return wfp::Transaction::Execute(*engine, wrapper);
 }
+
+//static
+size_t ObjectPurger::RemoveStaleFilters(wfp::FilterEngine &engine)
+{
+	//
+	// Older versions that crashed may have left filters behind that this version
+	// does not know about. These prevent our sublayers and providers from being
+	// removed, so they are identified by their provider rather than by their identity.
+	//
+
+	const auto registry = MullvadGuids::DetailedRegistry(MullvadGuids::IdentityQualifier::IncludeAll);
+	const auto knownIdentities = MullvadGuids::Registry(MullvadGuids::IdentityQualifier::IncludeAll);
+
+	std::unordered_set<GUID> providers;
+	const auto providerRange = registry.equal_range(WfpObjectType::Provider);
+
+	std::for_each(providerRange.first, providerRange.second, [&](const auto &record)
+	{
+		providers.insert(record.second);
+	});
+
+	HANDLE enumHandle = nullptr;
+
+	auto status = FwpmFilterCreateEnumHandle0(engine.session(), nullptr, &enumHandle);
+
+	if (ERROR_SUCCESS != status)
+	{
+		THROW_WINDOWS_ERROR(status, "FwpmFilterCreateEnumHandle0");
+	}
+
+	common::memory::ScopeDestructor dtor;
+
+	dtor += [&engine, enumHandle]()
+	{
+		FwpmFilterDestroyEnumHandle0(engine.session(), enumHandle);
+	};
+
+	std::vector<UINT64> staleFilters;
+
+	for (;;)
+	{
+		FWPM_FILTER0 **filters = nullptr;
+		UINT32 numFilters = 0;
+
+		status = FwpmFilterEnum0(engine.session(), enumHandle, FILTER_ENUM_BATCH_SIZE, &filters, &numFilters);
+
+		if (ERROR_SUCCESS != status)
+		{
+			THROW_WINDOWS_ERROR(status, "FwpmFilterEnum0");
+		}
+
+		for (UINT32 i = 0; i < numFilters; ++i)
+		{
+			const auto filter = filters[i];
+
+			if (nullptr != filter->providerKey
+				&& 0 != providers.count(*filter->providerKey)
+				&& 0 == knownIdentities.count(filter->filterKey))
+			{
+				staleFilters.push_back(filter->filterId);
+			}
+		}
+
+		FwpmFreeMemory0(reinterpret_cast<void **>(&filters));
+
+		if (numFilters < FILTER_ENUM_BATCH_SIZE)
+		{
+			break;
+		}
+	}
+
+	for (const auto filterId : staleFilters)
+	{
+		status = FwpmFilterDeleteById0(engine.session(), filterId);
+
+		if (ERROR_SUCCESS != status && FWP_E_FILTER_NOT_FOUND != status)
+		{
+			THROW_WINDOWS_ERROR(status, "FwpmFilterDeleteById0");
+		}
+	}
+
+	return staleFilters.size();
+}
@@ -18,4 +18,10 @@ public:
 	static RemovalFunctor GetRemoveNonPersistentFunctor();
 
 	static bool Execute(RemovalFunctor f);
+
+	//
+	// Remove filters that belong to one of our providers, but whose identity is unknown
+	// to this version. Returns the number of removed filters.
+	//
+	static size_t RemoveStaleFilters(wfp::FilterEngine &engine);
 };
@@ -518,3 +518,50 @@ WinFw_Reset()
 		return WINFW_POLICY_STATUS_GENERAL_FAILURE;
 	}
 }
+
+WINFW_LINKAGE
+WINFW_POLICY_STATUS
+WINFW_API
+WinFw_RemoveStaleFilters(uint32_t *numRemoved)
+{
+	try
+	{
+		if (nullptr == numRemoved)
+		{
+			THROW_ERROR("Invalid argument: numRemoved");
+		}
+
+		size_t removed = 0;
+
+		const auto status = ObjectPurger::Execute([&removed](wfp::FilterEngine &engine)
+		{
+			removed = ObjectPurger::RemoveStaleFilters(engine);
+		});
+
+		if (false == status)
+		{
+			return WINFW_POLICY_STATUS_GENERAL_FAILURE;
+		}
+
+		*numRemoved = static_cast<uint32_t>(removed);
+
+		return WINFW_POLICY_STATUS_SUCCESS;
+	}
+	catch (common::error::WindowsException &err)
+	{
+		return HandlePolicyException(err);
+	}
+	catch (std::exception &err)
+	{
+		if (nullptr != g_logSink)
+		{
+			g_logSink(MULLVAD_LOG_LEVEL_ERROR, err.what(), g_logSinkContext);
+		}
+
+		return WINFW_POLICY_STATUS_GENERAL_FAILURE;
+	}
+	catch (...)
+	{
+		return WINFW_POLICY_STATUS_GENERAL_FAILURE;
+	}
+}
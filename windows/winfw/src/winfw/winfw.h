@@ -229,3 +229,20 @@ WINFW_LINKAGE
 WINFW_POLICY_STATUS
 WINFW_API
 WinFw_Reset();
+
+//
+// RemoveStaleFilters:
+//
+// Remove filters that were registered by an older version of WINFW, and that
+// this version does not recognize. These may be left behind if the older version
+// crashed. This may be called whether or not WINFW is initialized.
+//
+// The number of removed filters is written to numRemoved.
+//
+extern "C"
+WINFW_LINKAGE
+WINFW_POLICY_STATUS
+WINFW_API
+WinFw_RemoveStaleFilters(
+	uint32_t *numRemoved
+);
@@ -0,0 +1,15 @@
+/* SPDX-License-Identifier: Apache-2.0
+ *
+ * Copyright (C) 2024 Mullvad VPN AB. All Rights Reserved.
+ */
+
+package main
+
+import (
+	"golang.zx2c4.com/wireguard/conn"
+	"golang.zx2c4.com/wireguard/device"
+)
+
+func newBind(logger *device.Logger) conn.Bind {
+	return conn.NewDefaultBind()
+}
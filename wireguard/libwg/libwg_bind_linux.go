@@ -0,0 +1,20 @@
+// +build linux
+// +build !android
+
+/* SPDX-License-Identifier: Apache-2.0
+ *
+ * Copyright (C) 2024 Mullvad VPN AB. All Rights Reserved.
+ */
+
+package main
+
+import (
+	"golang.zx2c4.com/wireguard/conn"
+	"golang.zx2c4.com/wireguard/device"
+
+	"github.com/mullvad/mullvadvpn-app/wireguard/libwg/offload"
+)
+
+func newBind(logger *device.Logger) conn.Bind {
+	return offload.NewBind(logger)
+}
@@ -17,7 +17,6 @@ import (
 	"strings"
 	"unsafe"
 
-	"golang.zx2c4.com/wireguard/conn"
 	"golang.zx2c4.com/wireguard/device"
 	"golang.zx2c4.com/wireguard/tun"
 
@@ -41,17 +40,31 @@ func wgTurnOn(mtu int, cSettings *C.char, fd int, logSink LogSink, logContext Lo
 	}
 	settings := C.GoString(cSettings)
 
+	tunDevice, status := createTUNFromFd(fd, mtu, logger)
+	if tunDevice == nil {
+		return status
+	}
+
+	return startTunnel(tunDevice, settings, logger)
+}
+
+// createTUNFromFd takes ownership of fd. On failure, the returned status is the error code to
+// return from the FFI function.
+func createTUNFromFd(fd int, mtu int, logger *device.Logger) (tun.Device, int32) {
 	file := os.NewFile(uintptr(fd), "")
 	tunDevice, err := tun.CreateTUNFromFile(file, mtu)
 	if err != nil {
 		logger.Errorf("%s\n", err)
 		if err.Error() == "bad file descriptor" {
-			return ERROR_INTERMITTENT_FAILURE
+			return nil, ERROR_INTERMITTENT_FAILURE
 		}
-		return ERROR_GENERAL_FAILURE
+		return nil, ERROR_GENERAL_FAILURE
 	}
+	return tunDevice, 0
+}
 
-	device := device.NewDevice(tunDevice, conn.NewDefaultBind(), logger)
+func startTunnel(tunDevice tun.Device, settings string, logger *device.Logger) int32 {
+	device := device.NewDevice(tunDevice, newBind(logger), logger)
 
 	setErr := device.IpcSetOperation(bufio.NewReader(strings.NewReader(settings)))
 	if setErr != nil {
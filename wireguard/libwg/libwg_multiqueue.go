@@ -0,0 +1,206 @@
+// +build linux
+// +build !android
+
+/* SPDX-License-Identifier: Apache-2.0
+ *
+ * Copyright (C) 2024 Mullvad VPN AB. All Rights Reserved.
+ */
+
+package main
+
+// #include <stdlib.h>
+import "C"
+import (
+	"os"
+	"runtime"
+	"sync"
+	"unsafe"
+
+	"golang.org/x/sys/unix"
+
+	"golang.zx2c4.com/wireguard/device"
+	"golang.zx2c4.com/wireguard/tun"
+
+	"github.com/mullvad/mullvadvpn-app/wireguard/libwg/logging"
+)
+
+// wgTurnOnMultiQueue is like wgTurnOn, but takes one file descriptor for every queue of a
+// multi-queue tunnel device. Each queue is read on its own thread, which is pinned to one of the
+// given CPUs in turn. If threads is positive, it limits the number of threads that run Go code at
+// once.
+//
+//export wgTurnOnMultiQueue
+func wgTurnOnMultiQueue(mtu int, cSettings *C.char, cFds *C.int, fdCount int, threads int, cCpus *C.int, cpuCount int, logSink LogSink, logContext LogContext) int32 {
+
+	logger := logging.NewLogger(logSink, logContext)
+
+	if cSettings == nil || fdCount < 1 {
+		logger.Errorf("cSettings is null or no file descriptors were given\n")
+		return ERROR_GENERAL_FAILURE
+	}
+	settings := C.GoString(cSettings)
+	fds := unsafe.Slice(cFds, fdCount)
+
+	if threads > 0 {
+		runtime.GOMAXPROCS(threads)
+	}
+
+	var cpus []int
+	if cpuCount > 0 {
+		for _, cpu := range unsafe.Slice(cCpus, cpuCount) {
+			cpus = append(cpus, int(cpu))
+		}
+	}
+
+	queues := make([]tun.Device, 0, fdCount)
+	for i, fd := range fds {
+		queue, status := createTUNFromFd(int(fd), mtu, logger)
+		if queue == nil {
+			for _, queue := range queues {
+				queue.Close()
+			}
+			for _, fd := range fds[i+1:] {
+				unix.Close(int(fd))
+			}
+			return status
+		}
+		queues = append(queues, queue)
+	}
+
+	if len(queues) == 1 && len(cpus) == 0 {
+		return startTunnel(queues[0], settings, logger)
+	}
+	return startTunnel(newMultiQueueTUN(queues, cpus, logger), settings, logger)
+}
+
+// multiQueueTUN reads packets from every queue of a tunnel device, each on its own thread.
+// Packets are written to the first queue, since every peer writes from a single goroutine anyway.
+type multiQueueTUN struct {
+	tun.Device
+	queues    []tun.Device
+	batches   chan *packetBatch
+	pending   *packetBatch
+	closed    chan struct{}
+	closeOnce sync.Once
+}
+
+// packetBatch holds packets that were read from one queue. The reader of the queue waits for the
+// batch to be consumed before reading into it again.
+type packetBatch struct {
+	bufs     [][]byte
+	sizes    []int
+	count    int
+	next     int
+	err      error
+	consumed chan struct{}
+}
+
+func newMultiQueueTUN(queues []tun.Device, cpus []int, logger *device.Logger) *multiQueueTUN {
+	t := &multiQueueTUN{
+		Device:  queues[0],
+		queues:  queues,
+		batches: make(chan *packetBatch, len(queues)),
+		closed:  make(chan struct{}),
+	}
+	for i, queue := range queues {
+		cpu := -1
+		if len(cpus) > 0 {
+			cpu = cpus[i%len(cpus)]
+		}
+		go t.routineReadQueue(queue, cpu, logger)
+		if i > 0 {
+			// Only the events of the first queue are handled by the device
+			go func(queue tun.Device) {
+				for range queue.Events() {
+				}
+			}(queue)
+		}
+	}
+	return t
+}
+
+func (t *multiQueueTUN) routineReadQueue(queue tun.Device, cpu int, logger *device.Logger) {
+	if cpu >= 0 {
+		// The thread exits along with the goroutine, so that no other goroutine is pinned
+		runtime.LockOSThread()
+		var set unix.CPUSet
+		set.Set(cpu)
+		if err := unix.SchedSetaffinity(0, &set); err != nil {
+			logger.Errorf("Failed to pin tunnel queue reader to CPU %d: %s\n", cpu, err)
+		}
+	}
+
+	batchSize := queue.BatchSize()
+	batch := &packetBatch{
+		bufs:     make([][]byte, batchSize),
+		sizes:    make([]int, batchSize),
+		consumed: make(chan struct{}, 1),
+	}
+	for i := range batch.bufs {
+		batch.bufs[i] = make([]byte, device.MaxMessageSize)
+	}
+
+	for {
+		batch.count, batch.err = queue.Read(batch.bufs, batch.sizes, 0)
+		batch.next = 0
+		if batch.err != nil {
+			batch.count = 0
+		}
+		select {
+		case t.batches <- batch:
+		case <-t.closed:
+			return
+		}
+		if batch.err != nil {
+			return
+		}
+		select {
+		case <-batch.consumed:
+		case <-t.closed:
+			return
+		}
+	}
+}
+
+// Read is only called from a single goroutine by the device.
+func (t *multiQueueTUN) Read(bufs [][]byte, sizes []int, offset int) (int, error) {
+	batch := t.pending
+	if batch == nil {
+		select {
+		case batch = <-t.batches:
+		case <-t.closed:
+			return 0, os.ErrClosed
+		}
+		if batch.err != nil {
+			return 0, batch.err
+		}
+	}
+
+	n := 0
+	for n < len(bufs) && batch.next < batch.count {
+		sizes[n] = copy(bufs[n][offset:], batch.bufs[batch.next][:batch.sizes[batch.next]])
+		batch.next++
+		n++
+	}
+
+	if batch.next < batch.count {
+		t.pending = batch
+	} else {
+		t.pending = nil
+		batch.consumed <- struct{}{}
+	}
+	return n, nil
+}
+
+func (t *multiQueueTUN) Close() error {
+	var err error
+	t.closeOnce.Do(func() {
+		close(t.closed)
+		for _, queue := range t.queues {
+			if closeErr := queue.Close(); closeErr != nil && err == nil {
+				err = closeErr
+			}
+		}
+	})
+	return err
+}
@@ -0,0 +1,501 @@
+// +build linux
+
+/* SPDX-License-Identifier: Apache-2.0
+ *
+ * Copyright (C) 2024 Mullvad VPN AB. All Rights Reserved.
+ */
+
+// Package offload implements a bind that uses UDP segmentation offload (GSO) and receive offload
+// (GRO) on Linux. Consecutive packets of the same size to the same endpoint are handed to the
+// kernel as one large datagram, which is split into packets by the kernel or the network card.
+// Likewise, the kernel coalesces received datagrams, which are split here. This saves a trip
+// through the network stack for most packets.
+//
+// Support for offloading is probed when the sockets are opened, and segmentation offload is
+// disabled if the network device turns out not to support it.
+package offload
+
+import (
+	"context"
+	"encoding/binary"
+	"errors"
+	"net"
+	"net/netip"
+	"strconv"
+	"sync"
+	"sync/atomic"
+	"syscall"
+	"unsafe"
+
+	"golang.org/x/net/ipv4"
+	"golang.org/x/net/ipv6"
+	"golang.org/x/sys/unix"
+
+	"golang.zx2c4.com/wireguard/conn"
+	"golang.zx2c4.com/wireguard/device"
+)
+
+const (
+	// Number of datagrams that are sent or received with a single system call.
+	batchSize = 128
+
+	// The kernel does not split or coalesce more datagrams than this at once.
+	maxSegments = 64
+
+	// Largest UDP payloads that fit in an IP packet.
+	maxIPv4PayloadLen = 1<<16 - 1 - 20 - 8
+	maxIPv6PayloadLen = 1<<16 - 1 - 8
+
+	// Size of the socket buffers, which must fit bursts of coalesced datagrams.
+	socketBufferSize = 7 << 20
+)
+
+// Space for a single UDP_SEGMENT or UDP_GRO control message.
+var controlSize = unix.CmsgSpace(4)
+
+var (
+	errBindAlreadyOpen    = errors.New("bind is already open")
+	errWrongEndpointType  = errors.New("endpoint is not an offload.Endpoint")
+	errSplitOverflow      = errors.New("coalesced datagrams do not fit in the receive buffers")
+	errUnsupportedAddress = syscall.EAFNOSUPPORT
+)
+
+// Endpoint is the address of a peer.
+type Endpoint netip.AddrPort
+
+func (e Endpoint) ClearSrc() {}
+
+func (e Endpoint) SrcToString() string {
+	return ""
+}
+
+func (e Endpoint) DstToString() string {
+	return netip.AddrPort(e).String()
+}
+
+func (e Endpoint) DstToBytes() []byte {
+	b, _ := netip.AddrPort(e).MarshalBinary()
+	return b
+}
+
+func (e Endpoint) DstIP() netip.Addr {
+	return netip.AddrPort(e).Addr()
+}
+
+func (e Endpoint) SrcIP() netip.Addr {
+	return netip.Addr{}
+}
+
+// batchConn is implemented by both ipv4.PacketConn and ipv6.PacketConn, whose messages are the
+// same type.
+type batchConn interface {
+	ReadBatch(msgs []ipv6.Message, flags int) (int, error)
+	WriteBatch(msgs []ipv6.Message, flags int) (int, error)
+}
+
+type socket struct {
+	conn      *net.UDPConn
+	pc        batchConn
+	isIPv6    bool
+	rxOffload bool
+	txOffload atomic.Bool
+}
+
+// Bind sends and receives datagrams on one IPv4 and one IPv6 socket.
+type Bind struct {
+	mu     sync.RWMutex
+	logger *device.Logger
+	ipv4   *socket
+	ipv6   *socket
+	msgs   sync.Pool
+}
+
+func NewBind(logger *device.Logger) conn.Bind {
+	return &Bind{
+		logger: logger,
+		msgs: sync.Pool{
+			New: func() any {
+				msgs := make([]ipv6.Message, batchSize)
+				for i := range msgs {
+					msgs[i].Buffers = make([][]byte, 1)
+					msgs[i].OOB = make([]byte, 0, controlSize)
+				}
+				return &msgs
+			},
+		},
+	}
+}
+
+func (b *Bind) Open(uport uint16) ([]conn.ReceiveFunc, uint16, error) {
+	b.mu.Lock()
+	defer b.mu.Unlock()
+
+	if b.ipv4 != nil || b.ipv6 != nil {
+		return nil, 0, errBindAlreadyOpen
+	}
+
+	// Use the same port for both IP versions. If the port is chosen by the kernel, it may already
+	// be in use for IPv6, in which case another one is tried.
+	var v4, v6 *socket
+	var port4, port6 int
+	var err error
+	for tries := 0; ; tries++ {
+		v4, port4, err = b.listen("udp4", int(uport))
+		if err != nil && !errors.Is(err, errUnsupportedAddress) {
+			return nil, 0, err
+		}
+		v6, port6, err = b.listen("udp6", port4)
+		if uport == 0 && errors.Is(err, syscall.EADDRINUSE) && tries < 100 {
+			v4.close()
+			continue
+		}
+		if err != nil && !errors.Is(err, errUnsupportedAddress) {
+			v4.close()
+			return nil, 0, err
+		}
+		break
+	}
+
+	var fns []conn.ReceiveFunc
+	port := port4
+	if v4 != nil {
+		b.ipv4 = v4
+		fns = append(fns, b.makeReceiveFunc(v4))
+	}
+	if v6 != nil {
+		b.ipv6 = v6
+		fns = append(fns, b.makeReceiveFunc(v6))
+		port = port6
+	}
+	if len(fns) == 0 {
+		return nil, 0, errUnsupportedAddress
+	}
+	return fns, uint16(port), nil
+}
+
+func (b *Bind) listen(network string, port int) (*socket, int, error) {
+	config := net.ListenConfig{
+		Control: func(network, address string, c syscall.RawConn) error {
+			return c.Control(func(fd uintptr) {
+				_ = unix.SetsockoptInt(int(fd), unix.SOL_SOCKET, unix.SO_RCVBUFFORCE, socketBufferSize)
+				_ = unix.SetsockoptInt(int(fd), unix.SOL_SOCKET, unix.SO_SNDBUFFORCE, socketBufferSize)
+				// Failure means that receive offload is unsupported, which is probed below
+				_ = unix.SetsockoptInt(int(fd), unix.IPPROTO_UDP, unix.UDP_GRO, 1)
+			})
+		},
+	}
+	packetConn, err := config.ListenPacket(context.Background(), network, ":"+strconv.Itoa(port))
+	if err != nil {
+		return nil, 0, err
+	}
+	udpConn := packetConn.(*net.UDPConn)
+
+	s := &socket{conn: udpConn, isIPv6: network == "udp6"}
+	if s.isIPv6 {
+		s.pc = ipv6.NewPacketConn(udpConn)
+	} else {
+		s.pc = ipv4.NewPacketConn(udpConn)
+	}
+
+	txOffload, rxOffload := probeOffload(udpConn)
+	s.txOffload.Store(txOffload)
+	s.rxOffload = rxOffload
+	b.logger.Verbosef("UDP offload on %s socket: segmentation %t, receive %t\n", network, txOffload, rxOffload)
+
+	return s, udpConn.LocalAddr().(*net.UDPAddr).Port, nil
+}
+
+func probeOffload(udpConn *net.UDPConn) (txOffload, rxOffload bool) {
+	rawConn, err := udpConn.SyscallConn()
+	if err != nil {
+		return false, false
+	}
+	err = rawConn.Control(func(fd uintptr) {
+		_, err := unix.GetsockoptInt(int(fd), unix.IPPROTO_UDP, unix.UDP_SEGMENT)
+		txOffload = err == nil
+		gro, err := unix.GetsockoptInt(int(fd), unix.IPPROTO_UDP, unix.UDP_GRO)
+		rxOffload = err == nil && gro == 1
+	})
+	if err != nil {
+		return false, false
+	}
+	return txOffload, rxOffload
+}
+
+func (s *socket) close() error {
+	if s == nil {
+		return nil
+	}
+	return s.conn.Close()
+}
+
+func (b *Bind) Close() error {
+	b.mu.Lock()
+	defer b.mu.Unlock()
+
+	err4 := b.ipv4.close()
+	err6 := b.ipv6.close()
+	b.ipv4 = nil
+	b.ipv6 = nil
+	if err4 != nil {
+		return err4
+	}
+	return err6
+}
+
+func (b *Bind) SetMark(mark uint32) error {
+	b.mu.RLock()
+	defer b.mu.RUnlock()
+
+	for _, s := range []*socket{b.ipv4, b.ipv6} {
+		if s == nil {
+			continue
+		}
+		rawConn, err := s.conn.SyscallConn()
+		if err != nil {
+			return err
+		}
+		var markErr error
+		err = rawConn.Control(func(fd uintptr) {
+			markErr = unix.SetsockoptInt(int(fd), unix.SOL_SOCKET, unix.SO_MARK, int(mark))
+		})
+		if err != nil {
+			return err
+		}
+		if markErr != nil {
+			return markErr
+		}
+	}
+	return nil
+}
+
+func (b *Bind) BatchSize() int {
+	return batchSize
+}
+
+func (b *Bind) ParseEndpoint(s string) (conn.Endpoint, error) {
+	addrPort, err := netip.ParseAddrPort(s)
+	if err != nil {
+		return nil, err
+	}
+	return Endpoint(addrPort), nil
+}
+
+func (b *Bind) getMessages() *[]ipv6.Message {
+	return b.msgs.Get().(*[]ipv6.Message)
+}
+
+func (b *Bind) putMessages(msgs *[]ipv6.Message) {
+	for i := range *msgs {
+		(*msgs)[i].Buffers[0] = nil
+		(*msgs)[i].OOB = (*msgs)[i].OOB[:0]
+		(*msgs)[i].Addr = nil
+		(*msgs)[i].N = 0
+		(*msgs)[i].NN = 0
+	}
+	b.msgs.Put(msgs)
+}
+
+func (b *Bind) makeReceiveFunc(s *socket) conn.ReceiveFunc {
+	return func(bufs [][]byte, sizes []int, eps []conn.Endpoint) (int, error) {
+		pooled := b.getMessages()
+		defer b.putMessages(pooled)
+
+		msgs := (*pooled)[:min(len(bufs), batchSize)]
+		for i := range msgs {
+			msgs[i].Buffers[0] = bufs[i]
+			msgs[i].OOB = msgs[i].OOB[:cap(msgs[i].OOB)]
+		}
+
+		var n int
+		var err error
+		if s.rxOffload {
+			// Read coalesced datagrams into the last buffers, and split them into the others
+			readAt := len(msgs) - max(1, len(msgs)/maxSegments)
+			if _, err = s.pc.ReadBatch(msgs[readAt:], 0); err != nil {
+				return 0, err
+			}
+			if n, err = splitCoalescedMessages(msgs, readAt); err != nil {
+				return 0, err
+			}
+		} else {
+			if n, err = s.pc.ReadBatch(msgs, 0); err != nil {
+				return 0, err
+			}
+		}
+
+		for i := 0; i < n; i++ {
+			sizes[i] = msgs[i].N
+			addrPort := msgs[i].Addr.(*net.UDPAddr).AddrPort()
+			eps[i] = Endpoint(netip.AddrPortFrom(addrPort.Addr().Unmap(), addrPort.Port()))
+		}
+		return n, nil
+	}
+}
+
+// splitCoalescedMessages splits the datagrams that were read into msgs[firstMsgAt:] into
+// msgs[0:], and returns the number of datagrams.
+func splitCoalescedMessages(msgs []ipv6.Message, firstMsgAt int) (int, error) {
+	n := 0
+	for i := firstMsgAt; i < len(msgs); i++ {
+		msg := &msgs[i]
+		if msg.N == 0 {
+			break
+		}
+		segmentSize := getGROSize(msg.OOB[:msg.NN])
+		if segmentSize <= 0 {
+			segmentSize = msg.N
+		}
+		for start := 0; start < msg.N; start += segmentSize {
+			if n > i {
+				return 0, errSplitOverflow
+			}
+			end := min(start+segmentSize, msg.N)
+			msgs[n].N = copy(msgs[n].Buffers[0], msg.Buffers[0][start:end])
+			msgs[n].Addr = msg.Addr
+			n++
+		}
+	}
+	return n, nil
+}
+
+// getGROSize returns the size of the datagrams that were coalesced, or 0 if they were not.
+func getGROSize(control []byte) int {
+	for len(control) >= unix.SizeofCmsghdr {
+		hdr, data, rest, err := unix.ParseOneSocketControlMessage(control)
+		if err != nil {
+			return 0
+		}
+		if hdr.Level == unix.SOL_UDP && hdr.Type == unix.UDP_GRO && len(data) >= 4 {
+			return int(int32(binary.NativeEndian.Uint32(data)))
+		}
+		control = rest
+	}
+	return 0
+}
+
+func (b *Bind) Send(bufs [][]byte, endpoint conn.Endpoint) error {
+	ep, ok := endpoint.(Endpoint)
+	if !ok {
+		return errWrongEndpointType
+	}
+
+	b.mu.RLock()
+	s := b.ipv4
+	if ep.DstIP().Is6() {
+		s = b.ipv6
+	}
+	b.mu.RUnlock()
+	if s == nil {
+		return errUnsupportedAddress
+	}
+
+	addr := net.UDPAddrFromAddrPort(netip.AddrPort(ep))
+	pooled := b.getMessages()
+	defer b.putMessages(pooled)
+
+	for len(bufs) > 0 {
+		chunk := bufs[:min(len(bufs), batchSize)]
+		bufs = bufs[len(chunk):]
+
+		if s.txOffload.Load() {
+			n := coalesceMessages(addr, s.isIPv6, chunk, *pooled)
+			err := s.send((*pooled)[:n])
+			if err == nil {
+				continue
+			}
+			// EIO means that the network device cannot checksum the segments, which is required
+			if !errors.Is(err, unix.EIO) {
+				return err
+			}
+			s.txOffload.Store(false)
+			b.logger.Verbosef("Disabling UDP segmentation offload: %s\n", err)
+		}
+
+		n := prepareMessages(addr, chunk, *pooled)
+		if err := s.send((*pooled)[:n]); err != nil {
+			return err
+		}
+	}
+	return nil
+}
+
+func (s *socket) send(msgs []ipv6.Message) error {
+	for len(msgs) > 0 {
+		n, err := s.pc.WriteBatch(msgs, 0)
+		if err != nil {
+			return err
+		}
+		msgs = msgs[n:]
+	}
+	return nil
+}
+
+func prepareMessages(addr *net.UDPAddr, bufs [][]byte, msgs []ipv6.Message) int {
+	for i, buf := range bufs {
+		msgs[i].Buffers[0] = buf
+		msgs[i].Addr = addr
+		msgs[i].OOB = msgs[i].OOB[:0]
+	}
+	return len(bufs)
+}
+
+// coalesceMessages appends runs of datagrams of the same size to the first datagram of the run,
+// so that they are sent as one message with a UDP_SEGMENT control message. The last datagram of a
+// run may be smaller. The datagrams are only appended within the spare capacity of the buffer of
+// the first datagram, so bufs itself is not modified.
+func coalesceMessages(addr *net.UDPAddr, isIPv6 bool, bufs [][]byte, msgs []ipv6.Message) int {
+	maxPayloadLen := maxIPv4PayloadLen
+	if isIPv6 {
+		maxPayloadLen = maxIPv6PayloadLen
+	}
+
+	base := -1
+	segmentSize := 0
+	segments := 0
+	endRun := false
+	for _, buf := range bufs {
+		if base >= 0 {
+			baseLen := len(msgs[base].Buffers[0])
+			freeCap := cap(msgs[base].Buffers[0]) - baseLen
+			if !endRun &&
+				segments < maxSegments &&
+				len(buf) <= segmentSize &&
+				len(buf) <= freeCap &&
+				baseLen+len(buf) <= maxPayloadLen {
+				msgs[base].Buffers[0] = append(msgs[base].Buffers[0], buf...)
+				segments++
+				endRun = len(buf) < segmentSize
+				continue
+			}
+			if segments > 1 {
+				setSegmentSize(&msgs[base].OOB, segmentSize)
+			}
+		}
+		base++
+		msgs[base].Buffers[0] = buf
+		msgs[base].Addr = addr
+		msgs[base].OOB = msgs[base].OOB[:0]
+		segmentSize = len(buf)
+		segments = 1
+		endRun = false
+	}
+	if segments > 1 {
+		setSegmentSize(&msgs[base].OOB, segmentSize)
+	}
+	return base + 1
+}
+
+func setSegmentSize(control *[]byte, segmentSize int) {
+	space := unix.CmsgSpace(2)
+	if cap(*control)-len(*control) < space {
+		return
+	}
+	start := len(*control)
+	*control = (*control)[:start+space]
+	hdr := (*unix.Cmsghdr)(unsafe.Pointer(&(*control)[start]))
+	hdr.Level = unix.SOL_UDP
+	hdr.Type = unix.UDP_SEGMENT
+	hdr.SetLen(unix.CmsgLen(2))
+	binary.NativeEndian.PutUint16((*control)[start+unix.SizeofCmsghdr:], uint16(segmentSize))
+}